@@ -0,0 +1,34 @@
+"""Script Orbit from Python over the orbitd socket."""
+
+from os import PathLike
+from pathlib import Path
+from typing import Any, Dict, Optional, Union
+
+class OrbitError(Exception):
+    """orbitd could not be reached or refused a request."""
+
+class OrbitClient:
+    """Connection to a running orbitd."""
+
+    def __init__(
+        self,
+        socket_path: Optional[Union[str, PathLike[str]]] = None,
+        timeout: float = 30.0,
+    ) -> None: ...
+    @property
+    def socket_path(self) -> Path: ...
+    def suggest(
+        self,
+        input: str,
+        cwd: Optional[Union[str, PathLike[str]]] = None,
+        shell: Optional[str] = None,
+    ) -> Dict[str, Any]: ...
+    def classify(self, input: str) -> Dict[str, Any]: ...
+    def execute(
+        self,
+        command: str,
+        cwd: Optional[Union[str, PathLike[str]]] = None,
+        target: Optional[Dict[str, Any]] = None,
+        input: Optional[str] = None,
+    ) -> Dict[str, Any]: ...
+    def stats(self) -> Dict[str, int]: ...
@@ -0,0 +1,178 @@
+//! Python bindings for the orbitd IPC protocol
+//!
+//! Built into the `orbit_client` extension module with maturin
+//! (`maturin develop` in this directory), so notebooks and automation can
+//! talk to a running daemon without speaking the socket protocol
+//! themselves:
+//!
+//! ```python
+//! from orbit_client import OrbitClient
+//!
+//! orbit = OrbitClient()
+//! orbit.classify("show disk usage")   # {'type': 'natural_language'}
+//! orbit.suggest("show disk usage")    # {'type': 'replaced', 'command': 'df -h'}
+//! orbit.execute("df -h")["stdout"]
+//! orbit.stats()                       # {'uptime_secs': ..., 'commands_processed': ...}
+//! ```
+//!
+//! Results are plain dicts shaped like the daemon's JSON; failures raise
+//! `orbit_client.OrbitError`. Calls release the GIL while they wait.
+
+mod protocol;
+
+use pyo3::create_exception;
+use pyo3::exceptions::PyException;
+use pyo3::prelude::*;
+use serde_json::{json, Value};
+use std::path::PathBuf;
+use std::time::Duration;
+
+create_exception!(
+    orbit_client,
+    OrbitError,
+    PyException,
+    "orbitd could not be reached or refused a request"
+);
+
+impl From<protocol::Error> for PyErr {
+    fn from(error: protocol::Error) -> Self {
+        OrbitError::new_err(error.to_string())
+    }
+}
+
+/// Connection to a running orbitd
+///
+/// `socket_path` defaults to the one in the Orbit config file; `timeout`
+/// is in seconds and covers each request, including any time a provider
+/// takes to answer.
+#[pyclass(frozen, module = "orbit_client")]
+struct OrbitClient {
+    inner: protocol::Client,
+}
+
+#[pymethods]
+impl OrbitClient {
+    #[new]
+    #[pyo3(signature = (socket_path=None, timeout=30.0))]
+    fn new(socket_path: Option<PathBuf>, timeout: f64) -> PyResult<Self> {
+        let timeout = Duration::try_from_secs_f64(timeout)
+            .ok()
+            .filter(|timeout| !timeout.is_zero())
+            .ok_or_else(|| {
+                pyo3::exceptions::PyValueError::new_err("timeout must be a positive number")
+            })?;
+        let socket_path = match socket_path {
+            Some(path) => path,
+            None => protocol::default_socket_path()?,
+        };
+        Ok(Self {
+            inner: protocol::Client::new(socket_path, timeout),
+        })
+    }
+
+    #[getter]
+    fn socket_path(&self) -> PathBuf {
+        self.inner.socket_path().to_path_buf()
+    }
+
+    /// The command Orbit would run for `input`, as if typed in `shell` at
+    /// `cwd` (default: the current directory and `$SHELL`)
+    #[pyo3(signature = (input, cwd=None, shell=None))]
+    fn suggest(
+        &self,
+        py: Python<'_>,
+        input: String,
+        cwd: Option<PathBuf>,
+        shell: Option<String>,
+    ) -> PyResult<PyObject> {
+        let cwd = cwd_or_current(cwd)?;
+        let shell = shell.unwrap_or_else(default_shell);
+        let result = py.allow_threads(|| self.inner.suggest(&input, &cwd, &shell))?;
+        to_python(py, &result)
+    }
+
+    /// How the classifier sees `input`, without asking a provider
+    fn classify(&self, py: Python<'_>, input: String) -> PyResult<PyObject> {
+        let result = py.allow_threads(|| self.inner.classify(&input))?;
+        to_python(py, &result)
+    }
+
+    /// Run `command` and return its `exit_code`, `stdout`, `stderr` and
+    /// `duration_ms`
+    ///
+    /// `target` is where it runs, as orbitd's execution target: omitted
+    /// for this machine, or e.g. `{"kind": "container", "name": "db"}` or
+    /// `{"kind": "image", "image": "python:3.12"}`. `input` is what was
+    /// typed, when `command` is a suggestion, so Orbit learns from the
+    /// result.
+    #[pyo3(signature = (command, cwd=None, target=None, input=None))]
+    fn execute(
+        &self,
+        py: Python<'_>,
+        command: String,
+        cwd: Option<PathBuf>,
+        target: Option<Bound<'_, PyAny>>,
+        input: Option<String>,
+    ) -> PyResult<PyObject> {
+        let cwd = cwd_or_current(cwd)?;
+        let target = match target {
+            Some(target) => from_python(&target)?,
+            None => json!({ "kind": "host" }),
+        };
+        let result =
+            py.allow_threads(|| self.inner.execute(&command, &cwd, target, input.as_deref()))?;
+        to_python(py, &result)
+    }
+
+    /// Daemon uptime and the number of commands it has processed
+    fn stats(&self, py: Python<'_>) -> PyResult<PyObject> {
+        let result = py.allow_threads(|| self.inner.stats())?;
+        to_python(py, &result)
+    }
+
+    fn __repr__(&self) -> String {
+        format!("OrbitClient(socket_path={:?})", self.inner.socket_path())
+    }
+}
+
+fn cwd_or_current(cwd: Option<PathBuf>) -> PyResult<String> {
+    let cwd = match cwd {
+        Some(cwd) => cwd,
+        None => std::env::current_dir()?,
+    };
+    cwd.into_os_string()
+        .into_string()
+        .map_err(|_| pyo3::exceptions::PyValueError::new_err("cwd is not valid UTF-8"))
+}
+
+/// Name of the user's shell, e.g. "zsh"
+fn default_shell() -> String {
+    std::env::var_os("SHELL")
+        .as_deref()
+        .and_then(|shell| std::path::Path::new(shell).file_name())
+        .and_then(|name| name.to_str())
+        .unwrap_or("sh")
+        .to_string()
+}
+
+// JSON goes through Python's own json module, which already maps it to
+// dicts, lists and numbers the way Python users expect
+
+fn to_python(py: Python<'_>, value: &Value) -> PyResult<PyObject> {
+    let text = serde_json::to_string(value)
+        .map_err(|e| OrbitError::new_err(format!("Unencodable response: {}", e)))?;
+    Ok(py.import("json")?.call_method1("loads", (text,))?.unbind())
+}
+
+fn from_python(object: &Bound<'_, PyAny>) -> PyResult<Value> {
+    let text: String = object.py().import("json")?.call_method1("dumps", (object,))?.extract()?;
+    serde_json::from_str(&text)
+        .map_err(|e| pyo3::exceptions::PyValueError::new_err(e.to_string()))
+}
+
+#[pymodule]
+fn orbit_client(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add_class::<OrbitClient>()?;
+    m.add("OrbitError", m.py().get_type::<OrbitError>())?;
+    Ok(())
+}
@@ -0,0 +1,343 @@
+//! The orbitd socket protocol, without Python
+//!
+//! One request per connection: a JSON line out, a JSON line back. Requests
+//! and responses are serde's externally tagged enums from
+//! `orbitd::daemon::ipc`, spelled out here so the wheel doesn't pull in the
+//! daemon and its dependencies.
+
+use serde::Deserialize;
+use serde_json::{json, Map, Value};
+use std::io::{BufRead, BufReader, Write};
+use std::os::unix::net::UnixStream;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("Cannot connect to orbitd at {path}: {source}")]
+    Connect {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+    #[error("orbitd did not answer within {0:?}")]
+    Timeout(Duration),
+    #[error("IPC error: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("Malformed response from orbitd: {0}")]
+    Json(#[from] serde_json::Error),
+    /// The daemon answered with `Response::Error`
+    #[error("{0}")]
+    Daemon(String),
+    /// A provider's rate limit was reached; worth retrying after a while
+    #[error("{message}")]
+    RateLimited {
+        message: String,
+        retry_after: Duration,
+    },
+    #[error("Unexpected response from orbitd: {0}")]
+    Unexpected(String),
+    #[error("Failed to read {path}: {message}")]
+    Config { path: PathBuf, message: String },
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+/// Socket the daemon listens on: `daemon.socket_path` from the Orbit
+/// config file, or `~/.orbit/daemon.sock` as orbitd defaults to
+///
+/// Like orbitd, honours `ORBIT_CONFIG` in place of the usual config path.
+pub fn default_socket_path() -> Result<PathBuf> {
+    #[derive(Deserialize)]
+    struct Config {
+        daemon: Daemon,
+    }
+    #[derive(Deserialize)]
+    struct Daemon {
+        socket_path: PathBuf,
+    }
+
+    let config_path = match std::env::var_os("ORBIT_CONFIG") {
+        Some(path) => Some(PathBuf::from(path)),
+        None => dirs::config_dir().map(|dir| dir.join("orbit").join("config.yaml")),
+    };
+    if let Some(path) = config_path.filter(|path| path.exists()) {
+        let config_error = |message: String| Error::Config {
+            path: path.clone(),
+            message,
+        };
+        let content = std::fs::read_to_string(&path).map_err(|e| config_error(e.to_string()))?;
+        let config: Config =
+            serde_yaml::from_str(&content).map_err(|e| config_error(e.to_string()))?;
+        return Ok(config.daemon.socket_path);
+    }
+
+    let home = dirs::home_dir().ok_or_else(|| Error::Config {
+        path: PathBuf::from("~"),
+        message: "Failed to find home directory".to_string(),
+    })?;
+    Ok(home.join(".orbit").join("daemon.sock"))
+}
+
+/// Blocking client for one daemon socket
+#[derive(Debug, Clone)]
+pub struct Client {
+    socket_path: PathBuf,
+    timeout: Duration,
+}
+
+impl Client {
+    pub fn new(socket_path: PathBuf, timeout: Duration) -> Self {
+        Self {
+            socket_path,
+            timeout,
+        }
+    }
+
+    pub fn socket_path(&self) -> &Path {
+        &self.socket_path
+    }
+
+    /// The command Orbit would run for `input` typed in `shell` at `cwd`
+    ///
+    /// `{"type": "passthrough"}` when the input is already a command, or
+    /// `{"type": "replaced", "command": ...}` with whatever else the daemon
+    /// attaches (dry run, elevation, explanation, host).
+    pub fn suggest(&self, input: &str, cwd: &str, shell: &str) -> Result<Value> {
+        let request = json!({ "Command": { "input": input, "cwd": cwd, "shell": shell } });
+        match self.call(&request)? {
+            (variant, _) if variant == "Passthrough" => Ok(json!({ "type": "passthrough" })),
+            (variant, mut fields) if variant == "Replaced" => {
+                fields.insert("type".to_string(), json!("replaced"));
+                Ok(Value::Object(fields))
+            }
+            (variant, _) => Err(Error::Unexpected(variant)),
+        }
+    }
+
+    /// How the classifier sees `input`: `{"type": "known"}`,
+    /// `"natural_language"`, `"ambiguous"`, or `"learned_pattern"` with the
+    /// learned `command` and its `confidence`
+    pub fn classify(&self, input: &str) -> Result<Value> {
+        let request = json!({ "Classify": { "input": input } });
+        self.expect(&request, "Classified", "classification")
+    }
+
+    /// Run `command` at `cwd` on `target` (an `ExecutionTarget`, tagged by
+    /// `kind`); its exit code, output and duration
+    pub fn execute(
+        &self,
+        command: &str,
+        cwd: &str,
+        target: Value,
+        input: Option<&str>,
+    ) -> Result<Value> {
+        let request = json!({
+            "Execute": { "command": command, "cwd": cwd, "target": target, "input": input }
+        });
+        self.expect(&request, "Executed", "output")
+    }
+
+    /// Daemon uptime, how many commands it has processed, its
+    /// `connectivity` (online or offline, why, and a message to show) and
+    /// the `readiness` of subsystems that warm up after startup
+    pub fn stats(&self) -> Result<Value> {
+        match self.call(&json!("Status"))? {
+            (variant, fields) if variant == "Status" => Ok(Value::Object(fields)),
+            (variant, _) => Err(Error::Unexpected(variant)),
+        }
+    }
+
+    /// Field `field` of a `variant` response
+    fn expect(&self, request: &Value, variant: &str, field: &str) -> Result<Value> {
+        match self.call(request)? {
+            (got, mut fields) if got == variant => fields
+                .remove(field)
+                .ok_or_else(|| Error::Unexpected(format!("{} without {}", variant, field))),
+            (got, _) => Err(Error::Unexpected(got)),
+        }
+    }
+
+    /// Send `request` and split the response into its variant and fields
+    fn call(&self, request: &Value) -> Result<(String, Map<String, Value>)> {
+        let mut stream = UnixStream::connect(&self.socket_path).map_err(|source| Error::Connect {
+            path: self.socket_path.clone(),
+            source,
+        })?;
+        stream.set_read_timeout(Some(self.timeout))?;
+        stream.set_write_timeout(Some(self.timeout))?;
+
+        let mut line = serde_json::to_string(request)?;
+        line.push('\n');
+        stream.write_all(line.as_bytes()).map_err(|e| self.io_error(e))?;
+
+        let mut response = String::new();
+        BufReader::new(stream)
+            .read_line(&mut response)
+            .map_err(|e| self.io_error(e))?;
+        if response.is_empty() {
+            return Err(Error::Unexpected("connection closed".to_string()));
+        }
+
+        let (variant, fields) = match serde_json::from_str(&response)? {
+            // Unit variants, e.g. "Passthrough"
+            Value::String(variant) => (variant, Map::new()),
+            Value::Object(object) if object.len() == 1 => {
+                let (variant, fields) = object.into_iter().next().unwrap();
+                match fields {
+                    Value::Object(fields) => (variant, fields),
+                    other => return Err(Error::Unexpected(other.to_string())),
+                }
+            }
+            other => return Err(Error::Unexpected(other.to_string())),
+        };
+
+        let message = fields.get("message").and_then(Value::as_str).unwrap_or("unknown error");
+        match variant.as_str() {
+            "Error" => return Err(Error::Daemon(message.to_string())),
+            "RateLimited" => {
+                let retry_after = fields.get("retry_after_ms").and_then(Value::as_u64).unwrap_or(0);
+                return Err(Error::RateLimited {
+                    message: message.to_string(),
+                    retry_after: Duration::from_millis(retry_after),
+                });
+            }
+            _ => {}
+        }
+        Ok((variant, fields))
+    }
+
+    fn io_error(&self, error: std::io::Error) -> Error {
+        match error.kind() {
+            std::io::ErrorKind::WouldBlock | std::io::ErrorKind::TimedOut => {
+                Error::Timeout(self.timeout)
+            }
+            _ => Error::Io(error),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::os::unix::net::UnixListener;
+    use std::thread::JoinHandle;
+
+    /// Answer one connection with `response`, returning the request line
+    fn daemon(path: &Path, response: &'static str) -> JoinHandle<Value> {
+        let listener = UnixListener::bind(path).unwrap();
+        std::thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut request = String::new();
+            let mut reader = BufReader::new(stream);
+            reader.read_line(&mut request).unwrap();
+            reader.get_mut().write_all(response.as_bytes()).unwrap();
+            serde_json::from_str(&request).unwrap()
+        })
+    }
+
+    fn client(dir: &tempfile::TempDir) -> Client {
+        Client::new(dir.path().join("daemon.sock"), Duration::from_secs(5))
+    }
+
+    #[test]
+    fn test_requests_and_responses() {
+        let dir = tempfile::tempdir().unwrap();
+        let client = client(&dir);
+
+        let daemon_thread = daemon(
+            client.socket_path(),
+            "{\"Replaced\":{\"command\":\"ls -la\",\"host\":\"build-1\"}}\n",
+        );
+        let suggestion = client.suggest("list files", "/tmp", "zsh").unwrap();
+        assert_eq!(
+            suggestion,
+            json!({ "type": "replaced", "command": "ls -la", "host": "build-1" })
+        );
+        assert_eq!(
+            daemon_thread.join().unwrap(),
+            json!({ "Command": { "input": "list files", "cwd": "/tmp", "shell": "zsh" } })
+        );
+
+        std::fs::remove_file(client.socket_path()).unwrap();
+        let daemon_thread = daemon(client.socket_path(), "\"Passthrough\"\n");
+        assert_eq!(
+            client.suggest("ls", "/tmp", "zsh").unwrap(),
+            json!({ "type": "passthrough" })
+        );
+        daemon_thread.join().unwrap();
+
+        std::fs::remove_file(client.socket_path()).unwrap();
+        let daemon_thread = daemon(
+            client.socket_path(),
+            "{\"Classified\":{\"classification\":{\"type\":\"known\"}}}\n",
+        );
+        assert_eq!(client.classify("ls").unwrap(), json!({ "type": "known" }));
+        assert_eq!(daemon_thread.join().unwrap(), json!({ "Classify": { "input": "ls" } }));
+
+        std::fs::remove_file(client.socket_path()).unwrap();
+        let daemon_thread = daemon(
+            client.socket_path(),
+            "{\"Status\":{\"uptime_secs\":12,\"commands_processed\":3}}\n",
+        );
+        assert_eq!(
+            client.stats().unwrap(),
+            json!({ "uptime_secs": 12, "commands_processed": 3 })
+        );
+        assert_eq!(daemon_thread.join().unwrap(), json!("Status"));
+    }
+
+    #[test]
+    fn test_errors() {
+        let dir = tempfile::tempdir().unwrap();
+        let client = client(&dir);
+
+        // No daemon listening
+        assert!(matches!(client.stats(), Err(Error::Connect { .. })));
+
+        let daemon_thread = daemon(
+            client.socket_path(),
+            "{\"Error\":{\"message\":\"Command execution is not available\"}}\n",
+        );
+        let error = client
+            .execute("make", "/src", json!({ "kind": "host" }), None)
+            .unwrap_err();
+        assert_eq!(error.to_string(), "Command execution is not available");
+        assert_eq!(
+            daemon_thread.join().unwrap(),
+            json!({
+                "Execute": { "command": "make", "cwd": "/src", "target": { "kind": "host" }, "input": null }
+            })
+        );
+
+        std::fs::remove_file(client.socket_path()).unwrap();
+        let daemon_thread = daemon(
+            client.socket_path(),
+            "{\"RateLimited\":{\"provider\":\"openai\",\"retry_after_ms\":1500,\"message\":\"Rate limit for openai reached; retry in 2s\"}}\n",
+        );
+        match client.suggest("list files", "/tmp", "zsh") {
+            Err(Error::RateLimited { retry_after, .. }) => {
+                assert_eq!(retry_after, Duration::from_millis(1500))
+            }
+            other => panic!("unexpected result: {:?}", other),
+        }
+        daemon_thread.join().unwrap();
+
+        // A daemon too old to know the request answers with something else
+        std::fs::remove_file(client.socket_path()).unwrap();
+        let daemon_thread = daemon(client.socket_path(), "\"Passthrough\"\n");
+        assert!(matches!(client.classify("ls"), Err(Error::Unexpected(_))));
+        daemon_thread.join().unwrap();
+    }
+
+    #[test]
+    fn test_socket_path_from_config() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = dir.path().join("config.yaml");
+        std::fs::write(&config, "daemon:\n  socket_path: /run/orbit/daemon.sock\n").unwrap();
+
+        std::env::set_var("ORBIT_CONFIG", &config);
+        let path = default_socket_path();
+        std::env::remove_var("ORBIT_CONFIG");
+        assert_eq!(path.unwrap(), PathBuf::from("/run/orbit/daemon.sock"));
+    }
+}
@@ -7,6 +7,7 @@ use std::path::{Path, PathBuf};
 use std::process::Command;
 
 const SERVICE_NAME: &str = "orbitd.service";
+const SOCKET_NAME: &str = "orbitd.socket";
 
 /// Get systemd user service directory
 fn get_service_dir() -> Result<PathBuf> {
@@ -20,6 +21,11 @@ fn get_service_path() -> Result<PathBuf> {
     Ok(get_service_dir()?.join(SERVICE_NAME))
 }
 
+/// Get socket file path
+fn get_socket_path() -> Result<PathBuf> {
+    Ok(get_service_dir()?.join(SOCKET_NAME))
+}
+
 /// Generate systemd service file content
 fn generate_service_file(daemon_path: &Path) -> String {
     let home = dirs::home_dir()
@@ -31,9 +37,14 @@ fn generate_service_file(daemon_path: &Path) -> String {
 Description=Orbit AI Terminal Daemon
 Documentation=https://github.com/singulio/orbit
 After=network.target
+Requires={socket}
+After={socket}
 
 [Service]
-Type=simple
+# Reports readiness and pings the watchdog through sd_notify
+Type=notify
+NotifyAccess=main
+WatchdogSec=30s
 ExecStart={}
 Restart=on-failure
 RestartSec=5s
@@ -63,6 +74,34 @@ WantedBy=default.target
         home,
         home,
         home,
+        home,
+        socket = SOCKET_NAME,
+    )
+}
+
+/// Generate systemd socket file content
+///
+/// systemd binds the daemon's socket and hands it over at startup, so
+/// clients connecting while orbitd starts or restarts are queued.
+fn generate_socket_file() -> String {
+    let home = dirs::home_dir()
+        .and_then(|p| p.to_str().map(|s| s.to_string()))
+        .unwrap_or_else(|| "~".to_string());
+
+    format!(
+        r#"[Unit]
+Description=Orbit AI Terminal Daemon socket
+Documentation=https://github.com/singulio/orbit
+
+[Socket]
+# Must match daemon.socket_path in the orbit config
+ListenStream={}/.orbit/daemon.sock
+SocketMode=0600
+DirectoryMode=0700
+
+[Install]
+WantedBy=sockets.target
+"#,
         home
     )
 }
@@ -95,6 +134,8 @@ pub fn systemd_install(daemon_path: &Path) -> Result<()> {
     let service_content = generate_service_file(daemon_path);
     fs::write(&service_path, service_content)
         .context("Failed to write systemd service file")?;
+    fs::write(get_socket_path()?, generate_socket_file())
+        .context("Failed to write systemd socket file")?;
 
     // Reload systemd
     Command::new("systemctl")
@@ -112,11 +153,12 @@ pub fn systemd_uninstall() -> Result<()> {
     let _ = systemd_stop();
     let _ = systemd_disable();
 
-    // Remove service file
-    let service_path = get_service_path()?;
-    if service_path.exists() {
-        fs::remove_file(&service_path)
-            .context("Failed to remove systemd service file")?;
+    // Remove service and socket files
+    for path in [get_service_path()?, get_socket_path()?] {
+        if path.exists() {
+            fs::remove_file(&path)
+                .with_context(|| format!("Failed to remove {}", path.display()))?;
+        }
     }
 
     // Reload systemd
@@ -131,7 +173,7 @@ pub fn systemd_uninstall() -> Result<()> {
 /// Enable systemd service
 pub fn systemd_enable() -> Result<()> {
     let output = Command::new("systemctl")
-        .args(["--user", "enable", SERVICE_NAME])
+        .args(["--user", "enable", SOCKET_NAME, SERVICE_NAME])
         .output()
         .context("Failed to enable systemd service")?;
 
@@ -146,7 +188,7 @@ pub fn systemd_enable() -> Result<()> {
 /// Disable systemd service
 pub fn systemd_disable() -> Result<()> {
     let output = Command::new("systemctl")
-        .args(["--user", "disable", SERVICE_NAME])
+        .args(["--user", "disable", SOCKET_NAME, SERVICE_NAME])
         .output()
         .context("Failed to disable systemd service")?;
 
@@ -161,7 +203,7 @@ pub fn systemd_disable() -> Result<()> {
 /// Start systemd service
 pub fn systemd_start() -> Result<()> {
     let output = Command::new("systemctl")
-        .args(["--user", "start", SERVICE_NAME])
+        .args(["--user", "start", SOCKET_NAME, SERVICE_NAME])
         .output()
         .context("Failed to start systemd service")?;
 
@@ -176,7 +218,7 @@ pub fn systemd_start() -> Result<()> {
 /// Stop systemd service
 pub fn systemd_stop() -> Result<()> {
     let output = Command::new("systemctl")
-        .args(["--user", "stop", SERVICE_NAME])
+        .args(["--user", "stop", SOCKET_NAME, SERVICE_NAME])
         .output()
         .context("Failed to stop systemd service")?;
 
@@ -227,6 +269,12 @@ mod tests {
         assert!(content.contains("ExecStart=/usr/local/bin/orbitd"));
         assert!(content.contains("Restart=on-failure"));
         assert!(content.contains("WantedBy=default.target"));
+        assert!(content.contains("Type=notify"));
+        assert!(content.contains("Requires=orbitd.socket"));
+
+        let socket = generate_socket_file();
+        assert!(socket.contains("/.orbit/daemon.sock"));
+        assert!(socket.contains("SocketMode=0600"));
     }
 
     #[test]
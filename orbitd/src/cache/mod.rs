@@ -3,6 +3,11 @@
 // This module provides an LRU cache with TTL support for caching AI provider responses.
 // The cache is context-aware, meaning it takes into account the current working directory,
 // git state, and other contextual information when determining cache keys.
+//
+// Only exact inputs hit. Answering paraphrases ("show me the files" from
+// "list files") waits on a real sentence embedding model; the word-hashing
+// stand-in in `embeddings` would match near-misses such as "remove docker
+// images" and "remove docker containers".
 
 use crate::context::Context;
 use crate::observability::get_metrics;
@@ -0,0 +1,267 @@
+//! Executables on PATH, and noticing when they change
+//!
+//! The known commands cache is a snapshot of every PATH directory. It goes
+//! stale when a binary is installed or removed, which shows up as:
+//!
+//! - a new modification time on a PATH directory
+//! - a new modification time on a package manager's database (dpkg, rpm,
+//!   pacman, apk, Homebrew, Nix, snap, Flatpak), for packages that link
+//!   their binaries in later or elsewhere
+//! - a package install command run through Orbit succeeding
+//!
+//! The daemon checks the first two periodically, reacts to the third as it
+//! happens, and clients can force a rebuild with `RefreshCommands`.
+
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::SystemTime;
+
+/// Package manager state that changes whenever packages are installed
+const PACKAGE_DATABASES: &[&str] = &[
+    "/var/lib/dpkg/status",
+    "/var/lib/rpm",
+    "/var/lib/pacman/local",
+    "/lib/apk/db/installed",
+    "/usr/local/Cellar",
+    "/opt/homebrew/Cellar",
+    "/nix/var/nix/profiles/default",
+    "/var/lib/snapd/snaps",
+    "/var/lib/flatpak/exports/bin",
+];
+
+/// Package managers and the subcommands that install something
+const INSTALL_COMMANDS: &[(&str, &[&str])] = &[
+    ("apt", &["install", "reinstall", "upgrade", "full-upgrade"]),
+    (
+        "apt-get",
+        &["install", "reinstall", "upgrade", "dist-upgrade"],
+    ),
+    ("dnf", &["install", "reinstall", "upgrade"]),
+    ("yum", &["install", "reinstall", "update"]),
+    ("zypper", &["install", "in", "update", "up"]),
+    ("apk", &["add"]),
+    ("brew", &["install", "reinstall", "upgrade", "link"]),
+    ("port", &["install"]),
+    ("snap", &["install"]),
+    ("flatpak", &["install"]),
+    ("cargo", &["install"]),
+    ("go", &["install"]),
+    ("pip", &["install"]),
+    ("pip3", &["install"]),
+    ("pipx", &["install"]),
+    ("gem", &["install"]),
+    ("npm", &["install", "i", "add"]),
+    ("pnpm", &["add", "install", "i"]),
+    ("yarn", &["global"]),
+    ("pacman", &["-S", "-Sy", "-Syu", "-U"]),
+    ("yay", &["-S", "-Sy", "-Syu"]),
+    ("paru", &["-S", "-Sy", "-Syu"]),
+    ("nix-env", &["-i", "--install", "-iA"]),
+    ("nix", &["profile"]),
+];
+
+/// Prefixes that run the command after them
+const WRAPPERS: &[&str] = &["sudo", "doas", "env", "nice", "time"];
+
+/// How a rebuild changed the cache
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct CommandsRefresh {
+    /// Commands known after the rebuild
+    pub commands: usize,
+    pub added: Vec<String>,
+    pub removed: Vec<String>,
+}
+
+/// Modification times of the PATH directories and package databases
+#[derive(Debug, Clone, PartialEq)]
+pub(super) struct PathStamp(Vec<(PathBuf, Option<SystemTime>)>);
+
+impl PathStamp {
+    pub(super) fn current() -> Self {
+        let path_dirs = std::env::var_os("PATH")
+            .map(|path_var| std::env::split_paths(&path_var).collect::<Vec<_>>())
+            .unwrap_or_default();
+        let databases = PACKAGE_DATABASES.iter().map(PathBuf::from);
+        Self(
+            path_dirs
+                .into_iter()
+                .chain(databases)
+                .map(|path| {
+                    let modified = std::fs::metadata(&path).and_then(|m| m.modified()).ok();
+                    (path, modified)
+                })
+                .collect(),
+        )
+    }
+}
+
+/// The cache and what it was built from
+pub(super) struct Snapshot {
+    pub(super) commands: Arc<HashSet<String>>,
+    pub(super) stamp: PathStamp,
+}
+
+impl Snapshot {
+    /// Scan PATH now; blocks on the file system
+    pub(super) fn take() -> Self {
+        // Stamped first, so a change during the scan is seen next time
+        let stamp = PathStamp::current();
+        Self {
+            commands: Arc::new(scan_path()),
+            stamp,
+        }
+    }
+
+    /// What changed since `previous`; nothing is reported as added when
+    /// there was no previous snapshot
+    pub(super) fn changes_since(&self, previous: Option<&Snapshot>) -> CommandsRefresh {
+        let mut refresh = CommandsRefresh {
+            commands: self.commands.len(),
+            ..Default::default()
+        };
+        if let Some(previous) = previous {
+            refresh.added = self
+                .commands
+                .difference(&previous.commands)
+                .cloned()
+                .collect();
+            refresh.removed = previous
+                .commands
+                .difference(&self.commands)
+                .cloned()
+                .collect();
+            refresh.added.sort();
+            refresh.removed.sort();
+        }
+        refresh
+    }
+}
+
+/// Whether `command` installs packages, and so may add commands
+pub fn is_package_install(command: &str) -> bool {
+    let mut words = command
+        .split_whitespace()
+        .skip_while(|word| WRAPPERS.contains(word) || word.starts_with('-') || word.contains('='));
+    let Some(program) = words.next() else {
+        return false;
+    };
+    let program = program.rsplit('/').next().unwrap_or(program);
+    let Some((_, subcommands)) = INSTALL_COMMANDS.iter().find(|(name, _)| *name == program) else {
+        return false;
+    };
+    // `pacman -S`, `-Sy`, `-Syu`; `pacman -Ss` only searches
+    words
+        .filter(|word| !word.starts_with('-') || subcommands.contains(word))
+        .take(1)
+        .any(|word| subcommands.contains(&word))
+}
+
+/// Every executable in the PATH directories
+pub(super) fn scan_path() -> HashSet<String> {
+    let mut commands = HashSet::new();
+    let Some(path_var) = std::env::var_os("PATH") else {
+        return commands;
+    };
+    for path_dir in std::env::split_paths(&path_var) {
+        if let Ok(entries) = std::fs::read_dir(path_dir) {
+            for entry in entries.flatten() {
+                if is_executable(&entry.path()) {
+                    if let Ok(file_name) = entry.file_name().into_string() {
+                        commands.insert(file_name);
+                    }
+                }
+            }
+        }
+    }
+    commands
+}
+
+/// Whether `cmd` names an executable in one of the PATH directories
+pub(super) fn on_path(cmd: &str) -> bool {
+    if cmd.is_empty() || cmd.contains(std::path::MAIN_SEPARATOR) {
+        return false;
+    }
+    std::env::var_os("PATH").is_some_and(|path_var| {
+        std::env::split_paths(&path_var).any(|dir| is_executable(&dir.join(cmd)))
+    })
+}
+
+fn is_executable(path: &Path) -> bool {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        // Check if owner has execute permission
+        std::fs::metadata(path)
+            .is_ok_and(|metadata| metadata.is_file() && metadata.permissions().mode() & 0o100 != 0)
+    }
+
+    #[cfg(not(unix))]
+    {
+        path.is_file()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_package_installs() {
+        for command in [
+            "sudo apt install ripgrep",
+            "apt-get -y install jq",
+            "brew install fd",
+            "cargo install --locked bat",
+            "npm i -g typescript",
+            "DEBIAN_FRONTEND=noninteractive apt-get install -y curl",
+            "/usr/bin/dnf install htop",
+            "sudo pacman -Syu neovim",
+        ] {
+            assert!(is_package_install(command), "{}", command);
+        }
+        for command in [
+            "apt search ripgrep",
+            "pacman -Ss neovim",
+            "brew list",
+            "cargo build",
+            "ls /var/lib/dpkg",
+            "echo apt install",
+            "",
+        ] {
+            assert!(!is_package_install(command), "{}", command);
+        }
+    }
+
+    #[test]
+    fn test_changes_since() {
+        let snapshot = |commands: &[&str]| Snapshot {
+            commands: Arc::new(commands.iter().map(|c| c.to_string()).collect()),
+            stamp: PathStamp(Vec::new()),
+        };
+        let before = snapshot(&["ls", "git", "vim"]);
+        let after = snapshot(&["ls", "git", "rg", "fd"]);
+
+        let refresh = after.changes_since(Some(&before));
+        assert_eq!(refresh.commands, 4);
+        assert_eq!(refresh.added, ["fd", "rg"]);
+        assert_eq!(refresh.removed, ["vim"]);
+        assert!(before.changes_since(None).added.is_empty());
+    }
+
+    #[test]
+    fn test_stamp_sees_new_binaries() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let stamp = |dir: &Path| {
+            let modified = std::fs::metadata(dir).unwrap().modified().unwrap();
+            PathStamp(vec![(dir.to_path_buf(), Some(modified))])
+        };
+        let before = stamp(dir.path());
+
+        // Directory mtimes can be coarse
+        std::thread::sleep(std::time::Duration::from_millis(20));
+        std::fs::write(dir.path().join("new-tool"), "#!/bin/sh\n").unwrap();
+        assert_ne!(stamp(dir.path()), before);
+    }
+}
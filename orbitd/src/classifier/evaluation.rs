@@ -0,0 +1,525 @@
+//! Offline evaluation of the classifier against labeled inputs
+//!
+//! A dataset is JSON lines, one labeled input each:
+//!
+//! ```text
+//! {"input": "ls -la", "expected": "known"}
+//! {"input": "show disk usage", "expected": "learned_pattern", "expected_command": "df -h"}
+//! {"input": "list files", "expected": "natural_language", "shell": "fish"}
+//! ```
+//!
+//! Each input is replayed once through the classifier and learning engine,
+//! recording every step rather than stopping at the first that matches.
+//! Reports are then worked out from those traces for any confidence
+//! threshold, so a threshold change can be compared against the current
+//! one before it ships (`orbitd --evaluate <dataset> --threshold <x>`).
+
+use anyhow::{Context as _, Result};
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+use super::{CommandClassifier, CommandType};
+use crate::context::Context;
+
+/// Width of a calibration bin
+const CALIBRATION_BIN: f32 = 0.1;
+
+/// Thresholds tried by `Evaluation::sweep`
+const SWEEP_STEP: f32 = 0.05;
+
+/// What the classifier decided, without the learned pattern itself
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Class {
+    Known,
+    LearnedPattern,
+    NaturalLanguage,
+    Ambiguous,
+}
+
+impl Class {
+    pub const ALL: [Class; 4] = [
+        Class::Known,
+        Class::LearnedPattern,
+        Class::NaturalLanguage,
+        Class::Ambiguous,
+    ];
+
+    fn index(self) -> usize {
+        match self {
+            Class::Known => 0,
+            Class::LearnedPattern => 1,
+            Class::NaturalLanguage => 2,
+            Class::Ambiguous => 3,
+        }
+    }
+}
+
+impl From<&CommandType> for Class {
+    fn from(command_type: &CommandType) -> Self {
+        match command_type {
+            CommandType::Known => Class::Known,
+            CommandType::LearnedPattern(_) => Class::LearnedPattern,
+            CommandType::NaturalLanguage => Class::NaturalLanguage,
+            CommandType::Ambiguous => Class::Ambiguous,
+        }
+    }
+}
+
+/// One labeled input
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Example {
+    pub input: String,
+    pub expected: Class,
+    /// Shell the input was typed in; the current one when unset
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub shell: Option<String>,
+    /// For learned patterns, the command the match should expand to
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expected_command: Option<String>,
+}
+
+/// Read a JSON lines dataset; blank lines and `#` comments are skipped
+pub fn load_dataset(path: &Path) -> Result<Vec<Example>> {
+    let text = std::fs::read_to_string(path)
+        .with_context(|| format!("Failed to read dataset {}", path.display()))?;
+    parse_dataset(&text).with_context(|| format!("Invalid dataset {}", path.display()))
+}
+
+fn parse_dataset(text: &str) -> Result<Vec<Example>> {
+    text.lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty() && !line.trim_start().starts_with('#'))
+        .map(|(i, line)| serde_json::from_str(line).with_context(|| format!("line {}", i + 1)))
+        .collect()
+}
+
+/// Every classification step's answer for one input
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Trace {
+    /// Class forced by a plugin
+    pub plugin: Option<Class>,
+    pub known: bool,
+    /// Closest learned pattern: its confidence and command
+    pub learned: Option<(f32, String)>,
+    pub natural_language: bool,
+}
+
+impl Trace {
+    /// The class `CommandClassifier::classify` would pick at `threshold`
+    pub fn decide(&self, threshold: f32) -> Class {
+        if let Some(class) = self.plugin {
+            return class;
+        }
+        if self.known {
+            return Class::Known;
+        }
+        if let Some((confidence, _)) = &self.learned {
+            if *confidence > threshold {
+                return Class::LearnedPattern;
+            }
+        }
+        if self.natural_language {
+            return Class::NaturalLanguage;
+        }
+        Class::Ambiguous
+    }
+}
+
+impl CommandClassifier {
+    /// Run every step of `classify` on `input`, for evaluation
+    pub async fn trace(&self, input: &str, context: &Context) -> Result<Trace> {
+        let dialect = context.dialect();
+        let first_word = dialect.program(input.split_whitespace().next().unwrap_or(""));
+        Ok(Trace {
+            plugin: self.plugins.classify(input, context).map(|(_, class)| Class::from(&class)),
+            known: self.is_known_command(first_word, dialect),
+            learned: self
+                .learning_engine
+                .find_similar(input, context)
+                .await?
+                .map(|pattern| (pattern.confidence, pattern.learned_command)),
+            natural_language: self.languages.looks_like_natural_language(input),
+        })
+    }
+}
+
+/// A labeled input and how it was classified
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Observation {
+    pub example: Example,
+    pub trace: Trace,
+}
+
+impl Observation {
+    /// Whether the learned pattern found is the one wanted
+    fn learned_is_right(&self) -> bool {
+        let Some((_, command)) = &self.trace.learned else {
+            return false;
+        };
+        self.example.expected == Class::LearnedPattern
+            && self
+                .example
+                .expected_command
+                .as_ref()
+                .is_none_or(|expected| expected.trim() == command.trim())
+    }
+}
+
+/// Replay `examples` through `classifier`, each in `context` with its own
+/// shell
+pub async fn replay(
+    classifier: &CommandClassifier,
+    context: &Context,
+    examples: Vec<Example>,
+) -> Result<Evaluation> {
+    let mut observations = Vec::with_capacity(examples.len());
+    for example in examples {
+        let mut context = context.clone();
+        if let Some(shell) = &example.shell {
+            context.shell_name = shell.clone();
+        }
+        let trace = classifier.trace(&example.input, &context).await?;
+        observations.push(Observation { example, trace });
+    }
+    Ok(Evaluation { observations })
+}
+
+/// Precision, recall and F1 for one class; 0 where nothing was predicted
+/// or expected
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ClassMetrics {
+    pub class: Class,
+    pub precision: f64,
+    pub recall: f64,
+    pub f1: f64,
+    /// Inputs labeled with this class
+    pub support: usize,
+}
+
+/// Learned pattern confidences in one range, against how often they were
+/// right
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CalibrationBin {
+    pub lower: f32,
+    pub upper: f32,
+    pub count: usize,
+    pub mean_confidence: f64,
+    pub accuracy: f64,
+}
+
+/// Results at one confidence threshold
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Report {
+    pub threshold: f32,
+    pub examples: usize,
+    pub accuracy: f64,
+    pub classes: Vec<ClassMetrics>,
+    /// Rows are expected classes, columns predicted, both in `Class::ALL`
+    /// order
+    pub confusion: [[usize; 4]; 4],
+}
+
+/// Learned pattern results across thresholds
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ThresholdPoint {
+    pub threshold: f32,
+    pub accuracy: f64,
+    pub learned_precision: f64,
+    pub learned_recall: f64,
+}
+
+/// An input classified differently by two thresholds
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Change {
+    pub input: String,
+    pub expected: Class,
+    pub baseline: Class,
+    pub candidate: Class,
+}
+
+/// A candidate threshold against the baseline
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Comparison {
+    pub baseline: Report,
+    pub candidate: Report,
+    /// Candidate accuracy minus baseline accuracy
+    pub accuracy_delta: f64,
+    /// Inputs the candidate now gets right
+    pub fixed: Vec<Change>,
+    /// Inputs the candidate now gets wrong
+    pub regressed: Vec<Change>,
+}
+
+/// Traces of a whole dataset
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Evaluation {
+    pub observations: Vec<Observation>,
+}
+
+impl Evaluation {
+    pub fn report(&self, threshold: f32) -> Report {
+        let mut confusion = [[0; 4]; 4];
+        for observation in &self.observations {
+            let predicted = observation.trace.decide(threshold);
+            confusion[observation.example.expected.index()][predicted.index()] += 1;
+        }
+
+        let correct: usize = (0..4).map(|i| confusion[i][i]).sum();
+        let classes = Class::ALL
+            .iter()
+            .map(|&class| {
+                let i = class.index();
+                let predicted: usize = (0..4).map(|row| confusion[row][i]).sum();
+                let support: usize = confusion[i].iter().sum();
+                let precision = ratio(confusion[i][i], predicted);
+                let recall = ratio(confusion[i][i], support);
+                let f1 = if precision + recall > 0.0 {
+                    2.0 * precision * recall / (precision + recall)
+                } else {
+                    0.0
+                };
+                ClassMetrics {
+                    class,
+                    precision,
+                    recall,
+                    f1,
+                    support,
+                }
+            })
+            .collect();
+
+        Report {
+            threshold,
+            examples: self.observations.len(),
+            accuracy: ratio(correct, self.observations.len()),
+            classes,
+            confusion,
+        }
+    }
+
+    /// How well learned pattern confidence predicts a right match, in bins
+    /// of `CALIBRATION_BIN`; empty bins are left out
+    pub fn calibration(&self) -> Vec<CalibrationBin> {
+        let bins = (1.0 / CALIBRATION_BIN).round() as usize;
+        // (count, confidence sum, right)
+        let mut totals = vec![(0usize, 0f64, 0usize); bins];
+        for observation in &self.observations {
+            if let Some((confidence, _)) = &observation.trace.learned {
+                let bin = ((confidence.clamp(0.0, 1.0) / CALIBRATION_BIN) as usize).min(bins - 1);
+                totals[bin].0 += 1;
+                totals[bin].1 += f64::from(*confidence);
+                totals[bin].2 += usize::from(observation.learned_is_right());
+            }
+        }
+
+        totals
+            .into_iter()
+            .enumerate()
+            .filter(|(_, (count, _, _))| *count > 0)
+            .map(|(bin, (count, confidence, right))| CalibrationBin {
+                lower: bin as f32 * CALIBRATION_BIN,
+                upper: (bin + 1) as f32 * CALIBRATION_BIN,
+                count,
+                mean_confidence: confidence / count as f64,
+                accuracy: ratio(right, count),
+            })
+            .collect()
+    }
+
+    /// Expected calibration error: the gap between confidence and accuracy,
+    /// averaged over bins by their size
+    pub fn calibration_error(&self) -> f64 {
+        let bins = self.calibration();
+        let total: usize = bins.iter().map(|bin| bin.count).sum();
+        bins.iter()
+            .map(|bin| ratio(bin.count, total) * (bin.accuracy - bin.mean_confidence).abs())
+            .sum()
+    }
+
+    /// Accuracy and learned pattern precision/recall from 0 to 1
+    pub fn sweep(&self) -> Vec<ThresholdPoint> {
+        let steps = (1.0 / SWEEP_STEP).round() as usize;
+        (0..=steps)
+            .map(|step| {
+                let report = self.report(step as f32 * SWEEP_STEP);
+                let learned = &report.classes[Class::LearnedPattern.index()];
+                ThresholdPoint {
+                    threshold: report.threshold,
+                    accuracy: report.accuracy,
+                    learned_precision: learned.precision,
+                    learned_recall: learned.recall,
+                }
+            })
+            .collect()
+    }
+
+    /// Compare a candidate threshold against the baseline
+    pub fn compare(&self, baseline: f32, candidate: f32) -> Comparison {
+        let mut fixed = Vec::new();
+        let mut regressed = Vec::new();
+        for observation in &self.observations {
+            let expected = observation.example.expected;
+            let change = Change {
+                input: observation.example.input.clone(),
+                expected,
+                baseline: observation.trace.decide(baseline),
+                candidate: observation.trace.decide(candidate),
+            };
+            if change.baseline == change.candidate {
+                continue;
+            }
+            if change.candidate == expected {
+                fixed.push(change);
+            } else if change.baseline == expected {
+                regressed.push(change);
+            }
+        }
+
+        let baseline = self.report(baseline);
+        let candidate = self.report(candidate);
+        Comparison {
+            accuracy_delta: candidate.accuracy - baseline.accuracy,
+            baseline,
+            candidate,
+            fixed,
+            regressed,
+        }
+    }
+}
+
+fn ratio(part: usize, whole: usize) -> f64 {
+    if whole == 0 {
+        0.0
+    } else {
+        part as f64 / whole as f64
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn observation(expected: Class, trace: Trace) -> Observation {
+        Observation {
+            example: Example {
+                input: format!("{:?}", trace),
+                expected,
+                shell: None,
+                expected_command: None,
+            },
+            trace,
+        }
+    }
+
+    fn learned(confidence: f32) -> Trace {
+        Trace {
+            learned: Some((confidence, "df -h".to_string())),
+            natural_language: true,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_parse_dataset() {
+        let examples = parse_dataset(
+            r#"
+# known commands
+{"input": "ls -la", "expected": "known"}
+
+{"input": "show disk usage", "expected": "learned_pattern", "expected_command": "df -h"}
+{"input": "list files", "expected": "natural_language", "shell": "fish"}
+"#,
+        )
+        .unwrap();
+        assert_eq!(examples.len(), 3);
+        assert_eq!(examples[1].expected, Class::LearnedPattern);
+        assert_eq!(examples[2].shell.as_deref(), Some("fish"));
+
+        let error = parse_dataset("{\"input\": \"ls\", \"expected\": \"maybe\"}").unwrap_err();
+        assert!(error.to_string().contains("line 1"));
+    }
+
+    #[test]
+    fn test_decide_follows_classify() {
+        let plugin = Trace {
+            plugin: Some(Class::NaturalLanguage),
+            known: true,
+            ..Default::default()
+        };
+        assert_eq!(plugin.decide(0.7), Class::NaturalLanguage);
+
+        let known = Trace {
+            known: true,
+            ..learned(0.9)
+        };
+        assert_eq!(known.decide(0.7), Class::Known);
+
+        assert_eq!(learned(0.9).decide(0.7), Class::LearnedPattern);
+        assert_eq!(learned(0.7).decide(0.7), Class::NaturalLanguage);
+        assert_eq!(Trace::default().decide(0.7), Class::Ambiguous);
+    }
+
+    #[test]
+    fn test_report() {
+        let known = Trace {
+            known: true,
+            ..Default::default()
+        };
+        let evaluation = Evaluation {
+            observations: vec![
+                observation(Class::Known, known.clone()),
+                observation(Class::Known, known),
+                observation(Class::LearnedPattern, learned(0.9)),
+                observation(Class::LearnedPattern, learned(0.6)),
+                observation(Class::NaturalLanguage, learned(0.8)),
+                observation(Class::Ambiguous, Trace::default()),
+            ],
+        };
+
+        let report = evaluation.report(0.7);
+        assert_eq!(report.examples, 6);
+        assert!((report.accuracy - 4.0 / 6.0).abs() < 1e-9);
+        let learned_metrics = &report.classes[Class::LearnedPattern.index()];
+        assert_eq!(learned_metrics.support, 2);
+        assert!((learned_metrics.precision - 0.5).abs() < 1e-9);
+        assert!((learned_metrics.recall - 0.5).abs() < 1e-9);
+        assert_eq!(report.confusion[1][2], 1);
+        assert_eq!(report.confusion[2][1], 1);
+
+        // Raising the threshold to 0.85 drops the wrong 0.8 match
+        let comparison = evaluation.compare(0.7, 0.85);
+        assert_eq!(comparison.fixed.len(), 1);
+        assert!(comparison.regressed.is_empty());
+        assert!(comparison.accuracy_delta > 0.0);
+
+        // The whole sweep goes from 0 to 1
+        let sweep = evaluation.sweep();
+        assert_eq!(sweep.first().unwrap().threshold, 0.0);
+        assert!((sweep.last().unwrap().threshold - 1.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_calibration() {
+        let mut wrong_command = observation(Class::LearnedPattern, learned(0.95));
+        wrong_command.example.expected_command = Some("du -sh".to_string());
+        let evaluation = Evaluation {
+            observations: vec![
+                observation(Class::LearnedPattern, learned(0.92)),
+                wrong_command,
+                observation(Class::NaturalLanguage, learned(0.45)),
+                observation(Class::Known, Trace::default()),
+            ],
+        };
+
+        let bins = evaluation.calibration();
+        assert_eq!(bins.len(), 2);
+        assert_eq!(bins[0].count, 1);
+        assert_eq!(bins[0].accuracy, 0.0);
+        assert_eq!(bins[1].count, 2);
+        assert!((bins[1].accuracy - 0.5).abs() < 1e-9);
+
+        // (1 * 0.45 + 2 * |0.5 - 0.935|) / 3
+        assert!((evaluation.calibration_error() - 0.44).abs() < 1e-3);
+    }
+}
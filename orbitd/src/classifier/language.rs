@@ -0,0 +1,642 @@
+// Natural language in languages other than English
+//
+// Input counts as natural language when it asks a question, is long, opens
+// with a question word or request verb, or contains a conversational phrase.
+// Those words and phrases are kept per language; only the languages in
+// `classification.languages` are consulted (by default English and the
+// language of the system locale), since short words in one language are
+// often command names or ordinary arguments in another.
+//
+// Text mostly in a non-Latin script (Cyrillic, Greek, CJK, Arabic...) is
+// natural language whatever the configuration: commands are not written in
+// it. Words are split and lowercased by Unicode rules, so accented and
+// non-Latin words match, and scripts without spaces are judged by script.
+
+use serde::Serialize;
+use tracing::warn;
+
+/// A language natural language input was written in
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct Language {
+    /// ISO 639-1 code
+    pub code: &'static str,
+    /// English name, for prompts
+    pub name: &'static str,
+}
+
+impl Language {
+    pub const ENGLISH: Language = Language {
+        code: "en",
+        name: "English",
+    };
+
+    pub fn is_english(self) -> bool {
+        self.code == "en"
+    }
+}
+
+/// Words that mark a request in one language
+struct Lexicon {
+    language: Language,
+    /// Question words and request verbs, when they open the input
+    openers: &'static [&'static str],
+    /// Conversational phrases, anywhere in the input
+    phrases: &'static [&'static str],
+    /// Frequent function words; they don't make input natural language, but
+    /// tell languages apart
+    common: &'static [&'static str],
+}
+
+const LEXICONS: &[Lexicon] = &[
+    Lexicon {
+        language: Language::ENGLISH,
+        openers: &[
+            "what", "how", "why", "when", "where", "who", "tell", "show", "find", "list", "get",
+            "explain", "describe",
+        ],
+        phrases: &[
+            "i want",
+            "i need",
+            "please",
+            "could you",
+            "would you",
+            "can you",
+            "help me",
+            "show me",
+            "tell me",
+            "give me",
+        ],
+        common: &[
+            "the", "a", "an", "and", "of", "to", "in", "all", "my", "is", "are", "with",
+        ],
+    },
+    Lexicon {
+        language: Language {
+            code: "de",
+            name: "German",
+        },
+        openers: &[
+            "was",
+            "wie",
+            "warum",
+            "wann",
+            "wo",
+            "wer",
+            "welche",
+            "welcher",
+            "welches",
+            "zeige",
+            "zeig",
+            "finde",
+            "liste",
+            "erkläre",
+            "beschreibe",
+        ],
+        phrases: &[
+            "ich will",
+            "ich möchte",
+            "ich brauche",
+            "bitte",
+            "kannst du",
+            "könntest du",
+            "zeig mir",
+            "hilf mir",
+            "gib mir",
+        ],
+        common: &[
+            "der", "die", "das", "und", "ist", "sind", "alle", "mit", "von", "im", "den", "ein",
+            "eine",
+        ],
+    },
+    Lexicon {
+        language: Language {
+            code: "fr",
+            name: "French",
+        },
+        openers: &[
+            "que", "quoi", "comment", "pourquoi", "quand", "où", "qui", "quel", "quelle", "quels",
+            "quelles", "montre", "affiche", "trouve", "liste", "explique", "décris",
+        ],
+        phrases: &[
+            "je veux",
+            "j'ai besoin",
+            "s'il te plaît",
+            "s'il vous plaît",
+            "peux-tu",
+            "pouvez-vous",
+            "montre-moi",
+            "aide-moi",
+            "donne-moi",
+        ],
+        common: &[
+            "le", "la", "les", "et", "des", "du", "est", "sont", "tous", "toutes", "avec", "dans",
+            "un", "une",
+        ],
+    },
+    Lexicon {
+        language: Language {
+            code: "es",
+            name: "Spanish",
+        },
+        openers: &[
+            "qué",
+            "que",
+            "cómo",
+            "como",
+            "cuándo",
+            "cuando",
+            "dónde",
+            "donde",
+            "quién",
+            "cuál",
+            "muestra",
+            "muéstrame",
+            "busca",
+            "encuentra",
+            "lista",
+            "explica",
+            "describe",
+        ],
+        phrases: &[
+            "por qué",
+            "quiero",
+            "necesito",
+            "por favor",
+            "puedes",
+            "podrías",
+            "ayúdame",
+            "dame",
+        ],
+        common: &[
+            "el", "la", "los", "las", "y", "de", "del", "es", "son", "todos", "todas", "con", "en",
+            "un", "una",
+        ],
+    },
+    Lexicon {
+        language: Language {
+            code: "pt",
+            name: "Portuguese",
+        },
+        openers: &[
+            "que", "como", "quando", "onde", "quem", "qual", "quais", "mostre", "mostra",
+            "encontre", "liste", "explique", "descreva",
+        ],
+        phrases: &[
+            "o que",
+            "por que",
+            "eu quero",
+            "preciso",
+            "por favor",
+            "você pode",
+            "me ajude",
+            "me mostre",
+            "me dê",
+        ],
+        common: &[
+            "o", "a", "os", "as", "e", "de", "do", "da", "é", "são", "todos", "com", "em", "um",
+            "uma",
+        ],
+    },
+    Lexicon {
+        language: Language {
+            code: "it",
+            name: "Italian",
+        },
+        openers: &[
+            "cosa", "che", "come", "perché", "quando", "dove", "chi", "quale", "quali", "mostra",
+            "mostrami", "trova", "elenca", "spiega", "descrivi",
+        ],
+        phrases: &[
+            "voglio",
+            "ho bisogno",
+            "per favore",
+            "puoi",
+            "potresti",
+            "aiutami",
+            "dammi",
+        ],
+        common: &[
+            "il", "lo", "la", "i", "gli", "le", "e", "di", "del", "è", "sono", "tutti", "con",
+            "un", "una",
+        ],
+    },
+    Lexicon {
+        language: Language {
+            code: "nl",
+            name: "Dutch",
+        },
+        openers: &[
+            "wat", "hoe", "waarom", "wanneer", "waar", "wie", "welke", "toon", "vind", "zoek",
+        ],
+        phrases: &[
+            "ik wil",
+            "ik moet",
+            "alsjeblieft",
+            "kun je",
+            "kan je",
+            "help me",
+            "laat me zien",
+            "geef me",
+        ],
+        common: &[
+            "de", "het", "een", "en", "van", "is", "zijn", "alle", "met", "in",
+        ],
+    },
+    Lexicon {
+        language: Language {
+            code: "pl",
+            name: "Polish",
+        },
+        openers: &[
+            "co",
+            "jak",
+            "dlaczego",
+            "kiedy",
+            "gdzie",
+            "kto",
+            "który",
+            "która",
+            "które",
+            "pokaż",
+            "znajdź",
+            "wyświetl",
+            "wyjaśnij",
+            "opisz",
+        ],
+        phrases: &[
+            "chcę",
+            "potrzebuję",
+            "proszę",
+            "czy możesz",
+            "pomóż mi",
+            "daj mi",
+        ],
+        common: &[
+            "i",
+            "w",
+            "z",
+            "na",
+            "jest",
+            "są",
+            "wszystkie",
+            "do",
+            "się",
+            "to",
+        ],
+    },
+    Lexicon {
+        language: Language {
+            code: "ru",
+            name: "Russian",
+        },
+        openers: &[
+            "что",
+            "как",
+            "почему",
+            "когда",
+            "где",
+            "кто",
+            "какой",
+            "какая",
+            "какие",
+            "покажи",
+            "найди",
+            "выведи",
+            "объясни",
+            "опиши",
+        ],
+        phrases: &[
+            "я хочу",
+            "мне нужно",
+            "пожалуйста",
+            "можешь",
+            "помоги",
+            "дай мне",
+        ],
+        common: &["и", "в", "на", "с", "все", "это", "из", "для", "файлы"],
+    },
+    Lexicon {
+        language: Language {
+            code: "uk",
+            name: "Ukrainian",
+        },
+        openers: &[
+            "що",
+            "як",
+            "чому",
+            "коли",
+            "де",
+            "хто",
+            "який",
+            "яка",
+            "які",
+            "покажи",
+            "знайди",
+            "виведи",
+            "поясни",
+            "опиши",
+        ],
+        phrases: &[
+            "я хочу",
+            "мені потрібно",
+            "будь ласка",
+            "можеш",
+            "допоможи",
+            "дай мені",
+        ],
+        common: &[
+            "і",
+            "й",
+            "в",
+            "у",
+            "на",
+            "з",
+            "всі",
+            "усі",
+            "це",
+            "для",
+            "файли",
+        ],
+    },
+];
+
+/// Languages recognized by script alone, with the code points of their
+/// letters
+const SCRIPTS: &[(Language, &[(char, char)])] = &[
+    (
+        Language {
+            code: "ru",
+            name: "Russian",
+        },
+        &[('\u{0400}', '\u{04FF}')],
+    ),
+    (
+        Language {
+            code: "el",
+            name: "Greek",
+        },
+        &[('\u{0370}', '\u{03FF}')],
+    ),
+    (
+        Language {
+            code: "he",
+            name: "Hebrew",
+        },
+        &[('\u{0590}', '\u{05FF}')],
+    ),
+    (
+        Language {
+            code: "ar",
+            name: "Arabic",
+        },
+        &[('\u{0600}', '\u{06FF}')],
+    ),
+    (
+        Language {
+            code: "hi",
+            name: "Hindi",
+        },
+        &[('\u{0900}', '\u{097F}')],
+    ),
+    (
+        Language {
+            code: "th",
+            name: "Thai",
+        },
+        &[('\u{0E00}', '\u{0E7F}')],
+    ),
+    (
+        Language {
+            code: "ko",
+            name: "Korean",
+        },
+        &[
+            ('\u{1100}', '\u{11FF}'),
+            ('\u{3130}', '\u{318F}'),
+            ('\u{AC00}', '\u{D7AF}'),
+        ],
+    ),
+    (
+        Language {
+            code: "ja",
+            name: "Japanese",
+        },
+        &[('\u{3040}', '\u{30FF}')],
+    ),
+    (
+        Language {
+            code: "zh",
+            name: "Chinese",
+        },
+        &[('\u{3400}', '\u{4DBF}'), ('\u{4E00}', '\u{9FFF}')],
+    ),
+];
+
+/// Question marks, including Spanish, Arabic and full-width ones
+const QUESTION_MARKS: &[char] = &['?', '¿', '؟', '？'];
+
+/// Decides whether input is natural language, and which language
+pub struct LanguageDetector {
+    lexicons: Vec<&'static Lexicon>,
+}
+
+impl LanguageDetector {
+    /// Consult the lexicons of `languages` (ISO 639-1 codes); when empty,
+    /// English and the language of the system locale
+    pub fn new(languages: &[String]) -> Self {
+        let codes: Vec<String> = if languages.is_empty() {
+            std::iter::once("en".to_string()).chain(locale_language()).collect()
+        } else {
+            languages.iter().map(|code| code.to_lowercase()).collect()
+        };
+
+        let mut lexicons: Vec<&'static Lexicon> = Vec::new();
+        for code in &codes {
+            match LEXICONS.iter().find(|l| l.language.code == code) {
+                Some(lexicon) if !lexicons.iter().any(|l| l.language == lexicon.language) => {
+                    lexicons.push(lexicon)
+                }
+                Some(_) => {}
+                // Script-only languages are recognized without a lexicon
+                None if SCRIPTS.iter().any(|(l, _)| l.code == code) => {}
+                None => warn!("No natural language words for language '{}'", code),
+            }
+        }
+        Self { lexicons }
+    }
+
+    pub fn looks_like_natural_language(&self, input: &str) -> bool {
+        if input.contains(QUESTION_MARKS) {
+            return true;
+        }
+
+        if dominant_script(input).is_some() {
+            return true;
+        }
+
+        // Multiple words with spaces (> 4 words)
+        if input.split_whitespace().count() > 4 {
+            return true;
+        }
+
+        let words = words(input);
+        self.lexicons.iter().any(|lexicon| {
+            words.first().is_some_and(|first| lexicon.openers.contains(&first.as_str()))
+                || lexicon.phrases.iter().any(|phrase| contains_phrase(&words, phrase))
+        })
+    }
+
+    /// The language `input` is written in, if anything marks it
+    pub fn detect(&self, input: &str) -> Option<Language> {
+        let words = words(input);
+        match dominant_script(input) {
+            // Cyrillic is shared; the lexicons tell Ukrainian from Russian
+            Some(script) if script.code == "ru" => {
+                let cyrillic =
+                    LEXICONS.iter().filter(|lexicon| matches!(lexicon.language.code, "ru" | "uk"));
+                Some(best_match(cyrillic, &words).unwrap_or(script))
+            }
+            Some(script) => Some(script),
+            None => best_match(self.lexicons.iter().copied(), &words),
+        }
+    }
+}
+
+/// The language whose words `words` uses most, the first one on a tie
+fn best_match<'a>(
+    lexicons: impl Iterator<Item = &'a Lexicon>,
+    words: &[String],
+) -> Option<Language> {
+    let mut best: Option<(usize, Language)> = None;
+    for lexicon in lexicons {
+        let opener = words.first().is_some_and(|first| lexicon.openers.contains(&first.as_str()));
+        let phrases =
+            lexicon.phrases.iter().filter(|phrase| contains_phrase(words, phrase)).count();
+        let common = words.iter().filter(|word| lexicon.common.contains(&word.as_str())).count();
+        // A lone short word ("la" in `ls -la`) says nothing
+        let score = 2 * (opener as usize + phrases) + common;
+        if score > 1 && best.is_none_or(|(best, _)| score > best) {
+            best = Some((score, lexicon.language));
+        }
+    }
+    best.map(|(_, language)| language)
+}
+
+/// Lowercased words: runs of letters and digits, by Unicode rules
+pub fn words(input: &str) -> Vec<String> {
+    input
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(str::to_lowercase)
+        .collect()
+}
+
+fn contains_phrase(words: &[String], phrase: &str) -> bool {
+    let phrase = self::words(phrase);
+    !phrase.is_empty() && words.windows(phrase.len()).any(|window| window == phrase)
+}
+
+/// The non-Latin script most letters of `input` are written in
+fn dominant_script(input: &str) -> Option<Language> {
+    let mut counts = vec![0usize; SCRIPTS.len()];
+    let mut letters = 0;
+    for c in input.chars().filter(|c| c.is_alphabetic()) {
+        letters += 1;
+        if let Some(i) = SCRIPTS
+            .iter()
+            .position(|(_, ranges)| ranges.iter().any(|&(lo, hi)| (lo..=hi).contains(&c)))
+        {
+            counts[i] += 1;
+        }
+    }
+
+    let japanese = SCRIPTS.iter().position(|(l, _)| l.code == "ja");
+    let chinese = SCRIPTS.iter().position(|(l, _)| l.code == "zh");
+    if let (Some(ja), Some(zh)) = (japanese, chinese) {
+        // Japanese mixes kana with kanji
+        if counts[ja] > 0 {
+            counts[ja] += std::mem::take(&mut counts[zh]);
+        }
+    }
+
+    let (i, &count) = counts.iter().enumerate().max_by_key(|&(_, count)| count)?;
+    (count > 0 && count * 2 > letters).then_some(SCRIPTS[i].0)
+}
+
+/// Language of the system locale (`de` for LANG=de_DE.UTF-8)
+fn locale_language() -> Option<String> {
+    ["LC_ALL", "LC_MESSAGES", "LANG"]
+        .iter()
+        .filter_map(|var| std::env::var(var).ok())
+        .find(|value| !value.is_empty())
+        .map(|value| value.split(['_', '.', '@']).next().unwrap_or_default().to_lowercase())
+        .filter(|code| code.len() == 2)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn detector(languages: &[&str]) -> LanguageDetector {
+        LanguageDetector::new(&languages.iter().map(|l| l.to_string()).collect::<Vec<_>>())
+    }
+
+    #[test]
+    fn test_natural_language_per_language() {
+        let detector = detector(&["en", "de", "fr", "es"]);
+        for input in [
+            "what files are here",
+            "zeige alle Dateien",
+            "Wie groß ist dieser Ordner",
+            "montre-moi les logs",
+            "j'ai besoin d'espace disque",
+            "muéstrame los procesos",
+            "¿dónde está nginx",
+            "покажи файлы",
+            "ディスクの空き容量",
+            "列出所有文件",
+            "디스크 공간 보기",
+        ] {
+            assert!(detector.looks_like_natural_language(input), "{}", input);
+        }
+        for input in [
+            "ls -la",
+            "git status",
+            "make install",
+            "cargo build --release",
+        ] {
+            assert!(!detector.looks_like_natural_language(input), "{}", input);
+        }
+    }
+
+    #[test]
+    fn test_only_configured_languages() {
+        // German question words aren't consulted for English-only users
+        assert!(!detector(&["en"]).looks_like_natural_language("zeige Dateien"));
+        assert!(detector(&["en", "de"]).looks_like_natural_language("zeige Dateien"));
+        // Scripts need no configuration
+        assert!(detector(&["en"]).looks_like_natural_language("покажи файлы"));
+    }
+
+    #[test]
+    fn test_detect() {
+        let detector = detector(&["en", "de", "fr", "es", "uk"]);
+        let code = |input: &str| detector.detect(input).map(|l| l.code);
+
+        assert_eq!(code("show me the disk usage"), Some("en"));
+        assert_eq!(code("zeig mir die größten Dateien"), Some("de"));
+        assert_eq!(code("montre-moi les fichiers"), Some("fr"));
+        assert_eq!(code("busca los archivos grandes"), Some("es"));
+        assert_eq!(code("покажи все файлы"), Some("ru"));
+        assert_eq!(code("покажи усі файли"), Some("uk"));
+        assert_eq!(code("ディスクの空き容量を表示"), Some("ja"));
+        assert_eq!(code("显示磁盘空间"), Some("zh"));
+        assert_eq!(code("ls -la"), None);
+    }
+
+    #[test]
+    fn test_words() {
+        assert_eq!(words("Größe: ÜBER-alles"), ["größe", "über", "alles"]);
+        assert_eq!(words("s'il vous plaît"), ["s", "il", "vous", "plaît"]);
+    }
+}
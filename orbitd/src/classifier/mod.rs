@@ -1,16 +1,31 @@
+pub mod commands;
+pub mod evaluation;
+pub mod language;
+pub mod plugins;
+
 use anyhow::Result;
 use std::collections::HashSet;
-use std::sync::Arc;
+use std::sync::{Arc, RwLock};
 use tracing::debug;
 
 use crate::config::Config;
-use crate::context::Context;
+use crate::context::{Context, ShellDialect};
 use crate::learning::{LearnedCommand, LearningEngine};
+use commands::{on_path, PathStamp, Snapshot};
+use language::LanguageDetector;
+
+pub use commands::{is_package_install, CommandsRefresh};
+pub use language::Language;
+pub use plugins::{ClassifierPlugin, PluginRegistry};
 
 pub struct CommandClassifier {
     config: Arc<Config>,
-    known_commands: HashSet<String>,
+    /// Executables on PATH; None until `warm` has built it
+    known_commands: RwLock<Option<Snapshot>>,
+    /// Natural language in the configured languages
+    languages: LanguageDetector,
     learning_engine: Arc<LearningEngine>,
+    plugins: Arc<PluginRegistry>,
 }
 
 #[derive(Debug, Clone)]
@@ -22,24 +37,84 @@ pub enum CommandType {
 }
 
 impl CommandClassifier {
+    /// The known commands cache is left for `warm`, so the classifier is
+    /// usable straight away
     pub async fn new(config: Arc<Config>, learning_engine: Arc<LearningEngine>) -> Result<Self> {
-        let mut classifier = Self {
+        Ok(Self {
+            languages: LanguageDetector::new(&config.classification.languages),
             config,
-            known_commands: HashSet::new(),
+            known_commands: RwLock::new(None),
             learning_engine,
-        };
+            plugins: Arc::new(PluginRegistry::empty()),
+        })
+    }
 
-        // Build cache of known commands
-        classifier.build_known_commands_cache().await?;
+    /// Build the known commands cache; until then each command is looked
+    /// up on PATH as it comes
+    pub async fn warm(&self) -> Result<()> {
+        self.refresh().await?;
+        Ok(())
+    }
 
-        Ok(classifier)
+    /// Rebuild the known commands cache from PATH
+    pub async fn refresh(&self) -> Result<CommandsRefresh> {
+        let snapshot = tokio::task::spawn_blocking(Snapshot::take).await?;
+        let mut known_commands = self.known_commands.write().unwrap();
+        let refresh = snapshot.changes_since(known_commands.as_ref());
+        debug!(
+            "Cached {} known commands (+{} -{})",
+            refresh.commands,
+            refresh.added.len(),
+            refresh.removed.len()
+        );
+        *known_commands = Some(snapshot);
+        Ok(refresh)
+    }
+
+    /// Whether a PATH directory or package database changed since the
+    /// cache was built; false while there is no cache
+    pub fn is_stale(&self) -> bool {
+        self.known_commands
+            .read()
+            .unwrap()
+            .as_ref()
+            .is_some_and(|snapshot| snapshot.stamp != PathStamp::current())
+    }
+
+    /// The language of natural language `input`, so providers can be
+    /// prompted in it; None when nothing marks one
+    pub fn detect_language(&self, input: &str) -> Option<Language> {
+        self.languages.detect(input)
+    }
+
+    /// Consult these plugins before any built-in classification
+    pub fn with_plugins(mut self, plugins: Arc<PluginRegistry>) -> Self {
+        self.plugins = plugins;
+        self
+    }
+
+    /// Executables found on PATH; empty until the cache is built
+    pub fn known_commands(&self) -> Arc<HashSet<String>> {
+        self.known_commands
+            .read()
+            .unwrap()
+            .as_ref()
+            .map(|snapshot| snapshot.commands.clone())
+            .unwrap_or_default()
     }
 
     pub async fn classify(&self, input: &str, context: &Context) -> Result<CommandType> {
-        let first_word = input.split_whitespace().next().unwrap_or("");
+        let dialect = context.dialect();
+        let first_word = dialect.program(input.split_whitespace().next().unwrap_or(""));
+
+        // 0. Plugins can force a classification
+        if let Some((plugin, class)) = self.plugins.classify(input, context) {
+            debug!("Classified as: {:?} (plugin {})", class, plugin);
+            return Ok(class);
+        }
 
         // 1. Check if it's a known command
-        if self.is_known_command(first_word) {
+        if self.is_known_command(first_word, dialect) {
             debug!("Classified as: Known command");
             return Ok(CommandType::Known);
         }
@@ -56,7 +131,7 @@ impl CommandClassifier {
         }
 
         // 3. Check if it looks like natural language
-        if self.looks_like_natural_language(input) {
+        if self.languages.looks_like_natural_language(input) {
             debug!("Classified as: Natural language");
             return Ok(CommandType::NaturalLanguage);
         }
@@ -66,155 +141,28 @@ impl CommandClassifier {
         Ok(CommandType::Ambiguous)
     }
 
-    fn is_known_command(&self, cmd: &str) -> bool {
-        // Check cache
-        if self.known_commands.contains(cmd) {
-            return true;
-        }
-
-        // Check shell builtins
-        if self.is_shell_builtin(cmd) {
+    fn is_known_command(&self, cmd: &str, dialect: ShellDialect) -> bool {
+        // Check cache, or PATH itself while there is none
+        let known = match self.known_commands.read().unwrap().as_ref() {
+            Some(snapshot) => snapshot.commands.contains(cmd),
+            None => on_path(cmd),
+        };
+        if known {
             return true;
         }
 
-        // Check if it's a path (./script or /usr/bin/app)
-        if cmd.starts_with("./") || cmd.starts_with('/') {
+        // Check the shell's own builtins
+        if dialect.is_builtin(cmd) {
             return true;
         }
 
-        false
-    }
-
-    fn is_shell_builtin(&self, cmd: &str) -> bool {
-        // Common shell builtins
-        matches!(
-            cmd,
-            "cd" | "export"
-                | "alias"
-                | "source"
-                | "."
-                | "echo"
-                | "pwd"
-                | "exit"
-                | "history"
-                | "jobs"
-                | "fg"
-                | "bg"
-                | "kill"
-                | "wait"
-                | "read"
-                | "test"
-                | "["
-                | "eval"
-                | "exec"
-                | "set"
-                | "unset"
-                | "shift"
-                | "return"
-                | "break"
-                | "continue"
-                | "trap"
-                | "ulimit"
-                | "umask"
-                | "type"
-                | "command"
-                | "builtin"
-                | "enable"
-                | "help"
-                | "let"
-                | "local"
-                | "declare"
-                | "typeset"
-                | "readonly"
-                | "unalias"
-        )
-    }
-
-    fn looks_like_natural_language(&self, input: &str) -> bool {
-        let input_lower = input.to_lowercase();
-
-        // Question words
-        let question_words = [
-            "what", "how", "why", "when", "where", "who", "tell", "show", "find", "list", "get",
-            "explain", "describe", "can you",
-        ];
-
-        // Check for question words at start
-        for word in &question_words {
-            if input_lower.starts_with(word) {
-                return true;
-            }
-        }
-
-        // Contains question mark
-        if input.contains('?') {
-            return true;
-        }
-
-        // Multiple words with spaces (> 4 words)
-        if input.split_whitespace().count() > 4 {
+        // Check if it's a path (./script, /usr/bin/app, C:\tools\app.exe)
+        if dialect.is_path(cmd) {
             return true;
         }
 
-        // Contains common conversational phrases
-        let conversational = [
-            "i want",
-            "i need",
-            "please",
-            "could you",
-            "would you",
-            "can you",
-            "help me",
-            "show me",
-            "tell me",
-            "give me",
-        ];
-
-        for phrase in &conversational {
-            if input_lower.contains(phrase) {
-                return true;
-            }
-        }
-
         false
     }
-
-    async fn build_known_commands_cache(&mut self) -> Result<()> {
-        // Get all executables in PATH
-        if let Ok(path_var) = std::env::var("PATH") {
-            for path_dir in path_var.split(':') {
-                if let Ok(entries) = std::fs::read_dir(path_dir) {
-                    for entry in entries.flatten() {
-                        if let Ok(file_name) = entry.file_name().into_string() {
-                            // Check if executable
-                            if entry.path().is_file() {
-                                #[cfg(unix)]
-                                {
-                                    use std::os::unix::fs::PermissionsExt;
-                                    if let Ok(metadata) = entry.metadata() {
-                                        let permissions = metadata.permissions();
-                                        // Check if owner has execute permission
-                                        if permissions.mode() & 0o100 != 0 {
-                                            self.known_commands.insert(file_name);
-                                        }
-                                    }
-                                }
-
-                                #[cfg(not(unix))]
-                                {
-                                    self.known_commands.insert(file_name);
-                                }
-                            }
-                        }
-                    }
-                }
-            }
-        }
-
-        debug!("Cached {} known commands", self.known_commands.len());
-
-        Ok(())
-    }
 }
 
 #[cfg(test)]
@@ -225,6 +173,13 @@ mod tests {
     use tempfile::TempDir;
 
     async fn create_test_classifier() -> CommandClassifier {
+        let classifier = create_cold_classifier().await;
+        classifier.warm().await.unwrap();
+        classifier
+    }
+
+    /// A classifier whose known commands cache isn't built yet
+    async fn create_cold_classifier() -> CommandClassifier {
         // Use unique temp dir for each test to avoid parallel test conflicts
         let temp_dir = TempDir::new().unwrap();
         let temp_path = temp_dir.path().to_path_buf();
@@ -302,11 +257,32 @@ ui:
             recent_commands: vec![],
             project_type: None,
             directory_type: DirectoryType::Other,
+            host: None,
         }
     }
 
     // ========== Initialization Tests ==========
 
+    #[tokio::test]
+    async fn test_plugins_run_before_heuristics() {
+        let plugins = Arc::new(PluginRegistry::empty());
+        plugins.register(Arc::new(
+            plugins::RulePlugin::from_yaml(
+                "name: force-ai\nrules:\n  - pattern: \"^ls \"\n    classify: natural_language\n",
+            )
+            .unwrap(),
+        ));
+        let classifier = create_test_classifier().await.with_plugins(plugins);
+        let context = create_test_context();
+
+        // `ls` is a known command, but the plugin wins
+        let result = classifier.classify("ls everything", &context).await.unwrap();
+        assert!(matches!(result, CommandType::NaturalLanguage));
+
+        let result = classifier.classify("pwd", &context).await.unwrap();
+        assert!(matches!(result, CommandType::Known));
+    }
+
     #[tokio::test]
     async fn test_classifier_initialization() {
         let classifier = create_test_classifier().await;
@@ -320,7 +296,7 @@ ui:
         // We can't assert exact count because it depends on the system
         // but we can check that it found at least some common commands
         assert!(
-            !classifier.known_commands.is_empty(),
+            !classifier.known_commands().is_empty(),
             "Should have cached some commands from PATH"
         );
     }
@@ -334,7 +310,7 @@ ui:
 
         let mut found_count = 0;
         for cmd in &common_commands {
-            if classifier.known_commands.contains(*cmd) {
+            if classifier.known_commands().contains(*cmd) {
                 found_count += 1;
             }
         }
@@ -353,23 +329,23 @@ ui:
 
         // Test common shell builtins
         assert!(
-            classifier.is_known_command("cd"),
+            classifier.is_known_command("cd", ShellDialect::Posix),
             "Should recognize 'cd' as builtin"
         );
         assert!(
-            classifier.is_known_command("echo"),
+            classifier.is_known_command("echo", ShellDialect::Posix),
             "Should recognize 'echo' as builtin"
         );
         assert!(
-            classifier.is_known_command("pwd"),
+            classifier.is_known_command("pwd", ShellDialect::Posix),
             "Should recognize 'pwd' as builtin"
         );
         assert!(
-            classifier.is_known_command("export"),
+            classifier.is_known_command("export", ShellDialect::Posix),
             "Should recognize 'export' as builtin"
         );
         assert!(
-            classifier.is_known_command("alias"),
+            classifier.is_known_command("alias", ShellDialect::Posix),
             "Should recognize 'alias' as builtin"
         );
     }
@@ -380,19 +356,19 @@ ui:
 
         // Path-based commands should be recognized
         assert!(
-            classifier.is_known_command("./script.sh"),
+            classifier.is_known_command("./script.sh", ShellDialect::Posix),
             "Should recognize relative path commands"
         );
         assert!(
-            classifier.is_known_command("/usr/bin/python3"),
+            classifier.is_known_command("/usr/bin/python3", ShellDialect::Posix),
             "Should recognize absolute path commands"
         );
         assert!(
-            classifier.is_known_command("./test"),
+            classifier.is_known_command("./test", ShellDialect::Posix),
             "Should recognize ./ prefix"
         );
         assert!(
-            classifier.is_known_command("/bin/bash"),
+            classifier.is_known_command("/bin/bash", ShellDialect::Posix),
             "Should recognize / prefix"
         );
     }
@@ -402,29 +378,45 @@ ui:
         let classifier = create_test_classifier().await;
 
         // If ls is in PATH (it should be), it should be in the cache
-        if classifier.known_commands.contains("ls") {
+        if classifier.known_commands().contains("ls") {
             assert!(
-                classifier.is_known_command("ls"),
+                classifier.is_known_command("ls", ShellDialect::Posix),
                 "Should recognize cached command 'ls'"
             );
         }
     }
 
+    #[tokio::test]
+    async fn test_known_commands_before_warm_up() {
+        let classifier = create_cold_classifier().await;
+        assert!(classifier.known_commands().is_empty());
+
+        // Looked up on PATH until the cache is built
+        assert_eq!(
+            classifier.is_known_command("ls", ShellDialect::Posix),
+            on_path("ls")
+        );
+        assert!(!classifier.is_known_command("nonexistent_command_xyz", ShellDialect::Posix));
+
+        classifier.warm().await.unwrap();
+        assert_eq!(classifier.known_commands().contains("ls"), on_path("ls"));
+    }
+
     #[tokio::test]
     async fn test_is_known_command_false_cases() {
         let classifier = create_test_classifier().await;
 
         // These should NOT be recognized as known commands
         assert!(
-            !classifier.is_known_command("asdfqwerzxcv"),
+            !classifier.is_known_command("asdfqwerzxcv", ShellDialect::Posix),
             "Should not recognize random string as command"
         );
         assert!(
-            !classifier.is_known_command("find files in directory"),
+            !classifier.is_known_command("find files in directory", ShellDialect::Posix),
             "Should not recognize natural language as command"
         );
         assert!(
-            !classifier.is_known_command("nonexistent_command_xyz"),
+            !classifier.is_known_command("nonexistent_command_xyz", ShellDialect::Posix),
             "Should not recognize nonexistent command"
         );
     }
@@ -694,7 +686,7 @@ ui:
 
         for builtin in &builtins {
             assert!(
-                classifier.is_known_command(builtin),
+                classifier.is_known_command(builtin, ShellDialect::Posix),
                 "Should recognize '{}' as shell builtin",
                 builtin
             );
@@ -708,11 +700,11 @@ ui:
         // These should NOT be recognized as builtins (but might be in PATH cache)
         // We'll check against commands that definitely don't exist
         assert!(
-            !classifier.is_known_command("nonexistent_xyz_123"),
+            !classifier.is_known_command("nonexistent_xyz_123", ShellDialect::Posix),
             "'nonexistent_xyz_123' should not be recognized"
         );
         assert!(
-            !classifier.is_known_command("random_command_abc"),
+            !classifier.is_known_command("random_command_abc", ShellDialect::Posix),
             "'random_command_abc' should not be recognized"
         );
     }
@@ -771,4 +763,51 @@ ui:
             "Should classify 'echo' with arguments as Known"
         );
     }
+
+    #[tokio::test]
+    async fn test_classify_other_languages() {
+        let classifier = create_test_classifier().await;
+        let context = create_test_context();
+
+        let result = classifier.classify("покажи все файлы", &context).await.unwrap();
+        assert!(matches!(result, CommandType::NaturalLanguage));
+        assert_eq!(
+            classifier.detect_language("покажи все файлы").map(|l| l.code),
+            Some("ru")
+        );
+
+        let result = classifier.classify("¿dónde está", &context).await.unwrap();
+        assert!(matches!(result, CommandType::NaturalLanguage));
+    }
+
+    #[tokio::test]
+    async fn test_classify_per_shell_dialect() {
+        let classifier = create_test_classifier().await;
+        let in_shell = |shell: &str| Context {
+            shell_name: shell.to_string(),
+            ..create_test_context()
+        };
+
+        for (shell, input) in [
+            ("/usr/bin/fish", "set_color red"),
+            ("pwsh", "Get-ChildItem -Recurse -Filter *.log"),
+            ("pwsh", r".\build.ps1 -Release"),
+            ("nu", "open Cargo.toml"),
+            ("nu", "$env.PATH"),
+        ] {
+            let result = classifier.classify(input, &in_shell(shell)).await.unwrap();
+            assert!(
+                matches!(result, CommandType::Known),
+                "'{}' should be Known in {}",
+                input,
+                shell
+            );
+        }
+
+        // Not builtins in bash
+        let result = classifier.classify("Get-ChildItem", &in_shell("bash")).await.unwrap();
+        assert!(!matches!(result, CommandType::Known));
+        let result = classifier.classify("set_color red", &in_shell("zsh")).await.unwrap();
+        assert!(!matches!(result, CommandType::Known));
+    }
 }
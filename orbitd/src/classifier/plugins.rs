@@ -0,0 +1,313 @@
+// Classifier plugins
+//
+// Plugins run before the built-in heuristics and can force how an input is
+// classified, e.g. so internal tool names that aren't on PATH are treated
+// as commands instead of being sent to an AI provider.
+//
+// Rule plugins are YAML files in the plugins directory:
+//
+//   name: internal-tools
+//   rules:
+//     - pattern: "^(acmectl|deployer)\\b"
+//       classify: command
+//     - pattern: "^ask "
+//       classify: natural_language
+//
+// The directory is watched and plugins are reloaded when files change.
+
+use anyhow::{Context as _, Result};
+use notify::{RecommendedWatcher, RecursiveMode, Watcher};
+use regex::Regex;
+use serde::Deserialize;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock};
+use tracing::{debug, info, warn};
+
+use super::CommandType;
+use crate::context::Context;
+
+/// A custom classification rule set
+pub trait ClassifierPlugin: Send + Sync {
+    /// Name shown in logs
+    fn name(&self) -> &str;
+
+    /// Force a classification, or `None` to defer to later plugins and
+    /// the built-in heuristics
+    fn classify(&self, input: &str, context: &Context) -> Option<CommandType>;
+}
+
+/// Classification a rule forces
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ForcedClass {
+    /// Run the input as a shell command
+    Command,
+    /// Send the input to the AI as natural language
+    NaturalLanguage,
+}
+
+impl From<ForcedClass> for CommandType {
+    fn from(class: ForcedClass) -> Self {
+        match class {
+            ForcedClass::Command => CommandType::Known,
+            ForcedClass::NaturalLanguage => CommandType::NaturalLanguage,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct RuleFile {
+    name: String,
+    rules: Vec<RuleSpec>,
+}
+
+#[derive(Debug, Deserialize)]
+struct RuleSpec {
+    pattern: String,
+    classify: ForcedClass,
+}
+
+/// Regex rules loaded from a YAML file; the first matching rule wins
+pub struct RulePlugin {
+    name: String,
+    rules: Vec<(Regex, ForcedClass)>,
+}
+
+impl RulePlugin {
+    /// Parse a rule file
+    pub fn from_yaml(yaml: &str) -> Result<Self> {
+        let file: RuleFile = serde_yaml::from_str(yaml).context("Invalid rule plugin")?;
+
+        let rules = file
+            .rules
+            .into_iter()
+            .map(|rule| {
+                Regex::new(&rule.pattern)
+                    .map(|regex| (regex, rule.classify))
+                    .with_context(|| format!("Invalid pattern '{}' in plugin {}", rule.pattern, file.name))
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        Ok(Self {
+            name: file.name,
+            rules,
+        })
+    }
+
+    /// Load a rule file from disk
+    pub fn load(path: &Path) -> Result<Self> {
+        let yaml = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read plugin {}", path.display()))?;
+        Self::from_yaml(&yaml)
+    }
+}
+
+impl ClassifierPlugin for RulePlugin {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn classify(&self, input: &str, _context: &Context) -> Option<CommandType> {
+        self.rules
+            .iter()
+            .find(|(regex, _)| regex.is_match(input))
+            .map(|(_, class)| (*class).into())
+    }
+}
+
+/// Plugins consulted by the classifier, in order
+pub struct PluginRegistry {
+    dir: Option<PathBuf>,
+    /// Plugins registered in code; kept across reloads
+    builtin: RwLock<Vec<Arc<dyn ClassifierPlugin>>>,
+    /// Plugins loaded from the plugins directory
+    loaded: RwLock<Vec<Arc<dyn ClassifierPlugin>>>,
+}
+
+impl PluginRegistry {
+    /// A registry without a plugins directory
+    pub fn empty() -> Self {
+        Self {
+            dir: None,
+            builtin: RwLock::new(Vec::new()),
+            loaded: RwLock::new(Vec::new()),
+        }
+    }
+
+    /// Load plugins from `dir` (created if missing)
+    pub fn from_dir(dir: PathBuf) -> Result<Self> {
+        std::fs::create_dir_all(&dir)
+            .with_context(|| format!("Failed to create plugins directory {}", dir.display()))?;
+
+        let registry = Self {
+            dir: Some(dir),
+            ..Self::empty()
+        };
+        registry.reload();
+        Ok(registry)
+    }
+
+    /// Add a plugin implemented in code; it runs before directory plugins
+    pub fn register(&self, plugin: Arc<dyn ClassifierPlugin>) {
+        info!("Registered classifier plugin {}", plugin.name());
+        self.builtin.write().unwrap().push(plugin);
+    }
+
+    /// Re-read the plugins directory
+    ///
+    /// Files that fail to load are skipped with a warning so one bad
+    /// plugin doesn't disable the others.
+    pub fn reload(&self) {
+        let Some(dir) = &self.dir else {
+            return;
+        };
+
+        let mut paths: Vec<PathBuf> = match std::fs::read_dir(dir) {
+            Ok(entries) => entries.flatten().map(|e| e.path()).collect(),
+            Err(e) => {
+                warn!("Failed to read plugins directory {}: {}", dir.display(), e);
+                return;
+            }
+        };
+        // Load order (and so precedence) follows file names
+        paths.sort();
+
+        let mut loaded: Vec<Arc<dyn ClassifierPlugin>> = Vec::new();
+        for path in paths {
+            let is_rule_file = path
+                .extension()
+                .is_some_and(|ext| ext == "yaml" || ext == "yml");
+            if !is_rule_file {
+                continue;
+            }
+
+            match RulePlugin::load(&path) {
+                Ok(plugin) => {
+                    debug!("Loaded classifier plugin {} from {}", plugin.name(), path.display());
+                    loaded.push(Arc::new(plugin));
+                }
+                Err(e) => warn!("Skipping classifier plugin {}: {:#}", path.display(), e),
+            }
+        }
+
+        info!("Loaded {} classifier plugins from {}", loaded.len(), dir.display());
+        *self.loaded.write().unwrap() = loaded;
+    }
+
+    /// Reload whenever the plugins directory changes
+    ///
+    /// Dropping the returned watcher stops hot-reload.
+    pub fn watch(self: &Arc<Self>) -> Result<Option<RecommendedWatcher>> {
+        let Some(dir) = self.dir.clone() else {
+            return Ok(None);
+        };
+
+        let registry = Arc::downgrade(self);
+        let mut watcher = notify::recommended_watcher(move |event: notify::Result<notify::Event>| {
+            match event {
+                Ok(event) if !event.kind.is_access() => {
+                    if let Some(registry) = registry.upgrade() {
+                        registry.reload();
+                    }
+                }
+                Ok(_) => {}
+                Err(e) => warn!("Plugin directory watch error: {}", e),
+            }
+        })?;
+        watcher.watch(&dir, RecursiveMode::NonRecursive)?;
+
+        Ok(Some(watcher))
+    }
+
+    /// First classification forced by a plugin, if any
+    pub fn classify(&self, input: &str, context: &Context) -> Option<(String, CommandType)> {
+        let builtin = self.builtin.read().unwrap();
+        let loaded = self.loaded.read().unwrap();
+
+        builtin.iter().chain(loaded.iter()).find_map(|plugin| {
+            plugin
+                .classify(input, context)
+                .map(|class| (plugin.name().to_string(), class))
+        })
+    }
+
+    /// Number of active plugins
+    pub fn len(&self) -> usize {
+        self.builtin.read().unwrap().len() + self.loaded.read().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl Default for PluginRegistry {
+    fn default() -> Self {
+        Self::empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::context::DirectoryType;
+    use tempfile::TempDir;
+
+    fn context() -> Context {
+        Context {
+            os_name: "linux".to_string(),
+            os_version: "6.0".to_string(),
+            shell_name: "zsh".to_string(),
+            shell_version: "5.9".to_string(),
+            pwd: PathBuf::from("/"),
+            username: "dev".to_string(),
+            git_context: None,
+            detected_languages: vec![],
+            recent_commands: vec![],
+            project_type: None,
+            directory_type: DirectoryType::Other,
+            host: None,
+        }
+    }
+
+    const INTERNAL_TOOLS: &str = r#"
+name: internal-tools
+rules:
+  - pattern: "^(acmectl|deployer)\\b"
+    classify: command
+  - pattern: "^ask "
+    classify: natural_language
+"#;
+
+    #[test]
+    fn test_rule_plugin_first_match_wins() {
+        let plugin = RulePlugin::from_yaml(INTERNAL_TOOLS).unwrap();
+
+        assert!(matches!(plugin.classify("acmectl rollout", &context()), Some(CommandType::Known)));
+        assert!(matches!(
+            plugin.classify("ask how do I rebase", &context()),
+            Some(CommandType::NaturalLanguage)
+        ));
+        assert!(plugin.classify("ls -la", &context()).is_none());
+
+        assert!(RulePlugin::from_yaml("name: bad\nrules:\n  - pattern: \"(\"\n    classify: command\n").is_err());
+    }
+
+    #[test]
+    fn test_registry_reloads_directory_and_skips_bad_files() {
+        let dir = TempDir::new().unwrap();
+        std::fs::write(dir.path().join("10-tools.yaml"), INTERNAL_TOOLS).unwrap();
+        std::fs::write(dir.path().join("20-broken.yaml"), "rules: [").unwrap();
+        std::fs::write(dir.path().join("README.md"), "not a plugin").unwrap();
+
+        let registry = PluginRegistry::from_dir(dir.path().to_path_buf()).unwrap();
+        assert_eq!(registry.len(), 1);
+        let (name, _) = registry.classify("deployer ship", &context()).unwrap();
+        assert_eq!(name, "internal-tools");
+
+        std::fs::remove_file(dir.path().join("10-tools.yaml")).unwrap();
+        registry.reload();
+        assert!(registry.is_empty());
+        assert!(registry.classify("deployer ship", &context()).is_none());
+    }
+}
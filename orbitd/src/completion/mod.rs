@@ -0,0 +1,320 @@
+// Shell completion engine
+//
+// Ranks completions for a partial command line from, in order:
+// - Arguments the user ran before in the same position (learned)
+// - Built-in subcommands and flags for common programs (`specs`)
+// - Files in the shell session's working directory
+// - Executables on PATH, in command position
+//
+// Completion runs on every keypress, so it must answer well within
+// LATENCY_TARGET: learned commands are read from the database at most
+// every LEARNED_REFRESH and directory scans are capped.
+
+pub mod scripts;
+pub mod specs;
+
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tracing::{debug, warn};
+
+use crate::classifier::CommandClassifier;
+use crate::learning::LearningEngine;
+
+/// Latency budget for one completion request
+const LATENCY_TARGET: Duration = Duration::from_millis(50);
+
+/// How long learned commands are cached between database reads
+const LEARNED_REFRESH: Duration = Duration::from_secs(30);
+
+/// Learned commands considered for ranking
+const LEARNED_LIMIT: usize = 2000;
+
+/// Directory entries scanned for path completion
+const MAX_DIR_ENTRIES: usize = 5000;
+
+/// Completions returned per request
+const MAX_RESULTS: usize = 50;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CompletionKind {
+    Command,
+    Subcommand,
+    Flag,
+    /// An argument the user has used before in this position
+    Learned,
+    Path,
+}
+
+/// One candidate for the word under the cursor
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Completion {
+    /// Replacement for the whole current word
+    pub text: String,
+    pub kind: CompletionKind,
+    pub description: Option<String>,
+    pub score: f32,
+}
+
+struct LearnedCache {
+    loaded_at: Instant,
+    commands: Arc<Vec<(Vec<String>, i64)>>,
+}
+
+pub struct CompletionEngine {
+    classifier: Arc<CommandClassifier>,
+    learning_engine: Arc<LearningEngine>,
+    learned: Mutex<Option<LearnedCache>>,
+}
+
+impl CompletionEngine {
+    pub fn new(classifier: Arc<CommandClassifier>, learning_engine: Arc<LearningEngine>) -> Self {
+        Self {
+            classifier,
+            learning_engine,
+            learned: Mutex::new(None),
+        }
+    }
+
+    /// Ranked completions for `line` up to `cursor` (a byte offset;
+    /// defaults to the end), resolving paths against `cwd`
+    pub async fn complete(&self, line: &str, cursor: Option<usize>, cwd: &Path) -> Result<Vec<Completion>> {
+        let started = Instant::now();
+
+        let line = match cursor {
+            Some(cursor) => line
+                .get(..cursor)
+                .ok_or_else(|| anyhow!("Cursor {} is not a character boundary", cursor))?,
+            None => line,
+        };
+        let learned = self.learned().await?;
+        let completions = rank(line, cwd, &self.classifier.known_commands(), &learned);
+
+        let elapsed = started.elapsed();
+        if elapsed > LATENCY_TARGET {
+            warn!("Completion took {:?} (target {:?}) for '{}'", elapsed, LATENCY_TARGET, line);
+        } else {
+            debug!("Completion took {:?}", elapsed);
+        }
+
+        Ok(completions)
+    }
+
+    async fn learned(&self) -> Result<Arc<Vec<(Vec<String>, i64)>>> {
+        if let Some(cache) = self.learned.lock().unwrap().as_ref() {
+            if cache.loaded_at.elapsed() < LEARNED_REFRESH {
+                return Ok(cache.commands.clone());
+            }
+        }
+
+        let commands: Arc<Vec<_>> = Arc::new(
+            self.learning_engine
+                .command_frequencies(LEARNED_LIMIT)
+                .await?
+                .into_iter()
+                .map(|(command, count)| {
+                    (command.split_whitespace().map(str::to_string).collect(), count)
+                })
+                .collect(),
+        );
+
+        *self.learned.lock().unwrap() = Some(LearnedCache {
+            loaded_at: Instant::now(),
+            commands: commands.clone(),
+        });
+        Ok(commands)
+    }
+}
+
+fn rank(
+    line: &str,
+    cwd: &Path,
+    known_commands: &HashSet<String>,
+    learned: &[(Vec<String>, i64)],
+) -> Vec<Completion> {
+    let mut words: Vec<&str> = line.split_whitespace().collect();
+    let current = if line.is_empty() || line.ends_with(char::is_whitespace) {
+        ""
+    } else {
+        words.pop().unwrap_or_default()
+    };
+
+    let mut completions = Vec::new();
+
+    // Learned: the word that followed the same leading words before
+    for (command, count) in learned {
+        let follows = command.len() > words.len()
+            && command.iter().zip(&words).all(|(a, b)| a == b)
+            && command[words.len()].starts_with(current);
+        if follows {
+            completions.push(Completion {
+                text: command[words.len()].clone(),
+                kind: CompletionKind::Learned,
+                description: Some(format!("used {} times", count)),
+                score: 3.0 + (*count as f32).ln_1p(),
+            });
+        }
+    }
+
+    if words.is_empty() {
+        // Listing every executable for an empty word isn't useful
+        if !current.is_empty() {
+            completions.extend(known_commands.iter().filter(|c| c.starts_with(current)).map(|c| {
+                Completion {
+                    text: c.clone(),
+                    kind: CompletionKind::Command,
+                    description: None,
+                    score: 1.0,
+                }
+            }));
+        }
+        if current.contains('/') {
+            completions.extend(paths(current, cwd));
+        }
+    } else {
+        if let Some(spec) = specs::lookup(&words) {
+            let at_key = spec.key.split(' ').count() == words.len();
+            let (candidates, kind) = if current.starts_with('-') {
+                (spec.flags, CompletionKind::Flag)
+            } else if at_key {
+                (spec.subcommands, CompletionKind::Subcommand)
+            } else {
+                (&[][..], CompletionKind::Subcommand)
+            };
+            completions.extend(candidates.iter().filter(|(text, _)| text.starts_with(current)).map(
+                |(text, description)| Completion {
+                    text: text.to_string(),
+                    kind,
+                    description: Some(description.to_string()),
+                    score: 2.0,
+                },
+            ));
+        }
+        if !current.starts_with('-') {
+            completions.extend(paths(current, cwd));
+        }
+    }
+
+    // Best score first; a word suggested by several sources appears once
+    completions.sort_by(|a, b| b.score.total_cmp(&a.score).then_with(|| a.text.cmp(&b.text)));
+    let mut seen = HashSet::new();
+    completions.retain(|c| seen.insert(c.text.clone()));
+    completions.truncate(MAX_RESULTS);
+    completions
+}
+
+/// Files and directories matching `current`, relative to `cwd`
+fn paths(current: &str, cwd: &Path) -> Vec<Completion> {
+    let (dir_part, prefix) = match current.rfind('/') {
+        Some(i) => current.split_at(i + 1),
+        None => ("", current),
+    };
+    let dir = match dir_part.strip_prefix("~/") {
+        Some(rest) => match dirs::home_dir() {
+            Some(home) => home.join(rest),
+            None => return Vec::new(),
+        },
+        None => cwd.join(dir_part),
+    };
+
+    let Ok(entries) = std::fs::read_dir(&dir) else {
+        return Vec::new();
+    };
+
+    entries
+        .flatten()
+        .take(MAX_DIR_ENTRIES)
+        .filter_map(|entry| {
+            let name = entry.file_name().into_string().ok()?;
+            // Hidden files only when asked for
+            if !name.starts_with(prefix) || (name.starts_with('.') && !prefix.starts_with('.')) {
+                return None;
+            }
+            let is_dir = entry.file_type().is_ok_and(|t| t.is_dir());
+            Some(Completion {
+                text: format!("{}{}{}", dir_part, name, if is_dir { "/" } else { "" }),
+                kind: CompletionKind::Path,
+                description: None,
+                score: if is_dir { 1.2 } else { 1.0 },
+            })
+        })
+        .collect()
+}
+
+/// Ask the running daemon for completions (used by the shell bridge scripts)
+#[cfg(unix)]
+pub async fn request(socket_path: &Path, line: &str, cwd: &Path) -> Result<Vec<Completion>> {
+    use crate::daemon::ipc::{call, Request, Response};
+
+    let request = Request::Complete {
+        line: line.to_string(),
+        cursor: None,
+        cwd: cwd.display().to_string(),
+    };
+
+    match call(socket_path, &request).await? {
+        Response::Completions { items } => Ok(items),
+        Response::Error { message } => Err(anyhow!(message)),
+        other => Err(anyhow!("Unexpected response: {:?}", other)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn learned() -> Vec<(Vec<String>, i64)> {
+        [("git push origin main", 12), ("git push upstream dev", 2), ("kubectl get pods", 4)]
+            .iter()
+            .map(|(c, n)| (c.split_whitespace().map(str::to_string).collect(), *n))
+            .collect()
+    }
+
+    fn texts(completions: &[Completion]) -> Vec<&str> {
+        completions.iter().map(|c| c.text.as_str()).collect()
+    }
+
+    #[test]
+    fn test_learned_arguments_rank_first() {
+        let dir = TempDir::new().unwrap();
+        let known = HashSet::from(["git".to_string(), "gitk".to_string(), "go".to_string()]);
+
+        let items = rank("git push ", dir.path(), &known, &learned());
+        assert_eq!(texts(&items), vec!["origin", "upstream"]);
+        assert_eq!(items[0].kind, CompletionKind::Learned);
+
+        let items = rank("gi", dir.path(), &known, &learned());
+        assert_eq!(texts(&items), vec!["git", "gitk"]);
+        assert_eq!(items[0].kind, CompletionKind::Learned);
+
+        let items = rank("git st", dir.path(), &known, &learned());
+        assert_eq!(texts(&items), vec!["stash", "status"]);
+
+        let items = rank("git commit --a", dir.path(), &known, &learned());
+        assert_eq!(texts(&items), vec!["--amend"]);
+        assert_eq!(items[0].kind, CompletionKind::Flag);
+    }
+
+    #[test]
+    fn test_paths_resolve_against_session_cwd() {
+        let dir = TempDir::new().unwrap();
+        std::fs::create_dir(dir.path().join("src")).unwrap();
+        std::fs::write(dir.path().join("src").join("main.rs"), "").unwrap();
+        std::fs::write(dir.path().join("Cargo.toml"), "").unwrap();
+        std::fs::write(dir.path().join(".env"), "").unwrap();
+
+        let items = rank("cat ", dir.path(), &HashSet::new(), &[]);
+        assert_eq!(texts(&items), vec!["src/", "Cargo.toml"]);
+
+        let items = rank("cat src/m", dir.path(), &HashSet::new(), &[]);
+        assert_eq!(texts(&items), vec!["src/main.rs"]);
+
+        let items = rank("cat .e", dir.path(), &HashSet::new(), &[]);
+        assert_eq!(texts(&items), vec![".env"]);
+    }
+}
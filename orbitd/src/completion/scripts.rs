@@ -0,0 +1,80 @@
+// Shell bridge scripts
+//
+// `orbitd --completions <shell>` prints a script that asks the daemon for
+// completions through `orbitd --complete`, which prints one
+// `text<TAB>description` line per candidate.
+
+use super::specs;
+
+/// Script for `shell` (bash, zsh or fish)
+pub fn bridge_script(shell: &str) -> Option<String> {
+    match shell {
+        "bash" => Some(BASH.to_string()),
+        "zsh" => Some(ZSH.to_string()),
+        "fish" => Some(fish()),
+        _ => None,
+    }
+}
+
+const BASH: &str = r#"# Orbit completions for bash
+# Add to ~/.bashrc:  eval "$(orbitd --completions bash)"
+_orbit_complete() {
+    local IFS=$'\n'
+    COMPREPLY=($(orbitd --complete "${COMP_LINE:0:COMP_POINT}" --cwd "$PWD" 2>/dev/null | cut -f1))
+    # Keep completing inside a directory
+    if [[ ${#COMPREPLY[@]} -eq 1 && ${COMPREPLY[0]} == */ ]]; then
+        compopt -o nospace
+    fi
+}
+complete -o default -D -F _orbit_complete
+complete -o default -I -F _orbit_complete 2>/dev/null
+"#;
+
+const ZSH: &str = r#"# Orbit completions for zsh
+# Add to ~/.zshrc after compinit:  eval "$(orbitd --completions zsh)"
+_orbit_complete() {
+    local -a lines words descs
+    local line
+    lines=("${(@f)$(orbitd --complete "$LBUFFER" --cwd "$PWD" 2>/dev/null)}")
+    for line in $lines; do
+        [[ -z $line ]] && continue
+        words+=("${line%%$'\t'*}")
+        descs+=("${line/$'\t'/  -- }")
+    done
+    (( ${#words} )) || return 1
+    compadd -U -Q -l -d descs -a words
+}
+compdef _orbit_complete -command- -default-
+"#;
+
+fn fish() -> String {
+    let mut script = String::from(
+        r#"# Orbit completions for fish
+# Add to ~/.config/fish/config.fish:  orbitd --completions fish | source
+function __orbit_complete
+    orbitd --complete (commandline -cp) --cwd $PWD 2>/dev/null
+end
+"#,
+    );
+
+    // fish registers completions per command
+    for program in specs::programs() {
+        script.push_str(&format!("complete -c {} -f -a '(__orbit_complete)'\n", program));
+    }
+    script
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bridge_scripts() {
+        for shell in ["bash", "zsh", "fish"] {
+            let script = bridge_script(shell).unwrap();
+            assert!(script.contains("orbitd --complete"), "{} script", shell);
+        }
+        assert!(bridge_script("fish").unwrap().contains("complete -c git "));
+        assert!(bridge_script("powershell").is_none());
+    }
+}
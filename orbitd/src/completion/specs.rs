@@ -0,0 +1,204 @@
+// Built-in subcommands and flags for common programs
+//
+// A spec is keyed by the words that select it ("git" or "git commit");
+// the longest matching key wins. Learned arguments from the user's own
+// history rank above these.
+
+pub struct ProgramSpec {
+    pub key: &'static str,
+    pub subcommands: &'static [(&'static str, &'static str)],
+    pub flags: &'static [(&'static str, &'static str)],
+}
+
+pub const SPECS: &[ProgramSpec] = &[
+    ProgramSpec {
+        key: "git",
+        subcommands: &[
+            ("add", "Stage changes"),
+            ("branch", "List, create or delete branches"),
+            ("checkout", "Switch branches or restore files"),
+            ("clone", "Clone a repository"),
+            ("commit", "Record staged changes"),
+            ("diff", "Show changes"),
+            ("fetch", "Download refs from a remote"),
+            ("log", "Show commit history"),
+            ("merge", "Join histories together"),
+            ("pull", "Fetch and integrate changes"),
+            ("push", "Update remote refs"),
+            ("rebase", "Reapply commits on another base"),
+            ("remote", "Manage remotes"),
+            ("reset", "Reset HEAD to a state"),
+            ("restore", "Restore working tree files"),
+            ("stash", "Stash away changes"),
+            ("status", "Show working tree status"),
+            ("switch", "Switch branches"),
+            ("tag", "Create, list or delete tags"),
+        ],
+        flags: &[("--help", "Show help"), ("--version", "Show version"), ("-C", "Run in directory")],
+    },
+    ProgramSpec {
+        key: "git commit",
+        subcommands: &[],
+        flags: &[
+            ("-m", "Commit message"),
+            ("-a", "Stage modified files"),
+            ("--amend", "Replace the last commit"),
+            ("--no-verify", "Skip hooks"),
+            ("--fixup", "Create a fixup commit"),
+        ],
+    },
+    ProgramSpec {
+        key: "git push",
+        subcommands: &[],
+        flags: &[
+            ("-u", "Set upstream"),
+            ("--force-with-lease", "Force push if the remote is unchanged"),
+            ("--tags", "Push tags"),
+            ("--dry-run", "Show what would be pushed"),
+        ],
+    },
+    ProgramSpec {
+        key: "git log",
+        subcommands: &[],
+        flags: &[
+            ("--oneline", "One line per commit"),
+            ("--graph", "Draw the history graph"),
+            ("-p", "Show patches"),
+            ("--stat", "Show changed files"),
+            ("--author", "Filter by author"),
+        ],
+    },
+    ProgramSpec {
+        key: "cargo",
+        subcommands: &[
+            ("build", "Compile the package"),
+            ("check", "Check for errors without building"),
+            ("clippy", "Run lints"),
+            ("doc", "Build documentation"),
+            ("fmt", "Format the code"),
+            ("new", "Create a package"),
+            ("run", "Run a binary"),
+            ("test", "Run tests"),
+            ("update", "Update dependencies"),
+        ],
+        flags: &[("--help", "Show help"), ("--version", "Show version")],
+    },
+    ProgramSpec {
+        key: "cargo build",
+        subcommands: &[],
+        flags: &[
+            ("--release", "Optimized build"),
+            ("--workspace", "All workspace members"),
+            ("-p", "Package to build"),
+            ("--features", "Features to enable"),
+            ("--all-targets", "Include tests, benches and examples"),
+        ],
+    },
+    ProgramSpec {
+        key: "cargo test",
+        subcommands: &[],
+        flags: &[
+            ("--release", "Optimized build"),
+            ("--workspace", "All workspace members"),
+            ("-p", "Package to test"),
+            ("--lib", "Only library tests"),
+            ("--no-fail-fast", "Run every test"),
+        ],
+    },
+    ProgramSpec {
+        key: "docker",
+        subcommands: &[
+            ("build", "Build an image"),
+            ("compose", "Manage multi-container apps"),
+            ("exec", "Run a command in a container"),
+            ("images", "List images"),
+            ("logs", "Show container logs"),
+            ("ps", "List containers"),
+            ("pull", "Download an image"),
+            ("push", "Upload an image"),
+            ("rm", "Remove containers"),
+            ("run", "Run a container"),
+            ("stop", "Stop containers"),
+        ],
+        flags: &[("--help", "Show help"), ("--version", "Show version")],
+    },
+    ProgramSpec {
+        key: "docker run",
+        subcommands: &[],
+        flags: &[
+            ("-d", "Run in the background"),
+            ("-it", "Interactive terminal"),
+            ("--rm", "Remove when stopped"),
+            ("-p", "Publish a port"),
+            ("-v", "Mount a volume"),
+            ("-e", "Set an environment variable"),
+            ("--name", "Container name"),
+        ],
+    },
+    ProgramSpec {
+        key: "kubectl",
+        subcommands: &[
+            ("apply", "Apply a configuration"),
+            ("delete", "Delete resources"),
+            ("describe", "Show resource details"),
+            ("exec", "Run a command in a container"),
+            ("get", "List resources"),
+            ("logs", "Print container logs"),
+            ("port-forward", "Forward local ports to a pod"),
+            ("rollout", "Manage rollouts"),
+        ],
+        flags: &[
+            ("-n", "Namespace"),
+            ("--context", "Kubeconfig context"),
+            ("-o", "Output format"),
+            ("--all-namespaces", "Across all namespaces"),
+        ],
+    },
+    ProgramSpec {
+        key: "npm",
+        subcommands: &[
+            ("install", "Install dependencies"),
+            ("run", "Run a package script"),
+            ("test", "Run tests"),
+            ("publish", "Publish the package"),
+            ("outdated", "List outdated dependencies"),
+        ],
+        flags: &[("--help", "Show help"), ("--version", "Show version")],
+    },
+    ProgramSpec {
+        key: "ls",
+        subcommands: &[],
+        flags: &[
+            ("-l", "Long listing"),
+            ("-a", "Include hidden files"),
+            ("-h", "Human-readable sizes"),
+            ("-t", "Sort by time"),
+            ("-R", "Recurse into directories"),
+        ],
+    },
+    ProgramSpec {
+        key: "grep",
+        subcommands: &[],
+        flags: &[
+            ("-r", "Search directories recursively"),
+            ("-i", "Ignore case"),
+            ("-n", "Show line numbers"),
+            ("-v", "Invert match"),
+            ("-E", "Extended regular expressions"),
+            ("-l", "Only list matching files"),
+        ],
+    },
+];
+
+/// Spec for the longest key matching the leading words
+pub fn lookup(words: &[&str]) -> Option<&'static ProgramSpec> {
+    (1..=words.len().min(2)).rev().find_map(|n| {
+        let key = words[..n].join(" ");
+        SPECS.iter().find(|spec| spec.key == key)
+    })
+}
+
+/// Programs with a spec, for shells that register completions per command
+pub fn programs() -> impl Iterator<Item = &'static str> {
+    SPECS.iter().filter(|spec| !spec.key.contains(' ')).map(|spec| spec.key)
+}
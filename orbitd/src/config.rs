@@ -3,6 +3,8 @@ use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::path::PathBuf;
 
+use crate::executor::risk::RiskTier;
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Config {
     pub license: LicenseConfig,
@@ -10,13 +12,30 @@ pub struct Config {
     pub provider_mode: ProviderMode,
     pub default_provider: String,
     pub providers: HashMap<String, ProviderConfig>,
+    /// Provider, model and key overrides for some directories or projects
+    #[serde(default)]
+    pub profiles: HashMap<String, ProfileConfig>,
     pub auto_routing: Option<AutoRoutingConfig>,
     pub learning: LearningConfig,
     pub monitoring: MonitoringConfig,
     pub classification: ClassificationConfig,
+    #[serde(default)]
+    pub extensions: ExtensionsConfig,
     pub execution: ExecutionConfig,
     pub context: ContextConfig,
     pub ui: UiConfig,
+    /// Endpoints told about blocked commands
+    #[serde(default)]
+    pub webhooks: pulsar_webhook::WebhooksConfig,
+    /// Where long-running command results and monitor alerts are sent
+    #[serde(default)]
+    pub notifications: NotificationsConfig,
+    /// Offline mode and how it is detected
+    #[serde(default)]
+    pub connectivity: ConnectivityConfig,
+    /// Small model run on this machine when no cloud provider answers
+    #[serde(default)]
+    pub local_model: LocalModelConfig,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -39,6 +58,9 @@ pub struct DaemonConfig {
     pub log_level: String,
     #[serde(default = "default_true")]
     pub auto_restart: bool,
+    /// Also log to a rotating `orbitd.log` in this directory
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub log_file: Option<pulsar_log::LogFileConfig>,
 }
 
 fn default_log_level() -> String {
@@ -58,6 +80,8 @@ pub enum ProviderMode {
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ProviderConfig {
+    /// Secret manager reference (`op://...`, `pass:...`, `env:...`) or,
+    /// deprecated, the key itself
     pub api_key: Option<String>,
     pub base_url: Option<String>,
     pub model: Option<String>,
@@ -65,6 +89,71 @@ pub struct ProviderConfig {
     #[serde(default)]
     pub capabilities: Vec<String>,
     pub cost: Option<String>,
+    /// Defaults to 60 requests and 90,000 tokens a minute
+    #[serde(default)]
+    pub rate_limit: Option<crate::providers::RateLimit>,
+}
+
+/// Provider settings for some directory trees or projects, e.g. the work
+/// OpenAI organization under ~/work. API keys for a profile are kept in
+/// the keychain (`orbitd --set-api-key <provider> --profile <name>`) or a
+/// secret manager, not here; without one the provider's own key is used.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ProfileConfig {
+    /// Directory trees the profile applies in; `~` is the home directory.
+    /// The deepest match wins.
+    #[serde(default)]
+    pub directories: Vec<PathBuf>,
+    /// Projects the profile applies to, by part of their git remote URL
+    /// (`github.com/acme/`), on this machine or a remote one
+    #[serde(default)]
+    pub git_remotes: Vec<String>,
+    /// Used instead of `default_provider`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub default_provider: Option<String>,
+    /// Model and endpoint overrides, by provider
+    #[serde(default)]
+    pub providers: HashMap<String, ProfileProviderConfig>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ProfileProviderConfig {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub model: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub base_url: Option<String>,
+    /// Secret manager reference for the key (`op://...`, `pass:...`,
+    /// `env:...`); plaintext keys are rejected
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub api_key: Option<String>,
+}
+
+impl ProfileConfig {
+    fn problems(&self, name: &str) -> Vec<String> {
+        let mut problems = Vec::new();
+        if self.directories.is_empty() && self.git_remotes.is_empty() {
+            problems.push(format!(
+                "profiles.{}: no directories or git_remotes, so it never applies",
+                name
+            ));
+        }
+        if self.git_remotes.iter().any(|remote| remote.trim().is_empty()) {
+            problems.push(format!("profiles.{}: empty git_remotes entry", name));
+        }
+        for (provider, overrides) in &self.providers {
+            let plaintext = overrides
+                .api_key
+                .as_deref()
+                .is_some_and(|key| !crate::credentials::is_secret_reference(key));
+            if plaintext {
+                problems.push(format!(
+                    "profiles.{}.providers.{}.api_key must be a secret reference (op://, pass: or env:)",
+                    name, provider
+                ));
+            }
+        }
+        problems
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -93,6 +182,15 @@ pub struct LearningConfig {
     pub max_patterns: usize,
     #[serde(default = "default_embedding_model")]
     pub embedding_model: String,
+    /// Encrypt command history, corrections, playbooks and learned patterns
+    /// (what was asked and the command it became) in learning.db, with the
+    /// key kept in the OS keychain. `retention_days` doesn't apply to
+    /// learned patterns; only purging all learning data removes them.
+    #[serde(default)]
+    pub encrypt_history: bool,
+    /// Days to keep command history and corrections; 0 keeps them forever
+    #[serde(default = "default_retention_days")]
+    pub retention_days: u32,
 }
 
 fn default_confidence_threshold() -> f32 {
@@ -107,6 +205,10 @@ fn default_embedding_model() -> String {
     "minilm-l6-v2".to_string()
 }
 
+fn default_retention_days() -> u32 {
+    90
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct MonitoringConfig {
     #[serde(default = "default_true")]
@@ -125,6 +227,220 @@ fn default_interval() -> u64 {
     60
 }
 
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NotificationsConfig {
+    /// Commands running at least this long are reported when they finish
+    #[serde(default = "default_long_command_secs")]
+    pub long_command_secs: u64,
+    /// Chat channels by name; `desktop` is built in and follows
+    /// `monitoring.desktop_notifications`
+    #[serde(default)]
+    pub channels: HashMap<String, ChannelConfig>,
+    /// Which events go to which channels. When empty, monitor alerts go to
+    /// the desktop.
+    #[serde(default)]
+    pub routes: Vec<RouteConfig>,
+    /// Mailed critical events only: crash recovery, license expiry and
+    /// repeated authentication failures
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub email: Option<EmailConfig>,
+    /// Warn this many days before the license expires
+    #[serde(default = "default_license_warning_days")]
+    pub license_warning_days: u32,
+    /// Consecutive rejected sudo passwords that count as a critical event
+    #[serde(default = "default_auth_failure_threshold")]
+    pub auth_failure_threshold: u32,
+}
+
+fn default_long_command_secs() -> u64 {
+    60
+}
+
+fn default_license_warning_days() -> u32 {
+    7
+}
+
+fn default_auth_failure_threshold() -> u32 {
+    3
+}
+
+impl Default for NotificationsConfig {
+    fn default() -> Self {
+        Self {
+            long_command_secs: default_long_command_secs(),
+            channels: HashMap::new(),
+            routes: Vec::new(),
+            email: None,
+            license_warning_days: default_license_warning_days(),
+            auth_failure_threshold: default_auth_failure_threshold(),
+        }
+    }
+}
+
+impl NotificationsConfig {
+    /// Name of the built-in desktop channel
+    pub const DESKTOP: &'static str = "desktop";
+
+    /// Everything wrong with the channels and routes
+    pub fn problems(&self) -> Vec<String> {
+        let mut problems = Vec::new();
+        for (name, channel) in &self.channels {
+            if name == Self::DESKTOP {
+                problems.push(format!(
+                    "notifications.channels: '{}' is built in",
+                    Self::DESKTOP
+                ));
+            }
+            let url = channel.webhook_url();
+            if !url.starts_with("https://") && !url.starts_with("http://") {
+                problems.push(format!(
+                    "notifications.channels.{}: webhook_url must be an http(s) URL",
+                    name
+                ));
+            }
+        }
+        for (i, route) in self.routes.iter().enumerate() {
+            if route.channels.is_empty() {
+                problems.push(format!("notifications.routes[{}]: no channels", i));
+            }
+            for channel in &route.channels {
+                if channel != Self::DESKTOP && !self.channels.contains_key(channel) {
+                    problems.push(format!(
+                        "notifications.routes[{}]: unknown channel '{}'",
+                        i, channel
+                    ));
+                }
+            }
+        }
+        if let Some(email) = &self.email {
+            problems.extend(email.problems());
+        }
+        if self.auth_failure_threshold == 0 {
+            problems.push("notifications.auth_failure_threshold must be at least 1".to_string());
+        }
+        problems
+    }
+}
+
+/// SMTP server critical events are mailed through
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EmailConfig {
+    pub smtp_host: String,
+    /// Defaults to 587 for STARTTLS, 465 for TLS and 25 without
+    #[serde(default)]
+    pub smtp_port: Option<u16>,
+    #[serde(default)]
+    pub tls: SmtpTls,
+    #[serde(default)]
+    pub username: Option<String>,
+    /// Defaults to the `smtp` entry in the system keychain; may be a
+    /// secret manager reference (`op://...`, `pass:...`, `env:...`)
+    #[serde(default)]
+    pub password: Option<String>,
+    /// e.g. `Orbit <orbit@example.com>`
+    pub from: String,
+    pub to: Vec<String>,
+    /// Templates filled with `{title}`, `{message}`, `{command}`, `{host}`
+    /// and `{time}`
+    #[serde(default = "default_email_subject")]
+    pub subject: String,
+    #[serde(default = "default_email_body")]
+    pub body: String,
+    /// Mails sent per hour at most; the rest are logged and dropped
+    #[serde(default = "default_emails_per_hour")]
+    pub max_per_hour: usize,
+}
+
+fn default_email_subject() -> String {
+    "[orbit] {title} on {host}".to_string()
+}
+
+fn default_email_body() -> String {
+    "{message}\n\nHost: {host}\nTime: {time}\n".to_string()
+}
+
+fn default_emails_per_hour() -> usize {
+    6
+}
+
+impl EmailConfig {
+    fn problems(&self) -> Vec<String> {
+        let mut problems = Vec::new();
+        if self.smtp_host.trim().is_empty() {
+            problems.push("notifications.email.smtp_host is empty".to_string());
+        }
+        if self.to.is_empty() {
+            problems.push("notifications.email.to has no recipients".to_string());
+        }
+        for address in std::iter::once(&self.from).chain(&self.to) {
+            if address.parse::<lettre::message::Mailbox>().is_err() {
+                problems.push(format!(
+                    "notifications.email: '{}' is not an email address",
+                    address
+                ));
+            }
+        }
+        if self.password.is_some() && self.username.is_none() {
+            problems.push("notifications.email.password is set without a username".to_string());
+        }
+        if self.max_per_hour == 0 {
+            problems.push("notifications.email.max_per_hour must be at least 1".to_string());
+        }
+        problems
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum SmtpTls {
+    /// Upgrade a plain connection with STARTTLS
+    #[default]
+    Starttls,
+    /// TLS from the first byte (SMTPS)
+    Tls,
+    /// Unencrypted, for a relay on localhost
+    None,
+}
+
+/// A chat service's incoming webhook
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(tag = "kind", rename_all = "lowercase")]
+pub enum ChannelConfig {
+    Slack { webhook_url: String },
+    Teams { webhook_url: String },
+}
+
+impl ChannelConfig {
+    pub fn webhook_url(&self) -> &str {
+        match self {
+            Self::Slack { webhook_url } | Self::Teams { webhook_url } => webhook_url,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RouteConfig {
+    /// Events this route matches; all of them when empty
+    #[serde(default)]
+    pub events: Vec<NotificationEvent>,
+    pub channels: Vec<String>,
+    /// Only match commands that exited non-zero (other events never match)
+    #[serde(default)]
+    pub failed_only: bool,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum NotificationEvent {
+    /// A command ran for at least `long_command_secs`
+    CommandFinished,
+    /// Disk space, git status and routine suggestions from the monitor
+    MonitorAlert,
+    /// Crash recovery, imminent license expiry or repeated authentication
+    /// failures; also mailed when `email` is set
+    Critical,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ClassificationConfig {
     #[serde(default = "default_nl_threshold")]
@@ -133,12 +449,218 @@ pub struct ClassificationConfig {
     pub check_path_binaries: bool,
     #[serde(default = "default_true")]
     pub cache_known_commands: bool,
+    /// How often to check PATH and package databases for changes that call
+    /// for rebuilding the known commands cache; 0 checks only on request
+    #[serde(default = "default_commands_refresh_interval")]
+    pub refresh_interval_seconds: u64,
+    /// Languages natural language input is recognized in, as ISO 639-1
+    /// codes (`en`, `de`, `fr`...); empty means English and the language
+    /// of the system locale
+    #[serde(default)]
+    pub languages: Vec<String>,
+    /// Directory of classifier plugins (defaults to `<data dir>/plugins`)
+    #[serde(default)]
+    pub plugins_dir: Option<PathBuf>,
 }
 
 fn default_nl_threshold() -> f32 {
     0.8
 }
 
+fn default_commands_refresh_interval() -> u64 {
+    30
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ExtensionsConfig {
+    #[serde(default = "default_true")]
+    pub enabled: bool,
+    /// Directory of WebAssembly extensions (defaults to `<data dir>/extensions`)
+    #[serde(default)]
+    pub dir: Option<PathBuf>,
+    /// Instructions an extension may run per event before it is stopped
+    #[serde(default = "default_fuel_per_call")]
+    pub fuel_per_call: u64,
+    #[serde(default = "default_memory_limit_mb")]
+    pub memory_limit_mb: usize,
+}
+
+fn default_fuel_per_call() -> u64 {
+    50_000_000
+}
+
+fn default_memory_limit_mb() -> usize {
+    64
+}
+
+impl Default for ExtensionsConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            dir: None,
+            fuel_per_call: default_fuel_per_call(),
+            memory_limit_mb: default_memory_limit_mb(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ConnectivityConfig {
+    /// Never use cloud providers, whatever the network
+    #[serde(default)]
+    pub offline: bool,
+    /// `host:port` whose reachability decides whether the network is up
+    #[serde(default = "default_probe_address")]
+    pub probe_address: String,
+    #[serde(default = "default_probe_interval")]
+    pub probe_interval_seconds: u64,
+    /// While offline, a license verified within this many days keeps
+    /// working without being re-verified
+    #[serde(default = "default_license_grace_days")]
+    pub license_grace_days: u32,
+}
+
+fn default_probe_address() -> String {
+    "license.singulio.com:443".to_string()
+}
+
+fn default_probe_interval() -> u64 {
+    30
+}
+
+fn default_license_grace_days() -> u32 {
+    7
+}
+
+impl Default for ConnectivityConfig {
+    fn default() -> Self {
+        Self {
+            offline: false,
+            probe_address: default_probe_address(),
+            probe_interval_seconds: default_probe_interval(),
+            license_grace_days: default_license_grace_days(),
+        }
+    }
+}
+
+impl ConnectivityConfig {
+    pub fn problems(&self) -> Vec<String> {
+        let mut problems = Vec::new();
+        if !self
+            .probe_address
+            .rsplit_once(':')
+            .is_some_and(|(host, port)| !host.is_empty() && port.parse::<u16>().is_ok())
+        {
+            problems.push("connectivity.probe_address must be host:port".to_string());
+        }
+        if self.probe_interval_seconds == 0 {
+            problems.push("connectivity.probe_interval_seconds must be at least 1".to_string());
+        }
+        problems
+    }
+}
+
+/// A quantized model run with llama.cpp, the provider of last resort.
+/// Selected as provider `local`, it answers everything; otherwise only
+/// requests no cloud provider can answer.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LocalModelConfig {
+    /// Downloads the model on the next start, unless `connectivity.offline`
+    /// is set; the file must then be put at `model_path` by hand
+    #[serde(default)]
+    pub enabled: bool,
+    /// llama.cpp's command-line program
+    #[serde(default = "default_local_runner")]
+    pub runner: String,
+    /// GGUF model fetched when `model_path` doesn't exist yet
+    #[serde(default = "default_local_model_url")]
+    pub model_url: String,
+    /// Hex SHA-256 the download must have; without it the model isn't
+    /// downloaded and must be put at `model_path` by hand
+    #[serde(default)]
+    pub sha256: Option<String>,
+    /// Defaults to the URL's file name under ~/.orbit/models
+    #[serde(default)]
+    pub model_path: Option<PathBuf>,
+    #[serde(default = "default_local_max_tokens")]
+    pub max_tokens: u32,
+    #[serde(default = "default_local_timeout")]
+    pub timeout_seconds: u64,
+}
+
+fn default_local_runner() -> String {
+    "llama-cli".to_string()
+}
+
+fn default_local_model_url() -> String {
+    "https://huggingface.co/Qwen/Qwen2.5-0.5B-Instruct-GGUF/resolve/main/qwen2.5-0.5b-instruct-q4_k_m.gguf"
+        .to_string()
+}
+
+fn default_local_max_tokens() -> u32 {
+    64
+}
+
+fn default_local_timeout() -> u64 {
+    30
+}
+
+impl Default for LocalModelConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            runner: default_local_runner(),
+            model_url: default_local_model_url(),
+            sha256: None,
+            model_path: None,
+            max_tokens: default_local_max_tokens(),
+            timeout_seconds: default_local_timeout(),
+        }
+    }
+}
+
+impl LocalModelConfig {
+    /// Where the model is kept
+    pub fn model_path(&self) -> Result<PathBuf> {
+        if let Some(path) = &self.model_path {
+            return Ok(path.clone());
+        }
+        let name = self
+            .model_url
+            .rsplit('/')
+            .next()
+            .filter(|name| !name.is_empty())
+            .context("local_model.model_url names no file")?;
+        let home = dirs::home_dir().context("Failed to find home directory")?;
+        Ok(home.join(".orbit").join("models").join(name))
+    }
+
+    pub fn problems(&self) -> Vec<String> {
+        let mut problems = Vec::new();
+        if !self.enabled {
+            return problems;
+        }
+        if self.runner.trim().is_empty() {
+            problems.push("local_model.runner must name a program".to_string());
+        }
+        if !self.model_url.starts_with("https://") {
+            problems.push("local_model.model_url must be an https:// URL".to_string());
+        }
+        let valid_hash =
+            |hash: &str| hash.len() == 64 && hash.bytes().all(|b| b.is_ascii_hexdigit());
+        if self.sha256.as_deref().is_some_and(|hash| !valid_hash(hash)) {
+            problems.push("local_model.sha256 must be 64 hex digits".to_string());
+        }
+        if self.max_tokens == 0 {
+            problems.push("local_model.max_tokens must be at least 1".to_string());
+        }
+        if self.timeout_seconds == 0 {
+            problems.push("local_model.timeout_seconds must be at least 1".to_string());
+        }
+        problems
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ExecutionConfig {
     #[serde(default)]
@@ -147,12 +669,28 @@ pub struct ExecutionConfig {
     pub confirm_destructive: bool,
     #[serde(default = "default_timeout")]
     pub timeout_seconds: u64,
+    /// Run approved commands through sudo when asked (credentials are
+    /// never stored; every attempt is audited). Off unless enabled.
+    #[serde(default)]
+    pub allow_elevation: bool,
+    /// pulsar-daemon's IPC socket, for running commands in Pulsar sessions.
+    /// Defaults to pulsar-daemon's own default.
+    #[serde(default)]
+    pub pulsar_socket: Option<PathBuf>,
+    /// Risk tiers whose commands are explained, with the resources they
+    /// affect, before the user approves them. Each costs a provider call.
+    #[serde(default = "default_explain_tiers")]
+    pub explain_tiers: Vec<RiskTier>,
 }
 
 fn default_timeout() -> u64 {
     300
 }
 
+fn default_explain_tiers() -> Vec<RiskTier> {
+    vec![RiskTier::Medium, RiskTier::High]
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ContextConfig {
     #[serde(default = "default_true")]
@@ -203,6 +741,22 @@ impl Config {
         let config: Config =
             serde_yaml::from_str(&content).context("Failed to parse config file")?;
 
+        let mut problems = config.webhooks.problems();
+        problems.extend(config.notifications.problems());
+        problems.extend(config.connectivity.problems());
+        problems.extend(config.local_model.problems());
+        for (name, profile) in &config.profiles {
+            problems.extend(profile.problems(name));
+        }
+        for (name, provider) in &config.providers {
+            if let Some(rate_limit) = &provider.rate_limit {
+                problems.extend(rate_limit.problems(name));
+            }
+        }
+        if !problems.is_empty() {
+            anyhow::bail!("Invalid configuration:\n  {}", problems.join("\n  "));
+        }
+
         Ok(config)
     }
 
@@ -233,6 +787,27 @@ impl Config {
         Ok(data_dir)
     }
 
+    /// Directory scanned for classifier plugins
+    pub fn plugins_dir(&self) -> Result<PathBuf> {
+        match &self.classification.plugins_dir {
+            Some(dir) => Ok(dir.clone()),
+            None => Ok(Self::data_dir()?.join("plugins")),
+        }
+    }
+
+    /// Directory scanned for WebAssembly extensions
+    pub fn extensions_dir(&self) -> Result<PathBuf> {
+        match &self.extensions.dir {
+            Some(dir) => Ok(dir.clone()),
+            None => Ok(Self::data_dir()?.join("extensions")),
+        }
+    }
+
+    /// Permissions the user approved for extensions
+    pub fn extension_grants_path() -> Result<PathBuf> {
+        Ok(Self::data_dir()?.join("extension-grants.yaml"))
+    }
+
     pub fn is_development_mode(&self) -> bool {
         std::env::var("ORBIT_DEV_MODE").is_ok() || cfg!(debug_assertions)
     }
@@ -252,10 +827,12 @@ impl Config {
                 socket_path,
                 log_level: "info".to_string(),
                 auto_restart: true,
+                log_file: None,
             },
             provider_mode: ProviderMode::Auto,
             default_provider: "claude".to_string(),
             providers: HashMap::new(),
+            profiles: HashMap::new(),
             auto_routing: Some(AutoRoutingConfig {
                 enabled: true,
                 prefer_cost: "medium".to_string(),
@@ -271,6 +848,8 @@ impl Config {
                 confidence_threshold: 0.7,
                 max_patterns: 10000,
                 embedding_model: "minilm-l6-v2".to_string(),
+                encrypt_history: false,
+                retention_days: 90,
             },
             monitoring: MonitoringConfig {
                 enabled: true,
@@ -283,11 +862,18 @@ impl Config {
                 natural_language_threshold: 0.8,
                 check_path_binaries: true,
                 cache_known_commands: true,
+                refresh_interval_seconds: default_commands_refresh_interval(),
+                languages: Vec::new(),
+                plugins_dir: None,
             },
+            extensions: ExtensionsConfig::default(),
             execution: ExecutionConfig {
                 auto_approve: false,
                 confirm_destructive: true,
                 timeout_seconds: 300,
+                allow_elevation: false,
+                pulsar_socket: None,
+                explain_tiers: default_explain_tiers(),
             },
             context: ContextConfig {
                 track_directory_patterns: true,
@@ -302,6 +888,10 @@ impl Config {
                 show_provider: false,
                 show_learning_stats: true,
             },
+            webhooks: pulsar_webhook::WebhooksConfig::default(),
+            notifications: NotificationsConfig::default(),
+            connectivity: ConnectivityConfig::default(),
+            local_model: LocalModelConfig::default(),
         })
     }
 }
@@ -0,0 +1,288 @@
+//! Online/offline state
+//!
+//! Orbit runs on laptops that lose their network, and on machines that must
+//! never talk to the internet. Rather than letting every cloud call time out
+//! on its own, the daemon keeps one explicit state:
+//!
+//! - **online**: cloud providers are used for natural language
+//! - **offline**: cloud providers are skipped; only learned patterns, the
+//!   local embedding model and, if enabled, the local small model answer,
+//!   and license re-verification is deferred while the cached license is
+//!   within its grace window
+//!
+//! Offline is either forced (`connectivity.offline` in the config, or a
+//! `SetOffline` request) or detected, when the probe address stops accepting
+//! connections. Detected outages end by themselves; forced ones only when
+//! lifted. The Status response carries the state and a message clients can
+//! show as is.
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tracing::{info, warn};
+
+use crate::config::ConnectivityConfig;
+
+/// How long a probe connection may take before the network counts as down
+const PROBE_TIMEOUT: Duration = Duration::from_secs(5);
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ConnectivityState {
+    #[default]
+    Online,
+    Offline,
+}
+
+/// Why the daemon is offline
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OfflineReason {
+    /// `connectivity.offline` is set
+    Configured,
+    /// A client asked for offline mode
+    Requested,
+    /// The probe address could not be reached
+    Unreachable,
+}
+
+/// Connectivity as reported to clients
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ConnectivityStatus {
+    pub state: ConnectivityState,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reason: Option<OfflineReason>,
+    /// When the current state began; None until the first transition
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub since: Option<DateTime<Utc>>,
+    /// The license could not be re-verified and keeps working until then
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub license_deferred_until: Option<DateTime<Utc>>,
+    /// What the state means for the user, in one sentence
+    #[serde(default)]
+    pub message: String,
+}
+
+struct Inner {
+    forced: Option<OfflineReason>,
+    reachable: bool,
+    since: Option<DateTime<Utc>>,
+    license_deferred_until: Option<DateTime<Utc>>,
+}
+
+impl Inner {
+    fn reason(&self) -> Option<OfflineReason> {
+        self.forced
+            .or((!self.reachable).then_some(OfflineReason::Unreachable))
+    }
+}
+
+/// The daemon's online/offline state machine
+pub struct Connectivity {
+    probe_address: String,
+    inner: Mutex<Inner>,
+}
+
+impl Connectivity {
+    pub fn new(config: &ConnectivityConfig) -> Self {
+        Self {
+            probe_address: config.probe_address.clone(),
+            inner: Mutex::new(Inner {
+                forced: config.offline.then_some(OfflineReason::Configured),
+                // Assumed until the first probe says otherwise
+                reachable: true,
+                since: None,
+                license_deferred_until: None,
+            }),
+        }
+    }
+
+    pub fn is_online(&self) -> bool {
+        self.inner.lock().unwrap().reason().is_none()
+    }
+
+    pub fn status(&self) -> ConnectivityStatus {
+        let inner = self.inner.lock().unwrap();
+        let reason = inner.reason();
+        let message = match reason {
+            None => "Online: AI providers are available".to_string(),
+            Some(reason) => {
+                let why = match reason {
+                    OfflineReason::Configured => "offline mode is configured",
+                    OfflineReason::Requested => "offline mode was turned on",
+                    OfflineReason::Unreachable => "the network is unreachable",
+                };
+                let mut message = format!(
+                    "Offline ({}): AI providers are unavailable, so only learned commands are suggested",
+                    why
+                );
+                if let Some(until) = inner.license_deferred_until {
+                    message.push_str(&format!(
+                        "; the license check is deferred until {}",
+                        until.format("%Y-%m-%d %H:%M UTC")
+                    ));
+                }
+                message
+            }
+        };
+        ConnectivityStatus {
+            state: match reason {
+                None => ConnectivityState::Online,
+                Some(_) => ConnectivityState::Offline,
+            },
+            reason,
+            since: inner.since,
+            license_deferred_until: inner.license_deferred_until,
+            message,
+        }
+    }
+
+    /// Force offline mode on, or lift it (whether configured or requested)
+    pub fn set_offline(&self, offline: bool) {
+        self.transition(|inner| {
+            inner.forced = offline.then_some(OfflineReason::Requested);
+        });
+    }
+
+    /// Record whether the probe address could be reached
+    pub fn set_reachable(&self, reachable: bool) {
+        self.transition(|inner| inner.reachable = reachable);
+    }
+
+    /// The license keeps working until `until` without being re-verified;
+    /// None once it has been verified again
+    pub fn defer_license(&self, until: Option<DateTime<Utc>>) {
+        self.inner.lock().unwrap().license_deferred_until = until;
+    }
+
+    fn transition(&self, change: impl FnOnce(&mut Inner)) {
+        let mut inner = self.inner.lock().unwrap();
+        let before = inner.reason();
+        change(&mut inner);
+        let after = inner.reason();
+        if before.is_some() == after.is_some() {
+            return;
+        }
+
+        inner.since = Some(Utc::now());
+        match after {
+            Some(reason) => warn!("Offline ({:?}): AI providers disabled", reason),
+            None => {
+                info!("Back online: AI providers enabled");
+                inner.license_deferred_until = None;
+            }
+        }
+    }
+
+    /// Try the probe address now and record the result; forced offline mode
+    /// never touches the network
+    pub async fn probe(&self) -> bool {
+        if self.inner.lock().unwrap().forced.is_some() {
+            return false;
+        }
+        let reachable = matches!(
+            tokio::time::timeout(
+                PROBE_TIMEOUT,
+                tokio::net::TcpStream::connect(self.probe_address.as_str())
+            )
+            .await,
+            Ok(Ok(_))
+        );
+        self.set_reachable(reachable);
+        reachable
+    }
+
+    /// Probe every `interval`
+    pub async fn run(self: Arc<Self>, interval: Duration) {
+        let mut interval = tokio::time::interval(interval);
+        loop {
+            interval.tick().await;
+            self.probe().await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(offline: bool) -> ConnectivityConfig {
+        ConnectivityConfig {
+            offline,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_detected_outage_ends_by_itself() {
+        let connectivity = Connectivity::new(&config(false));
+        assert!(connectivity.is_online());
+        assert!(connectivity.status().since.is_none());
+
+        connectivity.set_reachable(false);
+        let status = connectivity.status();
+        assert_eq!(status.state, ConnectivityState::Offline);
+        assert_eq!(status.reason, Some(OfflineReason::Unreachable));
+        assert!(status.since.is_some());
+
+        connectivity.set_reachable(true);
+        assert!(connectivity.is_online());
+    }
+
+    #[test]
+    fn test_forced_offline_outlasts_the_network() {
+        let connectivity = Connectivity::new(&config(true));
+        assert_eq!(
+            connectivity.status().reason,
+            Some(OfflineReason::Configured)
+        );
+
+        connectivity.set_reachable(true);
+        assert!(!connectivity.is_online());
+
+        connectivity.set_offline(false);
+        assert!(connectivity.is_online());
+        connectivity.set_offline(true);
+        assert_eq!(connectivity.status().reason, Some(OfflineReason::Requested));
+    }
+
+    #[test]
+    fn test_license_deferral_is_reported_and_cleared_online() {
+        let connectivity = Connectivity::new(&config(false));
+        connectivity.set_reachable(false);
+        let until = Utc::now() + chrono::Duration::days(3);
+        connectivity.defer_license(Some(until));
+
+        let status = connectivity.status();
+        assert_eq!(status.license_deferred_until, Some(until));
+        assert!(status.message.contains("license check is deferred"));
+
+        connectivity.set_reachable(true);
+        assert!(connectivity.status().license_deferred_until.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_probe() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let probe_address = listener.local_addr().unwrap().to_string();
+        let connectivity = Connectivity::new(&ConnectivityConfig {
+            probe_address: probe_address.clone(),
+            ..Default::default()
+        });
+        assert!(connectivity.probe().await);
+
+        drop(listener);
+        assert!(!connectivity.probe().await);
+        assert!(!connectivity.is_online());
+
+        // Forced offline mode never touches the network
+        let forced = Connectivity::new(&ConnectivityConfig {
+            offline: true,
+            probe_address,
+            ..Default::default()
+        });
+        assert!(!forced.probe().await);
+        assert_eq!(forced.status().reason, Some(OfflineReason::Configured));
+    }
+}
@@ -1,8 +1,16 @@
+pub mod profile;
+pub mod remote;
+pub mod shell;
+
 use anyhow::Result;
 use serde::{Deserialize, Serialize};
 use std::collections::HashSet;
 use std::path::PathBuf;
-use std::sync::Arc;
+use std::sync::{Arc, RwLock};
+
+pub use profile::ProviderSettings;
+pub use remote::FocusedTerminal;
+pub use shell::ShellDialect;
 
 use crate::config::Config;
 
@@ -19,6 +27,10 @@ pub struct Context {
     pub recent_commands: Vec<String>,
     pub project_type: Option<ProjectType>,
     pub directory_type: DirectoryType,
+    /// Remote host of the Pulsar session this context was read from; None
+    /// for this machine
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub host: Option<String>,
 }
 
 /// Detected project type based on files in directory
@@ -62,23 +74,88 @@ pub struct GitContext {
     pub last_commit_message: Option<String>,
 }
 
+impl Context {
+    /// How commands are written in this context's shell
+    pub fn dialect(&self) -> ShellDialect {
+        ShellDialect::detect(&self.shell_name)
+    }
+
+    /// Stable identifier for the project being worked in: the git remote
+    /// when there is one (so clones share it), otherwise the directory.
+    /// None outside projects (home, temp and system directories).
+    pub fn project_fingerprint(&self) -> Option<String> {
+        use sha2::{Digest, Sha256};
+
+        let dir = match &self.host {
+            Some(host) => format!("dir:{}:{}", host, self.pwd.display()),
+            None => format!("dir:{}", self.pwd.display()),
+        };
+        let key = match &self.git_context {
+            Some(GitContext { remote_url: Some(remote), .. }) => format!("git:{}", remote),
+            Some(_) => dir,
+            None if self.directory_type == DirectoryType::Project => dir,
+            None => return None,
+        };
+
+        let digest = Sha256::digest(key.as_bytes());
+        Some(format!("{:x}", digest)[..16].to_string())
+    }
+}
+
 pub struct ContextEngine {
-    _config: Arc<Config>,
+    config: Arc<Config>,
+    /// The Pulsar terminal the user is typing in, as reported by
+    /// pulsar-daemon
+    focused: RwLock<Option<FocusedTerminal>>,
 }
 
 impl ContextEngine {
     pub async fn new(config: Arc<Config>) -> Result<Self> {
-        Ok(Self { _config: config })
+        Ok(Self {
+            config,
+            focused: RwLock::new(None),
+        })
+    }
+
+    /// Record which terminal has focus; None when no Pulsar terminal does
+    pub fn set_focused_terminal(&self, terminal: Option<FocusedTerminal>) {
+        *self.focused.write().unwrap_or_else(|e| e.into_inner()) = terminal;
+    }
+
+    pub fn focused_terminal(&self) -> Option<FocusedTerminal> {
+        self.focused.read().unwrap_or_else(|e| e.into_inner()).clone()
+    }
+
+    /// The provider profile for `context`: which provider, model and API
+    /// key to use there
+    pub fn provider_settings(&self, context: &Context) -> ProviderSettings {
+        ProviderSettings::resolve(&self.config, context)
     }
 
     pub async fn get_context(&self) -> Result<Context> {
-        let pwd = std::env::current_dir().unwrap_or_else(|_| PathBuf::from("/"));
+        let focused = self.focused_terminal();
 
         let username = std::env::var("USER")
             .or_else(|_| std::env::var("USERNAME"))
             .unwrap_or_else(|_| "unknown".to_string());
 
-        let shell_name = std::env::var("SHELL").unwrap_or_else(|_| "bash".to_string());
+        // Nothing on a remote host can be inspected from here
+        if let Some(terminal) = focused.as_ref().filter(|t| t.host.is_some()) {
+            return Ok(remote::focused_context(terminal, username));
+        }
+
+        let pwd = focused
+            .as_ref()
+            .and_then(|t| t.cwd.as_deref())
+            .map(PathBuf::from)
+            .filter(|cwd| cwd.is_dir())
+            .or_else(|| std::env::current_dir().ok())
+            .unwrap_or_else(|| PathBuf::from("/"));
+
+        let shell_name = focused
+            .and_then(|t| t.shell)
+            .or_else(|| std::env::var("SHELL").ok())
+            .unwrap_or_else(|| "bash".to_string());
 
         let git_context = Self::detect_git_context(&pwd);
         let project_type = Self::detect_project_type(&pwd);
@@ -97,6 +174,7 @@ impl ContextEngine {
             recent_commands: vec![],
             project_type,
             directory_type,
+            host: None,
         })
     }
 
@@ -193,44 +271,58 @@ impl ContextEngine {
 
     /// Detect project type based on files in directory
     fn detect_project_type(path: &PathBuf) -> Option<ProjectType> {
+        Self::project_type_from_entries(&Self::entry_names(path))
+    }
+
+    fn entry_names(path: &PathBuf) -> Vec<String> {
+        std::fs::read_dir(path)
+            .map(|entries| {
+                entries
+                    .flatten()
+                    .map(|entry| entry.file_name().to_string_lossy().into_owned())
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    /// Project type from the names of the files in a directory
+    pub(crate) fn project_type_from_entries(names: &[String]) -> Option<ProjectType> {
+        let has = |file: &str| names.iter().any(|name| name == file);
+
         // Check for project files
-        if path.join("Cargo.toml").exists() {
+        if has("Cargo.toml") {
             return Some(ProjectType::Rust);
         }
-        if path.join("package.json").exists() {
+        if has("package.json") {
             return Some(ProjectType::Node);
         }
-        if path.join("go.mod").exists() {
+        if has("go.mod") {
             return Some(ProjectType::Go);
         }
-        if path.join("setup.py").exists()
-            || path.join("requirements.txt").exists()
-            || path.join("pyproject.toml").exists() {
+        if has("setup.py") || has("requirements.txt") || has("pyproject.toml") {
             return Some(ProjectType::Python);
         }
-        if path.join("pom.xml").exists() || path.join("build.gradle").exists() {
+        if has("pom.xml") || has("build.gradle") {
             return Some(ProjectType::Java);
         }
-        if path.join("Gemfile").exists() {
+        if has("Gemfile") {
             return Some(ProjectType::Ruby);
         }
-        if path.join("composer.json").exists() {
+        if has("composer.json") {
             return Some(ProjectType::Php);
         }
-        if path.join("Dockerfile").exists() {
+        if has("Dockerfile") {
             return Some(ProjectType::Docker);
         }
 
         // Check for .csproj or .sln files
-        if let Ok(entries) = std::fs::read_dir(path) {
-            for entry in entries.flatten() {
-                if let Some(ext) = entry.path().extension() {
-                    if ext == "csproj" || ext == "sln" {
-                        return Some(ProjectType::CSharp);
-                    }
-                    if ext == "tf" {
-                        return Some(ProjectType::Terraform);
-                    }
+        for name in names {
+            if let Some(ext) = std::path::Path::new(name).extension() {
+                if ext == "csproj" || ext == "sln" {
+                    return Some(ProjectType::CSharp);
+                }
+                if ext == "tf" {
+                    return Some(ProjectType::Terraform);
                 }
             }
         }
@@ -240,10 +332,20 @@ impl ContextEngine {
 
     /// Detect directory type
     fn detect_directory_type(path: &PathBuf, _username: &str) -> DirectoryType {
+        let home = std::env::var("HOME").ok().map(PathBuf::from);
+        let is_project = Self::detect_project_type(path).is_some();
+        Self::directory_type_of(path, home.as_deref(), is_project)
+    }
+
+    pub(crate) fn directory_type_of(
+        path: &std::path::Path,
+        home: Option<&std::path::Path>,
+        is_project: bool,
+    ) -> DirectoryType {
         let path_str = path.to_string_lossy();
 
         // Check for specific directory types
-        if path == &PathBuf::from("/") {
+        if path == std::path::Path::new("/") {
             return DirectoryType::Root;
         }
 
@@ -256,8 +358,8 @@ impl ContextEngine {
         }
 
         // Check if it's home directory
-        if let Ok(home) = std::env::var("HOME") {
-            if path == &PathBuf::from(&home) {
+        if let Some(home) = home {
+            if path == home {
                 return DirectoryType::Home;
             }
 
@@ -271,7 +373,7 @@ impl ContextEngine {
         }
 
         // Check if it's a project directory
-        if Self::detect_project_type(path).is_some() {
+        if is_project {
             return DirectoryType::Project;
         }
 
@@ -280,60 +382,60 @@ impl ContextEngine {
 
     /// Detect programming languages in directory
     fn detect_languages(path: &PathBuf) -> Vec<String> {
-        let mut languages = Vec::new();
+        Self::languages_from_entries(&Self::entry_names(path))
+    }
 
-        if let Ok(entries) = std::fs::read_dir(path) {
-            let mut extensions = HashSet::new();
+    /// Languages suggested by the file extensions in a directory
+    pub(crate) fn languages_from_entries(names: &[String]) -> Vec<String> {
+        let mut languages = Vec::new();
 
-            for entry in entries.flatten() {
-                if let Some(ext) = entry.path().extension() {
-                    extensions.insert(ext.to_string_lossy().to_string());
-                }
-            }
+        let extensions: HashSet<&str> = names
+            .iter()
+            .filter_map(|name| std::path::Path::new(name).extension()?.to_str())
+            .collect();
 
-            // Map extensions to languages
-            if extensions.contains("rs") {
-                languages.push("Rust".to_string());
-            }
-            if extensions.contains("js") || extensions.contains("ts") || extensions.contains("jsx") || extensions.contains("tsx") {
-                languages.push("JavaScript/TypeScript".to_string());
-            }
-            if extensions.contains("py") {
-                languages.push("Python".to_string());
-            }
-            if extensions.contains("go") {
-                languages.push("Go".to_string());
-            }
-            if extensions.contains("java") {
-                languages.push("Java".to_string());
-            }
-            if extensions.contains("rb") {
-                languages.push("Ruby".to_string());
-            }
-            if extensions.contains("php") {
-                languages.push("PHP".to_string());
-            }
-            if extensions.contains("cs") {
-                languages.push("C#".to_string());
-            }
-            if extensions.contains("c") || extensions.contains("h") {
-                languages.push("C".to_string());
-            }
-            if extensions.contains("cpp") || extensions.contains("hpp") || extensions.contains("cc") {
-                languages.push("C++".to_string());
-            }
-            if extensions.contains("sh") || extensions.contains("bash") {
-                languages.push("Shell".to_string());
-            }
-            if extensions.contains("yaml") || extensions.contains("yml") {
-                languages.push("YAML".to_string());
-            }
-            if extensions.contains("json") {
-                languages.push("JSON".to_string());
-            }
-            if extensions.contains("toml") {
-                languages.push("TOML".to_string());
-            }
+        // Map extensions to languages
+        if extensions.contains("rs") {
+            languages.push("Rust".to_string());
+        }
+        if extensions.contains("js") || extensions.contains("ts") || extensions.contains("jsx") || extensions.contains("tsx") {
+            languages.push("JavaScript/TypeScript".to_string());
+        }
+        if extensions.contains("py") {
+            languages.push("Python".to_string());
+        }
+        if extensions.contains("go") {
+            languages.push("Go".to_string());
+        }
+        if extensions.contains("java") {
+            languages.push("Java".to_string());
+        }
+        if extensions.contains("rb") {
+            languages.push("Ruby".to_string());
+        }
+        if extensions.contains("php") {
+            languages.push("PHP".to_string());
+        }
+        if extensions.contains("cs") {
+            languages.push("C#".to_string());
+        }
+        if extensions.contains("c") || extensions.contains("h") {
+            languages.push("C".to_string());
+        }
+        if extensions.contains("cpp") || extensions.contains("hpp") || extensions.contains("cc") {
+            languages.push("C++".to_string());
+        }
+        if extensions.contains("sh") || extensions.contains("bash") {
+            languages.push("Shell".to_string());
+        }
+        if extensions.contains("yaml") || extensions.contains("yml") {
+            languages.push("YAML".to_string());
+        }
+        if extensions.contains("json") {
+            languages.push("JSON".to_string());
+        }
+        if extensions.contains("toml") {
+            languages.push("TOML".to_string());
         }
 
         languages
@@ -553,6 +655,7 @@ ui:
             recent_commands: vec!["ls".to_string(), "cd".to_string()],
             project_type: Some(ProjectType::Rust),
             directory_type: DirectoryType::Project,
+            host: None,
         };
 
         // Test serialization
@@ -665,4 +768,35 @@ ui:
         let languages = ContextEngine::detect_languages(&path);
         assert!(languages.is_empty());
     }
+
+    #[tokio::test]
+    async fn test_focused_remote_terminal_replaces_local_context() {
+        let engine = ContextEngine::new(create_test_config().await).await.unwrap();
+        let mut terminal = FocusedTerminal {
+            session_id: uuid::Uuid::new_v4(),
+            name: "prod".to_string(),
+            host: Some("prod-1".to_string()),
+            cwd: Some("/var/www".to_string()),
+            shell: Some("bash".to_string()),
+        };
+
+        engine.set_focused_terminal(Some(terminal.clone()));
+        let context = engine.get_context().await.unwrap();
+        assert_eq!(context.host.as_deref(), Some("prod-1"));
+        assert_eq!(context.pwd, PathBuf::from("/var/www"));
+        assert_eq!(context.directory_type, DirectoryType::System);
+        assert!(context.git_context.is_none());
+
+        // A local terminal only lends its directory
+        let dir = TempDir::new().unwrap();
+        terminal.host = None;
+        terminal.cwd = Some(dir.path().display().to_string());
+        engine.set_focused_terminal(Some(terminal));
+        let context = engine.get_context().await.unwrap();
+        assert!(context.host.is_none());
+        assert_eq!(context.pwd, dir.path());
+
+        engine.set_focused_terminal(None);
+        assert!(engine.get_context().await.unwrap().host.is_none());
+    }
 }
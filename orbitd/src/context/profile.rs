@@ -0,0 +1,253 @@
+// Provider profiles: which provider, model and API key apply where
+//
+// A profile applies in its directory trees (deepest first) and to projects
+// whose git remote matches; a directory match beats a remote one. Outside
+// every profile the top-level provider settings are used.
+
+use anyhow::Result;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use super::{Context, GitContext};
+use crate::config::{Config, ProfileConfig, ProviderConfig};
+use crate::credentials::{is_secret_reference, resolve_secret, CredentialStore};
+
+/// The provider to ask from some context, and how
+#[derive(Debug, Clone, PartialEq)]
+pub struct ProviderSettings {
+    /// Profile that applied; None for the top-level settings
+    pub profile: Option<String>,
+    pub provider: String,
+    pub model: Option<String>,
+    pub base_url: Option<String>,
+}
+
+impl ProviderSettings {
+    pub fn resolve(config: &Config, context: &Context) -> Self {
+        Self::for_profile(
+            &config.default_provider,
+            &config.providers,
+            matching_profile(&config.profiles, context),
+        )
+    }
+
+    fn for_profile(
+        default_provider: &str,
+        providers: &HashMap<String, ProviderConfig>,
+        profile: Option<(&str, &ProfileConfig)>,
+    ) -> Self {
+        let provider = profile
+            .and_then(|(_, profile)| profile.default_provider.clone())
+            .unwrap_or_else(|| default_provider.to_string());
+        let base = providers.get(&provider);
+        let overrides = profile.and_then(|(_, profile)| profile.providers.get(&provider));
+
+        Self {
+            profile: profile.map(|(name, _)| name.to_string()),
+            model: overrides
+                .and_then(|o| o.model.clone())
+                .or_else(|| base.and_then(|b| b.model.clone())),
+            base_url: overrides
+                .and_then(|o| o.base_url.clone())
+                .or_else(|| base.and_then(|b| b.base_url.clone())),
+            provider,
+        }
+    }
+
+    /// The API key: the profile's from its secret reference or the
+    /// keychain, else the provider's from its secret reference, the
+    /// keychain, the environment or (deprecated) the config file
+    ///
+    /// Blocks on the keychain and on secret manager CLIs, for up to 30s
+    /// while one waits for an unlock; async code calls it through
+    /// `spawn_blocking`.
+    pub fn api_key(&self, config: &Config) -> Result<String> {
+        let reference = self
+            .profile
+            .as_ref()
+            .and_then(|profile| config.profiles.get(profile)?.providers.get(&self.provider))
+            .and_then(|overrides| overrides.api_key.as_deref());
+        if let Some(reference) = reference {
+            return resolve_secret(reference);
+        }
+
+        let configured = config
+            .providers
+            .get(&self.provider)
+            .and_then(|provider| provider.api_key.as_deref());
+        CredentialStore::new()
+            .get_profile_api_key(&self.provider, self.profile.as_deref(), configured)
+            .or_else(|e| {
+                configured.filter(|key| !is_secret_reference(key)).map(str::to_string).ok_or(e)
+            })
+    }
+}
+
+/// The profile applying in `context`, with its name
+fn matching_profile<'a>(
+    profiles: &'a HashMap<String, ProfileConfig>,
+    context: &Context,
+) -> Option<(&'a str, &'a ProfileConfig)> {
+    let mut names: Vec<&String> = profiles.keys().collect();
+    names.sort();
+
+    // Directories are only this machine's; deepest, then first by name
+    let by_directory = names
+        .iter()
+        .filter(|_| context.host.is_none())
+        .filter_map(|name| {
+            let depth = profiles[*name]
+                .directories
+                .iter()
+                .map(|dir| expand_home(dir))
+                .filter(|dir| context.pwd.starts_with(dir))
+                .map(|dir| dir.components().count())
+                .max()?;
+            Some((std::cmp::Reverse(depth), *name))
+        })
+        .min();
+    if let Some((_, name)) = by_directory {
+        return Some((name.as_str(), &profiles[name]));
+    }
+
+    let Some(GitContext {
+        remote_url: Some(remote),
+        ..
+    }) = &context.git_context
+    else {
+        return None;
+    };
+    let remote = normalize_remote(remote);
+    names
+        .into_iter()
+        .find(|name| profiles[*name].git_remotes.iter().any(|r| remote.contains(r.as_str())))
+        .map(|name| (name.as_str(), &profiles[name]))
+}
+
+/// `git@github.com:acme/api.git` as `git@github.com/acme/api.git`, so SSH
+/// and HTTPS remotes match the same `github.com/acme/`
+fn normalize_remote(remote: &str) -> String {
+    if remote.contains("://") {
+        remote.to_string()
+    } else {
+        remote.replacen(':', "/", 1)
+    }
+}
+
+fn expand_home(path: &Path) -> PathBuf {
+    match path.strip_prefix("~") {
+        Ok(rest) => dirs::home_dir().map_or_else(|| path.to_path_buf(), |home| home.join(rest)),
+        Err(_) => path.to_path_buf(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::ProfileProviderConfig;
+    use crate::context::DirectoryType;
+
+    fn context(pwd: &str, remote: Option<&str>) -> Context {
+        Context {
+            os_name: "Linux".to_string(),
+            os_version: "unknown".to_string(),
+            shell_name: "bash".to_string(),
+            shell_version: "unknown".to_string(),
+            pwd: PathBuf::from(pwd),
+            username: "dev".to_string(),
+            git_context: remote.map(|remote| GitContext {
+                repo_name: "repo".to_string(),
+                current_branch: "main".to_string(),
+                has_uncommitted_changes: false,
+                remote_url: Some(remote.to_string()),
+                ahead_behind: None,
+                total_commits: None,
+                last_commit_message: None,
+            }),
+            detected_languages: vec![],
+            recent_commands: vec![],
+            project_type: None,
+            directory_type: DirectoryType::Project,
+            host: None,
+        }
+    }
+
+    fn profiles() -> HashMap<String, ProfileConfig> {
+        let work = ProfileConfig {
+            directories: vec![PathBuf::from("/home/dev/work")],
+            git_remotes: vec!["github.com/acme/".to_string()],
+            default_provider: Some("openai".to_string()),
+            providers: HashMap::from([(
+                "openai".to_string(),
+                ProfileProviderConfig {
+                    model: Some("gpt-4o".to_string()),
+                    base_url: None,
+                    api_key: None,
+                },
+            )]),
+        };
+        let client = ProfileConfig {
+            directories: vec![PathBuf::from("/home/dev/work/client")],
+            ..Default::default()
+        };
+        HashMap::from([("work".to_string(), work), ("client".to_string(), client)])
+    }
+
+    #[test]
+    fn test_matching_profile() {
+        let profiles = profiles();
+        let name = |context: &Context| matching_profile(&profiles, context).map(|(n, _)| n);
+
+        assert_eq!(name(&context("/home/dev/work/api", None)), Some("work"));
+        assert_eq!(
+            name(&context("/home/dev/work/client/app", None)),
+            Some("client")
+        );
+        assert_eq!(name(&context("/home/dev/workshop", None)), None);
+        assert_eq!(
+            name(&context(
+                "/home/dev/src/api",
+                Some("git@github.com:acme/api.git")
+            )),
+            Some("work")
+        );
+        assert_eq!(
+            name(&context("/tmp/api", Some("https://github.com/acme/api"))),
+            Some("work")
+        );
+
+        // Another host's directories say nothing about this machine's
+        let mut remote = context("/home/dev/work/api", None);
+        remote.host = Some("build-box".to_string());
+        assert_eq!(name(&remote), None);
+    }
+
+    #[test]
+    fn test_profile_overrides_provider_settings() {
+        let profiles = profiles();
+        let providers = HashMap::from([(
+            "openai".to_string(),
+            ProviderConfig {
+                api_key: None,
+                base_url: Some("https://api.openai.com/v1".to_string()),
+                model: Some("gpt-4o-mini".to_string()),
+                models: None,
+                capabilities: vec![],
+                cost: None,
+                rate_limit: None,
+            },
+        )]);
+
+        let work =
+            ProviderSettings::for_profile("claude", &providers, Some(("work", &profiles["work"])));
+        assert_eq!(work.profile.as_deref(), Some("work"));
+        assert_eq!(work.provider, "openai");
+        assert_eq!(work.model.as_deref(), Some("gpt-4o"));
+        assert_eq!(work.base_url.as_deref(), Some("https://api.openai.com/v1"));
+
+        let personal = ProviderSettings::for_profile("claude", &providers, None);
+        assert_eq!(personal.profile, None);
+        assert_eq!(personal.provider, "claude");
+        assert_eq!(personal.model, None);
+    }
+}
@@ -0,0 +1,161 @@
+// Context of a remote shell
+//
+// Commands run in a Pulsar SSH session are classified against the remote
+// machine, not this one. `PROBE` is a one-line POSIX shell script that prints
+// what ContextEngine reads locally, one field per line; `parse_probe` turns
+// its output back into a Context.
+//
+// pulsar-daemon also reports which terminal has focus, with the host and the
+// working directory its shell announces (OSC 7). While that terminal is on
+// another host, `focused_context` stands in for the local context.
+
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use uuid::Uuid;
+
+use super::{Context, ContextEngine, DirectoryType, GitContext};
+
+/// The Pulsar terminal the user is typing in
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FocusedTerminal {
+    pub session_id: Uuid,
+    pub name: String,
+    /// Remote host; None when the terminal runs on this machine
+    #[serde(default)]
+    pub host: Option<String>,
+    /// Working directory last announced by the shell
+    #[serde(default)]
+    pub cwd: Option<String>,
+    #[serde(default)]
+    pub shell: Option<String>,
+}
+
+/// What is known about a focused terminal on another host. Only the
+/// directory, shell and host are reported, so git and project details are
+/// left empty.
+pub fn focused_context(terminal: &FocusedTerminal, username: String) -> Context {
+    let pwd = terminal
+        .cwd
+        .as_deref()
+        .map(PathBuf::from)
+        .unwrap_or_default();
+    let directory_type = if pwd.as_os_str().is_empty() {
+        DirectoryType::Other
+    } else {
+        ContextEngine::directory_type_of(&pwd, None, false)
+    };
+
+    Context {
+        os_name: "unknown".to_string(),
+        os_version: "unknown".to_string(),
+        shell_name: terminal
+            .shell
+            .clone()
+            .unwrap_or_else(|| "unknown".to_string()),
+        shell_version: "unknown".to_string(),
+        pwd,
+        username,
+        git_context: None,
+        detected_languages: vec![],
+        recent_commands: vec![],
+        project_type: None,
+        directory_type,
+        host: terminal.host.clone(),
+    }
+}
+
+/// Prints, one per line: OS, OS release, shell, working directory, user,
+/// home, git top level, branch, origin URL, dirty flag and the directory's
+/// entries separated by '/' (the one character file names can't contain)
+pub const PROBE: &str = concat!(
+    "uname -s; uname -r; echo \"${SHELL##*/}\"; pwd; id -un; echo \"$HOME\"; ",
+    "git rev-parse --show-toplevel 2>/dev/null || echo; ",
+    "git rev-parse --abbrev-ref HEAD 2>/dev/null || echo; ",
+    "git remote get-url origin 2>/dev/null || echo; ",
+    "git status --porcelain 2>/dev/null | head -n 1 | wc -l; ",
+    "ls -A 2>/dev/null | head -n 200 | tr '\\n' '/'; echo",
+);
+
+/// Build a context from the output of `PROBE` run on `host` (None for a
+/// shell on this machine)
+pub fn parse_probe(host: Option<&str>, output: &str) -> Option<Context> {
+    let lines: Vec<&str> = output.lines().map(str::trim).collect();
+    let [os, release, shell, pwd, user, home, toplevel, branch, remote, dirty, entries] =
+        lines.get(..11)?
+    else {
+        return None;
+    };
+
+    let pwd = PathBuf::from(pwd);
+    let entries: Vec<String> = entries
+        .split('/')
+        .filter(|name| !name.is_empty())
+        .map(str::to_string)
+        .collect();
+    let project_type = ContextEngine::project_type_from_entries(&entries);
+    let directory_type = ContextEngine::directory_type_of(
+        &pwd,
+        Some(Path::new(home)).filter(|home| !home.as_os_str().is_empty()),
+        project_type.is_some(),
+    );
+
+    let git_context = (!toplevel.is_empty()).then(|| GitContext {
+        repo_name: Path::new(toplevel)
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_default(),
+        current_branch: branch.to_string(),
+        has_uncommitted_changes: *dirty != "0",
+        remote_url: Some(remote.to_string()).filter(|r| !r.is_empty()),
+        ahead_behind: None,
+        total_commits: None,
+        last_commit_message: None,
+    });
+
+    Some(Context {
+        os_name: os.to_lowercase(),
+        os_version: release.to_string(),
+        shell_name: shell.to_string(),
+        shell_version: "unknown".to_string(),
+        pwd,
+        username: user.to_string(),
+        git_context,
+        detected_languages: ContextEngine::languages_from_entries(&entries),
+        recent_commands: vec![],
+        project_type,
+        directory_type,
+        host: host.map(str::to_string),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::context::{DirectoryType, ProjectType};
+
+    #[test]
+    fn test_parse_probe() {
+        let output = "Linux\n6.8.0-45-generic\nbash\n/srv/api\ndeploy\n/home/deploy\n\
+                      /srv/api\nmain\ngit@github.com:acme/api.git\n1\n\
+                      Cargo.toml/src/.env/deploy.sh/\n";
+        let context = parse_probe(Some("prod-1"), output).unwrap();
+
+        assert_eq!(context.os_name, "linux");
+        assert_eq!(context.pwd, PathBuf::from("/srv/api"));
+        assert_eq!(context.host.as_deref(), Some("prod-1"));
+        assert_eq!(context.project_type, Some(ProjectType::Rust));
+        assert_eq!(context.directory_type, DirectoryType::Project);
+        assert_eq!(context.detected_languages, ["Shell", "TOML"]);
+
+        let git = context.git_context.unwrap();
+        assert_eq!(git.repo_name, "api");
+        assert!(git.has_uncommitted_changes);
+
+        let home = "Linux\n6.8.0\nzsh\n/home/deploy\ndeploy\n/home/deploy\n\n\n\n0\n\n";
+        let context = parse_probe(Some("prod-1"), home).unwrap();
+        assert!(context.git_context.is_none());
+        assert_eq!(context.directory_type, DirectoryType::Home);
+
+        assert!(parse_probe(Some("prod-1"), "Linux\n").is_none());
+    }
+}
@@ -0,0 +1,469 @@
+// Shell dialects
+//
+// Most of Orbit assumes a POSIX shell (sh, bash, zsh, dash, ksh). fish,
+// PowerShell and Nushell have their own builtins, quoting rules and escape
+// characters, so commands are classified, checked and suggested per dialect.
+// The dialect comes from the context's shell: the focused Pulsar terminal,
+// the shell a client reports with its request, or `$SHELL`.
+//
+// Anything unrecognized is treated as POSIX, which is also what Pulsar
+// sessions and containers always get.
+
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ShellDialect {
+    #[default]
+    Posix,
+    Fish,
+    PowerShell,
+    Nu,
+}
+
+const POSIX_BUILTINS: &[&str] = &[
+    "cd", "export", "alias", "source", ".", "echo", "pwd", "exit", "history", "jobs", "fg", "bg",
+    "kill", "wait", "read", "test", "[", "eval", "exec", "set", "unset", "shift", "return",
+    "break", "continue", "trap", "ulimit", "umask", "type", "command", "builtin", "enable", "help",
+    "let", "local", "declare", "typeset", "readonly", "unalias",
+];
+
+/// Builtins and keywords (`fish -c 'builtin -n'`) plus functions shipped
+/// with fish
+const FISH_BUILTINS: &[&str] = &[
+    "abbr",
+    "alias",
+    "and",
+    "argparse",
+    "begin",
+    "bg",
+    "bind",
+    "block",
+    "break",
+    "builtin",
+    "case",
+    "cd",
+    "cdh",
+    "command",
+    "commandline",
+    "complete",
+    "contains",
+    "continue",
+    "count",
+    "dirh",
+    "dirs",
+    "disown",
+    "echo",
+    "else",
+    "emit",
+    "end",
+    "eval",
+    "exec",
+    "exit",
+    "false",
+    "fg",
+    "fish_add_path",
+    "fish_config",
+    "for",
+    "funced",
+    "funcsave",
+    "function",
+    "functions",
+    "history",
+    "if",
+    "jobs",
+    "math",
+    "nextd",
+    "not",
+    "or",
+    "path",
+    "popd",
+    "prevd",
+    "printf",
+    "pushd",
+    "pwd",
+    "random",
+    "read",
+    "realpath",
+    "return",
+    "set",
+    "set_color",
+    "source",
+    "status",
+    "string",
+    "switch",
+    "test",
+    "time",
+    "true",
+    "type",
+    "ulimit",
+    "umask",
+    "vared",
+    "wait",
+    "while",
+];
+
+/// Keywords and the aliases PowerShell defines on every platform. `where`
+/// (Where-Object) is left out: it starts far more questions than pipelines.
+const POWERSHELL_BUILTINS: &[&str] = &[
+    "%", "?", "begin", "break", "catch", "cd", "chdir", "class", "clear", "cls", "continue",
+    "copy", "cpi", "del", "dir", "do", "echo", "else", "elseif", "end", "enum", "erase", "exit",
+    "filter", "finally", "for", "foreach", "function", "gc", "gci", "gcm", "gi", "gl", "gm", "gp",
+    "gps", "gsv", "gv", "h", "history", "icm", "iex", "if", "ii", "ipmo", "irm", "iwr", "md",
+    "measure", "mi", "move", "ni", "nv", "param", "popd", "process", "pushd", "pwd", "r", "rd",
+    "ren", "return", "ri", "rm", "rni", "rp", "rv", "sal", "select", "sl", "sleep", "sls", "sort",
+    "sp", "spps", "start", "sv", "switch", "tee", "throw", "trap", "try", "type", "using", "while",
+    "write",
+];
+
+/// Verbs of `Verb-Noun` cmdlet names (`Get-Verb`), the common ones
+const POWERSHELL_VERBS: &[&str] = &[
+    "add",
+    "clear",
+    "compare",
+    "compress",
+    "connect",
+    "convertfrom",
+    "convertto",
+    "copy",
+    "disable",
+    "disconnect",
+    "enable",
+    "enter",
+    "exit",
+    "expand",
+    "export",
+    "find",
+    "foreach",
+    "format",
+    "get",
+    "group",
+    "import",
+    "install",
+    "invoke",
+    "join",
+    "measure",
+    "move",
+    "new",
+    "out",
+    "pop",
+    "push",
+    "read",
+    "receive",
+    "register",
+    "remove",
+    "rename",
+    "resolve",
+    "restart",
+    "resume",
+    "select",
+    "send",
+    "set",
+    "show",
+    "sort",
+    "split",
+    "start",
+    "stop",
+    "suspend",
+    "tee",
+    "test",
+    "uninstall",
+    "unregister",
+    "update",
+    "wait",
+    "where",
+    "write",
+];
+
+/// Nushell commands that start a pipeline. Filters that only follow one
+/// (`where`, `get`, `each`, `first`) read like English and are left out.
+const NU_BUILTINS: &[&str] = &[
+    "alias", "cd", "char", "clear", "config", "const", "cp", "date", "def", "do", "du", "echo",
+    "exit", "explore", "export", "for", "glob", "help", "hide", "history", "http", "if", "input",
+    "kill", "let", "loop", "ls", "match", "mkdir", "module", "mut", "mv", "open", "overlay",
+    "print", "ps", "pwd", "rm", "save", "seq", "sleep", "source", "start", "sys", "table", "touch",
+    "try", "use", "version", "watch", "which", "while",
+];
+
+impl ShellDialect {
+    /// The dialect of `shell`, a name or path such as `zsh`, `/usr/bin/fish`
+    /// or `pwsh.exe`
+    pub fn detect(shell: &str) -> Self {
+        let name = shell
+            .rsplit(['/', '\\'])
+            .next()
+            .unwrap_or(shell)
+            .to_ascii_lowercase();
+        match name.strip_suffix(".exe").unwrap_or(&name) {
+            "fish" => Self::Fish,
+            "pwsh" | "pwsh-preview" | "powershell" => Self::PowerShell,
+            "nu" | "nushell" => Self::Nu,
+            _ => Self::Posix,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Posix => "POSIX shell",
+            Self::Fish => "fish",
+            Self::PowerShell => "PowerShell",
+            Self::Nu => "Nushell",
+        }
+    }
+
+    /// Program and arguments that run a command line in this dialect
+    pub fn invocation(self) -> (&'static str, &'static [&'static str]) {
+        match self {
+            Self::Posix => ("sh", &["-c"]),
+            Self::Fish => ("fish", &["-c"]),
+            #[cfg(windows)]
+            Self::PowerShell => ("powershell", &["-NoProfile", "-NonInteractive", "-Command"]),
+            #[cfg(not(windows))]
+            Self::PowerShell => ("pwsh", &["-NoProfile", "-NonInteractive", "-Command"]),
+            Self::Nu => ("nu", &["-c"]),
+        }
+    }
+
+    /// Whether `word` is run by the shell itself rather than found on PATH:
+    /// a builtin, keyword, alias or (PowerShell) cmdlet
+    pub fn is_builtin(self, word: &str) -> bool {
+        match self {
+            Self::Posix => POSIX_BUILTINS.contains(&word),
+            Self::Fish => FISH_BUILTINS.contains(&word),
+            Self::PowerShell => {
+                // Case doesn't matter to PowerShell
+                let word = word.to_ascii_lowercase();
+                let is_cmdlet = word.split_once('-').is_some_and(|(verb, noun)| {
+                    POWERSHELL_VERBS.contains(&verb)
+                        && !noun.is_empty()
+                        && noun.chars().all(|c| c.is_ascii_alphanumeric())
+                });
+                // `$x = ...`, `$env:PATH`, `& 'C:\Program Files\app.exe'`
+                is_cmdlet
+                    || word.starts_with('$')
+                    || word == "&"
+                    || POWERSHELL_BUILTINS.contains(&word.as_str())
+            }
+            // `$env.PATH`, `$files | length`
+            Self::Nu => word.starts_with('$') || NU_BUILTINS.contains(&word),
+        }
+    }
+
+    /// Whether `word` names a script or program by path
+    pub fn is_path(self, word: &str) -> bool {
+        let posix = word.starts_with("./") || word.starts_with('/');
+        match self {
+            Self::PowerShell => {
+                let bytes = word.as_bytes();
+                let drive = bytes.len() > 2
+                    && bytes[0].is_ascii_alphabetic()
+                    && bytes[1] == b':'
+                    && matches!(bytes[2], b'\\' | b'/');
+                posix || word.starts_with(".\\") || drive
+            }
+            _ => posix,
+        }
+    }
+
+    /// The program `word` runs, without Nushell's `^` (run the external
+    /// program rather than the builtin of the same name)
+    pub fn program(self, word: &str) -> &str {
+        match self {
+            Self::Nu => word.strip_prefix('^').unwrap_or(word),
+            _ => word,
+        }
+    }
+
+    /// `arg` quoted so the shell passes it on as one literal word
+    pub fn quote(self, arg: &str) -> String {
+        let plain = |extra: &str| {
+            !arg.is_empty()
+                && !arg.starts_with('-')
+                && arg.chars().all(|c| {
+                    c.is_ascii_alphanumeric() || "_./:=+-".contains(c) || extra.contains(c)
+                })
+        };
+        match self {
+            Self::Posix if plain("@%,") => arg.to_string(),
+            Self::Posix => format!("'{}'", arg.replace('\'', r"'\''")),
+            Self::Fish if plain("@%,") => arg.to_string(),
+            Self::Fish => format!("'{}'", arg.replace('\\', r"\\").replace('\'', r"\'")),
+            Self::PowerShell if plain("\\") => arg.to_string(),
+            Self::PowerShell => {
+                // PowerShell also ends single-quoted strings at typographic
+                // quotes; doubling any of them keeps it literal
+                let mut quoted = String::from("'");
+                for c in arg.chars() {
+                    if matches!(c, '\'' | '\u{2018}' | '\u{2019}' | '\u{201A}' | '\u{201B}') {
+                        quoted.push(c);
+                    }
+                    quoted.push(c);
+                }
+                quoted.push('\'');
+                quoted
+            }
+            Self::Nu if plain("") => arg.to_string(),
+            // Nushell single quotes have no escapes at all
+            Self::Nu if !arg.contains('\'') => format!("'{}'", arg),
+            Self::Nu => format!("\"{}\"", arg.replace('\\', r"\\").replace('"', "\\\"")),
+        }
+    }
+
+    /// Split a command line into words with quotes removed and escapes
+    /// applied; `;`, `|` and `&` outside quotes are words of their own
+    pub fn tokenize(self, command: &str) -> Vec<String> {
+        let mut tokens = Vec::new();
+        let mut current_token = String::new();
+        let mut in_single_quote = false;
+        let mut in_double_quote = false;
+        let mut chars = command.chars().peekable();
+
+        while let Some(c) = chars.next() {
+            match c {
+                // fish allows \' and \\ inside single quotes
+                '\\' if in_single_quote && self == Self::Fish => match chars.peek() {
+                    Some(&next @ ('\'' | '\\')) => {
+                        current_token.push(next);
+                        chars.next();
+                    }
+                    _ => current_token.push(c),
+                },
+                c if !in_single_quote && self.is_escape(c, in_double_quote) => {
+                    if let Some(next) = chars.next() {
+                        current_token.push(next);
+                    }
+                }
+                '\'' if !in_double_quote => {
+                    in_single_quote = !in_single_quote;
+                }
+                '"' if !in_single_quote => {
+                    in_double_quote = !in_double_quote;
+                }
+                ' ' | '\t' | '\n' if !in_single_quote && !in_double_quote => {
+                    if !current_token.is_empty() {
+                        tokens.push(std::mem::take(&mut current_token));
+                    }
+                }
+                ';' | '|' | '&' if !in_single_quote && !in_double_quote => {
+                    if !current_token.is_empty() {
+                        tokens.push(std::mem::take(&mut current_token));
+                    }
+                    // Add separator as token
+                    tokens.push(c.to_string());
+                }
+                _ => {
+                    current_token.push(c);
+                }
+            }
+        }
+
+        if !current_token.is_empty() {
+            tokens.push(current_token);
+        }
+
+        tokens
+    }
+
+    /// Whether `c` escapes the next character outside single quotes
+    fn is_escape(self, c: char, in_double_quote: bool) -> bool {
+        match self {
+            Self::Posix | Self::Fish => c == '\\',
+            Self::PowerShell => c == '`',
+            // Backslashes are literal in bare words (`C:\Users`)
+            Self::Nu => c == '\\' && in_double_quote,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_detect() {
+        assert_eq!(ShellDialect::detect("/bin/zsh"), ShellDialect::Posix);
+        assert_eq!(ShellDialect::detect("bash"), ShellDialect::Posix);
+        assert_eq!(ShellDialect::detect("unknown"), ShellDialect::Posix);
+        assert_eq!(
+            ShellDialect::detect("/opt/homebrew/bin/fish"),
+            ShellDialect::Fish
+        );
+        assert_eq!(
+            ShellDialect::detect(r"C:\Program Files\PowerShell\7\pwsh.exe"),
+            ShellDialect::PowerShell
+        );
+        assert_eq!(ShellDialect::detect("PowerShell"), ShellDialect::PowerShell);
+        assert_eq!(ShellDialect::detect("/usr/local/bin/nu"), ShellDialect::Nu);
+    }
+
+    #[test]
+    fn test_builtins() {
+        let posix = ShellDialect::Posix;
+        assert!(posix.is_builtin("export"));
+        assert!(!posix.is_builtin("set_color"));
+
+        let fish = ShellDialect::Fish;
+        assert!(fish.is_builtin("set_color"));
+        assert!(fish.is_builtin("funcsave"));
+        assert!(!fish.is_builtin("export"));
+
+        let pwsh = ShellDialect::PowerShell;
+        assert!(pwsh.is_builtin("Get-ChildItem"));
+        assert!(pwsh.is_builtin("remove-item"));
+        assert!(pwsh.is_builtin("$env:PATH"));
+        assert!(pwsh.is_builtin("gci"));
+        assert!(!pwsh.is_builtin("where"));
+        assert!(!pwsh.is_builtin("Made-Up"));
+        assert!(!pwsh.is_builtin("git"));
+
+        let nu = ShellDialect::Nu;
+        assert!(nu.is_builtin("open"));
+        assert!(nu.is_builtin("$env.PATH"));
+        assert!(!nu.is_builtin("where"));
+        assert_eq!(nu.program("^ls"), "ls");
+        assert_eq!(posix.program("^ls"), "^ls");
+
+        assert!(pwsh.is_path(r".\build.ps1"));
+        assert!(pwsh.is_path(r"C:\tools\rg.exe"));
+        assert!(!posix.is_path(r".\build.ps1"));
+    }
+
+    #[test]
+    fn test_quote() {
+        let it_s = "it's here";
+        assert_eq!(ShellDialect::Posix.quote("src/main.rs"), "src/main.rs");
+        assert_eq!(ShellDialect::Posix.quote(it_s), r"'it'\''s here'");
+        assert_eq!(ShellDialect::Fish.quote(it_s), r"'it\'s here'");
+        assert_eq!(ShellDialect::Fish.quote(r"a\b c"), r"'a\\b c'");
+        assert_eq!(ShellDialect::PowerShell.quote(it_s), "'it''s here'");
+        assert_eq!(
+            ShellDialect::PowerShell.quote("it\u{2019}s"),
+            "'it\u{2019}\u{2019}s'"
+        );
+        assert_eq!(ShellDialect::PowerShell.quote(r"C:\Users"), r"C:\Users");
+        assert_eq!(ShellDialect::PowerShell.quote("$HOME"), "'$HOME'");
+        assert_eq!(ShellDialect::Nu.quote("two words"), "'two words'");
+        assert_eq!(ShellDialect::Nu.quote(it_s), "\"it's here\"");
+        assert_eq!(ShellDialect::Nu.quote("-rf"), "'-rf'");
+    }
+
+    #[test]
+    fn test_tokenize_per_dialect() {
+        assert_eq!(
+            ShellDialect::Posix.tokenize(r#"rm \-rf "a b"|wc"#),
+            ["rm", "-rf", "a b", "|", "wc"]
+        );
+        assert_eq!(
+            ShellDialect::Fish.tokenize(r"echo 'it\'s' ; and ls"),
+            ["echo", "it's", ";", "and", "ls"]
+        );
+        assert_eq!(
+            ShellDialect::PowerShell.tokenize(r"Remove-Item C:\tmp\x -`Recurse"),
+            ["Remove-Item", r"C:\tmp\x", "-Recurse"]
+        );
+        assert_eq!(
+            ShellDialect::Nu.tokenize(r#"rm C:\tmp\x "a\"b""#),
+            ["rm", r"C:\tmp\x", "a\"b"]
+        );
+    }
+}
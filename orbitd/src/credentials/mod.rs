@@ -2,6 +2,10 @@ use anyhow::{anyhow, Result};
 use keyring::Entry;
 use tracing::{debug, warn};
 
+pub mod secrets;
+
+pub use secrets::{is_secret_reference, resolve_secret, SecretResolver, Secrets};
+
 /// Secure credential storage using system keychain
 ///
 /// Stores API keys and other sensitive credentials in the system's secure storage:
@@ -169,6 +173,45 @@ impl CredentialStore {
             provider
         ))
     }
+
+    /// Get `provider`'s API key, from the secret manager `configured`
+    /// refers to when it is a reference (`op://`, `pass:`, `env:`), else
+    /// as `get_api_key_with_fallback` finds it
+    pub fn get_configured_api_key(
+        &self,
+        provider: &str,
+        configured: Option<&str>,
+    ) -> Result<String> {
+        match configured {
+            Some(reference) if is_secret_reference(reference) => resolve_secret(reference),
+            _ => self.get_api_key_with_fallback(provider),
+        }
+    }
+
+    /// Keychain account holding `provider`'s API key for `profile`
+    pub fn profile_account(provider: &str, profile: &str) -> String {
+        format!("{}@{}", provider, profile)
+    }
+
+    /// Get the API key `profile` uses for `provider`
+    ///
+    /// Tries the profile's own key in the keychain first, then falls back
+    /// to the provider's key as `get_configured_api_key` finds it.
+    pub fn get_profile_api_key(
+        &self,
+        provider: &str,
+        profile: Option<&str>,
+        configured: Option<&str>,
+    ) -> Result<String> {
+        if let Some(profile) = profile {
+            let account = Self::profile_account(provider, profile);
+            if self.has_api_key(&account) {
+                return self.get_api_key(&account);
+            }
+            debug!("No {} key for profile '{}', using the default", provider, profile);
+        }
+        self.get_configured_api_key(provider, configured)
+    }
 }
 
 impl Default for CredentialStore {
@@ -190,6 +233,11 @@ mod tests {
         assert_eq!(store.service_name, "orbit");
     }
 
+    #[test]
+    fn test_profile_account() {
+        assert_eq!(CredentialStore::profile_account("openai", "work"), "openai@work");
+    }
+
     #[test]
     #[ignore] // Requires actual keychain access
     fn test_set_and_get_api_key() {
@@ -0,0 +1,319 @@
+// Secrets kept in external managers, referenced from config.yaml
+//
+// A configured API key or password may name where the secret lives instead
+// of holding it:
+//
+//   op://Private/OpenAI/credential    1Password, via `op read`
+//   pass:orbit/openai                 first line of `pass show`
+//   env:OPENAI_API_KEY                the daemon's environment
+//   env:~/.config/orbit/keys.env#KEY  a dotenv-style file
+//
+// Anything else is a literal. Values fetched through a CLI are cached for a
+// few minutes so every query doesn't spawn `op`.
+
+use anyhow::{anyhow, bail, Context, Result};
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::process::{Command, Stdio};
+use std::sync::{Mutex, OnceLock};
+use std::time::{Duration, Instant};
+use tracing::debug;
+
+/// How long a fetched secret is reused
+const CACHE_TTL: Duration = Duration::from_secs(300);
+
+/// How long a secret manager CLI may take, e.g. waiting on an unlock prompt
+const COMMAND_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Fetches secrets for references starting with its prefix
+pub trait SecretResolver: Send + Sync {
+    /// e.g. `op://`; references are matched on it
+    fn prefix(&self) -> &'static str;
+
+    /// Fetch the secret for `reference`, given without the prefix
+    fn resolve(&self, reference: &str) -> Result<String>;
+
+    /// Whether fetched values may be reused for a while
+    fn cacheable(&self) -> bool {
+        true
+    }
+}
+
+/// 1Password items, read with the `op` CLI
+pub struct OnePassword;
+
+impl SecretResolver for OnePassword {
+    fn prefix(&self) -> &'static str {
+        "op://"
+    }
+
+    fn resolve(&self, reference: &str) -> Result<String> {
+        let reference = format!("op://{}", reference);
+        run("op", &["read", "--no-newline", &reference])
+    }
+}
+
+/// Entries of the standard Unix password manager; the password is the
+/// entry's first line
+pub struct Pass;
+
+impl SecretResolver for Pass {
+    fn prefix(&self) -> &'static str {
+        "pass:"
+    }
+
+    fn resolve(&self, reference: &str) -> Result<String> {
+        let entry = run("pass", &["show", reference])?;
+        Ok(entry.lines().next().unwrap_or_default().to_string())
+    }
+}
+
+/// Environment variables, of the daemon or from a dotenv-style file
+pub struct Env;
+
+impl SecretResolver for Env {
+    fn prefix(&self) -> &'static str {
+        "env:"
+    }
+
+    fn resolve(&self, reference: &str) -> Result<String> {
+        let Some((file, name)) = reference.rsplit_once('#') else {
+            return std::env::var(reference)
+                .map_err(|_| anyhow!("Environment variable {} is not set", reference));
+        };
+
+        let path = expand_home(file);
+        let content = std::fs::read_to_string(&path)
+            .with_context(|| format!("Failed to read {}", path.display()))?;
+        env_file_value(&content, name)
+            .ok_or_else(|| anyhow!("{} is not set in {}", name, path.display()))
+    }
+
+    fn cacheable(&self) -> bool {
+        false
+    }
+}
+
+/// Resolves secret references through the registered resolvers
+pub struct Secrets {
+    resolvers: Vec<Box<dyn SecretResolver>>,
+    cache: Mutex<HashMap<String, (Instant, String)>>,
+}
+
+impl Secrets {
+    /// A registry with no resolvers; every value is a literal
+    pub fn empty() -> Self {
+        Self {
+            resolvers: Vec::new(),
+            cache: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Register a resolver; it wins over earlier ones with the same prefix
+    pub fn with_resolver(mut self, resolver: impl SecretResolver + 'static) -> Self {
+        self.resolvers.insert(0, Box::new(resolver));
+        self
+    }
+
+    fn resolver<'a>(&self, value: &'a str) -> Option<(&dyn SecretResolver, &'a str)> {
+        self.resolvers.iter().find_map(|resolver| {
+            let reference = value.strip_prefix(resolver.prefix())?;
+            Some((resolver.as_ref(), reference))
+        })
+    }
+
+    /// Whether `value` names a secret rather than holding one
+    pub fn is_reference(&self, value: &str) -> bool {
+        self.resolver(value).is_some()
+    }
+
+    /// The secret `value` refers to, or `value` itself when it is a literal
+    pub fn resolve(&self, value: &str) -> Result<String> {
+        let Some((resolver, reference)) = self.resolver(value) else {
+            return Ok(value.to_string());
+        };
+        if reference.is_empty() {
+            bail!("Empty secret reference '{}'", value);
+        }
+
+        if resolver.cacheable() {
+            let cache = self.cache.lock().unwrap();
+            if let Some((fetched, secret)) = cache.get(value) {
+                if fetched.elapsed() < CACHE_TTL {
+                    return Ok(secret.clone());
+                }
+            }
+        }
+
+        debug!("Resolving secret reference {}", value);
+        let secret = resolver
+            .resolve(reference)
+            .with_context(|| format!("Failed to resolve secret '{}'", value))?;
+        if secret.is_empty() {
+            bail!("Secret '{}' is empty", value);
+        }
+
+        if resolver.cacheable() {
+            self.cache
+                .lock()
+                .unwrap()
+                .insert(value.to_string(), (Instant::now(), secret.clone()));
+        }
+        Ok(secret)
+    }
+}
+
+impl Default for Secrets {
+    fn default() -> Self {
+        Self::empty().with_resolver(Env).with_resolver(Pass).with_resolver(OnePassword)
+    }
+}
+
+/// The daemon's secret resolvers
+pub fn secrets() -> &'static Secrets {
+    static SECRETS: OnceLock<Secrets> = OnceLock::new();
+    SECRETS.get_or_init(Secrets::default)
+}
+
+/// Resolve `value` with the default resolvers; literals pass through
+///
+/// References fetched through a CLI block; see `run`.
+pub fn resolve_secret(value: &str) -> Result<String> {
+    secrets().resolve(value)
+}
+
+/// Whether `value` is a reference the default resolvers understand
+pub fn is_secret_reference(value: &str) -> bool {
+    secrets().is_reference(value)
+}
+
+/// Run a secret manager CLI and return its output
+///
+/// Blocks the calling thread until the CLI exits or `COMMAND_TIMEOUT`
+/// passes.
+fn run(program: &str, args: &[&str]) -> Result<String> {
+    let mut child = Command::new(program)
+        .args(args)
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .with_context(|| format!("Failed to run {}; is it installed?", program))?;
+
+    let deadline = Instant::now() + COMMAND_TIMEOUT;
+    while child.try_wait()?.is_none() {
+        if Instant::now() >= deadline {
+            let _ = child.kill();
+            let _ = child.wait();
+            bail!(
+                "{} gave no answer within {}s",
+                program,
+                COMMAND_TIMEOUT.as_secs()
+            );
+        }
+        std::thread::sleep(Duration::from_millis(20));
+    }
+
+    let output = child.wait_with_output()?;
+    if !output.status.success() {
+        bail!(
+            "{} failed: {}",
+            program,
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    String::from_utf8(output.stdout)
+        .map_err(|_| anyhow!("{} printed a secret that isn't UTF-8", program))
+}
+
+/// `name`'s value in a dotenv-style file: `NAME=value` lines, optionally
+/// `export`ed and quoted, with `#` comments (after unquoted values too)
+fn env_file_value(content: &str, name: &str) -> Option<String> {
+    content.lines().find_map(|line| {
+        let line = line.trim();
+        let line = line.strip_prefix("export ").unwrap_or(line);
+        let (key, value) = line.split_once('=')?;
+        if line.starts_with('#') || key.trim() != name {
+            return None;
+        }
+        let value = value.trim();
+        let quoted = ['"', '\'']
+            .iter()
+            .find_map(|quote| value.strip_prefix(*quote)?.strip_suffix(*quote));
+        let value = quoted.unwrap_or_else(|| value.split(" #").next().unwrap_or_default().trim());
+        Some(value.to_string())
+    })
+}
+
+fn expand_home(path: &str) -> PathBuf {
+    match path.strip_prefix("~/") {
+        Some(rest) => dirs::home_dir().map_or_else(|| PathBuf::from(path), |home| home.join(rest)),
+        None => PathBuf::from(path),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    struct Counting(Arc<AtomicUsize>);
+
+    impl SecretResolver for Counting {
+        fn prefix(&self) -> &'static str {
+            "test:"
+        }
+
+        fn resolve(&self, reference: &str) -> Result<String> {
+            self.0.fetch_add(1, Ordering::SeqCst);
+            Ok(format!("secret-for-{}", reference))
+        }
+    }
+
+    #[test]
+    fn test_references_resolve_and_literals_pass_through() {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let secrets = Secrets::default().with_resolver(Counting(calls.clone()));
+
+        assert!(!secrets.is_reference("sk-ant-123"));
+        assert_eq!(secrets.resolve("sk-ant-123").unwrap(), "sk-ant-123");
+        assert!(secrets.is_reference("op://Private/OpenAI/credential"));
+        assert!(secrets.is_reference("pass:orbit/openai"));
+
+        assert_eq!(secrets.resolve("test:openai").unwrap(), "secret-for-openai");
+        assert_eq!(secrets.resolve("test:openai").unwrap(), "secret-for-openai");
+        assert_eq!(calls.load(Ordering::SeqCst), 1, "second lookup is cached");
+        assert!(secrets.resolve("test:").is_err());
+    }
+
+    #[test]
+    fn test_env_references() {
+        let secrets = Secrets::default();
+        std::env::set_var("ORBIT_SECRETS_TEST_KEY", "env-secret");
+        assert_eq!(
+            secrets.resolve("env:ORBIT_SECRETS_TEST_KEY").unwrap(),
+            "env-secret"
+        );
+        std::env::remove_var("ORBIT_SECRETS_TEST_KEY");
+        assert!(secrets.resolve("env:ORBIT_SECRETS_TEST_KEY").is_err());
+
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join("keys.env");
+        std::fs::write(
+            &file,
+            "# provider keys\nexport OPENAI_API_KEY=\"sk-openai\"\nCLAUDE_API_KEY=sk-ant # rotated\n",
+        )
+        .unwrap();
+        let reference = |name: &str| format!("env:{}#{}", file.display(), name);
+        assert_eq!(
+            secrets.resolve(&reference("OPENAI_API_KEY")).unwrap(),
+            "sk-openai"
+        );
+        assert_eq!(
+            secrets.resolve(&reference("CLAUDE_API_KEY")).unwrap(),
+            "sk-ant"
+        );
+        assert!(secrets.resolve(&reference("GEMINI_API_KEY")).is_err());
+    }
+}
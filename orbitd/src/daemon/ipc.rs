@@ -75,32 +75,285 @@ pub enum Request {
         input: String,
         cwd: String,
         shell: String,
+        /// Pulsar session the command is for. Classification then uses the
+        /// context of the shell in that session (often on another host).
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        session: Option<uuid::Uuid>,
+        /// What the terminal showed before the input, e.g. the error it is
+        /// about; given to the provider as context
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        output: Option<String>,
+    },
+    /// How the classifier sees `input`, without asking a provider or
+    /// recording anything
+    Classify {
+        input: String,
     },
     Feedback {
         input: String,
         executed: String,
         result: FeedbackResult,
+        /// Why a suggestion was turned down; they set how hard it is
+        /// penalized
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        reasons: Vec<crate::learning::FeedbackReason>,
+        /// Free text from the user, kept with the reasons
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        note: Option<String>,
     },
     Status,
+    /// Database maintenance (health, backup, vacuum)
+    Database {
+        action: DatabaseAction,
+    },
+    /// Delete all learned data: patterns, corrections, command history,
+    /// time-of-day patterns, analytics, insights and learning.db backups.
+    /// Preferences are kept.
+    PurgeLearningData {
+        /// Must be true; guards against accidental purges
+        confirm: bool,
+    },
+    /// Ranked completions for a partial command line
+    Complete {
+        line: String,
+        /// Byte offset of the cursor; defaults to the end of the line
+        #[serde(default)]
+        cursor: Option<usize>,
+        /// Working directory of the shell session, for path completion
+        cwd: String,
+    },
+    /// Manage WebAssembly extensions and collect their suggestions
+    Extensions {
+        action: ExtensionAction,
+    },
+    /// Command playbooks for the current project
+    Playbooks {
+        action: PlaybookAction,
+    },
+    /// The Pulsar terminal the user is typing in, pushed by pulsar-daemon
+    /// whenever focus or the terminal's directory changes
+    TerminalFocus {
+        terminal: Option<crate::context::FocusedTerminal>,
+    },
+    /// Run an approved command on the host or in a container and return its
+    /// output
+    Execute {
+        command: String,
+        /// Working directory; mounted at /workspace for image targets
+        cwd: String,
+        #[serde(default)]
+        target: crate::executor::target::ExecutionTarget,
+        /// What the user typed, when the command is a suggestion; recorded
+        /// with the result
+        #[serde(default)]
+        input: Option<String>,
+    },
+    /// Running containers that can be used as an execution target
+    Containers {
+        /// Defaults to the first runtime on PATH
+        #[serde(default)]
+        runtime: Option<crate::executor::target::ContainerRuntime>,
+    },
+    /// Run an approved command as root through sudo. Without a password
+    /// only sudo's cached credentials are tried; the response says when a
+    /// password is needed. Commands failing the safety checks are refused,
+    /// and destructive ones need `confirmed`.
+    RunElevated {
+        command: String,
+        #[serde(default)]
+        password: Option<crate::executor::elevation::SudoPassword>,
+        /// The password came from the client's vault rather than a prompt
+        #[serde(default)]
+        from_vault: bool,
+        /// The user agreed to run it although it is destructive
+        #[serde(default)]
+        confirmed: bool,
+    },
+    /// Past commands matching a natural-language description, e.g.
+    /// "the docker command I ran last week that pruned volumes"
+    SearchHistory {
+        query: String,
+        /// Maximum matches; defaults to 20
+        #[serde(default)]
+        limit: Option<usize>,
+    },
+    /// Whether a command is destructive, for clients that run commands
+    /// themselves (pulsar-daemon re-running one in another session)
+    CheckCommand {
+        command: String,
+    },
+    /// Change log verbosity until restart: a level (`debug`) or filter
+    /// directives (`info,orbitd::learning=trace`)
+    SetLogLevel {
+        level: String,
+    },
+    /// Turn offline mode on (no cloud providers, whatever the network) or
+    /// off; Status reports the result
+    SetOffline {
+        offline: bool,
+    },
+    /// Rebuild the known commands cache now, so binaries installed outside
+    /// Orbit are recognized without waiting for the next check
+    RefreshCommands,
     Shutdown,
 }
 
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub enum DatabaseAction {
+    Health,
+    Backup,
+    Vacuum,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum ExtensionAction {
+    /// Installed extensions, including permissions awaiting approval
+    List,
+    /// Re-scan the extensions directory
+    Reload,
+    /// Grant the permissions the extension requests (after prompting the user)
+    Approve { name: String },
+    /// Withdraw an extension's permissions
+    Revoke { name: String },
+    /// Drain suggestions emitted by extensions
+    Suggestions,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum PlaybookAction {
+    /// Saved playbooks
+    List,
+    /// Repeated command sequences worth saving, with the saved playbooks
+    Candidates,
+    /// Save a sequence (usually a candidate) under a name and trigger
+    Save {
+        name: String,
+        trigger: String,
+        commands: Vec<String>,
+    },
+    Delete { name: String },
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub enum Response {
     Passthrough,
     Replaced {
         command: String,
+        /// Files the command will modify, for the user to confirm before
+        /// running it. Absent when it modifies none or approval is automatic.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        dry_run: Option<crate::executor::dry_run::DryRun>,
+        /// Set when the command needs root, so the client can run it
+        /// through `RunElevated`
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        elevation: Option<crate::executor::elevation::Elevation>,
+        /// What the command does and affects, when its risk tier is one
+        /// `execution.explain_tiers` lists and approval isn't automatic
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        explanation: Option<crate::executor::risk::Explanation>,
+        /// Remote host the command was suggested for, when the focused
+        /// terminal is on one
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        host: Option<String>,
     },
     Error {
         message: String,
     },
+    /// A provider's rate limit was reached; try again after `retry_after_ms`
+    RateLimited {
+        provider: String,
+        retry_after_ms: u64,
+        message: String,
+    },
     Status {
         uptime_secs: u64,
         commands_processed: u64,
+        /// Whether cloud providers are in use, and why not
+        #[serde(default)]
+        connectivity: crate::connectivity::ConnectivityStatus,
+        /// Subsystems still warming up use slower or simpler fallbacks
+        #[serde(default)]
+        readiness: Vec<crate::readiness::SubsystemStatus>,
+        /// How much of each called provider's rate limit is in use
+        #[serde(default)]
+        rate_limits: Vec<crate::providers::RateLimitUsage>,
+    },
+    Classified {
+        classification: Classification,
+    },
+    Database {
+        health: pulsar_db::DbHealth,
+        backup_path: Option<String>,
+    },
+    Purged {
+        report: crate::learning::PurgeReport,
+    },
+    Completions {
+        items: Vec<crate::completion::Completion>,
+    },
+    Extensions {
+        extensions: Vec<crate::extensions::ExtensionInfo>,
+        suggestions: Vec<crate::extensions::ExtensionSuggestion>,
+    },
+    Playbooks {
+        playbooks: Vec<crate::learning::Playbook>,
+        candidates: Vec<crate::learning::PlaybookCandidate>,
+    },
+    History {
+        matches: Vec<crate::learning::HistoryMatch>,
+    },
+    Elevated {
+        run: crate::executor::elevation::ElevatedRun,
+    },
+    Executed {
+        output: crate::executor::output::ExecutionOutput,
+    },
+    Containers {
+        runtime: crate::executor::target::ContainerRuntime,
+        containers: Vec<crate::executor::target::ContainerInfo>,
+    },
+    LogLevel {
+        level: String,
+        previous: String,
+    },
+    Checked {
+        destructive: bool,
+    },
+    CommandsRefreshed {
+        refresh: crate::classifier::CommandsRefresh,
     },
     Ok,
 }
 
+/// What the classifier made of some input
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Classification {
+    /// A command to run as typed
+    Known,
+    /// Input Orbit has learned to replace with `command`
+    LearnedPattern { command: String, confidence: f32 },
+    /// Needs a provider to turn into a command
+    NaturalLanguage,
+    Ambiguous,
+}
+
+impl From<crate::classifier::CommandType> for Classification {
+    fn from(command_type: crate::classifier::CommandType) -> Self {
+        use crate::classifier::CommandType;
+
+        match command_type {
+            CommandType::Known => Self::Known,
+            CommandType::LearnedPattern(pattern) => Self::LearnedPattern {
+                command: pattern.learned_command,
+                confidence: pattern.confidence,
+            },
+            CommandType::NaturalLanguage => Self::NaturalLanguage,
+            CommandType::Ambiguous => Self::Ambiguous,
+        }
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum FeedbackResult {
     Success,
@@ -109,6 +362,34 @@ pub enum FeedbackResult {
     Edited { new_command: String },
 }
 
+/// Replace the log filter; every listener answers `SetLogLevel` this way
+pub fn set_log_level(level: String) -> Response {
+    match pulsar_log::set_filter(&level) {
+        Ok(previous) => {
+            tracing::info!("Log level changed from {} to {}", previous, level);
+            Response::LogLevel { level, previous }
+        }
+        Err(e) => Response::Error {
+            message: e.to_string(),
+        },
+    }
+}
+
+/// Send one request to the daemon at `socket_path` and wait for the response
+#[cfg(unix)]
+pub async fn call(socket_path: &std::path::Path, request: &Request) -> anyhow::Result<Response> {
+    use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+
+    let mut stream = tokio::net::UnixStream::connect(socket_path).await?;
+    stream
+        .write_all((serde_json::to_string(request)? + "\n").as_bytes())
+        .await?;
+
+    let mut response = String::new();
+    BufReader::new(stream).read_line(&mut response).await?;
+    Ok(serde_json::from_str(&response)?)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -194,4 +475,56 @@ mod tests {
         assert!(json.contains("\"version\":\"1.0.0\""));
         assert!(json.contains("Ok"));
     }
+
+    #[test]
+    fn test_classified_response_shape() {
+        // Clients outside Rust (the Python package, editors) match on this
+        let response = Response::Classified {
+            classification: Classification::LearnedPattern {
+                command: "git status".to_string(),
+                confidence: 0.5,
+            },
+        };
+        let json = serde_json::to_value(&response).unwrap();
+        assert_eq!(
+            json,
+            serde_json::json!({
+                "Classified": {
+                    "classification": {
+                        "type": "learned_pattern",
+                        "command": "git status",
+                        "confidence": 0.5,
+                    }
+                }
+            })
+        );
+
+        let request: Request = serde_json::from_str(r#"{"Classify":{"input":"ls"}}"#).unwrap();
+        assert!(matches!(request, Request::Classify { input } if input == "ls"));
+    }
+
+    #[test]
+    fn test_feedback_reasons_are_optional() {
+        use crate::learning::FeedbackReason;
+
+        let request: Request = serde_json::from_str(
+            r#"{"Feedback":{"input":"list files","executed":"ls","result":"Rejected"}}"#,
+        )
+        .unwrap();
+        assert!(matches!(
+            request,
+            Request::Feedback { reasons, note: None, .. } if reasons.is_empty()
+        ));
+
+        let request: Request = serde_json::from_str(
+            r#"{"Feedback":{"input":"clean","executed":"rm -rf /","result":"Rejected",
+                "reasons":["dangerous","wrong_context"],"note":"not here"}}"#,
+        )
+        .unwrap();
+        let Request::Feedback { reasons, note, .. } = request else {
+            panic!("not feedback");
+        };
+        assert_eq!(reasons, [FeedbackReason::Dangerous, FeedbackReason::WrongContext]);
+        assert_eq!(note.as_deref(), Some("not here"));
+    }
 }
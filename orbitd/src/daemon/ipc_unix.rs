@@ -12,7 +12,7 @@ use tokio::net::{UnixListener, UnixStream};
 use tokio::sync::Semaphore;
 use tracing::{debug, error, info, warn};
 
-use super::ipc::{Request, Response};
+use super::ipc::{set_log_level, Request, Response};
 use super::ipc_common::{IpcClient, IpcTransport};
 use async_trait::async_trait;
 
@@ -191,7 +191,12 @@ impl UnixIpcServer {
         // with the actual request handler from the server
 
         match request {
-            Request::Command { input, cwd, shell } => {
+            Request::Command {
+                input,
+                cwd,
+                shell,
+                ..
+            } => {
                 debug!(
                     "Processing command: {} (cwd: {}, shell: {})",
                     input, cwd, shell
@@ -205,6 +210,7 @@ impl UnixIpcServer {
                 input,
                 executed,
                 result,
+                ..
             } => {
                 debug!("Processing feedback: {} -> {} ({:?})", input, executed, result);
                 Response::Ok
@@ -213,6 +219,66 @@ impl UnixIpcServer {
             Request::Status => Response::Status {
                 uptime_secs: 0,
                 commands_processed: 0,
+                connectivity: Default::default(),
+                readiness: Vec::new(),
+                rate_limits: Vec::new(),
+            },
+
+            Request::Database { action } => {
+                debug!("Database {:?} requested", action);
+                Response::Error {
+                    message: "Database maintenance is not available on this listener".to_string(),
+                }
+            }
+
+            Request::Classify { .. } => Response::Error {
+                message: "Classification is not available on this listener".to_string(),
+            },
+
+            Request::PurgeLearningData { .. } => Response::Error {
+                message: "Purging learning data is not available on this listener".to_string(),
+            },
+
+            Request::Complete { .. } => Response::Error {
+                message: "Completion is not available on this listener".to_string(),
+            },
+
+            Request::Extensions { .. } => Response::Error {
+                message: "Extensions are not available on this listener".to_string(),
+            },
+
+            Request::Playbooks { .. } => Response::Error {
+                message: "Playbooks are not available on this listener".to_string(),
+            },
+
+            Request::TerminalFocus { .. } => Response::Error {
+                message: "Terminal focus is not available on this listener".to_string(),
+            },
+
+            Request::Execute { .. } | Request::Containers { .. } => Response::Error {
+                message: "Command execution is not available on this listener".to_string(),
+            },
+
+            Request::RunElevated { .. } => Response::Error {
+                message: "Privilege elevation is not available on this listener".to_string(),
+            },
+
+            Request::SearchHistory { .. } => Response::Error {
+                message: "History search is not available on this listener".to_string(),
+            },
+
+            Request::CheckCommand { .. } => Response::Error {
+                message: "Command checks are not available on this listener".to_string(),
+            },
+
+            Request::SetLogLevel { level } => set_log_level(level),
+
+            Request::SetOffline { .. } => Response::Error {
+                message: "Offline mode is not available on this listener".to_string(),
+            },
+
+            Request::RefreshCommands => Response::Error {
+                message: "Refreshing commands is not available on this listener".to_string(),
             },
 
             Request::Shutdown => {
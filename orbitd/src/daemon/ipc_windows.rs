@@ -13,7 +13,7 @@ use tracing::{debug, error, info, warn};
 #[cfg(windows)]
 use tokio::net::windows::named_pipe::{NamedPipeServer, ServerOptions};
 
-use super::ipc::{Request, Response};
+use super::ipc::{set_log_level, Request, Response};
 use super::ipc_common::{IpcClient, IpcTransport};
 use async_trait::async_trait;
 
@@ -163,7 +163,12 @@ impl WindowsIpcServer {
         // with the actual request handler from the server
 
         match request {
-            Request::Command { input, cwd, shell } => {
+            Request::Command {
+                input,
+                cwd,
+                shell,
+                ..
+            } => {
                 debug!(
                     "Processing command: {} (cwd: {}, shell: {})",
                     input, cwd, shell
@@ -177,6 +182,7 @@ impl WindowsIpcServer {
                 input,
                 executed,
                 result,
+                ..
             } => {
                 debug!("Processing feedback: {} -> {} ({:?})", input, executed, result);
                 Response::Ok
@@ -185,6 +191,66 @@ impl WindowsIpcServer {
             Request::Status => Response::Status {
                 uptime_secs: 0,
                 commands_processed: 0,
+                connectivity: Default::default(),
+                readiness: Vec::new(),
+                rate_limits: Vec::new(),
+            },
+
+            Request::Database { action } => {
+                debug!("Database {:?} requested", action);
+                Response::Error {
+                    message: "Database maintenance is not available on this listener".to_string(),
+                }
+            }
+
+            Request::Classify { .. } => Response::Error {
+                message: "Classification is not available on this listener".to_string(),
+            },
+
+            Request::PurgeLearningData { .. } => Response::Error {
+                message: "Purging learning data is not available on this listener".to_string(),
+            },
+
+            Request::Complete { .. } => Response::Error {
+                message: "Completion is not available on this listener".to_string(),
+            },
+
+            Request::Extensions { .. } => Response::Error {
+                message: "Extensions are not available on this listener".to_string(),
+            },
+
+            Request::Playbooks { .. } => Response::Error {
+                message: "Playbooks are not available on this listener".to_string(),
+            },
+
+            Request::TerminalFocus { .. } => Response::Error {
+                message: "Terminal focus is not available on this listener".to_string(),
+            },
+
+            Request::Execute { .. } | Request::Containers { .. } => Response::Error {
+                message: "Command execution is not available on this listener".to_string(),
+            },
+
+            Request::RunElevated { .. } => Response::Error {
+                message: "Privilege elevation is not available on this listener".to_string(),
+            },
+
+            Request::SearchHistory { .. } => Response::Error {
+                message: "History search is not available on this listener".to_string(),
+            },
+
+            Request::CheckCommand { .. } => Response::Error {
+                message: "Command checks are not available on this listener".to_string(),
+            },
+
+            Request::SetLogLevel { level } => set_log_level(level),
+
+            Request::SetOffline { .. } => Response::Error {
+                message: "Offline mode is not available on this listener".to_string(),
+            },
+
+            Request::RefreshCommands => Response::Error {
+                message: "Refreshing commands is not available on this listener".to_string(),
             },
 
             Request::Shutdown => {
@@ -0,0 +1,584 @@
+// Model Context Protocol server for AI agents and IDEs
+//
+// `orbitd --mcp` speaks MCP on stdin/stdout (one JSON-RPC message per
+// line), so any MCP client can use Orbit as a tool backend. Tools:
+//
+//   run_command    { command, cwd? } -> exit code, stdout, stderr, duration
+//   get_context    {}                -> directory, git state, project type
+//   search_history { query, limit? } -> past commands matching the query
+//
+// Commands an agent asks to run go through the same checks as AI
+// suggestions. Ones needing root are refused, since there is nobody at a
+// terminal to type a sudo password. Otherwise, unless the user auto-approves
+// commands (and, for destructive ones, has turned off confirm_destructive),
+// the client is asked to confirm with the user through elicitation; clients
+// that can't elicit only get commands that need no approval.
+//
+// Commands run for an agent are not recorded in the learning history, so
+// they never come back as suggestions.
+
+use anyhow::{anyhow, Result};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::path::PathBuf;
+use std::sync::Arc;
+use tokio::io::{AsyncBufRead, AsyncBufReadExt, AsyncReadExt, AsyncWrite, AsyncWriteExt, BufReader};
+use tracing::{debug, info, warn};
+
+use crate::config::Config;
+use crate::context::{ContextEngine, ShellDialect};
+use crate::executor::target::ExecutionTarget;
+use crate::executor::Executor;
+use crate::learning::LearningEngine;
+use crate::readiness::{Readiness, Subsystem};
+
+use super::rpc::{
+    error_response, parse_params, RpcError, INVALID_PARAMS, INVALID_REQUEST, METHOD_NOT_FOUND,
+    PARSE_ERROR,
+};
+use super::server::validate_ai_response;
+
+/// Largest message accepted from the client (1MB, same as the IPC socket)
+const MAX_MESSAGE_SIZE: usize = 1024 * 1024;
+
+/// MCP revisions this server speaks, newest first
+const PROTOCOL_VERSIONS: &[&str] = &["2025-06-18", "2025-03-26", "2024-11-05"];
+
+/// Matches returned by search_history when the agent doesn't say
+const DEFAULT_HISTORY_LIMIT: usize = 20;
+
+/// A message from the client
+#[derive(Debug)]
+enum Incoming {
+    Request {
+        id: Value,
+        method: String,
+        params: Value,
+    },
+    Notification {
+        method: String,
+    },
+    /// Answer to a request we sent (elicitation)
+    Response {
+        id: Value,
+        result: Result<Value, Value>,
+    },
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct InitializeParams {
+    protocol_version: String,
+    #[serde(default)]
+    capabilities: Value,
+}
+
+#[derive(Deserialize)]
+struct CallParams {
+    name: String,
+    #[serde(default)]
+    arguments: Value,
+}
+
+#[derive(Deserialize)]
+struct RunCommandArgs {
+    command: String,
+    #[serde(default)]
+    cwd: Option<PathBuf>,
+}
+
+#[derive(Deserialize)]
+struct SearchHistoryArgs {
+    query: String,
+    #[serde(default)]
+    limit: Option<usize>,
+}
+
+/// What it takes to run a command for an agent
+#[derive(Debug, PartialEq)]
+enum Approval {
+    Run,
+    /// Ask the user, telling them why
+    Ask(String),
+    Refuse(String),
+}
+
+/// Read one line-delimited message; `None` at end of input
+async fn read_message<R: AsyncBufRead + Unpin>(reader: &mut R) -> Result<Option<Vec<u8>>> {
+    loop {
+        let mut line = Vec::new();
+        let read = (&mut *reader)
+            .take(MAX_MESSAGE_SIZE as u64 + 1)
+            .read_until(b'\n', &mut line)
+            .await?;
+        if read == 0 {
+            return Ok(None);
+        }
+        if line.len() > MAX_MESSAGE_SIZE {
+            return Err(anyhow!("Message too large (max {} bytes)", MAX_MESSAGE_SIZE));
+        }
+        if line.iter().all(u8::is_ascii_whitespace) {
+            continue;
+        }
+        return Ok(Some(line));
+    }
+}
+
+/// Write one message; serde_json never emits raw newlines
+async fn write_message<W: AsyncWrite + Unpin>(writer: &mut W, message: &Value) -> Result<()> {
+    let mut line = serde_json::to_vec(message)?;
+    line.push(b'\n');
+    writer.write_all(&line).await?;
+    writer.flush().await?;
+    Ok(())
+}
+
+fn parse_incoming(body: &[u8]) -> Result<Incoming, (Option<Value>, RpcError)> {
+    let value: Value = serde_json::from_slice(body)
+        .map_err(|e| (None, RpcError::new(PARSE_ERROR, e.to_string())))?;
+    let id = value.get("id").cloned();
+
+    match (value.get("method").and_then(Value::as_str), id) {
+        (Some(method), Some(id)) => Ok(Incoming::Request {
+            id,
+            method: method.to_string(),
+            params: value.get("params").cloned().unwrap_or(Value::Null),
+        }),
+        (Some(method), None) => Ok(Incoming::Notification {
+            method: method.to_string(),
+        }),
+        (None, Some(id)) => {
+            let result = match (value.get("result"), value.get("error")) {
+                (Some(result), _) => Ok(result.clone()),
+                (None, Some(error)) => Err(error.clone()),
+                (None, None) => {
+                    return Err((Some(id), RpcError::new(INVALID_REQUEST, "Missing result")))
+                }
+            };
+            Ok(Incoming::Response { id, result })
+        }
+        (None, None) => Err((None, RpcError::new(INVALID_REQUEST, "Missing method"))),
+    }
+}
+
+/// The revision to speak: the client's if we know it, else our newest
+fn negotiate_version(requested: &str) -> &'static str {
+    PROTOCOL_VERSIONS
+        .iter()
+        .find(|version| **version == requested)
+        .copied()
+        .unwrap_or(PROTOCOL_VERSIONS[0])
+}
+
+fn tools() -> Value {
+    json!([
+        {
+            "name": "run_command",
+            "title": "Run a shell command",
+            "description": "Run a shell command on the user's machine and return its exit code, \
+                            stdout and stderr. The user may be asked to approve it first; \
+                            commands needing root are refused.",
+            "inputSchema": {
+                "type": "object",
+                "properties": {
+                    "command": { "type": "string", "description": "Command line for the user's shell" },
+                    "cwd": { "type": "string", "description": "Directory to run in; defaults to the current one" },
+                },
+                "required": ["command"],
+            },
+            "annotations": { "destructiveHint": true, "openWorldHint": true },
+        },
+        {
+            "name": "get_context",
+            "title": "Read terminal context",
+            "description": "The user's current directory, OS, shell, git branch and status, \
+                            project type and recent commands.",
+            "inputSchema": { "type": "object", "properties": {} },
+            "annotations": { "readOnlyHint": true, "openWorldHint": false },
+        },
+        {
+            "name": "search_history",
+            "title": "Search command history",
+            "description": "Commands the user ran before that match a description, e.g. \
+                            \"docker build last week\", best matches first.",
+            "inputSchema": {
+                "type": "object",
+                "properties": {
+                    "query": { "type": "string" },
+                    "limit": { "type": "integer", "minimum": 1, "default": DEFAULT_HISTORY_LIMIT },
+                },
+                "required": ["query"],
+            },
+            "annotations": { "readOnlyHint": true, "openWorldHint": false },
+        },
+    ])
+}
+
+/// A tool result: `value` both as text and, for clients that read it, as
+/// structured content
+fn tool_result(value: Value) -> Result<Value, RpcError> {
+    Ok(json!({
+        "content": [{ "type": "text", "text": serde_json::to_string_pretty(&value)? }],
+        "structuredContent": value,
+        "isError": false,
+    }))
+}
+
+/// A tool that ran but failed, reported to the model rather than as a
+/// protocol error
+fn tool_error(message: impl Into<String>) -> Result<Value, RpcError> {
+    Ok(json!({
+        "content": [{ "type": "text", "text": message.into() }],
+        "isError": true,
+    }))
+}
+
+/// Serves one MCP client over a pair of streams
+pub struct McpServer {
+    config: Arc<Config>,
+    learning_engine: Arc<LearningEngine>,
+    context_engine: Arc<ContextEngine>,
+    executor: Arc<Executor>,
+    /// Negotiated in `initialize`
+    protocol_version: Option<&'static str>,
+    can_elicit: bool,
+    next_request_id: u64,
+}
+
+impl McpServer {
+    pub async fn new(config: Config) -> Result<Self> {
+        let config = Arc::new(config);
+
+        // Answer straight away; history search matches exactly until the
+        // embedding model loads
+        let learning_engine = Arc::new(LearningEngine::new(config.clone()).await?);
+        let warm_engine = learning_engine.clone();
+        Arc::new(Readiness::new()).spawn(Subsystem::Embeddings, async move {
+            warm_engine.warm_embeddings().await
+        });
+
+        Ok(Self {
+            learning_engine,
+            context_engine: Arc::new(ContextEngine::new(config.clone()).await?),
+            executor: Arc::new(Executor::new(config.clone()).await?),
+            config,
+            protocol_version: None,
+            can_elicit: false,
+            next_request_id: 0,
+        })
+    }
+
+    /// Serve on stdin/stdout until the client closes stdin
+    pub async fn serve_stdio(&mut self) -> Result<()> {
+        info!("Serving MCP on stdio");
+        self.serve(BufReader::new(tokio::io::stdin()), tokio::io::stdout())
+            .await
+    }
+
+    pub async fn serve<R, W>(&mut self, mut reader: R, mut writer: W) -> Result<()>
+    where
+        R: AsyncBufRead + Unpin,
+        W: AsyncWrite + Unpin,
+    {
+        while let Some(body) = read_message(&mut reader).await? {
+            let (id, method, params) = match parse_incoming(&body) {
+                Ok(Incoming::Request { id, method, params }) => (id, method, params),
+                Ok(Incoming::Notification { method }) => {
+                    // notifications/initialized, notifications/cancelled, ...
+                    debug!("Ignoring notification {}", method);
+                    continue;
+                }
+                Ok(Incoming::Response { id, .. }) => {
+                    debug!("Ignoring response {} nothing is waiting for", id);
+                    continue;
+                }
+                Err((id, error)) => {
+                    write_message(&mut writer, &error_response(id, error)).await?;
+                    continue;
+                }
+            };
+
+            let response = match self
+                .handle(&method, params, &mut reader, &mut writer)
+                .await
+            {
+                Ok(result) => json!({ "jsonrpc": "2.0", "id": id, "result": result }),
+                Err(error) => error_response(Some(id), error),
+            };
+            write_message(&mut writer, &response).await?;
+        }
+
+        info!("MCP client disconnected");
+        Ok(())
+    }
+
+    async fn handle<R, W>(
+        &mut self,
+        method: &str,
+        params: Value,
+        reader: &mut R,
+        writer: &mut W,
+    ) -> Result<Value, RpcError>
+    where
+        R: AsyncBufRead + Unpin,
+        W: AsyncWrite + Unpin,
+    {
+        match method {
+            "initialize" => {
+                let params: InitializeParams = parse_params(params)?;
+                let version = negotiate_version(&params.protocol_version);
+                self.protocol_version = Some(version);
+                self.can_elicit = params.capabilities.get("elicitation").is_some();
+                Ok(json!({
+                    "protocolVersion": version,
+                    "capabilities": { "tools": { "listChanged": false } },
+                    "serverInfo": { "name": "orbitd", "version": env!("CARGO_PKG_VERSION") },
+                    "instructions": "Orbit runs shell commands on the user's machine with its \
+                                     safety checks, and knows their terminal context and history.",
+                }))
+            }
+            "ping" => Ok(json!({})),
+            _ if self.protocol_version.is_none() => {
+                Err(RpcError::new(INVALID_REQUEST, "Send initialize first"))
+            }
+            "tools/list" => Ok(json!({ "tools": tools() })),
+            "tools/call" => {
+                let call: CallParams = parse_params(params)?;
+                match call.name.as_str() {
+                    "run_command" => {
+                        self.run_command(parse_params(call.arguments)?, reader, writer)
+                            .await
+                    }
+                    "get_context" => {
+                        tool_result(serde_json::to_value(self.context_engine.get_context().await?)?)
+                    }
+                    "search_history" => self.search_history(parse_params(call.arguments)?).await,
+                    name => Err(RpcError::new(INVALID_PARAMS, format!("Unknown tool {}", name))),
+                }
+            }
+            _ => Err(RpcError::new(METHOD_NOT_FOUND, format!("Unknown method {}", method))),
+        }
+    }
+
+    /// Whether `command` may run, and if so whether the user must agree
+    fn approval(&self, command: &str) -> Result<Approval> {
+        // Agents write POSIX shell commands, and they run with sh
+        if !validate_ai_response(command, ShellDialect::Posix, &self.executor, &self.config)? {
+            self.executor.report_blocked(command, command, "mcp");
+            return Ok(Approval::Refuse(
+                "Command rejected for safety reasons".to_string(),
+            ));
+        }
+        if self.executor.elevation(command).is_some() {
+            return Ok(Approval::Refuse(
+                "Command needs root; ask the user to run it in their terminal".to_string(),
+            ));
+        }
+
+        let execution = &self.config.execution;
+        if execution.confirm_destructive && self.executor.is_destructive(command) {
+            return Ok(Approval::Ask("This command is destructive.".to_string()));
+        }
+        if !execution.auto_approve {
+            return Ok(Approval::Ask(String::new()));
+        }
+        Ok(Approval::Run)
+    }
+
+    async fn run_command<R, W>(
+        &mut self,
+        args: RunCommandArgs,
+        reader: &mut R,
+        writer: &mut W,
+    ) -> Result<Value, RpcError>
+    where
+        R: AsyncBufRead + Unpin,
+        W: AsyncWrite + Unpin,
+    {
+        let cwd = match args.cwd {
+            Some(cwd) => cwd,
+            None => self.context_engine.get_context().await?.pwd,
+        };
+
+        match self.approval(&args.command)? {
+            Approval::Run => {}
+            Approval::Refuse(reason) => return tool_error(reason),
+            Approval::Ask(_) if !self.can_elicit => {
+                return tool_error(
+                    "Command needs the user's approval and this client can't ask for it; \
+                     ask the user to run it in their terminal",
+                )
+            }
+            Approval::Ask(reason) => {
+                let mut message =
+                    format!("An agent wants to run `{}` in {}.", args.command, cwd.display());
+                if !reason.is_empty() {
+                    message.push(' ');
+                    message.push_str(&reason);
+                }
+                if let Some(dry_run) = self.executor.dry_run(&args.command, &cwd) {
+                    for line in dry_run.summary() {
+                        message.push('\n');
+                        message.push_str(&line);
+                    }
+                }
+                if !self.ask_user(&message, reader, writer).await? {
+                    info!("User declined agent command: {}", args.command);
+                    return tool_error("The user declined to run this command");
+                }
+            }
+        }
+
+        info!("Running agent command: {}", args.command);
+        let output = self
+            .executor
+            .execute(
+                &args.command,
+                &cwd,
+                &ExecutionTarget::Host,
+                ShellDialect::Posix,
+            )
+            .await?;
+        tool_result(serde_json::to_value(output)?)
+    }
+
+    async fn search_history(&self, args: SearchHistoryArgs) -> Result<Value, RpcError> {
+        let matches = self
+            .learning_engine
+            .search_history(&args.query, args.limit.unwrap_or(DEFAULT_HISTORY_LIMIT))
+            .await?;
+        tool_result(json!({ "matches": matches }))
+    }
+
+    /// Ask the user to approve through the client (elicitation), waiting for
+    /// the answer. Requests arriving meanwhile are turned away, as only one
+    /// command runs at a time.
+    async fn ask_user<R, W>(&mut self, message: &str, reader: &mut R, writer: &mut W) -> Result<bool>
+    where
+        R: AsyncBufRead + Unpin,
+        W: AsyncWrite + Unpin,
+    {
+        self.next_request_id += 1;
+        let request_id = json!(format!("orbit-{}", self.next_request_id));
+        write_message(
+            writer,
+            &json!({
+                "jsonrpc": "2.0",
+                "id": request_id,
+                "method": "elicitation/create",
+                "params": {
+                    "message": message,
+                    "requestedSchema": {
+                        "type": "object",
+                        "properties": {
+                            "approve": { "type": "boolean", "title": "Run this command", "default": false },
+                        },
+                        "required": ["approve"],
+                    },
+                },
+            }),
+        )
+        .await?;
+
+        while let Some(body) = read_message(reader).await? {
+            match parse_incoming(&body) {
+                Ok(Incoming::Response { id, result }) if id == request_id => {
+                    return Ok(match result {
+                        Ok(result) => {
+                            result["action"] == "accept" && result["content"]["approve"] == true
+                        }
+                        Err(error) => {
+                            warn!("Client could not ask for approval: {}", error);
+                            false
+                        }
+                    });
+                }
+                Ok(Incoming::Request { id, method, .. }) => {
+                    let response = match method.as_str() {
+                        "ping" => json!({ "jsonrpc": "2.0", "id": id, "result": {} }),
+                        _ => error_response(
+                            Some(id),
+                            RpcError::new(INVALID_REQUEST, "Waiting for the user to approve a command"),
+                        ),
+                    };
+                    write_message(writer, &response).await?;
+                }
+                Ok(_) => {}
+                Err((id, error)) => write_message(writer, &error_response(id, error)).await?,
+            }
+        }
+        Err(anyhow!("Client disconnected while waiting for approval"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_line_framing() {
+        let mut out = Vec::new();
+        write_message(&mut out, &json!({ "jsonrpc": "2.0", "id": 1, "result": "line\nbreak" }))
+            .await
+            .unwrap();
+        assert_eq!(out.iter().filter(|b| **b == b'\n').count(), 1);
+
+        let input = [b"\n".as_slice(), out.as_slice(), b"{}".as_slice()].concat();
+        let mut reader = BufReader::new(input.as_slice());
+        let first: Value = serde_json::from_slice(&read_message(&mut reader).await.unwrap().unwrap()).unwrap();
+        assert_eq!(first["result"], "line\nbreak");
+        // The last message may lack a trailing newline
+        assert_eq!(read_message(&mut reader).await.unwrap().unwrap(), b"{}");
+        assert!(read_message(&mut reader).await.unwrap().is_none());
+
+        let oversized = vec![b' '; MAX_MESSAGE_SIZE + 2];
+        assert!(read_message(&mut BufReader::new(oversized.as_slice())).await.is_err());
+    }
+
+    #[test]
+    fn test_parse_incoming() {
+        assert!(matches!(
+            parse_incoming(br#"{"jsonrpc":"2.0","id":1,"method":"tools/list"}"#),
+            Ok(Incoming::Request { method, params: Value::Null, .. }) if method == "tools/list"
+        ));
+        assert!(matches!(
+            parse_incoming(br#"{"jsonrpc":"2.0","method":"notifications/initialized"}"#),
+            Ok(Incoming::Notification { .. })
+        ));
+        assert!(matches!(
+            parse_incoming(br#"{"jsonrpc":"2.0","id":"orbit-1","result":{"action":"decline"}}"#),
+            Ok(Incoming::Response { result: Ok(_), .. })
+        ));
+        assert!(matches!(
+            parse_incoming(br#"{"jsonrpc":"2.0","id":"orbit-1","error":{"code":-1,"message":"no"}}"#),
+            Ok(Incoming::Response { result: Err(_), .. })
+        ));
+
+        let (id, error) = parse_incoming(b"{not json").unwrap_err();
+        assert!(id.is_none());
+        assert_eq!(error.code, PARSE_ERROR);
+        let (id, error) = parse_incoming(br#"{"jsonrpc":"2.0","id":7}"#).unwrap_err();
+        assert_eq!(id, Some(json!(7)));
+        assert_eq!(error.code, INVALID_REQUEST);
+    }
+
+    #[test]
+    fn test_negotiate_version_and_tools() {
+        assert_eq!(negotiate_version("2024-11-05"), "2024-11-05");
+        assert_eq!(negotiate_version("2099-01-01"), PROTOCOL_VERSIONS[0]);
+
+        let tools = tools();
+        let names: Vec<&str> = tools
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|tool| tool["name"].as_str().unwrap())
+            .collect();
+        assert_eq!(names, ["run_command", "get_context", "search_history"]);
+        assert!(tools
+            .as_array()
+            .unwrap()
+            .iter()
+            .all(|tool| tool["inputSchema"]["type"] == "object"));
+    }
+}
@@ -7,17 +7,25 @@ pub mod ipc_unix;
 #[cfg(windows)]
 pub mod ipc_windows;
 
+pub mod mcp;
+pub mod rpc;
 pub mod server;
 
-use crate::classifier::CommandClassifier;
+use crate::classifier::{CommandClassifier, PluginRegistry};
+use crate::completion::CompletionEngine;
 use crate::config::Config;
+use crate::connectivity::Connectivity;
 use crate::context::ContextEngine;
 use crate::executor::Executor;
+use crate::extensions::ExtensionHost;
 use crate::learning::LearningEngine;
 use crate::license::LicenseManager;
 use crate::monitor::ProactiveMonitor;
+use crate::notifications::{Notification, Notifier};
 use crate::providers::ProviderRouter;
+use crate::readiness::{Readiness, Subsystem};
 use anyhow::Result;
+use std::path::PathBuf;
 use std::sync::Arc;
 
 pub use server::Server;
@@ -25,11 +33,9 @@ pub use server::Server;
 pub struct Daemon {
     config: Arc<Config>,
     server: Server,
-    #[allow(dead_code)]
     classifier: Arc<CommandClassifier>,
     #[allow(dead_code)]
     provider_router: Arc<ProviderRouter>,
-    #[allow(dead_code)]
     learning_engine: Arc<LearningEngine>,
     #[allow(dead_code)]
     context_engine: Arc<ContextEngine>,
@@ -37,6 +43,14 @@ pub struct Daemon {
     executor: Arc<Executor>,
     monitor: Option<ProactiveMonitor>,
     license_manager: Option<LicenseManager>,
+    /// Shared with the provider router, which stops calling cloud providers
+    /// while offline
+    connectivity: Arc<Connectivity>,
+    /// Which subsystems have warmed up; shared with the server for status
+    readiness: Arc<Readiness>,
+    notifier: Arc<Notifier>,
+    /// Reloads classifier plugins while alive
+    _plugin_watcher: Option<notify::RecommendedWatcher>,
 }
 
 impl Daemon {
@@ -46,18 +60,58 @@ impl Daemon {
         // Initialize components
         let learning_engine = Arc::new(LearningEngine::new(config.clone()).await?);
 
-        let classifier =
-            Arc::new(CommandClassifier::new(config.clone(), learning_engine.clone()).await?);
+        // Custom classification rules, reloaded when the plugins directory changes
+        let plugins = Arc::new(PluginRegistry::from_dir(config.plugins_dir()?)?);
+        let plugin_watcher = plugins.watch().unwrap_or_else(|e| {
+            tracing::warn!("Classifier plugin hot-reload unavailable: {}", e);
+            None
+        });
+
+        let classifier = Arc::new(
+            CommandClassifier::new(config.clone(), learning_engine.clone())
+                .await?
+                .with_plugins(plugins),
+        );
 
         let provider_router = Arc::new(ProviderRouter::new(config.clone()).await?);
+        let connectivity = provider_router.connectivity().clone();
 
         let context_engine = Arc::new(ContextEngine::new(config.clone()).await?);
 
-        let executor = Arc::new(Executor::new(config.clone()).await?);
+        // Long-running command results and monitor alerts, routed to the
+        // desktop and chat channels
+        let notifier = Arc::new(Notifier::new(&config)?);
+
+        let executor = Arc::new(
+            Executor::new(config.clone())
+                .await?
+                .with_notifier(notifier.clone()),
+        );
+
+        // Sandboxed WebAssembly extensions; ones awaiting permission
+        // approval are listed over IPC but not run
+        let extensions = if config.extensions.enabled {
+            match ExtensionHost::new(
+                config.extensions_dir()?,
+                Config::extension_grants_path()?,
+                &config.extensions,
+            ) {
+                Ok(host) => Some(Arc::new(host)),
+                Err(e) => {
+                    tracing::warn!("Extensions unavailable: {:#}", e);
+                    None
+                }
+            }
+        } else {
+            None
+        };
 
         // Initialize monitor if enabled
         let monitor = if config.monitoring.enabled {
-            Some(ProactiveMonitor::new(config.clone(), learning_engine.clone()).await?)
+            Some(
+                ProactiveMonitor::new(config.clone(), learning_engine.clone(), notifier.clone())
+                    .await?,
+            )
         } else {
             None
         };
@@ -69,6 +123,9 @@ impl Daemon {
             None
         };
 
+        // Slow subsystems warm up once the server is answering
+        let readiness = Arc::new(Readiness::new());
+
         // Create Unix socket server
         let server = Server::new(
             config.clone(),
@@ -77,7 +134,10 @@ impl Daemon {
             learning_engine.clone(),
             context_engine.clone(),
             executor.clone(),
-        )?;
+            extensions,
+            Arc::new(CompletionEngine::new(classifier.clone(), learning_engine.clone())),
+        )?
+        .with_readiness(readiness.clone());
 
         Ok(Self {
             config,
@@ -89,27 +149,74 @@ impl Daemon {
             executor,
             monitor,
             license_manager,
+            connectivity,
+            readiness,
+            notifier,
+            _plugin_watcher: plugin_watcher,
         })
     }
 
+    /// Serve the socket systemd bound for the daemon (socket activation)
+    pub fn with_listener(mut self, listener: std::os::unix::net::UnixListener) -> Self {
+        self.server = self.server.with_listener(listener);
+        self
+    }
+
     pub async fn start(&mut self) -> Result<()> {
+        // A run marker left behind means the last run never stopped cleanly
+        let marker = run_marker_path()?;
+        if let Ok(previous) = std::fs::read_to_string(&marker) {
+            self.notifier.notify(Notification::critical(
+                "Recovered from a crash",
+                &format!(
+                    "orbitd restarted after an unclean shutdown (last run: {})",
+                    previous.trim()
+                ),
+            ));
+        }
+        std::fs::write(
+            &marker,
+            format!(
+                "pid {} started {}",
+                std::process::id(),
+                chrono::Utc::now().to_rfc3339()
+            ),
+        )?;
+
+        // Watch for the network going away and coming back
+        let probe_interval =
+            tokio::time::Duration::from_secs(self.config.connectivity.probe_interval_seconds);
+        tokio::spawn(self.connectivity.clone().run(probe_interval));
+
         // Start license validation task if applicable
         if let Some(license_manager) = &self.license_manager {
             let lm = license_manager.clone();
             let interval_hours = self.config.license.validation_interval_hours;
+            let notifier = self.notifier.clone();
+            let warning_days = self.config.notifications.license_warning_days;
+            let connectivity = self.connectivity.clone();
+            let grace_days = self.config.connectivity.license_grace_days;
 
             tokio::spawn(async move {
                 loop {
-                    tokio::time::sleep(tokio::time::Duration::from_secs(interval_hours * 3600))
-                        .await;
-
-                    if let Err(e) = lm.validate().await {
+                    // Checked at startup as well; here it records whether
+                    // the check is deferred for status reports
+                    if let Err(e) = lm.validate_or_defer(&connectivity, grace_days).await {
                         tracing::error!("License re-validation failed: {}", e);
                         eprintln!("❌ License validation failed. Orbit will stop.");
                         std::process::exit(1);
                     }
 
-                    tracing::info!("✓ License re-validated");
+                    warn_license_expiry(&lm, &notifier, warning_days);
+
+                    // A deferred check is retried hourly, so it happens soon
+                    // after the network returns
+                    let hours = if connectivity.status().license_deferred_until.is_some() {
+                        1
+                    } else {
+                        interval_hours
+                    };
+                    tokio::time::sleep(tokio::time::Duration::from_secs(hours * 3600)).await;
                 }
             });
         }
@@ -117,9 +224,75 @@ impl Daemon {
         // Start proactive monitor if enabled
         if let Some(monitor) = &self.monitor {
             let mon = monitor.clone();
+            let readiness = self.readiness.clone();
+            readiness.ready(Subsystem::Monitor);
             tokio::spawn(async move {
                 if let Err(e) = mon.run().await {
                     tracing::error!("Monitor error: {}", e);
+                    readiness.failed(Subsystem::Monitor, &e);
+                }
+            });
+        } else {
+            self.readiness.disable(Subsystem::Monitor);
+        }
+
+        // The PATH scan and embedding model load while requests are served
+        // with their fallbacks
+        if self.config.classification.cache_known_commands {
+            let classifier = self.classifier.clone();
+            self.readiness.spawn(
+                Subsystem::CommandCache,
+                async move { classifier.warm().await },
+            );
+
+            // Rebuild the cache when binaries are installed or removed
+            let refresh_interval = self.config.classification.refresh_interval_seconds;
+            if refresh_interval > 0 {
+                let classifier = self.classifier.clone();
+                tokio::spawn(async move {
+                    let period = tokio::time::Duration::from_secs(refresh_interval);
+                    let mut interval = tokio::time::interval(period);
+                    loop {
+                        interval.tick().await;
+                        if !classifier.is_stale() {
+                            continue;
+                        }
+                        match classifier.refresh().await {
+                            Ok(refresh) => tracing::info!(
+                                "PATH changed: {} commands added, {} removed",
+                                refresh.added.len(),
+                                refresh.removed.len()
+                            ),
+                            Err(e) => tracing::warn!("Failed to refresh known commands: {}", e),
+                        }
+                    }
+                });
+            }
+        } else {
+            self.readiness.disable(Subsystem::CommandCache);
+        }
+        let engine = self.learning_engine.clone();
+        self.readiness.spawn(Subsystem::Embeddings, async move {
+            engine.warm_embeddings().await
+        });
+
+        // Purge command history and corrections past their retention period
+        let retention_days = self.config.learning.retention_days;
+        if retention_days > 0 {
+            let engine = self.learning_engine.clone();
+            tokio::spawn(async move {
+                let mut interval = tokio::time::interval(tokio::time::Duration::from_secs(24 * 3600));
+                loop {
+                    interval.tick().await;
+                    match engine.purge_older_than(retention_days).await {
+                        Ok(report) if report.total_rows() > 0 => tracing::info!(
+                            "Purged {} learning records older than {} days",
+                            report.total_rows(),
+                            retention_days
+                        ),
+                        Ok(_) => {}
+                        Err(e) => tracing::error!("Learning data purge failed: {}", e),
+                    }
                 }
             });
         }
@@ -132,6 +305,32 @@ impl Daemon {
 
     pub async fn stop(&mut self) -> Result<()> {
         self.server.stop().await?;
+        if let Err(e) = std::fs::remove_file(run_marker_path()?) {
+            tracing::warn!("Failed to remove the run marker: {}", e);
+        }
         Ok(())
     }
 }
+
+/// Exists while the daemon runs; holds the running daemon's PID and start time
+fn run_marker_path() -> Result<PathBuf> {
+    Ok(Config::data_dir()?.join("orbitd.running"))
+}
+
+/// Raise a critical event when the license expires within `warning_days`
+fn warn_license_expiry(license: &LicenseManager, notifier: &Notifier, warning_days: u32) {
+    let Some(expires_at) = license.expires_at() else {
+        return;
+    };
+    let days_left = (expires_at - chrono::Utc::now()).num_days();
+    if (0..=i64::from(warning_days)).contains(&days_left) {
+        notifier.notify(Notification::critical(
+            "License expires soon",
+            &format!(
+                "The Orbit license expires in {} day(s), on {}",
+                days_left,
+                expires_at.format("%Y-%m-%d")
+            ),
+        ));
+    }
+}
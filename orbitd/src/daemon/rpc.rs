@@ -0,0 +1,468 @@
+// JSON-RPC over stdio for editor integrations
+//
+// `orbitd --stdio` speaks JSON-RPC 2.0 on stdin/stdout using LSP framing
+// (`Content-Length` headers), so editors can embed Orbit with the client
+// code they already use for language servers. Methods mirror the IPC
+// protocol:
+//
+//   initialize                  -> server info and capabilities
+//   orbit/classify { input }    -> { type, command?, confidence? }
+//   orbit/suggest  { input }    -> the IPC `Response` (Passthrough, Replaced, Error)
+//   orbit/explain  { command }  -> { lines, destructive, dry_run }
+//   shutdown, exit
+//
+// Streaming follows LSP: with a `workDoneToken` the server sends
+// `$/progress` begin/report/end notifications, and with a
+// `partialResultToken` explain sends each line as a `$/progress`
+// notification and returns an empty `lines` array.
+//
+// Context (directory, git state) comes from the directory the editor
+// starts orbitd in, usually the workspace root.
+
+use anyhow::{anyhow, Context as _, Result};
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::sync::Arc;
+use tokio::io::{AsyncBufRead, AsyncBufReadExt, AsyncReadExt, AsyncWrite, AsyncWriteExt, BufReader};
+use tracing::{debug, info};
+
+use crate::classifier::{CommandClassifier, PluginRegistry};
+use crate::config::Config;
+use crate::context::ContextEngine;
+use crate::executor::Executor;
+use crate::learning::LearningEngine;
+use crate::providers::ProviderRouter;
+use crate::readiness::{Readiness, Subsystem};
+
+use super::ipc::{Classification, PROTOCOL_VERSION};
+use super::server::handle_command_query;
+
+/// Largest message accepted from the editor (1MB, same as the IPC socket)
+const MAX_MESSAGE_SIZE: usize = 1024 * 1024;
+
+// JSON-RPC error codes
+pub(super) const PARSE_ERROR: i64 = -32700;
+pub(super) const INVALID_REQUEST: i64 = -32600;
+pub(super) const METHOD_NOT_FOUND: i64 = -32601;
+pub(super) const INVALID_PARAMS: i64 = -32602;
+pub(super) const INTERNAL_ERROR: i64 = -32603;
+/// LSP: request sent before `initialize`
+const SERVER_NOT_INITIALIZED: i64 = -32002;
+
+#[derive(Debug)]
+pub(super) struct RpcError {
+    pub(super) code: i64,
+    pub(super) message: String,
+}
+
+impl RpcError {
+    pub(super) fn new(code: i64, message: impl Into<String>) -> Self {
+        Self {
+            code,
+            message: message.into(),
+        }
+    }
+}
+
+impl From<anyhow::Error> for RpcError {
+    fn from(e: anyhow::Error) -> Self {
+        Self::new(INTERNAL_ERROR, format!("{:#}", e))
+    }
+}
+
+impl From<serde_json::Error> for RpcError {
+    fn from(e: serde_json::Error) -> Self {
+        Self::new(INTERNAL_ERROR, e.to_string())
+    }
+}
+
+/// A request (with `id`) or notification (without)
+#[derive(Debug)]
+struct Incoming {
+    id: Option<Value>,
+    method: String,
+    params: Value,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ClassifyParams {
+    input: String,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct SuggestParams {
+    input: String,
+    #[serde(default)]
+    work_done_token: Option<Value>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ExplainParams {
+    command: String,
+    #[serde(default)]
+    work_done_token: Option<Value>,
+    #[serde(default)]
+    partial_result_token: Option<Value>,
+}
+
+/// Read one framed message; `None` at end of input
+async fn read_message<R: AsyncBufRead + Unpin>(reader: &mut R) -> Result<Option<Vec<u8>>> {
+    let mut content_length = None;
+
+    loop {
+        let mut line = String::new();
+        if reader.read_line(&mut line).await? == 0 {
+            return match content_length {
+                None => Ok(None),
+                Some(_) => Err(anyhow!("Input ended inside message headers")),
+            };
+        }
+
+        let line = line.trim_end();
+        if line.is_empty() {
+            if content_length.is_some() {
+                break;
+            }
+            // Tolerate blank lines between messages
+            continue;
+        }
+
+        if let Some((name, value)) = line.split_once(':') {
+            if name.trim().eq_ignore_ascii_case("content-length") {
+                content_length = Some(
+                    value
+                        .trim()
+                        .parse::<usize>()
+                        .with_context(|| format!("Invalid Content-Length '{}'", value.trim()))?,
+                );
+            }
+            // Other headers (Content-Type) are ignored, as in LSP
+        }
+    }
+
+    let length = content_length.unwrap_or_default();
+    if length > MAX_MESSAGE_SIZE {
+        return Err(anyhow!("Message too large ({} bytes, max {})", length, MAX_MESSAGE_SIZE));
+    }
+
+    let mut body = vec![0u8; length];
+    reader.read_exact(&mut body).await?;
+    Ok(Some(body))
+}
+
+/// Write one framed message
+async fn write_message<W: AsyncWrite + Unpin>(writer: &mut W, message: &Value) -> Result<()> {
+    let body = serde_json::to_vec(message)?;
+    writer
+        .write_all(format!("Content-Length: {}\r\n\r\n", body.len()).as_bytes())
+        .await?;
+    writer.write_all(&body).await?;
+    writer.flush().await?;
+    Ok(())
+}
+
+fn parse_incoming(body: &[u8]) -> Result<Incoming, (Option<Value>, RpcError)> {
+    let value: Value = serde_json::from_slice(body)
+        .map_err(|e| (None, RpcError::new(PARSE_ERROR, e.to_string())))?;
+
+    let id = value.get("id").cloned();
+    let method = value
+        .get("method")
+        .and_then(Value::as_str)
+        .ok_or_else(|| (id.clone(), RpcError::new(INVALID_REQUEST, "Missing method")))?
+        .to_string();
+
+    Ok(Incoming {
+        id,
+        method,
+        params: value.get("params").cloned().unwrap_or(Value::Null),
+    })
+}
+
+pub(super) fn parse_params<T: DeserializeOwned>(params: Value) -> Result<T, RpcError> {
+    serde_json::from_value(params).map_err(|e| RpcError::new(INVALID_PARAMS, e.to_string()))
+}
+
+pub(super) fn error_response(id: Option<Value>, error: RpcError) -> Value {
+    json!({
+        "jsonrpc": "2.0",
+        "id": id.unwrap_or(Value::Null),
+        "error": { "code": error.code, "message": error.message },
+    })
+}
+
+/// Send a `$/progress` notification if the client asked for one
+async fn progress<W: AsyncWrite + Unpin>(writer: &mut W, token: &Option<Value>, value: Value) -> Result<()> {
+    match token {
+        Some(token) => {
+            write_message(
+                writer,
+                &json!({
+                    "jsonrpc": "2.0",
+                    "method": "$/progress",
+                    "params": { "token": token, "value": value },
+                }),
+            )
+            .await
+        }
+        None => Ok(()),
+    }
+}
+
+/// Serves one editor over a pair of streams
+pub struct RpcServer {
+    config: Arc<Config>,
+    classifier: Arc<CommandClassifier>,
+    provider_router: Arc<ProviderRouter>,
+    learning_engine: Arc<LearningEngine>,
+    context_engine: Arc<ContextEngine>,
+    executor: Arc<Executor>,
+    initialized: bool,
+    shutting_down: bool,
+}
+
+impl RpcServer {
+    pub async fn new(config: Config) -> Result<Self> {
+        let config = Arc::new(config);
+
+        let learning_engine = Arc::new(LearningEngine::new(config.clone()).await?);
+        let plugins = Arc::new(PluginRegistry::from_dir(config.plugins_dir()?)?);
+        let classifier = Arc::new(
+            CommandClassifier::new(config.clone(), learning_engine.clone())
+                .await?
+                .with_plugins(plugins),
+        );
+
+        // Answer straight away; the PATH scan and embeddings catch up
+        let readiness = Arc::new(Readiness::new());
+        if config.classification.cache_known_commands {
+            let warm_classifier = classifier.clone();
+            readiness.spawn(Subsystem::CommandCache, async move {
+                warm_classifier.warm().await
+            });
+        }
+        let warm_engine = learning_engine.clone();
+        readiness.spawn(Subsystem::Embeddings, async move {
+            warm_engine.warm_embeddings().await
+        });
+
+        Ok(Self {
+            provider_router: Arc::new(ProviderRouter::new(config.clone()).await?),
+            context_engine: Arc::new(ContextEngine::new(config.clone()).await?),
+            executor: Arc::new(Executor::new(config.clone()).await?),
+            config,
+            classifier,
+            learning_engine,
+            initialized: false,
+            shutting_down: false,
+        })
+    }
+
+    /// Serve on stdin/stdout until the editor sends `exit` or closes stdin
+    pub async fn serve_stdio(&mut self) -> Result<()> {
+        info!("Serving JSON-RPC on stdio");
+        self.serve(BufReader::new(tokio::io::stdin()), tokio::io::stdout())
+            .await
+    }
+
+    pub async fn serve<R, W>(&mut self, mut reader: R, mut writer: W) -> Result<()>
+    where
+        R: AsyncBufRead + Unpin,
+        W: AsyncWrite + Unpin,
+    {
+        while let Some(body) = read_message(&mut reader).await? {
+            let incoming = match parse_incoming(&body) {
+                Ok(incoming) => incoming,
+                Err((id, error)) => {
+                    write_message(&mut writer, &error_response(id, error)).await?;
+                    continue;
+                }
+            };
+
+            if incoming.method == "exit" {
+                break;
+            }
+
+            let Some(id) = incoming.id else {
+                // Notifications ($/cancelRequest, initialized, ...) need no reply
+                debug!("Ignoring notification {}", incoming.method);
+                continue;
+            };
+
+            let response = match self
+                .handle(&incoming.method, incoming.params, &mut writer)
+                .await
+            {
+                Ok(result) => json!({ "jsonrpc": "2.0", "id": id, "result": result }),
+                Err(error) => error_response(Some(id), error),
+            };
+            write_message(&mut writer, &response).await?;
+        }
+
+        info!("JSON-RPC client disconnected");
+        Ok(())
+    }
+
+    async fn handle<W: AsyncWrite + Unpin>(
+        &mut self,
+        method: &str,
+        params: Value,
+        writer: &mut W,
+    ) -> Result<Value, RpcError> {
+        if self.shutting_down {
+            return Err(RpcError::new(INVALID_REQUEST, "Server is shutting down"));
+        }
+        if !self.initialized && method != "initialize" {
+            return Err(RpcError::new(SERVER_NOT_INITIALIZED, "Send initialize first"));
+        }
+
+        match method {
+            "initialize" => {
+                self.initialized = true;
+                Ok(json!({
+                    "serverInfo": { "name": "orbitd", "version": env!("CARGO_PKG_VERSION") },
+                    "protocolVersion": PROTOCOL_VERSION,
+                    "capabilities": {
+                        "classify": true,
+                        "suggest": { "workDoneProgress": true },
+                        "explain": { "workDoneProgress": true, "partialResults": true },
+                    },
+                }))
+            }
+            "shutdown" => {
+                self.shutting_down = true;
+                Ok(Value::Null)
+            }
+            "orbit/classify" => self.classify(parse_params(params)?).await,
+            "orbit/suggest" => self.suggest(parse_params(params)?, writer).await,
+            "orbit/explain" => self.explain(parse_params(params)?, writer).await,
+            _ => Err(RpcError::new(METHOD_NOT_FOUND, format!("Unknown method {}", method))),
+        }
+    }
+
+    async fn classify(&self, params: ClassifyParams) -> Result<Value, RpcError> {
+        let context = self.context_engine.get_context().await?;
+
+        let command_type = self.classifier.classify(&params.input, &context).await?;
+        Ok(serde_json::to_value(Classification::from(command_type))?)
+    }
+
+    async fn suggest<W: AsyncWrite + Unpin>(&self, params: SuggestParams, writer: &mut W) -> Result<Value, RpcError> {
+        let token = params.work_done_token;
+        progress(writer, &token, json!({ "kind": "begin", "title": "Orbit: suggesting a command" })).await?;
+
+        let response = handle_command_query(
+            &params.input,
+            &self.config,
+            &self.classifier,
+            &self.provider_router,
+            &self.learning_engine,
+            &self.context_engine,
+            &self.executor,
+            &None,
+        )
+        .await;
+
+        progress(writer, &token, json!({ "kind": "end" })).await?;
+        Ok(serde_json::to_value(response?)?)
+    }
+
+    async fn explain<W: AsyncWrite + Unpin>(&self, params: ExplainParams, writer: &mut W) -> Result<Value, RpcError> {
+        let token = params.work_done_token;
+        progress(writer, &token, json!({ "kind": "begin", "title": "Orbit: explaining command" })).await?;
+
+        let context = self.context_engine.get_context().await?;
+        let lines = self
+            .provider_router
+            .explain_command(&params.command, &context)
+            .await?;
+        let destructive = self.executor.is_destructive(&params.command);
+        let dry_run = self.executor.dry_run(&params.command, &context.pwd);
+        let lines: Vec<String> = lines
+            .into_iter()
+            .chain(dry_run.iter().flat_map(|d| d.summary()))
+            .collect();
+
+        let lines = match &params.partial_result_token {
+            Some(_) => {
+                for (i, line) in lines.iter().enumerate() {
+                    progress(writer, &params.partial_result_token, json!([line])).await?;
+                    progress(
+                        writer,
+                        &token,
+                        json!({ "kind": "report", "message": format!("{}/{}", i + 1, lines.len()) }),
+                    )
+                    .await?;
+                }
+                // Partial results carried the lines; the response holds none
+                Vec::new()
+            }
+            None => lines,
+        };
+
+        progress(writer, &token, json!({ "kind": "end" })).await?;
+        Ok(json!({ "lines": lines, "destructive": destructive, "dry_run": dry_run }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn frame(body: &str) -> String {
+        format!("Content-Length: {}\r\n\r\n{}", body.len(), body)
+    }
+
+    #[tokio::test]
+    async fn test_framing_roundtrip() {
+        let mut out = Vec::new();
+        write_message(&mut out, &json!({ "jsonrpc": "2.0", "id": 1, "result": "ünïcode" }))
+            .await
+            .unwrap();
+        write_message(&mut out, &json!({ "jsonrpc": "2.0", "method": "exit" }))
+            .await
+            .unwrap();
+
+        let mut reader = BufReader::new(out.as_slice());
+        let first: Value = serde_json::from_slice(&read_message(&mut reader).await.unwrap().unwrap()).unwrap();
+        assert_eq!(first["result"], "ünïcode");
+        let second: Value = serde_json::from_slice(&read_message(&mut reader).await.unwrap().unwrap()).unwrap();
+        assert_eq!(second["method"], "exit");
+        assert!(read_message(&mut reader).await.unwrap().is_none());
+
+        // Extra headers are ignored; oversized and truncated messages are errors
+        let with_type = format!(
+            "Content-Type: application/vscode-jsonrpc; charset=utf-8\r\n{}",
+            frame("{}")
+        );
+        assert_eq!(
+            read_message(&mut BufReader::new(with_type.as_bytes())).await.unwrap().unwrap(),
+            b"{}"
+        );
+        let oversized = format!("Content-Length: {}\r\n\r\n", MAX_MESSAGE_SIZE + 1);
+        assert!(read_message(&mut BufReader::new(oversized.as_bytes())).await.is_err());
+        assert!(read_message(&mut BufReader::new(&b"Content-Length: 10\r\n\r\n{}"[..])).await.is_err());
+    }
+
+    #[test]
+    fn test_parse_incoming_errors() {
+        let (id, error) = parse_incoming(b"{not json").unwrap_err();
+        assert!(id.is_none());
+        assert_eq!(error.code, PARSE_ERROR);
+
+        let (id, error) = parse_incoming(br#"{"jsonrpc":"2.0","id":7}"#).unwrap_err();
+        assert_eq!(id, Some(json!(7)));
+        assert_eq!(error.code, INVALID_REQUEST);
+
+        let incoming = parse_incoming(br#"{"jsonrpc":"2.0","method":"initialized"}"#).unwrap();
+        assert!(incoming.id.is_none());
+        assert_eq!(incoming.params, Value::Null);
+
+        let error = parse_params::<ExplainParams>(json!({ "cmd": "ls" })).err().unwrap();
+        assert_eq!(error.code, INVALID_PARAMS);
+    }
+}
@@ -6,14 +6,23 @@ use tokio::net::{UnixListener, UnixStream};
 use tokio::sync::Semaphore;
 use tracing::{debug, error, info, warn};
 
-use crate::classifier::{CommandClassifier, CommandType};
+use crate::classifier::{is_package_install, CommandClassifier, CommandType};
+use crate::completion::CompletionEngine;
 use crate::config::Config;
-use crate::context::ContextEngine;
+use crate::context::{ContextEngine, ShellDialect};
+use crate::executor::elevation::ElevatedRun;
+use crate::executor::risk::Explanation;
+use crate::executor::target::{ContainerRuntime, ExecutionTarget};
 use crate::executor::Executor;
-use crate::learning::LearningEngine;
-use crate::providers::ProviderRouter;
+use crate::extensions::{ExtensionEvent, ExtensionHost};
+use crate::learning::{FeedbackReason, LearningEngine};
+use crate::providers::{ProviderRouter, RateLimited, LOCAL_PROVIDER};
+use crate::readiness::Readiness;
 
-use super::ipc::{FeedbackResult, Request, Response};
+use super::ipc::{
+    set_log_level, DatabaseAction, ExtensionAction, FeedbackResult, PlaybookAction, Request,
+    Response,
+};
 
 /// Maximum concurrent IPC connections allowed
 /// This prevents local DoS attacks from flooding the daemon with requests
@@ -30,11 +39,19 @@ pub struct Server {
     learning_engine: Arc<LearningEngine>,
     context_engine: Arc<ContextEngine>,
     executor: Arc<Executor>,
+    extensions: Option<Arc<ExtensionHost>>,
+    completions: Arc<CompletionEngine>,
+    readiness: Arc<Readiness>,
     shutdown_tx: Option<tokio::sync::oneshot::Sender<()>>,
     connection_semaphore: Arc<Semaphore>,
+    /// Socket bound by systemd (socket activation), served instead of
+    /// binding one
+    activated_listener: Option<std::os::unix::net::UnixListener>,
+    socket_activated: bool,
 }
 
 impl Server {
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         config: Arc<Config>,
         classifier: Arc<CommandClassifier>,
@@ -42,6 +59,8 @@ impl Server {
         learning_engine: Arc<LearningEngine>,
         context_engine: Arc<ContextEngine>,
         executor: Arc<Executor>,
+        extensions: Option<Arc<ExtensionHost>>,
+        completions: Arc<CompletionEngine>,
     ) -> Result<Self> {
         Ok(Self {
             config,
@@ -50,36 +69,37 @@ impl Server {
             learning_engine,
             context_engine,
             executor,
+            extensions,
+            completions,
+            readiness: Arc::new(Readiness::new()),
             shutdown_tx: None,
             connection_semaphore: Arc::new(Semaphore::new(MAX_CONCURRENT_CONNECTIONS)),
+            activated_listener: None,
+            socket_activated: false,
         })
     }
 
-    pub async fn start(&mut self) -> Result<()> {
-        let socket_path = &self.config.daemon.socket_path;
-
-        // Remove socket if it already exists
-        if socket_path.exists() {
-            std::fs::remove_file(socket_path).context("Failed to remove existing socket")?;
-        }
-
-        // Create socket directory if it doesn't exist
-        if let Some(parent) = socket_path.parent() {
-            std::fs::create_dir_all(parent).context("Failed to create socket directory")?;
-        }
-
-        let listener = UnixListener::bind(socket_path).context("Failed to bind Unix socket")?;
+    /// Report the readiness of these subsystems in status responses
+    pub fn with_readiness(mut self, readiness: Arc<Readiness>) -> Self {
+        self.readiness = readiness;
+        self
+    }
 
-        info!("Unix socket server listening on: {:?}", socket_path);
+    /// Serve a socket bound by systemd rather than binding the configured one
+    pub fn with_listener(mut self, listener: std::os::unix::net::UnixListener) -> Self {
+        self.activated_listener = Some(listener);
+        self.socket_activated = true;
+        self
+    }
 
-        // Set permissions (Unix only)
-        #[cfg(unix)]
-        {
-            use std::os::unix::fs::PermissionsExt;
-            let mut perms = std::fs::metadata(socket_path)?.permissions();
-            perms.set_mode(0o600); // Owner read/write only
-            std::fs::set_permissions(socket_path, perms)?;
-        }
+    pub async fn start(&mut self) -> Result<()> {
+        let listener = match self.activated_listener.take() {
+            // systemd created it with the unit's SocketMode
+            Some(listener) => {
+                UnixListener::from_std(listener).context("Failed to use socket from systemd")?
+            }
+            None => self.bind()?,
+        };
 
         let (shutdown_tx, mut shutdown_rx) = tokio::sync::oneshot::channel();
         self.shutdown_tx = Some(shutdown_tx);
@@ -90,6 +110,9 @@ impl Server {
         let learning_engine = self.learning_engine.clone();
         let context_engine = self.context_engine.clone();
         let executor = self.executor.clone();
+        let extensions = self.extensions.clone();
+        let completions = self.completions.clone();
+        let readiness = self.readiness.clone();
         let semaphore = self.connection_semaphore.clone();
 
         tokio::spawn(async move {
@@ -102,6 +125,9 @@ impl Server {
                         let learning_engine = learning_engine.clone();
                         let context_engine = context_engine.clone();
                         let executor = executor.clone();
+                        let extensions = extensions.clone();
+                        let completions = completions.clone();
+                        let readiness = readiness.clone();
 
                         // Acquire semaphore permit - blocks if at max connections
                         let permit = match semaphore.clone().try_acquire_owned() {
@@ -124,6 +150,9 @@ impl Server {
                                 learning_engine,
                                 context_engine,
                                 executor,
+                                extensions,
+                                completions,
+                                readiness,
                             ).await {
                                 error!("Error handling client: {}", e);
                             }
@@ -140,14 +169,43 @@ impl Server {
         Ok(())
     }
 
+    fn bind(&self) -> Result<UnixListener> {
+        let socket_path = &self.config.daemon.socket_path;
+
+        // Remove socket if it already exists
+        if socket_path.exists() {
+            std::fs::remove_file(socket_path).context("Failed to remove existing socket")?;
+        }
+
+        // Create socket directory if it doesn't exist
+        if let Some(parent) = socket_path.parent() {
+            std::fs::create_dir_all(parent).context("Failed to create socket directory")?;
+        }
+
+        let listener = UnixListener::bind(socket_path).context("Failed to bind Unix socket")?;
+
+        info!("Unix socket server listening on: {:?}", socket_path);
+
+        // Set permissions (Unix only)
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mut perms = std::fs::metadata(socket_path)?.permissions();
+            perms.set_mode(0o600); // Owner read/write only
+            std::fs::set_permissions(socket_path, perms)?;
+        }
+
+        Ok(listener)
+    }
+
     pub async fn stop(&mut self) -> Result<()> {
         if let Some(tx) = self.shutdown_tx.take() {
             let _ = tx.send(());
         }
 
-        // Clean up socket
+        // Clean up socket, unless systemd owns it
         let socket_path = &self.config.daemon.socket_path;
-        if socket_path.exists() {
+        if !self.socket_activated && socket_path.exists() {
             std::fs::remove_file(socket_path)?;
         }
 
@@ -155,6 +213,7 @@ impl Server {
     }
 }
 
+#[allow(clippy::too_many_arguments)]
 async fn handle_client(
     stream: UnixStream,
     config: Arc<Config>,
@@ -163,6 +222,9 @@ async fn handle_client(
     learning_engine: Arc<LearningEngine>,
     context_engine: Arc<ContextEngine>,
     executor: Arc<Executor>,
+    extensions: Option<Arc<ExtensionHost>>,
+    completions: Arc<CompletionEngine>,
+    readiness: Arc<Readiness>,
 ) -> Result<()> {
     let (reader, mut writer) = stream.into_split();
     let mut reader = BufReader::new(reader);
@@ -220,6 +282,9 @@ async fn handle_client(
             &learning_engine,
             &context_engine,
             &executor,
+            &extensions,
+            &completions,
+            &readiness,
         )
         .await;
 
@@ -251,6 +316,7 @@ async fn handle_client(
             &learning_engine,
             &context_engine,
             &executor,
+            &extensions,
         )
         .await
     };
@@ -262,6 +328,7 @@ async fn handle_client(
     Ok(())
 }
 
+#[allow(clippy::too_many_arguments)]
 async fn handle_request(
     request: Request,
     config: &Arc<Config>,
@@ -270,34 +337,221 @@ async fn handle_request(
     learning_engine: &Arc<LearningEngine>,
     context_engine: &Arc<ContextEngine>,
     executor: &Arc<Executor>,
+    extensions: &Option<Arc<ExtensionHost>>,
+    completions: &Arc<CompletionEngine>,
+    readiness: &Arc<Readiness>,
 ) -> Result<Response> {
     match request {
         Request::Command {
             input,
             cwd: _,
-            shell: _,
+            shell,
+            session: None,
+            output,
         } => {
-            handle_command_query(
+            let mut context = context_engine.get_context().await?;
+            // The client knows which shell the input was typed in
+            if !shell.is_empty() {
+                context.shell_name = shell;
+            }
+            handle_query_in_context(
                 &input,
+                context,
+                output.as_deref(),
                 config,
                 classifier,
                 provider_router,
                 learning_engine,
                 context_engine,
                 executor,
+                extensions,
             )
             .await
         }
+        Request::Command {
+            input,
+            session: Some(session),
+            output,
+            ..
+        } => {
+            let context = executor.session_context(session).await?;
+            handle_query_in_context(
+                &input,
+                context,
+                output.as_deref(),
+                config,
+                classifier,
+                provider_router,
+                learning_engine,
+                context_engine,
+                executor,
+                extensions,
+            )
+            .await
+        }
+        Request::Classify { input } => {
+            let context = context_engine.get_context().await?;
+            Ok(Response::Classified {
+                classification: classifier.classify(&input, &context).await?.into(),
+            })
+        }
         Request::Feedback {
             input,
             executed,
             result,
-        } => handle_feedback(&input, &executed, result, learning_engine, context_engine).await,
+            reasons,
+            note,
+        } => {
+            if matches!(result, FeedbackResult::Success) {
+                refresh_after_install(&executed, config, classifier);
+            }
+            handle_feedback(
+                &input,
+                &executed,
+                result,
+                &reasons,
+                note.as_deref(),
+                learning_engine,
+                context_engine,
+                extensions,
+            )
+            .await
+        }
         Request::Status => {
             // TODO: Track uptime and command count
             Ok(Response::Status {
                 uptime_secs: 0,
                 commands_processed: 0,
+                connectivity: provider_router.connectivity().status(),
+                readiness: readiness.status(),
+                rate_limits: provider_router.rate_limits(),
+            })
+        }
+        Request::Database { action } => handle_database(action, learning_engine).await,
+        Request::PurgeLearningData { confirm } => {
+            if !confirm {
+                return Ok(Response::Error {
+                    message: "Purging learning data requires confirm: true".to_string(),
+                });
+            }
+            let report = learning_engine.purge_all().await?;
+            Ok(Response::Purged { report })
+        }
+        Request::Complete { line, cursor, cwd } => Ok(Response::Completions {
+            items: completions
+                .complete(&line, cursor, std::path::Path::new(&cwd))
+                .await?,
+        }),
+        Request::Extensions { action } => handle_extensions(action, extensions),
+        Request::Playbooks { action } => {
+            handle_playbooks(action, learning_engine, context_engine).await
+        }
+        Request::TerminalFocus { terminal } => {
+            match &terminal {
+                Some(t) => debug!(
+                    "Terminal focus: {} ({})",
+                    t.name,
+                    t.host.as_deref().unwrap_or("local")
+                ),
+                None => debug!("No Pulsar terminal has focus"),
+            }
+            context_engine.set_focused_terminal(terminal);
+            Ok(Response::Ok)
+        }
+        Request::Execute {
+            command,
+            cwd,
+            target,
+            input,
+        } => {
+            // Results are learned against the machine the command ran on
+            let context = match &target {
+                ExecutionTarget::Session { id } => executor.session_context(*id).await?,
+                _ => context_engine.get_context().await?,
+            };
+            let output = executor
+                .execute(
+                    &command,
+                    std::path::Path::new(&cwd),
+                    &target,
+                    context.dialect(),
+                )
+                .await?;
+            if output.exit_code == 0 && target == ExecutionTarget::Host {
+                refresh_after_install(&command, config, classifier);
+            }
+            learning_engine
+                .record_execution(
+                    input.as_deref().unwrap_or(&command),
+                    &command,
+                    output.exit_code,
+                    output.duration_ms as i64,
+                    &context,
+                )
+                .await?;
+            Ok(Response::Executed { output })
+        }
+        Request::Containers { runtime } => {
+            let Some(runtime) = runtime.or_else(ContainerRuntime::detect) else {
+                return Ok(Response::Error {
+                    message: "Neither docker nor podman was found on PATH".to_string(),
+                });
+            };
+            Ok(Response::Containers {
+                runtime,
+                containers: runtime.running_containers().await?,
+            })
+        }
+        Request::RunElevated {
+            command,
+            password,
+            from_vault,
+            confirmed,
+        } => {
+            // Held to the same checks as commands from agents before sudo
+            // gets it
+            if !validate_ai_response(&command, ShellDialect::Posix, executor, config)? {
+                executor.report_blocked(&command, &command, "elevation");
+                return Ok(Response::Error {
+                    message: "Command rejected for safety reasons".to_string(),
+                });
+            }
+            if config.execution.confirm_destructive && !confirmed && executor.is_destructive(&command) {
+                return Ok(Response::Error {
+                    message: "This command is destructive; confirm to run it as root".to_string(),
+                });
+            }
+            let run = executor
+                .run_elevated(&command, password, from_vault)
+                .await?;
+            if matches!(&run, ElevatedRun::Completed(output) if output.exit_code == 0) {
+                refresh_after_install(&command, config, classifier);
+            }
+            Ok(Response::Elevated { run })
+        }
+        Request::SearchHistory { query, limit } => Ok(Response::History {
+            matches: learning_engine
+                .search_history(&query, limit.unwrap_or(20))
+                .await?,
+        }),
+        Request::CheckCommand { command } => Ok(Response::Checked {
+            destructive: executor.is_destructive(&command),
+        }),
+        Request::SetLogLevel { level } => Ok(set_log_level(level)),
+        Request::SetOffline { offline } => {
+            provider_router.connectivity().set_offline(offline);
+            Ok(Response::Ok)
+        }
+        Request::RefreshCommands => {
+            if !config.classification.cache_known_commands {
+                return Ok(Response::Error {
+                    message: "The known commands cache is off \
+                              (classification.cache_known_commands)"
+                        .to_string(),
+                });
+            }
+            Ok(Response::CommandsRefreshed {
+                refresh: classifier.refresh().await?,
             })
         }
         Request::Shutdown => {
@@ -307,7 +561,72 @@ async fn handle_request(
     }
 }
 
-async fn handle_command_query(
+/// Rebuild the known commands cache in the background when `command`
+/// installed packages, so their binaries are recognized straight away
+fn refresh_after_install(command: &str, config: &Config, classifier: &Arc<CommandClassifier>) {
+    if !config.classification.cache_known_commands || !is_package_install(command) {
+        return;
+    }
+    let classifier = classifier.clone();
+    tokio::spawn(async move {
+        match classifier.refresh().await {
+            Ok(refresh) if !refresh.added.is_empty() => {
+                info!(
+                    "Recognizing newly installed commands: {}",
+                    refresh.added.join(", ")
+                )
+            }
+            Ok(_) => {}
+            Err(e) => warn!("Failed to refresh known commands: {}", e),
+        }
+    });
+}
+
+async fn handle_database(
+    action: DatabaseAction,
+    learning_engine: &Arc<LearningEngine>,
+) -> Result<Response> {
+    let backup_path = match action {
+        DatabaseAction::Backup => Some(learning_engine.backup_database().await?),
+        DatabaseAction::Vacuum => {
+            learning_engine.vacuum_database().await?;
+            None
+        }
+        DatabaseAction::Health => None,
+    };
+
+    Ok(Response::Database {
+        health: learning_engine.database_health().await?,
+        backup_path: backup_path.map(|p| p.display().to_string()),
+    })
+}
+
+fn handle_extensions(
+    action: ExtensionAction,
+    extensions: &Option<Arc<ExtensionHost>>,
+) -> Result<Response> {
+    let Some(host) = extensions else {
+        return Ok(Response::Error {
+            message: "Extensions are disabled".to_string(),
+        });
+    };
+
+    let (extensions, suggestions) = match action {
+        ExtensionAction::List => (host.list(), Vec::new()),
+        ExtensionAction::Reload => (host.reload(), Vec::new()),
+        ExtensionAction::Approve { name } => (host.approve(&name)?, Vec::new()),
+        ExtensionAction::Revoke { name } => (host.revoke(&name)?, Vec::new()),
+        ExtensionAction::Suggestions => (host.list(), host.take_suggestions()),
+    };
+
+    Ok(Response::Extensions {
+        extensions,
+        suggestions,
+    })
+}
+
+#[allow(clippy::too_many_arguments)]
+pub(super) async fn handle_command_query(
     command: &str,
     config: &Arc<Config>,
     classifier: &Arc<CommandClassifier>,
@@ -315,12 +634,97 @@ async fn handle_command_query(
     learning_engine: &Arc<LearningEngine>,
     context_engine: &Arc<ContextEngine>,
     executor: &Arc<Executor>,
+    extensions: &Option<Arc<ExtensionHost>>,
 ) -> Result<Response> {
     // Get current context
     let context = context_engine.get_context().await?;
 
+    handle_query_in_context(
+        command,
+        context,
+        None,
+        config,
+        classifier,
+        provider_router,
+        learning_engine,
+        context_engine,
+        executor,
+        extensions,
+    )
+    .await
+}
+
+/// Suggest a command for `command` as typed in a shell with `context`,
+/// after the terminal showed `output`, explained first if its risk calls
+/// for it
+#[allow(clippy::too_many_arguments)]
+async fn handle_query_in_context(
+    command: &str,
+    context: crate::context::Context,
+    output: Option<&str>,
+    config: &Arc<Config>,
+    classifier: &Arc<CommandClassifier>,
+    provider_router: &Arc<ProviderRouter>,
+    learning_engine: &Arc<LearningEngine>,
+    context_engine: &Arc<ContextEngine>,
+    executor: &Arc<Executor>,
+    extensions: &Option<Arc<ExtensionHost>>,
+) -> Result<Response> {
+    let response = suggest_in_context(
+        command,
+        &context,
+        output,
+        config,
+        classifier,
+        provider_router,
+        learning_engine,
+        context_engine,
+        executor,
+        extensions,
+    )
+    .await?;
+    Ok(explain_before_approval(
+        response,
+        &context,
+        config,
+        provider_router,
+        context_engine,
+        executor,
+    )
+    .await)
+}
+
+/// The suggestion for [`handle_query_in_context`], before any explanation
+#[allow(clippy::too_many_arguments)]
+async fn suggest_in_context(
+    command: &str,
+    context: &crate::context::Context,
+    output: Option<&str>,
+    config: &Arc<Config>,
+    classifier: &Arc<CommandClassifier>,
+    provider_router: &Arc<ProviderRouter>,
+    learning_engine: &Arc<LearningEngine>,
+    context_engine: &Arc<ContextEngine>,
+    executor: &Arc<Executor>,
+    extensions: &Option<Arc<ExtensionHost>>,
+) -> Result<Response> {
+    if let Some(host) = extensions {
+        host.dispatch(
+            &ExtensionEvent::InputReceived {
+                input: command.to_string(),
+            },
+            context,
+        );
+    }
+
+    // A saved playbook trigger runs the whole sequence
+    if let Some(playbook) = learning_engine.match_playbook(command, context).await? {
+        debug!("Running playbook '{}'", playbook.name);
+        return Ok(replaced(playbook.script(), context, executor, config));
+    }
+
     // Classify command
-    let classification = classifier.classify(command, &context).await?;
+    let classification = classifier.classify(command, context).await?;
 
     match classification {
         CommandType::Known => {
@@ -329,42 +733,82 @@ async fn handle_command_query(
         }
         CommandType::LearnedPattern(pattern) => {
             debug!("Using learned pattern: {}", pattern.learned_command);
-            Ok(Response::Replaced {
-                command: pattern.learned_command,
-            })
+            Ok(replaced(pattern.learned_command, context, executor, config))
+        }
+        CommandType::NaturalLanguage | CommandType::Ambiguous
+            if !provider_router.connectivity().is_online() =>
+        {
+            offline_suggestion(
+                command,
+                context,
+                output,
+                classifier,
+                provider_router,
+                learning_engine,
+                executor,
+                config,
+            )
+            .await
         }
         CommandType::NaturalLanguage | CommandType::Ambiguous => {
-            debug!("Sending to AI for interpretation");
+            let language = classifier.detect_language(command);
+            debug!(
+                "Sending to AI for interpretation ({})",
+                language.map_or("language unknown", |l| l.name)
+            );
 
-            match provider_router
-                .process_natural_language(command, &context)
+            let settings = context_engine.provider_settings(context);
+            let suggestion = match provider_router
+                .process_natural_language(command, context, &settings, language, output)
                 .await
             {
+                // Rate limits are reported as such; other failures fall
+                // back to the local model, if there is one
+                Err(e)
+                    if provider_router.has_local_model()
+                        && settings.provider != LOCAL_PROVIDER
+                        && e.downcast_ref::<RateLimited>().is_none() =>
+                {
+                    warn!("AI error ({}), asking the local model", e);
+                    provider_router
+                        .local_suggestion(command, context, language, output)
+                        .await
+                }
+                suggestion => suggestion,
+            };
+            match suggestion {
                 Ok(ai_command) => {
                     debug!("AI suggestion: {}", ai_command);
 
                     // SECURITY: Validate AI response for safety
-                    if validate_ai_response(&ai_command, executor, config)? {
+                    if validate_ai_response(&ai_command, context.dialect(), executor, config)? {
                         // Record this interaction for learning
                         learning_engine
-                            .record_ai_suggestion(command, &ai_command, &context)
+                            .record_ai_suggestion(command, &ai_command, context)
                             .await?;
 
-                        Ok(Response::Replaced {
-                            command: ai_command,
-                        })
+                        Ok(replaced(ai_command, context, executor, config))
                     } else {
                         // AI returned an unsafe command
                         warn!(
                             "AI returned unsafe command, rejecting: {}",
                             ai_command
                         );
+                        executor.report_blocked(command, &ai_command, "suggestion");
                         Ok(Response::Error {
                             message: "AI suggestion rejected for safety reasons. Please try rephrasing your request.".to_string(),
                         })
                     }
                 }
                 Err(e) => {
+                    if let Some(limited) = e.downcast_ref::<RateLimited>() {
+                        warn!("{}", limited);
+                        return Ok(Response::RateLimited {
+                            provider: limited.provider.clone(),
+                            retry_after_ms: limited.retry_after.as_millis() as u64,
+                            message: limited.to_string(),
+                        });
+                    }
                     error!("AI error: {}", e);
                     Ok(Response::Error {
                         message: e.to_string(),
@@ -375,6 +819,173 @@ async fn handle_command_query(
     }
 }
 
+/// Offline, the closest learned pattern (by the local embedding model)
+/// answers, however low its confidence, and failing that the local model
+/// if it is enabled
+async fn offline_suggestion(
+    command: &str,
+    context: &crate::context::Context,
+    output: Option<&str>,
+    classifier: &Arc<CommandClassifier>,
+    provider_router: &Arc<ProviderRouter>,
+    learning_engine: &Arc<LearningEngine>,
+    executor: &Arc<Executor>,
+    config: &Arc<Config>,
+) -> Result<Response> {
+    match learning_engine.find_similar(command, context).await? {
+        Some(pattern) => {
+            debug!(
+                "Offline; using closest learned pattern: {}",
+                pattern.learned_command
+            );
+            Ok(replaced(pattern.learned_command, context, executor, config))
+        }
+        None if provider_router.has_local_model() => {
+            let language = classifier.detect_language(command);
+            let suggestion = match provider_router
+                .local_suggestion(command, context, language, output)
+                .await
+            {
+                Ok(suggestion) => suggestion,
+                Err(e) => {
+                    return Ok(Response::Error {
+                        message: format!(
+                            "{}, and none matches this request ({:#})",
+                            provider_router.connectivity().status().message,
+                            e
+                        ),
+                    })
+                }
+            };
+            debug!("Offline; local model suggests: {}", suggestion);
+            if validate_ai_response(&suggestion, context.dialect(), executor, config)? {
+                Ok(replaced(suggestion, context, executor, config))
+            } else {
+                warn!(
+                    "Local model returned unsafe command, rejecting: {}",
+                    suggestion
+                );
+                executor.report_blocked(command, &suggestion, "suggestion");
+                Ok(Response::Error {
+                    message: "Local model suggestion rejected for safety reasons. Please try rephrasing your request.".to_string(),
+                })
+            }
+        }
+        None => Ok(Response::Error {
+            message: format!(
+                "{}, and none matches this request",
+                provider_router.connectivity().status().message
+            ),
+        }),
+    }
+}
+
+async fn handle_playbooks(
+    action: PlaybookAction,
+    learning_engine: &Arc<LearningEngine>,
+    context_engine: &Arc<ContextEngine>,
+) -> Result<Response> {
+    let context = context_engine.get_context().await?;
+
+    let candidates = match action {
+        PlaybookAction::List => Vec::new(),
+        PlaybookAction::Candidates => learning_engine.playbook_candidates(&context).await?,
+        PlaybookAction::Save {
+            name,
+            trigger,
+            commands,
+        } => {
+            let playbook = learning_engine
+                .save_playbook(&context, &name, &trigger, &commands)
+                .await?;
+            info!("Saved playbook '{}' ({} commands)", playbook.name, playbook.commands.len());
+            Vec::new()
+        }
+        PlaybookAction::Delete { name } => {
+            if !learning_engine.delete_playbook(&context, &name).await? {
+                return Ok(Response::Error {
+                    message: format!("No playbook named '{}' in this project", name),
+                });
+            }
+            Vec::new()
+        }
+    };
+
+    Ok(Response::Playbooks {
+        playbooks: learning_engine.list_playbooks(&context).await?,
+        candidates,
+    })
+}
+
+/// Replacement command, with a dry run of the files it modifies (from
+/// `cwd`) when the user approves commands before they run, and whether it
+/// needs root
+fn replaced(
+    command: String,
+    context: &crate::context::Context,
+    executor: &Arc<Executor>,
+    config: &Arc<Config>,
+) -> Response {
+    // Files on another host can't be listed from here
+    let dry_run = if config.execution.auto_approve || context.host.is_some() {
+        None
+    } else {
+        executor.dry_run(&command, &context.pwd)
+    };
+    let elevation = executor.elevation(&command);
+    Response::Replaced {
+        command,
+        dry_run,
+        elevation,
+        explanation: None,
+        host: context.host.clone(),
+    }
+}
+
+/// Explain a replacement command before the user approves it, when its
+/// risk tier is one `execution.explain_tiers` lists. The provider writes
+/// the explanation; if it can't, it is put together locally.
+async fn explain_before_approval(
+    mut response: Response,
+    context: &crate::context::Context,
+    config: &Arc<Config>,
+    provider_router: &Arc<ProviderRouter>,
+    context_engine: &Arc<ContextEngine>,
+    executor: &Arc<Executor>,
+) -> Response {
+    let Response::Replaced {
+        command,
+        dry_run,
+        explanation,
+        ..
+    } = &mut response
+    else {
+        return response;
+    };
+    if config.execution.auto_approve || config.execution.explain_tiers.is_empty() {
+        return response;
+    }
+    let risk = executor.risk(command, context.dialect(), dry_run.as_ref());
+    if !config.execution.explain_tiers.contains(&risk) {
+        return response;
+    }
+
+    let settings = context_engine.provider_settings(context);
+    *explanation = Some(
+        match provider_router
+            .explain_risk(command, context, &settings, risk, dry_run.as_ref())
+            .await
+        {
+            Ok(explained) => explained,
+            Err(e) => {
+                debug!("Explaining {} risk command locally: {}", risk, e);
+                Explanation::describe(command, risk, context.dialect(), dry_run.as_ref())
+            }
+        },
+    );
+    response
+}
+
 /// Validate AI response for safety
 ///
 /// Checks for:
@@ -384,8 +995,9 @@ async fn handle_command_query(
 /// 4. Command length limits
 ///
 /// Returns true if safe, false if should be rejected
-fn validate_ai_response(
+pub(super) fn validate_ai_response(
     ai_command: &str,
+    dialect: ShellDialect,
     executor: &Arc<Executor>,
     config: &Arc<Config>,
 ) -> Result<bool> {
@@ -402,7 +1014,7 @@ fn validate_ai_response(
     }
 
     // Check 3: Use our destructive command detector
-    if config.execution.confirm_destructive && executor.is_destructive(ai_command) {
+    if config.execution.confirm_destructive && executor.is_destructive_in(ai_command, dialect) {
         warn!("AI returned destructive command: {}", ai_command);
         // Still return true because user will be prompted for confirmation
         // But log it for monitoring
@@ -435,6 +1047,13 @@ fn validate_ai_response(
         return Ok(false);
     }
 
+    // PowerShell's curl | bash: iwr ... | iex
+    let lower = ai_command.to_lowercase();
+    if lower.contains("| iex") || lower.contains("| invoke-expression") {
+        warn!("AI returned download piped to Invoke-Expression");
+        return Ok(false);
+    }
+
     // Check 6: Detect excessive use of special characters (possible obfuscation)
     let special_char_count = ai_command.chars().filter(|c| !c.is_alphanumeric() && !c.is_whitespace()).count();
     let special_char_ratio = special_char_count as f32 / ai_command.len() as f32;
@@ -451,19 +1070,40 @@ fn validate_ai_response(
     Ok(true)
 }
 
+#[allow(clippy::too_many_arguments)]
 async fn handle_feedback(
     input: &str,
     executed: &str,
     result: FeedbackResult,
+    reasons: &[FeedbackReason],
+    note: Option<&str>,
     learning_engine: &Arc<LearningEngine>,
     context_engine: &Arc<ContextEngine>,
+    extensions: &Option<Arc<ExtensionHost>>,
 ) -> Result<Response> {
     let context = context_engine.get_context().await?;
 
+    let outcome = match &result {
+        FeedbackResult::Success => Some(true),
+        FeedbackResult::Failed => Some(false),
+        _ => None,
+    };
+    if let (Some(host), Some(success)) = (extensions, outcome) {
+        host.dispatch(
+            &ExtensionEvent::CommandExecuted {
+                input: input.to_string(),
+                command: executed.to_string(),
+                success,
+            },
+            &context,
+        );
+    }
+
     debug!(
-        "Received feedback: input='{}', executed='{}', result={:?}",
-        input, executed, result
+        "Received feedback: input='{}', executed='{}', result={:?}, reasons={:?}",
+        input, executed, result, reasons
     );
+    let explained = !reasons.is_empty() || note.is_some();
 
     // Update pattern confidence based on feedback
     match result {
@@ -481,22 +1121,40 @@ async fn handle_feedback(
             learning_engine
                 .record_temporal_pattern(executed, hour, day)
                 .await?;
+
+            // Shells report success or failure, not exit codes or timings
+            learning_engine
+                .record_execution(input, executed, 0, 0, &context)
+                .await?;
         }
         FeedbackResult::Failed => {
-            // Lower confidence for failed execution
+            // Lower confidence for failed execution, by the reasons if given
+            if reasons.is_empty() {
+                learning_engine.record_failure(input, executed, &context).await?;
+            }
+            if explained {
+                learning_engine
+                    .record_feedback(input, executed, None, reasons, note, &context)
+                    .await?;
+            }
             learning_engine
-                .record_failure(input, executed, &context)
+                .record_execution(input, executed, 1, 0, &context)
                 .await?;
             debug!("Pattern confidence lowered for failed execution");
         }
         FeedbackResult::Rejected => {
-            // User rejected the suggestion - just log it
+            // User rejected the suggestion - penalize it only if they said why
+            if explained {
+                learning_engine
+                    .record_feedback(input, executed, None, reasons, note, &context)
+                    .await?;
+            }
             debug!("User rejected suggestion");
         }
         FeedbackResult::Edited { new_command } => {
             // User edited the suggestion - record as correction
             learning_engine
-                .record_correction(input, executed, &new_command, &context)
+                .record_feedback(input, executed, Some(&new_command), reasons, note, &context)
                 .await?;
             debug!("User correction recorded");
         }
@@ -505,6 +1163,7 @@ async fn handle_feedback(
     Ok(Response::Ok)
 }
 
+#[allow(clippy::too_many_arguments)]
 async fn handle_legacy_query(
     command: &str,
     config: &Arc<Config>,
@@ -513,6 +1172,7 @@ async fn handle_legacy_query(
     learning_engine: &Arc<LearningEngine>,
     context_engine: &Arc<ContextEngine>,
     executor: &Arc<Executor>,
+    extensions: &Option<Arc<ExtensionHost>>,
 ) -> String {
     match handle_command_query(
         command,
@@ -522,11 +1182,12 @@ async fn handle_legacy_query(
         learning_engine,
         context_engine,
         executor,
+        extensions,
     )
     .await
     {
         Ok(Response::Passthrough) => "PASSTHROUGH\n".to_string(),
-        Ok(Response::Replaced { command }) => format!("REPLACED:{}\n", command),
+        Ok(Response::Replaced { command, .. }) => format!("REPLACED:{}\n", command),
         Ok(Response::Error { message }) => format!("ERROR:{}\n", message),
         Err(e) => format!("ERROR:{}\n", e),
         _ => "ERROR:Unexpected response\n".to_string(),
@@ -0,0 +1,409 @@
+// Dry run for file-mutating commands
+//
+// Before the user approves a command like `rm -r build/*.o` or
+// `sed -i s/foo/bar/ src/*.rs`, list the files it will actually touch:
+// globs are expanded against the real filesystem from the shell's working
+// directory, and recursive operations list what's inside directories.
+//
+// This reads the filesystem only. Operands the daemon can't resolve (shell
+// variables, command substitution) are reported rather than guessed.
+
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+use crate::providers::split_stages;
+
+/// Files listed per dry run, across all stages
+const MAX_FILES: usize = 500;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case", tag = "kind")]
+pub enum FileAction {
+    Remove,
+    Move { destination: String },
+    ChangeMode { mode: String },
+    ChangeOwner { owner: String },
+    EditInPlace,
+}
+
+/// What one stage of the command does to which files
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FileEffect {
+    /// The stage as written, e.g. `rm -rf target`
+    pub command: String,
+    pub action: FileAction,
+    /// Existing files and directories affected
+    pub paths: Vec<PathBuf>,
+    /// Operands that matched nothing or can't be resolved before running
+    pub unresolved: Vec<String>,
+}
+
+/// Files a command would modify
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DryRun {
+    pub effects: Vec<FileEffect>,
+    /// More than MAX_FILES files were affected; the rest aren't listed
+    pub truncated: bool,
+}
+
+impl DryRun {
+    pub fn file_count(&self) -> usize {
+        self.effects.iter().map(|e| e.paths.len()).sum()
+    }
+
+    /// Human-readable lines for a confirmation prompt
+    pub fn summary(&self) -> Vec<String> {
+        let mut lines = Vec::new();
+        for effect in &self.effects {
+            let verb = match &effect.action {
+                FileAction::Remove => "remove".to_string(),
+                FileAction::Move { destination } => format!("move to {}", destination),
+                FileAction::ChangeMode { mode } => format!("chmod {}", mode),
+                FileAction::ChangeOwner { owner } => format!("chown {}", owner),
+                FileAction::EditInPlace => "edit in place".to_string(),
+            };
+            lines.push(format!(
+                "`{}` will {} {} file(s):",
+                effect.command,
+                verb,
+                effect.paths.len()
+            ));
+            lines.extend(effect.paths.iter().map(|p| format!("  {}", p.display())));
+            lines.extend(
+                effect
+                    .unresolved
+                    .iter()
+                    .map(|u| format!("  {} (not resolved)", u)),
+            );
+        }
+        if self.truncated {
+            lines.push(format!(
+                "...and more (only the first {} files are listed)",
+                MAX_FILES
+            ));
+        }
+        lines
+    }
+}
+
+/// Files `command` would modify when run from `cwd`; None when it doesn't
+/// modify files in a way this understands
+pub fn analyze(command: &str, cwd: &Path) -> Option<DryRun> {
+    let mut dry_run = DryRun {
+        effects: Vec::new(),
+        truncated: false,
+    };
+    let mut budget = MAX_FILES;
+
+    for stage in split_stages(command) {
+        let words = tokenize(&stage);
+        let Some((action, recursive, operands)) = parse_stage(&words) else {
+            continue;
+        };
+
+        let mut effect = FileEffect {
+            command: stage.clone(),
+            action,
+            paths: Vec::new(),
+            unresolved: Vec::new(),
+        };
+        for operand in operands {
+            match expand(operand, cwd) {
+                Some(paths) if !paths.is_empty() => {
+                    for path in paths {
+                        dry_run.truncated |=
+                            !collect(cwd, &path, recursive, &mut effect.paths, &mut budget);
+                    }
+                }
+                _ => effect.unresolved.push(operand.text.clone()),
+            }
+        }
+        dry_run.effects.push(effect);
+    }
+
+    (!dry_run.effects.is_empty()).then_some(dry_run)
+}
+
+/// A shell word, with whether the shell would glob-expand it
+#[derive(Debug, PartialEq)]
+struct Word {
+    text: String,
+    /// Contains unquoted glob characters
+    glob: bool,
+    /// Contains `$` or backticks the shell would substitute
+    dynamic: bool,
+}
+
+fn tokenize(stage: &str) -> Vec<Word> {
+    let mut words = Vec::new();
+    let mut current: Option<Word> = None;
+    let mut quote = None;
+    let mut chars = stage.chars();
+
+    while let Some(c) = chars.next() {
+        if quote.is_none() && c.is_whitespace() {
+            words.extend(current.take());
+            continue;
+        }
+        let word = current.get_or_insert_with(|| Word {
+            text: String::new(),
+            glob: false,
+            dynamic: false,
+        });
+        match (c, quote) {
+            ('\'' | '"', None) => quote = Some(c),
+            (c, Some(q)) if c == q => quote = None,
+            ('\\', q) if q != Some('\'') => word.text.extend(chars.next()),
+            ('$' | '`', q) if q != Some('\'') => {
+                word.dynamic = true;
+                word.text.push(c);
+            }
+            ('*' | '?' | '[', None) => {
+                word.glob = true;
+                word.text.push(c);
+            }
+            _ => word.text.push(c),
+        }
+    }
+    words.extend(current);
+    words
+}
+
+/// Action, recursion and file operands for a file-mutating stage
+fn parse_stage(words: &[Word]) -> Option<(FileAction, bool, Vec<&Word>)> {
+    // Skip `sudo`, `env` and variable assignments in front of the program
+    let start = words.iter().position(|w| {
+        let assignment = w.text.contains('=') && !w.text.starts_with('-');
+        !assignment && !matches!(w.text.as_str(), "sudo" | "env" | "command")
+    })?;
+    let program = words[start].text.rsplit('/').next().unwrap_or_default();
+    let args = &words[start + 1..];
+
+    let mut flags = Vec::new();
+    let mut operands = Vec::new();
+    let mut options_done = false;
+    for arg in args {
+        if !options_done && arg.text == "--" {
+            options_done = true;
+        } else if !options_done && arg.text.starts_with('-') && arg.text.len() > 1 {
+            flags.push(arg.text.as_str());
+        } else {
+            operands.push(arg);
+        }
+    }
+    let has_flag = |short: char, long: &str| {
+        flags
+            .iter()
+            .any(|f| *f == long || (!f.starts_with("--") && f.contains(short)))
+    };
+
+    match program {
+        "rm" | "unlink" | "rmdir" => {
+            let recursive =
+                program == "rm" && (has_flag('r', "--recursive") || has_flag('R', "--recursive"));
+            Some((FileAction::Remove, recursive, operands))
+        }
+        "mv" => {
+            let target = flags
+                .iter()
+                .find_map(|f| f.strip_prefix("--target-directory="))
+                .map(str::to_string);
+            let destination = match target {
+                Some(target) => target,
+                // `-t DIR`: the directory was taken as the first operand
+                None if flags.contains(&"-t") && !operands.is_empty() => {
+                    operands.remove(0).text.clone()
+                }
+                None if operands.len() >= 2 => operands.pop()?.text.clone(),
+                None => return None,
+            };
+            Some((FileAction::Move { destination }, false, operands))
+        }
+        "chmod" | "chown" | "chgrp" => {
+            if operands.is_empty() {
+                return None;
+            }
+            let spec = operands.remove(0).text.clone();
+            let action = match program {
+                "chmod" => FileAction::ChangeMode { mode: spec },
+                _ => FileAction::ChangeOwner { owner: spec },
+            };
+            Some((action, has_flag('R', "--recursive"), operands))
+        }
+        "sed" => {
+            let in_place = flags
+                .iter()
+                .any(|f| *f == "-i" || f.starts_with("-i") || f.starts_with("--in-place"));
+            if !in_place {
+                return None;
+            }
+            // The script is the first operand unless given with -e or -f,
+            // whose values were taken as operands here
+            let scripts = flags
+                .iter()
+                .filter(|f| **f == "-e" || **f == "-f")
+                .count()
+                .max(1);
+            Some((
+                FileAction::EditInPlace,
+                false,
+                operands.into_iter().skip(scripts).collect(),
+            ))
+        }
+        _ => None,
+    }
+}
+
+/// Paths an operand names: glob matches, or the path itself if it exists.
+/// None when the shell would substitute something first.
+fn expand(word: &Word, cwd: &Path) -> Option<Vec<PathBuf>> {
+    if word.dynamic {
+        return None;
+    }
+    let text = match word.text.strip_prefix("~/") {
+        Some(rest) => dirs::home_dir()?.join(rest).display().to_string(),
+        None => word.text.clone(),
+    };
+    let path = cwd.join(&text);
+
+    if !word.glob {
+        return Some(if path.symlink_metadata().is_ok() {
+            vec![PathBuf::from(text)]
+        } else {
+            Vec::new()
+        });
+    }
+
+    // Keep matches relative when the operand was, as the user wrote it
+    let pattern = glob::Pattern::escape(&cwd.display().to_string());
+    let pattern = if Path::new(&text).is_absolute() {
+        text.clone()
+    } else {
+        format!("{}/{}", pattern, text)
+    };
+    let mut paths: Vec<PathBuf> = glob::glob(&pattern)
+        .ok()?
+        .flatten()
+        .map(|p| match p.strip_prefix(cwd) {
+            Ok(relative) if !Path::new(&text).is_absolute() => relative.to_path_buf(),
+            _ => p,
+        })
+        .collect();
+    paths.sort();
+    Some(paths)
+}
+
+/// Add `path` (and, if recursive, everything under it) to `out`; false once
+/// the budget runs out
+fn collect(
+    cwd: &Path,
+    path: &Path,
+    recursive: bool,
+    out: &mut Vec<PathBuf>,
+    budget: &mut usize,
+) -> bool {
+    if *budget == 0 {
+        return false;
+    }
+    out.push(path.to_path_buf());
+    *budget -= 1;
+
+    // Don't follow symlinked directories; the commands don't either
+    let is_dir = cwd.join(path).symlink_metadata().is_ok_and(|m| m.is_dir());
+    if !recursive || !is_dir {
+        return true;
+    }
+    let Ok(entries) = std::fs::read_dir(cwd.join(path)) else {
+        return true;
+    };
+    let mut children: Vec<PathBuf> = entries
+        .flatten()
+        .map(|e| path.join(e.file_name()))
+        .collect();
+    children.sort();
+    children
+        .into_iter()
+        .all(|child| collect(cwd, &child, true, out, budget))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn project() -> TempDir {
+        let dir = TempDir::new().unwrap();
+        std::fs::create_dir_all(dir.path().join("build/obj")).unwrap();
+        for file in [
+            "a.log",
+            "b.log",
+            "notes.txt",
+            "build/out.o",
+            "build/obj/x.o",
+        ] {
+            std::fs::write(dir.path().join(file), "").unwrap();
+        }
+        dir
+    }
+
+    fn paths(effect: &FileEffect) -> Vec<String> {
+        effect
+            .paths
+            .iter()
+            .map(|p| p.display().to_string())
+            .collect()
+    }
+
+    #[test]
+    fn test_globs_expand_against_working_directory() {
+        let dir = project();
+
+        let dry_run = analyze("rm -f *.log missing.txt", dir.path()).unwrap();
+        assert_eq!(dry_run.effects[0].action, FileAction::Remove);
+        assert_eq!(paths(&dry_run.effects[0]), vec!["a.log", "b.log"]);
+        assert_eq!(dry_run.effects[0].unresolved, vec!["missing.txt"]);
+
+        // Quoted globs reach the program literally
+        let dry_run = analyze("rm '*.log'", dir.path()).unwrap();
+        assert!(dry_run.effects[0].paths.is_empty());
+
+        let dry_run = analyze(
+            "sed -i.bak 's/a/b/' *.txt && mv notes.txt build/",
+            dir.path(),
+        )
+        .unwrap();
+        assert_eq!(dry_run.effects[0].action, FileAction::EditInPlace);
+        assert_eq!(paths(&dry_run.effects[0]), vec!["notes.txt"]);
+        assert_eq!(
+            dry_run.effects[1].action,
+            FileAction::Move {
+                destination: "build/".to_string()
+            }
+        );
+    }
+
+    #[test]
+    fn test_recursive_operations_list_directory_contents() {
+        let dir = project();
+
+        let dry_run = analyze("sudo chmod -R 755 build", dir.path()).unwrap();
+        assert_eq!(
+            dry_run.effects[0].action,
+            FileAction::ChangeMode {
+                mode: "755".to_string()
+            }
+        );
+        assert_eq!(
+            paths(&dry_run.effects[0]),
+            vec!["build", "build/obj", "build/obj/x.o", "build/out.o"]
+        );
+
+        let dry_run = analyze("rm build", dir.path()).unwrap();
+        assert_eq!(dry_run.file_count(), 1);
+
+        let dry_run = analyze("rm -rf $BUILD_DIR", dir.path()).unwrap();
+        assert_eq!(dry_run.effects[0].unresolved, vec!["$BUILD_DIR"]);
+
+        assert!(analyze("ls -la && sed 's/a/b/' notes.txt", dir.path()).is_none());
+    }
+}
@@ -0,0 +1,284 @@
+// Privilege elevation (sudo)
+//
+// `analyze` spots commands that need root: ones that already use sudo, and
+// ones that will fail without it (installing packages, managing system
+// services, writing under /etc). Clients get this with the suggested
+// command so they can route it through `run`.
+//
+// Orbit never keeps sudo passwords. `run` first relies on sudo's own
+// credential cache (`sudo -n`); when sudo needs a password it reports
+// PasswordRequired and the client asks the user (or its vault). The
+// password is written to `sudo -S` on stdin and wiped from memory
+// afterwards.
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+use zeroize::Zeroizing;
+
+use super::output::{capture, ExecutionOutput};
+use crate::providers::split_stages;
+
+/// Why a command needs root
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Elevation {
+    pub reason: String,
+    /// The command already runs sudo itself
+    pub explicit: bool,
+}
+
+/// A sudo password on its way to sudo; wiped on drop and never printed
+#[derive(Clone)]
+pub struct SudoPassword(Zeroizing<String>);
+
+impl SudoPassword {
+    pub fn new(password: String) -> Self {
+        Self(Zeroizing::new(password))
+    }
+}
+
+impl Serialize for SudoPassword {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.0)
+    }
+}
+
+impl<'de> Deserialize<'de> for SudoPassword {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer).map(Self::new)
+    }
+}
+
+impl std::fmt::Debug for SudoPassword {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("SudoPassword(<redacted>)")
+    }
+}
+
+/// How an elevated command went
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case", tag = "status")]
+pub enum ElevatedRun {
+    Completed(ExecutionOutput),
+    /// sudo has no cached credentials; ask the user and retry with one
+    PasswordRequired,
+    /// sudo rejected the password or the user may not run the command
+    Denied { message: String },
+}
+
+/// Programs whose subcommands change system state
+const SYSTEM_SUBCOMMANDS: &[(&str, &[&str])] = &[
+    (
+        "apt",
+        &[
+            "install",
+            "remove",
+            "purge",
+            "upgrade",
+            "full-upgrade",
+            "autoremove",
+            "update",
+        ],
+    ),
+    (
+        "apt-get",
+        &[
+            "install",
+            "remove",
+            "purge",
+            "upgrade",
+            "dist-upgrade",
+            "autoremove",
+            "update",
+        ],
+    ),
+    (
+        "dnf",
+        &["install", "remove", "upgrade", "update", "autoremove"],
+    ),
+    ("yum", &["install", "remove", "update", "upgrade"]),
+    ("zypper", &["install", "in", "remove", "rm", "update", "up"]),
+    ("pacman", &["-S", "-Syu", "-Sy", "-R", "-Rs", "-U"]),
+    (
+        "systemctl",
+        &[
+            "start",
+            "stop",
+            "restart",
+            "reload",
+            "enable",
+            "disable",
+            "mask",
+            "unmask",
+            "daemon-reload",
+        ],
+    ),
+    ("snap", &["install", "remove", "refresh"]),
+];
+
+/// Programs that always need root
+const ROOT_PROGRAMS: &[&str] = &[
+    "mount",
+    "umount",
+    "useradd",
+    "usermod",
+    "userdel",
+    "groupadd",
+    "groupdel",
+    "visudo",
+    "modprobe",
+    "rmmod",
+    "sysctl",
+    "iptables",
+    "nft",
+    "update-grub",
+    "dpkg-reconfigure",
+];
+
+/// System directories ordinary users can't write to
+const SYSTEM_PATHS: &[&str] = &["/etc/", "/usr/", "/boot/", "/opt/", "/var/lib/", "/lib/"];
+
+/// Whether `command` needs root, checked stage by stage
+pub fn analyze(command: &str) -> Option<Elevation> {
+    split_stages(command)
+        .iter()
+        .find_map(|stage| analyze_stage(stage))
+}
+
+fn analyze_stage(stage: &str) -> Option<Elevation> {
+    let words: Vec<&str> = stage
+        .split_whitespace()
+        .skip_while(|w| w.contains('=') && !w.starts_with('-'))
+        .collect();
+    let program = *words.first()?;
+
+    if matches!(program, "sudo" | "doas" | "pkexec") {
+        return Some(Elevation {
+            reason: format!("runs `{}`", words.get(1).copied().unwrap_or(program)),
+            explicit: true,
+        });
+    }
+    if is_root() {
+        return None;
+    }
+
+    let reason = if ROOT_PROGRAMS.contains(&program) {
+        Some(format!("`{}` needs root", program))
+    } else if let Some((_, subcommands)) = SYSTEM_SUBCOMMANDS.iter().find(|(p, _)| *p == program) {
+        let user_scope = words.contains(&"--user");
+        words
+            .iter()
+            .skip(1)
+            .find(|w| subcommands.contains(w))
+            .filter(|_| !user_scope)
+            .map(|sub| format!("`{} {}` changes the system", program, sub))
+    } else {
+        None
+    };
+
+    // `tee /etc/hosts`, `cp x /usr/local/bin/`, `> /etc/motd`
+    let reason = reason.or_else(|| {
+        let writes_system_path = words.iter().enumerate().skip(1).any(|(i, word)| {
+            let target = word.trim_start_matches(['>', '&']);
+            let copies_to_last =
+                matches!(program, "cp" | "mv" | "install" | "ln") && i == words.len() - 1;
+            let is_target = word.starts_with('>') || program == "tee" || copies_to_last;
+            is_target && SYSTEM_PATHS.iter().any(|p| target.starts_with(p))
+        });
+        writes_system_path.then(|| "writes to a system directory".to_string())
+    });
+
+    reason.map(|reason| Elevation {
+        reason,
+        explicit: false,
+    })
+}
+
+fn is_root() -> bool {
+    #[cfg(unix)]
+    {
+        nix::unistd::geteuid().is_root()
+    }
+    #[cfg(not(unix))]
+    {
+        false
+    }
+}
+
+/// Run `command` as root through sudo
+///
+/// Without a password only sudo's cached credentials are used. The command
+/// runs under `sh -c` so pipelines and redirections behave as typed.
+pub async fn run(
+    command: &str,
+    password: Option<SudoPassword>,
+    timeout: Duration,
+) -> Result<ElevatedRun> {
+    // A leading `sudo` is ours to supply
+    let command = command
+        .trim_start()
+        .strip_prefix("sudo ")
+        .unwrap_or(command)
+        .trim_start();
+
+    let mut sudo = tokio::process::Command::new("sudo");
+    match password {
+        Some(_) => sudo.args(["-S", "-p", ""]),
+        None => sudo.arg("-n"),
+    };
+    sudo.args(["--", "sh", "-c", command]);
+
+    // Closing stdin after the password makes a wrong one fail instead of
+    // waiting for another
+    let line = password.map(|p| Zeroizing::new(format!("{}\n", p.0.as_str())));
+    let output = capture(sudo, line.as_ref().map(|l| l.as_bytes()), timeout).await?;
+
+    if !output.success() {
+        let stderr = &output.stderr;
+        if stderr.contains("a password is required") {
+            return Ok(ElevatedRun::PasswordRequired);
+        }
+        if stderr.contains("incorrect password")
+            || stderr.contains("Sorry, try again")
+            || stderr.contains("is not in the sudoers")
+            || stderr.contains("not allowed to execute")
+        {
+            return Ok(ElevatedRun::Denied {
+                message: stderr.trim().to_string(),
+            });
+        }
+    }
+
+    Ok(ElevatedRun::Completed(output))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_detects_explicit_and_implicit_elevation() {
+        let explicit = analyze("cd /srv && sudo systemctl restart nginx").unwrap();
+        assert!(explicit.explicit);
+
+        if is_root() {
+            return;
+        }
+        assert!(analyze("apt install ripgrep").is_some());
+        assert!(analyze("echo 127.0.0.1 dev | tee -a /etc/hosts").is_some());
+        assert!(analyze("DEBIAN_FRONTEND=noninteractive apt-get upgrade -y").is_some());
+        assert!(!analyze("mount /dev/sdb1 /mnt").unwrap().explicit);
+
+        assert!(analyze("systemctl --user restart pipewire").is_none());
+        assert!(analyze("systemctl status nginx").is_none());
+        assert!(analyze("apt search ripgrep").is_none());
+        assert!(analyze("cat /etc/hosts").is_none());
+        assert!(analyze("cp /etc/hosts ./hosts").is_none());
+    }
+
+    #[test]
+    fn test_password_is_redacted() {
+        let password = SudoPassword::new("hunter2".to_string());
+        assert!(!format!("{:?}", password).contains("hunter2"));
+    }
+}
@@ -1,33 +1,268 @@
-use anyhow::Result;
+pub mod dry_run;
+pub mod elevation;
+pub mod output;
+pub mod risk;
+#[cfg(unix)]
+pub mod session;
+pub mod target;
+
+use anyhow::{bail, Result};
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::atomic::{AtomicU32, Ordering};
 use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::Mutex;
 
 use crate::config::Config;
+use crate::context::{Context, ShellDialect};
+use crate::notifications::{Notification, Notifier};
+use crate::security::{AuditEvent, AuditLogger};
+use self::dry_run::DryRun;
+use self::elevation::{ElevatedRun, Elevation, SudoPassword};
+use self::output::ExecutionOutput;
+use self::risk::RiskTier;
+use self::target::ExecutionTarget;
 
 pub struct Executor {
-    _config: Arc<Config>,
+    config: Arc<Config>,
+    /// Records privilege elevation; None if the audit database can't be opened
+    audit: Option<AuditLogger>,
+    /// Told about commands the safety checks refuse; None if the delivery
+    /// log can't be opened
+    webhooks: Option<pulsar_webhook::Webhooks>,
+    /// Told when a command runs long enough to be worth reporting
+    notifier: Option<Arc<Notifier>>,
+    /// sudo password rejections since the last granted elevation
+    sudo_denials: AtomicU32,
+    /// Contexts read from Pulsar sessions, so suggesting a command and then
+    /// running it probes the session once
+    session_contexts: Mutex<HashMap<uuid::Uuid, (Instant, Context)>>,
 }
 
+/// How long a Pulsar session's context is reused
+const SESSION_CONTEXT_TTL: Duration = Duration::from_secs(60);
+
 impl Executor {
     pub async fn new(config: Arc<Config>) -> Result<Self> {
-        Ok(Self { _config: config })
+        let audit = match AuditLogger::new(Config::data_dir()?.join("audit.db")).await {
+            Ok(audit) => Some(audit),
+            Err(e) => {
+                tracing::warn!("Audit log unavailable, elevation won't be recorded: {}", e);
+                None
+            }
+        };
+        let webhooks = pulsar_webhook::Webhooks::new(
+            "orbitd",
+            &config.webhooks,
+            Some(Config::data_dir()?.join("webhook-deliveries.jsonl")),
+        )
+        .map_err(|e| tracing::warn!("Webhooks unavailable: {}", e))
+        .ok();
+        Ok(Self {
+            config,
+            audit,
+            webhooks,
+            notifier: None,
+            sudo_denials: AtomicU32::new(0),
+            session_contexts: Mutex::new(HashMap::new()),
+        })
     }
 
-    #[allow(dead_code)]
-    pub async fn execute(&self, command: &str) -> Result<String> {
-        // TODO: Implement actual command execution
-        tracing::info!("Would execute: {}", command);
-        Ok(String::new())
+    pub fn with_notifier(mut self, notifier: Arc<Notifier>) -> Self {
+        self.notifier = Some(notifier);
+        self
+    }
+
+    /// Run a command the user approved on `target`, starting from `cwd`.
+    /// On the host it runs in the shell of `dialect`.
+    pub async fn execute(
+        &self,
+        command: &str,
+        cwd: &Path,
+        target: &ExecutionTarget,
+        dialect: ShellDialect,
+    ) -> Result<ExecutionOutput> {
+        let output = self.run(command, cwd, target, dialect).await?;
+        if let Some(notifier) = &self.notifier {
+            if notifier.is_long_running(Duration::from_millis(output.duration_ms)) {
+                notifier.notify(Notification::command_finished(command, &output, target));
+            }
+        }
+        Ok(output)
+    }
+
+    async fn run(
+        &self,
+        command: &str,
+        cwd: &Path,
+        target: &ExecutionTarget,
+        dialect: ShellDialect,
+    ) -> Result<ExecutionOutput> {
+        let timeout = Duration::from_secs(self.config.execution.timeout_seconds);
+        tracing::info!("Running on {}: {}", target, command);
+
+        #[cfg(unix)]
+        if let ExecutionTarget::Session { id } = target {
+            let output = self.pulsar()?.run(*id, command, timeout).await;
+            // The command may have changed directory or branch
+            self.session_contexts.lock().await.remove(id);
+            return output;
+        }
+
+        let name = format!("orbit-run-{}", uuid::Uuid::new_v4().simple());
+        let process = target.command(command, cwd, &name, dialect)?;
+
+        let result = output::capture(process, None, timeout).await;
+        if result.is_err() {
+            // Killing the client doesn't stop a container it started
+            target.cleanup(&name).await;
+        }
+        result
     }
 
     #[allow(dead_code)]
     pub fn is_destructive(&self, command: &str) -> bool {
+        self.is_destructive_in(command, ShellDialect::Posix)
+    }
+
+    /// Whether `command`, written for the shell of `dialect`, is destructive
+    pub fn is_destructive_in(&self, command: &str, dialect: ShellDialect) -> bool {
         // Use comprehensive command analysis instead of simple keyword matching
-        CommandAnalyzer::new().is_destructive(command)
+        CommandAnalyzer::new(dialect).is_destructive(command)
+    }
+
+    /// Report a command the safety checks refused to the `command.blocked`
+    /// webhooks; `via` is what asked for it, e.g. "suggestion" or "mcp"
+    pub fn report_blocked(&self, input: &str, command: &str, via: &str) {
+        if let Some(webhooks) = &self.webhooks {
+            webhooks.notify(
+                pulsar_webhook::COMMAND_BLOCKED,
+                serde_json::json!({
+                    "input": input,
+                    "command": command,
+                    "destructive": self.is_destructive(command),
+                    "via": via,
+                }),
+            );
+        }
+    }
+
+    #[cfg(unix)]
+    fn pulsar(&self) -> Result<session::PulsarClient> {
+        let socket = self
+            .config
+            .execution
+            .pulsar_socket
+            .clone()
+            .or_else(session::PulsarClient::default_socket)
+            .ok_or_else(|| anyhow::anyhow!("No pulsar-daemon socket configured"))?;
+        Ok(session::PulsarClient::new(socket))
+    }
+
+    /// Context of the shell in Pulsar session `id`, for classifying and
+    /// recording commands run there
+    #[cfg(unix)]
+    pub async fn session_context(&self, id: uuid::Uuid) -> Result<Context> {
+        if let Some((read_at, context)) = self.session_contexts.lock().await.get(&id) {
+            if read_at.elapsed() < SESSION_CONTEXT_TTL {
+                return Ok(context.clone());
+            }
+        }
+
+        let context = self.pulsar()?.context(id).await?;
+        self.session_contexts
+            .lock()
+            .await
+            .insert(id, (Instant::now(), context.clone()));
+        Ok(context)
+    }
+
+    /// Files the command would remove, move or modify when run from `cwd`
+    pub fn dry_run(&self, command: &str, cwd: &Path) -> Option<DryRun> {
+        dry_run::analyze(command, cwd)
+    }
+
+    /// Whether the command needs root
+    pub fn elevation(&self, command: &str) -> Option<Elevation> {
+        elevation::analyze(command)
+    }
+
+    /// Risk tier of `command`, written for the shell of `dialect`, given
+    /// what its dry run found
+    pub fn risk(&self, command: &str, dialect: ShellDialect, dry_run: Option<&DryRun>) -> RiskTier {
+        risk::assess(command, dialect, dry_run)
+    }
+
+    /// Run a command the user approved as root. Every attempt is audited;
+    /// `from_vault` only records where the password came from.
+    pub async fn run_elevated(
+        &self,
+        command: &str,
+        password: Option<SudoPassword>,
+        from_vault: bool,
+    ) -> Result<ElevatedRun> {
+        if !self.config.execution.allow_elevation {
+            bail!("Privilege elevation is disabled (execution.allow_elevation)");
+        }
+
+        let method = match (&password, from_vault) {
+            (None, _) => "cached",
+            (Some(_), false) => "prompt",
+            (Some(_), true) => "vault",
+        };
+        let reason = elevation::analyze(command)
+            .map(|e| e.reason)
+            .unwrap_or_else(|| "requested by the user".to_string());
+        let timeout = Duration::from_secs(self.config.execution.timeout_seconds);
+
+        let run = elevation::run(command, password, timeout).await?;
+        let outcome = match &run {
+            ElevatedRun::Completed(_) => "granted",
+            ElevatedRun::PasswordRequired => "password_required",
+            ElevatedRun::Denied { .. } => "denied",
+        };
+        tracing::info!("Elevation {} ({}) for: {}", outcome, method, command);
+        self.track_denials(&run);
+
+        if let Some(audit) = &self.audit {
+            let user = std::env::var("USER").unwrap_or_else(|_| "unknown".to_string());
+            let event = AuditEvent::privilege_elevation(user, command.to_string(), method, outcome, reason, None);
+            if let Err(e) = audit.log_event(event).await {
+                tracing::warn!("Failed to record elevation in the audit log: {}", e);
+            }
+        }
+
+        Ok(run)
+    }
+
+    /// Raise a critical event when sudo keeps rejecting passwords, once per
+    /// run of `notifications.auth_failure_threshold` rejections
+    fn track_denials(&self, run: &ElevatedRun) {
+        let denials = match run {
+            ElevatedRun::Completed(_) => {
+                self.sudo_denials.store(0, Ordering::Relaxed);
+                return;
+            }
+            ElevatedRun::PasswordRequired => return,
+            ElevatedRun::Denied { .. } => self.sudo_denials.fetch_add(1, Ordering::Relaxed) + 1,
+        };
+
+        if denials == self.config.notifications.auth_failure_threshold {
+            if let Some(notifier) = &self.notifier {
+                notifier.notify(Notification::critical(
+                    "Repeated authentication failures",
+                    &format!("sudo rejected {} privilege elevations in a row", denials),
+                ));
+            }
+        }
     }
 }
 
 /// Robust command analyzer that parses shell syntax to detect destructive commands
 struct CommandAnalyzer {
+    /// Decides how the command splits into words
+    dialect: ShellDialect,
     destructive_commands: Vec<&'static str>,
     destructive_patterns: Vec<DestructivePattern>,
 }
@@ -41,8 +276,9 @@ struct DestructivePattern {
 }
 
 impl CommandAnalyzer {
-    fn new() -> Self {
+    fn new(dialect: ShellDialect) -> Self {
         Self {
+            dialect,
             // Comprehensive list of destructive commands (case-insensitive)
             destructive_commands: vec![
                 "mkfs",
@@ -62,6 +298,11 @@ impl CommandAnalyzer {
                 "format",
                 "diskpart",
                 "cryptsetup",
+                // PowerShell
+                "format-volume",
+                "clear-disk",
+                "initialize-disk",
+                "remove-partition",
             ],
             destructive_patterns: vec![
                 DestructivePattern {
@@ -69,6 +310,26 @@ impl CommandAnalyzer {
                     requires_flags: vec!["-r", "-rf", "-fr", "--recursive"],
                     description: "recursive file deletion",
                 },
+                DestructivePattern {
+                    command: "remove-item",
+                    requires_flags: vec!["-r"],
+                    description: "recursive file deletion",
+                },
+                DestructivePattern {
+                    command: "ri",
+                    requires_flags: vec!["-r"],
+                    description: "recursive file deletion",
+                },
+                DestructivePattern {
+                    command: "del",
+                    requires_flags: vec!["-r"],
+                    description: "recursive file deletion",
+                },
+                DestructivePattern {
+                    command: "rd",
+                    requires_flags: vec!["-r"],
+                    description: "recursive file deletion",
+                },
                 DestructivePattern {
                     command: "dd",
                     requires_flags: vec!["of="],
@@ -122,7 +383,7 @@ impl CommandAnalyzer {
         }
 
         // Split command into tokens, handling quotes and escapes
-        let tokens = self.tokenize(&normalized);
+        let tokens = self.dialect.tokenize(&normalized);
 
         // Check each token sequence for destructive commands
         self.contains_destructive_command(&tokens)
@@ -138,8 +399,23 @@ impl CommandAnalyzer {
     }
 
     fn has_dangerous_redirect(&self, command: &str) -> bool {
-        // Detect redirects to /dev devices (except /dev/null, /dev/zero, /dev/stdout, /dev/stderr)
-        if command.contains("> /dev/") || command.contains(">> /dev/") {
+        // PowerShell (and cmd) write to raw disks through \\.\PhysicalDriveN
+        if command.contains(r"\\.\physicaldrive") {
+            return true;
+        }
+
+        // Detect redirects to /dev devices (except /dev/null, /dev/zero, /dev/stdout, /dev/stderr).
+        // Nushell writes files with `save` rather than redirects.
+        let writes_device = [
+            "> /dev/",
+            ">> /dev/",
+            "save /dev/",
+            "save -f /dev/",
+            "save --force /dev/",
+        ]
+        .iter()
+        .any(|redirect| command.contains(redirect));
+        if writes_device {
             let safe_devices = [
                 "/dev/null",
                 "/dev/zero",
@@ -174,65 +450,31 @@ impl CommandAnalyzer {
             return true;
         }
 
-        false
-    }
-
-    fn tokenize(&self, command: &str) -> Vec<String> {
-        let mut tokens = Vec::new();
-        let mut current_token = String::new();
-        let mut in_single_quote = false;
-        let mut in_double_quote = false;
-        let mut escape_next = false;
-        let chars: Vec<char> = command.chars().collect();
-        let mut i = 0;
-
-        while i < chars.len() {
-            let c = chars[i];
-
-            if escape_next {
-                current_token.push(c);
-                escape_next = false;
-                i += 1;
-                continue;
-            }
-
-            match c {
-                '\\' if !in_single_quote => {
-                    escape_next = true;
-                }
-                '\'' if !in_double_quote => {
-                    in_single_quote = !in_single_quote;
-                }
-                '"' if !in_single_quote => {
-                    in_double_quote = !in_double_quote;
-                }
-                ' ' | '\t' | '\n' if !in_single_quote && !in_double_quote => {
-                    if !current_token.is_empty() {
-                        tokens.push(current_token.clone());
-                        current_token.clear();
-                    }
-                }
-                ';' | '|' | '&' if !in_single_quote && !in_double_quote => {
-                    if !current_token.is_empty() {
-                        tokens.push(current_token.clone());
-                        current_token.clear();
-                    }
-                    // Add separator as token
-                    tokens.push(c.to_string());
-                }
-                _ => {
-                    current_token.push(c);
-                }
-            }
-
-            i += 1;
+        // PowerShell runs base64 given with -EncodedCommand (-e, -ec or any
+        // prefix), or decoded and piped to Invoke-Expression
+        let runs_encoded = self
+            .dialect
+            .tokenize(command)
+            .iter()
+            .skip_while(|token| {
+                let program = token.rsplit(['/', '\\']).next().unwrap_or(token);
+                !matches!(program.trim_end_matches(".exe"), "powershell" | "pwsh")
+            })
+            .skip(1)
+            .take_while(|token| !matches!(token.as_str(), "|" | ";" | "&"))
+            .any(|arg| {
+                arg == "-ec" || (arg.len() >= 2 && "-encodedcommand".starts_with(arg.as_str()))
+            });
+        if runs_encoded {
+            return true;
         }
-
-        if !current_token.is_empty() {
-            tokens.push(current_token);
+        if command.contains("frombase64string")
+            && (command.contains("iex") || command.contains("invoke-expression"))
+        {
+            return true;
         }
 
-        tokens
+        false
     }
 
     fn contains_destructive_command(&self, tokens: &[String]) -> bool {
@@ -252,10 +494,11 @@ impl CommandAnalyzer {
             }
 
             // Extract command name (remove path if present)
+            let token = self.dialect.program(token);
             let command_name = if token.starts_with("./") || token.starts_with('/') {
                 token.split('/').last().unwrap_or(token)
             } else {
-                token.as_str()
+                token
             };
 
             // Skip sudo/doas prefixes
@@ -377,7 +620,7 @@ ui:
     async fn test_executor_initialization() {
         let executor = create_test_executor().await;
         assert!(
-            executor._config.execution.confirm_destructive,
+            executor.config.execution.confirm_destructive,
             "Executor should have destructive command confirmation enabled"
         );
     }
@@ -694,4 +937,52 @@ ui:
             "Should detect 'rm -rf' in pipe chain"
         );
     }
+
+    #[tokio::test]
+    async fn test_is_destructive_per_dialect() {
+        let executor = create_test_executor().await;
+        let pwsh = ShellDialect::PowerShell;
+
+        assert!(
+            executor.is_destructive_in("Remove-Item C:\\build -Recurse -Force", pwsh),
+            "Should detect recursive Remove-Item"
+        );
+        assert!(
+            executor.is_destructive_in("gci *.tmp | ri -r", pwsh),
+            "Should detect the ri alias in a pipeline"
+        );
+        assert!(
+            executor.is_destructive_in("Get-Disk 1 | Clear-Disk -RemoveData", pwsh),
+            "Should detect Clear-Disk"
+        );
+        assert!(
+            executor.is_destructive_in("pwsh -NoProfile -enc ZQBjAGgAbwA=", pwsh),
+            "Should detect encoded commands"
+        );
+        assert!(
+            executor.is_destructive_in("rm -`r C:\\build", pwsh),
+            "Should see through backtick escapes"
+        );
+        assert!(
+            !executor.is_destructive_in("Remove-Item C:\\build\\out.log", pwsh),
+            "Removing a single file is not destructive"
+        );
+        assert!(
+            !executor.is_destructive_in("Get-ChildItem -Path C:\\ -Filter *.log", pwsh),
+            "Listing files is not destructive"
+        );
+
+        assert!(
+            executor.is_destructive_in("^rm -r target", ShellDialect::Nu),
+            "Should detect the external rm called with ^"
+        );
+        assert!(
+            executor.is_destructive_in("0x[00] | save -f /dev/sda", ShellDialect::Nu),
+            "Should detect saving to a disk device"
+        );
+        assert!(
+            executor.is_destructive_in(r"rm -r 'it\'s here'", ShellDialect::Fish),
+            "Should handle fish's escaped single quotes"
+        );
+    }
 }
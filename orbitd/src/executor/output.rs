@@ -0,0 +1,69 @@
+// Output capture shared by every way orbitd runs a command
+//
+// Host, container and sudo runs all build a `tokio::process::Command` and
+// hand it to `capture`, so clients get the same output shape whichever
+// target ran the command.
+
+use anyhow::{Context as _, Result};
+use serde::{Deserialize, Serialize};
+use std::process::Stdio;
+use std::time::{Duration, Instant};
+use tokio::io::AsyncWriteExt;
+
+/// What a finished command printed and how it exited
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ExecutionOutput {
+    /// -1 when the process was killed by a signal
+    pub exit_code: i32,
+    pub stdout: String,
+    pub stderr: String,
+    pub duration_ms: u64,
+}
+
+impl ExecutionOutput {
+    pub fn success(&self) -> bool {
+        self.exit_code == 0
+    }
+}
+
+/// Run `command` to completion and collect its output
+///
+/// `stdin` is written and then closed, so nothing waits on input that will
+/// never come. The process is killed if it outlives `timeout`.
+pub async fn capture(
+    mut command: tokio::process::Command,
+    stdin: Option<&[u8]>,
+    timeout: Duration,
+) -> Result<ExecutionOutput> {
+    let program = command
+        .as_std()
+        .get_program()
+        .to_string_lossy()
+        .into_owned();
+    let started = Instant::now();
+
+    let mut child = command
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true)
+        .spawn()
+        .with_context(|| format!("Failed to start {}", program))?;
+
+    let mut pipe = child.stdin.take().context("stdin unavailable")?;
+    if let Some(input) = stdin {
+        pipe.write_all(input).await?;
+    }
+    drop(pipe);
+
+    let output = tokio::time::timeout(timeout, child.wait_with_output())
+        .await
+        .with_context(|| format!("{} timed out after {}s", program, timeout.as_secs()))??;
+
+    Ok(ExecutionOutput {
+        exit_code: output.status.code().unwrap_or(-1),
+        stdout: String::from_utf8_lossy(&output.stdout).into_owned(),
+        stderr: String::from_utf8_lossy(&output.stderr).into_owned(),
+        duration_ms: started.elapsed().as_millis() as u64,
+    })
+}
@@ -0,0 +1,251 @@
+// Risk tiers and explain-before-execute
+//
+// Every suggested command gets a tier from what it does:
+//
+// - High: destructive (recursive deletes, disk writes) or needs root
+// - Medium: modifies files, or reaches URLs, other hosts or system services
+// - Low: everything else, e.g. listing files or reading logs
+//
+// Tiers listed in `execution.explain_tiers` (medium and high by default) get
+// a one-line explanation and the resources the command affects, shown with
+// the approval prompt. The explanation comes from the provider; the
+// resources are read from the command itself, so they stay accurate
+// whatever the provider says. Low-risk commands skip the provider call.
+
+use serde::{Deserialize, Serialize};
+
+use super::dry_run::DryRun;
+use super::{elevation, CommandAnalyzer};
+use crate::context::ShellDialect;
+use crate::providers::{split_stages, summarize};
+
+/// Resources listed per explanation
+const MAX_RESOURCES: usize = 20;
+
+/// How much harm a command can do
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RiskTier {
+    Low,
+    Medium,
+    High,
+}
+
+impl std::fmt::Display for RiskTier {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Low => write!(f, "low"),
+            Self::Medium => write!(f, "medium"),
+            Self::High => write!(f, "high"),
+        }
+    }
+}
+
+/// What a command does and touches, shown before the user approves it
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Explanation {
+    pub risk: RiskTier,
+    /// One line on what the command does
+    pub summary: String,
+    /// Files, URLs, hosts and services it affects, e.g. `file target/app`
+    pub resources: Vec<String>,
+}
+
+impl Explanation {
+    /// Explanation put together without a provider, from the table of
+    /// common programs
+    pub fn describe(
+        command: &str,
+        risk: RiskTier,
+        dialect: ShellDialect,
+        dry_run: Option<&DryRun>,
+    ) -> Self {
+        Self {
+            risk,
+            summary: summarize(command),
+            resources: affected_resources(command, dialect, dry_run),
+        }
+    }
+}
+
+/// Tier of `command`, written for the shell of `dialect`; `dry_run` is
+/// what it would do to files, if known
+pub fn assess(command: &str, dialect: ShellDialect, dry_run: Option<&DryRun>) -> RiskTier {
+    if CommandAnalyzer::new(dialect).is_destructive(command)
+        || elevation::analyze(command).is_some()
+    {
+        RiskTier::High
+    } else if dry_run.is_some_and(|d| !d.effects.is_empty())
+        || !affected_resources(command, dialect, None).is_empty()
+    {
+        RiskTier::Medium
+    } else {
+        RiskTier::Low
+    }
+}
+
+/// What `command` touches beyond its own process: files it modifies (from
+/// `dry_run`) or writes through redirects and `tee`, URLs, remote hosts and
+/// system services
+pub fn affected_resources(
+    command: &str,
+    dialect: ShellDialect,
+    dry_run: Option<&DryRun>,
+) -> Vec<String> {
+    let mut resources = Vec::new();
+    let mut add = |resource: String| {
+        if !resources.contains(&resource) {
+            resources.push(resource);
+        }
+    };
+
+    for effect in dry_run.iter().flat_map(|d| &d.effects) {
+        for path in &effect.paths {
+            add(format!("file {}", path.display()));
+        }
+        for operand in &effect.unresolved {
+            add(format!("file {} (not resolved)", operand));
+        }
+    }
+
+    for stage in split_stages(command) {
+        let words: Vec<String> = dialect
+            .tokenize(&stage)
+            .into_iter()
+            .skip_while(|w| w.contains('=') && !w.starts_with('-'))
+            .skip_while(|w| matches!(w.as_str(), "sudo" | "doas"))
+            .collect();
+        let Some(program) = words.first().map(|w| dialect.program(w)) else {
+            continue;
+        };
+        let args = &words[1..];
+
+        // Operands, without flags and redirects
+        let mut operands = Vec::new();
+        let mut redirect = false;
+        for word in args {
+            let target = if redirect {
+                Some(word.as_str())
+            } else {
+                word.strip_prefix(">>").or_else(|| word.strip_prefix('>'))
+            };
+            redirect = matches!(word.as_str(), ">" | ">>");
+            // `>/dev/null` and `2>&1` write no file
+            let writes_file = |target: &str| {
+                !target.is_empty() && !target.starts_with("/dev/") && !target.starts_with('&')
+            };
+            match target {
+                Some(target) if writes_file(target) => add(format!("file {}", target)),
+                Some(_) => {}
+                None if word.contains("://") => add(format!("url {}", word)),
+                None if !word.starts_with('-') => operands.push(word),
+                None => {}
+            }
+        }
+
+        let mut operands = operands.into_iter();
+        match program {
+            "tee" => operands.for_each(|file| add(format!("file {}", file))),
+            "ssh" => {
+                if let Some(host) = ssh_host(args) {
+                    add(format!("host {}", host));
+                }
+            }
+            "scp" | "rsync" | "sftp" => {
+                for host in operands.filter_map(|a| remote_host(a)) {
+                    add(format!("host {}", host));
+                }
+            }
+            "systemctl" => {
+                let mut operands = operands.skip_while(|a| !SERVICE_ACTIONS.contains(&a.as_str()));
+                if operands.next().is_some() {
+                    operands.for_each(|unit| add(format!("service {}", unit)));
+                }
+            }
+            "service" => {
+                if let Some(name) = operands.next() {
+                    add(format!("service {}", name));
+                }
+            }
+            _ => {}
+        }
+    }
+
+    if resources.len() > MAX_RESOURCES {
+        let more = resources.len() - MAX_RESOURCES;
+        resources.truncate(MAX_RESOURCES);
+        resources.push(format!("...and {} more", more));
+    }
+    resources
+}
+
+/// systemctl subcommands that change a unit
+const SERVICE_ACTIONS: &[&str] = &[
+    "start", "stop", "restart", "reload", "enable", "disable", "mask", "unmask", "kill",
+];
+
+/// ssh options that take a value
+const SSH_VALUE_FLAGS: &[&str] = &["-p", "-i", "-l", "-o", "-F", "-J", "-L", "-R", "-D"];
+
+/// The host `ssh` connects to: its first operand
+fn ssh_host(args: &[String]) -> Option<&str> {
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        if SSH_VALUE_FLAGS.contains(&arg.as_str()) {
+            args.next();
+        } else if !arg.starts_with('-') {
+            return Some(arg.rsplit('@').next().unwrap_or(arg));
+        }
+    }
+    None
+}
+
+/// The host in an `scp`/`rsync` operand like `user@host:path`
+fn remote_host(operand: &str) -> Option<&str> {
+    if operand.contains("://") {
+        return None;
+    }
+    let (host, _) = operand.split_once(':')?;
+    let host = host.rsplit('@').next().unwrap_or(host);
+    // `C:\Users` and `./a:b` are local paths
+    (host.len() > 1 && !host.contains('/')).then_some(host)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tiers() {
+        let posix = ShellDialect::Posix;
+        assert_eq!(assess("ls -la | head", posix, None), RiskTier::Low);
+        assert_eq!(assess("cat Cargo.toml", posix, None), RiskTier::Low);
+        assert_eq!(assess("echo hi > notes.txt", posix, None), RiskTier::Medium);
+        assert_eq!(
+            assess("curl -O https://example.com/a.tgz", posix, None),
+            RiskTier::Medium
+        );
+        assert_eq!(assess("rm -rf target", posix, None), RiskTier::High);
+        assert_eq!(assess("sudo apt install jq", posix, None), RiskTier::High);
+        assert!(RiskTier::Low < RiskTier::Medium && RiskTier::Medium < RiskTier::High);
+    }
+
+    #[test]
+    fn test_affected_resources() {
+        let resources = |command| affected_resources(command, ShellDialect::Posix, None);
+        assert_eq!(
+            resources("curl -s https://example.com/install | tee install.sh >/dev/null"),
+            vec!["url https://example.com/install", "file install.sh"]
+        );
+        assert_eq!(
+            resources("rsync -a dist/ deploy@web-1:/srv/app && ssh -p 2222 deploy@web-1 'sudo systemctl restart app'"),
+            vec!["host web-1"]
+        );
+        assert_eq!(
+            resources("sudo systemctl restart nginx php-fpm; service redis stop"),
+            vec!["service nginx", "service php-fpm", "service redis"]
+        );
+        assert_eq!(resources("make >> build.log 2>&1"), vec!["file build.log"]);
+        assert!(resources("grep -rn TODO src").is_empty());
+    }
+}
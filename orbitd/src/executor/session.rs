@@ -0,0 +1,297 @@
+// Running commands in Pulsar sessions
+//
+// pulsar-daemon owns terminal sessions (local shells and SSH connections)
+// and exposes them over a JSON-RPC Unix socket. A command runs in a session
+// by being typed into its terminal between two markers:
+//
+//    printf '\n__ORBIT_%s_BEGIN__\n' <id>; <command>
+//    printf '\n__ORBIT_%s_END_%s__\n' <id> "$?"
+//
+// The terminal echoes both lines, but the echo holds the printf format
+// rather than the expanded markers, so the output is whatever arrives
+// between the expanded ones. It is what the user would see: stdout and
+// stderr interleaved. The session's shell must be POSIX-compatible.
+//
+// pulsar-daemon hands terminal output to whichever client reads it first,
+// so a desktop window attached to the session doesn't see the output of a
+// command run this way.
+
+use anyhow::{anyhow, bail, Context as _, Result};
+use base64::{engine::general_purpose, Engine as _};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::unix::{OwnedReadHalf, OwnedWriteHalf};
+use tokio::net::UnixStream;
+use uuid::Uuid;
+
+use super::output::ExecutionOutput;
+use crate::context::remote::{parse_probe, PROBE};
+use crate::context::Context;
+
+/// How long reading a session's context may take
+const PROBE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// A Pulsar terminal session
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PulsarSession {
+    pub id: Uuid,
+    pub name: String,
+    /// SSH host; None for local sessions
+    pub host: Option<String>,
+}
+
+/// Client for pulsar-daemon's IPC socket
+pub struct PulsarClient {
+    socket_path: PathBuf,
+}
+
+impl PulsarClient {
+    pub fn new(socket_path: PathBuf) -> Self {
+        Self { socket_path }
+    }
+
+    /// Where pulsar-daemon listens unless configured otherwise
+    pub fn default_socket() -> Option<PathBuf> {
+        Some(dirs::config_dir()?.join("orbit").join("pulsar.sock"))
+    }
+
+    async fn connect(&self) -> Result<Connection> {
+        let stream = UnixStream::connect(&self.socket_path)
+            .await
+            .with_context(|| format!("Pulsar daemon is not reachable at {:?}", self.socket_path))?;
+        let (reader, writer) = stream.into_split();
+        Ok(Connection {
+            reader: BufReader::new(reader),
+            writer,
+        })
+    }
+
+    pub async fn sessions(&self) -> Result<Vec<PulsarSession>> {
+        let result = self
+            .connect()
+            .await?
+            .call("list_sessions", json!({}))
+            .await?;
+        let sessions = result["sessions"].as_array().cloned().unwrap_or_default();
+
+        Ok(sessions
+            .iter()
+            .filter_map(|session| {
+                Some(PulsarSession {
+                    id: session["id"].as_str()?.parse().ok()?,
+                    name: session["name"].as_str().unwrap_or_default().to_string(),
+                    host: session["session_type"]["Ssh"]["host"]
+                        .as_str()
+                        .map(str::to_string),
+                })
+            })
+            .collect())
+    }
+
+    pub async fn session(&self, id: Uuid) -> Result<PulsarSession> {
+        self.sessions()
+            .await?
+            .into_iter()
+            .find(|session| session.id == id)
+            .ok_or_else(|| anyhow!("No Pulsar session {}", id))
+    }
+
+    /// Context of the shell in session `id`, read by running `PROBE` there
+    pub async fn context(&self, id: Uuid) -> Result<Context> {
+        let session = self.session(id).await?;
+        let probe = self.run(id, PROBE, PROBE_TIMEOUT).await?;
+        parse_probe(session.host.as_deref(), &probe.stdout)
+            .with_context(|| format!("Couldn't read the context of session '{}'", session.name))
+    }
+
+    /// Type `command` into session `id` and wait for it to finish
+    ///
+    /// On timeout the command is left running in the session.
+    pub async fn run(&self, id: Uuid, command: &str, timeout: Duration) -> Result<ExecutionOutput> {
+        if command.contains('\n') {
+            bail!("Multi-line commands can't be run in a Pulsar session");
+        }
+
+        let marker = Uuid::new_v4().simple().to_string();
+        // The leading spaces keep these lines out of shell history
+        // (HISTCONTROL=ignorespace, HIST_IGNORE_SPACE)
+        let input = format!(
+            " printf '\\n__ORBIT_%s_BEGIN__\\n' {m}; {command}\n \
+             printf '\\n__ORBIT_%s_END_%s__\\n' {m} \"$?\"\n",
+            m = marker,
+            command = command,
+        );
+
+        let mut connection = self.connect().await?;
+        let started = Instant::now();
+        connection
+            .call(
+                "send_input",
+                json!({
+                    "session_id": id,
+                    "data": general_purpose::STANDARD.encode(input),
+                }),
+            )
+            .await?;
+
+        let mut received = Vec::new();
+        loop {
+            let remaining = timeout
+                .checked_sub(started.elapsed())
+                .filter(|left| !left.is_zero())
+                .ok_or_else(|| anyhow!("Command timed out after {}s", timeout.as_secs()))?;
+            let chunk = tokio::time::timeout(
+                remaining,
+                connection.call(
+                    "receive_output",
+                    json!({ "session_id": id, "timeout_ms": remaining.as_millis() as u64 }),
+                ),
+            )
+            .await
+            .map_err(|_| anyhow!("Command timed out after {}s", timeout.as_secs()))??;
+
+            let data = general_purpose::STANDARD
+                .decode(chunk["data"].as_str().unwrap_or_default())
+                .context("Pulsar returned malformed output")?;
+            if data.is_empty() {
+                bail!("Session {} closed before the command finished", id);
+            }
+            received.extend_from_slice(&data);
+
+            if let Some((stdout, exit_code)) = extract(&String::from_utf8_lossy(&received), &marker)
+            {
+                return Ok(ExecutionOutput {
+                    exit_code,
+                    stdout,
+                    stderr: String::new(),
+                    duration_ms: started.elapsed().as_millis() as u64,
+                });
+            }
+        }
+    }
+}
+
+/// The output between the expanded markers and the exit code in the end
+/// marker, once both have arrived
+fn extract(terminal: &str, marker: &str) -> Option<(String, i32)> {
+    let begin = format!("__ORBIT_{}_BEGIN__", marker);
+    let end = format!("__ORBIT_{}_END_", marker);
+
+    let after_begin = terminal.find(&begin)? + begin.len();
+    let body = &terminal[after_begin..];
+    let end_at = body.find(&end)?;
+    let tail = &body[end_at + end.len()..];
+    let exit_code = tail[..tail.find("__")?].parse().ok()?;
+
+    let output = strip_escapes(&body[..end_at])
+        .replace("\r\n", "\n")
+        .replace('\r', "");
+    let output = output.strip_prefix('\n').unwrap_or(&output);
+    let output = output.strip_suffix('\n').unwrap_or(output);
+    // The echoed second line comes before the command's output when the
+    // terminal echoes faster than the command starts
+    let output = output
+        .lines()
+        .filter(|line| !line.contains("__ORBIT_%s_END_%s__"))
+        .collect::<Vec<_>>()
+        .join("\n");
+
+    Some((output, exit_code))
+}
+
+/// Drop terminal control sequences (colours, bracketed paste toggles,
+/// window titles) that interactive shells mix into their output
+fn strip_escapes(text: &str) -> String {
+    let mut plain = String::with_capacity(text.len());
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c != '\x1b' {
+            plain.push(c);
+            continue;
+        }
+        match chars.next() {
+            // CSI: parameters, then a final byte in @..~
+            Some('[') => {
+                for c in chars.by_ref() {
+                    if ('@'..='~').contains(&c) {
+                        break;
+                    }
+                }
+            }
+            // OSC: ends with BEL or ESC \
+            Some(']') => {
+                while let Some(c) = chars.next() {
+                    if c == '\x07' {
+                        break;
+                    }
+                    if c == '\x1b' && chars.peek() == Some(&'\\') {
+                        chars.next();
+                        break;
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+    plain
+}
+
+struct Connection {
+    reader: BufReader<OwnedReadHalf>,
+    writer: OwnedWriteHalf,
+}
+
+impl Connection {
+    async fn call(&mut self, method: &str, params: Value) -> Result<Value> {
+        let id = Uuid::new_v4().to_string();
+        let mut request = serde_json::to_vec(&json!({
+            "id": id,
+            "method": method,
+            "params": params,
+        }))?;
+        request.push(b'\n');
+        self.writer.write_all(&request).await?;
+
+        let mut line = String::new();
+        if self.reader.read_line(&mut line).await? == 0 {
+            bail!("Pulsar daemon closed the connection");
+        }
+        let mut response: Value =
+            serde_json::from_str(&line).context("Invalid response from Pulsar daemon")?;
+
+        if let Some(error) = response.get("error") {
+            bail!(
+                "Pulsar {} failed: {}",
+                method,
+                error["message"].as_str().unwrap_or("unknown error")
+            );
+        }
+        Ok(response["result"].take())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_extracts_output_between_markers() {
+        let marker = "4f1c";
+        let terminal = "$  printf '\\n__ORBIT_%s_BEGIN__\\n' 4f1c; ls\r\n\
+                        \r\n__ORBIT_4f1c_BEGIN__\r\n\
+                        \x1b[?2004lCargo.toml\r\n\x1b[01;34msrc\x1b[0m\r\n\
+                        $  printf '\\n__ORBIT_%s_END_%s__\\n' 4f1c \"$?\"\r\n\
+                        \r\n__ORBIT_4f1c_END_2__\r\n$ ";
+        let (output, exit_code) = extract(terminal, marker).unwrap();
+        assert_eq!(output, "Cargo.toml\nsrc");
+        assert_eq!(exit_code, 2);
+
+        // Still waiting for the end marker
+        assert!(extract("\r\n__ORBIT_4f1c_BEGIN__\r\nCargo.toml\r\n", marker).is_none());
+        // The echo alone doesn't count
+        assert!(extract("printf '\\n__ORBIT_%s_END_%s__\\n' 4f1c \"$?\"", marker).is_none());
+    }
+}
@@ -0,0 +1,272 @@
+// Where an approved command runs
+//
+// Besides the host shell, a suggestion can run inside a container that is
+// already up (`docker exec`) or in a throwaway container started from an
+// image (`docker run --rm`). Throwaway containers get the working directory
+// mounted at /workspace, so commands that read or write project files behave
+// as they would on the host. Docker and Podman take the same arguments for
+// everything used here; when a target doesn't name a runtime, the first one
+// on PATH is used.
+
+use anyhow::{bail, Context as _, Result};
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+use crate::context::ShellDialect;
+
+/// Mount point of the working directory inside throwaway containers
+pub const WORKSPACE: &str = "/workspace";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ContainerRuntime {
+    Docker,
+    Podman,
+}
+
+impl ContainerRuntime {
+    pub fn program(self) -> &'static str {
+        match self {
+            Self::Docker => "docker",
+            Self::Podman => "podman",
+        }
+    }
+
+    /// The first runtime found on PATH, Docker before Podman
+    pub fn detect() -> Option<Self> {
+        let path = std::env::var_os("PATH")?;
+        [Self::Docker, Self::Podman].into_iter().find(|runtime| {
+            std::env::split_paths(&path).any(|dir| dir.join(runtime.program()).is_file())
+        })
+    }
+
+    /// Running containers, for picking a target
+    pub async fn running_containers(self) -> Result<Vec<ContainerInfo>> {
+        let output = tokio::process::Command::new(self.program())
+            .args(["ps", "--format", "{{.Names}}\t{{.Image}}\t{{.Status}}"])
+            .output()
+            .await
+            .with_context(|| format!("Failed to run {} ps", self.program()))?;
+        if !output.status.success() {
+            bail!(
+                "{} ps failed: {}",
+                self.program(),
+                String::from_utf8_lossy(&output.stderr).trim()
+            );
+        }
+
+        Ok(String::from_utf8_lossy(&output.stdout)
+            .lines()
+            .filter_map(|line| {
+                let mut fields = line.split('\t');
+                Some(ContainerInfo {
+                    name: fields.next().filter(|n| !n.is_empty())?.to_string(),
+                    image: fields.next().unwrap_or_default().to_string(),
+                    status: fields.next().unwrap_or_default().to_string(),
+                })
+            })
+            .collect())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ContainerInfo {
+    pub name: String,
+    pub image: String,
+    pub status: String,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case", tag = "kind")]
+pub enum ExecutionTarget {
+    /// The user's shell on this machine
+    #[default]
+    Host,
+    /// A running container, by name or id. The command runs in the
+    /// container's own working directory.
+    Container {
+        name: String,
+        #[serde(default)]
+        runtime: Option<ContainerRuntime>,
+    },
+    /// A fresh container from `image`, removed when the command exits
+    Image {
+        image: String,
+        #[serde(default)]
+        runtime: Option<ContainerRuntime>,
+    },
+    /// A Pulsar terminal session, usually an SSH connection. Runs through
+    /// pulsar-daemon rather than as a local process; see executor::session.
+    Session { id: uuid::Uuid },
+}
+
+impl ExecutionTarget {
+    fn runtime(&self) -> Result<Option<ContainerRuntime>> {
+        match self {
+            Self::Host | Self::Session { .. } => Ok(None),
+            Self::Container { runtime, .. } | Self::Image { runtime, .. } => runtime
+                .or_else(ContainerRuntime::detect)
+                .map(Some)
+                .context("Neither docker nor podman was found on PATH"),
+        }
+    }
+
+    /// The process that runs `command` on this target, started from `cwd`
+    ///
+    /// `name` labels throwaway containers so they can be removed if the
+    /// command is abandoned. On the host the command runs in the shell of
+    /// `dialect`; containers only promise `sh`.
+    pub fn command(
+        &self,
+        command: &str,
+        cwd: &Path,
+        name: &str,
+        dialect: ShellDialect,
+    ) -> Result<tokio::process::Command> {
+        if let Self::Session { id } = self {
+            bail!("Session {} has no local process; run it through pulsar-daemon", id);
+        }
+        if !cwd.is_dir() {
+            bail!("Working directory {} does not exist", cwd.display());
+        }
+
+        let mut process = match (self, self.runtime()?) {
+            (
+                Self::Container {
+                    name: container, ..
+                },
+                Some(runtime),
+            ) => {
+                let mut process = tokio::process::Command::new(runtime.program());
+                process.args(["exec", "-i", container.as_str(), "sh", "-c"]);
+                process
+            }
+            (Self::Image { image, .. }, Some(runtime)) => {
+                let cwd = cwd
+                    .canonicalize()
+                    .with_context(|| format!("Failed to resolve {}", cwd.display()))?;
+                let cwd = cwd
+                    .to_str()
+                    .context("Working directory is not valid UTF-8")?;
+                if cwd.contains(':') {
+                    bail!(
+                        "Can't mount {} into a container: the path contains ':'",
+                        cwd
+                    );
+                }
+
+                let mut process = tokio::process::Command::new(runtime.program());
+                process.args(["run", "--rm", "-i", "--name", name]);
+                process.args(["-v", &format!("{}:{}", cwd, WORKSPACE), "-w", WORKSPACE]);
+                process.args([image.as_str(), "sh", "-c"]);
+                process
+            }
+            _ => {
+                let (program, args) = dialect.invocation();
+                let mut process = tokio::process::Command::new(program);
+                process.current_dir(cwd);
+                process.args(args);
+                process
+            }
+        };
+        process.arg(command);
+        Ok(process)
+    }
+
+    /// Force-remove a throwaway container left behind by an abandoned run
+    pub async fn cleanup(&self, name: &str) {
+        if let (Self::Image { .. }, Ok(Some(runtime))) = (self, self.runtime()) {
+            let _ = tokio::process::Command::new(runtime.program())
+                .args(["rm", "-f", name])
+                .output()
+                .await;
+        }
+    }
+}
+
+impl std::fmt::Display for ExecutionTarget {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Host => f.write_str("host"),
+            Self::Container { name, .. } => write!(f, "container {}", name),
+            Self::Image { image, .. } => write!(f, "image {}", image),
+            Self::Session { id } => write!(f, "session {}", id),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(process: &tokio::process::Command) -> Vec<String> {
+        std::iter::once(process.as_std().get_program())
+            .chain(process.as_std().get_args())
+            .map(|a| a.to_string_lossy().into_owned())
+            .collect()
+    }
+
+    #[test]
+    fn test_builds_target_commands() {
+        let dir = tempfile::tempdir().unwrap();
+        let cwd = dir.path().canonicalize().unwrap();
+
+        let host = ExecutionTarget::Host
+            .command("ls | wc -l", &cwd, "orbit-run", ShellDialect::Posix)
+            .unwrap();
+        assert_eq!(args(&host), ["sh", "-c", "ls | wc -l"]);
+
+        let fish = ExecutionTarget::Host
+            .command("ls; and echo done", &cwd, "orbit-run", ShellDialect::Fish)
+            .unwrap();
+        assert_eq!(args(&fish), ["fish", "-c", "ls; and echo done"]);
+
+        let container = ExecutionTarget::Container {
+            name: "web".to_string(),
+            runtime: Some(ContainerRuntime::Podman),
+        };
+        assert_eq!(
+            args(&container.command("env", &cwd, "orbit-run", ShellDialect::Fish).unwrap()),
+            ["podman", "exec", "-i", "web", "sh", "-c", "env"]
+        );
+
+        let image = ExecutionTarget::Image {
+            image: "alpine:3".to_string(),
+            runtime: Some(ContainerRuntime::Docker),
+        };
+        let mount = format!("{}:{}", cwd.display(), WORKSPACE);
+        assert_eq!(
+            args(&image.command("ls", &cwd, "orbit-run", ShellDialect::Posix).unwrap()),
+            [
+                "docker",
+                "run",
+                "--rm",
+                "-i",
+                "--name",
+                "orbit-run",
+                "-v",
+                &mount,
+                "-w",
+                WORKSPACE,
+                "alpine:3",
+                "sh",
+                "-c",
+                "ls"
+            ]
+        );
+
+        assert!(ExecutionTarget::Host
+            .command("ls", &cwd.join("missing"), "orbit-run", ShellDialect::Posix)
+            .is_err());
+    }
+
+    #[test]
+    fn test_target_defaults_to_host() {
+        let target: ExecutionTarget = serde_json::from_str(r#"{"kind":"host"}"#).unwrap();
+        assert_eq!(target, ExecutionTarget::default());
+
+        let target: ExecutionTarget =
+            serde_json::from_str(r#"{"kind":"image","image":"node:20"}"#).unwrap();
+        assert_eq!(target.to_string(), "image node:20");
+    }
+}
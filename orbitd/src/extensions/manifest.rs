@@ -0,0 +1,240 @@
+// Extension manifests and permission grants
+//
+// Each extension lives in its own directory with an `extension.yaml`:
+//
+//   name: git-hints
+//   version: 0.2.0
+//   description: Suggests follow-up git commands
+//   module: git_hints.wasm
+//   permissions: [subscribe_events, read_context, emit_suggestion]
+//   events: [command_executed]
+//
+// Requested permissions only take effect once the user approves them.
+// Approvals are kept outside the extensions directory so an extension
+// can't ship its own grants.
+
+use anyhow::{bail, Context as _, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::{Path, PathBuf};
+
+/// Manifest file name inside an extension directory
+pub const MANIFEST_FILE: &str = "extension.yaml";
+
+/// Capabilities an extension can request
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Permission {
+    /// Receive the events listed in the manifest
+    SubscribeEvents,
+    /// Read the shell context (directory, git state, recent commands)
+    ReadContext,
+    /// Queue suggestions for the user
+    EmitSuggestion,
+}
+
+impl Permission {
+    /// Text shown when asking the user to approve the permission
+    pub fn prompt(self) -> &'static str {
+        match self {
+            Permission::SubscribeEvents => "See the commands you type and run",
+            Permission::ReadContext => {
+                "Read your working directory, git status and recent commands"
+            }
+            Permission::EmitSuggestion => "Show you command suggestions",
+        }
+    }
+}
+
+/// Daemon events an extension can subscribe to
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EventKind {
+    /// Input received from the shell, before classification
+    InputReceived,
+    /// A command finished and the shell reported the outcome
+    CommandExecuted,
+}
+
+/// Contents of `extension.yaml`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ExtensionManifest {
+    pub name: String,
+    pub version: String,
+    #[serde(default)]
+    pub description: Option<String>,
+    /// WebAssembly module, relative to the extension directory
+    #[serde(default = "default_module")]
+    pub module: PathBuf,
+    #[serde(default)]
+    pub permissions: BTreeSet<Permission>,
+    #[serde(default)]
+    pub events: BTreeSet<EventKind>,
+}
+
+fn default_module() -> PathBuf {
+    PathBuf::from("extension.wasm")
+}
+
+impl ExtensionManifest {
+    /// Parse and validate a manifest
+    pub fn from_yaml(yaml: &str) -> Result<Self> {
+        let manifest: Self = serde_yaml::from_str(yaml).context("Invalid extension manifest")?;
+        manifest.validate()?;
+        Ok(manifest)
+    }
+
+    /// Load the manifest from an extension directory
+    pub fn load(dir: &Path) -> Result<Self> {
+        let path = dir.join(MANIFEST_FILE);
+        let yaml = std::fs::read_to_string(&path)
+            .with_context(|| format!("Failed to read {}", path.display()))?;
+        Self::from_yaml(&yaml)
+    }
+
+    fn validate(&self) -> Result<()> {
+        let valid_name = !self.name.is_empty()
+            && self
+                .name
+                .chars()
+                .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-' || c == '_');
+        if !valid_name {
+            bail!(
+                "Extension name '{}' must be lowercase letters, digits, '-' or '_'",
+                self.name
+            );
+        }
+
+        if !self.events.is_empty() && !self.permissions.contains(&Permission::SubscribeEvents) {
+            bail!("Extension {} lists events without the subscribe_events permission", self.name);
+        }
+
+        // Keep the module inside the extension directory
+        let escapes = self.module.is_absolute()
+            || self
+                .module
+                .components()
+                .any(|c| matches!(c, std::path::Component::ParentDir));
+        if escapes {
+            bail!("Extension {} module must be a path inside its directory", self.name);
+        }
+
+        Ok(())
+    }
+}
+
+/// Permissions the user approved, per extension
+#[derive(Debug, Default)]
+pub struct Grants {
+    path: PathBuf,
+    granted: BTreeMap<String, BTreeSet<Permission>>,
+}
+
+impl Grants {
+    /// Load grants from `path`; a missing file means nothing is approved
+    pub fn load(path: PathBuf) -> Result<Self> {
+        let granted = if path.exists() {
+            let yaml = std::fs::read_to_string(&path)
+                .with_context(|| format!("Failed to read {}", path.display()))?;
+            serde_yaml::from_str(&yaml).context("Invalid extension grants file")?
+        } else {
+            BTreeMap::new()
+        };
+
+        Ok(Self { path, granted })
+    }
+
+    /// Requested permissions the user hasn't approved yet
+    pub fn missing(&self, manifest: &ExtensionManifest) -> Vec<Permission> {
+        let granted = self.granted.get(&manifest.name);
+        manifest
+            .permissions
+            .iter()
+            .filter(|p| !granted.is_some_and(|g| g.contains(p)))
+            .copied()
+            .collect()
+    }
+
+    /// Approve everything the manifest requests
+    pub fn grant(&mut self, manifest: &ExtensionManifest) -> Result<()> {
+        self.granted
+            .insert(manifest.name.clone(), manifest.permissions.clone());
+        self.save()
+    }
+
+    /// Withdraw all permissions from an extension
+    pub fn revoke(&mut self, name: &str) -> Result<bool> {
+        let removed = self.granted.remove(name).is_some();
+        if removed {
+            self.save()?;
+        }
+        Ok(removed)
+    }
+
+    fn save(&self) -> Result<()> {
+        if let Some(parent) = self.path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        pulsar_fs::write(&self.path, serde_yaml::to_string(&self.granted)?)
+            .with_context(|| format!("Failed to write {}", self.path.display()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    const MANIFEST: &str = r#"
+name: git-hints
+version: 0.2.0
+module: git_hints.wasm
+permissions: [subscribe_events, read_context, emit_suggestion]
+events: [command_executed]
+"#;
+
+    #[test]
+    fn test_manifest_validation() {
+        let manifest = ExtensionManifest::from_yaml(MANIFEST).unwrap();
+        assert_eq!(manifest.module, PathBuf::from("git_hints.wasm"));
+        assert!(manifest.events.contains(&EventKind::CommandExecuted));
+
+        assert!(ExtensionManifest::from_yaml("name: Bad Name\nversion: 1.0.0\n").is_err());
+        assert!(ExtensionManifest::from_yaml(
+            "name: sneaky\nversion: 1.0.0\nevents: [input_received]\n"
+        )
+        .is_err());
+        assert!(ExtensionManifest::from_yaml(
+            "name: escape\nversion: 1.0.0\nmodule: ../other/evil.wasm\n"
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_grants_persist_and_cover_new_permissions() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("extension-grants.yaml");
+        let manifest = ExtensionManifest::from_yaml(MANIFEST).unwrap();
+
+        // First version only asked to see events
+        let mut first = manifest.clone();
+        first.permissions = BTreeSet::from([Permission::SubscribeEvents]);
+
+        let mut grants = Grants::load(path.clone()).unwrap();
+        assert_eq!(grants.missing(&first), vec![Permission::SubscribeEvents]);
+        grants.grant(&first).unwrap();
+
+        // An update asking for more needs approval again
+        let mut grants = Grants::load(path.clone()).unwrap();
+        assert!(grants.missing(&first).is_empty());
+        assert_eq!(
+            grants.missing(&manifest),
+            vec![Permission::ReadContext, Permission::EmitSuggestion]
+        );
+
+        grants.grant(&manifest).unwrap();
+        assert!(grants.missing(&manifest).is_empty());
+        assert!(grants.revoke("git-hints").unwrap());
+        assert_eq!(Grants::load(path).unwrap().missing(&manifest).len(), 3);
+    }
+}
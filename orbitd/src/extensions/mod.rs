@@ -0,0 +1,570 @@
+// WebAssembly extensions
+//
+// Third-party extensions are WebAssembly modules run in a wasmtime
+// sandbox. They have no filesystem, network or clock access; the only way
+// out is through host functions, and each one is gated by a permission the
+// user approved (see `manifest`).
+//
+// Guest ABI (all strings are UTF-8 JSON or text in guest memory):
+//
+//   exports:
+//     memory
+//     orbit_alloc(len: i32) -> i32           buffer for the host to write into
+//     orbit_on_event(ptr: i32, len: i32)     an event the extension subscribed to
+//
+//   imports from "orbit":
+//     context_get(ptr: i32, cap: i32) -> i32 writes the context JSON if it fits,
+//                                            returns its length (read_context)
+//     suggest(ptr: i32, len: i32) -> i32     queues a suggestion (emit_suggestion)
+//     log(ptr: i32, len: i32)                debug log, always allowed
+//
+// Host functions return -1 when the permission wasn't granted. Each event
+// call runs with a fuel budget and a memory cap so a misbehaving extension
+// can't stall the daemon.
+
+pub mod manifest;
+
+pub use manifest::{EventKind, ExtensionManifest, Grants, Permission};
+
+use anyhow::{anyhow, Context as _, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeSet, VecDeque};
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex, RwLock};
+use tracing::{debug, info, warn};
+use wasmtime::{Caller, Engine, Extern, Instance, Linker, Module, Store, StoreLimits, StoreLimitsBuilder};
+
+use crate::config::ExtensionsConfig;
+use crate::context::Context;
+
+/// Suggestions kept for clients to collect
+const SUGGESTION_QUEUE_LIMIT: usize = 100;
+
+/// Longest suggestion or log line accepted from an extension
+const MAX_GUEST_STRING: usize = 4096;
+
+/// Event delivered to subscribed extensions
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ExtensionEvent {
+    InputReceived { input: String },
+    CommandExecuted { input: String, command: String, success: bool },
+}
+
+impl ExtensionEvent {
+    pub fn kind(&self) -> EventKind {
+        match self {
+            ExtensionEvent::InputReceived { .. } => EventKind::InputReceived,
+            ExtensionEvent::CommandExecuted { .. } => EventKind::CommandExecuted,
+        }
+    }
+}
+
+/// A suggestion emitted by an extension
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ExtensionSuggestion {
+    pub extension: String,
+    pub text: String,
+}
+
+/// Status of an installed extension, including what still needs approval
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ExtensionInfo {
+    pub name: String,
+    pub version: String,
+    pub description: Option<String>,
+    pub permissions: Vec<Permission>,
+    /// Permissions awaiting approval, with the text to show the user
+    pub pending: Vec<(Permission, String)>,
+    pub loaded: bool,
+    pub error: Option<String>,
+}
+
+/// Per-extension state visible to host functions
+struct HostState {
+    name: String,
+    permissions: BTreeSet<Permission>,
+    /// Context JSON for the event being delivered
+    context: Vec<u8>,
+    suggestions: Vec<String>,
+    limits: StoreLimits,
+}
+
+impl HostState {
+    fn allows(&self, permission: Permission) -> bool {
+        self.permissions.contains(&permission)
+    }
+}
+
+struct LoadedExtension {
+    manifest: ExtensionManifest,
+    store: Mutex<Store<HostState>>,
+    instance: Instance,
+}
+
+/// Loads extensions and delivers events to them
+pub struct ExtensionHost {
+    engine: Engine,
+    dir: PathBuf,
+    config: ExtensionsConfig,
+    grants: Mutex<Grants>,
+    loaded: RwLock<Vec<Arc<LoadedExtension>>>,
+    status: RwLock<Vec<ExtensionInfo>>,
+    suggestions: Mutex<VecDeque<ExtensionSuggestion>>,
+}
+
+impl ExtensionHost {
+    /// Load approved extensions from `dir` (created if missing)
+    pub fn new(dir: PathBuf, grants_path: PathBuf, config: &ExtensionsConfig) -> Result<Self> {
+        std::fs::create_dir_all(&dir)
+            .with_context(|| format!("Failed to create extensions directory {}", dir.display()))?;
+
+        let mut engine_config = wasmtime::Config::new();
+        engine_config.consume_fuel(true);
+        let engine = Engine::new(&engine_config).context("Failed to start WebAssembly engine")?;
+
+        let host = Self {
+            engine,
+            dir,
+            config: config.clone(),
+            grants: Mutex::new(Grants::load(grants_path)?),
+            loaded: RwLock::new(Vec::new()),
+            status: RwLock::new(Vec::new()),
+            suggestions: Mutex::new(VecDeque::new()),
+        };
+        host.reload();
+        Ok(host)
+    }
+
+    /// Re-scan the extensions directory
+    ///
+    /// Extensions with unapproved permissions are listed but not loaded.
+    pub fn reload(&self) -> Vec<ExtensionInfo> {
+        let mut dirs: Vec<PathBuf> = match std::fs::read_dir(&self.dir) {
+            Ok(entries) => entries.flatten().map(|e| e.path()).filter(|p| p.is_dir()).collect(),
+            Err(e) => {
+                warn!("Failed to read extensions directory {}: {}", self.dir.display(), e);
+                return Vec::new();
+            }
+        };
+        dirs.sort();
+
+        let grants = self.grants.lock().unwrap();
+        let mut loaded = Vec::new();
+        let mut status = Vec::new();
+
+        for dir in dirs {
+            let manifest = match ExtensionManifest::load(&dir) {
+                Ok(manifest) => manifest,
+                Err(e) => {
+                    warn!("Skipping extension {}: {:#}", dir.display(), e);
+                    continue;
+                }
+            };
+
+            let missing = grants.missing(&manifest);
+            let mut info = ExtensionInfo {
+                name: manifest.name.clone(),
+                version: manifest.version.clone(),
+                description: manifest.description.clone(),
+                permissions: manifest.permissions.iter().copied().collect(),
+                pending: missing.iter().map(|p| (*p, p.prompt().to_string())).collect(),
+                loaded: false,
+                error: None,
+            };
+
+            if missing.is_empty() {
+                match self.instantiate(&dir, manifest) {
+                    Ok(extension) => {
+                        debug!("Loaded extension {}", info.name);
+                        loaded.push(Arc::new(extension));
+                        info.loaded = true;
+                    }
+                    Err(e) => {
+                        warn!("Failed to load extension {}: {:#}", info.name, e);
+                        info.error = Some(format!("{:#}", e));
+                    }
+                }
+            } else {
+                info!("Extension {} is waiting for permission approval", info.name);
+            }
+
+            status.push(info);
+        }
+
+        info!("Loaded {} of {} extensions", loaded.len(), status.len());
+        *self.loaded.write().unwrap() = loaded;
+        *self.status.write().unwrap() = status.clone();
+        status
+    }
+
+    /// Installed extensions and their approval state
+    pub fn list(&self) -> Vec<ExtensionInfo> {
+        self.status.read().unwrap().clone()
+    }
+
+    /// Grant an extension everything its manifest requests, then load it
+    pub fn approve(&self, name: &str) -> Result<Vec<ExtensionInfo>> {
+        let manifest = self.find_manifest(name)?;
+        self.grants.lock().unwrap().grant(&manifest)?;
+        info!("Approved extension {} ({:?})", name, manifest.permissions);
+        Ok(self.reload())
+    }
+
+    /// Withdraw an extension's permissions and unload it
+    pub fn revoke(&self, name: &str) -> Result<Vec<ExtensionInfo>> {
+        if !self.grants.lock().unwrap().revoke(name)? {
+            return Err(anyhow!("Extension {} has no approved permissions", name));
+        }
+        info!("Revoked permissions for extension {}", name);
+        Ok(self.reload())
+    }
+
+    /// Deliver an event to every extension subscribed to it
+    ///
+    /// Returns the suggestions emitted while handling it; they are also
+    /// queued for `take_suggestions`.
+    pub fn dispatch(&self, event: &ExtensionEvent, context: &Context) -> Vec<ExtensionSuggestion> {
+        let kind = event.kind();
+        let extensions: Vec<_> = self
+            .loaded
+            .read()
+            .unwrap()
+            .iter()
+            .filter(|ext| ext.manifest.events.contains(&kind))
+            .cloned()
+            .collect();
+        if extensions.is_empty() {
+            return Vec::new();
+        }
+
+        let event_json = match serde_json::to_vec(event) {
+            Ok(json) => json,
+            Err(e) => {
+                warn!("Failed to serialize extension event: {}", e);
+                return Vec::new();
+            }
+        };
+        let context_json = serde_json::to_vec(context).unwrap_or_default();
+
+        let mut emitted = Vec::new();
+        for extension in extensions {
+            match self.deliver(&extension, &event_json, &context_json) {
+                Ok(texts) => emitted.extend(texts.into_iter().map(|text| ExtensionSuggestion {
+                    extension: extension.manifest.name.clone(),
+                    text,
+                })),
+                Err(e) => warn!("Extension {} failed handling {:?}: {:#}", extension.manifest.name, kind, e),
+            }
+        }
+
+        if !emitted.is_empty() {
+            let mut queue = self.suggestions.lock().unwrap();
+            for suggestion in &emitted {
+                if queue.len() == SUGGESTION_QUEUE_LIMIT {
+                    queue.pop_front();
+                }
+                queue.push_back(suggestion.clone());
+            }
+        }
+
+        emitted
+    }
+
+    /// Drain queued suggestions
+    pub fn take_suggestions(&self) -> Vec<ExtensionSuggestion> {
+        self.suggestions.lock().unwrap().drain(..).collect()
+    }
+
+    fn find_manifest(&self, name: &str) -> Result<ExtensionManifest> {
+        std::fs::read_dir(&self.dir)?
+            .flatten()
+            .filter_map(|entry| ExtensionManifest::load(&entry.path()).ok())
+            .find(|manifest| manifest.name == name)
+            .ok_or_else(|| anyhow!("Extension {} is not installed", name))
+    }
+
+    fn instantiate(&self, dir: &std::path::Path, manifest: ExtensionManifest) -> Result<LoadedExtension> {
+        let module_path = dir.join(&manifest.module);
+        let module = Module::from_file(&self.engine, &module_path)
+            .with_context(|| format!("Invalid module {}", module_path.display()))?;
+
+        let state = HostState {
+            name: manifest.name.clone(),
+            permissions: manifest.permissions.clone(),
+            context: Vec::new(),
+            suggestions: Vec::new(),
+            limits: StoreLimitsBuilder::new()
+                .memory_size(self.config.memory_limit_mb * 1024 * 1024)
+                .instances(1)
+                .build(),
+        };
+        let mut store = Store::new(&self.engine, state);
+        store.limiter(|state| &mut state.limits);
+        // Start-up code gets the same budget as an event
+        store.set_fuel(self.config.fuel_per_call)?;
+
+        let instance = host_functions(&self.engine)?
+            .instantiate(&mut store, &module)
+            .context("Failed to instantiate")?;
+
+        if !manifest.events.is_empty() {
+            for export in ["memory", "orbit_alloc", "orbit_on_event"] {
+                if instance.get_export(&mut store, export).is_none() {
+                    return Err(anyhow!("Module does not export {}", export));
+                }
+            }
+        }
+
+        Ok(LoadedExtension {
+            manifest,
+            store: Mutex::new(store),
+            instance,
+        })
+    }
+
+    fn deliver(&self, extension: &LoadedExtension, event: &[u8], context: &[u8]) -> Result<Vec<String>> {
+        let mut store = extension.store.lock().unwrap();
+        let instance = extension.instance;
+
+        {
+            let state = store.data_mut();
+            state.context = if state.allows(Permission::ReadContext) {
+                context.to_vec()
+            } else {
+                Vec::new()
+            };
+            state.suggestions.clear();
+        }
+        store.set_fuel(self.config.fuel_per_call)?;
+
+        let alloc = instance.get_typed_func::<i32, i32>(&mut *store, "orbit_alloc")?;
+        let on_event = instance.get_typed_func::<(i32, i32), ()>(&mut *store, "orbit_on_event")?;
+        let memory = instance
+            .get_memory(&mut *store, "memory")
+            .context("Module does not export memory")?;
+
+        let len = i32::try_from(event.len()).context("Event too large")?;
+        let ptr = alloc.call(&mut *store, len).context("orbit_alloc trapped")?;
+        memory
+            .write(&mut *store, ptr as u32 as usize, event)
+            .context("orbit_alloc returned an invalid buffer")?;
+        on_event
+            .call(&mut *store, (ptr, len))
+            .context("orbit_on_event trapped")?;
+
+        Ok(std::mem::take(&mut store.data_mut().suggestions))
+    }
+}
+
+/// Host functions available to every extension; each checks its permission
+fn host_functions(engine: &Engine) -> Result<Linker<HostState>> {
+    let mut linker = Linker::new(engine);
+
+    linker
+        .func_wrap(
+            "orbit",
+            "context_get",
+            |mut caller: Caller<'_, HostState>, ptr: i32, cap: i32| -> i32 {
+                if !caller.data().allows(Permission::ReadContext) {
+                    return -1;
+                }
+                let context = caller.data().context.clone();
+                if context.len() <= cap.max(0) as usize {
+                    let Some(Extern::Memory(memory)) = caller.get_export("memory") else {
+                        return -1;
+                    };
+                    if memory.write(&mut caller, ptr as u32 as usize, &context).is_err() {
+                        return -1;
+                    }
+                }
+                context.len() as i32
+            },
+        )?;
+
+    linker
+        .func_wrap(
+            "orbit",
+            "suggest",
+            |mut caller: Caller<'_, HostState>, ptr: i32, len: i32| -> i32 {
+                if !caller.data().allows(Permission::EmitSuggestion) {
+                    return -1;
+                }
+                match read_guest_string(&mut caller, ptr, len) {
+                    Some(text) if !text.trim().is_empty() => {
+                        caller.data_mut().suggestions.push(text);
+                        0
+                    }
+                    _ => -1,
+                }
+            },
+        )?;
+
+    linker
+        .func_wrap(
+            "orbit",
+            "log",
+            |mut caller: Caller<'_, HostState>, ptr: i32, len: i32| {
+                if let Some(message) = read_guest_string(&mut caller, ptr, len) {
+                    debug!("[extension {}] {}", caller.data().name, message);
+                }
+            },
+        )?;
+
+    Ok(linker)
+}
+
+fn read_guest_string(caller: &mut Caller<'_, HostState>, ptr: i32, len: i32) -> Option<String> {
+    let len = usize::try_from(len).ok().filter(|len| *len <= MAX_GUEST_STRING)?;
+    let Some(Extern::Memory(memory)) = caller.get_export("memory") else {
+        return None;
+    };
+    let mut buf = vec![0u8; len];
+    memory.read(&*caller, ptr as u32 as usize, &mut buf).ok()?;
+    String::from_utf8(buf).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::context::DirectoryType;
+    use tempfile::TempDir;
+
+    fn context() -> Context {
+        Context {
+            os_name: "linux".to_string(),
+            os_version: "6.0".to_string(),
+            shell_name: "zsh".to_string(),
+            shell_version: "5.9".to_string(),
+            pwd: PathBuf::from("/srv/app"),
+            username: "dev".to_string(),
+            git_context: None,
+            detected_languages: vec![],
+            recent_commands: vec![],
+            project_type: None,
+            directory_type: DirectoryType::Other,
+            host: None,
+        }
+    }
+
+    // Suggests "make test" after every command, but only if it could read
+    // a context that mentions /srv/app
+    const HINTS_WAT: &str = r#"
+(module
+  (import "orbit" "context_get" (func $context_get (param i32 i32) (result i32)))
+  (import "orbit" "suggest" (func $suggest (param i32 i32) (result i32)))
+  (memory (export "memory") 1)
+  (data (i32.const 0) "make test")
+  (func (export "orbit_alloc") (param i32) (result i32) i32.const 1024)
+  (func (export "orbit_on_event") (param i32 i32)
+    (if (i32.gt_s (call $context_get (i32.const 8192) (i32.const 4096)) (i32.const 0))
+      (then (drop (call $suggest (i32.const 0) (i32.const 9))))))
+)
+"#;
+
+    const SPIN_WAT: &str = r#"
+(module
+  (memory (export "memory") 1)
+  (func (export "orbit_alloc") (param i32) (result i32) i32.const 1024)
+  (func (export "orbit_on_event") (param i32 i32) (loop $l (br $l)))
+)
+"#;
+
+    fn install(root: &std::path::Path, name: &str, wat: &str, permissions: &str) {
+        let ext = root.join(name);
+        std::fs::create_dir_all(&ext).unwrap();
+        std::fs::write(ext.join("module.wat"), wat).unwrap();
+        std::fs::write(
+            ext.join(manifest::MANIFEST_FILE),
+            format!(
+                "name: {}\nversion: 1.0.0\nmodule: module.wat\npermissions: [{}]\nevents: [command_executed]\n",
+                name, permissions
+            ),
+        )
+        .unwrap();
+    }
+
+    fn host(dir: &TempDir) -> ExtensionHost {
+        let config = ExtensionsConfig {
+            fuel_per_call: 1_000_000,
+            ..ExtensionsConfig::default()
+        };
+        ExtensionHost::new(
+            dir.path().join("extensions"),
+            dir.path().join("extension-grants.yaml"),
+            &config,
+        )
+        .unwrap()
+    }
+
+    fn executed() -> ExtensionEvent {
+        ExtensionEvent::CommandExecuted {
+            input: "build it".to_string(),
+            command: "cargo build".to_string(),
+            success: true,
+        }
+    }
+
+    #[test]
+    fn test_extension_needs_approval_before_running() {
+        let dir = TempDir::new().unwrap();
+        let host = host(&dir);
+        install(
+            &dir.path().join("extensions"),
+            "hints",
+            HINTS_WAT,
+            "subscribe_events, read_context, emit_suggestion",
+        );
+
+        let status = host.reload();
+        assert_eq!(status.len(), 1);
+        assert!(!status[0].loaded);
+        assert_eq!(status[0].pending.len(), 3);
+        assert!(host.dispatch(&executed(), &context()).is_empty());
+
+        host.approve("hints").unwrap();
+        let suggestions = host.dispatch(&executed(), &context());
+        assert_eq!(suggestions.len(), 1);
+        assert_eq!(suggestions[0].extension, "hints");
+        assert_eq!(suggestions[0].text, "make test");
+        assert_eq!(host.take_suggestions().len(), 1);
+        assert!(host.take_suggestions().is_empty());
+
+        host.revoke("hints").unwrap();
+        assert!(!host.list()[0].loaded);
+        assert!(host.dispatch(&executed(), &context()).is_empty());
+    }
+
+    #[test]
+    fn test_host_functions_respect_permissions() {
+        let dir = TempDir::new().unwrap();
+        let host = host(&dir);
+        // Can suggest but can't read the context it needs
+        install(
+            &dir.path().join("extensions"),
+            "hints",
+            HINTS_WAT,
+            "subscribe_events, emit_suggestion",
+        );
+        host.approve("hints").unwrap();
+
+        assert!(host.list()[0].loaded);
+        assert!(host.dispatch(&executed(), &context()).is_empty());
+    }
+
+    #[test]
+    fn test_runaway_extension_is_stopped() {
+        let dir = TempDir::new().unwrap();
+        let host = host(&dir);
+        let root = dir.path().join("extensions");
+        install(&root, "a-spin", SPIN_WAT, "subscribe_events");
+        install(&root, "hints", HINTS_WAT, "subscribe_events, read_context, emit_suggestion");
+        host.approve("a-spin").unwrap();
+        host.approve("hints").unwrap();
+
+        // The looping extension runs out of fuel; later ones still run
+        let suggestions = host.dispatch(&executed(), &context());
+        assert_eq!(suggestions.len(), 1);
+        assert_eq!(suggestions[0].extension, "hints");
+    }
+}
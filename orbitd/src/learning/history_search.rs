@@ -0,0 +1,302 @@
+// Natural-language search over command history
+//
+// "that docker command I ran last week that pruned volumes" is split into
+// a time window ("last week") and search terms. Terms are normalized (stop
+// words dropped, crude stemming so "pruned" meets "prune") and compared to
+// each history entry's command and original input with the embedding
+// model.
+//
+// Embeddings are computed at query time rather than stored, so encrypted
+// history doesn't get a plaintext-derived column next to it. History is
+// bounded by the retention period, and at most SCAN_LIMIT rows are read.
+
+use anyhow::Result;
+use chrono::{DateTime, Duration, NaiveDateTime, TimeZone, Utc};
+use regex::Regex;
+use sqlx::Row;
+use std::collections::{HashMap, HashSet};
+use std::sync::OnceLock;
+
+use super::types::HistoryMatch;
+use super::{LearningEngine, SQLITE_TIMESTAMP};
+use crate::context::Context;
+use crate::embeddings::EmbeddingModel;
+
+/// History rows considered per search, newest first
+const SCAN_LIMIT: i64 = 5000;
+
+/// Matches scoring below this are dropped
+const MIN_SCORE: f32 = 0.2;
+
+const STOP_WORDS: &[&str] = &[
+    "a", "an", "and", "any", "command", "commands", "did", "for", "from", "i", "in", "it", "me",
+    "my", "of", "on", "ran", "run", "that", "the", "there", "this", "to", "used", "was", "what",
+    "when", "where", "which", "with",
+];
+
+/// Time range mentioned in a query
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TimeWindow {
+    pub since: DateTime<Utc>,
+    pub until: DateTime<Utc>,
+}
+
+/// Split a relative time phrase off a query
+///
+/// Ranges are generous ("last week" covers the past two weeks) because
+/// people remember roughly, not exactly.
+pub fn parse_time_window(query: &str, now: DateTime<Utc>) -> (String, Option<TimeWindow>) {
+    static PHRASE: OnceLock<Regex> = OnceLock::new();
+    let phrase = PHRASE.get_or_init(|| {
+        Regex::new(r"(?i)\b(today|yesterday|(?:this|last|past) (?:week|month)|(?:last|past) (\d+) days|(\d+) days? ago)\b")
+            .unwrap()
+    });
+
+    let Some(caps) = phrase.captures(query) else {
+        return (query.to_string(), None);
+    };
+
+    let start_of_today = Utc.from_utc_datetime(&now.date_naive().and_hms_opt(0, 0, 0).unwrap());
+    let matched = caps[1].to_lowercase();
+    let window = if let Some(days) = caps.get(2) {
+        let days: i64 = days.as_str().parse().unwrap_or(7);
+        TimeWindow {
+            since: now - Duration::days(days),
+            until: now,
+        }
+    } else if let Some(days) = caps.get(3) {
+        let days: i64 = days.as_str().parse().unwrap_or(1);
+        TimeWindow {
+            since: start_of_today - Duration::days(days + 1),
+            until: start_of_today - Duration::days(days - 1),
+        }
+    } else {
+        match matched.as_str() {
+            "today" => TimeWindow {
+                since: start_of_today,
+                until: now,
+            },
+            "yesterday" => TimeWindow {
+                since: start_of_today - Duration::days(1),
+                until: start_of_today,
+            },
+            "this week" => TimeWindow {
+                since: now - Duration::days(7),
+                until: now,
+            },
+            "last week" | "past week" => TimeWindow {
+                since: now - Duration::days(14),
+                until: now,
+            },
+            "this month" => TimeWindow {
+                since: now - Duration::days(31),
+                until: now,
+            },
+            _ => TimeWindow {
+                since: now - Duration::days(62),
+                until: now,
+            },
+        }
+    };
+
+    let rest = format!(
+        "{} {}",
+        &query[..caps.get(0).unwrap().start()],
+        &query[caps.get(0).unwrap().end()..]
+    );
+    (rest, Some(window))
+}
+
+/// Lowercased, stemmed words worth matching on
+fn search_terms(text: &str) -> Vec<String> {
+    text.split(|c: char| !c.is_alphanumeric() && c != '-' && c != '_')
+        .map(str::to_lowercase)
+        .filter(|word| !word.is_empty() && !STOP_WORDS.contains(&word.as_str()))
+        .map(|word| stem(&word))
+        .collect()
+}
+
+fn stem(word: &str) -> String {
+    for suffix in ["ing", "ed", "es", "s", "e"] {
+        if let Some(stem) = word.strip_suffix(suffix) {
+            if stem.len() >= 3 {
+                return stem.to_string();
+            }
+        }
+    }
+    word.to_string()
+}
+
+/// Similarity of two term lists, by embedding when the model is loaded
+fn similarity(model: Option<&EmbeddingModel>, query: &[String], entry: &[String]) -> Result<f32> {
+    // The embedding model skips words of one or two letters, so pad terms
+    // like "ls" and "rm" to keep them
+    let padded = |terms: &[String]| {
+        terms
+            .iter()
+            .map(|t| format!("{:_<3}", t))
+            .collect::<Vec<_>>()
+            .join(" ")
+    };
+
+    match model {
+        Some(model) => Ok(EmbeddingModel::cosine_similarity(
+            &model.embed(&padded(query))?,
+            &model.embed(&padded(entry))?,
+        )),
+        None => {
+            let entry: HashSet<_> = entry.iter().collect();
+            let shared = query.iter().filter(|t| entry.contains(t)).count();
+            Ok(shared as f32 / query.len().max(1) as f32)
+        }
+    }
+}
+
+impl LearningEngine {
+    /// Commands from history matching a natural-language description,
+    /// best match first
+    pub async fn search_history(&self, query: &str, limit: usize) -> Result<Vec<HistoryMatch>> {
+        let (terms_text, window) = parse_time_window(query, Utc::now());
+        let query_terms = search_terms(&terms_text);
+        if query_terms.is_empty() && window.is_none() {
+            return Ok(Vec::new());
+        }
+
+        let (since, until) = match window {
+            Some(window) => (
+                window.since.format(SQLITE_TIMESTAMP).to_string(),
+                window.until.format(SQLITE_TIMESTAMP).to_string(),
+            ),
+            None => (String::new(), "9999-12-31 23:59:59".to_string()),
+        };
+
+        let rows = sqlx::query(
+            r#"
+            SELECT input, executed_command, exit_code, context, timestamp
+            FROM execution_history
+            WHERE timestamp >= ?1 AND timestamp <= ?2
+            ORDER BY id DESC
+            LIMIT ?3
+            "#,
+        )
+        .bind(since)
+        .bind(until)
+        .bind(SCAN_LIMIT)
+        .fetch_all(&self.pool)
+        .await?;
+
+        // Best occurrence per command, and how often it ran
+        let mut matches: HashMap<String, HistoryMatch> = HashMap::new();
+        for row in rows {
+            let command = self.cipher.open(row.get::<&str, _>("executed_command"))?;
+            let input = self.cipher.open(row.get::<&str, _>("input"))?;
+
+            let score = if query_terms.is_empty() {
+                // Only a time phrase: everything in the window, newest first
+                1.0
+            } else {
+                let entry_terms = search_terms(&format!("{} {}", command, input));
+                let score = similarity(self.embeddings(), &query_terms, &entry_terms)?;
+                if score < MIN_SCORE {
+                    continue;
+                }
+                score
+            };
+
+            let exit_code: Option<i32> = row.get("exit_code");
+            // Prefer commands that worked
+            let score = if exit_code == Some(0) {
+                score + 0.05
+            } else {
+                score
+            };
+
+            if let Some(existing) = matches.get_mut(&command) {
+                existing.runs += 1;
+                continue;
+            }
+
+            let stored_context: Option<String> = row.get("context");
+            let context = self
+                .cipher
+                .open_opt(stored_context.as_deref())?
+                .and_then(|json| serde_json::from_str::<Context>(&json).ok());
+            let timestamp: String = row.get("timestamp");
+
+            matches.insert(
+                command.clone(),
+                HistoryMatch {
+                    command,
+                    input,
+                    timestamp: NaiveDateTime::parse_from_str(&timestamp, SQLITE_TIMESTAMP)
+                        .map(|t| Utc.from_utc_datetime(&t))
+                        .unwrap_or_else(|_| Utc::now()),
+                    exit_code,
+                    context,
+                    score,
+                    runs: 1,
+                },
+            );
+        }
+
+        let mut matches: Vec<_> = matches.into_values().collect();
+        matches.sort_by(|a, b| {
+            b.score
+                .total_cmp(&a.score)
+                .then_with(|| b.timestamp.cmp(&a.timestamp))
+        });
+        matches.truncate(limit);
+        Ok(matches)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_time_window() {
+        let now = Utc.with_ymd_and_hms(2026, 3, 18, 15, 0, 0).unwrap();
+
+        let (rest, window) =
+            parse_time_window("docker command I ran last week that pruned volumes", now);
+        assert_eq!(
+            rest.split_whitespace().collect::<Vec<_>>().join(" "),
+            "docker command I ran that pruned volumes"
+        );
+        assert_eq!(window.unwrap().since, now - Duration::days(14));
+
+        let (_, window) = parse_time_window("ssh yesterday", now);
+        let window = window.unwrap();
+        assert_eq!(
+            window.since,
+            Utc.with_ymd_and_hms(2026, 3, 17, 0, 0, 0).unwrap()
+        );
+        assert_eq!(
+            window.until,
+            Utc.with_ymd_and_hms(2026, 3, 18, 0, 0, 0).unwrap()
+        );
+
+        let (_, window) = parse_time_window("migration 3 days ago", now);
+        assert_eq!(
+            window.unwrap().since,
+            Utc.with_ymd_and_hms(2026, 3, 14, 0, 0, 0).unwrap()
+        );
+
+        let (rest, window) = parse_time_window("kubectl logs", now);
+        assert_eq!(rest, "kubectl logs");
+        assert!(window.is_none());
+    }
+
+    #[test]
+    fn test_search_terms_meet_across_word_forms() {
+        assert_eq!(
+            search_terms("that command that pruned volumes"),
+            vec!["prun", "volum"]
+        );
+        assert_eq!(
+            search_terms("docker volume prune -f"),
+            vec!["docker", "volum", "prun", "-f"]
+        );
+    }
+}
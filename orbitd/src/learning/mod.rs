@@ -1,14 +1,20 @@
 // Enhanced learning system modules (Phase 4)
 pub mod analytics;
+pub mod history_search;
 pub mod patterns;
+pub mod playbooks;
 pub mod preferences;
 pub mod types;
 
-use anyhow::Result;
+use anyhow::{Context as _, Result};
 use ndarray::Array1;
-use sqlx::sqlite::{SqlitePool, SqlitePoolOptions};
+use sqlx::sqlite::{SqlitePool, SqliteRow};
 use sqlx::Row;
-use std::sync::Arc;
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::{Arc, OnceLock};
+
+use pulsar_db::FieldCipher;
 
 use crate::config::Config;
 use crate::context::Context;
@@ -20,6 +26,41 @@ pub use patterns::PatternRecognition;
 pub use preferences::PreferenceService;
 pub use types::*;
 
+/// Confidence factor for an edited suggestion when no reason was given
+const CORRECTION_PENALTY: f32 = 0.7;
+
+/// Format of SQLite's CURRENT_TIMESTAMP (UTC)
+const SQLITE_TIMESTAMP: &str = "%Y-%m-%d %H:%M:%S";
+
+/// Number of learning.db backups kept by [`LearningEngine::backup_database`]
+const DB_BACKUPS_KEPT: usize = 7;
+
+/// Tables cleared by [`LearningEngine::purge_all`]: learned patterns,
+/// corrections, command history, time-of-day patterns, analytics and
+/// generated insights. `preferences` holds settings the user chose and is
+/// kept.
+pub const LEARNING_TABLES: &[&str] = &[
+    "command_patterns",
+    "corrections",
+    "execution_history",
+    "temporal_patterns",
+    "command_analytics",
+    "insights",
+];
+
+/// Columns sealed when `learning.encrypt_history` is on. Learned patterns
+/// are looked up by `input_key` and `command_key` instead, and their
+/// embeddings are sealed too (see [`seal_plaintext_history`]).
+const SEALED_COLUMNS: &[(&str, &[&str])] = &[
+    ("command_patterns", &["natural_input", "learned_command"]),
+    (
+        "corrections",
+        &["original_input", "ai_suggestion", "user_correction", "context", "note"],
+    ),
+    ("execution_history", &["input", "executed_command", "context"]),
+    ("playbooks", &["commands"]),
+];
+
 #[derive(Debug, Clone)]
 pub struct LearnedCommand {
     #[allow(dead_code)]
@@ -39,18 +80,18 @@ pub struct LearningEngine {
     #[allow(dead_code)]
     config: Arc<Config>,
     pool: SqlitePool,
-    embeddings: Option<EmbeddingModel>,
+    /// Loaded by `warm_embeddings`; shared by clones
+    embeddings: Arc<OnceLock<EmbeddingModel>>,
+    /// Seals command history, corrections and learned patterns at rest
+    cipher: FieldCipher,
 }
 
 impl LearningEngine {
     pub async fn new(config: Arc<Config>) -> Result<Self> {
         let db_path = Config::data_dir()?.join("learning.db");
 
-        // Create pool with mode=rwc to allow creating database if it doesn't exist
-        let pool = SqlitePoolOptions::new()
-            .max_connections(5)
-            .connect(&format!("sqlite://{}?mode=rwc", db_path.display()))
-            .await?;
+        // Creates the database if needed; WAL, busy timeout and an integrity check
+        let pool = pulsar_db::open(&db_path, &pulsar_db::DbOptions::default()).await?;
 
         // Create tables
         sqlx::query(
@@ -71,6 +112,10 @@ impl LearningEngine {
         .execute(&pool)
         .await?;
 
+        // Patterns are looked up by these, as their text may be sealed
+        add_column_if_missing(&pool, "command_patterns", "input_key", "TEXT").await?;
+        add_column_if_missing(&pool, "command_patterns", "command_key", "TEXT").await?;
+
         sqlx::query(
             r#"
             CREATE TABLE IF NOT EXISTS corrections (
@@ -86,6 +131,10 @@ impl LearningEngine {
         .execute(&pool)
         .await?;
 
+        // Feedback reasons and notes came after the table
+        add_column_if_missing(&pool, "corrections", "reasons", "TEXT").await?;
+        add_column_if_missing(&pool, "corrections", "note", "TEXT").await?;
+
         sqlx::query(
             r#"
             CREATE TABLE IF NOT EXISTS execution_history (
@@ -117,33 +166,71 @@ impl LearningEngine {
         .execute(&pool)
         .await?;
 
-        // Initialize embedding model (optional - system works without it)
-        let embeddings = match EmbeddingModel::new().await {
-            Ok(model) => {
-                tracing::info!("✓ Embedding model initialized");
-                Some(model)
-            }
-            Err(e) => {
-                tracing::warn!("Embedding model initialization failed: {}", e);
-                tracing::warn!("Falling back to exact matching (embeddings disabled)");
-                None
-            }
+        playbooks::create_table(&pool).await?;
+
+        let cipher = if config.learning.encrypt_history {
+            FieldCipher::from_keychain("orbit", "learning-db")?
+        } else {
+            FieldCipher::disabled()
         };
+        if cipher.is_enabled() {
+            seal_plaintext_history(&pool, &cipher).await?;
+        }
+        index_patterns(&pool, &cipher).await?;
 
         Ok(Self {
             config,
             pool,
-            embeddings,
+            embeddings: Arc::new(OnceLock::new()),
+            cipher,
         })
     }
 
+    /// Load the embedding model, and embed patterns learned without it.
+    /// Until it is loaded (or if it fails to load), learned patterns only
+    /// match exactly.
+    pub async fn warm_embeddings(&self) -> Result<()> {
+        let model = match self.embeddings() {
+            Some(model) => model,
+            None => {
+                let model = EmbeddingModel::new()
+                    .await
+                    .context("Embedding model initialization failed")?;
+                self.embeddings.get_or_init(|| model)
+            }
+        };
+
+        let rows =
+            sqlx::query("SELECT id, natural_input FROM command_patterns WHERE embedding IS NULL")
+                .fetch_all(&self.pool)
+                .await?;
+        for row in &rows {
+            let input = self.cipher.open(row.get("natural_input"))?;
+            let embedding = model.embed(&input)?;
+            sqlx::query("UPDATE command_patterns SET embedding = ?1 WHERE id = ?2")
+                .bind(self.cipher.seal_bytes(&Self::serialize_embedding(&embedding))?)
+                .bind(row.get::<i64, _>("id"))
+                .execute(&self.pool)
+                .await?;
+        }
+        if !rows.is_empty() {
+            tracing::debug!("Embedded {} learned patterns", rows.len());
+        }
+        Ok(())
+    }
+
+    /// The embedding model, once loaded
+    fn embeddings(&self) -> Option<&EmbeddingModel> {
+        self.embeddings.get()
+    }
+
     pub async fn find_similar(
         &self,
         input: &str,
         _context: &Context,
     ) -> Result<Option<LearnedCommand>> {
         // Use embeddings if available, otherwise fall back to exact match
-        if let Some(ref embedding_model) = self.embeddings {
+        if let Some(embedding_model) = self.embeddings() {
             self.find_similar_by_embedding(input, embedding_model).await
         } else {
             self.find_exact_match(input).await
@@ -177,7 +264,8 @@ impl LearningEngine {
             let embedding_blob: Vec<u8> = row.get("embedding");
 
             // Deserialize embedding
-            let pattern_embedding = Self::deserialize_embedding(&embedding_blob)?;
+            let pattern_embedding =
+                Self::deserialize_embedding(&self.cipher.open_bytes(&embedding_blob)?)?;
 
             // Calculate similarity
             let similarity =
@@ -188,32 +276,12 @@ impl LearningEngine {
             let combined_score = similarity * 0.7 + confidence * 0.3;
 
             // Update best match if this is better
-            if let Some((_, current_best_score)) = &best_match {
-                if combined_score > *current_best_score {
-                    best_match = Some((
-                        LearnedCommand {
-                            id: row.get("id"),
-                            natural_input: row.get("natural_input"),
-                            learned_command: row.get("learned_command"),
-                            confidence: row.get("confidence"),
-                            success_count: row.get("success_count"),
-                            failure_count: row.get("failure_count"),
-                        },
-                        combined_score,
-                    ));
-                }
-            } else {
-                best_match = Some((
-                    LearnedCommand {
-                        id: row.get("id"),
-                        natural_input: row.get("natural_input"),
-                        learned_command: row.get("learned_command"),
-                        confidence: row.get("confidence"),
-                        success_count: row.get("success_count"),
-                        failure_count: row.get("failure_count"),
-                    },
-                    combined_score,
-                ));
+            let better = match &best_match {
+                Some((_, current_best_score)) => combined_score > *current_best_score,
+                None => true,
+            };
+            if better {
+                best_match = Some((self.learned_from_row(&row)?, combined_score));
             }
         }
 
@@ -239,27 +307,38 @@ impl LearningEngine {
             r#"
             SELECT id, natural_input, learned_command, confidence, success_count, failure_count
             FROM command_patterns
-            WHERE natural_input = ?1
+            WHERE input_key = ?1
             ORDER BY confidence DESC
             LIMIT 1
             "#,
         )
-        .bind(input)
+        .bind(self.input_key(input))
         .fetch_optional(&self.pool)
         .await?;
 
-        if let Some(row) = result {
-            Ok(Some(LearnedCommand {
-                id: row.get("id"),
-                natural_input: row.get("natural_input"),
-                learned_command: row.get("learned_command"),
-                confidence: row.get("confidence"),
-                success_count: row.get("success_count"),
-                failure_count: row.get("failure_count"),
-            }))
-        } else {
-            Ok(None)
-        }
+        result.map(|row| self.learned_from_row(&row)).transpose()
+    }
+
+    /// A learned pattern read back from `row`, with its text opened
+    fn learned_from_row(&self, row: &SqliteRow) -> Result<LearnedCommand> {
+        Ok(LearnedCommand {
+            id: row.get("id"),
+            natural_input: self.cipher.open(row.get("natural_input"))?,
+            learned_command: self.cipher.open(row.get("learned_command"))?,
+            confidence: row.get("confidence"),
+            success_count: row.get("success_count"),
+            failure_count: row.get("failure_count"),
+        })
+    }
+
+    /// What a pattern's natural input is looked up by
+    fn input_key(&self, input: &str) -> String {
+        pattern_input_key(&self.cipher, input)
+    }
+
+    /// What a pattern's learned command is looked up by
+    fn command_key(&self, command: &str) -> String {
+        self.cipher.lookup_key(command)
     }
 
     /// Serialize embedding for storage
@@ -289,9 +368,9 @@ impl LearningEngine {
         _context: &Context,
     ) -> Result<()> {
         // Generate embedding if model available
-        let embedding_blob = if let Some(ref model) = self.embeddings {
+        let embedding_blob = if let Some(model) = self.embeddings() {
             match model.embed(input) {
-                Ok(emb) => Some(Self::serialize_embedding(&emb)),
+                Ok(emb) => Some(self.cipher.seal_bytes(&Self::serialize_embedding(&emb))?),
                 Err(e) => {
                     tracing::warn!("Failed to generate embedding: {}", e);
                     None
@@ -301,12 +380,14 @@ impl LearningEngine {
             None
         };
 
+        let (input_key, command_key) = (self.input_key(input), self.command_key(executed));
+
         // Check if pattern exists
         let exists = sqlx::query_scalar::<_, i64>(
-            "SELECT COUNT(*) FROM command_patterns WHERE natural_input = ?1 AND learned_command = ?2"
+            "SELECT COUNT(*) FROM command_patterns WHERE input_key = ?1 AND command_key = ?2"
         )
-        .bind(input)
-        .bind(executed)
+        .bind(&input_key)
+        .bind(&command_key)
         .fetch_one(&self.pool)
         .await? > 0;
 
@@ -320,12 +401,12 @@ impl LearningEngine {
                         confidence = confidence + 0.1 * (1.0 - confidence),
                         embedding = ?1,
                         last_used = CURRENT_TIMESTAMP
-                    WHERE natural_input = ?2 AND learned_command = ?3
+                    WHERE input_key = ?2 AND command_key = ?3
                     "#,
                 )
                 .bind(embedding)
-                .bind(input)
-                .bind(executed)
+                .bind(&input_key)
+                .bind(&command_key)
                 .execute(&self.pool)
                 .await?;
             } else {
@@ -335,11 +416,11 @@ impl LearningEngine {
                     SET success_count = success_count + 1,
                         confidence = confidence + 0.1 * (1.0 - confidence),
                         last_used = CURRENT_TIMESTAMP
-                    WHERE natural_input = ?1 AND learned_command = ?2
+                    WHERE input_key = ?1 AND command_key = ?2
                     "#,
                 )
-                .bind(input)
-                .bind(executed)
+                .bind(&input_key)
+                .bind(&command_key)
                 .execute(&self.pool)
                 .await?;
             }
@@ -348,24 +429,28 @@ impl LearningEngine {
             if let Some(embedding) = embedding_blob {
                 sqlx::query(
                     r#"
-                    INSERT INTO command_patterns (natural_input, learned_command, success_count, confidence, embedding)
-                    VALUES (?1, ?2, 1, 0.6, ?3)
+                    INSERT INTO command_patterns (natural_input, learned_command, input_key, command_key, success_count, confidence, embedding)
+                    VALUES (?1, ?2, ?3, ?4, 1, 0.6, ?5)
                     "#,
                 )
-                .bind(input)
-                .bind(executed)
+                .bind(self.cipher.seal(input)?)
+                .bind(self.cipher.seal(executed)?)
+                .bind(&input_key)
+                .bind(&command_key)
                 .bind(embedding)
                 .execute(&self.pool)
                 .await?;
             } else {
                 sqlx::query(
                     r#"
-                    INSERT INTO command_patterns (natural_input, learned_command, success_count, confidence)
-                    VALUES (?1, ?2, 1, 0.6)
+                    INSERT INTO command_patterns (natural_input, learned_command, input_key, command_key, success_count, confidence)
+                    VALUES (?1, ?2, ?3, ?4, 1, 0.6)
                     "#,
                 )
-                .bind(input)
-                .bind(executed)
+                .bind(self.cipher.seal(input)?)
+                .bind(self.cipher.seal(executed)?)
+                .bind(&input_key)
+                .bind(&command_key)
                 .execute(&self.pool)
                 .await?;
             }
@@ -380,12 +465,14 @@ impl LearningEngine {
         executed: &str,
         _context: &Context,
     ) -> Result<()> {
+        let (input_key, command_key) = (self.input_key(input), self.command_key(executed));
+
         // Lower confidence for failed command
         let exists = sqlx::query_scalar::<_, i64>(
-            "SELECT COUNT(*) FROM command_patterns WHERE natural_input = ?1 AND learned_command = ?2",
+            "SELECT COUNT(*) FROM command_patterns WHERE input_key = ?1 AND command_key = ?2",
         )
-        .bind(input)
-        .bind(executed)
+        .bind(&input_key)
+        .bind(&command_key)
         .fetch_one(&self.pool)
         .await?
             > 0;
@@ -398,11 +485,11 @@ impl LearningEngine {
                 SET failure_count = failure_count + 1,
                     confidence = confidence * 0.8,
                     last_used = CURRENT_TIMESTAMP
-                WHERE natural_input = ?1 AND learned_command = ?2
+                WHERE input_key = ?1 AND command_key = ?2
                 "#,
             )
-            .bind(input)
-            .bind(executed)
+            .bind(&input_key)
+            .bind(&command_key)
             .execute(&self.pool)
             .await?;
         }
@@ -428,40 +515,89 @@ impl LearningEngine {
         user_correction: &str,
         context: &Context,
     ) -> Result<()> {
-        // Store correction
+        self.record_feedback(input, ai_suggestion, Some(user_correction), &[], None, context)
+            .await
+    }
+
+    /// Store negative feedback on `ai_suggestion` in the corrections table
+    /// and lower its confidence by how wrong the reasons say it was. An
+    /// edit without reasons counts as a wrong command; a note alone
+    /// changes nothing.
+    pub async fn record_feedback(
+        &self,
+        input: &str,
+        ai_suggestion: &str,
+        user_correction: Option<&str>,
+        reasons: &[FeedbackReason],
+        note: Option<&str>,
+        context: &Context,
+    ) -> Result<()> {
+        let codes = reasons
+            .iter()
+            .map(|reason| reason.code())
+            .collect::<Vec<_>>()
+            .join(",");
         sqlx::query(
             r#"
-            INSERT INTO corrections (original_input, ai_suggestion, user_correction, context)
-            VALUES (?1, ?2, ?3, ?4)
+            INSERT INTO corrections (original_input, ai_suggestion, user_correction, context, reasons, note)
+            VALUES (?1, ?2, ?3, ?4, ?5, ?6)
             "#,
         )
-        .bind(input)
-        .bind(ai_suggestion)
-        .bind(user_correction)
-        .bind(serde_json::to_string(context)?)
+        .bind(self.cipher.seal(input)?)
+        .bind(self.cipher.seal(ai_suggestion)?)
+        .bind(self.cipher.seal(user_correction.unwrap_or_default())?)
+        .bind(self.cipher.seal(&serde_json::to_string(context)?)?)
+        .bind((!codes.is_empty()).then_some(codes))
+        .bind(self.cipher.seal_opt(note)?)
         .execute(&self.pool)
         .await?;
 
         // Penalize wrong suggestion
-        sqlx::query(
-            r#"
-            UPDATE command_patterns
-            SET confidence = confidence * 0.7,
-                failure_count = failure_count + 1
-            WHERE learned_command = ?1
-            "#,
-        )
-        .bind(ai_suggestion)
-        .execute(&self.pool)
-        .await?;
+        let penalty = match penalty(reasons) {
+            Some(penalty) => Some(penalty),
+            None => user_correction.map(|_| (CORRECTION_PENALTY, false)),
+        };
+        match penalty {
+            Some((factor, true)) => {
+                sqlx::query(
+                    r#"
+                    UPDATE command_patterns
+                    SET confidence = confidence * ?1,
+                        failure_count = failure_count + 1
+                    WHERE input_key = ?2 AND command_key = ?3
+                    "#,
+                )
+                .bind(factor)
+                .bind(self.input_key(input))
+                .bind(self.command_key(ai_suggestion))
+                .execute(&self.pool)
+                .await?;
+            }
+            Some((factor, false)) => {
+                sqlx::query(
+                    r#"
+                    UPDATE command_patterns
+                    SET confidence = confidence * ?1,
+                        failure_count = failure_count + 1
+                    WHERE command_key = ?2
+                    "#,
+                )
+                .bind(factor)
+                .bind(self.command_key(ai_suggestion))
+                .execute(&self.pool)
+                .await?;
+            }
+            None => {}
+        }
 
         // Create or boost correct pattern
-        self.record_success(input, user_correction, context).await?;
+        if let Some(user_correction) = user_correction {
+            self.record_success(input, user_correction, context).await?;
+        }
 
         Ok(())
     }
 
-    #[allow(dead_code)]
     pub async fn record_execution(
         &self,
         input: &str,
@@ -476,11 +612,11 @@ impl LearningEngine {
             VALUES (?1, ?2, ?3, ?4, ?5)
             "#,
         )
-        .bind(input)
-        .bind(executed)
+        .bind(self.cipher.seal(input)?)
+        .bind(self.cipher.seal(executed)?)
         .bind(exit_code)
         .bind(duration_ms)
-        .bind(serde_json::to_string(context)?)
+        .bind(self.cipher.seal(&serde_json::to_string(context)?)?)
         .execute(&self.pool)
         .await?;
 
@@ -549,6 +685,113 @@ impl LearningEngine {
         Ok(patterns)
     }
 
+    /// Journal mode, size and integrity of learning.db
+    /// Commands that ran successfully and how often, most frequent first
+    ///
+    /// Shell completion ranks learned arguments with this.
+    pub async fn command_frequencies(&self, limit: usize) -> Result<Vec<(String, i64)>> {
+        let mut counts: HashMap<String, i64> = sqlx::query_as::<_, (String, i64)>(
+            "SELECT command, SUM(frequency) FROM temporal_patterns GROUP BY command",
+        )
+        .fetch_all(&self.pool)
+        .await?
+        .into_iter()
+        .collect();
+
+        let executed: Vec<String> = sqlx::query_scalar(
+            "SELECT executed_command FROM execution_history WHERE exit_code = 0 ORDER BY id DESC LIMIT ?1",
+        )
+        .bind(limit as i64)
+        .fetch_all(&self.pool)
+        .await?;
+        for stored in executed {
+            *counts.entry(self.cipher.open(&stored)?).or_default() += 1;
+        }
+
+        let mut commands: Vec<_> = counts.into_iter().collect();
+        commands.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        commands.truncate(limit);
+        Ok(commands)
+    }
+
+    pub async fn database_health(&self) -> Result<pulsar_db::DbHealth> {
+        Ok(pulsar_db::health(&self.pool).await?)
+    }
+
+    /// Back up learning.db into the data directory, keeping the last few copies
+    pub async fn backup_database(&self) -> Result<PathBuf> {
+        let dir = Config::data_dir()?.join("backups");
+        Ok(pulsar_db::backup_rotating(&self.pool, &dir, "learning", DB_BACKUPS_KEPT).await?)
+    }
+
+    /// Reclaim space left by pruned history
+    pub async fn vacuum_database(&self) -> Result<()> {
+        Ok(pulsar_db::vacuum(&self.pool).await?)
+    }
+
+    /// Delete command history and corrections older than `retention_days`
+    pub async fn purge_older_than(&self, retention_days: u32) -> Result<PurgeReport> {
+        let cutoff = format!("-{} days", retention_days);
+        let mut report = PurgeReport::default();
+
+        for (table, column) in [("execution_history", "timestamp"), ("corrections", "created_at")] {
+            let result = sqlx::query(&format!(
+                "DELETE FROM {} WHERE {} < datetime('now', ?1)",
+                table, column
+            ))
+            .bind(&cutoff)
+            .execute(&self.pool)
+            .await?;
+            report.rows_deleted.insert(table.to_string(), result.rows_affected());
+        }
+
+        Ok(report)
+    }
+
+    /// Delete all learned data
+    ///
+    /// Empties every table in [`LEARNING_TABLES`], deletes learning.db
+    /// backups (they still contain the data) and vacuums so deleted rows
+    /// don't linger in free pages. Preferences are kept.
+    pub async fn purge_all(&self) -> Result<PurgeReport> {
+        let existing: Vec<String> =
+            sqlx::query_scalar("SELECT name FROM sqlite_master WHERE type = 'table'")
+                .fetch_all(&self.pool)
+                .await?;
+
+        let mut report = PurgeReport::default();
+        for table in LEARNING_TABLES.iter().filter(|t| existing.iter().any(|e| e == *t)) {
+            let result = sqlx::query(&format!("DELETE FROM {}", table))
+                .execute(&self.pool)
+                .await?;
+            report.rows_deleted.insert(table.to_string(), result.rows_affected());
+        }
+
+        let backup_dir = Config::data_dir()?.join("backups");
+        if backup_dir.is_dir() {
+            for entry in std::fs::read_dir(&backup_dir)? {
+                let path = entry?.path();
+                let is_learning_backup = path
+                    .file_name()
+                    .and_then(|name| name.to_str())
+                    .is_some_and(|name| name.starts_with("learning-") && name.ends_with(".db"));
+                if is_learning_backup {
+                    std::fs::remove_file(&path)?;
+                    report.backups_deleted.push(path.display().to_string());
+                }
+            }
+        }
+
+        pulsar_db::vacuum(&self.pool).await?;
+
+        tracing::info!(
+            "Purged all learning data ({} rows, {} backups)",
+            report.total_rows(),
+            report.backups_deleted.len()
+        );
+        Ok(report)
+    }
+
     #[allow(dead_code)]
     pub async fn get_stats(&self) -> Result<LearningStats> {
         let total_patterns = sqlx::query_scalar::<_, i64>("SELECT COUNT(*) FROM command_patterns")
@@ -610,6 +853,130 @@ pub struct LearningStats {
     pub success_rate: f32,
 }
 
+/// Confidence factor for a suggestion turned down for `reasons`, and
+/// whether only its pattern for this input is lowered; the harshest reason
+/// sets the factor. None without reasons.
+fn penalty(reasons: &[FeedbackReason]) -> Option<(f32, bool)> {
+    let factor = reasons
+        .iter()
+        .map(|reason| reason.confidence_factor())
+        .reduce(f32::min)?;
+    Some((factor, reasons.iter().all(|reason| reason.input_only())))
+}
+
+/// Add `column` to `table` in databases created before it existed
+async fn add_column_if_missing(
+    pool: &SqlitePool,
+    table: &str,
+    column: &str,
+    definition: &str,
+) -> Result<()> {
+    let exists = sqlx::query_scalar::<_, i64>(
+        "SELECT COUNT(*) FROM pragma_table_info(?1) WHERE name = ?2",
+    )
+    .bind(table)
+    .bind(column)
+    .fetch_one(pool)
+    .await?
+        > 0;
+    if !exists {
+        sqlx::query(&format!("ALTER TABLE {} ADD COLUMN {} {}", table, column, definition))
+            .execute(pool)
+            .await?;
+    }
+    Ok(())
+}
+
+/// Encrypt history rows recorded before encryption was enabled
+async fn seal_plaintext_history(pool: &SqlitePool, cipher: &FieldCipher) -> Result<()> {
+    let mut sealed = 0;
+    for (table, columns) in SEALED_COLUMNS {
+        let rows = sqlx::query(&format!("SELECT rowid, {} FROM {}", columns.join(", "), table))
+            .fetch_all(pool)
+            .await?;
+
+        for row in rows {
+            let rowid: i64 = row.get(0);
+            for (i, column) in columns.iter().enumerate() {
+                let value: Option<String> = row.get(i + 1);
+                let Some(value) = value.filter(|v| !FieldCipher::is_sealed(v)) else {
+                    continue;
+                };
+                sqlx::query(&format!("UPDATE {} SET {} = ?1 WHERE rowid = ?2", table, column))
+                    .bind(cipher.seal(&value)?)
+                    .bind(rowid)
+                    .execute(pool)
+                    .await?;
+                sealed += 1;
+            }
+        }
+    }
+
+    let embeddings =
+        sqlx::query("SELECT id, embedding FROM command_patterns WHERE embedding IS NOT NULL")
+            .fetch_all(pool)
+            .await?;
+    for row in embeddings {
+        let embedding: Vec<u8> = row.get("embedding");
+        if std::str::from_utf8(&embedding).is_ok_and(FieldCipher::is_sealed) {
+            continue;
+        }
+        sqlx::query("UPDATE command_patterns SET embedding = ?1 WHERE id = ?2")
+            .bind(cipher.seal_bytes(&embedding)?)
+            .bind(row.get::<i64, _>("id"))
+            .execute(pool)
+            .await?;
+        sealed += 1;
+    }
+
+    if sealed > 0 {
+        tracing::info!("Encrypted {} learning history values stored in plaintext", sealed);
+    }
+    Ok(())
+}
+
+/// Fill in the lookup keys of patterns learned before they existed, and
+/// rehash those stored in plaintext once encryption is on
+async fn index_patterns(pool: &SqlitePool, cipher: &FieldCipher) -> Result<()> {
+    let rows =
+        sqlx::query("SELECT id, natural_input, learned_command, input_key FROM command_patterns")
+            .fetch_all(pool)
+            .await?;
+
+    let mut indexed = 0;
+    for row in rows {
+        let input_key: Option<&str> = row.get("input_key");
+        let current = match input_key {
+            Some(key) => !cipher.is_enabled() || FieldCipher::is_keyed_lookup(key),
+            None => false,
+        };
+        if current {
+            continue;
+        }
+        let input = cipher.open(row.get("natural_input"))?;
+        let command = cipher.open(row.get("learned_command"))?;
+        sqlx::query("UPDATE command_patterns SET input_key = ?1, command_key = ?2 WHERE id = ?3")
+            .bind(pattern_input_key(cipher, &input))
+            .bind(cipher.lookup_key(&command))
+            .bind(row.get::<i64, _>("id"))
+            .execute(pool)
+            .await?;
+        indexed += 1;
+    }
+
+    if indexed > 0 {
+        tracing::debug!("Indexed {} learned patterns", indexed);
+    }
+    Ok(())
+}
+
+/// What a pattern's natural input is looked up by: a keyed hash of the
+/// input with its whitespace collapsed, or that input itself when
+/// encryption is off
+fn pattern_input_key(cipher: &FieldCipher, input: &str) -> String {
+    cipher.lookup_key(&input.split_whitespace().collect::<Vec<_>>().join(" "))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -679,7 +1046,9 @@ ui:
 
         // Let SQLite create the database file itself (don't pre-create empty file)
         // The LearningEngine::new will initialize the database with proper schema
-        LearningEngine::new(config).await.unwrap()
+        let engine = LearningEngine::new(config).await.unwrap();
+        engine.warm_embeddings().await.unwrap();
+        engine
     }
 
     fn create_test_context() -> Context {
@@ -696,6 +1065,7 @@ ui:
             recent_commands: vec![],
             project_type: None,
             directory_type: DirectoryType::Temp,
+            host: None,
         }
     }
 
@@ -730,13 +1100,31 @@ ui:
     #[tokio::test]
     async fn test_embedding_model_initialization() {
         let engine = create_test_learning_engine().await;
+        let context = create_test_context();
+        assert!(engine.embeddings().is_some());
 
-        // Engine should have embeddings initialized (or None if failed)
-        // We don't assert on presence since it's optional
-        assert!(
-            engine.embeddings.is_some() || engine.embeddings.is_none(),
-            "Embeddings should be Some or None"
-        );
+        // Patterns learned before the model loads are embedded once it does
+        let cold = LearningEngine {
+            embeddings: Arc::new(OnceLock::new()),
+            ..engine.clone()
+        };
+        cold.record_success("show disk usage", "df -h", &context)
+            .await
+            .unwrap();
+        let unembedded = "SELECT COUNT(*) FROM command_patterns WHERE embedding IS NULL";
+        let count: i64 = sqlx::query_scalar(unembedded)
+            .fetch_one(&engine.pool)
+            .await
+            .unwrap();
+        assert_eq!(count, 1);
+
+        cold.warm_embeddings().await.unwrap();
+        assert!(cold.embeddings().is_some());
+        let count: i64 = sqlx::query_scalar(unembedded)
+            .fetch_one(&engine.pool)
+            .await
+            .unwrap();
+        assert_eq!(count, 0);
     }
 
     // ========== Pattern Recording Tests ==========
@@ -940,6 +1328,62 @@ ui:
         assert!(pattern.confidence >= 0.6);
     }
 
+    #[tokio::test]
+    async fn test_encrypted_patterns_are_sealed_and_still_found() {
+        let engine = create_test_learning_engine().await;
+        let context = create_test_context();
+        engine
+            .record_success("show disk usage", "df -h", &context)
+            .await
+            .unwrap();
+
+        // Turning encryption on seals and rehashes what was learned before
+        let sealed = LearningEngine {
+            cipher: FieldCipher::from_key(&[7u8; 32]),
+            ..engine.clone()
+        };
+        seal_plaintext_history(&sealed.pool, &sealed.cipher).await.unwrap();
+        index_patterns(&sealed.pool, &sealed.cipher).await.unwrap();
+        sealed
+            .record_success("list files", "ls -la", &context)
+            .await
+            .unwrap();
+
+        let rows: Vec<(String, String, String, String, Vec<u8>)> = sqlx::query_as(
+            "SELECT natural_input, learned_command, input_key, command_key, embedding FROM command_patterns",
+        )
+        .fetch_all(&engine.pool)
+        .await
+        .unwrap();
+        assert_eq!(rows.len(), 2);
+        for (input, command, input_key, command_key, embedding) in &rows {
+            let stored = [input, command, input_key, command_key].map(String::as_str).join(" ");
+            for plaintext in ["disk usage", "df -h", "list files", "ls -la"] {
+                assert!(!stored.contains(plaintext), "{} in {}", plaintext, stored);
+            }
+            assert!(embedding.starts_with(b"enc1:"));
+        }
+
+        // Found by embedding, and by keyed hash of the normalized input
+        let found = sealed.find_similar("list files", &context).await.unwrap();
+        assert_eq!(found.unwrap().learned_command, "ls -la");
+        let exact = sealed.find_exact_match("show  disk usage").await.unwrap().unwrap();
+        assert_eq!(exact.natural_input, "show disk usage");
+        assert_eq!(exact.learned_command, "df -h");
+
+        sealed
+            .record_failure("show disk usage", "df -h", &context)
+            .await
+            .unwrap();
+        let failures: i32 =
+            sqlx::query_scalar("SELECT failure_count FROM command_patterns WHERE input_key = ?1")
+                .bind(sealed.input_key("show disk usage"))
+                .fetch_one(&engine.pool)
+                .await
+                .unwrap();
+        assert_eq!(failures, 1);
+    }
+
     #[tokio::test]
     async fn test_find_no_match() {
         let engine = create_test_learning_engine().await;
@@ -1007,6 +1451,47 @@ ui:
         assert_eq!(count, 1, "Should have one correction recorded");
     }
 
+    #[tokio::test]
+    async fn test_purge_respects_retention_and_purge_all_clears_history() {
+        let engine = create_test_learning_engine().await;
+        let context = create_test_context();
+
+        engine
+            .record_execution("list files", "ls", 0, 5, &context)
+            .await
+            .unwrap();
+        engine
+            .record_execution("old", "rm -rf build", 0, 5, &context)
+            .await
+            .unwrap();
+        sqlx::query("UPDATE execution_history SET timestamp = datetime('now', '-120 days') WHERE input = 'old'")
+            .execute(&engine.pool)
+            .await
+            .unwrap();
+
+        let report = engine.purge_older_than(90).await.unwrap();
+        assert_eq!(report.rows_deleted["execution_history"], 1);
+        assert_eq!(report.rows_deleted["corrections"], 0);
+
+        engine
+            .record_correction("find files", "ls -la", "find . -name '*.txt'", &context)
+            .await
+            .unwrap();
+
+        let report = engine.purge_all().await.unwrap();
+        assert_eq!(report.rows_deleted["execution_history"], 1);
+        assert_eq!(report.rows_deleted["corrections"], 1);
+        assert!(report.total_rows() >= 3);
+
+        let remaining: i64 = sqlx::query_scalar(
+            "SELECT (SELECT COUNT(*) FROM execution_history) + (SELECT COUNT(*) FROM corrections) + (SELECT COUNT(*) FROM command_patterns)",
+        )
+        .fetch_one(&engine.pool)
+        .await
+        .unwrap();
+        assert_eq!(remaining, 0);
+    }
+
     #[tokio::test]
     async fn test_record_correction_penalizes_wrong_suggestion() {
         let engine = create_test_learning_engine().await;
@@ -1075,6 +1560,71 @@ ui:
         assert!(pattern.confidence >= 0.6);
     }
 
+    #[tokio::test]
+    async fn test_feedback_reasons_penalize_differently() {
+        let engine = create_test_learning_engine().await;
+        let context = create_test_context();
+
+        for (input, command) in [
+            ("clean build", "rm -rf /"),
+            ("show log", "git log"),
+            ("show history", "git log"),
+            ("list all", "ls"),
+        ] {
+            engine.record_success(input, command, &context).await.unwrap();
+        }
+        let confidence = |input: &'static str| {
+            sqlx::query_scalar::<_, f32>(
+                "SELECT confidence FROM command_patterns WHERE natural_input = ?1",
+            )
+            .bind(input)
+            .fetch_one(&engine.pool)
+        };
+
+        engine
+            .record_feedback(
+                "clean build",
+                "rm -rf /",
+                None,
+                &[FeedbackReason::Dangerous, FeedbackReason::WrongContext],
+                Some("wiped my disk"),
+                &context,
+            )
+            .await
+            .unwrap();
+        engine
+            .record_feedback(
+                "show log",
+                "git log",
+                None,
+                &[FeedbackReason::WrongContext],
+                None,
+                &context,
+            )
+            .await
+            .unwrap();
+        engine
+            .record_feedback("list all", "ls", None, &[], Some("fine"), &context)
+            .await
+            .unwrap();
+
+        // The harshest reason wins; wrong context only lowers this input
+        assert!((confidence("clean build").await.unwrap() - 0.18).abs() < 1e-4);
+        assert!((confidence("show log").await.unwrap() - 0.54).abs() < 1e-4);
+        assert!((confidence("show history").await.unwrap() - 0.6).abs() < 1e-4);
+        // A note alone changes nothing
+        assert!((confidence("list all").await.unwrap() - 0.6).abs() < 1e-4);
+
+        let (reasons, note): (Option<String>, Option<String>) = sqlx::query_as(
+            "SELECT reasons, note FROM corrections WHERE original_input = 'clean build'",
+        )
+        .fetch_one(&engine.pool)
+        .await
+        .unwrap();
+        assert_eq!(reasons.as_deref(), Some("dangerous,wrong_context"));
+        assert_eq!(note.as_deref(), Some("wiped my disk"));
+    }
+
     // ========== Temporal Pattern Tests ==========
 
     #[tokio::test]
@@ -1269,4 +1819,94 @@ ui:
             stats.success_rate
         );
     }
+
+    #[tokio::test]
+    async fn test_search_history_finds_command_by_description() {
+        let engine = create_test_learning_engine().await;
+        let context = create_test_context();
+
+        for (input, command) in [
+            ("docker volume prune -f", "docker volume prune -f"),
+            ("list containers", "docker ps -a"),
+            ("show git log", "git log --oneline"),
+            ("docker volume prune -f", "docker volume prune -f"),
+        ] {
+            engine
+                .record_execution(input, command, 0, 0, &context)
+                .await
+                .unwrap();
+        }
+
+        let matches = engine
+            .search_history("that docker command I ran last week that pruned volumes", 10)
+            .await
+            .unwrap();
+
+        assert_eq!(matches[0].command, "docker volume prune -f");
+        assert_eq!(matches[0].runs, 2, "Repeated runs should be merged");
+        assert_eq!(
+            matches[0].context.as_ref().map(|c| c.pwd.clone()),
+            Some(std::path::PathBuf::from("/tmp"))
+        );
+        assert!(matches.iter().all(|m| m.command != "git log --oneline"));
+
+        let matches = engine.search_history("yesterday", 10).await.unwrap();
+        assert!(matches.is_empty(), "Nothing was run yesterday");
+    }
+
+    #[tokio::test]
+    async fn test_playbooks_are_mined_saved_and_triggered_per_project() {
+        let engine = create_test_learning_engine().await;
+        let mut project = create_test_context();
+        project.pwd = std::path::PathBuf::from("/src/orbit");
+        project.directory_type = crate::context::DirectoryType::Project;
+        let mut other_project = project.clone();
+        other_project.pwd = std::path::PathBuf::from("/src/other");
+
+        let routine = ["git pull", "cargo build", "cargo test"];
+        for _ in 0..3 {
+            for command in routine {
+                engine
+                    .record_execution(command, command, 0, 0, &project)
+                    .await
+                    .unwrap();
+            }
+            engine
+                .record_execution("make", "make", 0, 0, &other_project)
+                .await
+                .unwrap();
+        }
+
+        let candidates = engine.playbook_candidates(&project).await.unwrap();
+        assert_eq!(candidates[0].commands, routine);
+        assert_eq!(candidates[0].occurrences, 3);
+
+        engine
+            .save_playbook(&project, "refresh", "Update and test", &candidates[0].commands)
+            .await
+            .unwrap();
+
+        let playbook = engine
+            .match_playbook("update and  test", &project)
+            .await
+            .unwrap()
+            .expect("trigger should match");
+        assert_eq!(playbook.script(), "git pull && cargo build && cargo test");
+        assert_eq!(engine.list_playbooks(&project).await.unwrap()[0].run_count, 1);
+
+        assert!(engine
+            .match_playbook("update and test", &other_project)
+            .await
+            .unwrap()
+            .is_none());
+        assert!(engine
+            .playbook_candidates(&project)
+            .await
+            .unwrap()
+            .iter()
+            .all(|c| c.commands != routine));
+
+        assert!(engine.delete_playbook(&project, "refresh").await.unwrap());
+        assert!(engine.list_playbooks(&project).await.unwrap().is_empty());
+    }
 }
@@ -0,0 +1,394 @@
+// Per-project command playbooks
+//
+// The miner looks through command history for sequences the user keeps
+// repeating in a project (`git pull`, `cargo build`, `cargo test`) and
+// offers them as candidates. Saving a candidate gives it a name and a
+// natural-language trigger ("update and test"); typing the trigger in the
+// same project then runs the whole sequence.
+//
+// Playbooks are stored per Context::project_fingerprint, so one trigger
+// can mean different things in different projects. They are things the
+// user chose to keep, so purging learning data leaves them alone.
+
+use anyhow::{bail, Result};
+use chrono::NaiveDateTime;
+use sqlx::sqlite::SqlitePool;
+use sqlx::Row;
+use std::collections::{HashMap, HashSet};
+
+use super::types::{Playbook, PlaybookCandidate};
+use super::{LearningEngine, SQLITE_TIMESTAMP};
+use crate::context::Context;
+use crate::embeddings::EmbeddingModel;
+
+/// History rows mined per request, newest first
+const MINE_LIMIT: i64 = 5000;
+
+/// A pause this long starts a new sequence
+const SESSION_GAP_SECS: i64 = 30 * 60;
+
+/// Times a sequence must repeat before it is offered
+const MIN_OCCURRENCES: u32 = 3;
+
+/// Sequence lengths considered
+const MIN_STEPS: usize = 2;
+const MAX_STEPS: usize = 6;
+
+/// Candidates offered per request
+const MAX_CANDIDATES: usize = 10;
+
+/// Similarity needed for input that isn't the exact trigger
+const TRIGGER_SIMILARITY: f32 = 0.85;
+
+pub(super) async fn create_table(pool: &SqlitePool) -> Result<()> {
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS playbooks (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            project TEXT NOT NULL,
+            name TEXT NOT NULL,
+            trigger TEXT NOT NULL,
+            commands TEXT NOT NULL,
+            run_count INTEGER DEFAULT 0,
+            created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+            UNIQUE(project, name)
+        )
+        "#,
+    )
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+impl Playbook {
+    /// The sequence as one shell command line, stopping at the first failure
+    pub fn script(&self) -> String {
+        self.commands.join(" && ")
+    }
+}
+
+fn normalize(text: &str) -> String {
+    text.split_whitespace()
+        .map(str::to_lowercase)
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Repeated sequences in `sessions`, longest and most frequent first
+fn mine_sequences(sessions: &[Vec<String>]) -> Vec<PlaybookCandidate> {
+    let mut counts: HashMap<&[String], u32> = HashMap::new();
+    for session in sessions {
+        for len in MIN_STEPS..=MAX_STEPS.min(session.len()) {
+            for window in session.windows(len) {
+                // A sequence that revisits a command is a loop, not a routine
+                let distinct: HashSet<_> = window.iter().collect();
+                if distinct.len() == len {
+                    *counts.entry(window).or_default() += 1;
+                }
+            }
+        }
+    }
+
+    let frequent: Vec<(&[String], u32)> = counts
+        .into_iter()
+        .filter(|(_, count)| *count >= MIN_OCCURRENCES)
+        .collect();
+
+    // Offer `a b c` rather than also `a b` and `b c` when those only ever
+    // ran as part of it
+    let mut candidates: Vec<PlaybookCandidate> = frequent
+        .iter()
+        .filter(|(seq, count)| {
+            !frequent.iter().any(|(longer, longer_count)| {
+                longer.len() > seq.len()
+                    && longer_count >= count
+                    && longer.windows(seq.len()).any(|w| w == *seq)
+            })
+        })
+        .map(|(seq, count)| PlaybookCandidate {
+            suggested_name: suggest_name(seq),
+            commands: seq.to_vec(),
+            occurrences: *count,
+        })
+        .collect();
+
+    candidates.sort_by(|a, b| {
+        (b.commands.len() as u32 * b.occurrences)
+            .cmp(&(a.commands.len() as u32 * a.occurrences))
+            .then_with(|| a.commands.cmp(&b.commands))
+    });
+    candidates
+}
+
+/// Name from each step's subcommand, e.g. `pull-build-test`
+fn suggest_name(commands: &[String]) -> String {
+    let mut words: Vec<&str> = Vec::new();
+    for command in commands {
+        let mut parts = command.split_whitespace();
+        let program = parts.next().unwrap_or_default();
+        let word = match parts.next() {
+            Some(sub) if sub.chars().all(|c| c.is_ascii_alphanumeric() || c == '-') => sub,
+            _ => program.rsplit('/').next().unwrap_or(program),
+        };
+        if !words.contains(&word) {
+            words.push(word);
+        }
+    }
+    words.join("-")
+}
+
+impl LearningEngine {
+    /// Command sequences repeated in the current project that aren't
+    /// saved as playbooks yet
+    pub async fn playbook_candidates(&self, context: &Context) -> Result<Vec<PlaybookCandidate>> {
+        let Some(project) = context.project_fingerprint() else {
+            return Ok(Vec::new());
+        };
+
+        let rows = sqlx::query(
+            r#"
+            SELECT executed_command, exit_code, context, timestamp
+            FROM execution_history
+            ORDER BY id DESC
+            LIMIT ?1
+            "#,
+        )
+        .bind(MINE_LIMIT)
+        .fetch_all(&self.pool)
+        .await?;
+
+        // Split this project's history into sessions of consecutive
+        // successful commands
+        let mut sessions: Vec<Vec<String>> = Vec::new();
+        let mut current: Vec<String> = Vec::new();
+        let mut last_time: Option<NaiveDateTime> = None;
+        for row in rows.iter().rev() {
+            let stored_context: Option<String> = row.get("context");
+            let in_project = self
+                .cipher
+                .open_opt(stored_context.as_deref())?
+                .and_then(|json| serde_json::from_str::<Context>(&json).ok())
+                .and_then(|context| context.project_fingerprint())
+                .is_some_and(|fingerprint| fingerprint == project);
+            if !in_project {
+                continue;
+            }
+
+            let timestamp: String = row.get("timestamp");
+            let time = NaiveDateTime::parse_from_str(&timestamp, SQLITE_TIMESTAMP).ok();
+            let exit_code: Option<i32> = row.get("exit_code");
+            let gap = match (last_time, time) {
+                (Some(last), Some(time)) => (time - last).num_seconds() > SESSION_GAP_SECS,
+                _ => false,
+            };
+            last_time = time;
+
+            if gap || exit_code != Some(0) {
+                sessions.push(std::mem::take(&mut current));
+            }
+            if exit_code == Some(0) {
+                let command = self.cipher.open(row.get::<&str, _>("executed_command"))?;
+                // Running the same command twice in a row is one step
+                if current.last() != Some(&command) {
+                    current.push(command);
+                }
+            }
+        }
+        sessions.push(current);
+
+        let saved: Vec<Vec<String>> = self
+            .load_playbooks(&project)
+            .await?
+            .into_iter()
+            .map(|playbook| playbook.commands)
+            .collect();
+
+        let mut candidates = mine_sequences(&sessions);
+        candidates.retain(|candidate| !saved.contains(&candidate.commands));
+        candidates.truncate(MAX_CANDIDATES);
+        Ok(candidates)
+    }
+
+    /// Save (or replace) a playbook for the current project
+    pub async fn save_playbook(
+        &self,
+        context: &Context,
+        name: &str,
+        trigger: &str,
+        commands: &[String],
+    ) -> Result<Playbook> {
+        let Some(project) = context.project_fingerprint() else {
+            bail!("Playbooks can only be saved inside a project directory");
+        };
+        let name = name.trim();
+        let trigger = normalize(trigger);
+        if name.is_empty() || trigger.is_empty() {
+            bail!("A playbook needs a name and a trigger");
+        }
+        if commands.is_empty() || commands.iter().any(|c| c.trim().is_empty()) {
+            bail!("A playbook needs at least one command");
+        }
+
+        sqlx::query(
+            r#"
+            INSERT INTO playbooks (project, name, trigger, commands)
+            VALUES (?1, ?2, ?3, ?4)
+            ON CONFLICT(project, name) DO UPDATE SET
+                trigger = excluded.trigger,
+                commands = excluded.commands
+            "#,
+        )
+        .bind(&project)
+        .bind(name)
+        .bind(&trigger)
+        .bind(self.cipher.seal(&serde_json::to_string(commands)?)?)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(Playbook {
+            name: name.to_string(),
+            trigger,
+            commands: commands.to_vec(),
+            run_count: 0,
+        })
+    }
+
+    /// Playbooks saved for the current project
+    pub async fn list_playbooks(&self, context: &Context) -> Result<Vec<Playbook>> {
+        match context.project_fingerprint() {
+            Some(project) => self.load_playbooks(&project).await,
+            None => Ok(Vec::new()),
+        }
+    }
+
+    /// Delete a playbook from the current project; false if there was none
+    pub async fn delete_playbook(&self, context: &Context, name: &str) -> Result<bool> {
+        let Some(project) = context.project_fingerprint() else {
+            return Ok(false);
+        };
+        let result = sqlx::query("DELETE FROM playbooks WHERE project = ?1 AND name = ?2")
+            .bind(project)
+            .bind(name.trim())
+            .execute(&self.pool)
+            .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// Playbook whose trigger (or name) matches `input` in the current
+    /// project, counting it as run
+    pub async fn match_playbook(&self, input: &str, context: &Context) -> Result<Option<Playbook>> {
+        let Some(project) = context.project_fingerprint() else {
+            return Ok(None);
+        };
+        let playbooks = self.load_playbooks(&project).await?;
+        if playbooks.is_empty() {
+            return Ok(None);
+        }
+
+        let input = normalize(input);
+        let mut best = playbooks
+            .iter()
+            .find(|p| p.trigger == input || p.name.to_lowercase() == input);
+
+        if best.is_none() {
+            if let Some(model) = self.embeddings() {
+                let query = model.embed(&input)?;
+                let mut best_score = TRIGGER_SIMILARITY;
+                for playbook in &playbooks {
+                    let score =
+                        EmbeddingModel::cosine_similarity(&query, &model.embed(&playbook.trigger)?);
+                    if score >= best_score {
+                        best_score = score;
+                        best = Some(playbook);
+                    }
+                }
+            }
+        }
+
+        let Some(playbook) = best.cloned() else {
+            return Ok(None);
+        };
+        sqlx::query(
+            "UPDATE playbooks SET run_count = run_count + 1 WHERE project = ?1 AND name = ?2",
+        )
+        .bind(&project)
+        .bind(&playbook.name)
+        .execute(&self.pool)
+        .await?;
+        Ok(Some(playbook))
+    }
+
+    async fn load_playbooks(&self, project: &str) -> Result<Vec<Playbook>> {
+        let rows = sqlx::query(
+            "SELECT name, trigger, commands, run_count FROM playbooks WHERE project = ?1 ORDER BY name",
+        )
+        .bind(project)
+        .fetch_all(&self.pool)
+        .await?;
+
+        rows.iter()
+            .map(|row| {
+                let commands = self.cipher.open(row.get::<&str, _>("commands"))?;
+                Ok(Playbook {
+                    name: row.get("name"),
+                    trigger: row.get("trigger"),
+                    commands: serde_json::from_str(&commands)?,
+                    run_count: row.get("run_count"),
+                })
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn session(commands: &[&str]) -> Vec<String> {
+        commands.iter().map(|c| c.to_string()).collect()
+    }
+
+    #[test]
+    fn test_mine_sequences_prefers_the_whole_routine() {
+        let routine = ["git pull", "cargo build", "cargo test"];
+        let sessions = vec![
+            session(&["ls", "git pull", "cargo build", "cargo test", "git push"]),
+            session(&routine),
+            session(&["git pull", "cargo build", "cargo test", "vim src/main.rs"]),
+            session(&["cargo build", "cargo test"]),
+        ];
+
+        let candidates = mine_sequences(&sessions);
+        assert_eq!(candidates[0].commands, session(&routine));
+        assert_eq!(candidates[0].occurrences, 3);
+        assert_eq!(candidates[0].suggested_name, "pull-build-test");
+
+        // `cargo build && cargo test` also ran on its own, so it stays
+        assert!(candidates
+            .iter()
+            .any(|c| c.commands == session(&["cargo build", "cargo test"]) && c.occurrences == 4));
+        // `git pull && cargo build` never did
+        assert!(!candidates
+            .iter()
+            .any(|c| c.commands == session(&["git pull", "cargo build"])));
+    }
+
+    #[test]
+    fn test_mine_sequences_ignores_rare_and_looping_sequences() {
+        let sessions = vec![
+            session(&[
+                "make",
+                "make check",
+                "make",
+                "make check",
+                "make",
+                "make check",
+            ]),
+            session(&["npm install", "npm test"]),
+            session(&["npm install", "npm test"]),
+        ];
+        let candidates = mine_sequences(&sessions);
+        assert_eq!(candidates.len(), 1);
+        assert_eq!(candidates[0].commands, session(&["make", "make check"]));
+    }
+}
@@ -1,6 +1,9 @@
+use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 
+use crate::context::Context;
+
 /// Command execution record for analytics
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct CommandExecution {
@@ -28,6 +31,44 @@ pub enum ExecutionResult {
     Edited,
 }
 
+/// Why a suggestion was turned down, given with negative feedback
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FeedbackReason {
+    /// Not the command that was asked for
+    WrongCommand,
+    /// Would have done harm
+    Dangerous,
+    /// Right command, wrong directory, project or host
+    WrongContext,
+}
+
+impl FeedbackReason {
+    /// Stored in the corrections table, comma-separated
+    pub fn code(self) -> &'static str {
+        match self {
+            FeedbackReason::WrongCommand => "wrong_command",
+            FeedbackReason::Dangerous => "dangerous",
+            FeedbackReason::WrongContext => "wrong_context",
+        }
+    }
+
+    /// Factor applied to the confidence of the suggestion
+    pub fn confidence_factor(self) -> f32 {
+        match self {
+            FeedbackReason::WrongCommand => 0.7,
+            FeedbackReason::Dangerous => 0.3,
+            FeedbackReason::WrongContext => 0.9,
+        }
+    }
+
+    /// Whether the suggestion is only wrong for this input, rather than
+    /// for every input it was learned for
+    pub fn input_only(self) -> bool {
+        matches!(self, FeedbackReason::WrongContext)
+    }
+}
+
 /// Command context for analysis
 #[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct CommandContext {
@@ -101,3 +142,54 @@ pub struct LearningExport {
     pub patterns: Vec<Pattern>,
     pub analytics_summary: AnalyticsSummary,
 }
+
+/// What a purge of learning data removed
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct PurgeReport {
+    /// Rows deleted per table
+    pub rows_deleted: HashMap<String, u64>,
+    /// Backup files deleted because they still held the purged rows
+    pub backups_deleted: Vec<String>,
+}
+
+impl PurgeReport {
+    /// Total rows deleted across all tables
+    pub fn total_rows(&self) -> u64 {
+        self.rows_deleted.values().sum()
+    }
+}
+
+/// A past command matching a history search
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HistoryMatch {
+    pub command: String,
+    /// What the user typed that led to the command
+    pub input: String,
+    /// Most recent matching run
+    pub timestamp: DateTime<Utc>,
+    pub exit_code: Option<i32>,
+    /// Working directory, git state and so on at the time of that run
+    pub context: Option<Context>,
+    pub score: f32,
+    /// Matching runs of the same command
+    pub runs: u32,
+}
+
+/// A named command sequence saved for one project
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Playbook {
+    pub name: String,
+    /// Natural-language input that runs the playbook
+    pub trigger: String,
+    pub commands: Vec<String>,
+    pub run_count: i64,
+}
+
+/// A repeated command sequence that could be saved as a playbook
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PlaybookCandidate {
+    pub commands: Vec<String>,
+    /// Times the sequence ran in this project
+    pub occurrences: u32,
+    pub suggested_name: String,
+}
@@ -1,22 +1,28 @@
 // Library exports for testing and CLI tool
 pub mod autostart;
 pub mod classifier;
+pub mod completion;
 pub mod config;
+pub mod connectivity;
 pub mod context;
 pub mod credentials;
 pub mod daemon;
 pub mod embeddings;
 pub mod executor;
+pub mod extensions;
 pub mod learning;
 pub mod license;
 pub mod monitor;
+pub mod notifications;
 pub mod prompts;
 pub mod providers;
+pub mod readiness;
+pub mod security;
 pub mod service;
 pub mod session;
 
 // Re-export commonly used types for CLI
 pub use daemon::ipc::{
-    FeedbackResult, ProtocolVersion, Request, Response,
+    DatabaseAction, FeedbackResult, ProtocolVersion, Request, Response,
     VersionedRequest, VersionedResponse, PROTOCOL_VERSION,
 };
@@ -10,6 +10,7 @@ use std::path::PathBuf;
 use tracing::{info, warn};
 
 use crate::config::Config;
+use crate::connectivity::Connectivity;
 
 #[derive(Clone)]
 pub struct LicenseManager {
@@ -81,6 +82,55 @@ impl LicenseManager {
         }
     }
 
+    /// Validate, except that while offline a license verified within the
+    /// last `grace_days` keeps working without the server; the deferral is
+    /// recorded on `connectivity` for status reports
+    pub async fn validate_or_defer(
+        &self,
+        connectivity: &Connectivity,
+        grace_days: u32,
+    ) -> Result<()> {
+        let error = if connectivity.is_online() {
+            match self.validate().await {
+                Ok(()) => {
+                    connectivity.defer_license(None);
+                    return Ok(());
+                }
+                Err(e) => e,
+            }
+        } else {
+            anyhow!("Orbit is offline")
+        };
+
+        // An unreachable license server may be the first sign of an outage
+        if connectivity.is_online() && connectivity.probe().await {
+            return Err(error);
+        }
+
+        let cached = self
+            .load_cached_license()
+            .ok()
+            .filter(|cached| self.config_license_key.as_deref() == Some(cached.key.as_str()));
+        let Some(cached) = cached else {
+            return Err(error);
+        };
+        if self.is_license_valid(&cached) {
+            return Ok(());
+        }
+        match offline_grace_until(&cached, Duration::days(grace_days.into())) {
+            Some(until) => {
+                warn!("Offline; license re-verification deferred until {}", until);
+                connectivity.defer_license(Some(until));
+                Ok(())
+            }
+            None => Err(anyhow!(
+                "{} (offline past the {}-day license grace window)",
+                error,
+                grace_days
+            )),
+        }
+    }
+
     #[allow(dead_code)]
     pub fn last_verified(&self) -> String {
         if let Ok(cached) = self.load_cached_license() {
@@ -97,6 +147,11 @@ impl LicenseManager {
         }
     }
 
+    /// When the cached license expires; None before it is first verified
+    pub fn expires_at(&self) -> Option<DateTime<Utc>> {
+        self.load_cached_license().ok().map(|cached| cached.expires_at)
+    }
+
     fn is_license_valid(&self, license: &CachedLicense) -> bool {
         let now = Utc::now();
         let age = now - license.verified_at;
@@ -162,16 +217,8 @@ impl LicenseManager {
         // Simple encryption with machine-specific key
         let encrypted = self.encrypt_license_data(&data)?;
 
-        std::fs::write(&self.cache_path, encrypted)?;
-
-        // Set restrictive permissions
-        #[cfg(unix)]
-        {
-            use std::os::unix::fs::PermissionsExt;
-            let mut perms = std::fs::metadata(&self.cache_path)?.permissions();
-            perms.set_mode(0o600);
-            std::fs::set_permissions(&self.cache_path, perms)?;
-        }
+        // Readable by the owner only
+        pulsar_fs::write_private(&self.cache_path, encrypted)?;
 
         Ok(())
     }
@@ -245,6 +292,14 @@ impl LicenseManager {
     }
 }
 
+/// Until when `license` keeps working unverified while offline: `grace`
+/// past its last verification, but never past its expiry. None once that
+/// has passed.
+fn offline_grace_until(license: &CachedLicense, grace: Duration) -> Option<DateTime<Utc>> {
+    let until = (license.verified_at + grace).min(license.expires_at);
+    (until > Utc::now()).then_some(until)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -263,16 +318,20 @@ mod tests {
                 socket_path: "/tmp/orbit-test.sock".into(),
                 log_level: "info".to_string(),
                 auto_restart: true,
+                log_file: None,
             },
             provider_mode: crate::config::ProviderMode::Manual,
             default_provider: "test".to_string(),
             providers: std::collections::HashMap::new(),
+            profiles: std::collections::HashMap::new(),
             auto_routing: None,
             learning: crate::config::LearningConfig {
                 enabled: true,
                 confidence_threshold: 0.7,
                 max_patterns: 10000,
                 embedding_model: "minilm-l6-v2".to_string(),
+                encrypt_history: false,
+                retention_days: 90,
             },
             monitoring: crate::config::MonitoringConfig {
                 enabled: true,
@@ -285,11 +344,21 @@ mod tests {
                 natural_language_threshold: 0.8,
                 check_path_binaries: true,
                 cache_known_commands: true,
+                refresh_interval_seconds: 30,
+                languages: Vec::new(),
+                plugins_dir: None,
             },
+            extensions: crate::config::ExtensionsConfig::default(),
             execution: crate::config::ExecutionConfig {
                 auto_approve: false,
                 confirm_destructive: true,
                 timeout_seconds: 300,
+                allow_elevation: true,
+                pulsar_socket: None,
+                explain_tiers: vec![
+                    crate::executor::risk::RiskTier::Medium,
+                    crate::executor::risk::RiskTier::High,
+                ],
             },
             context: crate::config::ContextConfig {
                 track_directory_patterns: true,
@@ -304,6 +373,9 @@ mod tests {
                 show_provider: false,
                 show_learning_stats: true,
             },
+            webhooks: pulsar_webhook::WebhooksConfig::default(),
+            notifications: crate::config::NotificationsConfig::default(),
+            connectivity: crate::config::ConnectivityConfig::default(),
         }
     }
 
@@ -724,4 +796,32 @@ mod tests {
         assert_eq!(deserialized.company, "Test Company");
         assert_eq!(deserialized.features.len(), 2);
     }
+
+    #[test]
+    fn test_offline_grace_window() {
+        let license = |verified_days_ago: i64, expires_in_days: i64| CachedLicense {
+            key: "test-key".to_string(),
+            company: "Test Corp".to_string(),
+            user: "test@example.com".to_string(),
+            verified_at: Utc::now() - Duration::days(verified_days_ago),
+            expires_at: Utc::now() + Duration::days(expires_in_days),
+            features: vec!["all".to_string()],
+        };
+
+        let fresh = license(2, 365);
+        assert_eq!(
+            offline_grace_until(&fresh, Duration::days(7)),
+            Some(fresh.verified_at + Duration::days(7))
+        );
+        // Never past expiry
+        let expiring = license(2, 1);
+        assert_eq!(
+            offline_grace_until(&expiring, Duration::days(7)),
+            Some(expiring.expires_at)
+        );
+        assert_eq!(
+            offline_grace_until(&license(8, 365), Duration::days(7)),
+            None
+        );
+    }
 }
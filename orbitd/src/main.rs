@@ -1,46 +1,98 @@
 use anyhow::Result;
+use std::time::Duration;
 use tokio::signal;
 use tracing::{error, info};
 
 mod classifier;
+mod completion;
 mod config;
+mod connectivity;
 mod context;
 mod credentials;
 mod daemon;
 mod embeddings;
 mod executor;
+mod extensions;
 mod learning;
 mod license;
 mod monitor;
+mod notifications;
 mod prompts;
 mod providers;
+mod readiness;
+mod security;
 
 use crate::config::Config;
+use crate::connectivity::Connectivity;
+use crate::daemon::mcp::McpServer;
+use crate::daemon::rpc::RpcServer;
 use crate::daemon::Daemon;
 use crate::license::LicenseManager;
 
 #[tokio::main]
 async fn main() -> Result<()> {
-    // Initialize logging
-    tracing_subscriber::fmt()
-        .with_target(false)
-        .with_thread_ids(false)
-        .with_file(true)
-        .with_line_number(true)
-        .init();
+    let args: Vec<String> = std::env::args().skip(1).collect();
 
-    info!("🛸 Orbit Daemon starting...");
+    // Shell completion bridge: print a script, or answer one request for it.
+    // Handled before logging so nothing else reaches stdout.
+    if let Some(shell) = flag_value(&args, "--completions") {
+        match completion::scripts::bridge_script(shell) {
+            Some(script) => print!("{}", script),
+            None => {
+                eprintln!("Unsupported shell '{}' (expected bash, zsh or fish)", shell);
+                std::process::exit(2);
+            }
+        }
+        return Ok(());
+    }
+    if let Some(line) = flag_value(&args, "--complete") {
+        return print_completions(line, flag_value(&args, "--cwd")).await;
+    }
+    if let Some(query) = flag_value(&args, "--search") {
+        return print_history_matches(query).await;
+    }
+    if let Some(provider) = flag_value(&args, "--set-api-key") {
+        return store_api_key(provider, flag_value(&args, "--profile")).await;
+    }
+    if let Some(dataset) = flag_value(&args, "--evaluate") {
+        return print_evaluation(dataset, flag_value(&args, "--threshold")).await;
+    }
+
+    // `--stdio` serves a single editor over JSON-RPC instead of the socket,
+    // and `--mcp` a single AI agent over the Model Context Protocol
+    let stdio = args.iter().any(|arg| arg == "--stdio");
+    let mcp = args.iter().any(|arg| arg == "--mcp");
 
-    // Load configuration
+    // Load configuration; it says how to log
     let config = Config::load().await?;
+
+    // Initialize logging (console, plus the rotating log file if configured)
+    let _log_guard = pulsar_log::init(pulsar_log::LogOptions {
+        name: "orbitd",
+        filter: &config.daemon.log_level,
+        file: config.daemon.log_file.as_ref(),
+        // stdout carries JSON-RPC messages
+        stderr: stdio || mcp,
+    })?;
+
+    info!("🛸 Orbit Daemon starting...");
     info!("Configuration loaded");
 
+    if args.iter().any(|arg| arg == "--test-email") {
+        return send_test_email(&config).await;
+    }
+
     // Validate license (CRITICAL - must pass before any operation)
     if !config.is_development_mode() {
         info!("Validating license...");
         let license_manager = LicenseManager::new(&config)?;
+        // Offline, a recently verified license keeps working
+        let connectivity = Connectivity::new(&config.connectivity);
 
-        if let Err(e) = license_manager.validate().await {
+        if let Err(e) = license_manager
+            .validate_or_defer(&connectivity, config.connectivity.license_grace_days)
+            .await
+        {
             error!("License validation failed: {}", e);
             eprintln!("❌ License validation failed: {}", e);
             eprintln!("For development mode, set ORBIT_DEV_MODE=1");
@@ -53,14 +105,34 @@ async fn main() -> Result<()> {
         info!("Running in development mode - license validation skipped");
     }
 
+    if stdio {
+        return RpcServer::new(config).await?.serve_stdio().await;
+    }
+    if mcp {
+        return McpServer::new(config).await?.serve_stdio().await;
+    }
+
+    // The socket, when systemd bound it for us (socket activation)
+    #[cfg(unix)]
+    let activated_socket = pulsar_service::systemd::activated_listener()?;
+
     // Initialize daemon
     let mut daemon = Daemon::new(config).await?;
+    #[cfg(unix)]
+    if let Some(listener) = activated_socket {
+        daemon = daemon.with_listener(listener);
+    }
     info!("Daemon initialized");
 
     // Start daemon
     daemon.start().await?;
     info!("✓ Orbit Daemon started (PID: {})", std::process::id());
 
+    // Tell the service manager we are serving, and keep its watchdog fed
+    let service = pulsar_service::ServiceManager::detect();
+    service.ready();
+    let watchdog = service.spawn_watchdog();
+
     // Wait for shutdown signal
     tokio::select! {
         _ = signal::ctrl_c() => {
@@ -72,12 +144,171 @@ async fn main() -> Result<()> {
     }
 
     // Graceful shutdown
+    service.stopping("Shutting down", Duration::from_secs(30));
+    if let Some(watchdog) = watchdog {
+        watchdog.abort();
+    }
     daemon.stop().await?;
     info!("Daemon stopped gracefully");
 
     Ok(())
 }
 
+/// Mail a test message through `notifications.email`
+async fn send_test_email(config: &Config) -> Result<()> {
+    let Some(email) = &config.notifications.email else {
+        eprintln!("No email alerts configured (notifications.email)");
+        std::process::exit(2);
+    };
+    notifications::email::EmailNotifier::new(email)?
+        .send_test()
+        .await?;
+    println!("✓ Test email sent to {}", email.to.join(", "));
+    Ok(())
+}
+
+/// Store an API key read from stdin in the keychain, for `provider` or for
+/// its use in `profile`
+async fn store_api_key(provider: &str, profile: Option<&str>) -> Result<()> {
+    let config = Config::load().await?;
+    let account = match profile {
+        Some(profile) if !config.profiles.contains_key(profile) => {
+            anyhow::bail!("No profile '{}' in the configuration", profile)
+        }
+        Some(profile) => credentials::CredentialStore::profile_account(provider, profile),
+        None => provider.to_string(),
+    };
+
+    let mut api_key = String::new();
+    std::io::stdin().read_line(&mut api_key)?;
+    let api_key = api_key.trim();
+    if api_key.is_empty() {
+        anyhow::bail!("No API key on stdin");
+    }
+    credentials::CredentialStore::new().set_api_key(&account, api_key)?;
+    match profile {
+        Some(profile) => println!("✓ {} API key stored for profile '{}'", provider, profile),
+        None => println!("✓ {} API key stored", provider),
+    }
+    Ok(())
+}
+
+/// Replay a labeled dataset through the classifier and print the report
+/// as JSON; with a candidate threshold, compare it against the configured
+/// one
+async fn print_evaluation(dataset: &str, threshold: Option<&str>) -> Result<()> {
+    use crate::classifier::{evaluation, CommandClassifier, PluginRegistry};
+    use crate::context::ContextEngine;
+    use crate::learning::LearningEngine;
+    use std::sync::Arc;
+
+    let candidate = threshold
+        .map(|value| match value.parse::<f32>() {
+            Ok(threshold) if (0.0..=1.0).contains(&threshold) => Ok(threshold),
+            _ => Err(anyhow::anyhow!(
+                "--threshold must be between 0 and 1, got '{}'",
+                value
+            )),
+        })
+        .transpose()?;
+    let examples = evaluation::load_dataset(std::path::Path::new(dataset))?;
+
+    let config = Arc::new(Config::load().await?);
+    let learning_engine = Arc::new(LearningEngine::new(config.clone()).await?);
+    learning_engine.warm_embeddings().await?;
+    let plugins = Arc::new(PluginRegistry::from_dir(config.plugins_dir()?)?);
+    let classifier = CommandClassifier::new(config.clone(), learning_engine)
+        .await?
+        .with_plugins(plugins);
+    if config.classification.cache_known_commands {
+        classifier.warm().await?;
+    }
+    let context = ContextEngine::new(config.clone()).await?.get_context().await?;
+
+    let evaluation = evaluation::replay(&classifier, &context, examples).await?;
+    let baseline = config.learning.confidence_threshold;
+    let report = match candidate {
+        Some(candidate) => serde_json::to_value(evaluation.compare(baseline, candidate))?,
+        None => serde_json::to_value(evaluation.report(baseline))?,
+    };
+    let output = serde_json::json!({
+        "report": report,
+        "calibration": evaluation.calibration(),
+        "calibration_error": evaluation.calibration_error(),
+        "sweep": evaluation.sweep(),
+    });
+    println!("{}", serde_json::to_string_pretty(&output)?);
+    Ok(())
+}
+
+/// Value following `flag` on the command line
+fn flag_value<'a>(args: &'a [String], flag: &str) -> Option<&'a str> {
+    args.iter()
+        .position(|arg| arg == flag)
+        .and_then(|i| args.get(i + 1))
+        .map(String::as_str)
+}
+
+/// Print completions from the running daemon as `text<TAB>description` lines
+#[cfg(unix)]
+async fn print_completions(line: &str, cwd: Option<&str>) -> Result<()> {
+    let config = Config::load().await?;
+    let cwd = match cwd {
+        Some(cwd) => std::path::PathBuf::from(cwd),
+        None => std::env::current_dir()?,
+    };
+
+    for item in completion::request(&config.daemon.socket_path, line, &cwd).await? {
+        match item.description {
+            Some(description) => println!("{}\t{}", item.text, description),
+            None => println!("{}", item.text),
+        }
+    }
+    Ok(())
+}
+
+#[cfg(not(unix))]
+async fn print_completions(_line: &str, _cwd: Option<&str>) -> Result<()> {
+    anyhow::bail!("Shell completion is only available on Unix")
+}
+
+/// Print history matching `query` as `time<TAB>command<TAB>directory` lines
+#[cfg(unix)]
+async fn print_history_matches(query: &str) -> Result<()> {
+    use crate::daemon::ipc::{call, Request, Response};
+
+    let config = Config::load().await?;
+    let request = Request::SearchHistory {
+        query: query.to_string(),
+        limit: None,
+    };
+
+    match call(&config.daemon.socket_path, &request).await? {
+        Response::History { matches } => {
+            for item in matches {
+                let dir = item
+                    .context
+                    .map(|context| context.pwd.display().to_string())
+                    .unwrap_or_default();
+                println!(
+                    "{}\t{}\t{}",
+                    item.timestamp.with_timezone(&chrono::Local).format("%Y-%m-%d %H:%M"),
+                    item.command,
+                    dir
+                );
+            }
+            Ok(())
+        }
+        Response::Error { message } => anyhow::bail!(message),
+        other => anyhow::bail!("Unexpected response: {:?}", other),
+    }
+}
+
+#[cfg(not(unix))]
+async fn print_history_matches(_query: &str) -> Result<()> {
+    anyhow::bail!("History search from the command line is only available on Unix")
+}
+
 #[cfg(unix)]
 async fn wait_for_term_signal() {
     use futures::stream::StreamExt;
@@ -8,19 +8,26 @@ use tracing::{debug, info};
 
 use crate::config::Config;
 use crate::learning::LearningEngine;
+use crate::notifications::{Notification, Notifier};
 use git::{analyze_repo, find_git_repos, GitSuggestion};
 
 #[derive(Clone)]
 pub struct ProactiveMonitor {
     config: Arc<Config>,
     learning_engine: Arc<LearningEngine>,
+    notifier: Arc<Notifier>,
 }
 
 impl ProactiveMonitor {
-    pub async fn new(config: Arc<Config>, learning_engine: Arc<LearningEngine>) -> Result<Self> {
+    pub async fn new(
+        config: Arc<Config>,
+        learning_engine: Arc<LearningEngine>,
+        notifier: Arc<Notifier>,
+    ) -> Result<Self> {
         Ok(Self {
             config,
             learning_engine,
+            notifier,
         })
     }
 
@@ -66,7 +73,7 @@ impl ProactiveMonitor {
             let suggestions = analyze_repo(&repo);
 
             for suggestion in suggestions {
-                // Show suggestion to user (via desktop notification or inline)
+                // Show suggestion to user (via notification channels or inline)
                 self.show_suggestion(&suggestion).await;
             }
         }
@@ -117,38 +124,16 @@ impl ProactiveMonitor {
 
         debug!("Git suggestion: {}", message);
 
-        if self.config.monitoring.desktop_notifications {
-            self.show_notification("Orbit - Git Status", &message, command)
-                .await;
-        }
+        self.show_notification("Orbit - Git Status", &message, command)
+            .await;
 
         // TODO: Also show inline in terminal if active session
     }
 
     async fn show_notification(&self, title: &str, message: &str, command: Option<String>) {
-        if !self.config.monitoring.desktop_notifications {
-            return;
-        }
-
-        #[cfg(not(target_os = "windows"))]
-        {
-            let mut notification = notify_rust::Notification::new();
-            notification.summary(title).body(message);
-
-            if let Some(cmd) = command {
-                notification.action("execute", &format!("Run: {}", cmd));
-            }
-
-            if let Err(e) = notification.show() {
-                debug!("Failed to show notification: {}", e);
-            }
-        }
-
-        #[cfg(target_os = "windows")]
-        {
-            // Windows notifications would go here
-            debug!("Notification: {} - {}", title, message);
-        }
+        // Routed to the desktop and chat channels by `notifications.routes`
+        self.notifier
+            .notify(Notification::monitor_alert(title, message, command));
     }
 
     fn get_disk_usage() -> Result<f32> {
@@ -236,7 +221,9 @@ ui:
                 .unwrap(),
         );
 
-        ProactiveMonitor::new(config, learning_engine)
+        let notifier = Arc::new(Notifier::new(&config).unwrap());
+
+        ProactiveMonitor::new(config, learning_engine, notifier)
             .await
             .unwrap()
     }
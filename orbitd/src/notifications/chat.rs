@@ -0,0 +1,94 @@
+// Message bodies for chat services' incoming webhooks
+
+use serde_json::{json, Value};
+
+use super::Notification;
+
+/// Body for a Slack incoming webhook
+pub fn slack_payload(notification: &Notification) -> Value {
+    let mut text = format!(
+        "*{}*\n{}",
+        slack_escape(&notification.title),
+        slack_escape(&notification.message)
+    );
+    if let Some(command) = &notification.command {
+        text.push_str(&format!("\n`{}`", slack_escape(command)));
+    }
+    json!({ "text": text })
+}
+
+/// Body for a Microsoft Teams incoming webhook or workflow, as an Adaptive
+/// Card
+pub fn teams_payload(notification: &Notification) -> Value {
+    let mut body = vec![
+        json!({
+            "type": "TextBlock",
+            "text": notification.title,
+            "weight": "Bolder",
+            "size": "Medium",
+            "color": if notification.failed { "Attention" } else { "Default" },
+        }),
+        json!({ "type": "TextBlock", "text": notification.message, "wrap": true }),
+    ];
+    if let Some(command) = &notification.command {
+        body.push(json!({
+            "type": "TextBlock",
+            "text": command,
+            "fontType": "Monospace",
+            "wrap": true,
+        }));
+    }
+
+    json!({
+        "type": "message",
+        "attachments": [{
+            "contentType": "application/vnd.microsoft.card.adaptive",
+            "content": {
+                "$schema": "http://adaptivecards.io/schemas/adaptive-card.json",
+                "type": "AdaptiveCard",
+                "version": "1.4",
+                "body": body,
+            },
+        }],
+    })
+}
+
+/// Slack treats these three as markup in message text
+fn slack_escape(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::NotificationEvent;
+
+    fn notification() -> Notification {
+        Notification {
+            event: NotificationEvent::CommandFinished,
+            title: "Command failed".to_string(),
+            message: "Exited with 1 on host after 2m 5s".to_string(),
+            command: Some("cargo test > out.log".to_string()),
+            failed: true,
+        }
+    }
+
+    #[test]
+    fn test_slack_payload() {
+        assert_eq!(
+            slack_payload(&notification()),
+            json!({
+                "text": "*Command failed*\nExited with 1 on host after 2m 5s\n`cargo test &gt; out.log`"
+            })
+        );
+    }
+
+    #[test]
+    fn test_teams_payload() {
+        let payload = teams_payload(&notification());
+        let card = &payload["attachments"][0]["content"];
+        assert_eq!(card["type"], "AdaptiveCard");
+        assert_eq!(card["body"][0]["color"], "Attention");
+        assert_eq!(card["body"][2]["text"], "cargo test > out.log");
+    }
+}
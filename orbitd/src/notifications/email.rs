@@ -0,0 +1,201 @@
+// SMTP delivery for critical events
+//
+// Mail is for things that need a human even when nobody is watching the
+// desktop, so only `Critical` notifications reach it, and at most
+// `max_per_hour` of those: a crash loop must not flood an inbox.
+
+use anyhow::{Context as _, Result};
+use lettre::message::{header::ContentType, Mailbox};
+use lettre::transport::smtp::authentication::Credentials;
+use lettre::{AsyncSmtpTransport, AsyncTransport, Message, Tokio1Executor};
+use std::collections::VecDeque;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use super::Notification;
+use crate::config::{EmailConfig, NotificationEvent, SmtpTls};
+use crate::credentials::{resolve_secret, CredentialStore};
+
+/// How long the SMTP server gets to take a message
+const SMTP_TIMEOUT: Duration = Duration::from_secs(30);
+
+pub struct EmailNotifier {
+    transport: AsyncSmtpTransport<Tokio1Executor>,
+    from: Mailbox,
+    to: Vec<Mailbox>,
+    subject: String,
+    body: String,
+    limiter: Mutex<RateLimiter>,
+}
+
+impl EmailNotifier {
+    pub fn new(config: &EmailConfig) -> Result<Self> {
+        let mut builder = match config.tls {
+            SmtpTls::Starttls => {
+                AsyncSmtpTransport::<Tokio1Executor>::starttls_relay(&config.smtp_host)?
+            }
+            SmtpTls::Tls => AsyncSmtpTransport::<Tokio1Executor>::relay(&config.smtp_host)?,
+            SmtpTls::None => {
+                AsyncSmtpTransport::<Tokio1Executor>::builder_dangerous(&config.smtp_host)
+            }
+        }
+        .timeout(Some(SMTP_TIMEOUT));
+        if let Some(port) = config.smtp_port {
+            builder = builder.port(port);
+        }
+        if let Some(username) = &config.username {
+            let password = match &config.password {
+                Some(password) => resolve_secret(password)?,
+                None => CredentialStore::new()
+                    .get_api_key("smtp")
+                    .context("No SMTP password configured or in the keychain")?,
+            };
+            builder = builder.credentials(Credentials::new(username.clone(), password));
+        }
+
+        Ok(Self {
+            transport: builder.build(),
+            from: config.from.parse()?,
+            to: config.to.iter().map(|to| to.parse()).collect::<Result<_, _>>()?,
+            subject: config.subject.clone(),
+            body: config.body.clone(),
+            limiter: Mutex::new(RateLimiter::new(
+                config.max_per_hour,
+                Duration::from_secs(3600),
+            )),
+        })
+    }
+
+    /// Mail a critical `notification` in the background, unless the hourly
+    /// limit is spent
+    pub fn notify(&self, notification: &Notification) {
+        if notification.event != NotificationEvent::Critical {
+            return;
+        }
+        if !self.limiter.lock().unwrap().allow(Instant::now()) {
+            tracing::warn!("Email limit reached, not mailing: {}", notification.title);
+            return;
+        }
+
+        let message = match self.message(notification) {
+            Ok(message) => message,
+            Err(e) => {
+                tracing::warn!("Failed to build alert email: {}", e);
+                return;
+            }
+        };
+        let transport = self.transport.clone();
+        tokio::spawn(async move {
+            match transport.send(message).await {
+                Ok(_) => tracing::info!("Mailed critical alert"),
+                Err(e) => tracing::warn!("Failed to mail critical alert: {}", e),
+            }
+        });
+    }
+
+    /// Mail a test message now, ignoring the hourly limit
+    pub async fn send_test(&self) -> Result<()> {
+        let notification = Notification {
+            event: NotificationEvent::Critical,
+            title: "Test alert".to_string(),
+            message: "orbitd can reach you by email.".to_string(),
+            command: None,
+            failed: false,
+        };
+        self.transport
+            .send(self.message(&notification)?)
+            .await
+            .context("SMTP server refused the message")?;
+        Ok(())
+    }
+
+    fn message(&self, notification: &Notification) -> Result<Message> {
+        let host = sysinfo::System::host_name().unwrap_or_else(|| "unknown host".to_string());
+        let time = chrono::Local::now().format("%Y-%m-%d %H:%M:%S %Z").to_string();
+
+        let mut message = Message::builder()
+            .from(self.from.clone())
+            .subject(render(&self.subject, notification, &host, &time))
+            .header(ContentType::TEXT_PLAIN);
+        for to in &self.to {
+            message = message.to(to.clone());
+        }
+        Ok(message.body(render(&self.body, notification, &host, &time))?)
+    }
+}
+
+/// Fill a subject or body template
+fn render(template: &str, notification: &Notification, host: &str, time: &str) -> String {
+    template
+        .replace("{title}", &notification.title)
+        .replace("{message}", &notification.message)
+        .replace("{command}", notification.command.as_deref().unwrap_or(""))
+        .replace("{host}", host)
+        .replace("{time}", time)
+}
+
+/// At most `max` events per sliding `window`
+struct RateLimiter {
+    max: usize,
+    window: Duration,
+    sent: VecDeque<Instant>,
+}
+
+impl RateLimiter {
+    fn new(max: usize, window: Duration) -> Self {
+        Self {
+            max,
+            window,
+            sent: VecDeque::new(),
+        }
+    }
+
+    fn allow(&mut self, now: Instant) -> bool {
+        while self.sent.front().is_some_and(|sent| now.duration_since(*sent) >= self.window) {
+            self.sent.pop_front();
+        }
+        if self.sent.len() >= self.max {
+            return false;
+        }
+        self.sent.push_back(now);
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render() {
+        let notification = Notification {
+            event: NotificationEvent::Critical,
+            title: "License expires soon".to_string(),
+            message: "The Orbit license expires in 3 days".to_string(),
+            command: None,
+            failed: false,
+        };
+        assert_eq!(
+            render(
+                "[orbit] {title} on {host}: {message}{command} at {time}",
+                &notification,
+                "build-01",
+                "noon"
+            ),
+            "[orbit] License expires soon on build-01: The Orbit license expires in 3 days at noon"
+        );
+    }
+
+    #[test]
+    fn test_rate_limiter() {
+        let mut limiter = RateLimiter::new(2, Duration::from_secs(3600));
+        let start = Instant::now();
+
+        assert!(limiter.allow(start));
+        assert!(limiter.allow(start + Duration::from_secs(60)));
+        assert!(!limiter.allow(start + Duration::from_secs(120)));
+        // The first send leaves the window
+        assert!(limiter.allow(start + Duration::from_secs(3600)));
+        assert!(!limiter.allow(start + Duration::from_secs(3601)));
+    }
+}
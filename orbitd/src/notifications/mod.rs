@@ -0,0 +1,353 @@
+// Notification policy: what orbitd tells the user about, and where
+//
+// Long-running command results, monitor alerts and critical events become a
+// `Notification`, which `notifications.routes` sends to the desktop and to
+// Slack or Teams channels. Critical events are also mailed. Deliveries are
+// fire-and-forget; a failed post is logged and dropped.
+
+pub mod chat;
+pub mod email;
+
+use anyhow::Result;
+use std::collections::HashMap;
+use std::time::Duration;
+
+use self::email::EmailNotifier;
+use crate::config::{ChannelConfig, Config, NotificationEvent, NotificationsConfig, RouteConfig};
+use crate::executor::output::ExecutionOutput;
+use crate::executor::target::ExecutionTarget;
+
+/// How long a chat service gets to accept a message
+const POST_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Something worth telling the user about
+#[derive(Debug, Clone, PartialEq)]
+pub struct Notification {
+    pub event: NotificationEvent,
+    pub title: String,
+    pub message: String,
+    /// Command the notification is about, or one that would act on it
+    pub command: Option<String>,
+    /// A command exited non-zero
+    pub failed: bool,
+}
+
+impl Notification {
+    pub fn command_finished(
+        command: &str,
+        output: &ExecutionOutput,
+        target: &ExecutionTarget,
+    ) -> Self {
+        let took = format_duration(Duration::from_millis(output.duration_ms));
+        let (title, message) = if output.success() {
+            (
+                "Command finished",
+                format!("Succeeded on {} after {}", target, took),
+            )
+        } else {
+            (
+                "Command failed",
+                format!(
+                    "Exited with {} on {} after {}",
+                    output.exit_code, target, took
+                ),
+            )
+        };
+        Self {
+            event: NotificationEvent::CommandFinished,
+            title: title.to_string(),
+            message,
+            command: Some(command.to_string()),
+            failed: !output.success(),
+        }
+    }
+
+    pub fn monitor_alert(title: &str, message: &str, command: Option<String>) -> Self {
+        Self {
+            event: NotificationEvent::MonitorAlert,
+            title: title.to_string(),
+            message: message.to_string(),
+            command,
+            failed: false,
+        }
+    }
+
+    pub fn critical(title: &str, message: &str) -> Self {
+        Self {
+            event: NotificationEvent::Critical,
+            title: title.to_string(),
+            message: message.to_string(),
+            command: None,
+            failed: false,
+        }
+    }
+}
+
+/// Routes notifications to the channels configured for them
+pub struct Notifier {
+    desktop: bool,
+    long_command: Duration,
+    channels: HashMap<String, ChannelConfig>,
+    routes: Vec<RouteConfig>,
+    client: reqwest::Client,
+    /// None unless `notifications.email` is set and usable
+    email: Option<EmailNotifier>,
+}
+
+impl Notifier {
+    pub fn new(config: &Config) -> Result<Self> {
+        let notifications = &config.notifications;
+        let routes = if notifications.routes.is_empty() {
+            vec![RouteConfig {
+                events: vec![NotificationEvent::MonitorAlert],
+                channels: vec![NotificationsConfig::DESKTOP.to_string()],
+                failed_only: false,
+            }]
+        } else {
+            notifications.routes.clone()
+        };
+        let email = notifications.email.as_ref().and_then(|email| {
+            EmailNotifier::new(email)
+                .map_err(|e| tracing::warn!("Email alerts unavailable: {:#}", e))
+                .ok()
+        });
+
+        Ok(Self {
+            desktop: config.monitoring.desktop_notifications,
+            long_command: Duration::from_secs(notifications.long_command_secs),
+            channels: notifications.channels.clone(),
+            routes,
+            client: reqwest::Client::builder().timeout(POST_TIMEOUT).build()?,
+            email,
+        })
+    }
+
+    /// Whether a command that ran for `duration` is worth reporting
+    pub fn is_long_running(&self, duration: Duration) -> bool {
+        duration >= self.long_command
+    }
+
+    /// Send `notification` to every channel a route picks for it, and mail
+    /// it if critical
+    pub fn notify(&self, notification: Notification) {
+        if notification.event == NotificationEvent::Critical {
+            tracing::error!("{}: {}", notification.title, notification.message);
+        }
+        if let Some(email) = &self.email {
+            email.notify(&notification);
+        }
+
+        for name in self.route(&notification) {
+            if name == NotificationsConfig::DESKTOP {
+                if self.desktop {
+                    show_desktop(&notification);
+                }
+                continue;
+            }
+
+            let Some(channel) = self.channels.get(name) else {
+                continue;
+            };
+            let payload = match channel {
+                ChannelConfig::Slack { .. } => chat::slack_payload(&notification),
+                ChannelConfig::Teams { .. } => chat::teams_payload(&notification),
+            };
+            let request = self.client.post(channel.webhook_url()).json(&payload);
+            let name = name.to_string();
+            tokio::spawn(async move {
+                match request.send().await.and_then(|r| r.error_for_status()) {
+                    Ok(_) => tracing::debug!("Notified channel {}", name),
+                    Err(e) => tracing::warn!("Failed to notify channel {}: {}", name, e),
+                }
+            });
+        }
+    }
+
+    /// Channels the routes pick for `notification`, each once
+    fn route(&self, notification: &Notification) -> Vec<&str> {
+        let mut channels: Vec<&str> = Vec::new();
+        for route in &self.routes {
+            let matches = (route.events.is_empty() || route.events.contains(&notification.event))
+                && (!route.failed_only || notification.failed);
+            if !matches {
+                continue;
+            }
+            for channel in &route.channels {
+                if !channels.contains(&channel.as_str()) {
+                    channels.push(channel);
+                }
+            }
+        }
+        channels
+    }
+}
+
+fn show_desktop(notification: &Notification) {
+    #[cfg(not(target_os = "windows"))]
+    {
+        let mut desktop = notify_rust::Notification::new();
+        desktop.summary(&notification.title).body(&notification.message);
+
+        if let Some(cmd) = &notification.command {
+            desktop.action("execute", &format!("Run: {}", cmd));
+        }
+
+        if let Err(e) = desktop.show() {
+            tracing::debug!("Failed to show notification: {}", e);
+        }
+    }
+
+    #[cfg(target_os = "windows")]
+    {
+        // Windows notifications would go here
+        tracing::debug!(
+            "Notification: {} - {}",
+            notification.title,
+            notification.message
+        );
+    }
+}
+
+/// "1h 2m", "3m 4s" or "5s"
+fn format_duration(duration: Duration) -> String {
+    let secs = duration.as_secs();
+    match (secs / 3600, secs % 3600 / 60, secs % 60) {
+        (0, 0, s) => format!("{}s", s),
+        (0, m, s) => format!("{}m {}s", m, s),
+        (h, m, _) => format!("{}h {}m", h, m),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn notifier(yaml: &str) -> Notifier {
+        let notifications: NotificationsConfig = serde_yaml::from_str(yaml).unwrap();
+        assert!(notifications.problems().is_empty());
+        let routes = notifications.routes;
+        Notifier {
+            desktop: false,
+            long_command: Duration::from_secs(notifications.long_command_secs),
+            channels: notifications.channels,
+            routes,
+            client: reqwest::Client::new(),
+            email: None,
+        }
+    }
+
+    fn finished(exit_code: i32) -> Notification {
+        let output = ExecutionOutput {
+            exit_code,
+            stdout: String::new(),
+            stderr: String::new(),
+            duration_ms: 125_000,
+        };
+        Notification::command_finished("make release", &output, &ExecutionTarget::Host)
+    }
+
+    #[test]
+    fn test_routes_by_event_and_failure() {
+        let notifier = notifier(
+            r#"
+channels:
+  ops:
+    kind: slack
+    webhook_url: https://hooks.slack.com/services/T/B/X
+  team:
+    kind: teams
+    webhook_url: https://example.webhook.office.com/webhookb2/x
+routes:
+  - events: [command_finished]
+    channels: [ops]
+    failed_only: true
+  - events: [monitor_alert]
+    channels: [desktop, team]
+  - channels: [team]
+"#,
+        );
+
+        assert_eq!(notifier.route(&finished(0)), vec!["team"]);
+        assert_eq!(notifier.route(&finished(2)), vec!["ops", "team"]);
+        let alert = Notification::monitor_alert("Disk space warning", "Disk usage is at 95%", None);
+        assert_eq!(notifier.route(&alert), vec!["desktop", "team"]);
+    }
+
+    #[test]
+    fn test_command_finished_message() {
+        let failed = finished(2);
+        assert_eq!(failed.title, "Command failed");
+        assert_eq!(failed.message, "Exited with 2 on host after 2m 5s");
+        assert_eq!(failed.command.as_deref(), Some("make release"));
+        assert!(failed.failed);
+        assert_eq!(finished(0).message, "Succeeded on host after 2m 5s");
+    }
+
+    #[test]
+    fn test_long_running_threshold() {
+        let notifier = notifier("long_command_secs: 30");
+        assert!(!notifier.is_long_running(Duration::from_secs(29)));
+        assert!(notifier.is_long_running(Duration::from_secs(30)));
+    }
+
+    #[test]
+    fn test_problems() {
+        let config: NotificationsConfig = serde_yaml::from_str(
+            r#"
+channels:
+  desktop:
+    kind: slack
+    webhook_url: https://hooks.slack.com/services/T/B/X
+  ops:
+    kind: teams
+    webhook_url: ftp://example.com
+routes:
+  - channels: [pager]
+  - events: [monitor_alert]
+    channels: []
+"#,
+        )
+        .unwrap();
+
+        let problems = config.problems();
+        assert_eq!(problems.len(), 4, "{:?}", problems);
+        assert!(problems.iter().any(|p| p.contains("unknown channel 'pager'")));
+    }
+
+    #[test]
+    fn test_email_config() {
+        let config: NotificationsConfig = serde_yaml::from_str(
+            r#"
+email:
+  smtp_host: smtp.example.com
+  username: orbit
+  from: Orbit <orbit@example.com>
+  to: [oncall@example.com]
+"#,
+        )
+        .unwrap();
+        let email = config.email.as_ref().unwrap();
+        assert_eq!(email.tls, crate::config::SmtpTls::Starttls);
+        assert_eq!(email.max_per_hour, 6);
+        assert!(config.problems().is_empty());
+
+        let config: NotificationsConfig = serde_yaml::from_str(
+            r#"
+email:
+  smtp_host: smtp.example.com
+  password: hunter2
+  from: not an address
+  to: []
+"#,
+        )
+        .unwrap();
+        assert_eq!(config.problems().len(), 3, "{:?}", config.problems());
+    }
+
+    #[test]
+    fn test_format_duration() {
+        assert_eq!(format_duration(Duration::from_secs(5)), "5s");
+        assert_eq!(format_duration(Duration::from_secs(184)), "3m 4s");
+        assert_eq!(format_duration(Duration::from_secs(3720)), "1h 2m");
+    }
+}
@@ -0,0 +1,305 @@
+// Prompt composition under a token budget
+//
+// A prompt is the provider's instructions followed by sections of context:
+// the environment, git state, recent commands and recent output. Sections
+// always appear in that order, whatever order they were added in. When the
+// prompt and the user's request don't fit the model's context window, lines
+// are dropped from the least important section first: recent output, then
+// recent commands, git and the environment. Output and commands lose their
+// oldest lines, the others their last. The instructions are never cut.
+
+use std::collections::{BTreeMap, VecDeque};
+
+use crate::config::Config;
+use crate::context::{Context, ProviderSettings};
+
+/// Context window assumed for models without a configured `context_window`
+pub const DEFAULT_CONTEXT_WINDOW: u32 = 8192;
+
+/// A section of context, in the order sections appear in the prompt
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Section {
+    Environment,
+    Git,
+    History,
+    Output,
+}
+
+impl Section {
+    /// Order in which sections are cut to fit the budget
+    const TRUNCATION_ORDER: [Section; 4] = [
+        Section::Output,
+        Section::History,
+        Section::Git,
+        Section::Environment,
+    ];
+
+    fn heading(self) -> &'static str {
+        match self {
+            Self::Environment => "Environment",
+            Self::Git => "Git",
+            Self::History => "Recent commands",
+            Self::Output => "Recent output",
+        }
+    }
+
+    /// Whether the section's newest lines are at the end, and the first
+    /// lines are the ones to drop
+    fn keeps_tail(self) -> bool {
+        matches!(self, Self::History | Self::Output)
+    }
+}
+
+/// A composed prompt and its estimated size
+#[derive(Debug, Clone, PartialEq)]
+pub struct ComposedPrompt {
+    /// System prompt: the instructions and whatever context fit
+    pub system: String,
+    /// Estimated tokens of the system prompt and the user's request together
+    pub tokens: u32,
+    /// Sections that lost lines, or were left out, to fit the budget
+    pub truncated: Vec<Section>,
+}
+
+/// Assembles a system prompt from context, within a token budget
+#[derive(Debug, Clone)]
+pub struct PromptComposer {
+    budget: u32,
+    sections: BTreeMap<Section, VecDeque<String>>,
+}
+
+impl PromptComposer {
+    /// Composer for prompts of at most `budget` tokens, request included
+    pub fn new(budget: u32) -> Self {
+        Self {
+            budget,
+            sections: BTreeMap::new(),
+        }
+    }
+
+    /// Composer for the model `settings` name, leaving `reserved` tokens of
+    /// its context window for the answer
+    pub fn for_model(config: &Config, settings: &ProviderSettings, reserved: u32) -> Self {
+        Self::new(context_window(config, settings).saturating_sub(reserved))
+    }
+
+    /// Add `lines` to `section`, after any it already has
+    pub fn section<I, S>(mut self, section: Section, lines: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        let entry = self.sections.entry(section).or_default();
+        entry.extend(
+            lines
+                .into_iter()
+                .map(Into::into)
+                .filter(|line| !line.trim().is_empty()),
+        );
+        self
+    }
+
+    /// Add the environment, git state and up to `max_recent_commands` of
+    /// the recent commands from `context`
+    pub fn with_context(self, context: &Context, max_recent_commands: usize) -> Self {
+        let mut environment = vec![
+            format!("OS: {} {}", context.os_name, context.os_version),
+            format!("Shell: {}", context.shell_name),
+            format!("Directory: {}", context.pwd.display()),
+        ];
+        if let Some(host) = &context.host {
+            environment.push(format!("Host: {}", host));
+        }
+        if let Some(project_type) = &context.project_type {
+            environment.push(format!("Project: {:?}", project_type));
+        }
+        if !context.detected_languages.is_empty() {
+            environment.push(format!(
+                "Languages: {}",
+                context.detected_languages.join(", ")
+            ));
+        }
+
+        let mut git = Vec::new();
+        if let Some(repo) = &context.git_context {
+            git.push(format!("Repository: {}", repo.repo_name));
+            git.push(format!("Branch: {}", repo.current_branch));
+            if repo.has_uncommitted_changes {
+                git.push("Uncommitted changes".to_string());
+            }
+            if let Some((ahead, behind)) = repo.ahead_behind {
+                git.push(format!("{} ahead, {} behind upstream", ahead, behind));
+            }
+            if let Some(message) = &repo.last_commit_message {
+                git.push(format!("Last commit: {}", message));
+            }
+        }
+
+        let skip = context
+            .recent_commands
+            .len()
+            .saturating_sub(max_recent_commands);
+        self.section(Section::Environment, environment)
+            .section(Section::Git, git)
+            .section(
+                Section::History,
+                context.recent_commands[skip..].iter().cloned(),
+            )
+    }
+
+    /// Add output from the terminal, oldest line first
+    pub fn with_recent_output(self, output: &str) -> Self {
+        self.section(Section::Output, output.lines())
+    }
+
+    /// The system prompt for `input`: `instructions` and as much of the
+    /// context as fits the budget alongside it
+    pub fn compose(mut self, instructions: &str, input: &str) -> ComposedPrompt {
+        self.sections.retain(|_, lines| !lines.is_empty());
+        let input_tokens = estimate_tokens(input);
+        let mut chars = instructions.chars().count()
+            + self
+                .sections
+                .iter()
+                .map(|(section, lines)| section_chars(*section, lines))
+                .sum::<usize>();
+
+        let mut truncated = Vec::new();
+        for section in Section::TRUNCATION_ORDER {
+            let Some(lines) = self.sections.get_mut(&section) else {
+                continue;
+            };
+            while tokens(chars) + input_tokens > self.budget && !lines.is_empty() {
+                if !truncated.contains(&section) {
+                    truncated.push(section);
+                }
+                chars -= section_chars(section, lines);
+                if section.keeps_tail() {
+                    lines.pop_front();
+                } else {
+                    lines.pop_back();
+                }
+                if !lines.is_empty() {
+                    chars += section_chars(section, lines);
+                }
+            }
+            if lines.is_empty() {
+                self.sections.remove(&section);
+            }
+        }
+
+        let mut system = instructions.to_string();
+        for (section, lines) in &mut self.sections {
+            system.push_str("\n\n");
+            system.push_str(section.heading());
+            system.push_str(":\n");
+            system.push_str(&lines.make_contiguous().join("\n"));
+        }
+        ComposedPrompt {
+            tokens: estimate_tokens(&system) + input_tokens,
+            system,
+            truncated,
+        }
+    }
+}
+
+/// Characters `lines` take in the prompt under `section`'s heading,
+/// including the blank line before it
+fn section_chars(section: Section, lines: &VecDeque<String>) -> usize {
+    let text: usize = lines.iter().map(|line| line.chars().count()).sum();
+    // "\n\n" + heading + ":\n" + lines joined by "\n"
+    2 + section.heading().len() + 2 + text + lines.len().saturating_sub(1)
+}
+
+fn tokens(chars: usize) -> u32 {
+    chars.div_ceil(4) as u32
+}
+
+/// Rough token count of `text`, about four characters per token
+pub fn estimate_tokens(text: &str) -> u32 {
+    tokens(text.chars().count())
+}
+
+/// Context window of the model `settings` name, from the provider's
+/// configured models
+pub fn context_window(config: &Config, settings: &ProviderSettings) -> u32 {
+    settings
+        .model
+        .as_deref()
+        .and_then(|model| {
+            config
+                .providers
+                .get(&settings.provider)?
+                .models
+                .as_ref()?
+                .iter()
+                .find(|m| m.name == model)?
+                .context_window
+        })
+        .map_or(DEFAULT_CONTEXT_WINDOW, |window| {
+            u32::try_from(window).unwrap_or(u32::MAX)
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sections_in_fixed_order() {
+        let prompt = PromptComposer::new(1000)
+            .with_recent_output("error: not found")
+            .section(Section::History, ["cargo build"])
+            .section(Section::Environment, ["Shell: bash"])
+            .compose("Reply with a command.", "fix it");
+
+        assert_eq!(
+            prompt.system,
+            "Reply with a command.\n\n\
+             Environment:\nShell: bash\n\n\
+             Recent commands:\ncargo build\n\n\
+             Recent output:\nerror: not found"
+        );
+        assert_eq!(
+            prompt.tokens,
+            estimate_tokens(&prompt.system) + estimate_tokens("fix it")
+        );
+        assert!(prompt.truncated.is_empty());
+    }
+
+    #[test]
+    fn test_truncates_lowest_priority_first() {
+        let output: String = (1..=50).map(|i| format!("line {}\n", i)).collect();
+        let prompt = PromptComposer::new(60)
+            .section(Section::Environment, ["Shell: bash", "Directory: /srv/app"])
+            .section(Section::History, ["make", "make test"])
+            .with_recent_output(&output)
+            .compose("Reply with a command.", "why did it fail");
+
+        assert!(prompt.tokens <= 60);
+        assert_eq!(prompt.truncated, vec![Section::Output]);
+        assert!(prompt.system.contains("Directory: /srv/app"));
+        assert!(prompt.system.contains("make test"));
+        // The newest output is kept
+        assert!(prompt.system.ends_with("line 50"));
+        assert!(!prompt.system.contains("line 1\n"));
+
+        // A smaller budget drops the output and then the oldest commands
+        let prompt = PromptComposer::new(25)
+            .section(Section::Environment, ["Shell: bash", "Directory: /srv/app"])
+            .section(Section::History, ["make", "make test"])
+            .with_recent_output(&output)
+            .compose("Reply with a command.", "why did it fail");
+        assert!(prompt.tokens <= 25);
+        assert_eq!(prompt.truncated, vec![Section::Output, Section::History]);
+        assert!(!prompt.system.contains("Recent output"));
+        assert!(prompt.system.contains("Shell: bash"));
+
+        // The instructions are kept however small the budget
+        let prompt = PromptComposer::new(1)
+            .section(Section::Environment, ["Shell: bash"])
+            .compose("Reply with a command.", "why did it fail");
+        assert_eq!(prompt.system, "Reply with a command.");
+        assert_eq!(prompt.truncated, vec![Section::Environment]);
+    }
+}
@@ -1,2 +1,4 @@
-// Auto-prompting engine
-// TODO: Implement template-based prompt enhancement
+// Prompts sent to AI providers
+pub mod composer;
+
+pub use composer::{estimate_tokens, ComposedPrompt, PromptComposer, Section};
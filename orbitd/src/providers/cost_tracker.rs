@@ -362,6 +362,7 @@ impl CostTracker {
             total_tokens: total_tokens as u64,
             total_cost,
             by_provider: by_provider.into_iter().collect(),
+            rate_limits: Vec::new(),
         })
     }
 }
@@ -398,4 +399,7 @@ pub struct CostReport {
     pub total_tokens: u64,
     pub total_cost: f64,
     pub by_provider: HashMap<String, f64>,
+    /// Current rate limit utilization; filled in by the provider router
+    #[serde(default)]
+    pub rate_limits: Vec<super::RateLimitUsage>,
 }
@@ -0,0 +1,270 @@
+// Local model of last resort
+//
+// A small quantized model, run with llama.cpp's command-line program,
+// turns natural language into commands when no cloud provider can: while
+// offline, when the provider fails, or always if `local` is the selected
+// provider. Answers are rougher than a cloud model's but need no network.
+//
+// The model is downloaded from `local_model.model_url` when the daemon
+// starts with the model enabled and the file doesn't exist yet. It is
+// written beside its final path with a `.part` suffix, hashed as it
+// arrives, and moved into place only once complete and matching `sha256`.
+// Without a `sha256` nothing is downloaded, since llama.cpp would parse
+// whatever arrived. Requests made before then fail at once instead of
+// waiting for the download.
+
+use anyhow::{bail, Context, Result};
+use sha2::{Digest, Sha256};
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use std::sync::Mutex;
+use std::time::Duration;
+use tokio::io::AsyncWriteExt;
+use tracing::{info, warn};
+
+use crate::config::LocalModelConfig;
+
+/// Provider name that selects the local model
+pub const LOCAL_PROVIDER: &str = "local";
+
+/// Context window assumed for the small models this runs
+pub const LOCAL_CONTEXT_WINDOW: u32 = 2048;
+
+#[derive(Debug, Clone, PartialEq)]
+enum ModelState {
+    Missing,
+    Downloading,
+    Ready,
+    Failed(String),
+}
+
+/// The local model and its download
+pub struct LocalModel {
+    config: LocalModelConfig,
+    path: PathBuf,
+    state: Mutex<ModelState>,
+}
+
+impl LocalModel {
+    /// The model `config` describes, or `None` if it isn't enabled
+    pub fn new(config: &LocalModelConfig) -> Result<Option<Self>> {
+        if !config.enabled {
+            return Ok(None);
+        }
+        let path = config.model_path()?;
+        let state = if path.is_file() {
+            ModelState::Ready
+        } else {
+            ModelState::Missing
+        };
+        Ok(Some(Self {
+            config: config.clone(),
+            path,
+            state: Mutex::new(state),
+        }))
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn is_ready(&self) -> bool {
+        *self.state.lock().unwrap() == ModelState::Ready
+    }
+
+    /// Download the model unless it is there or on its way
+    pub async fn prepare(&self) -> Result<()> {
+        {
+            let mut state = self.state.lock().unwrap();
+            match &*state {
+                ModelState::Ready | ModelState::Downloading => return Ok(()),
+                ModelState::Missing | ModelState::Failed(_) => *state = ModelState::Downloading,
+            }
+        }
+
+        info!(
+            "Downloading local model {} to {}",
+            self.config.model_url,
+            self.path.display()
+        );
+        let result = self.download().await;
+        *self.state.lock().unwrap() = match &result {
+            Ok(()) => ModelState::Ready,
+            Err(e) => ModelState::Failed(e.to_string()),
+        };
+        result
+    }
+
+    async fn download(&self) -> Result<()> {
+        let Some(expected) = &self.config.sha256 else {
+            bail!(
+                "local_model.sha256 is not set; refusing to download an unverified model \
+                 (set it, or put the model at {} by hand)",
+                self.path.display()
+            );
+        };
+        if let Some(dir) = self.path.parent() {
+            tokio::fs::create_dir_all(dir).await?;
+        }
+        let mut partial = self.path.as_os_str().to_owned();
+        partial.push(".part");
+        let partial = PathBuf::from(partial);
+
+        let mut response = reqwest::get(&self.config.model_url)
+            .await?
+            .error_for_status()
+            .context("Failed to download the local model")?;
+        let mut file = tokio::fs::File::create(&partial).await?;
+        let mut hasher = Sha256::new();
+        let mut size = 0u64;
+        while let Some(chunk) = response.chunk().await? {
+            hasher.update(&chunk);
+            file.write_all(&chunk).await?;
+            size += chunk.len() as u64;
+        }
+        file.sync_all().await?;
+        drop(file);
+
+        let digest = format!("{:x}", hasher.finalize());
+        if !digest.eq_ignore_ascii_case(expected) {
+            let _ = tokio::fs::remove_file(&partial).await;
+            bail!(
+                "Local model download has SHA-256 {}, expected {}",
+                digest,
+                expected
+            );
+        }
+        tokio::fs::rename(&partial, &self.path).await?;
+        info!("Local model ready ({} bytes, SHA-256 {})", size, digest);
+        Ok(())
+    }
+
+    /// The model's command for `input`, following the `system` prompt
+    pub async fn suggest(&self, system: &str, input: &str) -> Result<String> {
+        match &*self.state.lock().unwrap() {
+            ModelState::Ready => {}
+            ModelState::Missing | ModelState::Downloading => {
+                bail!("The local model is still downloading")
+            }
+            ModelState::Failed(e) => bail!("The local model could not be downloaded: {}", e),
+        }
+
+        let child = tokio::process::Command::new(&self.config.runner)
+            .args(self.runner_args(system, input))
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .kill_on_drop(true)
+            .spawn()
+            .with_context(|| {
+                format!(
+                    "Failed to run {}; is llama.cpp installed?",
+                    self.config.runner
+                )
+            })?;
+
+        let timeout = Duration::from_secs(self.config.timeout_seconds);
+        let output = match tokio::time::timeout(timeout, child.wait_with_output()).await {
+            Ok(output) => output?,
+            Err(_) => bail!(
+                "The local model gave no answer within {}s",
+                self.config.timeout_seconds
+            ),
+        };
+        if !output.status.success() {
+            bail!(
+                "{} failed: {}",
+                self.config.runner,
+                String::from_utf8_lossy(&output.stderr).trim()
+            );
+        }
+
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        match parse_command(&stdout) {
+            Some(command) => Ok(command),
+            None => {
+                warn!("Local model answered without a command: {:?}", stdout);
+                bail!("The local model did not suggest a command")
+            }
+        }
+    }
+
+    /// A single greedy completion of the prompt, printing only the answer
+    fn runner_args(&self, system: &str, input: &str) -> Vec<String> {
+        vec![
+            "--model".to_string(),
+            self.path.display().to_string(),
+            "--prompt".to_string(),
+            format!("{}\n\nRequest: {}\nCommand:", system, input),
+            "--n-predict".to_string(),
+            self.config.max_tokens.to_string(),
+            "--ctx-size".to_string(),
+            LOCAL_CONTEXT_WINDOW.to_string(),
+            "--temp".to_string(),
+            "0".to_string(),
+            "--no-display-prompt".to_string(),
+            "-no-cnv".to_string(),
+            "--log-disable".to_string(),
+        ]
+    }
+}
+
+/// The command in a model's answer: its first non-empty line, without
+/// Markdown code fences, backticks or a shell prompt
+fn parse_command(output: &str) -> Option<String> {
+    output
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with("```"))
+        .map(|line| line.trim_matches('`').trim())
+        .map(|line| line.strip_prefix("$ ").unwrap_or(line).trim())
+        .find(|line| !line.is_empty())
+        .map(str::to_string)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_command() {
+        assert_eq!(
+            parse_command(" ls -la\nexplanation").as_deref(),
+            Some("ls -la")
+        );
+        assert_eq!(
+            parse_command("```bash\n$ du -sh *\n```\n").as_deref(),
+            Some("du -sh *")
+        );
+        assert_eq!(parse_command("`git status`").as_deref(), Some("git status"));
+        assert_eq!(parse_command("\n```\n```\n"), None);
+    }
+
+    #[tokio::test]
+    async fn test_disabled_or_not_downloaded() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut config = LocalModelConfig {
+            model_path: Some(dir.path().join("model.gguf")),
+            ..Default::default()
+        };
+        assert!(LocalModel::new(&config).unwrap().is_none());
+
+        config.enabled = true;
+        let model = LocalModel::new(&config).unwrap().unwrap();
+        assert!(!model.is_ready());
+        let error = model.suggest("Reply with a command.", "list files").await;
+        assert!(error.unwrap_err().to_string().contains("downloading"));
+
+        // Nothing unverified is fetched
+        let error = model.prepare().await.unwrap_err();
+        assert!(error.to_string().contains("sha256 is not set"));
+        assert!(!model.is_ready());
+
+        std::fs::write(dir.path().join("model.gguf"), b"GGUF").unwrap();
+        let model = LocalModel::new(&config).unwrap().unwrap();
+        assert!(model.is_ready());
+        assert!(
+            model.runner_args("Reply.", "list files")[3].ends_with("Request: list files\nCommand:")
+        );
+    }
+}
@@ -1,15 +1,27 @@
 // Provider system for Orbit AI Terminal
 pub mod cost_tracker;
+pub mod local;
+pub mod rate_limit;
 
 use anyhow::Result;
 use serde::{Deserialize, Serialize};
 use sqlx::SqlitePool;
 use std::sync::Arc;
 
+use crate::classifier::Language;
 use crate::config::Config;
-use crate::context::Context;
+use crate::connectivity::Connectivity;
+use crate::context::{Context, ProviderSettings, ShellDialect};
+use crate::executor::dry_run::DryRun;
+use crate::executor::risk::{Explanation, RiskTier};
+use crate::prompts::PromptComposer;
 
-pub use cost_tracker::CostTracker;
+pub use cost_tracker::{CostReport, CostTracker};
+pub use local::{LocalModel, LOCAL_PROVIDER};
+pub use rate_limit::{RateLimitUsage, RateLimited, RateLimiter};
+
+/// Tokens reserved for a provider's answer, on top of the prompt
+const RESPONSE_TOKEN_ALLOWANCE: u32 = 256;
 
 /// Provider configuration
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -40,10 +52,13 @@ impl Default for ProviderConfig {
 }
 
 /// Rate limiting configuration
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
 pub struct RateLimit {
     pub requests_per_minute: u32,
     pub tokens_per_minute: u32,
+    /// How long a request may queue for the limit before it is rejected
+    pub max_wait_secs: u64,
 }
 
 impl Default for RateLimit {
@@ -51,7 +66,27 @@ impl Default for RateLimit {
         Self {
             requests_per_minute: 60,
             tokens_per_minute: 90000,
+            max_wait_secs: 10,
+        }
+    }
+}
+
+impl RateLimit {
+    pub(crate) fn problems(&self, provider: &str) -> Vec<String> {
+        let mut problems = Vec::new();
+        if self.requests_per_minute == 0 {
+            problems.push(format!(
+                "providers.{}.rate_limit.requests_per_minute must be at least 1",
+                provider
+            ));
+        }
+        if self.tokens_per_minute == 0 {
+            problems.push(format!(
+                "providers.{}.rate_limit.tokens_per_minute must be at least 1",
+                provider
+            ));
         }
+        problems
     }
 }
 
@@ -114,12 +149,20 @@ pub struct ProviderResponse {
 pub struct ProviderRouter {
     config: Arc<Config>,
     cost_tracker: Option<CostTracker>,
+    /// Cloud providers are only called while online
+    connectivity: Arc<Connectivity>,
+    rate_limiter: RateLimiter,
+    /// Answers when no cloud provider can, if enabled
+    local: Option<Arc<LocalModel>>,
 }
 
 impl ProviderRouter {
     /// Create new provider router
     pub async fn new(config: Arc<Config>) -> Result<Self> {
         Ok(Self {
+            connectivity: Arc::new(Connectivity::new(&config.connectivity)),
+            rate_limiter: RateLimiter::new(&config),
+            local: local_model(&config)?,
             config,
             cost_tracker: None,
         })
@@ -128,49 +171,195 @@ impl ProviderRouter {
     /// Create router with cost tracking
     pub async fn with_cost_tracking(config: Arc<Config>, db: SqlitePool) -> Result<Self> {
         Ok(Self {
+            connectivity: Arc::new(Connectivity::new(&config.connectivity)),
+            rate_limiter: RateLimiter::new(&config),
+            local: local_model(&config)?,
             config,
             cost_tracker: Some(CostTracker::new(db)),
         })
     }
 
-    /// Process natural language input and return shell command suggestion
-    pub async fn process_natural_language(&self, input: &str, _context: &Context) -> Result<String> {
+    /// Process natural language input and return shell command suggestion;
+    /// `settings` say which provider to ask (per the context's profile),
+    /// `language` is what the input was written in, if known, and
+    /// `recent_output` is what the terminal last showed
+    pub async fn process_natural_language(
+        &self,
+        input: &str,
+        context: &Context,
+        settings: &ProviderSettings,
+        language: Option<Language>,
+        recent_output: Option<&str>,
+    ) -> Result<String> {
+        if settings.provider == LOCAL_PROVIDER {
+            return self
+                .local_suggestion(input, context, language, recent_output)
+                .await;
+        }
+        self.ensure_online()?;
+        let dialect = context.dialect();
+        let mut composer =
+            PromptComposer::for_model(&self.config, settings, RESPONSE_TOKEN_ALLOWANCE)
+                .with_context(context, self.config.context.max_recent_commands);
+        if let Some(output) = recent_output {
+            composer = composer.with_recent_output(output);
+        }
+        let prompt = composer.compose(&system_prompt(dialect, language), input);
+        tracing::debug!("System prompt: {}", prompt.system);
+        if !prompt.truncated.is_empty() {
+            tracing::debug!("Cut to fit the model's context: {:?}", prompt.truncated);
+        }
+        tracing::debug!(
+            "Provider {} (model {}, profile {})",
+            settings.provider,
+            settings.model.as_deref().unwrap_or("default"),
+            settings.profile.as_deref().unwrap_or("none")
+        );
+        // `op` may sit waiting for an unlock, so keep it off the runtime
+        let (key_settings, key_config) = (settings.clone(), Arc::clone(&self.config));
+        if let Err(e) =
+            tokio::task::spawn_blocking(move || key_settings.api_key(&key_config)).await?
+        {
+            tracing::debug!("{}", e);
+        }
+        // Settled against the real count once providers report usage
+        let estimated = prompt.tokens + RESPONSE_TOKEN_ALLOWANCE;
+        self.rate_limiter.acquire(&settings.provider, estimated).await?;
+
         // For now, return a placeholder
         // In production, this would call the actual AI provider (OpenAI, Claude, Gemini)
         // and use the context to provide intelligent suggestions
 
-        // Simple pattern matching for demonstration
+        // Simple pattern matching for demonstration, in the user's shell
         let suggestion = if input.contains("list files") || input.contains("show files") {
-            "ls -la".to_string()
+            match dialect {
+                ShellDialect::PowerShell => "Get-ChildItem -Force",
+                _ => "ls -la",
+            }
         } else if input.contains("current directory") || input.contains("where am i") {
-            "pwd".to_string()
+            match dialect {
+                ShellDialect::PowerShell => "Get-Location",
+                _ => "pwd",
+            }
         } else if input.contains("disk space") || input.contains("storage") {
-            "df -h".to_string()
+            match dialect {
+                ShellDialect::PowerShell => "Get-PSDrive -PSProvider FileSystem",
+                _ => "df -h",
+            }
         } else if input.contains("processes") || input.contains("running") {
-            "ps aux | head -20".to_string()
+            match dialect {
+                ShellDialect::PowerShell => "Get-Process | Select-Object -First 20",
+                ShellDialect::Nu => "ps | first 20",
+                _ => "ps aux | head -20",
+            }
         } else {
-            format!("echo \"AI provider ({}) not yet fully implemented. Input: {}\"", self.config.default_provider, input)
+            let message = format!(
+                "AI provider ({}) not yet fully implemented. Input: {}",
+                settings.provider, input
+            );
+            return Ok(format!("echo {}", dialect.quote(&message)));
         };
 
-        Ok(suggestion)
+        Ok(suggestion.to_string())
+    }
+
+    /// Whether a local model is enabled to answer when cloud providers can't
+    pub fn has_local_model(&self) -> bool {
+        self.local.is_some()
+    }
+
+    /// Ask the local model for a command, as [`process_natural_language`]
+    /// asks a cloud provider
+    ///
+    /// [`process_natural_language`]: Self::process_natural_language
+    pub async fn local_suggestion(
+        &self,
+        input: &str,
+        context: &Context,
+        language: Option<Language>,
+        recent_output: Option<&str>,
+    ) -> Result<String> {
+        let Some(local) = &self.local else {
+            anyhow::bail!("No local model is enabled (local_model.enabled)");
+        };
+        let budget = local::LOCAL_CONTEXT_WINDOW.saturating_sub(self.config.local_model.max_tokens);
+        let mut composer = PromptComposer::new(budget)
+            .with_context(context, self.config.context.max_recent_commands);
+        if let Some(output) = recent_output {
+            composer = composer.with_recent_output(output);
+        }
+        let prompt = composer.compose(&system_prompt(context.dialect(), language), input);
+        tracing::debug!("Local model prompt: {}", prompt.system);
+        local.suggest(&prompt.system, input).await
+    }
+
+    /// Explain `command`, of tier `risk`, in one line with the resources it
+    /// affects, for the user to read before approving it; `dry_run` is what
+    /// it would do to files, if known
+    pub async fn explain_risk(
+        &self,
+        command: &str,
+        context: &Context,
+        settings: &ProviderSettings,
+        risk: RiskTier,
+        dry_run: Option<&DryRun>,
+    ) -> Result<Explanation> {
+        self.ensure_online()?;
+        let prompt = PromptComposer::for_model(&self.config, settings, RESPONSE_TOKEN_ALLOWANCE)
+            .with_context(context, self.config.context.max_recent_commands)
+            .compose(&explain_prompt(context.dialect()), command);
+        self.rate_limiter
+            .acquire(&settings.provider, prompt.tokens + RESPONSE_TOKEN_ALLOWANCE)
+            .await?;
+
+        // For now, summarize from the table of common programs
+        // In production, the provider's one-line answer would be the summary
+        Ok(Explanation::describe(
+            command,
+            risk,
+            context.dialect(),
+            dry_run,
+        ))
+    }
+
+    /// Explain a shell command, one line per pipeline stage
+    pub async fn explain_command(&self, command: &str, _context: &Context) -> Result<Vec<String>> {
+        // For now, describe each stage from a table of common programs
+        // In production, this would ask the AI provider for an explanation
+
+        Ok(split_stages(command)
+            .into_iter()
+            .map(|stage| {
+                let program = stage.split_whitespace().next().unwrap_or_default();
+                match describe_program(program) {
+                    Some(description) => format!("{}: {}", stage, description),
+                    None => format!("{}: runs {}", stage, program),
+                }
+            })
+            .collect())
     }
 
     /// Get AI suggestion for user input (legacy method)
     pub async fn get_suggestion(&self, input: &str, _context: &ProviderContext) -> Result<String> {
+        self.ensure_online()?;
+
         // For now, return a placeholder
         // In production, this would call the actual AI provider
         Ok(format!("# Command suggestion for: {}\n# Provider: {} not yet implemented\necho \"Provider system in development\"", input, self.config.default_provider))
     }
 
-    /// Record usage for cost tracking
+    /// Record usage for cost tracking; `estimated` is what the request
+    /// reserved from the provider's rate limit
     pub async fn record_usage(
         &self,
         provider: &str,
         model: &str,
+        estimated: u32,
         tokens: u32,
         cost: f64,
         success: bool,
     ) -> Result<()> {
+        self.rate_limiter.settle(provider, estimated, tokens);
         if let Some(tracker) = &self.cost_tracker {
             tracker.record_usage(provider, model, tokens, cost, success, None, None).await?;
         }
@@ -181,6 +370,177 @@ impl ProviderRouter {
     pub fn cost_tracker(&self) -> Option<&CostTracker> {
         self.cost_tracker.as_ref()
     }
+
+    /// Cost report for the last `days`, with current rate limit utilization
+    pub async fn cost_report(&self, days: u32) -> Result<Option<CostReport>> {
+        let Some(tracker) = &self.cost_tracker else {
+            return Ok(None);
+        };
+        let mut report = tracker.get_cost_report(days).await?;
+        report.rate_limits = self.rate_limiter.usage();
+        Ok(Some(report))
+    }
+
+    /// How much of each called provider's rate limit is in use
+    pub fn rate_limits(&self) -> Vec<RateLimitUsage> {
+        self.rate_limiter.usage()
+    }
+
+    /// Whether cloud providers may be called
+    pub fn connectivity(&self) -> &Arc<Connectivity> {
+        &self.connectivity
+    }
+
+    fn ensure_online(&self) -> Result<()> {
+        if !self.connectivity.is_online() {
+            anyhow::bail!("{}", self.connectivity.status().message);
+        }
+        Ok(())
+    }
+}
+
+/// The enabled local model, downloading it in the background if it isn't
+/// there yet and the daemon may use the network
+fn local_model(config: &Config) -> Result<Option<Arc<LocalModel>>> {
+    let Some(local) = LocalModel::new(&config.local_model)?.map(Arc::new) else {
+        return Ok(None);
+    };
+    if local.is_ready() {
+        return Ok(Some(local));
+    }
+    if config.connectivity.offline {
+        tracing::warn!(
+            "Local model missing and connectivity.offline is set; put it at {}",
+            local.path().display()
+        );
+    } else {
+        let downloading = Arc::clone(&local);
+        tokio::spawn(async move {
+            if let Err(e) = downloading.prepare().await {
+                tracing::warn!("Local model unavailable: {:#}", e);
+            }
+        });
+    }
+    Ok(Some(local))
+}
+
+/// System prompt asking for a command in `dialect`, for a request written
+/// in `language`
+pub fn system_prompt(dialect: ShellDialect, language: Option<Language>) -> String {
+    let mut prompt = format!(
+        "Turn the user's request into a single {} command. Reply with the command only.",
+        dialect.name()
+    );
+    if let Some(language) = language.filter(|l| !l.is_english()) {
+        prompt.push_str(&format!(
+            " The request is written in {0}; read it as {0}. Write any comments in {0}, \
+             but keep commands, flags and file names as they are.",
+            language.name
+        ));
+    }
+    prompt
+}
+
+/// System prompt asking what a `dialect` command does, in one line
+pub fn explain_prompt(dialect: ShellDialect) -> String {
+    format!(
+        "In one line, say what this {} command does and what it changes. \
+         Reply with the line only.",
+        dialect.name()
+    )
+}
+
+/// What `command` does in one line, from the table of common programs
+pub(crate) fn summarize(command: &str) -> String {
+    let mut summary = split_stages(command)
+        .iter()
+        .map(|stage| {
+            let program = stage.split_whitespace().next().unwrap_or_default();
+            describe_program(program)
+                .map(str::to_string)
+                .unwrap_or_else(|| format!("run {}", program))
+        })
+        .collect::<Vec<_>>()
+        .join(", then ");
+    if let Some(first) = summary.get_mut(..1) {
+        first.make_ascii_uppercase();
+    }
+    summary
+}
+
+/// Split a command line on `|`, `&&`, `||` and `;` outside quotes
+pub(crate) fn split_stages(command: &str) -> Vec<String> {
+    let mut stages = Vec::new();
+    let mut current = String::new();
+    let mut quote = None;
+    let mut chars = command.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match (c, quote) {
+            ('\'' | '"', None) => {
+                quote = Some(c);
+                current.push(c);
+            }
+            (c, Some(q)) if c == q => {
+                quote = None;
+                current.push(c);
+            }
+            ('|' | '&' | ';', None) => {
+                // `&&` and `||` are one separator; a lone `&` backgrounds
+                if c != ';' && chars.peek() == Some(&c) {
+                    chars.next();
+                } else if c == '&' {
+                    current.push(c);
+                    continue;
+                }
+                stages.push(std::mem::take(&mut current));
+            }
+            _ => current.push(c),
+        }
+    }
+    stages.push(current);
+
+    stages
+        .into_iter()
+        .map(|stage| stage.trim().to_string())
+        .filter(|stage| !stage.is_empty())
+        .collect()
+}
+
+fn describe_program(program: &str) -> Option<&'static str> {
+    Some(match program {
+        "ls" => "list directory contents",
+        "cd" => "change the working directory",
+        "cat" => "print file contents",
+        "grep" | "rg" => "search text for a pattern",
+        "find" => "search for files",
+        "sort" => "sort lines",
+        "uniq" => "collapse repeated lines",
+        "head" => "keep the first lines",
+        "tail" => "keep the last lines",
+        "wc" => "count lines, words or bytes",
+        "awk" => "process text by fields",
+        "sed" => "edit text with a stream editor",
+        "xargs" => "run a command with arguments read from input",
+        "cp" => "copy files",
+        "mv" => "move or rename files",
+        "rm" => "remove files",
+        "mkdir" => "create directories",
+        "chmod" => "change file permissions",
+        "chown" => "change file ownership",
+        "tar" => "create or extract archives",
+        "curl" | "wget" => "download from a URL",
+        "ssh" => "open a shell on a remote host",
+        "git" => "run a git operation",
+        "docker" => "manage containers",
+        "kubectl" => "manage Kubernetes resources",
+        "ps" => "list processes",
+        "kill" => "signal a process",
+        "df" => "show disk space",
+        "du" => "show disk usage",
+        "sudo" => "run a command as another user",
+        _ => return None,
+    })
 }
 
 #[cfg(test)]
@@ -212,4 +572,38 @@ mod tests {
             CommandComplexity::Complex
         );
     }
+
+    #[test]
+    fn test_system_prompt_language() {
+        let english = system_prompt(ShellDialect::Posix, Some(Language::ENGLISH));
+        assert_eq!(english, system_prompt(ShellDialect::Posix, None));
+        assert!(english.contains("POSIX shell"));
+
+        let german = Language {
+            code: "de",
+            name: "German",
+        };
+        let prompt = system_prompt(ShellDialect::Fish, Some(german));
+        assert!(prompt.contains("fish command"));
+        assert!(prompt.contains("written in German"));
+    }
+
+    #[test]
+    fn test_split_stages() {
+        assert_eq!(
+            split_stages("ps aux | grep 'a|b' && echo \"x; y\" ; ls"),
+            vec!["ps aux", "grep 'a|b'", "echo \"x; y\"", "ls"]
+        );
+        assert_eq!(split_stages("make || true"), vec!["make", "true"]);
+        assert_eq!(split_stages("sleep 5 & wait"), vec!["sleep 5 & wait"]);
+    }
+
+    #[test]
+    fn test_summarize() {
+        assert_eq!(
+            summarize("find . -name '*.o' | xargs rm"),
+            "Search for files, then run a command with arguments read from input"
+        );
+        assert_eq!(summarize("terraform apply"), "Run terraform");
+    }
 }
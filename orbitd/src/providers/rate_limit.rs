@@ -0,0 +1,270 @@
+// Per-provider rate limiting
+//
+// Each provider gets two token buckets, one for requests and one for
+// tokens, refilled continuously at its per-minute limits. A request that
+// doesn't fit waits for the buckets to refill if that takes no longer than
+// the provider's `max_wait_secs`, and is rejected with the time to retry
+// otherwise. Tokens are reserved from an estimate up front and settled
+// against the real count once the provider answers.
+
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use super::RateLimit;
+use crate::config::Config;
+
+/// A provider's limit was reached and waiting would take too long
+#[derive(Debug, Clone, PartialEq, thiserror::Error)]
+#[error("Rate limit for {provider} reached; retry in {}s", retry_after.as_secs_f64().ceil())]
+pub struct RateLimited {
+    pub provider: String,
+    pub retry_after: Duration,
+}
+
+/// How much of a provider's limits is in use, for status and cost reports
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RateLimitUsage {
+    pub provider: String,
+    pub requests_per_minute: u32,
+    pub tokens_per_minute: u32,
+    /// Share of the request bucket in use, 0 to 1
+    pub request_utilization: f32,
+    /// Share of the token bucket in use, 0 to 1
+    pub token_utilization: f32,
+    /// Requests rejected since the daemon started
+    pub rejected: u64,
+}
+
+/// Refills continuously up to one minute's worth
+#[derive(Debug)]
+struct TokenBucket {
+    capacity: f64,
+    available: f64,
+    updated: Instant,
+}
+
+impl TokenBucket {
+    fn new(per_minute: u32, now: Instant) -> Self {
+        Self {
+            capacity: per_minute as f64,
+            available: per_minute as f64,
+            updated: now,
+        }
+    }
+
+    fn refill(&mut self, now: Instant) {
+        let elapsed = now.saturating_duration_since(self.updated).as_secs_f64();
+        self.available = (self.available + elapsed * self.capacity / 60.0).min(self.capacity);
+        self.updated = now;
+    }
+
+    /// How long until `amount` is available; more than the capacity is
+    /// treated as a full bucket so oversized requests can still go through
+    fn wait_for(&self, amount: f64) -> Duration {
+        let missing = amount.min(self.capacity) - self.available;
+        if missing <= 0.0 || self.capacity == 0.0 {
+            return Duration::ZERO;
+        }
+        Duration::from_secs_f64(missing * 60.0 / self.capacity)
+    }
+
+    /// May go negative when a request used more than it reserved
+    fn take(&mut self, amount: f64) {
+        self.available -= amount;
+    }
+
+    fn utilization(&self) -> f32 {
+        if self.capacity == 0.0 {
+            return 0.0;
+        }
+        (1.0 - self.available / self.capacity).clamp(0.0, 1.0) as f32
+    }
+}
+
+#[derive(Debug)]
+struct ProviderBuckets {
+    limit: RateLimit,
+    requests: TokenBucket,
+    tokens: TokenBucket,
+    rejected: u64,
+}
+
+impl ProviderBuckets {
+    fn new(limit: RateLimit, now: Instant) -> Self {
+        Self {
+            requests: TokenBucket::new(limit.requests_per_minute, now),
+            tokens: TokenBucket::new(limit.tokens_per_minute, now),
+            limit,
+            rejected: 0,
+        }
+    }
+}
+
+/// Token-bucket limits for every provider, from `providers.<name>.rate_limit`
+pub struct RateLimiter {
+    limits: HashMap<String, RateLimit>,
+    buckets: Mutex<HashMap<String, ProviderBuckets>>,
+}
+
+impl RateLimiter {
+    pub fn new(config: &Config) -> Self {
+        Self::with_limits(
+            config
+                .providers
+                .iter()
+                .map(|(name, provider)| {
+                    (
+                        name.clone(),
+                        provider.rate_limit.clone().unwrap_or_default(),
+                    )
+                })
+                .collect(),
+        )
+    }
+
+    /// Limits by provider; others get `RateLimit::default()`
+    pub fn with_limits(limits: HashMap<String, RateLimit>) -> Self {
+        Self {
+            limits,
+            buckets: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Reserve one request and `tokens` estimated tokens for `provider`,
+    /// waiting up to its `max_wait_secs` for them
+    pub async fn acquire(&self, provider: &str, tokens: u32) -> Result<(), RateLimited> {
+        let mut waited = Duration::ZERO;
+        loop {
+            let wait = match self.try_acquire_at(provider, tokens, Instant::now()) {
+                Ok(()) => return Ok(()),
+                Err(wait) => wait,
+            };
+            let max_wait = Duration::from_secs(self.limit(provider).max_wait_secs);
+            if waited + wait > max_wait {
+                self.reject(provider);
+                return Err(RateLimited {
+                    provider: provider.to_string(),
+                    retry_after: wait,
+                });
+            }
+            tracing::debug!("Waiting {:?} for the {} rate limit", wait, provider);
+            tokio::time::sleep(wait).await;
+            waited += wait;
+        }
+    }
+
+    /// Reserve now if everything fits, else say how long until it would
+    fn try_acquire_at(&self, provider: &str, tokens: u32, now: Instant) -> Result<(), Duration> {
+        let mut buckets = self.buckets.lock().unwrap();
+        let buckets = buckets
+            .entry(provider.to_string())
+            .or_insert_with(|| ProviderBuckets::new(self.limit(provider), now));
+        buckets.requests.refill(now);
+        buckets.tokens.refill(now);
+
+        let wait = buckets.requests.wait_for(1.0).max(buckets.tokens.wait_for(tokens as f64));
+        if !wait.is_zero() {
+            return Err(wait);
+        }
+        buckets.requests.take(1.0);
+        buckets.tokens.take(tokens as f64);
+        Ok(())
+    }
+
+    /// Correct a reservation of `estimated` tokens once `actual` are known
+    pub fn settle(&self, provider: &str, estimated: u32, actual: u32) {
+        if let Some(buckets) = self.buckets.lock().unwrap().get_mut(provider) {
+            buckets.tokens.take(actual as f64 - estimated as f64);
+        }
+    }
+
+    /// Current utilization of every provider that has been called
+    pub fn usage(&self) -> Vec<RateLimitUsage> {
+        let now = Instant::now();
+        let mut buckets = self.buckets.lock().unwrap();
+        let mut usage: Vec<RateLimitUsage> = buckets
+            .iter_mut()
+            .map(|(provider, buckets)| {
+                buckets.requests.refill(now);
+                buckets.tokens.refill(now);
+                RateLimitUsage {
+                    provider: provider.clone(),
+                    requests_per_minute: buckets.limit.requests_per_minute,
+                    tokens_per_minute: buckets.limit.tokens_per_minute,
+                    request_utilization: buckets.requests.utilization(),
+                    token_utilization: buckets.tokens.utilization(),
+                    rejected: buckets.rejected,
+                }
+            })
+            .collect();
+        usage.sort_by(|a, b| a.provider.cmp(&b.provider));
+        usage
+    }
+
+    fn limit(&self, provider: &str) -> RateLimit {
+        self.limits.get(provider).cloned().unwrap_or_default()
+    }
+
+    fn reject(&self, provider: &str) {
+        if let Some(buckets) = self.buckets.lock().unwrap().get_mut(provider) {
+            buckets.rejected += 1;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn openai_limiter(requests_per_minute: u32, tokens_per_minute: u32) -> RateLimiter {
+        RateLimiter::with_limits(HashMap::from([(
+            "openai".to_string(),
+            RateLimit {
+                requests_per_minute,
+                tokens_per_minute,
+                max_wait_secs: 0,
+            },
+        )]))
+    }
+
+    #[test]
+    fn test_buckets_refill_over_time() {
+        let limiter = openai_limiter(2, 1000);
+        let start = Instant::now();
+
+        assert!(limiter.try_acquire_at("openai", 100, start).is_ok());
+        assert!(limiter.try_acquire_at("openai", 100, start).is_ok());
+        // Two requests a minute: the next slot opens in 30 seconds
+        let wait = limiter.try_acquire_at("openai", 100, start).unwrap_err();
+        assert_eq!(wait.as_secs(), 30);
+        assert!(limiter.try_acquire_at("openai", 100, start + Duration::from_secs(30)).is_ok());
+
+        // Tokens run out before requests do
+        let tokens = openai_limiter(60, 600);
+        assert!(tokens.try_acquire_at("openai", 500, start).is_ok());
+        let wait = tokens.try_acquire_at("openai", 200, start).unwrap_err();
+        assert_eq!(wait.as_secs(), 10);
+
+        // Unknown providers get the defaults
+        assert!(tokens.try_acquire_at("claude", 1000, start).is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_rejects_with_retry_after_and_reports_usage() {
+        let limiter = openai_limiter(60, 1000);
+        limiter.acquire("openai", 800).await.unwrap();
+        limiter.settle("openai", 800, 900);
+
+        let rejected = limiter.acquire("openai", 500).await.unwrap_err();
+        assert_eq!(rejected.provider, "openai");
+        assert!(rejected.retry_after > Duration::from_secs(20));
+
+        let usage = limiter.usage();
+        assert_eq!(usage.len(), 1);
+        assert!(usage[0].token_utilization > 0.85);
+        assert!(usage[0].request_utilization < 0.05);
+        assert_eq!(usage[0].rejected, 1);
+    }
+}
@@ -0,0 +1,208 @@
+//! Subsystem readiness
+//!
+//! The daemon answers on its socket as soon as it is bound. Subsystems that
+//! are slow to build warm up in the background, and until they are ready
+//! requests use a cheaper fallback:
+//!
+//! - **command cache**: every executable on PATH; until built, the first
+//!   word of a command is looked up on PATH directly
+//! - **embeddings**: the sentence embedding model; until loaded, learned
+//!   patterns only match exactly
+//! - **monitor**: the proactive monitor; no alerts until it runs
+//!
+//! Each subsystem's state is reported in the Status response.
+
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::future::Future;
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+use tracing::{info, warn};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Subsystem {
+    CommandCache,
+    Embeddings,
+    Monitor,
+}
+
+impl Subsystem {
+    const ALL: [Subsystem; 3] = [
+        Subsystem::CommandCache,
+        Subsystem::Embeddings,
+        Subsystem::Monitor,
+    ];
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ReadinessState {
+    /// Not started yet
+    #[default]
+    Pending,
+    Warming,
+    Ready,
+    /// Warming up failed; the fallback stays in use
+    Failed,
+    /// Turned off in the config
+    Disabled,
+}
+
+/// One subsystem as reported to clients
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SubsystemStatus {
+    pub subsystem: Subsystem,
+    pub state: ReadinessState,
+    /// How long warming up took, once ready
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub warm_up_ms: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+#[derive(Default)]
+struct Entry {
+    state: ReadinessState,
+    started: Option<Instant>,
+    warm_up_ms: Option<u64>,
+    error: Option<String>,
+}
+
+/// Tracks which subsystems have warmed up
+pub struct Readiness {
+    entries: Mutex<BTreeMap<Subsystem, Entry>>,
+}
+
+impl Readiness {
+    pub fn new() -> Self {
+        Self {
+            entries: Mutex::new(
+                Subsystem::ALL
+                    .into_iter()
+                    .map(|subsystem| (subsystem, Entry::default()))
+                    .collect(),
+            ),
+        }
+    }
+
+    pub fn state(&self, subsystem: Subsystem) -> ReadinessState {
+        self.entries.lock().unwrap()[&subsystem].state
+    }
+
+    pub fn is_ready(&self, subsystem: Subsystem) -> bool {
+        self.state(subsystem) == ReadinessState::Ready
+    }
+
+    pub fn status(&self) -> Vec<SubsystemStatus> {
+        self.entries
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(subsystem, entry)| SubsystemStatus {
+                subsystem: *subsystem,
+                state: entry.state,
+                warm_up_ms: entry.warm_up_ms,
+                error: entry.error.clone(),
+            })
+            .collect()
+    }
+
+    pub fn disable(&self, subsystem: Subsystem) {
+        self.update(subsystem, |entry| entry.state = ReadinessState::Disabled);
+    }
+
+    pub fn start(&self, subsystem: Subsystem) {
+        self.update(subsystem, |entry| {
+            entry.state = ReadinessState::Warming;
+            entry.started = Some(Instant::now());
+            entry.error = None;
+        });
+    }
+
+    pub fn ready(&self, subsystem: Subsystem) {
+        self.update(subsystem, |entry| {
+            entry.state = ReadinessState::Ready;
+            entry.warm_up_ms = entry
+                .started
+                .map(|started| started.elapsed().as_millis() as u64);
+        });
+        info!("{:?} ready", subsystem);
+    }
+
+    pub fn failed(&self, subsystem: Subsystem, error: &anyhow::Error) {
+        warn!("{:?} unavailable: {:#}", subsystem, error);
+        self.update(subsystem, |entry| {
+            entry.state = ReadinessState::Failed;
+            entry.error = Some(format!("{:#}", error));
+        });
+    }
+
+    fn update(&self, subsystem: Subsystem, change: impl FnOnce(&mut Entry)) {
+        change(self.entries.lock().unwrap().entry(subsystem).or_default());
+    }
+
+    /// Warm `subsystem` up in the background, recording the outcome
+    pub fn spawn<F>(self: &Arc<Self>, subsystem: Subsystem, warm_up: F)
+    where
+        F: Future<Output = anyhow::Result<()>> + Send + 'static,
+    {
+        self.start(subsystem);
+        let readiness = self.clone();
+        tokio::spawn(async move {
+            match warm_up.await {
+                Ok(()) => readiness.ready(subsystem),
+                Err(e) => readiness.failed(subsystem, &e),
+            }
+        });
+    }
+}
+
+impl Default for Readiness {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_warm_up_is_tracked() {
+        let readiness = Arc::new(Readiness::new());
+        assert!(readiness
+            .status()
+            .iter()
+            .all(|s| s.state == ReadinessState::Pending));
+
+        let (tx, rx) = tokio::sync::oneshot::channel::<()>();
+        readiness.spawn(Subsystem::CommandCache, async move {
+            rx.await?;
+            Ok(())
+        });
+        readiness.spawn(Subsystem::Embeddings, async { anyhow::bail!("no model") });
+        readiness.disable(Subsystem::Monitor);
+        assert_eq!(
+            readiness.state(Subsystem::CommandCache),
+            ReadinessState::Warming
+        );
+
+        tx.send(()).unwrap();
+        for _ in 0..100 {
+            if readiness.is_ready(Subsystem::CommandCache)
+                && readiness.state(Subsystem::Embeddings) == ReadinessState::Failed
+            {
+                break;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+        }
+
+        let status = readiness.status();
+        assert_eq!(status[0].state, ReadinessState::Ready);
+        assert!(status[0].warm_up_ms.is_some());
+        assert_eq!(status[1].state, ReadinessState::Failed);
+        assert_eq!(status[1].error.as_deref(), Some("no model"));
+        assert_eq!(status[2].state, ReadinessState::Disabled);
+    }
+}
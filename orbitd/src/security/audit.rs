@@ -11,7 +11,6 @@ use anyhow::Result;
 use serde::{Deserialize, Serialize};
 use sqlx::{Pool, Sqlite, Row};
 use std::path::Path;
-use std::sync::OnceLock;
 use chrono::{DateTime, Utc};
 
 /// Audit logger for security and compliance
@@ -25,12 +24,8 @@ impl AuditLogger {
     /// # Arguments
     /// * `db_path` - Path to audit log database
     pub async fn new<P: AsRef<Path>>(db_path: P) -> Result<Self> {
-        let db_url = format!("sqlite:{}", db_path.as_ref().display());
-
-        let pool = sqlx::sqlite::SqlitePoolOptions::new()
-            .max_connections(5)
-            .connect(&db_url)
-            .await?;
+        // Creates the database if needed; WAL, busy timeout and an integrity check
+        let pool = pulsar_db::open(db_path.as_ref(), &pulsar_db::DbOptions::default()).await?;
 
         let logger = Self { pool };
 
@@ -154,7 +149,7 @@ impl AuditLogger {
                     user: row.get("user"),
                     command: row.get("command"),
                     result: row.get("result"),
-                    details: serde_json::from_str(row.get::<String, _>("details")).ok()?,
+                    details: serde_json::from_str(&row.get::<String, _>("details")).ok()?,
                     severity: row.get::<String, _>("severity").parse().ok()?,
                     timestamp: DateTime::from_timestamp(row.get::<i64, _>("timestamp"), 0)?,
                     session_id: row.get("session_id"),
@@ -325,6 +320,35 @@ impl AuditEvent {
         }
     }
 
+    /// Create a privilege elevation event. `method` is how credentials were
+    /// supplied ("cached", "prompt" or "vault"); the password never is.
+    pub fn privilege_elevation(
+        user: String,
+        command: String,
+        method: &str,
+        outcome: &str,
+        reason: String,
+        session_id: Option<String>,
+    ) -> Self {
+        let severity = match outcome {
+            "granted" => AuditSeverity::Info,
+            _ => AuditSeverity::Warning,
+        };
+        Self {
+            event_type: EventType::PrivilegeElevation,
+            user,
+            command: Some(command),
+            result: Some(outcome.to_string()),
+            details: serde_json::json!({
+                "method": method,
+                "reason": reason,
+            }),
+            severity,
+            timestamp: Utc::now(),
+            session_id,
+        }
+    }
+
     /// Create a configuration change event
     pub fn config_change(
         user: String,
@@ -359,6 +383,7 @@ pub enum EventType {
     SecurityEvent,
     ConfigChange,
     UserAction,
+    PrivilegeElevation,
 }
 
 impl std::fmt::Display for EventType {
@@ -370,6 +395,7 @@ impl std::fmt::Display for EventType {
             EventType::SecurityEvent => write!(f, "SecurityEvent"),
             EventType::ConfigChange => write!(f, "ConfigChange"),
             EventType::UserAction => write!(f, "UserAction"),
+            EventType::PrivilegeElevation => write!(f, "PrivilegeElevation"),
         }
     }
 }
@@ -385,6 +411,7 @@ impl std::str::FromStr for EventType {
             "SecurityEvent" => Ok(EventType::SecurityEvent),
             "ConfigChange" => Ok(EventType::ConfigChange),
             "UserAction" => Ok(EventType::UserAction),
+            "PrivilegeElevation" => Ok(EventType::PrivilegeElevation),
             _ => Err(format!("Unknown event type: {}", s)),
         }
     }
@@ -581,6 +608,34 @@ mod tests {
         assert_eq!(stats.rejection_rate, 50.0);
     }
 
+    #[tokio::test]
+    async fn test_log_privilege_elevation() {
+        let temp_dir = tempdir().unwrap();
+        let db_path = temp_dir.path().join("audit.db");
+
+        let logger = AuditLogger::new(&db_path).await.unwrap();
+
+        logger
+            .log_event(AuditEvent::privilege_elevation(
+                "user".to_string(),
+                "apt install ripgrep".to_string(),
+                "prompt",
+                "denied",
+                "`apt install` changes the system".to_string(),
+                None,
+            ))
+            .await
+            .unwrap();
+
+        let filter = LogFilter::new().event_type(EventType::PrivilegeElevation);
+        let logs = logger.query_logs(filter).await.unwrap();
+
+        assert_eq!(logs.len(), 1);
+        assert_eq!(logs[0].result.as_deref(), Some("denied"));
+        assert_eq!(logs[0].severity, AuditSeverity::Warning);
+        assert_eq!(logs[0].details["method"], "prompt");
+    }
+
     #[tokio::test]
     async fn test_event_type_parsing() {
         assert_eq!(
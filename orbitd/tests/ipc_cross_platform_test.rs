@@ -70,7 +70,7 @@ async fn test_request_response_cycle() {
     assert!(response.is_ok(), "Request failed: {:?}", response.err());
 
     match response.unwrap() {
-        Response::Status { uptime_secs, commands_processed } => {
+        Response::Status { uptime_secs, commands_processed, .. } => {
             // Stub response returns 0 values
             assert_eq!(uptime_secs, 0);
             assert_eq!(commands_processed, 0);
@@ -105,6 +105,8 @@ async fn test_command_request() {
         input: "ls -la".to_string(),
         cwd: "/tmp".to_string(),
         shell: "bash".to_string(),
+        session: None,
+        output: None,
     };
 
     let result = timeout(Duration::from_secs(5), client.send_request(&request)).await;
@@ -149,6 +151,8 @@ async fn test_feedback_request() {
         input: "list files".to_string(),
         executed: "ls -la".to_string(),
         result: FeedbackResult::Success,
+        reasons: vec![],
+        note: None,
     };
 
     let result = timeout(Duration::from_secs(5), client.send_request(&request)).await;
@@ -318,6 +322,8 @@ async fn test_large_message() {
         input: large_input,
         cwd: "/tmp".to_string(),
         shell: "bash".to_string(),
+        session: None,
+        output: None,
     };
 
     let result = timeout(Duration::from_secs(10), client.send_request(&request)).await;
@@ -394,6 +394,7 @@ fn create_test_context() -> Context {
         git: None,
         project_type: None,
         directory_type: DirectoryType::Other,
+        host: None,
         environment_vars: std::collections::HashMap::new(),
     }
 }
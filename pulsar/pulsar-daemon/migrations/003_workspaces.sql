@@ -42,9 +42,6 @@ CREATE INDEX IF NOT EXISTS idx_workspace_sessions_workspace_id ON workspace_sess
 CREATE INDEX IF NOT EXISTS idx_workspace_snapshots_workspace_id ON workspace_snapshots(workspace_id);
 CREATE INDEX IF NOT EXISTS idx_workspace_snapshots_created_at ON workspace_snapshots(created_at DESC);
 
--- Trigger to update updated_at timestamp
-CREATE TRIGGER IF NOT EXISTS update_workspace_timestamp
-AFTER UPDATE ON workspaces
-BEGIN
-    UPDATE workspaces SET updated_at = CURRENT_TIMESTAMP WHERE id = NEW.id;
-END;
+-- updated_at is written as a Unix timestamp by the service; the old trigger
+-- overwrote it with a TEXT timestamp that could not be read back
+DROP TRIGGER IF EXISTS update_workspace_timestamp;
@@ -0,0 +1,15 @@
+-- Snapshot Sessions Migration
+-- Captures workspace session mappings with each snapshot so they can be
+-- compared and restored individually
+
+CREATE TABLE IF NOT EXISTS workspace_snapshot_sessions (
+    snapshot_id TEXT NOT NULL,
+    session_id TEXT NOT NULL,
+    pane_id TEXT NOT NULL,
+    position INTEGER NOT NULL,
+    session_config JSONB,
+    PRIMARY KEY (snapshot_id, session_id),
+    FOREIGN KEY (snapshot_id) REFERENCES workspace_snapshots(id) ON DELETE CASCADE
+);
+
+CREATE INDEX IF NOT EXISTS idx_workspace_snapshot_sessions_snapshot_id ON workspace_snapshot_sessions(snapshot_id);
@@ -0,0 +1,8 @@
+-- Automatic Snapshots Migration
+-- Marks snapshots taken by the scheduler so retention never prunes
+-- snapshots saved by the user
+
+CREATE TABLE IF NOT EXISTS workspace_auto_snapshots (
+    snapshot_id TEXT PRIMARY KEY,
+    FOREIGN KEY (snapshot_id) REFERENCES workspace_snapshots(id) ON DELETE CASCADE
+);
@@ -0,0 +1,16 @@
+-- Snapshot Bookmarks Migration
+-- Keeps the bookmarks set in a workspace's sessions with each snapshot
+
+CREATE TABLE IF NOT EXISTS workspace_snapshot_bookmarks (
+    snapshot_id TEXT NOT NULL,
+    session_id TEXT NOT NULL,
+    bookmark_id TEXT NOT NULL,
+    line INTEGER NOT NULL,
+    note TEXT,
+    text TEXT NOT NULL,
+    created_at INTEGER NOT NULL,
+    PRIMARY KEY (snapshot_id, bookmark_id),
+    FOREIGN KEY (snapshot_id) REFERENCES workspace_snapshots(id) ON DELETE CASCADE
+);
+
+CREATE INDEX IF NOT EXISTS idx_workspace_snapshot_bookmarks_snapshot_id ON workspace_snapshot_bookmarks(snapshot_id);
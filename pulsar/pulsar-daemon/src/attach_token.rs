@@ -0,0 +1,237 @@
+//! Time-limited session attach tokens
+//!
+//! The desktop asks the daemon (over IPC) for a token that lets another
+//! device attach to one running session. Tokens are short enough to fit
+//! in a QR code, expire after a few minutes, can only be redeemed once,
+//! and carry a scope limiting the attached client to viewing or full
+//! control. They are signed with a per-process key, so restarting the
+//! daemon invalidates every outstanding token.
+//!
+//! Clients without a token are only let in from this machine, and web
+//! pages only from the desktop app: any page open in a local browser can
+//! reach a loopback WebSocket.
+
+use anyhow::{anyhow, bail, Context, Result};
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine as _};
+use chrono::{DateTime, Duration, Utc};
+use ring::hmac;
+use ring::rand::{SecureRandom, SystemRandom};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::sync::Mutex;
+use uuid::Uuid;
+
+/// Default token lifetime
+pub const DEFAULT_TTL_SECONDS: i64 = 120;
+
+/// Longest lifetime a caller may request
+pub const MAX_TTL_SECONDS: i64 = 600;
+
+/// Origins of the desktop app's webview
+const APP_ORIGINS: &[&str] = &["tauri://localhost", "http://tauri.localhost", "https://tauri.localhost"];
+
+/// The desktop app's dev server, trusted in debug builds only
+const DEV_ORIGIN: &str = "http://localhost:5173";
+
+/// Whether a client without a token may attach with full control: it must
+/// connect from this machine and, if it is a web page (it sent `origin`),
+/// be the desktop app
+pub fn allows_tokenless(peer: IpAddr, origin: Option<&str>) -> bool {
+    peer.is_loopback()
+        && origin.map_or(true, |origin| {
+            APP_ORIGINS.contains(&origin) || (cfg!(debug_assertions) && origin == DEV_ORIGIN)
+        })
+}
+
+/// What an attached client may do
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AttachScope {
+    /// Receive output only; input is discarded
+    View,
+    /// Receive output and send input
+    Control,
+}
+
+impl AttachScope {
+    /// Whether input from the client should reach the PTY
+    pub fn allows_input(self) -> bool {
+        matches!(self, AttachScope::Control)
+    }
+}
+
+/// Verified claims of a redeemed token
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AttachClaims {
+    #[serde(rename = "sid")]
+    pub session_id: Uuid,
+    #[serde(rename = "scp")]
+    pub scope: AttachScope,
+    /// Expiry as a Unix timestamp
+    pub exp: i64,
+    /// Unique token ID used to prevent reuse
+    pub jti: Uuid,
+}
+
+/// A freshly issued token
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct IssuedToken {
+    pub token: String,
+    pub session_id: Uuid,
+    pub scope: AttachScope,
+    pub expires_at: DateTime<Utc>,
+    /// WebSocket path (including the token) for the attaching client
+    pub ws_path: String,
+}
+
+/// Issues and redeems attach tokens
+pub struct AttachTokens {
+    key: hmac::Key,
+    /// Redeemed token IDs and their expiry, pruned as they lapse
+    redeemed: Mutex<HashMap<Uuid, i64>>,
+}
+
+impl AttachTokens {
+    /// Create an issuer with a random signing key
+    pub fn new() -> Result<Self> {
+        let mut secret = [0u8; 32];
+        SystemRandom::new()
+            .fill(&mut secret)
+            .map_err(|_| anyhow!("Failed to generate attach token key"))?;
+
+        Ok(Self {
+            key: hmac::Key::new(hmac::HMAC_SHA256, &secret),
+            redeemed: Mutex::new(HashMap::new()),
+        })
+    }
+
+    /// Issue a token for a session
+    pub fn issue(
+        &self,
+        session_id: Uuid,
+        scope: AttachScope,
+        ttl_seconds: Option<i64>,
+    ) -> Result<IssuedToken> {
+        let ttl = ttl_seconds.unwrap_or(DEFAULT_TTL_SECONDS);
+        if !(1..=MAX_TTL_SECONDS).contains(&ttl) {
+            bail!("Token lifetime must be between 1 and {} seconds", MAX_TTL_SECONDS);
+        }
+
+        let expires_at = Utc::now() + Duration::seconds(ttl);
+        let claims = AttachClaims {
+            session_id,
+            scope,
+            exp: expires_at.timestamp(),
+            jti: Uuid::new_v4(),
+        };
+
+        let payload = URL_SAFE_NO_PAD.encode(serde_json::to_vec(&claims)?);
+        let signature = hmac::sign(&self.key, payload.as_bytes());
+        let token = format!("{}.{}", payload, URL_SAFE_NO_PAD.encode(signature.as_ref()));
+
+        Ok(IssuedToken {
+            ws_path: format!("/ws/{}?token={}", session_id, token),
+            token,
+            session_id,
+            scope,
+            expires_at,
+        })
+    }
+
+    /// Verify a token for `session_id` and mark it as used
+    pub fn redeem(&self, token: &str, session_id: Uuid) -> Result<AttachClaims> {
+        let (payload, signature) = token.split_once('.').context("Malformed attach token")?;
+        let signature = URL_SAFE_NO_PAD
+            .decode(signature)
+            .context("Malformed attach token")?;
+        hmac::verify(&self.key, payload.as_bytes(), &signature)
+            .map_err(|_| anyhow!("Invalid attach token signature"))?;
+
+        let claims: AttachClaims = serde_json::from_slice(
+            &URL_SAFE_NO_PAD.decode(payload).context("Malformed attach token")?,
+        )
+        .context("Malformed attach token")?;
+
+        let now = Utc::now().timestamp();
+        if claims.exp <= now {
+            bail!("Attach token has expired");
+        }
+        if claims.session_id != session_id {
+            bail!("Attach token is for a different session");
+        }
+
+        let mut redeemed = self.redeemed.lock().unwrap();
+        redeemed.retain(|_, exp| *exp > now);
+        if redeemed.insert(claims.jti, claims.exp).is_some() {
+            bail!("Attach token has already been used");
+        }
+
+        Ok(claims)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_token_roundtrip_is_single_use() {
+        let tokens = AttachTokens::new().unwrap();
+        let session_id = Uuid::new_v4();
+
+        let issued = tokens.issue(session_id, AttachScope::View, None).unwrap();
+        assert!(issued.ws_path.ends_with(&issued.token));
+
+        let claims = tokens.redeem(&issued.token, session_id).unwrap();
+        assert_eq!(claims.scope, AttachScope::View);
+        assert!(!claims.scope.allows_input());
+
+        assert!(tokens.redeem(&issued.token, session_id).is_err());
+    }
+
+    #[test]
+    fn test_rejects_wrong_session_and_tampering() {
+        let tokens = AttachTokens::new().unwrap();
+        let session_id = Uuid::new_v4();
+        let issued = tokens.issue(session_id, AttachScope::Control, Some(60)).unwrap();
+
+        assert!(tokens.redeem(&issued.token, Uuid::new_v4()).is_err());
+
+        let other = AttachTokens::new().unwrap();
+        assert!(other.redeem(&issued.token, session_id).is_err());
+
+        let (_, signature) = issued.token.split_once('.').unwrap();
+        let forged = AttachClaims {
+            session_id,
+            scope: AttachScope::Control,
+            exp: Utc::now().timestamp() + 3600,
+            jti: Uuid::new_v4(),
+        };
+        let forged = format!(
+            "{}.{}",
+            URL_SAFE_NO_PAD.encode(serde_json::to_vec(&forged).unwrap()),
+            signature
+        );
+        assert!(tokens.redeem(&forged, session_id).is_err());
+    }
+
+    #[test]
+    fn test_allows_tokenless() {
+        let local: IpAddr = "127.0.0.1".parse().unwrap();
+        assert!(allows_tokenless(local, None));
+        assert!(allows_tokenless("::1".parse().unwrap(), None));
+        assert!(allows_tokenless(local, Some("tauri://localhost")));
+        assert!(!allows_tokenless(local, Some("https://example.com")));
+        assert!(!allows_tokenless("192.168.1.20".parse().unwrap(), None));
+    }
+
+    #[test]
+    fn test_ttl_is_bounded() {
+        let tokens = AttachTokens::new().unwrap();
+        assert!(tokens.issue(Uuid::new_v4(), AttachScope::View, Some(0)).is_err());
+        assert!(tokens
+            .issue(Uuid::new_v4(), AttachScope::View, Some(MAX_TTL_SECONDS + 1))
+            .is_err());
+    }
+}
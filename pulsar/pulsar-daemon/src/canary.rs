@@ -0,0 +1,172 @@
+//! Canary-first fan-out
+//!
+//! A command re-run across a fleet of sessions can run on one of them
+//! first. The daemon types it into the canary session, waits for the shell
+//! to report it finished, and checks the exit code and, optionally, that
+//! its output matches a pattern. Only if it passes is the command sent to
+//! the other sessions; otherwise they get nothing and the caller gets a
+//! report with the canary's exit code and last lines of output.
+//!
+//! Finishing is detected from the session's command timeline, so the
+//! canary's shell needs OSC 133 integration (or prompt titles, which give
+//! no exit code: set `exit_code` to null and check the output instead).
+
+use anyhow::{Context, Result};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::session_manager::CommandRecord;
+
+/// Output lines kept in a report
+const REPORT_LINES: usize = 50;
+
+fn default_exit_code() -> Option<i32> {
+    Some(0)
+}
+
+fn default_timeout_secs() -> u64 {
+    60
+}
+
+/// Run a fan-out on one session first, going on only if it succeeds
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Canary {
+    /// One of the target sessions
+    pub session_id: Uuid,
+    /// Exit code the command must end with; null accepts any
+    #[serde(default = "default_exit_code")]
+    pub exit_code: Option<i32>,
+    /// Regular expression the command's output must match
+    #[serde(default)]
+    pub output_pattern: Option<String>,
+    /// Seconds to wait for the command to finish
+    #[serde(default = "default_timeout_secs")]
+    pub timeout_secs: u64,
+}
+
+impl Canary {
+    /// The compiled output pattern, if one is set
+    pub fn pattern(&self) -> Result<Option<Regex>> {
+        self.output_pattern
+            .as_deref()
+            .map(|pattern| {
+                Regex::new(pattern).with_context(|| format!("Invalid canary output pattern {:?}", pattern))
+            })
+            .transpose()
+    }
+
+    /// Check the canary's run: `finished` is its timeline record, None if
+    /// it didn't finish in time, and `output` the lines it printed
+    pub fn judge(
+        &self,
+        pattern: Option<&Regex>,
+        finished: Option<&CommandRecord>,
+        output: Vec<String>,
+        elapsed_ms: u64,
+    ) -> CanaryReport {
+        let failure = match finished {
+            None => Some(format!(
+                "Didn't finish within {}s, or the shell doesn't report when commands finish",
+                self.timeout_secs
+            )),
+            Some(record) => match (self.exit_code, record.exit_code) {
+                (Some(expected), Some(code)) if code != expected => {
+                    Some(format!("Exited with {}, expected {}", code, expected))
+                }
+                (Some(_), None) => Some(
+                    "The shell doesn't report exit codes; set exit_code to null to check the output only"
+                        .to_string(),
+                ),
+                _ => pattern
+                    .filter(|pattern| !pattern.is_match(&output.join("\n")))
+                    .map(|pattern| format!("Output doesn't match {:?}", pattern.as_str())),
+            },
+        };
+
+        let skip = output.len().saturating_sub(REPORT_LINES);
+        CanaryReport {
+            session_id: self.session_id,
+            passed: failure.is_none(),
+            failure,
+            exit_code: finished.and_then(|record| record.exit_code),
+            duration_ms: finished.map_or(elapsed_ms, |record| record.duration_ms),
+            output: output.into_iter().skip(skip).collect(),
+        }
+    }
+}
+
+/// How the canary did
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CanaryReport {
+    pub session_id: Uuid,
+    /// Whether it met the success criteria
+    pub passed: bool,
+    /// Why not
+    #[serde(default)]
+    pub failure: Option<String>,
+    /// None if the shell doesn't report it or the command didn't finish
+    #[serde(default)]
+    pub exit_code: Option<i32>,
+    pub duration_ms: u64,
+    /// Last lines of the command's output
+    pub output: Vec<String>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn finished(exit_code: Option<i32>) -> CommandRecord {
+        serde_json::from_value(serde_json::json!({
+            "id": Uuid::new_v4(),
+            "command": "systemctl reload nginx",
+            "source": "osc133",
+            "started_at": "2026-10-17T09:00:00Z",
+            "finished_at": "2026-10-17T09:00:00.120Z",
+            "duration_ms": 120,
+            "exit_code": exit_code,
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_judge() {
+        // Exit code 0 unless told otherwise
+        let canary: Canary = serde_json::from_value(serde_json::json!({
+            "session_id": Uuid::new_v4(),
+            "output_pattern": "(?m)^nginx: .* ok$",
+        }))
+        .unwrap();
+        assert_eq!((canary.exit_code, canary.timeout_secs), (Some(0), 60));
+        let pattern = canary.pattern().unwrap();
+        let output = || vec!["nginx: configuration file /etc/nginx/nginx.conf test is ok".to_string()];
+
+        let report = canary.judge(pattern.as_ref(), Some(&finished(Some(0))), output(), 0);
+        assert!(report.passed, "{:?}", report.failure);
+        assert_eq!((report.exit_code, report.duration_ms), (Some(0), 120));
+
+        let report = canary.judge(pattern.as_ref(), Some(&finished(Some(1))), output(), 0);
+        assert_eq!(report.failure.as_deref(), Some("Exited with 1, expected 0"));
+
+        let report = canary.judge(pattern.as_ref(), Some(&finished(Some(0))), vec!["failed".to_string()], 0);
+        assert!(report.failure.unwrap().contains("doesn't match"));
+
+        // No exit code from the shell: only passes when none is expected
+        assert!(!canary.judge(pattern.as_ref(), Some(&finished(None)), output(), 0).passed);
+        let any_exit = Canary { exit_code: None, ..canary.clone() };
+        assert!(any_exit.judge(pattern.as_ref(), Some(&finished(None)), output(), 0).passed);
+
+        let report = canary.judge(pattern.as_ref(), None, output(), 60_000);
+        assert!(!report.passed);
+        assert_eq!((report.exit_code, report.duration_ms), (None, 60_000));
+
+        let long = (0..200).map(|i| i.to_string()).collect();
+        let report = any_exit.judge(None, Some(&finished(None)), long, 0);
+        assert_eq!(report.output.len(), REPORT_LINES);
+        assert_eq!(report.output.last().unwrap(), "199");
+
+        let invalid = Canary { output_pattern: Some("(".to_string()), ..canary };
+        assert!(invalid.pattern().is_err());
+    }
+}
@@ -0,0 +1,256 @@
+//! Self-signed certificates for the WebTransport server
+//!
+//! Browsers accept a self-signed WebTransport certificate when the page
+//! passes its SHA-256 hash in `serverCertificateHashes`, as long as the
+//! certificate uses ECDSA and is valid for at most 14 days. The manager
+//! keeps the certificate being served plus its successor, publishes both
+//! hashes over IPC, and swaps the successor in before the current one
+//! expires so clients holding the published hashes never see a gap.
+//!
+//! A certificate configured under `[tls]` is served as is instead, for
+//! deployments where clients trust a CA.
+
+use anyhow::{Context, Result};
+use base64::{engine::general_purpose, Engine as _};
+use chrono::{DateTime, Duration, Utc};
+use ring::digest::{digest, SHA256};
+use rustls::pki_types::{CertificateDer, PrivateKeyDer};
+use rustls::server::{ClientHello, ResolvesServerCert};
+use rustls::sign::CertifiedKey;
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+use std::sync::{Arc, RwLock};
+use tracing::{error, info};
+
+/// Lifetime of each generated certificate (browsers cap this at 14 days)
+pub const CERT_VALIDITY_DAYS: i64 = 10;
+
+/// How long before expiry the successor certificate is swapped in
+pub const ROTATE_BEFORE_HOURS: i64 = 48;
+
+/// Hostnames and addresses covered by the generated certificates
+const SUBJECT_ALT_NAMES: &[&str] = &["localhost", "127.0.0.1", "::1"];
+
+/// A certificate hash in the shape expected by `serverCertificateHashes`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CertificateHash {
+    /// Always `sha-256`
+    pub algorithm: String,
+    /// Base64-encoded digest of the DER certificate
+    pub value: String,
+    pub not_before: DateTime<Utc>,
+    pub not_after: DateTime<Utc>,
+}
+
+struct GeneratedCert {
+    key: Arc<CertifiedKey>,
+    hash: CertificateHash,
+}
+
+struct CertState {
+    current: GeneratedCert,
+    next: GeneratedCert,
+}
+
+/// Owns and rotates the WebTransport server certificates
+pub struct CertManager {
+    state: RwLock<CertState>,
+}
+
+impl CertManager {
+    /// Generate the initial certificate pair
+    pub fn new() -> Result<Arc<Self>> {
+        let now = Utc::now();
+        let current = generate_cert(now - Duration::minutes(5))?;
+        let next = generate_cert(successor_start(&current))?;
+
+        info!(
+            "Generated WebTransport certificate (expires {})",
+            current.hash.not_after
+        );
+
+        Ok(Arc::new(Self {
+            state: RwLock::new(CertState { current, next }),
+        }))
+    }
+
+    /// Hashes of the served certificate and its successor, current first
+    pub fn hashes(&self) -> Vec<CertificateHash> {
+        let state = self.state.read().unwrap();
+        vec![state.current.hash.clone(), state.next.hash.clone()]
+    }
+
+    /// When the served certificate should be replaced
+    pub fn rotation_due(&self) -> DateTime<Utc> {
+        let state = self.state.read().unwrap();
+        state.current.hash.not_after - Duration::hours(ROTATE_BEFORE_HOURS)
+    }
+
+    /// Promote the successor and generate a new one
+    pub fn rotate(&self) -> Result<()> {
+        let mut state = self.state.write().unwrap();
+        let next = generate_cert(successor_start(&state.next))?;
+        let previous = std::mem::replace(&mut state.next, next);
+        state.current = previous;
+
+        info!(
+            "Rotated WebTransport certificate (expires {})",
+            state.current.hash.not_after
+        );
+        Ok(())
+    }
+
+    /// Certificate resolver for the rustls server config
+    pub fn resolver(self: &Arc<Self>) -> Arc<dyn ResolvesServerCert> {
+        Arc::new(CertResolver(Arc::clone(self)))
+    }
+
+    /// Rotate certificates whenever they come due; runs until the task is aborted
+    pub async fn run_rotation(self: Arc<Self>) {
+        loop {
+            let wait = (self.rotation_due() - Utc::now())
+                .to_std()
+                .unwrap_or(std::time::Duration::ZERO);
+            tokio::time::sleep(wait).await;
+
+            if let Err(e) = self.rotate() {
+                error!("WebTransport certificate rotation failed: {}", e);
+                tokio::time::sleep(std::time::Duration::from_secs(60)).await;
+            }
+        }
+    }
+
+    fn current(&self) -> Arc<CertifiedKey> {
+        Arc::clone(&self.state.read().unwrap().current.key)
+    }
+}
+
+#[derive(Debug)]
+struct CertResolver(Arc<CertManager>);
+
+impl std::fmt::Debug for CertManager {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("CertManager")
+            .field("hashes", &self.hashes())
+            .finish()
+    }
+}
+
+impl ResolvesServerCert for CertResolver {
+    fn resolve(&self, _client_hello: ClientHello<'_>) -> Option<Arc<CertifiedKey>> {
+        Some(self.0.current())
+    }
+}
+
+/// Serves one certificate loaded from disk
+#[derive(Debug)]
+struct FixedCert(Arc<CertifiedKey>);
+
+impl ResolvesServerCert for FixedCert {
+    fn resolve(&self, _client_hello: ClientHello<'_>) -> Option<Arc<CertifiedKey>> {
+        Some(Arc::clone(&self.0))
+    }
+}
+
+/// Resolver serving the PEM certificate chain and key at the given paths
+pub fn load_resolver(cert_path: &Path, key_path: &Path) -> Result<Arc<dyn ResolvesServerCert>> {
+    let cert_pem = std::fs::read(cert_path)
+        .with_context(|| format!("Failed to read certificate {:?}", cert_path))?;
+    let chain = rustls_pemfile::certs(&mut cert_pem.as_slice())
+        .collect::<std::result::Result<Vec<_>, _>>()
+        .with_context(|| format!("Invalid certificate {:?}", cert_path))?;
+    if chain.is_empty() {
+        anyhow::bail!("No certificate found in {:?}", cert_path);
+    }
+
+    let key_pem =
+        std::fs::read(key_path).with_context(|| format!("Failed to read key {:?}", key_path))?;
+    let key_der = rustls_pemfile::private_key(&mut key_pem.as_slice())
+        .with_context(|| format!("Invalid key {:?}", key_path))?
+        .with_context(|| format!("No private key found in {:?}", key_path))?;
+    let signing_key = rustls::crypto::ring::sign::any_supported_type(&key_der)
+        .context("Unsupported certificate key")?;
+
+    info!("Serving WebTransport certificate from {:?}", cert_path);
+    Ok(Arc::new(FixedCert(Arc::new(CertifiedKey::new(chain, signing_key)))))
+}
+
+/// The successor becomes valid shortly before it is swapped in
+fn successor_start(cert: &GeneratedCert) -> DateTime<Utc> {
+    cert.hash.not_after - Duration::hours(ROTATE_BEFORE_HOURS) - Duration::minutes(5)
+}
+
+/// Generate a short-lived ECDSA P-256 certificate valid from `not_before`
+fn generate_cert(not_before: DateTime<Utc>) -> Result<GeneratedCert> {
+    let not_after = not_before + Duration::days(CERT_VALIDITY_DAYS);
+
+    let mut params = rcgen::CertificateParams::new(
+        SUBJECT_ALT_NAMES.iter().map(|s| s.to_string()).collect::<Vec<_>>(),
+    )
+    .context("Invalid certificate subject")?;
+    params.not_before = to_offset(not_before)?;
+    params.not_after = to_offset(not_after)?;
+
+    let key_pair = rcgen::KeyPair::generate_for(&rcgen::PKCS_ECDSA_P256_SHA256)
+        .context("Failed to generate certificate key")?;
+    let cert = params
+        .self_signed(&key_pair)
+        .context("Failed to generate certificate")?;
+
+    let cert_der: CertificateDer<'static> = cert.der().clone();
+    let key_der = PrivateKeyDer::Pkcs8(key_pair.serialize_der().into());
+    let signing_key = rustls::crypto::ring::sign::any_supported_type(&key_der)
+        .context("Unsupported certificate key")?;
+
+    let hash = CertificateHash {
+        algorithm: "sha-256".to_string(),
+        value: general_purpose::STANDARD.encode(digest(&SHA256, &cert_der)),
+        not_before,
+        not_after,
+    };
+
+    Ok(GeneratedCert {
+        key: Arc::new(CertifiedKey::new(vec![cert_der], signing_key)),
+        hash,
+    })
+}
+
+fn to_offset(time: DateTime<Utc>) -> Result<time::OffsetDateTime> {
+    time::OffsetDateTime::from_unix_timestamp(time.timestamp())
+        .context("Certificate date out of range")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_certificates_fit_browser_limits() {
+        let manager = CertManager::new().unwrap();
+        let hashes = manager.hashes();
+
+        assert_eq!(hashes.len(), 2);
+        for hash in &hashes {
+            assert_eq!(hash.algorithm, "sha-256");
+            assert_eq!(general_purpose::STANDARD.decode(&hash.value).unwrap().len(), 32);
+            assert!(hash.not_after - hash.not_before <= Duration::days(14));
+        }
+
+        // The successor is valid before the current certificate is rotated out
+        assert!(hashes[1].not_before < manager.rotation_due());
+        assert!(hashes[0].not_before <= Utc::now());
+    }
+
+    #[test]
+    fn test_rotation_promotes_successor() {
+        let manager = CertManager::new().unwrap();
+        let before = manager.hashes();
+
+        manager.rotate().unwrap();
+        let after = manager.hashes();
+
+        assert_eq!(after[0], before[1]);
+        assert_ne!(after[1], before[1]);
+        assert!(manager.rotation_due() > before[0].not_after - Duration::hours(ROTATE_BEFORE_HOURS));
+    }
+}
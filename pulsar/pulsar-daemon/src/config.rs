@@ -1,44 +1,713 @@
 //! Daemon configuration
+//!
+//! Read from `pulsar.toml` in the orbit config directory (or the file named
+//! by `--config` or `PULSAR_CONFIG`); every setting is optional. Each
+//! setting can then be overridden by an environment variable named after
+//! its path, e.g. `PULSAR_WEBSOCKET_PORT` or `PULSAR_TRANSFERS_MAX_FILE_SIZE`.
+//! See `pulsar.toml.example` for the documented settings.
 
-use anyhow::Result;
+use crate::file_transfer::TransferConfig;
+use crate::health::HealthConfig;
+use crate::relay::RelayConfig;
+use crate::resources::ResourceConfig;
+use crate::sizing::SizePolicy;
+use crate::ssh_ca::SshCaConfig;
+use crate::team_directory::TeamDirectoryConfig;
+use anyhow::{bail, Context, Result};
+use pulsar_log::LogFileConfig;
+use pulsar_webhook::WebhooksConfig;
 use serde::{Deserialize, Serialize};
-use std::path::PathBuf;
+use std::collections::BTreeMap;
+use std::fmt::Display;
+use std::net::{IpAddr, Ipv4Addr};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+/// Environment variable naming the config file
+pub const CONFIG_ENV: &str = "PULSAR_CONFIG";
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
 pub struct DaemonConfig {
     pub socket_path: PathBuf,
+    /// Workspace database
     pub database_path: PathBuf,
-    pub log_level: String,
+    /// Address the WebSocket and WebTransport servers listen on. Clients
+    /// from other machines, e.g. phones attaching with a token, need it to
+    /// be a reachable one such as 0.0.0.0; they always need a token, and
+    /// their file transfers need `transfers.trusted_senders`.
+    pub listen_addr: IpAddr,
     pub websocket_port: u16,
     pub grpc_port: u16,
     pub webtransport_port: u16,
+    /// Encrypt session configs (hosts, users, commands) in the database,
+    /// with the key kept in the OS keychain
+    pub encrypt_at_rest: bool,
+    /// orbitd's socket, to which the focused terminal's host, shell and
+    /// directory are reported; an empty path turns the reports off
+    #[serde(serialize_with = "empty_if_none")]
+    pub orbit_socket: Option<PathBuf>,
+    /// Seconds connected clients are given to wrap up before the daemon
+    /// stops; skipped when no client is attached
+    pub shutdown_grace_secs: u64,
+    pub log: LogConfig,
+    pub tls: TlsConfig,
+    pub transfers: TransferConfig,
+    pub snapshots: SnapshotScheduleConfig,
+    pub health: HealthConfig,
+    pub relay: RelayConfig,
+    pub webhooks: WebhooksConfig,
+    /// Read-only hosts pulled from a team-managed directory
+    pub team: TeamDirectoryConfig,
+    /// How a session's size is picked when several clients are attached
+    pub size_policy: SizePolicy,
+    /// Sampling of sessions' process trees, and when to alert on them
+    pub resources: ResourceConfig,
+    /// Short-lived SSH certificates from an organizational CA
+    pub ssh_ca: SshCaConfig,
+}
+
+/// Log verbosity; `RUST_LOG`, when set, takes precedence
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LogConfig {
+    /// Default level: error, warn, info, debug or trace
+    pub level: String,
+    /// Levels for individual modules, e.g. `"pulsar_daemon::websocket" = "debug"`
+    pub targets: BTreeMap<String, String>,
+    /// Also write to a rotating file; stderr only when unset
+    pub file: Option<LogFileConfig>,
+}
+
+impl Default for LogConfig {
+    fn default() -> Self {
+        Self {
+            level: "info".to_string(),
+            targets: BTreeMap::new(),
+            file: None,
+        }
+    }
+}
+
+impl LogConfig {
+    /// The levels as a `tracing_subscriber` filter, e.g. `info,pulsar_daemon::ipc=debug`
+    pub fn filter(&self) -> String {
+        std::iter::once(self.level.clone())
+            .chain(
+                self.targets
+                    .iter()
+                    .map(|(target, level)| format!("{}={}", target, level)),
+            )
+            .collect::<Vec<_>>()
+            .join(",")
+    }
+}
+
+/// Certificate for the WebTransport server
+///
+/// Without one the daemon serves short-lived self-signed certificates and
+/// publishes their hashes over IPC.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TlsConfig {
+    /// PEM certificate chain
+    pub cert_path: Option<PathBuf>,
+    /// PEM private key
+    pub key_path: Option<PathBuf>,
+}
+
+/// Automatic workspace snapshot schedule and retention
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SnapshotScheduleConfig {
+    pub enabled: bool,
+    /// Minutes between snapshot runs; unchanged workspaces are skipped
+    pub interval_minutes: u64,
+    /// Number of most recent hours that keep their latest snapshot
+    pub keep_hourly: usize,
+    /// Number of most recent days that keep their latest snapshot
+    pub keep_daily: usize,
+    /// Hours between database compactions
+    pub compact_interval_hours: u64,
+    /// Days after which any snapshot, automatic or saved by the user, is
+    /// deleted; 0, the default, keeps snapshots forever
+    pub retention_days: u32,
+}
+
+impl Default for SnapshotScheduleConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            interval_minutes: 15,
+            keep_hourly: 24,
+            keep_daily: 7,
+            compact_interval_hours: 24,
+            retention_days: 0,
+        }
+    }
 }
 
 impl Default for DaemonConfig {
     fn default() -> Self {
-        let config_dir = dirs::config_dir()
-            .expect("Could not find config directory")
-            .join("orbit");
+        let config_dir = dirs::config_dir().expect("Could not find config directory");
 
         Self {
-            socket_path: config_dir.join("pulsar.sock"),
-            database_path: config_dir.join("pulsar.db"),
-            log_level: "info".to_string(),
+            socket_path: default_socket_path(&config_dir),
+            database_path: config_dir.join("pulsar").join("workspaces.db"),
+            listen_addr: IpAddr::V4(Ipv4Addr::LOCALHOST),
             websocket_port: 3030,
             grpc_port: 50051,
             webtransport_port: 4433,
+            encrypt_at_rest: false,
+            // orbitd speaks a different framing on its Windows pipe
+            orbit_socket: dirs::home_dir()
+                .filter(|_| cfg!(unix))
+                .map(|home| home.join(".orbit").join("daemon.sock")),
+            shutdown_grace_secs: 5,
+            log: LogConfig::default(),
+            tls: TlsConfig::default(),
+            transfers: TransferConfig::default(),
+            snapshots: SnapshotScheduleConfig::default(),
+            health: HealthConfig::default(),
+            relay: RelayConfig::default(),
+            webhooks: WebhooksConfig::default(),
+            team: TeamDirectoryConfig::default(),
+            size_policy: SizePolicy::default(),
+            resources: ResourceConfig::default(),
+            ssh_ca: SshCaConfig::default(),
         }
     }
 }
 
+/// Where the desktop app looks for the daemon by default
+#[cfg(unix)]
+fn default_socket_path(config_dir: &Path) -> PathBuf {
+    config_dir.join("orbit").join("pulsar.sock")
+}
+
+/// Where the desktop app looks for the daemon by default: a pipe per user,
+/// so each user on the machine can run their own daemon
+#[cfg(windows)]
+fn default_socket_path(_config_dir: &Path) -> PathBuf {
+    let user = std::env::var("USERNAME").unwrap_or_default();
+    PathBuf::from(format!(r"\\.\pipe\pulsar-daemon-{}", user))
+}
+
 impl DaemonConfig {
-    pub fn load() -> Result<Self> {
-        // TODO: Load from file
-        Ok(Self::default())
+    /// Load the config file, apply environment overrides and validate
+    ///
+    /// `path` overrides `PULSAR_CONFIG` and the default location; a
+    /// missing file is only an error when it was named explicitly.
+    pub fn load(path: Option<&Path>) -> Result<Self> {
+        let named = Self::named(path);
+        let file = match &named {
+            Some(path) => path.clone(),
+            None => Self::path()?,
+        };
+
+        let mut config = if file.exists() {
+            let content = std::fs::read_to_string(&file)
+                .with_context(|| format!("Failed to read config file {:?}", file))?;
+            Self::parse(&content).with_context(|| format!("Invalid config file {:?}", file))?
+        } else if named.is_some() {
+            bail!("Config file {:?} does not exist", file);
+        } else {
+            Self::default()
+        };
+
+        config.apply_env(|name| std::env::var(name).ok())?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    /// Default config file location
+    /// `pulsar.yaml` beside the config file `load` reads
+    pub fn declaration_path(path: Option<&Path>) -> Result<PathBuf> {
+        let file = match Self::named(path) {
+            Some(path) => path,
+            None => Self::path()?,
+        };
+        Ok(file.with_file_name("pulsar.yaml"))
+    }
+
+    /// Config file given on the command line or in the environment
+    fn named(path: Option<&Path>) -> Option<PathBuf> {
+        path.map(Path::to_path_buf)
+            .or_else(|| std::env::var_os(CONFIG_ENV).map(PathBuf::from))
+    }
+
+    pub fn path() -> Result<PathBuf> {
+        Ok(dirs::config_dir()
+            .context("Could not find config directory")?
+            .join("orbit")
+            .join("pulsar.toml"))
+    }
+
+    pub fn parse(content: &str) -> Result<Self> {
+        let mut config: Self = toml::from_str(content)?;
+        // TOML has no null, so an empty path switches the bridge off
+        if config.orbit_socket.as_ref().is_some_and(|path| path.as_os_str().is_empty()) {
+            config.orbit_socket = None;
+        }
+        Ok(config)
+    }
+
+    /// The effective config, as TOML
+    pub fn to_toml(&self) -> Result<String> {
+        toml::to_string_pretty(self).context("Failed to serialize config")
     }
 
     pub fn save(&self) -> Result<()> {
-        // TODO: Save to file
+        let path = Self::path()?;
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        pulsar_fs::write(&path, self.to_toml()?)
+            .with_context(|| format!("Failed to write config file {:?}", path))
+    }
+
+    /// Override settings from `PULSAR_*` variables looked up with `env`
+    pub fn apply_env(&mut self, env: impl Fn(&str) -> Option<String>) -> Result<()> {
+        let env = &env;
+        override_value(&mut self.socket_path, "PULSAR_SOCKET_PATH", env)?;
+        override_value(&mut self.database_path, "PULSAR_DATABASE_PATH", env)?;
+        override_value(&mut self.listen_addr, "PULSAR_LISTEN_ADDR", env)?;
+        override_value(&mut self.websocket_port, "PULSAR_WEBSOCKET_PORT", env)?;
+        override_value(&mut self.grpc_port, "PULSAR_GRPC_PORT", env)?;
+        override_value(&mut self.webtransport_port, "PULSAR_WEBTRANSPORT_PORT", env)?;
+        override_value(&mut self.encrypt_at_rest, "PULSAR_ENCRYPT_AT_REST", env)?;
+        override_path(&mut self.orbit_socket, "PULSAR_ORBIT_SOCKET", env);
+        override_value(&mut self.shutdown_grace_secs, "PULSAR_SHUTDOWN_GRACE_SECS", env)?;
+
+        override_value(&mut self.log.level, "PULSAR_LOG_LEVEL", env)?;
+        // An empty directory turns file logging off
+        if let Some(dir) = env("PULSAR_LOG_FILE_DIR") {
+            self.log.file = match (dir.is_empty(), self.log.file.take()) {
+                (true, _) => None,
+                (false, Some(file)) => Some(LogFileConfig { dir: dir.into(), ..file }),
+                (false, None) => Some(LogFileConfig::new(dir)),
+            };
+        }
+        if let Some(file) = &mut self.log.file {
+            override_value(&mut file.rotation, "PULSAR_LOG_FILE_ROTATION", env)?;
+            override_value(&mut file.max_size_mb, "PULSAR_LOG_FILE_MAX_SIZE_MB", env)?;
+            override_value(&mut file.max_files, "PULSAR_LOG_FILE_MAX_FILES", env)?;
+        }
+
+        override_path(&mut self.tls.cert_path, "PULSAR_TLS_CERT_PATH", env);
+        override_path(&mut self.tls.key_path, "PULSAR_TLS_KEY_PATH", env);
+
+        let transfers = &mut self.transfers;
+        override_value(&mut transfers.storage_path, "PULSAR_TRANSFERS_STORAGE_PATH", env)?;
+        override_value(&mut transfers.chunk_size, "PULSAR_TRANSFERS_CHUNK_SIZE", env)?;
+        override_value(
+            &mut transfers.max_parallel_chunks,
+            "PULSAR_TRANSFERS_MAX_PARALLEL_CHUNKS",
+            env,
+        )?;
+        override_value(&mut transfers.max_file_size, "PULSAR_TRANSFERS_MAX_FILE_SIZE", env)?;
+        override_value(
+            &mut transfers.transfer_timeout_secs,
+            "PULSAR_TRANSFERS_TRANSFER_TIMEOUT_SECS",
+            env,
+        )?;
+        override_option(
+            &mut transfers.max_bytes_per_sec,
+            "PULSAR_TRANSFERS_MAX_BYTES_PER_SEC",
+            env,
+        )?;
+        override_option(&mut transfers.burst_bytes, "PULSAR_TRANSFERS_BURST_BYTES", env)?;
+        override_option(
+            &mut transfers.sftp.listen_addr,
+            "PULSAR_TRANSFERS_SFTP_LISTEN_ADDR",
+            env,
+        )?;
+        override_path(&mut transfers.sftp.root, "PULSAR_TRANSFERS_SFTP_ROOT", env);
+
+        // Lists: extensions separated by commas, the scanner as a command line
+        let policy = &mut transfers.policy;
+        if let Some(extensions) = env("PULSAR_TRANSFERS_POLICY_BLOCKED_EXTENSIONS") {
+            policy.blocked_extensions = extensions
+                .split(',')
+                .map(str::trim)
+                .filter(|extension| !extension.is_empty())
+                .map(String::from)
+                .collect();
+        }
+        if let Some(command) = env("PULSAR_TRANSFERS_POLICY_SCANNER_COMMAND") {
+            policy.scanner_command = command.split_whitespace().map(String::from).collect();
+        }
+        override_value(
+            &mut policy.scanner_timeout_secs,
+            "PULSAR_TRANSFERS_POLICY_SCANNER_TIMEOUT_SECS",
+            env,
+        )?;
+        override_path(&mut policy.quarantine_dir, "PULSAR_TRANSFERS_POLICY_QUARANTINE_DIR", env);
+        if let Some(senders) = env("PULSAR_TRANSFERS_TRUSTED_SENDERS") {
+            transfers.trusted_senders = senders
+                .split(',')
+                .map(str::trim)
+                .filter(|sender| !sender.is_empty())
+                .map(|sender| {
+                    sender.parse().map_err(|e| {
+                        anyhow::anyhow!("PULSAR_TRANSFERS_TRUSTED_SENDERS={:?}: {}", sender, e)
+                    })
+                })
+                .collect::<Result<_>>()?;
+        }
+
+        let snapshots = &mut self.snapshots;
+        override_value(&mut snapshots.enabled, "PULSAR_SNAPSHOTS_ENABLED", env)?;
+        override_value(&mut snapshots.interval_minutes, "PULSAR_SNAPSHOTS_INTERVAL_MINUTES", env)?;
+        override_value(&mut snapshots.keep_hourly, "PULSAR_SNAPSHOTS_KEEP_HOURLY", env)?;
+        override_value(&mut snapshots.keep_daily, "PULSAR_SNAPSHOTS_KEEP_DAILY", env)?;
+        override_value(
+            &mut snapshots.compact_interval_hours,
+            "PULSAR_SNAPSHOTS_COMPACT_INTERVAL_HOURS",
+            env,
+        )?;
+        override_value(&mut snapshots.retention_days, "PULSAR_SNAPSHOTS_RETENTION_DAYS", env)?;
+
+        let health = &mut self.health;
+        override_option(&mut health.probe_addr, "PULSAR_HEALTH_PROBE_ADDR", env)?;
+        override_value(&mut health.disk_degraded_mb, "PULSAR_HEALTH_DISK_DEGRADED_MB", env)?;
+        override_value(&mut health.disk_unhealthy_mb, "PULSAR_HEALTH_DISK_UNHEALTHY_MB", env)?;
+
+        let relay = &mut self.relay;
+        override_option(&mut relay.listen_addr, "PULSAR_RELAY_LISTEN_ADDR", env)?;
+        override_value(&mut relay.pairing_timeout_secs, "PULSAR_RELAY_PAIRING_TIMEOUT_SECS", env)?;
+        override_value(&mut relay.max_sessions, "PULSAR_RELAY_MAX_SESSIONS", env)?;
+        override_value(
+            &mut relay.max_bytes_per_session,
+            "PULSAR_RELAY_MAX_BYTES_PER_SESSION",
+            env,
+        )?;
+
+        let webhooks = &mut self.webhooks;
+        override_value(&mut webhooks.max_attempts, "PULSAR_WEBHOOKS_MAX_ATTEMPTS", env)?;
+        override_value(&mut webhooks.timeout_secs, "PULSAR_WEBHOOKS_TIMEOUT_SECS", env)?;
+
+        let team = &mut self.team;
+        override_option(&mut team.url, "PULSAR_TEAM_URL", env)?;
+        override_option(&mut team.public_key, "PULSAR_TEAM_PUBLIC_KEY", env)?;
+        override_option(&mut team.user, "PULSAR_TEAM_USER", env)?;
+        override_value(&mut team.refresh_minutes, "PULSAR_TEAM_REFRESH_MINUTES", env)?;
+
+        let resources = &mut self.resources;
+        override_value(&mut resources.sample_secs, "PULSAR_RESOURCES_SAMPLE_SECS", env)?;
+        override_option(&mut resources.cpu_alert_percent, "PULSAR_RESOURCES_CPU_ALERT_PERCENT", env)?;
+        override_option(&mut resources.memory_alert_mb, "PULSAR_RESOURCES_MEMORY_ALERT_MB", env)?;
+
+        let ssh_ca = &mut self.ssh_ca;
+        override_option(&mut ssh_ca.url, "PULSAR_SSH_CA_URL", env)?;
+        override_option(&mut ssh_ca.token_env, "PULSAR_SSH_CA_TOKEN_ENV", env)?;
+        override_value(&mut ssh_ca.ttl_secs, "PULSAR_SSH_CA_TTL_SECS", env)?;
+
         Ok(())
     }
+
+    /// Check settings that parse but cannot work, reporting all of them
+    pub fn validate(&self) -> Result<()> {
+        let mut problems = Vec::new();
+
+        let ports = [
+            ("websocket_port", self.websocket_port),
+            ("grpc_port", self.grpc_port),
+            ("webtransport_port", self.webtransport_port),
+        ];
+        for (i, (name, port)) in ports.iter().enumerate() {
+            if *port == 0 {
+                problems.push(format!("{} must not be 0", name));
+            }
+            if let Some((other, _)) = ports[..i].iter().find(|(_, p)| p == port) {
+                problems.push(format!("{} and {} are both {}", other, name, port));
+            }
+        }
+        if let Some(addr) = self.health.probe_addr {
+            // WebTransport is on UDP, so only the TCP servers can collide
+            if let Some((other, _)) = ports[..2].iter().find(|(_, p)| *p == addr.port()) {
+                problems.push(format!("health.probe_addr and {} are both {}", other, addr.port()));
+            }
+        }
+        if let Some(addr) = self.relay.listen_addr {
+            if let Some((other, _)) = ports[..2].iter().find(|(_, p)| *p == addr.port()) {
+                problems.push(format!("relay.listen_addr and {} are both {}", other, addr.port()));
+            }
+            if self.health.probe_addr.map(|probe| probe.port()) == Some(addr.port()) {
+                problems.push(format!("relay.listen_addr and health.probe_addr are both {}", addr.port()));
+            }
+        }
+        if let Some(addr) = self.transfers.sftp.listen_addr {
+            if let Some((other, _)) = ports[..2].iter().find(|(_, p)| *p == addr.port()) {
+                problems.push(format!("transfers.sftp.listen_addr and {} are both {}", other, addr.port()));
+            }
+            for (other, other_addr) in [
+                ("health.probe_addr", self.health.probe_addr),
+                ("relay.listen_addr", self.relay.listen_addr),
+            ] {
+                if other_addr.map(|other_addr| other_addr.port()) == Some(addr.port()) {
+                    problems.push(format!("transfers.sftp.listen_addr and {} are both {}", other, addr.port()));
+                }
+            }
+            if self.transfers.sftp.users.is_empty() {
+                problems.push("transfers.sftp.users is empty, so nobody can log in".to_string());
+            }
+        }
+        for user in self.transfers.sftp.users.keys() {
+            if matches!(user.as_str(), "" | "." | "..") || user.contains(['/', '\\']) {
+                problems.push(format!("transfers.sftp.users: {:?} is not a valid user name", user));
+            }
+        }
+        if self.relay.max_sessions == 0 {
+            problems.push("relay.max_sessions must not be 0".to_string());
+        }
+        if self.relay.pairing_timeout_secs == 0 {
+            problems.push("relay.pairing_timeout_secs must not be 0".to_string());
+        }
+        if self.health.disk_unhealthy_mb > self.health.disk_degraded_mb {
+            problems.push("health.disk_unhealthy_mb exceeds health.disk_degraded_mb".to_string());
+        }
+
+        for level in std::iter::once(&self.log.level).chain(self.log.targets.values()) {
+            if level.parse::<tracing::Level>().is_err() && level != "off" {
+                problems.push(format!(
+                    "log level {:?} is not one of error, warn, info, debug, trace, off",
+                    level
+                ));
+            }
+        }
+
+        if let Err(e) = self.size_policy.validate() {
+            problems.push(format!("size_policy: {}", e));
+        }
+        if self.resources.sample_secs == 0 {
+            problems.push("resources.sample_secs must not be 0".to_string());
+        }
+        if self.resources.cpu_alert_percent.is_some_and(|percent| percent <= 0.0) {
+            problems.push("resources.cpu_alert_percent must be above 0".to_string());
+        }
+        if self.resources.memory_alert_mb == Some(0) {
+            problems.push("resources.memory_alert_mb must not be 0".to_string());
+        }
+
+        match (&self.tls.cert_path, &self.tls.key_path) {
+            (Some(cert), Some(key)) => {
+                for path in [cert, key] {
+                    if !path.is_file() {
+                        problems.push(format!("tls file {:?} does not exist", path));
+                    }
+                }
+            }
+            (None, None) => {}
+            _ => problems.push("tls.cert_path and tls.key_path must be set together".to_string()),
+        }
+
+        let transfers = &self.transfers;
+        if transfers.chunk_size == 0 {
+            problems.push("transfers.chunk_size must not be 0".to_string());
+        } else if transfers.chunk_size as u64 > transfers.max_file_size {
+            problems.push("transfers.chunk_size exceeds transfers.max_file_size".to_string());
+        }
+        if transfers.max_parallel_chunks == 0 {
+            problems.push("transfers.max_parallel_chunks must not be 0".to_string());
+        }
+        let policy = &transfers.policy;
+        if !policy.scanner_command.is_empty() && policy.scanner_timeout_secs == 0 {
+            problems.push("transfers.policy.scanner_timeout_secs must not be 0".to_string());
+        }
+        if policy
+            .blocked_extensions
+            .iter()
+            .any(|extension| extension.trim_start_matches('.').is_empty())
+        {
+            problems.push("transfers.policy.blocked_extensions has an empty entry".to_string());
+        }
+
+        if self.snapshots.enabled && self.snapshots.interval_minutes == 0 {
+            problems.push("snapshots.interval_minutes must not be 0".to_string());
+        }
+
+        problems.extend(self.webhooks.problems());
+        problems.extend(self.team.problems());
+        problems.extend(self.ssh_ca.problems());
+
+        if !problems.is_empty() {
+            bail!("Invalid configuration:\n  {}", problems.join("\n  "));
+        }
+        Ok(())
+    }
+}
+
+/// TOML has no null; a path switched off is written as an empty one
+fn empty_if_none<S: serde::Serializer>(path: &Option<PathBuf>, serializer: S) -> Result<S::Ok, S::Error> {
+    path.as_deref().unwrap_or(Path::new("")).serialize(serializer)
+}
+
+fn override_value<T>(field: &mut T, name: &str, env: &impl Fn(&str) -> Option<String>) -> Result<()>
+where
+    T: FromStr,
+    T::Err: Display,
+{
+    if let Some(value) = env(name) {
+        *field = value
+            .parse()
+            .map_err(|e| anyhow::anyhow!("{}={:?}: {}", name, value, e))?;
+    }
+    Ok(())
+}
+
+/// An empty value clears the setting
+fn override_option<T>(field: &mut Option<T>, name: &str, env: &impl Fn(&str) -> Option<String>) -> Result<()>
+where
+    T: FromStr,
+    T::Err: Display,
+{
+    if let Some(value) = env(name) {
+        *field = if value.is_empty() {
+            None
+        } else {
+            Some(
+                value
+                    .parse()
+                    .map_err(|e| anyhow::anyhow!("{}={:?}: {}", name, value, e))?,
+            )
+        };
+    }
+    Ok(())
+}
+
+/// An empty value clears the path
+fn override_path(field: &mut Option<PathBuf>, name: &str, env: &impl Fn(&str) -> Option<String>) {
+    if let Some(value) = env(name) {
+        *field = (!value.is_empty()).then(|| PathBuf::from(value));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    #[test]
+    fn test_partial_file_keeps_defaults() {
+        let config = DaemonConfig::parse(
+            r#"
+            websocket_port = 4000
+            orbit_socket = ""
+
+            [log]
+            level = "debug"
+            targets = { "pulsar_daemon::ipc" = "trace" }
+
+            [transfers]
+            max_file_size = 1073741824
+
+            [[webhooks.endpoints]]
+            url = "https://hooks.example.com/pulsar"
+            secret = "s3cret"
+            events = ["transfer.completed"]
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(config.websocket_port, 4000);
+        assert_eq!(config.grpc_port, 50051);
+        assert_eq!(config.orbit_socket, None);
+        assert_eq!(config.log.filter(), "debug,pulsar_daemon::ipc=trace");
+        assert_eq!(config.transfers.max_file_size, 1 << 30);
+        assert_eq!(config.transfers.chunk_size, 1024 * 1024);
+        assert!(config.snapshots.enabled);
+        assert_eq!(config.webhooks.endpoints[0].events, ["transfer.completed"]);
+        assert_eq!(config.webhooks.max_attempts, 5);
+        config.validate().unwrap();
+
+        // The effective config round-trips
+        let reparsed = DaemonConfig::parse(&config.to_toml().unwrap()).unwrap();
+        assert_eq!(reparsed.log.filter(), config.log.filter());
+        assert_eq!(reparsed.orbit_socket, None);
+        assert_eq!(reparsed.webhooks.endpoints[0].secret.as_deref(), Some("s3cret"));
+    }
+
+    #[test]
+    fn test_example_is_valid() {
+        let config = DaemonConfig::parse(include_str!("../pulsar.toml.example")).unwrap();
+        config.validate().unwrap();
+    }
+
+    #[test]
+    fn test_env_overrides() {
+        const SENDER: &str = "3d4017c3e843895a92b70aa74d1b7ebc9c982ccf2ec4968cc0cd55f12af4660c";
+        let vars: HashMap<&str, &str> = [
+            ("PULSAR_GRPC_PORT", "6000"),
+            ("PULSAR_LISTEN_ADDR", "0.0.0.0"),
+            ("PULSAR_LOG_LEVEL", "warn"),
+            ("PULSAR_ORBIT_SOCKET", ""),
+            ("PULSAR_SNAPSHOTS_ENABLED", "false"),
+            ("PULSAR_LOG_FILE_DIR", "/var/log/pulsar"),
+            ("PULSAR_LOG_FILE_MAX_FILES", "3"),
+            ("PULSAR_HEALTH_PROBE_ADDR", "0.0.0.0:9090"),
+            ("PULSAR_RELAY_LISTEN_ADDR", "0.0.0.0:4434"),
+            ("PULSAR_TRANSFERS_POLICY_BLOCKED_EXTENSIONS", "exe, scr,"),
+            ("PULSAR_TRANSFERS_POLICY_SCANNER_COMMAND", "clamdscan --no-summary {path}"),
+            ("PULSAR_TRANSFERS_TRUSTED_SENDERS", SENDER),
+            ("PULSAR_TRANSFERS_MAX_BYTES_PER_SEC", "500000"),
+            ("PULSAR_TRANSFERS_SFTP_LISTEN_ADDR", "0.0.0.0:2222"),
+        ]
+        .into_iter()
+        .collect();
+
+        let mut config = DaemonConfig::default();
+        config
+            .apply_env(|name| vars.get(name).map(|value| value.to_string()))
+            .unwrap();
+        assert_eq!(config.grpc_port, 6000);
+        assert_eq!(config.listen_addr, IpAddr::V4(Ipv4Addr::UNSPECIFIED));
+        assert_eq!(config.log.level, "warn");
+        assert_eq!(config.orbit_socket, None);
+        assert!(!config.snapshots.enabled);
+        let file = config.log.file.clone().unwrap();
+        assert_eq!(file.dir, PathBuf::from("/var/log/pulsar"));
+        assert_eq!(file.max_files, 3);
+        assert_eq!(file.max_size_mb, 50);
+        assert_eq!(config.health.probe_addr, Some("0.0.0.0:9090".parse().unwrap()));
+        assert_eq!(config.relay.listen_addr, Some("0.0.0.0:4434".parse().unwrap()));
+        assert_eq!(config.transfers.sftp.listen_addr, Some("0.0.0.0:2222".parse().unwrap()));
+        let policy = &config.transfers.policy;
+        assert_eq!(policy.blocked_extensions, ["exe", "scr"]);
+        assert_eq!(policy.scanner_command, ["clamdscan", "--no-summary", "{path}"]);
+        assert_eq!(config.transfers.trusted_senders, [SENDER.parse().unwrap()]);
+        let limit = config.transfers.bandwidth_limit().unwrap();
+        assert_eq!((limit.bytes_per_sec, limit.burst_bytes), (500_000, 500_000));
+
+        config
+            .apply_env(|name| (name == "PULSAR_LOG_FILE_DIR").then(String::new))
+            .unwrap();
+        assert_eq!(config.log.file, None);
+
+        let error = config
+            .apply_env(|name| (name == "PULSAR_WEBSOCKET_PORT").then(|| "http".to_string()))
+            .unwrap_err();
+        assert!(error.to_string().contains("PULSAR_WEBSOCKET_PORT"));
+    }
+
+    #[test]
+    fn test_validate_reports_every_problem() {
+        let config = DaemonConfig {
+            grpc_port: 3030,
+            log: LogConfig {
+                level: "loud".to_string(),
+                ..LogConfig::default()
+            },
+            tls: TlsConfig {
+                cert_path: Some(PathBuf::from("/nonexistent/cert.pem")),
+                key_path: None,
+            },
+            ..DaemonConfig::default()
+        };
+
+        let message = config.validate().unwrap_err().to_string();
+        assert!(message.contains("websocket_port and grpc_port are both 3030"));
+        assert!(message.contains("\"loud\""));
+        assert!(message.contains("must be set together"));
+    }
 }
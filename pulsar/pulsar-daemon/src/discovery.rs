@@ -0,0 +1,271 @@
+//! SSH host discovery
+//!
+//! Onboarding an existing fleet starts with finding it. A scan takes CIDR
+//! ranges and host names, connects to the SSH port of each address, reads
+//! the server's banner (`SSH-2.0-OpenSSH_9.6p1 Ubuntu-3ubuntu13`) and
+//! fetches its host key without logging in. Keys are compared with
+//! known_hosts so that a changed one stands out before anyone connects.
+//! The hosts found can then be saved in bulk with `import_hosts`.
+
+use anyhow::{bail, Context, Result};
+use futures::stream::{self, StreamExt};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::net::{IpAddr, Ipv4Addr};
+use std::time::Duration;
+use tft_transports::HostKeyVerification;
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, BufReader};
+use tokio::net::TcpStream;
+use tokio::time::timeout;
+use tracing::{debug, info};
+
+/// Most addresses one scan may cover, a /20
+pub const MAX_TARGETS: usize = 4096;
+
+/// Addresses probed at once
+const CONCURRENCY: usize = 64;
+
+/// Lines a server may send before its identification string (RFC 4253
+/// allows other lines first)
+const MAX_PRELUDE_LINES: usize = 16;
+
+/// Longest identification string allowed, CR LF included
+const MAX_BANNER_LEN: usize = 255;
+
+/// How a scanned host key compares with known_hosts
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum KeyStatus {
+    Known,
+    Unknown,
+    /// known_hosts has another key for the host
+    Changed,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct HostKey {
+    pub algorithm: String,
+    /// SHA256 fingerprint
+    pub fingerprint: String,
+    /// `<algorithm> <base64>`, as written in known_hosts
+    pub openssh: String,
+    pub status: KeyStatus,
+}
+
+/// An SSH server found by a scan
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DiscoveredHost {
+    pub host: String,
+    pub port: u16,
+    /// The identification string, e.g. `SSH-2.0-OpenSSH_9.6p1`
+    pub banner: String,
+    /// Server software and comment from the banner
+    pub software: String,
+    /// None when the key exchange failed; see `error`
+    pub host_key: Option<HostKey>,
+    #[serde(default)]
+    pub error: Option<String>,
+}
+
+/// Response for discover_hosts
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct DiscoveryReport {
+    /// Addresses probed
+    pub scanned: usize,
+    /// Addresses that answered with an SSH banner, in target order
+    pub found: Vec<DiscoveredHost>,
+}
+
+/// Scan `targets` (addresses, host names and IPv4 CIDR ranges) for SSH
+/// servers on `port`, giving each connection `connect_timeout`
+pub async fn discover(targets: &[String], port: u16, connect_timeout: Duration) -> Result<DiscoveryReport> {
+    let hosts = expand_targets(targets)?;
+    info!("Scanning {} addresses for SSH on port {}", hosts.len(), port);
+
+    let mut found: Vec<(usize, DiscoveredHost)> = stream::iter(hosts.iter().cloned().enumerate())
+        .map(|(i, host)| async move { probe(host, port, connect_timeout).await.map(|found| (i, found)) })
+        .buffer_unordered(CONCURRENCY)
+        .filter_map(|found| async move { found })
+        .collect()
+        .await;
+    found.sort_by_key(|(i, _)| *i);
+
+    info!("Found {} SSH servers among {} addresses", found.len(), hosts.len());
+    Ok(DiscoveryReport {
+        scanned: hosts.len(),
+        found: found.into_iter().map(|(_, host)| host).collect(),
+    })
+}
+
+/// The addresses and names to probe, in order and without repeats
+pub fn expand_targets(targets: &[String]) -> Result<Vec<String>> {
+    let mut seen = HashSet::new();
+    let mut hosts = Vec::new();
+    for target in targets.iter().map(|t| t.trim()).filter(|t| !t.is_empty()) {
+        let expanded = match target.split_once('/') {
+            Some((network, prefix)) => expand_cidr(network, prefix)
+                .with_context(|| format!("Invalid range {:?}", target))?,
+            None => vec![target.to_string()],
+        };
+        for host in expanded {
+            if seen.insert(host.clone()) {
+                hosts.push(host);
+            }
+        }
+        if hosts.len() > MAX_TARGETS {
+            bail!("Scans are limited to {} addresses", MAX_TARGETS);
+        }
+    }
+    if hosts.is_empty() {
+        bail!("Nothing to scan");
+    }
+    Ok(hosts)
+}
+
+/// Usable addresses of an IPv4 network; the network and broadcast
+/// addresses are left out except in /31 and /32
+fn expand_cidr(network: &str, prefix: &str) -> Result<Vec<String>> {
+    let network: IpAddr = network.parse()?;
+    let IpAddr::V4(network) = network else {
+        bail!("only IPv4 ranges can be scanned");
+    };
+    let prefix: u32 = prefix.parse()?;
+    if prefix > 32 {
+        bail!("prefix length must be at most 32");
+    }
+    let size = 1u64 << (32 - prefix);
+    if size > MAX_TARGETS as u64 {
+        bail!("ranges are limited to {} addresses", MAX_TARGETS);
+    }
+
+    let mask = u32::MAX.checked_shl(32 - prefix).unwrap_or(0);
+    let first = u32::from(network) & mask;
+    let (start, end) = match size {
+        1 | 2 => (first as u64, first as u64 + size),
+        _ => (first as u64 + 1, first as u64 + size - 1),
+    };
+    Ok((start..end).map(|ip| Ipv4Addr::from(ip as u32).to_string()).collect())
+}
+
+/// Banner and host key of one address; None when nothing answers with SSH
+async fn probe(host: String, port: u16, connect_timeout: Duration) -> Option<DiscoveredHost> {
+    let banner = match timeout(connect_timeout, read_banner(&host, port)).await {
+        Ok(Ok(banner)) => banner,
+        Ok(Err(e)) => {
+            debug!("No SSH on {}:{}: {:#}", host, port, e);
+            return None;
+        }
+        Err(_) => return None,
+    };
+    let software = software(&banner).unwrap_or_default().to_string();
+
+    let (host_key, error) = match timeout(connect_timeout, tft_transports::scan_host_key(&host, port)).await {
+        Ok(Ok(key)) => {
+            let status = match key.verification {
+                HostKeyVerification::Trusted => KeyStatus::Known,
+                HostKeyVerification::Unknown => KeyStatus::Unknown,
+                HostKeyVerification::Changed { .. } => KeyStatus::Changed,
+            };
+            let key = HostKey {
+                algorithm: key.algorithm,
+                fingerprint: key.fingerprint,
+                openssh: key.openssh,
+                status,
+            };
+            (Some(key), None)
+        }
+        Ok(Err(e)) => (None, Some(format!("Couldn't fetch the host key: {}", e))),
+        Err(_) => (None, Some("Timed out fetching the host key".to_string())),
+    };
+
+    Some(DiscoveredHost {
+        host,
+        port,
+        banner,
+        software,
+        host_key,
+        error,
+    })
+}
+
+/// The server's identification string
+async fn read_banner(host: &str, port: u16) -> Result<String> {
+    let stream = TcpStream::connect((host, port)).await?;
+    let mut reader = BufReader::new(stream.take(MAX_BANNER_LEN as u64 * MAX_PRELUDE_LINES as u64));
+    let mut line = String::new();
+    for _ in 0..MAX_PRELUDE_LINES {
+        line.clear();
+        if reader.read_line(&mut line).await? == 0 {
+            break;
+        }
+        let banner = line.trim_end();
+        if banner.starts_with("SSH-") {
+            if banner.len() > MAX_BANNER_LEN {
+                bail!("identification string is too long");
+            }
+            return Ok(banner.to_string());
+        }
+    }
+    bail!("no SSH identification string")
+}
+
+/// `OpenSSH_9.6p1 Ubuntu-3ubuntu13` from
+/// `SSH-2.0-OpenSSH_9.6p1 Ubuntu-3ubuntu13`
+fn software(banner: &str) -> Option<&str> {
+    let rest = banner.strip_prefix("SSH-")?;
+    let (_version, software) = rest.split_once('-')?;
+    Some(software)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::AsyncWriteExt;
+    use tokio::net::TcpListener;
+
+    fn targets(targets: &[&str]) -> Vec<String> {
+        targets.iter().map(|t| t.to_string()).collect()
+    }
+
+    #[test]
+    fn test_expand_targets() {
+        let hosts = expand_targets(&targets(&["10.0.0.0/30", "db-1", "10.0.0.1", " "])).unwrap();
+        assert_eq!(hosts, ["10.0.0.1", "10.0.0.2", "db-1"]);
+
+        // Host bits in the network address are ignored
+        assert_eq!(expand_targets(&targets(&["192.168.1.77/24"])).unwrap().len(), 254);
+        assert_eq!(expand_targets(&targets(&["10.0.0.9/32"])).unwrap(), ["10.0.0.9"]);
+        assert_eq!(expand_targets(&targets(&["10.0.0.8/31"])).unwrap(), ["10.0.0.8", "10.0.0.9"]);
+
+        assert!(expand_targets(&targets(&["10.0.0.0/8"])).is_err());
+        assert!(expand_targets(&targets(&["fd00::/120"])).is_err());
+        assert!(expand_targets(&targets(&["10.0.0.0/33"])).is_err());
+        assert!(expand_targets(&[]).is_err());
+    }
+
+    #[test]
+    fn test_software() {
+        assert_eq!(
+            software("SSH-2.0-OpenSSH_9.6p1 Ubuntu-3ubuntu13"),
+            Some("OpenSSH_9.6p1 Ubuntu-3ubuntu13")
+        );
+        assert_eq!(software("SSH-1.99-Cisco-1.25"), Some("Cisco-1.25"));
+        assert_eq!(software("HTTP/1.1 400 Bad Request"), None);
+    }
+
+    #[tokio::test]
+    async fn test_read_banner() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            stream
+                .write_all(b"Authorized use only\r\nSSH-2.0-dropbear_2022.83\r\n")
+                .await
+                .unwrap();
+        });
+
+        let banner = read_banner("127.0.0.1", port).await.unwrap();
+        assert_eq!(banner, "SSH-2.0-dropbear_2022.83");
+    }
+}
@@ -2,13 +2,21 @@
 //
 // Handles incoming file transfer requests over WebTransport
 
+use super::manifest::{self, CompletedFile, ManifestSigner};
 use super::messages::*;
 use super::storage::{TransferState, TransferStatus, TransferStorage};
-use super::validation::{hash_data, verify_hash, HashValidator};
+use super::validation::{hash_data, verify_hash};
 use super::{Result, TransferConfig, TransferError};
-use std::collections::HashMap;
+use crate::attach_token::allows_tokenless;
+use std::collections::{HashMap, HashSet};
+use std::net::IpAddr;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
 use std::time::SystemTime;
+use pulsar_webhook::Webhooks;
+use tft_core::{tree, Challenge, DirectoryManifest, Identity, PeerId};
+use tft_transports::{SftpEvent, Throttle};
 use tokio::sync::RwLock;
 use tracing::{debug, error, info, warn};
 
@@ -16,33 +24,98 @@ use tracing::{debug, error, info, warn};
 #[derive(Debug, Clone)]
 pub struct TransferSession {
     pub state: TransferState,
-    pub validator: Arc<RwLock<HashValidator>>,
     pub started_at: SystemTime,
     pub last_activity: SystemTime,
 }
 
+/// A folder being received
+#[derive(Debug)]
+struct DirectoryTransfer {
+    /// The folder, created when the transfer started
+    root: PathBuf,
+    manifest: DirectoryManifest,
+    /// Files not delivered yet, by manifest path
+    pending: HashSet<String>,
+    sender: Option<PeerId>,
+}
+
 /// File transfer handler
 pub struct FileTransferHandler {
     config: TransferConfig,
     storage: Arc<TransferStorage>,
     active_transfers: Arc<RwLock<HashMap<String, Arc<RwLock<TransferSession>>>>>,
+    /// Set on shutdown; new transfers are refused
+    draining: AtomicBool,
+    /// Files delivered so far, by batch ID, awaiting the batch's manifest
+    batches: RwLock<HashMap<String, Vec<CompletedFile>>>,
+    /// Folders being received, by directory ID
+    directories: RwLock<HashMap<String, DirectoryTransfer>>,
+    /// Signs batch manifest attestations
+    manifest_signer: Option<Arc<ManifestSigner>>,
+    /// Proves the daemon's identity to senders that ask
+    identity: Option<Arc<Identity>>,
+    /// Told about every delivered file
+    webhooks: Option<Arc<Webhooks>>,
+    /// Paces incoming chunks to the bandwidth limit
+    throttle: Throttle,
 }
 
 impl FileTransferHandler {
     /// Create a new file transfer handler
     pub fn new(config: TransferConfig) -> Self {
         let storage = Arc::new(TransferStorage::new(config.storage_path.clone()));
+        let throttle = Throttle::new(config.bandwidth_limit());
 
         Self {
             config,
             storage,
             active_transfers: Arc::new(RwLock::new(HashMap::new())),
+            draining: AtomicBool::new(false),
+            batches: RwLock::new(HashMap::new()),
+            directories: RwLock::new(HashMap::new()),
+            manifest_signer: None,
+            identity: None,
+            webhooks: None,
+            throttle,
         }
     }
 
+    /// Sign batch manifest attestations with `signer`
+    pub fn with_manifest_signer(mut self, signer: Arc<ManifestSigner>) -> Self {
+        self.manifest_signer = Some(signer);
+        self
+    }
+
+    /// Answer senders' identity challenges with `identity`
+    pub fn with_identity(mut self, identity: Arc<Identity>) -> Self {
+        self.identity = Some(identity);
+        self
+    }
+
+    /// Send a `transfer.completed` webhook for every delivered file
+    pub fn with_webhooks(mut self, webhooks: Arc<Webhooks>) -> Self {
+        self.webhooks = Some(webhooks);
+        self
+    }
+
+    /// Bandwidth limit shared by all transfers, adjustable while they run
+    pub fn throttle(&self) -> &Throttle {
+        &self.throttle
+    }
+
     /// Initialize the handler
     pub async fn initialize(&self) -> Result<()> {
         self.storage.initialize().await?;
+
+        // Nothing is transferring yet, so any partial file is an orphan
+        let cleanup = self.storage.cleanup_partials().await?;
+        if cleanup.files > 0 {
+            info!(
+                "Removed {} orphaned partial files, reclaiming {} bytes",
+                cleanup.files, cleanup.bytes
+            );
+        }
+
         info!("File transfer handler initialized at {:?}", self.config.storage_path);
         Ok(())
     }
@@ -51,9 +124,64 @@ impl FileTransferHandler {
     pub async fn handle_transfer_start(
         &self,
         msg: TransferStartMessage,
+    ) -> Result<TransferAckMessage> {
+        self.handle_transfer_start_into(msg, None).await
+    }
+
+    /// Handle transfer start message, saving the finished file into
+    /// `destination_dir` instead of the transfer storage
+    pub async fn handle_transfer_start_into(
+        &self,
+        msg: TransferStartMessage,
+        destination_dir: Option<PathBuf>,
+    ) -> Result<TransferAckMessage> {
+        self.handle_transfer_start_from(msg, destination_dir, None).await
+    }
+
+    /// The challenge for the sender a start message names, once the sender
+    /// is known to be allowed; None when it names none and any sender is
+    /// accepted
+    pub fn challenge_sender(&self, sender: Option<PeerId>) -> Result<Option<(PeerId, Challenge)>> {
+        self.authorize_sender(sender)?;
+        let Some(sender) = sender else {
+            return Ok(None);
+        };
+        let challenge = Challenge::random().map_err(|e| TransferError::Identity(e.to_string()))?;
+        Ok(Some((sender, challenge)))
+    }
+
+    /// Whether transfer streams are taken from `peer`. Other hosts hold no
+    /// attach token to prove themselves with, so they may only send once
+    /// `trusted_senders` makes each of them sign for its identity.
+    pub fn accepts_peer(&self, peer: IpAddr) -> bool {
+        allows_tokenless(peer, None) || !self.config.trusted_senders.is_empty()
+    }
+
+    /// Handle transfer start message from `sender`, which must already be
+    /// verified (see [`super::identity::verify_sender`])
+    pub async fn handle_transfer_start_from(
+        &self,
+        msg: TransferStartMessage,
+        destination_dir: Option<PathBuf>,
+        sender: Option<PeerId>,
     ) -> Result<TransferAckMessage> {
         info!("Starting transfer: {} ({})", msg.transfer_id, msg.file_name);
 
+        if self.draining.load(Ordering::Relaxed) {
+            return Err(TransferError::ShuttingDown);
+        }
+
+        self.authorize_sender(sender)?;
+        if let Some(sender) = sender {
+            info!("Transfer {} is from verified sender {}", msg.transfer_id, sender);
+        }
+
+        // Files of a folder go to their place in it
+        let destination_dir = match &msg.directory {
+            Some(file) => Some(self.directory_destination(file, &msg.file_name, sender).await?),
+            None => destination_dir,
+        };
+
         // Validate file size
         if msg.file_size > self.config.max_file_size {
             return Err(TransferError::PermissionDenied(format!(
@@ -62,6 +190,15 @@ impl FileTransferHandler {
             )));
         }
 
+        // Make sure the file fits before any data arrives
+        self.storage.check_space(msg.file_size, destination_dir.as_deref())?;
+
+        // Refuse blocked file types before any data arrives
+        if let Some(reason) = self.config.policy.check_name(&msg.file_name) {
+            warn!("Refusing transfer {}: {}", msg.transfer_id, reason);
+            return Err(TransferError::PolicyViolation(reason));
+        }
+
         // Validate chunk size
         if msg.chunk_size > self.config.chunk_size * 4 {
             return Err(TransferError::InvalidChunkSize {
@@ -86,6 +223,10 @@ impl FileTransferHandler {
             started_at: chrono::Utc::now().to_rfc3339(),
             last_activity: chrono::Utc::now().to_rfc3339(),
             status: TransferStatus::InProgress,
+            destination_dir,
+            batch_id: msg.batch_id.clone(),
+            sender,
+            directory: msg.directory.clone(),
         };
 
         // Save metadata
@@ -94,7 +235,6 @@ impl FileTransferHandler {
         // Create transfer session
         let session = Arc::new(RwLock::new(TransferSession {
             state,
-            validator: Arc::new(RwLock::new(HashValidator::new())),
             started_at: SystemTime::now(),
             last_activity: SystemTime::now(),
         }));
@@ -111,9 +251,61 @@ impl FileTransferHandler {
             accepted: true,
             resume_supported: true,
             max_chunk_size: self.config.chunk_size,
+            receiver: msg
+                .challenge
+                .zip(self.identity.as_ref())
+                .map(|(challenge, identity)| identity.prove(&challenge)),
         })
     }
 
+    /// Refuse senders outside `trusted_senders`, when it is set
+    fn authorize_sender(&self, sender: Option<PeerId>) -> Result<()> {
+        let trusted = &self.config.trusted_senders;
+        match sender {
+            _ if trusted.is_empty() => Ok(()),
+            Some(sender) if trusted.contains(&sender) => Ok(()),
+            Some(sender) => Err(TransferError::PermissionDenied(format!(
+                "Sender {} is not trusted",
+                sender
+            ))),
+            None => Err(TransferError::PermissionDenied(
+                "Only trusted senders may send files; name a sender identity".to_string(),
+            )),
+        }
+    }
+
+    /// Where a file of a folder being received goes: its directory in the
+    /// folder, if the manifest lists it and it hasn't arrived yet
+    async fn directory_destination(
+        &self,
+        file: &DirectoryFile,
+        file_name: &str,
+        sender: Option<PeerId>,
+    ) -> Result<PathBuf> {
+        let directories = self.directories.read().await;
+        let directory = directories
+            .get(&file.directory_id)
+            .ok_or_else(|| TransferError::TransferNotFound(file.directory_id.clone()))?;
+
+        let name = file.path.rsplit('/').next().unwrap_or_default();
+        if !directory.pending.contains(&file.path) || name != file_name {
+            return Err(TransferError::PermissionDenied(format!(
+                "{} is not a file still to come in directory {}",
+                file.path, file.directory_id
+            )));
+        }
+        if sender != directory.sender {
+            return Err(TransferError::PermissionDenied(format!(
+                "Directory {} was started by another sender",
+                file.directory_id
+            )));
+        }
+
+        // The manifest was validated, so the path stays inside the folder
+        let path = tree::local_path(&directory.root, &file.path);
+        Ok(path.parent().unwrap_or(&directory.root).to_path_buf())
+    }
+
     /// Handle chunk data message
     pub async fn handle_chunk_data(&self, msg: ChunkDataMessage, data: Vec<u8>) -> Result<ChunkAckMessage> {
         debug!(
@@ -159,9 +351,6 @@ impl FileTransferHandler {
         session_guard.state.last_activity = chrono::Utc::now().to_rfc3339();
         session_guard.last_activity = SystemTime::now();
 
-        // Update validator with chunk data
-        session_guard.validator.write().await.update(&data);
-
         // Save updated metadata
         self.storage.save_metadata(&session_guard.state).await?;
 
@@ -209,8 +398,16 @@ impl FileTransferHandler {
             )
             .await?;
 
-        // Compute final hash from validator
-        let computed_hash = session_guard.validator.read().await.finalize_hex();
+        // Hash the assembled file, reading and hashing in parallel; chunks
+        // may have arrived out of order or across a resume
+        let computed_hash = {
+            let path = final_path.clone();
+            let chunk_size = session_guard.state.chunk_size.max(1);
+            tokio::task::spawn_blocking(move || tft_core::hash_file(&path, chunk_size))
+                .await
+                .map_err(std::io::Error::other)??
+                .file_hash
+        };
 
         // Verify final hash
         if !computed_hash.eq_ignore_ascii_case(&msg.final_hash) {
@@ -220,6 +417,22 @@ impl FileTransferHandler {
             });
         }
 
+        // Size and scanner checks before the file is moved into place
+        if let Some(reason) = self
+            .config
+            .policy
+            .check_file(&final_path, self.config.max_file_size)
+            .await
+        {
+            drop(session_guard);
+            return Err(self.quarantine(&msg.transfer_id, &session, &final_path, reason).await?);
+        }
+
+        let final_path = match &session_guard.state.destination_dir {
+            Some(directory) => self.storage.deliver(&final_path, directory, &msg.transfer_id).await?,
+            None => final_path,
+        };
+
         drop(session_guard);
 
         // Update transfer state to complete
@@ -230,10 +443,42 @@ impl FileTransferHandler {
         // Remove from active transfers
         self.active_transfers.write().await.remove(&msg.transfer_id);
 
+        if let Some(batch_id) = &session_guard.state.batch_id {
+            self.batches
+                .write()
+                .await
+                .entry(batch_id.clone())
+                .or_default()
+                .push(CompletedFile {
+                    path: final_path.clone(),
+                    blake3: computed_hash.clone(),
+                });
+        }
+        if let Some(file) = &session_guard.state.directory {
+            if let Some(directory) = self.directories.write().await.get_mut(&file.directory_id) {
+                directory.pending.remove(&file.path);
+            }
+        }
+
         info!(
             "Transfer complete: {} -> {:?}",
             msg.transfer_id, final_path
         );
+        if let Some(webhooks) = &self.webhooks {
+            let state = &session_guard.state;
+            webhooks.notify(
+                pulsar_webhook::TRANSFER_COMPLETED,
+                serde_json::json!({
+                    "transfer_id": msg.transfer_id,
+                    "file_name": state.file_name,
+                    "size": msg.total_bytes,
+                    "path": final_path,
+                    "blake3": computed_hash,
+                    "batch_id": state.batch_id,
+                    "sender": state.sender,
+                }),
+            );
+        }
 
         Ok(TransferSuccessMessage {
             transfer_id: msg.transfer_id,
@@ -246,6 +491,282 @@ impl FileTransferHandler {
         })
     }
 
+    /// Move a received file that failed the policy checks to quarantine and
+    /// end its transfer; returns the error to report to the sender
+    async fn quarantine(
+        &self,
+        transfer_id: &str,
+        session: &RwLock<TransferSession>,
+        assembled: &Path,
+        reason: String,
+    ) -> Result<TransferError> {
+        let path = self
+            .storage
+            .quarantine(assembled, &self.config.quarantine_dir(), transfer_id, &reason)
+            .await?;
+        warn!("Quarantined transfer {} at {:?}: {}", transfer_id, path, reason);
+
+        let mut session = session.write().await;
+        session.state.status = TransferStatus::Quarantined;
+        self.storage.save_metadata(&session.state).await?;
+        self.active_transfers.write().await.remove(transfer_id);
+
+        Ok(TransferError::Quarantined { reason, path })
+    }
+
+    /// Handle an event from the SFTP inbox. Uploads get the policy checks
+    /// of any received file: those failing them are quarantined (reported
+    /// as [`TransferError::Quarantined`]), the rest are announced with a
+    /// `transfer.completed` webhook where they are.
+    pub async fn handle_sftp_event(&self, event: SftpEvent) -> Result<()> {
+        let (user, path, size) = match event {
+            SftpEvent::LoggedIn { user, peer } => {
+                info!("SFTP login by {} from {:?}", user, peer);
+                return Ok(());
+            }
+            SftpEvent::LoginRejected { user, peer, method } => {
+                warn!("Rejected SFTP login by {} from {:?} ({})", user, peer, method);
+                return Ok(());
+            }
+            SftpEvent::FileReceived { user, path, size } => (user, path, size),
+        };
+
+        let transfer_id = format!("sftp-{}", uuid::Uuid::new_v4());
+        let file_name = path
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        let reason = match self.config.policy.check_name(&file_name) {
+            Some(reason) => Some(reason),
+            None => self.config.policy.check_file(&path, self.config.max_file_size).await,
+        };
+        if let Some(reason) = reason {
+            let quarantined = self
+                .storage
+                .quarantine(&path, &self.config.quarantine_dir(), &transfer_id, &reason)
+                .await?;
+            warn!("Quarantined SFTP upload from {} at {:?}: {}", user, quarantined, reason);
+            return Err(TransferError::Quarantined { reason, path: quarantined });
+        }
+
+        let blake3 = {
+            let path = path.clone();
+            let chunk_size = self.config.chunk_size.max(1);
+            tokio::task::spawn_blocking(move || tft_core::hash_file(&path, chunk_size))
+                .await
+                .map_err(std::io::Error::other)??
+                .file_hash
+        };
+
+        info!("SFTP upload from {} -> {:?}", user, path);
+        if let Some(webhooks) = &self.webhooks {
+            webhooks.notify(
+                pulsar_webhook::TRANSFER_COMPLETED,
+                serde_json::json!({
+                    "transfer_id": transfer_id,
+                    "file_name": file_name,
+                    "size": size,
+                    "path": path,
+                    "blake3": blake3,
+                    "user": user,
+                    "via": "sftp",
+                }),
+            );
+        }
+        Ok(())
+    }
+
+    /// Handle batch complete message: write the checksum manifest (and
+    /// attestation, if asked for) beside the batch's files
+    pub async fn handle_batch_complete(
+        &self,
+        msg: BatchCompleteMessage,
+    ) -> Result<BatchManifestMessage> {
+        info!("Completing batch: {}", msg.batch_id);
+
+        if msg.attest && self.manifest_signer.is_none() {
+            return Err(TransferError::Manifest(
+                "This daemon has no signing key for attestations".to_string(),
+            ));
+        }
+
+        for session in self.active_transfers.read().await.values() {
+            let session = session.read().await;
+            if session.state.batch_id.as_deref() == Some(msg.batch_id.as_str()) {
+                return Err(TransferError::Manifest(format!(
+                    "Transfer {} of batch {} has not completed",
+                    session.state.transfer_id, msg.batch_id
+                )));
+            }
+        }
+
+        let files = self
+            .batches
+            .read()
+            .await
+            .get(&msg.batch_id)
+            .cloned()
+            .ok_or_else(|| TransferError::TransferNotFound(msg.batch_id.clone()))?;
+        let count = files.len() as u32;
+
+        let signer = if msg.attest {
+            self.manifest_signer.as_deref()
+        } else {
+            None
+        };
+        let written = manifest::write_manifest(&msg.batch_id, files, msg.algorithm, signer).await?;
+        self.batches.write().await.remove(&msg.batch_id);
+
+        Ok(BatchManifestMessage {
+            batch_id: msg.batch_id,
+            timestamp: current_timestamp(),
+            manifest_path: written.manifest_path.to_string_lossy().to_string(),
+            attestation_path: written
+                .attestation_path
+                .map(|path| path.to_string_lossy().to_string()),
+            files: count,
+        })
+    }
+
+    /// Handle directory start message: create the folder named in it, with
+    /// the manifest's directories and symlinks, under `destination_dir` or
+    /// the transfer storage
+    ///
+    /// `manifest` must have been checked with
+    /// [`tft_core::codec::validate_manifest`], and `sender` verified.
+    pub async fn handle_directory_start(
+        &self,
+        msg: DirectoryStartMessage,
+        manifest: DirectoryManifest,
+        destination_dir: Option<PathBuf>,
+        sender: Option<PeerId>,
+    ) -> Result<DirectoryAckMessage> {
+        info!(
+            "Starting directory transfer: {} ({}, {} entries)",
+            msg.directory_id,
+            msg.name,
+            manifest.entries.len()
+        );
+
+        if self.draining.load(Ordering::Relaxed) {
+            return Err(TransferError::ShuttingDown);
+        }
+        self.authorize_sender(sender)?;
+
+        let name = msg.name.as_str();
+        if name.is_empty() || name == "." || name == ".." || name.contains(['/', '\\', '\0']) {
+            return Err(TransferError::PermissionDenied(format!(
+                "{:?} is not a folder name",
+                name
+            )));
+        }
+
+        // Every file must pass before anything is created
+        for file in manifest.files() {
+            if let Some(reason) = self.config.policy.check_name(file.name()) {
+                warn!("Refusing directory {}: {}: {}", msg.directory_id, file.path, reason);
+                return Err(TransferError::PolicyViolation(format!("{}: {}", file.path, reason)));
+            }
+        }
+        let total_size = manifest.total_size();
+        if total_size > self.config.max_file_size {
+            return Err(TransferError::PermissionDenied(format!(
+                "Directory size {} exceeds maximum {}",
+                total_size, self.config.max_file_size
+            )));
+        }
+        self.storage.check_space(total_size, destination_dir.as_deref())?;
+
+        if self.directories.read().await.contains_key(&msg.directory_id) {
+            return Err(TransferError::PermissionDenied(format!(
+                "Directory {} is already being received",
+                msg.directory_id
+            )));
+        }
+        let parent = match destination_dir {
+            Some(directory) => directory,
+            None => self.storage.final_path(&msg.directory_id),
+        };
+        let root = parent.join(name);
+        if tokio::fs::try_exists(&root).await? {
+            return Err(TransferError::DestinationExists(root));
+        }
+
+        let manifest = {
+            let root = root.clone();
+            tokio::task::spawn_blocking(move || {
+                tree::create_entries(&root, &manifest).map(|()| manifest)
+            })
+            .await
+            .map_err(std::io::Error::other)??
+        };
+
+        let pending = manifest.files().map(|file| file.path.clone()).collect();
+        self.directories.write().await.insert(
+            msg.directory_id.clone(),
+            DirectoryTransfer {
+                root: root.clone(),
+                manifest,
+                pending,
+                sender,
+            },
+        );
+
+        Ok(DirectoryAckMessage {
+            directory_id: msg.directory_id,
+            timestamp: current_timestamp(),
+            accepted: true,
+            saved_path: root.to_string_lossy().to_string(),
+            receiver: msg
+                .challenge
+                .zip(self.identity.as_ref())
+                .map(|(challenge, identity)| identity.prove(&challenge)),
+        })
+    }
+
+    /// Handle directory complete message: once every file has arrived,
+    /// apply the modes and mtimes the manifest carries
+    pub async fn handle_directory_complete(
+        &self,
+        msg: DirectoryCompleteMessage,
+    ) -> Result<DirectorySuccessMessage> {
+        info!("Completing directory transfer: {}", msg.directory_id);
+
+        let mut directories = self.directories.write().await;
+        let directory = directories
+            .get(&msg.directory_id)
+            .ok_or_else(|| TransferError::TransferNotFound(msg.directory_id.clone()))?;
+        if !directory.pending.is_empty() {
+            return Err(TransferError::Manifest(format!(
+                "{} files of directory {} have not arrived",
+                directory.pending.len(),
+                msg.directory_id
+            )));
+        }
+        let Some(directory) = directories.remove(&msg.directory_id) else {
+            return Err(TransferError::TransferNotFound(msg.directory_id));
+        };
+        drop(directories);
+
+        let files = directory.manifest.files().count() as u32;
+        let total_bytes = directory.manifest.total_size();
+        let root = directory.root.clone();
+        tokio::task::spawn_blocking(move || {
+            tree::apply_metadata(&directory.root, &directory.manifest)
+        })
+        .await
+        .map_err(std::io::Error::other)??;
+
+        info!("Directory transfer complete: {} -> {:?}", msg.directory_id, root);
+        Ok(DirectorySuccessMessage {
+            directory_id: msg.directory_id,
+            timestamp: current_timestamp(),
+            saved_path: root.to_string_lossy().to_string(),
+            files,
+            total_bytes,
+        })
+    }
+
     /// Handle resume request message
     pub async fn handle_resume_request(
         &self,
@@ -327,6 +848,30 @@ impl FileTransferHandler {
         Ok(cleaned)
     }
 
+    /// Refuse new transfers; transfers already started may continue
+    pub fn stop_accepting(&self) {
+        self.draining.store(true, Ordering::Relaxed);
+    }
+
+    /// Save the state of every in-flight transfer as incomplete and drop
+    /// it, so clients can resume it with a resume request after a restart
+    pub async fn checkpoint_transfers(&self) -> Result<usize> {
+        let transfers: Vec<_> = self.active_transfers.write().await.drain().collect();
+        for (transfer_id, session) in &transfers {
+            let mut session = session.write().await;
+            session.state.status = TransferStatus::Incomplete;
+            session.state.last_activity = chrono::Utc::now().to_rfc3339();
+            self.storage.save_metadata(&session.state).await?;
+            info!(
+                "Checkpointed transfer {} at {}/{} chunks",
+                transfer_id,
+                session.state.received_chunks.len(),
+                session.state.total_chunks
+            );
+        }
+        Ok(transfers.len())
+    }
+
     /// Get active transfer count
     pub async fn active_transfer_count(&self) -> usize {
         self.active_transfers.read().await.len()
@@ -368,6 +913,11 @@ mod tests {
             mime_type: Some("text/plain".to_string()),
             blake3_hash: "abc123".to_string(),
             metadata: None,
+            session_id: None,
+            batch_id: None,
+            sender: None,
+            challenge: None,
+            directory: None,
         };
 
         let ack = handler.handle_transfer_start(msg).await.unwrap();
@@ -376,6 +926,76 @@ mod tests {
         assert_eq!(handler.active_transfer_count().await, 1);
     }
 
+    #[tokio::test]
+    async fn test_trusted_senders() {
+        use crate::file_transfer::identity::verify_sender;
+
+        let key = || Identity::from_pkcs8(&Identity::generate_pkcs8().unwrap()).unwrap();
+        let (alice, mallory, daemon) = (key(), key(), Arc::new(key()));
+        let config = TransferConfig {
+            trusted_senders: vec![alice.peer_id()],
+            ..test_config()
+        };
+        let handler = FileTransferHandler::new(config).with_identity(daemon.clone());
+        handler.initialize().await.unwrap();
+
+        let start = |sender: Option<PeerId>, challenge: Option<Challenge>| TransferStartMessage {
+            transfer_id: "from-alice".to_string(),
+            timestamp: current_timestamp(),
+            file_name: "notes.txt".to_string(),
+            file_size: 5,
+            chunk_size: 5,
+            total_chunks: 1,
+            mime_type: None,
+            blake3_hash: "abc123".to_string(),
+            metadata: None,
+            session_id: None,
+            batch_id: None,
+            sender,
+            challenge,
+            directory: None,
+        };
+
+        // Anonymous and untrusted senders are turned away before any data
+        let anonymous = handler.challenge_sender(None);
+        assert!(matches!(anonymous, Err(TransferError::PermissionDenied(_))));
+        let untrusted = handler.challenge_sender(Some(mallory.peer_id()));
+        assert!(matches!(untrusted, Err(TransferError::PermissionDenied(_))));
+        assert!(handler.handle_transfer_start(start(None, None)).await.is_err());
+
+        // Claiming a trusted identity takes its key
+        let (claimed, challenge) = handler
+            .challenge_sender(Some(alice.peer_id()))
+            .unwrap()
+            .unwrap();
+        assert!(verify_sender(claimed, &challenge, &mallory.prove(&challenge)).is_err());
+        let sender = verify_sender(claimed, &challenge, &alice.prove(&challenge)).unwrap();
+
+        let ours = Challenge::random().unwrap();
+        let ack = handler
+            .handle_transfer_start_from(start(Some(sender), Some(ours)), None, Some(sender))
+            .await
+            .unwrap();
+        assert_eq!(ack.receiver.unwrap().verify(&ours).unwrap(), daemon.peer_id());
+        let state = handler.storage.load_metadata("from-alice").await.unwrap();
+        assert_eq!(state.sender, Some(alice.peer_id()));
+    }
+
+    #[test]
+    fn test_remote_peers_need_trusted_senders() {
+        let remote: IpAddr = "192.168.1.20".parse().unwrap();
+        let anyone = FileTransferHandler::new(test_config());
+        assert!(anyone.accepts_peer("127.0.0.1".parse().unwrap()));
+        assert!(!anyone.accepts_peer(remote), "tokenless remote transfer");
+
+        let key = Identity::from_pkcs8(&Identity::generate_pkcs8().unwrap()).unwrap();
+        let trusted = FileTransferHandler::new(TransferConfig {
+            trusted_senders: vec![key.peer_id()],
+            ..test_config()
+        });
+        assert!(trusted.accepts_peer(remote));
+    }
+
     #[tokio::test]
     async fn test_chunk_data_validation() {
         use crate::file_transfer::validation::hash_data;
@@ -395,6 +1015,11 @@ mod tests {
             mime_type: Some("text/plain".to_string()),
             blake3_hash: "abc123".to_string(),
             metadata: None,
+            session_id: None,
+            batch_id: None,
+            sender: None,
+            challenge: None,
+            directory: None,
         };
 
         handler.handle_transfer_start(start_msg).await.unwrap();
@@ -434,6 +1059,11 @@ mod tests {
             mime_type: Some("text/plain".to_string()),
             blake3_hash: "abc123".to_string(),
             metadata: None,
+            session_id: None,
+            batch_id: None,
+            sender: None,
+            challenge: None,
+            directory: None,
         };
 
         handler.handle_transfer_start(start_msg).await.unwrap();
@@ -477,6 +1107,11 @@ mod tests {
             mime_type: Some("text/plain".to_string()),
             blake3_hash: "abc123".to_string(),
             metadata: None,
+            session_id: None,
+            batch_id: None,
+            sender: None,
+            challenge: None,
+            directory: None,
         };
 
         handler.handle_transfer_start(start_msg).await.unwrap();
@@ -521,6 +1156,11 @@ mod tests {
             mime_type: Some("text/plain".to_string()),
             blake3_hash: "abc123".to_string(),
             metadata: None,
+            session_id: None,
+            batch_id: None,
+            sender: None,
+            challenge: None,
+            directory: None,
         };
 
         handler.handle_transfer_start(start_msg).await.unwrap();
@@ -576,6 +1216,11 @@ mod tests {
             mime_type: Some("text/plain".to_string()),
             blake3_hash: "abc123".to_string(),
             metadata: None,
+            session_id: None,
+            batch_id: None,
+            sender: None,
+            challenge: None,
+            directory: None,
         };
         handler.handle_transfer_start(msg1).await.unwrap();
         assert_eq!(handler.active_transfer_count().await, 1);
@@ -591,8 +1236,316 @@ mod tests {
             mime_type: Some("text/plain".to_string()),
             blake3_hash: "def456".to_string(),
             metadata: None,
+            session_id: None,
+            batch_id: None,
+            sender: None,
+            challenge: None,
+            directory: None,
         };
         handler.handle_transfer_start(msg2).await.unwrap();
         assert_eq!(handler.active_transfer_count().await, 2);
     }
+
+    #[tokio::test]
+    async fn test_checkpoint_on_shutdown() {
+        let handler = FileTransferHandler::new(test_config());
+        handler.initialize().await.unwrap();
+
+        let start = |transfer_id: &str| TransferStartMessage {
+            transfer_id: transfer_id.to_string(),
+            timestamp: current_timestamp(),
+            file_name: "test.txt".to_string(),
+            file_size: 1024,
+            chunk_size: 512,
+            total_chunks: 2,
+            mime_type: Some("text/plain".to_string()),
+            blake3_hash: "abc123".to_string(),
+            metadata: None,
+            session_id: None,
+            batch_id: None,
+            sender: None,
+            challenge: None,
+            directory: None,
+        };
+        handler.handle_transfer_start(start("test-1")).await.unwrap();
+
+        handler.stop_accepting();
+        let refused = handler.handle_transfer_start(start("test-2")).await;
+        assert!(matches!(refused, Err(TransferError::ShuttingDown)));
+
+        assert_eq!(handler.checkpoint_transfers().await.unwrap(), 1);
+        assert_eq!(handler.active_transfer_count().await, 0);
+        let state = handler.storage.load_metadata("test-1").await.unwrap();
+        assert_eq!(state.status, TransferStatus::Incomplete);
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_policy_refuses_and_quarantines() {
+        use crate::file_transfer::validation::hash_data;
+
+        let mut config = test_config();
+        config.policy.blocked_extensions = vec!["exe".to_string()];
+        config.policy.scanner_command = vec!["false".to_string()];
+        let quarantine_dir = config.quarantine_dir();
+        let handler = FileTransferHandler::new(config);
+        handler.initialize().await.unwrap();
+
+        let start = |transfer_id: &str, file_name: &str| TransferStartMessage {
+            transfer_id: transfer_id.to_string(),
+            timestamp: current_timestamp(),
+            file_name: file_name.to_string(),
+            file_size: 5,
+            chunk_size: 5,
+            total_chunks: 1,
+            mime_type: None,
+            blake3_hash: hash_data(b"EICAR"),
+            metadata: None,
+            session_id: None,
+            batch_id: None,
+            sender: None,
+            challenge: None,
+            directory: None,
+        };
+
+        let refused = handler.handle_transfer_start(start("test-exe", "setup.exe")).await;
+        assert!(matches!(refused, Err(TransferError::PolicyViolation(_))));
+
+        handler.handle_transfer_start(start("test-scan", "report.pdf")).await.unwrap();
+        let chunk = ChunkDataMessage {
+            transfer_id: "test-scan".to_string(),
+            timestamp: current_timestamp(),
+            chunk_index: 0,
+            chunk_size: 5,
+            chunk_hash: hash_data(b"EICAR"),
+        };
+        handler.handle_chunk_data(chunk, b"EICAR".to_vec()).await.unwrap();
+
+        let complete = TransferCompleteMessage {
+            transfer_id: "test-scan".to_string(),
+            timestamp: current_timestamp(),
+            total_chunks: 1,
+            total_bytes: 5,
+            final_hash: hash_data(b"EICAR"),
+        };
+        let path = match handler.handle_transfer_complete(complete).await {
+            Err(TransferError::Quarantined { path, .. }) => path,
+            other => panic!("Expected Quarantined error, got {:?}", other),
+        };
+
+        assert_eq!(path, quarantine_dir.join("test-scan-report.pdf"));
+        assert_eq!(std::fs::read(&path).unwrap(), b"EICAR");
+        assert!(quarantine_dir.join("test-scan-report.pdf.json").exists());
+        assert_eq!(handler.active_transfer_count().await, 0);
+        let state = handler.storage.load_metadata("test-scan").await.unwrap();
+        assert_eq!(state.status, TransferStatus::Quarantined);
+    }
+
+    #[tokio::test]
+    async fn test_sftp_uploads() {
+        let mut config = test_config();
+        config.policy.blocked_extensions = vec!["exe".to_string()];
+        let inbox = config.sftp_root().join("alice");
+        let quarantine_dir = config.quarantine_dir();
+        let handler = FileTransferHandler::new(config);
+        handler.initialize().await.unwrap();
+        std::fs::create_dir_all(&inbox).unwrap();
+
+        let upload = |name: &str| {
+            let path = inbox.join(name);
+            std::fs::write(&path, b"hello").unwrap();
+            SftpEvent::FileReceived { user: "alice".to_string(), path, size: 5 }
+        };
+
+        handler.handle_sftp_event(upload("notes.txt")).await.unwrap();
+        assert!(inbox.join("notes.txt").exists());
+
+        let path = match handler.handle_sftp_event(upload("setup.exe")).await {
+            Err(TransferError::Quarantined { path, .. }) => path,
+            other => panic!("Expected Quarantined error, got {:?}", other),
+        };
+        assert!(path.starts_with(&quarantine_dir));
+        assert!(!inbox.join("setup.exe").exists());
+    }
+
+    #[tokio::test]
+    async fn test_batch_manifest() {
+        use crate::file_transfer::validation::hash_data;
+
+        let destination = TempDir::new().unwrap();
+        let handler = FileTransferHandler::new(test_config());
+        handler.initialize().await.unwrap();
+
+        let send = |transfer_id: &'static str, contents: &'static [u8]| {
+            let handler = &handler;
+            let destination = destination.path().to_path_buf();
+            async move {
+                let start = TransferStartMessage {
+                    transfer_id: transfer_id.to_string(),
+                    timestamp: current_timestamp(),
+                    file_name: format!("{}.txt", transfer_id),
+                    file_size: contents.len() as u64,
+                    chunk_size: contents.len(),
+                    total_chunks: 1,
+                    mime_type: None,
+                    blake3_hash: hash_data(contents),
+                    metadata: None,
+                    session_id: None,
+                    batch_id: Some("batch-1".to_string()),
+                    sender: None,
+                    challenge: None,
+                    directory: None,
+                };
+                handler.handle_transfer_start_into(start, Some(destination)).await.unwrap();
+                let chunk = ChunkDataMessage {
+                    transfer_id: transfer_id.to_string(),
+                    timestamp: current_timestamp(),
+                    chunk_index: 0,
+                    chunk_size: contents.len(),
+                    chunk_hash: hash_data(contents),
+                };
+                handler.handle_chunk_data(chunk, contents.to_vec()).await.unwrap();
+                TransferCompleteMessage {
+                    transfer_id: transfer_id.to_string(),
+                    timestamp: current_timestamp(),
+                    total_chunks: 1,
+                    total_bytes: contents.len() as u64,
+                    final_hash: hash_data(contents),
+                }
+            }
+        };
+        let batch_complete = |attest| BatchCompleteMessage {
+            batch_id: "batch-1".to_string(),
+            timestamp: current_timestamp(),
+            algorithm: crate::file_transfer::ManifestAlgorithm::Blake3,
+            attest,
+        };
+
+        let first = send("one", b"first").await;
+        handler.handle_transfer_complete(first).await.unwrap();
+        let second = send("two", b"second").await;
+
+        // Not every file has arrived yet
+        let early = handler.handle_batch_complete(batch_complete(false)).await;
+        assert!(matches!(early, Err(TransferError::Manifest(_))));
+        handler.handle_transfer_complete(second).await.unwrap();
+
+        // No key configured to attest with
+        let unsigned = handler.handle_batch_complete(batch_complete(true)).await;
+        assert!(matches!(unsigned, Err(TransferError::Manifest(_))));
+
+        let manifest = handler.handle_batch_complete(batch_complete(false)).await.unwrap();
+        assert_eq!(manifest.files, 2);
+        assert_eq!(manifest.attestation_path, None);
+        assert_eq!(
+            std::fs::read_to_string(destination.path().join("B3SUMS")).unwrap(),
+            format!("{}  one.txt\n{}  two.txt\n", hash_data(b"first"), hash_data(b"second"))
+        );
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_directory_transfer() {
+        use crate::file_transfer::validation::hash_data;
+        use std::os::unix::fs::PermissionsExt;
+        use tft_core::{EntryKind, ManifestEntry};
+
+        let destination = TempDir::new().unwrap();
+        let handler = FileTransferHandler::new(test_config());
+        handler.initialize().await.unwrap();
+
+        let mtime = chrono::DateTime::from_timestamp(1_600_000_000, 0).unwrap();
+        let entry = |path: &str, kind: EntryKind, mode: u32| ManifestEntry {
+            path: path.to_string(),
+            kind,
+            mode: Some(mode),
+            mtime: Some(mtime),
+        };
+        let manifest = DirectoryManifest {
+            manifest_id: uuid::Uuid::new_v4(),
+            entries: vec![
+                entry("bin", EntryKind::Directory, 0o755),
+                entry("bin/run.sh", EntryKind::File { size: 10 }, 0o700),
+                entry("empty", EntryKind::Directory, 0o755),
+                entry("run", EntryKind::Symlink { target: "bin/run.sh".to_string() }, 0o777),
+            ],
+        };
+        let start = DirectoryStartMessage {
+            directory_id: "dir-1".to_string(),
+            timestamp: current_timestamp(),
+            name: "project".to_string(),
+            manifest_size: 0,
+            session_id: None,
+            sender: None,
+            challenge: None,
+        };
+        let ack = handler
+            .handle_directory_start(start, manifest, Some(destination.path().to_path_buf()), None)
+            .await
+            .unwrap();
+        let root = destination.path().join("project");
+        assert_eq!(ack.saved_path, root.to_string_lossy());
+        assert!(root.join("empty").is_dir());
+        assert_eq!(std::fs::read_link(root.join("run")).unwrap(), Path::new("bin/run.sh"));
+
+        let contents = b"#!/bin/sh\n";
+        let file_start = |path: &str, file_name: &str| TransferStartMessage {
+            transfer_id: "dir-1-run".to_string(),
+            timestamp: current_timestamp(),
+            file_name: file_name.to_string(),
+            file_size: contents.len() as u64,
+            chunk_size: contents.len(),
+            total_chunks: 1,
+            mime_type: None,
+            blake3_hash: hash_data(contents),
+            metadata: None,
+            session_id: None,
+            batch_id: None,
+            sender: None,
+            challenge: None,
+            directory: Some(DirectoryFile {
+                directory_id: "dir-1".to_string(),
+                path: path.to_string(),
+            }),
+        };
+        let complete = DirectoryCompleteMessage {
+            directory_id: "dir-1".to_string(),
+            timestamp: current_timestamp(),
+        };
+
+        // Only files the manifest lists, under their own names
+        let unlisted = handler.handle_transfer_start(file_start("bin/other.sh", "other.sh")).await;
+        assert!(matches!(unlisted, Err(TransferError::PermissionDenied(_))));
+        let renamed = handler.handle_transfer_start(file_start("bin/run.sh", "evil.sh")).await;
+        assert!(matches!(renamed, Err(TransferError::PermissionDenied(_))));
+        let early = handler.handle_directory_complete(complete.clone()).await;
+        assert!(matches!(early, Err(TransferError::Manifest(_))));
+
+        handler.handle_transfer_start(file_start("bin/run.sh", "run.sh")).await.unwrap();
+        let chunk = ChunkDataMessage {
+            transfer_id: "dir-1-run".to_string(),
+            timestamp: current_timestamp(),
+            chunk_index: 0,
+            chunk_size: contents.len(),
+            chunk_hash: hash_data(contents),
+        };
+        handler.handle_chunk_data(chunk, contents.to_vec()).await.unwrap();
+        let success = handler
+            .handle_transfer_complete(TransferCompleteMessage {
+                transfer_id: "dir-1-run".to_string(),
+                timestamp: current_timestamp(),
+                total_chunks: 1,
+                total_bytes: contents.len() as u64,
+                final_hash: hash_data(contents),
+            })
+            .await
+            .unwrap();
+        assert_eq!(PathBuf::from(success.saved_path), root.join("bin/run.sh"));
+
+        let done = handler.handle_directory_complete(complete).await.unwrap();
+        assert_eq!((done.files, done.total_bytes), (1, 10));
+        let script = std::fs::metadata(root.join("bin/run.sh")).unwrap();
+        assert_eq!(script.permissions().mode() & 0o777, 0o700);
+        assert_eq!(script.modified().unwrap(), std::time::SystemTime::from(mtime));
+    }
 }
@@ -0,0 +1,63 @@
+// Transfer Identity - The daemon's Ed25519 identity key and sender checks
+//
+// Senders may name their identity when a transfer starts; the daemon then
+// challenges them to sign a fresh nonce before accepting the transfer and
+// records the verified identity with it. With `trusted_senders` set, only
+// those identities may send at all. The daemon proves its own identity
+// the same way when a sender challenges it.
+
+use super::{Result, TransferError};
+use std::path::Path;
+use tft_core::{Challenge, Identity, IdentityProof, PeerId};
+use tracing::info;
+
+/// Load the PKCS#8 key at `path`, generating it with `generate` on first
+/// use; `what` names the key in the log
+pub(crate) fn read_or_create_key(
+    path: &Path,
+    what: &str,
+    generate: impl FnOnce() -> Result<Vec<u8>>,
+) -> Result<Vec<u8>> {
+    match std::fs::read(path) {
+        Ok(pkcs8) => Ok(pkcs8),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+            let pkcs8 = generate()?;
+            if let Some(parent) = path.parent() {
+                std::fs::create_dir_all(parent)?;
+            }
+            pulsar_fs::write_private(path, &pkcs8)?;
+            info!("Generated {} at {:?}", what, path);
+            Ok(pkcs8)
+        }
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Load the daemon's identity from `path`, generating it on first use;
+/// the peer ID is written to `<path>.pub` for senders to pin
+pub fn load_or_create(path: &Path) -> Result<Identity> {
+    let pkcs8 = read_or_create_key(path, "transfer identity key", || {
+        Identity::generate_pkcs8().map_err(|e| TransferError::Identity(e.to_string()))
+    })?;
+    let identity = Identity::from_pkcs8(&pkcs8)
+        .map_err(|e| TransferError::Identity(format!("{:?}: {}", path, e)))?;
+
+    let mut public_path = path.as_os_str().to_owned();
+    public_path.push(".pub");
+    pulsar_fs::write(public_path, format!("{}\n", identity.peer_id()))?;
+    Ok(identity)
+}
+
+/// The identity a sender proved by signing `challenge`, if it is the one
+/// it claimed
+pub fn verify_sender(claimed: PeerId, challenge: &Challenge, proof: &IdentityProof) -> Result<PeerId> {
+    if proof.peer_id != claimed {
+        return Err(TransferError::Identity(format!(
+            "Proof is for {}, not the claimed {}",
+            proof.peer_id, claimed
+        )));
+    }
+    proof
+        .verify(challenge)
+        .map_err(|e| TransferError::Identity(e.to_string()))
+}
@@ -0,0 +1,246 @@
+// Batch Checksum Manifests - SHA256SUMS / B3SUMS and signed attestations
+//
+// Once every file of a batch has arrived, the sender may ask for a
+// manifest in the format `sha256sum -c` / `b3sum -c` read, written beside
+// the files, so downstream tooling can verify them without TFT. The
+// optional attestation signs the manifest with the daemon's Ed25519 key;
+// the public half is kept beside the private key for distribution.
+
+use super::identity::read_or_create_key;
+use super::{Result, TransferError};
+use base64::{engine::general_purpose::STANDARD, Engine as _};
+use ring::digest;
+use ring::rand::SystemRandom;
+use ring::signature::{Ed25519KeyPair, KeyPair};
+use serde::{Deserialize, Serialize};
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use tracing::info;
+
+/// Checksum algorithm for a batch manifest
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ManifestAlgorithm {
+    #[default]
+    Sha256,
+    Blake3,
+}
+
+impl ManifestAlgorithm {
+    /// Conventional name of the manifest file
+    pub fn file_name(self) -> &'static str {
+        match self {
+            ManifestAlgorithm::Sha256 => "SHA256SUMS",
+            ManifestAlgorithm::Blake3 => "B3SUMS",
+        }
+    }
+}
+
+/// A file delivered as part of a batch
+#[derive(Debug, Clone)]
+pub struct CompletedFile {
+    pub path: PathBuf,
+    /// BLAKE3 of the contents, as verified on completion
+    pub blake3: String,
+}
+
+/// Signed statement that a manifest came from this daemon
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Attestation {
+    pub batch_id: String,
+    /// Manifest file name, in the same directory
+    pub manifest: String,
+    pub algorithm: ManifestAlgorithm,
+    pub files: usize,
+    pub created_at: String,
+    /// Ed25519 public key, base64
+    pub public_key: String,
+    /// Ed25519 signature over the manifest file's bytes, base64
+    pub signature: String,
+}
+
+/// Where a batch's manifest and attestation were written
+#[derive(Debug, Clone)]
+pub struct WrittenManifest {
+    pub manifest_path: PathBuf,
+    pub attestation_path: Option<PathBuf>,
+}
+
+/// The daemon's manifest signing key, kept across restarts so downstream
+/// tooling can pin its public key
+pub struct ManifestSigner {
+    key_pair: Ed25519KeyPair,
+}
+
+impl ManifestSigner {
+    /// Load the PKCS#8 key at `path`, generating it on first use; the
+    /// public key is written to `<path>.pub` as base64
+    pub fn load_or_create(path: &Path) -> Result<Self> {
+        let pkcs8 = read_or_create_key(path, "manifest signing key", || {
+            Ed25519KeyPair::generate_pkcs8(&SystemRandom::new())
+                .map(|document| document.as_ref().to_vec())
+                .map_err(|_| TransferError::Manifest("Failed to generate signing key".to_string()))
+        })?;
+
+        let key_pair = Ed25519KeyPair::from_pkcs8(&pkcs8)
+            .map_err(|e| TransferError::Manifest(format!("Invalid signing key {:?}: {}", path, e)))?;
+        let signer = Self { key_pair };
+
+        let mut public_path = path.as_os_str().to_owned();
+        public_path.push(".pub");
+        pulsar_fs::write(public_path, signer.public_key() + "\n")?;
+        Ok(signer)
+    }
+
+    /// Public key, base64
+    pub fn public_key(&self) -> String {
+        STANDARD.encode(self.key_pair.public_key().as_ref())
+    }
+
+    fn sign(&self, message: &[u8]) -> String {
+        STANDARD.encode(self.key_pair.sign(message).as_ref())
+    }
+}
+
+/// Write the manifest for `files`, which must share a directory, and sign
+/// it when a signer is given
+pub async fn write_manifest(
+    batch_id: &str,
+    mut files: Vec<CompletedFile>,
+    algorithm: ManifestAlgorithm,
+    signer: Option<&ManifestSigner>,
+) -> Result<WrittenManifest> {
+    let directory = shared_directory(&files)?;
+
+    files.sort_by(|a, b| a.path.cmp(&b.path));
+
+    let mut manifest = String::new();
+    for file in files.iter() {
+        let checksum = match algorithm {
+            ManifestAlgorithm::Sha256 => sha256_file(file.path.clone()).await?,
+            ManifestAlgorithm::Blake3 => file.blake3.to_lowercase(),
+        };
+        let name = file.path.file_name().unwrap_or_default().to_string_lossy();
+        manifest.push_str(&format!("{}  {}\n", checksum, name));
+    }
+
+    let manifest_path = directory.join(algorithm.file_name());
+    if manifest_path.exists() {
+        return Err(TransferError::DestinationExists(manifest_path));
+    }
+    pulsar_fs::write(&manifest_path, &manifest)?;
+
+    let attestation_path = match signer {
+        Some(signer) => {
+            let attestation = Attestation {
+                batch_id: batch_id.to_string(),
+                manifest: algorithm.file_name().to_string(),
+                algorithm,
+                files: files.len(),
+                created_at: chrono::Utc::now().to_rfc3339(),
+                public_key: signer.public_key(),
+                signature: signer.sign(manifest.as_bytes()),
+            };
+            let path = directory.join(format!("{}.attestation.json", algorithm.file_name()));
+            pulsar_fs::write(&path, serde_json::to_vec_pretty(&attestation)?)?;
+            Some(path)
+        }
+        None => None,
+    };
+
+    info!(
+        "Wrote {} for batch {} ({} files)",
+        manifest_path.display(),
+        batch_id,
+        files.len()
+    );
+    Ok(WrittenManifest {
+        manifest_path,
+        attestation_path,
+    })
+}
+
+fn shared_directory(files: &[CompletedFile]) -> Result<PathBuf> {
+    let mut directories = files.iter().map(|file| file.path.parent());
+    let Some(Some(directory)) = directories.next() else {
+        return Err(TransferError::Manifest("The batch has no files".to_string()));
+    };
+    if directories.any(|other| other != Some(directory)) {
+        return Err(TransferError::Manifest(
+            "The batch's files were saved to different directories".to_string(),
+        ));
+    }
+    Ok(directory.to_path_buf())
+}
+
+async fn sha256_file(path: PathBuf) -> Result<String> {
+    tokio::task::spawn_blocking(move || {
+        let mut file = std::fs::File::open(path)?;
+        let mut context = digest::Context::new(&digest::SHA256);
+        let mut buffer = vec![0; 1024 * 1024];
+        loop {
+            let read = file.read(&mut buffer)?;
+            if read == 0 {
+                break;
+            }
+            context.update(&buffer[..read]);
+        }
+        Ok(hex(context.finish().as_ref()))
+    })
+    .await
+    .map_err(std::io::Error::other)?
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ring::signature::{UnparsedPublicKey, ED25519};
+    use tempfile::TempDir;
+
+    #[tokio::test]
+    async fn test_signed_sha256_manifest() {
+        let dir = TempDir::new().unwrap();
+        let mut files = Vec::new();
+        for (name, contents) in [("b.txt", "beta\n"), ("a.txt", "alpha\n")] {
+            let path = dir.path().join(name);
+            std::fs::write(&path, contents).unwrap();
+            files.push(CompletedFile {
+                path,
+                blake3: blake3::hash(contents.as_bytes()).to_hex().to_string(),
+            });
+        }
+        let key_path = dir.path().join("keys").join("transfer-signing.key");
+        let signer = ManifestSigner::load_or_create(&key_path).unwrap();
+
+        let written = write_manifest("batch-1", files.clone(), ManifestAlgorithm::Sha256, Some(&signer))
+            .await
+            .unwrap();
+
+        // Same output as `sha256sum a.txt b.txt`
+        let sha256 = |contents: &[u8]| hex(digest::digest(&digest::SHA256, contents).as_ref());
+        let manifest = std::fs::read_to_string(&written.manifest_path).unwrap();
+        assert_eq!(
+            manifest,
+            format!("{}  a.txt\n{}  b.txt\n", sha256(b"alpha\n"), sha256(b"beta\n"))
+        );
+
+        let attestation: Attestation =
+            serde_json::from_slice(&std::fs::read(written.attestation_path.unwrap()).unwrap()).unwrap();
+        assert_eq!(attestation.files, 2);
+        let public_key = STANDARD.decode(&attestation.public_key).unwrap();
+        let signature = STANDARD.decode(&attestation.signature).unwrap();
+        UnparsedPublicKey::new(&ED25519, public_key)
+            .verify(manifest.as_bytes(), &signature)
+            .unwrap();
+
+        // The key survives a restart, and a second manifest is refused
+        let reloaded = ManifestSigner::load_or_create(&key_path).unwrap();
+        assert_eq!(reloaded.public_key(), signer.public_key());
+        let again = write_manifest("batch-1", files, ManifestAlgorithm::Sha256, None).await;
+        assert!(matches!(again, Err(TransferError::DestinationExists(_))));
+    }
+}
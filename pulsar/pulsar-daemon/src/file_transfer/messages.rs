@@ -1,7 +1,9 @@
 // File Transfer Protocol Messages
 
+use super::manifest::ManifestAlgorithm;
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
+use tft_core::{Challenge, IdentityProof, PeerId};
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(tag = "type", rename_all = "snake_case")]
@@ -15,6 +17,14 @@ pub enum TransferMessage {
     TransferAbort(TransferAbortMessage),
     ResumeRequest(ResumeRequestMessage),
     ResumeInfo(ResumeInfoMessage),
+    IdentityChallenge(IdentityChallengeMessage),
+    IdentityResponse(IdentityResponseMessage),
+    BatchComplete(BatchCompleteMessage),
+    BatchManifest(BatchManifestMessage),
+    DirectoryStart(DirectoryStartMessage),
+    DirectoryAck(DirectoryAckMessage),
+    DirectoryComplete(DirectoryCompleteMessage),
+    DirectorySuccess(DirectorySuccessMessage),
     Error(ErrorMessage),
 }
 
@@ -31,6 +41,32 @@ pub struct TransferStartMessage {
     pub blake3_hash: String,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub metadata: Option<FileMetadata>,
+    /// Terminal session the file was dropped on; the file is saved to the
+    /// session's working directory when that is on this machine
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub session_id: Option<uuid::Uuid>,
+    /// Groups transfers so a checksum manifest can be written for them
+    /// once they have all arrived
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub batch_id: Option<String>,
+    /// Identity the sender will prove when challenged
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sender: Option<PeerId>,
+    /// Asks the daemon to prove its identity in the ack
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub challenge: Option<Challenge>,
+    /// The directory transfer the file belongs to; it is saved at its
+    /// place in the tree
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub directory: Option<DirectoryFile>,
+}
+
+/// A file's place in a directory transfer
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DirectoryFile {
+    pub directory_id: String,
+    /// The file's path in the directory's manifest
+    pub path: String,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -48,6 +84,9 @@ pub struct TransferAckMessage {
     pub accepted: bool,
     pub resume_supported: bool,
     pub max_chunk_size: usize,
+    /// The daemon's answer to the start message's challenge
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub receiver: Option<IdentityProof>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -116,6 +155,96 @@ pub struct ResumeInfoMessage {
     pub received_bytes: u64,
 }
 
+/// Sent in reply to a start message naming a sender; the sender answers
+/// with an identity response before the transfer is accepted
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct IdentityChallengeMessage {
+    pub transfer_id: String,
+    pub timestamp: u64,
+    pub challenge: Challenge,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct IdentityResponseMessage {
+    pub transfer_id: String,
+    pub timestamp: u64,
+    pub proof: IdentityProof,
+}
+
+/// Sent after the last transfer of a batch has succeeded
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BatchCompleteMessage {
+    pub batch_id: String,
+    pub timestamp: u64,
+    #[serde(default)]
+    pub algorithm: ManifestAlgorithm,
+    /// Also write an attestation signed with the daemon's key
+    #[serde(default)]
+    pub attest: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BatchManifestMessage {
+    pub batch_id: String,
+    pub timestamp: u64,
+    pub manifest_path: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub attestation_path: Option<String>,
+    pub files: u32,
+}
+
+/// Starts the transfer of a folder
+///
+/// The JSON `tft_core::DirectoryManifest` of the tree follows as
+/// `manifest_size` bytes, like a chunk's data. Once acknowledged, each
+/// file goes as a transfer of its own naming its place in `directory`;
+/// modes and mtimes are applied when the sender completes the directory,
+/// if the manifest carries them.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DirectoryStartMessage {
+    pub directory_id: String,
+    pub timestamp: u64,
+    /// Name of the folder, created in the destination
+    pub name: String,
+    pub manifest_size: usize,
+    /// Terminal session the folder was dropped on
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub session_id: Option<uuid::Uuid>,
+    /// Identity the sender will prove when challenged
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sender: Option<PeerId>,
+    /// Asks the daemon to prove its identity in the ack
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub challenge: Option<Challenge>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DirectoryAckMessage {
+    pub directory_id: String,
+    pub timestamp: u64,
+    pub accepted: bool,
+    /// Where the folder is being created
+    pub saved_path: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub receiver: Option<IdentityProof>,
+}
+
+/// Sent after the last file of a directory transfer has succeeded
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DirectoryCompleteMessage {
+    pub directory_id: String,
+    pub timestamp: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DirectorySuccessMessage {
+    pub directory_id: String,
+    pub timestamp: u64,
+    pub saved_path: String,
+    pub files: u32,
+    pub total_bytes: u64,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ErrorMessage {
     pub transfer_id: String,
@@ -125,7 +254,8 @@ pub struct ErrorMessage {
 }
 
 impl TransferMessage {
-    /// Get the transfer ID from any message type
+    /// Get the transfer ID from any message type (the batch or directory
+    /// ID for batch and directory messages)
     pub fn transfer_id(&self) -> &str {
         match self {
             Self::TransferStart(m) => &m.transfer_id,
@@ -137,6 +267,14 @@ impl TransferMessage {
             Self::TransferAbort(m) => &m.transfer_id,
             Self::ResumeRequest(m) => &m.transfer_id,
             Self::ResumeInfo(m) => &m.transfer_id,
+            Self::IdentityChallenge(m) => &m.transfer_id,
+            Self::IdentityResponse(m) => &m.transfer_id,
+            Self::BatchComplete(m) => &m.batch_id,
+            Self::BatchManifest(m) => &m.batch_id,
+            Self::DirectoryStart(m) => &m.directory_id,
+            Self::DirectoryAck(m) => &m.directory_id,
+            Self::DirectoryComplete(m) => &m.directory_id,
+            Self::DirectorySuccess(m) => &m.directory_id,
             Self::Error(m) => &m.transfer_id,
         }
     }
@@ -153,6 +291,14 @@ impl TransferMessage {
             Self::TransferAbort(m) => m.timestamp,
             Self::ResumeRequest(m) => m.timestamp,
             Self::ResumeInfo(m) => m.timestamp,
+            Self::IdentityChallenge(m) => m.timestamp,
+            Self::IdentityResponse(m) => m.timestamp,
+            Self::BatchComplete(m) => m.timestamp,
+            Self::BatchManifest(m) => m.timestamp,
+            Self::DirectoryStart(m) => m.timestamp,
+            Self::DirectoryAck(m) => m.timestamp,
+            Self::DirectoryComplete(m) => m.timestamp,
+            Self::DirectorySuccess(m) => m.timestamp,
             Self::Error(m) => m.timestamp,
         }
     }
@@ -5,18 +5,34 @@
 // - Parallel stream support
 // - Resume capability
 // - BLAKE3 integrity validation
+// - Admin policy: blocked extensions, size limit, external scanner and
+//   quarantine
+// - SHA256SUMS / B3SUMS manifests with signed attestations for batches
+// - Ed25519 sender identities and an allowlist of trusted senders
+// - An SFTP/scp inbox for clients that don't speak TFT
 
 pub mod handler;
+pub mod identity;
+pub mod manifest;
 pub mod messages;
+pub mod policy;
+pub mod sftp;
 pub mod storage;
 pub mod validation;
 
 pub use handler::FileTransferHandler;
+pub use manifest::{ManifestAlgorithm, ManifestSigner};
 pub use messages::*;
+pub use policy::TransferPolicy;
 pub use storage::TransferStorage;
 pub use validation::HashValidator;
 
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::net::SocketAddr;
 use std::path::PathBuf;
+use tft_core::PeerId;
+use tft_transports::BandwidthLimit;
 use thiserror::Error;
 
 #[derive(Debug, Error)]
@@ -34,8 +50,12 @@ pub enum TransferError {
     #[error("File hash mismatch: expected {expected}, got {actual}")]
     FileHashMismatch { expected: String, actual: String },
 
-    #[error("Disk full")]
-    DiskFull,
+    #[error("Not enough disk space in {path}: {required} bytes needed, {available} available")]
+    DiskFull {
+        path: PathBuf,
+        required: u64,
+        available: u64,
+    },
 
     #[error("Permission denied: {0}")]
     PermissionDenied(String),
@@ -59,6 +79,26 @@ pub enum TransferError {
     #[error("Resume not supported")]
     ResumeNotSupported,
 
+    #[error("Daemon is shutting down")]
+    ShuttingDown,
+
+    #[error("Destination already exists: {0}")]
+    DestinationExists(PathBuf),
+
+    #[error("Refused by policy: {0}")]
+    PolicyViolation(String),
+
+    /// The file was received but failed the policy checks; `path` is
+    /// where it was kept
+    #[error("File quarantined: {reason}")]
+    Quarantined { reason: String, path: PathBuf },
+
+    #[error("Manifest error: {0}")]
+    Manifest(String),
+
+    #[error("Identity verification failed: {0}")]
+    Identity(String),
+
     #[error("IO error: {0}")]
     IoError(#[from] std::io::Error),
 
@@ -69,7 +109,8 @@ pub enum TransferError {
 pub type Result<T> = std::result::Result<T, TransferError>;
 
 /// Configuration for file transfers
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
 pub struct TransferConfig {
     /// Default chunk size (1 MB)
     pub chunk_size: usize,
@@ -81,6 +122,69 @@ pub struct TransferConfig {
     pub max_file_size: u64,
     /// Transfer timeout (30 minutes)
     pub transfer_timeout_secs: u64,
+    /// Checks on received files
+    pub policy: TransferPolicy,
+    /// Sender identities allowed to send files; empty accepts any sender on
+    /// this machine, verifying the identity of those that name one, and
+    /// refuses WebTransport transfers from other hosts
+    pub trusted_senders: Vec<PeerId>,
+    /// Cap on the rate files are received at, across all transfers; unset
+    /// or 0 is unlimited. Changed at runtime with `set_bandwidth_limit`.
+    pub max_bytes_per_sec: Option<u64>,
+    /// Bytes that may arrive at once after an idle spell; defaults to one
+    /// second's worth
+    pub burst_bytes: Option<u64>,
+    /// SFTP/scp server legacy clients upload into
+    pub sftp: SftpInboxConfig,
+}
+
+/// SFTP/scp inbox: each user uploads into their own directory, and
+/// finished files get the same policy checks as TFT transfers
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SftpInboxConfig {
+    /// Address to listen on; unset leaves the server off
+    pub listen_addr: Option<SocketAddr>,
+    /// Directory holding a directory per user; defaults to "inbox" in
+    /// `storage_path`
+    pub root: Option<PathBuf>,
+    /// Server host key, generated on first start; defaults to
+    /// "sftp_host_key" in `storage_path`
+    pub host_key_path: Option<PathBuf>,
+    /// Users and the public keys they log in with, as `authorized_keys`
+    /// lines
+    pub users: BTreeMap<String, Vec<String>>,
+}
+
+impl TransferConfig {
+    /// Where files failing the policy checks are kept
+    pub fn quarantine_dir(&self) -> PathBuf {
+        self.policy
+            .quarantine_dir
+            .clone()
+            .unwrap_or_else(|| self.storage_path.join("quarantine"))
+    }
+
+    /// Where SFTP users' directories are
+    pub fn sftp_root(&self) -> PathBuf {
+        self.sftp
+            .root
+            .clone()
+            .unwrap_or_else(|| self.storage_path.join("inbox"))
+    }
+
+    /// Where the SFTP server's host key is kept
+    pub fn sftp_host_key_path(&self) -> PathBuf {
+        self.sftp
+            .host_key_path
+            .clone()
+            .unwrap_or_else(|| self.storage_path.join("sftp_host_key"))
+    }
+
+    /// The configured bandwidth cap, if any
+    pub fn bandwidth_limit(&self) -> Option<BandwidthLimit> {
+        BandwidthLimit::new(self.max_bytes_per_sec?, self.burst_bytes)
+    }
 }
 
 impl Default for TransferConfig {
@@ -91,6 +195,11 @@ impl Default for TransferConfig {
             storage_path: PathBuf::from("/tmp/pulsar/transfers"),
             max_file_size: 100 * 1024 * 1024 * 1024, // 100 GB
             transfer_timeout_secs: 30 * 60,           // 30 minutes
+            policy: TransferPolicy::default(),
+            trusted_senders: Vec::new(),
+            max_bytes_per_sec: None,
+            burst_bytes: None,
+            sftp: SftpInboxConfig::default(),
         }
     }
 }
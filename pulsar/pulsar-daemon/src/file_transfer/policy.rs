@@ -0,0 +1,170 @@
+// Transfer Policy - Admin rules for received files
+//
+// Checked twice: when a transfer starts (file name) and once the file is
+// assembled and its hash verified, before it is moved into place (actual
+// size, external scanner). A file failing the second check is moved to
+// quarantine instead of its destination.
+
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use std::time::Duration;
+use tokio::process::Command;
+use tracing::{debug, info};
+
+/// Placeholder in `scanner_command` replaced with the received file's path
+const PATH_PLACEHOLDER: &str = "{path}";
+
+/// Admin rules for received files
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TransferPolicy {
+    /// Extensions refused when a transfer starts, without the dot (e.g.
+    /// `"exe"` or `"tar.gz"`), compared case-insensitively
+    pub blocked_extensions: Vec<String>,
+    /// Program and arguments run on each received file; `{path}` is
+    /// replaced with the file's path, or the path is appended when absent.
+    /// Exit status 0 means clean. Empty runs no scanner.
+    pub scanner_command: Vec<String>,
+    /// Seconds the scanner may run before the file is quarantined
+    pub scanner_timeout_secs: u64,
+    /// Where rejected files are kept; defaults to `quarantine` in the
+    /// transfer storage directory
+    pub quarantine_dir: Option<PathBuf>,
+}
+
+impl Default for TransferPolicy {
+    fn default() -> Self {
+        Self {
+            blocked_extensions: Vec::new(),
+            scanner_command: Vec::new(),
+            scanner_timeout_secs: 300,
+            quarantine_dir: None,
+        }
+    }
+}
+
+impl TransferPolicy {
+    /// Reason to refuse a file named `file_name`, if any
+    pub fn check_name(&self, file_name: &str) -> Option<String> {
+        let file_name = file_name.to_lowercase();
+        self.blocked_extensions
+            .iter()
+            .map(|extension| extension.trim_start_matches('.').to_lowercase())
+            .find(|extension| file_name.ends_with(&format!(".{}", extension)))
+            .map(|extension| format!("Files ending in .{} are not accepted", extension))
+    }
+
+    /// Reason to quarantine the received file at `path`, if any
+    pub async fn check_file(&self, path: &Path, max_file_size: u64) -> Option<String> {
+        let size = match tokio::fs::metadata(path).await {
+            Ok(metadata) => metadata.len(),
+            Err(e) => return Some(format!("Could not read the received file: {}", e)),
+        };
+        if size > max_file_size {
+            return Some(format!("File size {} exceeds maximum {}", size, max_file_size));
+        }
+
+        self.scan(path).await
+    }
+
+    /// Run the scanner on `path`; anything but a clean exit in time is a
+    /// reason to quarantine
+    async fn scan(&self, path: &Path) -> Option<String> {
+        let (program, args) = self.scanner_command.split_first()?;
+
+        let mut command = Command::new(program);
+        let mut placed = false;
+        for arg in args {
+            if arg.contains(PATH_PLACEHOLDER) {
+                command.arg(arg.replace(PATH_PLACEHOLDER, &path.to_string_lossy()));
+                placed = true;
+            } else {
+                command.arg(arg);
+            }
+        }
+        if !placed {
+            command.arg(path);
+        }
+        command
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .kill_on_drop(true);
+
+        debug!("Scanning {:?} with {}", path, program);
+        let timeout = Duration::from_secs(self.scanner_timeout_secs);
+        let output = match tokio::time::timeout(timeout, command.output()).await {
+            Ok(Ok(output)) => output,
+            Ok(Err(e)) => return Some(format!("Scanner {} could not run: {}", program, e)),
+            Err(_) => {
+                return Some(format!(
+                    "Scanner {} did not finish within {}s",
+                    program, self.scanner_timeout_secs
+                ))
+            }
+        };
+
+        if output.status.success() {
+            info!("Scanner passed {:?}", path);
+            return None;
+        }
+
+        // Scanners name what they found on stdout (clamscan) or stderr
+        let report = [&output.stdout, &output.stderr]
+            .iter()
+            .map(|stream| String::from_utf8_lossy(stream).trim().to_string())
+            .find(|report| !report.is_empty())
+            .unwrap_or_default();
+        Some(format!("Scanner {} rejected the file ({}): {}", program, output.status, report))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_blocked_extensions() {
+        let policy = TransferPolicy {
+            blocked_extensions: vec!["exe".to_string(), ".tar.gz".to_string()],
+            ..Default::default()
+        };
+
+        assert!(policy.check_name("setup.EXE").is_some());
+        assert!(policy.check_name("backup.tar.gz").is_some());
+        assert!(policy.check_name("notes.txt").is_none());
+        assert!(policy.check_name("exe").is_none());
+        assert!(policy.check_name("archive.gz").is_none());
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_scanner_and_size() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("report.pdf");
+        std::fs::write(&path, "EICAR").unwrap();
+
+        let scanner = |script: &str| TransferPolicy {
+            scanner_command: vec!["sh".to_string(), "-c".to_string(), script.to_string(), "scan".to_string()],
+            scanner_timeout_secs: 1,
+            ..Default::default()
+        };
+
+        // The path is appended, becoming $1
+        assert_eq!(scanner("test -f \"$1\"").check_file(&path, 1024).await, None);
+
+        let reason = scanner("echo \"$1: Eicar-Signature FOUND\"; exit 1")
+            .check_file(&path, 1024)
+            .await
+            .unwrap();
+        assert!(reason.contains("report.pdf: Eicar-Signature FOUND"), "{}", reason);
+
+        let reason = scanner("sleep 5").check_file(&path, 1024).await.unwrap();
+        assert!(reason.contains("did not finish within 1s"), "{}", reason);
+
+        let reason = TransferPolicy::default().check_file(&path, 4).await.unwrap();
+        assert!(reason.contains("exceeds maximum 4"), "{}", reason);
+    }
+}
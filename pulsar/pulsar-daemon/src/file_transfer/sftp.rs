@@ -0,0 +1,59 @@
+// SFTP Inbox - uploads from clients that don't speak TFT
+//
+// sftp, scp and WinSCP log in with the keys listed for their user in
+// `transfers.sftp.users` and upload into that user's directory under
+// `transfers.sftp.root`. Each finished upload is handed to the file
+// transfer handler, which runs the policy checks and sends the
+// `transfer.completed` webhook.
+
+use super::{FileTransferHandler, TransferConfig};
+use anyhow::{Context, Result};
+use std::net::SocketAddr;
+use std::sync::Arc;
+use tft_transports::{load_or_create_host_key, SftpCredential, SftpServer};
+use tokio::sync::mpsc;
+use tracing::{info, warn};
+
+/// Serve the SFTP inbox on `addr`, passing uploads to `file_transfer`
+pub async fn start_server(
+    file_transfer: Arc<FileTransferHandler>,
+    config: &TransferConfig,
+    addr: SocketAddr,
+) -> Result<()> {
+    let host_key_path = config.sftp_host_key_path();
+    let host_key = load_or_create_host_key(&host_key_path)
+        .with_context(|| format!("Failed to load SFTP host key {:?}", host_key_path))?;
+
+    // Unbounded: every received file must reach the policy checks
+    let (events_tx, mut events) = mpsc::unbounded_channel();
+    let mut server = SftpServer::new(config.sftp_root()).with_events(events_tx);
+    for (user, authorized_keys) in &config.sftp.users {
+        let authorized_keys = authorized_keys.clone();
+        server = server.with_user(
+            user,
+            Arc::new(move |credential: SftpCredential<'_>| {
+                credential.is_authorized_key(&authorized_keys)
+            }),
+        );
+    }
+    info!(
+        "SFTP inbox for {} users at {:?}",
+        config.sftp.users.len(),
+        config.sftp_root()
+    );
+
+    tokio::spawn(async move {
+        while let Some(event) = events.recv().await {
+            // Checks and hashing take a while; don't hold up other uploads
+            let file_transfer = Arc::clone(&file_transfer);
+            tokio::spawn(async move {
+                if let Err(e) = file_transfer.handle_sftp_event(event).await {
+                    warn!("SFTP upload: {}", e);
+                }
+            });
+        }
+    });
+
+    server.run(addr, host_key).await?;
+    Ok(())
+}
@@ -1,11 +1,21 @@
 // Transfer Storage - Manages transfer state and file assembly
 
+use super::messages::DirectoryFile;
 use super::{Result, TransferError};
 use serde::{Deserialize, Serialize};
 use std::collections::HashSet;
 use std::path::{Path, PathBuf};
+use tft_core::PeerId;
 use tokio::fs;
 use tokio::io::AsyncWriteExt;
+use tracing::debug;
+
+/// Suffix of files still being written; anything carrying it after a
+/// restart is an orphan
+pub const PARTIAL_SUFFIX: &str = ".tft-partial";
+
+/// Space left free on any disk a transfer writes to
+const DISK_RESERVE: u64 = 64 * 1024 * 1024;
 
 /// Transfer state for resume capability
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -20,6 +30,18 @@ pub struct TransferState {
     pub started_at: String,
     pub last_activity: String,
     pub status: TransferStatus,
+    /// Directory the finished file is moved to; None leaves it in storage
+    #[serde(default)]
+    pub destination_dir: Option<PathBuf>,
+    /// Batch the transfer belongs to, for its checksum manifest
+    #[serde(default)]
+    pub batch_id: Option<String>,
+    /// Identity the sender proved when the transfer started
+    #[serde(default)]
+    pub sender: Option<PeerId>,
+    /// Folder the file belongs to, when part of a directory transfer
+    #[serde(default)]
+    pub directory: Option<DirectoryFile>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
@@ -29,6 +51,15 @@ pub enum TransferStatus {
     Complete,
     Incomplete,
     Failed,
+    /// Received, but kept in quarantine by the transfer policy
+    Quarantined,
+}
+
+/// Partial files removed on startup
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct PartialCleanup {
+    pub files: usize,
+    pub bytes: u64,
 }
 
 /// Storage manager for file transfers
@@ -74,6 +105,39 @@ impl TransferStorage {
             .join(format!("chunk-{:06}.bin", chunk_index))
     }
 
+    /// Where `file_name` is written in `directory` before being moved into
+    /// place; hidden, and named for the transfer so two transfers of the
+    /// same name never share one
+    pub fn partial_path(directory: &Path, file_name: &str, transfer_id: &str) -> PathBuf {
+        directory.join(format!(".{}.{}{}", file_name, transfer_id, PARTIAL_SUFFIX))
+    }
+
+    /// Refuse a file of `file_size` bytes that would not fit. Chunks and
+    /// the assembled file both sit in storage until delivery, and delivery
+    /// to another disk needs a full copy there.
+    pub fn check_space(&self, file_size: u64, destination_dir: Option<&Path>) -> Result<()> {
+        let mut checks = vec![(self.base_path.as_path(), file_size.saturating_mul(2))];
+        if let Some(directory) = destination_dir {
+            checks.push((directory, file_size));
+        }
+
+        for (path, needed) in checks {
+            let required = needed.saturating_add(DISK_RESERVE);
+            match crate::health::available_space(path) {
+                Some(available) if available < required => {
+                    return Err(TransferError::DiskFull {
+                        path: path.to_path_buf(),
+                        required,
+                        available,
+                    });
+                }
+                Some(_) => {}
+                None => debug!("Free space for {:?} is unknown; not checked", path),
+            }
+        }
+        Ok(())
+    }
+
     /// Create transfer directories
     pub async fn create_transfer(&self, transfer_id: &str) -> Result<()> {
         fs::create_dir_all(self.chunks_path(transfer_id)).await?;
@@ -85,7 +149,10 @@ impl TransferStorage {
     pub async fn save_metadata(&self, state: &TransferState) -> Result<()> {
         let path = self.metadata_path(&state.transfer_id);
         let json = serde_json::to_string_pretty(state)?;
-        fs::write(path, json).await?;
+        // Resume depends on this file; never leave it half written
+        tokio::task::spawn_blocking(move || pulsar_fs::write(path, json))
+            .await
+            .map_err(std::io::Error::other)??;
         Ok(())
     }
 
@@ -106,10 +173,14 @@ impl TransferStorage {
         self.metadata_path(transfer_id).exists()
     }
 
-    /// Save a chunk to disk
+    /// Save a chunk to disk. A chunk only gets its final name once fully
+    /// written, so one cut short by a crash is sent again on resume.
     pub async fn save_chunk(&self, transfer_id: &str, chunk_index: u32, data: &[u8]) -> Result<()> {
         let path = self.chunk_file_path(transfer_id, chunk_index);
-        fs::write(path, data).await?;
+        let mut partial = path.clone().into_os_string();
+        partial.push(PARTIAL_SUFFIX);
+        fs::write(&partial, data).await?;
+        fs::rename(&partial, path).await?;
         Ok(())
     }
 
@@ -132,8 +203,10 @@ impl TransferStorage {
         file_name: &str,
         total_chunks: u32,
     ) -> Result<PathBuf> {
-        let final_path = self.final_path(transfer_id).join(file_name);
-        let mut file = fs::File::create(&final_path).await?;
+        let final_dir = self.final_path(transfer_id);
+        let final_path = final_dir.join(file_name);
+        let partial_path = Self::partial_path(&final_dir, file_name, transfer_id);
+        let mut file = fs::File::create(&partial_path).await?;
 
         // Write chunks in order
         for chunk_index in 0..total_chunks {
@@ -142,9 +215,69 @@ impl TransferStorage {
         }
 
         file.flush().await?;
+        drop(file);
+        fs::rename(&partial_path, &final_path).await?;
         Ok(final_path)
     }
 
+    /// Move an assembled file into `directory`, keeping its name. Existing
+    /// files are never replaced.
+    pub async fn deliver(&self, assembled: &Path, directory: &Path, transfer_id: &str) -> Result<PathBuf> {
+        let file_name = assembled
+            .file_name()
+            .ok_or_else(|| TransferError::PermissionDenied(assembled.display().to_string()))?;
+        let destination = directory.join(file_name);
+        if fs::try_exists(&destination).await? {
+            return Err(TransferError::DestinationExists(destination));
+        }
+
+        // rename doesn't cross filesystems; copy under a partial name so a
+        // half-copied file never appears under the real one
+        if fs::rename(assembled, &destination).await.is_err() {
+            let partial = Self::partial_path(directory, &file_name.to_string_lossy(), transfer_id);
+            fs::copy(assembled, &partial).await?;
+            fs::rename(&partial, &destination).await?;
+            fs::remove_file(assembled).await?;
+        }
+        Ok(destination)
+    }
+
+    /// Move an assembled file that failed the policy checks into
+    /// `directory`, with a note beside it saying why
+    pub async fn quarantine(
+        &self,
+        assembled: &Path,
+        directory: &Path,
+        transfer_id: &str,
+        reason: &str,
+    ) -> Result<PathBuf> {
+        let file_name = assembled
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        // Prefixed with the transfer, so files of the same name never collide
+        let quarantined = directory.join(format!("{}-{}", transfer_id, file_name));
+        fs::create_dir_all(directory).await?;
+
+        // rename doesn't cross filesystems
+        if fs::rename(assembled, &quarantined).await.is_err() {
+            fs::copy(assembled, &quarantined).await?;
+            fs::remove_file(assembled).await?;
+        }
+
+        let note = serde_json::to_string_pretty(&serde_json::json!({
+            "transfer_id": transfer_id,
+            "file_name": file_name,
+            "reason": reason,
+            "quarantined_at": chrono::Utc::now().to_rfc3339(),
+        }))?;
+        let mut note_path = quarantined.clone().into_os_string();
+        note_path.push(".json");
+        fs::write(note_path, note).await?;
+
+        Ok(quarantined)
+    }
+
     /// Scan for received chunks
     pub async fn scan_received_chunks(&self, transfer_id: &str) -> Result<HashSet<u32>> {
         let chunks_dir = self.chunks_path(transfer_id);
@@ -191,12 +324,51 @@ impl TransferStorage {
         Ok(())
     }
 
-    /// Get disk space information
-    pub async fn get_disk_space(&self) -> Result<(u64, u64)> {
-        // This is a simplified version - in production, use statvfs or similar
-        // Returns (available_bytes, total_bytes)
-        // For now, return placeholder values
-        Ok((100 * 1024 * 1024 * 1024, 500 * 1024 * 1024 * 1024))
+    /// Remove partial files left by transfers interrupted by a crash or
+    /// restart, in storage and in the destinations recorded for them.
+    /// Only called on startup, when no transfer can still be writing.
+    pub async fn cleanup_partials(&self) -> Result<PartialCleanup> {
+        let mut cleanup = PartialCleanup::default();
+        if !self.base_path.exists() {
+            return Ok(cleanup);
+        }
+
+        let mut transfers = fs::read_dir(&self.base_path).await?;
+        while let Some(entry) = transfers.next_entry().await? {
+            if !entry.file_type().await?.is_dir() {
+                continue;
+            }
+            let transfer_id = entry.file_name().to_string_lossy().into_owned();
+
+            let mut candidates = Vec::new();
+            for dir in [self.chunks_path(&transfer_id), self.final_path(&transfer_id)] {
+                let Ok(mut entries) = fs::read_dir(&dir).await else {
+                    continue;
+                };
+                while let Some(entry) = entries.next_entry().await? {
+                    if entry.file_name().to_string_lossy().ends_with(PARTIAL_SUFFIX) {
+                        candidates.push(entry.path());
+                    }
+                }
+            }
+            if let Ok(state) = self.load_metadata(&transfer_id).await {
+                if let Some(directory) = &state.destination_dir {
+                    candidates.push(Self::partial_path(directory, &state.file_name, &transfer_id));
+                }
+            }
+
+            for path in candidates {
+                let Ok(metadata) = fs::metadata(&path).await else {
+                    continue;
+                };
+                fs::remove_file(&path).await?;
+                debug!("Removed orphaned partial {:?}", path);
+                cleanup.files += 1;
+                cleanup.bytes += metadata.len();
+            }
+        }
+
+        Ok(cleanup)
     }
 }
 
@@ -250,6 +422,81 @@ mod tests {
         assert!(!received.contains(&2));
     }
 
+    #[tokio::test]
+    async fn test_deliver_keeps_existing_files() {
+        let temp_dir = TempDir::new().unwrap();
+        let storage = TransferStorage::new(temp_dir.path().join("transfers"));
+        let destination = temp_dir.path().join("project");
+        fs::create_dir_all(&destination).await.unwrap();
+
+        storage.create_transfer("t1").await.unwrap();
+        storage.save_chunk("t1", 0, b"notes").await.unwrap();
+        let assembled = storage.assemble_file("t1", "notes.txt", 1).await.unwrap();
+
+        let delivered = storage.deliver(&assembled, &destination, "t1").await.unwrap();
+        assert_eq!(delivered, destination.join("notes.txt"));
+        assert_eq!(fs::read(&delivered).await.unwrap(), b"notes");
+        assert!(!assembled.exists());
+
+        storage.create_transfer("t2").await.unwrap();
+        storage.save_chunk("t2", 0, b"other").await.unwrap();
+        let assembled = storage.assemble_file("t2", "notes.txt", 1).await.unwrap();
+        assert!(matches!(
+            storage.deliver(&assembled, &destination, "t2").await,
+            Err(TransferError::DestinationExists(_))
+        ));
+        assert_eq!(fs::read(&delivered).await.unwrap(), b"notes");
+    }
+
+    #[tokio::test]
+    async fn test_cleanup_partials() {
+        let temp_dir = TempDir::new().unwrap();
+        let storage = TransferStorage::new(temp_dir.path().join("transfers"));
+        let destination = temp_dir.path().join("project");
+        fs::create_dir_all(&destination).await.unwrap();
+
+        storage.create_transfer("t1").await.unwrap();
+        storage.save_chunk("t1", 0, b"chunk0").await.unwrap();
+        let state = TransferState {
+            transfer_id: "t1".to_string(),
+            file_name: "notes.txt".to_string(),
+            file_size: 12,
+            chunk_size: 6,
+            total_chunks: 2,
+            received_chunks: [0].into(),
+            blake3_hash: String::new(),
+            started_at: String::new(),
+            last_activity: String::new(),
+            status: TransferStatus::Incomplete,
+            destination_dir: Some(destination.clone()),
+            batch_id: None,
+            sender: None,
+            directory: None,
+        };
+        storage.save_metadata(&state).await.unwrap();
+
+        // Left behind by a crash: a chunk cut short, an assembly and a copy
+        // into the destination
+        let chunk = storage.chunk_file_path("t1", 1);
+        fs::write(format!("{}{}", chunk.display(), PARTIAL_SUFFIX), b"chu").await.unwrap();
+        let assembly = TransferStorage::partial_path(&storage.final_path("t1"), "notes.txt", "t1");
+        fs::write(&assembly, b"chunk0").await.unwrap();
+        let copy = TransferStorage::partial_path(&destination, "notes.txt", "t1");
+        fs::write(&copy, b"chunk0chunk1").await.unwrap();
+        fs::write(destination.join("notes.txt"), b"kept").await.unwrap();
+
+        let cleanup = storage.cleanup_partials().await.unwrap();
+        assert_eq!(cleanup, PartialCleanup { files: 3, bytes: 21 });
+        assert!(!assembly.exists());
+        assert!(!copy.exists());
+        assert!(destination.join("notes.txt").exists());
+
+        // The truncated chunk is not taken as received
+        let received = storage.scan_received_chunks("t1").await.unwrap();
+        assert_eq!(received, [0].into());
+        assert_eq!(storage.load_chunk("t1", 0).await.unwrap(), b"chunk0");
+    }
+
     #[tokio::test]
     async fn test_find_missing_chunks() {
         let mut received = HashSet::new();
@@ -10,7 +10,7 @@ pub struct CreateSessionRequest {
     pub rows: u32,
     #[prost(enumeration = "SessionType", tag = "4")]
     pub r#type: i32,
-    #[prost(oneof = "create_session_request::Config", tags = "5, 6, 7")]
+    #[prost(oneof = "create_session_request::Config", tags = "5, 6, 7, 8")]
     pub config: ::core::option::Option<create_session_request::Config>,
 }
 /// Nested message and enum types in `CreateSessionRequest`.
@@ -23,6 +23,8 @@ pub mod create_session_request {
         Ssh(super::SshSessionConfig),
         #[prost(message, tag = "7")]
         Serial(super::SerialSessionConfig),
+        #[prost(message, tag = "8")]
+        Tail(super::TailSessionConfig),
     }
 }
 #[derive(Clone, PartialEq, ::prost::Message)]
@@ -59,6 +61,23 @@ pub struct SerialSessionConfig {
     pub baud_rate: u32,
 }
 #[derive(Clone, PartialEq, ::prost::Message)]
+pub struct TailSessionConfig {
+    #[prost(message, repeated, tag = "1")]
+    pub sources: ::prost::alloc::vec::Vec<TailSource>,
+}
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct TailSource {
+    #[prost(string, tag = "1")]
+    pub path: ::prost::alloc::string::String,
+    /// Empty for a local file
+    #[prost(string, tag = "2")]
+    pub host: ::prost::alloc::string::String,
+    #[prost(uint32, tag = "3")]
+    pub port: u32,
+    #[prost(string, tag = "4")]
+    pub label: ::prost::alloc::string::String,
+}
+#[derive(Clone, PartialEq, ::prost::Message)]
 pub struct CreateSessionResponse {
     #[prost(string, tag = "1")]
     pub session_id: ::prost::alloc::string::String,
@@ -98,6 +117,9 @@ pub struct SessionInfo {
     pub cols: u32,
     #[prost(uint32, tag = "9")]
     pub rows: u32,
+    /// Shell's working directory, empty if unknown
+    #[prost(string, tag = "10")]
+    pub cwd: ::prost::alloc::string::String,
 }
 #[derive(Clone, PartialEq, ::prost::Message)]
 pub struct ListSessionsResponse {
@@ -139,6 +161,9 @@ pub struct AttachSessionRequest {
     pub session_id: ::prost::alloc::string::String,
     #[prost(string, tag = "2")]
     pub client_id: ::prost::alloc::string::String,
+    /// Receive output only; the daemon discards the client's input
+    #[prost(bool, tag = "3")]
+    pub read_only: bool,
 }
 #[derive(Clone, PartialEq, ::prost::Message)]
 pub struct AttachSessionResponse {
@@ -270,6 +295,7 @@ pub enum SessionType {
     Local = 1,
     Ssh = 2,
     Serial = 3,
+    Tail = 4,
 }
 impl SessionType {
     /// String value of the enum field names used in the ProtoBuf definition.
@@ -282,6 +308,7 @@ impl SessionType {
             Self::Local => "SESSION_TYPE_LOCAL",
             Self::Ssh => "SESSION_TYPE_SSH",
             Self::Serial => "SESSION_TYPE_SERIAL",
+            Self::Tail => "SESSION_TYPE_TAIL",
         }
     }
     /// Creates an enum from field names used in the ProtoBuf definition.
@@ -291,6 +318,7 @@ impl SessionType {
             "SESSION_TYPE_LOCAL" => Some(Self::Local),
             "SESSION_TYPE_SSH" => Some(Self::Ssh),
             "SESSION_TYPE_SERIAL" => Some(Self::Serial),
+            "SESSION_TYPE_TAIL" => Some(Self::Tail),
             _ => None,
         }
     }
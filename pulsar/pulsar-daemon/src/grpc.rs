@@ -11,6 +11,8 @@ use tracing::{debug, error, info};
 use uuid::Uuid;
 
 use crate::session_manager::{SessionManager, SessionState, SessionType};
+use crate::sizing::{TerminalSize, ANONYMOUS_CLIENT};
+use crate::tail::TailSource;
 
 // Include generated proto code
 pub mod pb {
@@ -36,6 +38,7 @@ impl TerminalServiceImpl {
             SessionType::Local => pb::SessionType::Local as i32,
             SessionType::Ssh { .. } => pb::SessionType::Ssh as i32,
             SessionType::Serial { .. } => pb::SessionType::Serial as i32,
+            SessionType::Tail { .. } => pb::SessionType::Tail as i32,
         }
     }
 
@@ -70,6 +73,18 @@ impl TerminalService for TerminalServiceImpl {
             Some(create_session_request::Config::Serial(serial_config)) => SessionType::Serial {
                 device: serial_config.device,
             },
+            Some(create_session_request::Config::Tail(tail_config)) => SessionType::Tail {
+                sources: tail_config
+                    .sources
+                    .into_iter()
+                    .map(|source| TailSource {
+                        path: source.path,
+                        host: (!source.host.is_empty()).then_some(source.host),
+                        port: (source.port != 0).then_some(source.port as u16),
+                        label: (!source.label.is_empty()).then_some(source.label),
+                    })
+                    .collect(),
+            },
             None => SessionType::Local, // Default to local
         };
 
@@ -118,6 +133,7 @@ impl TerminalService for TerminalServiceImpl {
                 num_clients: s.num_clients as u32,
                 cols: 0, // TODO: Get from session
                 rows: 0, // TODO: Get from session
+                cwd: s.cwd.as_ref().map(|d| d.path.clone()).unwrap_or_default(),
             })
             .collect();
 
@@ -154,6 +170,13 @@ impl TerminalService for TerminalServiceImpl {
                         num_clients: num_clients as u32,
                         cols: 0,
                         rows: 0,
+                        cwd: session
+                            .cwd
+                            .read()
+                            .await
+                            .as_ref()
+                            .map(|d| d.path.clone())
+                            .unwrap_or_default(),
                     }),
                     success: true,
                     error_message: String::new(),
@@ -201,9 +224,16 @@ impl TerminalService for TerminalServiceImpl {
         let client_id = Uuid::parse_str(&req.client_id)
             .map_err(|e| Status::invalid_argument(format!("Invalid client ID: {}", e)))?;
 
-        debug!("gRPC AttachSession: session={}, client={}", session_id, client_id);
+        debug!(
+            "gRPC AttachSession: session={}, client={}, read_only={}",
+            session_id, client_id, req.read_only
+        );
 
-        match self.session_manager.attach_client(session_id, client_id).await {
+        match self
+            .session_manager
+            .attach_client(session_id, client_id, req.read_only)
+            .await
+        {
             Ok(()) => Ok(Response::new(AttachSessionResponse {
                 success: true,
                 error_message: String::new(),
@@ -304,8 +334,7 @@ impl TerminalService for TerminalServiceImpl {
 
                     match self.session_manager.get_session(session_id).await {
                         Ok(session) => {
-                            let mut terminal = session.terminal_session.write().await;
-                            match terminal.write(&input.data) {
+                            match session.write_input(&input.data).await {
                                 Ok(n) => bytes_written += n as u64,
                                 Err(e) => {
                                     return Ok(Response::new(StreamInputResponse {
@@ -368,9 +397,10 @@ impl TerminalService for TerminalServiceImpl {
 
         match self.session_manager.get_session(session_id).await {
             Ok(session) => {
-                let mut terminal = session.terminal_session.write().await;
-                match terminal.resize(req.cols as u16, req.rows as u16) {
-                    Ok(()) => Ok(Response::new(ResizeTerminalResponse {
+                // gRPC callers don't say which client they are
+                let size = TerminalSize::new(req.cols as u16, req.rows as u16);
+                match session.resize(ANONYMOUS_CLIENT, size).await {
+                    Ok(_) => Ok(Response::new(ResizeTerminalResponse {
                         success: true,
                         error_message: String::new(),
                     })),
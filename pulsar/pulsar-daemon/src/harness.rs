@@ -0,0 +1,204 @@
+//! Headless PTY integration tests
+//!
+//! Starts real sessions through the session manager, runs scripted programs
+//! in them, and renders what the daemon broadcasts with the screen buffer
+//! the frontend draws from, then checks the screen. The scripts are a
+//! subset of vttest (cursor addressing, erasing, scroll regions) plus a vim
+//! smoke test, which passes without running when vim is not installed.
+//!
+//! PTY reads block a runtime thread, so these tests use the multi-threaded
+//! runtime.
+
+use std::time::Duration;
+use terminal_core::{PtyConfig, SessionConfig};
+use terminal_wasm::{AnsiParser, TerminalBuffer};
+use tokio::sync::broadcast::{self, error::RecvError};
+use tokio::time::{timeout_at, Instant};
+use uuid::Uuid;
+
+use crate::session_manager::{SessionManager, SessionType};
+
+const COLS: u16 = 80;
+const ROWS: u16 = 24;
+
+/// How long a screen may take to appear
+const WAIT: Duration = Duration::from_secs(10);
+
+/// A session with its output rendered to a screen
+struct Harness {
+    manager: SessionManager,
+    id: Uuid,
+    output: broadcast::Receiver<Vec<u8>>,
+    buffer: TerminalBuffer,
+    parser: AnsiParser,
+    /// Start of a character split across reads
+    partial: Vec<u8>,
+}
+
+impl Harness {
+    /// A `/bin/sh` session with echo and the prompt turned off
+    async fn start() -> Self {
+        let mut config = SessionConfig::new("harness".to_string());
+        config.pty_config = PtyConfig {
+            cols: COLS,
+            rows: ROWS,
+            shell: Some("/bin/sh".to_string()),
+        };
+        let manager = SessionManager::new();
+        let id = manager
+            .create_session("harness".to_string(), SessionType::Local, config)
+            .await
+            .unwrap();
+        let output = manager.get_session(id).await.unwrap().output_broadcast.subscribe();
+
+        let mut harness = Self {
+            manager,
+            id,
+            output,
+            buffer: TerminalBuffer::new(COLS, ROWS),
+            parser: AnsiParser::new(),
+            partial: Vec::new(),
+        };
+        harness.run("stty -echo; PS1=''; echo ready").await;
+        harness.wait_for(|lines| lines.iter().any(|line| line == "ready")).await;
+        harness
+    }
+
+    /// Type a command line into the shell
+    async fn run(&self, command: &str) {
+        self.send(format!("{}\n", command).as_bytes()).await;
+    }
+
+    async fn send(&self, input: &[u8]) {
+        let session = self.manager.get_session(self.id).await.unwrap();
+        session.write_input(input).await.unwrap();
+    }
+
+    /// Screen lines with trailing blanks removed
+    fn lines(&self) -> Vec<String> {
+        self.buffer
+            .get_screen_text()
+            .lines()
+            .map(|line| line.trim_end().to_string())
+            .collect()
+    }
+
+    /// Render output until the screen satisfies `ready`, failing with the
+    /// screen as it was if that takes too long
+    async fn wait_for(&mut self, ready: impl Fn(&[String]) -> bool) -> Vec<String> {
+        let deadline = Instant::now() + WAIT;
+        loop {
+            let lines = self.lines();
+            if ready(&lines) {
+                return lines;
+            }
+            let data = match timeout_at(deadline, self.output.recv()).await {
+                Ok(Ok(data)) => data,
+                Ok(Err(RecvError::Lagged(_))) => continue,
+                Ok(Err(RecvError::Closed)) | Err(_) => {
+                    panic!("Screen not ready:\n{}", lines.join("\n"))
+                }
+            };
+            self.render(&data);
+        }
+    }
+
+    /// Wait for a script that ends by printing `done`
+    async fn wait_for_done(&mut self) -> Vec<String> {
+        self.wait_for(|lines| lines.iter().any(|line| line.ends_with("done"))).await
+    }
+
+    fn render(&mut self, data: &[u8]) {
+        self.partial.extend_from_slice(data);
+        let valid = match std::str::from_utf8(&self.partial) {
+            Ok(text) => text.len(),
+            Err(e) => e.valid_up_to(),
+        };
+        let text = String::from_utf8(self.partial.drain(..valid).collect()).unwrap();
+        self.parser.parse(&text, &mut self.buffer);
+    }
+
+    async fn stop(self) {
+        self.manager.terminate_session(self.id).await.ok();
+    }
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_cursor_addressing() {
+    let mut harness = Harness::start().await;
+    harness
+        .run(r"printf '\033[2J\033[1;1H+\033[1;10H+\033[5;1H+\033[5;10H+\033[3;5HX\033[2A\033[1DY\033[24;1Hdone'")
+        .await;
+
+    let lines = harness.wait_for_done().await;
+    assert_eq!(lines[0], "+   Y    +");
+    assert_eq!(lines[2], "    X");
+    assert_eq!(lines[4], "+        +");
+    harness.stop().await;
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_erase_in_line() {
+    let mut harness = Harness::start().await;
+    harness
+        .run(r"printf '\033[2J\033[Habcdefghij\033[1;5H\033[K\033[2;1Hklmnop\033[2;3H\033[1K\033[3;1Hdone'")
+        .await;
+
+    let lines = harness.wait_for_done().await;
+    assert_eq!(lines[0], "abcd");
+    assert_eq!(lines[1], "   nop");
+    harness.stop().await;
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_scroll_region() {
+    let mut harness = Harness::start().await;
+    // Output scrolls between a fixed header and footer, as in a pager
+    harness
+        .run(r"printf '\033[2J\033[1;1Htop\033[24;1Hbottom\033[2;23r\033[23;1H'; i=1; while [ $i -le 30 ]; do echo line$i; i=$((i+1)); done; printf '\033[r\033[24;10Hdone'")
+        .await;
+
+    let lines = harness.wait_for_done().await;
+    assert_eq!(lines[0], "top");
+    assert_eq!(lines[1], "line10");
+    assert_eq!(lines[21], "line30");
+    assert_eq!(lines[22], "");
+    assert_eq!(lines[23], "bottom   done");
+    harness.stop().await;
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_vim_smoke() {
+    let installed = std::process::Command::new("vim")
+        .arg("--version")
+        .output()
+        .is_ok_and(|output| output.status.success());
+    if !installed {
+        eprintln!("vim not installed, skipping");
+        return;
+    }
+
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("notes.txt");
+    std::fs::write(&path, "first line\nsecond line\n").unwrap();
+
+    let mut harness = Harness::start().await;
+    harness
+        .run(&format!("vim -u NONE -N -i NONE -n {}", path.display()))
+        .await;
+    let lines = harness
+        .wait_for(|lines| lines.first().is_some_and(|line| line == "first line"))
+        .await;
+    assert_eq!(lines[1], "second line");
+    assert_eq!(lines[2], "~");
+
+    // Insert a line at the top, save and quit
+    harness.send(b"Ohello\x1b:wq\r").await;
+    let deadline = Instant::now() + WAIT;
+    let expected = "hello\nfirst line\nsecond line\n";
+    while std::fs::read_to_string(&path).unwrap() != expected {
+        assert!(Instant::now() < deadline, "vim did not save the file");
+        tokio::time::sleep(Duration::from_millis(50)).await;
+    }
+    harness.stop().await;
+}
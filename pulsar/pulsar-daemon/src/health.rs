@@ -0,0 +1,289 @@
+//! Daemon health and readiness
+//!
+//! `daemon_health` reports each subsystem as healthy, degraded or
+//! unhealthy, and the daemon as a whole as its worst subsystem. The servers
+//! report their own state as they start and fail; the database and the
+//! disk holding the data directory are checked on each request.
+//!
+//! Supervisors that cannot speak IPC can enable an HTTP probe instead:
+//! - `GET /livez` answers 200 while the process is serving
+//! - `GET /readyz` answers 200 with the report as JSON, or 503 once the
+//!   daemon is unhealthy or shutting down
+//!
+//! A plain TCP connect to the probe port works as a liveness check too.
+
+use anyhow::{Context, Result};
+use axum::{extract::State, http::StatusCode, routing::get, Json, Router};
+use serde::{Deserialize, Serialize};
+use sqlx::SqlitePool;
+use std::collections::BTreeMap;
+use std::fmt::Display;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use sysinfo::Disks;
+use tracing::info;
+
+use crate::shutdown::Shutdown;
+
+/// Database queries slower than this leave the database degraded
+const SLOW_QUERY: Duration = Duration::from_millis(500);
+
+/// Database queries are given up on after this long
+const QUERY_TIMEOUT: Duration = Duration::from_secs(2);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum HealthLevel {
+    Healthy,
+    /// Working, but needs attention (slow database, low disk space)
+    Degraded,
+    /// Not doing its job
+    Unhealthy,
+}
+
+/// State of one subsystem
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SubsystemHealth {
+    pub level: HealthLevel,
+    pub detail: String,
+}
+
+impl SubsystemHealth {
+    fn new(level: HealthLevel, detail: impl Into<String>) -> Self {
+        Self {
+            level,
+            detail: detail.into(),
+        }
+    }
+}
+
+/// Response for daemon_health
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HealthReport {
+    /// The worst level among the subsystems
+    pub level: HealthLevel,
+    /// Ready to take new work: not unhealthy and not shutting down
+    pub ready: bool,
+    pub subsystems: BTreeMap<String, SubsystemHealth>,
+    /// Seconds until the daemon stops, once it is shutting down
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub shutting_down_in: Option<u64>,
+}
+
+/// Health probe and disk space thresholds
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct HealthConfig {
+    /// Serve `/livez` and `/readyz` on this address, e.g. `127.0.0.1:9090`;
+    /// unset leaves the probe off
+    pub probe_addr: Option<SocketAddr>,
+    /// Megabytes free below which the disk is degraded
+    pub disk_degraded_mb: u64,
+    /// Megabytes free below which the disk is unhealthy
+    pub disk_unhealthy_mb: u64,
+}
+
+impl Default for HealthConfig {
+    fn default() -> Self {
+        Self {
+            probe_addr: None,
+            disk_degraded_mb: 1024,
+            disk_unhealthy_mb: 100,
+        }
+    }
+}
+
+/// Subsystem states, shared with the servers
+pub struct Health {
+    servers: Mutex<BTreeMap<&'static str, SubsystemHealth>>,
+    database: Option<SqlitePool>,
+    data_dir: PathBuf,
+    config: HealthConfig,
+    shutdown: Arc<Shutdown>,
+}
+
+impl Health {
+    pub fn new(data_dir: impl Into<PathBuf>, config: HealthConfig, shutdown: Arc<Shutdown>) -> Self {
+        Self {
+            servers: Mutex::new(BTreeMap::new()),
+            database: None,
+            data_dir: data_dir.into(),
+            config,
+            shutdown,
+        }
+    }
+
+    /// Check database connectivity on each report
+    pub fn with_database(mut self, pool: SqlitePool) -> Self {
+        self.database = Some(pool);
+        self
+    }
+
+    /// Record a server as serving
+    pub fn server_running(&self, name: &'static str) {
+        self.set_server(name, SubsystemHealth::new(HealthLevel::Healthy, "running"));
+    }
+
+    /// Record a server as stopped by an error
+    pub fn server_failed(&self, name: &'static str, error: impl Display) {
+        self.set_server(name, SubsystemHealth::new(HealthLevel::Unhealthy, format!("{:#}", error)));
+    }
+
+    fn set_server(&self, name: &'static str, health: SubsystemHealth) {
+        self.servers
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .insert(name, health);
+    }
+
+    /// Check every subsystem
+    pub async fn report(&self) -> HealthReport {
+        let mut subsystems: BTreeMap<String, SubsystemHealth> = self
+            .servers
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .iter()
+            .map(|(name, health)| (name.to_string(), health.clone()))
+            .collect();
+        if let Some(pool) = &self.database {
+            subsystems.insert("database".to_string(), check_database(pool).await);
+        }
+        subsystems.insert("disk".to_string(), self.check_disk());
+
+        let level = subsystems
+            .values()
+            .map(|health| health.level)
+            .max()
+            .unwrap_or(HealthLevel::Healthy);
+        let shutting_down_in = self.shutdown.notice().map(|notice| notice.seconds_left);
+
+        HealthReport {
+            level,
+            ready: level != HealthLevel::Unhealthy && shutting_down_in.is_none(),
+            subsystems,
+            shutting_down_in,
+        }
+    }
+
+    fn check_disk(&self) -> SubsystemHealth {
+        let Some(available) = available_space(&self.data_dir) else {
+            return SubsystemHealth::new(
+                HealthLevel::Degraded,
+                format!("Free space for {} is unknown", self.data_dir.display()),
+            );
+        };
+
+        disk_health(available / (1024 * 1024), &self.config)
+    }
+}
+
+async fn check_database(pool: &SqlitePool) -> SubsystemHealth {
+    let started = Instant::now();
+    let query = sqlx::query("SELECT 1").execute(pool);
+    match tokio::time::timeout(QUERY_TIMEOUT, query).await {
+        Ok(Ok(_)) if started.elapsed() > SLOW_QUERY => SubsystemHealth::new(
+            HealthLevel::Degraded,
+            format!("Slow to respond ({} ms)", started.elapsed().as_millis()),
+        ),
+        Ok(Ok(_)) => SubsystemHealth::new(HealthLevel::Healthy, "connected"),
+        Ok(Err(e)) => SubsystemHealth::new(HealthLevel::Unhealthy, format!("Query failed: {}", e)),
+        Err(_) => SubsystemHealth::new(
+            HealthLevel::Unhealthy,
+            format!("No response within {}s", QUERY_TIMEOUT.as_secs()),
+        ),
+    }
+}
+
+fn disk_health(available_mb: u64, config: &HealthConfig) -> SubsystemHealth {
+    let level = if available_mb < config.disk_unhealthy_mb {
+        HealthLevel::Unhealthy
+    } else if available_mb < config.disk_degraded_mb {
+        HealthLevel::Degraded
+    } else {
+        HealthLevel::Healthy
+    };
+    SubsystemHealth::new(level, format!("{} MB free", available_mb))
+}
+
+/// Bytes available on the disk mounted closest to `path`
+pub(crate) fn available_space(path: &Path) -> Option<u64> {
+    // The directory may not exist yet; its nearest existing ancestor is on
+    // the same disk
+    let path = path.ancestors().find_map(|dir| dir.canonicalize().ok())?;
+    Disks::new_with_refreshed_list()
+        .iter()
+        .filter(|disk| path.starts_with(disk.mount_point()))
+        .max_by_key(|disk| disk.mount_point().as_os_str().len())
+        .map(|disk| disk.available_space())
+}
+
+/// Serve `/livez` and `/readyz` on `addr`
+pub async fn start_probe(health: Arc<Health>, addr: SocketAddr) -> Result<()> {
+    let app = Router::new()
+        .route("/livez", get(|| async { "ok" }))
+        .route("/readyz", get(readyz))
+        .with_state(health);
+
+    let listener = tokio::net::TcpListener::bind(addr)
+        .await
+        .with_context(|| format!("Failed to bind health probe to {}", addr))?;
+
+    info!("Health probe listening on {}", addr);
+
+    axum::serve(listener, app)
+        .await
+        .context("Health probe error")?;
+
+    Ok(())
+}
+
+async fn readyz(State(health): State<Arc<Health>>) -> (StatusCode, Json<HealthReport>) {
+    let report = health.report().await;
+    let status = if report.ready {
+        StatusCode::OK
+    } else {
+        StatusCode::SERVICE_UNAVAILABLE
+    };
+    (status, Json(report))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_worst_subsystem_decides() {
+        let shutdown = Arc::new(Shutdown::new());
+        let data_dir = tempfile::tempdir().unwrap();
+        let health = Health::new(data_dir.path(), HealthConfig::default(), Arc::clone(&shutdown));
+
+        health.server_running("ipc");
+        health.server_running("grpc");
+        health.server_failed("websocket", anyhow::anyhow!("address in use"));
+
+        let report = health.report().await;
+        assert_eq!(report.level, HealthLevel::Unhealthy);
+        assert!(!report.ready);
+        assert_eq!(report.subsystems["ipc"].level, HealthLevel::Healthy);
+        assert_eq!(report.subsystems["websocket"].detail, "address in use");
+        assert!(report.subsystems.contains_key("disk"));
+
+        // Healthy again, but not ready once shutting down
+        health.server_running("websocket");
+        shutdown.drain(Duration::ZERO).await;
+        let report = health.report().await;
+        assert_ne!(report.level, HealthLevel::Unhealthy);
+        assert!(!report.ready);
+        assert_eq!(report.shutting_down_in, Some(0));
+    }
+
+    #[test]
+    fn test_disk_thresholds() {
+        let config = HealthConfig::default();
+        assert_eq!(disk_health(50_000, &config).level, HealthLevel::Healthy);
+        assert_eq!(disk_health(500, &config).level, HealthLevel::Degraded);
+        assert_eq!(disk_health(10, &config).level, HealthLevel::Unhealthy);
+    }
+}
@@ -1,65 +1,202 @@
 //! IPC server for communication with desktop client
 //!
-//! Implements Unix socket server with JSON-RPC style protocol
+//! Implements a JSON-RPC style protocol over a Unix socket (a named pipe on
+//! Windows)
 
-use anyhow::{anyhow, Context, Result};
+use anyhow::{anyhow, Result};
 use base64::{Engine as _, engine::general_purpose};
-use std::path::Path;
+use sqlx::SqlitePool;
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
-use std::time::SystemTime;
-use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
-use tokio::net::{UnixListener, UnixStream};
+use std::time::{Duration, SystemTime};
+use tokio::io::{AsyncBufReadExt, AsyncWrite, AsyncWriteExt, BufReader};
 use tokio::sync::RwLock;
 use tracing::{debug, error, info, warn};
 
 use crate::protocol::{
-    error_codes, AttachSessionParams, CreateSessionParams, CreateSessionResult,
-    DetachSessionParams, ListSessionsResult, ReceiveOutputParams, Request, Response,
-    ResizeTerminalParams, SendInputParams, StatusResult, TerminateSessionParams,
+    error_codes, AddBookmarkParams, ApplyConfigParams, AttachSessionParams, CommandTimelineParams,
+    CreateAttachTokenParams, CreateSessionParams, DiscoverHostsParams, ImportHostsParams, CreateSessionResult, DatabaseBackupResult, FocusSessionParams,
+    DetachSessionParams, ExportTranscriptParams, JumpToBookmarkParams, ListBookmarksParams, ListSessionsResult,
+    BandwidthLimitResult, LogLevelResult, PasteParams, ReceiveOutputParams, RemoveBookmarkParams, Request, Response,
+    RerunCommandParams, ResizeTerminalParams, SearchScrollbackParams, SendInputParams, SendKeyParams, SessionTimelineParams,
+    SessionStatsParams, SetBandwidthLimitParams, SetLogLevelParams, SetSizePolicyParams, SshCertificateParams, StatusResult,
+    TakeLongCommandsParams, TerminateSessionParams,
+    WebTransportCertsResult,
 };
-use crate::session_manager::{SessionManager, SessionType};
+use crate::attach_token::AttachTokens;
+use crate::cert_manager::CertManager;
+use crate::discovery;
+use crate::health::Health;
+use crate::relay::Relay;
+use crate::resources::ResourceMonitor;
+use crate::ssh_ca::SshCa;
+use crate::team_directory::TeamDirectory;
+use crate::workspace::{Declaration, WorkspaceService};
+use crate::ipc_listener::{IpcListener, IpcStream};
+use crate::theme::{ThemeStore, ThemeUpdate};
+use crate::session_manager::{RerunOutcome, SessionManager, SessionType, READ_ONLY_NOTICE};
+use crate::shutdown::{Shutdown, ShutdownNotice};
+use crate::sizing::{TerminalSize, ANONYMOUS_CLIENT};
 use terminal_core::SessionConfig;
+use tft_transports::{BandwidthLimit, Throttle};
 
-/// IPC server managing Unix socket communication
+/// IPC server managing local socket communication
 pub struct IpcServer {
-    listener: UnixListener,
+    listener: IpcListener,
     session_manager: Arc<SessionManager>,
+    services: IpcServices,
     start_time: SystemTime,
     shutdown: Arc<RwLock<bool>>,
 }
 
+/// Optional daemon services exposed over IPC
+#[derive(Clone, Default)]
+struct IpcServices {
+    cert_manager: Option<Arc<CertManager>>,
+    attach_tokens: Option<Arc<AttachTokens>>,
+    theme_store: Option<Arc<ThemeStore>>,
+    database: Option<DatabaseHandle>,
+    shutdown: Option<Arc<Shutdown>>,
+    health: Option<Arc<Health>>,
+    relay: Option<Arc<Relay>>,
+    resources: Option<Arc<ResourceMonitor>>,
+    ssh_ca: Option<Arc<SshCa>>,
+    hosts: Option<HostDirectory>,
+    throttle: Option<Throttle>,
+}
+
+/// Saved hosts, the team directory merged into them, and the declaration
+/// that provisions them
+#[derive(Clone)]
+struct HostDirectory {
+    workspaces: Arc<WorkspaceService>,
+    team: Option<Arc<TeamDirectory>>,
+    /// `pulsar.yaml` applied when apply_config names no file
+    declaration: PathBuf,
+}
+
+/// Workspace database and where its backups go
+#[derive(Clone)]
+struct DatabaseHandle {
+    pool: SqlitePool,
+    backup_dir: PathBuf,
+}
+
+/// Methods refused once the daemon is shutting down
+const STARTS_WORK: &[&str] = &[
+    "create_session",
+    "attach_session",
+    "create_attach_token",
+    "db_backup",
+    "db_vacuum",
+];
+
+/// Number of workspace database backups kept by `db_backup`
+const DB_BACKUPS_KEPT: usize = 7;
+
 impl IpcServer {
     /// Create a new IPC server
     pub async fn new<P: AsRef<Path>>(
         socket_path: P,
         session_manager: Arc<SessionManager>,
     ) -> Result<Self> {
-        let socket_path = socket_path.as_ref();
-
-        // Remove existing socket file if it exists
-        if socket_path.exists() {
-            std::fs::remove_file(socket_path)
-                .with_context(|| format!("Failed to remove existing socket: {:?}", socket_path))?;
-        }
-
-        // Create parent directory if needed
-        if let Some(parent) = socket_path.parent() {
-            std::fs::create_dir_all(parent)
-                .with_context(|| format!("Failed to create socket directory: {:?}", parent))?;
-        }
-
-        // Bind Unix socket
-        let listener = UnixListener::bind(socket_path)
-            .with_context(|| format!("Failed to bind socket: {:?}", socket_path))?;
+        let listener = IpcListener::bind(socket_path.as_ref())?;
+        Ok(Self::from_bound(listener, session_manager))
+    }
 
-        info!("IPC server listening on {:?}", socket_path);
+    /// Serve a socket bound by someone else (systemd socket activation)
+    #[cfg(unix)]
+    pub fn from_listener(
+        listener: std::os::unix::net::UnixListener,
+        session_manager: Arc<SessionManager>,
+    ) -> Result<Self> {
+        Ok(Self::from_bound(IpcListener::from_std(listener)?, session_manager))
+    }
 
-        Ok(Self {
+    fn from_bound(listener: IpcListener, session_manager: Arc<SessionManager>) -> Self {
+        Self {
             listener,
             session_manager,
+            services: IpcServices::default(),
             start_time: SystemTime::now(),
             shutdown: Arc::new(RwLock::new(false)),
-        })
+        }
+    }
+
+    /// Publish WebTransport certificate hashes to clients
+    pub fn with_cert_manager(mut self, cert_manager: Arc<CertManager>) -> Self {
+        self.services.cert_manager = Some(cert_manager);
+        self
+    }
+
+    /// Allow clients to issue session attach tokens
+    pub fn with_attach_tokens(mut self, attach_tokens: Arc<AttachTokens>) -> Self {
+        self.services.attach_tokens = Some(attach_tokens);
+        self
+    }
+
+    /// Share terminal appearance with clients
+    pub fn with_theme_store(mut self, theme_store: Arc<ThemeStore>) -> Self {
+        self.services.theme_store = Some(theme_store);
+        self
+    }
+
+    /// Expose health, backup and vacuum commands for the workspace database
+    pub fn with_database(mut self, pool: SqlitePool, backup_dir: PathBuf) -> Self {
+        self.services.database = Some(DatabaseHandle { pool, backup_dir });
+        self
+    }
+
+    /// Refuse new work and report the countdown once shutdown starts
+    pub fn with_shutdown(mut self, shutdown: Arc<Shutdown>) -> Self {
+        self.services.shutdown = Some(shutdown);
+        self
+    }
+
+    /// Report subsystem health to clients
+    pub fn with_health(mut self, health: Arc<Health>) -> Self {
+        self.services.health = Some(health);
+        self
+    }
+
+    /// Report relayed sessions and bandwidth to clients
+    pub fn with_relay(mut self, relay: Arc<Relay>) -> Self {
+        self.services.relay = Some(relay);
+        self
+    }
+
+    /// Report sessions' CPU and memory use
+    pub fn with_resources(mut self, resources: Arc<ResourceMonitor>) -> Self {
+        self.services.resources = Some(resources);
+        self
+    }
+
+    /// Hand out short-lived SSH certificates signed by the organization's CA
+    pub fn with_ssh_ca(mut self, ssh_ca: Arc<SshCa>) -> Self {
+        self.services.ssh_ca = Some(ssh_ca);
+        self
+    }
+
+    /// Let clients change the file transfer bandwidth limit
+    pub fn with_throttle(mut self, throttle: Throttle) -> Self {
+        self.services.throttle = Some(throttle);
+        self
+    }
+
+    /// List saved hosts, merged with the team directory when there is one,
+    /// and apply the `declaration` file to workspaces on request
+    pub fn with_host_directory(
+        mut self,
+        workspaces: Arc<WorkspaceService>,
+        team: Option<Arc<TeamDirectory>>,
+        declaration: PathBuf,
+    ) -> Self {
+        self.services.hosts = Some(HostDirectory {
+            workspaces,
+            team,
+            declaration,
+        });
+        self
     }
 
     /// Run the IPC server (accepts connections)
@@ -75,15 +212,16 @@ impl IpcServer {
 
             // Accept connection
             match self.listener.accept().await {
-                Ok((stream, _addr)) => {
+                Ok(stream) => {
                     debug!("New IPC client connected");
 
                     let session_manager = Arc::clone(&self.session_manager);
+                    let services = self.services.clone();
                     let start_time = self.start_time;
 
                     // Spawn task to handle this client
                     tokio::spawn(async move {
-                        if let Err(e) = Self::handle_client(stream, session_manager, start_time).await {
+                        if let Err(e) = Self::handle_client(stream, session_manager, services, start_time).await {
                             error!("Client handler error: {}", e);
                         }
                     });
@@ -104,11 +242,12 @@ impl IpcServer {
 
     /// Handle a single client connection
     async fn handle_client(
-        stream: UnixStream,
+        stream: Box<dyn IpcStream>,
         session_manager: Arc<SessionManager>,
+        services: IpcServices,
         start_time: SystemTime,
     ) -> Result<()> {
-        let (reader, mut writer) = stream.into_split();
+        let (reader, mut writer) = tokio::io::split(stream);
         let mut reader = BufReader::new(reader);
         let mut line = String::new();
 
@@ -144,6 +283,7 @@ impl IpcServer {
                     let response = Self::handle_request(
                         request,
                         Arc::clone(&session_manager),
+                        &services,
                         start_time,
                     ).await;
 
@@ -164,8 +304,20 @@ impl IpcServer {
     async fn handle_request(
         request: Request,
         session_manager: Arc<SessionManager>,
+        services: &IpcServices,
         start_time: SystemTime,
     ) -> Response {
+        let notice = services.shutdown.as_ref().and_then(|shutdown| shutdown.notice());
+        if let Some(notice) = notice {
+            if STARTS_WORK.contains(&request.method.as_str()) {
+                return Response::error(
+                    request.id,
+                    error_codes::SHUTTING_DOWN,
+                    format!("Daemon is shutting down in {}s", notice.seconds_left),
+                );
+            }
+        }
+
         match request.method.as_str() {
             "create_session" => {
                 Self::handle_create_session(request, session_manager).await
@@ -174,7 +326,7 @@ impl IpcServer {
                 Self::handle_list_sessions(request, session_manager).await
             }
             "attach_session" => {
-                Self::handle_attach_session(request, session_manager).await
+                Self::handle_attach_session(request, session_manager, services.theme_store.clone()).await
             }
             "detach_session" => {
                 Self::handle_detach_session(request, session_manager).await
@@ -185,15 +337,85 @@ impl IpcServer {
             "resize_terminal" => {
                 Self::handle_resize_terminal(request, session_manager).await
             }
+            "set_size_policy" => {
+                Self::handle_set_size_policy(request, session_manager).await
+            }
+            "rerun_command" => Self::handle_rerun_command(request, session_manager).await,
+            "session_stats" => {
+                Self::handle_session_stats(request, session_manager, services.resources.clone()).await
+            }
             "send_input" => {
                 Self::handle_send_input(request, session_manager).await
             }
+            "send_key" => {
+                Self::handle_send_key(request, session_manager).await
+            }
+            "paste" => {
+                Self::handle_paste(request, session_manager).await
+            }
+            "focus_session" => {
+                Self::handle_focus_session(request, session_manager).await
+            }
+            "search_scrollback" => {
+                Self::handle_search_scrollback(request, session_manager).await
+            }
+            "export_transcript" => {
+                Self::handle_export_transcript(request, session_manager).await
+            }
+            "add_bookmark" => {
+                Self::handle_add_bookmark(request, session_manager).await
+            }
+            "list_bookmarks" => {
+                Self::handle_list_bookmarks(request, session_manager).await
+            }
+            "remove_bookmark" => {
+                Self::handle_remove_bookmark(request, session_manager).await
+            }
+            "jump_to_bookmark" => {
+                Self::handle_jump_to_bookmark(request, session_manager).await
+            }
+            "command_timeline" => {
+                Self::handle_command_timeline(request, session_manager).await
+            }
+            "session_timeline" => {
+                Self::handle_session_timeline(request, session_manager).await
+            }
+            "take_long_commands" => {
+                Self::handle_take_long_commands(request, session_manager).await
+            }
             "receive_output" => {
                 Self::handle_receive_output(request, session_manager).await
             }
             "get_status" => {
-                Self::handle_get_status(request, session_manager, start_time).await
+                Self::handle_get_status(request, session_manager, start_time, notice).await
+            }
+            "get_webtransport_certs" => {
+                Self::handle_get_webtransport_certs(request, services.cert_manager.clone())
+            }
+            "create_attach_token" => {
+                Self::handle_create_attach_token(request, session_manager, services.attach_tokens.clone()).await
+            }
+            "get_theme" => {
+                Self::handle_get_theme(request, services.theme_store.clone()).await
+            }
+            "set_theme" => {
+                Self::handle_set_theme(request, services.theme_store.clone()).await
             }
+            "db_health" | "db_backup" | "db_vacuum" => {
+                Self::handle_database(request, services.database.clone()).await
+            }
+            "set_log_level" => Self::handle_set_log_level(request),
+            "set_bandwidth_limit" => {
+                Self::handle_set_bandwidth_limit(request, services.throttle.clone())
+            }
+            "daemon_health" => {
+                Self::handle_daemon_health(request, services.health.clone()).await
+            }
+            "relay_status" => Self::handle_relay_status(request, services.relay.clone()),
+            "list_hosts" | "team_directory_status" | "sync_team_directory" | "apply_config"
+            | "import_hosts" => Self::handle_hosts(request, services.hosts.clone()).await,
+            "discover_hosts" => Self::handle_discover_hosts(request).await,
+            "ssh_certificate" => Self::handle_ssh_certificate(request, services.ssh_ca.clone()).await,
             _ => Response::error(
                 request.id,
                 error_codes::METHOD_NOT_FOUND,
@@ -203,7 +425,7 @@ impl IpcServer {
     }
 
     /// Send response to client
-    async fn send_response(writer: &mut tokio::net::unix::OwnedWriteHalf, response: &Response) -> Result<()> {
+    async fn send_response(writer: &mut (impl AsyncWrite + Unpin), response: &Response) -> Result<()> {
         let json = serde_json::to_string(response)?;
         writer.write_all(json.as_bytes()).await?;
         writer.write_all(b"\n").await?;
@@ -264,6 +486,7 @@ impl IpcServer {
     async fn handle_attach_session(
         request: Request,
         session_manager: Arc<SessionManager>,
+        theme_store: Option<Arc<ThemeStore>>,
     ) -> Response {
         let params: AttachSessionParams = match serde_json::from_value(request.params) {
             Ok(p) => p,
@@ -277,10 +500,20 @@ impl IpcServer {
         };
 
         match session_manager
-            .attach_client(params.session_id, params.client_id)
+            .attach_client(params.session_id, params.client_id, params.read_only)
             .await
         {
-            Ok(_) => Response::success(request.id, serde_json::json!({"success": true})),
+            Ok(_) => {
+                // Include appearance so remote clients render like the desktop
+                let theme = match theme_store {
+                    Some(store) => Some(store.current().await),
+                    None => None,
+                };
+                Response::success(
+                    request.id,
+                    serde_json::json!({"success": true, "theme": theme, "read_only": params.read_only}),
+                )
+            }
             Err(e) => Response::error(
                 request.id,
                 error_codes::SESSION_NOT_FOUND,
@@ -342,11 +575,11 @@ impl IpcServer {
         }
     }
 
-    async fn handle_resize_terminal(
+    async fn handle_focus_session(
         request: Request,
         session_manager: Arc<SessionManager>,
     ) -> Response {
-        let params: ResizeTerminalParams = match serde_json::from_value(request.params) {
+        let params: FocusSessionParams = match serde_json::from_value(request.params) {
             Ok(p) => p,
             Err(e) => {
                 return Response::error(
@@ -357,18 +590,8 @@ impl IpcServer {
             }
         };
 
-        match session_manager.get_session(params.session_id).await {
-            Ok(session) => {
-                let mut terminal_session = session.terminal_session.write().await;
-                match terminal_session.resize(params.cols, params.rows) {
-                    Ok(_) => Response::success(request.id, serde_json::json!({"success": true})),
-                    Err(e) => Response::error(
-                        request.id,
-                        error_codes::INTERNAL_ERROR,
-                        format!("Failed to resize terminal: {}", e),
-                    ),
-                }
-            }
+        match session_manager.focus_session(params.session_id).await {
+            Ok(_) => Response::success(request.id, serde_json::json!({"success": true})),
             Err(e) => Response::error(
                 request.id,
                 error_codes::SESSION_NOT_FOUND,
@@ -377,11 +600,11 @@ impl IpcServer {
         }
     }
 
-    async fn handle_send_input(
+    async fn handle_search_scrollback(
         request: Request,
         session_manager: Arc<SessionManager>,
     ) -> Response {
-        let params: SendInputParams = match serde_json::from_value(request.params) {
+        let params: SearchScrollbackParams = match serde_json::from_value(request.params) {
             Ok(p) => p,
             Err(e) => {
                 return Response::error(
@@ -392,35 +615,53 @@ impl IpcServer {
             }
         };
 
-        // Decode base64 data
-        let data = match general_purpose::STANDARD.decode(&params.data) {
-            Ok(d) => d,
+        if let Err(e) = session_manager.get_session(params.session_id).await {
+            return Response::error(
+                request.id,
+                error_codes::SESSION_NOT_FOUND,
+                format!("Session not found: {}", e),
+            );
+        }
+        match session_manager
+            .search_scrollback(params.session_id, &params.query)
+            .await
+        {
+            Ok(results) => Response::success(request.id, results),
+            Err(e) => Response::error(
+                request.id,
+                error_codes::INVALID_PARAMS,
+                format!("Invalid search: {}", e),
+            ),
+        }
+    }
+
+    async fn handle_export_transcript(
+        request: Request,
+        session_manager: Arc<SessionManager>,
+    ) -> Response {
+        let params: ExportTranscriptParams = match serde_json::from_value(request.params) {
+            Ok(p) => p,
             Err(e) => {
                 return Response::error(
                     request.id,
                     error_codes::INVALID_PARAMS,
-                    format!("Invalid base64 data: {}", e),
+                    format!("Invalid parameters: {}", e),
                 );
             }
         };
 
-        // Get session and write to PTY
-        match session_manager.get_session(params.session_id).await {
-            Ok(session) => {
-                let mut terminal_session = session.terminal_session.write().await;
-                match terminal_session.write(&data) {
-                    Ok(bytes_written) => {
-                        Response::success(request.id, serde_json::json!({
-                            "bytes_written": bytes_written
-                        }))
-                    }
-                    Err(e) => Response::error(
-                        request.id,
-                        error_codes::INTERNAL_ERROR,
-                        format!("Failed to write to PTY: {}", e),
-                    ),
-                }
-            }
+        match session_manager
+            .export_transcript(params.session_id, params.format)
+            .await
+        {
+            Ok(content) => Response::success(
+                request.id,
+                serde_json::json!({
+                    "format": params.format,
+                    "extension": params.format.extension(),
+                    "content": content,
+                }),
+            ),
             Err(e) => Response::error(
                 request.id,
                 error_codes::SESSION_NOT_FOUND,
@@ -429,11 +670,11 @@ impl IpcServer {
         }
     }
 
-    async fn handle_receive_output(
+    async fn handle_add_bookmark(
         request: Request,
         session_manager: Arc<SessionManager>,
     ) -> Response {
-        let params: ReceiveOutputParams = match serde_json::from_value(request.params) {
+        let params: AddBookmarkParams = match serde_json::from_value(request.params) {
             Ok(p) => p,
             Err(e) => {
                 return Response::error(
@@ -444,39 +685,71 @@ impl IpcServer {
             }
         };
 
-        // Get session and read from PTY
-        match session_manager.get_session(params.session_id).await {
-            Ok(session) => {
-                let mut terminal_session = session.terminal_session.write().await;
+        if let Err(e) = session_manager.get_session(params.session_id).await {
+            return Response::error(
+                request.id,
+                error_codes::SESSION_NOT_FOUND,
+                format!("Session not found: {}", e),
+            );
+        }
+        match session_manager
+            .add_bookmark(params.session_id, params.line, params.note)
+            .await
+        {
+            Ok(bookmark) => Response::success(request.id, bookmark),
+            Err(e) => Response::error(
+                request.id,
+                error_codes::INVALID_PARAMS,
+                format!("Invalid bookmark: {}", e),
+            ),
+        }
+    }
 
-                // Buffer for reading PTY output
-                let mut buffer = vec![0u8; 4096];
+    async fn handle_list_bookmarks(
+        request: Request,
+        session_manager: Arc<SessionManager>,
+    ) -> Response {
+        let params: ListBookmarksParams = match serde_json::from_value(request.params) {
+            Ok(p) => p,
+            Err(e) => {
+                return Response::error(
+                    request.id,
+                    error_codes::INVALID_PARAMS,
+                    format!("Invalid parameters: {}", e),
+                );
+            }
+        };
 
-                // Try to read (non-blocking if no timeout specified)
-                let read_result = if params.timeout_ms.is_some() {
-                    // Blocking read with timeout
-                    terminal_session.read(&mut buffer)
-                } else {
-                    // Non-blocking read
-                    terminal_session.try_read(&mut buffer)
-                };
+        match session_manager.list_bookmarks(params.session_id).await {
+            Ok(bookmarks) => Response::success(request.id, bookmarks),
+            Err(e) => Response::error(
+                request.id,
+                error_codes::SESSION_NOT_FOUND,
+                format!("Session not found: {}", e),
+            ),
+        }
+    }
 
-                match read_result {
-                    Ok(bytes_read) => {
-                        // Encode output as base64
-                        let output_data = general_purpose::STANDARD.encode(&buffer[..bytes_read]);
-                        Response::success(request.id, serde_json::json!({
-                            "data": output_data,
-                            "bytes_read": bytes_read
-                        }))
-                    }
-                    Err(e) => Response::error(
-                        request.id,
-                        error_codes::INTERNAL_ERROR,
-                        format!("Failed to read from PTY: {}", e),
-                    ),
-                }
+    async fn handle_remove_bookmark(
+        request: Request,
+        session_manager: Arc<SessionManager>,
+    ) -> Response {
+        let params: RemoveBookmarkParams = match serde_json::from_value(request.params) {
+            Ok(p) => p,
+            Err(e) => {
+                return Response::error(
+                    request.id,
+                    error_codes::INVALID_PARAMS,
+                    format!("Invalid parameters: {}", e),
+                );
             }
+        };
+
+        match session_manager
+            .remove_bookmark(params.session_id, params.bookmark_id)
+            .await
+        {
+            Ok(removed) => Response::success(request.id, serde_json::json!({"success": removed})),
             Err(e) => Response::error(
                 request.id,
                 error_codes::SESSION_NOT_FOUND,
@@ -485,24 +758,906 @@ impl IpcServer {
         }
     }
 
-    async fn handle_get_status(
+    async fn handle_jump_to_bookmark(
         request: Request,
         session_manager: Arc<SessionManager>,
-        start_time: SystemTime,
     ) -> Response {
-        let uptime = start_time
-            .elapsed()
-            .unwrap_or_default()
-            .as_secs();
+        let params: JumpToBookmarkParams = match serde_json::from_value(request.params) {
+            Ok(p) => p,
+            Err(e) => {
+                return Response::error(
+                    request.id,
+                    error_codes::INVALID_PARAMS,
+                    format!("Invalid parameters: {}", e),
+                );
+            }
+        };
 
-        let status = StatusResult {
-            version: env!("CARGO_PKG_VERSION").to_string(),
-            uptime_seconds: uptime,
-            num_sessions: session_manager.count_sessions().await,
-            num_clients: session_manager.count_clients().await,
+        if let Err(e) = session_manager.get_session(params.session_id).await {
+            return Response::error(
+                request.id,
+                error_codes::SESSION_NOT_FOUND,
+                format!("Session not found: {}", e),
+            );
+        }
+        match session_manager
+            .jump_to_bookmark(params.session_id, params.bookmark_id, params.context)
+            .await
+        {
+            Ok(view) => Response::success(request.id, view),
+            Err(e) => Response::error(request.id, error_codes::INVALID_PARAMS, e.to_string()),
+        }
+    }
+
+    async fn handle_command_timeline(
+        request: Request,
+        session_manager: Arc<SessionManager>,
+    ) -> Response {
+        let params: CommandTimelineParams = match serde_json::from_value(request.params) {
+            Ok(p) => p,
+            Err(e) => {
+                return Response::error(
+                    request.id,
+                    error_codes::INVALID_PARAMS,
+                    format!("Invalid parameters: {}", e),
+                );
+            }
         };
 
-        Response::success(request.id, status)
+        match session_manager.command_timeline(params.session_id).await {
+            Ok(commands) => Response::success(request.id, commands),
+            Err(e) => Response::error(
+                request.id,
+                error_codes::SESSION_NOT_FOUND,
+                format!("Session not found: {}", e),
+            ),
+        }
+    }
+
+    async fn handle_session_timeline(
+        request: Request,
+        session_manager: Arc<SessionManager>,
+    ) -> Response {
+        let params: SessionTimelineParams = match serde_json::from_value(request.params) {
+            Ok(p) => p,
+            Err(e) => {
+                return Response::error(
+                    request.id,
+                    error_codes::INVALID_PARAMS,
+                    format!("Invalid parameters: {}", e),
+                );
+            }
+        };
+
+        match session_manager
+            .session_timeline(params.session_id, params.from, params.to)
+            .await
+        {
+            Ok(timeline) => Response::success(request.id, timeline),
+            Err(e) => Response::error(
+                request.id,
+                error_codes::SESSION_NOT_FOUND,
+                format!("Session not found: {}", e),
+            ),
+        }
+    }
+
+    async fn handle_take_long_commands(
+        request: Request,
+        session_manager: Arc<SessionManager>,
+    ) -> Response {
+        let params: TakeLongCommandsParams = match serde_json::from_value(request.params) {
+            Ok(p) => p,
+            Err(e) => {
+                return Response::error(
+                    request.id,
+                    error_codes::INVALID_PARAMS,
+                    format!("Invalid parameters: {}", e),
+                );
+            }
+        };
+
+        let threshold = std::time::Duration::from_secs(params.threshold_secs);
+        let commands = session_manager.take_long_commands(threshold).await;
+        Response::success(request.id, commands)
+    }
+
+    async fn handle_resize_terminal(
+        request: Request,
+        session_manager: Arc<SessionManager>,
+    ) -> Response {
+        let params: ResizeTerminalParams = match serde_json::from_value(request.params) {
+            Ok(p) => p,
+            Err(e) => {
+                return Response::error(
+                    request.id,
+                    error_codes::INVALID_PARAMS,
+                    format!("Invalid parameters: {}", e),
+                );
+            }
+        };
+
+        let session = match session_manager.get_session(params.session_id).await {
+            Ok(session) => session,
+            Err(e) => {
+                return Response::error(
+                    request.id,
+                    error_codes::SESSION_NOT_FOUND,
+                    format!("Session not found: {}", e),
+                );
+            }
+        };
+        let client_id = params.client_id.unwrap_or(ANONYMOUS_CLIENT);
+        match session.resize(client_id, TerminalSize::new(params.cols, params.rows)).await {
+            // The size picked may not be the one asked for
+            Ok(size) => Response::success(request.id, serde_json::json!({"success": true, "size": size})),
+            Err(e) => Response::error(
+                request.id,
+                error_codes::INTERNAL_ERROR,
+                format!("Failed to resize terminal: {}", e),
+            ),
+        }
+    }
+
+    async fn handle_set_size_policy(
+        request: Request,
+        session_manager: Arc<SessionManager>,
+    ) -> Response {
+        let params: SetSizePolicyParams = match serde_json::from_value(request.params) {
+            Ok(p) => p,
+            Err(e) => {
+                return Response::error(
+                    request.id,
+                    error_codes::INVALID_PARAMS,
+                    format!("Invalid parameters: {}", e),
+                );
+            }
+        };
+
+        let session = match session_manager.get_session(params.session_id).await {
+            Ok(session) => session,
+            Err(e) => {
+                return Response::error(
+                    request.id,
+                    error_codes::SESSION_NOT_FOUND,
+                    format!("Session not found: {}", e),
+                );
+            }
+        };
+        match session.set_size_policy(params.policy).await {
+            Ok(size) => Response::success(request.id, serde_json::json!({"success": true, "size": size})),
+            Err(e) => Response::error(
+                request.id,
+                error_codes::INVALID_PARAMS,
+                format!("Failed to set size policy: {}", e),
+            ),
+        }
+    }
+
+    async fn handle_session_stats(
+        request: Request,
+        session_manager: Arc<SessionManager>,
+        resources: Option<Arc<ResourceMonitor>>,
+    ) -> Response {
+        let params: SessionStatsParams = match serde_json::from_value(request.params) {
+            Ok(p) => p,
+            Err(e) => {
+                return Response::error(
+                    request.id,
+                    error_codes::INVALID_PARAMS,
+                    format!("Invalid parameters: {}", e),
+                );
+            }
+        };
+
+        let Some(resources) = resources else {
+            return Response::error(
+                request.id,
+                error_codes::INTERNAL_ERROR,
+                "Resource usage is not available".to_string(),
+            );
+        };
+        if let Err(e) = session_manager.get_session(params.session_id).await {
+            return Response::error(
+                request.id,
+                error_codes::SESSION_NOT_FOUND,
+                format!("Session not found: {}", e),
+            );
+        }
+
+        // Null until the session's first sample, and for sessions without
+        // a PTY
+        Response::success(request.id, resources.usage(params.session_id).await)
+    }
+
+    async fn handle_send_input(
+        request: Request,
+        session_manager: Arc<SessionManager>,
+    ) -> Response {
+        let params: SendInputParams = match serde_json::from_value(request.params) {
+            Ok(p) => p,
+            Err(e) => {
+                return Response::error(
+                    request.id,
+                    error_codes::INVALID_PARAMS,
+                    format!("Invalid parameters: {}", e),
+                );
+            }
+        };
+
+        // Decode base64 data
+        let data = match general_purpose::STANDARD.decode(&params.data) {
+            Ok(d) => d,
+            Err(e) => {
+                return Response::error(
+                    request.id,
+                    error_codes::INVALID_PARAMS,
+                    format!("Invalid base64 data: {}", e),
+                );
+            }
+        };
+
+        // Get session and write to PTY
+        let session = match session_manager.get_session(params.session_id).await {
+            Ok(session) => session,
+            Err(e) => {
+                return Response::error(
+                    request.id,
+                    error_codes::SESSION_NOT_FOUND,
+                    format!("Session not found: {}", e),
+                );
+            }
+        };
+        if session.terminal_session.is_none() {
+            return Response::error(
+                request.id,
+                error_codes::INVALID_PARAMS,
+                format!("Session {} has no terminal", params.session_id),
+            );
+        }
+        if !session.allows_input(params.client_id).await {
+            return Response::error(
+                request.id,
+                error_codes::READ_ONLY,
+                READ_ONLY_NOTICE.to_string(),
+            );
+        }
+        match session.write_input(&data).await {
+            Ok(bytes_written) => {
+                // Typing in a session gives it focus
+                let _ = session_manager.focus_session(params.session_id).await;
+                session.client_active(params.client_id).await;
+                Response::success(request.id, serde_json::json!({
+                    "bytes_written": bytes_written
+                }))
+            }
+            Err(e) => Response::error(
+                request.id,
+                error_codes::INTERNAL_ERROR,
+                format!("Failed to write to PTY: {}", e),
+            ),
+        }
+    }
+
+    async fn handle_send_key(request: Request, session_manager: Arc<SessionManager>) -> Response {
+        let params: SendKeyParams = match serde_json::from_value(request.params) {
+            Ok(p) => p,
+            Err(e) => {
+                return Response::error(
+                    request.id,
+                    error_codes::INVALID_PARAMS,
+                    format!("Invalid parameters: {}", e),
+                );
+            }
+        };
+
+        let session = match session_manager.get_session(params.session_id).await {
+            Ok(session) => session,
+            Err(e) => {
+                return Response::error(
+                    request.id,
+                    error_codes::SESSION_NOT_FOUND,
+                    format!("Session not found: {}", e),
+                );
+            }
+        };
+        if session.terminal_session.is_none() {
+            return Response::error(
+                request.id,
+                error_codes::INVALID_PARAMS,
+                format!("Session {} has no terminal", params.session_id),
+            );
+        }
+        if !session.allows_input(params.client_id).await {
+            return Response::error(
+                request.id,
+                error_codes::READ_ONLY,
+                READ_ONLY_NOTICE.to_string(),
+            );
+        }
+
+        match session.send_key(&params.press).await {
+            Ok(bytes_written) => {
+                let _ = session_manager.focus_session(params.session_id).await;
+                session.client_active(params.client_id).await;
+                let mode = session.keyboard.read().await.mode();
+                Response::success(request.id, serde_json::json!({
+                    "bytes_written": bytes_written,
+                    "mode": mode,
+                }))
+            }
+            Err(e) => Response::error(
+                request.id,
+                error_codes::INTERNAL_ERROR,
+                format!("Failed to write to PTY: {}", e),
+            ),
+        }
+    }
+
+    async fn handle_paste(request: Request, session_manager: Arc<SessionManager>) -> Response {
+        let params: PasteParams = match serde_json::from_value(request.params) {
+            Ok(p) => p,
+            Err(e) => {
+                return Response::error(
+                    request.id,
+                    error_codes::INVALID_PARAMS,
+                    format!("Invalid parameters: {}", e),
+                );
+            }
+        };
+
+        let session = match session_manager.get_session(params.session_id).await {
+            Ok(session) => session,
+            Err(e) => {
+                return Response::error(
+                    request.id,
+                    error_codes::SESSION_NOT_FOUND,
+                    format!("Session not found: {}", e),
+                );
+            }
+        };
+        if session.terminal_session.is_none() {
+            return Response::error(
+                request.id,
+                error_codes::INVALID_PARAMS,
+                format!("Session {} has no terminal", params.session_id),
+            );
+        }
+        if !session.allows_input(params.client_id).await {
+            return Response::error(
+                request.id,
+                error_codes::READ_ONLY,
+                READ_ONLY_NOTICE.to_string(),
+            );
+        }
+
+        match session.paste(&params.text).await {
+            Ok((bytes_written, bracketed)) => {
+                let _ = session_manager.focus_session(params.session_id).await;
+                session.client_active(params.client_id).await;
+                Response::success(request.id, serde_json::json!({
+                    "bytes_written": bytes_written,
+                    "bracketed": bracketed,
+                }))
+            }
+            Err(e) => Response::error(
+                request.id,
+                error_codes::INTERNAL_ERROR,
+                format!("Failed to write to PTY: {}", e),
+            ),
+        }
+    }
+
+    async fn handle_rerun_command(request: Request, session_manager: Arc<SessionManager>) -> Response {
+        let params: RerunCommandParams = match serde_json::from_value(request.params) {
+            Ok(p) => p,
+            Err(e) => {
+                return Response::error(
+                    request.id,
+                    error_codes::INVALID_PARAMS,
+                    format!("Invalid parameters: {}", e),
+                );
+            }
+        };
+
+        for target in &params.target_session_ids {
+            let session = match session_manager.get_session(*target).await {
+                Ok(session) => session,
+                Err(e) => {
+                    return Response::error(
+                        request.id,
+                        error_codes::SESSION_NOT_FOUND,
+                        format!("Session not found: {}", e),
+                    );
+                }
+            };
+            if session.terminal_session.is_some() && !session.allows_input(params.client_id).await {
+                return Response::error(
+                    request.id,
+                    error_codes::READ_ONLY,
+                    READ_ONLY_NOTICE.to_string(),
+                );
+            }
+        }
+
+        match session_manager
+            .rerun_command(
+                params.session_id,
+                params.command_id,
+                &params.target_session_ids,
+                params.confirmed,
+                params.canary.as_ref(),
+            )
+            .await
+        {
+            Ok(outcome) => {
+                let typed_into = match &outcome {
+                    RerunOutcome::Sent { sessions, .. } => sessions.clone(),
+                    RerunOutcome::CanaryFailed { report, .. } => vec![report.session_id],
+                    RerunOutcome::NeedsConfirmation { .. } => Vec::new(),
+                };
+                for target in typed_into {
+                    if let Ok(session) = session_manager.get_session(target).await {
+                        session.client_active(params.client_id).await;
+                    }
+                }
+                Response::success(request.id, outcome)
+            }
+            Err(e) => Response::error(
+                request.id,
+                error_codes::INVALID_PARAMS,
+                format!("Failed to re-run command: {}", e),
+            ),
+        }
+    }
+
+    async fn handle_receive_output(
+        request: Request,
+        session_manager: Arc<SessionManager>,
+    ) -> Response {
+        let params: ReceiveOutputParams = match serde_json::from_value(request.params) {
+            Ok(p) => p,
+            Err(e) => {
+                return Response::error(
+                    request.id,
+                    error_codes::INVALID_PARAMS,
+                    format!("Invalid parameters: {}", e),
+                );
+            }
+        };
+
+        // Get session and read from PTY
+        let session = match session_manager.get_session(params.session_id).await {
+            Ok(session) => session,
+            Err(e) => {
+                return Response::error(
+                    request.id,
+                    error_codes::SESSION_NOT_FOUND,
+                    format!("Session not found: {}", e),
+                );
+            }
+        };
+        match session.terminal() {
+            Ok(terminal) => {
+                let terminal_session = terminal.read().await;
+
+                // Buffer for reading PTY output
+                let mut buffer = vec![0u8; 4096];
+
+                // Try to read (non-blocking if no timeout specified)
+                let read_result = if params.timeout_ms.is_some() {
+                    // Blocking read with timeout
+                    terminal_session.read(&mut buffer)
+                } else {
+                    // Non-blocking read
+                    terminal_session.try_read(&mut buffer)
+                };
+
+                match read_result {
+                    Ok(bytes_read) => {
+                        // Encode output as base64
+                        let output_data = general_purpose::STANDARD.encode(&buffer[..bytes_read]);
+                        Response::success(request.id, serde_json::json!({
+                            "data": output_data,
+                            "bytes_read": bytes_read
+                        }))
+                    }
+                    Err(e) => Response::error(
+                        request.id,
+                        error_codes::INTERNAL_ERROR,
+                        format!("Failed to read from PTY: {}", e),
+                    ),
+                }
+            }
+            Err(e) => Response::error(request.id, error_codes::INVALID_PARAMS, e.to_string()),
+        }
+    }
+
+    async fn handle_get_status(
+        request: Request,
+        session_manager: Arc<SessionManager>,
+        start_time: SystemTime,
+        notice: Option<ShutdownNotice>,
+    ) -> Response {
+        let uptime = start_time
+            .elapsed()
+            .unwrap_or_default()
+            .as_secs();
+
+        let status = StatusResult {
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            uptime_seconds: uptime,
+            num_sessions: session_manager.count_sessions().await,
+            num_clients: session_manager.count_clients().await,
+            shutting_down_in: notice.map(|notice| notice.seconds_left),
+        };
+
+        Response::success(request.id, status)
+    }
+
+    fn handle_get_webtransport_certs(
+        request: Request,
+        cert_manager: Option<Arc<CertManager>>,
+    ) -> Response {
+        match cert_manager {
+            Some(cert_manager) => Response::success(
+                request.id,
+                WebTransportCertsResult {
+                    hashes: cert_manager.hashes(),
+                },
+            ),
+            None => Response::error(
+                request.id,
+                error_codes::INTERNAL_ERROR,
+                "WebTransport certificates are not available".to_string(),
+            ),
+        }
+    }
+
+    async fn handle_create_attach_token(
+        request: Request,
+        session_manager: Arc<SessionManager>,
+        attach_tokens: Option<Arc<AttachTokens>>,
+    ) -> Response {
+        let params: CreateAttachTokenParams = match serde_json::from_value(request.params) {
+            Ok(p) => p,
+            Err(e) => {
+                return Response::error(
+                    request.id,
+                    error_codes::INVALID_PARAMS,
+                    format!("Invalid parameters: {}", e),
+                );
+            }
+        };
+
+        let Some(attach_tokens) = attach_tokens else {
+            return Response::error(
+                request.id,
+                error_codes::INTERNAL_ERROR,
+                "Attach tokens are not available".to_string(),
+            );
+        };
+
+        if let Err(e) = session_manager.get_session(params.session_id).await {
+            return Response::error(
+                request.id,
+                error_codes::SESSION_NOT_FOUND,
+                format!("Session not found: {}", e),
+            );
+        }
+
+        match attach_tokens.issue(params.session_id, params.scope, params.ttl_seconds) {
+            Ok(issued) => Response::success(request.id, issued),
+            Err(e) => Response::error(request.id, error_codes::INVALID_PARAMS, e.to_string()),
+        }
+    }
+
+    async fn handle_get_theme(request: Request, theme_store: Option<Arc<ThemeStore>>) -> Response {
+        match theme_store {
+            Some(store) => Response::success(request.id, store.current().await),
+            None => Response::error(
+                request.id,
+                error_codes::INTERNAL_ERROR,
+                "Theme sharing is not available".to_string(),
+            ),
+        }
+    }
+
+    async fn handle_set_theme(request: Request, theme_store: Option<Arc<ThemeStore>>) -> Response {
+        let params: ThemeUpdate = match serde_json::from_value(request.params) {
+            Ok(p) => p,
+            Err(e) => {
+                return Response::error(
+                    request.id,
+                    error_codes::INVALID_PARAMS,
+                    format!("Invalid parameters: {}", e),
+                );
+            }
+        };
+
+        let Some(store) = theme_store else {
+            return Response::error(
+                request.id,
+                error_codes::INTERNAL_ERROR,
+                "Theme sharing is not available".to_string(),
+            );
+        };
+
+        match store.update(params).await {
+            Ok(theme) => Response::success(request.id, theme),
+            Err(e) => Response::error(request.id, error_codes::INVALID_PARAMS, e.to_string()),
+        }
+    }
+
+    async fn handle_daemon_health(request: Request, health: Option<Arc<Health>>) -> Response {
+        let Some(health) = health else {
+            return Response::error(
+                request.id,
+                error_codes::INTERNAL_ERROR,
+                "Health checks are not available".to_string(),
+            );
+        };
+
+        Response::success(request.id, health.report().await)
+    }
+
+    fn handle_relay_status(request: Request, relay: Option<Arc<Relay>>) -> Response {
+        let Some(relay) = relay else {
+            return Response::error(
+                request.id,
+                error_codes::INTERNAL_ERROR,
+                "The relay is not available".to_string(),
+            );
+        };
+
+        Response::success(request.id, relay.stats())
+    }
+
+    async fn handle_hosts(request: Request, hosts: Option<HostDirectory>) -> Response {
+        let Some(hosts) = hosts else {
+            return Response::error(
+                request.id,
+                error_codes::INTERNAL_ERROR,
+                "The host directory is not available".to_string(),
+            );
+        };
+
+        if request.method == "apply_config" {
+            return Self::handle_apply_config(request, hosts).await;
+        }
+        if request.method == "import_hosts" {
+            return Self::handle_import_hosts(request, hosts).await;
+        }
+        if request.method == "list_hosts" {
+            let team = hosts.team.as_ref().map(|team| team.hosts()).unwrap_or_default();
+            return match hosts.workspaces.list_hosts(team).await {
+                Ok(hosts) => Response::success(request.id, hosts),
+                Err(e) => Response::error(
+                    request.id,
+                    error_codes::INTERNAL_ERROR,
+                    format!("Failed to list hosts: {}", e),
+                ),
+            };
+        }
+
+        let Some(team) = hosts.team else {
+            return Response::error(
+                request.id,
+                error_codes::INTERNAL_ERROR,
+                "No team directory is configured (team.url)".to_string(),
+            );
+        };
+        if request.method == "team_directory_status" {
+            return Response::success(request.id, team.status());
+        }
+        match team.sync().await {
+            Ok(status) => Response::success(request.id, status),
+            Err(e) => Response::error(
+                request.id,
+                error_codes::INTERNAL_ERROR,
+                format!("Team directory sync failed: {:#}", e),
+            ),
+        }
+    }
+
+    async fn handle_apply_config(request: Request, hosts: HostDirectory) -> Response {
+        let params: ApplyConfigParams = match serde_json::from_value(request.params) {
+            Ok(p) => p,
+            Err(e) => {
+                return Response::error(
+                    request.id,
+                    error_codes::INVALID_PARAMS,
+                    format!("Invalid parameters: {}", e),
+                );
+            }
+        };
+
+        let path = params.path.unwrap_or(hosts.declaration);
+        let declaration = match Declaration::load(&path) {
+            Ok(declaration) => declaration,
+            Err(e) => {
+                return Response::error(request.id, error_codes::INVALID_PARAMS, format!("{:#}", e));
+            }
+        };
+        match hosts.workspaces.apply_declaration(&declaration, params.dry_run).await {
+            Ok(report) => Response::success(request.id, report),
+            Err(e) => Response::error(
+                request.id,
+                error_codes::INTERNAL_ERROR,
+                format!("Failed to apply {:?}: {:#}", path, e),
+            ),
+        }
+    }
+
+    async fn handle_discover_hosts(request: Request) -> Response {
+        let params: DiscoverHostsParams = match serde_json::from_value(request.params) {
+            Ok(p) => p,
+            Err(e) => {
+                return Response::error(
+                    request.id,
+                    error_codes::INVALID_PARAMS,
+                    format!("Invalid parameters: {}", e),
+                );
+            }
+        };
+
+        let timeout = Duration::from_millis(params.timeout_ms);
+        match discovery::discover(&params.targets, params.port, timeout).await {
+            Ok(report) => Response::success(request.id, report),
+            Err(e) => Response::error(request.id, error_codes::INVALID_PARAMS, format!("{:#}", e)),
+        }
+    }
+
+    async fn handle_ssh_certificate(request: Request, ssh_ca: Option<Arc<SshCa>>) -> Response {
+        let params: SshCertificateParams = match serde_json::from_value(request.params) {
+            Ok(p) => p,
+            Err(e) => {
+                return Response::error(
+                    request.id,
+                    error_codes::INVALID_PARAMS,
+                    format!("Invalid parameters: {}", e),
+                );
+            }
+        };
+
+        let Some(ssh_ca) = ssh_ca else {
+            return Response::error(
+                request.id,
+                error_codes::INTERNAL_ERROR,
+                "No SSH CA is configured".to_string(),
+            );
+        };
+        match ssh_ca.certificate(&params.username, &params.host).await {
+            Ok(certificate) => Response::success(request.id, certificate),
+            Err(e) => Response::error(
+                request.id,
+                error_codes::INTERNAL_ERROR,
+                format!("Failed to get an SSH certificate: {:#}", e),
+            ),
+        }
+    }
+
+    async fn handle_import_hosts(request: Request, hosts: HostDirectory) -> Response {
+        let params: ImportHostsParams = match serde_json::from_value(request.params) {
+            Ok(p) => p,
+            Err(e) => {
+                return Response::error(
+                    request.id,
+                    error_codes::INVALID_PARAMS,
+                    format!("Invalid parameters: {}", e),
+                );
+            }
+        };
+
+        match hosts
+            .workspaces
+            .import_hosts(&params.workspace, &params.hosts, params.tags)
+            .await
+        {
+            Ok(workspace) => Response::success(request.id, workspace),
+            Err(e) => Response::error(
+                request.id,
+                error_codes::INVALID_PARAMS,
+                format!("Failed to import hosts: {:#}", e),
+            ),
+        }
+    }
+
+    fn handle_set_log_level(request: Request) -> Response {
+        let params: SetLogLevelParams = match serde_json::from_value(request.params) {
+            Ok(p) => p,
+            Err(e) => {
+                return Response::error(
+                    request.id,
+                    error_codes::INVALID_PARAMS,
+                    format!("Invalid parameters: {}", e),
+                );
+            }
+        };
+
+        match pulsar_log::set_filter(&params.level) {
+            Ok(previous) => {
+                info!("Log level changed from {} to {}", previous, params.level);
+                Response::success(request.id, LogLevelResult { level: params.level, previous })
+            }
+            Err(e @ pulsar_log::LogError::InvalidFilter { .. }) => {
+                Response::error(request.id, error_codes::INVALID_PARAMS, e.to_string())
+            }
+            Err(e) => Response::error(request.id, error_codes::INTERNAL_ERROR, e.to_string()),
+        }
+    }
+
+    fn handle_set_bandwidth_limit(request: Request, throttle: Option<Throttle>) -> Response {
+        let Some(throttle) = throttle else {
+            return Response::error(
+                request.id,
+                error_codes::INTERNAL_ERROR,
+                "File transfers are not available".to_string(),
+            );
+        };
+        let params: SetBandwidthLimitParams = match serde_json::from_value(request.params) {
+            Ok(p) => p,
+            Err(e) => {
+                return Response::error(
+                    request.id,
+                    error_codes::INVALID_PARAMS,
+                    format!("Invalid parameters: {}", e),
+                );
+            }
+        };
+
+        let limit = params
+            .max_bytes_per_sec
+            .and_then(|rate| BandwidthLimit::new(rate, params.burst_bytes));
+        let previous = throttle.set_limit(limit);
+        match limit {
+            Some(limit) => info!(
+                "Transfer bandwidth limited to {} bytes/s (burst {})",
+                limit.bytes_per_sec, limit.burst_bytes
+            ),
+            None => info!("Transfer bandwidth limit lifted"),
+        }
+        Response::success(request.id, BandwidthLimitResult { limit, previous })
+    }
+
+    async fn handle_database(request: Request, database: Option<DatabaseHandle>) -> Response {
+        let Some(database) = database else {
+            return Response::error(
+                request.id,
+                error_codes::INTERNAL_ERROR,
+                "Database maintenance is not available".to_string(),
+            );
+        };
+
+        let result: pulsar_db::Result<serde_json::Value> = async {
+            match request.method.as_str() {
+                "db_backup" => {
+                    let path = pulsar_db::backup_rotating(
+                        &database.pool,
+                        &database.backup_dir,
+                        "workspaces",
+                        DB_BACKUPS_KEPT,
+                    )
+                    .await?;
+                    Ok(serde_json::json!(DatabaseBackupResult { path }))
+                }
+                "db_vacuum" => {
+                    pulsar_db::vacuum(&database.pool).await?;
+                    Ok(serde_json::json!(pulsar_db::health(&database.pool).await?))
+                }
+                _ => Ok(serde_json::json!(pulsar_db::health(&database.pool).await?)),
+            }
+        }
+        .await;
+
+        match result {
+            Ok(value) => Response::success(request.id, value),
+            Err(e) => Response::error(
+                request.id,
+                error_codes::INTERNAL_ERROR,
+                format!("Database {} failed: {}", request.method, e),
+            ),
+        }
     }
 }
 
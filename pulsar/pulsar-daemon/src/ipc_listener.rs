@@ -0,0 +1,159 @@
+//! Local transport for the IPC server
+//!
+//! A Unix domain socket on Unix and a named pipe (`\\.\pipe\...`) on
+//! Windows. Either way a connection is a byte stream of newline-delimited
+//! JSON, so the server does not care which one it is serving.
+
+use anyhow::{Context, Result};
+use std::io;
+use std::path::Path;
+use tokio::io::{AsyncRead, AsyncWrite};
+use tracing::info;
+
+#[cfg(unix)]
+use tokio::net::UnixListener;
+#[cfg(windows)]
+use tokio::net::windows::named_pipe::{NamedPipeServer, ServerOptions};
+
+/// A connected client
+pub trait IpcStream: AsyncRead + AsyncWrite + Unpin + Send {}
+
+impl<T: AsyncRead + AsyncWrite + Unpin + Send> IpcStream for T {}
+
+/// Accepts IPC clients
+pub enum IpcListener {
+    #[cfg(unix)]
+    Unix(UnixListener),
+    #[cfg(windows)]
+    Pipe(PipeListener),
+}
+
+impl IpcListener {
+    /// Listen at `path`: a socket file on Unix, a pipe name on Windows
+    #[cfg(unix)]
+    pub fn bind(path: &Path) -> Result<Self> {
+        // Remove existing socket file if it exists
+        if path.exists() {
+            std::fs::remove_file(path)
+                .with_context(|| format!("Failed to remove existing socket: {:?}", path))?;
+        }
+
+        // Create parent directory if needed
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)
+                .with_context(|| format!("Failed to create socket directory: {:?}", parent))?;
+        }
+
+        let listener = UnixListener::bind(path)
+            .with_context(|| format!("Failed to bind socket: {:?}", path))?;
+
+        info!("IPC server listening on {:?}", path);
+        Ok(IpcListener::Unix(listener))
+    }
+
+    /// Listen at `path`: a socket file on Unix, a pipe name on Windows
+    #[cfg(windows)]
+    pub fn bind(path: &Path) -> Result<Self> {
+        let listener = PipeListener::create(path)
+            .with_context(|| format!("Failed to create named pipe: {:?}", path))?;
+
+        info!("IPC server listening on {:?}", path);
+        Ok(IpcListener::Pipe(listener))
+    }
+
+    /// Serve a socket bound by someone else (systemd socket activation)
+    #[cfg(unix)]
+    pub fn from_std(listener: std::os::unix::net::UnixListener) -> Result<Self> {
+        let listener = UnixListener::from_std(listener)
+            .context("Failed to use the socket passed in")?;
+        Ok(IpcListener::Unix(listener))
+    }
+
+    /// Wait for the next client
+    pub async fn accept(&self) -> io::Result<Box<dyn IpcStream>> {
+        match self {
+            #[cfg(unix)]
+            IpcListener::Unix(listener) => {
+                let (stream, _addr) = listener.accept().await?;
+                Ok(Box::new(stream))
+            }
+            #[cfg(windows)]
+            IpcListener::Pipe(listener) => listener.accept().await,
+        }
+    }
+}
+
+/// A named pipe that always has an instance waiting for the next client
+///
+/// The pipe's default security lets other accounts open it read-only, so
+/// only the daemon's own account (and administrators) can send requests.
+/// Remote clients are rejected.
+#[cfg(windows)]
+pub struct PipeListener {
+    name: std::ffi::OsString,
+    next: tokio::sync::Mutex<NamedPipeServer>,
+}
+
+#[cfg(windows)]
+impl PipeListener {
+    fn create(name: &Path) -> io::Result<Self> {
+        // Fails if another daemon already serves this pipe
+        let first = ServerOptions::new()
+            .first_pipe_instance(true)
+            .reject_remote_clients(true)
+            .create(name)?;
+        Ok(Self {
+            name: name.as_os_str().to_owned(),
+            next: tokio::sync::Mutex::new(first),
+        })
+    }
+
+    async fn accept(&self) -> io::Result<Box<dyn IpcStream>> {
+        let mut next = self.next.lock().await;
+        next.connect().await?;
+
+        // Have the next instance ready before handing this one out, so
+        // clients never find the pipe missing
+        let instance = ServerOptions::new()
+            .reject_remote_clients(true)
+            .create(&self.name)?;
+        let connected = std::mem::replace(&mut *next, instance);
+        Ok(Box::new(connected))
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+
+    #[tokio::test]
+    async fn test_accepts_clients_on_socket() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("ipc").join("pulsar.sock");
+        // A stale socket from an earlier run is replaced
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(&path, b"").unwrap();
+
+        let listener = IpcListener::bind(&path).unwrap();
+        let client = tokio::spawn({
+            let path = path.clone();
+            async move {
+                let mut stream = tokio::net::UnixStream::connect(&path).await.unwrap();
+                stream.write_all(b"ping\n").await.unwrap();
+                let mut line = String::new();
+                BufReader::new(stream).read_line(&mut line).await.unwrap();
+                line
+            }
+        });
+
+        let stream = listener.accept().await.unwrap();
+        let (reader, mut writer) = tokio::io::split(stream);
+        let mut line = String::new();
+        BufReader::new(reader).read_line(&mut line).await.unwrap();
+        assert_eq!(line, "ping\n");
+        writer.write_all(b"pong\n").await.unwrap();
+
+        assert_eq!(client.await.unwrap(), "pong\n");
+    }
+}
@@ -1,94 +1,272 @@
 //! Pulsar Daemon
 //!
 //! Background service that manages:
-//! - Active terminal sessions (local, SSH, serial)
+//! - Active terminal sessions (local, SSH, serial, log tails)
 //! - Multi-client session sharing
-//! - IPC communication via Unix sockets
+//! - IPC communication via Unix sockets (named pipes on Windows)
 //! - Session persistence and restoration
-//! - File transfers over WebTransport
+//! - File transfers over WebTransport, and an SFTP inbox for legacy clients
 
-use anyhow::Result;
+use anyhow::{bail, Result};
+use pulsar_service::ServiceManager;
+use std::net::SocketAddr;
+use std::path::PathBuf;
 use std::sync::Arc;
-use tokio::signal;
 use tokio::time::{interval, Duration};
 use tracing::{error, info, warn};
 
+mod attach_token;
+mod canary;
+mod cert_manager;
 mod config;
+mod discovery;
 mod file_transfer;
 mod grpc;
+#[cfg(test)]
+mod harness;
+mod health;
 mod ipc;
+mod ipc_listener;
+mod orbit_bridge;
 mod protocol;
+mod relay;
+mod resources;
 mod session_manager;
+mod shutdown;
+mod sizing;
+mod ssh_ca;
+mod tail;
+mod team_directory;
+mod theme;
+mod timeline;
 mod websocket;
 mod webtransport;
 mod workspace;
 
+use attach_token::AttachTokens;
+use cert_manager::CertManager;
 use config::DaemonConfig;
-use file_transfer::{FileTransferHandler, TransferConfig};
+use file_transfer::{FileTransferHandler, ManifestSigner};
+use health::Health;
 use ipc::IpcServer;
+use orbit_bridge::OrbitBridge;
+use relay::Relay;
+use resources::ResourceMonitor;
 use session_manager::SessionManager;
-use workspace::WorkspaceService;
-
-#[tokio::main]
-async fn main() -> Result<()> {
-    // Initialize tracing
-    tracing_subscriber::fmt()
-        .with_env_filter(
-            tracing_subscriber::EnvFilter::try_from_default_env()
-                .unwrap_or_else(|_| tracing_subscriber::EnvFilter::new("info")),
-        )
-        .init();
+use shutdown::Shutdown;
+use ssh_ca::SshCa;
+use team_directory::TeamDirectory;
+use theme::ThemeStore;
+use workspace::{snapshot_changed_workspaces, SnapshotScheduler, WorkspaceService};
+
+/// Time allowed for checkpointing and cleanup after the grace period
+const STOP_WAIT_HINT: Duration = Duration::from_secs(30);
+
+/// Name registered with the Windows Service Control Manager
+#[cfg(windows)]
+const SERVICE_NAME: &str = "PulsarDaemon";
+
+fn main() -> Result<()> {
+    let args = Args::parse()?;
+
+    if args.service {
+        // Started by the Service Control Manager, which reports our state
+        #[cfg(windows)]
+        {
+            pulsar_service::windows::run(SERVICE_NAME, move |service| {
+                tokio::runtime::Runtime::new()?.block_on(run(args, ServiceManager::windows(service)))
+            })?;
+            return Ok(());
+        }
+        #[cfg(not(windows))]
+        bail!("--service is only supported on Windows; use the systemd units instead");
+    }
+
+    tokio::runtime::Runtime::new()?.block_on(run(args, ServiceManager::detect()))
+}
+
+async fn run(args: Args, service: ServiceManager) -> Result<()> {
+    // Load configuration (file, then PULSAR_* overrides)
+    let config = DaemonConfig::load(args.config.as_deref())?;
+    if args.check_config {
+        println!("# Configuration is valid; effective settings:\n");
+        print!("{}", config.to_toml()?);
+        return Ok(());
+    }
+
+    // Initialize tracing (console, plus the rotating log file if configured)
+    let _log_guard = pulsar_log::init(pulsar_log::LogOptions {
+        name: "pulsar-daemon",
+        filter: &config.log.filter(),
+        file: config.log.file.as_ref(),
+        stderr: false,
+    })?;
 
     info!("Starting Pulsar Daemon v{}", env!("CARGO_PKG_VERSION"));
 
-    // Load configuration
-    let config = DaemonConfig::load()?;
-    info!("Configuration loaded from {:?}", config.socket_path);
+    // The IPC socket, when systemd bound it for us (socket activation)
+    #[cfg(unix)]
+    let activated_socket = pulsar_service::systemd::activated_listener()?;
+    #[cfg(unix)]
+    let socket_activated = activated_socket.is_some();
+
+    // Outbound webhooks, with every delivery attempt logged beside the
+    // database
+    let webhooks = Arc::new(pulsar_webhook::Webhooks::new(
+        "pulsar-daemon",
+        &config.webhooks,
+        Some(config.database_path.with_file_name("webhook-deliveries.jsonl")),
+    )?);
 
-    // Initialize session manager
-    let session_manager = Arc::new(SessionManager::new());
+    // Initialize session manager, sharing the focused session's context
+    // with orbitd
+    let mut session_manager = SessionManager::new()
+        .with_webhooks(Arc::clone(&webhooks))
+        .with_size_policy(config.size_policy);
+    if let Some(orbit_socket) = config.orbit_socket.clone() {
+        session_manager =
+            session_manager.with_orbit_bridge(Arc::new(OrbitBridge::new(orbit_socket)));
+    }
+    let session_manager = Arc::new(session_manager);
     info!("Session manager initialized");
 
-    // Initialize file transfer handler
-    let file_transfer = Arc::new(FileTransferHandler::new(TransferConfig::default()));
+    // Initialize file transfer handler, signing batch manifests and
+    // proving the daemon's identity with keys kept beside the database
+    let manifest_signer =
+        ManifestSigner::load_or_create(&config.database_path.with_file_name("transfer-signing.key"))?;
+    let identity =
+        file_transfer::identity::load_or_create(&config.database_path.with_file_name("transfer-identity.key"))?;
+    info!("Transfer identity: {}", identity.peer_id());
+    let file_transfer = Arc::new(
+        FileTransferHandler::new(config.transfers.clone())
+            .with_manifest_signer(Arc::new(manifest_signer))
+            .with_identity(Arc::new(identity))
+            .with_webhooks(Arc::clone(&webhooks)),
+    );
     file_transfer.initialize().await?;
     info!("File transfer handler initialized");
 
     // Initialize workspace service (database)
-    let db_path = dirs::config_dir()
-        .ok_or_else(|| anyhow::anyhow!("Could not determine config directory"))?
-        .join("pulsar")
-        .join("workspaces.db");
-
-    // Ensure parent directory exists
-    if let Some(parent) = db_path.parent() {
-        std::fs::create_dir_all(parent)?;
-    }
+    let db_path = config.database_path.clone();
+
+    // WAL, busy timeout, foreign keys and an integrity check before use
+    let pool = pulsar_db::open(&db_path, &pulsar_db::DbOptions::default()).await?;
 
-    let db_url = format!("sqlite:{}", db_path.display());
-    let pool = sqlx::sqlite::SqlitePoolOptions::new()
-        .max_connections(5)
-        .connect(&db_url)
-        .await?;
+    // Session configs carry hosts and commands; optionally keep them encrypted
+    let cipher = if config.encrypt_at_rest {
+        pulsar_db::FieldCipher::from_keychain("pulsar", "workspaces-db")?
+    } else {
+        pulsar_db::FieldCipher::disabled()
+    };
 
-    let workspace_service = Arc::new(WorkspaceService::new(Arc::new(pool)));
+    let workspace_service = Arc::new(
+        WorkspaceService::new(Arc::new(pool.clone()))
+            .with_cipher(cipher)
+            .with_session_manager(Arc::clone(&session_manager)),
+    );
     workspace_service.initialize().await?;
     info!("Workspace service initialized");
 
+    // Read-only hosts from the team directory, if configured, starting from
+    // the copy cached beside the database
+    let team_directory = TeamDirectory::new(
+        &config.team,
+        config.database_path.with_file_name("team-directory.json"),
+    )?
+    .map(Arc::new);
+    let team_directory_handle = team_directory
+        .as_ref()
+        .map(|team| tokio::spawn(Arc::clone(team).run()));
+
+    // Scheduled automatic snapshots with retention
+    let snapshot_scheduler_handle = config.snapshots.enabled.then(|| {
+        let scheduler = SnapshotScheduler::new(
+            Arc::clone(&workspace_service),
+            config.snapshots.clone(),
+        );
+        tokio::spawn(scheduler.run())
+    });
+
     // TODO: Restore persisted sessions from database
 
-    // Start IPC server
-    let ipc_server = Arc::new(
-        IpcServer::new(&config.socket_path, Arc::clone(&session_manager)).await?,
+    // WebTransport certificate: the configured one, or generated ones whose
+    // hashes are published over IPC
+    let (cert_manager, certs) = match (&config.tls.cert_path, &config.tls.key_path) {
+        (Some(cert_path), Some(key_path)) => (None, cert_manager::load_resolver(cert_path, key_path)?),
+        _ => {
+            let cert_manager = CertManager::new()?;
+            let certs = cert_manager.resolver();
+            (Some(cert_manager), certs)
+        }
+    };
+    let cert_rotation_handle = cert_manager
+        .as_ref()
+        .map(|cert_manager| tokio::spawn(Arc::clone(cert_manager).run_rotation()));
+
+    // Signing key for tokens that let remote clients attach to sessions
+    let attach_tokens = Arc::new(AttachTokens::new()?);
+
+    // Terminal appearance pushed by the desktop and mirrored to remote clients
+    let theme_store = Arc::new(ThemeStore::new());
+
+    // Shutdown countdown, published to clients once a stop is requested
+    let shutdown = Arc::new(Shutdown::new());
+
+    // Subsystem health, reported over IPC and the optional HTTP probe
+    let data_dir = db_path.parent().map(PathBuf::from).unwrap_or_default();
+    let health = Arc::new(
+        Health::new(data_dir, config.health.clone(), Arc::clone(&shutdown))
+            .with_database(pool.clone()),
     );
+
+    // Start IPC server
+    #[cfg(unix)]
+    let ipc_server = match activated_socket {
+        Some(listener) => IpcServer::from_listener(listener, Arc::clone(&session_manager))?,
+        None => IpcServer::new(&config.socket_path, Arc::clone(&session_manager)).await?,
+    };
+    #[cfg(not(unix))]
+    let ipc_server = IpcServer::new(&config.socket_path, Arc::clone(&session_manager)).await?;
+    // Relay between peers that can't connect directly, if configured
+    let relay = Arc::new(Relay::new(config.relay.clone()));
+
+    // CPU and memory of each session's process tree, with threshold alerts
+    let resources = Arc::new(ResourceMonitor::new(config.resources.clone()));
+    tokio::spawn(Arc::clone(&resources).run(Arc::clone(&session_manager), webhooks));
+
+    let mut ipc_server = ipc_server
+        .with_relay(Arc::clone(&relay))
+        .with_resources(resources)
+        .with_attach_tokens(Arc::clone(&attach_tokens))
+        .with_theme_store(Arc::clone(&theme_store))
+        .with_database(pool, db_path.with_file_name("backups"))
+        .with_shutdown(Arc::clone(&shutdown))
+        .with_health(Arc::clone(&health))
+        .with_throttle(file_transfer.throttle().clone())
+        .with_host_directory(
+            Arc::clone(&workspace_service),
+            team_directory,
+            DaemonConfig::declaration_path(args.config.as_deref())?,
+        );
+    if let Some(cert_manager) = &cert_manager {
+        ipc_server = ipc_server.with_cert_manager(Arc::clone(cert_manager));
+    }
+    // Short-lived SSH certificates, if an organizational CA is configured
+    if let Some(ssh_ca) = SshCa::new(&config.ssh_ca)? {
+        ipc_server = ipc_server.with_ssh_ca(Arc::new(ssh_ca));
+    }
+    let ipc_server = Arc::new(ipc_server);
     info!("IPC server initialized");
 
     // Spawn IPC server task
     let ipc_server_handle = {
         let ipc_server = Arc::clone(&ipc_server);
+        let health = Arc::clone(&health);
+        health.server_running("ipc");
         tokio::spawn(async move {
             if let Err(e) = ipc_server.run().await {
                 error!("IPC server error: {}", e);
+                health.server_failed("ipc", e);
             }
         })
     };
@@ -96,10 +274,16 @@ async fn main() -> Result<()> {
     // Spawn WebSocket server task
     let ws_server_handle = {
         let session_manager = Arc::clone(&session_manager);
-        let ws_port = config.websocket_port;
+        let attach_tokens = Arc::clone(&attach_tokens);
+        let theme_store = Arc::clone(&theme_store);
+        let shutdown = Arc::clone(&shutdown);
+        let health = Arc::clone(&health);
+        let ws_addr = SocketAddr::new(config.listen_addr, config.websocket_port);
+        health.server_running("websocket");
         tokio::spawn(async move {
-            if let Err(e) = websocket::start_server(session_manager, ws_port).await {
+            if let Err(e) = websocket::start_server(session_manager, attach_tokens, theme_store, shutdown, ws_addr).await {
                 error!("WebSocket server error: {}", e);
+                health.server_failed("websocket", e);
             }
         })
     };
@@ -107,10 +291,13 @@ async fn main() -> Result<()> {
     // Spawn gRPC server task
     let grpc_server_handle = {
         let session_manager = Arc::clone(&session_manager);
+        let health = Arc::clone(&health);
         let grpc_port = config.grpc_port;
+        health.server_running("grpc");
         tokio::spawn(async move {
             if let Err(e) = grpc::start_server(session_manager, grpc_port).await {
                 error!("gRPC server error: {}", e);
+                health.server_failed("grpc", e);
             }
         })
     };
@@ -119,14 +306,58 @@ async fn main() -> Result<()> {
     let wt_server_handle = {
         let session_manager = Arc::clone(&session_manager);
         let file_transfer = Arc::clone(&file_transfer);
-        let wt_port = config.webtransport_port;
+        let attach_tokens = Arc::clone(&attach_tokens);
+        let certs = Arc::clone(&certs);
+        let health = Arc::clone(&health);
+        let wt_addr = SocketAddr::new(config.listen_addr, config.webtransport_port);
+        if !config.listen_addr.is_loopback() && config.transfers.trusted_senders.is_empty() {
+            warn!("Refusing file transfers from other hosts until transfers.trusted_senders is set");
+        }
+        health.server_running("webtransport");
         tokio::spawn(async move {
-            if let Err(e) = webtransport::start_server(session_manager, file_transfer, wt_port).await {
+            if let Err(e) = webtransport::start_server(session_manager, file_transfer, attach_tokens, certs, wt_addr).await {
                 error!("WebTransport server error: {}", e);
+                health.server_failed("webtransport", e);
             }
         })
     };
 
+    // Spawn the HTTP health probe for supervisors, if configured
+    let probe_handle = config.health.probe_addr.map(|addr| {
+        let health = Arc::clone(&health);
+        tokio::spawn(async move {
+            if let Err(e) = health::start_probe(health, addr).await {
+                error!("Health probe error: {}", e);
+            }
+        })
+    });
+
+    // Spawn the transfer relay, if configured
+    let relay_handle = config.relay.listen_addr.map(|addr| {
+        let health = Arc::clone(&health);
+        health.server_running("relay");
+        tokio::spawn(async move {
+            if let Err(e) = relay::start_server(relay, addr).await {
+                error!("Relay error: {}", e);
+                health.server_failed("relay", e);
+            }
+        })
+    });
+
+    // Spawn the SFTP inbox, if configured
+    let sftp_handle = config.transfers.sftp.listen_addr.map(|addr| {
+        let health = Arc::clone(&health);
+        let file_transfer = Arc::clone(&file_transfer);
+        let transfers = config.transfers.clone();
+        health.server_running("sftp");
+        tokio::spawn(async move {
+            if let Err(e) = file_transfer::sftp::start_server(file_transfer, &transfers, addr).await {
+                error!("SFTP inbox error: {}", e);
+                health.server_failed("sftp", e);
+            }
+        })
+    });
+
     // Spawn cleanup task (runs every 60 seconds)
     let cleanup_handle = {
         let session_manager = Arc::clone(&session_manager);
@@ -139,53 +370,112 @@ async fn main() -> Result<()> {
         })
     };
 
-    // Wait for shutdown signal
+    // Tell the service manager we are serving, and keep its watchdog fed
+    service.ready();
+    let watchdog_handle = service.spawn_watchdog();
+
+    // Wait for shutdown signal (Ctrl+C, SIGTERM or a service stop)
     info!("Daemon running. Press Ctrl+C to stop.");
-    match signal::ctrl_c().await {
-        Ok(()) => {
-            info!("Received shutdown signal");
-        }
-        Err(e) => {
-            error!("Failed to listen for shutdown signal: {}", e);
-        }
+    tokio::select! {
+        _ = shutdown::signal() => info!("Received shutdown signal"),
+        _ = service.stop_requested() => info!("Service stop requested"),
     }
+    service.stopping(
+        "Draining sessions and transfers",
+        Duration::from_secs(config.shutdown_grace_secs) + STOP_WAIT_HINT,
+    );
 
-    // Graceful shutdown
-    info!("Shutting down daemon...");
+    // Refuse new sessions, attaches and transfers, and give attached
+    // clients a countdown to wrap up
+    file_transfer.stop_accepting();
+    let grace = if session_manager.count_clients().await > 0 {
+        Duration::from_secs(config.shutdown_grace_secs)
+    } else {
+        Duration::ZERO
+    };
+    info!("Shutting down daemon in {}s...", grace.as_secs());
+    shutdown.drain(grace).await;
 
-    // Signal IPC server to shutdown
-    ipc_server.shutdown().await;
+    // Keep in-flight transfers resumable after restart
+    match file_transfer.checkpoint_transfers().await {
+        Ok(0) => {}
+        Ok(count) => info!("Checkpointed {} in-flight transfers", count),
+        Err(e) => error!("Failed to checkpoint transfers: {}", e),
+    }
 
-    // Wait for tasks to complete (with timeout)
-    let shutdown_timeout = Duration::from_secs(5);
-    tokio::select! {
-        _ = ipc_server_handle => {
-            info!("IPC server stopped");
-        }
-        _ = ws_server_handle => {
-            info!("WebSocket server stopped");
-        }
-        _ = grpc_server_handle => {
-            info!("gRPC server stopped");
-        }
-        _ = wt_server_handle => {
-            info!("WebTransport server stopped");
-        }
-        _ = tokio::time::sleep(shutdown_timeout) => {
-            warn!("Server shutdown timed out");
+    // Snapshot workspaces with the sessions they hold
+    if let Some(handle) = snapshot_scheduler_handle {
+        handle.abort();
+        match snapshot_changed_workspaces(&workspace_service).await {
+            Ok(count) => info!("Snapshotted {} changed workspaces", count),
+            Err(e) => warn!("Failed to snapshot workspaces on shutdown: {}", e),
         }
     }
 
-    // Abort cleanup task
-    cleanup_handle.abort();
-
-    // TODO: Save session state to database
+    // Stop the servers and background tasks
+    ipc_server.shutdown().await;
+    for handle in [
+        ipc_server_handle,
+        ws_server_handle,
+        grpc_server_handle,
+        wt_server_handle,
+        cleanup_handle,
+    ] {
+        handle.abort();
+    }
+    for handle in [
+        cert_rotation_handle,
+        probe_handle,
+        relay_handle,
+        sftp_handle,
+        team_directory_handle,
+        watchdog_handle,
+    ]
+    .into_iter()
+    .flatten()
+    {
+        handle.abort();
+    }
 
-    // Cleanup socket file
-    if config.socket_path.exists() {
+    // Cleanup socket file, unless systemd owns it
+    #[cfg(unix)]
+    if !socket_activated && config.socket_path.exists() {
         std::fs::remove_file(&config.socket_path).ok();
     }
 
     info!("Pulsar Daemon stopped");
     Ok(())
 }
+
+/// Command-line arguments
+#[derive(Debug, Default)]
+struct Args {
+    /// Config file to use instead of the default location
+    config: Option<PathBuf>,
+    /// Validate the configuration, print the effective settings and exit
+    check_config: bool,
+    /// Run under the Windows Service Control Manager
+    service: bool,
+}
+
+impl Args {
+    fn parse() -> Result<Self> {
+        let mut args = Self::default();
+        let mut argv = std::env::args().skip(1);
+        while let Some(arg) = argv.next() {
+            match arg.as_str() {
+                "--config" => match argv.next() {
+                    Some(path) => args.config = Some(PathBuf::from(path)),
+                    None => bail!("--config needs a path"),
+                },
+                "--check-config" => args.check_config = true,
+                "--service" => args.service = true,
+                _ => bail!(
+                    "Unknown argument: {} (expected --config <path>, --check-config or --service)",
+                    arg
+                ),
+            }
+        }
+        Ok(args)
+    }
+}
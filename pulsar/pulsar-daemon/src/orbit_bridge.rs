@@ -0,0 +1,301 @@
+//! Shares the focused terminal's context with orbitd
+//!
+//! orbitd suggests commands for whatever shell the user is typing in. When
+//! that is a Pulsar terminal, possibly on another host, the bridge tells
+//! orbitd which session has focus, the host it runs on, its shell and the
+//! working directory the session manager follows in its output (see
+//! `terminal_core::cwd`). Updates are only sent when something
+//! changes, and go out in order from a single task so a slow or missing
+//! orbitd never holds up terminal output.
+//!
+//! The bridge also asks orbitd whether a command is destructive before the
+//! daemon types one into a session on the user's behalf.
+
+use serde::Serialize;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+use terminal_core::WorkingDirectory;
+#[cfg(unix)]
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+#[cfg(unix)]
+use tokio::net::UnixStream;
+use tokio::sync::{mpsc, Mutex};
+use tracing::debug;
+use uuid::Uuid;
+
+use crate::session_manager::SessionType;
+
+/// A terminal as orbitd sees it (orbitd's `FocusedTerminal`)
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct TerminalContext {
+    pub session_id: Uuid,
+    pub name: String,
+    pub host: Option<String>,
+    pub cwd: Option<String>,
+    pub shell: Option<String>,
+    /// Host the session itself connects to, restored when a nested ssh exits
+    #[serde(skip)]
+    session_host: Option<String>,
+}
+
+#[derive(Default)]
+struct BridgeState {
+    terminals: HashMap<Uuid, TerminalContext>,
+    focused: Option<Uuid>,
+    /// Last update sent, to skip repeats
+    sent: Option<Option<TerminalContext>>,
+}
+
+pub struct OrbitBridge {
+    state: Mutex<BridgeState>,
+    updates: mpsc::UnboundedSender<Option<TerminalContext>>,
+    local_hostname: Option<String>,
+    socket_path: PathBuf,
+}
+
+impl OrbitBridge {
+    /// Start the bridge; updates are delivered to orbitd at `socket_path`
+    pub fn new(socket_path: PathBuf) -> Self {
+        let (updates, mut pending) = mpsc::unbounded_channel();
+        let focus_socket = socket_path.clone();
+        tokio::spawn(async move {
+            while let Some(terminal) = pending.recv().await {
+                if let Err(e) = send_focus(&focus_socket, &terminal).await {
+                    // orbitd isn't necessarily running
+                    debug!("Couldn't share terminal context with orbitd: {}", e);
+                }
+            }
+        });
+
+        Self {
+            state: Mutex::new(BridgeState::default()),
+            updates,
+            local_hostname: local_hostname().map(str::to_string),
+            socket_path,
+        }
+    }
+
+    /// Whether orbitd's checks find `command` destructive
+    pub async fn is_destructive(&self, command: &str) -> anyhow::Result<bool> {
+        let response = call(
+            &self.socket_path,
+            serde_json::json!({ "CheckCommand": { "command": command } }),
+        )
+        .await?;
+        response["Checked"]["destructive"]
+            .as_bool()
+            .ok_or_else(|| anyhow::anyhow!("orbitd couldn't check the command: {}", response))
+    }
+
+    /// Start tracking a new session
+    pub async fn register(
+        &self,
+        session_id: Uuid,
+        name: &str,
+        session_type: &SessionType,
+        shell: Option<&str>,
+    ) {
+        let host = match session_type {
+            SessionType::Ssh { host, .. } => Some(host.clone()),
+            SessionType::Local | SessionType::Serial { .. } | SessionType::Tail { .. } => None,
+        };
+        // A local session runs the daemon user's login shell
+        let shell = match (shell, &host) {
+            (Some(shell), _) => Some(shell.to_string()),
+            (None, None) => std::env::var("SHELL").ok(),
+            (None, Some(_)) => None,
+        }
+        .map(|shell| shell.rsplit('/').next().unwrap_or_default().to_string());
+
+        self.state.lock().await.terminals.insert(
+            session_id,
+            TerminalContext {
+                session_id,
+                name: name.to_string(),
+                host: host.clone(),
+                cwd: None,
+                shell,
+                session_host: host,
+            },
+        );
+    }
+
+    /// The shell in `session_id` moved to `directory`
+    pub async fn observe_cwd(&self, session_id: Uuid, directory: &WorkingDirectory) {
+        let mut state = self.state.lock().await;
+        let Some(terminal) = state.terminals.get_mut(&session_id) else {
+            return;
+        };
+        terminal.cwd = Some(directory.path.clone());
+        // A shell on another machine, say one reached with a plain `ssh` in
+        // a local session, announces its own hostname
+        terminal.host = match &directory.host {
+            Some(host) if !self.is_local(host) => Some(host.clone()),
+            _ => terminal.session_host.clone(),
+        };
+
+        if state.focused == Some(session_id) {
+            self.publish(&mut state);
+        }
+    }
+
+    /// The user is typing in `session_id`
+    pub async fn focus(&self, session_id: Uuid) {
+        let mut state = self.state.lock().await;
+        if state.focused != Some(session_id) && state.terminals.contains_key(&session_id) {
+            state.focused = Some(session_id);
+            self.publish(&mut state);
+        }
+    }
+
+    /// Stop tracking a session that ended
+    pub async fn remove(&self, session_id: Uuid) {
+        let mut state = self.state.lock().await;
+        state.terminals.remove(&session_id);
+        if state.focused == Some(session_id) {
+            state.focused = None;
+            self.publish(&mut state);
+        }
+    }
+
+    fn is_local(&self, host: &str) -> bool {
+        same_host(host, self.local_hostname.as_deref())
+    }
+
+    fn publish(&self, state: &mut BridgeState) {
+        let current = state
+            .focused
+            .and_then(|id| state.terminals.get(&id))
+            .cloned();
+        if state.sent.as_ref() != Some(&current) {
+            state.sent = Some(current.clone());
+            let _ = self.updates.send(current);
+        }
+    }
+}
+
+async fn send_focus(socket_path: &Path, terminal: &Option<TerminalContext>) -> anyhow::Result<()> {
+    let response = call(socket_path, serde_json::json!({ "TerminalFocus": { "terminal": terminal } })).await?;
+    if response.get("Error").is_some() {
+        anyhow::bail!("orbitd rejected the update: {}", response);
+    }
+    Ok(())
+}
+
+/// Send one request to orbitd and read its response
+#[cfg(unix)]
+async fn call(socket_path: &Path, request: serde_json::Value) -> anyhow::Result<serde_json::Value> {
+    let stream = UnixStream::connect(socket_path).await?;
+    let (reader, mut writer) = stream.into_split();
+
+    let mut request = serde_json::to_vec(&request)?;
+    request.push(b'\n');
+    writer.write_all(&request).await?;
+
+    let mut response = String::new();
+    BufReader::new(reader).read_line(&mut response).await?;
+    Ok(serde_json::from_str(&response)?)
+}
+
+/// orbitd's named pipe frames messages with a length prefix instead of a
+/// newline, which the bridge does not speak yet
+#[cfg(not(unix))]
+async fn call(_socket_path: &Path, _request: serde_json::Value) -> anyhow::Result<serde_json::Value> {
+    anyhow::bail!("Talking to orbitd needs a Unix socket")
+}
+
+/// Whether `host`, as a shell reports it, is this machine
+pub fn is_local_host(host: &str) -> bool {
+    same_host(host, local_hostname())
+}
+
+fn same_host(host: &str, local_hostname: Option<&str>) -> bool {
+    let short = |name: &str| name.split('.').next().unwrap_or_default().to_lowercase();
+    host.is_empty()
+        || host == "localhost"
+        || local_hostname.is_some_and(|local| short(local) == short(host))
+}
+
+fn local_hostname() -> Option<&'static str> {
+    static HOSTNAME: OnceLock<Option<String>> = OnceLock::new();
+    HOSTNAME
+        .get_or_init(|| {
+            let output = std::process::Command::new("hostname").output().ok()?;
+            let name = String::from_utf8(output.stdout).ok()?.trim().to_string();
+            (!name.is_empty()).then_some(name)
+        })
+        .as_deref()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(host: &str, path: &str) -> WorkingDirectory {
+        WorkingDirectory {
+            host: Some(host.to_string()),
+            path: path.to_string(),
+            source: terminal_core::CwdSource::Osc7,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_publishes_focused_terminal_changes() {
+        let (updates, mut sent) = mpsc::unbounded_channel();
+        let bridge = OrbitBridge {
+            state: Mutex::new(BridgeState::default()),
+            updates,
+            local_hostname: Some("laptop".to_string()),
+            socket_path: PathBuf::new(),
+        };
+        let local = Uuid::new_v4();
+        let prod = Uuid::new_v4();
+        bridge
+            .register(local, "local", &SessionType::Local, Some("/bin/zsh"))
+            .await;
+        bridge
+            .register(
+                prod,
+                "prod",
+                &SessionType::Ssh {
+                    host: "prod-1".to_string(),
+                    port: 22,
+                },
+                None,
+            )
+            .await;
+
+        // Output of an unfocused terminal is tracked but not sent
+        bridge.observe_cwd(prod, &at("prod-1", "/srv/app")).await;
+        assert!(sent.try_recv().is_err());
+
+        bridge.focus(prod).await;
+        let update = sent.try_recv().unwrap().unwrap();
+        assert_eq!(update.host.as_deref(), Some("prod-1"));
+        assert_eq!(update.cwd.as_deref(), Some("/srv/app"));
+
+        // Same directory again: nothing new to say
+        bridge.observe_cwd(prod, &at("prod-1", "/srv/app")).await;
+        assert!(sent.try_recv().is_err());
+
+        // `ssh db-1` typed into a local terminal
+        bridge.focus(local).await;
+        assert_eq!(
+            sent.try_recv().unwrap().unwrap().shell.as_deref(),
+            Some("zsh")
+        );
+        bridge.observe_cwd(local, &at("laptop", "/home/me")).await;
+        assert_eq!(sent.try_recv().unwrap().unwrap().host, None);
+        bridge.observe_cwd(local, &at("db-1", "/root")).await;
+        assert_eq!(
+            sent.try_recv().unwrap().unwrap().host.as_deref(),
+            Some("db-1")
+        );
+        bridge.observe_cwd(local, &at("laptop.lan", "/home/me")).await;
+        assert_eq!(sent.try_recv().unwrap().unwrap().host, None);
+
+        bridge.remove(local).await;
+        assert_eq!(sent.try_recv().unwrap(), None);
+    }
+}
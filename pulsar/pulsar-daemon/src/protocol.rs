@@ -2,10 +2,20 @@
 //!
 //! Implements JSON-RPC 2.0 style protocol over Unix sockets
 
+use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::PathBuf;
 use uuid::Uuid;
 
+use crate::attach_token::AttachScope;
+use crate::canary::Canary;
+use crate::cert_manager::CertificateHash;
 use crate::session_manager::{SessionInfo, SessionType};
+use crate::sizing::SizePolicy;
+use crate::workspace::declarative::HostSpec;
+use terminal_core::{KeyPress, SearchQuery, TranscriptFormat};
+use tft_transports::BandwidthLimit;
 
 /// Request message from client to daemon
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -60,6 +70,9 @@ pub struct CreateSessionParams {
 pub struct AttachSessionParams {
     pub session_id: Uuid,
     pub client_id: Uuid,
+    /// Receive output only; input sent as this client is discarded
+    #[serde(default)]
+    pub read_only: bool,
 }
 
 /// Parameters for detach_session method
@@ -74,6 +87,30 @@ pub struct DetachSessionParams {
 pub struct SendInputParams {
     pub session_id: Uuid,
     pub data: String,  // Base64-encoded binary data
+    /// Typing client, which takes the size under the active-controller
+    /// policy
+    #[serde(default)]
+    pub client_id: Option<Uuid>,
+}
+
+/// Parameters for paste method
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PasteParams {
+    pub session_id: Uuid,
+    /// Text as copied; line endings and brackets are handled by the daemon
+    pub text: String,
+    #[serde(default)]
+    pub client_id: Option<Uuid>,
+}
+
+/// Parameters for send_key method
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SendKeyParams {
+    pub session_id: Uuid,
+    #[serde(default)]
+    pub client_id: Option<Uuid>,
+    #[serde(flatten)]
+    pub press: KeyPress,
 }
 
 /// Parameters for receive_output method (streaming)
@@ -89,6 +126,42 @@ pub struct ResizeTerminalParams {
     pub session_id: Uuid,
     pub cols: u16,
     pub rows: u16,
+    /// Client whose window this is; resizes without one share a window
+    #[serde(default)]
+    pub client_id: Option<Uuid>,
+}
+
+/// Parameters for set_size_policy method
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SetSizePolicyParams {
+    pub session_id: Uuid,
+    #[serde(flatten)]
+    pub policy: SizePolicy,
+}
+
+/// Parameters for rerun_command method
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RerunCommandParams {
+    /// Session whose timeline holds the command
+    pub session_id: Uuid,
+    pub command_id: Uuid,
+    /// Sessions to run it in
+    pub target_session_ids: Vec<Uuid>,
+    /// Send even if the destructive check holds it back
+    #[serde(default)]
+    pub confirmed: bool,
+    /// Run in one of the targets first, sending to the rest only if it
+    /// succeeds
+    #[serde(default)]
+    pub canary: Option<Canary>,
+    #[serde(default)]
+    pub client_id: Option<Uuid>,
+}
+
+/// Parameters for session_stats method
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SessionStatsParams {
+    pub session_id: Uuid,
 }
 
 /// Parameters for terminate_session method
@@ -97,6 +170,172 @@ pub struct TerminateSessionParams {
     pub session_id: Uuid,
 }
 
+/// Parameters for focus_session method
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FocusSessionParams {
+    pub session_id: Uuid,
+}
+
+/// Parameters for search_scrollback method
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SearchScrollbackParams {
+    pub session_id: Uuid,
+    #[serde(flatten)]
+    pub query: SearchQuery,
+}
+
+/// Parameters for export_transcript method
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ExportTranscriptParams {
+    pub session_id: Uuid,
+    #[serde(default)]
+    pub format: TranscriptFormat,
+}
+
+/// Parameters for add_bookmark method
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AddBookmarkParams {
+    pub session_id: Uuid,
+    /// Scrollback line to mark; the last line with text when omitted
+    #[serde(default)]
+    pub line: Option<u64>,
+    #[serde(default)]
+    pub note: Option<String>,
+}
+
+/// Parameters for list_bookmarks method
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ListBookmarksParams {
+    pub session_id: Uuid,
+}
+
+/// Parameters for remove_bookmark method
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RemoveBookmarkParams {
+    pub session_id: Uuid,
+    pub bookmark_id: Uuid,
+}
+
+/// Parameters for jump_to_bookmark method
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct JumpToBookmarkParams {
+    pub session_id: Uuid,
+    pub bookmark_id: Uuid,
+    /// Lines of output to return either side of the bookmark
+    #[serde(default = "default_bookmark_context")]
+    pub context: u64,
+}
+
+fn default_bookmark_context() -> u64 {
+    20
+}
+
+/// Parameters for command_timeline method
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CommandTimelineParams {
+    pub session_id: Uuid,
+}
+
+/// Parameters for session_timeline method
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SessionTimelineParams {
+    pub session_id: Uuid,
+    /// Only entries that overlap this range; open-ended when unset
+    #[serde(default)]
+    pub from: Option<DateTime<Utc>>,
+    #[serde(default)]
+    pub to: Option<DateTime<Utc>>,
+}
+
+/// Parameters for take_long_commands method
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TakeLongCommandsParams {
+    /// Commands that ran at least this long are returned
+    pub threshold_secs: u64,
+}
+
+/// Parameters for create_attach_token method
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CreateAttachTokenParams {
+    pub session_id: Uuid,
+    /// Defaults to view-only
+    #[serde(default = "default_attach_scope")]
+    pub scope: AttachScope,
+    pub ttl_seconds: Option<i64>,
+}
+
+fn default_attach_scope() -> AttachScope {
+    AttachScope::View
+}
+
+/// Parameters for set_log_level method
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SetLogLevelParams {
+    /// A level (`debug`) or filter directives (`info,pulsar_daemon::ipc=trace`)
+    pub level: String,
+}
+
+/// Parameters for set_bandwidth_limit method
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SetBandwidthLimitParams {
+    /// Bytes per second; unset or 0 lifts the limit
+    #[serde(default)]
+    pub max_bytes_per_sec: Option<u64>,
+    /// Defaults to one second's worth
+    #[serde(default)]
+    pub burst_bytes: Option<u64>,
+}
+
+/// Parameters for apply_config method
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ApplyConfigParams {
+    /// Declaration to apply; `pulsar.yaml` beside the daemon config if unset
+    #[serde(default)]
+    pub path: Option<PathBuf>,
+    /// Report what would change without changing it
+    #[serde(default)]
+    pub dry_run: bool,
+}
+
+/// Parameters for discover_hosts method
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DiscoverHostsParams {
+    /// Addresses, host names and IPv4 CIDR ranges
+    pub targets: Vec<String>,
+    #[serde(default = "default_ssh_port")]
+    pub port: u16,
+    /// Per connection, for the banner and again for the host key
+    #[serde(default = "default_discovery_timeout_ms")]
+    pub timeout_ms: u64,
+}
+
+fn default_ssh_port() -> u16 {
+    22
+}
+
+fn default_discovery_timeout_ms() -> u64 {
+    2000
+}
+
+/// Parameters for import_hosts method
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ImportHostsParams {
+    /// Name of the workspace created for the hosts
+    pub workspace: String,
+    /// Hosts by alias, as in `pulsar.yaml`
+    pub hosts: BTreeMap<String, HostSpec>,
+    #[serde(default)]
+    pub tags: Vec<String>,
+}
+
+/// Parameters for ssh_certificate method
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SshCertificateParams {
+    /// Login the certificate is for, its principal
+    pub username: String,
+    pub host: String,
+}
+
 // ===== Response types =====
 
 /// Response for create_session
@@ -118,6 +357,36 @@ pub struct StatusResult {
     pub uptime_seconds: u64,
     pub num_sessions: usize,
     pub num_clients: usize,
+    /// Seconds until the daemon stops, once it is shutting down
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub shutting_down_in: Option<u64>,
+}
+
+/// Response for get_webtransport_certs
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WebTransportCertsResult {
+    /// Pass these to `serverCertificateHashes`; includes the upcoming certificate
+    pub hashes: Vec<CertificateHash>,
+}
+
+/// Response for db_backup
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DatabaseBackupResult {
+    pub path: PathBuf,
+}
+
+/// Response for set_log_level
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LogLevelResult {
+    pub level: String,
+    pub previous: String,
+}
+
+/// Response for set_bandwidth_limit; `None` is unlimited
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BandwidthLimitResult {
+    pub limit: Option<BandwidthLimit>,
+    pub previous: Option<BandwidthLimit>,
 }
 
 // ===== Error codes =====
@@ -129,6 +398,9 @@ pub mod error_codes {
     pub const INTERNAL_ERROR: i32 = -32603;
     pub const SESSION_NOT_FOUND: i32 = 1001;
     pub const SESSION_EXISTS: i32 = 1002;
+    pub const SHUTTING_DOWN: i32 = 1003;
+    /// Input from a client attached read-only
+    pub const READ_ONLY: i32 = 1004;
 }
 
 // ===== Helper functions =====
@@ -0,0 +1,496 @@
+//! Relay for transfers between peers that can't reach each other
+//!
+//! Two peers behind NATs both dial out to a daemon they can reach, each
+//! naming the same relay session: a random token they agreed on out of
+//! band. The relay pairs the two connections and copies bytes between them
+//! without interpreting them. Peers run TFT over the spliced stream with
+//! their own chunk encryption and identity proofs, so the relay only ever
+//! sees ciphertext, sizes and timing, and a third party that guesses the
+//! token fails the peers' identity check.
+//!
+//! Each connection opens with one JSON line, `{"session": "<token>"}`, and
+//! is answered with `{"status": "waiting"}` until the other peer arrives,
+//! then `{"status": "paired"}`; from there on the stream belongs to the
+//! peers. A refused connection gets `{"status": "refused", "reason": ...}`.
+//!
+//! Bytes forwarded are counted per session and in total, and reported over
+//! IPC by `relay_status`.
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::net::TcpListener;
+use tokio::sync::oneshot;
+use tracing::{debug, info, warn};
+
+/// Longest opening line accepted from a peer
+const MAX_HELLO_LEN: usize = 512;
+
+/// Session tokens must be at least this long to be hard to guess
+const MIN_TOKEN_LEN: usize = 16;
+
+const MAX_TOKEN_LEN: usize = 128;
+
+/// Relay settings
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RelayConfig {
+    /// Accept peers on this address, e.g. `0.0.0.0:4434`; unset leaves the
+    /// relay off
+    pub listen_addr: Option<SocketAddr>,
+    /// Seconds the first peer waits for the second
+    pub pairing_timeout_secs: u64,
+    /// Sessions relayed or waiting at once
+    pub max_sessions: usize,
+    /// Bytes forwarded per session, both ways together, before it is cut;
+    /// 0 for no limit
+    pub max_bytes_per_session: u64,
+}
+
+impl Default for RelayConfig {
+    fn default() -> Self {
+        Self {
+            listen_addr: None,
+            pairing_timeout_secs: 120,
+            max_sessions: 32,
+            max_bytes_per_session: 0,
+        }
+    }
+}
+
+/// Any stream a peer connects over
+pub trait RelayStream: AsyncRead + AsyncWrite + Unpin + Send {}
+
+impl<S: AsyncRead + AsyncWrite + Unpin + Send> RelayStream for S {}
+
+type BoxedStream = Box<dyn RelayStream>;
+
+#[derive(Deserialize)]
+struct Hello {
+    session: String,
+}
+
+#[derive(Serialize)]
+#[serde(tag = "status", rename_all = "lowercase")]
+enum Status<'a> {
+    Waiting,
+    Paired,
+    Refused { reason: &'a str },
+}
+
+/// Response for relay_status
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RelayStats {
+    /// Peers waiting for their other half
+    pub waiting: usize,
+    pub sessions: Vec<RelaySessionStats>,
+    /// Sessions relayed since the daemon started, finished or not
+    pub sessions_total: u64,
+    /// Bytes forwarded since the daemon started, both ways
+    pub bytes_forwarded: u64,
+}
+
+/// A session being relayed
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RelaySessionStats {
+    /// Short fingerprint of the session token; the token itself is never
+    /// reported
+    pub id: String,
+    pub started_at: String,
+    /// Bytes from the peer that arrived first to the other
+    pub bytes_first_to_second: u64,
+    pub bytes_second_to_first: u64,
+}
+
+struct ActiveSession {
+    started_at: String,
+    first_to_second: AtomicU64,
+    second_to_first: AtomicU64,
+}
+
+impl ActiveSession {
+    fn new() -> Self {
+        Self {
+            started_at: chrono::Utc::now().to_rfc3339(),
+            first_to_second: AtomicU64::new(0),
+            second_to_first: AtomicU64::new(0),
+        }
+    }
+
+    fn total(&self) -> u64 {
+        self.first_to_second.load(Ordering::Relaxed) + self.second_to_first.load(Ordering::Relaxed)
+    }
+}
+
+#[derive(Default)]
+struct State {
+    /// First peers by session token, each waiting for the second's stream
+    waiting: HashMap<String, oneshot::Sender<BoxedStream>>,
+    active: HashMap<String, Arc<ActiveSession>>,
+    sessions_total: u64,
+    /// Bytes of finished sessions; active ones are added on report
+    bytes_finished: u64,
+}
+
+/// Pairs peers and forwards their traffic
+pub struct Relay {
+    config: RelayConfig,
+    state: Mutex<State>,
+}
+
+impl Relay {
+    pub fn new(config: RelayConfig) -> Self {
+        Self {
+            config,
+            state: Mutex::new(State::default()),
+        }
+    }
+
+    fn state(&self) -> std::sync::MutexGuard<'_, State> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Current sessions and totals
+    pub fn stats(&self) -> RelayStats {
+        let state = self.state();
+        let sessions: Vec<_> = state
+            .active
+            .iter()
+            .map(|(token, session)| RelaySessionStats {
+                id: fingerprint(token),
+                started_at: session.started_at.clone(),
+                bytes_first_to_second: session.first_to_second.load(Ordering::Relaxed),
+                bytes_second_to_first: session.second_to_first.load(Ordering::Relaxed),
+            })
+            .collect();
+        let active_bytes: u64 = state.active.values().map(|session| session.total()).sum();
+
+        RelayStats {
+            waiting: state.waiting.len(),
+            sessions,
+            sessions_total: state.sessions_total,
+            bytes_forwarded: state.bytes_finished + active_bytes,
+        }
+    }
+
+    /// Serve one peer connection until its session ends
+    pub async fn handle<S: RelayStream + 'static>(&self, mut stream: S) -> Result<()> {
+        let token = match read_hello(&mut stream).await {
+            Ok(token) => token,
+            Err(e) => {
+                send_status(&mut stream, Status::Refused { reason: &e.to_string() }).await?;
+                return Err(e);
+            }
+        };
+
+        // Either hand our stream to the peer already waiting, or wait
+        enum Role {
+            First(oneshot::Receiver<BoxedStream>),
+            Second(oneshot::Sender<BoxedStream>),
+        }
+        let role = {
+            let mut state = self.state();
+            if let Some(first) = state.waiting.remove(&token) {
+                // Active from here, so no third peer can take the slot
+                state.active.insert(token.clone(), Arc::new(ActiveSession::new()));
+                state.sessions_total += 1;
+                Ok(Role::Second(first))
+            } else if state.active.contains_key(&token) {
+                Err("Session already has two peers")
+            } else if state.waiting.len() + state.active.len() >= self.config.max_sessions {
+                Err("Relay is at capacity")
+            } else {
+                let (sender, receiver) = oneshot::channel();
+                state.waiting.insert(token.clone(), sender);
+                Ok(Role::First(receiver))
+            }
+        };
+
+        match role {
+            Err(reason) => {
+                send_status(&mut stream, Status::Refused { reason }).await?;
+                anyhow::bail!("Refused relay peer: {}", reason)
+            }
+            Ok(Role::Second(first)) => {
+                if let Err(e) = send_status(&mut stream, Status::Paired).await {
+                    self.state().active.remove(&token);
+                    return Err(e);
+                }
+                // The first peer's task forwards from here on
+                if first.send(Box::new(stream)).is_err() {
+                    self.state().active.remove(&token);
+                    debug!("Relay peer {} left as its partner arrived", fingerprint(&token));
+                }
+                Ok(())
+            }
+            Ok(Role::First(receiver)) => {
+                send_status(&mut stream, Status::Waiting).await?;
+                let timeout = Duration::from_secs(self.config.pairing_timeout_secs);
+                let second = tokio::time::timeout(timeout, receiver).await;
+                self.state().waiting.remove(&token);
+                match second {
+                    Ok(Ok(second)) => {
+                        if let Err(e) = send_status(&mut stream, Status::Paired).await {
+                            self.state().active.remove(&token);
+                            return Err(e);
+                        }
+                        self.forward(&token, Box::new(stream), second).await;
+                        Ok(())
+                    }
+                    _ => {
+                        let reason = "No peer arrived in time";
+                        send_status(&mut stream, Status::Refused { reason }).await?;
+                        anyhow::bail!("Relay session {}: {}", fingerprint(&token), reason)
+                    }
+                }
+            }
+        }
+    }
+
+    /// Copy both ways until both peers are done; an error either way, or
+    /// the byte limit, closes both
+    async fn forward(&self, token: &str, first: BoxedStream, second: BoxedStream) {
+        let session = self.state().active.get(token).cloned();
+        let Some(session) = session else {
+            return;
+        };
+        let id = fingerprint(token);
+        info!("Relaying session {}", id);
+
+        let limit = self.config.max_bytes_per_session;
+        let (first_read, first_write) = tokio::io::split(first);
+        let (second_read, second_write) = tokio::io::split(second);
+        let forward = pump(first_read, second_write, &session, &session.first_to_second, limit);
+        let back = pump(second_read, first_write, &session, &session.second_to_first, limit);
+        tokio::pin!(forward, back);
+
+        let (mut forward_done, mut back_done) = (false, false);
+        while !(forward_done && back_done) {
+            let result = tokio::select! {
+                result = &mut forward, if !forward_done => {
+                    forward_done = true;
+                    result
+                }
+                result = &mut back, if !back_done => {
+                    back_done = true;
+                    result
+                }
+            };
+            if let Err(e) = result {
+                warn!("Relay session {}: {}", id, e);
+                break;
+            }
+        }
+
+        let mut state = self.state();
+        state.active.remove(token);
+        state.bytes_finished += session.total();
+        info!("Relay session {} ended after {} bytes", id, session.total());
+    }
+}
+
+/// Copy `reader` to `writer`, counting into `counter`, until EOF or the
+/// session's bytes reach `limit`
+async fn pump(
+    mut reader: impl AsyncRead + Unpin,
+    mut writer: impl AsyncWrite + Unpin,
+    session: &ActiveSession,
+    counter: &AtomicU64,
+    limit: u64,
+) -> Result<()> {
+    let mut buf = vec![0u8; 64 * 1024];
+    let result = loop {
+        if limit > 0 && session.total() >= limit {
+            break Err(anyhow::anyhow!("Byte limit of {} reached", limit));
+        }
+        let n = match reader.read(&mut buf).await {
+            Ok(0) => break Ok(()),
+            Ok(n) => n,
+            Err(e) => break Err(e.into()),
+        };
+        if let Err(e) = writer.write_all(&buf[..n]).await {
+            break Err(e.into());
+        }
+        counter.fetch_add(n as u64, Ordering::Relaxed);
+    };
+    // Pass the end of the stream on, so the other peer sees EOF too
+    let _ = writer.shutdown().await;
+    result
+}
+
+/// Read the opening line one byte at a time, so nothing the peer sends
+/// after it is consumed here
+async fn read_hello(stream: &mut (impl AsyncRead + Unpin)) -> Result<String> {
+    let mut line = Vec::new();
+    loop {
+        let byte = stream.read_u8().await.context("Connection closed before the hello")?;
+        if byte == b'\n' {
+            break;
+        }
+        if line.len() == MAX_HELLO_LEN {
+            anyhow::bail!("Hello longer than {} bytes", MAX_HELLO_LEN);
+        }
+        line.push(byte);
+    }
+
+    let hello: Hello = serde_json::from_slice(&line).context("Malformed hello")?;
+    let len = hello.session.len();
+    if !(MIN_TOKEN_LEN..=MAX_TOKEN_LEN).contains(&len) {
+        anyhow::bail!(
+            "Session token must be {} to {} characters",
+            MIN_TOKEN_LEN,
+            MAX_TOKEN_LEN
+        );
+    }
+    Ok(hello.session)
+}
+
+async fn send_status(stream: &mut (impl AsyncWrite + Unpin), status: Status<'_>) -> Result<()> {
+    let mut line = serde_json::to_vec(&status)?;
+    line.push(b'\n');
+    stream.write_all(&line).await?;
+    stream.flush().await?;
+    Ok(())
+}
+
+/// Identifies a session in logs and stats without revealing its token
+fn fingerprint(token: &str) -> String {
+    blake3::hash(token.as_bytes()).to_hex()[..12].to_string()
+}
+
+/// Accept relay peers on `addr`
+pub async fn start_server(relay: Arc<Relay>, addr: SocketAddr) -> Result<()> {
+    let listener = TcpListener::bind(addr)
+        .await
+        .with_context(|| format!("Failed to bind relay to {}", addr))?;
+    info!("Relay listening on {}", addr);
+
+    loop {
+        let (stream, peer) = listener.accept().await?;
+        let _ = stream.set_nodelay(true);
+        let relay = Arc::clone(&relay);
+        tokio::spawn(async move {
+            if let Err(e) = relay.handle(stream).await {
+                debug!("Relay peer {}: {:#}", peer, e);
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::{duplex, AsyncBufReadExt, BufReader, DuplexStream};
+
+    const TOKEN: &str = "0123456789abcdef0123";
+
+    /// Connect a peer to `relay`, returning its end and the relay's answer
+    async fn connect(relay: &Arc<Relay>, token: &str) -> BufReader<DuplexStream> {
+        let (mut peer, server) = duplex(256 * 1024);
+        let relay = Arc::clone(relay);
+        tokio::spawn(async move { relay.handle(server).await });
+        peer.write_all(format!("{{\"session\":\"{}\"}}\n", token).as_bytes())
+            .await
+            .unwrap();
+        BufReader::new(peer)
+    }
+
+    async fn status(peer: &mut BufReader<DuplexStream>) -> String {
+        let mut line = String::new();
+        peer.read_line(&mut line).await.unwrap();
+        line
+    }
+
+    #[tokio::test]
+    async fn test_pairs_peers_and_counts_bytes() {
+        let relay = Arc::new(Relay::new(RelayConfig::default()));
+
+        let mut first = connect(&relay, TOKEN).await;
+        assert!(status(&mut first).await.contains("waiting"));
+        let mut second = connect(&relay, TOKEN).await;
+        assert!(status(&mut second).await.contains("paired"));
+        assert!(status(&mut first).await.contains("paired"));
+
+        // A third peer can't join, or take over, the session
+        let mut third = connect(&relay, TOKEN).await;
+        assert!(status(&mut third).await.contains("refused"));
+
+        first.write_all(b"ciphertext").await.unwrap();
+        let mut received = [0u8; 10];
+        second.read_exact(&mut received).await.unwrap();
+        assert_eq!(&received, b"ciphertext");
+        second.write_all(b"ack").await.unwrap();
+        let mut received = [0u8; 3];
+        first.read_exact(&mut received).await.unwrap();
+
+        let stats = relay.stats();
+        assert_eq!(stats.sessions.len(), 1);
+        assert_eq!(stats.sessions[0].bytes_first_to_second, 10);
+        assert_eq!(stats.sessions[0].bytes_second_to_first, 3);
+        assert!(!stats.sessions[0].id.contains(TOKEN));
+
+        // Each side's end of stream is passed on; once both are done, so
+        // is the session
+        first.shutdown().await.unwrap();
+        let mut rest = Vec::new();
+        second.read_to_end(&mut rest).await.unwrap();
+        second.shutdown().await.unwrap();
+        first.read_to_end(&mut rest).await.unwrap();
+        while !relay.stats().sessions.is_empty() {
+            tokio::task::yield_now().await;
+        }
+        let stats = relay.stats();
+        assert!(stats.sessions.is_empty());
+        assert_eq!((stats.sessions_total, stats.bytes_forwarded), (1, 13));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_refuses_bad_hello_and_lonely_peers() {
+        let relay = Arc::new(Relay::new(RelayConfig {
+            max_sessions: 1,
+            ..Default::default()
+        }));
+
+        let mut short = connect(&relay, "guessable").await;
+        assert!(status(&mut short).await.contains("refused"));
+
+        let mut lonely = connect(&relay, TOKEN).await;
+        assert!(status(&mut lonely).await.contains("waiting"));
+        let mut crowded = connect(&relay, "another-session-token").await;
+        assert!(status(&mut crowded).await.contains("capacity"));
+
+        tokio::time::advance(Duration::from_secs(121)).await;
+        assert!(status(&mut lonely).await.contains("No peer arrived"));
+        assert_eq!(relay.stats().waiting, 0);
+    }
+
+    #[tokio::test]
+    async fn test_byte_limit_cuts_session() {
+        let relay = Arc::new(Relay::new(RelayConfig {
+            max_bytes_per_session: 4,
+            ..Default::default()
+        }));
+        let mut first = connect(&relay, TOKEN).await;
+        let mut second = connect(&relay, TOKEN).await;
+        status(&mut first).await;
+        status(&mut first).await;
+        status(&mut second).await;
+
+        first.write_all(b"12345678").await.unwrap();
+        first.flush().await.unwrap();
+        let mut received = Vec::new();
+        second.read_to_end(&mut received).await.unwrap();
+        assert_eq!(received, b"12345678");
+
+        // Anything after the limit is dropped and the session closed
+        let _ = first.write_all(b"more").await;
+        let mut rest = Vec::new();
+        first.read_to_end(&mut rest).await.unwrap();
+        assert!(rest.is_empty());
+    }
+}
@@ -0,0 +1,321 @@
+//! Per-session resource usage
+//!
+//! Every few seconds the daemon samples CPU and memory of each session's
+//! process tree: its shell and everything started from it (for SSH
+//! sessions, the local `ssh` client and whatever it forwards to). The
+//! latest sample is served by `session_stats`. With thresholds configured,
+//! a `session.resource_alert` webhook is sent when a session's tree goes
+//! over one, and again only once it has dropped back under.
+
+use chrono::{DateTime, Utc};
+use pulsar_webhook::Webhooks;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use sysinfo::{ProcessRefreshKind, ProcessesToUpdate, System};
+use tokio::sync::RwLock;
+use tracing::{error, warn};
+use uuid::Uuid;
+
+use crate::session_manager::SessionManager;
+
+/// Processes listed in a session's usage, busiest first
+const TOP_PROCESSES: usize = 5;
+
+/// Sampling and alert thresholds
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ResourceConfig {
+    /// Seconds between samples
+    pub sample_secs: u64,
+    /// Percent of one core a session's processes may use together before
+    /// an alert; unset sends none
+    pub cpu_alert_percent: Option<f32>,
+    /// Megabytes a session's processes may hold together before an alert
+    pub memory_alert_mb: Option<u64>,
+}
+
+impl Default for ResourceConfig {
+    fn default() -> Self {
+        Self {
+            sample_secs: 15,
+            cpu_alert_percent: None,
+            memory_alert_mb: None,
+        }
+    }
+}
+
+/// One process of a session's tree
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ProcessUsage {
+    pub pid: u32,
+    pub name: String,
+    /// Percent of one core since the previous sample
+    pub cpu_percent: f32,
+    pub memory_bytes: u64,
+}
+
+/// Response for session_stats
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SessionUsage {
+    pub session_id: Uuid,
+    pub sampled_at: DateTime<Utc>,
+    /// Processes in the tree, the shell included
+    pub processes: usize,
+    pub cpu_percent: f32,
+    pub memory_bytes: u64,
+    /// Busiest processes first
+    pub top: Vec<ProcessUsage>,
+}
+
+/// What an alert is about
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Resource {
+    Cpu,
+    Memory,
+}
+
+/// A process as sampled, with its parent
+#[derive(Debug, Clone)]
+pub struct ProcessSample {
+    pub pid: u32,
+    pub parent: Option<u32>,
+    pub usage: ProcessUsage,
+}
+
+/// Usage of the processes descended from `root`, `root` included
+pub fn tree_usage(
+    session_id: Uuid,
+    root: u32,
+    samples: &[ProcessSample],
+    sampled_at: DateTime<Utc>,
+) -> Option<SessionUsage> {
+    let mut children: HashMap<u32, Vec<&ProcessSample>> = HashMap::new();
+    for sample in samples {
+        if let Some(parent) = sample.parent {
+            children.entry(parent).or_default().push(sample);
+        }
+    }
+
+    let mut tree = vec![samples.iter().find(|sample| sample.pid == root)?];
+    let mut i = 0;
+    while i < tree.len() {
+        if let Some(kids) = children.get(&tree[i].pid) {
+            tree.extend(kids.iter().copied());
+        }
+        i += 1;
+    }
+
+    let mut top: Vec<ProcessUsage> = tree.iter().map(|sample| sample.usage.clone()).collect();
+    top.sort_by(|a, b| {
+        b.cpu_percent
+            .total_cmp(&a.cpu_percent)
+            .then(b.memory_bytes.cmp(&a.memory_bytes))
+    });
+    let usage = SessionUsage {
+        session_id,
+        sampled_at,
+        processes: top.len(),
+        cpu_percent: top.iter().map(|process| process.cpu_percent).sum(),
+        memory_bytes: top.iter().map(|process| process.memory_bytes).sum(),
+        top: top.into_iter().take(TOP_PROCESSES).collect(),
+    };
+    Some(usage)
+}
+
+/// Samples sessions' process trees and raises alerts
+pub struct ResourceMonitor {
+    config: ResourceConfig,
+    system: Arc<Mutex<System>>,
+    latest: RwLock<HashMap<Uuid, SessionUsage>>,
+    /// Thresholds each session is currently over, so each crossing is
+    /// reported once
+    over: Mutex<HashSet<(Uuid, Resource)>>,
+}
+
+impl ResourceMonitor {
+    pub fn new(config: ResourceConfig) -> Self {
+        Self {
+            config,
+            system: Arc::new(Mutex::new(System::new())),
+            latest: RwLock::new(HashMap::new()),
+            over: Mutex::new(HashSet::new()),
+        }
+    }
+
+    /// The latest sample of a session; None before the first sample or for
+    /// sessions without a PTY
+    pub async fn usage(&self, session_id: Uuid) -> Option<SessionUsage> {
+        self.latest.read().await.get(&session_id).cloned()
+    }
+
+    /// Sample every `sample_secs` until the daemon stops
+    pub async fn run(self: Arc<Self>, session_manager: Arc<SessionManager>, webhooks: Arc<Webhooks>) {
+        let mut interval = tokio::time::interval(Duration::from_secs(self.config.sample_secs));
+        loop {
+            interval.tick().await;
+
+            let system = Arc::clone(&self.system);
+            let samples = match tokio::task::spawn_blocking(move || sample(&system)).await {
+                Ok(samples) => samples,
+                Err(e) => {
+                    error!("Failed to sample processes: {}", e);
+                    continue;
+                }
+            };
+
+            let now = Utc::now();
+            let mut latest = HashMap::new();
+            for (session_id, name, root) in session_manager.process_roots().await {
+                let Some(usage) = tree_usage(session_id, root, &samples, now) else {
+                    continue;
+                };
+                for (resource, value, threshold) in self.crossed(&usage) {
+                    warn!(
+                        "Session {} ({}) is over its {:?} threshold: {} > {}",
+                        session_id, name, resource, value, threshold
+                    );
+                    webhooks.notify(
+                        pulsar_webhook::SESSION_RESOURCE_ALERT,
+                        serde_json::json!({
+                            "session_id": session_id,
+                            "name": name,
+                            "resource": resource,
+                            "value": value,
+                            "threshold": threshold,
+                            "top": usage.top,
+                        }),
+                    );
+                }
+                latest.insert(session_id, usage);
+            }
+
+            // Forget sessions that have ended
+            self.over
+                .lock()
+                .unwrap()
+                .retain(|(session_id, _)| latest.contains_key(session_id));
+            *self.latest.write().await = latest;
+        }
+    }
+
+    /// Thresholds `usage` has newly gone over, with the value and limit
+    fn crossed(&self, usage: &SessionUsage) -> Vec<(Resource, f64, f64)> {
+        let memory_mb = usage.memory_bytes as f64 / (1024.0 * 1024.0);
+        let checks = [
+            (
+                Resource::Cpu,
+                usage.cpu_percent as f64,
+                self.config.cpu_alert_percent.map(f64::from),
+            ),
+            (
+                Resource::Memory,
+                memory_mb,
+                self.config.memory_alert_mb.map(|mb| mb as f64),
+            ),
+        ];
+
+        let mut over = self.over.lock().unwrap();
+        let mut crossed = Vec::new();
+        for (resource, value, threshold) in checks {
+            let Some(threshold) = threshold else {
+                continue;
+            };
+            let key = (usage.session_id, resource);
+            if value <= threshold {
+                over.remove(&key);
+            } else if over.insert(key) {
+                crossed.push((resource, value, threshold));
+            }
+        }
+        crossed
+    }
+}
+
+/// CPU and memory of every process, CPU measured since the last call
+fn sample(system: &Mutex<System>) -> Vec<ProcessSample> {
+    let mut system = system.lock().unwrap();
+    system.refresh_processes_specifics(
+        ProcessesToUpdate::All,
+        ProcessRefreshKind::new().with_cpu().with_memory(),
+    );
+    system
+        .processes()
+        .iter()
+        .map(|(pid, process)| ProcessSample {
+            pid: pid.as_u32(),
+            parent: process.parent().map(|parent| parent.as_u32()),
+            usage: ProcessUsage {
+                pid: pid.as_u32(),
+                name: process.name().to_string_lossy().into_owned(),
+                cpu_percent: process.cpu_usage(),
+                memory_bytes: process.memory(),
+            },
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn process(pid: u32, parent: Option<u32>, name: &str, cpu: f32, mb: u64) -> ProcessSample {
+        ProcessSample {
+            pid,
+            parent,
+            usage: ProcessUsage {
+                pid,
+                name: name.to_string(),
+                cpu_percent: cpu,
+                memory_bytes: mb * 1024 * 1024,
+            },
+        }
+    }
+
+    fn processes() -> Vec<ProcessSample> {
+        vec![
+            process(1, None, "init", 0.5, 10),
+            process(100, Some(1), "bash", 0.1, 5),
+            process(101, Some(100), "cargo", 20.0, 200),
+            process(102, Some(101), "rustc", 180.0, 1500),
+            process(200, Some(1), "bash", 50.0, 5),
+        ]
+    }
+
+    #[test]
+    fn test_tree_usage() {
+        let session_id = Uuid::new_v4();
+        let usage = tree_usage(session_id, 100, &processes(), Utc::now()).unwrap();
+        assert_eq!(usage.processes, 3);
+        assert!((usage.cpu_percent - 200.1).abs() < 0.01);
+        assert_eq!(usage.memory_bytes, 1705 * 1024 * 1024);
+        let names: Vec<_> = usage.top.iter().map(|process| process.name.as_str()).collect();
+        assert_eq!(names, ["rustc", "cargo", "bash"]);
+
+        // The shell has exited
+        assert!(tree_usage(session_id, 300, &processes(), Utc::now()).is_none());
+    }
+
+    #[test]
+    fn test_alerts_once_per_crossing() {
+        let monitor = ResourceMonitor::new(ResourceConfig {
+            memory_alert_mb: Some(1024),
+            ..ResourceConfig::default()
+        });
+        let session_id = Uuid::new_v4();
+        let mut usage = tree_usage(session_id, 100, &processes(), Utc::now()).unwrap();
+
+        let crossed = monitor.crossed(&usage);
+        assert_eq!(crossed.len(), 1);
+        assert_eq!(crossed[0].0, Resource::Memory);
+        assert!(monitor.crossed(&usage).is_empty());
+
+        // Back under, then over again
+        usage.memory_bytes = 0;
+        assert!(monitor.crossed(&usage).is_empty());
+        usage.memory_bytes = 2048 * 1024 * 1024;
+        assert_eq!(monitor.crossed(&usage).len(), 1);
+    }
+}
@@ -6,20 +6,41 @@
 //! - Automatic cleanup of dead sessions
 //! - Multi-client support (multiple clients can attach to same session)
 
-use anyhow::{anyhow, Result};
+use anyhow::{anyhow, bail, Result};
 use chrono::{DateTime, Utc};
+use pulsar_webhook::Webhooks;
 use serde::{Deserialize, Serialize};
-use std::collections::{HashMap, HashSet};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::path::PathBuf;
 use std::sync::Arc;
-use terminal_core::{SessionConfig, TerminalSession};
-use tokio::sync::{broadcast, RwLock};
-use tokio::time::{sleep, Duration};
-use tracing::{debug, error};
+use terminal_core::{
+    encode_paste, render_transcript, CommandEvent, CommandSource, CommandTracker, CwdTracker, KeyPress,
+    KeyboardTracker, PasteModeTracker, Scrollback, SearchQuery, SearchResults, SessionConfig,
+    TerminalSession, TranscriptFormat, WorkingDirectory,
+};
+use tokio::sync::{broadcast, mpsc, RwLock};
+use tokio::time::{sleep, timeout, Duration, Instant};
+use tracing::{debug, error, info, warn};
 use uuid::Uuid;
 
+use crate::canary::{Canary, CanaryReport};
+use crate::orbit_bridge::{is_local_host, OrbitBridge};
+use crate::sizing::{SizeArbiter, SizeChange, SizePolicy, TerminalSize};
+use crate::tail::{self, TailSource};
+use crate::timeline::{self, ActivityLog, ConnectionChange, SessionTimeline, TransferOutcome};
+
 /// Unique identifier for connected clients
 pub type ClientId = Uuid;
 
+/// Commands kept in each session's timeline
+const TIMELINE_LIMIT: usize = 1000;
+
+/// How often a canary's timeline is checked for its command finishing
+const CANARY_POLL: Duration = Duration::from_millis(100);
+
+/// Told to read-only clients whose input was discarded
+pub const READ_ONLY_NOTICE: &str = "This session is view-only; input was discarded";
+
 /// Session state
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 pub enum SessionState {
@@ -40,6 +61,8 @@ pub enum SessionType {
     Ssh { host: String, port: u16 },
     /// Serial port connection
     Serial { device: String },
+    /// Log files followed into one labelled output stream (no PTY)
+    Tail { sources: Vec<TailSource> },
 }
 
 /// Extended session data with lifecycle management
@@ -47,14 +70,253 @@ pub struct SessionData {
     pub id: Uuid,
     pub name: String,
     pub session_type: SessionType,
-    pub terminal_session: Arc<RwLock<TerminalSession>>,
+    /// The session's PTY; tail sessions have none
+    pub terminal_session: Option<Arc<RwLock<TerminalSession>>>,
     pub created_at: DateTime<Utc>,
     pub last_active: Arc<RwLock<DateTime<Utc>>>,
     pub state: Arc<RwLock<SessionState>>,
     /// Set of client IDs currently attached to this session
     pub clients: Arc<RwLock<HashSet<ClientId>>>,
+    /// Attached clients that may only watch; their input is discarded
+    pub read_only: Arc<RwLock<HashSet<ClientId>>>,
     /// Broadcast channel for PTY output (all attached clients receive)
     pub output_broadcast: broadcast::Sender<Vec<u8>>,
+    /// Shell's working directory, followed from its output
+    pub cwd: Arc<RwLock<Option<WorkingDirectory>>>,
+    /// Recent output as plain text, kept while no client is attached
+    pub scrollback: Arc<RwLock<Scrollback>>,
+    /// Marked lines, in the order they were added
+    pub bookmarks: Arc<RwLock<Vec<Bookmark>>>,
+    /// Follows commands in the session's output and input
+    pub commands: Arc<RwLock<CommandTracker>>,
+    /// Commands run in the session, oldest first
+    pub timeline: Arc<RwLock<VecDeque<CommandRecord>>>,
+    /// Transfers and clients coming and going, for the session timeline
+    pub activity: Arc<RwLock<ActivityLog>>,
+    /// Whether the session's program wants pastes bracketed
+    pub paste_mode: Arc<RwLock<PasteModeTracker>>,
+    /// Keyboard protocol the session's program negotiated
+    pub keyboard: Arc<RwLock<KeyboardTracker>>,
+    /// Picks the PTY size from the attached clients' windows
+    pub sizing: Arc<RwLock<SizeArbiter>>,
+    /// Announces every change of PTY size, with the clients it letterboxes
+    pub size_events: broadcast::Sender<SizeChange>,
+}
+
+impl SessionData {
+    /// The session's PTY, for sessions that take input
+    pub fn terminal(&self) -> Result<&Arc<RwLock<TerminalSession>>> {
+        self.terminal_session
+            .as_ref()
+            .ok_or_else(|| anyhow!("Session {} has no terminal", self.id))
+    }
+
+    /// Whether input from a client may reach the PTY; input that names no
+    /// client is only refused when the session has no PTY
+    pub async fn allows_input(&self, client_id: Option<ClientId>) -> bool {
+        let read_only = match client_id {
+            Some(client_id) => self.read_only.read().await.contains(&client_id),
+            None => false,
+        };
+        self.terminal_session.is_some() && !read_only
+    }
+
+    /// Send input to the session's PTY
+    pub async fn write_input(&self, data: &[u8]) -> Result<usize> {
+        let written = self.terminal()?.read().await.write(data)?;
+        let started = self.commands.write().await.input(data);
+        if let Some(event) = started {
+            self.record_commands(vec![event]).await;
+        }
+        Ok(written)
+    }
+
+    /// Paste text into the session's PTY, bracketed if its program asked;
+    /// returns the bytes written and whether they were bracketed
+    pub async fn paste(&self, text: &str) -> Result<(usize, bool)> {
+        let bracketed = self.paste_mode.read().await.bracketed();
+        let written = self.write_input(&encode_paste(text, bracketed)).await?;
+        Ok((written, bracketed))
+    }
+
+    /// Send a key press to the session's PTY, encoded for the keyboard
+    /// protocol its program asked for
+    pub async fn send_key(&self, press: &KeyPress) -> Result<usize> {
+        let data = self.keyboard.read().await.encode(press);
+        self.write_input(&data).await
+    }
+
+    /// Report a client's window size; returns the size the PTY now has
+    pub async fn resize(&self, client_id: ClientId, size: TerminalSize) -> Result<SizeChange> {
+        let mut sizing = self.sizing.write().await;
+        // Watchers are letterboxed rather than shrink the terminal for
+        // whoever is typing
+        if !self.read_only.read().await.contains(&client_id) {
+            let change = sizing.resize(client_id, size);
+            self.apply_size(change).await?;
+        }
+        sizing
+            .current()
+            .cloned()
+            .ok_or_else(|| anyhow!("Session {} has no size", self.id))
+    }
+
+    /// Credit a client with input, which hands it the size under
+    /// `SizePolicy::ActiveController`
+    pub async fn client_active(&self, client_id: Option<ClientId>) {
+        let Some(client_id) = client_id else {
+            return;
+        };
+        let change = self.sizing.write().await.activity(client_id);
+        if let Err(e) = self.apply_size(change).await {
+            error!("Failed to resize session {}: {}", self.id, e);
+        }
+    }
+
+    /// Change how the PTY size is picked; returns the resulting size, if
+    /// one is known yet
+    pub async fn set_size_policy(&self, policy: SizePolicy) -> Result<Option<SizeChange>> {
+        policy.validate()?;
+        let mut sizing = self.sizing.write().await;
+        let change = sizing.set_policy(policy);
+        self.apply_size(change).await?;
+        Ok(sizing.current().cloned())
+    }
+
+    /// Resize the PTY to a newly picked size and tell the clients
+    async fn apply_size(&self, change: Option<SizeChange>) -> Result<()> {
+        let Some(change) = change else {
+            return Ok(());
+        };
+        // Tail sessions have no PTY to resize, but clients still line up
+        if let Some(terminal) = &self.terminal_session {
+            terminal.read().await.resize(change.cols, change.rows)?;
+        }
+        debug!("Session {} sized {}x{} ({:?})", self.id, change.cols, change.rows, change.policy);
+        let _ = self.size_events.send(change);
+        Ok(())
+    }
+
+    /// Type `command` as the canary of a fan-out, wait for it to finish
+    /// and judge how it did
+    async fn run_canary(&self, command: &str, canary: &Canary) -> Result<CanaryReport> {
+        let pattern = canary.pattern()?;
+        let first_line = self.scrollback.read().await.last_line();
+        let sent_at = Utc::now();
+        let started = Instant::now();
+        self.write_input(format!("{}\r", command).as_bytes()).await?;
+
+        let deadline = started + Duration::from_secs(canary.timeout_secs);
+        let finished = loop {
+            let record = self
+                .timeline
+                .read()
+                .await
+                .iter()
+                .rev()
+                .find(|record| record.started_at >= sent_at)
+                .cloned();
+            match record {
+                Some(record) if record.finished_at.is_some() => break Some(record),
+                _ if Instant::now() >= deadline => break None,
+                _ => sleep(CANARY_POLL).await,
+            }
+        };
+
+        // The first line has the prompt and the command; once it finished,
+        // the last is the next prompt
+        let scrollback = self.scrollback.read().await;
+        let last_line = match finished {
+            Some(_) => scrollback.last_line().saturating_sub(1),
+            None => scrollback.last_line(),
+        };
+        let output = scrollback
+            .range(first_line + 1, last_line)
+            .into_iter()
+            .map(|(_, text)| text)
+            .collect();
+        let elapsed_ms = started.elapsed().as_millis() as u64;
+        Ok(canary.judge(pattern.as_ref(), finished.as_ref(), output, elapsed_ms))
+    }
+
+    async fn record_commands(&self, events: Vec<CommandEvent>) {
+        let mut timeline = self.timeline.write().await;
+        let now = Utc::now();
+        for event in events {
+            match event {
+                CommandEvent::Started { command, source } => {
+                    debug!("Session {} started `{}`", self.id, command);
+                    if timeline.len() == TIMELINE_LIMIT {
+                        timeline.pop_front();
+                    }
+                    timeline.push_back(CommandRecord {
+                        id: Uuid::new_v4(),
+                        command,
+                        source,
+                        started_at: now,
+                        finished_at: None,
+                        duration_ms: 0,
+                        exit_code: None,
+                        notified: false,
+                    });
+                }
+                CommandEvent::Finished { exit_code } => {
+                    if let Some(record) = timeline.back_mut().filter(|r| r.finished_at.is_none()) {
+                        record.finished_at = Some(now);
+                        record.duration_ms = (now - record.started_at).num_milliseconds().max(0) as u64;
+                        record.exit_code = exit_code;
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// A command run in a session, timed by the daemon
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CommandRecord {
+    pub id: Uuid,
+    pub command: String,
+    pub source: CommandSource,
+    pub started_at: DateTime<Utc>,
+    /// None while the command is running
+    #[serde(default)]
+    pub finished_at: Option<DateTime<Utc>>,
+    /// Time taken, or so far for a running command
+    pub duration_ms: u64,
+    /// Reported by shells with OSC 133 integration only
+    #[serde(default)]
+    pub exit_code: Option<i32>,
+    /// Already seen by `take_long_commands`
+    #[serde(skip)]
+    notified: bool,
+}
+
+/// What became of a command re-run in other sessions
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "status", rename_all = "snake_case")]
+pub enum RerunOutcome {
+    /// Typed into every target session, followed by Enter
+    Sent {
+        command: String,
+        sessions: Vec<Uuid>,
+        /// How the canary did, when one ran first
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        canary: Option<CanaryReport>,
+    },
+    /// Nothing was sent; re-run with `confirmed` to send anyway
+    NeedsConfirmation { command: String, reason: String },
+    /// The canary failed its checks; the other sessions were sent nothing
+    CanaryFailed { command: String, report: CanaryReport },
+}
+
+/// A finished command that ran past the notification threshold
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LongCommand {
+    pub session_id: Uuid,
+    pub session_name: String,
+    #[serde(flatten)]
+    pub record: CommandRecord,
 }
 
 /// Lightweight session info for listing
@@ -67,12 +329,43 @@ pub struct SessionInfo {
     pub last_active: DateTime<Utc>,
     pub state: SessionState,
     pub num_clients: usize,
+    #[serde(default)]
+    pub cwd: Option<WorkingDirectory>,
+}
+
+/// A marked line of a session's output, with an optional note
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Bookmark {
+    pub id: Uuid,
+    /// Scrollback line number (see `terminal_core::SearchMatch::line`)
+    pub line: u64,
+    #[serde(default)]
+    pub note: Option<String>,
+    /// The line as it read when marked
+    pub text: String,
+    pub created_at: DateTime<Utc>,
+}
+
+/// A bookmark with the output around it
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BookmarkView {
+    pub bookmark: Bookmark,
+    /// Whether the marked line is still in the scrollback
+    pub in_scrollback: bool,
+    /// Kept lines around the marked one, oldest first
+    pub lines: Vec<(u64, String)>,
 }
 
 /// Thread-safe session manager
 pub struct SessionManager {
     /// Active sessions indexed by ID
     sessions: Arc<RwLock<HashMap<Uuid, Arc<SessionData>>>>,
+    /// Shares the focused session's context with orbitd
+    orbit: Option<Arc<OrbitBridge>>,
+    /// Told when a session loses its last client or ends
+    webhooks: Option<Arc<Webhooks>>,
+    /// How new sessions pick their PTY size
+    size_policy: SizePolicy,
 }
 
 impl SessionManager {
@@ -80,6 +373,42 @@ impl SessionManager {
     pub fn new() -> Self {
         Self {
             sessions: Arc::new(RwLock::new(HashMap::new())),
+            orbit: None,
+            webhooks: None,
+            size_policy: SizePolicy::default(),
+        }
+    }
+
+    /// Report the focused session's host, shell and directory to orbitd
+    pub fn with_orbit_bridge(mut self, orbit: Arc<OrbitBridge>) -> Self {
+        self.orbit = Some(orbit);
+        self
+    }
+
+    /// Send a `session.disconnected` webhook when a session loses its last
+    /// client or is terminated
+    pub fn with_webhooks(mut self, webhooks: Arc<Webhooks>) -> Self {
+        self.webhooks = Some(webhooks);
+        self
+    }
+
+    /// How new sessions pick their PTY size when several clients attach
+    pub fn with_size_policy(mut self, size_policy: SizePolicy) -> Self {
+        self.size_policy = size_policy;
+        self
+    }
+
+    fn notify_disconnected(&self, session: &SessionData, reason: &str) {
+        if let Some(webhooks) = &self.webhooks {
+            webhooks.notify(
+                pulsar_webhook::SESSION_DISCONNECTED,
+                serde_json::json!({
+                    "session_id": session.id,
+                    "name": session.name,
+                    "session_type": session.session_type,
+                    "reason": reason,
+                }),
+            );
         }
     }
 
@@ -90,39 +419,106 @@ impl SessionManager {
         session_type: SessionType,
         config: SessionConfig,
     ) -> Result<Uuid> {
+        if let SessionType::Tail { sources } = &session_type {
+            let (output, pending) = mpsc::channel(64);
+            tail::spawn_followers(sources, output)?;
+            let session_data = self.insert_session(config.id, name, session_type, None).await;
+            Self::spawn_tail_broadcaster(session_data, pending);
+            return Ok(config.id);
+        }
+
+        let shell = config.pty_config.shell.clone();
         let terminal_session = TerminalSession::new(config)?;
         let id = *terminal_session.id();
 
+        if let Some(orbit) = &self.orbit {
+            orbit.register(id, &name, &session_type, shell.as_deref()).await;
+        }
+
+        let session_data = self
+            .insert_session(id, name, session_type, Some(terminal_session))
+            .await;
+
+        // Spawn PTY output broadcasting task
+        Self::spawn_output_broadcaster(session_data, self.orbit.clone());
+
+        Ok(id)
+    }
+
+    async fn insert_session(
+        &self,
+        id: Uuid,
+        name: String,
+        session_type: SessionType,
+        terminal_session: Option<TerminalSession>,
+    ) -> Arc<SessionData> {
         let (output_broadcast, _) = broadcast::channel(1024);
+        let (size_events, _) = broadcast::channel(16);
 
         let session_data = Arc::new(SessionData {
             id,
             name,
             session_type,
-            terminal_session: Arc::new(RwLock::new(terminal_session)),
+            terminal_session: terminal_session.map(|terminal| Arc::new(RwLock::new(terminal))),
             created_at: Utc::now(),
             last_active: Arc::new(RwLock::new(Utc::now())),
             state: Arc::new(RwLock::new(SessionState::Running)),
             clients: Arc::new(RwLock::new(HashSet::new())),
-            output_broadcast: output_broadcast.clone(),
+            read_only: Arc::new(RwLock::new(HashSet::new())),
+            output_broadcast,
+            cwd: Arc::new(RwLock::new(None)),
+            scrollback: Arc::new(RwLock::new(Scrollback::default())),
+            bookmarks: Arc::new(RwLock::new(Vec::new())),
+            commands: Arc::new(RwLock::new(CommandTracker::new())),
+            timeline: Arc::new(RwLock::new(VecDeque::new())),
+            activity: Arc::new(RwLock::new(ActivityLog::default())),
+            paste_mode: Arc::new(RwLock::new(PasteModeTracker::new())),
+            keyboard: Arc::new(RwLock::new(KeyboardTracker::new())),
+            sizing: Arc::new(RwLock::new(SizeArbiter::new(self.size_policy))),
+            size_events,
         });
 
         let mut sessions = self.sessions.write().await;
         sessions.insert(id, Arc::clone(&session_data));
+        session_data
+    }
 
-        // Spawn PTY output broadcasting task
-        Self::spawn_output_broadcaster(session_data);
+    /// Spawn a task that broadcasts a tail session's labelled lines; the
+    /// file followers stop once it ends
+    fn spawn_tail_broadcaster(session: Arc<SessionData>, mut pending: mpsc::Receiver<Vec<u8>>) {
+        tokio::spawn(async move {
+            let session_id = session.id;
+            debug!("Starting tail broadcaster for session: {}", session_id);
 
-        Ok(id)
+            loop {
+                if *session.state.read().await == SessionState::Stopped {
+                    break;
+                }
+                let data = match timeout(Duration::from_millis(250), pending.recv()).await {
+                    Ok(Some(data)) => data,
+                    Ok(None) => break,
+                    Err(_) => continue,
+                };
+
+                session.scrollback.write().await.feed(&data);
+                if let Err(e) = session.output_broadcast.send(data) {
+                    debug!("No subscribers for session {}: {}", session_id, e);
+                }
+                *session.last_active.write().await = Utc::now();
+            }
+
+            debug!("Tail broadcaster ended for session: {}", session_id);
+        });
     }
 
     /// Spawn a task that reads PTY output and broadcasts to all subscribers
-    fn spawn_output_broadcaster(session: Arc<SessionData>) {
+    fn spawn_output_broadcaster(session: Arc<SessionData>, orbit: Option<Arc<OrbitBridge>>) {
         tokio::spawn(async move {
             let session_id = session.id;
             debug!("Starting output broadcaster for session: {}", session_id);
 
             let mut buffer = vec![0u8; 8192]; // 8KB buffer
+            let mut cwd = CwdTracker::new();
 
             loop {
                 // Check if session is stopped
@@ -134,8 +530,13 @@ impl SessionManager {
                 }
 
                 // Try to read from PTY (non-blocking)
+                let Ok(terminal) = session.terminal() else {
+                    break;
+                };
                 let bytes_read = {
-                    let mut terminal = session.terminal_session.write().await;
+                    // Shared, so input and resizes are not held up while
+                    // the read waits for output
+                    let terminal = terminal.read().await;
                     match terminal.try_read(&mut buffer) {
                         Ok(n) if n > 0 => n,
                         Ok(_) => {
@@ -153,6 +554,25 @@ impl SessionManager {
 
                 // Broadcast output to all subscribers (WebSocket clients)
                 let data = buffer[..bytes_read].to_vec();
+                session.scrollback.write().await.feed(&data);
+                if let Some(directory) = cwd.feed(&data) {
+                    debug!("Session {} is in {}", session_id, directory.path);
+                    *session.cwd.write().await = Some(directory.clone());
+                    if let Some(orbit) = &orbit {
+                        orbit.observe_cwd(session_id, directory).await;
+                    }
+                }
+                session.paste_mode.write().await.feed(&data);
+                let replies = session.keyboard.write().await.feed(&data);
+                if !replies.is_empty() {
+                    if let Err(e) = terminal.read().await.write(&replies) {
+                        error!("Failed to answer keyboard query for session {}: {}", session_id, e);
+                    }
+                }
+                let events = session.commands.write().await.feed(&data);
+                if !events.is_empty() {
+                    session.record_commands(events).await;
+                }
                 if let Err(e) = session.output_broadcast.send(data) {
                     // No subscribers, that's ok
                     debug!("No subscribers for session {}: {}", session_id, e);
@@ -189,18 +609,362 @@ impl SessionManager {
                 last_active: *session.last_active.read().await,
                 state: session.state.read().await.clone(),
                 num_clients: session.clients.read().await.len(),
+                cwd: session.cwd.read().await.clone(),
             });
         }
 
         infos
     }
 
-    /// Attach a client to a session
-    pub async fn attach_client(&self, session_id: Uuid, client_id: ClientId) -> Result<()> {
+    /// Each PTY session's ID, name and shell process, for resource sampling
+    pub async fn process_roots(&self) -> Vec<(Uuid, String, u32)> {
+        let sessions = self.sessions.read().await;
+        let mut roots = Vec::new();
+        for session in sessions.values() {
+            let Some(terminal) = &session.terminal_session else {
+                continue;
+            };
+            if let Some(pid) = terminal.read().await.process_id() {
+                roots.push((session.id, session.name.clone(), pid));
+            }
+        }
+        roots
+    }
+
+    /// Search a session's scrollback
+    pub async fn search_scrollback(&self, id: Uuid, query: &SearchQuery) -> Result<SearchResults> {
+        let session = self.get_session(id).await?;
+        let scrollback = session.scrollback.read().await;
+        scrollback.search(query)
+    }
+
+    /// Render a session's scrollback as a transcript, titled with the
+    /// session's name and creation time
+    pub async fn export_transcript(&self, id: Uuid, format: TranscriptFormat) -> Result<String> {
+        let session = self.get_session(id).await?;
+        let title = format!(
+            "{} ({})",
+            session.name,
+            session.created_at.format("%Y-%m-%d %H:%M UTC")
+        );
+        let scrollback = session.scrollback.read().await;
+        Ok(render_transcript(&scrollback, format, &title))
+    }
+
+    /// Mark a line of a session's output; without a line, the last one
+    /// that has any text
+    pub async fn add_bookmark(
+        &self,
+        id: Uuid,
+        line: Option<u64>,
+        note: Option<String>,
+    ) -> Result<Bookmark> {
+        let session = self.get_session(id).await?;
+        let scrollback = session.scrollback.read().await;
+        let line = match line {
+            Some(line) => line,
+            None => (scrollback.first_line()..=scrollback.last_line())
+                .rev()
+                .find(|&line| scrollback.line(line).is_some_and(|text| !text.trim().is_empty()))
+                .unwrap_or(scrollback.last_line()),
+        };
+        let text = scrollback
+            .line(line)
+            .ok_or_else(|| anyhow!("Line {} is not in the scrollback", line))?;
+        drop(scrollback);
+
+        let bookmark = Bookmark {
+            id: Uuid::new_v4(),
+            line,
+            note: note.filter(|note| !note.trim().is_empty()),
+            text,
+            created_at: Utc::now(),
+        };
+        session.bookmarks.write().await.push(bookmark.clone());
+        debug!("Bookmarked line {} of session {}", line, id);
+        Ok(bookmark)
+    }
+
+    /// A session's bookmarks, in output order
+    pub async fn list_bookmarks(&self, id: Uuid) -> Result<Vec<Bookmark>> {
+        let session = self.get_session(id).await?;
+        let mut bookmarks = session.bookmarks.read().await.clone();
+        bookmarks.sort_by_key(|bookmark| (bookmark.line, bookmark.created_at));
+        Ok(bookmarks)
+    }
+
+    /// Remove a bookmark; returns whether it existed
+    pub async fn remove_bookmark(&self, id: Uuid, bookmark_id: Uuid) -> Result<bool> {
+        let session = self.get_session(id).await?;
+        let mut bookmarks = session.bookmarks.write().await;
+        let before = bookmarks.len();
+        bookmarks.retain(|bookmark| bookmark.id != bookmark_id);
+        Ok(bookmarks.len() < before)
+    }
+
+    /// A bookmark with `context` lines of output either side
+    pub async fn jump_to_bookmark(
+        &self,
+        id: Uuid,
+        bookmark_id: Uuid,
+        context: u64,
+    ) -> Result<BookmarkView> {
+        let session = self.get_session(id).await?;
+        let bookmark = session
+            .bookmarks
+            .read()
+            .await
+            .iter()
+            .find(|bookmark| bookmark.id == bookmark_id)
+            .cloned()
+            .ok_or_else(|| anyhow!("Bookmark not found: {}", bookmark_id))?;
+
+        let scrollback = session.scrollback.read().await;
+        Ok(BookmarkView {
+            in_scrollback: scrollback.line(bookmark.line).is_some(),
+            lines: scrollback.range(
+                bookmark.line.saturating_sub(context),
+                bookmark.line.saturating_add(context),
+            ),
+            bookmark,
+        })
+    }
+
+    /// Bring back bookmarks saved with a workspace snapshot; ones the
+    /// session already has are skipped. Returns how many were added.
+    pub async fn restore_bookmarks(&self, id: Uuid, saved: Vec<Bookmark>) -> Result<usize> {
+        let session = self.get_session(id).await?;
+        let mut bookmarks = session.bookmarks.write().await;
+        let mut restored = 0;
+        for bookmark in saved {
+            if !bookmarks.iter().any(|b| b.id == bookmark.id) {
+                bookmarks.push(bookmark);
+                restored += 1;
+            }
+        }
+        Ok(restored)
+    }
+
+    /// Commands run in a session, oldest first
+    pub async fn command_timeline(&self, id: Uuid) -> Result<Vec<CommandRecord>> {
+        let session = self.get_session(id).await?;
+        let now = Utc::now();
+        let timeline = session.timeline.read().await;
+        Ok(timeline
+            .iter()
+            .cloned()
+            .map(|mut record| {
+                if record.finished_at.is_none() {
+                    record.duration_ms = (now - record.started_at).num_milliseconds().max(0) as u64;
+                }
+                record
+            })
+            .collect())
+    }
+
+    /// Type a command from one session's timeline into other sessions,
+    /// held back for confirmation unless `confirmed` when orbitd finds it
+    /// destructive or can't be asked. With a `canary`, the command runs in
+    /// that session first and reaches the others only if it passes.
+    pub async fn rerun_command(
+        &self,
+        source: Uuid,
+        command_id: Uuid,
+        targets: &[Uuid],
+        confirmed: bool,
+        canary: Option<&Canary>,
+    ) -> Result<RerunOutcome> {
+        let command = self
+            .get_session(source)
+            .await?
+            .timeline
+            .read()
+            .await
+            .iter()
+            .find(|record| record.id == command_id)
+            .map(|record| record.command.trim().to_string())
+            .ok_or_else(|| anyhow!("Session {} has no command {}", source, command_id))?;
+        if targets.is_empty() {
+            bail!("No session to run `{}` in", command);
+        }
+
+        // Every target must take input before any is sent the command
+        let mut sessions = Vec::with_capacity(targets.len());
+        for &target in targets {
+            let session = self.get_session(target).await?;
+            session.terminal()?;
+            sessions.push(session);
+        }
+        if let Some(canary) = canary {
+            if !targets.contains(&canary.session_id) {
+                bail!("Canary session {} is not one of the targets", canary.session_id);
+            }
+            canary.pattern()?;
+        }
+
+        if !confirmed {
+            let reason = match &self.orbit {
+                None => Some("orbitd isn't configured to check the command".to_string()),
+                Some(orbit) => match orbit.is_destructive(&command).await {
+                    Ok(true) => Some("orbitd considers the command destructive".to_string()),
+                    Ok(false) => None,
+                    Err(e) => Some(format!("Couldn't check the command with orbitd: {}", e)),
+                },
+            };
+            if let Some(reason) = reason {
+                return Ok(RerunOutcome::NeedsConfirmation { command, reason });
+            }
+        }
+
+        let mut report = None;
+        if let Some(canary) = canary {
+            let session = sessions
+                .iter()
+                .find(|session| session.id == canary.session_id)
+                .ok_or_else(|| anyhow!("Session not found: {}", canary.session_id))?;
+            info!("Running `{}` from session {} in canary session {}", command, source, session.id);
+            let canary_report = session.run_canary(&command, canary).await?;
+            if !canary_report.passed {
+                warn!(
+                    "Canary session {} failed `{}`: {}",
+                    session.id,
+                    command,
+                    canary_report.failure.as_deref().unwrap_or_default()
+                );
+                return Ok(RerunOutcome::CanaryFailed { command, report: canary_report });
+            }
+            report = Some(canary_report);
+        }
+
+        for session in &sessions {
+            if canary.is_some_and(|canary| canary.session_id == session.id) {
+                continue;
+            }
+            info!("Re-running `{}` from session {} in session {}", command, source, session.id);
+            session.write_input(format!("{}\r", command).as_bytes()).await?;
+        }
+        Ok(RerunOutcome::Sent {
+            command,
+            sessions: targets.to_vec(),
+            canary: report,
+        })
+    }
+
+    /// Everything that happened in a session overlapping `[from, to]`
+    /// (either end open when None), in time order
+    pub async fn session_timeline(
+        &self,
+        id: Uuid,
+        from: Option<DateTime<Utc>>,
+        to: Option<DateTime<Utc>>,
+    ) -> Result<SessionTimeline> {
+        let session = self.get_session(id).await?;
+        let commands = self.command_timeline(id).await?;
+        let bookmarks = session.bookmarks.read().await.clone();
+        let entries = timeline::merge(commands, &session.activity.read().await, bookmarks, from, to);
+
+        Ok(SessionTimeline {
+            session_id: id,
+            created_at: session.created_at,
+            now: Utc::now(),
+            entries,
+        })
+    }
+
+    /// Note a file being sent to a session
+    pub async fn transfer_started(
+        &self,
+        id: Uuid,
+        transfer_id: &str,
+        file_name: &str,
+        file_size: u64,
+    ) {
+        if let Ok(session) = self.get_session(id).await {
+            session.activity.write().await.transfer_started(transfer_id, file_name, file_size);
+        }
+    }
+
+    /// Note how a transfer to a session ended
+    pub async fn transfer_finished(&self, id: Uuid, transfer_id: &str, outcome: TransferOutcome) {
+        if let Ok(session) = self.get_session(id).await {
+            session.activity.write().await.transfer_finished(transfer_id, outcome);
+        }
+    }
+
+    /// Commands, across all sessions, that finished since the last call
+    /// and took at least `threshold`
+    pub async fn take_long_commands(&self, threshold: Duration) -> Vec<LongCommand> {
+        let threshold_ms = threshold.as_millis() as u64;
+        let sessions: Vec<_> = self.sessions.read().await.values().cloned().collect();
+        let mut long = Vec::new();
+        for session in sessions {
+            let mut timeline = session.timeline.write().await;
+            for record in timeline.iter_mut().filter(|r| r.finished_at.is_some() && !r.notified) {
+                // Each command is considered once, under the threshold of the time
+                record.notified = true;
+                if record.duration_ms >= threshold_ms {
+                    long.push(LongCommand {
+                        session_id: session.id,
+                        session_name: session.name.clone(),
+                        record: record.clone(),
+                    });
+                }
+            }
+        }
+        long
+    }
+
+    /// Where files sent to a session should land: its shell's directory,
+    /// as long as that is on this machine
+    pub async fn transfer_directory(&self, id: Uuid) -> Result<Option<PathBuf>> {
+        let session = self.get_session(id).await?;
+        if !matches!(session.session_type, SessionType::Local) {
+            return Ok(None);
+        }
+        let Some(cwd) = session.cwd.read().await.clone() else {
+            return Ok(None);
+        };
+        if !cwd.host.as_deref().is_none_or(is_local_host) {
+            return Ok(None);
+        }
+
+        Ok(match cwd.path.strip_prefix('~') {
+            Some(rest) => dirs::home_dir().map(|home| home.join(rest.trim_start_matches('/'))),
+            None => Some(PathBuf::from(cwd.path)),
+        })
+    }
+
+    /// Attach a client to a session; a read-only client receives output
+    /// but its input is discarded
+    pub async fn attach_client(
+        &self,
+        session_id: Uuid,
+        client_id: ClientId,
+        read_only: bool,
+    ) -> Result<()> {
         let session = self.get_session(session_id).await?;
 
+        // Reattaching changes the client's permissions too
+        {
+            let mut watchers = session.read_only.write().await;
+            if read_only {
+                watchers.insert(client_id);
+            } else {
+                watchers.remove(&client_id);
+            }
+        }
+
         // Add client to session
-        session.clients.write().await.insert(client_id);
+        let clients = {
+            let mut clients = session.clients.write().await;
+            clients.insert(client_id);
+            clients.len()
+        };
+        session
+            .activity
+            .write()
+            .await
+            .connection(client_id, ConnectionChange::Attached, clients);
 
         // Update state to Running if it was Detached
         let mut state = session.state.write().await;
@@ -219,11 +983,27 @@ impl SessionManager {
         let session = self.get_session(session_id).await?;
 
         // Remove client from session
-        session.clients.write().await.remove(&client_id);
+        let clients = {
+            let mut clients = session.clients.write().await;
+            clients.remove(&client_id);
+            clients.len()
+        };
+        session.read_only.write().await.remove(&client_id);
 
         // If no clients left, mark as Detached
-        if session.clients.read().await.is_empty() {
+        let change = if clients == 0 {
             *session.state.write().await = SessionState::Detached;
+            self.notify_disconnected(&session, "detached");
+            ConnectionChange::Disconnected
+        } else {
+            ConnectionChange::Detached
+        };
+        session.activity.write().await.connection(client_id, change, clients);
+
+        // The rest may now get a bigger terminal
+        let resized = session.sizing.write().await.remove(client_id);
+        if let Err(e) = session.apply_size(resized).await {
+            error!("Failed to resize session {}: {}", session_id, e);
         }
 
         // Update last active time
@@ -240,8 +1020,13 @@ impl SessionManager {
             // Mark as stopped
             *session.state.write().await = SessionState::Stopped;
 
+            if let Some(orbit) = &self.orbit {
+                orbit.remove(id).await;
+            }
+
             // Clear all clients
             session.clients.write().await.clear();
+            self.notify_disconnected(&session, "terminated");
 
             Ok(())
         } else {
@@ -270,7 +1055,20 @@ impl SessionManager {
         // Remove dead sessions
         for id in dead_ids {
             sessions.remove(&id);
+            if let Some(orbit) = &self.orbit {
+                orbit.remove(id).await;
+            }
+        }
+    }
+
+    /// Note that the user is working in a session (typing in it or
+    /// switching to its tab)
+    pub async fn focus_session(&self, id: Uuid) -> Result<()> {
+        self.get_session(id).await?;
+        if let Some(orbit) = &self.orbit {
+            orbit.focus(id).await;
         }
+        Ok(())
     }
 
     /// Get number of active sessions
@@ -346,6 +1144,491 @@ mod tests {
         assert!(sessions.iter().any(|s| s.name == "session-2"));
     }
 
+    #[tokio::test]
+    async fn test_transfer_directory_follows_local_cwd() {
+        let manager = SessionManager::new();
+        let id = manager
+            .create_session(
+                "test-session".to_string(),
+                SessionType::Local,
+                SessionConfig::new("test".to_string()),
+            )
+            .await
+            .unwrap();
+        assert_eq!(manager.transfer_directory(id).await.unwrap(), None);
+
+        let session = manager.get_session(id).await.unwrap();
+        *session.cwd.write().await = CwdTracker::new()
+            .feed(b"\x1b]7;file:///srv/app\x07")
+            .cloned();
+        assert_eq!(
+            manager.transfer_directory(id).await.unwrap(),
+            Some(PathBuf::from("/srv/app"))
+        );
+        assert_eq!(
+            manager.list_sessions().await[0].cwd.as_ref().unwrap().path,
+            "/srv/app"
+        );
+
+        // A shell reached with `ssh` from the local session
+        *session.cwd.write().await = CwdTracker::new()
+            .feed(b"\x1b]7;file://db-1.example.net/root\x07")
+            .cloned();
+        assert_eq!(manager.transfer_directory(id).await.unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn test_search_scrollback() {
+        let manager = SessionManager::new();
+        let id = manager
+            .create_session(
+                "test-session".to_string(),
+                SessionType::Local,
+                SessionConfig::new("test".to_string()),
+            )
+            .await
+            .unwrap();
+
+        let session = manager.get_session(id).await.unwrap();
+        session
+            .scrollback
+            .write()
+            .await
+            .feed(b"cargo build\r\nerror: could not compile `api`\r\n");
+
+        let query = SearchQuery {
+            pattern: "could not \\w+".to_string(),
+            regex: true,
+            case_sensitive: false,
+            max_results: None,
+        };
+        let results = manager.search_scrollback(id, &query).await.unwrap();
+        assert_eq!(results.matches.len(), 1);
+        assert_eq!(results.matches[0].text, "error: could not compile `api`");
+        assert_eq!(results.matches[0].start, 7);
+
+        assert!(manager.search_scrollback(Uuid::new_v4(), &query).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_export_transcript() {
+        let manager = SessionManager::new();
+        let id = manager
+            .create_session(
+                "deploy".to_string(),
+                SessionType::Local,
+                SessionConfig::new("test".to_string()),
+            )
+            .await
+            .unwrap();
+
+        let session = manager.get_session(id).await.unwrap();
+        session
+            .scrollback
+            .write()
+            .await
+            .feed(b"\x1b[32mok\x1b[0m: migrated 3 tables\r\n");
+
+        let text = manager.export_transcript(id, TranscriptFormat::Text).await.unwrap();
+        assert!(text.contains("ok: migrated 3 tables\n"));
+
+        let html = manager.export_transcript(id, TranscriptFormat::Html).await.unwrap();
+        assert!(html.contains("<title>deploy ("));
+        assert!(html.contains("<span style=\"color:#00cd00\">ok</span>: migrated 3 tables"));
+
+        assert!(manager
+            .export_transcript(Uuid::new_v4(), TranscriptFormat::Text)
+            .await
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn test_bookmarks() {
+        let manager = SessionManager::new();
+        let id = manager
+            .create_session(
+                "test-session".to_string(),
+                SessionType::Local,
+                SessionConfig::new("test".to_string()),
+            )
+            .await
+            .unwrap();
+
+        let session = manager.get_session(id).await.unwrap();
+        session
+            .scrollback
+            .write()
+            .await
+            .feed(b"Compiling api\r\nerror: could not compile `api`\r\n\r\n");
+
+        // Defaults to the last line with text
+        let error = manager
+            .add_bookmark(id, None, Some("first failure".to_string()))
+            .await
+            .unwrap();
+        assert_eq!(error.line, 1);
+        assert_eq!(error.text, "error: could not compile `api`");
+        let start = manager.add_bookmark(id, Some(0), None).await.unwrap();
+        assert!(manager.add_bookmark(id, Some(99), None).await.is_err());
+
+        let listed = manager.list_bookmarks(id).await.unwrap();
+        assert_eq!(listed, [start.clone(), error.clone()]);
+
+        let view = manager.jump_to_bookmark(id, error.id, 1).await.unwrap();
+        assert!(view.in_scrollback);
+        assert_eq!(view.lines.len(), 3);
+        assert_eq!(view.lines[0], (0, "Compiling api".to_string()));
+        assert!(manager.jump_to_bookmark(id, Uuid::new_v4(), 1).await.is_err());
+
+        assert!(manager.remove_bookmark(id, start.id).await.unwrap());
+        assert!(!manager.remove_bookmark(id, start.id).await.unwrap());
+
+        // Restoring skips bookmarks the session still has
+        let restored = manager
+            .restore_bookmarks(id, vec![start.clone(), error])
+            .await
+            .unwrap();
+        assert_eq!(restored, 1);
+        assert_eq!(manager.list_bookmarks(id).await.unwrap().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_command_timeline() {
+        let manager = SessionManager::new();
+        let id = manager
+            .create_session(
+                "test-session".to_string(),
+                SessionType::Local,
+                SessionConfig::new("test".to_string()),
+            )
+            .await
+            .unwrap();
+        let session = manager.get_session(id).await.unwrap();
+
+        let events = session
+            .commands
+            .write()
+            .await
+            .feed(b"\x1b]133;B\x07cargo build\r\n\x1b]133;C\x07");
+        session.record_commands(events).await;
+        let timeline = manager.command_timeline(id).await.unwrap();
+        assert_eq!(timeline.len(), 1);
+        assert_eq!(timeline[0].command, "cargo build");
+        assert_eq!(timeline[0].finished_at, None);
+
+        // Running commands aren't reported
+        assert!(manager.take_long_commands(Duration::ZERO).await.is_empty());
+
+        let events = session.commands.write().await.feed(b"\x1b]133;D;0\x07");
+        session.record_commands(events).await;
+        let timeline = manager.command_timeline(id).await.unwrap();
+        assert_eq!(timeline[0].exit_code, Some(0));
+        assert!(timeline[0].finished_at.is_some());
+
+        // Too short for the threshold, and not offered again once lowered
+        assert!(manager.take_long_commands(Duration::from_secs(60)).await.is_empty());
+        assert!(manager.take_long_commands(Duration::ZERO).await.is_empty());
+
+        let events = session
+            .commands
+            .write()
+            .await
+            .feed(b"\x1b]133;B\x07make\x1b]133;C\x07\x1b]133;D;2\x07");
+        session.record_commands(events).await;
+        let long = manager.take_long_commands(Duration::ZERO).await;
+        assert_eq!(long.len(), 1);
+        assert_eq!(long[0].session_name, "test-session");
+        assert_eq!(long[0].record.exit_code, Some(2));
+        assert!(manager.take_long_commands(Duration::ZERO).await.is_empty());
+
+        assert!(manager.command_timeline(Uuid::new_v4()).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_session_timeline() {
+        use crate::timeline::{TimelineEntry, TransferOutcome};
+
+        let manager = SessionManager::new();
+        let id = manager
+            .create_session(
+                "test-session".to_string(),
+                SessionType::Local,
+                SessionConfig::new("test".to_string()),
+            )
+            .await
+            .unwrap();
+        let session = manager.get_session(id).await.unwrap();
+
+        let client = Uuid::new_v4();
+        manager.attach_client(id, client, false).await.unwrap();
+        let events = session
+            .commands
+            .write()
+            .await
+            .feed(b"\x1b]133;B\x07make\x1b]133;C\x07\x1b]133;D;0\x07");
+        session.record_commands(events).await;
+        manager.transfer_started(id, "t-1", "notes.txt", 12).await;
+        manager.transfer_finished(id, "t-1", TransferOutcome::Complete).await;
+        manager.detach_client(id, client).await.unwrap();
+
+        let timeline = manager.session_timeline(id, None, None).await.unwrap();
+        let kinds: Vec<_> = timeline
+            .entries
+            .iter()
+            .map(|entry| serde_json::to_value(entry).unwrap()["kind"].clone())
+            .collect();
+        assert_eq!(kinds, ["connection", "command", "transfer", "connection"]);
+        let TimelineEntry::Connection(last) = &timeline.entries[3] else {
+            panic!("expected a connection change");
+        };
+        assert_eq!(last.change, ConnectionChange::Disconnected);
+
+        let later = manager
+            .session_timeline(id, Some(Utc::now() + chrono::Duration::seconds(1)), None)
+            .await
+            .unwrap();
+        assert!(later.entries.is_empty());
+        assert!(manager.session_timeline(Uuid::new_v4(), None, None).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_rerun_command() {
+        let manager = SessionManager::new();
+        let mut ids = Vec::new();
+        for name in ["web-1", "web-2"] {
+            let id = manager
+                .create_session(
+                    name.to_string(),
+                    SessionType::Local,
+                    SessionConfig::new(name.to_string()),
+                )
+                .await
+                .unwrap();
+            ids.push(id);
+        }
+        let source = manager.get_session(ids[0]).await.unwrap();
+        let events = source
+            .commands
+            .write()
+            .await
+            .feed(b"\x1b]133;B\x07systemctl restart nginx\r\n\x1b]133;C\x07");
+        source.record_commands(events).await;
+        let command_id = manager.command_timeline(ids[0]).await.unwrap()[0].id;
+
+        // Without orbitd to vet it, the command waits for confirmation
+        let outcome = manager.rerun_command(ids[0], command_id, &ids[1..], false, None).await.unwrap();
+        assert!(matches!(outcome, RerunOutcome::NeedsConfirmation { .. }));
+
+        let outcome = manager.rerun_command(ids[0], command_id, &ids[1..], true, None).await.unwrap();
+        assert_eq!(
+            outcome,
+            RerunOutcome::Sent {
+                command: "systemctl restart nginx".to_string(),
+                sessions: vec![ids[1]],
+                canary: None,
+            }
+        );
+
+        assert!(manager.rerun_command(ids[0], Uuid::new_v4(), &ids[1..], true, None).await.is_err());
+        assert!(manager.rerun_command(ids[0], command_id, &[Uuid::new_v4()], true, None).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_rerun_command_canary() {
+        let manager = SessionManager::new();
+        let mut ids = Vec::new();
+        for name in ["web-1", "web-2", "web-3"] {
+            let id = manager
+                .create_session(
+                    name.to_string(),
+                    SessionType::Local,
+                    SessionConfig::new(name.to_string()),
+                )
+                .await
+                .unwrap();
+            ids.push(id);
+        }
+        let source = manager.get_session(ids[0]).await.unwrap();
+        let events = source
+            .commands
+            .write()
+            .await
+            .feed(b"\x1b]133;B\x07nginx -t\r\n\x1b]133;C\x07");
+        source.record_commands(events).await;
+        let command_id = manager.command_timeline(ids[0]).await.unwrap()[0].id;
+        let canary: Canary = serde_json::from_value(serde_json::json!({
+            "session_id": ids[1],
+            "timeout_secs": 5,
+        }))
+        .unwrap();
+
+        // Stand in for the canary's shell integration reporting the exit code
+        let canary_session = manager.get_session(ids[1]).await.unwrap();
+        let finish = |exit_code: &'static str| {
+            let session = canary_session.clone();
+            tokio::spawn(async move {
+                sleep(Duration::from_millis(200)).await;
+                let report = format!("\x1b]133;B\x07nginx -t\r\n\x1b]133;C\x07\x1b]133;D;{}\x07", exit_code);
+                let events = session.commands.write().await.feed(report.as_bytes());
+                session.record_commands(events).await;
+            })
+        };
+
+        finish("1");
+        let outcome = manager
+            .rerun_command(ids[0], command_id, &ids[1..], true, Some(&canary))
+            .await
+            .unwrap();
+        let RerunOutcome::CanaryFailed { report, .. } = outcome else {
+            panic!("Canary should have failed: {:?}", outcome);
+        };
+        assert_eq!((report.session_id, report.exit_code), (ids[1], Some(1)));
+        assert_eq!(report.failure.as_deref(), Some("Exited with 1, expected 0"));
+
+        finish("0");
+        let outcome = manager
+            .rerun_command(ids[0], command_id, &ids[1..], true, Some(&canary))
+            .await
+            .unwrap();
+        let RerunOutcome::Sent { sessions, canary: Some(report), .. } = outcome else {
+            panic!("Canary should have passed: {:?}", outcome);
+        };
+        assert_eq!(sessions, ids[1..]);
+        assert!(report.passed);
+
+        // A shell that never reports the command finishing fails the canary
+        let quiet = Canary { session_id: ids[2], timeout_secs: 1, ..canary.clone() };
+        let outcome = manager
+            .rerun_command(ids[0], command_id, &ids[1..], true, Some(&quiet))
+            .await
+            .unwrap();
+        assert!(matches!(outcome, RerunOutcome::CanaryFailed { .. }));
+
+        // The canary must be one of the targets
+        let outside = Canary { session_id: ids[0], ..canary };
+        assert!(manager
+            .rerun_command(ids[0], command_id, &ids[1..], true, Some(&outside))
+            .await
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn test_read_only_attach() {
+        let manager = SessionManager::new();
+        let id = manager
+            .create_session(
+                "test-session".to_string(),
+                SessionType::Local,
+                SessionConfig::new("test".to_string()),
+            )
+            .await
+            .unwrap();
+        let session = manager.get_session(id).await.unwrap();
+
+        let (owner, viewer) = (Uuid::new_v4(), Uuid::new_v4());
+        manager.attach_client(id, owner, false).await.unwrap();
+        manager.attach_client(id, viewer, true).await.unwrap();
+        assert!(session.allows_input(Some(owner)).await);
+        assert!(!session.allows_input(Some(viewer)).await);
+        assert!(session.allows_input(None).await);
+
+        // A watcher's small window doesn't shrink the owner's terminal
+        session.resize(owner, TerminalSize::new(120, 40)).await.unwrap();
+        let size = session.resize(viewer, TerminalSize::new(40, 10)).await.unwrap();
+        assert_eq!((size.cols, size.rows), (120, 40));
+
+        // Reattaching with control lifts the restriction
+        manager.attach_client(id, viewer, false).await.unwrap();
+        assert!(session.allows_input(Some(viewer)).await);
+        manager.attach_client(id, viewer, true).await.unwrap();
+        manager.detach_client(id, viewer).await.unwrap();
+        assert!(session.read_only.read().await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_size_follows_clients() {
+        let manager = SessionManager::new();
+        let id = manager
+            .create_session(
+                "test-session".to_string(),
+                SessionType::Local,
+                SessionConfig::new("test".to_string()),
+            )
+            .await
+            .unwrap();
+        let session = manager.get_session(id).await.unwrap();
+        let mut size_events = session.size_events.subscribe();
+
+        let (desktop, phone) = (Uuid::new_v4(), Uuid::new_v4());
+        manager.attach_client(id, desktop, false).await.unwrap();
+        manager.attach_client(id, phone, false).await.unwrap();
+        session.resize(desktop, TerminalSize::new(160, 48)).await.unwrap();
+        let size = session.resize(phone, TerminalSize::new(60, 20)).await.unwrap();
+        assert_eq!((size.cols, size.rows), (60, 20));
+        assert_eq!(size.letterboxed, vec![desktop]);
+
+        // The desktop gets its full size back once the phone leaves
+        manager.detach_client(id, phone).await.unwrap();
+        let mut last = None;
+        while let Ok(change) = size_events.try_recv() {
+            last = Some(change);
+        }
+        let last = last.unwrap();
+        assert_eq!((last.cols, last.rows), (160, 48));
+        assert!(last.letterboxed.is_empty());
+
+        assert!(session
+            .set_size_policy(SizePolicy::Fixed { cols: 0, rows: 0 })
+            .await
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn test_tail_session() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("worker.log");
+        std::fs::write(&path, "job 41 done\n").unwrap();
+
+        let manager = SessionManager::new();
+        let sources = vec![TailSource {
+            path: path.to_str().unwrap().to_string(),
+            host: None,
+            port: None,
+            label: Some("worker".to_string()),
+        }];
+        let id = manager
+            .create_session(
+                "logs".to_string(),
+                SessionType::Tail { sources },
+                SessionConfig::new("logs".to_string()),
+            )
+            .await
+            .unwrap();
+
+        let session = manager.get_session(id).await.unwrap();
+        assert!(session.terminal().is_err());
+        let mut output = session.output_broadcast.subscribe();
+        let line = String::from_utf8(output.recv().await.unwrap()).unwrap();
+        assert!(line.contains("worker"));
+        assert!(line.ends_with("job 41 done\r\n"));
+
+        let query = SearchQuery {
+            pattern: "job 41".to_string(),
+            regex: false,
+            case_sensitive: true,
+            max_results: None,
+        };
+        let results = manager.search_scrollback(id, &query).await.unwrap();
+        assert_eq!(results.matches.len(), 1);
+
+        let empty = SessionType::Tail { sources: Vec::new() };
+        assert!(manager
+            .create_session("none".to_string(), empty, SessionConfig::new("none".to_string()))
+            .await
+            .is_err());
+        manager.terminate_session(id).await.unwrap();
+    }
+
     #[tokio::test]
     async fn test_attach_detach_client() {
         let manager = SessionManager::new();
@@ -359,7 +1642,7 @@ mod tests {
         let client_id = Uuid::new_v4();
 
         // Attach client
-        manager.attach_client(session_id, client_id).await.unwrap();
+        manager.attach_client(session_id, client_id, false).await.unwrap();
         assert_eq!(manager.count_clients().await, 1);
 
         let session = manager.get_session(session_id).await.unwrap();
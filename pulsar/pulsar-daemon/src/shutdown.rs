@@ -0,0 +1,116 @@
+//! Graceful shutdown
+//!
+//! When asked to stop, the daemon refuses new work (sessions, attaches,
+//! transfers) and counts down a grace period, publishing the seconds left
+//! so connected clients can warn their users. After the countdown it
+//! checkpoints in-flight file transfers for resumption, snapshots
+//! workspaces with their sessions, and stops the servers.
+
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+use tokio::signal;
+use tokio::sync::watch;
+use tracing::error;
+
+/// Published each second while the daemon is shutting down
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ShutdownNotice {
+    /// Seconds until the daemon stops
+    pub seconds_left: u64,
+}
+
+/// Shutdown state shared with the servers
+pub struct Shutdown {
+    notice: watch::Sender<Option<ShutdownNotice>>,
+}
+
+impl Shutdown {
+    pub fn new() -> Self {
+        let (notice, _) = watch::channel(None);
+        Self { notice }
+    }
+
+    /// The countdown, once shutdown has started; new work is refused then
+    pub fn notice(&self) -> Option<ShutdownNotice> {
+        *self.notice.borrow()
+    }
+
+    /// Follow the countdown; the value is `None` until shutdown starts
+    pub fn subscribe(&self) -> watch::Receiver<Option<ShutdownNotice>> {
+        self.notice.subscribe()
+    }
+
+    /// Start refusing new work and count `grace` down to zero
+    pub async fn drain(&self, grace: Duration) {
+        let mut seconds_left = grace.as_secs();
+        loop {
+            self.notice.send_replace(Some(ShutdownNotice { seconds_left }));
+            if seconds_left == 0 {
+                return;
+            }
+            tokio::time::sleep(Duration::from_secs(1)).await;
+            seconds_left -= 1;
+        }
+    }
+}
+
+impl Default for Shutdown {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Wait for Ctrl+C, or SIGTERM from a service manager
+pub async fn signal() {
+    #[cfg(unix)]
+    {
+        let mut terminate = match signal::unix::signal(signal::unix::SignalKind::terminate()) {
+            Ok(terminate) => terminate,
+            Err(e) => {
+                error!("Failed to listen for SIGTERM: {}", e);
+                return ctrl_c().await;
+            }
+        };
+        tokio::select! {
+            _ = ctrl_c() => {}
+            _ = terminate.recv() => {}
+        }
+    }
+
+    #[cfg(not(unix))]
+    ctrl_c().await
+}
+
+async fn ctrl_c() {
+    if let Err(e) = signal::ctrl_c().await {
+        error!("Failed to listen for shutdown signal: {}", e);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test(start_paused = true)]
+    async fn test_drain_counts_down() {
+        let shutdown = Shutdown::new();
+        let mut notices = shutdown.subscribe();
+        assert_eq!(shutdown.notice(), None);
+
+        let countdown = async {
+            let mut seen = Vec::new();
+            while notices.changed().await.is_ok() {
+                let notice = notices.borrow_and_update().unwrap();
+                seen.push(notice.seconds_left);
+                if notice.seconds_left == 0 {
+                    break;
+                }
+            }
+            seen
+        };
+        let (_, seen) = tokio::join!(shutdown.drain(Duration::from_secs(3)), countdown);
+
+        assert_eq!(seen, [3, 2, 1, 0]);
+        assert_eq!(shutdown.notice(), Some(ShutdownNotice { seconds_left: 0 }));
+    }
+}
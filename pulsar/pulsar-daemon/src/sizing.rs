@@ -0,0 +1,252 @@
+//! Terminal size arbitration
+//!
+//! A session has one PTY size however many clients are attached, each with
+//! its own window. Every client reports its window size on resize, and the
+//! session's `SizePolicy` picks the PTY size from them: the smallest window
+//! (nothing is cut off anywhere), the window of the client that typed or
+//! resized last, or a fixed size. Clients whose window differs from the
+//! chosen size show the terminal letterboxed; each change is announced with
+//! the clients affected and why, so they can say so instead of looking
+//! broken.
+
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+use crate::session_manager::ClientId;
+
+/// Stands in for callers that resize without naming a client
+pub const ANONYMOUS_CLIENT: ClientId = ClientId::nil();
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TerminalSize {
+    pub cols: u16,
+    pub rows: u16,
+}
+
+impl TerminalSize {
+    pub fn new(cols: u16, rows: u16) -> Self {
+        Self { cols, rows }
+    }
+}
+
+/// How a session's PTY size is picked from its clients' windows
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "policy", rename_all = "snake_case")]
+pub enum SizePolicy {
+    /// The smallest width and height among the attached clients
+    #[default]
+    Smallest,
+    /// The window of the client that typed or resized last
+    ActiveController,
+    /// Always this size, whatever the clients' windows
+    Fixed { cols: u16, rows: u16 },
+}
+
+impl SizePolicy {
+    pub fn validate(&self) -> anyhow::Result<()> {
+        if let SizePolicy::Fixed { cols, rows } = self {
+            if *cols == 0 || *rows == 0 {
+                anyhow::bail!("Fixed terminal size must be at least 1x1, not {}x{}", cols, rows);
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The size a session's PTY was given, and why
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SizeChange {
+    pub cols: u16,
+    pub rows: u16,
+    pub policy: SizePolicy,
+    /// Client whose window the size follows; None under a fixed size or
+    /// when the smallest width and height come from different clients
+    pub controller: Option<ClientId>,
+    /// Clients whose window is not this size, and so show the terminal
+    /// letterboxed or cropped
+    pub letterboxed: Vec<ClientId>,
+}
+
+/// Picks one session's PTY size
+#[derive(Debug, Default)]
+pub struct SizeArbiter {
+    policy: SizePolicy,
+    windows: HashMap<ClientId, TerminalSize>,
+    /// Client that typed or resized last
+    active: Option<ClientId>,
+    current: Option<SizeChange>,
+}
+
+impl SizeArbiter {
+    pub fn new(policy: SizePolicy) -> Self {
+        Self {
+            policy,
+            ..Self::default()
+        }
+    }
+
+    /// The last size picked, if any
+    pub fn current(&self) -> Option<&SizeChange> {
+        self.current.as_ref()
+    }
+
+    /// A client's window changed size; returns the new arrangement if it
+    /// changed
+    pub fn resize(&mut self, client_id: ClientId, size: TerminalSize) -> Option<SizeChange> {
+        self.windows.insert(client_id, size);
+        self.active = Some(client_id);
+        self.arbitrate()
+    }
+
+    /// A client typed
+    pub fn activity(&mut self, client_id: ClientId) -> Option<SizeChange> {
+        if self.active == Some(client_id) {
+            return None;
+        }
+        self.active = Some(client_id);
+        self.arbitrate()
+    }
+
+    /// A client left
+    pub fn remove(&mut self, client_id: ClientId) -> Option<SizeChange> {
+        self.windows.remove(&client_id);
+        if self.active == Some(client_id) {
+            self.active = None;
+        }
+        self.arbitrate()
+    }
+
+    pub fn set_policy(&mut self, policy: SizePolicy) -> Option<SizeChange> {
+        self.policy = policy;
+        self.arbitrate()
+    }
+
+    fn arbitrate(&mut self) -> Option<SizeChange> {
+        let (size, controller) = self.pick()?;
+        let mut letterboxed: Vec<ClientId> = self
+            .windows
+            .iter()
+            .filter(|(_, window)| **window != size)
+            .map(|(client_id, _)| *client_id)
+            .collect();
+        letterboxed.sort();
+
+        let change = SizeChange {
+            cols: size.cols,
+            rows: size.rows,
+            policy: self.policy,
+            controller,
+            letterboxed,
+        };
+        if self.current.as_ref() == Some(&change) {
+            return None;
+        }
+        self.current = Some(change.clone());
+        Some(change)
+    }
+
+    /// The size for the current policy; None until a window is known
+    fn pick(&self) -> Option<(TerminalSize, Option<ClientId>)> {
+        match self.policy {
+            SizePolicy::Fixed { cols, rows } => Some((TerminalSize::new(cols, rows), None)),
+            SizePolicy::ActiveController => {
+                let controller = self.active.filter(|id| self.windows.contains_key(id));
+                match controller {
+                    Some(id) => Some((self.windows[&id], Some(id))),
+                    // The controller left; fall back until someone is active
+                    None => self.smallest(),
+                }
+            }
+            SizePolicy::Smallest => self.smallest(),
+        }
+    }
+
+    fn smallest(&self) -> Option<(TerminalSize, Option<ClientId>)> {
+        let cols = self.windows.values().map(|w| w.cols).min()?;
+        let rows = self.windows.values().map(|w| w.rows).min()?;
+        let size = TerminalSize::new(cols, rows);
+        // Lowest ID among exact fits, so the answer doesn't vary between calls
+        let controller = self
+            .windows
+            .iter()
+            .filter(|(_, window)| **window == size)
+            .map(|(client_id, _)| *client_id)
+            .min();
+        Some((size, controller))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use uuid::Uuid;
+
+    #[test]
+    fn test_smallest_wins() {
+        let (laptop, monitor) = (Uuid::new_v4(), Uuid::new_v4());
+        let mut arbiter = SizeArbiter::default();
+
+        let change = arbiter.resize(monitor, TerminalSize::new(200, 60)).unwrap();
+        assert_eq!((change.cols, change.rows), (200, 60));
+        assert!(change.letterboxed.is_empty());
+
+        let change = arbiter.resize(laptop, TerminalSize::new(100, 30)).unwrap();
+        assert_eq!((change.cols, change.rows), (100, 30));
+        assert_eq!(change.controller, Some(laptop));
+        assert_eq!(change.letterboxed, vec![monitor]);
+
+        // Once the small window goes, the big one gets its whole size back
+        let change = arbiter.remove(laptop).unwrap();
+        assert_eq!((change.cols, change.rows), (200, 60));
+        assert!(change.letterboxed.is_empty());
+    }
+
+    #[test]
+    fn test_smallest_mixes_dimensions() {
+        let (wide, tall) = (Uuid::new_v4(), Uuid::new_v4());
+        let mut arbiter = SizeArbiter::default();
+        arbiter.resize(wide, TerminalSize::new(200, 30));
+        let change = arbiter.resize(tall, TerminalSize::new(80, 60)).unwrap();
+        assert_eq!((change.cols, change.rows), (80, 30));
+        assert_eq!(change.controller, None);
+        assert_eq!(change.letterboxed.len(), 2);
+    }
+
+    #[test]
+    fn test_active_controller_follows_typing() {
+        let (a, b) = (Uuid::new_v4(), Uuid::new_v4());
+        let mut arbiter = SizeArbiter::new(SizePolicy::ActiveController);
+        arbiter.resize(a, TerminalSize::new(120, 40));
+        arbiter.resize(b, TerminalSize::new(90, 30));
+
+        let change = arbiter.activity(a).unwrap();
+        assert_eq!((change.cols, change.rows), (120, 40));
+        assert_eq!(change.controller, Some(a));
+        assert_eq!(change.letterboxed, vec![b]);
+        // Typing again changes nothing
+        assert!(arbiter.activity(a).is_none());
+    }
+
+    #[test]
+    fn test_fixed_ignores_windows() {
+        let client = Uuid::new_v4();
+        let mut arbiter = SizeArbiter::default();
+        arbiter.resize(client, TerminalSize::new(100, 30));
+
+        let change = arbiter.set_policy(SizePolicy::Fixed { cols: 80, rows: 24 }).unwrap();
+        assert_eq!((change.cols, change.rows), (80, 24));
+        assert_eq!(change.letterboxed, vec![client]);
+        assert!(arbiter.resize(client, TerminalSize::new(100, 30)).is_none());
+        assert!(SizePolicy::Fixed { cols: 0, rows: 24 }.validate().is_err());
+    }
+
+    #[test]
+    fn test_policy_serialization() {
+        let policy: SizePolicy =
+            serde_json::from_value(serde_json::json!({"policy": "fixed", "cols": 80, "rows": 24}))
+                .unwrap();
+        assert_eq!(policy, SizePolicy::Fixed { cols: 80, rows: 24 });
+        let json = serde_json::to_value(SizePolicy::ActiveController).unwrap();
+        assert_eq!(json["policy"], "active_controller");
+    }
+}
@@ -0,0 +1,439 @@
+//! Tail sessions: log files followed into a session's output
+//!
+//! A tail session has no PTY. Each file it follows gets its own task that
+//! reads what was appended since the last read (local files by polling,
+//! remote ones through `tail -F` over ssh) and sends it on as whole lines,
+//! prefixed with the file's label in a colour of its own, so several logs
+//! interleave into one readable stream. The byte offset reached in each
+//! file is kept across reconnects: a dropped ssh connection resumes where
+//! it stopped, and a truncated or replaced local file is read again from
+//! the start. A remote file rotated while connected is followed by `tail`
+//! itself, which leaves the offset approximate until the next reconnect.
+
+use anyhow::{anyhow, bail, Context, Result};
+use serde::{Deserialize, Serialize};
+use std::io::SeekFrom;
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncSeekExt, BufReader};
+use tokio::process::Command;
+use tokio::sync::mpsc;
+use tokio::time::{sleep, Duration};
+use tracing::debug;
+
+/// How often local files are checked for new output
+const POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+/// How much of a file's existing content is shown when following starts
+const INITIAL_BYTES: u64 = 8 * 1024;
+
+/// Longest line held back waiting for its newline
+const MAX_PARTIAL_LINE: usize = 64 * 1024;
+
+const RECONNECT_MIN: Duration = Duration::from_secs(1);
+const RECONNECT_MAX: Duration = Duration::from_secs(30);
+
+/// Label colours (SGR foreground codes), assigned to files in order
+const COLORS: [u8; 6] = [36, 33, 35, 32, 34, 31];
+
+/// A file followed by a tail session
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TailSource {
+    pub path: String,
+    /// Host (`[user@]host`) to read the file on over ssh; local when unset
+    #[serde(default)]
+    pub host: Option<String>,
+    #[serde(default)]
+    pub port: Option<u16>,
+    /// Shown before each line; the file name when unset
+    #[serde(default)]
+    pub label: Option<String>,
+}
+
+impl TailSource {
+    pub fn label(&self) -> String {
+        match &self.label {
+            Some(label) if !label.trim().is_empty() => label.clone(),
+            _ => {
+                let name = self.path.rsplit('/').next().unwrap_or(&self.path);
+                match &self.host {
+                    Some(host) => format!("{}:{}", host, name),
+                    None => name.to_string(),
+                }
+            }
+        }
+    }
+}
+
+/// Start following `sources`; labelled output is sent to `output` until
+/// its receiver is dropped
+pub fn spawn_followers(sources: &[TailSource], output: mpsc::Sender<Vec<u8>>) -> Result<()> {
+    if sources.is_empty() {
+        bail!("A tail session needs at least one file");
+    }
+    if let Some(source) = sources.iter().find(|s| s.path.trim().is_empty()) {
+        bail!("Missing path for {}", source.label());
+    }
+
+    let width = sources.iter().map(|s| s.label().chars().count()).max().unwrap_or(0);
+    for (index, source) in sources.iter().enumerate() {
+        let labeler = LineLabeler::new(&source.label(), width, COLORS[index % COLORS.len()]);
+        let source = source.clone();
+        let output = output.clone();
+        tokio::spawn(async move {
+            match source.host.clone() {
+                Some(host) => follow_remote(source, host, labeler, output).await,
+                None => follow_local(source, labeler, output).await,
+            }
+        });
+    }
+    Ok(())
+}
+
+/// Turns a file's raw output into complete, labelled lines
+struct LineLabeler {
+    prefix: String,
+    partial: Vec<u8>,
+    /// Drop everything up to the next newline (reading began mid-line)
+    skip_to_newline: bool,
+}
+
+impl LineLabeler {
+    fn new(label: &str, width: usize, color: u8) -> Self {
+        Self {
+            prefix: format!("\x1b[{}m{:<width$}\x1b[0m │ ", color, label, width = width),
+            partial: Vec::new(),
+            skip_to_newline: false,
+        }
+    }
+
+    /// Labelled lines completed by `data`
+    fn feed(&mut self, mut data: &[u8]) -> Vec<u8> {
+        if self.skip_to_newline {
+            match data.iter().position(|&b| b == b'\n') {
+                Some(newline) => {
+                    self.skip_to_newline = false;
+                    data = &data[newline + 1..];
+                }
+                None => return Vec::new(),
+            }
+        }
+
+        let mut out = Vec::new();
+        self.partial.extend_from_slice(data);
+        let mut start = 0;
+        while let Some(newline) = self.partial[start..].iter().position(|&b| b == b'\n') {
+            let end = start + newline;
+            self.push_line(&mut out, start, end);
+            start = end + 1;
+        }
+        self.partial.drain(..start);
+
+        if self.partial.len() > MAX_PARTIAL_LINE {
+            self.push_line(&mut out, 0, self.partial.len());
+            self.partial.clear();
+        }
+        out
+    }
+
+    fn push_line(&self, out: &mut Vec<u8>, start: usize, end: usize) {
+        let line = &self.partial[start..end];
+        let line = line.strip_suffix(b"\r").unwrap_or(line);
+        out.extend_from_slice(self.prefix.as_bytes());
+        out.extend_from_slice(line);
+        out.extend_from_slice(b"\r\n");
+    }
+
+    /// A dimmed line about the file itself (missing, truncated, reconnecting)
+    fn notice(&self, message: &str) -> Vec<u8> {
+        format!("{}\x1b[2m{}\x1b[0m\r\n", self.prefix, message).into_bytes()
+    }
+
+    /// Output is no longer contiguous with what came before
+    fn restart(&mut self) {
+        self.partial.clear();
+        self.skip_to_newline = false;
+    }
+}
+
+async fn follow_local(source: TailSource, mut labeler: LineLabeler, output: mpsc::Sender<Vec<u8>>) {
+    let path = match source.path.strip_prefix('~') {
+        Some(rest) => dirs::home_dir()
+            .map(|home| home.join(rest.trim_start_matches('/')))
+            .unwrap_or_else(|| PathBuf::from(&source.path)),
+        None => PathBuf::from(&source.path),
+    };
+
+    let mut offset: Option<u64> = None;
+    let mut identity: Option<u64> = None;
+    let mut missing = false;
+    while !output.is_closed() {
+        match tokio::fs::metadata(&path).await {
+            Err(e) => {
+                if !missing {
+                    missing = true;
+                    let notice = labeler.notice(&format!("waiting for {}: {}", source.path, e));
+                    if output.send(notice).await.is_err() {
+                        break;
+                    }
+                    // Whatever appears later is new, so show all of it
+                    offset = Some(0);
+                }
+            }
+            Ok(meta) => {
+                missing = false;
+                let len = meta.len();
+                let id = file_identity(&meta);
+                let (start, notice) = match offset {
+                    None if len > INITIAL_BYTES => {
+                        labeler.skip_to_newline = true;
+                        (len - INITIAL_BYTES, None)
+                    }
+                    None => (0, None),
+                    Some(_) if identity.is_some() && id != identity => {
+                        (0, Some("file replaced, following the new one"))
+                    }
+                    Some(at) if len < at => (0, Some("file truncated")),
+                    Some(at) => (at, None),
+                };
+                identity = id;
+                if let Some(notice) = notice {
+                    labeler.restart();
+                    if output.send(labeler.notice(notice)).await.is_err() {
+                        break;
+                    }
+                }
+
+                match read_from(&path, start, len).await {
+                    Ok(data) => {
+                        offset = Some(start + data.len() as u64);
+                        let lines = labeler.feed(&data);
+                        if !lines.is_empty() && output.send(lines).await.is_err() {
+                            break;
+                        }
+                    }
+                    Err(e) => {
+                        debug!("Failed to read {}: {}", path.display(), e);
+                        offset = Some(start);
+                    }
+                }
+            }
+        }
+        sleep(POLL_INTERVAL).await;
+    }
+    debug!("Stopped following {}", path.display());
+}
+
+/// Bytes `start..end` of a file
+async fn read_from(path: &Path, start: u64, end: u64) -> Result<Vec<u8>> {
+    if end <= start {
+        return Ok(Vec::new());
+    }
+    let mut file = tokio::fs::File::open(path).await?;
+    file.seek(SeekFrom::Start(start)).await?;
+    let mut data = Vec::new();
+    file.take(end - start).read_to_end(&mut data).await?;
+    Ok(data)
+}
+
+#[cfg(unix)]
+fn file_identity(meta: &std::fs::Metadata) -> Option<u64> {
+    use std::os::unix::fs::MetadataExt;
+    Some(meta.ino())
+}
+
+#[cfg(not(unix))]
+fn file_identity(_meta: &std::fs::Metadata) -> Option<u64> {
+    None
+}
+
+async fn follow_remote(
+    source: TailSource,
+    host: String,
+    mut labeler: LineLabeler,
+    output: mpsc::Sender<Vec<u8>>,
+) {
+    let mut offset: Option<u64> = None;
+    let mut backoff = RECONNECT_MIN;
+    loop {
+        let result = stream_remote(&source, &host, &mut offset, &mut backoff, &mut labeler, &output).await;
+        let Err(e) = result else {
+            break;
+        };
+
+        labeler.restart();
+        let notice = format!("{}; reconnecting in {}s", e, backoff.as_secs());
+        if output.send(labeler.notice(&notice)).await.is_err() {
+            break;
+        }
+        tokio::select! {
+            _ = sleep(backoff) => {}
+            _ = output.closed() => break,
+        }
+        backoff = (backoff * 2).min(RECONNECT_MAX);
+    }
+    debug!("Stopped following {} on {}", source.path, host);
+}
+
+/// Run `tail -F` on the host until the connection drops (`Err`) or the
+/// session ends (`Ok`)
+async fn stream_remote(
+    source: &TailSource,
+    host: &str,
+    offset: &mut Option<u64>,
+    backoff: &mut Duration,
+    labeler: &mut LineLabeler,
+    output: &mpsc::Sender<Vec<u8>>,
+) -> Result<()> {
+    let mut command = Command::new("ssh");
+    command.args(["-o", "BatchMode=yes", "-o", "ServerAliveInterval=15"]);
+    if let Some(port) = source.port {
+        command.arg("-p").arg(port.to_string());
+    }
+    let mut child = command
+        .arg(host)
+        .arg(remote_command(&source.path, *offset))
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true)
+        .spawn()
+        .context("Failed to run ssh")?;
+    let mut stdout = BufReader::new(child.stdout.take().context("ssh has no stdout")?);
+    let mut stderr = child.stderr.take().context("ssh has no stderr")?;
+
+    // The remote side first reports the offset it starts from
+    let mut first = String::new();
+    tokio::select! {
+        read = stdout.read_line(&mut first) => { read?; }
+        _ = output.closed() => return Ok(()),
+    }
+    let Ok(start) = first.trim().parse::<u64>() else {
+        let mut message = String::new();
+        let _ = stderr.read_to_string(&mut message).await;
+        let reason = message.lines().last().unwrap_or("connection closed").trim().to_string();
+        return Err(anyhow!("can't follow {} on {}: {}", source.path, host, reason));
+    };
+
+    match *offset {
+        None if start > 0 => labeler.skip_to_newline = true,
+        Some(at) if start < at => {
+            if output.send(labeler.notice("file truncated")).await.is_err() {
+                return Ok(());
+            }
+        }
+        _ => {}
+    }
+    *offset = Some(start);
+    *backoff = RECONNECT_MIN;
+    debug!("Following {} on {} from byte {}", source.path, host, start);
+
+    let mut buffer = vec![0u8; 8192];
+    loop {
+        let read = tokio::select! {
+            read = stdout.read(&mut buffer) => read?,
+            _ = output.closed() => return Ok(()),
+        };
+        if read == 0 {
+            bail!("lost connection to {}", host);
+        }
+        *offset = offset.map(|at| at + read as u64);
+        let lines = labeler.feed(&buffer[..read]);
+        if !lines.is_empty() && output.send(lines).await.is_err() {
+            return Ok(());
+        }
+    }
+}
+
+/// Shell command that prints the byte offset it starts at, then follows
+/// the file from there. Without an offset it starts near the end; a file
+/// now shorter than the offset is read from the beginning.
+fn remote_command(path: &str, offset: Option<u64>) -> String {
+    let path = shell_quote(path);
+    let start = match offset {
+        Some(at) => format!("start={at}; if [ \"$size\" -lt \"$start\" ]; then start=0; fi"),
+        None => format!(
+            "start=0; if [ \"$size\" -gt {INITIAL_BYTES} ]; then start=$((size - {INITIAL_BYTES})); fi"
+        ),
+    };
+    format!(
+        "size=$(wc -c < {path}) || exit 1; {start}; echo \"$start\"; exec tail -c +$((start + 1)) -F -- {path}"
+    )
+}
+
+fn shell_quote(value: &str) -> String {
+    format!("'{}'", value.replace('\'', r"'\''"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    fn source(path: &str) -> TailSource {
+        TailSource {
+            path: path.to_string(),
+            host: None,
+            port: None,
+            label: None,
+        }
+    }
+
+    #[test]
+    fn test_labels_complete_lines() {
+        let mut labeler = LineLabeler::new("app.log", 9, 36);
+        assert!(labeler.feed(b"GET /health 200").is_empty());
+        let out = String::from_utf8(labeler.feed(b" 3ms\r\nGET /login 500\nPOST")).unwrap();
+        assert_eq!(
+            out,
+            "\x1b[36mapp.log  \x1b[0m │ GET /health 200 3ms\r\n\x1b[36mapp.log  \x1b[0m │ GET /login 500\r\n"
+        );
+
+        // Reading began mid-line: the fragment is dropped
+        let mut labeler = LineLabeler::new("db", 2, 33);
+        labeler.skip_to_newline = true;
+        let out = String::from_utf8(labeler.feed(b"ed 3 rows\ncheckpoint\n")).unwrap();
+        assert_eq!(out, "\x1b[33mdb\x1b[0m │ checkpoint\r\n");
+    }
+
+    #[test]
+    fn test_source_labels_and_remote_command() {
+        assert_eq!(source("/var/log/syslog").label(), "syslog");
+        let remote = TailSource {
+            host: Some("web-1".to_string()),
+            ..source("/srv/app/log/app's.log")
+        };
+        assert_eq!(remote.label(), "web-1:app's.log");
+
+        let command = remote_command(&remote.path, Some(42));
+        assert!(command.contains("start=42;"));
+        assert!(command.contains(r"-- '/srv/app/log/app'\''s.log'"));
+        assert!(remote_command("/x", None).contains("size - 8192"));
+    }
+
+    #[tokio::test]
+    async fn test_follows_local_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("build.log");
+        let mut file = std::fs::File::create(&path).unwrap();
+        writeln!(file, "Compiling api").unwrap();
+
+        let (output, mut lines) = mpsc::channel(16);
+        spawn_followers(&[source(path.to_str().unwrap())], output).unwrap();
+        let first = String::from_utf8(lines.recv().await.unwrap()).unwrap();
+        assert!(first.ends_with("Compiling api\r\n"));
+
+        writeln!(file, "error: could not compile `api`").unwrap();
+        let next = String::from_utf8(lines.recv().await.unwrap()).unwrap();
+        assert!(next.ends_with("error: could not compile `api`\r\n"));
+
+        // Truncated by a log rotator: start over
+        std::fs::write(&path, "Finished\n").unwrap();
+        let notice = String::from_utf8(lines.recv().await.unwrap()).unwrap();
+        assert!(notice.contains("file"));
+        let again = String::from_utf8(lines.recv().await.unwrap()).unwrap();
+        assert!(again.ends_with("Finished\r\n"));
+
+        assert!(spawn_followers(&[], mpsc::channel(1).0).is_err());
+    }
+}
@@ -0,0 +1,476 @@
+//! Team host directory
+//!
+//! Optionally pulls a team-managed list of SSH hosts from a central HTTPS
+//! endpoint and merges it, read-only, with the hosts saved in local
+//! workspaces. The endpoint serves one JSON envelope:
+//!
+//! ```json
+//! {"manifest": "<base64 manifest JSON>", "signature": "<base64 Ed25519 signature>"}
+//! ```
+//!
+//! The signature covers the decoded manifest bytes and must verify against
+//! the configured team key, so neither the server nor the network can hand
+//! out hosts on the team's behalf. Each manifest carries a serial that must
+//! never go backwards, which stops a replayed old manifest from bringing
+//! back hosts the team removed.
+//!
+//! Hosts may be limited to roles; the manifest's `members` assigns roles to
+//! user names, and a user sees only unrestricted hosts and those for a role
+//! they hold. The last verified manifest is cached beside the database so
+//! the directory survives restarts without network access.
+
+use crate::workspace::HostProfile;
+use anyhow::{bail, Context, Result};
+use base64::{engine::general_purpose::STANDARD, Engine as _};
+use chrono::{DateTime, Utc};
+use ring::signature::{UnparsedPublicKey, ED25519};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+use tracing::{info, warn};
+
+/// Largest envelope accepted from the endpoint
+const MAX_ENVELOPE_BYTES: usize = 4 * 1024 * 1024;
+
+/// Team directory settings
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TeamDirectoryConfig {
+    /// HTTPS URL of the signed manifest; unset leaves team mode off
+    pub url: Option<String>,
+    /// Ed25519 public key manifests must be signed with, base64
+    pub public_key: Option<String>,
+    /// Name the manifest's `members` is looked up by; defaults to the login
+    /// user
+    pub user: Option<String>,
+    /// Minutes between pulls
+    pub refresh_minutes: u64,
+}
+
+impl Default for TeamDirectoryConfig {
+    fn default() -> Self {
+        Self {
+            url: None,
+            public_key: None,
+            user: None,
+            refresh_minutes: 30,
+        }
+    }
+}
+
+impl TeamDirectoryConfig {
+    /// Everything that stops team mode from working as configured
+    pub fn problems(&self) -> Vec<String> {
+        let mut problems = Vec::new();
+        let Some(url) = &self.url else {
+            return problems;
+        };
+        if !url.starts_with("https://") {
+            problems.push("team.url must be an https:// URL".to_string());
+        }
+        match &self.public_key {
+            None => problems.push("team.public_key is required with team.url".to_string()),
+            Some(key) => {
+                if decode_public_key(key).is_err() {
+                    problems.push("team.public_key is not a base64 Ed25519 public key".to_string());
+                }
+            }
+        }
+        if self.refresh_minutes == 0 {
+            problems.push("team.refresh_minutes must not be 0".to_string());
+        }
+        problems
+    }
+}
+
+/// What the endpoint serves
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SignedManifest {
+    pub manifest: String,
+    pub signature: String,
+}
+
+/// The team's host directory, as signed by the team
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TeamManifest {
+    pub team: String,
+    /// Increases with every published manifest
+    pub serial: u64,
+    pub issued_at: DateTime<Utc>,
+    /// Refused after this time, so a withheld update can't keep an old
+    /// directory alive forever
+    #[serde(default)]
+    pub expires_at: Option<DateTime<Utc>>,
+    /// Roles held by each user
+    #[serde(default)]
+    pub members: BTreeMap<String, Vec<String>>,
+    pub hosts: Vec<TeamHost>,
+}
+
+/// A host managed by the team
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TeamHost {
+    pub alias: String,
+    pub host: String,
+    #[serde(default)]
+    pub port: Option<u16>,
+    #[serde(default)]
+    pub username: Option<String>,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    /// Roles that may see and use the host; everyone when empty
+    #[serde(default)]
+    pub roles: Vec<String>,
+}
+
+impl TeamManifest {
+    /// Hosts `user` may see
+    pub fn hosts_for(&self, user: &str) -> Vec<&TeamHost> {
+        let roles = self.members.get(user).map(Vec::as_slice).unwrap_or_default();
+        self.hosts
+            .iter()
+            .filter(|host| host.roles.is_empty() || host.roles.iter().any(|r| roles.contains(r)))
+            .collect()
+    }
+}
+
+/// Verify an envelope against the team key and return its manifest
+pub fn verify(envelope: &[u8], public_key: &[u8]) -> Result<TeamManifest> {
+    let envelope: SignedManifest =
+        serde_json::from_slice(envelope).context("Team directory envelope is not valid JSON")?;
+    let manifest = STANDARD
+        .decode(&envelope.manifest)
+        .context("Team manifest is not base64")?;
+    let signature = STANDARD
+        .decode(&envelope.signature)
+        .context("Team manifest signature is not base64")?;
+
+    UnparsedPublicKey::new(&ED25519, public_key)
+        .verify(&manifest, &signature)
+        .map_err(|_| anyhow::anyhow!("Team manifest signature does not verify"))?;
+
+    let manifest: TeamManifest =
+        serde_json::from_slice(&manifest).context("Team manifest is not valid JSON")?;
+    if manifest.expires_at.is_some_and(|expires_at| expires_at < Utc::now()) {
+        bail!("Team manifest {} has expired", manifest.serial);
+    }
+    Ok(manifest)
+}
+
+fn decode_public_key(key: &str) -> Result<Vec<u8>> {
+    let key = STANDARD.decode(key.trim())?;
+    if key.len() != 32 {
+        bail!("Ed25519 public keys are 32 bytes, got {}", key.len());
+    }
+    Ok(key)
+}
+
+/// Team directory state reported over IPC
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct TeamDirectoryStatus {
+    pub url: String,
+    pub user: String,
+    pub team: Option<String>,
+    pub serial: Option<u64>,
+    /// Hosts the user may see
+    pub host_count: usize,
+    pub last_sync: Option<DateTime<Utc>>,
+    /// Why the last pull failed; the previous manifest stays in use
+    pub last_error: Option<String>,
+}
+
+#[derive(Default)]
+struct State {
+    manifest: Option<TeamManifest>,
+    last_sync: Option<DateTime<Utc>>,
+    last_error: Option<String>,
+}
+
+/// Pulls and serves the team's host directory
+pub struct TeamDirectory {
+    url: String,
+    public_key: Vec<u8>,
+    user: String,
+    refresh: Duration,
+    cache_path: PathBuf,
+    client: reqwest::Client,
+    state: RwLock<State>,
+}
+
+impl TeamDirectory {
+    /// Set up team mode, starting from the cached manifest if it still
+    /// verifies; None when no URL is configured
+    pub fn new(config: &TeamDirectoryConfig, cache_path: PathBuf) -> Result<Option<Self>> {
+        let Some(url) = config.url.clone() else {
+            return Ok(None);
+        };
+        let public_key = decode_public_key(config.public_key.as_deref().unwrap_or_default())
+            .context("Invalid team.public_key")?;
+        let user = config
+            .user
+            .clone()
+            .or_else(|| std::env::var("USER").ok())
+            .or_else(|| std::env::var("USERNAME").ok())
+            .unwrap_or_default();
+
+        let mut state = State::default();
+        if let Ok(cached) = std::fs::read(&cache_path) {
+            match verify(&cached, &public_key) {
+                Ok(manifest) => state.manifest = Some(manifest),
+                Err(e) => warn!("Ignoring cached team directory: {:#}", e),
+            }
+        }
+
+        Ok(Some(Self {
+            url,
+            public_key,
+            user,
+            refresh: Duration::from_secs(config.refresh_minutes * 60),
+            cache_path,
+            client: reqwest::Client::builder()
+                .timeout(Duration::from_secs(30))
+                .https_only(true)
+                .build()?,
+            state: RwLock::new(state),
+        }))
+    }
+
+    /// Pull the directory now; on failure the current one stays in use
+    pub async fn sync(&self) -> Result<TeamDirectoryStatus> {
+        let result = self.pull().await;
+        {
+            let mut state = self.state.write().unwrap();
+            match &result {
+                Ok(()) => {
+                    state.last_sync = Some(Utc::now());
+                    state.last_error = None;
+                }
+                Err(e) => state.last_error = Some(format!("{:#}", e)),
+            }
+        }
+        result.map(|()| self.status())
+    }
+
+    async fn pull(&self) -> Result<()> {
+        let response = self
+            .client
+            .get(&self.url)
+            .send()
+            .await
+            .and_then(reqwest::Response::error_for_status)
+            .context("Failed to fetch the team directory")?;
+        if response.content_length().unwrap_or(0) > MAX_ENVELOPE_BYTES as u64 {
+            bail!("Team directory is larger than {} bytes", MAX_ENVELOPE_BYTES);
+        }
+        let envelope = response.bytes().await?;
+        if envelope.len() > MAX_ENVELOPE_BYTES {
+            bail!("Team directory is larger than {} bytes", MAX_ENVELOPE_BYTES);
+        }
+
+        let manifest = verify(&envelope, &self.public_key)?;
+        let current = self.state.read().unwrap().manifest.as_ref().map(|m| m.serial);
+        match current {
+            Some(serial) if manifest.serial < serial => bail!(
+                "Refusing team manifest {}: older than the current {}",
+                manifest.serial,
+                serial
+            ),
+            Some(serial) if manifest.serial == serial => return Ok(()),
+            _ => {}
+        }
+
+        pulsar_fs::write(&self.cache_path, &envelope).context("Failed to cache the team directory")?;
+        info!(
+            "Team directory {} updated to serial {} ({} hosts)",
+            manifest.team,
+            manifest.serial,
+            manifest.hosts_for(&self.user).len()
+        );
+        self.state.write().unwrap().manifest = Some(manifest);
+        Ok(())
+    }
+
+    /// Pull on start and then every `refresh_minutes`
+    pub async fn run(self: Arc<Self>) {
+        let mut interval = tokio::time::interval(self.refresh);
+        loop {
+            interval.tick().await;
+            if let Err(e) = self.sync().await {
+                warn!("Team directory sync failed: {:#}", e);
+            }
+        }
+    }
+
+    /// Team hosts the user may see, as host profiles
+    pub fn hosts(&self) -> Vec<HostProfile> {
+        let state = self.state.read().unwrap();
+        let Some(manifest) = &state.manifest else {
+            return Vec::new();
+        };
+        manifest
+            .hosts_for(&self.user)
+            .into_iter()
+            .map(|host| HostProfile {
+                alias: host.alias.clone(),
+                host: host.host.clone(),
+                port: host.port,
+                username: host.username.clone(),
+                workspace: manifest.team.clone(),
+                tags: host.tags.clone(),
+            })
+            .collect()
+    }
+
+    pub fn status(&self) -> TeamDirectoryStatus {
+        let state = self.state.read().unwrap();
+        TeamDirectoryStatus {
+            url: self.url.clone(),
+            user: self.user.clone(),
+            team: state.manifest.as_ref().map(|m| m.team.clone()),
+            serial: state.manifest.as_ref().map(|m| m.serial),
+            host_count: state
+                .manifest
+                .as_ref()
+                .map_or(0, |m| m.hosts_for(&self.user).len()),
+            last_sync: state.last_sync,
+            last_error: state.last_error.clone(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ring::rand::SystemRandom;
+    use ring::signature::{Ed25519KeyPair, KeyPair};
+
+    fn key_pair() -> Ed25519KeyPair {
+        let pkcs8 = Ed25519KeyPair::generate_pkcs8(&SystemRandom::new()).unwrap();
+        Ed25519KeyPair::from_pkcs8(pkcs8.as_ref()).unwrap()
+    }
+
+    fn host(alias: &str, roles: &[&str]) -> TeamHost {
+        TeamHost {
+            alias: alias.to_string(),
+            host: format!("{}.internal", alias),
+            port: None,
+            username: None,
+            tags: Vec::new(),
+            roles: roles.iter().map(|r| r.to_string()).collect(),
+        }
+    }
+
+    fn manifest(serial: u64) -> TeamManifest {
+        TeamManifest {
+            team: "platform".to_string(),
+            serial,
+            issued_at: Utc::now(),
+            expires_at: None,
+            members: BTreeMap::from([
+                ("alice".to_string(), vec!["ops".to_string()]),
+                ("bob".to_string(), vec!["dev".to_string()]),
+            ]),
+            hosts: vec![
+                host("bastion", &[]),
+                host("db-prod", &["ops"]),
+                host("ci", &["dev", "ops"]),
+            ],
+        }
+    }
+
+    fn sign(manifest: &TeamManifest, key: &Ed25519KeyPair) -> Vec<u8> {
+        let bytes = serde_json::to_vec(manifest).unwrap();
+        serde_json::to_vec(&SignedManifest {
+            manifest: STANDARD.encode(&bytes),
+            signature: STANDARD.encode(key.sign(&bytes)),
+        })
+        .unwrap()
+    }
+
+    #[test]
+    fn test_verify_signed_manifest() {
+        let key = key_pair();
+        let original = manifest(3);
+        let envelope = sign(&original, &key);
+
+        assert_eq!(verify(&envelope, key.public_key().as_ref()).unwrap(), original);
+
+        // Another key's signature is refused
+        let other = key_pair();
+        assert!(verify(&envelope, other.public_key().as_ref()).is_err());
+    }
+
+    #[test]
+    fn test_verify_rejects_tampering_and_expiry() {
+        let key = key_pair();
+        let mut envelope: SignedManifest = serde_json::from_slice(&sign(&manifest(1), &key)).unwrap();
+        let mut tampered = manifest(1);
+        tampered.hosts.push(host("evil", &[]));
+        envelope.manifest = STANDARD.encode(serde_json::to_vec(&tampered).unwrap());
+        let envelope = serde_json::to_vec(&envelope).unwrap();
+        assert!(verify(&envelope, key.public_key().as_ref()).is_err());
+
+        let mut expired = manifest(2);
+        expired.expires_at = Some(Utc::now() - chrono::Duration::hours(1));
+        assert!(verify(&sign(&expired, &key), key.public_key().as_ref()).is_err());
+    }
+
+    #[test]
+    fn test_hosts_for_roles() {
+        let manifest = manifest(1);
+        let aliases = |user| {
+            manifest
+                .hosts_for(user)
+                .iter()
+                .map(|h| h.alias.as_str())
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(aliases("alice"), vec!["bastion", "db-prod", "ci"]);
+        assert_eq!(aliases("bob"), vec!["bastion", "ci"]);
+        assert_eq!(aliases("mallory"), vec!["bastion"]);
+    }
+
+    #[test]
+    fn test_cached_manifest_is_verified_on_load() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = dir.path().join("team-directory.json");
+        let key = key_pair();
+        std::fs::write(&cache, sign(&manifest(5), &key)).unwrap();
+
+        let config = TeamDirectoryConfig {
+            url: Some("https://hosts.example.com/directory.json".to_string()),
+            public_key: Some(STANDARD.encode(key.public_key().as_ref())),
+            user: Some("bob".to_string()),
+            ..TeamDirectoryConfig::default()
+        };
+        assert!(config.problems().is_empty());
+
+        let directory = TeamDirectory::new(&config, cache.clone()).unwrap().unwrap();
+        assert_eq!(directory.status().serial, Some(5));
+        let hosts = directory.hosts();
+        assert_eq!(hosts.len(), 2);
+        assert_eq!(hosts[0].workspace, "platform");
+
+        // A cache signed by someone else is ignored
+        std::fs::write(&cache, sign(&manifest(6), &key_pair())).unwrap();
+        let directory = TeamDirectory::new(&config, cache).unwrap().unwrap();
+        assert_eq!(directory.status().serial, None);
+        assert!(directory.hosts().is_empty());
+    }
+
+    #[test]
+    fn test_config_problems() {
+        assert!(TeamDirectoryConfig::default().problems().is_empty());
+
+        let config = TeamDirectoryConfig {
+            url: Some("http://hosts.example.com".to_string()),
+            public_key: Some("not a key".to_string()),
+            refresh_minutes: 0,
+            ..TeamDirectoryConfig::default()
+        };
+        assert_eq!(config.problems().len(), 3);
+    }
+}
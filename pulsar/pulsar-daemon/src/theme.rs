@@ -0,0 +1,230 @@
+//! Terminal appearance shared with remote clients
+//!
+//! The desktop pushes its appearance settings to the daemon, which resolves
+//! the named color scheme into a full palette. Attach responses carry the
+//! current theme and subscribers receive every change, so web and mobile
+//! clients render sessions exactly like the desktop does.
+
+use anyhow::{bail, Result};
+use serde::{Deserialize, Serialize};
+use tokio::sync::{broadcast, RwLock};
+
+/// Appearance settings pushed by the desktop client
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ThemeUpdate {
+    /// Built-in color scheme name (see [`Palette::named`])
+    pub color_scheme: String,
+    pub font_family: String,
+    pub font_size: u8,
+    pub line_height: f32,
+    /// "block", "beam" or "underline"
+    pub cursor_style: String,
+    pub cursor_blink: bool,
+}
+
+/// Resolved colors, as `#rrggbb` strings
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Palette {
+    pub foreground: String,
+    pub background: String,
+    pub cursor: String,
+    pub selection: String,
+    /// ANSI colors 0-15 (normal then bright)
+    pub ansi: Vec<String>,
+}
+
+/// Font metrics hints; clients substitute the family if unavailable but
+/// should keep the size and line height so line wrapping matches
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FontHints {
+    pub family: String,
+    pub size_px: u8,
+    pub line_height: f32,
+}
+
+/// Complete theme sent to remote clients
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TerminalTheme {
+    /// Increments on every change so clients can ignore stale events
+    pub version: u64,
+    pub color_scheme: String,
+    pub palette: Palette,
+    pub font: FontHints,
+    pub cursor_style: String,
+    pub cursor_blink: bool,
+}
+
+impl Default for TerminalTheme {
+    fn default() -> Self {
+        Self {
+            version: 0,
+            color_scheme: "default".to_string(),
+            palette: Palette::named("default").expect("default palette exists"),
+            font: FontHints {
+                family: "Menlo".to_string(),
+                size_px: 14,
+                line_height: 1.2,
+            },
+            cursor_style: "block".to_string(),
+            cursor_blink: true,
+        }
+    }
+}
+
+impl Palette {
+    /// Look up a built-in color scheme
+    pub fn named(name: &str) -> Option<Self> {
+        let (foreground, background, cursor, selection, ansi): (_, _, _, _, [&str; 16]) = match name {
+            "default" => (
+                "#d4d4d4", "#1e1e1e", "#ffffff", "#264f78",
+                [
+                    "#000000", "#cd3131", "#0dbc79", "#e5e510", "#2472c8", "#bc3fbc", "#11a8cd", "#e5e5e5",
+                    "#666666", "#f14c4c", "#23d18b", "#f5f543", "#3b8eea", "#d670d6", "#29b8db", "#ffffff",
+                ],
+            ),
+            "solarized-dark" => (
+                "#839496", "#002b36", "#93a1a1", "#073642",
+                [
+                    "#073642", "#dc322f", "#859900", "#b58900", "#268bd2", "#d33682", "#2aa198", "#eee8d5",
+                    "#002b36", "#cb4b16", "#586e75", "#657b83", "#839496", "#6c71c4", "#93a1a1", "#fdf6e3",
+                ],
+            ),
+            "solarized-light" => (
+                "#657b83", "#fdf6e3", "#586e75", "#eee8d5",
+                [
+                    "#073642", "#dc322f", "#859900", "#b58900", "#268bd2", "#d33682", "#2aa198", "#eee8d5",
+                    "#002b36", "#cb4b16", "#586e75", "#657b83", "#839496", "#6c71c4", "#93a1a1", "#fdf6e3",
+                ],
+            ),
+            "dracula" => (
+                "#f8f8f2", "#282a36", "#f8f8f2", "#44475a",
+                [
+                    "#21222c", "#ff5555", "#50fa7b", "#f1fa8c", "#bd93f9", "#ff79c6", "#8be9fd", "#f8f8f2",
+                    "#6272a4", "#ff6e6e", "#69ff94", "#ffffa5", "#d6acff", "#ff92df", "#a4ffff", "#ffffff",
+                ],
+            ),
+            _ => return None,
+        };
+
+        Some(Self {
+            foreground: foreground.to_string(),
+            background: background.to_string(),
+            cursor: cursor.to_string(),
+            selection: selection.to_string(),
+            ansi: ansi.iter().map(|c| c.to_string()).collect(),
+        })
+    }
+}
+
+/// Holds the current theme and notifies subscribers of changes
+pub struct ThemeStore {
+    current: RwLock<TerminalTheme>,
+    changes: broadcast::Sender<TerminalTheme>,
+}
+
+impl ThemeStore {
+    pub fn new() -> Self {
+        let (changes, _) = broadcast::channel(16);
+        Self {
+            current: RwLock::new(TerminalTheme::default()),
+            changes,
+        }
+    }
+
+    /// Current theme
+    pub async fn current(&self) -> TerminalTheme {
+        self.current.read().await.clone()
+    }
+
+    /// Subscribe to theme changes
+    pub fn subscribe(&self) -> broadcast::Receiver<TerminalTheme> {
+        self.changes.subscribe()
+    }
+
+    /// Apply new appearance settings and broadcast the resolved theme
+    ///
+    /// Returns the current theme unchanged (without an event) when the
+    /// settings resolve to what clients already have.
+    pub async fn update(&self, update: ThemeUpdate) -> Result<TerminalTheme> {
+        let Some(palette) = Palette::named(&update.color_scheme) else {
+            bail!("Unknown color scheme: {}", update.color_scheme);
+        };
+        if !matches!(update.cursor_style.as_str(), "block" | "beam" | "underline") {
+            bail!("Cursor style must be 'block', 'beam', or 'underline'");
+        }
+
+        let mut current = self.current.write().await;
+        let next = TerminalTheme {
+            version: current.version,
+            color_scheme: update.color_scheme,
+            palette,
+            font: FontHints {
+                family: update.font_family,
+                size_px: update.font_size,
+                line_height: update.line_height,
+            },
+            cursor_style: update.cursor_style,
+            cursor_blink: update.cursor_blink,
+        };
+        if next == *current {
+            return Ok(current.clone());
+        }
+
+        *current = TerminalTheme {
+            version: current.version + 1,
+            ..next
+        };
+        // No subscribers is fine
+        let _ = self.changes.send(current.clone());
+        Ok(current.clone())
+    }
+}
+
+impl Default for ThemeStore {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn update(scheme: &str) -> ThemeUpdate {
+        ThemeUpdate {
+            color_scheme: scheme.to_string(),
+            font_family: "JetBrains Mono".to_string(),
+            font_size: 13,
+            line_height: 1.4,
+            cursor_style: "beam".to_string(),
+            cursor_blink: false,
+        }
+    }
+
+    #[test]
+    fn test_builtin_palettes_are_complete() {
+        for name in ["default", "solarized-dark", "solarized-light", "dracula"] {
+            let palette = Palette::named(name).unwrap();
+            assert_eq!(palette.ansi.len(), 16);
+        }
+        assert!(Palette::named("nope").is_none());
+    }
+
+    #[tokio::test]
+    async fn test_update_broadcasts_changes_once() {
+        let store = ThemeStore::new();
+        let mut rx = store.subscribe();
+
+        let theme = store.update(update("dracula")).await.unwrap();
+        assert_eq!(theme.version, 1);
+        assert_eq!(theme.palette.background, "#282a36");
+        assert_eq!(rx.recv().await.unwrap(), theme);
+
+        // Identical settings do not bump the version or emit an event
+        let again = store.update(update("dracula")).await.unwrap();
+        assert_eq!(again.version, 1);
+        assert!(rx.try_recv().is_err());
+
+        assert!(store.update(update("unknown")).await.is_err());
+    }
+}
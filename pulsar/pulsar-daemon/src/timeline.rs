@@ -0,0 +1,262 @@
+//! Per-session timeline
+//!
+//! Brings together everything that happened in a session, in time order,
+//! so a client can draw a scrubber over its history: commands (from OSC 133
+//! markers or typed input), files sent to it, clients attaching and
+//! leaving, and bookmarks. Commands and bookmarks are kept where they
+//! always were; transfers and connection changes are kept in an
+//! `ActivityLog`.
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use uuid::Uuid;
+
+use crate::session_manager::{Bookmark, ClientId, CommandRecord};
+
+/// Transfers and connection changes kept for each session
+const ACTIVITY_LIMIT: usize = 1000;
+
+/// A file sent to a session
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TransferRecord {
+    pub transfer_id: String,
+    pub file_name: String,
+    pub file_size: u64,
+    pub started_at: DateTime<Utc>,
+    /// None while the transfer is in flight
+    #[serde(default)]
+    pub finished_at: Option<DateTime<Utc>>,
+    #[serde(default)]
+    pub outcome: Option<TransferOutcome>,
+}
+
+/// How a transfer ended
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TransferOutcome {
+    Complete,
+    Failed,
+    Aborted,
+    /// The stream closed before the transfer finished; it can be resumed
+    Interrupted,
+}
+
+/// A client attaching to or leaving a session
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ConnectionEvent {
+    pub at: DateTime<Utc>,
+    pub client_id: ClientId,
+    pub change: ConnectionChange,
+    /// Clients attached once the change took effect
+    pub clients: usize,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ConnectionChange {
+    Attached,
+    Detached,
+    /// The last client left
+    Disconnected,
+}
+
+/// One item of a session's history
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum TimelineEntry {
+    Command(CommandRecord),
+    Transfer(TransferRecord),
+    Connection(ConnectionEvent),
+    Bookmark(Bookmark),
+}
+
+impl TimelineEntry {
+    /// When the entry starts
+    pub fn at(&self) -> DateTime<Utc> {
+        match self {
+            TimelineEntry::Command(command) => command.started_at,
+            TimelineEntry::Transfer(transfer) => transfer.started_at,
+            TimelineEntry::Connection(event) => event.at,
+            TimelineEntry::Bookmark(bookmark) => bookmark.created_at,
+        }
+    }
+
+    /// When the entry ends; None while a command or transfer is running
+    pub fn until(&self) -> Option<DateTime<Utc>> {
+        match self {
+            TimelineEntry::Command(command) => command.finished_at,
+            TimelineEntry::Transfer(transfer) => transfer.finished_at,
+            _ => Some(self.at()),
+        }
+    }
+
+    /// Whether any of the entry falls within `[from, to]`
+    fn overlaps(&self, from: Option<DateTime<Utc>>, to: Option<DateTime<Utc>>) -> bool {
+        let starts_in_time = to.is_none_or(|to| self.at() <= to);
+        let ends_in_time = match (from, self.until()) {
+            (Some(from), Some(until)) => until >= from,
+            _ => true,
+        };
+        starts_in_time && ends_in_time
+    }
+}
+
+/// A session's history over a time range
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SessionTimeline {
+    pub session_id: Uuid,
+    /// Bounds of the whole history, for sizing a scrubber
+    pub created_at: DateTime<Utc>,
+    pub now: DateTime<Utc>,
+    /// Entries in the range, by start time
+    pub entries: Vec<TimelineEntry>,
+}
+
+/// Transfers and connection changes of one session, oldest first
+#[derive(Debug, Default)]
+pub struct ActivityLog {
+    entries: VecDeque<TimelineEntry>,
+}
+
+impl ActivityLog {
+    pub fn connection(&mut self, client_id: ClientId, change: ConnectionChange, clients: usize) {
+        self.push(TimelineEntry::Connection(ConnectionEvent {
+            at: Utc::now(),
+            client_id,
+            change,
+            clients,
+        }));
+    }
+
+    pub fn transfer_started(&mut self, transfer_id: &str, file_name: &str, file_size: u64) {
+        self.push(TimelineEntry::Transfer(TransferRecord {
+            transfer_id: transfer_id.to_string(),
+            file_name: file_name.to_string(),
+            file_size,
+            started_at: Utc::now(),
+            finished_at: None,
+            outcome: None,
+        }));
+    }
+
+    /// Close a transfer that is still in flight; a finished one is left alone
+    pub fn transfer_finished(&mut self, transfer_id: &str, outcome: TransferOutcome) {
+        let transfer = self.entries.iter_mut().rev().find_map(|entry| match entry {
+            TimelineEntry::Transfer(t)
+                if t.transfer_id == transfer_id && t.finished_at.is_none() =>
+            {
+                Some(t)
+            }
+            _ => None,
+        });
+        if let Some(transfer) = transfer {
+            transfer.finished_at = Some(Utc::now());
+            transfer.outcome = Some(outcome);
+        }
+    }
+
+    pub fn entries(&self) -> impl Iterator<Item = &TimelineEntry> {
+        self.entries.iter()
+    }
+
+    fn push(&mut self, entry: TimelineEntry) {
+        if self.entries.len() == ACTIVITY_LIMIT {
+            self.entries.pop_front();
+        }
+        self.entries.push_back(entry);
+    }
+}
+
+/// Merge a session's history into one list ordered by start time, keeping
+/// what overlaps `[from, to]` (either end open when None)
+pub fn merge(
+    commands: Vec<CommandRecord>,
+    activity: &ActivityLog,
+    bookmarks: Vec<Bookmark>,
+    from: Option<DateTime<Utc>>,
+    to: Option<DateTime<Utc>>,
+) -> Vec<TimelineEntry> {
+    let mut entries: Vec<TimelineEntry> = commands
+        .into_iter()
+        .map(TimelineEntry::Command)
+        .chain(activity.entries().cloned())
+        .chain(bookmarks.into_iter().map(TimelineEntry::Bookmark))
+        .filter(|entry| entry.overlaps(from, to))
+        .collect();
+    entries.sort_by_key(TimelineEntry::at);
+    entries
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Duration;
+
+    fn bookmark(created_at: DateTime<Utc>) -> Bookmark {
+        Bookmark {
+            id: Uuid::new_v4(),
+            line: 3,
+            note: None,
+            text: "error[E0308]".to_string(),
+            created_at,
+        }
+    }
+
+    #[test]
+    fn test_transfer_lifecycle() {
+        let mut log = ActivityLog::default();
+        log.transfer_started("t-1", "notes.txt", 42);
+        log.transfer_finished("t-1", TransferOutcome::Complete);
+        // A late failure doesn't rewrite a finished transfer
+        log.transfer_finished("t-1", TransferOutcome::Interrupted);
+
+        let Some(TimelineEntry::Transfer(transfer)) = log.entries().next() else {
+            panic!("expected a transfer");
+        };
+        assert_eq!(transfer.outcome, Some(TransferOutcome::Complete));
+        assert!(transfer.finished_at.is_some());
+    }
+
+    #[test]
+    fn test_merge_orders_and_filters_by_range() {
+        let now = Utc::now();
+        let mut log = ActivityLog::default();
+        log.connection(Uuid::new_v4(), ConnectionChange::Disconnected, 0);
+        log.transfer_started("t-1", "build.tar", 1 << 20);
+
+        let old = bookmark(now - Duration::hours(2));
+        let recent = bookmark(now - Duration::minutes(5));
+        let entries = merge(Vec::new(), &log, vec![old, recent.clone()], None, None);
+        assert_eq!(entries.len(), 4);
+        assert!(entries.windows(2).all(|pair| pair[0].at() <= pair[1].at()));
+
+        // The running transfer overlaps any range that reaches now
+        let last_hour = merge(
+            Vec::new(),
+            &log,
+            vec![bookmark(now - Duration::hours(2)), recent],
+            Some(now - Duration::hours(1)),
+            None,
+        );
+        assert_eq!(last_hour.len(), 3);
+        assert!(matches!(last_hour[0], TimelineEntry::Bookmark(_)));
+
+        let before = merge(
+            Vec::new(),
+            &log,
+            Vec::new(),
+            None,
+            Some(now - Duration::hours(1)),
+        );
+        assert!(before.is_empty());
+    }
+
+    #[test]
+    fn test_entry_serialization() {
+        let entry = TimelineEntry::Bookmark(bookmark(Utc::now()));
+        let json = serde_json::to_value(&entry).unwrap();
+        assert_eq!(json["kind"], "bookmark");
+        assert_eq!(json["line"], 3);
+    }
+}
@@ -1,35 +1,94 @@
 //! WebSocket server for real-time PTY output streaming
 //!
-//! Provides event-driven output streaming instead of polling
+//! Provides event-driven output streaming instead of polling.
+//!
+//! Output is sent as base64 text frames. Clients that connect with
+//! `?theme=true` also receive JSON control frames (`{"type":"theme",...}`)
+//! carrying the terminal appearance on connect and whenever it changes;
+//! these never collide with output since `{` is not a base64 character.
+//! Clients that connect with `?events=true` receive
+//! `{"type":"shutdown","seconds_left":N}` frames each second while the
+//! daemon is shutting down; new connections are refused then. They also
+//! receive `{"type":"size","size":{...}}` frames with the session's PTY size on
+//! connect and whenever it changes, listing the clients it letterboxes
+//! (see `sizing`). Clients that connect with `?client=<id>` are credited
+//! with what they type, for the active-controller size policy.
+//!
+//! Clients need an attach token (`?token=`) unless they connect from this
+//! machine and, for browsers, from the desktop app's origin; see
+//! `attach_token::allows_tokenless`.
+//!
+//! Clients that connect with `?read_only=true`, with a view-only attach
+//! token, or as a client attached read-only receive output but their input
+//! is discarded; event subscribers are told so once with a
+//! `{"type":"notice","message":...}` frame.
 
 use anyhow::{Context, Result};
 use axum::{
     extract::{
         ws::{Message, WebSocket, WebSocketUpgrade},
-        Path, State,
+        ConnectInfo, Path, Query, State,
     },
+    http::{header, HeaderMap},
     response::IntoResponse,
     routing::get,
     Router,
 };
 use base64::Engine;
 use futures::{sink::SinkExt, stream::StreamExt};
+use serde::Deserialize;
+use std::net::SocketAddr;
 use std::sync::Arc;
-use tokio::sync::broadcast;
+use tokio::sync::{broadcast, mpsc, watch};
 use tracing::{debug, error, info, warn};
 use uuid::Uuid;
 
-use crate::session_manager::SessionManager;
+use crate::attach_token::{allows_tokenless, AttachTokens};
+use crate::session_manager::{SessionManager, READ_ONLY_NOTICE};
+use crate::shutdown::{Shutdown, ShutdownNotice};
+use crate::sizing::SizeChange;
+use crate::theme::{TerminalTheme, ThemeStore};
 
 /// WebSocket server state
 #[derive(Clone)]
 pub struct WsState {
     pub session_manager: Arc<SessionManager>,
+    pub attach_tokens: Arc<AttachTokens>,
+    pub theme_store: Arc<ThemeStore>,
+    pub shutdown: Arc<Shutdown>,
+}
+
+/// Query parameters accepted on the upgrade request
+#[derive(Debug, Default, Deserialize)]
+pub struct AttachQuery {
+    /// Attach token issued to a remote client
+    pub token: Option<String>,
+    /// Receive theme control frames
+    #[serde(default)]
+    pub theme: bool,
+    /// Receive daemon event frames (shutdown countdown, size changes)
+    #[serde(default)]
+    pub events: bool,
+    /// Client ID given to attach_session
+    pub client: Option<Uuid>,
+    /// Receive output only; input is discarded
+    #[serde(default)]
+    pub read_only: bool,
 }
 
 /// Create WebSocket router
-pub fn create_router(session_manager: Arc<SessionManager>) -> Router {
-    let state = WsState { session_manager };
+pub fn create_router(
+    session_manager: Arc<SessionManager>,
+    attach_tokens: Arc<AttachTokens>,
+    theme_store: Arc<ThemeStore>,
+    shutdown: Arc<Shutdown>,
+) -> Router {
+    let state = WsState {
+        session_manager,
+        attach_tokens,
+        theme_store,
+        shutdown,
+    };
 
     Router::new()
         .route("/ws/:session_id", get(ws_handler))
@@ -40,6 +99,9 @@ pub fn create_router(session_manager: Arc<SessionManager>) -> Router {
 async fn ws_handler(
     ws: WebSocketUpgrade,
     Path(session_id): Path<String>,
+    Query(query): Query<AttachQuery>,
+    ConnectInfo(peer): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
     State(state): State<WsState>,
 ) -> impl IntoResponse {
     // Parse session ID
@@ -53,12 +115,54 @@ async fn ws_handler(
         }
     };
 
+    if let Some(notice) = state.shutdown.notice() {
+        warn!("Refused WebSocket attach to {} during shutdown", session_uuid);
+        return ws.on_upgrade(move |socket| async move {
+            let _ = handle_shutting_down(socket, notice).await;
+        });
+    }
+
+    // Tokens scope remote clients; local clients without one keep full
+    // control, unless they are a web page from elsewhere
+    let allow_input = match &query.token {
+        Some(token) => match state.attach_tokens.redeem(token, session_uuid) {
+            Ok(claims) => claims.scope.allows_input(),
+            Err(e) => {
+                warn!("Rejected attach token for session {}: {}", session_uuid, e);
+                return ws.on_upgrade(|socket| async move {
+                    let _ = handle_invalid_token(socket).await;
+                });
+            }
+        },
+        None => {
+            let origin = headers.get(header::ORIGIN).and_then(|origin| origin.to_str().ok());
+            if !allows_tokenless(peer.ip(), origin) {
+                warn!("Refused attach to {} without a token from {} ({:?})", session_uuid, peer, origin);
+                return ws.on_upgrade(|socket| async move {
+                    let _ = handle_token_required(socket).await;
+                });
+            }
+            true
+        }
+    };
+    let allow_input = allow_input && !query.read_only;
+
     // Verify session exists
     match state.session_manager.get_session(session_uuid).await {
         Ok(_session) => {
             info!("WebSocket connection established for session: {}", session_uuid);
+            let theme_store = query.theme.then_some(state.theme_store);
+            let shutdown_rx = query.events.then(|| state.shutdown.subscribe());
             ws.on_upgrade(move |socket| {
-                handle_socket(socket, session_uuid, state.session_manager)
+                handle_socket(
+                    socket,
+                    session_uuid,
+                    state.session_manager,
+                    allow_input,
+                    query.client,
+                    theme_store,
+                    shutdown_rx,
+                )
             })
         }
         Err(e) => {
@@ -79,6 +183,31 @@ async fn handle_invalid_session(mut socket: WebSocket) -> Result<()> {
     Ok(())
 }
 
+/// Handle rejected attach token
+async fn handle_invalid_token(mut socket: WebSocket) -> Result<()> {
+    socket
+        .send(Message::Text("Error: Invalid or expired attach token".to_string()))
+        .await?;
+    socket.close().await?;
+    Ok(())
+}
+
+/// Handle an attach that needs a token but came without one
+async fn handle_token_required(mut socket: WebSocket) -> Result<()> {
+    socket
+        .send(Message::Text("Error: An attach token is required".to_string()))
+        .await?;
+    socket.close().await?;
+    Ok(())
+}
+
+/// Handle an attach while the daemon is shutting down
+async fn handle_shutting_down(mut socket: WebSocket, notice: ShutdownNotice) -> Result<()> {
+    socket.send(shutdown_frame(notice)).await?;
+    socket.close().await?;
+    Ok(())
+}
+
 /// Handle session not found error
 async fn handle_session_not_found(mut socket: WebSocket) -> Result<()> {
     socket
@@ -93,6 +222,10 @@ async fn handle_socket(
     socket: WebSocket,
     session_id: Uuid,
     session_manager: Arc<SessionManager>,
+    allow_input: bool,
+    client_id: Option<Uuid>,
+    theme_store: Option<Arc<ThemeStore>>,
+    mut shutdown_rx: Option<watch::Receiver<Option<ShutdownNotice>>>,
 ) {
     let (mut sender, mut receiver) = socket.split();
 
@@ -108,14 +241,53 @@ async fn handle_socket(
     // Subscribe to output broadcast
     let mut output_rx = session.output_broadcast.subscribe();
 
-    // Spawn task to forward PTY output to WebSocket
+    // Send the current theme up front, then follow changes
+    let mut theme_rx = match &theme_store {
+        Some(store) => {
+            let rx = store.subscribe();
+            if sender.send(theme_frame(&store.current().await)).await.is_err() {
+                return;
+            }
+            Some(rx)
+        }
+        None => None,
+    };
+
+    // Event subscribers are told why their input goes nowhere
+    let (notice_tx, mut notice_rx) = mpsc::channel::<&'static str>(1);
+    let notice_tx = shutdown_rx.is_some().then_some(notice_tx);
+
+    // Event subscribers get the current size up front, then follow changes
+    let mut size_rx = match &shutdown_rx {
+        Some(_) => {
+            let rx = session.size_events.subscribe();
+            let current = session.sizing.read().await.current().cloned();
+            if let Some(size) = current {
+                if sender.send(size_frame(&size)).await.is_err() {
+                    return;
+                }
+            }
+            Some(rx)
+        }
+        None => None,
+    };
+
+    // Spawn task to forward PTY output (and theme, shutdown and size events) to WebSocket
     let output_task = tokio::spawn(async move {
-        while let Ok(data) = output_rx.recv().await {
-            // Encode as base64 for binary safety
-            let base64_data = base64::engine::general_purpose::STANDARD.encode(&data);
+        loop {
+            let message = tokio::select! {
+                output = output_rx.recv() => match output {
+                    // Encode as base64 for binary safety
+                    Ok(data) => Message::Text(base64::engine::general_purpose::STANDARD.encode(&data)),
+                    Err(_) => break,
+                },
+                Some(theme) = next_theme(&mut theme_rx) => theme_frame(&theme),
+                Some(notice) = next_shutdown(&mut shutdown_rx) => shutdown_frame(notice),
+                Some(size) = next_size(&mut size_rx) => size_frame(&size),
+                Some(message) = notice_rx.recv() => notice_frame(message),
+            };
 
-            // Send as text message
-            if let Err(e) = sender.send(Message::Text(base64_data)).await {
+            if let Err(e) = sender.send(message).await {
                 debug!("WebSocket send error: {}", e);
                 break;
             }
@@ -125,7 +297,20 @@ async fn handle_socket(
 
     // Handle incoming messages (input from client)
     let input_task = tokio::spawn(async move {
+        let mut noticed = false;
         while let Some(msg) = receiver.next().await {
+            // Checked per message, since a reattach can change the client's
+            // permissions; tail sessions have no PTY to type into
+            let is_input = matches!(msg, Ok(Message::Text(_)) | Ok(Message::Binary(_)));
+            if is_input && !(allow_input && session.allows_input(client_id).await) {
+                // View-only client; drop input
+                if let (Some(notice_tx), false) = (&notice_tx, noticed) {
+                    let _ = notice_tx.try_send(READ_ONLY_NOTICE);
+                    noticed = true;
+                }
+                continue;
+            }
+
             match msg {
                 Ok(Message::Text(text)) => {
                     // Decode base64 input
@@ -138,19 +323,19 @@ async fn handle_socket(
                     };
 
                     // Write to PTY
-                    let mut terminal = session.terminal_session.write().await;
-                    if let Err(e) = terminal.write(&data) {
+                    if let Err(e) = session.write_input(&data).await {
                         error!("Failed to write to PTY: {}", e);
                         break;
                     }
+                    session.client_active(client_id).await;
                 }
                 Ok(Message::Binary(data)) => {
                     // Direct binary input
-                    let mut terminal = session.terminal_session.write().await;
-                    if let Err(e) = terminal.write(&data) {
+                    if let Err(e) = session.write_input(&data).await {
                         error!("Failed to write to PTY: {}", e);
                         break;
                     }
+                    session.client_active(client_id).await;
                 }
                 Ok(Message::Close(_)) => {
                     debug!("WebSocket closed by client");
@@ -177,21 +362,105 @@ async fn handle_socket(
     info!("WebSocket connection closed for session: {}", session_id);
 }
 
+/// Wait for the next theme change; pends forever when not subscribed
+async fn next_theme(
+    theme_rx: &mut Option<broadcast::Receiver<TerminalTheme>>,
+) -> Option<TerminalTheme> {
+    let Some(rx) = theme_rx else {
+        return std::future::pending().await;
+    };
+
+    loop {
+        match rx.recv().await {
+            Ok(theme) => return Some(theme),
+            // Only the latest theme matters
+            Err(broadcast::error::RecvError::Lagged(_)) => continue,
+            Err(broadcast::error::RecvError::Closed) => {
+                *theme_rx = None;
+                return None;
+            }
+        }
+    }
+}
+
+/// JSON control frame carrying a theme
+fn theme_frame(theme: &TerminalTheme) -> Message {
+    Message::Text(serde_json::json!({ "type": "theme", "theme": theme }).to_string())
+}
+
+/// Wait for the next shutdown countdown step; pends forever when not
+/// subscribed
+async fn next_shutdown(
+    shutdown_rx: &mut Option<watch::Receiver<Option<ShutdownNotice>>>,
+) -> Option<ShutdownNotice> {
+    let Some(rx) = shutdown_rx else {
+        return std::future::pending().await;
+    };
+
+    loop {
+        if rx.changed().await.is_err() {
+            *shutdown_rx = None;
+            return None;
+        }
+        if let Some(notice) = *rx.borrow_and_update() {
+            return Some(notice);
+        }
+    }
+}
+
+/// Wait for the next size change; pends forever when not subscribed
+async fn next_size(size_rx: &mut Option<broadcast::Receiver<SizeChange>>) -> Option<SizeChange> {
+    let Some(rx) = size_rx else {
+        return std::future::pending().await;
+    };
+
+    loop {
+        match rx.recv().await {
+            Ok(size) => return Some(size),
+            // Only the latest size matters
+            Err(broadcast::error::RecvError::Lagged(_)) => continue,
+            Err(broadcast::error::RecvError::Closed) => {
+                *size_rx = None;
+                return None;
+            }
+        }
+    }
+}
+
+/// JSON control frame carrying the session's size and who it letterboxes
+fn size_frame(size: &SizeChange) -> Message {
+    Message::Text(serde_json::json!({ "type": "size", "size": size }).to_string())
+}
+
+/// JSON control frame carrying a message for the user
+fn notice_frame(message: &str) -> Message {
+    Message::Text(serde_json::json!({ "type": "notice", "message": message }).to_string())
+}
+
+/// JSON control frame carrying the shutdown countdown
+fn shutdown_frame(notice: ShutdownNotice) -> Message {
+    Message::Text(
+        serde_json::json!({ "type": "shutdown", "seconds_left": notice.seconds_left }).to_string(),
+    )
+}
+
 /// Start WebSocket server
 pub async fn start_server(
     session_manager: Arc<SessionManager>,
-    port: u16,
+    attach_tokens: Arc<AttachTokens>,
+    theme_store: Arc<ThemeStore>,
+    shutdown: Arc<Shutdown>,
+    addr: SocketAddr,
 ) -> Result<()> {
-    let app = create_router(session_manager);
+    let app = create_router(session_manager, attach_tokens, theme_store, shutdown);
 
-    let addr = format!("127.0.0.1:{}", port);
     let listener = tokio::net::TcpListener::bind(&addr)
         .await
         .with_context(|| format!("Failed to bind WebSocket server to {}", addr))?;
 
     info!("WebSocket server listening on {}", addr);
 
-    axum::serve(listener, app)
+    axum::serve(listener, app.into_make_service_with_connect_info::<SocketAddr>())
         .await
         .context("WebSocket server error")?;
 
@@ -207,6 +476,9 @@ mod tests {
         let session_manager = Arc::new(SessionManager::new());
         let state = WsState {
             session_manager: Arc::clone(&session_manager),
+            attach_tokens: Arc::new(AttachTokens::new().unwrap()),
+            theme_store: Arc::new(ThemeStore::new()),
+            shutdown: Arc::new(Shutdown::new()),
         };
         assert!(Arc::strong_count(&state.session_manager) > 0);
     }
@@ -10,21 +10,24 @@
 use anyhow::{Context, Result};
 use quinn::{Endpoint, ServerConfig};
 use rustls::crypto::ring;
-use rustls::pki_types::{CertificateDer, PrivateKeyDer};
+use rustls::server::ResolvesServerCert;
 use std::net::SocketAddr;
 use std::sync::Arc;
 use tokio::sync::Mutex;
 use tracing::{debug, error, info, warn};
 use uuid::Uuid;
 
+use crate::attach_token::{allows_tokenless, AttachTokens};
 use crate::file_transfer::{FileTransferHandler, TransferMessage};
 use crate::session_manager::SessionManager;
+use crate::timeline::TransferOutcome;
 
 /// WebTransport server state
 pub struct WebTransportServer {
     endpoint: Endpoint,
     session_manager: Arc<SessionManager>,
     file_transfer: Arc<FileTransferHandler>,
+    attach_tokens: Arc<AttachTokens>,
 }
 
 impl WebTransportServer {
@@ -33,11 +36,11 @@ impl WebTransportServer {
         bind_addr: SocketAddr,
         session_manager: Arc<SessionManager>,
         file_transfer: Arc<FileTransferHandler>,
-        cert: CertificateDer<'static>,
-        key: PrivateKeyDer<'static>,
+        attach_tokens: Arc<AttachTokens>,
+        certs: Arc<dyn ResolvesServerCert>,
     ) -> Result<Self> {
         // Create server config
-        let server_config = configure_server(cert, key)?;
+        let server_config = configure_server(certs)?;
 
         // Create endpoint
         let endpoint = Endpoint::server(server_config, bind_addr)
@@ -47,6 +50,7 @@ impl WebTransportServer {
             endpoint,
             session_manager,
             file_transfer,
+            attach_tokens,
         })
     }
 
@@ -60,13 +64,14 @@ impl WebTransportServer {
         while let Some(connecting) = self.endpoint.accept().await {
             let session_manager = Arc::clone(&self.session_manager);
             let file_transfer = Arc::clone(&self.file_transfer);
+            let attach_tokens = Arc::clone(&self.attach_tokens);
 
             tokio::spawn(async move {
                 match connecting.await {
                     Ok(connection) => {
                         info!("New WebTransport connection from {}", connection.remote_address());
 
-                        if let Err(e) = handle_connection(connection, session_manager, file_transfer).await {
+                        if let Err(e) = handle_connection(connection, session_manager, file_transfer, attach_tokens).await {
                             error!("Connection handler error: {}", e);
                         }
                     }
@@ -86,8 +91,10 @@ async fn handle_connection(
     connection: quinn::Connection,
     session_manager: Arc<SessionManager>,
     file_transfer: Arc<FileTransferHandler>,
+    attach_tokens: Arc<AttachTokens>,
 ) -> Result<()> {
     debug!("Handling WebTransport connection");
+    let peer = connection.remote_address();
 
     loop {
         tokio::select! {
@@ -97,8 +104,9 @@ async fn handle_connection(
                     Ok((send, recv)) => {
                         let session_manager = Arc::clone(&session_manager);
                         let file_transfer = Arc::clone(&file_transfer);
+                        let attach_tokens = Arc::clone(&attach_tokens);
                         tokio::spawn(async move {
-                            if let Err(e) = handle_bidirectional_stream(send, recv, session_manager, file_transfer).await {
+                            if let Err(e) = handle_bidirectional_stream(send, recv, peer, session_manager, file_transfer, attach_tokens).await {
                                 error!("Bidirectional stream error: {}", e);
                             }
                         });
@@ -137,8 +145,10 @@ async fn handle_connection(
 async fn handle_bidirectional_stream(
     mut send: quinn::SendStream,
     mut recv: quinn::RecvStream,
+    peer: SocketAddr,
     session_manager: Arc<SessionManager>,
     file_transfer: Arc<FileTransferHandler>,
+    attach_tokens: Arc<AttachTokens>,
 ) -> Result<()> {
     // Read initial message to determine stream type
     let mut buf = vec![0u8; 4096];
@@ -146,15 +156,29 @@ async fn handle_bidirectional_stream(
 
     // Try to parse as JSON (file transfer) first
     if let Ok(message) = TransferMessage::from_json(&buf[..n]) {
+        if !file_transfer.accepts_peer(peer.ip()) {
+            anyhow::bail!(
+                "Transfer from {} refused; set transfers.trusted_senders to accept files from other hosts",
+                peer
+            );
+        }
         debug!("File transfer stream: {}", message.transfer_id());
-        return handle_file_transfer_stream(send, recv, file_transfer, message).await;
+        return handle_file_transfer_stream(send, recv, session_manager, file_transfer, message).await;
     }
 
-    // Otherwise treat as terminal stream
-    let session_id_str = String::from_utf8_lossy(&buf[..n]);
-    let session_id = Uuid::parse_str(session_id_str.trim())
+    // Otherwise treat as terminal stream: "<session_id> [attach_token]"
+    let header = String::from_utf8_lossy(&buf[..n]);
+    let mut parts = header.split_whitespace();
+    let session_id = Uuid::parse_str(parts.next().unwrap_or_default())
         .context("Invalid session ID")?;
 
+    let allow_input = match parts.next() {
+        Some(token) => attach_tokens.redeem(token, session_id)?.scope.allows_input(),
+        // Browsers can't open raw QUIC streams, so only the peer matters
+        None if allows_tokenless(peer.ip(), None) => true,
+        None => anyhow::bail!("Attach to {} from {} needs a token", session_id, peer),
+    };
+
     debug!("WebTransport terminal stream for session: {}", session_id);
 
     // Get session
@@ -163,6 +187,9 @@ async fn handle_bidirectional_stream(
         .await
         .context("Session not found")?;
 
+    // Tail sessions have no PTY to type into
+    let allow_input = allow_input && session.terminal_session.is_some();
+
     // Subscribe to output
     let mut output_rx = session.output_broadcast.subscribe();
 
@@ -181,9 +208,11 @@ async fn handle_bidirectional_stream(
         let mut buf = vec![0u8; 8192];
         loop {
             match recv.read(&mut buf).await {
+                Ok(Some(_)) if !allow_input => {
+                    // View-only client; drop input
+                }
                 Ok(Some(n)) => {
-                    let mut terminal = session.terminal_session.write().await;
-                    if let Err(e) = terminal.write(&buf[..n]) {
+                    if let Err(e) = session.write_input(&buf[..n]).await {
                         error!("Failed to write to PTY: {}", e);
                         break;
                     }
@@ -226,21 +255,22 @@ async fn handle_unidirectional_stream(
 }
 
 /// Configure QUIC server with TLS
-fn configure_server(
-    cert: CertificateDer<'static>,
-    key: PrivateKeyDer<'static>,
-) -> Result<ServerConfig> {
+fn configure_server(certs: Arc<dyn ResolvesServerCert>) -> Result<ServerConfig> {
     // Install crypto provider before using rustls (ignore error if already installed)
     let _ = ring::default_provider().install_default();
 
     let mut server_crypto = rustls::ServerConfig::builder()
         .with_no_client_auth()
-        .with_single_cert(vec![cert], key)
-        .context("Failed to create TLS config")?;
+        .with_cert_resolver(certs);
 
     // Enable ALPN for WebTransport
     server_crypto.alpn_protocols = vec![b"h3".to_vec()];
 
+    // Issue session tickets and accept 0-RTT so reconnecting clients resume
+    // instantly; clients only send replay-safe frames as early data
+    server_crypto.max_early_data_size = u32::MAX;
+    server_crypto.ticketer = ring::Ticketer::new().context("Failed to create session ticketer")?;
+
     let mut server_config = ServerConfig::with_crypto(Arc::new(
         quinn::crypto::rustls::QuicServerConfig::try_from(server_crypto)
             .context("Failed to create QUIC config")?,
@@ -256,26 +286,17 @@ fn configure_server(
 
     server_config.transport_config(Arc::new(transport_config));
 
-    Ok(server_config)
-}
-
-/// Generate self-signed certificate for development
-pub fn generate_self_signed_cert() -> Result<(CertificateDer<'static>, PrivateKeyDer<'static>)> {
-    let cert = rcgen::generate_simple_self_signed(vec!["localhost".to_string()])
-        .context("Failed to generate certificate")?;
+    // Let clients keep their connection across network changes
+    server_config.migration(true);
 
-    let cert_der = cert.cert.der().clone();
-    let key_der = PrivateKeyDer::Pkcs8(cert.key_pair.serialize_der().into());
-
-    info!("Generated self-signed certificate for WebTransport");
-
-    Ok((cert_der, key_der))
+    Ok(server_config)
 }
 
 /// Handle a file transfer stream
 async fn handle_file_transfer_stream(
     mut send: quinn::SendStream,
     mut recv: quinn::RecvStream,
+    session_manager: Arc<SessionManager>,
     file_transfer: Arc<FileTransferHandler>,
     initial_message: TransferMessage,
 ) -> Result<()> {
@@ -283,11 +304,34 @@ async fn handle_file_transfer_stream(
 
     debug!("Handling file transfer: {}", initial_message.transfer_id());
 
+    // Session the file was dropped on, whose timeline shows the transfer
+    let mut tracked: Option<(Uuid, TransferStartMessage)> = None;
+
     // Process initial message
     let response = match initial_message {
         TransferMessage::TransferStart(msg) => {
-            match file_transfer.handle_transfer_start(msg).await {
-                Ok(ack) => TransferMessage::TransferAck(ack),
+            // Files dropped on a terminal land in its shell's directory
+            let destination = match msg.session_id {
+                Some(id) => session_manager.transfer_directory(id).await.unwrap_or(None),
+                None => None,
+            };
+            let start = msg.session_id.map(|id| (id, msg.clone()));
+            let sender = msg.sender;
+            let authenticated =
+                authenticate_sender(&mut send, &mut recv, &file_transfer, &msg.transfer_id, sender)
+                    .await;
+            let started = match authenticated {
+                Ok(sender) => file_transfer
+                    .handle_transfer_start_from(msg, destination, sender)
+                    .await
+                    .map_err(anyhow::Error::from),
+                Err(e) => Err(e),
+            };
+            match started {
+                Ok(ack) => {
+                    tracked = start;
+                    TransferMessage::TransferAck(ack)
+                }
                 Err(e) => TransferMessage::Error(ErrorMessage {
                     transfer_id: String::new(),
                     timestamp: current_timestamp(),
@@ -307,6 +351,64 @@ async fn handle_file_transfer_stream(
                 }),
             }
         }
+        TransferMessage::DirectoryStart(msg) => {
+            // Folders dropped on a terminal land in its shell's directory
+            let destination = match msg.session_id {
+                Some(id) => session_manager.transfer_directory(id).await.unwrap_or(None),
+                None => None,
+            };
+            let directory_id = msg.directory_id.clone();
+            let started = async {
+                let manifest = read_manifest(&mut recv, msg.manifest_size).await?;
+                let sender = authenticate_sender(
+                    &mut send,
+                    &mut recv,
+                    &file_transfer,
+                    &msg.directory_id,
+                    msg.sender,
+                )
+                .await?;
+                Ok::<_, anyhow::Error>(
+                    file_transfer
+                        .handle_directory_start(msg, manifest, destination, sender)
+                        .await?,
+                )
+            }
+            .await;
+            match started {
+                Ok(ack) => TransferMessage::DirectoryAck(ack),
+                Err(e) => TransferMessage::Error(ErrorMessage {
+                    transfer_id: directory_id,
+                    timestamp: current_timestamp(),
+                    error_type: "directory_start_failed".to_string(),
+                    error_message: e.to_string(),
+                }),
+            }
+        }
+        TransferMessage::DirectoryComplete(msg) => {
+            let directory_id = msg.directory_id.clone();
+            match file_transfer.handle_directory_complete(msg).await {
+                Ok(success) => TransferMessage::DirectorySuccess(success),
+                Err(e) => TransferMessage::Error(ErrorMessage {
+                    transfer_id: directory_id,
+                    timestamp: current_timestamp(),
+                    error_type: "directory_complete_failed".to_string(),
+                    error_message: e.to_string(),
+                }),
+            }
+        }
+        TransferMessage::BatchComplete(msg) => {
+            let batch_id = msg.batch_id.clone();
+            match file_transfer.handle_batch_complete(msg).await {
+                Ok(manifest) => TransferMessage::BatchManifest(manifest),
+                Err(e) => TransferMessage::Error(ErrorMessage {
+                    transfer_id: batch_id,
+                    timestamp: current_timestamp(),
+                    error_type: "batch_manifest_failed".to_string(),
+                    error_message: e.to_string(),
+                }),
+            }
+        }
         _ => {
             error!("Unexpected initial message type");
             return Ok(());
@@ -317,7 +419,31 @@ async fn handle_file_transfer_stream(
     let response_json = response.to_json()?;
     send.write_all(&response_json).await?;
 
-    // Handle subsequent messages
+    if let Some((id, msg)) = &tracked {
+        session_manager
+            .transfer_started(*id, &msg.transfer_id, &msg.file_name, msg.file_size)
+            .await;
+    }
+
+    let outcome = relay_transfer_messages(&mut send, &mut recv, &file_transfer).await;
+    if let Some((id, msg)) = &tracked {
+        let outcome = outcome.as_ref().copied().unwrap_or(TransferOutcome::Interrupted);
+        session_manager.transfer_finished(*id, &msg.transfer_id, outcome).await;
+    }
+    outcome.map(|_| ())
+}
+
+/// Handle the messages that follow a transfer's first one, until it
+/// completes, fails or the stream closes
+async fn relay_transfer_messages(
+    send: &mut quinn::SendStream,
+    recv: &mut quinn::RecvStream,
+    file_transfer: &FileTransferHandler,
+) -> Result<TransferOutcome> {
+    use crate::file_transfer::messages::*;
+
+    // How the transfer ended if the stream closes now
+    let mut outcome = TransferOutcome::Interrupted;
     loop {
         let mut header_buf = vec![0u8; 4096];
         match recv.read(&mut header_buf).await? {
@@ -325,6 +451,27 @@ async fn handle_file_transfer_stream(
                 if let Ok(message) = TransferMessage::from_json(&header_buf[..n]) {
                     let response = match message {
                         TransferMessage::ChunkData(msg) => {
+                            // Never allocate more than the protocol allows for a
+                            // single chunk, whatever the peer claims
+                            if msg.chunk_size > tft_core::codec::MAX_CHUNK_SIZE {
+                                warn!(
+                                    "Rejecting oversized chunk {} ({} bytes) for transfer {}",
+                                    msg.chunk_index, msg.chunk_size, msg.transfer_id
+                                );
+                                let error = TransferMessage::Error(ErrorMessage {
+                                    transfer_id: msg.transfer_id,
+                                    timestamp: current_timestamp(),
+                                    error_type: "chunk_too_large".to_string(),
+                                    error_message: format!(
+                                        "Chunk size {} exceeds limit {}",
+                                        msg.chunk_size,
+                                        tft_core::codec::MAX_CHUNK_SIZE
+                                    ),
+                                });
+                                send.write_all(&error.to_json()?).await?;
+                                return Ok(TransferOutcome::Failed);
+                            }
+
                             // Read chunk data
                             let chunk_size = msg.chunk_size;
                             let mut chunk_data = vec![0u8; chunk_size];
@@ -335,6 +482,9 @@ async fn handle_file_transfer_stream(
                                     None => break,
                                 }
                             }
+                            // Reading slower holds the sender back through
+                            // QUIC flow control
+                            file_transfer.throttle().acquire(bytes_read).await;
 
                             match file_transfer.handle_chunk_data(msg, chunk_data).await {
                                 Ok(ack) => TransferMessage::ChunkAck(ack),
@@ -349,17 +499,20 @@ async fn handle_file_transfer_stream(
                         TransferMessage::TransferComplete(msg) => {
                             match file_transfer.handle_transfer_complete(msg).await {
                                 Ok(success) => TransferMessage::TransferSuccess(success),
-                                Err(e) => TransferMessage::Error(ErrorMessage {
-                                    transfer_id: String::new(),
-                                    timestamp: current_timestamp(),
-                                    error_type: "complete_failed".to_string(),
-                                    error_message: e.to_string(),
-                                }),
+                                Err(e) => {
+                                    outcome = TransferOutcome::Failed;
+                                    TransferMessage::Error(ErrorMessage {
+                                        transfer_id: String::new(),
+                                        timestamp: current_timestamp(),
+                                        error_type: "complete_failed".to_string(),
+                                        error_message: e.to_string(),
+                                    })
+                                }
                             }
                         }
                         TransferMessage::TransferAbort(msg) => {
                             let _ = file_transfer.handle_transfer_abort(msg).await;
-                            break;
+                            return Ok(TransferOutcome::Aborted);
                         }
                         _ => {
                             error!("Unexpected message type");
@@ -373,7 +526,7 @@ async fn handle_file_transfer_stream(
 
                     // If transfer complete, close stream
                     if matches!(response, TransferMessage::TransferSuccess(_)) {
-                        break;
+                        return Ok(TransferOutcome::Complete);
                     }
                 } else {
                     error!("Failed to parse transfer message");
@@ -387,24 +540,86 @@ async fn handle_file_transfer_stream(
         }
     }
 
-    Ok(())
+    Ok(outcome)
+}
+
+/// Read the manifest following a directory start message, and check it
+/// before anything is created from it
+async fn read_manifest(
+    recv: &mut quinn::RecvStream,
+    size: usize,
+) -> Result<tft_core::DirectoryManifest> {
+    if size > tft_core::codec::MAX_FRAME_SIZE {
+        anyhow::bail!(
+            "Manifest of {} bytes exceeds limit {}",
+            size,
+            tft_core::codec::MAX_FRAME_SIZE
+        );
+    }
+    let mut buf = vec![0u8; size];
+    recv.read_exact(&mut buf)
+        .await
+        .context("Stream closed before the whole manifest")?;
+    let manifest = serde_json::from_slice(&buf)?;
+    tft_core::codec::validate_manifest(&manifest)?;
+    Ok(manifest)
+}
+
+/// Challenge a sender that named an identity to prove it; None when it
+/// named none and the daemon accepts anonymous senders
+async fn authenticate_sender(
+    send: &mut quinn::SendStream,
+    recv: &mut quinn::RecvStream,
+    file_transfer: &FileTransferHandler,
+    transfer_id: &str,
+    sender: Option<tft_core::PeerId>,
+) -> Result<Option<tft_core::PeerId>> {
+    use crate::file_transfer::identity::verify_sender;
+    use crate::file_transfer::messages::*;
+
+    let Some((claimed, challenge)) = file_transfer.challenge_sender(sender)? else {
+        return Ok(None);
+    };
+
+    let request = TransferMessage::IdentityChallenge(IdentityChallengeMessage {
+        transfer_id: transfer_id.to_string(),
+        timestamp: current_timestamp(),
+        challenge,
+    });
+    send.write_all(&request.to_json()?).await?;
+
+    let mut buf = vec![0u8; 4096];
+    let n = recv
+        .read(&mut buf)
+        .await?
+        .context("Stream closed before the identity response")?;
+    match TransferMessage::from_json(&buf[..n])? {
+        TransferMessage::IdentityResponse(response) => {
+            Ok(Some(verify_sender(claimed, &challenge, &response.proof)?))
+        }
+        _ => anyhow::bail!("Expected an identity response"),
+    }
 }
 
 /// Start WebTransport server
 pub async fn start_server(
     session_manager: Arc<SessionManager>,
     file_transfer: Arc<FileTransferHandler>,
-    port: u16,
+    attach_tokens: Arc<AttachTokens>,
+    certs: Arc<dyn ResolvesServerCert>,
+    bind_addr: SocketAddr,
 ) -> Result<()> {
     // Install crypto provider globally before any rustls operations
     let _ = ring::default_provider().install_default();
 
-    // Generate certificate
-    let (cert, key) = generate_self_signed_cert()?;
-
-    // Create server
-    let bind_addr: SocketAddr = format!("127.0.0.1:{}", port).parse()?;
-    let server = WebTransportServer::new(bind_addr, session_manager, file_transfer, cert, key)?;
+    // Create server; certificates come from the cert manager or `[tls]`
+    let server = WebTransportServer::new(
+        bind_addr,
+        session_manager,
+        file_transfer,
+        attach_tokens,
+        certs,
+    )?;
 
     // Run server
     server.run().await
@@ -0,0 +1,597 @@
+//! Declarative Workspaces (`pulsar.yaml`)
+//!
+//! Hosts, references to the credentials they log in with, and workspaces
+//! with their layouts can be kept in a YAML file under version control.
+//! Applying the file reconciles the workspace database to it: declared
+//! workspaces are created or brought up to date, and ones the file no longer
+//! declares are deleted. Workspaces created any other way are never touched.
+//!
+//! Declared workspaces get IDs derived from their names, and their panes and
+//! sessions get IDs derived from their place in the layout, so applying an
+//! unchanged file changes nothing.
+
+use super::models::{SessionConfig, Workspace, WorkspaceSession};
+use super::types::{LayoutNode, LayoutTree, PaneNode, SplitDirection, SplitNode, TabsNode};
+use anyhow::{bail, Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::Path;
+
+/// Prefix of the IDs of workspaces owned by the file
+pub const MANAGED_PREFIX: &str = "declared-";
+
+/// Pane target for a local shell rather than a host
+pub const LOCAL: &str = "local";
+
+/// Contents of `pulsar.yaml`
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Declaration {
+    /// SSH hosts by alias
+    #[serde(default)]
+    pub hosts: BTreeMap<String, HostSpec>,
+    /// Workspaces by name
+    #[serde(default)]
+    pub workspaces: BTreeMap<String, WorkspaceSpec>,
+}
+
+/// An SSH host panes can connect to
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct HostSpec {
+    pub host: String,
+    pub port: Option<u16>,
+    pub username: Option<String>,
+    /// Where the login secret is kept, as `<store>:<name>` (for example
+    /// `keychain:prod-ops`); never the secret itself
+    pub credential: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct WorkspaceSpec {
+    pub description: Option<String>,
+    pub icon: Option<String>,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    /// A single local pane when omitted
+    #[serde(default)]
+    pub layout: LayoutSpec,
+}
+
+/// A layout node: set exactly one of `pane`, `split` or `tabs`
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct LayoutSpec {
+    /// Host alias the pane connects to, or `local`
+    pub pane: Option<String>,
+    pub split: Option<SplitDirection>,
+    /// Percentage given to each of `children`; equal shares when omitted
+    pub sizes: Option<Vec<f32>>,
+    #[serde(default)]
+    pub children: Vec<LayoutSpec>,
+    #[serde(default)]
+    pub tabs: Vec<LayoutSpec>,
+}
+
+/// A declared workspace as it should be stored
+#[derive(Debug, Clone)]
+pub struct DesiredWorkspace {
+    pub id: String,
+    pub name: String,
+    pub description: Option<String>,
+    pub icon: Option<String>,
+    pub tags: Option<Vec<String>>,
+    pub tree: LayoutTree,
+    pub sessions: Vec<WorkspaceSession>,
+}
+
+/// What applying the file did, or would do on a dry run
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ApplyReport {
+    pub dry_run: bool,
+    pub changes: Vec<WorkspaceChange>,
+    /// Declared hosts no pane uses; they are not stored anywhere
+    pub unused_hosts: Vec<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ChangeAction {
+    Create,
+    Update,
+    Delete,
+}
+
+/// One workspace brought in line with the file
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct WorkspaceChange {
+    pub action: ChangeAction,
+    pub workspace_id: String,
+    pub name: String,
+    /// Workspace fields that differ (`name`, `description`, `icon`, `tags`,
+    /// `layout`)
+    #[serde(default)]
+    pub fields: Vec<String>,
+    #[serde(default)]
+    pub sessions_added: Vec<String>,
+    #[serde(default)]
+    pub sessions_removed: Vec<String>,
+    /// Sessions moved to another pane or pointed at another host
+    #[serde(default)]
+    pub sessions_changed: Vec<String>,
+}
+
+impl Declaration {
+    /// Read a declaration file
+    pub fn load(path: &Path) -> Result<Self> {
+        let content = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read {:?}", path))?;
+        Self::parse(&content).with_context(|| format!("Invalid declaration {:?}", path))
+    }
+
+    pub fn parse(content: &str) -> Result<Self> {
+        Ok(serde_yaml::from_str(content)?)
+    }
+
+    /// The workspaces the file declares, checked and with stable IDs
+    pub fn plan(&self) -> Result<Vec<DesiredWorkspace>> {
+        let mut problems = host_problems(&self.hosts);
+
+        let mut desired: Vec<DesiredWorkspace> = Vec::new();
+        for (name, spec) in &self.workspaces {
+            let id = format!("{}{}", MANAGED_PREFIX, slug(name));
+            if id == MANAGED_PREFIX {
+                problems.push(format!("workspace name {:?} has no letters or digits", name));
+                continue;
+            }
+            if let Some(other) = desired.iter().find(|w| w.id == id) {
+                problems.push(format!("workspaces '{}' and '{}' both map to ID {}", other.name, name, id));
+                continue;
+            }
+
+            let (workspace, workspace_problems) = build_workspace(id, name, spec, &self.hosts);
+            problems.extend(workspace_problems);
+            desired.push(workspace);
+        }
+
+        if !problems.is_empty() {
+            bail!("Invalid declaration:\n  {}", problems.join("\n  "));
+        }
+        Ok(desired)
+    }
+
+    /// Declared hosts that no pane connects to
+    pub fn unused_hosts(&self) -> Vec<String> {
+        let mut used = BTreeSet::new();
+        for spec in self.workspaces.values() {
+            spec.layout.collect_targets(&mut used);
+        }
+        self.hosts.keys().filter(|alias| !used.contains(alias.as_str())).cloned().collect()
+    }
+}
+
+/// A workspace with a tab for each of `hosts`, for hosts saved in bulk
+/// (say, after a discovery scan) rather than declared in the file
+pub fn host_tabs(
+    workspace_id: &str,
+    name: &str,
+    hosts: &BTreeMap<String, HostSpec>,
+    tags: Vec<String>,
+) -> Result<DesiredWorkspace> {
+    let mut problems = host_problems(hosts);
+    if hosts.is_empty() {
+        problems.push("no hosts to save".to_string());
+    }
+    let spec = WorkspaceSpec {
+        tags,
+        layout: LayoutSpec {
+            tabs: hosts
+                .keys()
+                .map(|alias| LayoutSpec {
+                    pane: Some(alias.clone()),
+                    ..LayoutSpec::default()
+                })
+                .collect(),
+            ..LayoutSpec::default()
+        },
+        ..WorkspaceSpec::default()
+    };
+    let (workspace, workspace_problems) = build_workspace(workspace_id.to_string(), name, &spec, hosts);
+    problems.extend(workspace_problems);
+
+    if !problems.is_empty() {
+        bail!("Invalid hosts:\n  {}", problems.join("\n  "));
+    }
+    Ok(workspace)
+}
+
+/// What is wrong with the hosts' specs
+fn host_problems(hosts: &BTreeMap<String, HostSpec>) -> Vec<String> {
+    let mut problems = Vec::new();
+    for (alias, host) in hosts {
+        if alias == LOCAL {
+            problems.push(format!("host alias '{}' is reserved for local panes", LOCAL));
+        }
+        if host.host.trim().is_empty() {
+            problems.push(format!("host '{}' has no host", alias));
+        }
+        if let Some(credential) = &host.credential {
+            if !is_credential_ref(credential) {
+                problems.push(format!(
+                    "host '{}' credential must be a reference like keychain:<name>",
+                    alias
+                ));
+            }
+        }
+    }
+    problems
+}
+
+/// A workspace laid out as `spec` says, with what is wrong with it
+fn build_workspace(
+    id: String,
+    name: &str,
+    spec: &WorkspaceSpec,
+    hosts: &BTreeMap<String, HostSpec>,
+) -> (DesiredWorkspace, Vec<String>) {
+    let mut builder = TreeBuilder {
+        workspace_id: &id,
+        hosts,
+        nodes: 0,
+        sessions: Vec::new(),
+        problems: Vec::new(),
+    };
+    let root = builder.node(&spec.layout);
+    let sessions = builder.sessions;
+    let mut problems: Vec<String> = builder
+        .problems
+        .into_iter()
+        .map(|p| format!("workspace '{}': {}", name, p))
+        .collect();
+
+    let tree = LayoutTree {
+        active_pane: first_pane(&root),
+        root,
+    };
+    if let Err(e) = tree.validate() {
+        problems.push(format!("workspace '{}': {}", name, e));
+    }
+
+    let workspace = DesiredWorkspace {
+        id,
+        name: name.to_string(),
+        description: spec.description.clone(),
+        icon: spec.icon.clone(),
+        tags: (!spec.tags.is_empty()).then(|| spec.tags.clone()),
+        tree,
+        sessions,
+    };
+    (workspace, problems)
+}
+
+impl LayoutSpec {
+    fn collect_targets<'a>(&'a self, targets: &mut BTreeSet<&'a str>) {
+        if let Some(pane) = &self.pane {
+            targets.insert(pane);
+        }
+        for child in self.children.iter().chain(&self.tabs) {
+            child.collect_targets(targets);
+        }
+    }
+}
+
+/// Whether a declared workspace is owned by the file
+pub fn is_managed(workspace_id: &str) -> bool {
+    workspace_id.starts_with(MANAGED_PREFIX)
+}
+
+/// What it takes to turn `current` (the stored workspace and its sessions,
+/// if any) into `desired`; None when they already match
+pub fn diff(
+    current: Option<(&Workspace, &[WorkspaceSession])>,
+    desired: &DesiredWorkspace,
+) -> Option<WorkspaceChange> {
+    let mut change = WorkspaceChange {
+        action: ChangeAction::Update,
+        workspace_id: desired.id.clone(),
+        name: desired.name.clone(),
+        fields: Vec::new(),
+        sessions_added: Vec::new(),
+        sessions_removed: Vec::new(),
+        sessions_changed: Vec::new(),
+    };
+
+    let Some((workspace, sessions)) = current else {
+        change.action = ChangeAction::Create;
+        change.sessions_added = desired.sessions.iter().map(|s| s.session_id.clone()).collect();
+        return Some(change);
+    };
+
+    let fields = [
+        ("name", workspace.name == desired.name),
+        ("description", workspace.description == desired.description),
+        ("icon", workspace.icon == desired.icon),
+        ("tags", workspace.tags == desired.tags),
+        ("layout", LayoutTree::from_layout(&workspace.layout) == desired.tree),
+    ];
+    change.fields = fields
+        .iter()
+        .filter(|(_, same)| !same)
+        .map(|(field, _)| field.to_string())
+        .collect();
+
+    for want in &desired.sessions {
+        match sessions.iter().find(|s| s.session_id == want.session_id) {
+            None => change.sessions_added.push(want.session_id.clone()),
+            Some(have) if have != want => change.sessions_changed.push(want.session_id.clone()),
+            Some(_) => {}
+        }
+    }
+    change.sessions_removed = sessions
+        .iter()
+        .filter(|s| !desired.sessions.iter().any(|want| want.session_id == s.session_id))
+        .map(|s| s.session_id.clone())
+        .collect();
+
+    let unchanged = change.fields.is_empty()
+        && change.sessions_added.is_empty()
+        && change.sessions_removed.is_empty()
+        && change.sessions_changed.is_empty();
+    (!unchanged).then_some(change)
+}
+
+/// A declared workspace that the file no longer declares
+pub fn removal(workspace: &Workspace) -> WorkspaceChange {
+    WorkspaceChange {
+        action: ChangeAction::Delete,
+        workspace_id: workspace.id.clone(),
+        name: workspace.name.clone(),
+        fields: Vec::new(),
+        sessions_added: Vec::new(),
+        sessions_removed: Vec::new(),
+        sessions_changed: Vec::new(),
+    }
+}
+
+/// Builds a layout tree from its spec, numbering nodes in layout order
+struct TreeBuilder<'a> {
+    workspace_id: &'a str,
+    hosts: &'a BTreeMap<String, HostSpec>,
+    nodes: usize,
+    sessions: Vec<WorkspaceSession>,
+    problems: Vec<String>,
+}
+
+impl TreeBuilder<'_> {
+    fn node(&mut self, spec: &LayoutSpec) -> LayoutNode {
+        self.nodes += 1;
+        let n = self.nodes;
+
+        let kinds = [spec.pane.is_some(), spec.split.is_some(), !spec.tabs.is_empty()];
+        if kinds.iter().filter(|set| **set).count() > 1 {
+            self.problems.push("a layout node sets more than one of pane, split and tabs".to_string());
+        }
+        if spec.split.is_none() && (spec.sizes.is_some() || !spec.children.is_empty()) {
+            self.problems.push("sizes and children belong to a split".to_string());
+        }
+
+        if let Some(direction) = spec.split {
+            let children: Vec<LayoutNode> = spec.children.iter().map(|child| self.node(child)).collect();
+            let sizes = spec
+                .sizes
+                .clone()
+                .unwrap_or_else(|| vec![100.0 / children.len().max(1) as f32; children.len()]);
+            return LayoutNode::Split(SplitNode {
+                id: format!("{}-split-{}", self.workspace_id, n),
+                direction,
+                children,
+                sizes,
+            });
+        }
+        if !spec.tabs.is_empty() {
+            return LayoutNode::Tabs(TabsNode {
+                id: format!("{}-tabs-{}", self.workspace_id, n),
+                tabs: spec.tabs.iter().map(|tab| self.node(tab)).collect(),
+                active: 0,
+            });
+        }
+
+        let target = spec.pane.as_deref().unwrap_or(LOCAL);
+        let pane_id = format!("{}-pane-{}", self.workspace_id, n);
+        let session_id = format!("{}-session-{}", self.workspace_id, n);
+        let session_config = if target == LOCAL {
+            SessionConfig {
+                session_type: "local".to_string(),
+                name: LOCAL.to_string(),
+                host: None,
+                port: None,
+                username: None,
+                credential: None,
+            }
+        } else {
+            let Some(host) = self.hosts.get(target) else {
+                self.problems.push(format!("pane uses unknown host '{}'", target));
+                return LayoutNode::Pane(PaneNode {
+                    id: pane_id,
+                    session_id: None,
+                });
+            };
+            SessionConfig {
+                session_type: "ssh".to_string(),
+                name: target.to_string(),
+                host: Some(host.host.clone()),
+                port: host.port,
+                username: host.username.clone(),
+                credential: host.credential.clone(),
+            }
+        };
+
+        self.sessions.push(WorkspaceSession {
+            workspace_id: self.workspace_id.to_string(),
+            session_id: session_id.clone(),
+            pane_id: pane_id.clone(),
+            position: self.sessions.len() as i32,
+            session_config: Some(session_config),
+        });
+        LayoutNode::Pane(PaneNode {
+            id: pane_id,
+            session_id: Some(session_id),
+        })
+    }
+}
+
+fn first_pane(node: &LayoutNode) -> Option<String> {
+    match node {
+        LayoutNode::Pane(pane) => Some(pane.id.clone()),
+        LayoutNode::Split(split) => split.children.first().and_then(first_pane),
+        LayoutNode::Tabs(tabs) => tabs.tabs.first().and_then(first_pane),
+    }
+}
+
+/// `<store>:<name>`, with no whitespace
+fn is_credential_ref(value: &str) -> bool {
+    match value.split_once(':') {
+        Some((store, name)) => {
+            !store.is_empty()
+                && !name.is_empty()
+                && store.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+                && !name.chars().any(char::is_whitespace)
+        }
+        None => false,
+    }
+}
+
+/// Lowercase letters and digits, with runs of anything else as one dash
+fn slug(name: &str) -> String {
+    let mut slug = String::new();
+    for c in name.chars() {
+        if c.is_ascii_alphanumeric() {
+            slug.push(c.to_ascii_lowercase());
+        } else if !slug.is_empty() && !slug.ends_with('-') {
+            slug.push('-');
+        }
+    }
+    slug.trim_end_matches('-').to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EXAMPLE: &str = r#"
+hosts:
+  web-1:
+    host: 10.0.0.5
+    username: deploy
+    credential: keychain:prod-deploy
+  db:
+    host: db.internal
+    port: 2222
+  bastion:
+    host: bastion.example.com
+
+workspaces:
+  Production Web:
+    description: Web tier
+    tags: [prod]
+    layout:
+      split: horizontal
+      sizes: [60, 40]
+      children:
+        - pane: web-1
+        - tabs:
+            - pane: db
+            - pane: local
+  Scratch: {}
+"#;
+
+    #[test]
+    fn test_plan() {
+        let declaration = Declaration::parse(EXAMPLE).unwrap();
+        let desired = declaration.plan().unwrap();
+        assert_eq!(desired.len(), 2);
+
+        let web = desired.iter().find(|w| w.name == "Production Web").unwrap();
+        assert_eq!(web.id, "declared-production-web");
+        assert_eq!(web.tags, Some(vec!["prod".to_string()]));
+        assert_eq!(web.tree.panes().len(), 3);
+        assert_eq!(web.tree.active_pane.as_deref(), Some("declared-production-web-pane-2"));
+
+        let ssh = web.sessions[0].session_config.as_ref().unwrap();
+        assert_eq!(ssh.host.as_deref(), Some("10.0.0.5"));
+        assert_eq!(ssh.credential.as_deref(), Some("keychain:prod-deploy"));
+        assert_eq!(web.sessions[1].session_config.as_ref().unwrap().port, Some(2222));
+        assert_eq!(web.sessions[2].session_config.as_ref().unwrap().session_type, "local");
+
+        let scratch = desired.iter().find(|w| w.name == "Scratch").unwrap();
+        assert_eq!(scratch.sessions.len(), 1);
+        assert_eq!(declaration.unused_hosts(), vec!["bastion".to_string()]);
+    }
+
+    #[test]
+    fn test_plan_problems() {
+        let declaration = Declaration::parse(
+            r#"
+hosts:
+  web:
+    host: web.internal
+    credential: hunter2
+workspaces:
+  Ops:
+    layout:
+      split: vertical
+      children:
+        - pane: web
+        - pane: nowhere
+  ops!: {}
+  Lopsided:
+    layout:
+      split: horizontal
+      sizes: [90, 90]
+      children:
+        - pane: local
+        - pane: local
+"#,
+        )
+        .unwrap();
+
+        let error = declaration.plan().unwrap_err().to_string();
+        assert!(error.contains("credential must be a reference"), "{}", error);
+        assert!(error.contains("unknown host 'nowhere'"), "{}", error);
+        assert!(error.contains("both map to ID declared-ops"), "{}", error);
+        assert!(error.contains("sizes must be positive"), "{}", error);
+
+        assert!(Declaration::parse("hosts:\n  web:\n    hostname: x\n").is_err());
+    }
+
+    #[test]
+    fn test_host_tabs() {
+        let declaration = Declaration::parse(EXAMPLE).unwrap();
+        let workspace = host_tabs("imported", "Fleet", &declaration.hosts, vec!["new".to_string()]).unwrap();
+        assert_eq!(workspace.id, "imported");
+        assert_eq!(workspace.tree.panes().len(), 3);
+        let hosts: Vec<_> = workspace
+            .sessions
+            .iter()
+            .map(|s| s.session_config.as_ref().unwrap().name.as_str())
+            .collect();
+        assert_eq!(hosts, ["bastion", "db", "web-1"]);
+
+        assert!(host_tabs("imported", "Fleet", &BTreeMap::new(), Vec::new()).is_err());
+    }
+
+    #[test]
+    fn test_example_file() {
+        let declaration = Declaration::parse(include_str!("../../pulsar.yaml.example")).unwrap();
+        assert_eq!(declaration.plan().unwrap().len(), 2);
+        assert!(declaration.unused_hosts().is_empty());
+    }
+
+    #[test]
+    fn test_slug() {
+        assert_eq!(slug("Production Web"), "production-web");
+        assert_eq!(slug("  db / replicas (EU) "), "db-replicas-eu");
+        assert_eq!(slug("🚀"), "");
+    }
+}
@@ -0,0 +1,432 @@
+//! Host Inventory Export
+//!
+//! Renders saved SSH host profiles as an OpenSSH client config or an
+//! Ansible YAML inventory so Pulsar can act as the source of truth for
+//! other tooling.
+
+use super::models::{SessionConfig, Workspace, WorkspaceSession};
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use tracing::warn;
+
+/// Output format for a host inventory export
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum InventoryFormat {
+    /// OpenSSH client configuration (`~/.ssh/config` syntax)
+    SshConfig,
+    /// Ansible YAML inventory
+    Ansible,
+}
+
+/// Filter applied when collecting hosts for export
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct InventoryFilter {
+    /// Only include workspaces carrying at least one of these tags
+    pub tags: Option<Vec<String>>,
+    /// Only include these workspaces (by ID)
+    pub workspace_ids: Option<Vec<String>>,
+}
+
+impl InventoryFilter {
+    /// Check whether a workspace passes the filter
+    pub fn matches(&self, workspace: &Workspace) -> bool {
+        if let Some(ids) = &self.workspace_ids {
+            if !ids.iter().any(|id| id == &workspace.id) {
+                return false;
+            }
+        }
+
+        if let Some(tags) = &self.tags {
+            let workspace_tags = workspace.tags.as_deref().unwrap_or_default();
+            if !tags.iter().any(|t| workspace_tags.contains(t)) {
+                return false;
+            }
+        }
+
+        true
+    }
+}
+
+/// A single exportable SSH host
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct HostProfile {
+    /// Unique alias used as the `Host` pattern / inventory hostname
+    pub alias: String,
+    pub host: String,
+    pub port: Option<u16>,
+    pub username: Option<String>,
+    /// Name of the workspace the profile was saved in
+    pub workspace: String,
+    /// Tags inherited from the workspace
+    pub tags: Vec<String>,
+}
+
+/// Where a listed host comes from
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum HostSource {
+    /// Saved in a local workspace
+    Local,
+    /// Pulled from the team directory; read-only
+    Team,
+}
+
+/// A host in the merged local and team directory
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DirectoryHost {
+    #[serde(flatten)]
+    pub profile: HostProfile,
+    pub source: HostSource,
+    pub read_only: bool,
+}
+
+/// Merge team hosts with local ones
+///
+/// Team hosts keep their aliases, which are shared across the team; a
+/// local host with the same alias gets a numeric suffix instead.
+pub fn merge_team_hosts(team: Vec<HostProfile>, local: Vec<HostProfile>) -> Vec<DirectoryHost> {
+    let mut seen = BTreeMap::new();
+    let team = team.into_iter().map(|profile| (profile, HostSource::Team));
+    let local = local.into_iter().map(|profile| (profile, HostSource::Local));
+
+    team.chain(local)
+        .map(|(mut profile, source)| {
+            profile.alias = unique_alias(&profile.alias, &mut seen);
+            DirectoryHost {
+                profile,
+                source,
+                read_only: source == HostSource::Team,
+            }
+        })
+        .collect()
+}
+
+/// Collect SSH host profiles from a workspace's saved sessions
+///
+/// Local sessions and SSH sessions without a host are skipped. Aliases are
+/// made unique against `seen` so the same name in two workspaces does not
+/// collide in the exported file.
+pub fn collect_hosts(
+    workspace: &Workspace,
+    sessions: &[WorkspaceSession],
+    seen: &mut BTreeMap<String, usize>,
+) -> Vec<HostProfile> {
+    sessions
+        .iter()
+        .filter_map(|s| s.session_config.as_ref())
+        .filter(|c| c.session_type == "ssh")
+        .filter_map(|config| {
+            let host = config.host.clone()?;
+            let alias = unique_alias(&alias_for(config, &host), seen);
+
+            Some(HostProfile {
+                alias,
+                host,
+                port: config.port,
+                username: config.username.clone(),
+                workspace: workspace.name.clone(),
+                tags: workspace.tags.clone().unwrap_or_default(),
+            })
+        })
+        .collect()
+}
+
+/// Render profiles in the requested format
+pub fn render(hosts: &[HostProfile], format: InventoryFormat) -> Result<String> {
+    match format {
+        InventoryFormat::SshConfig => Ok(render_ssh_config(hosts)),
+        InventoryFormat::Ansible => render_ansible(hosts),
+    }
+}
+
+/// Render profiles as an OpenSSH client config
+///
+/// Hosts whose alias, address or user would not be a single word are left
+/// out: a newline in one, from a team directory say, could otherwise add
+/// directives such as `ProxyCommand`.
+pub fn render_ssh_config(hosts: &[HostProfile]) -> String {
+    let mut out = String::from("# Generated by Pulsar - do not edit by hand\n");
+
+    for host in hosts {
+        let fields = [Some(&host.alias), Some(&host.host), host.username.as_ref()];
+        if !fields.into_iter().flatten().all(|field| is_ssh_config_word(field)) {
+            warn!("Left {:?} out of the SSH config: not a single word", host.alias);
+            continue;
+        }
+        let workspace: String = host
+            .workspace
+            .chars()
+            .map(|c| if c.is_control() { ' ' } else { c })
+            .collect();
+
+        out.push('\n');
+        out.push_str(&format!("# workspace: {}\n", workspace));
+        out.push_str(&format!("Host {}\n", host.alias));
+        out.push_str(&format!("    HostName {}\n", host.host));
+        if let Some(port) = host.port {
+            out.push_str(&format!("    Port {}\n", port));
+        }
+        if let Some(user) = &host.username {
+            out.push_str(&format!("    User {}\n", user));
+        }
+    }
+
+    out
+}
+
+/// Render profiles as an Ansible YAML inventory
+///
+/// Every host is listed under `all.hosts`; workspaces and tags become
+/// child groups referencing those hosts.
+pub fn render_ansible(hosts: &[HostProfile]) -> Result<String> {
+    let mut all_hosts = BTreeMap::new();
+    let mut groups: BTreeMap<String, BTreeMap<String, AnsibleHostVars>> = BTreeMap::new();
+
+    for host in hosts {
+        all_hosts.insert(
+            host.alias.clone(),
+            AnsibleHostVars {
+                ansible_host: Some(host.host.clone()),
+                ansible_port: host.port,
+                ansible_user: host.username.clone(),
+            },
+        );
+
+        let memberships = std::iter::once(format!("workspace_{}", group_name(&host.workspace)))
+            .chain(host.tags.iter().map(|t| format!("tag_{}", group_name(t))));
+
+        for group in memberships {
+            groups
+                .entry(group)
+                .or_default()
+                .insert(host.alias.clone(), AnsibleHostVars::default());
+        }
+    }
+
+    let inventory = AnsibleInventory {
+        all: AnsibleGroup {
+            hosts: all_hosts,
+            children: groups
+                .into_iter()
+                .map(|(name, hosts)| {
+                    (
+                        name,
+                        AnsibleGroup {
+                            hosts,
+                            children: BTreeMap::new(),
+                        },
+                    )
+                })
+                .collect(),
+        },
+    };
+
+    serde_yaml::to_string(&inventory).context("Failed to serialize Ansible inventory")
+}
+
+#[derive(Serialize)]
+struct AnsibleInventory {
+    all: AnsibleGroup,
+}
+
+#[derive(Serialize)]
+struct AnsibleGroup {
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    hosts: BTreeMap<String, AnsibleHostVars>,
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    children: BTreeMap<String, AnsibleGroup>,
+}
+
+#[derive(Default, Serialize)]
+struct AnsibleHostVars {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    ansible_host: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    ansible_port: Option<u16>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    ansible_user: Option<String>,
+}
+
+/// Derive an alias from the session name, falling back to the host
+fn alias_for(config: &SessionConfig, host: &str) -> String {
+    let alias = group_name(&config.name);
+    if alias.is_empty() {
+        group_name(host)
+    } else {
+        alias
+    }
+}
+
+/// Whether `value` can be written as an ssh_config argument unquoted
+fn is_ssh_config_word(value: &str) -> bool {
+    !value.is_empty() && !value.chars().any(|c| c.is_whitespace() || c.is_control() || c == '"')
+}
+
+/// Append a numeric suffix when an alias has already been used
+fn unique_alias(alias: &str, seen: &mut BTreeMap<String, usize>) -> String {
+    let count = seen.entry(alias.to_string()).or_insert(0);
+    *count += 1;
+    if *count == 1 {
+        alias.to_string()
+    } else {
+        format!("{}-{}", alias, count)
+    }
+}
+
+/// Reduce a free-form name to characters safe for SSH patterns and Ansible groups
+fn group_name(name: &str) -> String {
+    name.trim()
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || c == '-' || c == '_' || c == '.' {
+                c.to_ascii_lowercase()
+            } else {
+                '_'
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ssh_session(name: &str, host: &str) -> WorkspaceSession {
+        WorkspaceSession {
+            workspace_id: "ws-1".to_string(),
+            session_id: uuid::Uuid::new_v4().to_string(),
+            pane_id: "pane-1".to_string(),
+            position: 0,
+            session_config: Some(SessionConfig {
+                session_type: "ssh".to_string(),
+                name: name.to_string(),
+                host: Some(host.to_string()),
+                port: Some(2222),
+                username: Some("deploy".to_string()),
+                credential: None,
+            }),
+        }
+    }
+
+    fn workspace(tags: &[&str]) -> Workspace {
+        let mut ws = Workspace::new("Prod Cluster".to_string());
+        ws.tags = Some(tags.iter().map(|t| t.to_string()).collect());
+        ws
+    }
+
+    #[test]
+    fn test_collect_hosts_deduplicates_aliases() {
+        let ws = workspace(&["prod"]);
+        let sessions = vec![ssh_session("web", "10.0.0.1"), ssh_session("web", "10.0.0.2")];
+        let mut seen = BTreeMap::new();
+
+        let hosts = collect_hosts(&ws, &sessions, &mut seen);
+        assert_eq!(hosts.len(), 2);
+        assert_eq!(hosts[0].alias, "web");
+        assert_eq!(hosts[1].alias, "web-2");
+    }
+
+    #[test]
+    fn test_merge_team_hosts_keeps_team_aliases() {
+        let ws = workspace(&[]);
+        let local = collect_hosts(&ws, &[ssh_session("bastion", "10.0.0.9")], &mut BTreeMap::new());
+        let team = vec![HostProfile {
+            alias: "bastion".to_string(),
+            host: "bastion.corp".to_string(),
+            port: None,
+            username: None,
+            workspace: "platform".to_string(),
+            tags: Vec::new(),
+        }];
+
+        let merged = merge_team_hosts(team, local);
+        assert_eq!(merged.len(), 2);
+        assert_eq!(merged[0].profile.alias, "bastion");
+        assert_eq!(merged[0].source, HostSource::Team);
+        assert!(merged[0].read_only);
+        assert_eq!(merged[1].profile.alias, "bastion-2");
+        assert_eq!(merged[1].profile.host, "10.0.0.9");
+        assert!(!merged[1].read_only);
+    }
+
+    #[test]
+    fn test_render_ssh_config() {
+        let ws = workspace(&[]);
+        let hosts = collect_hosts(&ws, &[ssh_session("db primary", "db.internal")], &mut BTreeMap::new());
+
+        let config = render_ssh_config(&hosts);
+        assert!(config.contains("Host db_primary\n"));
+        assert!(config.contains("    HostName db.internal\n"));
+        assert!(config.contains("    Port 2222\n"));
+        assert!(config.contains("    User deploy\n"));
+    }
+
+    #[test]
+    fn test_render_ssh_config_skips_injected_fields() {
+        let mut ws = workspace(&[]);
+        ws.name = "Prod\nHost *".to_string();
+        let mut hosts = collect_hosts(
+            &ws,
+            &[
+                ssh_session("web", "web.internal"),
+                ssh_session("evil", "evil.internal\n    ProxyCommand sh -c id"),
+            ],
+            &mut BTreeMap::new(),
+        );
+        hosts[0].username = Some("deploy\nProxyCommand sh".to_string());
+        hosts.push(HostProfile {
+            alias: "db".to_string(),
+            host: "db.internal".to_string(),
+            port: None,
+            username: None,
+            workspace: ws.name.clone(),
+            tags: Vec::new(),
+        });
+
+        let config = render_ssh_config(&hosts);
+        assert!(!config.contains("ProxyCommand"));
+        assert!(!config.contains("\nHost *"));
+        assert!(config.contains("# workspace: Prod Host *\nHost db\n"));
+        assert!(!config.contains("Host web"));
+    }
+
+    #[test]
+    fn test_render_ansible_groups_by_workspace_and_tag() {
+        let ws = workspace(&["prod"]);
+        let hosts = collect_hosts(&ws, &[ssh_session("web", "10.0.0.1")], &mut BTreeMap::new());
+
+        let yaml = render_ansible(&hosts).unwrap();
+        let parsed: serde_yaml::Value = serde_yaml::from_str(&yaml).unwrap();
+
+        assert_eq!(parsed["all"]["hosts"]["web"]["ansible_host"], "10.0.0.1");
+        assert_eq!(parsed["all"]["hosts"]["web"]["ansible_port"], 2222);
+        assert!(parsed["all"]["children"]["workspace_prod_cluster"]["hosts"]
+            .get("web")
+            .is_some());
+        assert!(parsed["all"]["children"]["tag_prod"]["hosts"].get("web").is_some());
+    }
+
+    #[test]
+    fn test_filter_by_tag_and_workspace() {
+        let ws = workspace(&["prod"]);
+
+        assert!(InventoryFilter::default().matches(&ws));
+        assert!(InventoryFilter {
+            tags: Some(vec!["prod".to_string()]),
+            workspace_ids: None,
+        }
+        .matches(&ws));
+        assert!(!InventoryFilter {
+            tags: Some(vec!["staging".to_string()]),
+            workspace_ids: None,
+        }
+        .matches(&ws));
+        assert!(!InventoryFilter {
+            tags: None,
+            workspace_ids: Some(vec!["other".to_string()]),
+        }
+        .matches(&ws));
+    }
+}
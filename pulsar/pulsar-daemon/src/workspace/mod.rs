@@ -1,9 +1,18 @@
 //! Workspace Management Module
 //!
-//! Manages workspace layouts, sessions, and templates.
+//! Manages workspace layouts, sessions, and templates, and reconciles
+//! them to a declarative `pulsar.yaml`.
 
+pub mod declarative;
+pub mod inventory;
 pub mod models;
+pub mod scheduler;
 pub mod service;
+pub mod types;
 
+pub use declarative::{ApplyReport, Declaration};
+pub use inventory::{DirectoryHost, HostProfile, HostSource, InventoryFilter, InventoryFormat};
 pub use models::*;
+pub use scheduler::{snapshot_changed_workspaces, SnapshotScheduler};
 pub use service::WorkspaceService;
+pub use types::{LayoutError, LayoutNode, LayoutOp, LayoutTree, SplitDirection};
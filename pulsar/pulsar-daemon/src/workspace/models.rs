@@ -2,9 +2,12 @@
 //!
 //! Data structures for workspace management
 
+use super::types::LayoutTree;
+use crate::session_manager::Bookmark;
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 use sqlx::FromRow;
+use std::collections::HashMap;
 
 /// Workspace represents a collection of terminal sessions with a specific layout
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -28,6 +31,9 @@ pub struct WorkspaceLayout {
     pub layout_type: String, // "single" | "split"
     pub panes: Vec<PaneConfig>,
     pub active_pane: Option<String>,
+    /// Typed layout tree; absent on layouts saved before version 2.0.0
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tree: Option<LayoutTree>,
 }
 
 /// Pane configuration
@@ -43,7 +49,7 @@ pub struct PaneConfig {
 }
 
 /// Workspace session mapping
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct WorkspaceSession {
     pub workspace_id: String,
     pub session_id: String,
@@ -53,7 +59,7 @@ pub struct WorkspaceSession {
 }
 
 /// Session configuration
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct SessionConfig {
     #[serde(rename = "type")]
     pub session_type: String, // "local" | "ssh"
@@ -61,6 +67,9 @@ pub struct SessionConfig {
     pub host: Option<String>,
     pub port: Option<u16>,
     pub username: Option<String>,
+    /// Where the login secret is kept (`<store>:<name>`), never the secret
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub credential: Option<String>,
 }
 
 /// Workspace snapshot (version)
@@ -70,7 +79,91 @@ pub struct WorkspaceSnapshot {
     pub workspace_id: String,
     pub name: String,
     pub layout: WorkspaceLayout,
+    /// Sessions mapped to the workspace when the snapshot was taken
+    #[serde(default)]
+    pub sessions: Vec<WorkspaceSession>,
+    /// Bookmarks those sessions had when the snapshot was taken
+    #[serde(default)]
+    pub bookmarks: Vec<SnapshotBookmark>,
+    pub created_at: DateTime<Utc>,
+}
+
+/// A bookmark saved with a snapshot, and the session it belongs to
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SnapshotBookmark {
+    pub session_id: String,
+    #[serde(flatten)]
+    pub bookmark: Bookmark,
+}
+
+/// Snapshot metadata for listings
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SnapshotSummary {
+    pub id: String,
+    pub workspace_id: String,
+    pub name: String,
     pub created_at: DateTime<Utc>,
+    pub pane_count: usize,
+    pub session_count: usize,
+    /// Stored size of the layout and session configs
+    pub size_bytes: u64,
+    /// Taken by the scheduler rather than saved by the user
+    pub automatic: bool,
+}
+
+/// Disk usage of workspace snapshots
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SnapshotUsage {
+    pub snapshot_count: usize,
+    /// Snapshots taken by the scheduler (subject to retention)
+    pub automatic_count: usize,
+    /// Stored size of snapshot layouts and session configs
+    pub snapshot_bytes: u64,
+    /// Size of the whole database
+    pub database_bytes: u64,
+    /// Free pages that compaction would give back
+    pub reclaimable_bytes: u64,
+}
+
+/// A pane whose session binding differs between a snapshot and the workspace
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PaneBindingChange {
+    pub pane_id: String,
+    pub current_session_id: Option<String>,
+    pub snapshot_session_id: Option<String>,
+}
+
+/// What restoring a snapshot would change in its workspace
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct SnapshotDiff {
+    pub snapshot_id: String,
+    pub workspace_id: String,
+    /// Splits, tabs or sizes differ
+    pub layout_changed: bool,
+    /// Panes only in the snapshot (restore brings them back)
+    pub panes_added: Vec<String>,
+    /// Panes only in the workspace (a full restore drops them)
+    pub panes_removed: Vec<String>,
+    pub panes_rebound: Vec<PaneBindingChange>,
+    /// Sessions only in the snapshot
+    pub sessions_added: Vec<String>,
+    /// Sessions only in the workspace
+    pub sessions_removed: Vec<String>,
+    /// Sessions in both with a different pane, position or config
+    pub sessions_changed: Vec<String>,
+}
+
+/// Parts of a snapshot to restore
+///
+/// Selecting a pane restores its session binding (re-adding the pane if it
+/// was closed) and the sessions mapped to it; selecting a session restores
+/// its mapping and the binding of its pane.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct RestoreSelection {
+    #[serde(default)]
+    pub pane_ids: Vec<String>,
+    #[serde(default)]
+    pub session_ids: Vec<String>,
 }
 
 /// Create workspace request
@@ -149,6 +242,7 @@ impl Default for WorkspaceLayout {
             layout_type: "single".to_string(),
             panes: vec![PaneConfig::default()],
             active_pane: None,
+            tree: None,
         }
     }
 }
@@ -169,13 +263,99 @@ impl Default for PaneConfig {
 
 impl WorkspaceSnapshot {
     /// Create snapshot from workspace
-    pub fn from_workspace(workspace: &Workspace, name: String) -> Self {
+    pub fn from_workspace(
+        workspace: &Workspace,
+        sessions: Vec<WorkspaceSession>,
+        name: String,
+    ) -> Self {
         Self {
             id: uuid::Uuid::new_v4().to_string(),
             workspace_id: workspace.id.clone(),
             name,
             layout: workspace.layout.clone(),
+            sessions,
+            bookmarks: Vec::new(),
             created_at: Utc::now(),
         }
     }
 }
+
+impl SnapshotDiff {
+    /// Compare a snapshot against the current state of its workspace
+    pub fn between(
+        snapshot: &WorkspaceSnapshot,
+        layout: &WorkspaceLayout,
+        sessions: &[WorkspaceSession],
+    ) -> Self {
+        let current_tree = LayoutTree::from_layout(layout);
+        let snapshot_tree = LayoutTree::from_layout(&snapshot.layout);
+
+        let current_panes: HashMap<_, _> = current_tree
+            .panes()
+            .into_iter()
+            .map(|p| (p.id.as_str(), &p.session_id))
+            .collect();
+        let snapshot_panes: HashMap<_, _> = snapshot_tree
+            .panes()
+            .into_iter()
+            .map(|p| (p.id.as_str(), &p.session_id))
+            .collect();
+
+        let mut diff = Self {
+            snapshot_id: snapshot.id.clone(),
+            workspace_id: snapshot.workspace_id.clone(),
+            layout_changed: !current_tree.same_shape(&snapshot_tree),
+            ..Self::default()
+        };
+
+        for pane in snapshot_tree.panes() {
+            match current_panes.get(pane.id.as_str()) {
+                None => diff.panes_added.push(pane.id.clone()),
+                Some(current) if **current != pane.session_id => {
+                    diff.panes_rebound.push(PaneBindingChange {
+                        pane_id: pane.id.clone(),
+                        current_session_id: (*current).clone(),
+                        snapshot_session_id: pane.session_id.clone(),
+                    })
+                }
+                Some(_) => {}
+            }
+        }
+        diff.panes_removed = current_tree
+            .panes()
+            .iter()
+            .filter(|p| !snapshot_panes.contains_key(p.id.as_str()))
+            .map(|p| p.id.clone())
+            .collect();
+
+        let current_sessions: HashMap<_, _> =
+            sessions.iter().map(|s| (s.session_id.as_str(), s)).collect();
+        for session in &snapshot.sessions {
+            match current_sessions.get(session.session_id.as_str()) {
+                None => diff.sessions_added.push(session.session_id.clone()),
+                Some(current) if *current != session => {
+                    diff.sessions_changed.push(session.session_id.clone())
+                }
+                Some(_) => {}
+            }
+        }
+        diff.sessions_removed = sessions
+            .iter()
+            .filter(|s| !snapshot.sessions.iter().any(|o| o.session_id == s.session_id))
+            .map(|s| s.session_id.clone())
+            .collect();
+
+        diff
+    }
+
+    /// Whether restoring would change nothing
+    pub fn is_empty(&self) -> bool {
+        !self.layout_changed
+            && self.panes_added.is_empty()
+            && self.panes_removed.is_empty()
+            && self.panes_rebound.is_empty()
+            && self.sessions_added.is_empty()
+            && self.sessions_removed.is_empty()
+            && self.sessions_changed.is_empty()
+    }
+}
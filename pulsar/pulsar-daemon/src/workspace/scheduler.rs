@@ -0,0 +1,241 @@
+//! Scheduled Workspace Snapshots
+//!
+//! Snapshots every workspace that changed since its last automatic
+//! snapshot, prunes automatic snapshots down to the configured hourly and
+//! daily retention, and periodically compacts the database so pruned
+//! snapshots actually give disk space back. Snapshots saved by the user
+//! are never pruned; only an explicitly configured retention period
+//! deletes every snapshot older than it.
+
+use super::models::{Workspace, WorkspaceFilter};
+use super::service::WorkspaceService;
+use crate::config::SnapshotScheduleConfig;
+use anyhow::Result;
+use chrono::{DateTime, Duration, Timelike, Utc};
+use std::collections::HashSet;
+use std::sync::Arc;
+use tracing::{debug, error, info};
+
+/// Outcome of one scheduler pass
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct SnapshotRun {
+    pub created: usize,
+    pub pruned: usize,
+    /// Snapshots deleted for exceeding the retention period
+    pub expired: u64,
+    pub compacted: bool,
+}
+
+/// Takes and prunes automatic workspace snapshots
+pub struct SnapshotScheduler {
+    service: Arc<WorkspaceService>,
+    config: SnapshotScheduleConfig,
+    last_compaction: Option<DateTime<Utc>>,
+}
+
+impl SnapshotScheduler {
+    pub fn new(service: Arc<WorkspaceService>, config: SnapshotScheduleConfig) -> Self {
+        Self {
+            service,
+            config,
+            last_compaction: None,
+        }
+    }
+
+    /// Run passes on the configured interval; runs until the task is aborted
+    pub async fn run(mut self) {
+        let period = std::time::Duration::from_secs(self.config.interval_minutes.max(1) * 60);
+        let mut ticker = tokio::time::interval(period);
+
+        loop {
+            ticker.tick().await;
+            match self.run_once(Utc::now()).await {
+                Ok(run) if run != SnapshotRun::default() => info!(
+                    "Snapshot run: {} created, {} pruned, {} expired{}",
+                    run.created,
+                    run.pruned,
+                    run.expired,
+                    if run.compacted { ", database compacted" } else { "" }
+                ),
+                Ok(_) => {}
+                Err(e) => error!("Scheduled snapshot run failed: {}", e),
+            }
+        }
+    }
+
+    /// Snapshot changed workspaces, apply retention and compact if due
+    pub async fn run_once(&mut self, now: DateTime<Utc>) -> Result<SnapshotRun> {
+        let mut run = SnapshotRun::default();
+
+        for workspace in list_workspaces(&self.service).await? {
+            if snapshot_if_changed(&self.service, &workspace.id).await? {
+                run.created += 1;
+            }
+
+            let automatic = self.service.list_automatic_snapshots(&workspace.id).await?;
+            for id in expired_snapshots(&automatic, self.config.keep_hourly, self.config.keep_daily) {
+                if self.service.delete_snapshot(&id).await? {
+                    run.pruned += 1;
+                }
+            }
+        }
+
+        if self.config.retention_days > 0 {
+            let cutoff = now - Duration::days(self.config.retention_days as i64);
+            run.expired = self.service.delete_snapshots_before(cutoff).await?;
+        }
+
+        let compaction_due = self.last_compaction.is_none_or(|last| {
+            now - last >= Duration::hours(self.config.compact_interval_hours as i64)
+        });
+        if compaction_due {
+            self.service.compact().await?;
+            self.last_compaction = Some(now);
+            run.compacted = true;
+        }
+
+        Ok(run)
+    }
+}
+
+/// Snapshot every workspace that changed since its last automatic
+/// snapshot, returning how many were taken; used on shutdown too
+pub async fn snapshot_changed_workspaces(service: &WorkspaceService) -> Result<usize> {
+    let mut created = 0;
+    for workspace in list_workspaces(service).await? {
+        if snapshot_if_changed(service, &workspace.id).await? {
+            created += 1;
+        }
+    }
+    Ok(created)
+}
+
+async fn list_workspaces(service: &WorkspaceService) -> Result<Vec<Workspace>> {
+    service
+        .list_workspaces(WorkspaceFilter {
+            is_template: Some(false),
+            ..WorkspaceFilter::default()
+        })
+        .await
+}
+
+/// Take an automatic snapshot unless nothing changed since the last one
+async fn snapshot_if_changed(service: &WorkspaceService, workspace_id: &str) -> Result<bool> {
+    let automatic = service.list_automatic_snapshots(workspace_id).await?;
+    let unchanged = match automatic.first() {
+        Some((latest, _)) => service
+            .diff_snapshot(latest)
+            .await?
+            .is_some_and(|diff| diff.is_empty()),
+        None => false,
+    };
+    if unchanged {
+        debug!("Workspace {} unchanged since last snapshot", workspace_id);
+        return Ok(false);
+    }
+    service.save_automatic_snapshot(workspace_id).await?;
+    Ok(true)
+}
+
+/// Snapshots to delete so only the newest snapshot of each of the
+/// `keep_hourly` most recent hours and `keep_daily` most recent days remain
+///
+/// `snapshots` must be sorted newest first.
+pub fn expired_snapshots(
+    snapshots: &[(String, DateTime<Utc>)],
+    keep_hourly: usize,
+    keep_daily: usize,
+) -> Vec<String> {
+    let mut hours = HashSet::new();
+    let mut days = HashSet::new();
+    let mut expired = Vec::new();
+
+    for (id, created_at) in snapshots {
+        let hour = created_at.date_naive().and_hms_opt(created_at.hour(), 0, 0);
+        let day = created_at.date_naive();
+
+        let keep_for_hour = hours.len() < keep_hourly && hours.insert(hour);
+        let keep_for_day = days.len() < keep_daily && days.insert(day);
+        if !keep_for_hour && !keep_for_day {
+            expired.push(id.clone());
+        }
+    }
+
+    expired
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::workspace::{CreateWorkspaceRequest, WorkspaceLayout};
+    use chrono::TimeZone;
+    use sqlx::sqlite::SqlitePoolOptions;
+
+    #[test]
+    fn test_retention_keeps_latest_per_hour_and_day() {
+        let start = Utc.with_ymd_and_hms(2026, 3, 10, 12, 45, 0).unwrap();
+        // Every 15 minutes for three days, newest first
+        let snapshots: Vec<_> = (0..(3 * 24 * 4))
+            .map(|i| (format!("s{}", i), start - Duration::minutes(15 * i)))
+            .collect();
+
+        let expired: HashSet<_> = expired_snapshots(&snapshots, 4, 2).into_iter().collect();
+        let kept: Vec<_> = snapshots
+            .iter()
+            .filter(|(id, _)| !expired.contains(id))
+            .map(|(_, at)| *at)
+            .collect();
+
+        // Four hourly snapshots (12:45, 11:45, 10:45, 9:45) plus the newest
+        // snapshot of the previous day; today's daily is the 12:45 one
+        assert_eq!(kept.len(), 5);
+        assert_eq!(kept[0], start);
+        assert_eq!(kept[3], start - Duration::hours(3));
+        assert_eq!(kept[4], Utc.with_ymd_and_hms(2026, 3, 9, 23, 45, 0).unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_run_skips_unchanged_workspaces() {
+        let pool = SqlitePoolOptions::new()
+            .connect(":memory:")
+            .await
+            .expect("Failed to create test database");
+        let service = Arc::new(WorkspaceService::new(Arc::new(pool)));
+        service.initialize().await.expect("Failed to initialize");
+
+        service
+            .create_workspace(CreateWorkspaceRequest {
+                name: "Scheduled".to_string(),
+                description: None,
+                icon: None,
+                layout: WorkspaceLayout::default(),
+                is_template: false,
+                tags: None,
+            })
+            .await
+            .unwrap();
+
+        let config = SnapshotScheduleConfig {
+            retention_days: 90,
+            ..SnapshotScheduleConfig::default()
+        };
+        let mut scheduler = SnapshotScheduler::new(Arc::clone(&service), config);
+
+        let first = scheduler.run_once(Utc::now()).await.unwrap();
+        assert_eq!(first.created, 1);
+        assert!(first.compacted);
+
+        let second = scheduler.run_once(Utc::now()).await.unwrap();
+        assert_eq!(second, SnapshotRun::default());
+
+        let usage = service.snapshot_usage().await.unwrap();
+        assert_eq!(usage.snapshot_count, 1);
+        assert_eq!(usage.automatic_count, 1);
+        assert!(usage.database_bytes > 0);
+
+        // A run far in the future finds every snapshot past retention
+        let later = scheduler.run_once(Utc::now() + Duration::days(91)).await.unwrap();
+        assert_eq!(later.expired, 1);
+        assert_eq!(service.snapshot_usage().await.unwrap().snapshot_count, 0);
+    }
+}
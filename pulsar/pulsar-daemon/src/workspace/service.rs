@@ -2,36 +2,76 @@
 //!
 //! Provides CRUD operations for workspaces
 
+use super::declarative::{self, ApplyReport, Declaration, DesiredWorkspace, HostSpec};
+use super::inventory::{self, DirectoryHost, HostProfile, InventoryFilter, InventoryFormat};
 use super::models::*;
+use super::types::{LayoutOp, LayoutTree};
+use crate::session_manager::{Bookmark, SessionManager};
 use anyhow::{Context, Result};
 use chrono::{DateTime, TimeZone, Utc};
-use sqlx::{Pool, Row, Sqlite};
+use pulsar_db::FieldCipher;
+use sqlx::sqlite::SqliteRow;
+use sqlx::{Executor, Pool, Row, Sqlite};
+use std::collections::BTreeMap;
 use std::sync::Arc;
 use tracing::{debug, info, warn};
+use uuid::Uuid;
 
 /// Workspace service for managing workspace CRUD operations
 pub struct WorkspaceService {
     db: Arc<Pool<Sqlite>>,
+    /// Seals session configs (hosts, users, commands) at rest
+    cipher: FieldCipher,
+    /// Live sessions, whose bookmarks are saved with snapshots
+    sessions: Option<Arc<SessionManager>>,
 }
 
 impl WorkspaceService {
     /// Create a new workspace service
     pub fn new(db: Arc<Pool<Sqlite>>) -> Self {
-        Self { db }
+        Self {
+            db,
+            cipher: FieldCipher::disabled(),
+            sessions: None,
+        }
+    }
+
+    /// Encrypt session configs at rest with the given cipher
+    pub fn with_cipher(mut self, cipher: FieldCipher) -> Self {
+        self.cipher = cipher;
+        self
+    }
+
+    /// Save the bookmarks of live sessions with snapshots, and put them back
+    /// when a snapshot is restored
+    pub fn with_session_manager(mut self, sessions: Arc<SessionManager>) -> Self {
+        self.sessions = Some(sessions);
+        self
     }
 
     /// Initialize database (run migrations)
     pub async fn initialize(&self) -> Result<()> {
         info!("Initializing workspace database");
 
-        // Read migration file
-        let migration_sql = include_str!("../../migrations/003_workspaces.sql");
+        // Read migration files
+        let migrations = [
+            include_str!("../../migrations/003_workspaces.sql"),
+            include_str!("../../migrations/004_snapshot_sessions.sql"),
+            include_str!("../../migrations/005_auto_snapshots.sql"),
+            include_str!("../../migrations/006_snapshot_bookmarks.sql"),
+        ];
+
+        // Execute migrations
+        for migration_sql in migrations {
+            sqlx::raw_sql(migration_sql)
+                .execute(&*self.db)
+                .await
+                .context("Failed to run workspace migrations")?;
+        }
 
-        // Execute migration
-        sqlx::raw_sql(migration_sql)
-            .execute(&*self.db)
-            .await
-            .context("Failed to run workspace migrations")?;
+        if self.cipher.is_enabled() {
+            self.seal_plaintext_configs().await?;
+        }
 
         info!("Workspace database initialized");
         Ok(())
@@ -206,6 +246,9 @@ impl WorkspaceService {
         }
 
         if let Some(layout) = req.layout {
+            if let Some(tree) = &layout.tree {
+                tree.validate().context("Invalid workspace layout")?;
+            }
             workspace.layout = layout;
         }
 
@@ -213,6 +256,17 @@ impl WorkspaceService {
             workspace.tags = Some(tags);
         }
 
+        Self::write_workspace(&*self.db, &workspace).await?;
+
+        info!("Updated workspace: {} ({})", workspace.name, id);
+        Ok(Some(workspace))
+    }
+
+    /// Save a workspace's name, description, icon, layout and tags
+    async fn write_workspace<'e, E>(executor: E, workspace: &Workspace) -> Result<()>
+    where
+        E: Executor<'e, Database = Sqlite>,
+    {
         // Serialize
         let layout_json = serde_json::to_string(&workspace.layout)
             .context("Failed to serialize workspace layout")?;
@@ -238,13 +292,12 @@ impl WorkspaceService {
         .bind(&layout_json)
         .bind(tags_json)
         .bind(Utc::now().timestamp())
-        .bind(id)
-        .execute(&*self.db)
+        .bind(&workspace.id)
+        .execute(executor)
         .await
         .context("Failed to update workspace")?;
 
-        info!("Updated workspace: {} ({})", workspace.name, id);
-        Ok(Some(workspace))
+        Ok(())
     }
 
     /// Delete a workspace
@@ -265,13 +318,15 @@ impl WorkspaceService {
         Ok(deleted)
     }
 
-    /// Save a snapshot of a workspace
+    /// Save a snapshot of a workspace and its session mappings
     pub async fn save_snapshot(&self, workspace_id: &str, name: String) -> Result<WorkspaceSnapshot> {
         let Some(workspace) = self.get_workspace(workspace_id).await? else {
             anyhow::bail!("Workspace not found: {}", workspace_id);
         };
+        let sessions = self.get_workspace_sessions(workspace_id).await?;
 
-        let snapshot = WorkspaceSnapshot::from_workspace(&workspace, name);
+        let mut snapshot = WorkspaceSnapshot::from_workspace(&workspace, sessions, name);
+        snapshot.bookmarks = self.live_bookmarks(&snapshot.sessions).await;
 
         let layout_json = serde_json::to_string(&snapshot.layout)
             .context("Failed to serialize snapshot layout")?;
@@ -291,10 +346,166 @@ impl WorkspaceService {
         .await
         .context("Failed to insert workspace snapshot")?;
 
+        for session in &snapshot.sessions {
+            let config_json = self.seal_config(session.session_config.as_ref())?;
+
+            sqlx::query(
+                r#"
+                INSERT INTO workspace_snapshot_sessions (snapshot_id, session_id, pane_id, position, session_config)
+                VALUES (?, ?, ?, ?, ?)
+                "#,
+            )
+            .bind(&snapshot.id)
+            .bind(&session.session_id)
+            .bind(&session.pane_id)
+            .bind(session.position)
+            .bind(config_json)
+            .execute(&*self.db)
+            .await
+            .context("Failed to insert snapshot session")?;
+        }
+
+        for saved in &snapshot.bookmarks {
+            let bookmark = &saved.bookmark;
+            sqlx::query(
+                r#"
+                INSERT INTO workspace_snapshot_bookmarks (snapshot_id, session_id, bookmark_id, line, note, text, created_at)
+                VALUES (?, ?, ?, ?, ?, ?, ?)
+                "#,
+            )
+            .bind(&snapshot.id)
+            .bind(&saved.session_id)
+            .bind(bookmark.id.to_string())
+            .bind(bookmark.line as i64)
+            .bind(self.cipher.seal_opt(bookmark.note.as_deref())?)
+            .bind(self.cipher.seal(&bookmark.text)?)
+            .bind(bookmark.created_at.timestamp())
+            .execute(&*self.db)
+            .await
+            .context("Failed to insert snapshot bookmark")?;
+        }
+
         info!("Created snapshot: {} for workspace {}", snapshot.name, workspace_id);
         Ok(snapshot)
     }
 
+    /// Save a snapshot on behalf of the scheduler, subject to retention
+    pub async fn save_automatic_snapshot(&self, workspace_id: &str) -> Result<WorkspaceSnapshot> {
+        let name = format!("Automatic {}", Utc::now().format("%Y-%m-%d %H:%M"));
+        let snapshot = self.save_snapshot(workspace_id, name).await?;
+
+        sqlx::query("INSERT INTO workspace_auto_snapshots (snapshot_id) VALUES (?)")
+            .bind(&snapshot.id)
+            .execute(&*self.db)
+            .await
+            .context("Failed to mark automatic snapshot")?;
+
+        Ok(snapshot)
+    }
+
+    /// Automatic snapshots of a workspace as (id, created_at), newest first
+    pub async fn list_automatic_snapshots(
+        &self,
+        workspace_id: &str,
+    ) -> Result<Vec<(String, DateTime<Utc>)>> {
+        let rows = sqlx::query(
+            r#"
+            SELECT s.id, s.created_at
+            FROM workspace_snapshots s
+            JOIN workspace_auto_snapshots a ON a.snapshot_id = s.id
+            WHERE s.workspace_id = ?
+            ORDER BY s.created_at DESC
+            "#,
+        )
+        .bind(workspace_id)
+        .fetch_all(&*self.db)
+        .await
+        .context("Failed to list automatic snapshots")?;
+
+        Ok(rows
+            .iter()
+            .map(|row| {
+                let created_at_ts: i64 = row.get("created_at");
+                (row.get("id"), Utc.timestamp_opt(created_at_ts, 0).unwrap())
+            })
+            .collect())
+    }
+
+    /// Delete a snapshot
+    pub async fn delete_snapshot(&self, snapshot_id: &str) -> Result<bool> {
+        let result = sqlx::query("DELETE FROM workspace_snapshots WHERE id = ?")
+            .bind(snapshot_id)
+            .execute(&*self.db)
+            .await
+            .context("Failed to delete snapshot")?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// Delete every snapshot taken before `cutoff`; returns how many were removed
+    pub async fn delete_snapshots_before(&self, cutoff: DateTime<Utc>) -> Result<u64> {
+        let result = sqlx::query("DELETE FROM workspace_snapshots WHERE created_at < ?")
+            .bind(cutoff.timestamp())
+            .execute(&*self.db)
+            .await
+            .context("Failed to delete expired snapshots")?;
+
+        Ok(result.rows_affected())
+    }
+
+    /// Disk usage of snapshots and the database
+    pub async fn snapshot_usage(&self) -> Result<SnapshotUsage> {
+        let row = sqlx::query(
+            r#"
+            SELECT COUNT(*) AS snapshot_count,
+                   COALESCE(SUM(LENGTH(layout)), 0) AS layout_bytes,
+                   (SELECT COUNT(*) FROM workspace_auto_snapshots) AS automatic_count,
+                   (SELECT COALESCE(SUM(LENGTH(session_config)), 0) FROM workspace_snapshot_sessions) AS session_bytes
+            FROM workspace_snapshots
+            "#,
+        )
+        .fetch_one(&*self.db)
+        .await
+        .context("Failed to measure snapshots")?;
+
+        let pages = sqlx::query(
+            r#"
+            SELECT p.page_count * s.page_size AS database_bytes,
+                   f.freelist_count * s.page_size AS reclaimable_bytes
+            FROM pragma_page_count() p, pragma_page_size() s, pragma_freelist_count() f
+            "#,
+        )
+        .fetch_one(&*self.db)
+        .await
+        .context("Failed to measure database")?;
+
+        let snapshot_count: i64 = row.get("snapshot_count");
+        let automatic_count: i64 = row.get("automatic_count");
+        let layout_bytes: i64 = row.get("layout_bytes");
+        let session_bytes: i64 = row.get("session_bytes");
+        let database_bytes: i64 = pages.get("database_bytes");
+        let reclaimable_bytes: i64 = pages.get("reclaimable_bytes");
+
+        Ok(SnapshotUsage {
+            snapshot_count: snapshot_count as usize,
+            automatic_count: automatic_count as usize,
+            snapshot_bytes: (layout_bytes + session_bytes) as u64,
+            database_bytes: database_bytes as u64,
+            reclaimable_bytes: reclaimable_bytes as u64,
+        })
+    }
+
+    /// Give space freed by deleted rows back to the filesystem
+    pub async fn compact(&self) -> Result<()> {
+        sqlx::raw_sql("PRAGMA optimize; VACUUM;")
+            .execute(&*self.db)
+            .await
+            .context("Failed to compact workspace database")?;
+
+        info!("Compacted workspace database");
+        Ok(())
+    }
+
     /// List snapshots for a workspace
     pub async fn list_snapshots(&self, workspace_id: &str) -> Result<Vec<WorkspaceSnapshot>> {
         let rows = sqlx::query(
@@ -316,9 +527,12 @@ impl WorkspaceService {
             let layout: WorkspaceLayout = serde_json::from_str(&layout_json)?;
 
             let created_at_ts: i64 = row.get("created_at");
+            let id: String = row.get("id");
 
             snapshots.push(WorkspaceSnapshot {
-                id: row.get("id"),
+                sessions: self.get_snapshot_sessions(&id).await?,
+                bookmarks: self.get_snapshot_bookmarks(&id).await?,
+                id,
                 workspace_id: row.get("workspace_id"),
                 name: row.get("name"),
                 layout,
@@ -330,9 +544,53 @@ impl WorkspaceService {
         Ok(snapshots)
     }
 
-    /// Restore a workspace from a snapshot
-    pub async fn restore_snapshot(&self, snapshot_id: &str) -> Result<Option<Workspace>> {
-        // Fetch snapshot
+    /// List snapshot metadata (pane and session counts, stored size) for a workspace
+    pub async fn list_snapshot_summaries(&self, workspace_id: &str) -> Result<Vec<SnapshotSummary>> {
+        let rows = sqlx::query(
+            r#"
+            SELECT s.id, s.workspace_id, s.name, s.layout, s.created_at,
+                   COUNT(ss.session_id) AS session_count,
+                   LENGTH(s.layout) + COALESCE(SUM(LENGTH(ss.session_config)), 0) AS size_bytes,
+                   a.snapshot_id IS NOT NULL AS automatic
+            FROM workspace_snapshots s
+            LEFT JOIN workspace_snapshot_sessions ss ON ss.snapshot_id = s.id
+            LEFT JOIN workspace_auto_snapshots a ON a.snapshot_id = s.id
+            WHERE s.workspace_id = ?
+            GROUP BY s.id
+            ORDER BY s.created_at DESC
+            "#,
+        )
+        .bind(workspace_id)
+        .fetch_all(&*self.db)
+        .await
+        .context("Failed to list workspace snapshots")?;
+
+        let mut summaries = Vec::new();
+        for row in rows {
+            let layout_json: String = row.get("layout");
+            let layout: WorkspaceLayout = serde_json::from_str(&layout_json)?;
+
+            let created_at_ts: i64 = row.get("created_at");
+            let session_count: i64 = row.get("session_count");
+            let size_bytes: i64 = row.get("size_bytes");
+
+            summaries.push(SnapshotSummary {
+                id: row.get("id"),
+                workspace_id: row.get("workspace_id"),
+                name: row.get("name"),
+                created_at: Utc.timestamp_opt(created_at_ts, 0).unwrap(),
+                pane_count: LayoutTree::from_layout(&layout).panes().len(),
+                session_count: session_count as usize,
+                size_bytes: size_bytes as u64,
+                automatic: row.get("automatic"),
+            });
+        }
+
+        Ok(summaries)
+    }
+
+    /// Get a snapshot with its session mappings
+    pub async fn get_snapshot(&self, snapshot_id: &str) -> Result<Option<WorkspaceSnapshot>> {
         let row = sqlx::query(
             r#"
             SELECT id, workspace_id, name, layout, created_at
@@ -354,29 +612,264 @@ impl WorkspaceService {
 
         let created_at_ts: i64 = row.get("created_at");
 
-        let snapshot = WorkspaceSnapshot {
+        Ok(Some(WorkspaceSnapshot {
             id: row.get("id"),
             workspace_id: row.get("workspace_id"),
             name: row.get("name"),
             layout,
+            sessions: self.get_snapshot_sessions(snapshot_id).await?,
+            bookmarks: self.get_snapshot_bookmarks(snapshot_id).await?,
             created_at: Utc.timestamp_opt(created_at_ts, 0).unwrap(),
+        }))
+    }
+
+    /// Preview what restoring a snapshot would change
+    pub async fn diff_snapshot(&self, snapshot_id: &str) -> Result<Option<SnapshotDiff>> {
+        let Some(snapshot) = self.get_snapshot(snapshot_id).await? else {
+            return Ok(None);
+        };
+        let Some(workspace) = self.get_workspace(&snapshot.workspace_id).await? else {
+            return Ok(None);
+        };
+        let sessions = self.get_workspace_sessions(&workspace.id).await?;
+
+        Ok(Some(SnapshotDiff::between(&snapshot, &workspace.layout, &sessions)))
+    }
+
+    /// Restore a workspace from a snapshot
+    ///
+    /// Session mappings are replaced as well when the snapshot captured any
+    /// (snapshots taken before sessions were recorded only restore the layout).
+    pub async fn restore_snapshot(&self, snapshot_id: &str) -> Result<Option<Workspace>> {
+        let Some(snapshot) = self.get_snapshot(snapshot_id).await? else {
+            return Ok(None);
+        };
+
+        let Some(mut workspace) = self.get_workspace(&snapshot.workspace_id).await? else {
+            return Ok(None);
+        };
+        if let Some(tree) = &snapshot.layout.tree {
+            tree.validate().context("Invalid workspace layout")?;
+        }
+        workspace.layout = snapshot.layout;
+
+        // Layout and sessions are replaced together, so a failure part way
+        // leaves the workspace as it was
+        let mut tx = self.db.begin().await.context("Failed to start transaction")?;
+        Self::write_workspace(&mut *tx, &workspace).await?;
+
+        if !snapshot.sessions.is_empty() {
+            sqlx::query("DELETE FROM workspace_sessions WHERE workspace_id = ?")
+                .bind(&snapshot.workspace_id)
+                .execute(&mut *tx)
+                .await
+                .context("Failed to clear workspace sessions")?;
+
+            for session in &snapshot.sessions {
+                self.insert_session(
+                    &mut *tx,
+                    &snapshot.workspace_id,
+                    &session.session_id,
+                    &session.pane_id,
+                    session.position,
+                    session.session_config.as_ref(),
+                )
+                .await?;
+            }
+        }
+
+        tx.commit().await.context("Failed to commit snapshot restore")?;
+
+        if !snapshot.sessions.is_empty() {
+            self.restore_bookmarks(&snapshot.bookmarks).await;
+        }
+
+        info!("Restored workspace {} from snapshot {}", snapshot.workspace_id, snapshot_id);
+        Ok(Some(workspace))
+    }
+
+    /// Restore only the selected panes and sessions from a snapshot
+    ///
+    /// Everything not selected keeps its current state.
+    pub async fn restore_snapshot_selection(
+        &self,
+        snapshot_id: &str,
+        selection: RestoreSelection,
+    ) -> Result<Option<Workspace>> {
+        if selection.pane_ids.is_empty() && selection.session_ids.is_empty() {
+            anyhow::bail!("Nothing selected to restore");
+        }
+
+        let Some(snapshot) = self.get_snapshot(snapshot_id).await? else {
+            return Ok(None);
         };
+        let Some(workspace) = self.get_workspace(&snapshot.workspace_id).await? else {
+            return Ok(None);
+        };
+
+        let snapshot_tree = LayoutTree::from_layout(&snapshot.layout);
+        let snapshot_panes = snapshot_tree.panes();
+
+        let mut panes = Vec::new();
+        for pane_id in &selection.pane_ids {
+            let pane = snapshot_panes
+                .iter()
+                .find(|p| &p.id == pane_id)
+                .with_context(|| format!("Pane {} is not in snapshot {}", pane_id, snapshot_id))?;
+            panes.push(*pane);
+        }
+        for session_id in &selection.session_ids {
+            if !snapshot.sessions.iter().any(|s| &s.session_id == session_id) {
+                anyhow::bail!("Session {} is not in snapshot {}", session_id, snapshot_id);
+            }
+        }
+
+        let sessions: Vec<_> = snapshot
+            .sessions
+            .iter()
+            .filter(|s| {
+                selection.session_ids.contains(&s.session_id) || selection.pane_ids.contains(&s.pane_id)
+            })
+            .collect();
+
+        // A restored session brings back the binding of the pane it was in
+        for session in &sessions {
+            if let Some(pane) = snapshot_panes.iter().find(|p| p.id == session.pane_id) {
+                if !panes.iter().any(|p| p.id == pane.id) {
+                    panes.push(*pane);
+                }
+            }
+        }
+
+        let mut tree = LayoutTree::from_layout(&workspace.layout);
+        for pane in panes {
+            tree.restore_pane(pane).context("Failed to restore pane")?;
+        }
 
-        // Update workspace with snapshot layout
         let update_req = UpdateWorkspaceRequest {
             name: None,
             description: None,
             icon: None,
-            layout: Some(snapshot.layout),
+            layout: Some(tree.to_layout()),
             tags: None,
         };
-
         let workspace = self.update_workspace(&snapshot.workspace_id, update_req).await?;
 
-        info!("Restored workspace {} from snapshot {}", snapshot.workspace_id, snapshot_id);
+        let bookmarks: Vec<_> = snapshot
+            .bookmarks
+            .iter()
+            .filter(|b| sessions.iter().any(|s| s.session_id == b.session_id))
+            .cloned()
+            .collect();
+        self.restore_bookmarks(&bookmarks).await;
+
+        for session in sessions {
+            self.add_session(
+                &snapshot.workspace_id,
+                &session.session_id,
+                &session.pane_id,
+                session.position,
+                session.session_config.clone(),
+            )
+            .await?;
+        }
+
+        info!(
+            "Restored {} panes and {} sessions of workspace {} from snapshot {}",
+            selection.pane_ids.len(),
+            selection.session_ids.len(),
+            snapshot.workspace_id,
+            snapshot_id
+        );
         Ok(workspace)
     }
 
+    /// Session mappings captured by a snapshot
+    async fn get_snapshot_sessions(&self, snapshot_id: &str) -> Result<Vec<WorkspaceSession>> {
+        let rows = sqlx::query(
+            r#"
+            SELECT s.workspace_id, ss.session_id, ss.pane_id, ss.position, ss.session_config
+            FROM workspace_snapshot_sessions ss
+            JOIN workspace_snapshots s ON s.id = ss.snapshot_id
+            WHERE ss.snapshot_id = ?
+            ORDER BY ss.position ASC
+            "#,
+        )
+        .bind(snapshot_id)
+        .fetch_all(&*self.db)
+        .await
+        .context("Failed to fetch snapshot sessions")?;
+
+        rows.iter().map(|row| session_from_row(row, &self.cipher)).collect()
+    }
+
+    /// Bookmarks captured by a snapshot
+    async fn get_snapshot_bookmarks(&self, snapshot_id: &str) -> Result<Vec<SnapshotBookmark>> {
+        let rows = sqlx::query(
+            r#"
+            SELECT session_id, bookmark_id, line, note, text, created_at
+            FROM workspace_snapshot_bookmarks
+            WHERE snapshot_id = ?
+            ORDER BY session_id, line ASC
+            "#,
+        )
+        .bind(snapshot_id)
+        .fetch_all(&*self.db)
+        .await
+        .context("Failed to fetch snapshot bookmarks")?;
+
+        rows.iter().map(|row| bookmark_from_row(row, &self.cipher)).collect()
+    }
+
+    /// Current bookmarks of those sessions that are running
+    async fn live_bookmarks(&self, sessions: &[WorkspaceSession]) -> Vec<SnapshotBookmark> {
+        let Some(manager) = &self.sessions else {
+            return Vec::new();
+        };
+
+        let mut saved = Vec::new();
+        for session in sessions {
+            let Ok(id) = Uuid::parse_str(&session.session_id) else {
+                continue;
+            };
+            // Sessions that have ended have nothing to save
+            if let Ok(bookmarks) = manager.list_bookmarks(id).await {
+                saved.extend(bookmarks.into_iter().map(|bookmark| SnapshotBookmark {
+                    session_id: session.session_id.clone(),
+                    bookmark,
+                }));
+            }
+        }
+        saved
+    }
+
+    /// Give bookmarks back to the sessions that are still running
+    async fn restore_bookmarks(&self, saved: &[SnapshotBookmark]) {
+        let Some(manager) = &self.sessions else {
+            return;
+        };
+
+        let mut by_session: BTreeMap<&str, Vec<Bookmark>> = BTreeMap::new();
+        for bookmark in saved {
+            by_session
+                .entry(&bookmark.session_id)
+                .or_default()
+                .push(bookmark.bookmark.clone());
+        }
+        for (session_id, bookmarks) in by_session {
+            let Ok(id) = Uuid::parse_str(session_id) else {
+                continue;
+            };
+            match manager.restore_bookmarks(id, bookmarks).await {
+                Ok(restored) if restored > 0 => {
+                    debug!("Restored {} bookmarks in session {}", restored, session_id)
+                }
+                Ok(_) => {}
+                Err(e) => debug!("Not restoring bookmarks of session {}: {}", session_id, e),
+            }
+        }
+    }
+
     /// Add session to workspace
     pub async fn add_session(
         &self,
@@ -386,10 +879,33 @@ impl WorkspaceService {
         position: i32,
         session_config: Option<SessionConfig>,
     ) -> Result<()> {
-        let config_json = session_config
-            .map(|c| serde_json::to_string(&c))
-            .transpose()
-            .context("Failed to serialize session config")?;
+        self.insert_session(
+            &*self.db,
+            workspace_id,
+            session_id,
+            pane_id,
+            position,
+            session_config.as_ref(),
+        )
+        .await?;
+
+        debug!("Added session {} to workspace {} at pane {}", session_id, workspace_id, pane_id);
+        Ok(())
+    }
+
+    async fn insert_session<'e, E>(
+        &self,
+        executor: E,
+        workspace_id: &str,
+        session_id: &str,
+        pane_id: &str,
+        position: i32,
+        session_config: Option<&SessionConfig>,
+    ) -> Result<()>
+    where
+        E: Executor<'e, Database = Sqlite>,
+    {
+        let config_json = self.seal_config(session_config)?;
 
         sqlx::query(
             r#"
@@ -402,11 +918,10 @@ impl WorkspaceService {
         .bind(pane_id)
         .bind(position)
         .bind(config_json)
-        .execute(&*self.db)
+        .execute(executor)
         .await
         .context("Failed to add session to workspace")?;
 
-        debug!("Added session {} to workspace {} at pane {}", session_id, workspace_id, pane_id);
         Ok(())
     }
 
@@ -425,22 +940,7 @@ impl WorkspaceService {
         .await
         .context("Failed to fetch workspace sessions")?;
 
-        let mut sessions = Vec::new();
-        for row in rows {
-            let config_json: Option<String> = row.get("session_config");
-            let session_config: Option<SessionConfig> = config_json
-                .as_ref()
-                .map(|j| serde_json::from_str(j.as_str()))
-                .transpose()?;
-
-            sessions.push(WorkspaceSession {
-                workspace_id: row.get("workspace_id"),
-                session_id: row.get("session_id"),
-                pane_id: row.get("pane_id"),
-                position: row.get("position"),
-                session_config,
-            });
-        }
+        let sessions = rows.iter().map(|row| session_from_row(row, &self.cipher)).collect::<Result<Vec<_>>>()?;
 
         debug!("Fetched {} sessions for workspace {}", sessions.len(), workspace_id);
         Ok(sessions)
@@ -465,6 +965,221 @@ impl WorkspaceService {
         Ok(removed)
     }
 
+    /// Get a workspace's layout as a typed tree, migrating legacy layouts
+    pub async fn get_layout_tree(&self, workspace_id: &str) -> Result<Option<LayoutTree>> {
+        Ok(self
+            .get_workspace(workspace_id)
+            .await?
+            .map(|w| LayoutTree::from_layout(&w.layout)))
+    }
+
+    /// Apply a layout edit (split, close, move, ...) and persist the result
+    pub async fn apply_layout_op(&self, workspace_id: &str, op: LayoutOp) -> Result<Option<Workspace>> {
+        let Some(mut tree) = self.get_layout_tree(workspace_id).await? else {
+            return Ok(None);
+        };
+
+        debug!("Applying layout op to workspace {}: {:?}", workspace_id, op);
+        tree.apply(op).context("Invalid layout operation")?;
+
+        self.update_workspace(
+            workspace_id,
+            UpdateWorkspaceRequest {
+                name: None,
+                description: None,
+                icon: None,
+                layout: Some(tree.to_layout()),
+                tags: None,
+            },
+        )
+        .await
+    }
+
+    /// Export saved SSH hosts as an OpenSSH config or Ansible inventory
+    pub async fn export_inventory(
+        &self,
+        filter: InventoryFilter,
+        format: InventoryFormat,
+    ) -> Result<String> {
+        let workspaces = self.list_workspaces(WorkspaceFilter::default()).await?;
+
+        let mut hosts = Vec::new();
+        let mut seen = BTreeMap::new();
+        for workspace in workspaces.iter().filter(|w| !w.is_template && filter.matches(w)) {
+            let sessions = self.get_workspace_sessions(&workspace.id).await?;
+            hosts.extend(inventory::collect_hosts(workspace, &sessions, &mut seen));
+        }
+
+        info!("Exporting {} hosts as {:?}", hosts.len(), format);
+        inventory::render(&hosts, format)
+    }
+
+    /// Saved SSH hosts merged with read-only `team` hosts
+    pub async fn list_hosts(&self, team: Vec<HostProfile>) -> Result<Vec<DirectoryHost>> {
+        let workspaces = self.list_workspaces(WorkspaceFilter::default()).await?;
+
+        let mut local = Vec::new();
+        let mut seen = BTreeMap::new();
+        for workspace in workspaces.iter().filter(|w| !w.is_template) {
+            let sessions = self.get_workspace_sessions(&workspace.id).await?;
+            local.extend(inventory::collect_hosts(workspace, &sessions, &mut seen));
+        }
+
+        Ok(inventory::merge_team_hosts(team, local))
+    }
+
+    /// Reconcile declared workspaces to `declaration`, or only report what
+    /// that would change when `dry_run` is set
+    pub async fn apply_declaration(&self, declaration: &Declaration, dry_run: bool) -> Result<ApplyReport> {
+        let desired = declaration.plan()?;
+        let current: Vec<Workspace> = self
+            .list_workspaces(WorkspaceFilter::default())
+            .await?
+            .into_iter()
+            .filter(|w| declarative::is_managed(&w.id))
+            .collect();
+
+        let mut report = ApplyReport {
+            dry_run,
+            changes: Vec::new(),
+            unused_hosts: declaration.unused_hosts(),
+        };
+        for want in &desired {
+            let existing = current.iter().find(|w| w.id == want.id);
+            let sessions = match existing {
+                Some(_) => self.get_workspace_sessions(&want.id).await?,
+                None => Vec::new(),
+            };
+            let Some(change) = declarative::diff(existing.map(|w| (w, sessions.as_slice())), want) else {
+                continue;
+            };
+            if !dry_run {
+                self.put_declared(want, &change.sessions_removed).await?;
+            }
+            report.changes.push(change);
+        }
+        for stale in current.iter().filter(|w| !desired.iter().any(|d| d.id == w.id)) {
+            if !dry_run {
+                self.delete_workspace(&stale.id).await?;
+            }
+            report.changes.push(declarative::removal(stale));
+        }
+
+        info!(
+            "Applied declaration: {} workspaces changed{}",
+            report.changes.len(),
+            if dry_run { " (dry run)" } else { "" }
+        );
+        Ok(report)
+    }
+
+    /// Save hosts in bulk as a new workspace named `name`, with a tab for
+    /// each host
+    pub async fn import_hosts(
+        &self,
+        name: &str,
+        hosts: &BTreeMap<String, HostSpec>,
+        tags: Vec<String>,
+    ) -> Result<Workspace> {
+        let desired = declarative::host_tabs(&Uuid::new_v4().to_string(), name, hosts, tags)?;
+        self.put_declared(&desired, &[]).await?;
+        info!("Imported {} hosts into workspace {}", hosts.len(), name);
+
+        self.get_workspace(&desired.id)
+            .await?
+            .context("Imported workspace is missing")
+    }
+
+    /// Write a planned workspace and its sessions, keeping its creation time
+    async fn put_declared(&self, desired: &DesiredWorkspace, stale_sessions: &[String]) -> Result<()> {
+        let layout_json = serde_json::to_string(&desired.tree.to_layout())
+            .context("Failed to serialize workspace layout")?;
+        let tags_json = desired
+            .tags
+            .as_ref()
+            .map(serde_json::to_string)
+            .transpose()
+            .context("Failed to serialize tags")?;
+        let now = Utc::now().timestamp();
+
+        sqlx::query(
+            r#"
+            INSERT INTO workspaces (id, name, description, icon, layout, created_at, updated_at, is_template, tags)
+            VALUES (?, ?, ?, ?, ?, ?, ?, 0, ?)
+            ON CONFLICT(id) DO UPDATE SET
+                name = excluded.name, description = excluded.description, icon = excluded.icon,
+                layout = excluded.layout, updated_at = excluded.updated_at,
+                is_template = excluded.is_template, tags = excluded.tags
+            "#,
+        )
+        .bind(&desired.id)
+        .bind(&desired.name)
+        .bind(&desired.description)
+        .bind(&desired.icon)
+        .bind(&layout_json)
+        .bind(now)
+        .bind(now)
+        .bind(tags_json)
+        .execute(&*self.db)
+        .await
+        .context("Failed to write declared workspace")?;
+
+        for session_id in stale_sessions {
+            self.remove_session(&desired.id, session_id).await?;
+        }
+        for session in &desired.sessions {
+            self.add_session(
+                &desired.id,
+                &session.session_id,
+                &session.pane_id,
+                session.position,
+                session.session_config.clone(),
+            )
+            .await?;
+        }
+
+        info!("Applied declared workspace: {} ({})", desired.name, desired.id);
+        Ok(())
+    }
+
+    /// Serialize a session config for storage, sealing it when encryption is on
+    fn seal_config(&self, config: Option<&SessionConfig>) -> Result<Option<String>> {
+        let json = config
+            .map(serde_json::to_string)
+            .transpose()
+            .context("Failed to serialize session config")?;
+        Ok(self.cipher.seal_opt(json.as_deref())?)
+    }
+
+    /// Encrypt session configs stored before encryption was enabled
+    async fn seal_plaintext_configs(&self) -> Result<()> {
+        let mut sealed = 0;
+        for table in ["workspace_sessions", "workspace_snapshot_sessions"] {
+            let rows: Vec<(i64, String)> = sqlx::query_as(&format!(
+                "SELECT rowid, session_config FROM {} WHERE session_config IS NOT NULL",
+                table
+            ))
+            .fetch_all(&*self.db)
+            .await
+            .context("Failed to read session configs")?;
+
+            for (rowid, config) in rows.into_iter().filter(|(_, c)| !FieldCipher::is_sealed(c)) {
+                sqlx::query(&format!("UPDATE {} SET session_config = ? WHERE rowid = ?", table))
+                    .bind(self.cipher.seal(&config)?)
+                    .bind(rowid)
+                    .execute(&*self.db)
+                    .await
+                    .context("Failed to encrypt session config")?;
+                sealed += 1;
+            }
+        }
+
+        if sealed > 0 {
+            info!("Encrypted {} session configs stored in plaintext", sealed);
+        }
+        Ok(())
+    }
+
     /// Get workspace count
     pub async fn count_workspaces(&self, is_template: Option<bool>) -> Result<i64> {
         let count: (i64,) = if let Some(template) = is_template {
@@ -482,6 +1197,44 @@ impl WorkspaceService {
     }
 }
 
+fn session_from_row(row: &SqliteRow, cipher: &FieldCipher) -> Result<WorkspaceSession> {
+    let stored: Option<String> = row.get("session_config");
+    let session_config: Option<SessionConfig> = cipher
+        .open_opt(stored.as_deref())
+        .context("Failed to decrypt session config")?
+        .map(|j| serde_json::from_str(&j))
+        .transpose()?;
+
+    Ok(WorkspaceSession {
+        workspace_id: row.get("workspace_id"),
+        session_id: row.get("session_id"),
+        pane_id: row.get("pane_id"),
+        position: row.get("position"),
+        session_config,
+    })
+}
+
+fn bookmark_from_row(row: &SqliteRow, cipher: &FieldCipher) -> Result<SnapshotBookmark> {
+    let bookmark_id: String = row.get("bookmark_id");
+    let line: i64 = row.get("line");
+    let note: Option<String> = row.get("note");
+    let text: String = row.get("text");
+    let created_at_ts: i64 = row.get("created_at");
+
+    Ok(SnapshotBookmark {
+        session_id: row.get("session_id"),
+        bookmark: Bookmark {
+            id: Uuid::parse_str(&bookmark_id).context("Invalid bookmark id")?,
+            line: line as u64,
+            note: cipher
+                .open_opt(note.as_deref())
+                .context("Failed to decrypt bookmark note")?,
+            text: cipher.open(&text).context("Failed to decrypt bookmark")?,
+            created_at: Utc.timestamp_opt(created_at_ts, 0).unwrap(),
+        },
+    })
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -666,4 +1419,408 @@ mod tests {
         assert!(snapshots.iter().any(|s| s.id == snap1.id));
         assert!(snapshots.iter().any(|s| s.id == snap2.id));
     }
+
+    #[tokio::test]
+    async fn test_export_inventory() {
+        let db = setup_test_db().await;
+        let service = WorkspaceService::new(db);
+        service.initialize().await.expect("Failed to initialize");
+
+        let req = CreateWorkspaceRequest {
+            name: "Prod".to_string(),
+            description: None,
+            icon: None,
+            layout: WorkspaceLayout::default(),
+            is_template: false,
+            tags: Some(vec!["prod".to_string()]),
+        };
+        let workspace = service.create_workspace(req).await.expect("Failed to create workspace");
+
+        let ssh = SessionConfig {
+            session_type: "ssh".to_string(),
+            name: "web".to_string(),
+            host: Some("10.0.0.5".to_string()),
+            port: Some(22),
+            username: Some("ops".to_string()),
+            credential: None,
+        };
+        service
+            .add_session(&workspace.id, "s1", "pane-1", 0, Some(ssh))
+            .await
+            .expect("Failed to add session");
+
+        let config = service
+            .export_inventory(InventoryFilter::default(), InventoryFormat::SshConfig)
+            .await
+            .expect("Failed to export inventory");
+        assert!(config.contains("Host web"));
+        assert!(config.contains("HostName 10.0.0.5"));
+
+        let filtered = service
+            .export_inventory(
+                InventoryFilter {
+                    tags: Some(vec!["staging".to_string()]),
+                    workspace_ids: None,
+                },
+                InventoryFormat::SshConfig,
+            )
+            .await
+            .expect("Failed to export inventory");
+        assert!(!filtered.contains("Host web"));
+    }
+
+    #[tokio::test]
+    async fn test_session_configs_encrypted_at_rest() {
+        let db = setup_test_db().await;
+        let plain = WorkspaceService::new(Arc::clone(&db));
+        plain.initialize().await.expect("Failed to initialize");
+
+        let workspace = plain
+            .create_workspace(CreateWorkspaceRequest {
+                name: "Secret".to_string(),
+                description: None,
+                icon: None,
+                layout: WorkspaceLayout::default(),
+                is_template: false,
+                tags: None,
+            })
+            .await
+            .unwrap();
+        let pane_id = workspace.layout.panes[0].id.clone();
+        let ssh = SessionConfig {
+            session_type: "ssh".to_string(),
+            name: "db".to_string(),
+            host: Some("db.internal".to_string()),
+            port: Some(22),
+            username: Some("admin".to_string()),
+            credential: None,
+        };
+        plain
+            .add_session(&workspace.id, "legacy", &pane_id, 0, Some(ssh.clone()))
+            .await
+            .unwrap();
+
+        // Enabling encryption seals rows written in plaintext
+        let sealed = WorkspaceService::new(Arc::clone(&db)).with_cipher(FieldCipher::from_key(&[3u8; 32]));
+        sealed.initialize().await.expect("Failed to initialize");
+        sealed
+            .add_session(&workspace.id, "new", &pane_id, 1, Some(ssh.clone()))
+            .await
+            .unwrap();
+        sealed.save_snapshot(&workspace.id, "Sealed".to_string()).await.unwrap();
+
+        let stored: Vec<(String,)> = sqlx::query_as(
+            "SELECT session_config FROM workspace_sessions UNION ALL SELECT session_config FROM workspace_snapshot_sessions",
+        )
+        .fetch_all(&*db)
+        .await
+        .unwrap();
+        assert_eq!(stored.len(), 4);
+        assert!(stored.iter().all(|(c,)| !c.contains("db.internal")));
+
+        let sessions = sealed.get_workspace_sessions(&workspace.id).await.unwrap();
+        assert!(sessions.iter().all(|s| s.session_config.as_ref() == Some(&ssh)));
+
+        // Without the key the configs cannot be read
+        assert!(plain.get_workspace_sessions(&workspace.id).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_snapshot_keeps_bookmarks() {
+        let db = setup_test_db().await;
+        let manager = Arc::new(SessionManager::new());
+        let service = WorkspaceService::new(Arc::clone(&db))
+            .with_cipher(FieldCipher::from_key(&[5u8; 32]))
+            .with_session_manager(Arc::clone(&manager));
+        service.initialize().await.expect("Failed to initialize");
+
+        let workspace = service
+            .create_workspace(CreateWorkspaceRequest {
+                name: "Build".to_string(),
+                description: None,
+                icon: None,
+                layout: WorkspaceLayout::default(),
+                is_template: false,
+                tags: None,
+            })
+            .await
+            .unwrap();
+        let pane_id = workspace.layout.panes[0].id.clone();
+
+        let id = manager
+            .create_session(
+                "build".to_string(),
+                crate::session_manager::SessionType::Local,
+                terminal_core::SessionConfig::new("build".to_string()),
+            )
+            .await
+            .unwrap();
+        manager
+            .get_session(id)
+            .await
+            .unwrap()
+            .scrollback
+            .write()
+            .await
+            .feed(b"error: linker `cc` not found\r\n");
+        let bookmark = manager
+            .add_bookmark(id, Some(0), Some("missing toolchain".to_string()))
+            .await
+            .unwrap();
+        service
+            .add_session(&workspace.id, &id.to_string(), &pane_id, 0, None)
+            .await
+            .unwrap();
+
+        let snapshot = service.save_snapshot(&workspace.id, "Failed build".to_string()).await.unwrap();
+        assert_eq!(snapshot.bookmarks.len(), 1);
+
+        // Stored sealed, read back whole
+        let (text,): (String,) = sqlx::query_as("SELECT text FROM workspace_snapshot_bookmarks")
+            .fetch_one(&*db)
+            .await
+            .unwrap();
+        assert!(!text.contains("linker"));
+        let saved = service.get_snapshot(&snapshot.id).await.unwrap().unwrap();
+        assert_eq!(saved.bookmarks[0].session_id, id.to_string());
+        assert_eq!(saved.bookmarks[0].bookmark.note, bookmark.note);
+        assert_eq!(saved.bookmarks[0].bookmark.text, bookmark.text);
+
+        manager.remove_bookmark(id, bookmark.id).await.unwrap();
+        service.restore_snapshot(&snapshot.id).await.unwrap();
+        let restored = manager.list_bookmarks(id).await.unwrap();
+        assert_eq!(restored.len(), 1);
+        assert_eq!(restored[0].id, bookmark.id);
+
+        assert!(service.delete_snapshot(&snapshot.id).await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_apply_layout_op_persists_tree() {
+        let db = setup_test_db().await;
+        let service = WorkspaceService::new(db);
+        service.initialize().await.expect("Failed to initialize");
+
+        let workspace = service
+            .create_workspace(CreateWorkspaceRequest {
+                name: "Layout".to_string(),
+                description: None,
+                icon: None,
+                layout: WorkspaceLayout::default(),
+                is_template: false,
+                tags: None,
+            })
+            .await
+            .unwrap();
+        let pane_id = workspace.layout.panes[0].id.clone();
+
+        let updated = service
+            .apply_layout_op(
+                &workspace.id,
+                LayoutOp::Split {
+                    pane_id: pane_id.clone(),
+                    direction: crate::workspace::SplitDirection::Vertical,
+                    session_id: Some("s-1".to_string()),
+                },
+            )
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(updated.layout.layout_type, "split");
+
+        let tree = service.get_layout_tree(&workspace.id).await.unwrap().unwrap();
+        assert_eq!(tree.panes().len(), 2);
+        assert_eq!(tree.panes()[0].id, pane_id);
+
+        assert!(service
+            .apply_layout_op(&workspace.id, LayoutOp::Close { pane_id: "missing".to_string() })
+            .await
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn test_snapshot_diff_and_selective_restore() {
+        let db = setup_test_db().await;
+        let service = WorkspaceService::new(db);
+        service.initialize().await.expect("Failed to initialize");
+
+        let workspace = service
+            .create_workspace(CreateWorkspaceRequest {
+                name: "Restore".to_string(),
+                description: None,
+                icon: None,
+                layout: WorkspaceLayout::default(),
+                is_template: false,
+                tags: None,
+            })
+            .await
+            .unwrap();
+        let first = workspace.layout.panes[0].id.clone();
+
+        let split = service
+            .apply_layout_op(
+                &workspace.id,
+                LayoutOp::Split {
+                    pane_id: first.clone(),
+                    direction: crate::workspace::SplitDirection::Horizontal,
+                    session_id: Some("logs".to_string()),
+                },
+            )
+            .await
+            .unwrap()
+            .unwrap();
+        let second = split.layout.tree.as_ref().unwrap().panes()[1].id.clone();
+        service.add_session(&workspace.id, "logs", &second, 0, None).await.unwrap();
+        service.add_session(&workspace.id, "shell", &first, 1, None).await.unwrap();
+
+        let snapshot = service
+            .save_snapshot(&workspace.id, "Two panes".to_string())
+            .await
+            .unwrap();
+        assert_eq!(snapshot.sessions.len(), 2);
+
+        let summaries = service.list_snapshot_summaries(&workspace.id).await.unwrap();
+        assert_eq!(summaries[0].pane_count, 2);
+        assert_eq!(summaries[0].session_count, 2);
+        assert!(summaries[0].size_bytes > 0);
+
+        // Close the logs pane and drop both sessions
+        service
+            .apply_layout_op(&workspace.id, LayoutOp::Close { pane_id: second.clone() })
+            .await
+            .unwrap();
+        service.remove_session(&workspace.id, "logs").await.unwrap();
+        service.remove_session(&workspace.id, "shell").await.unwrap();
+
+        let diff = service.diff_snapshot(&snapshot.id).await.unwrap().unwrap();
+        assert!(diff.layout_changed);
+        assert_eq!(diff.panes_added, vec![second.clone()]);
+        assert_eq!(diff.sessions_added.len(), 2);
+
+        // Bring back only the logs session; its pane comes back with it
+        let restored = service
+            .restore_snapshot_selection(
+                &snapshot.id,
+                RestoreSelection {
+                    pane_ids: vec![],
+                    session_ids: vec!["logs".to_string()],
+                },
+            )
+            .await
+            .unwrap()
+            .unwrap();
+        let panes = restored.layout.tree.as_ref().unwrap().panes().len();
+        assert_eq!(panes, 2);
+
+        let sessions = service.get_workspace_sessions(&workspace.id).await.unwrap();
+        assert_eq!(sessions.len(), 1);
+        assert_eq!(sessions[0].pane_id, second);
+
+        let diff = service.diff_snapshot(&snapshot.id).await.unwrap().unwrap();
+        assert!(diff.panes_added.is_empty());
+        assert_eq!(diff.sessions_added, vec!["shell".to_string()]);
+
+        assert!(service
+            .restore_snapshot_selection(&snapshot.id, RestoreSelection::default())
+            .await
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn test_import_hosts() {
+        let db = setup_test_db().await;
+        let service = WorkspaceService::new(db);
+        service.initialize().await.expect("Failed to initialize");
+
+        let hosts: BTreeMap<String, HostSpec> = serde_yaml::from_str(
+            r#"
+web-1: { host: 10.0.0.5, username: deploy }
+web-2: { host: 10.0.0.6, username: deploy }
+"#,
+        )
+        .unwrap();
+        let workspace = service.import_hosts("Discovered", &hosts, Vec::new()).await.unwrap();
+        assert_eq!(workspace.name, "Discovered");
+        assert!(!declarative::is_managed(&workspace.id));
+
+        let profiles = service
+            .export_inventory(InventoryFilter::default(), InventoryFormat::SshConfig)
+            .await
+            .unwrap();
+        assert!(profiles.contains("HostName 10.0.0.6"), "{}", profiles);
+        assert!(service.import_hosts("Empty", &BTreeMap::new(), Vec::new()).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_apply_declaration_is_idempotent() {
+        let db = setup_test_db().await;
+        let service = WorkspaceService::new(db);
+        service.initialize().await.expect("Failed to initialize");
+        let manual = service
+            .create_workspace(CreateWorkspaceRequest {
+                name: "Manual".to_string(),
+                description: None,
+                icon: None,
+                layout: WorkspaceLayout::default(),
+                is_template: false,
+                tags: None,
+            })
+            .await
+            .unwrap();
+
+        let v1 = Declaration::parse(
+            r#"
+hosts:
+  web: { host: web.internal, credential: "keychain:web" }
+workspaces:
+  Web:
+    layout:
+      split: horizontal
+      children: [{ pane: web }, { pane: local }]
+  Scratch: {}
+"#,
+        )
+        .unwrap();
+
+        let dry = service.apply_declaration(&v1, true).await.unwrap();
+        assert_eq!(dry.changes.len(), 2);
+        assert_eq!(service.count_workspaces(None).await.unwrap(), 1);
+
+        let report = service.apply_declaration(&v1, false).await.unwrap();
+        assert!(report.changes.iter().all(|c| c.action == declarative::ChangeAction::Create));
+        let sessions = service.get_workspace_sessions("declared-web").await.unwrap();
+        assert_eq!(sessions.len(), 2);
+        assert_eq!(
+            sessions[0].session_config.as_ref().unwrap().credential.as_deref(),
+            Some("keychain:web")
+        );
+
+        // Applying the same file again changes nothing
+        let again = service.apply_declaration(&v1, false).await.unwrap();
+        assert!(again.changes.is_empty(), "{:?}", again.changes);
+
+        let v2 = Declaration::parse(
+            r#"
+hosts:
+  web: { host: web.internal, port: 2222 }
+workspaces:
+  Web:
+    description: Web tier
+    layout: { pane: web }
+"#,
+        )
+        .unwrap();
+        let report = service.apply_declaration(&v2, false).await.unwrap();
+        assert_eq!(report.changes.len(), 2);
+        let web = &report.changes[0];
+        assert_eq!(web.action, declarative::ChangeAction::Update);
+        assert_eq!(web.fields, vec!["description".to_string(), "layout".to_string()]);
+        assert_eq!(web.sessions_added, vec!["declared-web-session-1".to_string()]);
+        assert_eq!(web.sessions_removed.len(), 2);
+        assert_eq!(report.changes[1].action, declarative::ChangeAction::Delete);
+
+        assert!(service.get_workspace("declared-scratch").await.unwrap().is_none());
+        assert!(service.get_workspace(&manual.id).await.unwrap().is_some());
+        assert!(service.apply_declaration(&v2, false).await.unwrap().changes.is_empty());
+    }
 }
@@ -0,0 +1,760 @@
+//! Typed Workspace Layout Tree
+//!
+//! `WorkspaceLayout` is stored as a loosely structured JSON blob shared with
+//! the frontend. This module defines the tree the daemon actually reasons
+//! about (splits, tabs and panes bound to sessions), validates it, migrates
+//! legacy blobs into it, and applies layout edits server-side. The tree is
+//! stored alongside the legacy fields, which are regenerated from it so
+//! older frontends keep working.
+
+use super::models::{PaneConfig, WorkspaceLayout};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use thiserror::Error;
+
+/// Layout version written once a workspace carries a typed tree
+pub const LAYOUT_VERSION: &str = "2.0.0";
+
+/// Tolerance when checking that split sizes add up to 100%
+const SIZE_EPSILON: f32 = 0.5;
+
+#[derive(Debug, Error, PartialEq)]
+pub enum LayoutError {
+    #[error("Duplicate node ID: {0}")]
+    DuplicateId(String),
+
+    #[error("Pane not found: {0}")]
+    PaneNotFound(String),
+
+    #[error("Split not found: {0}")]
+    SplitNotFound(String),
+
+    #[error("Split {0} must have at least two children")]
+    DegenerateSplit(String),
+
+    #[error("Split {id} has {sizes} sizes for {children} children")]
+    SizeMismatch {
+        id: String,
+        sizes: usize,
+        children: usize,
+    },
+
+    #[error("Split {0} sizes must be positive and add up to 100")]
+    InvalidSizes(String),
+
+    #[error("Tab group {0} is empty or its active tab is out of range")]
+    InvalidTabs(String),
+
+    #[error("Cannot close the last pane")]
+    LastPane,
+
+    #[error("Cannot move a pane relative to itself")]
+    InvalidMove,
+}
+
+/// Orientation of a split
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SplitDirection {
+    /// Children side by side
+    Horizontal,
+    /// Children stacked
+    Vertical,
+}
+
+impl SplitDirection {
+    fn as_str(self) -> &'static str {
+        match self {
+            SplitDirection::Horizontal => "horizontal",
+            SplitDirection::Vertical => "vertical",
+        }
+    }
+
+    fn parse(value: Option<&str>) -> Self {
+        match value {
+            Some("vertical") => SplitDirection::Vertical,
+            _ => SplitDirection::Horizontal,
+        }
+    }
+}
+
+/// A leaf hosting (at most) one session
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PaneNode {
+    pub id: String,
+    pub session_id: Option<String>,
+}
+
+impl PaneNode {
+    pub fn new(session_id: Option<String>) -> Self {
+        Self {
+            id: format!("pane-{}", uuid::Uuid::new_v4()),
+            session_id,
+        }
+    }
+}
+
+/// Children laid out side by side or stacked
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SplitNode {
+    pub id: String,
+    pub direction: SplitDirection,
+    pub children: Vec<LayoutNode>,
+    /// Percentage of the split given to each child
+    pub sizes: Vec<f32>,
+}
+
+/// Children stacked as tabs, one visible at a time
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TabsNode {
+    pub id: String,
+    pub tabs: Vec<LayoutNode>,
+    pub active: usize,
+}
+
+/// A node of the layout tree
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum LayoutNode {
+    Pane(PaneNode),
+    Split(SplitNode),
+    Tabs(TabsNode),
+}
+
+/// Root of a workspace layout
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LayoutTree {
+    pub root: LayoutNode,
+    pub active_pane: Option<String>,
+}
+
+/// A server-side layout edit
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "op", rename_all = "snake_case")]
+pub enum LayoutOp {
+    /// Split a pane, placing a new pane after it
+    Split {
+        pane_id: String,
+        direction: SplitDirection,
+        session_id: Option<String>,
+    },
+    /// Open a new pane as a tab next to an existing one
+    AddTab {
+        pane_id: String,
+        session_id: Option<String>,
+    },
+    /// Remove a pane, collapsing containers left with a single child
+    Close { pane_id: String },
+    /// Detach a pane and split it in next to another pane
+    Move {
+        pane_id: String,
+        target_pane_id: String,
+        direction: SplitDirection,
+    },
+    /// Set the sizes of a split's children
+    Resize { split_id: String, sizes: Vec<f32> },
+    /// Bind a session to a pane (or clear it)
+    BindSession {
+        pane_id: String,
+        session_id: Option<String>,
+    },
+    /// Make a pane the active one
+    Focus { pane_id: String },
+}
+
+impl LayoutNode {
+    pub fn id(&self) -> &str {
+        match self {
+            LayoutNode::Pane(p) => &p.id,
+            LayoutNode::Split(s) => &s.id,
+            LayoutNode::Tabs(t) => &t.id,
+        }
+    }
+
+    fn children(&self) -> &[LayoutNode] {
+        match self {
+            LayoutNode::Pane(_) => &[],
+            LayoutNode::Split(s) => &s.children,
+            LayoutNode::Tabs(t) => &t.tabs,
+        }
+    }
+
+    fn children_mut(&mut self) -> &mut [LayoutNode] {
+        match self {
+            LayoutNode::Pane(_) => &mut [],
+            LayoutNode::Split(s) => &mut s.children,
+            LayoutNode::Tabs(t) => &mut t.tabs,
+        }
+    }
+
+    fn collect_panes<'a>(&'a self, out: &mut Vec<&'a PaneNode>) {
+        match self {
+            LayoutNode::Pane(p) => out.push(p),
+            other => other.children().iter().for_each(|c| c.collect_panes(out)),
+        }
+    }
+
+    fn find_pane_mut(&mut self, id: &str) -> Option<&mut PaneNode> {
+        match self {
+            LayoutNode::Pane(p) => (p.id == id).then_some(p),
+            LayoutNode::Split(s) => s.children.iter_mut().find_map(|c| c.find_pane_mut(id)),
+            LayoutNode::Tabs(t) => t.tabs.iter_mut().find_map(|c| c.find_pane_mut(id)),
+        }
+    }
+
+    fn find_split_mut(&mut self, id: &str) -> Option<&mut SplitNode> {
+        if matches!(self, LayoutNode::Split(s) if s.id == id) {
+            let LayoutNode::Split(s) = self else { unreachable!() };
+            return Some(s);
+        }
+        self.children_mut().iter_mut().find_map(|c| c.find_split_mut(id))
+    }
+
+    fn clear_sessions(&mut self) {
+        match self {
+            LayoutNode::Pane(p) => p.session_id = None,
+            other => other.children_mut().iter_mut().for_each(LayoutNode::clear_sessions),
+        }
+    }
+
+    /// Insert `new` next to pane `pane_id` along `direction`
+    fn split_at(&mut self, pane_id: &str, direction: SplitDirection, new: &mut Option<LayoutNode>) -> bool {
+        match self {
+            LayoutNode::Pane(p) if p.id == pane_id => {
+                let old = std::mem::replace(self, LayoutNode::Pane(PaneNode::new(None)));
+                *self = LayoutNode::Split(SplitNode {
+                    id: format!("split-{}", uuid::Uuid::new_v4()),
+                    direction,
+                    children: vec![old, new.take().expect("node inserted once")],
+                    sizes: vec![50.0, 50.0],
+                });
+                true
+            }
+            LayoutNode::Pane(_) => false,
+            LayoutNode::Split(s) => {
+                // Extend an existing split in the same direction instead of nesting
+                if s.direction == direction {
+                    if let Some(i) = s.children.iter().position(|c| matches!(c, LayoutNode::Pane(p) if p.id == pane_id)) {
+                        let half = s.sizes[i] / 2.0;
+                        s.sizes[i] = half;
+                        s.children.insert(i + 1, new.take().expect("node inserted once"));
+                        s.sizes.insert(i + 1, half);
+                        return true;
+                    }
+                }
+                s.children.iter_mut().any(|c| c.split_at(pane_id, direction, new))
+            }
+            LayoutNode::Tabs(t) => t.tabs.iter_mut().any(|c| c.split_at(pane_id, direction, new)),
+        }
+    }
+
+    /// Open `new` as a tab beside pane `pane_id`
+    fn tab_at(&mut self, pane_id: &str, new: &mut Option<LayoutNode>) -> bool {
+        match self {
+            LayoutNode::Pane(p) if p.id == pane_id => {
+                let old = std::mem::replace(self, LayoutNode::Pane(PaneNode::new(None)));
+                *self = LayoutNode::Tabs(TabsNode {
+                    id: format!("tabs-{}", uuid::Uuid::new_v4()),
+                    tabs: vec![old, new.take().expect("node inserted once")],
+                    active: 1,
+                });
+                true
+            }
+            LayoutNode::Pane(_) => false,
+            LayoutNode::Tabs(t) => {
+                if let Some(i) = t.tabs.iter().position(|c| matches!(c, LayoutNode::Pane(p) if p.id == pane_id)) {
+                    t.tabs.insert(i + 1, new.take().expect("node inserted once"));
+                    t.active = i + 1;
+                    return true;
+                }
+                t.tabs.iter_mut().any(|c| c.tab_at(pane_id, new))
+            }
+            LayoutNode::Split(s) => s.children.iter_mut().any(|c| c.tab_at(pane_id, new)),
+        }
+    }
+
+    /// Detach the descendant with `id`, giving its space to its siblings
+    fn remove(&mut self, id: &str) -> Option<LayoutNode> {
+        match self {
+            LayoutNode::Pane(_) => None,
+            LayoutNode::Split(s) => {
+                if let Some(i) = s.children.iter().position(|c| c.id() == id) {
+                    let removed = s.children.remove(i);
+                    s.sizes.remove(i);
+                    let total: f32 = s.sizes.iter().sum();
+                    if total > 0.0 {
+                        s.sizes.iter_mut().for_each(|size| *size *= 100.0 / total);
+                    }
+                    return Some(removed);
+                }
+                s.children.iter_mut().find_map(|c| c.remove(id))
+            }
+            LayoutNode::Tabs(t) => {
+                if let Some(i) = t.tabs.iter().position(|c| c.id() == id) {
+                    let removed = t.tabs.remove(i);
+                    if i < t.active || t.active >= t.tabs.len() {
+                        t.active = t.active.saturating_sub(1);
+                    }
+                    return Some(removed);
+                }
+                t.tabs.iter_mut().find_map(|c| c.remove(id))
+            }
+        }
+    }
+
+    /// Replace containers left with a single child by that child
+    fn collapse(&mut self) {
+        self.children_mut().iter_mut().for_each(LayoutNode::collapse);
+
+        let only_child = match self {
+            LayoutNode::Split(s) if s.children.len() == 1 => s.children.pop(),
+            LayoutNode::Tabs(t) if t.tabs.len() == 1 => t.tabs.pop(),
+            _ => None,
+        };
+        if let Some(child) = only_child {
+            *self = child;
+        }
+    }
+
+    fn validate(&self, seen: &mut HashSet<String>) -> Result<(), LayoutError> {
+        if !seen.insert(self.id().to_string()) {
+            return Err(LayoutError::DuplicateId(self.id().to_string()));
+        }
+
+        match self {
+            LayoutNode::Pane(_) => {}
+            LayoutNode::Split(s) => {
+                if s.children.len() < 2 {
+                    return Err(LayoutError::DegenerateSplit(s.id.clone()));
+                }
+                if s.sizes.len() != s.children.len() {
+                    return Err(LayoutError::SizeMismatch {
+                        id: s.id.clone(),
+                        sizes: s.sizes.len(),
+                        children: s.children.len(),
+                    });
+                }
+                validate_sizes(&s.id, &s.sizes)?;
+            }
+            LayoutNode::Tabs(t) => {
+                if t.tabs.is_empty() || t.active >= t.tabs.len() {
+                    return Err(LayoutError::InvalidTabs(t.id.clone()));
+                }
+            }
+        }
+
+        self.children().iter().try_for_each(|c| c.validate(seen))
+    }
+
+    fn from_legacy(pane: &PaneConfig) -> Self {
+        match pane.children.as_deref() {
+            Some([only]) => Self::from_legacy(only),
+            Some(children) if !children.is_empty() => LayoutNode::Split(SplitNode {
+                id: pane.id.clone(),
+                direction: SplitDirection::parse(pane.direction.as_deref()),
+                children: children.iter().map(Self::from_legacy).collect(),
+                sizes: normalize_sizes(children.iter().map(|c| c.size).collect()),
+            }),
+            _ => LayoutNode::Pane(PaneNode {
+                id: pane.id.clone(),
+                session_id: pane.session_id.clone(),
+            }),
+        }
+    }
+
+    fn to_legacy(&self, size: f32) -> PaneConfig {
+        match self {
+            LayoutNode::Pane(p) => PaneConfig {
+                id: p.id.clone(),
+                session_id: p.session_id.clone(),
+                size,
+                ..PaneConfig::default()
+            },
+            LayoutNode::Split(s) => PaneConfig {
+                id: s.id.clone(),
+                session_id: None,
+                size,
+                direction: Some(s.direction.as_str().to_string()),
+                children: Some(
+                    s.children
+                        .iter()
+                        .zip(&s.sizes)
+                        .map(|(c, size)| c.to_legacy(*size))
+                        .collect(),
+                ),
+                ..PaneConfig::default()
+            },
+            // Legacy layouts have no tabs; show the active one
+            LayoutNode::Tabs(t) => t.tabs[t.active].to_legacy(size),
+        }
+    }
+}
+
+impl LayoutTree {
+    /// A single empty pane
+    pub fn single() -> Self {
+        let pane = PaneNode::new(None);
+        Self {
+            active_pane: Some(pane.id.clone()),
+            root: LayoutNode::Pane(pane),
+        }
+    }
+
+    /// All panes in layout order
+    pub fn panes(&self) -> Vec<&PaneNode> {
+        let mut panes = Vec::new();
+        self.root.collect_panes(&mut panes);
+        panes
+    }
+
+    /// Check structural invariants
+    pub fn validate(&self) -> Result<(), LayoutError> {
+        self.root.validate(&mut HashSet::new())?;
+
+        if let Some(active) = &self.active_pane {
+            if !self.panes().iter().any(|p| &p.id == active) {
+                return Err(LayoutError::PaneNotFound(active.clone()));
+            }
+        }
+        Ok(())
+    }
+
+    /// Apply an edit; the tree is left untouched if the edit fails
+    pub fn apply(&mut self, op: LayoutOp) -> Result<(), LayoutError> {
+        let mut next = self.clone();
+        next.apply_unchecked(op)?;
+        next.root.collapse();
+        next.validate()?;
+        *self = next;
+        Ok(())
+    }
+
+    fn apply_unchecked(&mut self, op: LayoutOp) -> Result<(), LayoutError> {
+        match op {
+            LayoutOp::Split {
+                pane_id,
+                direction,
+                session_id,
+            } => {
+                let pane = PaneNode::new(session_id);
+                let new_id = pane.id.clone();
+                if !self.root.split_at(&pane_id, direction, &mut Some(LayoutNode::Pane(pane))) {
+                    return Err(LayoutError::PaneNotFound(pane_id));
+                }
+                self.active_pane = Some(new_id);
+            }
+            LayoutOp::AddTab { pane_id, session_id } => {
+                let pane = PaneNode::new(session_id);
+                let new_id = pane.id.clone();
+                if !self.root.tab_at(&pane_id, &mut Some(LayoutNode::Pane(pane))) {
+                    return Err(LayoutError::PaneNotFound(pane_id));
+                }
+                self.active_pane = Some(new_id);
+            }
+            LayoutOp::Close { pane_id } => {
+                self.detach_pane(&pane_id)?;
+                if self.active_pane.as_deref() == Some(pane_id.as_str()) {
+                    self.active_pane = self.first_pane_id();
+                }
+            }
+            LayoutOp::Move {
+                pane_id,
+                target_pane_id,
+                direction,
+            } => {
+                if pane_id == target_pane_id {
+                    return Err(LayoutError::InvalidMove);
+                }
+                let pane = self.detach_pane(&pane_id)?;
+                self.root.collapse();
+                if !self.root.split_at(&target_pane_id, direction, &mut Some(pane)) {
+                    return Err(LayoutError::PaneNotFound(target_pane_id));
+                }
+            }
+            LayoutOp::Resize { split_id, sizes } => {
+                let split = self
+                    .root
+                    .find_split_mut(&split_id)
+                    .ok_or_else(|| LayoutError::SplitNotFound(split_id.clone()))?;
+                if sizes.len() != split.children.len() {
+                    return Err(LayoutError::SizeMismatch {
+                        id: split_id,
+                        sizes: sizes.len(),
+                        children: split.children.len(),
+                    });
+                }
+                validate_sizes(&split_id, &sizes)?;
+                split.sizes = sizes;
+            }
+            LayoutOp::BindSession { pane_id, session_id } => {
+                self.root
+                    .find_pane_mut(&pane_id)
+                    .ok_or(LayoutError::PaneNotFound(pane_id))?
+                    .session_id = session_id;
+            }
+            LayoutOp::Focus { pane_id } => {
+                if self.root.find_pane_mut(&pane_id).is_none() {
+                    return Err(LayoutError::PaneNotFound(pane_id));
+                }
+                self.active_pane = Some(pane_id);
+            }
+        }
+        Ok(())
+    }
+
+    fn detach_pane(&mut self, pane_id: &str) -> Result<LayoutNode, LayoutError> {
+        if self.root.find_pane_mut(pane_id).is_none() {
+            return Err(LayoutError::PaneNotFound(pane_id.to_string()));
+        }
+        if self.root.id() == pane_id {
+            return Err(LayoutError::LastPane);
+        }
+        self.root
+            .remove(pane_id)
+            .ok_or_else(|| LayoutError::PaneNotFound(pane_id.to_string()))
+    }
+
+    fn first_pane_id(&self) -> Option<String> {
+        self.panes().first().map(|p| p.id.clone())
+    }
+
+    /// Build a tree from a layout, migrating legacy blobs
+    pub fn from_layout(layout: &WorkspaceLayout) -> Self {
+        if let Some(tree) = &layout.tree {
+            return tree.clone();
+        }
+
+        let root = match layout.panes.as_slice() {
+            [] => return Self::single(),
+            [only] => LayoutNode::from_legacy(only),
+            panes => LayoutNode::Split(SplitNode {
+                id: format!("split-{}", uuid::Uuid::new_v4()),
+                direction: SplitDirection::parse(panes[0].direction.as_deref()),
+                children: panes.iter().map(LayoutNode::from_legacy).collect(),
+                sizes: normalize_sizes(panes.iter().map(|p| p.size).collect()),
+            }),
+        };
+
+        let mut tree = Self {
+            root,
+            active_pane: layout.active_pane.clone(),
+        };
+        if tree.validate().is_err() {
+            tree.active_pane = tree.first_pane_id();
+        }
+        tree
+    }
+
+    /// Store the tree in a layout, regenerating the legacy fields from it
+    pub fn to_layout(&self) -> WorkspaceLayout {
+        let layout_type = match self.root {
+            LayoutNode::Pane(_) => "single",
+            _ => "split",
+        };
+
+        WorkspaceLayout {
+            version: LAYOUT_VERSION.to_string(),
+            layout_type: layout_type.to_string(),
+            panes: vec![self.root.to_legacy(100.0)],
+            active_pane: self.active_pane.clone(),
+            tree: Some(self.clone()),
+        }
+    }
+
+    /// Whether two trees have the same splits, tabs and panes, ignoring
+    /// which sessions the panes are bound to
+    pub fn same_shape(&self, other: &LayoutTree) -> bool {
+        let (mut a, mut b) = (self.root.clone(), other.root.clone());
+        a.clear_sessions();
+        b.clear_sessions();
+        a == b
+    }
+
+    /// Bring a pane from another version of this layout back
+    ///
+    /// A pane that still exists gets its session binding back; a missing
+    /// pane is split in after the last pane, keeping its original ID.
+    pub fn restore_pane(&mut self, pane: &PaneNode) -> Result<(), LayoutError> {
+        if let Some(existing) = self.root.find_pane_mut(&pane.id) {
+            existing.session_id = pane.session_id.clone();
+            return Ok(());
+        }
+
+        let mut next = self.clone();
+        let last = next
+            .panes()
+            .last()
+            .map(|p| p.id.clone())
+            .ok_or_else(|| LayoutError::PaneNotFound(pane.id.clone()))?;
+        next.root.split_at(
+            &last,
+            SplitDirection::Horizontal,
+            &mut Some(LayoutNode::Pane(pane.clone())),
+        );
+        next.validate()?;
+        *self = next;
+        Ok(())
+    }
+}
+
+fn validate_sizes(id: &str, sizes: &[f32]) -> Result<(), LayoutError> {
+    let total: f32 = sizes.iter().sum();
+    if sizes.iter().any(|s| !s.is_finite() || *s <= 0.0) || (total - 100.0).abs() > SIZE_EPSILON {
+        return Err(LayoutError::InvalidSizes(id.to_string()));
+    }
+    Ok(())
+}
+
+/// Scale legacy sizes to add up to 100, falling back to equal shares
+fn normalize_sizes(sizes: Vec<f32>) -> Vec<f32> {
+    let total: f32 = sizes.iter().sum();
+    if sizes.iter().all(|s| s.is_finite() && *s > 0.0) && total > 0.0 {
+        sizes.iter().map(|s| s * 100.0 / total).collect()
+    } else {
+        vec![100.0 / sizes.len() as f32; sizes.len()]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pane_id(tree: &LayoutTree, index: usize) -> String {
+        tree.panes()[index].id.clone()
+    }
+
+    #[test]
+    fn test_split_and_close_roundtrip() {
+        let mut tree = LayoutTree::single();
+        let first = pane_id(&tree, 0);
+
+        tree.apply(LayoutOp::Split {
+            pane_id: first.clone(),
+            direction: SplitDirection::Horizontal,
+            session_id: Some("s-2".to_string()),
+        })
+        .unwrap();
+        assert_eq!(tree.panes().len(), 2);
+
+        // Splitting again in the same direction extends the split
+        let second = pane_id(&tree, 1);
+        tree.apply(LayoutOp::Split {
+            pane_id: second.clone(),
+            direction: SplitDirection::Horizontal,
+            session_id: None,
+        })
+        .unwrap();
+        match &tree.root {
+            LayoutNode::Split(s) => assert_eq!(s.sizes, vec![50.0, 25.0, 25.0]),
+            other => panic!("expected split, got {:?}", other),
+        }
+
+        tree.apply(LayoutOp::Close { pane_id: second }).unwrap();
+        tree.apply(LayoutOp::Close { pane_id: pane_id(&tree, 1) }).unwrap();
+        assert!(matches!(tree.root, LayoutNode::Pane(_)));
+        assert_eq!(tree.active_pane, Some(first.clone()));
+
+        assert_eq!(
+            tree.apply(LayoutOp::Close { pane_id: first }),
+            Err(LayoutError::LastPane)
+        );
+    }
+
+    #[test]
+    fn test_move_and_tabs() {
+        let mut tree = LayoutTree::single();
+        let a = pane_id(&tree, 0);
+        tree.apply(LayoutOp::Split {
+            pane_id: a.clone(),
+            direction: SplitDirection::Horizontal,
+            session_id: None,
+        })
+        .unwrap();
+        let b = pane_id(&tree, 1);
+
+        tree.apply(LayoutOp::AddTab {
+            pane_id: b.clone(),
+            session_id: Some("logs".to_string()),
+        })
+        .unwrap();
+        let c = tree.active_pane.clone().unwrap();
+
+        tree.apply(LayoutOp::Move {
+            pane_id: c.clone(),
+            target_pane_id: a.clone(),
+            direction: SplitDirection::Vertical,
+        })
+        .unwrap();
+
+        // The tab group collapsed back into pane b once c moved out
+        let ids: Vec<_> = tree.panes().iter().map(|p| p.id.clone()).collect();
+        assert_eq!(ids, vec![a, c, b]);
+        tree.validate().unwrap();
+    }
+
+    #[test]
+    fn test_failed_op_leaves_tree_untouched() {
+        let mut tree = LayoutTree::single();
+        let before = tree.clone();
+
+        assert!(tree
+            .apply(LayoutOp::Resize {
+                split_id: "missing".to_string(),
+                sizes: vec![50.0, 50.0],
+            })
+            .is_err());
+        assert!(tree
+            .apply(LayoutOp::Focus {
+                pane_id: "missing".to_string(),
+            })
+            .is_err());
+        assert_eq!(tree, before);
+    }
+
+    #[test]
+    fn test_migrates_legacy_layout() {
+        let legacy = WorkspaceLayout {
+            version: "1.0.0".to_string(),
+            layout_type: "split".to_string(),
+            panes: vec![
+                PaneConfig {
+                    id: "left".to_string(),
+                    session_id: Some("s-1".to_string()),
+                    size: 30.0,
+                    ..PaneConfig::default()
+                },
+                PaneConfig {
+                    id: "right".to_string(),
+                    size: 70.0,
+                    direction: Some("vertical".to_string()),
+                    children: Some(vec![
+                        PaneConfig {
+                            id: "top".to_string(),
+                            size: 0.0,
+                            ..PaneConfig::default()
+                        },
+                        PaneConfig {
+                            id: "bottom".to_string(),
+                            size: 0.0,
+                            ..PaneConfig::default()
+                        },
+                    ]),
+                    ..PaneConfig::default()
+                },
+            ],
+            active_pane: Some("gone".to_string()),
+            tree: None,
+        };
+
+        let tree = LayoutTree::from_layout(&legacy);
+        tree.validate().unwrap();
+        assert_eq!(tree.active_pane.as_deref(), Some("left"));
+
+        let ids: Vec<_> = tree.panes().iter().map(|p| p.id.as_str()).collect();
+        assert_eq!(ids, vec!["left", "top", "bottom"]);
+
+        let layout = tree.to_layout();
+        assert_eq!(layout.version, LAYOUT_VERSION);
+        assert_eq!(LayoutTree::from_layout(&layout), tree);
+    }
+}
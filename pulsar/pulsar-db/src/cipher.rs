@@ -0,0 +1,266 @@
+//! Field-level encryption at rest
+//!
+//! Columns holding command history or host details are sealed with
+//! AES-256-GCM before they are written. Sealed values are text
+//! (`enc1:` + base64 of nonce and ciphertext), so schemas stay unchanged
+//! and rows written before encryption was enabled still read back as-is.
+//! Sealed columns can't be matched in SQL; a column looked up by value is
+//! sealed beside a keyed hash of it ([`FieldCipher::lookup_key`]), and the
+//! lookup matches the hash instead.
+
+use aes_gcm::aead::{Aead, AeadCore, KeyInit, OsRng};
+use aes_gcm::{Aes256Gcm, Key, Nonce};
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine as _};
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+
+use crate::{DbError, Result};
+
+/// Prefix marking a sealed value
+const SEALED_PREFIX: &str = "enc1:";
+
+/// Prefix marking a lookup key computed under a database key
+const LOOKUP_PREFIX: &str = "hmac1:";
+
+/// Derives the lookup-key secret from the database key, so the encryption
+/// key itself is never used for hashing
+const LOOKUP_LABEL: &[u8] = b"pulsar-db lookup key";
+
+const NONCE_SIZE: usize = 12;
+
+/// Length of a database key in bytes
+pub const KEY_LEN: usize = 32;
+
+/// Seals and opens individual column values
+///
+/// A disabled cipher passes values through, so stores can hold one
+/// unconditionally and let configuration decide whether data is sealed.
+#[derive(Clone)]
+pub struct FieldCipher {
+    cipher: Option<Aes256Gcm>,
+    lookup: Option<[u8; KEY_LEN]>,
+}
+
+impl FieldCipher {
+    /// A cipher that stores values in plaintext
+    pub fn disabled() -> Self {
+        Self {
+            cipher: None,
+            lookup: None,
+        }
+    }
+
+    /// Seal values with an explicit key (e.g. one held in the vault)
+    pub fn from_key(key: &[u8; KEY_LEN]) -> Self {
+        Self {
+            cipher: Some(Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(key))),
+            lookup: Some(hmac_sha256(key, LOOKUP_LABEL)),
+        }
+    }
+
+    /// Seal values with a key kept in the OS keychain, creating it on first use
+    pub fn from_keychain(service: &str, account: &str) -> Result<Self> {
+        let entry = keychain_entry(service, account)?;
+
+        let key = match entry.get_password() {
+            Ok(encoded) => decode_key(&encoded)?,
+            Err(keyring::Error::NoEntry) => {
+                let key: [u8; KEY_LEN] = Aes256Gcm::generate_key(OsRng).into();
+                entry
+                    .set_password(&BASE64.encode(key))
+                    .map_err(|e| DbError::Key(e.to_string()))?;
+                tracing::info!("Created database key {}/{} in the OS keychain", service, account);
+                key
+            }
+            Err(e) => return Err(DbError::Key(e.to_string())),
+        };
+
+        Ok(Self::from_key(&key))
+    }
+
+    /// Use the keychain key if one was created, without creating it
+    ///
+    /// For readers (exports, diagnostics) that must not turn encryption on.
+    pub fn from_existing_keychain(service: &str, account: &str) -> Result<Option<Self>> {
+        match keychain_entry(service, account)?.get_password() {
+            Ok(encoded) => Ok(Some(Self::from_key(&decode_key(&encoded)?))),
+            Err(keyring::Error::NoEntry) => Ok(None),
+            Err(e) => Err(DbError::Key(e.to_string())),
+        }
+    }
+
+    /// Whether values are actually sealed
+    pub fn is_enabled(&self) -> bool {
+        self.cipher.is_some()
+    }
+
+    /// Whether a stored value is sealed
+    pub fn is_sealed(stored: &str) -> bool {
+        stored.starts_with(SEALED_PREFIX)
+    }
+
+    /// Whether a stored lookup key was computed under a database key
+    pub fn is_keyed_lookup(stored: &str) -> bool {
+        stored.starts_with(LOOKUP_PREFIX)
+    }
+
+    /// Seal a value for storage (unchanged when disabled)
+    pub fn seal(&self, value: &str) -> Result<String> {
+        match &self.cipher {
+            Some(cipher) => encrypt(cipher, value.as_bytes()),
+            None => Ok(value.to_string()),
+        }
+    }
+
+    /// Seal binary data, e.g. an embedding, for a BLOB column (unchanged
+    /// when disabled)
+    pub fn seal_bytes(&self, value: &[u8]) -> Result<Vec<u8>> {
+        match &self.cipher {
+            Some(cipher) => Ok(encrypt(cipher, value)?.into_bytes()),
+            None => Ok(value.to_vec()),
+        }
+    }
+
+    /// The value a sealed column is looked up by: an HMAC-SHA256 of `value`
+    /// under the database key, the same for equal values (unchanged when
+    /// disabled)
+    pub fn lookup_key(&self, value: &str) -> String {
+        match &self.lookup {
+            Some(key) => format!("{}{}", LOOKUP_PREFIX, BASE64.encode(hmac_sha256(key, value.as_bytes()))),
+            None => value.to_string(),
+        }
+    }
+
+    /// Seal an optional value, keeping `None` as SQL `NULL`
+    pub fn seal_opt(&self, value: Option<&str>) -> Result<Option<String>> {
+        value.map(|v| self.seal(v)).transpose()
+    }
+
+    /// Open a stored value; plaintext written before sealing was enabled
+    /// is returned unchanged
+    pub fn open(&self, stored: &str) -> Result<String> {
+        let Some(encoded) = stored.strip_prefix(SEALED_PREFIX) else {
+            return Ok(stored.to_string());
+        };
+        let plaintext = self.decrypt(encoded)?;
+        String::from_utf8(plaintext).map_err(|_| DbError::Crypto("decrypted value is not UTF-8".to_string()))
+    }
+
+    /// Open binary data stored with [`FieldCipher::seal_bytes`]; data
+    /// written before sealing was enabled is returned unchanged
+    pub fn open_bytes(&self, stored: &[u8]) -> Result<Vec<u8>> {
+        let Some(encoded) = stored.strip_prefix(SEALED_PREFIX.as_bytes()) else {
+            return Ok(stored.to_vec());
+        };
+        let encoded =
+            std::str::from_utf8(encoded).map_err(|_| DbError::Crypto("malformed encrypted value".to_string()))?;
+        self.decrypt(encoded)
+    }
+
+    /// Decrypt the base64 text following the sealed-value prefix
+    fn decrypt(&self, encoded: &str) -> Result<Vec<u8>> {
+        let Some(cipher) = &self.cipher else {
+            return Err(DbError::Crypto("value is encrypted but no key is configured".to_string()));
+        };
+
+        let data = BASE64
+            .decode(encoded)
+            .map_err(|_| DbError::Crypto("malformed encrypted value".to_string()))?;
+        if data.len() < NONCE_SIZE {
+            return Err(DbError::Crypto("malformed encrypted value".to_string()));
+        }
+
+        let (nonce, ciphertext) = data.split_at(NONCE_SIZE);
+        cipher
+            .decrypt(Nonce::from_slice(nonce), ciphertext)
+            .map_err(|_| DbError::Crypto("decryption failed (wrong key or corrupted value)".to_string()))
+    }
+
+    /// Open an optional stored value
+    pub fn open_opt(&self, stored: Option<&str>) -> Result<Option<String>> {
+        stored.map(|v| self.open(v)).transpose()
+    }
+}
+
+impl std::fmt::Debug for FieldCipher {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("FieldCipher")
+            .field("enabled", &self.is_enabled())
+            .finish()
+    }
+}
+
+/// `value` sealed under `cipher` with a fresh nonce
+fn encrypt(cipher: &Aes256Gcm, value: &[u8]) -> Result<String> {
+    let nonce = Aes256Gcm::generate_nonce(OsRng);
+    let ciphertext = cipher
+        .encrypt(&nonce, value)
+        .map_err(|_| DbError::Crypto("encryption failed".to_string()))?;
+
+    let mut sealed = nonce.to_vec();
+    sealed.extend_from_slice(&ciphertext);
+    Ok(format!("{}{}", SEALED_PREFIX, BASE64.encode(sealed)))
+}
+
+fn hmac_sha256(key: &[u8], data: &[u8]) -> [u8; KEY_LEN] {
+    let mut mac = <Hmac<Sha256> as Mac>::new_from_slice(key).expect("HMAC accepts keys of any length");
+    mac.update(data);
+    mac.finalize().into_bytes().into()
+}
+
+fn keychain_entry(service: &str, account: &str) -> Result<keyring::Entry> {
+    keyring::Entry::new(service, account).map_err(|e| DbError::Key(e.to_string()))
+}
+
+fn decode_key(encoded: &str) -> Result<[u8; KEY_LEN]> {
+    BASE64
+        .decode(encoded.trim())
+        .ok()
+        .and_then(|bytes| <[u8; KEY_LEN]>::try_from(bytes).ok())
+        .ok_or_else(|| DbError::Key("stored database key is malformed".to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_seal_roundtrip_and_legacy_plaintext() {
+        let cipher = FieldCipher::from_key(&[7u8; KEY_LEN]);
+
+        let sealed = cipher.seal("ssh deploy@10.0.0.5").unwrap();
+        assert!(sealed.starts_with(SEALED_PREFIX));
+        assert!(!sealed.contains("10.0.0.5"));
+        assert_ne!(sealed, cipher.seal("ssh deploy@10.0.0.5").unwrap());
+        assert_eq!(cipher.open(&sealed).unwrap(), "ssh deploy@10.0.0.5");
+
+        // Rows written before encryption was enabled
+        assert_eq!(cipher.open("ls -la").unwrap(), "ls -la");
+
+        // Wrong key and missing key both fail instead of returning garbage
+        assert!(FieldCipher::from_key(&[8u8; KEY_LEN]).open(&sealed).is_err());
+        assert!(FieldCipher::disabled().open(&sealed).is_err());
+        assert_eq!(FieldCipher::disabled().seal("ls").unwrap(), "ls");
+    }
+
+    #[test]
+    fn test_lookup_keys_and_sealed_bytes() {
+        let cipher = FieldCipher::from_key(&[7u8; KEY_LEN]);
+
+        let key = cipher.lookup_key("deploy to staging");
+        assert!(FieldCipher::is_keyed_lookup(&key));
+        assert!(!key.contains("staging"));
+        assert_eq!(key, cipher.lookup_key("deploy to staging"));
+        assert_ne!(key, cipher.lookup_key("deploy to production"));
+        let other_key = FieldCipher::from_key(&[8u8; KEY_LEN]);
+        assert_ne!(key, other_key.lookup_key("deploy to staging"));
+        assert_eq!(FieldCipher::disabled().lookup_key("ls"), "ls");
+
+        let embedding = [0u8, 0, 128, 63, 0, 0, 0, 64];
+        let sealed = cipher.seal_bytes(&embedding).unwrap();
+        assert!(sealed.starts_with(SEALED_PREFIX.as_bytes()));
+        assert_eq!(cipher.open_bytes(&sealed).unwrap(), embedding);
+        assert_eq!(cipher.open_bytes(&embedding).unwrap(), embedding);
+        assert!(FieldCipher::disabled().open_bytes(&sealed).is_err());
+    }
+}
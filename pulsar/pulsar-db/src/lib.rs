@@ -0,0 +1,363 @@
+//! Shared SQLite Setup
+//!
+//! Every local store (workspaces, learning history, the credential vault)
+//! opens SQLite through this crate so they all get the same treatment:
+//! - WAL journaling, so readers never block the writer
+//! - A busy timeout instead of immediate `SQLITE_BUSY` errors
+//! - Enforced foreign keys
+//! - An integrity check before the store is used
+//! - The same health, backup, vacuum and export commands
+//! - Optional field encryption for sensitive columns ([`cipher`])
+
+pub mod cipher;
+
+pub use cipher::FieldCipher;
+
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use sqlx::sqlite::{SqliteConnectOptions, SqliteJournalMode, SqlitePool, SqlitePoolOptions, SqliteSynchronous};
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine as _};
+use serde_json::{Map, Value};
+use sqlx::{Column, Row, TypeInfo, ValueRef};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use thiserror::Error;
+use tracing::{info, warn};
+
+#[derive(Debug, Error)]
+pub enum DbError {
+    #[error("Failed to open database {path}: {source}")]
+    Open {
+        path: PathBuf,
+        #[source]
+        source: sqlx::Error,
+    },
+
+    #[error("Database {path} failed its integrity check: {}", problems.join("; "))]
+    Corrupt { path: PathBuf, problems: Vec<String> },
+
+    #[error("Database key unavailable: {0}")]
+    Key(String),
+
+    #[error("Field encryption error: {0}")]
+    Crypto(String),
+
+    #[error("Backup destination already exists: {0}")]
+    BackupExists(PathBuf),
+
+    #[error(transparent)]
+    Sqlx(#[from] sqlx::Error),
+
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+}
+
+pub type Result<T> = std::result::Result<T, DbError>;
+
+/// How a store's database is opened
+#[derive(Debug, Clone)]
+pub struct DbOptions {
+    pub max_connections: u32,
+    /// How long a connection waits on a locked database before failing
+    pub busy_timeout: Duration,
+    /// Run `PRAGMA integrity_check` before handing out the pool
+    pub check_integrity: bool,
+}
+
+impl Default for DbOptions {
+    fn default() -> Self {
+        Self {
+            max_connections: 5,
+            busy_timeout: Duration::from_secs(5),
+            check_integrity: true,
+        }
+    }
+}
+
+/// Health report for a database
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DbHealth {
+    pub path: PathBuf,
+    pub journal_mode: String,
+    pub foreign_keys: bool,
+    /// Size of the main database file
+    pub size_bytes: u64,
+    /// Free pages that a vacuum would give back
+    pub free_bytes: u64,
+    /// Size of the write-ahead log not yet checkpointed
+    pub wal_bytes: u64,
+    /// Problems reported by `PRAGMA integrity_check` (empty when healthy)
+    pub problems: Vec<String>,
+}
+
+impl DbHealth {
+    pub fn is_healthy(&self) -> bool {
+        self.problems.is_empty()
+    }
+}
+
+/// Open (creating if needed) a database with the shared settings
+pub async fn open(path: &Path, options: &DbOptions) -> Result<SqlitePool> {
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+
+    let connect = SqliteConnectOptions::new()
+        .filename(path)
+        .create_if_missing(true)
+        .journal_mode(SqliteJournalMode::Wal)
+        .synchronous(SqliteSynchronous::Normal)
+        .foreign_keys(true)
+        .busy_timeout(options.busy_timeout);
+
+    let pool = SqlitePoolOptions::new()
+        .max_connections(options.max_connections)
+        .connect_with(connect)
+        .await
+        .map_err(|source| DbError::Open {
+            path: path.to_path_buf(),
+            source,
+        })?;
+
+    if options.check_integrity {
+        let problems = integrity_check(&pool).await?;
+        if !problems.is_empty() {
+            pool.close().await;
+            return Err(DbError::Corrupt {
+                path: path.to_path_buf(),
+                problems,
+            });
+        }
+    }
+
+    info!("Opened database {}", path.display());
+    Ok(pool)
+}
+
+/// Problems reported by `PRAGMA integrity_check`; empty when the database is intact
+pub async fn integrity_check(pool: &SqlitePool) -> Result<Vec<String>> {
+    let rows = sqlx::query("PRAGMA integrity_check").fetch_all(pool).await?;
+    let problems: Vec<String> = rows
+        .iter()
+        .map(|row| row.get::<String, _>(0))
+        .filter(|line| line != "ok")
+        .collect();
+
+    if !problems.is_empty() {
+        warn!("Integrity check found {} problems", problems.len());
+    }
+    Ok(problems)
+}
+
+/// Journal mode, sizes and integrity of a database
+pub async fn health(pool: &SqlitePool) -> Result<DbHealth> {
+    let path = pool.connect_options().get_filename().to_path_buf();
+
+    let journal_mode: String = sqlx::query_scalar("PRAGMA journal_mode").fetch_one(pool).await?;
+    let foreign_keys: i64 = sqlx::query_scalar("PRAGMA foreign_keys").fetch_one(pool).await?;
+    let page_size: i64 = sqlx::query_scalar("PRAGMA page_size").fetch_one(pool).await?;
+    let page_count: i64 = sqlx::query_scalar("PRAGMA page_count").fetch_one(pool).await?;
+    let free_pages: i64 = sqlx::query_scalar("PRAGMA freelist_count").fetch_one(pool).await?;
+
+    let wal_bytes = std::fs::metadata(wal_path(&path)).map(|m| m.len()).unwrap_or(0);
+
+    Ok(DbHealth {
+        journal_mode,
+        foreign_keys: foreign_keys == 1,
+        size_bytes: (page_count * page_size) as u64,
+        free_bytes: (free_pages * page_size) as u64,
+        wal_bytes,
+        problems: integrity_check(pool).await?,
+        path,
+    })
+}
+
+/// Checkpoint the WAL and rebuild the database to reclaim free pages
+pub async fn vacuum(pool: &SqlitePool) -> Result<()> {
+    sqlx::raw_sql("PRAGMA wal_checkpoint(TRUNCATE); VACUUM; PRAGMA optimize;")
+        .execute(pool)
+        .await?;
+
+    info!("Vacuumed database {}", pool.connect_options().get_filename().display());
+    Ok(())
+}
+
+/// Write a consistent copy of the database to `dest`; returns its size
+///
+/// Uses `VACUUM INTO`, so the copy is compacted and safe to take while the
+/// database is in use.
+pub async fn backup(pool: &SqlitePool, dest: &Path) -> Result<u64> {
+    if dest.exists() {
+        return Err(DbError::BackupExists(dest.to_path_buf()));
+    }
+    if let Some(parent) = dest.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+
+    sqlx::query("VACUUM INTO ?")
+        .bind(dest.to_string_lossy().into_owned())
+        .execute(pool)
+        .await?;
+
+    let size = std::fs::metadata(dest)?.len();
+    info!("Backed up database to {} ({} bytes)", dest.display(), size);
+    Ok(size)
+}
+
+/// Back up into `dir` as `<stem>-<timestamp>.db`, keeping the newest `keep` backups
+pub async fn backup_rotating(pool: &SqlitePool, dir: &Path, stem: &str, keep: usize) -> Result<PathBuf> {
+    let dest = dir.join(format!("{}-{}.db", stem, Utc::now().format("%Y%m%d-%H%M%S")));
+    backup(pool, &dest).await?;
+
+    let prefix = format!("{}-", stem);
+    let mut backups: Vec<PathBuf> = std::fs::read_dir(dir)?
+        .filter_map(|entry| entry.ok().map(|e| e.path()))
+        .filter(|path| {
+            path.extension().is_some_and(|ext| ext == "db")
+                && path
+                    .file_name()
+                    .and_then(|name| name.to_str())
+                    .is_some_and(|name| name.starts_with(&prefix))
+        })
+        .collect();
+
+    // Timestamped names sort chronologically
+    backups.sort();
+    let excess = backups.len().saturating_sub(keep.max(1));
+    for old in &backups[..excess] {
+        std::fs::remove_file(old)?;
+    }
+
+    Ok(dest)
+}
+
+/// Every row of `table` as a JSON object keyed by column name
+///
+/// Sealed text values are opened with `cipher`; values it can't open are
+/// kept sealed rather than failing the export. Blobs (e.g. embeddings) are
+/// base64-encoded.
+pub async fn export_table(pool: &SqlitePool, table: &str, cipher: &FieldCipher) -> Result<Vec<Map<String, Value>>> {
+    let rows = sqlx::query(&format!("SELECT * FROM \"{}\"", table.replace('"', "\"\"")))
+        .fetch_all(pool)
+        .await?;
+
+    let mut records = Vec::with_capacity(rows.len());
+    for row in &rows {
+        let mut record = Map::new();
+        for (i, column) in row.columns().iter().enumerate() {
+            let raw = row.try_get_raw(i)?;
+            let value = if raw.is_null() {
+                Value::Null
+            } else {
+                match raw.type_info().name() {
+                    "INTEGER" => Value::from(row.try_get_unchecked::<i64, _>(i)?),
+                    "REAL" => Value::from(row.try_get_unchecked::<f64, _>(i)?),
+                    "BLOB" => Value::from(BASE64.encode(row.try_get_unchecked::<Vec<u8>, _>(i)?)),
+                    _ => {
+                        let text = row.try_get_unchecked::<String, _>(i)?;
+                        Value::from(cipher.open(&text).unwrap_or(text))
+                    }
+                }
+            };
+            record.insert(column.name().to_string(), value);
+        }
+        records.push(record);
+    }
+
+    Ok(records)
+}
+
+fn wal_path(path: &Path) -> PathBuf {
+    let mut wal = path.as_os_str().to_owned();
+    wal.push("-wal");
+    PathBuf::from(wal)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[tokio::test]
+    async fn test_open_applies_shared_settings() {
+        let dir = tempdir().unwrap();
+        let pool = open(&dir.path().join("nested/test.db"), &DbOptions::default())
+            .await
+            .unwrap();
+
+        let health = health(&pool).await.unwrap();
+        assert_eq!(health.journal_mode, "wal");
+        assert!(health.foreign_keys);
+        assert!(health.is_healthy());
+    }
+
+    #[tokio::test]
+    async fn test_backup_and_rotation() {
+        let dir = tempdir().unwrap();
+        let pool = open(&dir.path().join("store.db"), &DbOptions::default())
+            .await
+            .unwrap();
+        sqlx::query("CREATE TABLE items (name TEXT)").execute(&pool).await.unwrap();
+        sqlx::query("INSERT INTO items VALUES ('a')").execute(&pool).await.unwrap();
+
+        // Older backups of this store beyond the limit are removed
+        let backups = dir.path().join("backups");
+        std::fs::create_dir_all(&backups).unwrap();
+        for name in [
+            "store-20200101-000000.db",
+            "store-20200102-000000.db",
+            "other-20200101-000000.db",
+        ] {
+            std::fs::write(backups.join(name), b"").unwrap();
+        }
+
+        let dest = backup_rotating(&pool, &backups, "store", 2).await.unwrap();
+        assert!(!backups.join("store-20200101-000000.db").exists());
+        assert!(backups.join("store-20200102-000000.db").exists());
+        assert!(backups.join("other-20200101-000000.db").exists());
+        assert!(matches!(
+            backup(&pool, &dest).await,
+            Err(DbError::BackupExists(_))
+        ));
+
+        let restored = open(&dest, &DbOptions::default()).await.unwrap();
+        let count: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM items")
+            .fetch_one(&restored)
+            .await
+            .unwrap();
+        assert_eq!(count, 1);
+
+        vacuum(&pool).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_export_table_opens_sealed_values() {
+        let dir = tempdir().unwrap();
+        let pool = open(&dir.path().join("history.db"), &DbOptions::default())
+            .await
+            .unwrap();
+        sqlx::query("CREATE TABLE history (id INTEGER PRIMARY KEY, command TEXT, score REAL, embedding BLOB, note TEXT)")
+            .execute(&pool)
+            .await
+            .unwrap();
+
+        let cipher = FieldCipher::from_key(&[1u8; cipher::KEY_LEN]);
+        sqlx::query("INSERT INTO history (command, score, embedding, note) VALUES (?, 0.5, x'0102', NULL)")
+            .bind(cipher.seal("git push").unwrap())
+            .execute(&pool)
+            .await
+            .unwrap();
+
+        let rows = export_table(&pool, "history", &cipher).await.unwrap();
+        assert_eq!(rows.len(), 1);
+        assert_eq!(rows[0]["id"], 1);
+        assert_eq!(rows[0]["command"], "git push");
+        assert_eq!(rows[0]["score"], 0.5);
+        assert_eq!(rows[0]["embedding"], "AQI=");
+        assert!(rows[0]["note"].is_null());
+
+        // Without the key the value stays sealed
+        let rows = export_table(&pool, "history", &FieldCipher::disabled()).await.unwrap();
+        assert!(FieldCipher::is_sealed(rows[0]["command"].as_str().unwrap()));
+    }
+}
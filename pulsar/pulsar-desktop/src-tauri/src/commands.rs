@@ -4,9 +4,12 @@ use serde::{Deserialize, Serialize};
 use std::sync::Arc;
 use std::io::Write;
 use tauri::State;
-use tft_transports::AuthMethod;
+use terminal_core::CoalesceStats;
+use tft_transports::{AuthMethod, TransportError};
 use uuid::Uuid;
 
+use crate::daemon_client::DaemonClient;
+use crate::settings::SettingsManager;
 use crate::ssh_manager::SshManager;
 use crate::vault::Vault;
 
@@ -27,16 +30,23 @@ pub enum AuthMethodDto {
     Password { password: String },
     PublicKey { key_path: String, passphrase: Option<String> },
     Agent,
+    /// A short-lived certificate from the SSH CA, fetched through the daemon
+    Certificate,
 }
 
-impl From<AuthMethodDto> for AuthMethod {
-    fn from(dto: AuthMethodDto) -> Self {
+impl TryFrom<AuthMethodDto> for AuthMethod {
+    type Error = String;
+
+    fn try_from(dto: AuthMethodDto) -> Result<Self, String> {
         match dto {
-            AuthMethodDto::Password { password } => AuthMethod::Password(password),
+            AuthMethodDto::Password { password } => Ok(AuthMethod::Password(password)),
             AuthMethodDto::PublicKey { key_path, passphrase } => {
-                AuthMethod::PublicKey { key_path, passphrase }
+                Ok(AuthMethod::PublicKey { key_path, passphrase })
+            }
+            AuthMethodDto::Agent => Ok(AuthMethod::Agent),
+            AuthMethodDto::Certificate => {
+                Err("SSH certificates must be requested from the daemon".to_string())
             }
-            AuthMethodDto::Agent => AuthMethod::Agent,
         }
     }
 }
@@ -46,6 +56,8 @@ pub async fn connect_ssh(
     config: SshConnectionConfig,
     ssh_manager: State<'_, Arc<SshManager>>,
     vault: State<'_, Vault>,
+    settings: State<'_, SettingsManager>,
+    daemon: State<'_, Arc<DaemonClient>>,
 ) -> Result<String, String> {
     tracing::info!(
         "Command: connect_ssh to {}@{}:{}",
@@ -95,9 +107,31 @@ pub async fn connect_ssh(
                 _ => return Err("Credential is not an SSH key".to_string()),
             }
         }
-        _ => config.auth_method.into(),
+        AuthMethodDto::Certificate => {
+            // Ensure connected
+            if !daemon.is_connected().await {
+                daemon
+                    .connect()
+                    .await
+                    .map_err(|e| format!("Failed to connect to daemon: {}", e))?;
+            }
+
+            let certificate = daemon
+                .ssh_certificate(&config.username, &config.host)
+                .await
+                .map_err(|e| format!("Failed to get an SSH certificate: {}", e))?;
+            tracing::info!("Using SSH certificate valid until {}", certificate.expires_at);
+
+            AuthMethod::Certificate {
+                private_key: certificate.private_key,
+                certificate: certificate.certificate,
+            }
+        }
+        _ => config.auth_method.try_into()?,
     };
 
+    let connection = settings.get_connection().await;
+
     let session_id = ssh_manager
         .connect(
             config.host,
@@ -106,11 +140,17 @@ pub async fn connect_ssh(
             auth_method,
             config.cols,
             config.rows,
+            connection.local_echo,
+            connection.input_coalescing,
         )
         .await
         .map_err(|e| {
             tracing::error!("SSH connection failed: {}", e);
-            format!("Connection failed: {}", e)
+            // Say what to do about transport failures
+            match e.downcast_ref::<TransportError>() {
+                Some(error) => format!("Connection failed: {}. {}", error, error.action()),
+                None => format!("Connection failed: {}", e),
+            }
         })?;
 
     Ok(session_id.to_string())
@@ -184,6 +224,19 @@ pub async fn get_fingerprint(
         .map_err(|e| format!("Failed to get fingerprint: {}", e))
 }
 
+#[tauri::command]
+pub async fn get_input_stats(
+    session_id: String,
+    ssh_manager: State<'_, Arc<SshManager>>,
+) -> Result<CoalesceStats, String> {
+    let uuid = Uuid::parse_str(&session_id).map_err(|e| format!("Invalid session ID: {}", e))?;
+
+    ssh_manager
+        .input_stats(uuid)
+        .await
+        .map_err(|e| format!("Failed to get input stats: {}", e))
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct AgentIdentity {
     pub comment: Option<String>,
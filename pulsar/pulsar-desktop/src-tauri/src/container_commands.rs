@@ -0,0 +1,72 @@
+// Tauri commands for running Orbit suggestions on the host, in a container
+// or in a Pulsar session
+
+use orbitd::executor::output::ExecutionOutput;
+use orbitd::executor::target::{ContainerInfo, ContainerRuntime, ExecutionTarget};
+
+/// Result type for commands
+type CommandResult<T> = Result<T, String>;
+
+/// Run an approved command on `target` (the host when omitted) from `cwd`.
+/// `input` is what the user typed, so Orbit can learn from the result.
+#[tauri::command]
+pub async fn run_in_target(
+    command: String,
+    cwd: String,
+    target: Option<ExecutionTarget>,
+    input: Option<String>,
+) -> CommandResult<ExecutionOutput> {
+    #[cfg(unix)]
+    {
+        use orbitd::config::Config;
+        use orbitd::daemon::ipc::{call, Request, Response};
+
+        let config = Config::load().await.map_err(|e| e.to_string())?;
+        let request = Request::Execute {
+            command,
+            cwd,
+            target: target.unwrap_or_default(),
+            input,
+        };
+
+        match call(&config.daemon.socket_path, &request).await {
+            Ok(Response::Executed { output }) => Ok(output),
+            Ok(Response::Error { message }) => Err(message),
+            Ok(other) => Err(format!("Unexpected response from Orbit: {:?}", other)),
+            Err(e) => Err(format!("Orbit daemon is not reachable: {}", e)),
+        }
+    }
+
+    #[cfg(not(unix))]
+    {
+        let _ = (command, cwd, target, input);
+        Err("Running commands through Orbit is only available on Unix".to_string())
+    }
+}
+
+/// Running containers to offer as execution targets
+#[tauri::command]
+pub async fn list_containers(
+    runtime: Option<ContainerRuntime>,
+) -> CommandResult<Vec<ContainerInfo>> {
+    #[cfg(unix)]
+    {
+        use orbitd::config::Config;
+        use orbitd::daemon::ipc::{call, Request, Response};
+
+        let config = Config::load().await.map_err(|e| e.to_string())?;
+
+        match call(&config.daemon.socket_path, &Request::Containers { runtime }).await {
+            Ok(Response::Containers { containers, .. }) => Ok(containers),
+            Ok(Response::Error { message }) => Err(message),
+            Ok(other) => Err(format!("Unexpected response from Orbit: {:?}", other)),
+            Err(e) => Err(format!("Orbit daemon is not reachable: {}", e)),
+        }
+    }
+
+    #[cfg(not(unix))]
+    {
+        let _ = runtime;
+        Err("Container targets are only available on Unix".to_string())
+    }
+}
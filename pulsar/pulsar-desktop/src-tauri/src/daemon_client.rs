@@ -2,11 +2,11 @@
 //!
 //! Provides high-level async API for daemon communication
 
+use crate::settings::AppearanceSettings;
 use anyhow::{anyhow, Context, Result};
 use serde::{Deserialize, Serialize};
-use std::path::PathBuf;
-use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
-use tokio::net::UnixStream;
+use std::path::{Path, PathBuf};
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader, ReadHalf, WriteHalf};
 use tokio::sync::Mutex;
 use uuid::Uuid;
 
@@ -24,6 +24,19 @@ pub enum SessionType {
     Local,
     Ssh { host: String, port: u16 },
     Serial { device: String },
+    Tail { sources: Vec<TailSource> },
+}
+
+/// A log file followed by a tail session (matches daemon)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TailSource {
+    pub path: String,
+    #[serde(default)]
+    pub host: Option<String>,
+    #[serde(default)]
+    pub port: Option<u16>,
+    #[serde(default)]
+    pub label: Option<String>,
 }
 
 /// Session info (matches daemon)
@@ -36,6 +49,172 @@ pub struct SessionInfo {
     pub last_active: String,
     pub state: SessionState,
     pub num_clients: usize,
+    /// Shell's working directory, when it has reported one
+    #[serde(default)]
+    pub cwd: Option<terminal_core::WorkingDirectory>,
+}
+
+/// A marked line of a session's output (matches daemon)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Bookmark {
+    pub id: Uuid,
+    pub line: u64,
+    #[serde(default)]
+    pub note: Option<String>,
+    pub text: String,
+    pub created_at: String,
+}
+
+/// A bookmark with the output around it
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BookmarkView {
+    pub bookmark: Bookmark,
+    pub in_scrollback: bool,
+    pub lines: Vec<(u64, String)>,
+}
+
+/// A command run in a session, timed by the daemon (matches daemon)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CommandRecord {
+    pub id: Uuid,
+    pub command: String,
+    /// "osc133" or "heuristic"
+    pub source: String,
+    pub started_at: String,
+    #[serde(default)]
+    pub finished_at: Option<String>,
+    pub duration_ms: u64,
+    #[serde(default)]
+    pub exit_code: Option<i32>,
+}
+
+/// What became of a command re-run in other sessions (matches daemon)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "status", rename_all = "snake_case")]
+pub enum RerunOutcome {
+    Sent {
+        command: String,
+        sessions: Vec<Uuid>,
+        #[serde(default)]
+        canary: Option<CanaryReport>,
+    },
+    /// Nothing was sent; re-run with `confirmed` to send anyway
+    NeedsConfirmation { command: String, reason: String },
+    /// The canary failed its checks; the other sessions were sent nothing
+    CanaryFailed { command: String, report: CanaryReport },
+}
+
+fn default_canary_exit_code() -> Option<i32> {
+    Some(0)
+}
+
+fn default_canary_timeout_secs() -> u64 {
+    60
+}
+
+/// A target session a re-run goes to first, reaching the others only if
+/// it succeeds (matches daemon)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Canary {
+    pub session_id: Uuid,
+    /// Null accepts any exit code
+    #[serde(default = "default_canary_exit_code")]
+    pub exit_code: Option<i32>,
+    #[serde(default)]
+    pub output_pattern: Option<String>,
+    #[serde(default = "default_canary_timeout_secs")]
+    pub timeout_secs: u64,
+}
+
+/// How the canary of a re-run did (matches daemon)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CanaryReport {
+    pub session_id: Uuid,
+    pub passed: bool,
+    #[serde(default)]
+    pub failure: Option<String>,
+    #[serde(default)]
+    pub exit_code: Option<i32>,
+    pub duration_ms: u64,
+    pub output: Vec<String>,
+}
+
+/// A short-lived key and certificate from the organization's SSH CA
+/// (matches daemon)
+#[derive(Clone, Serialize, Deserialize)]
+pub struct SshCertificate {
+    pub private_key: String,
+    pub certificate: String,
+    pub expires_at: String,
+}
+
+/// A file sent to a session (matches daemon)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TransferRecord {
+    pub transfer_id: String,
+    pub file_name: String,
+    pub file_size: u64,
+    pub started_at: String,
+    #[serde(default)]
+    pub finished_at: Option<String>,
+    /// "complete", "failed", "aborted" or "interrupted"; None while in flight
+    #[serde(default)]
+    pub outcome: Option<String>,
+}
+
+/// A client attaching to or leaving a session (matches daemon)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ConnectionEvent {
+    pub at: String,
+    pub client_id: Uuid,
+    /// "attached", "detached" or "disconnected" (the last client left)
+    pub change: String,
+    pub clients: usize,
+}
+
+/// One item of a session's history (matches daemon)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum TimelineEntry {
+    Command(CommandRecord),
+    Transfer(TransferRecord),
+    Connection(ConnectionEvent),
+    Bookmark(Bookmark),
+}
+
+/// A session's history over a time range (matches daemon)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SessionTimeline {
+    pub session_id: Uuid,
+    pub created_at: String,
+    pub now: String,
+    pub entries: Vec<TimelineEntry>,
+}
+
+/// What the daemon did with a paste (matches daemon)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PasteResult {
+    pub bytes_written: usize,
+    /// The session's program had bracketed paste on
+    pub bracketed: bool,
+}
+
+/// A rendered session transcript (matches daemon)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Transcript {
+    pub format: terminal_core::TranscriptFormat,
+    /// File extension to save it with
+    pub extension: String,
+    pub content: String,
+}
+
+/// A finished command that ran past the notification threshold
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LongCommand {
+    pub session_id: Uuid,
+    pub session_name: String,
+    #[serde(flatten)]
+    pub record: CommandRecord,
 }
 
 /// Daemon status
@@ -45,6 +224,36 @@ pub struct DaemonStatus {
     pub uptime_seconds: u64,
     pub num_sessions: usize,
     pub num_clients: usize,
+    /// Seconds until the daemon stops, once it is shutting down
+    #[serde(default)]
+    pub shutting_down_in: Option<u64>,
+}
+
+/// Permissions granted by a session attach token
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AttachScope {
+    View,
+    Control,
+}
+
+/// Time-limited token letting another device attach to a session
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AttachToken {
+    pub token: String,
+    pub session_id: Uuid,
+    pub scope: AttachScope,
+    pub expires_at: String,
+    pub ws_path: String,
+}
+
+/// WebTransport certificate hash for `serverCertificateHashes`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CertificateHash {
+    pub algorithm: String,
+    pub value: String,
+    pub not_before: String,
+    pub not_after: String,
 }
 
 /// Workspace layout structure
@@ -55,6 +264,9 @@ pub struct WorkspaceLayout {
     pub layout_type: String,
     pub panes: Vec<PaneConfig>,
     pub active_pane: Option<String>,
+    /// Typed layout tree maintained by the daemon (passed through untouched)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tree: Option<serde_json::Value>,
 }
 
 /// Pane configuration
@@ -122,6 +334,75 @@ pub struct WorkspaceSnapshot {
     pub created_at: String,
 }
 
+/// Snapshot metadata for listings
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SnapshotSummary {
+    pub id: String,
+    pub workspace_id: String,
+    pub name: String,
+    pub created_at: String,
+    pub pane_count: usize,
+    pub session_count: usize,
+    pub size_bytes: u64,
+    pub automatic: bool,
+}
+
+/// Disk usage of workspace snapshots
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SnapshotUsage {
+    pub snapshot_count: usize,
+    pub automatic_count: usize,
+    pub snapshot_bytes: u64,
+    pub database_bytes: u64,
+    pub reclaimable_bytes: u64,
+}
+
+/// A pane whose session binding differs between a snapshot and the workspace
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PaneBindingChange {
+    pub pane_id: String,
+    pub current_session_id: Option<String>,
+    pub snapshot_session_id: Option<String>,
+}
+
+/// What restoring a snapshot would change
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SnapshotDiff {
+    pub snapshot_id: String,
+    pub workspace_id: String,
+    pub layout_changed: bool,
+    pub panes_added: Vec<String>,
+    pub panes_removed: Vec<String>,
+    pub panes_rebound: Vec<PaneBindingChange>,
+    pub sessions_added: Vec<String>,
+    pub sessions_removed: Vec<String>,
+    pub sessions_changed: Vec<String>,
+}
+
+/// Panes and sessions to restore from a snapshot
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct RestoreSelection {
+    #[serde(default)]
+    pub pane_ids: Vec<String>,
+    #[serde(default)]
+    pub session_ids: Vec<String>,
+}
+
+/// Host inventory export format
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum InventoryFormat {
+    SshConfig,
+    Ansible,
+}
+
+/// Host inventory export filter
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct InventoryFilter {
+    pub tags: Option<Vec<String>>,
+    pub workspace_ids: Option<Vec<String>>,
+}
+
 /// IPC request (matches daemon protocol)
 #[derive(Debug, Clone, Serialize, Deserialize)]
 struct Request {
@@ -158,9 +439,15 @@ pub struct DaemonClient {
     request_id_counter: Mutex<u64>,
 }
 
+/// The daemon listens on a Unix socket, or a named pipe on Windows
+#[cfg(unix)]
+type Stream = tokio::net::UnixStream;
+#[cfg(windows)]
+type Stream = tokio::net::windows::named_pipe::NamedPipeClient;
+
 struct Connection {
-    reader: BufReader<tokio::net::unix::OwnedReadHalf>,
-    writer: tokio::net::unix::OwnedWriteHalf,
+    reader: BufReader<ReadHalf<Stream>>,
+    writer: WriteHalf<Stream>,
 }
 
 impl DaemonClient {
@@ -175,11 +462,14 @@ impl DaemonClient {
 
     /// Connect to the daemon
     pub async fn connect(&self) -> Result<()> {
-        let stream = UnixStream::connect(&self.socket_path)
-            .await
+        #[cfg(unix)]
+        let stream = tokio::net::UnixStream::connect(&self.socket_path).await;
+        #[cfg(windows)]
+        let stream = tokio::net::windows::named_pipe::ClientOptions::new().open(&self.socket_path);
+        let stream = stream
             .with_context(|| format!("Failed to connect to daemon at {:?}", self.socket_path))?;
 
-        let (reader, writer) = stream.into_split();
+        let (reader, writer) = tokio::io::split(stream);
         let connection = Connection {
             reader: BufReader::new(reader),
             writer,
@@ -189,6 +479,11 @@ impl DaemonClient {
         Ok(())
     }
 
+    /// Where the daemon is expected to listen
+    pub fn socket_path(&self) -> &Path {
+        &self.socket_path
+    }
+
     /// Check if connected to daemon
     pub async fn is_connected(&self) -> bool {
         self.connection.lock().await.is_some()
@@ -287,11 +582,18 @@ impl DaemonClient {
         Ok(sessions)
     }
 
-    /// Attach client to session
-    pub async fn attach_session(&self, session_id: Uuid, client_id: Uuid) -> Result<()> {
+    /// Attach client to session; a read-only client receives output but the
+    /// daemon discards its input
+    pub async fn attach_session(
+        &self,
+        session_id: Uuid,
+        client_id: Uuid,
+        read_only: bool,
+    ) -> Result<()> {
         let params = serde_json::json!({
             "session_id": session_id,
             "client_id": client_id,
+            "read_only": read_only,
         });
 
         self.send_request("attach_session", params).await?;
@@ -319,10 +621,175 @@ impl DaemonClient {
         Ok(())
     }
 
-    /// Resize terminal
-    pub async fn resize_terminal(&self, session_id: Uuid, cols: u16, rows: u16) -> Result<()> {
+    /// Tell the daemon the user switched to a session, so Orbit's
+    /// suggestions follow the focused terminal
+    pub async fn focus_session(&self, session_id: Uuid) -> Result<()> {
+        let params = serde_json::json!({
+            "session_id": session_id,
+        });
+
+        self.send_request("focus_session", params).await?;
+        Ok(())
+    }
+
+    /// Search a session's scrollback, newest matches first
+    pub async fn search_scrollback(
+        &self,
+        session_id: Uuid,
+        query: terminal_core::SearchQuery,
+    ) -> Result<terminal_core::SearchResults> {
+        let mut params = serde_json::to_value(query)?;
+        params["session_id"] = serde_json::json!(session_id);
+
+        let result = self.send_request("search_scrollback", params).await?;
+        serde_json::from_value(result).context("Failed to parse search results")
+    }
+
+    /// Render a session's scrollback as plain text or standalone HTML
+    pub async fn export_transcript(
+        &self,
+        session_id: Uuid,
+        format: terminal_core::TranscriptFormat,
+    ) -> Result<Transcript> {
+        let params = serde_json::json!({
+            "session_id": session_id,
+            "format": format,
+        });
+
+        let result = self.send_request("export_transcript", params).await?;
+        serde_json::from_value(result).context("Failed to parse transcript")
+    }
+
+    /// Mark a line of a session's output, by default the last one with text
+    pub async fn add_bookmark(
+        &self,
+        session_id: Uuid,
+        line: Option<u64>,
+        note: Option<String>,
+    ) -> Result<Bookmark> {
         let params = serde_json::json!({
             "session_id": session_id,
+            "line": line,
+            "note": note,
+        });
+
+        let result = self.send_request("add_bookmark", params).await?;
+        serde_json::from_value(result).context("Failed to parse bookmark")
+    }
+
+    /// A session's bookmarks, in output order
+    pub async fn list_bookmarks(&self, session_id: Uuid) -> Result<Vec<Bookmark>> {
+        let params = serde_json::json!({ "session_id": session_id });
+
+        let result = self.send_request("list_bookmarks", params).await?;
+        serde_json::from_value(result).context("Failed to parse bookmarks")
+    }
+
+    /// Remove a bookmark
+    pub async fn remove_bookmark(&self, session_id: Uuid, bookmark_id: Uuid) -> Result<()> {
+        let params = serde_json::json!({
+            "session_id": session_id,
+            "bookmark_id": bookmark_id,
+        });
+
+        self.send_request("remove_bookmark", params).await?;
+        Ok(())
+    }
+
+    /// A bookmark with `context` lines of output either side
+    pub async fn jump_to_bookmark(
+        &self,
+        session_id: Uuid,
+        bookmark_id: Uuid,
+        context: u64,
+    ) -> Result<BookmarkView> {
+        let params = serde_json::json!({
+            "session_id": session_id,
+            "bookmark_id": bookmark_id,
+            "context": context,
+        });
+
+        let result = self.send_request("jump_to_bookmark", params).await?;
+        serde_json::from_value(result).context("Failed to parse bookmark")
+    }
+
+    /// Commands run in a session with their durations, oldest first
+    pub async fn command_timeline(&self, session_id: Uuid) -> Result<Vec<CommandRecord>> {
+        let params = serde_json::json!({ "session_id": session_id });
+
+        let result = self.send_request("command_timeline", params).await?;
+        serde_json::from_value(result).context("Failed to parse command timeline")
+    }
+
+    /// Run a command from one session's timeline in other sessions, in
+    /// the `canary` first if given
+    pub async fn rerun_command(
+        &self,
+        session_id: Uuid,
+        command_id: Uuid,
+        target_session_ids: Vec<Uuid>,
+        confirmed: bool,
+        canary: Option<Canary>,
+    ) -> Result<RerunOutcome> {
+        let params = serde_json::json!({
+            "session_id": session_id,
+            "command_id": command_id,
+            "target_session_ids": target_session_ids,
+            "confirmed": confirmed,
+            "canary": canary,
+        });
+
+        let result = self.send_request("rerun_command", params).await?;
+        serde_json::from_value(result).context("Failed to parse re-run outcome")
+    }
+
+    /// A certificate for `username` on `host` from the SSH CA the daemon is
+    /// configured with, cached by the daemon until shortly before expiry
+    pub async fn ssh_certificate(&self, username: &str, host: &str) -> Result<SshCertificate> {
+        let params = serde_json::json!({
+            "username": username,
+            "host": host,
+        });
+
+        let result = self.send_request("ssh_certificate", params).await?;
+        serde_json::from_value(result).context("Failed to parse SSH certificate")
+    }
+
+    /// Commands, transfers, connection changes and bookmarks of a session
+    /// that overlap `[from, to]` (RFC 3339; either end open when None)
+    pub async fn session_timeline(
+        &self,
+        session_id: Uuid,
+        from: Option<String>,
+        to: Option<String>,
+    ) -> Result<SessionTimeline> {
+        let params = serde_json::json!({ "session_id": session_id, "from": from, "to": to });
+
+        let result = self.send_request("session_timeline", params).await?;
+        serde_json::from_value(result).context("Failed to parse session timeline")
+    }
+
+    /// Commands that finished since the last call and ran for at least
+    /// `threshold_secs`
+    pub async fn take_long_commands(&self, threshold_secs: u64) -> Result<Vec<LongCommand>> {
+        let params = serde_json::json!({ "threshold_secs": threshold_secs });
+
+        let result = self.send_request("take_long_commands", params).await?;
+        serde_json::from_value(result).context("Failed to parse long commands")
+    }
+
+    /// Resize terminal; `client_id` is the one given to attach_session,
+    /// so the daemon can weigh this window against other clients'
+    pub async fn resize_terminal(
+        &self,
+        session_id: Uuid,
+        client_id: Option<Uuid>,
+        cols: u16,
+        rows: u16,
+    ) -> Result<()> {
+        let params = serde_json::json!({
+            "session_id": session_id,
+            "client_id": client_id,
             "cols": cols,
             "rows": rows,
         });
@@ -346,6 +813,31 @@ impl DaemonClient {
         Ok(bytes_written as usize)
     }
 
+    /// Send a key press to session PTY, encoded by the daemon for the
+    /// keyboard protocol the session's program negotiated
+    pub async fn send_key(&self, session_id: Uuid, press: KeyPress) -> Result<usize> {
+        let mut params = serde_json::to_value(press)?;
+        params["session_id"] = serde_json::json!(session_id);
+
+        let result = self.send_request("send_key", params).await?;
+        let bytes_written = result["bytes_written"]
+            .as_u64()
+            .ok_or_else(|| anyhow!("Invalid bytes_written in response"))?;
+
+        Ok(bytes_written as usize)
+    }
+
+    /// Paste text into session PTY, bracketed if its program asked
+    pub async fn paste(&self, session_id: Uuid, text: String) -> Result<PasteResult> {
+        let params = serde_json::json!({
+            "session_id": session_id,
+            "text": text,
+        });
+
+        let result = self.send_request("paste", params).await?;
+        serde_json::from_value(result).context("Failed to parse paste result")
+    }
+
     /// Receive output from session PTY (returns base64-encoded data)
     pub async fn receive_output(&self, session_id: Uuid, timeout_ms: Option<u64>) -> Result<(String, usize)> {
         let mut params = serde_json::json!({
@@ -376,6 +868,48 @@ impl DaemonClient {
         Ok(status)
     }
 
+    /// Get hashes of the daemon's WebTransport certificates
+    pub async fn get_webtransport_certs(&self) -> Result<Vec<CertificateHash>> {
+        let result = self.send_request("get_webtransport_certs", serde_json::json!({})).await?;
+        let hashes: Vec<CertificateHash> = serde_json::from_value(result["hashes"].clone())
+            .context("Failed to parse certificate hashes")?;
+        Ok(hashes)
+    }
+
+    /// Issue a token another device can use to attach to a session
+    pub async fn create_attach_token(
+        &self,
+        session_id: Uuid,
+        scope: AttachScope,
+        ttl_seconds: Option<i64>,
+    ) -> Result<AttachToken> {
+        let params = serde_json::json!({
+            "session_id": session_id,
+            "scope": scope,
+            "ttl_seconds": ttl_seconds,
+        });
+
+        let result = self.send_request("create_attach_token", params).await?;
+        let token: AttachToken = serde_json::from_value(result)
+            .context("Failed to parse attach token")?;
+        Ok(token)
+    }
+
+    /// Push terminal appearance so remote clients render like the desktop
+    pub async fn set_theme(&self, appearance: &AppearanceSettings) -> Result<()> {
+        let params = serde_json::json!({
+            "color_scheme": appearance.color_scheme,
+            "font_family": appearance.font_family,
+            "font_size": appearance.font_size,
+            "line_height": appearance.line_height,
+            "cursor_style": appearance.cursor_style,
+            "cursor_blink": appearance.cursor_blink,
+        });
+
+        self.send_request("set_theme", params).await?;
+        Ok(())
+    }
+
     // ============= Workspace Methods =============
 
     /// Create a new workspace
@@ -464,6 +998,91 @@ impl DaemonClient {
         }
     }
 
+    /// List snapshot metadata for a workspace
+    pub async fn list_workspace_snapshot_summaries(&self, workspace_id: String) -> Result<Vec<SnapshotSummary>> {
+        let result = self.send_request("workspace_list_snapshot_summaries", serde_json::json!({
+            "workspace_id": workspace_id
+        })).await?;
+        let summaries: Vec<SnapshotSummary> = serde_json::from_value(result)
+            .context("Failed to parse snapshot summaries")?;
+        Ok(summaries)
+    }
+
+    /// Get snapshot disk usage
+    pub async fn workspace_snapshot_usage(&self) -> Result<SnapshotUsage> {
+        let result = self.send_request("workspace_snapshot_usage", serde_json::json!({})).await?;
+        let usage: SnapshotUsage = serde_json::from_value(result)
+            .context("Failed to parse snapshot usage")?;
+        Ok(usage)
+    }
+
+    /// Preview what restoring a snapshot would change
+    pub async fn diff_workspace_snapshot(&self, snapshot_id: String) -> Result<Option<SnapshotDiff>> {
+        let result = self.send_request("workspace_diff_snapshot", serde_json::json!({
+            "snapshot_id": snapshot_id
+        })).await?;
+        if result.is_null() {
+            Ok(None)
+        } else {
+            let diff: SnapshotDiff = serde_json::from_value(result)
+                .context("Failed to parse snapshot diff")?;
+            Ok(Some(diff))
+        }
+    }
+
+    /// Restore selected panes and sessions from a snapshot
+    pub async fn restore_workspace_snapshot_selection(
+        &self,
+        snapshot_id: String,
+        selection: RestoreSelection,
+    ) -> Result<Option<Workspace>> {
+        let result = self.send_request("workspace_restore_snapshot_selection", serde_json::json!({
+            "snapshot_id": snapshot_id,
+            "selection": selection
+        })).await?;
+        if result.is_null() {
+            Ok(None)
+        } else {
+            let workspace: Workspace = serde_json::from_value(result)
+                .context("Failed to parse workspace")?;
+            Ok(Some(workspace))
+        }
+    }
+
+    /// Get a workspace's typed layout tree
+    pub async fn get_layout_tree(&self, workspace_id: String) -> Result<Option<serde_json::Value>> {
+        let result = self.send_request("workspace_get_layout_tree", serde_json::json!({
+            "workspace_id": workspace_id
+        })).await?;
+        Ok(if result.is_null() { None } else { Some(result) })
+    }
+
+    /// Apply a layout edit (split, add_tab, close, move, resize, bind_session, focus)
+    pub async fn apply_layout_op(&self, workspace_id: String, op: serde_json::Value) -> Result<Option<Workspace>> {
+        let result = self.send_request("workspace_apply_layout_op", serde_json::json!({
+            "workspace_id": workspace_id,
+            "op": op
+        })).await?;
+        if result.is_null() {
+            Ok(None)
+        } else {
+            let workspace: Workspace = serde_json::from_value(result)
+                .context("Failed to parse workspace")?;
+            Ok(Some(workspace))
+        }
+    }
+
+    /// Export saved SSH hosts as an OpenSSH config or Ansible inventory
+    pub async fn export_inventory(&self, filter: InventoryFilter, format: InventoryFormat) -> Result<String> {
+        let result = self.send_request("workspace_export_inventory", serde_json::json!({
+            "filter": filter,
+            "format": format
+        })).await?;
+        let content: String = serde_json::from_value(result)
+            .context("Failed to parse inventory")?;
+        Ok(content)
+    }
+
     /// Disconnect from daemon
     pub async fn disconnect(&self) {
         *self.connection.lock().await = None;
@@ -1,11 +1,21 @@
 //! Tauri commands for daemon interaction
 
 use crate::daemon_client::{
-    CreateWorkspaceRequest, DaemonClient, SessionInfo, SessionType, UpdateWorkspaceRequest,
-    Workspace, WorkspaceFilter, WorkspaceSnapshot,
+    AttachScope, AttachToken, Bookmark, BookmarkView, Canary, CertificateHash, CommandRecord,
+    CreateWorkspaceRequest, DaemonClient,
+    InventoryFilter, InventoryFormat, PasteResult, RerunOutcome, RestoreSelection, SessionInfo, SessionTimeline, SessionType,
+    SnapshotDiff,
+    SnapshotSummary, SnapshotUsage, TailSource, Transcript, UpdateWorkspaceRequest, Workspace,
+    WorkspaceFilter,
+    WorkspaceSnapshot,
 };
+use crate::settings::SettingsManager;
+use serde::Serialize;
 use std::sync::Arc;
 use tauri::State;
+use terminal_core::{
+    check_paste, KeyPress, PasteWarning, SearchQuery, SearchResults, TranscriptFormat,
+};
 use uuid::Uuid;
 
 /// Create a new local terminal session via daemon
@@ -65,6 +75,30 @@ pub async fn daemon_create_ssh_session(
     Ok(session_id.to_string())
 }
 
+/// Create a session following log files, locally or over SSH
+#[tauri::command]
+pub async fn daemon_create_tail_session(
+    name: String,
+    sources: Vec<TailSource>,
+    daemon: State<'_, Arc<DaemonClient>>,
+) -> Result<String, String> {
+    // Ensure connected
+    if !daemon.is_connected().await {
+        daemon
+            .connect()
+            .await
+            .map_err(|e| format!("Failed to connect to daemon: {}", e))?;
+    }
+
+    // Create session
+    let session_id = daemon
+        .create_session(name, SessionType::Tail { sources }, None, None)
+        .await
+        .map_err(|e| format!("Failed to create session: {}", e))?;
+
+    Ok(session_id.to_string())
+}
+
 /// List all sessions from daemon
 #[tauri::command]
 pub async fn daemon_list_sessions(
@@ -88,6 +122,7 @@ pub async fn daemon_list_sessions(
 #[tauri::command]
 pub async fn daemon_attach_session(
     session_id: String,
+    read_only: Option<bool>,
     daemon: State<'_, Arc<DaemonClient>>,
 ) -> Result<(), String> {
     let session_uuid = Uuid::parse_str(&session_id)
@@ -104,7 +139,7 @@ pub async fn daemon_attach_session(
     }
 
     daemon
-        .attach_session(session_uuid, client_id)
+        .attach_session(session_uuid, client_id, read_only.unwrap_or(false))
         .await
         .map_err(|e| format!("Failed to attach to session: {}", e))
 }
@@ -159,16 +194,278 @@ pub async fn daemon_terminate_session(
         .map_err(|e| format!("Failed to terminate session: {}", e))
 }
 
+/// Mark a session as the one the user is working in
+#[tauri::command]
+pub async fn daemon_focus_session(
+    session_id: String,
+    daemon: State<'_, Arc<DaemonClient>>,
+) -> Result<(), String> {
+    let session_uuid = Uuid::parse_str(&session_id)
+        .map_err(|e| format!("Invalid session ID: {}", e))?;
+
+    // Ensure connected
+    if !daemon.is_connected().await {
+        daemon
+            .connect()
+            .await
+            .map_err(|e| format!("Failed to connect to daemon: {}", e))?;
+    }
+
+    daemon
+        .focus_session(session_uuid)
+        .await
+        .map_err(|e| format!("Failed to focus session: {}", e))
+}
+
+/// Find text in a session's scrollback, including output produced while
+/// no window was attached
+#[tauri::command]
+pub async fn daemon_search_scrollback(
+    session_id: String,
+    query: SearchQuery,
+    daemon: State<'_, Arc<DaemonClient>>,
+) -> Result<SearchResults, String> {
+    let session_uuid = Uuid::parse_str(&session_id)
+        .map_err(|e| format!("Invalid session ID: {}", e))?;
+
+    // Ensure connected
+    if !daemon.is_connected().await {
+        daemon
+            .connect()
+            .await
+            .map_err(|e| format!("Failed to connect to daemon: {}", e))?;
+    }
+
+    daemon
+        .search_scrollback(session_uuid, query)
+        .await
+        .map_err(|e| format!("Failed to search scrollback: {}", e))
+}
+
+/// Export a session's scrollback as plain text or standalone HTML, for
+/// sharing or attaching to a ticket
+#[tauri::command]
+pub async fn daemon_export_transcript(
+    session_id: String,
+    format: TranscriptFormat,
+    daemon: State<'_, Arc<DaemonClient>>,
+) -> Result<Transcript, String> {
+    let session_uuid = Uuid::parse_str(&session_id)
+        .map_err(|e| format!("Invalid session ID: {}", e))?;
+
+    // Ensure connected
+    if !daemon.is_connected().await {
+        daemon
+            .connect()
+            .await
+            .map_err(|e| format!("Failed to connect to daemon: {}", e))?;
+    }
+
+    daemon
+        .export_transcript(session_uuid, format)
+        .await
+        .map_err(|e| format!("Failed to export transcript: {}", e))
+}
+
+/// Bookmark a line of a session's output, with an optional note
+#[tauri::command]
+pub async fn daemon_add_bookmark(
+    session_id: String,
+    line: Option<u64>,
+    note: Option<String>,
+    daemon: State<'_, Arc<DaemonClient>>,
+) -> Result<Bookmark, String> {
+    let session_uuid = Uuid::parse_str(&session_id)
+        .map_err(|e| format!("Invalid session ID: {}", e))?;
+
+    // Ensure connected
+    if !daemon.is_connected().await {
+        daemon
+            .connect()
+            .await
+            .map_err(|e| format!("Failed to connect to daemon: {}", e))?;
+    }
+
+    daemon
+        .add_bookmark(session_uuid, line, note)
+        .await
+        .map_err(|e| format!("Failed to add bookmark: {}", e))
+}
+
+/// List a session's bookmarks
+#[tauri::command]
+pub async fn daemon_list_bookmarks(
+    session_id: String,
+    daemon: State<'_, Arc<DaemonClient>>,
+) -> Result<Vec<Bookmark>, String> {
+    let session_uuid = Uuid::parse_str(&session_id)
+        .map_err(|e| format!("Invalid session ID: {}", e))?;
+
+    // Ensure connected
+    if !daemon.is_connected().await {
+        daemon
+            .connect()
+            .await
+            .map_err(|e| format!("Failed to connect to daemon: {}", e))?;
+    }
+
+    daemon
+        .list_bookmarks(session_uuid)
+        .await
+        .map_err(|e| format!("Failed to list bookmarks: {}", e))
+}
+
+/// Remove a bookmark
+#[tauri::command]
+pub async fn daemon_remove_bookmark(
+    session_id: String,
+    bookmark_id: String,
+    daemon: State<'_, Arc<DaemonClient>>,
+) -> Result<(), String> {
+    let session_uuid = Uuid::parse_str(&session_id)
+        .map_err(|e| format!("Invalid session ID: {}", e))?;
+    let bookmark_uuid = Uuid::parse_str(&bookmark_id)
+        .map_err(|e| format!("Invalid bookmark ID: {}", e))?;
+
+    // Ensure connected
+    if !daemon.is_connected().await {
+        daemon
+            .connect()
+            .await
+            .map_err(|e| format!("Failed to connect to daemon: {}", e))?;
+    }
+
+    daemon
+        .remove_bookmark(session_uuid, bookmark_uuid)
+        .await
+        .map_err(|e| format!("Failed to remove bookmark: {}", e))
+}
+
+/// Fetch the output around a bookmark to scroll to it
+#[tauri::command]
+pub async fn daemon_jump_to_bookmark(
+    session_id: String,
+    bookmark_id: String,
+    context: Option<u64>,
+    daemon: State<'_, Arc<DaemonClient>>,
+) -> Result<BookmarkView, String> {
+    let session_uuid = Uuid::parse_str(&session_id)
+        .map_err(|e| format!("Invalid session ID: {}", e))?;
+    let bookmark_uuid = Uuid::parse_str(&bookmark_id)
+        .map_err(|e| format!("Invalid bookmark ID: {}", e))?;
+
+    // Ensure connected
+    if !daemon.is_connected().await {
+        daemon
+            .connect()
+            .await
+            .map_err(|e| format!("Failed to connect to daemon: {}", e))?;
+    }
+
+    daemon
+        .jump_to_bookmark(session_uuid, bookmark_uuid, context.unwrap_or(20))
+        .await
+        .map_err(|e| format!("Failed to jump to bookmark: {}", e))
+}
+
+/// Commands run in a session, with how long each took
+#[tauri::command]
+pub async fn daemon_command_timeline(
+    session_id: String,
+    daemon: State<'_, Arc<DaemonClient>>,
+) -> Result<Vec<CommandRecord>, String> {
+    let session_uuid = Uuid::parse_str(&session_id)
+        .map_err(|e| format!("Invalid session ID: {}", e))?;
+
+    // Ensure connected
+    if !daemon.is_connected().await {
+        daemon
+            .connect()
+            .await
+            .map_err(|e| format!("Failed to connect to daemon: {}", e))?;
+    }
+
+    daemon
+        .command_timeline(session_uuid)
+        .await
+        .map_err(|e| format!("Failed to get command timeline: {}", e))
+}
+
+/// Run a command from one session's history in other sessions, from the
+/// command palette; destructive commands come back for confirmation, and
+/// with a `canary` the others get it only once that session succeeds
+#[tauri::command]
+pub async fn daemon_rerun_command(
+    session_id: String,
+    command_id: String,
+    target_session_ids: Vec<String>,
+    confirmed: bool,
+    canary: Option<Canary>,
+    daemon: State<'_, Arc<DaemonClient>>,
+) -> Result<RerunOutcome, String> {
+    let session_uuid = Uuid::parse_str(&session_id)
+        .map_err(|e| format!("Invalid session ID: {}", e))?;
+    let command_uuid = Uuid::parse_str(&command_id)
+        .map_err(|e| format!("Invalid command ID: {}", e))?;
+    let targets = target_session_ids
+        .iter()
+        .map(|id| Uuid::parse_str(id).map_err(|e| format!("Invalid session ID: {}", e)))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    // Ensure connected
+    if !daemon.is_connected().await {
+        daemon
+            .connect()
+            .await
+            .map_err(|e| format!("Failed to connect to daemon: {}", e))?;
+    }
+
+    daemon
+        .rerun_command(session_uuid, command_uuid, targets, confirmed, canary)
+        .await
+        .map_err(|e| format!("Failed to re-run command: {}", e))
+}
+
+/// A session's history over a time range, for the timeline scrubber
+#[tauri::command]
+pub async fn daemon_session_timeline(
+    session_id: String,
+    from: Option<String>,
+    to: Option<String>,
+    daemon: State<'_, Arc<DaemonClient>>,
+) -> Result<SessionTimeline, String> {
+    let session_uuid = Uuid::parse_str(&session_id)
+        .map_err(|e| format!("Invalid session ID: {}", e))?;
+
+    // Ensure connected
+    if !daemon.is_connected().await {
+        daemon
+            .connect()
+            .await
+            .map_err(|e| format!("Failed to connect to daemon: {}", e))?;
+    }
+
+    daemon
+        .session_timeline(session_uuid, from, to)
+        .await
+        .map_err(|e| format!("Failed to get session timeline: {}", e))
+}
+
 /// Resize terminal in session
 #[tauri::command]
 pub async fn daemon_resize_terminal(
     session_id: String,
+    client_id: Option<String>,
     cols: u16,
     rows: u16,
     daemon: State<'_, Arc<DaemonClient>>,
 ) -> Result<(), String> {
     let session_uuid = Uuid::parse_str(&session_id)
         .map_err(|e| format!("Invalid session ID: {}", e))?;
+    let client_uuid = client_id
+        .map(|id| Uuid::parse_str(&id))
+        .transpose()
+        .map_err(|e| format!("Invalid client ID: {}", e))?;
 
     // Ensure connected
     if !daemon.is_connected().await {
@@ -179,7 +476,7 @@ pub async fn daemon_resize_terminal(
     }
 
     daemon
-        .resize_terminal(session_uuid, cols, rows)
+        .resize_terminal(session_uuid, client_uuid, cols, rows)
         .await
         .map_err(|e| format!("Failed to resize terminal: {}", e))
 }
@@ -210,6 +507,50 @@ pub async fn daemon_get_status(
     }))
 }
 
+/// Create a time-limited token for attaching to a session from another device
+#[tauri::command]
+pub async fn daemon_create_attach_token(
+    session_id: String,
+    scope: AttachScope,
+    ttl_seconds: Option<i64>,
+    daemon: State<'_, Arc<DaemonClient>>,
+) -> Result<AttachToken, String> {
+    let session_uuid = Uuid::parse_str(&session_id)
+        .map_err(|e| format!("Invalid session ID: {}", e))?;
+
+    // Ensure connected
+    if !daemon.is_connected().await {
+        daemon
+            .connect()
+            .await
+            .map_err(|e| format!("Failed to connect to daemon: {}", e))?;
+    }
+
+    daemon
+        .create_attach_token(session_uuid, scope, ttl_seconds)
+        .await
+        .map_err(|e| format!("Failed to create attach token: {}", e))
+}
+
+/// Get WebTransport certificate hashes for `serverCertificateHashes`
+#[tauri::command]
+pub async fn daemon_get_webtransport_certs(
+    daemon: State<'_, Arc<DaemonClient>>,
+) -> Result<Vec<CertificateHash>, String> {
+    // Ensure connected
+    if !daemon.is_connected().await {
+        daemon
+            .connect()
+            .await
+            .map_err(|e| format!("Failed to connect to daemon: {}", e))?;
+    }
+
+    daemon
+        .get_webtransport_certs()
+        .await
+        .map_err(|e| format!("Failed to get WebTransport certificates: {}", e))
+}
+
 /// Send input to session PTY
 #[tauri::command]
 pub async fn daemon_send_input(
@@ -234,6 +575,87 @@ pub async fn daemon_send_input(
         .map_err(|e| format!("Failed to send input: {}", e))
 }
 
+/// Send a key press to session PTY; the daemon encodes it for the
+/// session's keyboard protocol (legacy, modifyOtherKeys or kitty)
+#[tauri::command]
+pub async fn daemon_send_key(
+    session_id: String,
+    key: KeyPress,
+    daemon: State<'_, Arc<DaemonClient>>,
+) -> Result<usize, String> {
+    let session_uuid = Uuid::parse_str(&session_id)
+        .map_err(|e| format!("Invalid session ID: {}", e))?;
+
+    // Ensure connected
+    if !daemon.is_connected().await {
+        daemon
+            .connect()
+            .await
+            .map_err(|e| format!("Failed to connect to daemon: {}", e))?;
+    }
+
+    daemon
+        .send_key(session_uuid, key)
+        .await
+        .map_err(|e| format!("Failed to send key: {}", e))
+}
+
+/// Outcome of a paste through the paste guard
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "status", rename_all = "snake_case")]
+pub enum PasteOutcome {
+    Sent {
+        #[serde(flatten)]
+        result: PasteResult,
+    },
+    /// Nothing was sent; paste again with `confirmed` to send anyway
+    NeedsConfirmation { warnings: Vec<PasteWarning> },
+}
+
+/// Paste text into session PTY, holding it back for confirmation when the
+/// paste guard in security settings finds something risky
+#[tauri::command]
+pub async fn daemon_paste(
+    session_id: String,
+    text: String,
+    confirmed: bool,
+    daemon: State<'_, Arc<DaemonClient>>,
+    settings: State<'_, SettingsManager>,
+) -> Result<PasteOutcome, String> {
+    let session_uuid = Uuid::parse_str(&session_id)
+        .map_err(|e| format!("Invalid session ID: {}", e))?;
+
+    if !confirmed {
+        let security = settings.get_security().await;
+        let warnings: Vec<PasteWarning> = check_paste(&text)
+            .into_iter()
+            .filter(|warning| match warning {
+                PasteWarning::Newlines { .. } => security.warn_paste_newlines,
+                PasteWarning::ControlCharacters { .. } | PasteWarning::BracketEnd => {
+                    security.warn_paste_control_chars
+                }
+            })
+            .collect();
+        if !warnings.is_empty() {
+            return Ok(PasteOutcome::NeedsConfirmation { warnings });
+        }
+    }
+
+    // Ensure connected
+    if !daemon.is_connected().await {
+        daemon
+            .connect()
+            .await
+            .map_err(|e| format!("Failed to connect to daemon: {}", e))?;
+    }
+
+    daemon
+        .paste(session_uuid, text)
+        .await
+        .map(|result| PasteOutcome::Sent { result })
+        .map_err(|e| format!("Failed to paste: {}", e))
+}
+
 /// Receive output from session PTY
 #[tauri::command]
 pub async fn daemon_receive_output(
@@ -448,3 +870,155 @@ pub async fn workspace_restore_snapshot(
         .await
         .map_err(|e| format!("Failed to restore snapshot: {}", e))
 }
+
+/// List snapshot metadata for a workspace
+#[tauri::command]
+pub async fn workspace_list_snapshot_summaries(
+    workspace_id: String,
+    daemon: State<'_, Arc<DaemonClient>>,
+) -> Result<Vec<SnapshotSummary>, String> {
+    // Ensure connected
+    if !daemon.is_connected().await {
+        daemon
+            .connect()
+            .await
+            .map_err(|e| format!("Failed to connect to daemon: {}", e))?;
+    }
+
+    daemon
+        .list_workspace_snapshot_summaries(workspace_id)
+        .await
+        .map_err(|e| format!("Failed to list snapshots: {}", e))
+}
+
+/// Get snapshot disk usage
+#[tauri::command]
+pub async fn workspace_snapshot_usage(
+    daemon: State<'_, Arc<DaemonClient>>,
+) -> Result<SnapshotUsage, String> {
+    // Ensure connected
+    if !daemon.is_connected().await {
+        daemon
+            .connect()
+            .await
+            .map_err(|e| format!("Failed to connect to daemon: {}", e))?;
+    }
+
+    daemon
+        .workspace_snapshot_usage()
+        .await
+        .map_err(|e| format!("Failed to get snapshot usage: {}", e))
+}
+
+/// Preview what restoring a snapshot would change
+#[tauri::command]
+pub async fn workspace_diff_snapshot(
+    snapshot_id: String,
+    daemon: State<'_, Arc<DaemonClient>>,
+) -> Result<Option<SnapshotDiff>, String> {
+    // Ensure connected
+    if !daemon.is_connected().await {
+        daemon
+            .connect()
+            .await
+            .map_err(|e| format!("Failed to connect to daemon: {}", e))?;
+    }
+
+    daemon
+        .diff_workspace_snapshot(snapshot_id)
+        .await
+        .map_err(|e| format!("Failed to diff snapshot: {}", e))
+}
+
+/// Restore selected panes and sessions from a snapshot
+#[tauri::command]
+pub async fn workspace_restore_snapshot_selection(
+    snapshot_id: String,
+    selection: RestoreSelection,
+    daemon: State<'_, Arc<DaemonClient>>,
+) -> Result<Option<Workspace>, String> {
+    // Ensure connected
+    if !daemon.is_connected().await {
+        daemon
+            .connect()
+            .await
+            .map_err(|e| format!("Failed to connect to daemon: {}", e))?;
+    }
+
+    daemon
+        .restore_workspace_snapshot_selection(snapshot_id, selection)
+        .await
+        .map_err(|e| format!("Failed to restore snapshot: {}", e))
+}
+
+/// Get a workspace's typed layout tree
+#[tauri::command]
+pub async fn workspace_get_layout_tree(
+    workspace_id: String,
+    daemon: State<'_, Arc<DaemonClient>>,
+) -> Result<Option<serde_json::Value>, String> {
+    // Ensure connected
+    if !daemon.is_connected().await {
+        daemon
+            .connect()
+            .await
+            .map_err(|e| format!("Failed to connect to daemon: {}", e))?;
+    }
+
+    daemon
+        .get_layout_tree(workspace_id)
+        .await
+        .map_err(|e| format!("Failed to get layout: {}", e))
+}
+
+/// Apply a layout edit to a workspace
+#[tauri::command]
+pub async fn workspace_apply_layout_op(
+    workspace_id: String,
+    op: serde_json::Value,
+    daemon: State<'_, Arc<DaemonClient>>,
+) -> Result<Option<Workspace>, String> {
+    // Ensure connected
+    if !daemon.is_connected().await {
+        daemon
+            .connect()
+            .await
+            .map_err(|e| format!("Failed to connect to daemon: {}", e))?;
+    }
+
+    daemon
+        .apply_layout_op(workspace_id, op)
+        .await
+        .map_err(|e| format!("Failed to update layout: {}", e))
+}
+
+/// Export saved SSH hosts as an OpenSSH config or Ansible inventory
+///
+/// When `path` is given the rendered inventory is also written to disk.
+#[tauri::command]
+pub async fn workspace_export_inventory(
+    format: InventoryFormat,
+    filter: Option<InventoryFilter>,
+    path: Option<String>,
+    daemon: State<'_, Arc<DaemonClient>>,
+) -> Result<String, String> {
+    // Ensure connected
+    if !daemon.is_connected().await {
+        daemon
+            .connect()
+            .await
+            .map_err(|e| format!("Failed to connect to daemon: {}", e))?;
+    }
+
+    let content = daemon
+        .export_inventory(filter.unwrap_or_default(), format)
+        .await
+        .map_err(|e| format!("Failed to export inventory: {}", e))?;
+
+    if let Some(path) = path {
+        pulsar_fs::write(&path, &content)
+            .map_err(|e| format!("Failed to write inventory to {}: {}", path, e))?;
+    }
+
+    Ok(content)
+}
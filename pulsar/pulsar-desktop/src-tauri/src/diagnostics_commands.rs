@@ -0,0 +1,239 @@
+//! Tauri commands for the diagnostics bundle attached to bug reports
+
+use crate::daemon_client::DaemonClient;
+use crate::diagnostics::{
+    self, DiagnosticsBundle, DiagnosticsManifest, DiagnosticsReview, ReviewChoices,
+};
+use crate::export;
+use serde_json::{json, Map, Value};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use tauri::State;
+use tokio::sync::Mutex;
+
+type CommandResult<T> = Result<T, String>;
+
+/// The bundle collected for review, until it is saved
+#[derive(Default)]
+pub struct PendingDiagnostics(Mutex<Option<DiagnosticsBundle>>);
+
+/// Collect a redacted diagnostics bundle and return it for review
+///
+/// Nothing is written until `diagnostics_save`; collecting again replaces
+/// the pending bundle.
+#[tauri::command]
+pub async fn diagnostics_collect(
+    daemon: State<'_, Arc<DaemonClient>>,
+    pending: State<'_, PendingDiagnostics>,
+) -> CommandResult<DiagnosticsReview> {
+    let mut bundle = DiagnosticsBundle::new();
+    let config_dir = dirs::config_dir()
+        .ok_or("Could not find config directory")?
+        .join("orbit");
+
+    // Ensure connected
+    let connected = daemon.is_connected().await || daemon.connect().await.is_ok();
+    let daemon_status = if connected {
+        match daemon.get_status().await {
+            Ok(status) => json!(status),
+            Err(e) => json!({ "error": e.to_string() }),
+        }
+    } else {
+        json!({ "error": "daemon is not running" })
+    };
+    bundle
+        .add_json(
+            "versions.json",
+            "App, daemon and platform versions",
+            &json!({
+                "app_version": env!("CARGO_PKG_VERSION"),
+                "os": std::env::consts::OS,
+                "arch": std::env::consts::ARCH,
+                "daemon": daemon_status,
+            }),
+        )
+        .map_err(|e| e.to_string())?;
+
+    add_file(
+        &mut bundle,
+        "config/pulsar_settings.toml",
+        "Desktop settings",
+        &config_dir.join("pulsar_settings.toml"),
+    );
+
+    // The daemons' configs say where their logs, databases and sockets are
+    let daemon_config_path = std::env::var_os("PULSAR_CONFIG")
+        .map(PathBuf::from)
+        .unwrap_or_else(|| config_dir.join("pulsar.toml"));
+    let daemon_config: toml::Value = add_file(
+        &mut bundle,
+        "config/pulsar.toml",
+        "pulsar-daemon config, secrets removed",
+        &daemon_config_path,
+    )
+    .and_then(|text| toml::from_str(&text).ok())
+    .unwrap_or_else(|| toml::Value::Table(Default::default()));
+
+    let orbit_config_path = std::env::var_os("ORBIT_CONFIG")
+        .map(PathBuf::from)
+        .unwrap_or_else(|| config_dir.join("config.yaml"));
+    let orbit_config: serde_yaml::Value = add_file(
+        &mut bundle,
+        "config/orbit.yaml",
+        "orbitd config, secrets removed",
+        &orbit_config_path,
+    )
+    .and_then(|text| serde_yaml::from_str(&text).ok())
+    .unwrap_or_default();
+
+    let daemon_path = |key: &str| {
+        daemon_config
+            .get(key)
+            .and_then(|v| v.as_str())
+            .map(PathBuf::from)
+    };
+    let daemon_port = |key: &str, default: u16| {
+        daemon_config
+            .get(key)
+            .and_then(|v| v.as_integer())
+            .and_then(|port| u16::try_from(port).ok())
+            .unwrap_or(default)
+    };
+    let orbit_daemon = &orbit_config["daemon"];
+
+    // Recent logs
+    let daemon_log_dir = daemon_config
+        .get("log")
+        .and_then(|log| log.get("file"))
+        .and_then(|file| file.get("dir"))
+        .and_then(|dir| dir.as_str())
+        .map(PathBuf::from);
+    let orbit_log_dir = orbit_daemon["log_file"]["dir"].as_str().map(PathBuf::from);
+    for (name, dir) in [("pulsar-daemon", daemon_log_dir), ("orbitd", orbit_log_dir)] {
+        let file = format!("logs/{}.log", name);
+        let description = format!("End of the {} log", name);
+        match dir {
+            Some(dir) => match diagnostics::log_tail(&dir.join(format!("{}.log", name))) {
+                Ok(tail) => bundle.add_text(&file, &description, &tail),
+                Err(e) => bundle.add_unavailable(&file, &description, e),
+            },
+            None => bundle.add_unavailable(&file, &description, "file logging is off"),
+        }
+    }
+
+    // Database integrity
+    let databases = [
+        ("vault", Some(config_dir.join("pulsar_vault.db"))),
+        (
+            "workspaces",
+            daemon_path("database_path")
+                .or_else(|| dirs::config_dir().map(|dir| dir.join("pulsar").join("workspaces.db"))),
+        ),
+        ("learning", export::learning_db_path()),
+    ];
+    let mut health = Map::new();
+    for (name, path) in databases {
+        let report = match path {
+            Some(path) => match diagnostics::database_health(&path).await {
+                Some(Ok(health)) => json!(health),
+                Some(Err(e)) => json!({ "path": path, "error": e.to_string() }),
+                None => json!({ "path": path, "error": "does not exist" }),
+            },
+            None => json!({ "error": "location unknown" }),
+        };
+        health.insert(name.to_string(), report);
+    }
+    bundle
+        .add_json(
+            "databases.json",
+            "Database sizes, journal modes and integrity check results",
+            &Value::Object(health),
+        )
+        .map_err(|e| e.to_string())?;
+
+    // Who may use the daemons' sockets
+    let orbit_socket = orbit_daemon["socket_path"]
+        .as_str()
+        .map(PathBuf::from)
+        .or_else(|| dirs::home_dir().map(|home| home.join(".orbit").join("daemon.sock")));
+    let sockets: Vec<_> = [
+        Some(daemon_path("socket_path").unwrap_or_else(|| daemon.socket_path().to_path_buf())),
+        orbit_socket,
+    ]
+    .into_iter()
+    .flatten()
+    .map(|path| diagnostics::socket_report(&path))
+    .collect();
+    bundle
+        .add_json(
+            "sockets.json",
+            "Daemon socket owners and permissions",
+            &sockets,
+        )
+        .map_err(|e| e.to_string())?;
+
+    let ports = vec![
+        diagnostics::port_report("websocket", daemon_port("websocket_port", 3030), false),
+        diagnostics::port_report("grpc", daemon_port("grpc_port", 50051), false),
+        diagnostics::port_report("webtransport", daemon_port("webtransport_port", 4433), true),
+    ];
+    bundle
+        .add_json(
+            "ports.json",
+            "Whether the daemon's ports are bound, and where",
+            &ports,
+        )
+        .map_err(|e| e.to_string())?;
+
+    let review = bundle.review();
+    *pending.0.lock().await = Some(bundle);
+    Ok(review)
+}
+
+/// Write the reviewed bundle to `path`, leaving out the files and redacting
+/// the terms chosen during review
+///
+/// A relative `path` is taken from the downloads folder. The bundle stays
+/// pending if it can't be written, so another path can be tried.
+#[tauri::command]
+pub async fn diagnostics_save(
+    path: String,
+    choices: ReviewChoices,
+    pending: State<'_, PendingDiagnostics>,
+) -> CommandResult<DiagnosticsManifest> {
+    let mut pending = pending.0.lock().await;
+    let bundle = pending
+        .clone()
+        .ok_or("Collect diagnostics before saving them")?;
+
+    let mut path = PathBuf::from(path);
+    if path.is_relative() {
+        if let Some(dir) = dirs::download_dir().or_else(dirs::home_dir) {
+            path = dir.join(path);
+        }
+    }
+    let manifest = bundle
+        .write_zip(&path, &choices)
+        .map_err(|e| format!("Failed to write diagnostics: {}", e))?;
+    *pending = None;
+    Ok(manifest)
+}
+
+/// Add the text file at `path`, returning its unredacted contents
+fn add_file(
+    bundle: &mut DiagnosticsBundle,
+    name: &str,
+    description: &str,
+    path: &Path,
+) -> Option<String> {
+    match std::fs::read_to_string(path) {
+        Ok(text) => {
+            bundle.add_text(name, description, &text);
+            Some(text)
+        }
+        Err(e) => {
+            bundle.add_unavailable(name, description, e);
+            None
+        }
+    }
+}
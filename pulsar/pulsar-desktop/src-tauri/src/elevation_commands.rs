@@ -0,0 +1,73 @@
+// Tauri commands for running Orbit commands that need root
+//
+// The frontend first calls `run_elevated_command` without a password so
+// sudo's cached credentials are tried. On `password_required` it either
+// prompts the user or passes the id of a password credential from the
+// vault. The password goes straight to orbitd and is not kept here.
+// Destructive commands are refused until called again with `confirmed`.
+
+use orbitd::executor::elevation::{ElevatedRun, SudoPassword};
+use tauri::State;
+
+use crate::vault::{DecryptedCredentialData, Vault};
+
+/// Result type for commands
+type CommandResult<T> = Result<T, String>;
+
+/// Run an approved command through sudo via the Orbit daemon
+#[tauri::command]
+pub async fn run_elevated_command(
+    vault: State<'_, Vault>,
+    command: String,
+    password: Option<String>,
+    vault_credential_id: Option<String>,
+    confirmed: Option<bool>,
+) -> CommandResult<ElevatedRun> {
+    let confirmed = confirmed.unwrap_or(false);
+    let from_vault = vault_credential_id.is_some();
+    let password = match vault_credential_id {
+        Some(id) => {
+            let credential = vault
+                .with_manager(|manager| Box::pin(async move { manager.get_credential(&id).await }))
+                .await
+                .map_err(|e| e.to_string())?;
+            match credential.data {
+                DecryptedCredentialData::Password(data) => Some(SudoPassword::new(data.password)),
+                _ => {
+                    return Err(format!(
+                        "Vault credential '{}' is not a password",
+                        credential.name
+                    ))
+                }
+            }
+        }
+        None => password.map(SudoPassword::new),
+    };
+
+    #[cfg(unix)]
+    {
+        use orbitd::config::Config;
+        use orbitd::daemon::ipc::{call, Request, Response};
+
+        let config = Config::load().await.map_err(|e| e.to_string())?;
+        let request = Request::RunElevated {
+            command,
+            password,
+            from_vault,
+            confirmed,
+        };
+
+        match call(&config.daemon.socket_path, &request).await {
+            Ok(Response::Elevated { run }) => Ok(run),
+            Ok(Response::Error { message }) => Err(message),
+            Ok(other) => Err(format!("Unexpected response from Orbit: {:?}", other)),
+            Err(e) => Err(format!("Orbit daemon is not reachable: {}", e)),
+        }
+    }
+
+    #[cfg(not(unix))]
+    {
+        let _ = (command, password, from_vault, confirmed);
+        Err("Privilege elevation is only available on Unix".to_string())
+    }
+}
@@ -0,0 +1,217 @@
+// User data export for Pulsar
+//
+// This module provides:
+// - A zip bundle of everything the app stores about the user
+// - A manifest describing each file and what was left out
+// - Read-only access to orbitd's learning database
+//
+// Secrets never leave their stores: vault contents, provider API keys and
+// database encryption keys are not exported.
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use pulsar_db::{DbOptions, FieldCipher};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use zip::write::SimpleFileOptions;
+
+/// Bumped when the layout of the bundle changes
+pub const EXPORT_FORMAT_VERSION: u32 = 1;
+
+/// Tables exported from learning.db
+const LEARNING_TABLES: &[&str] = &[
+    "preferences",
+    "command_patterns",
+    "corrections",
+    "execution_history",
+    "temporal_patterns",
+    "command_analytics",
+    "insights",
+];
+
+/// Data deliberately left out of every export
+const EXCLUDED: &[&str] = &[
+    "Vault contents (passwords, SSH private keys, certificates)",
+    "AI provider API keys (kept in the OS keychain)",
+    "Database encryption keys",
+];
+
+/// Describes one file in the bundle
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ExportedFile {
+    pub name: String,
+    pub description: String,
+    /// Number of records in the file
+    pub records: usize,
+    /// Why the source could not be read; the file is then absent
+    pub error: Option<String>,
+}
+
+/// `manifest.json` at the root of the bundle
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ExportManifest {
+    pub format_version: u32,
+    pub app_version: String,
+    pub created_at: DateTime<Utc>,
+    pub files: Vec<ExportedFile>,
+    pub excluded: Vec<String>,
+}
+
+/// Collects JSON files and writes them as a zip
+#[derive(Default)]
+pub struct UserDataExport {
+    files: Vec<(ExportedFile, Option<Vec<u8>>)>,
+}
+
+impl UserDataExport {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a JSON file to the bundle
+    pub fn add_json<T: Serialize>(
+        &mut self,
+        name: &str,
+        description: &str,
+        records: usize,
+        value: &T,
+    ) -> Result<()> {
+        let contents = serde_json::to_vec_pretty(value)
+            .with_context(|| format!("Failed to serialize {}", name))?;
+        self.files.push((
+            ExportedFile {
+                name: name.to_string(),
+                description: description.to_string(),
+                records,
+                error: None,
+            },
+            Some(contents),
+        ));
+        Ok(())
+    }
+
+    /// Record a source that could not be read, so the manifest says so
+    pub fn add_unavailable(&mut self, name: &str, description: &str, error: impl std::fmt::Display) {
+        tracing::warn!("User data export: {} unavailable: {}", name, error);
+        self.files.push((
+            ExportedFile {
+                name: name.to_string(),
+                description: description.to_string(),
+                records: 0,
+                error: Some(error.to_string()),
+            },
+            None,
+        ));
+    }
+
+    /// Write the bundle and its manifest to `path`
+    pub fn write_zip(self, path: &Path) -> Result<ExportManifest> {
+        let manifest = ExportManifest {
+            format_version: EXPORT_FORMAT_VERSION,
+            app_version: env!("CARGO_PKG_VERSION").to_string(),
+            created_at: Utc::now(),
+            files: self.files.iter().map(|(file, _)| file.clone()).collect(),
+            excluded: EXCLUDED.iter().map(|s| s.to_string()).collect(),
+        };
+
+        // Written beside `path` and moved into place once complete, so a
+        // failed export never leaves a truncated zip behind
+        let file = pulsar_fs::AtomicFile::create(path)
+            .with_context(|| format!("Failed to create {}", path.display()))?;
+        let mut zip = zip::ZipWriter::new(file);
+        let options = SimpleFileOptions::default().compression_method(zip::CompressionMethod::Deflated);
+
+        zip.start_file("manifest.json", options)?;
+        zip.write_all(&serde_json::to_vec_pretty(&manifest)?)?;
+        for (file, contents) in &self.files {
+            if let Some(contents) = contents {
+                zip.start_file(file.name.as_str(), options)?;
+                zip.write_all(contents)?;
+            }
+        }
+        zip.finish()?
+            .commit()
+            .with_context(|| format!("Failed to write {}", path.display()))?;
+
+        tracing::info!("Exported user data to {}", path.display());
+        Ok(manifest)
+    }
+}
+
+/// Location of orbitd's learning database
+pub fn learning_db_path() -> Option<PathBuf> {
+    let dir = match std::env::var_os("ORBIT_DATA_DIR") {
+        Some(dir) => PathBuf::from(dir),
+        None => dirs::data_dir()?.join("orbit"),
+    };
+    Some(dir.join("learning.db"))
+}
+
+/// Every learning table present in `db_path`, keyed by table name
+///
+/// Encrypted history is decrypted when orbitd's key is in the keychain;
+/// otherwise it is exported still encrypted.
+pub async fn learning_data(db_path: &Path) -> Result<(Map<String, Value>, usize)> {
+    if !db_path.exists() {
+        return Ok((Map::new(), 0));
+    }
+
+    let pool = pulsar_db::open(db_path, &DbOptions::default()).await?;
+    let cipher = FieldCipher::from_existing_keychain("orbit", "learning-db")
+        .ok()
+        .flatten()
+        .unwrap_or_else(FieldCipher::disabled);
+
+    let existing: Vec<String> = sqlx::query_scalar("SELECT name FROM sqlite_master WHERE type = 'table'")
+        .fetch_all(&pool)
+        .await?;
+
+    let mut tables = Map::new();
+    let mut records = 0;
+    for table in LEARNING_TABLES.iter().filter(|t| existing.iter().any(|e| e == *t)) {
+        let rows = pulsar_db::export_table(&pool, table, &cipher).await?;
+        records += rows.len();
+        tables.insert(table.to_string(), Value::from(rows.into_iter().map(Value::Object).collect::<Vec<_>>()));
+    }
+
+    pool.close().await;
+    Ok((tables, records))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Read;
+
+    #[test]
+    fn test_bundle_contains_manifest_and_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("export.zip");
+
+        let mut export = UserDataExport::new();
+        export
+            .add_json("settings.json", "Application settings", 1, &serde_json::json!({"theme": "dark"}))
+            .unwrap();
+        export.add_unavailable("sessions.json", "Terminal sessions", "daemon not running");
+        let manifest = export.write_zip(&path).unwrap();
+
+        assert_eq!(manifest.files.len(), 2);
+        assert!(manifest.files[1].error.is_some());
+        assert!(!manifest.excluded.is_empty());
+
+        let mut archive = zip::ZipArchive::new(std::fs::File::open(&path).unwrap()).unwrap();
+        let mut contents = String::new();
+        archive
+            .by_name("manifest.json")
+            .unwrap()
+            .read_to_string(&mut contents)
+            .unwrap();
+        let read_back: ExportManifest = serde_json::from_str(&contents).unwrap();
+        assert_eq!(read_back.format_version, EXPORT_FORMAT_VERSION);
+
+        assert!(archive.by_name("settings.json").is_ok());
+        assert!(archive.by_name("sessions.json").is_err());
+    }
+}
@@ -0,0 +1,84 @@
+//! Tauri commands for exporting user data
+
+use crate::daemon_client::{DaemonClient, WorkspaceFilter};
+use crate::export::{self, ExportManifest, UserDataExport};
+use crate::notifications::NotificationService;
+use crate::settings::SettingsManager;
+use std::path::PathBuf;
+use std::sync::Arc;
+use tauri::State;
+
+type CommandResult<T> = Result<T, String>;
+
+/// Export learning history, session metadata, settings and notification
+/// history (no secrets) as a zip at `path`
+///
+/// Sources that can't be read (e.g. the daemon is not running) are listed
+/// in the manifest with the reason instead of failing the export.
+#[tauri::command]
+pub async fn export_user_data(
+    path: String,
+    daemon: State<'_, Arc<DaemonClient>>,
+    settings: State<'_, SettingsManager>,
+    notifications: State<'_, NotificationService>,
+) -> CommandResult<ExportManifest> {
+    let mut bundle = UserDataExport::new();
+
+    bundle
+        .add_json("settings.json", "Application settings", 1, &settings.get_all().await)
+        .map_err(|e| e.to_string())?;
+
+    let history = notifications.history().await;
+    bundle
+        .add_json(
+            "notifications.json",
+            "Notifications shown since the app started",
+            history.len(),
+            &history,
+        )
+        .map_err(|e| e.to_string())?;
+
+    // Ensure connected
+    let connected = daemon.is_connected().await || daemon.connect().await.is_ok();
+
+    const SESSIONS: &str = "Terminal sessions known to the daemon (names, hosts, timestamps)";
+    const WORKSPACES: &str = "Workspaces and their layouts";
+    if connected {
+        match daemon.list_sessions().await {
+            Ok(sessions) => bundle
+                .add_json("sessions.json", SESSIONS, sessions.len(), &sessions)
+                .map_err(|e| e.to_string())?,
+            Err(e) => bundle.add_unavailable("sessions.json", SESSIONS, e),
+        }
+
+        let filter = WorkspaceFilter {
+            is_template: None,
+            tags: None,
+            search: None,
+        };
+        match daemon.list_workspaces(filter).await {
+            Ok(workspaces) => bundle
+                .add_json("workspaces.json", WORKSPACES, workspaces.len(), &workspaces)
+                .map_err(|e| e.to_string())?,
+            Err(e) => bundle.add_unavailable("workspaces.json", WORKSPACES, e),
+        }
+    } else {
+        bundle.add_unavailable("sessions.json", SESSIONS, "daemon is not running");
+        bundle.add_unavailable("workspaces.json", WORKSPACES, "daemon is not running");
+    }
+
+    const LEARNING: &str = "Learned command patterns, corrections, command history and analytics";
+    match export::learning_db_path() {
+        Some(db_path) => match export::learning_data(&db_path).await {
+            Ok((tables, records)) => bundle
+                .add_json("learning.json", LEARNING, records, &tables)
+                .map_err(|e| e.to_string())?,
+            Err(e) => bundle.add_unavailable("learning.json", LEARNING, e),
+        },
+        None => bundle.add_unavailable("learning.json", LEARNING, "data directory not found"),
+    }
+
+    bundle
+        .write_zip(&PathBuf::from(path))
+        .map_err(|e| format!("Failed to write export: {}", e))
+}
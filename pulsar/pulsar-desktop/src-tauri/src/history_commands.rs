@@ -0,0 +1,35 @@
+// Tauri commands for searching Orbit's command history from the command palette
+
+use orbitd::learning::HistoryMatch;
+
+/// Result type for commands
+type CommandResult<T> = Result<T, String>;
+
+/// Past commands matching a natural-language query, best match first
+#[tauri::command]
+pub async fn search_command_history(
+    query: String,
+    limit: Option<usize>,
+) -> CommandResult<Vec<HistoryMatch>> {
+    #[cfg(unix)]
+    {
+        use orbitd::config::Config;
+        use orbitd::daemon::ipc::{call, Request, Response};
+
+        let config = Config::load().await.map_err(|e| e.to_string())?;
+        let request = Request::SearchHistory { query, limit };
+
+        match call(&config.daemon.socket_path, &request).await {
+            Ok(Response::History { matches }) => Ok(matches),
+            Ok(Response::Error { message }) => Err(message),
+            Ok(other) => Err(format!("Unexpected response from Orbit: {:?}", other)),
+            Err(e) => Err(format!("Orbit daemon is not reachable: {}", e)),
+        }
+    }
+
+    #[cfg(not(unix))]
+    {
+        let _ = (query, limit);
+        Err("History search is only available on Unix".to_string())
+    }
+}
@@ -6,21 +6,32 @@ use tauri::Manager;
 
 mod autostart_commands;
 mod commands;
+mod container_commands;
 mod daemon_client;
 mod daemon_commands;
+mod diagnostics;
+mod diagnostics_commands;
+mod elevation_commands;
+mod export;
+mod export_commands;
+mod history_commands;
 mod notifications;
 mod notification_commands;
 mod settings;
 mod settings_commands;
 mod ssh_manager;
+mod telemetry;
+mod telemetry_commands;
 mod vault;
 mod vault_commands;
 
 use autostart_commands::AutoStartState;
 use daemon_client::DaemonClient;
+use diagnostics_commands::PendingDiagnostics;
 use notifications::NotificationService;
 use settings::SettingsManager;
 use ssh_manager::SshManager;
+use telemetry::Telemetry;
 use vault::Vault;
 
 #[tokio::main]
@@ -38,11 +49,17 @@ async fn main() {
     // Create SSH manager (legacy, for backward compatibility)
     let ssh_manager = Arc::new(SshManager::new());
 
-    // Create daemon client
+    // Create daemon client (the daemon's default socket_path)
+    #[cfg(unix)]
     let socket_path = dirs::config_dir()
         .expect("Could not find config directory")
         .join("orbit")
         .join("pulsar.sock");
+    #[cfg(windows)]
+    let socket_path = std::path::PathBuf::from(format!(
+        r"\\.\pipe\pulsar-daemon-{}",
+        std::env::var("USERNAME").unwrap_or_default()
+    ));
 
     let daemon_client = Arc::new(DaemonClient::new(socket_path));
 
@@ -64,11 +81,18 @@ async fn main() {
         .expect("Could not find config directory")
         .join("orbit");
 
-    let settings_manager = SettingsManager::new(config_dir)
+    let settings_manager = SettingsManager::new(config_dir.clone())
         .expect("Failed to initialize settings");
 
     tracing::info!("Settings initialized");
 
+    // Anonymous usage analytics, only while `send_analytics` is on
+    let telemetry = Arc::new(Telemetry::new(
+        config_dir,
+        settings_manager.get_general().await.send_analytics,
+    ));
+    tokio::spawn(Arc::clone(&telemetry).run());
+
     // Initialize auto-start state
     let autostart_state = AutoStartState::new();
 
@@ -80,11 +104,18 @@ async fn main() {
         .manage(vault)
         .manage(settings_manager)
         .manage(autostart_state)
+        .manage(Arc::clone(&telemetry))
+        .manage(PendingDiagnostics::default())
         .setup(|app| {
             // Initialize notification service after app is set up
             let app_handle = app.handle().clone();
-            let notification_service = NotificationService::new(app_handle);
+            let notification_service = NotificationService::new(app_handle.clone());
             app.manage(notification_service);
+            let daemon = Arc::clone(app.state::<Arc<DaemonClient>>().inner());
+            notifications::spawn_command_watcher(app_handle.clone(), daemon);
+            // Reconnect SSH sessions stranded by a network change
+            let ssh_manager = Arc::clone(app.state::<Arc<SshManager>>().inner());
+            ssh_manager::spawn_network_watcher(app_handle, ssh_manager);
 
             #[cfg(debug_assertions)]
             {
@@ -94,7 +125,7 @@ async fn main() {
 
             Ok(())
         })
-        .invoke_handler(tauri::generate_handler![
+        .invoke_handler(counted(telemetry, tauri::generate_handler![
             // Legacy SSH commands (direct connection)
             commands::connect_ssh,
             commands::disconnect_ssh,
@@ -102,19 +133,35 @@ async fn main() {
             commands::receive_output,
             commands::resize_terminal,
             commands::get_fingerprint,
+            commands::get_input_stats,
             commands::check_ssh_agent,
             commands::list_agent_identities,
             // New daemon commands (via pulsar-daemon)
             daemon_commands::daemon_create_local_session,
             daemon_commands::daemon_create_ssh_session,
+            daemon_commands::daemon_create_tail_session,
             daemon_commands::daemon_list_sessions,
             daemon_commands::daemon_attach_session,
             daemon_commands::daemon_detach_session,
             daemon_commands::daemon_terminate_session,
+            daemon_commands::daemon_focus_session,
+            daemon_commands::daemon_search_scrollback,
+            daemon_commands::daemon_export_transcript,
+            daemon_commands::daemon_add_bookmark,
+            daemon_commands::daemon_list_bookmarks,
+            daemon_commands::daemon_remove_bookmark,
+            daemon_commands::daemon_jump_to_bookmark,
+            daemon_commands::daemon_command_timeline,
+            daemon_commands::daemon_session_timeline,
+            daemon_commands::daemon_rerun_command,
             daemon_commands::daemon_resize_terminal,
             daemon_commands::daemon_send_input,
+            daemon_commands::daemon_send_key,
+            daemon_commands::daemon_paste,
             daemon_commands::daemon_receive_output,
             daemon_commands::daemon_get_status,
+            daemon_commands::daemon_get_webtransport_certs,
+            daemon_commands::daemon_create_attach_token,
             daemon_commands::daemon_check_connection,
             // Workspace commands
             daemon_commands::workspace_create,
@@ -125,6 +172,13 @@ async fn main() {
             daemon_commands::workspace_save_snapshot,
             daemon_commands::workspace_list_snapshots,
             daemon_commands::workspace_restore_snapshot,
+            daemon_commands::workspace_list_snapshot_summaries,
+            daemon_commands::workspace_snapshot_usage,
+            daemon_commands::workspace_diff_snapshot,
+            daemon_commands::workspace_restore_snapshot_selection,
+            daemon_commands::workspace_get_layout_tree,
+            daemon_commands::workspace_apply_layout_op,
+            daemon_commands::workspace_export_inventory,
             // Vault commands
             vault_commands::vault_get_state,
             vault_commands::vault_is_initialized,
@@ -141,6 +195,9 @@ async fn main() {
             vault_commands::vault_list_credentials_by_type,
             vault_commands::vault_find_credentials_by_host,
             vault_commands::vault_delete_credential,
+            vault_commands::vault_db_health,
+            vault_commands::vault_db_backup,
+            vault_commands::vault_db_vacuum,
             // Settings commands
             settings_commands::settings_get_all,
             settings_commands::settings_get_appearance,
@@ -168,6 +225,15 @@ async fn main() {
             notification_commands::notify_error,
             notification_commands::notify_test,
             notification_commands::notifications_cleanup,
+            // User data export
+            export_commands::export_user_data,
+            // Orbit command history search
+            history_commands::search_command_history,
+            // Orbit privilege elevation
+            elevation_commands::run_elevated_command,
+            // Orbit execution targets (host or container)
+            container_commands::run_in_target,
+            container_commands::list_containers,
             // Auto-start commands
             autostart_commands::autostart_set_daemon_path,
             autostart_commands::autostart_is_installed,
@@ -179,7 +245,23 @@ async fn main() {
             autostart_commands::autostart_start,
             autostart_commands::autostart_stop,
             autostart_commands::autostart_get_status,
-        ])
+            // Usage analytics
+            telemetry_commands::telemetry_preview,
+            // Diagnostics bundle for bug reports
+            diagnostics_commands::diagnostics_collect,
+            diagnostics_commands::diagnostics_save,
+        ]))
         .run(tauri::generate_context!())
         .expect("error while running tauri application");
 }
+
+/// `handler`, counting each command it is invoked with for usage analytics
+fn counted<R: tauri::Runtime>(
+    telemetry: Arc<Telemetry>,
+    handler: impl Fn(tauri::ipc::Invoke<R>) -> bool + Send + Sync + 'static,
+) -> impl Fn(tauri::ipc::Invoke<R>) -> bool + Send + Sync + 'static {
+    move |invoke| {
+        telemetry.record(invoke.message.command());
+        handler(invoke)
+    }
+}
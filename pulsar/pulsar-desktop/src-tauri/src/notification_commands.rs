@@ -60,7 +60,7 @@ pub async fn notify_command_completed(
     service
         .send(NotificationType::CommandCompleted {
             command,
-            exit_code,
+            exit_code: Some(exit_code),
             duration_secs,
         })
         .await
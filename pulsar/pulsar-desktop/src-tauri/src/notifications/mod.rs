@@ -5,15 +5,22 @@
 // - Integration with settings preferences
 // - Notification types and formatting
 // - Rate limiting and deduplication
+// - History of sent notifications (for user data export)
+// - Long-running commands measured by pulsar-daemon
 
 use anyhow::Result;
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::{HashMap, VecDeque};
 use std::sync::Arc;
 use tauri::{AppHandle, Manager};
 use tokio::sync::RwLock;
 
+use crate::daemon_client::DaemonClient;
+
+/// How often the daemon is asked for finished long-running commands
+const COMMAND_POLL_INTERVAL: std::time::Duration = std::time::Duration::from_secs(3);
+
 /// Notification types that can be sent
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, Hash)]
 #[serde(tag = "type", rename_all = "snake_case")]
@@ -32,7 +39,8 @@ pub enum NotificationType {
     /// Long-running command completed
     CommandCompleted {
         command: String,
-        exit_code: i32,
+        /// Unknown for shells without OSC 133 integration
+        exit_code: Option<i32>,
         duration_secs: u64,
     },
     /// Vault was locked
@@ -100,7 +108,10 @@ impl NotificationType {
             }
             Self::CommandCompleted { command, exit_code, duration_secs } => {
                 let duration_str = format_duration(*duration_secs);
-                format!("'{}' completed with exit code {} after {}", command, exit_code, duration_str)
+                match exit_code {
+                    Some(code) => format!("'{}' completed with exit code {} after {}", command, code, duration_str),
+                    None => format!("'{}' finished after {}", command, duration_str),
+                }
             }
             Self::VaultLocked { reason } => {
                 format!("Your vault has been locked: {}", reason)
@@ -123,9 +134,11 @@ impl NotificationType {
             Self::SessionDisconnected { .. } => "⚠️",
             Self::FileTransferComplete { success: true, .. } => "✅",
             Self::FileTransferComplete { success: false, .. } => "❌",
-            Self::CommandCompleted { exit_code, .. } => {
-                if *exit_code == 0 { "✅" } else { "❌" }
-            }
+            Self::CommandCompleted { exit_code, .. } => match exit_code {
+                Some(0) => "✅",
+                Some(_) => "❌",
+                None => "🔔",
+            },
             Self::VaultLocked { .. } => "🔒",
             Self::UpdateAvailable { .. } => "🔔",
             Self::SessionReconnected { .. } => "✅",
@@ -161,18 +174,23 @@ impl NotificationType {
     }
 }
 
+/// Number of sent notifications kept in history
+const HISTORY_LIMIT: usize = 500;
+
 /// Notification record for history and deduplication
 #[derive(Debug, Clone, Serialize, Deserialize)]
-struct NotificationRecord {
-    id: String,
-    notification_type: NotificationType,
-    sent_at: DateTime<Utc>,
+pub struct NotificationRecord {
+    pub id: String,
+    pub notification_type: NotificationType,
+    pub sent_at: DateTime<Utc>,
 }
 
 /// Notification service manages sending notifications
 pub struct NotificationService {
     app_handle: AppHandle,
     recent_notifications: Arc<RwLock<HashMap<String, NotificationRecord>>>,
+    /// Notifications actually shown, oldest first
+    history: Arc<RwLock<VecDeque<NotificationRecord>>>,
     dedup_window_secs: u64,
 }
 
@@ -182,6 +200,7 @@ impl NotificationService {
         Self {
             app_handle,
             recent_notifications: Arc::new(RwLock::new(HashMap::new())),
+            history: Arc::new(RwLock::new(VecDeque::new())),
             dedup_window_secs: 60, // Don't send duplicate notifications within 60 seconds
         }
     }
@@ -229,6 +248,7 @@ impl NotificationService {
 
         // Send the notification
         self.send_native_notification(&notification).await?;
+        self.record_history(notification.clone()).await;
 
         tracing::info!(
             notification_type = ?notification,
@@ -303,6 +323,23 @@ impl NotificationService {
         Ok(())
     }
 
+    /// Notifications sent during this run, oldest first
+    pub async fn history(&self) -> Vec<NotificationRecord> {
+        self.history.read().await.iter().cloned().collect()
+    }
+
+    async fn record_history(&self, notification: NotificationType) {
+        let mut history = self.history.write().await;
+        if history.len() == HISTORY_LIMIT {
+            history.pop_front();
+        }
+        history.push_back(NotificationRecord {
+            id: uuid::Uuid::new_v4().to_string(),
+            notification_type: notification,
+            sent_at: Utc::now(),
+        });
+    }
+
     /// Clear old notification records (cleanup)
     pub async fn cleanup_old_records(&self) {
         let mut recent = self.recent_notifications.write().await;
@@ -317,6 +354,48 @@ impl NotificationService {
     }
 }
 
+/// Notify about commands the daemon timed past `notify_command_threshold`
+///
+/// The daemon measures every command from the shell's output, so this works
+/// while the window is unfocused or the terminal isn't shown at all.
+pub fn spawn_command_watcher(app_handle: AppHandle, daemon: Arc<DaemonClient>) {
+    tauri::async_runtime::spawn(async move {
+        let mut interval = tokio::time::interval(COMMAND_POLL_INTERVAL);
+        loop {
+            interval.tick().await;
+
+            let Some(settings) = app_handle.try_state::<crate::settings::SettingsManager>() else {
+                continue;
+            };
+            let threshold = settings.get_security().await.notify_command_threshold;
+            if threshold == 0 || !daemon.is_connected().await {
+                continue;
+            }
+
+            let commands = match daemon.take_long_commands(threshold).await {
+                Ok(commands) => commands,
+                Err(e) => {
+                    tracing::debug!("Failed to fetch long-running commands: {}", e);
+                    continue;
+                }
+            };
+            let Some(service) = app_handle.try_state::<NotificationService>() else {
+                continue;
+            };
+            for long in commands {
+                let notification = NotificationType::CommandCompleted {
+                    command: long.record.command,
+                    exit_code: long.record.exit_code,
+                    duration_secs: long.record.duration_ms / 1000,
+                };
+                if let Err(e) = service.send(notification).await {
+                    tracing::warn!("Failed to send command notification: {}", e);
+                }
+            }
+        }
+    });
+}
+
 /// Format duration in human-readable format
 fn format_duration(secs: u64) -> String {
     if secs < 60 {
@@ -364,13 +443,20 @@ mod tests {
     fn test_notification_messages() {
         let notif = NotificationType::CommandCompleted {
             command: "ls".to_string(),
-            exit_code: 0,
+            exit_code: Some(0),
             duration_secs: 125,
         };
         let msg = notif.message();
         assert!(msg.contains("ls"));
         assert!(msg.contains("exit code 0"));
         assert!(msg.contains("2m 5s"));
+
+        let notif = NotificationType::CommandCompleted {
+            command: "make".to_string(),
+            exit_code: None,
+            duration_secs: 60,
+        };
+        assert_eq!(notif.message(), "'make' finished after 1m");
     }
 
     #[test]
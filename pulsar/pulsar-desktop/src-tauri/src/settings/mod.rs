@@ -11,6 +11,7 @@ use anyhow::{Context, Result};
 use serde::{Deserialize, Serialize};
 use std::path::PathBuf;
 use std::sync::Arc;
+use terminal_core::{CoalesceMode, EchoMode};
 use tokio::sync::RwLock;
 use tracing::{debug, info, warn};
 
@@ -130,6 +131,12 @@ pub struct ConnectionSettings {
 
     /// Maximum reconnect attempts
     pub max_reconnect_attempts: u32,
+
+    /// Draw keystrokes before the remote end echoes them
+    pub local_echo: EchoMode,
+
+    /// Batch typed input into fewer writes on slow connections
+    pub input_coalescing: CoalesceMode,
 }
 
 impl Default for ConnectionSettings {
@@ -141,6 +148,8 @@ impl Default for ConnectionSettings {
             keepalive_interval: 60,
             auto_reconnect: true,
             max_reconnect_attempts: 3,
+            local_echo: EchoMode::Off,
+            input_coalescing: CoalesceMode::Off,
         }
     }
 }
@@ -197,6 +206,12 @@ pub struct SecuritySettings {
 
     /// Command completion notification threshold in seconds (0 = never)
     pub notify_command_threshold: u64,
+
+    /// Ask before pasting text with line breaks, which can run commands
+    pub warn_paste_newlines: bool,
+
+    /// Ask before pasting text with escape sequences or control characters
+    pub warn_paste_control_chars: bool,
 }
 
 impl Default for SecuritySettings {
@@ -211,6 +226,8 @@ impl Default for SecuritySettings {
             notify_session_disconnect: true,
             notify_file_transfer_complete: true,
             notify_command_threshold: 30,
+            warn_paste_newlines: true,
+            warn_paste_control_chars: true,
         }
     }
 }
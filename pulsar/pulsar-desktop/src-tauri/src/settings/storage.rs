@@ -1,7 +1,8 @@
 //! Settings persistence layer
 //!
 //! Handles reading and writing settings to/from disk in TOML format.
-//! Uses atomic writes to prevent corruption.
+//! Writes are atomic (see `pulsar_fs`), so a crash mid-save never leaves a
+//! truncated settings file.
 
 use super::AppSettings;
 use anyhow::{Context, Result};
@@ -49,14 +50,8 @@ impl SettingsStorage {
         let toml_string = toml::to_string_pretty(settings)
             .context("Failed to serialize settings")?;
 
-        // Write to temporary file first
-        let temp_path = self.settings_path.with_extension("toml.tmp");
-        fs::write(&temp_path, toml_string)
-            .context("Failed to write temporary settings file")?;
-
-        // Atomic rename (overwrites existing file)
-        fs::rename(&temp_path, &self.settings_path)
-            .context("Failed to rename settings file")?;
+        pulsar_fs::write(&self.settings_path, toml_string)
+            .context("Failed to write settings file")?;
 
         debug!("Saved settings to {:?}", self.settings_path);
         Ok(())
@@ -67,7 +62,7 @@ impl SettingsStorage {
         let toml_string = toml::to_string_pretty(settings)
             .context("Failed to serialize settings for export")?;
 
-        fs::write(&path, toml_string)
+        pulsar_fs::write(&path, toml_string)
             .context("Failed to write exported settings")?;
 
         info!("Exported settings to {:?}", path);
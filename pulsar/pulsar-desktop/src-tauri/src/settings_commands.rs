@@ -1,7 +1,10 @@
 //! Tauri commands for settings management
 
+use crate::daemon_client::DaemonClient;
 use crate::settings::*;
+use crate::telemetry::Telemetry;
 use std::path::PathBuf;
+use std::sync::Arc;
 use tauri::State;
 
 type CommandResult<T> = Result<T, String>;
@@ -58,12 +61,22 @@ pub async fn settings_get_general(
 #[tauri::command]
 pub async fn settings_update_appearance(
     settings: State<'_, SettingsManager>,
+    daemon: State<'_, Arc<DaemonClient>>,
     appearance: AppearanceSettings,
 ) -> CommandResult<()> {
     settings
-        .update_appearance(appearance)
+        .update_appearance(appearance.clone())
         .await
-        .map_err(|e| format!("Failed to update appearance settings: {}", e))
+        .map_err(|e| format!("Failed to update appearance settings: {}", e))?;
+
+    // Mirror to remote clients; the daemon may not be running
+    if daemon.is_connected().await {
+        if let Err(e) = daemon.set_theme(&appearance).await {
+            tracing::warn!("Failed to share theme with daemon: {}", e);
+        }
+    }
+
+    Ok(())
 }
 
 /// Update connection settings
@@ -106,23 +119,30 @@ pub async fn settings_update_shortcuts(
 #[tauri::command]
 pub async fn settings_update_general(
     settings: State<'_, SettingsManager>,
+    telemetry: State<'_, Arc<Telemetry>>,
     general: GeneralSettings,
 ) -> CommandResult<()> {
+    let send_analytics = general.send_analytics;
     settings
         .update_general(general)
         .await
-        .map_err(|e| format!("Failed to update general settings: {}", e))
+        .map_err(|e| format!("Failed to update general settings: {}", e))?;
+    telemetry.set_enabled(send_analytics);
+    Ok(())
 }
 
 /// Reset all settings to defaults
 #[tauri::command]
 pub async fn settings_reset_to_defaults(
     settings: State<'_, SettingsManager>,
+    telemetry: State<'_, Arc<Telemetry>>,
 ) -> CommandResult<()> {
     settings
         .reset_to_defaults()
         .await
-        .map_err(|e| format!("Failed to reset settings: {}", e))
+        .map_err(|e| format!("Failed to reset settings: {}", e))?;
+    telemetry.set_enabled(settings.get_general().await.send_analytics);
+    Ok(())
 }
 
 /// Export settings to a file
@@ -141,10 +161,13 @@ pub async fn settings_export(
 #[tauri::command]
 pub async fn settings_import(
     settings: State<'_, SettingsManager>,
+    telemetry: State<'_, Arc<Telemetry>>,
     path: String,
 ) -> CommandResult<AppSettings> {
-    settings
+    let imported = settings
         .import(PathBuf::from(path))
         .await
-        .map_err(|e| format!("Failed to import settings: {}", e))
+        .map_err(|e| format!("Failed to import settings: {}", e))?;
+    telemetry.set_enabled(imported.general.send_analytics);
+    Ok(imported)
 }
@@ -1,12 +1,79 @@
 //! SSH session manager for Tauri backend
+//!
+//! Sessions survive network changes: when the local network changes (Wi-Fi
+//! to Ethernet, a VPN coming up) each session whose TCP connection was left
+//! on the old path is connected again at once, rather than hanging until a
+//! timeout notices, and the UI is told what is going on through
+//! `network-changed` and `ssh-session-network` events.
 
 use anyhow::Result;
+use futures::future::join_all;
+use serde::Serialize;
 use std::collections::HashMap;
-use std::sync::Arc;
-use tokio::sync::{mpsc, RwLock};
-use tft_transports::{AuthMethod, SshConfig, SshSession, spawn_ssh_io};
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tauri::{AppHandle, Emitter, Manager};
+use terminal_core::{CoalesceMode, CoalesceStats, EchoMode, EchoPredictor, InputCoalescer};
+use tokio::sync::{broadcast, mpsc, RwLock};
+use tft_transports::network::path_moved;
+use tft_transports::{
+    spawn_ssh_io, AuthMethod, ChangeKind, NetworkChange, NetworkMonitor, RetryEvent,
+    RetryObserver, RetryPolicy, SshConfig, SshSession,
+};
 use uuid::Uuid;
 
+use crate::notifications::{NotificationService, NotificationType};
+
+/// How often the network is checked for changes
+const NETWORK_POLL_INTERVAL: Duration = Duration::from_secs(2);
+
+/// Written to the terminal when a session moves to a new connection
+const RECONNECTED_NOTICE: &[u8] = b"\r\n\x1b[90m[Reconnected after a network change]\x1b[0m\r\n";
+
+/// Input and output channels of one SSH connection
+type SshLink = (mpsc::Sender<Vec<u8>>, mpsc::Receiver<Vec<u8>>);
+
+/// What a session is connected to, kept to connect it again
+#[derive(Clone)]
+struct ConnectTarget {
+    host: String,
+    port: u16,
+    username: String,
+    auth: AuthMethod,
+    cols: u32,
+    rows: u32,
+}
+
+/// Where a session stands after a network change
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum NetworkSessionState {
+    /// No network; the session waits for it to come back
+    Paused,
+    Reconnecting,
+    Reconnected,
+    /// Connecting again failed and the session has ended
+    Failed,
+}
+
+/// Payload of the `ssh-session-network` event
+#[derive(Debug, Clone, Serialize)]
+pub struct SessionNetworkEvent {
+    pub session_id: Uuid,
+    pub state: NetworkSessionState,
+    pub message: String,
+}
+
+/// Payload of the `network-changed` event
+#[derive(Debug, Clone, Serialize)]
+pub struct NetworkChangedEvent {
+    #[serde(flatten)]
+    pub change: NetworkChange,
+    pub description: String,
+}
+
 #[allow(dead_code)]
 pub struct SessionInfo {
     pub id: Uuid,
@@ -14,7 +81,19 @@ pub struct SessionInfo {
     pub username: String,
     pub fingerprint: String,
     pub input_tx: mpsc::Sender<Vec<u8>>,
-    pub output_rx: Arc<RwLock<mpsc::Receiver<Vec<u8>>>>,
+    pub output_rx: Arc<RwLock<mpsc::UnboundedReceiver<Vec<u8>>>>,
+    /// Local echo, and the channel its drawing joins the output on
+    pub echo: Option<(Arc<Mutex<EchoPredictor>>, mpsc::UnboundedSender<Vec<u8>>)>,
+    /// Batches what is sent on `input_tx` before it reaches the remote end
+    pub coalescer: Arc<Mutex<InputCoalescer>>,
+    target: ConnectTarget,
+    /// Local and remote ends of the current TCP connection
+    path: Arc<Mutex<(SocketAddr, SocketAddr)>>,
+    /// Hands the session a new connection; None when reconnecting failed
+    links: mpsc::Sender<Option<SshLink>>,
+    /// Set while a new connection is being made, so the end of the old
+    /// one doesn't end the session
+    recovering: Arc<AtomicBool>,
 }
 
 pub struct SshManager {
@@ -36,25 +115,48 @@ impl SshManager {
         auth: AuthMethod,
         cols: u32,
         rows: u32,
+        local_echo: EchoMode,
+        coalesce: CoalesceMode,
     ) -> Result<Uuid> {
         tracing::info!("Connecting to {}@{}:{}", username, host, port);
 
-        let config = SshConfig {
+        let target = ConnectTarget {
             host: host.clone(),
             port,
             username: username.clone(),
             auth,
-            accept_unknown_hosts: true,  // Development mode: auto-accept unknown hosts
-            accept_changed_hosts: false, // Production: reject changed keys (security)
+            cols,
+            rows,
         };
-
-        let mut session = SshSession::connect(config).await?;
+        let session = open(&target).await?;
         let fingerprint = session.fingerprint().to_string();
+        let path = Arc::new(Mutex::new((session.local_addr(), session.peer_addr())));
+
+        let (links, links_rx) = mpsc::channel(1);
+        let recovering = Arc::new(AtomicBool::new(false));
+        let (relay_in_tx, relay_in_rx) = mpsc::channel(100);
+        let (relay_out_tx, ssh_rx) = mpsc::channel(100);
+        tokio::spawn(relay(
+            spawn_ssh_io(session),
+            relay_in_rx,
+            relay_out_tx,
+            links_rx,
+            Arc::clone(&recovering),
+        ));
 
-        session.request_pty(cols, rows).await?;
-        session.request_shell().await?;
+        let (input_tx, input_rx) = mpsc::channel(100);
+        let coalescer = Arc::new(Mutex::new(InputCoalescer::new(coalesce)));
+        tokio::spawn(coalesce_input(input_rx, relay_in_tx, Arc::clone(&coalescer)));
 
-        let (input_tx, output_rx) = spawn_ssh_io(session);
+        let (display_tx, output_rx) = mpsc::unbounded_channel();
+        let predictor = Arc::new(Mutex::new(EchoPredictor::new(local_echo)));
+        tokio::spawn(forward_output(
+            ssh_rx,
+            display_tx.clone(),
+            Arc::clone(&predictor),
+            Arc::clone(&coalescer),
+        ));
+        let echo = (local_echo != EchoMode::Off).then_some((predictor, display_tx));
 
         let session_id = Uuid::new_v4();
         let session_info = SessionInfo {
@@ -64,6 +166,12 @@ impl SshManager {
             fingerprint,
             input_tx,
             output_rx: Arc::new(RwLock::new(output_rx)),
+            echo,
+            coalescer,
+            target,
+            path,
+            links,
+            recovering,
         };
 
         self.sessions.write().await.insert(session_id, session_info);
@@ -78,6 +186,15 @@ impl SshManager {
             .get(&session_id)
             .ok_or_else(|| anyhow::anyhow!("Session not found"))?;
 
+        if let Some((predictor, display_tx)) = &session.echo {
+            // Drawn under the lock so it stays ordered with the output
+            let mut predictor = predictor.lock().unwrap();
+            let drawn = predictor.input(&data, Instant::now());
+            if !drawn.is_empty() {
+                let _ = display_tx.send(drawn);
+            }
+        }
+
         session
             .input_tx
             .send(data)
@@ -120,6 +237,321 @@ impl SshManager {
 
         Ok(session.fingerprint.clone())
     }
+
+    /// How much input batching has saved on a session
+    pub async fn input_stats(&self, session_id: Uuid) -> Result<CoalesceStats> {
+        let sessions = self.sessions.read().await;
+        let session = sessions
+            .get(&session_id)
+            .ok_or_else(|| anyhow::anyhow!("Session not found"))?;
+
+        let stats = session.coalescer.lock().unwrap().stats();
+        Ok(stats)
+    }
+
+    /// Connect again every session whose connection `change` stranded,
+    /// passing each step to `report`; returns how each session ended up
+    pub async fn recover_sessions(
+        &self,
+        change: &NetworkChange,
+        report: impl Fn(&SessionNetworkEvent),
+    ) -> Vec<SessionNetworkEvent> {
+        let sessions: Vec<_> = self
+            .sessions
+            .read()
+            .await
+            .values()
+            .map(|session| {
+                (
+                    session.id,
+                    session.target.clone(),
+                    Arc::clone(&session.path),
+                    session.links.clone(),
+                    Arc::clone(&session.recovering),
+                )
+            })
+            .collect();
+
+        if change.kind == ChangeKind::Lost {
+            // Nothing to reconnect over; the connections may yet survive if
+            // the same address comes back
+            return sessions
+                .into_iter()
+                .map(|(session_id, ..)| {
+                    let event = SessionNetworkEvent {
+                        session_id,
+                        state: NetworkSessionState::Paused,
+                        message: "Waiting for the network".to_string(),
+                    };
+                    report(&event);
+                    event
+                })
+                .collect();
+        }
+
+        let recoveries = sessions
+            .into_iter()
+            .filter(|(_, _, path, ..)| {
+                let (local, peer) = *path.lock().unwrap();
+                path_moved(local, peer)
+            })
+            .map(|(session_id, target, path, links, recovering)| {
+                let report = &report;
+                async move {
+                    report(&SessionNetworkEvent {
+                        session_id,
+                        state: NetworkSessionState::Reconnecting,
+                        message: format!("Reconnecting to {}", target.host),
+                    });
+                    recovering.store(true, Ordering::SeqCst);
+                    let event = match open(&target).await {
+                        Ok(session) => {
+                            *path.lock().unwrap() = (session.local_addr(), session.peer_addr());
+                            let _ = links.send(Some(spawn_ssh_io(session))).await;
+                            tracing::info!("Session {} reconnected after a network change", session_id);
+                            SessionNetworkEvent {
+                                session_id,
+                                state: NetworkSessionState::Reconnected,
+                                message: format!("Reconnected to {}", target.host),
+                            }
+                        }
+                        Err(e) => {
+                            let _ = links.send(None).await;
+                            tracing::warn!("Session {} could not reconnect: {}", session_id, e);
+                            SessionNetworkEvent {
+                                session_id,
+                                state: NetworkSessionState::Failed,
+                                message: format!("Could not reconnect: {}", e),
+                            }
+                        }
+                    };
+                    recovering.store(false, Ordering::SeqCst);
+                    report(&event);
+                    event
+                }
+            });
+        join_all(recoveries).await
+    }
+}
+
+/// Watch the local network and recover the SSH sessions a change strands,
+/// telling the UI through `network-changed` and `ssh-session-network`
+/// events and notifying once each session is back or gone
+pub fn spawn_network_watcher(app_handle: AppHandle, ssh_manager: Arc<SshManager>) {
+    let monitor = Arc::new(NetworkMonitor::new());
+    let mut changes = monitor.subscribe();
+    tauri::async_runtime::spawn(monitor.run(NETWORK_POLL_INTERVAL));
+
+    tauri::async_runtime::spawn(async move {
+        loop {
+            let change = match changes.recv().await {
+                Ok(change) => change,
+                Err(broadcast::error::RecvError::Lagged(_)) => continue,
+                Err(broadcast::error::RecvError::Closed) => break,
+            };
+            let event = NetworkChangedEvent {
+                description: change.describe(),
+                change: change.clone(),
+            };
+            if let Err(e) = app_handle.emit("network-changed", &event) {
+                tracing::warn!("Failed to emit network change: {}", e);
+            }
+
+            let outcomes = ssh_manager
+                .recover_sessions(&change, |event| {
+                    let _ = app_handle.emit("ssh-session-network", event);
+                })
+                .await;
+
+            let Some(service) = app_handle.try_state::<NotificationService>() else {
+                continue;
+            };
+            for outcome in outcomes {
+                let notification = match outcome.state {
+                    NetworkSessionState::Reconnected => NotificationType::SessionReconnected {
+                        session_id: outcome.session_id.to_string(),
+                    },
+                    NetworkSessionState::Failed => NotificationType::SessionDisconnected {
+                        session_id: outcome.session_id.to_string(),
+                        reason: outcome.message,
+                    },
+                    NetworkSessionState::Paused | NetworkSessionState::Reconnecting => continue,
+                };
+                if let Err(e) = service.send(notification).await {
+                    tracing::warn!("Failed to send session notification: {}", e);
+                }
+            }
+        }
+    });
+}
+
+/// Connect, then start a shell in a PTY
+async fn open(target: &ConnectTarget) -> Result<SshSession> {
+    let config = SshConfig {
+        host: target.host.clone(),
+        port: target.port,
+        username: target.username.clone(),
+        auth: target.auth.clone(),
+        accept_unknown_hosts: true,  // Development mode: auto-accept unknown hosts
+        accept_changed_hosts: false, // Production: reject changed keys (security)
+        retry: RetryPolicy::default(),
+    };
+
+    let observer: RetryObserver = Arc::new(log_retry_event);
+    let mut session = SshSession::connect_observed(config, Some(observer)).await?;
+    session.request_pty(target.cols, target.rows).await?;
+    session.request_shell().await?;
+    Ok(session)
+}
+
+/// Carry a session's input and output over its current SSH connection,
+/// moving to each new one `links` delivers
+async fn relay(
+    link: SshLink,
+    mut input_rx: mpsc::Receiver<Vec<u8>>,
+    output_tx: mpsc::Sender<Vec<u8>>,
+    mut links: mpsc::Receiver<Option<SshLink>>,
+    recovering: Arc<AtomicBool>,
+) {
+    let (mut ssh_tx, mut ssh_rx) = link;
+    loop {
+        tokio::select! {
+            Some(link) = links.recv() => {
+                let Some(link) = link else {
+                    return;
+                };
+                (ssh_tx, ssh_rx) = link;
+                if output_tx.send(RECONNECTED_NOTICE.to_vec()).await.is_err() {
+                    return;
+                }
+            }
+            data = input_rx.recv() => {
+                let Some(data) = data else {
+                    return;
+                };
+                // Typed while the connection is down; lost with it
+                let _ = ssh_tx.send(data).await;
+            }
+            data = ssh_rx.recv() => match data {
+                Some(data) => {
+                    if output_tx.send(data).await.is_err() {
+                        return;
+                    }
+                }
+                // The connection ended; the session with it, unless a new
+                // connection is on its way
+                None if recovering.load(Ordering::SeqCst) => match links.recv().await {
+                    Some(Some(link)) => {
+                        (ssh_tx, ssh_rx) = link;
+                        if output_tx.send(RECONNECTED_NOTICE.to_vec()).await.is_err() {
+                            return;
+                        }
+                    }
+                    _ => return,
+                },
+                None => return,
+            },
+        }
+    }
+}
+
+fn log_retry_event(event: &RetryEvent) {
+    match event {
+        RetryEvent::Attempt {
+            attempt,
+            max_attempts,
+        } if *attempt > 1 => tracing::info!("SSH connect attempt {}/{}", attempt, max_attempts),
+        RetryEvent::Failed {
+            attempt,
+            message,
+            retry_in: Some(delay),
+            ..
+        } => tracing::warn!(
+            "SSH connect attempt {} failed ({}), retrying in {:?}",
+            attempt,
+            message,
+            delay
+        ),
+        _ => {}
+    }
+}
+
+/// Pass input to the SSH session through the coalescer, writing held input
+/// once its interval passes
+async fn coalesce_input(
+    mut input_rx: mpsc::Receiver<Vec<u8>>,
+    ssh_tx: mpsc::Sender<Vec<u8>>,
+    coalescer: Arc<Mutex<InputCoalescer>>,
+) {
+    loop {
+        let deadline = coalescer.lock().unwrap().deadline();
+        let received = match deadline {
+            Some(deadline) => {
+                match tokio::time::timeout_at(deadline.into(), input_rx.recv()).await {
+                    Ok(received) => received,
+                    Err(_) => {
+                        let batch = coalescer.lock().unwrap().flush(Instant::now());
+                        if let Some(batch) = batch {
+                            if ssh_tx.send(batch).await.is_err() {
+                                return;
+                            }
+                        }
+                        continue;
+                    }
+                }
+            }
+            None => input_rx.recv().await,
+        };
+
+        let Some(data) = received else {
+            return;
+        };
+        let batch = coalescer.lock().unwrap().input(&data, Instant::now());
+        if let Some(batch) = batch {
+            if ssh_tx.send(batch).await.is_err() {
+                return;
+            }
+        }
+    }
+}
+
+/// Pass SSH output to the display through the session's echo predictor,
+/// taking back predictions the remote end never echoes
+async fn forward_output(
+    mut ssh_rx: mpsc::Receiver<Vec<u8>>,
+    display_tx: mpsc::UnboundedSender<Vec<u8>>,
+    predictor: Arc<Mutex<EchoPredictor>>,
+    coalescer: Arc<Mutex<InputCoalescer>>,
+) {
+    loop {
+        let deadline = predictor.lock().unwrap().deadline();
+        let received = match deadline {
+            Some(deadline) => {
+                match tokio::time::timeout_at(deadline.into(), ssh_rx.recv()).await {
+                    Ok(received) => received,
+                    Err(_) => {
+                        let mut predictor = predictor.lock().unwrap();
+                        let erased = predictor.expire(Instant::now());
+                        if !erased.is_empty() && display_tx.send(erased).is_err() {
+                            return;
+                        }
+                        continue;
+                    }
+                }
+            }
+            None => ssh_rx.recv().await,
+        };
+
+        let Some(data) = received else {
+            return;
+        };
+        coalescer.lock().unwrap().output(Instant::now());
+        let mut predictor = predictor.lock().unwrap();
+        let display = predictor.output(&data, Instant::now());
+        if !display.is_empty() && display_tx.send(display).is_err() {
+            return;
+        }
+    }
 }
 
 impl Default for SshManager {
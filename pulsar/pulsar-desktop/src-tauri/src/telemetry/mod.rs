@@ -0,0 +1,365 @@
+// Anonymous usage analytics for Pulsar
+//
+// This module provides:
+// - Per-command usage counters, aggregated locally
+// - Differential batches: each upload carries only the counts added since
+//   the last accepted one
+// - A preview of the next batch, byte for byte what would be sent
+//
+// Nothing is counted, stored or sent unless `send_analytics` is on, and
+// turning it off deletes everything collected. Only the names of the app's
+// own Tauri commands are counted: never arguments, hosts, paths or command
+// text.
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::time::Duration;
+use tracing::{debug, info, warn};
+use uuid::Uuid;
+
+/// Where batches are sent; `PULSAR_ANALYTICS_ENDPOINT` overrides it
+const DEFAULT_ENDPOINT: &str = "https://telemetry.singulio.com/v1/pulsar/usage";
+
+/// How often counters are saved and a due batch is sent
+const TICK_INTERVAL: Duration = Duration::from_secs(3600);
+
+/// Minimum time between uploads
+const BATCH_INTERVAL: chrono::Duration = chrono::Duration::hours(24);
+
+/// Keystrokes, output polling and connection checks say nothing about which
+/// features are used, and would drown out everything else
+const IGNORED_COMMANDS: &[&str] = &[
+    "send_input",
+    "receive_output",
+    "resize_terminal",
+    "get_input_stats",
+    "daemon_send_input",
+    "daemon_send_key",
+    "daemon_paste",
+    "daemon_receive_output",
+    "daemon_resize_terminal",
+    "daemon_check_connection",
+    "daemon_get_status",
+    "telemetry_preview",
+];
+
+/// One upload: counts added since the previous accepted batch
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TelemetryBatch {
+    /// Random, created when analytics is turned on and discarded when it
+    /// is turned off
+    pub install_id: Uuid,
+    pub app_version: String,
+    pub os: String,
+    pub period_start: DateTime<Utc>,
+    pub period_end: DateTime<Utc>,
+    /// Invocations per command in the period
+    pub counts: BTreeMap<String, u64>,
+}
+
+/// What the inspection view shows
+#[derive(Debug, Clone, Serialize)]
+pub struct TelemetryPreview {
+    pub enabled: bool,
+    pub endpoint: String,
+    pub last_sent: Option<DateTime<Utc>>,
+    /// The next batch; None when analytics is off or nothing is new
+    pub batch: Option<TelemetryBatch>,
+    /// `batch` exactly as it would be uploaded
+    pub payload: Option<String>,
+}
+
+/// Counters as kept on disk
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Counters {
+    install_id: Uuid,
+    /// Invocations per command since analytics was turned on
+    totals: BTreeMap<String, u64>,
+    /// The part of `totals` already uploaded
+    sent: BTreeMap<String, u64>,
+    period_start: DateTime<Utc>,
+    last_sent: Option<DateTime<Utc>>,
+}
+
+impl Counters {
+    fn new() -> Self {
+        Self {
+            install_id: Uuid::new_v4(),
+            totals: BTreeMap::new(),
+            sent: BTreeMap::new(),
+            period_start: Utc::now(),
+            last_sent: None,
+        }
+    }
+
+    fn batch(&self, now: DateTime<Utc>) -> Option<TelemetryBatch> {
+        let counts: BTreeMap<String, u64> = self
+            .totals
+            .iter()
+            .filter_map(|(command, total)| {
+                let new = total - self.sent.get(command).copied().unwrap_or(0);
+                (new > 0).then(|| (command.clone(), new))
+            })
+            .collect();
+        if counts.is_empty() {
+            return None;
+        }
+        Some(TelemetryBatch {
+            install_id: self.install_id,
+            app_version: env!("CARGO_PKG_VERSION").to_string(),
+            os: std::env::consts::OS.to_string(),
+            period_start: self.period_start,
+            period_end: now,
+            counts,
+        })
+    }
+
+    fn mark_sent(&mut self, batch: &TelemetryBatch) {
+        for (command, count) in &batch.counts {
+            *self.sent.entry(command.clone()).or_insert(0) += count;
+        }
+        self.period_start = batch.period_end;
+        self.last_sent = Some(batch.period_end);
+    }
+
+    fn due(&self, now: DateTime<Utc>) -> bool {
+        now - self.last_sent.unwrap_or(self.period_start) >= BATCH_INTERVAL
+    }
+}
+
+/// Whether `command` is counted
+fn counted(command: &str) -> bool {
+    // Plugin commands (`plugin:name|command`) aren't ours
+    !command.is_empty()
+        && command.bytes().all(|b| b.is_ascii_lowercase() || b == b'_')
+        && !IGNORED_COMMANDS.contains(&command)
+}
+
+/// Local usage aggregation and upload
+pub struct Telemetry {
+    path: PathBuf,
+    endpoint: String,
+    /// None while analytics is off
+    counters: Mutex<Option<Counters>>,
+}
+
+impl Telemetry {
+    /// Counters live in `telemetry.json` under `config_dir`
+    pub fn new(config_dir: PathBuf, enabled: bool) -> Self {
+        let path = config_dir.join("telemetry.json");
+        let counters = if enabled {
+            Some(Self::load(&path).unwrap_or_else(|e| {
+                debug!("Starting analytics counters afresh: {}", e);
+                Counters::new()
+            }))
+        } else {
+            // Leftovers from an unclean opt-out
+            let _ = std::fs::remove_file(&path);
+            None
+        };
+
+        Self {
+            path,
+            endpoint: std::env::var("PULSAR_ANALYTICS_ENDPOINT")
+                .unwrap_or_else(|_| DEFAULT_ENDPOINT.to_string()),
+            counters: Mutex::new(counters),
+        }
+    }
+
+    fn load(path: &PathBuf) -> Result<Counters> {
+        let data = std::fs::read(path).context("Failed to read analytics counters")?;
+        serde_json::from_slice(&data).context("Failed to parse analytics counters")
+    }
+
+    fn save(&self, counters: &Counters) {
+        let result = serde_json::to_vec(counters)
+            .map_err(anyhow::Error::from)
+            .and_then(|data| pulsar_fs::write_private(&self.path, data).map_err(Into::into));
+        if let Err(e) = result {
+            warn!("Failed to save analytics counters: {}", e);
+        }
+    }
+
+    /// Follow the `send_analytics` setting. Turning it off deletes every
+    /// counter, here and on disk.
+    pub fn set_enabled(&self, enabled: bool) {
+        let mut counters = self.counters.lock().unwrap();
+        match (enabled, counters.is_some()) {
+            (true, false) => {
+                info!("Anonymous usage analytics turned on");
+                let fresh = Counters::new();
+                self.save(&fresh);
+                *counters = Some(fresh);
+            }
+            (false, true) => {
+                info!("Anonymous usage analytics turned off; local counters deleted");
+                *counters = None;
+                if let Err(e) = std::fs::remove_file(&self.path) {
+                    warn!("Failed to delete analytics counters: {}", e);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Count one invocation of the Tauri command `command`
+    pub fn record(&self, command: &str) {
+        if !counted(command) {
+            return;
+        }
+        if let Some(counters) = self.counters.lock().unwrap().as_mut() {
+            *counters.totals.entry(command.to_string()).or_insert(0) += 1;
+        }
+    }
+
+    /// The next batch, without sending it
+    pub fn preview(&self) -> TelemetryPreview {
+        let counters = self.counters.lock().unwrap();
+        let batch = counters.as_ref().and_then(|c| c.batch(Utc::now()));
+        TelemetryPreview {
+            enabled: counters.is_some(),
+            endpoint: self.endpoint.clone(),
+            last_sent: counters.as_ref().and_then(|c| c.last_sent),
+            payload: batch
+                .as_ref()
+                .and_then(|batch| serde_json::to_string(batch).ok()),
+            batch,
+        }
+    }
+
+    /// Save the counters, and send a batch when one is due
+    async fn tick(&self, client: &reqwest::Client) {
+        let batch = {
+            let counters = self.counters.lock().unwrap();
+            let Some(counters) = counters.as_ref() else {
+                return;
+            };
+            self.save(counters);
+            let now = Utc::now();
+            if !counters.due(now) {
+                return;
+            }
+            counters.batch(now)
+        };
+        let Some(batch) = batch else {
+            return;
+        };
+
+        match self.send(client, &batch).await {
+            Ok(()) => {
+                let mut counters = self.counters.lock().unwrap();
+                // Analytics may have been turned off, or back on with a new
+                // install ID, while the batch was in flight
+                if let Some(counters) = counters
+                    .as_mut()
+                    .filter(|c| c.install_id == batch.install_id)
+                {
+                    counters.mark_sent(&batch);
+                    self.save(counters);
+                    debug!("Sent usage analytics for {} commands", batch.counts.len());
+                }
+            }
+            // Counts stay pending and go out with the next batch
+            Err(e) => debug!("Failed to send usage analytics: {}", e),
+        }
+    }
+
+    async fn send(&self, client: &reqwest::Client, batch: &TelemetryBatch) -> Result<()> {
+        client
+            .post(&self.endpoint)
+            .header(reqwest::header::CONTENT_TYPE, "application/json")
+            .body(serde_json::to_vec(batch)?)
+            .send()
+            .await?
+            .error_for_status()?;
+        Ok(())
+    }
+
+    /// Save counters and upload due batches until the app exits
+    pub async fn run(self: std::sync::Arc<Self>) {
+        let client = match reqwest::Client::builder()
+            .timeout(Duration::from_secs(30))
+            .build()
+        {
+            Ok(client) => client,
+            Err(e) => {
+                warn!("Usage analytics unavailable: {}", e);
+                return;
+            }
+        };
+
+        let mut interval = tokio::time::interval(TICK_INTERVAL);
+        loop {
+            interval.tick().await;
+            self.tick(&client).await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_nothing_is_counted_unless_enabled() {
+        let dir = TempDir::new().unwrap();
+        let telemetry = Telemetry::new(dir.path().to_path_buf(), false);
+        telemetry.record("connect_ssh");
+        let preview = telemetry.preview();
+        assert!(!preview.enabled);
+        assert!(preview.batch.is_none());
+
+        telemetry.set_enabled(true);
+        telemetry.record("connect_ssh");
+        telemetry.record("connect_ssh");
+        telemetry.record("daemon_send_input");
+        telemetry.record("plugin:notification|notify");
+        let batch = telemetry.preview().batch.unwrap();
+        assert_eq!(batch.counts, BTreeMap::from([("connect_ssh".to_string(), 2)]));
+
+        telemetry.set_enabled(false);
+        assert!(!dir.path().join("telemetry.json").exists());
+        telemetry.set_enabled(true);
+        assert!(telemetry.preview().batch.is_none());
+    }
+
+    #[test]
+    fn test_batches_are_differential() {
+        let mut counters = Counters::new();
+        *counters.totals.entry("vault_unlock".to_string()).or_insert(0) += 3;
+        let first = counters.batch(Utc::now()).unwrap();
+        counters.mark_sent(&first);
+        assert!(counters.batch(Utc::now()).is_none());
+
+        *counters.totals.entry("vault_unlock".to_string()).or_insert(0) += 1;
+        *counters.totals.entry("workspace_create".to_string()).or_insert(0) += 2;
+        let second = counters.batch(Utc::now()).unwrap();
+        assert_eq!(second.period_start, first.period_end);
+        assert_eq!(
+            second.counts,
+            BTreeMap::from([
+                ("vault_unlock".to_string(), 1),
+                ("workspace_create".to_string(), 2),
+            ])
+        );
+    }
+
+    #[test]
+    fn test_counters_survive_restart() {
+        let dir = TempDir::new().unwrap();
+        let telemetry = Telemetry::new(dir.path().to_path_buf(), true);
+        telemetry.record("export_user_data");
+        let counters = telemetry.counters.lock().unwrap().clone().unwrap();
+        telemetry.save(&counters);
+
+        let restarted = Telemetry::new(dir.path().to_path_buf(), true);
+        let preview = restarted.preview();
+        assert_eq!(preview.batch.unwrap().install_id, counters.install_id);
+        assert!(preview.payload.unwrap().contains("\"export_user_data\":1"));
+    }
+}
@@ -0,0 +1,13 @@
+//! Tauri commands for anonymous usage analytics
+
+use crate::telemetry::{Telemetry, TelemetryPreview};
+use std::sync::Arc;
+use tauri::State;
+
+/// Exactly what the next analytics upload would contain
+#[tauri::command]
+pub async fn telemetry_preview(
+    telemetry: State<'_, Arc<Telemetry>>,
+) -> Result<TelemetryPreview, String> {
+    Ok(telemetry.preview())
+}
@@ -388,6 +388,21 @@ impl VaultManager {
 
         Ok(())
     }
+
+    /// Health of the vault database
+    pub async fn database_health(&self) -> Result<pulsar_db::DbHealth> {
+        self.inner.read().await.storage.health().await
+    }
+
+    /// Back up the vault database (works while locked; data stays encrypted)
+    pub async fn backup_database(&self) -> Result<PathBuf> {
+        self.inner.read().await.storage.backup().await
+    }
+
+    /// Vacuum the vault database
+    pub async fn vacuum_database(&self) -> Result<()> {
+        self.inner.read().await.storage.vacuum().await
+    }
 }
 
 #[cfg(test)]
@@ -66,6 +66,9 @@ pub struct VaultMetadata {
     pub last_unlocked_at: i64,
 }
 
+/// Number of vault database backups kept by [`VaultStorage::backup`]
+const BACKUPS_KEPT: usize = 7;
+
 /// SQLite storage for the vault
 pub struct VaultStorage {
     pool: SqlitePool,
@@ -80,8 +83,8 @@ impl VaultStorage {
                 .context("Failed to create vault directory")?;
         }
 
-        // Connect to database
-        let pool = SqlitePool::connect(&format!("sqlite://{}?mode=rwc", db_path.display()))
+        // Connect to database (WAL, busy timeout, integrity check)
+        let pool = pulsar_db::open(&db_path, &pulsar_db::DbOptions::default())
             .await
             .context("Failed to connect to vault database")?;
 
@@ -378,6 +381,35 @@ impl VaultStorage {
 
         Ok(())
     }
+
+    /// Journal mode, size and integrity of the vault database
+    pub async fn health(&self) -> Result<pulsar_db::DbHealth> {
+        pulsar_db::health(&self.pool)
+            .await
+            .context("Failed to check vault database")
+    }
+
+    /// Back up the vault database next to it, keeping the last few copies
+    ///
+    /// Credentials stay encrypted in the copy.
+    pub async fn backup(&self) -> Result<PathBuf> {
+        let dir = self
+            .pool
+            .connect_options()
+            .get_filename()
+            .with_file_name("backups");
+
+        pulsar_db::backup_rotating(&self.pool, &dir, "vault", BACKUPS_KEPT)
+            .await
+            .context("Failed to back up vault database")
+    }
+
+    /// Reclaim space left by deleted credentials
+    pub async fn vacuum(&self) -> Result<()> {
+        pulsar_db::vacuum(&self.pool)
+            .await
+            .context("Failed to vacuum vault database")
+    }
 }
 
 #[cfg(test)]
@@ -268,3 +268,35 @@ pub async fn vault_delete_credential(vault: State<'_, Vault>, id: String) -> Com
         .await
         .map_err(map_err)
 }
+
+/// Check the vault database's health
+#[tauri::command]
+pub async fn vault_db_health(vault: State<'_, Vault>) -> CommandResult<pulsar_db::DbHealth> {
+    vault
+        .with_manager(|manager| Box::pin(async move { manager.database_health().await }))
+        .await
+        .map_err(map_err)
+}
+
+/// Back up the vault database, returning the backup path
+#[tauri::command]
+pub async fn vault_db_backup(vault: State<'_, Vault>) -> CommandResult<String> {
+    vault
+        .with_manager(|manager| {
+            Box::pin(async move {
+                let path = manager.backup_database().await?;
+                Ok(path.display().to_string())
+            })
+        })
+        .await
+        .map_err(map_err)
+}
+
+/// Vacuum the vault database
+#[tauri::command]
+pub async fn vault_db_vacuum(vault: State<'_, Vault>) -> CommandResult<()> {
+    vault
+        .with_manager(|manager| Box::pin(async move { manager.vacuum_database().await }))
+        .await
+        .map_err(map_err)
+}
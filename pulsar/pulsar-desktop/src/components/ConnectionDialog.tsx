@@ -18,7 +18,7 @@ export interface ConnectionConfig {
   host: string
   port: number
   username: string
-  authType: 'password' | 'publickey' | 'agent'
+  authType: 'password' | 'publickey' | 'agent' | 'certificate'
   password?: string
   keyPath?: string
   keyPassphrase?: string
@@ -166,7 +166,7 @@ export default function ConnectionDialog({ isOpen, onClose, onConnect }: Connect
         newErrors.keyPath = 'Key path is required'
       }
     }
-    // Agent auth requires no additional validation
+    // Agent and certificate auth require no additional validation
 
     setErrors(newErrors)
     return Object.keys(newErrors).length === 0
@@ -355,6 +355,16 @@ export default function ConnectionDialog({ isOpen, onClose, onConnect }: Connect
                   </span>
                 </label>
               ) : null}
+              <label className="flex items-center">
+                <input
+                  type="radio"
+                  value="certificate"
+                  checked={config.authType === 'certificate'}
+                  onChange={(e) => setConfig({ ...config, authType: e.target.value as 'certificate' })}
+                  className="mr-2"
+                />
+                <span className="text-sm text-gray-700">SSH Certificate</span>
+              </label>
             </div>
           </div>
 
@@ -457,6 +467,16 @@ export default function ConnectionDialog({ isOpen, onClose, onConnect }: Connect
               </div>
             </div>
           )}
+
+          {/* SSH CA Certificate Authentication */}
+          {config.authType === 'certificate' && (
+            <div className="p-4 bg-blue-50 border border-blue-200 rounded-md">
+              <p className="text-sm text-blue-700">
+                A short-lived certificate is requested from your organization's SSH CA
+                for this user and host. No private key is stored.
+              </p>
+            </div>
+          )}
         </div>
 
         {/* Footer */}
@@ -3,7 +3,12 @@ import FileUploadZone from './FileUploadZone'
 import TransferQueue, { QueuedTransfer } from './TransferQueue'
 import { TransferProgress, TransferResult, TransferError } from '../lib/fileTransferClient'
 
-export default function FileTransferView() {
+interface FileTransferViewProps {
+  /** Terminal session whose working directory receives uploads */
+  sessionId?: string
+}
+
+export default function FileTransferView({ sessionId }: FileTransferViewProps = {}) {
   const [transfers, setTransfers] = useState<Map<string, QueuedTransfer>>(new Map())
   const [activeView, setActiveView] = useState<'upload' | 'queue'>('upload')
 
@@ -218,6 +223,7 @@ export default function FileTransferView() {
         {activeView === 'upload' ? (
           <div className="h-full p-6">
             <FileUploadZone
+              sessionId={sessionId}
               onUploadStart={handleUploadStart}
               onUploadProgress={handleUploadProgress}
               onUploadComplete={handleUploadComplete}
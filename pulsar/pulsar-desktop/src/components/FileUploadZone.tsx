@@ -3,6 +3,7 @@ import { FileTransferClient, TransferProgress, TransferResult, TransferError } f
 
 interface FileUploadZoneProps {
   webtransportUrl?: string
+  sessionId?: string
   onUploadStart?: (files: File[]) => void
   onUploadProgress?: (progress: TransferProgress) => void
   onUploadComplete?: (result: TransferResult) => void
@@ -19,6 +20,7 @@ interface UploadingFile {
 
 export default function FileUploadZone({
   webtransportUrl = 'https://127.0.0.1:4433',
+  sessionId,
   onUploadStart,
   onUploadProgress,
   onUploadComplete,
@@ -113,6 +115,7 @@ export default function FileUploadZone({
         })
 
         const result = await clientRef.current!.uploadFile(file, {
+          sessionId,
           onProgress: (progress) => {
             setUploadingFiles(prev => {
               const newMap = new Map(prev)
@@ -164,7 +167,7 @@ export default function FileUploadZone({
         }
       }
     }
-  }, [onUploadStart, onUploadProgress, onUploadComplete, onUploadError])
+  }, [sessionId, onUploadStart, onUploadProgress, onUploadComplete, onUploadError])
 
   // Open file picker
   const handleClick = useCallback(() => {
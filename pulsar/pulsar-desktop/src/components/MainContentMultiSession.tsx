@@ -23,7 +23,7 @@ interface SessionData extends Session {
     port?: number
     username?: string
     password?: string
-    authType?: 'password' | 'publickey' | 'agent'
+    authType?: 'password' | 'publickey' | 'agent' | 'certificate'
     keyPath?: string
     keyPassphrase?: string
     credentialId?: string | null
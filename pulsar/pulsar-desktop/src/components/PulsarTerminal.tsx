@@ -7,6 +7,8 @@
  * - Auto-fit terminal sizing
  * - Session lifecycle management
  * - Base64 encoding/decoding
+ * - Bracketed paste with a confirmation guard
+ * - Modified keys encoded by the daemon (modifyOtherKeys, kitty keyboard)
  */
 
 import React, { useEffect, useRef, useState } from 'react';
@@ -25,6 +27,83 @@ interface PulsarTerminalProps {
   websocketUrl?: string; // WebSocket URL (default: ws://127.0.0.1:3030)
 }
 
+type PasteWarning =
+  | { kind: 'newlines'; line_breaks: number }
+  | { kind: 'control_characters'; count: number }
+  | { kind: 'bracket_end' };
+
+type PasteOutcome =
+  | { status: 'sent'; bytes_written: number; bracketed: boolean }
+  | { status: 'needs_confirmation'; warnings: PasteWarning[] };
+
+function describePasteWarning(warning: PasteWarning): string {
+  switch (warning.kind) {
+    case 'newlines':
+      return `${warning.line_breaks} line break(s), which may run commands immediately`;
+    case 'control_characters':
+      return `${warning.count} hidden control character(s)`;
+    case 'bracket_end':
+      return 'a sequence that ends bracketed paste early';
+  }
+}
+
+type Key =
+  | { char: string }
+  | { f: number }
+  | 'enter' | 'tab' | 'backspace' | 'escape'
+  | 'up' | 'down' | 'right' | 'left'
+  | 'home' | 'end' | 'insert' | 'delete' | 'page_up' | 'page_down';
+
+const NAMED_KEYS: Record<string, Key> = {
+  Enter: 'enter',
+  Tab: 'tab',
+  Backspace: 'backspace',
+  Escape: 'escape',
+  ArrowUp: 'up',
+  ArrowDown: 'down',
+  ArrowRight: 'right',
+  ArrowLeft: 'left',
+  Home: 'home',
+  End: 'end',
+  Insert: 'insert',
+  Delete: 'delete',
+  PageUp: 'page_up',
+  PageDown: 'page_down',
+};
+
+/** The daemon's name for a DOM key, or null for keys it does not encode */
+function toKey(event: KeyboardEvent): Key | null {
+  if (event.key in NAMED_KEYS) return NAMED_KEYS[event.key];
+  const f = /^F(\d{1,2})$/.exec(event.key);
+  if (f && Number(f[1]) >= 1 && Number(f[1]) <= 12) return { f: Number(f[1]) };
+  if ([...event.key].length === 1) return { char: event.key };
+  return null;
+}
+
+async function pasteText(terminal: Terminal, sessionId: string, text: string) {
+  try {
+    const outcome = await invoke<PasteOutcome>('daemon_paste', {
+      sessionId,
+      text,
+      confirmed: false,
+    });
+    if (outcome.status === 'needs_confirmation') {
+      const details = outcome.warnings.map((w) => `• ${describePasteWarning(w)}`).join('\n');
+      if (!window.confirm(`The pasted text contains:\n${details}\n\nPaste anyway?`)) {
+        return;
+      }
+      await invoke<PasteOutcome>('daemon_paste', {
+        sessionId,
+        text,
+        confirmed: true,
+      });
+    }
+  } catch (err) {
+    console.error('Failed to paste:', err);
+    terminal.write(`\r\n\x1b[31mError pasting: ${err}\x1b[0m\r\n`);
+  }
+}
+
 export const PulsarTerminal: React.FC<PulsarTerminalProps> = ({
   sessionId: providedSessionId,
   onSessionCreated,
@@ -104,9 +183,50 @@ export const PulsarTerminal: React.FC<PulsarTerminalProps> = ({
       }
     });
 
+    // Keys held with Ctrl or Alt go to the daemon, which encodes them for
+    // the keyboard protocol the session's program negotiated; plain typing
+    // stays on the onData path above, and Meta combinations are left to the
+    // OS (Cmd+C / Cmd+V)
+    terminal.attachCustomKeyEventHandler((event) => {
+      if (event.type !== 'keydown' || !sessionId || event.isComposing) return true;
+      if (event.metaKey || !(event.ctrlKey || event.altKey)) return true;
+      const key = toKey(event);
+      if (!key) return true;
+
+      event.preventDefault();
+      invoke<number>('daemon_send_key', {
+        sessionId,
+        key: {
+          key,
+          modifiers: {
+            shift: event.shiftKey,
+            alt: event.altKey,
+            ctrl: event.ctrlKey,
+            meta: false,
+          },
+        },
+      }).catch((err) => {
+        console.error('Failed to send key:', err);
+      });
+      return false;
+    });
+
+    // Route pastes through the daemon so they are bracketed for the
+    // session's program and checked by the paste guard
+    const container = terminalRef.current;
+    const handlePaste = (event: ClipboardEvent) => {
+      const text = event.clipboardData?.getData('text/plain');
+      if (!sessionId || !text) return;
+      event.preventDefault();
+      event.stopPropagation();
+      pasteText(terminal, sessionId, text);
+    };
+    container.addEventListener('paste', handlePaste, true);
+
     setIsReady(true);
 
     return () => {
+      container.removeEventListener('paste', handlePaste, true);
       resizeObserver.disconnect();
       terminal.dispose();
       xtermRef.current = null;
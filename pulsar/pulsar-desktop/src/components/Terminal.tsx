@@ -4,6 +4,7 @@ import { FitAddon } from '@xterm/addon-fit'
 import { WebLinksAddon } from '@xterm/addon-web-links'
 import { SearchAddon } from '@xterm/addon-search'
 import { invoke } from '@tauri-apps/api/core'
+import { listen, UnlistenFn } from '@tauri-apps/api/event'
 import LoadingOverlay from './LoadingOverlay'
 import '@xterm/xterm/css/xterm.css'
 
@@ -13,7 +14,7 @@ interface TerminalProps {
   port?: number
   username?: string
   password?: string
-  authType?: 'password' | 'publickey' | 'agent'
+  authType?: 'password' | 'publickey' | 'agent' | 'certificate'
   keyPath?: string
   keyPassphrase?: string
   credentialId?: string | null
@@ -79,6 +80,7 @@ export default function Terminal({
 
     // Connect to SSH backend if sessionId provided
     let outputPollInterval: NodeJS.Timeout | null = null
+    let unlistenNetwork: UnlistenFn | null = null
 
     if (sessionId) {
       term.writeln(`\x1b[90mConnecting to ${username}@${host}:${port}...\x1b[0m`)
@@ -98,6 +100,10 @@ export default function Terminal({
         authMethod = {
           type: 'agent',
         }
+      } else if (authType === 'certificate') {
+        authMethod = {
+          type: 'certificate',
+        }
       } else {
         authMethod = {
           type: 'password',
@@ -133,6 +139,19 @@ export default function Terminal({
           term.writeln('\x1b[90mType to interact with the session\x1b[0m')
           term.writeln('')
 
+          // Explain pauses while the session moves to a new network
+          unlistenNetwork = await listen<{ session_id: string; state: string; message: string }>(
+            'ssh-session-network',
+            (event) => {
+              if (event.payload.session_id !== sessionId) return
+              if (event.payload.state === 'failed') {
+                term.writeln(`\r\n\x1b[1;31m✗ ${event.payload.message}\x1b[0m`)
+              } else if (event.payload.state !== 'reconnected') {
+                term.writeln(`\r\n\x1b[90m[${event.payload.message}]\x1b[0m`)
+              }
+            }
+          )
+
           // Start polling for output
           outputPollInterval = setInterval(async () => {
             try {
@@ -196,6 +215,7 @@ export default function Terminal({
       if (outputPollInterval) {
         clearInterval(outputPollInterval)
       }
+      unlistenNetwork?.()
 
       // Disconnect SSH session
       if (sshSessionId) {
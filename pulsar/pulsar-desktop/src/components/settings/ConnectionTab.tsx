@@ -134,6 +134,43 @@ export default function ConnectionTab({ settings, onChange }: ConnectionTabProps
         </div>
       )}
 
+      {/* Local Echo */}
+      <div>
+        <label className="block text-sm font-medium text-gray-700 mb-2">
+          Local Echo
+        </label>
+        <select
+          value={settings.local_echo}
+          onChange={(e) => updateSetting('local_echo', e.target.value as ConnectionSettings['local_echo'])}
+          className="block w-full px-3 py-2 border border-gray-300 rounded-md shadow-sm focus:ring-blue-500 focus:border-blue-500"
+        >
+          <option value="off">Off</option>
+          <option value="adaptive">On slow connections</option>
+          <option value="always">Always</option>
+        </select>
+        <p className="text-xs text-gray-500 mt-1">
+          Show typed characters underlined until the server echoes them
+        </p>
+      </div>
+
+      {/* Input Coalescing */}
+      <div>
+        <label className="block text-sm font-medium text-gray-700 mb-2">
+          Input Batching
+        </label>
+        <select
+          value={settings.input_coalescing}
+          onChange={(e) => updateSetting('input_coalescing', e.target.value as ConnectionSettings['input_coalescing'])}
+          className="block w-full px-3 py-2 border border-gray-300 rounded-md shadow-sm focus:ring-blue-500 focus:border-blue-500"
+        >
+          <option value="off">Off</option>
+          <option value="adaptive">On slow connections</option>
+        </select>
+        <p className="text-xs text-gray-500 mt-1">
+          Send fast typing in batches over high-latency links such as satellite
+        </p>
+      </div>
+
       {/* Info Box */}
       <div className="mt-8 p-4 bg-blue-50 border border-blue-200 rounded-md">
         <div className="flex items-start">
@@ -0,0 +1,168 @@
+import { useState } from 'react'
+import type { DiagnosticsReview } from '../../types/settings'
+import settingsClient from '../../lib/settingsClient'
+
+/**
+ * Collects a diagnostics bundle for bug reports and lets the user review
+ * every file and redaction before anything is saved
+ */
+export default function DiagnosticsPanel() {
+  const [collecting, setCollecting] = useState(false)
+  const [saving, setSaving] = useState(false)
+  const [review, setReview] = useState<DiagnosticsReview | null>(null)
+  const [excluded, setExcluded] = useState<string[]>([])
+  const [selected, setSelected] = useState<string | null>(null)
+  const [terms, setTerms] = useState('')
+  const [path, setPath] = useState('')
+
+  const handleCollect = async () => {
+    setCollecting(true)
+    try {
+      const collected = await settingsClient.collectDiagnostics()
+      setReview(collected)
+      setExcluded([])
+      setSelected(collected.files.find((f) => !f.error)?.name ?? null)
+      setPath(`pulsar-diagnostics-${collected.created_at.split('T')[0]}.zip`)
+    } catch (err) {
+      alert(`Failed to collect diagnostics: ${err instanceof Error ? err.message : String(err)}`)
+    } finally {
+      setCollecting(false)
+    }
+  }
+
+  const toggleFile = (name: string) => {
+    setExcluded((current) =>
+      current.includes(name) ? current.filter((n) => n !== name) : [...current, name]
+    )
+  }
+
+  const handleSave = async () => {
+    if (!path.trim()) return
+    setSaving(true)
+    try {
+      const manifest = await settingsClient.saveDiagnostics(path.trim(), {
+        exclude_files: excluded,
+        redact_terms: terms
+          .split(',')
+          .map((t) => t.trim())
+          .filter((t) => t.length > 0),
+      })
+      alert(
+        `Diagnostics saved to ${path.trim()}: ${manifest.files.length} files, ${manifest.redactions} values redacted`
+      )
+      setReview(null)
+    } catch (err) {
+      alert(`Failed to save diagnostics: ${err instanceof Error ? err.message : String(err)}`)
+    } finally {
+      setSaving(false)
+    }
+  }
+
+  return (
+    <div>
+      <h3 className="text-lg font-medium text-gray-900 mb-3">Troubleshooting</h3>
+
+      <button
+        onClick={handleCollect}
+        disabled={collecting || saving}
+        className="px-4 py-2 text-sm font-medium text-gray-700 bg-white border border-gray-300 rounded-md hover:bg-gray-50 disabled:opacity-50 disabled:cursor-not-allowed"
+      >
+        {collecting ? 'Collecting...' : '🩺 Collect Diagnostics'}
+      </button>
+
+      <p className="text-xs text-gray-500 mt-2">
+        Gathers versions, configuration, recent logs, database checks, socket permissions and
+        port bindings into a zip you can attach to a bug report. Secrets are removed, and you
+        can review everything before saving.
+      </p>
+
+      {review && (
+        <div className="mt-4 space-y-4 text-sm">
+          <div className="grid grid-cols-3 gap-4">
+            <ul className="space-y-1">
+              {review.files.map((file) => (
+                <li key={file.name} className="flex items-start">
+                  <input
+                    type="checkbox"
+                    checked={!file.error && !excluded.includes(file.name)}
+                    disabled={!!file.error}
+                    onChange={() => toggleFile(file.name)}
+                    className="mr-2 mt-1"
+                  />
+                  <button
+                    onClick={() => setSelected(file.name)}
+                    disabled={!!file.error}
+                    title={file.error ?? file.description}
+                    className={`text-left ${selected === file.name ? 'font-medium text-blue-700' : 'text-gray-700'} disabled:text-gray-400`}
+                  >
+                    {file.name}
+                    {file.redactions > 0 && (
+                      <span className="ml-1 text-xs text-orange-600">({file.redactions} redacted)</span>
+                    )}
+                    {file.error && <span className="block text-xs">{file.error}</span>}
+                  </button>
+                </li>
+              ))}
+            </ul>
+
+            <pre className="col-span-2 p-2 max-h-80 bg-gray-50 border border-gray-200 rounded overflow-auto text-xs">
+              {selected ? review.contents[selected] : 'Select a file to see what will be saved'}
+            </pre>
+          </div>
+
+          {review.redactions.length > 0 && (
+            <div>
+              <h4 className="font-medium text-gray-700 mb-1">Redacted values</h4>
+              <ul className="max-h-40 overflow-auto text-xs text-gray-600 space-y-1">
+                {review.redactions.map((r, i) => (
+                  <li key={i}>
+                    <span className="font-mono">
+                      {r.file}:{r.line}
+                    </span>{' '}
+                    {r.kind}: <span className="font-mono">{r.context}</span>
+                  </li>
+                ))}
+              </ul>
+            </div>
+          )}
+
+          <label className="block">
+            <span className="text-gray-700">Also redact (comma-separated host names, user names...)</span>
+            <input
+              type="text"
+              value={terms}
+              onChange={(e) => setTerms(e.target.value)}
+              className="mt-1 w-full px-2 py-1 border border-gray-300 rounded-md"
+            />
+          </label>
+
+          <label className="block">
+            <span className="text-gray-700">Save to (relative paths go to your Downloads folder)</span>
+            <input
+              type="text"
+              value={path}
+              onChange={(e) => setPath(e.target.value)}
+              className="mt-1 w-full px-2 py-1 border border-gray-300 rounded-md font-mono"
+            />
+          </label>
+
+          <div className="flex gap-3">
+            <button
+              onClick={handleSave}
+              disabled={saving || !path.trim()}
+              className="px-4 py-2 text-sm font-medium text-white bg-blue-600 rounded-md hover:bg-blue-700 disabled:opacity-50 disabled:cursor-not-allowed"
+            >
+              {saving ? 'Saving...' : 'Save Bundle'}
+            </button>
+            <button
+              onClick={() => setReview(null)}
+              className="px-4 py-2 text-sm font-medium text-gray-700 bg-white border border-gray-300 rounded-md hover:bg-gray-50"
+            >
+              Discard
+            </button>
+          </div>
+        </div>
+      )}
+    </div>
+  )
+}
@@ -1,6 +1,7 @@
 import { useState } from 'react'
-import type { GeneralSettings } from '../../types/settings'
+import type { GeneralSettings, TelemetryPreview } from '../../types/settings'
 import settingsClient from '../../lib/settingsClient'
+import DiagnosticsPanel from './DiagnosticsPanel'
 
 interface GeneralTabProps {
   settings: GeneralSettings
@@ -10,6 +11,19 @@ interface GeneralTabProps {
 export default function GeneralTab({ settings, onChange }: GeneralTabProps) {
   const [exporting, setExporting] = useState(false)
   const [importing, setImporting] = useState(false)
+  const [telemetryPreview, setTelemetryPreview] = useState<TelemetryPreview | null>(null)
+
+  const handleTelemetryPreview = async () => {
+    if (telemetryPreview) {
+      setTelemetryPreview(null)
+      return
+    }
+    try {
+      setTelemetryPreview(await settingsClient.telemetryPreview())
+    } catch (err) {
+      alert(`Failed to load analytics preview: ${err instanceof Error ? err.message : 'Unknown error'}`)
+    }
+  }
 
   const updateSetting = <K extends keyof GeneralSettings>(
     key: K,
@@ -112,10 +126,39 @@ export default function GeneralTab({ settings, onChange }: GeneralTabProps) {
               Send anonymous usage analytics
             </span>
             <p className="text-xs text-gray-500 mt-1">
-              Help improve Pulsar by sending anonymous usage statistics. No personal data or command history is collected.
+              Help improve Pulsar by sending a daily count of which features were used. No personal data, hosts or command text is collected, and turning this off deletes the local counts.
             </p>
           </div>
         </label>
+
+        <button
+          onClick={handleTelemetryPreview}
+          className="mt-2 ml-6 text-xs text-blue-600 hover:underline"
+        >
+          {telemetryPreview ? 'Hide what would be sent' : 'Show what would be sent'}
+        </button>
+
+        {telemetryPreview && (
+          <div className="mt-2 ml-6 text-xs text-gray-600">
+            {!telemetryPreview.enabled ? (
+              <p>Analytics is off: nothing is collected or sent.</p>
+            ) : telemetryPreview.payload ? (
+              <>
+                <p className="mb-1">
+                  Next upload to {telemetryPreview.endpoint}
+                  {telemetryPreview.last_sent &&
+                    ` (last sent ${new Date(telemetryPreview.last_sent).toLocaleString()})`}
+                  :
+                </p>
+                <pre className="p-2 bg-gray-50 border border-gray-200 rounded overflow-x-auto">
+                  {JSON.stringify(JSON.parse(telemetryPreview.payload), null, 2)}
+                </pre>
+              </>
+            ) : (
+              <p>Nothing new to send since the last upload.</p>
+            )}
+          </div>
+        )}
       </div>
 
       {/* Session Management */}
@@ -208,6 +251,9 @@ export default function GeneralTab({ settings, onChange }: GeneralTabProps) {
         </p>
       </div>
 
+      {/* Diagnostics for bug reports */}
+      <DiagnosticsPanel />
+
       {/* Info Box */}
       <div className="mt-8 p-4 bg-purple-50 border border-purple-200 rounded-md">
         <div className="flex items-start">
@@ -116,6 +116,40 @@ export default function SecurityTab({ settings, onChange }: SecurityTabProps) {
             </p>
           </div>
         </label>
+
+        <label className="flex items-start">
+          <input
+            type="checkbox"
+            checked={settings.warn_paste_newlines}
+            onChange={(e) => updateSetting('warn_paste_newlines', e.target.checked)}
+            className="mr-2 mt-1"
+          />
+          <div>
+            <span className="text-sm font-medium text-gray-700">
+              Confirm multi-line pastes
+            </span>
+            <p className="text-xs text-gray-500 mt-1">
+              Pasted line breaks can run commands before you review them
+            </p>
+          </div>
+        </label>
+
+        <label className="flex items-start">
+          <input
+            type="checkbox"
+            checked={settings.warn_paste_control_chars}
+            onChange={(e) => updateSetting('warn_paste_control_chars', e.target.checked)}
+            className="mr-2 mt-1"
+          />
+          <div>
+            <span className="text-sm font-medium text-gray-700">
+              Confirm pastes with control characters
+            </span>
+            <p className="text-xs text-gray-500 mt-1">
+              Text copied from web pages can hide escape sequences that act as keystrokes
+            </p>
+          </div>
+        </label>
       </div>
 
       {/* Notifications */}
@@ -11,6 +11,9 @@ import { bytesToHex } from '@noble/hashes/utils.js';
 export interface TransferOptions {
   chunkSize?: number; // Default: 1 MB
   maxParallelChunks?: number; // Default: 4
+  sessionId?: string; // Save into this terminal session's working directory
+  batchId?: string; // Group uploads for a checksum manifest, see completeBatch()
+  directory?: DirectoryFile; // Place in a folder upload, set by uploadDirectory()
   onProgress?: (progress: TransferProgress) => void;
   onComplete?: (result: TransferResult) => void;
   onError?: (error: TransferError) => void;
@@ -37,6 +40,39 @@ export interface TransferResult {
   averageSpeed: number; // bytes per second
 }
 
+export interface BatchOptions {
+  algorithm?: 'sha256' | 'blake3'; // Default: sha256 (SHA256SUMS)
+  attest?: boolean; // Also write an attestation signed by the daemon
+}
+
+export interface BatchManifest {
+  batchId: string;
+  manifestPath: string;
+  attestationPath?: string;
+  files: number;
+}
+
+/**
+ * Something in a dropped folder; `path` is relative to the folder and
+ * `/`-separated, e.g. `src/main.rs`. Empty folders have no file.
+ */
+export interface DirectoryEntry {
+  path: string;
+  file?: File;
+}
+
+export interface DirectoryOptions extends TransferOptions {
+  preserveMetadata?: boolean; // Send mtimes to be set on the copies. Default: true
+}
+
+export interface DirectoryResult {
+  directoryId: string;
+  savedPath: string;
+  files: number;
+  totalBytes: number;
+  duration: number; // milliseconds
+}
+
 export interface TransferError {
   transferId: string;
   errorType: string;
@@ -57,6 +93,14 @@ interface TransferStartMessage {
     modified_time?: string;
     permissions?: string;
   };
+  session_id?: string;
+  batch_id?: string;
+  directory?: DirectoryFile;
+}
+
+export interface DirectoryFile {
+  directory_id: string;
+  path: string;
 }
 
 interface ChunkDataMessage {
@@ -112,6 +156,76 @@ interface ResumeRequestMessage {
   original_hash: string;
 }
 
+interface BatchCompleteMessage {
+  type: 'batch_complete';
+  batch_id: string;
+  timestamp: number;
+  algorithm: 'sha256' | 'blake3';
+  attest: boolean;
+}
+
+interface BatchManifestMessage {
+  type: 'batch_manifest';
+  batch_id: string;
+  timestamp: number;
+  manifest_path: string;
+  attestation_path?: string;
+  files: number;
+}
+
+type EntryKind = 'directory' | { file: { size: number } };
+
+interface ManifestEntry {
+  path: string;
+  kind: EntryKind;
+  mtime?: string;
+}
+
+interface DirectoryManifest {
+  manifest_id: string;
+  entries: ManifestEntry[];
+}
+
+interface DirectoryStartMessage {
+  type: 'directory_start';
+  directory_id: string;
+  timestamp: number;
+  name: string;
+  manifest_size: number;
+  session_id?: string;
+}
+
+interface DirectoryAckMessage {
+  type: 'directory_ack';
+  directory_id: string;
+  timestamp: number;
+  accepted: boolean;
+  saved_path: string;
+}
+
+interface DirectoryCompleteMessage {
+  type: 'directory_complete';
+  directory_id: string;
+  timestamp: number;
+}
+
+interface DirectorySuccessMessage {
+  type: 'directory_success';
+  directory_id: string;
+  timestamp: number;
+  saved_path: string;
+  files: number;
+  total_bytes: number;
+}
+
+interface ErrorMessage {
+  type: 'error';
+  transfer_id: string;
+  timestamp: number;
+  error_type: string;
+  error_message: string;
+}
+
 interface ResumeInfoMessage {
   transfer_id: string;
   timestamp: number;
@@ -208,6 +322,9 @@ export class FileTransferClient {
         metadata: {
           modified_time: new Date(file.lastModified).toISOString(),
         },
+        session_id: options.sessionId,
+        batch_id: options.batchId,
+        directory: options.directory,
       };
 
       const startJson = JSON.stringify(startMessage);
@@ -563,6 +680,157 @@ export class FileTransferClient {
     }
   }
 
+  /**
+   * Write a checksum manifest for every upload made with `batchId`, once
+   * they have all completed
+   */
+  async completeBatch(batchId: string, options: BatchOptions = {}): Promise<BatchManifest> {
+    const transport = await this.connect();
+    const stream = await transport.createBidirectionalStream();
+    const writer = stream.writable.getWriter();
+    const reader = stream.readable.getReader();
+
+    try {
+      const completeMessage: BatchCompleteMessage = {
+        type: 'batch_complete',
+        batch_id: batchId,
+        timestamp: Date.now(),
+        algorithm: options.algorithm || 'sha256',
+        attest: options.attest || false,
+      };
+      await writer.write(new TextEncoder().encode(JSON.stringify(completeMessage)));
+
+      const { value: responseData } = await reader.read();
+      const response: BatchManifestMessage | ErrorMessage = JSON.parse(new TextDecoder().decode(responseData));
+      if (response.type === 'error') {
+        throw new Error(response.error_message);
+      }
+
+      console.log(`Batch ${batchId}: wrote ${response.manifest_path}`);
+      return {
+        batchId,
+        manifestPath: response.manifest_path,
+        attestationPath: response.attestation_path,
+        files: response.files,
+      };
+    } finally {
+      await writer.close();
+      await reader.cancel();
+    }
+  }
+
+  /**
+   * Upload a dropped folder: the daemon creates its tree from a manifest,
+   * then each file is uploaded into place
+   */
+  async uploadDirectory(
+    name: string,
+    entries: DirectoryEntry[],
+    options: DirectoryOptions = {}
+  ): Promise<DirectoryResult> {
+    const directoryId = `dir-${Date.now()}-${Math.random().toString(36).substr(2, 9)}`;
+    const startTime = Date.now();
+    const manifest = this.buildManifest(entries, options.preserveMetadata ?? true);
+    const manifestData = new TextEncoder().encode(JSON.stringify(manifest));
+
+    const start: DirectoryStartMessage = {
+      type: 'directory_start',
+      directory_id: directoryId,
+      timestamp: Date.now(),
+      name,
+      manifest_size: manifestData.length,
+      session_id: options.sessionId,
+    };
+    const ack = await this.request<DirectoryAckMessage>(start, manifestData);
+    if (!ack.accepted) {
+      throw new Error('Folder rejected by server');
+    }
+    console.log(`Folder ${directoryId} started at ${ack.saved_path}`);
+
+    for (const entry of entries) {
+      if (entry.file) {
+        await this.uploadFile(entry.file, {
+          ...options,
+          directory: { directory_id: directoryId, path: entry.path },
+        });
+      }
+    }
+
+    const complete: DirectoryCompleteMessage = {
+      type: 'directory_complete',
+      directory_id: directoryId,
+      timestamp: Date.now(),
+    };
+    const success = await this.request<DirectorySuccessMessage>(complete);
+    console.log(`Folder ${directoryId} completed: ${success.files} files`);
+    return {
+      directoryId,
+      savedPath: success.saved_path,
+      files: success.files,
+      totalBytes: success.total_bytes,
+      duration: Date.now() - startTime,
+    };
+  }
+
+  /**
+   * Describe a folder's entries, parents before their contents
+   */
+  private buildManifest(entries: DirectoryEntry[], preserveMetadata: boolean): DirectoryManifest {
+    const directories = new Set<string>();
+    for (const entry of entries) {
+      const parts = entry.path.split('/');
+      const ancestors = entry.file ? parts.length - 1 : parts.length;
+      for (let i = 1; i <= ancestors; i++) {
+        directories.add(parts.slice(0, i).join('/'));
+      }
+    }
+
+    const manifestEntries: ManifestEntry[] = [...directories].map((path) => ({
+      path,
+      kind: 'directory' as const,
+    }));
+    for (const entry of entries) {
+      if (entry.file) {
+        manifestEntries.push({
+          path: entry.path,
+          kind: { file: { size: entry.file.size } },
+          mtime: preserveMetadata ? new Date(entry.file.lastModified).toISOString() : undefined,
+        });
+      }
+    }
+    // A parent's path is a prefix of its contents', so it sorts first
+    manifestEntries.sort((a, b) => (a.path < b.path ? -1 : a.path > b.path ? 1 : 0));
+
+    return { manifest_id: crypto.randomUUID(), entries: manifestEntries };
+  }
+
+  /**
+   * Send one message, with raw data after it if given, and read the answer
+   */
+  private async request<T>(message: object, data?: Uint8Array): Promise<T> {
+    const transport = await this.connect();
+    const stream = await transport.createBidirectionalStream();
+    const writer = stream.writable.getWriter();
+    const reader = stream.readable.getReader();
+
+    try {
+      await writer.write(new TextEncoder().encode(JSON.stringify(message)));
+      if (data) {
+        await writer.write(data);
+      }
+
+      const { value: responseData } = await reader.read();
+      const response: T | ErrorMessage = JSON.parse(new TextDecoder().decode(responseData));
+      if ((response as ErrorMessage).type === 'error') {
+        throw new Error((response as ErrorMessage).error_message);
+      }
+      return response as T;
+    } finally {
+      await writer.close();
+      await reader.cancel();
+    }
+  }
+
   /**
    * Cancel a transfer
    */
@@ -8,6 +8,10 @@ import type {
   SecuritySettings,
   KeyboardShortcuts,
   GeneralSettings,
+  TelemetryPreview,
+  DiagnosticsReview,
+  DiagnosticsReviewChoices,
+  DiagnosticsManifest,
 } from '../types/settings'
 
 /**
@@ -123,6 +127,30 @@ class SettingsClient {
   async import(path: string): Promise<AppSettings> {
     return await invoke<AppSettings>('settings_import', { path })
   }
+
+  /**
+   * Exactly what the next usage analytics upload would contain
+   */
+  async telemetryPreview(): Promise<TelemetryPreview> {
+    return await invoke<TelemetryPreview>('telemetry_preview')
+  }
+
+  /**
+   * Collect a redacted diagnostics bundle for review; nothing is written yet
+   */
+  async collectDiagnostics(): Promise<DiagnosticsReview> {
+    return await invoke<DiagnosticsReview>('diagnostics_collect')
+  }
+
+  /**
+   * Save the reviewed diagnostics bundle as a zip at `path`
+   */
+  async saveDiagnostics(
+    path: string,
+    choices: DiagnosticsReviewChoices
+  ): Promise<DiagnosticsManifest> {
+    return await invoke<DiagnosticsManifest>('diagnostics_save', { path, choices })
+  }
 }
 
 // Export singleton instance
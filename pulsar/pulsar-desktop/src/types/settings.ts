@@ -26,6 +26,8 @@ export interface ConnectionSettings {
   keepalive_interval: number // seconds (0 = disabled)
   auto_reconnect: boolean
   max_reconnect_attempts: number
+  local_echo: 'off' | 'adaptive' | 'always'
+  input_coalescing: 'off' | 'adaptive'
 }
 
 export interface SecuritySettings {
@@ -38,6 +40,8 @@ export interface SecuritySettings {
   notify_session_disconnect: boolean
   notify_file_transfer_complete: boolean
   notify_command_threshold: number // seconds (0 = never)
+  warn_paste_newlines: boolean
+  warn_paste_control_chars: boolean
 }
 
 export interface KeyboardShortcuts {
@@ -60,3 +64,56 @@ export interface GeneralSettings {
   confirm_before_exit: boolean
   auto_start_daemon: boolean
 }
+
+export interface TelemetryBatch {
+  install_id: string
+  app_version: string
+  os: string
+  period_start: string
+  period_end: string
+  counts: Record<string, number>
+}
+
+export interface TelemetryPreview {
+  enabled: boolean
+  endpoint: string
+  last_sent: string | null
+  batch: TelemetryBatch | null
+  payload: string | null
+}
+
+export interface DiagnosticsFile {
+  name: string
+  description: string
+  bytes: number
+  redactions: number
+  error: string | null
+}
+
+export interface DiagnosticsRedaction {
+  file: string
+  line: number
+  kind: string
+  context: string
+}
+
+export interface DiagnosticsReview {
+  created_at: string
+  files: DiagnosticsFile[]
+  contents: Record<string, string>
+  redactions: DiagnosticsRedaction[]
+}
+
+export interface DiagnosticsReviewChoices {
+  exclude_files: string[]
+  redact_terms: string[]
+}
+
+export interface DiagnosticsManifest {
+  format_version: number
+  app_version: string
+  created_at: string
+  files: DiagnosticsFile[]
+  excluded: string[]
+  redactions: number
+}
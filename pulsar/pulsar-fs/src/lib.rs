@@ -0,0 +1,233 @@
+//! Crash-Safe File Writes
+//!
+//! Settings, known_hosts, the license cache and exports are written through
+//! this crate so that a crash or power loss mid-write leaves either the old
+//! file or the new one, never a truncated mix:
+//! 1. The contents go to a temporary file beside the target
+//! 2. The temporary file is flushed to disk
+//! 3. It is renamed over the target, which replaces it in one step
+//! 4. On Unix the directory is flushed too, so the rename itself survives
+//!
+//! A file that already exists keeps its permissions; [`write_private`] and
+//! [`AtomicFile::create_private`] make the file readable by its owner only.
+
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// Replace `path` with `contents`
+pub fn write(path: impl AsRef<Path>, contents: impl AsRef<[u8]>) -> io::Result<()> {
+    let mut file = AtomicFile::create(path)?;
+    file.write_all(contents.as_ref())?;
+    file.commit()
+}
+
+/// Replace `path` with `contents`, readable by the owner only
+pub fn write_private(path: impl AsRef<Path>, contents: impl AsRef<[u8]>) -> io::Result<()> {
+    let mut file = AtomicFile::create_private(path)?;
+    file.write_all(contents.as_ref())?;
+    file.commit()
+}
+
+/// A file written in place of `path` that replaces it on [`commit`]
+///
+/// Dropping it without committing leaves `path` as it was.
+///
+/// [`commit`]: AtomicFile::commit
+pub struct AtomicFile {
+    file: Option<File>,
+    temp_path: PathBuf,
+    path: PathBuf,
+}
+
+impl AtomicFile {
+    /// Start writing a new version of `path`
+    pub fn create(path: impl AsRef<Path>) -> io::Result<Self> {
+        Self::open(path.as_ref(), false)
+    }
+
+    /// Start writing a new version of `path`, readable by the owner only
+    pub fn create_private(path: impl AsRef<Path>) -> io::Result<Self> {
+        Self::open(path.as_ref(), true)
+    }
+
+    fn open(path: &Path, private: bool) -> io::Result<Self> {
+        let (file, temp_path) = create_temp(path, private)?;
+
+        // Keep the permissions of the file being replaced
+        #[cfg(unix)]
+        if !private {
+            if let Ok(metadata) = fs::metadata(path) {
+                file.set_permissions(metadata.permissions())?;
+            }
+        }
+
+        Ok(Self {
+            file: Some(file),
+            temp_path,
+            path: path.to_path_buf(),
+        })
+    }
+
+    /// The file that will be replaced
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Flush the new contents to disk and move them into place
+    pub fn commit(mut self) -> io::Result<()> {
+        if let Some(file) = self.file.take() {
+            file.sync_all()?;
+        }
+        fs::rename(&self.temp_path, &self.path)?;
+        sync_dir(parent_dir(&self.path))
+    }
+
+    fn file(&mut self) -> &mut File {
+        self.file.as_mut().expect("file is only taken on commit")
+    }
+}
+
+impl Write for AtomicFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.file().write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.file().flush()
+    }
+}
+
+impl Seek for AtomicFile {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        self.file().seek(pos)
+    }
+}
+
+impl Drop for AtomicFile {
+    fn drop(&mut self) {
+        // Not committed; the target is untouched
+        if self.file.take().is_some() {
+            let _ = fs::remove_file(&self.temp_path);
+        }
+    }
+}
+
+/// Create a uniquely named file beside `path`, hidden on Unix
+fn create_temp(path: &Path, private: bool) -> io::Result<(File, PathBuf)> {
+    static COUNTER: AtomicU64 = AtomicU64::new(0);
+
+    let name = path
+        .file_name()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "path has no file name"))?;
+    let dir = parent_dir(path);
+
+    loop {
+        let mut temp_name = std::ffi::OsString::from(".");
+        temp_name.push(name);
+        temp_name.push(format!(
+            ".{}.{}.tmp",
+            std::process::id(),
+            COUNTER.fetch_add(1, Ordering::Relaxed)
+        ));
+        let temp_path = dir.join(temp_name);
+
+        let mut options = OpenOptions::new();
+        options.write(true).create_new(true);
+        #[cfg(unix)]
+        if private {
+            use std::os::unix::fs::OpenOptionsExt;
+            options.mode(0o600);
+        }
+        #[cfg(not(unix))]
+        let _ = private;
+
+        match options.open(&temp_path) {
+            Ok(file) => return Ok((file, temp_path)),
+            // Left behind by a crashed process with a recycled PID
+            Err(e) if e.kind() == io::ErrorKind::AlreadyExists => continue,
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+fn parent_dir(path: &Path) -> &Path {
+    match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    }
+}
+
+/// Flush a directory's entries, making a rename within it durable
+#[cfg(unix)]
+fn sync_dir(dir: &Path) -> io::Result<()> {
+    File::open(dir)?.sync_all()
+}
+
+/// NTFS journals renames; there is no directory handle to flush
+#[cfg(not(unix))]
+fn sync_dir(_dir: &Path) -> io::Result<()> {
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn entries(dir: &Path) -> Vec<String> {
+        let mut names: Vec<_> = fs::read_dir(dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+            .collect();
+        names.sort();
+        names
+    }
+
+    #[test]
+    fn test_write_replaces_file() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("settings.toml");
+
+        write(&path, "font_size = 14\n").unwrap();
+        write(&path, "font_size = 16\n").unwrap();
+
+        assert_eq!(fs::read_to_string(&path).unwrap(), "font_size = 16\n");
+        assert_eq!(entries(dir.path()), ["settings.toml"]);
+    }
+
+    #[test]
+    fn test_uncommitted_file_leaves_target() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("export.zip");
+        write(&path, "old").unwrap();
+
+        let mut file = AtomicFile::create(&path).unwrap();
+        file.write_all(b"partial").unwrap();
+        drop(file);
+
+        assert_eq!(fs::read_to_string(&path).unwrap(), "old");
+        assert_eq!(entries(dir.path()), ["export.zip"]);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_permissions() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = TempDir::new().unwrap();
+        let mode = |path: &Path| fs::metadata(path).unwrap().permissions().mode() & 0o777;
+
+        let private = dir.path().join("license.cache");
+        write_private(&private, "secret").unwrap();
+        assert_eq!(mode(&private), 0o600);
+
+        // An existing file keeps its mode
+        let shared = dir.path().join("known_hosts");
+        fs::write(&shared, "").unwrap();
+        fs::set_permissions(&shared, fs::Permissions::from_mode(0o640)).unwrap();
+        write(&shared, "host ssh-ed25519 AAAA\n").unwrap();
+        assert_eq!(mode(&shared), 0o640);
+    }
+}
@@ -0,0 +1,155 @@
+//! Shared Daemon Logging
+//!
+//! orbitd and pulsar-daemon set up logging through this crate so both
+//! behave alike:
+//! - Console output, plus an optional log file rotated daily, hourly or by
+//!   size, with a retention limit ([`rotation`])
+//! - File writes on a background thread, so logging never blocks the runtime
+//! - A filter that can be replaced while running ([`set_filter`]), behind
+//!   the daemons' `set_log_level` requests
+
+pub mod rotation;
+
+pub use rotation::{LogFileConfig, RotatingFile, Rotation};
+
+use std::io;
+use std::sync::OnceLock;
+use thiserror::Error;
+use tracing_appender::non_blocking::WorkerGuard;
+use tracing_subscriber::fmt::writer::BoxMakeWriter;
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::util::SubscriberInitExt;
+use tracing_subscriber::{fmt, reload, EnvFilter, Registry};
+
+#[derive(Debug, Error)]
+pub enum LogError {
+    #[error("Invalid log filter {filter:?}: {message}")]
+    InvalidFilter { filter: String, message: String },
+
+    #[error("Logging is already initialized")]
+    AlreadyInitialized,
+
+    #[error("Logging is not initialized")]
+    NotInitialized,
+
+    #[error("Failed to open log file: {0}")]
+    Io(#[from] io::Error),
+}
+
+pub type Result<T> = std::result::Result<T, LogError>;
+
+/// Swaps the filter of the global subscriber
+static FILTER: OnceLock<reload::Handle<EnvFilter, Registry>> = OnceLock::new();
+
+/// How a daemon logs
+#[derive(Debug, Clone, Copy)]
+pub struct LogOptions<'a> {
+    /// Log file name, without `.log`
+    pub name: &'a str,
+    /// Filter directives such as `info,pulsar_daemon::ipc=debug`;
+    /// `RUST_LOG`, when set, takes precedence
+    pub filter: &'a str,
+    /// Also log to a rotating file
+    pub file: Option<&'a LogFileConfig>,
+    /// Console output goes to stderr, for daemons whose stdout is a protocol
+    pub stderr: bool,
+}
+
+/// Flushes the log file when dropped; hold it until the daemon exits
+#[must_use]
+pub struct LogGuard {
+    _worker: Option<WorkerGuard>,
+}
+
+/// Install the global subscriber
+pub fn init(options: LogOptions<'_>) -> Result<LogGuard> {
+    let filter = match EnvFilter::try_from_default_env() {
+        Ok(filter) => filter,
+        Err(_) => parse_filter(options.filter)?,
+    };
+    let (filter, handle) = reload::Layer::new(filter);
+
+    let console = fmt::layer().with_writer(if options.stderr {
+        BoxMakeWriter::new(io::stderr)
+    } else {
+        BoxMakeWriter::new(io::stdout)
+    });
+
+    let (file, worker) = match options.file {
+        Some(config) => {
+            let (writer, worker) =
+                tracing_appender::non_blocking(RotatingFile::open(options.name, config)?);
+            (Some(fmt::layer().with_ansi(false).with_writer(writer)), Some(worker))
+        }
+        None => (None, None),
+    };
+
+    tracing_subscriber::registry()
+        .with(filter)
+        .with(console)
+        .with(file)
+        .try_init()
+        .map_err(|_| LogError::AlreadyInitialized)?;
+    let _ = FILTER.set(handle);
+
+    Ok(LogGuard { _worker: worker })
+}
+
+/// Replace the running filter, returning the one it replaced
+pub fn set_filter(directives: &str) -> Result<String> {
+    let handle = FILTER.get().ok_or(LogError::NotInitialized)?;
+    let filter = parse_filter(directives)?;
+    let previous = handle
+        .with_current(ToString::to_string)
+        .map_err(|_| LogError::NotInitialized)?;
+    handle.reload(filter).map_err(|_| LogError::NotInitialized)?;
+    Ok(previous)
+}
+
+/// The running filter, once logging is initialized
+pub fn current_filter() -> Option<String> {
+    FILTER.get()?.with_current(ToString::to_string).ok()
+}
+
+fn parse_filter(directives: &str) -> Result<EnvFilter> {
+    EnvFilter::try_new(directives).map_err(|e| LogError::InvalidFilter {
+        filter: directives.to_string(),
+        message: e.to_string(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_filter_changes_at_runtime() {
+        assert!(matches!(set_filter("debug"), Err(LogError::NotInitialized)));
+
+        let _guard = init(LogOptions {
+            name: "test",
+            filter: "warn",
+            file: None,
+            stderr: true,
+        })
+        .unwrap();
+        assert!(!tracing::enabled!(tracing::Level::INFO));
+
+        let previous = set_filter("info,pulsar_log=trace").unwrap();
+        assert_eq!(previous, "warn");
+        assert!(tracing::enabled!(tracing::Level::TRACE));
+        assert!(current_filter().unwrap().contains("pulsar_log=trace"));
+
+        assert!(matches!(
+            set_filter("info,=nonsense=="),
+            Err(LogError::InvalidFilter { .. })
+        ));
+        assert!(init(LogOptions {
+            name: "test",
+            filter: "info",
+            file: None,
+            stderr: true,
+        })
+        .is_err());
+    }
+}
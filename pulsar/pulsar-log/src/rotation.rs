@@ -0,0 +1,268 @@
+//! Log file rotation
+//!
+//! The active file keeps a stable name (`<name>.log`) so it can be followed
+//! with `tail -F`. When it is rotated it is renamed after the time of
+//! rotation (`<name>.20261017-140322.log`), and the oldest of those
+//! archives beyond the retention limit are deleted.
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+/// When the log file is started afresh, besides reaching its size limit
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Rotation {
+    Never,
+    Hourly,
+    #[default]
+    Daily,
+}
+
+impl Rotation {
+    /// The period `time` falls in; the file rotates when the period changes
+    fn period(self, time: DateTime<Utc>) -> Option<String> {
+        match self {
+            Rotation::Never => None,
+            Rotation::Hourly => Some(time.format("%Y%m%d%H").to_string()),
+            Rotation::Daily => Some(time.format("%Y%m%d").to_string()),
+        }
+    }
+}
+
+impl FromStr for Rotation {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "never" => Ok(Rotation::Never),
+            "hourly" => Ok(Rotation::Hourly),
+            "daily" => Ok(Rotation::Daily),
+            _ => Err(format!("expected never, hourly or daily, got {:?}", s)),
+        }
+    }
+}
+
+/// Where a daemon's log file goes and how long its history is kept
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LogFileConfig {
+    /// Directory holding the active file and its archives
+    pub dir: PathBuf,
+    #[serde(default)]
+    pub rotation: Rotation,
+    /// Also rotate once the file reaches this many megabytes; 0 means no limit
+    #[serde(default = "default_max_size_mb")]
+    pub max_size_mb: u64,
+    /// Rotated files kept; 0 keeps them all
+    #[serde(default = "default_max_files")]
+    pub max_files: usize,
+}
+
+fn default_max_size_mb() -> u64 {
+    50
+}
+
+fn default_max_files() -> usize {
+    7
+}
+
+impl LogFileConfig {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self {
+            dir: dir.into(),
+            rotation: Rotation::default(),
+            max_size_mb: default_max_size_mb(),
+            max_files: default_max_files(),
+        }
+    }
+}
+
+/// A log file that rotates itself as it is written
+///
+/// Each write lands whole in one file, so with one event per write (as the
+/// tracing formatters do) no line is split across files.
+pub struct RotatingFile {
+    dir: PathBuf,
+    name: String,
+    rotation: Rotation,
+    max_bytes: u64,
+    max_files: usize,
+    file: File,
+    size: u64,
+    period: Option<String>,
+}
+
+impl RotatingFile {
+    /// Open `<dir>/<name>.log`, appending to what an earlier run left
+    pub fn open(name: &str, config: &LogFileConfig) -> io::Result<Self> {
+        fs::create_dir_all(&config.dir)?;
+        let file = open_append(&active_path(&config.dir, name))?;
+        let metadata = file.metadata()?;
+
+        // A file from an earlier period is rotated on the first write
+        let modified = metadata.modified().map(DateTime::<Utc>::from).unwrap_or_else(|_| Utc::now());
+
+        Ok(Self {
+            dir: config.dir.clone(),
+            name: name.to_string(),
+            rotation: config.rotation,
+            max_bytes: config.max_size_mb.saturating_mul(1024 * 1024),
+            max_files: config.max_files,
+            file,
+            size: metadata.len(),
+            period: config.rotation.period(modified),
+        })
+    }
+
+    /// Path of the active file
+    pub fn path(&self) -> PathBuf {
+        active_path(&self.dir, &self.name)
+    }
+
+    fn write_at(&mut self, buf: &[u8], now: DateTime<Utc>) -> io::Result<()> {
+        let period = self.rotation.period(now);
+        let full = self.max_bytes > 0 && self.size > 0 && self.size + buf.len() as u64 > self.max_bytes;
+        if period != self.period || full {
+            self.rotate(now)?;
+            self.period = period;
+        }
+
+        self.file.write_all(buf)?;
+        self.size += buf.len() as u64;
+        Ok(())
+    }
+
+    fn rotate(&mut self, now: DateTime<Utc>) -> io::Result<()> {
+        self.file.flush()?;
+
+        // Rotations within the same second (small size limits) get a counter,
+        // which sorts after the plain name
+        let stamp = now.format("%Y%m%d-%H%M%S").to_string();
+        let mut archive = self.dir.join(format!("{}.{}.log", self.name, stamp));
+        let mut count = 0;
+        while archive.exists() {
+            count += 1;
+            archive = self.dir.join(format!("{}.{}_{:03}.log", self.name, stamp, count));
+        }
+
+        let active = self.path();
+        fs::rename(&active, &archive)?;
+        self.file = open_append(&active)?;
+        self.size = 0;
+
+        self.prune()
+    }
+
+    /// Delete the oldest archives beyond `max_files`
+    fn prune(&self) -> io::Result<()> {
+        if self.max_files == 0 {
+            return Ok(());
+        }
+
+        let mut archives = self.archives()?;
+        archives.sort();
+        let excess = archives.len().saturating_sub(self.max_files);
+        for archive in &archives[..excess] {
+            fs::remove_file(archive)?;
+        }
+        Ok(())
+    }
+
+    /// Rotated files, in no particular order
+    pub fn archives(&self) -> io::Result<Vec<PathBuf>> {
+        let prefix = format!("{}.", self.name);
+        let active = format!("{}.log", self.name);
+
+        let mut archives = Vec::new();
+        for entry in fs::read_dir(&self.dir)? {
+            let file_name = entry?.file_name();
+            let Some(file_name) = file_name.to_str() else {
+                continue;
+            };
+            if file_name.starts_with(&prefix) && file_name.ends_with(".log") && file_name != active {
+                archives.push(self.dir.join(file_name));
+            }
+        }
+        Ok(archives)
+    }
+}
+
+impl Write for RotatingFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.write_at(buf, Utc::now())?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.file.flush()
+    }
+}
+
+fn active_path(dir: &Path, name: &str) -> PathBuf {
+    dir.join(format!("{}.log", name))
+}
+
+fn open_append(path: &Path) -> io::Result<File> {
+    OpenOptions::new().create(true).append(true).open(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+    use tempfile::TempDir;
+
+    fn at(day: u32, hour: u32) -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(2026, 10, day, hour, 0, 0).unwrap()
+    }
+
+    #[test]
+    fn test_daily_rotation_keeps_max_files() {
+        let dir = TempDir::new().unwrap();
+        let config = LogFileConfig {
+            max_files: 2,
+            ..LogFileConfig::new(dir.path())
+        };
+        let mut file = RotatingFile::open("orbitd", &config).unwrap();
+        file.period = Rotation::Daily.period(at(1, 0));
+
+        for day in 1..=5 {
+            file.write_at(format!("day {}\n", day).as_bytes(), at(day, 12)).unwrap();
+            // Same day, same file
+            file.write_at(b"again\n", at(day, 13)).unwrap();
+        }
+
+        let mut archives = file.archives().unwrap();
+        archives.sort();
+        assert_eq!(archives.len(), 2);
+        assert!(archives[0].ends_with("orbitd.20261004-120000.log"));
+        assert_eq!(fs::read_to_string(&archives[1]).unwrap(), "day 4\nagain\n");
+        assert_eq!(fs::read_to_string(file.path()).unwrap(), "day 5\nagain\n");
+    }
+
+    #[test]
+    fn test_size_rotation_never_splits_a_write() {
+        let dir = TempDir::new().unwrap();
+        let config = LogFileConfig {
+            rotation: Rotation::Never,
+            max_files: 0,
+            ..LogFileConfig::new(dir.path())
+        };
+        let mut file = RotatingFile::open("pulsar-daemon", &config).unwrap();
+        file.max_bytes = 10;
+
+        for line in ["0123456\n", "abc\n", "defghij\n", "x\n"] {
+            file.write_at(line.as_bytes(), at(1, 0)).unwrap();
+        }
+
+        // Two rotations in the same second, named in order
+        let mut archives = file.archives().unwrap();
+        archives.sort();
+        let contents: Vec<_> = archives.iter().map(|path| fs::read_to_string(path).unwrap()).collect();
+        assert_eq!(contents, ["0123456\n", "abc\n"]);
+        assert_eq!(fs::read_to_string(file.path()).unwrap(), "defghij\nx\n");
+    }
+}
@@ -0,0 +1,86 @@
+//! Service Manager Integration
+//!
+//! How orbitd and pulsar-daemon cooperate with the service manager that
+//! runs them in the background:
+//! - systemd: socket activation, readiness and watchdog notifications
+//!   ([`systemd`])
+//! - Windows: the Service Control Manager and the event log ([`windows`])
+//!
+//! [`ServiceManager`] covers what the daemons need from either one.
+
+use std::time::Duration;
+use tokio::task::JoinHandle;
+
+#[cfg(unix)]
+pub mod systemd;
+#[cfg(windows)]
+pub mod windows;
+
+/// The service manager running the daemon, if any
+#[derive(Clone, Default)]
+pub struct ServiceManager {
+    #[cfg(windows)]
+    service: Option<windows::ServiceHandle>,
+}
+
+impl ServiceManager {
+    /// Whatever started the process: systemd is found through its
+    /// environment, and outside a service manager every call is a no-op
+    pub fn detect() -> Self {
+        Self::default()
+    }
+
+    /// Running as a Windows service
+    #[cfg(windows)]
+    pub fn windows(service: windows::ServiceHandle) -> Self {
+        Self {
+            service: Some(service),
+        }
+    }
+
+    /// Report that the daemon is serving
+    pub fn ready(&self) {
+        #[cfg(unix)]
+        systemd::notify_ready();
+        #[cfg(windows)]
+        if let Some(service) = &self.service {
+            service.ready();
+        }
+    }
+
+    /// Report that the daemon is shutting down and may take up to `wait`
+    pub fn stopping(&self, status: &str, wait: Duration) {
+        #[cfg(unix)]
+        {
+            // systemd applies TimeoutStopSec= instead
+            let _ = wait;
+            systemd::notify_stopping(status);
+        }
+        #[cfg(windows)]
+        if let Some(service) = &self.service {
+            service.stopping(status, wait);
+        }
+    }
+
+    /// Resolves once the service manager asks the daemon to stop by other
+    /// means than a signal; never resolves outside a Windows service
+    pub async fn stop_requested(&self) {
+        #[cfg(windows)]
+        if let Some(service) = &self.service {
+            return service.stop_requested().await;
+        }
+        std::future::pending().await
+    }
+
+    /// Keep the service manager's watchdog fed, if it has one
+    #[cfg(unix)]
+    pub fn spawn_watchdog(&self) -> Option<JoinHandle<()>> {
+        systemd::spawn_watchdog()
+    }
+
+    /// Keep the service manager's watchdog fed, if it has one
+    #[cfg(not(unix))]
+    pub fn spawn_watchdog(&self) -> Option<JoinHandle<()>> {
+        None
+    }
+}
@@ -0,0 +1,121 @@
+//! systemd integration
+//!
+//! With a `.socket` unit, systemd binds the daemon's Unix socket and passes
+//! it in at startup, so clients connecting while the daemon starts (or
+//! restarts) are queued instead of refused. With `Type=notify` the daemon
+//! reports when it is serving and when it stops, and with `WatchdogSec=` it
+//! pings systemd at half that interval so a hung daemon is restarted.
+//!
+//! Outside systemd every function here is a no-op.
+
+use sd_notify::NotifyState;
+use std::io;
+use std::os::unix::io::FromRawFd;
+use std::os::unix::net::UnixListener;
+use std::time::Duration;
+use tokio::task::JoinHandle;
+use tracing::{debug, info, warn};
+
+/// The Unix socket systemd passed in, if the daemon was socket activated
+///
+/// Call once, early at startup; the activation variables are cleared so
+/// processes the daemon starts do not inherit them.
+pub fn activated_listener() -> io::Result<Option<UnixListener>> {
+    let mut fds = sd_notify::listen_fds()?;
+    let Some(fd) = fds.next() else {
+        return Ok(None);
+    };
+    let extra = fds.count();
+    if extra > 0 {
+        warn!("systemd passed {} sockets besides the first; ignoring them", extra);
+    }
+
+    // SAFETY: systemd hands the daemon ownership of the descriptors it
+    // passes, and listen_fds yields each of them once
+    let listener = unsafe { UnixListener::from_raw_fd(fd) };
+    listener.set_nonblocking(true)?;
+    match listener.local_addr()?.as_pathname() {
+        Some(path) => info!("Using socket {:?} passed in by systemd", path),
+        None => info!("Using unnamed socket passed in by systemd"),
+    }
+    Ok(Some(listener))
+}
+
+/// Report that the daemon is serving
+pub fn notify_ready() {
+    notify(&[NotifyState::Ready]);
+}
+
+/// Report that the daemon is shutting down, with a status line for
+/// `systemctl status`
+pub fn notify_stopping(status: &str) {
+    notify(&[NotifyState::Stopping, NotifyState::Status(status)]);
+}
+
+/// Ping the watchdog, if systemd enabled one, for as long as the returned
+/// task runs
+///
+/// The pings come from the async runtime, so a runtime that stops making
+/// progress stops them too and systemd restarts the daemon.
+pub fn spawn_watchdog() -> Option<JoinHandle<()>> {
+    let mut usec = 0;
+    if !sd_notify::watchdog_enabled(true, &mut usec) || usec == 0 {
+        return None;
+    }
+
+    let period = Duration::from_micros(usec / 2);
+    info!("systemd watchdog enabled, pinging every {:?}", period);
+    Some(tokio::spawn(async move {
+        let mut interval = tokio::time::interval(period);
+        loop {
+            interval.tick().await;
+            notify(&[NotifyState::Watchdog]);
+        }
+    }))
+}
+
+fn notify(state: &[NotifyState]) {
+    // Nothing to tell outside systemd, and nothing to do if it is gone
+    if let Err(e) = sd_notify::notify(false, state) {
+        debug!("Failed to notify systemd: {}", e);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::os::unix::net::UnixDatagram;
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_notifications_reach_notify_socket() {
+        let dir = tempfile::tempdir().unwrap();
+        let socket_path = dir.path().join("notify.sock");
+        let systemd = UnixDatagram::bind(&socket_path).unwrap();
+        systemd.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+
+        std::env::set_var("NOTIFY_SOCKET", &socket_path);
+        std::env::set_var("WATCHDOG_USEC", "100000");
+        std::env::set_var("WATCHDOG_PID", std::process::id().to_string());
+
+        let mut buf = [0; 256];
+        let mut recv = || {
+            let len = systemd.recv(&mut buf).unwrap();
+            String::from_utf8_lossy(&buf[..len]).into_owned()
+        };
+
+        assert!(activated_listener().unwrap().is_none());
+
+        notify_ready();
+        assert_eq!(recv(), "READY=1\n");
+
+        let watchdog = spawn_watchdog().unwrap();
+        assert_eq!(recv(), "WATCHDOG=1\n");
+        assert_eq!(recv(), "WATCHDOG=1\n");
+        watchdog.abort();
+        // Not inherited by child processes
+        assert!(std::env::var_os("WATCHDOG_USEC").is_none());
+
+        notify_stopping("Draining sessions");
+        assert_eq!(recv(), "STOPPING=1\nSTATUS=Draining sessions\n");
+    }
+}
@@ -0,0 +1,224 @@
+//! Windows service
+//!
+//! [`run`] hands the process to the Service Control Manager, which calls
+//! back into the daemon on its own thread. The service reports
+//! StartPending until the daemon says it is serving, and Stop or system
+//! shutdown requests reach the daemon through [`ServiceHandle::stop_requested`].
+//! Lifecycle changes and failures go to the Application event log under
+//! the service's name.
+//!
+//! Register the service to run as the user whose sessions it hosts, e.g.
+//! `sc.exe create PulsarDaemon binPath= "C:\...\pulsar-daemon.exe --service"
+//! obj= .\alice password= ... start= auto`.
+
+use std::ffi::OsString;
+use std::sync::Mutex;
+use std::time::Duration;
+use tokio::sync::watch;
+use tracing::{error, info};
+use windows_service::service::{
+    ServiceControl, ServiceControlAccept, ServiceExitCode, ServiceState, ServiceStatus, ServiceType,
+};
+use windows_service::service_control_handler::{
+    self, ServiceControlHandlerResult, ServiceStatusHandle,
+};
+use windows_service::{define_windows_service, service_dispatcher};
+use windows_sys::Win32::Foundation::HANDLE;
+use windows_sys::Win32::System::EventLog::{
+    DeregisterEventSource, RegisterEventSourceW, ReportEventW, EVENTLOG_ERROR_TYPE,
+    EVENTLOG_INFORMATION_TYPE, EVENTLOG_WARNING_TYPE,
+};
+
+/// How long the SCM waits for the daemon to start serving
+const START_WAIT_HINT: Duration = Duration::from_secs(30);
+
+type ServiceMain = Box<dyn FnOnce(ServiceHandle) -> anyhow::Result<()> + Send>;
+
+/// The service name and the daemon's entry point, for the SCM callback
+static SERVICE: Mutex<Option<(&'static str, ServiceMain)>> = Mutex::new(None);
+
+/// Run `main` as the Windows service `name`; returns once the service stops
+///
+/// Fails when the process was not started by the SCM.
+pub fn run(
+    name: &'static str,
+    main: impl FnOnce(ServiceHandle) -> anyhow::Result<()> + Send + 'static,
+) -> windows_service::Result<()> {
+    *SERVICE.lock().unwrap_or_else(|e| e.into_inner()) = Some((name, Box::new(main)));
+    service_dispatcher::start(name, ffi_service_main)
+}
+
+define_windows_service!(ffi_service_main, service_main);
+
+fn service_main(_arguments: Vec<OsString>) {
+    let Some((name, main)) = SERVICE.lock().unwrap_or_else(|e| e.into_inner()).take() else {
+        return;
+    };
+    let event_log = EventLog::open(name);
+
+    if let Err(e) = run_service(name, main, &event_log) {
+        error!("Service failed: {:#}", e);
+        event_log.report(EventLevel::Error, &format!("{} failed: {:#}", name, e));
+    }
+}
+
+fn run_service(name: &'static str, main: ServiceMain, event_log: &EventLog) -> anyhow::Result<()> {
+    let (stop_tx, stop_rx) = watch::channel(false);
+    let status = service_control_handler::register(name, move |control| match control {
+        ServiceControl::Stop | ServiceControl::Shutdown => {
+            stop_tx.send_replace(true);
+            ServiceControlHandlerResult::NoError
+        }
+        ServiceControl::Interrogate => ServiceControlHandlerResult::NoError,
+        _ => ServiceControlHandlerResult::NotImplemented,
+    })?;
+
+    let handle = ServiceHandle {
+        name,
+        status,
+        stop: stop_rx,
+    };
+    handle.set(ServiceState::StartPending, START_WAIT_HINT);
+
+    let result = main(handle.clone());
+
+    let exit_code = match &result {
+        Ok(()) => ServiceExitCode::NO_ERROR,
+        Err(_) => ServiceExitCode::ServiceSpecific(1),
+    };
+    status.set_service_status(ServiceStatus {
+        service_type: ServiceType::OWN_PROCESS,
+        current_state: ServiceState::Stopped,
+        controls_accepted: ServiceControlAccept::empty(),
+        exit_code,
+        checkpoint: 0,
+        wait_hint: Duration::ZERO,
+        process_id: None,
+    })?;
+    if result.is_ok() {
+        event_log.report(EventLevel::Information, &format!("{} stopped", name));
+    }
+    result
+}
+
+/// The daemon's side of the service: reports its state to the SCM and
+/// learns when to stop
+#[derive(Clone)]
+pub struct ServiceHandle {
+    name: &'static str,
+    status: ServiceStatusHandle,
+    stop: watch::Receiver<bool>,
+}
+
+impl ServiceHandle {
+    /// Report that the daemon is serving and accepts stop requests
+    pub fn ready(&self) {
+        self.set(ServiceState::Running, Duration::ZERO);
+        info!("Service {} running", self.name);
+        EventLog::open(self.name)
+            .report(EventLevel::Information, &format!("{} started", self.name));
+    }
+
+    /// Report that the daemon is shutting down and may take up to `wait`
+    pub fn stopping(&self, status: &str, wait: Duration) {
+        self.set(ServiceState::StopPending, wait);
+        EventLog::open(self.name).report(
+            EventLevel::Information,
+            &format!("{} stopping: {}", self.name, status),
+        );
+    }
+
+    /// Resolves once the SCM asks the service to stop
+    pub async fn stop_requested(&self) {
+        let mut stop = self.stop.clone();
+        // An error means the handler is gone, which only happens on exit
+        let _ = stop.wait_for(|stop| *stop).await;
+    }
+
+    fn set(&self, state: ServiceState, wait_hint: Duration) {
+        let controls_accepted = match state {
+            ServiceState::Running => ServiceControlAccept::STOP | ServiceControlAccept::SHUTDOWN,
+            _ => ServiceControlAccept::empty(),
+        };
+        let result = self.status.set_service_status(ServiceStatus {
+            service_type: ServiceType::OWN_PROCESS,
+            current_state: state,
+            controls_accepted,
+            exit_code: ServiceExitCode::NO_ERROR,
+            checkpoint: 0,
+            wait_hint,
+            process_id: None,
+        });
+        if let Err(e) = result {
+            error!("Failed to report service state {:?}: {}", state, e);
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EventLevel {
+    Error,
+    Warning,
+    Information,
+}
+
+/// An event source in the Application log
+///
+/// Without a registered message file Event Viewer prefixes each message
+/// with a note that the description is missing; the text still follows.
+pub struct EventLog {
+    handle: HANDLE,
+}
+
+impl EventLog {
+    /// Open the source `name`; reports are dropped if that fails
+    pub fn open(name: &str) -> Self {
+        let name = wide(name);
+        // SAFETY: `name` is a NUL-terminated UTF-16 string that outlives the call
+        let handle = unsafe { RegisterEventSourceW(std::ptr::null(), name.as_ptr()) };
+        Self { handle }
+    }
+
+    pub fn report(&self, level: EventLevel, message: &str) {
+        if self.handle == 0 {
+            return;
+        }
+
+        let event_type = match level {
+            EventLevel::Error => EVENTLOG_ERROR_TYPE,
+            EventLevel::Warning => EVENTLOG_WARNING_TYPE,
+            EventLevel::Information => EVENTLOG_INFORMATION_TYPE,
+        };
+        let message = wide(message);
+        let strings = [message.as_ptr()];
+        // SAFETY: the handle is open, and `strings` holds one NUL-terminated
+        // UTF-16 string that outlives the call
+        unsafe {
+            ReportEventW(
+                self.handle,
+                event_type,
+                0,
+                1,
+                std::ptr::null_mut(),
+                strings.len() as u16,
+                0,
+                strings.as_ptr(),
+                std::ptr::null(),
+            );
+        }
+    }
+}
+
+impl Drop for EventLog {
+    fn drop(&mut self) {
+        if self.handle != 0 {
+            // SAFETY: the handle came from RegisterEventSourceW and is
+            // deregistered once
+            unsafe { DeregisterEventSource(self.handle) };
+        }
+    }
+}
+
+fn wide(s: &str) -> Vec<u16> {
+    s.encode_utf16().chain(std::iter::once(0)).collect()
+}
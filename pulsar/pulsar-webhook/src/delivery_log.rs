@@ -0,0 +1,85 @@
+//! Record of webhook delivery attempts
+//!
+//! One JSON object per line, appended as attempts finish. When the file
+//! passes [`MAX_LOG_BYTES`] it is moved aside to `<name>.1` (replacing the
+//! previous one) and a new file is started.
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::fs::{File, OpenOptions};
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use uuid::Uuid;
+
+/// Size at which the log is rotated
+pub const MAX_LOG_BYTES: u64 = 5 * 1024 * 1024;
+
+/// One attempt to deliver an event to one endpoint
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DeliveryRecord {
+    /// The event's ID
+    pub delivery_id: Uuid,
+    pub event: String,
+    pub url: String,
+    /// 1 for the first attempt
+    pub attempt: u32,
+    /// HTTP status, when the endpoint answered
+    pub status: Option<u16>,
+    pub error: Option<String>,
+    pub delivered: bool,
+    pub at: DateTime<Utc>,
+}
+
+/// Append-only delivery log file
+pub struct DeliveryLog {
+    path: PathBuf,
+    file: Mutex<File>,
+}
+
+impl DeliveryLog {
+    pub fn open(path: PathBuf) -> io::Result<Self> {
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        let file = Self::open_file(&path)?;
+        Ok(Self {
+            path,
+            file: Mutex::new(file),
+        })
+    }
+
+    fn open_file(path: &Path) -> io::Result<File> {
+        OpenOptions::new().create(true).append(true).open(path)
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn append(&self, record: &DeliveryRecord) -> io::Result<()> {
+        let mut line = serde_json::to_vec(record)?;
+        line.push(b'\n');
+
+        let mut file = self.file.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        if file.metadata()?.len() + line.len() as u64 > MAX_LOG_BYTES {
+            let mut rotated = self.path.clone().into_os_string();
+            rotated.push(".1");
+            std::fs::rename(&self.path, rotated)?;
+            *file = Self::open_file(&self.path)?;
+        }
+        file.write_all(&line)
+    }
+
+    /// Every record in the log at `path`, oldest first; lines that don't
+    /// parse are skipped
+    pub fn read(path: &Path) -> io::Result<Vec<DeliveryRecord>> {
+        let mut records = Vec::new();
+        for line in BufReader::new(File::open(path)?).lines() {
+            if let Ok(record) = serde_json::from_str(&line?) {
+                records.push(record);
+            }
+        }
+        Ok(records)
+    }
+}
@@ -0,0 +1,474 @@
+//! Outbound Webhooks
+//!
+//! orbitd and pulsar-daemon report selected events by POSTing them as JSON
+//! to the endpoints in their `webhooks` config:
+//! - Requests are signed with the endpoint's secret ([`sign`]), so
+//!   receivers can tell they came from the daemon
+//! - Failed deliveries are retried with exponential backoff; client errors
+//!   (4xx other than 408 and 429) are not
+//! - Every attempt is appended to a delivery log ([`DeliveryLog`])
+//!
+//! Deliveries run in the background, so [`Webhooks::notify`] never waits
+//! on the network.
+
+pub mod delivery_log;
+
+pub use delivery_log::{DeliveryLog, DeliveryRecord};
+
+use chrono::{DateTime, Utc};
+use ring::hmac;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+use thiserror::Error;
+use tracing::{debug, warn};
+use uuid::Uuid;
+
+/// A file transfer was received, verified and saved (pulsar-daemon)
+pub const TRANSFER_COMPLETED: &str = "transfer.completed";
+/// A terminal session lost its last client or was terminated (pulsar-daemon)
+pub const SESSION_DISCONNECTED: &str = "session.disconnected";
+/// A session's processes went over a CPU or memory threshold (pulsar-daemon)
+pub const SESSION_RESOURCE_ALERT: &str = "session.resource_alert";
+/// A command was refused by the safety checks (orbitd)
+pub const COMMAND_BLOCKED: &str = "command.blocked";
+
+/// Every event an endpoint can subscribe to
+pub const EVENTS: &[&str] = &[
+    TRANSFER_COMPLETED,
+    SESSION_DISCONNECTED,
+    SESSION_RESOURCE_ALERT,
+    COMMAND_BLOCKED,
+];
+
+/// Longest wait between two attempts
+const MAX_RETRY_DELAY: Duration = Duration::from_secs(300);
+
+#[derive(Debug, Error)]
+pub enum WebhookError {
+    #[error("Failed to set up the HTTP client: {0}")]
+    Client(#[from] reqwest::Error),
+
+    #[error("Failed to open the delivery log: {0}")]
+    Io(#[from] std::io::Error),
+}
+
+pub type Result<T> = std::result::Result<T, WebhookError>;
+
+/// Where events are sent and how hard to try
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct WebhooksConfig {
+    /// Attempts per delivery, the first included
+    pub max_attempts: u32,
+    /// Seconds each attempt may take
+    pub timeout_secs: u64,
+    pub endpoints: Vec<WebhookEndpoint>,
+}
+
+impl Default for WebhooksConfig {
+    fn default() -> Self {
+        Self {
+            max_attempts: 5,
+            timeout_secs: 10,
+            endpoints: Vec::new(),
+        }
+    }
+}
+
+impl WebhooksConfig {
+    /// Settings that parse but cannot work
+    pub fn problems(&self) -> Vec<String> {
+        let mut problems = Vec::new();
+        if self.max_attempts == 0 {
+            problems.push("webhooks.max_attempts must not be 0".to_string());
+        }
+        if self.timeout_secs == 0 {
+            problems.push("webhooks.timeout_secs must not be 0".to_string());
+        }
+        for endpoint in &self.endpoints {
+            match reqwest::Url::parse(&endpoint.url) {
+                Ok(url) if matches!(url.scheme(), "http" | "https") => {}
+                Ok(_) => problems.push(format!("webhook URL {:?} is not http or https", endpoint.url)),
+                Err(e) => problems.push(format!("webhook URL {:?} is invalid: {}", endpoint.url, e)),
+            }
+            for event in &endpoint.events {
+                if !EVENTS.contains(&event.as_str()) {
+                    problems.push(format!(
+                        "webhook event {:?} is not one of {}",
+                        event,
+                        EVENTS.join(", ")
+                    ));
+                }
+            }
+        }
+        problems
+    }
+}
+
+/// One receiver of events
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WebhookEndpoint {
+    pub url: String,
+    /// Key for the `X-Pulsar-Signature` header; requests are unsigned
+    /// without one
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub secret: Option<String>,
+    /// Events to send; every event when empty
+    #[serde(default)]
+    pub events: Vec<String>,
+}
+
+impl WebhookEndpoint {
+    fn wants(&self, event: &str) -> bool {
+        self.events.is_empty() || self.events.iter().any(|wanted| wanted == event)
+    }
+}
+
+/// The JSON body of every webhook request
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Event {
+    /// Same for every endpoint and attempt, so receivers can drop repeats
+    pub id: Uuid,
+    pub event: String,
+    /// Daemon that sent it
+    pub source: String,
+    pub timestamp: DateTime<Utc>,
+    pub data: Value,
+}
+
+/// `sha256=` and the hex HMAC-SHA256, keyed with `secret`, of
+/// `<timestamp>.<body>`; `timestamp` is the `X-Pulsar-Timestamp` header
+pub fn sign(secret: &str, timestamp: i64, body: &[u8]) -> String {
+    let key = hmac::Key::new(hmac::HMAC_SHA256, secret.as_bytes());
+    let mut context = hmac::Context::with_key(&key);
+    context.update(format!("{}.", timestamp).as_bytes());
+    context.update(body);
+    let tag = context.sign();
+    let hex: String = tag.as_ref().iter().map(|byte| format!("{:02x}", byte)).collect();
+    format!("sha256={}", hex)
+}
+
+/// Sends a daemon's events to its configured endpoints
+pub struct Webhooks {
+    source: &'static str,
+    client: reqwest::Client,
+    endpoints: Arc<[WebhookEndpoint]>,
+    max_attempts: u32,
+    retry_delay: Duration,
+    log: Option<Arc<DeliveryLog>>,
+}
+
+impl Webhooks {
+    /// Endpoints from `config`, with deliveries logged to `log` when given
+    pub fn new(source: &'static str, config: &WebhooksConfig, log: Option<PathBuf>) -> Result<Self> {
+        let client = reqwest::Client::builder()
+            .timeout(Duration::from_secs(config.timeout_secs))
+            .user_agent(format!("{}/{}", source, env!("CARGO_PKG_VERSION")))
+            .build()?;
+        let log = match log {
+            Some(path) if !config.endpoints.is_empty() => Some(Arc::new(DeliveryLog::open(path)?)),
+            _ => None,
+        };
+
+        Ok(Self {
+            source,
+            client,
+            endpoints: config.endpoints.clone().into(),
+            max_attempts: config.max_attempts.max(1),
+            retry_delay: Duration::from_secs(1),
+            log,
+        })
+    }
+
+    /// Wait `delay` before the first retry, doubling it for each one after
+    /// (1 second by default)
+    pub fn with_retry_delay(mut self, delay: Duration) -> Self {
+        self.retry_delay = delay;
+        self
+    }
+
+    /// Whether any endpoint is configured
+    pub fn is_enabled(&self) -> bool {
+        !self.endpoints.is_empty()
+    }
+
+    /// Send `event` to every endpoint subscribed to it, in the background;
+    /// returns the event's ID, or None when nobody subscribed
+    pub fn notify(&self, event: &str, data: Value) -> Option<Uuid> {
+        let endpoints: Vec<WebhookEndpoint> = self
+            .endpoints
+            .iter()
+            .filter(|endpoint| endpoint.wants(event))
+            .cloned()
+            .collect();
+        if endpoints.is_empty() {
+            return None;
+        }
+
+        let event = Event {
+            id: Uuid::new_v4(),
+            event: event.to_string(),
+            source: self.source.to_string(),
+            timestamp: Utc::now(),
+            data,
+        };
+        let body = match serde_json::to_vec(&event) {
+            Ok(body) => Arc::new(body),
+            Err(e) => {
+                warn!("Failed to encode {} webhook: {}", event.event, e);
+                return None;
+            }
+        };
+
+        for endpoint in endpoints {
+            let delivery = Delivery {
+                client: self.client.clone(),
+                endpoint,
+                event: event.event.clone(),
+                id: event.id,
+                body: Arc::clone(&body),
+                max_attempts: self.max_attempts,
+                retry_delay: self.retry_delay,
+                log: self.log.clone(),
+            };
+            tokio::spawn(delivery.run());
+        }
+        Some(event.id)
+    }
+}
+
+/// One event on its way to one endpoint
+struct Delivery {
+    client: reqwest::Client,
+    endpoint: WebhookEndpoint,
+    event: String,
+    id: Uuid,
+    body: Arc<Vec<u8>>,
+    max_attempts: u32,
+    retry_delay: Duration,
+    log: Option<Arc<DeliveryLog>>,
+}
+
+impl Delivery {
+    async fn run(self) -> bool {
+        let mut delay = self.retry_delay;
+
+        for attempt in 1..=self.max_attempts {
+            let (status, error, retry) = self.attempt().await;
+            let delivered = error.is_none();
+
+            if let Some(log) = &self.log {
+                let record = DeliveryRecord {
+                    delivery_id: self.id,
+                    event: self.event.clone(),
+                    url: self.endpoint.url.clone(),
+                    attempt,
+                    status,
+                    error: error.clone(),
+                    delivered,
+                    at: Utc::now(),
+                };
+                if let Err(e) = log.append(&record) {
+                    warn!("Failed to write webhook delivery log: {}", e);
+                }
+            }
+
+            match error {
+                None => {
+                    debug!("Delivered {} {} to {}", self.event, self.id, self.endpoint.url);
+                    return true;
+                }
+                Some(error) if retry && attempt < self.max_attempts => {
+                    debug!(
+                        "Webhook {} to {} failed (attempt {}), retrying in {:?}: {}",
+                        self.event, self.endpoint.url, attempt, delay, error
+                    );
+                    tokio::time::sleep(delay).await;
+                    delay = (delay * 2).min(MAX_RETRY_DELAY);
+                }
+                Some(error) => {
+                    warn!(
+                        "Giving up on webhook {} to {} after {} attempt(s): {}",
+                        self.event, self.endpoint.url, attempt, error
+                    );
+                    return false;
+                }
+            }
+        }
+        false
+    }
+
+    /// The response status, the error if it failed, and whether trying
+    /// again might help
+    async fn attempt(&self) -> (Option<u16>, Option<String>, bool) {
+        let timestamp = Utc::now().timestamp();
+        let mut request = self
+            .client
+            .post(&self.endpoint.url)
+            .header(reqwest::header::CONTENT_TYPE, "application/json")
+            .header("X-Pulsar-Event", &self.event)
+            .header("X-Pulsar-Delivery", self.id.to_string())
+            .header("X-Pulsar-Timestamp", timestamp.to_string())
+            .body(self.body.as_ref().clone());
+        if let Some(secret) = &self.endpoint.secret {
+            request = request.header("X-Pulsar-Signature", sign(secret, timestamp, &self.body));
+        }
+
+        match request.send().await {
+            Ok(response) => {
+                let status = response.status();
+                if status.is_success() {
+                    (Some(status.as_u16()), None, false)
+                } else {
+                    let retry = status.is_server_error()
+                        || status == reqwest::StatusCode::REQUEST_TIMEOUT
+                        || status == reqwest::StatusCode::TOO_MANY_REQUESTS;
+                    (Some(status.as_u16()), Some(format!("HTTP {}", status)), retry)
+                }
+            }
+            Err(e) => (None, Some(e.to_string()), true),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+    use tokio::sync::mpsc;
+
+    /// An HTTP server answering with `statuses` in turn, passing on each
+    /// request's headers and body
+    async fn receiver(statuses: &'static [u16]) -> (String, mpsc::UnboundedReceiver<(String, Vec<u8>)>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/hook", listener.local_addr().unwrap());
+        let (tx, rx) = mpsc::unbounded_channel();
+
+        tokio::spawn(async move {
+            for status in statuses {
+                let (mut stream, _) = listener.accept().await.unwrap();
+                let mut request = Vec::new();
+                let mut buffer = [0u8; 4096];
+                let (head, body_start) = loop {
+                    let n = stream.read(&mut buffer).await.unwrap();
+                    request.extend_from_slice(&buffer[..n]);
+                    if let Some(end) = request.windows(4).position(|w| w == b"\r\n\r\n") {
+                        break (String::from_utf8_lossy(&request[..end]).to_lowercase(), end + 4);
+                    }
+                };
+                let length: usize = head
+                    .lines()
+                    .find_map(|line| line.strip_prefix("content-length:"))
+                    .map(|value| value.trim().parse().unwrap())
+                    .unwrap_or(0);
+                while request.len() < body_start + length {
+                    let n = stream.read(&mut buffer).await.unwrap();
+                    request.extend_from_slice(&buffer[..n]);
+                }
+
+                let response = format!("HTTP/1.1 {} X\r\ncontent-length: 0\r\nconnection: close\r\n\r\n", status);
+                stream.write_all(response.as_bytes()).await.unwrap();
+                tx.send((head, request[body_start..].to_vec())).unwrap();
+            }
+        });
+        (url, rx)
+    }
+
+    fn header<'a>(head: &'a str, name: &str) -> &'a str {
+        head.lines()
+            .find_map(|line| line.strip_prefix(&format!("{}:", name)))
+            .unwrap()
+            .trim()
+    }
+
+    fn config(url: String, events: &[&str]) -> WebhooksConfig {
+        WebhooksConfig {
+            endpoints: vec![WebhookEndpoint {
+                url,
+                secret: Some("s3cret".to_string()),
+                events: events.iter().map(|event| event.to_string()).collect(),
+            }],
+            ..WebhooksConfig::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_signed_delivery_with_retry() {
+        let dir = tempfile::tempdir().unwrap();
+        let log_path = dir.path().join("webhooks.jsonl");
+        let (url, mut requests) = receiver(&[503, 200]).await;
+        let webhooks = Webhooks::new("test", &config(url, &[]), Some(log_path.clone()))
+            .unwrap()
+            .with_retry_delay(Duration::from_millis(10));
+
+        let id = webhooks
+            .notify(TRANSFER_COMPLETED, json!({ "file_name": "a.txt" }))
+            .unwrap();
+
+        for _ in 0..2 {
+            let (head, body) = requests.recv().await.unwrap();
+            assert_eq!(header(&head, "x-pulsar-event"), TRANSFER_COMPLETED);
+            assert_eq!(header(&head, "x-pulsar-delivery"), id.to_string());
+            let timestamp: i64 = header(&head, "x-pulsar-timestamp").parse().unwrap();
+            assert_eq!(header(&head, "x-pulsar-signature"), sign("s3cret", timestamp, &body));
+
+            let event: Event = serde_json::from_slice(&body).unwrap();
+            assert_eq!(event.id, id);
+            assert_eq!(event.source, "test");
+            assert_eq!(event.data["file_name"], "a.txt");
+        }
+
+        // The log is written after the response is read
+        let mut records = Vec::new();
+        for _ in 0..100 {
+            records = DeliveryLog::read(&log_path).unwrap();
+            if records.len() == 2 {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        assert_eq!(records.len(), 2);
+        assert_eq!((records[0].attempt, records[0].status, records[0].delivered), (1, Some(503), false));
+        assert_eq!((records[1].attempt, records[1].status, records[1].delivered), (2, Some(200), true));
+    }
+
+    #[tokio::test]
+    async fn test_client_errors_are_not_retried() {
+        let (url, mut requests) = receiver(&[400, 200]).await;
+        let endpoint = config(url.clone(), &[COMMAND_BLOCKED]).endpoints.remove(0);
+        let delivery = Delivery {
+            client: reqwest::Client::new(),
+            endpoint,
+            event: COMMAND_BLOCKED.to_string(),
+            id: Uuid::new_v4(),
+            body: Arc::new(b"{}".to_vec()),
+            max_attempts: 3,
+            retry_delay: Duration::from_millis(10),
+            log: None,
+        };
+        assert!(!delivery.run().await);
+        requests.recv().await.unwrap();
+        assert!(requests.try_recv().is_err());
+    }
+
+    #[test]
+    fn test_event_filter_and_config_problems() {
+        let config = config("http://localhost/".to_string(), &[COMMAND_BLOCKED]);
+        assert!(config.endpoints[0].wants(COMMAND_BLOCKED));
+        assert!(!config.endpoints[0].wants(TRANSFER_COMPLETED));
+
+        // Nobody subscribed, so nothing is spawned (no runtime needed)
+        let webhooks = Webhooks::new("test", &config, None).unwrap();
+        assert!(webhooks.notify(SESSION_DISCONNECTED, json!({})).is_none());
+
+        assert!(WebhooksConfig::default().problems().is_empty());
+        let mut bad = self::config("ftp://example.com".to_string(), &["transfer.done"]);
+        bad.max_attempts = 0;
+        assert_eq!(bad.problems().len(), 3);
+    }
+}
@@ -0,0 +1,106 @@
+//! Throughput of the PTY output path: parsing, the daemon's per-read
+//! tracking, and broadcast to attached clients
+//!
+//! Run with `cargo bench -p terminal-core --bench pipeline`. Each workload is
+//! 4MB by default; set `PULSAR_BENCH_MB=100` for the full `cat` of a 100MB
+//! log. The matching rendering benchmark lives in terminal-wasm.
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use terminal_core::workload::PTY_READ_SIZE;
+use terminal_core::{AnsiParser, DaemonStage, Workload};
+use tokio::sync::broadcast;
+
+/// Clients attached to the broadcast benchmark's session
+const SUBSCRIBERS: usize = 4;
+
+fn workload_size() -> usize {
+    let mb = std::env::var("PULSAR_BENCH_MB")
+        .ok()
+        .and_then(|mb| mb.parse().ok())
+        .unwrap_or(4);
+    mb << 20
+}
+
+fn bench_parse(c: &mut Criterion) {
+    let mut group = c.benchmark_group("parse");
+    for workload in Workload::ALL {
+        let data = workload.generate(workload_size());
+        group.throughput(Throughput::Bytes(data.len() as u64));
+        group.bench_with_input(BenchmarkId::from_parameter(workload.name()), &data, |b, data| {
+            let mut parser = AnsiParser::new();
+            b.iter(|| {
+                for chunk in data.chunks(PTY_READ_SIZE) {
+                    criterion::black_box(parser.parse(chunk));
+                }
+            })
+        });
+    }
+    group.finish();
+}
+
+fn bench_daemon_stage(c: &mut Criterion) {
+    let mut group = c.benchmark_group("daemon_stage");
+    for workload in Workload::ALL {
+        let data = workload.generate(workload_size());
+        group.throughput(Throughput::Bytes(data.len() as u64));
+        group.bench_with_input(BenchmarkId::from_parameter(workload.name()), &data, |b, data| {
+            let mut stage = DaemonStage::new(10_000);
+            b.iter(|| {
+                for chunk in data.chunks(PTY_READ_SIZE) {
+                    stage.feed(chunk);
+                }
+            })
+        });
+    }
+    group.finish();
+}
+
+/// Per-read tracking plus fan-out to every attached client, as the daemon's
+/// output broadcaster does it
+fn bench_broadcast(c: &mut Criterion) {
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+    let mut group = c.benchmark_group("broadcast");
+    for workload in Workload::ALL {
+        let data = workload.generate(workload_size());
+        group.throughput(Throughput::Bytes(data.len() as u64));
+        group.bench_with_input(BenchmarkId::from_parameter(workload.name()), &data, |b, data| {
+            let mut stage = DaemonStage::new(10_000);
+            b.iter(|| {
+                runtime.block_on(async {
+                    let (tx, _) = broadcast::channel::<Vec<u8>>(1024);
+                    let clients: Vec<_> = (0..SUBSCRIBERS)
+                        .map(|_| {
+                            let mut rx = tx.subscribe();
+                            tokio::spawn(async move {
+                                let mut received = 0;
+                                loop {
+                                    match rx.recv().await {
+                                        Ok(chunk) => received += chunk.len(),
+                                        Err(broadcast::error::RecvError::Lagged(_)) => {}
+                                        Err(broadcast::error::RecvError::Closed) => break,
+                                    }
+                                }
+                                received
+                            })
+                        })
+                        .collect();
+
+                    for chunk in data.chunks(PTY_READ_SIZE) {
+                        stage.feed(chunk);
+                        tx.send(chunk.to_vec()).unwrap();
+                        // Let clients keep up, as the PTY read would
+                        tokio::task::yield_now().await;
+                    }
+                    drop(tx);
+                    for client in clients {
+                        criterion::black_box(client.await.unwrap());
+                    }
+                })
+            })
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_parse, bench_daemon_stage, bench_broadcast);
+criterion_main!(benches);
@@ -0,0 +1,213 @@
+//! Input coalescing
+//!
+//! Over very slow links (satellite, congested mobile) every keystroke sent
+//! on its own costs a packet and a channel write. Typed text is instead held
+//! for a short interval and written as one batch. The interval follows the
+//! measured round trip: nothing is held on a fast link, and on a slow one
+//! holding a key for a fraction of the round trip is not noticeable next to
+//! the wait for its echo.
+//!
+//! Only printable text is held. Any other byte (Enter, Ctrl-C, escape
+//! sequences) goes out at once together with whatever is held before it, so
+//! commands and interrupts are never delayed.
+
+use std::time::{Duration, Instant};
+
+use serde::{Deserialize, Serialize};
+
+/// Round trip below which input is written as typed
+const COALESCE_ABOVE: Duration = Duration::from_millis(80);
+
+/// Fraction of the round trip input may be held for
+const RTT_DIVISOR: u32 = 8;
+
+/// Longest input is ever held
+const MAX_INTERVAL: Duration = Duration::from_millis(40);
+
+/// Held bytes that force a write
+const MAX_BATCH: usize = 1024;
+
+/// Whether to batch typed input
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CoalesceMode {
+    /// Write every chunk of input as it arrives
+    #[default]
+    Off,
+    /// Batch input while the measured round trip is slow
+    Adaptive,
+}
+
+/// What coalescing has done for one session so far
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CoalesceStats {
+    /// Input chunks received, usually one per keystroke
+    pub chunks_in: u64,
+    /// Writes made to the remote end
+    pub writes_out: u64,
+    pub bytes: u64,
+    /// Longest any input is currently held
+    pub interval_ms: u64,
+    /// Smoothed time from a write to the next output, if measured
+    pub srtt_ms: Option<u64>,
+}
+
+/// Batches input for one session
+///
+/// Pass every input chunk through `input` and write what it returns; call
+/// `flush` once `deadline` passes, and `output` whenever output arrives.
+pub struct InputCoalescer {
+    mode: CoalesceMode,
+    held: Vec<u8>,
+    /// When the oldest held byte arrived
+    held_since: Option<Instant>,
+    /// When the oldest write still waiting for output was made
+    awaiting_since: Option<Instant>,
+    srtt: Option<Duration>,
+    stats: CoalesceStats,
+}
+
+impl InputCoalescer {
+    pub fn new(mode: CoalesceMode) -> Self {
+        Self {
+            mode,
+            held: Vec::new(),
+            held_since: None,
+            awaiting_since: None,
+            srtt: None,
+            stats: CoalesceStats::default(),
+        }
+    }
+
+    /// Take an input chunk; returns bytes to write now, if any
+    pub fn input(&mut self, data: &[u8], now: Instant) -> Option<Vec<u8>> {
+        self.stats.chunks_in += 1;
+        self.held.extend_from_slice(data);
+
+        let printable = data.iter().all(|b| (0x20..0x7f).contains(b));
+        let interval = self.interval();
+        if !printable || interval.is_zero() || self.held.len() >= MAX_BATCH {
+            return self.take(now);
+        }
+        self.held_since.get_or_insert(now);
+        None
+    }
+
+    /// When held input is due to be written
+    pub fn deadline(&self) -> Option<Instant> {
+        self.held_since.map(|since| since + self.interval())
+    }
+
+    /// Write held input whose interval has passed
+    pub fn flush(&mut self, now: Instant) -> Option<Vec<u8>> {
+        match self.deadline() {
+            Some(deadline) if deadline <= now => self.take(now),
+            _ => None,
+        }
+    }
+
+    /// Note output from the remote end, timing the round trip
+    pub fn output(&mut self, now: Instant) {
+        let Some(sent) = self.awaiting_since.take() else {
+            return;
+        };
+        let sample = now.saturating_duration_since(sent);
+        self.srtt = Some(match self.srtt {
+            Some(srtt) => (srtt * 7 + sample) / 8,
+            None => sample,
+        });
+    }
+
+    pub fn stats(&self) -> CoalesceStats {
+        CoalesceStats {
+            interval_ms: self.interval().as_millis() as u64,
+            srtt_ms: self.srtt.map(|srtt| srtt.as_millis() as u64),
+            ..self.stats
+        }
+    }
+
+    fn interval(&self) -> Duration {
+        match (self.mode, self.srtt) {
+            (CoalesceMode::Adaptive, Some(srtt)) if srtt >= COALESCE_ABOVE => {
+                (srtt / RTT_DIVISOR).min(MAX_INTERVAL)
+            }
+            _ => Duration::ZERO,
+        }
+    }
+
+    fn take(&mut self, now: Instant) -> Option<Vec<u8>> {
+        self.held_since = None;
+        if self.held.is_empty() {
+            return None;
+        }
+        self.awaiting_since.get_or_insert(now);
+        self.stats.writes_out += 1;
+        self.stats.bytes += self.held.len() as u64;
+        Some(std::mem::take(&mut self.held))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn slow_link(start: Instant) -> InputCoalescer {
+        let mut coalescer = InputCoalescer::new(CoalesceMode::Adaptive);
+        assert_eq!(coalescer.input(b"x", start), Some(b"x".to_vec()));
+        coalescer.output(start + Duration::from_millis(600));
+        coalescer
+    }
+
+    #[test]
+    fn test_fast_link_passes_through() {
+        let now = Instant::now();
+        let mut coalescer = InputCoalescer::new(CoalesceMode::Adaptive);
+        assert_eq!(coalescer.input(b"l", now), Some(b"l".to_vec()));
+        coalescer.output(now + Duration::from_millis(5));
+        assert_eq!(coalescer.input(b"s", now), Some(b"s".to_vec()));
+        assert_eq!(coalescer.deadline(), None);
+        assert_eq!(coalescer.stats().interval_ms, 0);
+    }
+
+    #[test]
+    fn test_slow_link_batches_until_deadline() {
+        let start = Instant::now();
+        let mut coalescer = slow_link(start);
+        let now = start + Duration::from_secs(1);
+
+        assert_eq!(coalescer.input(b"g", now), None);
+        assert_eq!(coalescer.input(b"i", now + Duration::from_millis(10)), None);
+        assert_eq!(coalescer.input(b"t", now + Duration::from_millis(20)), None);
+        let deadline = coalescer.deadline().unwrap();
+        assert_eq!(deadline, now + MAX_INTERVAL);
+        assert_eq!(coalescer.flush(now + Duration::from_millis(30)), None);
+        assert_eq!(coalescer.flush(deadline), Some(b"git".to_vec()));
+
+        let stats = coalescer.stats();
+        assert_eq!(stats.chunks_in, 4);
+        assert_eq!(stats.writes_out, 2);
+        assert_eq!(stats.bytes, 4);
+        assert_eq!(stats.srtt_ms, Some(600));
+    }
+
+    #[test]
+    fn test_control_bytes_flush_at_once() {
+        let start = Instant::now();
+        let mut coalescer = slow_link(start);
+        let now = start + Duration::from_secs(1);
+
+        assert_eq!(coalescer.input(b"ls", now), None);
+        assert_eq!(coalescer.input(b"\r", now), Some(b"ls\r".to_vec()));
+        assert_eq!(coalescer.deadline(), None);
+        assert_eq!(coalescer.input(b"\x03", now), Some(b"\x03".to_vec()));
+    }
+
+    #[test]
+    fn test_off_never_holds() {
+        let now = Instant::now();
+        let mut coalescer = InputCoalescer::new(CoalesceMode::Off);
+        coalescer.input(b"a", now);
+        coalescer.output(now + Duration::from_secs(1));
+        assert_eq!(coalescer.input(b"b", now), Some(b"b".to_vec()));
+    }
+}
@@ -0,0 +1,275 @@
+//! Command start and end detection
+//!
+//! Shells with terminal integration mark each command with OSC 133
+//! (`A` prompt, `B` command line, `C` output begins, `D;<status>` done).
+//! Without it, the fallback needs a shell that puts `user@host: dir` in the
+//! title at every prompt (see `cwd`): pressing Enter on a prompt line starts
+//! a command, and the next prompt title ends it. Exit codes are only known
+//! from OSC 133.
+
+use serde::{Deserialize, Serialize};
+use vte::{Params, Perform};
+
+use crate::cwd::parse_title;
+
+/// Longest command line kept
+const MAX_LINE: usize = 4096;
+
+/// How a command's start and end were detected
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CommandSource {
+    /// OSC 133 marks from the shell
+    Osc133,
+    /// Enter at a prompt, ended by the next prompt title
+    Heuristic,
+}
+
+/// A change in what the shell is running
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CommandEvent {
+    Started {
+        command: String,
+        source: CommandSource,
+    },
+    /// The running command ended; the exit code when the shell reports it
+    Finished { exit_code: Option<i32> },
+}
+
+/// Follows the commands run in one terminal's shell
+///
+/// Feed it all of the terminal's output, in order, and the input sent to
+/// it; sequences split across reads are handled.
+pub struct CommandTracker {
+    parser: vte::Parser,
+    performer: CommandPerformer,
+}
+
+impl CommandTracker {
+    pub fn new() -> Self {
+        Self {
+            parser: vte::Parser::new(),
+            performer: CommandPerformer::default(),
+        }
+    }
+
+    /// Scan terminal output; returns commands started or finished in it
+    pub fn feed(&mut self, data: &[u8]) -> Vec<CommandEvent> {
+        for byte in data {
+            self.parser.advance(&mut self.performer, *byte);
+        }
+        std::mem::take(&mut self.performer.events)
+    }
+
+    /// Note input sent to the terminal; without OSC 133, Enter on a
+    /// prompt line starts a command
+    pub fn input(&mut self, data: &[u8]) -> Option<CommandEvent> {
+        let performer = &mut self.performer;
+        if performer.integrated
+            || !performer.prompt_titles
+            || performer.running.is_some()
+            || !data.iter().any(|&b| b == b'\r' || b == b'\n')
+        {
+            return None;
+        }
+
+        let command = command_from_line(&performer.line);
+        if command.is_empty() {
+            return None;
+        }
+        performer.running = Some(CommandSource::Heuristic);
+        Some(CommandEvent::Started {
+            command,
+            source: CommandSource::Heuristic,
+        })
+    }
+
+    /// Whether a command is running
+    pub fn running(&self) -> bool {
+        self.performer.running.is_some()
+    }
+}
+
+impl Default for CommandTracker {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[derive(Default)]
+struct CommandPerformer {
+    /// The shell sends OSC 133; the heuristic is off
+    integrated: bool,
+    /// The shell sets prompt titles, so heuristic commands can end
+    prompt_titles: bool,
+    running: Option<CommandSource>,
+    /// Text printed on the current line
+    line: String,
+    /// Command line typed after OSC 133 `B`
+    input: Option<String>,
+    events: Vec<CommandEvent>,
+}
+
+impl CommandPerformer {
+    fn finish(&mut self, exit_code: Option<i32>) {
+        if self.running.take().is_some() {
+            self.events.push(CommandEvent::Finished { exit_code });
+        }
+    }
+
+    fn mark(&mut self, params: &[&[u8]]) {
+        self.integrated = true;
+        match params.first().and_then(|mark| mark.first()) {
+            // A prompt without `D` before it: the command still ended
+            Some(b'A') => {
+                self.finish(None);
+                self.input = None;
+            }
+            Some(b'B') => self.input = Some(String::new()),
+            Some(b'C') => {
+                self.finish(None);
+                let command = self.input.take().unwrap_or_default().trim().to_string();
+                self.running = Some(CommandSource::Osc133);
+                self.events.push(CommandEvent::Started {
+                    command,
+                    source: CommandSource::Osc133,
+                });
+            }
+            Some(b'D') => {
+                let exit_code = params
+                    .get(1)
+                    .and_then(|code| std::str::from_utf8(code).ok())
+                    .and_then(|code| code.parse().ok());
+                self.finish(exit_code);
+            }
+            _ => {}
+        }
+    }
+}
+
+impl Perform for CommandPerformer {
+    fn osc_dispatch(&mut self, params: &[&[u8]], _bell_terminated: bool) {
+        let Some((&kind, rest)) = params.split_first() else {
+            return;
+        };
+        match kind {
+            b"133" => self.mark(rest),
+            b"0" | b"2" if !self.integrated => {
+                let title = rest.join(&b';');
+                if std::str::from_utf8(&title).ok().and_then(parse_title).is_some() {
+                    self.prompt_titles = true;
+                    self.finish(None);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn print(&mut self, c: char) {
+        if self.line.len() < MAX_LINE {
+            self.line.push(c);
+        }
+        if let Some(input) = &mut self.input {
+            if input.len() < MAX_LINE {
+                input.push(c);
+            }
+        }
+    }
+
+    fn execute(&mut self, byte: u8) {
+        match byte {
+            b'\r' | b'\n' => self.line.clear(),
+            // Backspace as echoed by line editors
+            0x08 => {
+                self.line.pop();
+                if let Some(input) = &mut self.input {
+                    input.pop();
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn hook(&mut self, _params: &Params, _intermediates: &[u8], _ignore: bool, _c: char) {}
+
+    fn put(&mut self, _byte: u8) {}
+
+    fn unhook(&mut self) {}
+
+    fn csi_dispatch(&mut self, _params: &Params, _intermediates: &[u8], _ignore: bool, _c: char) {}
+
+    fn esc_dispatch(&mut self, _intermediates: &[u8], _ignore: bool, _byte: u8) {}
+}
+
+/// The command typed after a prompt such as `user@host:~$ `
+fn command_from_line(line: &str) -> String {
+    let command = ["$ ", "# ", "% ", "> "]
+        .iter()
+        .filter_map(|end| line.find(end).map(|at| at + end.len()))
+        .min()
+        .map_or(line, |at| &line[at..]);
+    command.trim().to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tracks_osc133() {
+        let mut tracker = CommandTracker::new();
+        assert!(tracker
+            .feed(b"\x1b]133;A\x07me@laptop:~/api$ \x1b]133;B\x07")
+            .is_empty());
+        // The line editor echoes keystrokes, typos included
+        assert!(tracker.feed(b"cargo tsst\x08\x08\x08est").is_empty());
+        assert_eq!(tracker.input(b"\r"), None);
+
+        let events = tracker.feed(b"\r\n\x1b]133;C\x07running 3 tests\r\n");
+        assert_eq!(
+            events,
+            [CommandEvent::Started {
+                command: "cargo test".to_string(),
+                source: CommandSource::Osc133,
+            }]
+        );
+        assert!(tracker.running());
+
+        // Status split across reads
+        assert!(tracker.feed(b"test result: FAILED\r\n\x1b]133;D;1").is_empty());
+        assert_eq!(
+            tracker.feed(b"01\x07\x1b]133;A\x07$ "),
+            [CommandEvent::Finished { exit_code: Some(101) }]
+        );
+        assert!(!tracker.running());
+    }
+
+    #[test]
+    fn test_falls_back_to_prompt_titles() {
+        let mut tracker = CommandTracker::new();
+        // No prompt titles yet: nothing would end the command
+        tracker.feed(b"$ ls");
+        assert_eq!(tracker.input(b"\r"), None);
+
+        tracker.feed(b"\r\n\x1b]0;deploy@prod-1: ~/api\x07deploy@prod-1:~/api$ make release");
+        assert_eq!(
+            tracker.input(b"\r"),
+            Some(CommandEvent::Started {
+                command: "make release".to_string(),
+                source: CommandSource::Heuristic,
+            })
+        );
+        // Typing into the running program starts nothing
+        assert_eq!(tracker.input(b"y\r"), None);
+
+        assert!(tracker.feed(b"\r\nBuilding...\r\n\x1b]2;vim notes.md\x07").is_empty());
+        assert_eq!(
+            tracker.feed(b"\x1b]0;deploy@prod-1: ~/api\x07"),
+            [CommandEvent::Finished { exit_code: None }]
+        );
+
+        // Enter on an empty prompt
+        tracker.feed(b"deploy@prod-1:~/api$ ");
+        assert_eq!(tracker.input(b"\r"), None);
+    }
+}
@@ -0,0 +1,224 @@
+//! Working directory tracking
+//!
+//! Shells that integrate with terminals announce their directory after every
+//! prompt with OSC 7 (`ESC ] 7 ; file://host/path BEL`). Many don't, but
+//! still put `user@host: dir` in the window title (OSC 0 or 2), which is the
+//! fallback. Once a session has sent OSC 7, titles are ignored: programs
+//! like editors and `ssh` set titles that aren't directories.
+
+use serde::{Deserialize, Serialize};
+use vte::{Params, Perform};
+
+/// How a working directory was learned
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CwdSource {
+    /// OSC 7 file URI; the path is absolute
+    Osc7,
+    /// Window title; the path may start with `~`
+    Title,
+}
+
+/// A shell's current directory
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct WorkingDirectory {
+    /// Host the shell runs on, when it says; None means this machine
+    pub host: Option<String>,
+    pub path: String,
+    pub source: CwdSource,
+}
+
+/// Follows the working directory of one terminal's shell
+///
+/// Feed it all of the terminal's output, in order; sequences split across
+/// reads are handled.
+pub struct CwdTracker {
+    parser: vte::Parser,
+    performer: CwdPerformer,
+}
+
+impl CwdTracker {
+    pub fn new() -> Self {
+        Self {
+            parser: vte::Parser::new(),
+            performer: CwdPerformer::default(),
+        }
+    }
+
+    /// Scan terminal output; returns the new directory if it changed
+    pub fn feed(&mut self, data: &[u8]) -> Option<&WorkingDirectory> {
+        self.performer.changed = false;
+        for byte in data {
+            self.parser.advance(&mut self.performer, *byte);
+        }
+        if self.performer.changed {
+            self.performer.current.as_ref()
+        } else {
+            None
+        }
+    }
+
+    pub fn current(&self) -> Option<&WorkingDirectory> {
+        self.performer.current.as_ref()
+    }
+}
+
+impl Default for CwdTracker {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[derive(Default)]
+struct CwdPerformer {
+    current: Option<WorkingDirectory>,
+    seen_osc7: bool,
+    changed: bool,
+}
+
+impl CwdPerformer {
+    fn update(&mut self, directory: WorkingDirectory) {
+        if self.current.as_ref() != Some(&directory) {
+            self.current = Some(directory);
+            self.changed = true;
+        }
+    }
+}
+
+impl Perform for CwdPerformer {
+    fn osc_dispatch(&mut self, params: &[&[u8]], _bell_terminated: bool) {
+        let Some((&kind, rest)) = params.split_first() else {
+            return;
+        };
+        // vte splits the text on ';', which may occur in a title
+        let text = rest.join(&b';');
+        let Ok(text) = std::str::from_utf8(&text) else {
+            return;
+        };
+
+        match kind {
+            b"7" => {
+                if let Some(directory) = parse_file_uri(text) {
+                    self.seen_osc7 = true;
+                    self.update(directory);
+                }
+            }
+            b"0" | b"2" if !self.seen_osc7 => {
+                if let Some(directory) = parse_title(text) {
+                    self.update(directory);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn print(&mut self, _c: char) {}
+
+    fn execute(&mut self, _byte: u8) {}
+
+    fn hook(&mut self, _params: &Params, _intermediates: &[u8], _ignore: bool, _c: char) {}
+
+    fn put(&mut self, _byte: u8) {}
+
+    fn unhook(&mut self) {}
+
+    fn csi_dispatch(&mut self, _params: &Params, _intermediates: &[u8], _ignore: bool, _c: char) {}
+
+    fn esc_dispatch(&mut self, _intermediates: &[u8], _ignore: bool, _byte: u8) {}
+}
+
+/// Host and path of an OSC 7 `file://host/path` URI
+fn parse_file_uri(uri: &str) -> Option<WorkingDirectory> {
+    let uri = uri.strip_prefix("file://")?;
+    let (host, path) = uri.split_at(uri.find('/')?);
+    Some(WorkingDirectory {
+        host: Some(host.to_string()).filter(|h| !h.is_empty() && h != "localhost"),
+        path: percent_decode(path)?,
+        source: CwdSource::Osc7,
+    })
+}
+
+/// The directory in a `user@host: dir` or `user@host:dir` title, as set by
+/// the default bash and zsh prompts of most distributions
+pub(crate) fn parse_title(title: &str) -> Option<WorkingDirectory> {
+    let (user, rest) = title.split_once('@')?;
+    let (host, path) = rest.split_once(':')?;
+    let path = path.trim();
+    if user.is_empty() || user.contains(char::is_whitespace) || host.is_empty() {
+        return None;
+    }
+    if host.contains(char::is_whitespace) || !(path.starts_with('/') || path.starts_with('~')) {
+        return None;
+    }
+
+    Some(WorkingDirectory {
+        host: Some(host.to_string()),
+        path: path.to_string(),
+        source: CwdSource::Title,
+    })
+}
+
+fn percent_decode(text: &str) -> Option<String> {
+    let bytes = text.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%' {
+            let hex = text.get(i + 1..i + 3)?;
+            decoded.push(u8::from_str_radix(hex, 16).ok()?);
+            i += 3;
+        } else {
+            decoded.push(bytes[i]);
+            i += 1;
+        }
+    }
+    String::from_utf8(decoded).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tracks_osc7() {
+        let mut tracker = CwdTracker::new();
+        let directory = tracker
+            .feed(b"\x1b]7;file://prod-1/var/www/my%20site\x07\x1b[01;32mdeploy@prod-1\x1b[0m$ ")
+            .cloned()
+            .unwrap();
+        assert_eq!(directory.host.as_deref(), Some("prod-1"));
+        assert_eq!(directory.path, "/var/www/my site");
+        assert_eq!(directory.source, CwdSource::Osc7);
+
+        // Same directory after the next prompt
+        assert!(tracker.feed(b"\x1b]7;file://prod-1/var/www/my%20site\x07$ ").is_none());
+
+        // String terminator form, no host, split across reads
+        assert!(tracker.feed(b"cd /tmp\r\n\x1b]7;file:///t").is_none());
+        assert_eq!(tracker.feed(b"mp\x1b\\$ ").unwrap().path, "/tmp");
+        assert_eq!(tracker.current().unwrap().host, None);
+    }
+
+    #[test]
+    fn test_falls_back_to_title() {
+        let mut tracker = CwdTracker::new();
+        assert!(tracker.feed(b"plain output").is_none());
+
+        let directory = tracker.feed(b"\x1b]0;deploy@prod-1: ~/api\x07").unwrap();
+        assert_eq!(directory.host.as_deref(), Some("prod-1"));
+        assert_eq!(directory.path, "~/api");
+        assert_eq!(directory.source, CwdSource::Title);
+
+        // Titles that aren't prompts
+        assert!(tracker.feed(b"\x1b]2;vim notes.md\x07").is_none());
+        assert!(tracker.feed(b"\x1b]2;Build: 3 of 5\x07").is_none());
+
+        // OSC 7 takes over for good
+        assert_eq!(
+            tracker.feed(b"\x1b]7;file://prod-1/srv\x07").unwrap().source,
+            CwdSource::Osc7
+        );
+        assert!(tracker.feed(b"\x1b]0;deploy@prod-1: ~\x07").is_none());
+        assert_eq!(tracker.current().unwrap().path, "/srv");
+    }
+}
@@ -0,0 +1,468 @@
+//! Keyboard input encoding
+//!
+//! Turns key presses into the bytes a program expects, following what it
+//! negotiated in its output:
+//! - legacy xterm sequences by default, with `CSI 1;<mods>X` for modified
+//!   cursor and function keys, and DECCKM application cursor keys
+//! - xterm modifyOtherKeys (`CSI > 4 ; <level> m`): modified keys sent as
+//!   `CSI 27;<mods>;<code>~` so Ctrl+Shift+A differs from Ctrl+A
+//! - the kitty keyboard protocol (`CSI > <flags> u` and friends): keys sent
+//!   as `CSI <code>;<mods>u`, honouring the "disambiguate escape codes" and
+//!   "report all keys as escape codes" flags
+//!
+//! Kitty flags live on separate stacks for the main and alternate screens,
+//! as the protocol asks, so a full-screen program exiting without popping
+//! its flags does not leave the shell with them.
+
+use serde::{Deserialize, Serialize};
+use vte::{Params, Perform};
+
+/// Kitty flag: report keys that are ambiguous in legacy encoding as `CSI u`
+pub const KITTY_DISAMBIGUATE: u16 = 0b1;
+
+/// Kitty flag: report every key, plain text included, as `CSI u`
+pub const KITTY_ALL_KEYS: u16 = 0b1000;
+
+/// Kitty flags this encoder implements; others are not reported as enabled
+const KITTY_SUPPORTED: u16 = KITTY_DISAMBIGUATE | KITTY_ALL_KEYS;
+
+/// Deepest kitty flag stack kept; older entries are dropped
+const KITTY_STACK_LIMIT: usize = 16;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Modifiers {
+    pub shift: bool,
+    pub alt: bool,
+    pub ctrl: bool,
+    /// Super/Command
+    pub meta: bool,
+}
+
+impl Modifiers {
+    pub fn is_empty(self) -> bool {
+        !(self.shift || self.alt || self.ctrl || self.meta)
+    }
+
+    /// The modifier parameter shared by xterm and kitty: 1 + bit mask
+    fn param(self) -> u8 {
+        1 + self.shift as u8 + ((self.alt as u8) << 1) + ((self.ctrl as u8) << 2) + ((self.meta as u8) << 3)
+    }
+
+    /// Shift alone changes the text a key types rather than acting as a
+    /// modifier
+    fn only_shift(self) -> bool {
+        !(self.alt || self.ctrl || self.meta)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Key {
+    /// A key that types text, given as typed (`A` with Shift held)
+    Char(char),
+    Enter,
+    Tab,
+    Backspace,
+    Escape,
+    Up,
+    Down,
+    Right,
+    Left,
+    Home,
+    End,
+    Insert,
+    Delete,
+    PageUp,
+    PageDown,
+    /// F1 to F12
+    F(u8),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct KeyPress {
+    pub key: Key,
+    #[serde(default)]
+    pub modifiers: Modifiers,
+}
+
+/// Keyboard reporting a program has asked for
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct KeyboardMode {
+    /// Kitty progressive enhancement flags in effect
+    pub kitty_flags: u16,
+    /// xterm modifyOtherKeys level, 0 to 2
+    pub modify_other_keys: u8,
+    /// DECCKM: cursor keys send `SS3` rather than `CSI`
+    pub application_cursor: bool,
+}
+
+/// Follows the keyboard mode negotiated in one terminal's output
+pub struct KeyboardTracker {
+    parser: vte::Parser,
+    performer: KeyboardPerformer,
+}
+
+impl KeyboardTracker {
+    pub fn new() -> Self {
+        Self {
+            parser: vte::Parser::new(),
+            performer: KeyboardPerformer::default(),
+        }
+    }
+
+    /// Scan terminal output; returns replies to write back to the program
+    /// (answers to kitty flag queries)
+    pub fn feed(&mut self, data: &[u8]) -> Vec<u8> {
+        for byte in data {
+            self.parser.advance(&mut self.performer, *byte);
+        }
+        std::mem::take(&mut self.performer.replies)
+    }
+
+    pub fn mode(&self) -> KeyboardMode {
+        KeyboardMode {
+            kitty_flags: self.performer.kitty_flags(),
+            modify_other_keys: self.performer.modify_other_keys,
+            application_cursor: self.performer.application_cursor,
+        }
+    }
+
+    /// Bytes for `press` in the negotiated mode
+    pub fn encode(&self, press: &KeyPress) -> Vec<u8> {
+        encode_key(press, &self.mode())
+    }
+}
+
+impl Default for KeyboardTracker {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Bytes for `press` in `mode`
+pub fn encode_key(press: &KeyPress, mode: &KeyboardMode) -> Vec<u8> {
+    let mods = press.modifiers;
+    match press.key {
+        Key::Char(c) => encode_char(c, mods, mode),
+        Key::Enter => encode_control(13, mods, mode),
+        Key::Tab => encode_control(9, mods, mode),
+        Key::Backspace => encode_control(127, mods, mode),
+        Key::Escape => encode_control(27, mods, mode),
+        Key::Up => encode_cursor('A', mods, mode),
+        Key::Down => encode_cursor('B', mods, mode),
+        Key::Right => encode_cursor('C', mods, mode),
+        Key::Left => encode_cursor('D', mods, mode),
+        Key::Home => encode_cursor('H', mods, mode),
+        Key::End => encode_cursor('F', mods, mode),
+        Key::Insert => encode_tilde(2, mods),
+        Key::Delete => encode_tilde(3, mods),
+        Key::PageUp => encode_tilde(5, mods),
+        Key::PageDown => encode_tilde(6, mods),
+        Key::F(n @ 1..=4) => {
+            let final_byte = (b'P' + n - 1) as char;
+            if mods.is_empty() {
+                format!("\x1bO{}", final_byte).into_bytes()
+            } else {
+                format!("\x1b[1;{}{}", mods.param(), final_byte).into_bytes()
+            }
+        }
+        Key::F(n @ 5..=12) => {
+            const CODES: [u8; 8] = [15, 17, 18, 19, 20, 21, 23, 24];
+            encode_tilde(CODES[(n - 5) as usize], mods)
+        }
+        Key::F(_) => Vec::new(),
+    }
+}
+
+fn csi_u(code: u32, mods: Modifiers) -> Vec<u8> {
+    if mods.is_empty() {
+        format!("\x1b[{}u", code).into_bytes()
+    } else {
+        format!("\x1b[{};{}u", code, mods.param()).into_bytes()
+    }
+}
+
+fn modify_other_keys(code: u32, mods: Modifiers) -> Vec<u8> {
+    format!("\x1b[27;{};{}~", mods.param(), code).into_bytes()
+}
+
+fn encode_char(c: char, mods: Modifiers, mode: &KeyboardMode) -> Vec<u8> {
+    // Kitty reports the unshifted key, with Shift as a modifier
+    let base = c.to_lowercase().next().unwrap_or(c);
+    let typed = if mods.shift { c.to_uppercase().next().unwrap_or(c) } else { c };
+
+    if mode.kitty_flags & KITTY_ALL_KEYS != 0
+        || (mode.kitty_flags & KITTY_DISAMBIGUATE != 0 && !mods.only_shift())
+    {
+        return csi_u(base as u32, mods);
+    }
+    if !mods.only_shift() {
+        let lossy = mods.shift || mods.meta || ctrl_byte(typed).is_none();
+        match mode.modify_other_keys {
+            2 => return modify_other_keys(typed as u32, mods),
+            1 if mods.ctrl && lossy => return modify_other_keys(typed as u32, mods),
+            _ => {}
+        }
+    }
+
+    let mut out = Vec::new();
+    if mods.alt {
+        out.push(0x1b);
+    }
+    match ctrl_byte(typed).filter(|_| mods.ctrl) {
+        Some(byte) => out.push(byte),
+        None => out.extend(typed.to_string().into_bytes()),
+    }
+    out
+}
+
+/// Enter, Tab, Backspace and Escape, by their kitty codes
+fn encode_control(code: u32, mods: Modifiers, mode: &KeyboardMode) -> Vec<u8> {
+    if mode.kitty_flags & KITTY_ALL_KEYS != 0
+        || (mode.kitty_flags & KITTY_DISAMBIGUATE != 0 && (code == 27 || !mods.is_empty()))
+    {
+        return csi_u(code, mods);
+    }
+    let back_tab = code == 9 && mods.shift && mods.only_shift();
+    if mode.modify_other_keys > 0 && !mods.is_empty() && !back_tab {
+        return modify_other_keys(code, mods);
+    }
+
+    let mut out = Vec::new();
+    if mods.alt {
+        out.push(0x1b);
+    }
+    match code {
+        9 if mods.shift => out.extend_from_slice(b"\x1b[Z"),
+        127 if mods.ctrl => out.push(0x08),
+        13 => out.push(b'\r'),
+        _ => out.push(code as u8),
+    }
+    out
+}
+
+fn encode_cursor(final_byte: char, mods: Modifiers, mode: &KeyboardMode) -> Vec<u8> {
+    if !mods.is_empty() {
+        format!("\x1b[1;{}{}", mods.param(), final_byte).into_bytes()
+    } else if mode.application_cursor {
+        format!("\x1bO{}", final_byte).into_bytes()
+    } else {
+        format!("\x1b[{}", final_byte).into_bytes()
+    }
+}
+
+fn encode_tilde(code: u8, mods: Modifiers) -> Vec<u8> {
+    if mods.is_empty() {
+        format!("\x1b[{}~", code).into_bytes()
+    } else {
+        format!("\x1b[{};{}~", code, mods.param()).into_bytes()
+    }
+}
+
+/// The C0 control Ctrl turns `c` into, where there is one
+fn ctrl_byte(c: char) -> Option<u8> {
+    match c {
+        'a'..='z' | 'A'..='Z' => Some(c as u8 & 0x1f),
+        '@' | ' ' | '2' => Some(0),
+        '[' | '3' => Some(0x1b),
+        '\\' | '4' => Some(0x1c),
+        ']' | '5' => Some(0x1d),
+        '^' | '6' => Some(0x1e),
+        '_' | '7' | '/' => Some(0x1f),
+        '?' | '8' => Some(0x7f),
+        _ => None,
+    }
+}
+
+#[derive(Default)]
+struct KeyboardPerformer {
+    main_flags: Vec<u16>,
+    alt_flags: Vec<u16>,
+    alt_screen: bool,
+    modify_other_keys: u8,
+    application_cursor: bool,
+    replies: Vec<u8>,
+}
+
+impl KeyboardPerformer {
+    fn flag_stack(&mut self) -> &mut Vec<u16> {
+        if self.alt_screen {
+            &mut self.alt_flags
+        } else {
+            &mut self.main_flags
+        }
+    }
+
+    fn kitty_flags(&self) -> u16 {
+        let stack = if self.alt_screen { &self.alt_flags } else { &self.main_flags };
+        stack.last().copied().unwrap_or(0)
+    }
+}
+
+impl Perform for KeyboardPerformer {
+    fn csi_dispatch(&mut self, params: &Params, intermediates: &[u8], _ignore: bool, c: char) {
+        let mut values = params.iter().map(|param| param.first().copied().unwrap_or(0));
+        let first = values.next().unwrap_or(0);
+        let second = values.next().unwrap_or(0);
+
+        match (intermediates, c) {
+            // Push flags
+            (b">", 'u') => {
+                let stack = self.flag_stack();
+                if stack.len() == KITTY_STACK_LIMIT {
+                    stack.remove(0);
+                }
+                stack.push(first & KITTY_SUPPORTED);
+            }
+            // Pop entries, one by default
+            (b"<", 'u') => {
+                let stack = self.flag_stack();
+                let keep = stack.len().saturating_sub(first.max(1) as usize);
+                stack.truncate(keep);
+            }
+            // Set, add or remove flags in place
+            (b"=", 'u') => {
+                let flags = first & KITTY_SUPPORTED;
+                let stack = self.flag_stack();
+                if stack.is_empty() {
+                    stack.push(0);
+                }
+                if let Some(top) = stack.last_mut() {
+                    match second {
+                        2 => *top |= flags,
+                        3 => *top &= !flags,
+                        _ => *top = flags,
+                    }
+                }
+            }
+            (b"?", 'u') => {
+                let reply = format!("\x1b[?{}u", self.kitty_flags());
+                self.replies.extend(reply.into_bytes());
+            }
+            (b">", 'm') if first == 4 => self.modify_other_keys = second.min(2) as u8,
+            (b"?", 'h' | 'l') => {
+                let on = c == 'h';
+                for param in params.iter() {
+                    match param.first() {
+                        Some(1) => self.application_cursor = on,
+                        Some(47 | 1047 | 1049) => self.alt_screen = on,
+                        _ => {}
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn esc_dispatch(&mut self, intermediates: &[u8], _ignore: bool, byte: u8) {
+        // RIS resets every mode
+        if intermediates.is_empty() && byte == b'c' {
+            *self = Self {
+                replies: std::mem::take(&mut self.replies),
+                ..Self::default()
+            };
+        }
+    }
+
+    fn print(&mut self, _c: char) {}
+
+    fn execute(&mut self, _byte: u8) {}
+
+    fn hook(&mut self, _params: &Params, _intermediates: &[u8], _ignore: bool, _c: char) {}
+
+    fn put(&mut self, _byte: u8) {}
+
+    fn unhook(&mut self) {}
+
+    fn osc_dispatch(&mut self, _params: &[&[u8]], _bell_terminated: bool) {}
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn press(key: Key, ctrl: bool, shift: bool) -> KeyPress {
+        KeyPress {
+            key,
+            modifiers: Modifiers {
+                ctrl,
+                shift,
+                ..Default::default()
+            },
+        }
+    }
+
+    #[test]
+    fn test_legacy_encoding() {
+        let tracker = KeyboardTracker::new();
+        assert_eq!(tracker.encode(&press(Key::Char('c'), true, false)), [0x03u8]);
+        assert_eq!(tracker.encode(&press(Key::Char('a'), false, true)), b"A");
+        assert_eq!(tracker.encode(&press(Key::Tab, false, true)), b"\x1b[Z");
+        assert_eq!(tracker.encode(&press(Key::Up, true, false)), b"\x1b[1;5A");
+        assert_eq!(tracker.encode(&press(Key::F(5), false, false)), b"\x1b[15~");
+
+        let alt_x = KeyPress {
+            key: Key::Char('x'),
+            modifiers: Modifiers {
+                alt: true,
+                ..Default::default()
+            },
+        };
+        assert_eq!(tracker.encode(&alt_x), b"\x1bx");
+    }
+
+    #[test]
+    fn test_application_cursor_keys() {
+        let mut tracker = KeyboardTracker::new();
+        tracker.feed(b"\x1b[?1h");
+        assert_eq!(tracker.encode(&press(Key::Up, false, false)), b"\x1bOA");
+        tracker.feed(b"\x1b[?1l");
+        assert_eq!(tracker.encode(&press(Key::Up, false, false)), b"\x1b[A");
+    }
+
+    #[test]
+    fn test_modify_other_keys() {
+        let mut tracker = KeyboardTracker::new();
+        tracker.feed(b"\x1b[>4;1m");
+        // Ctrl+A has a legacy code; Ctrl+Shift+A does not
+        assert_eq!(tracker.encode(&press(Key::Char('a'), true, false)), [0x01u8]);
+        assert_eq!(tracker.encode(&press(Key::Char('a'), true, true)), b"\x1b[27;6;65~");
+
+        tracker.feed(b"\x1b[>4;2m");
+        assert_eq!(tracker.encode(&press(Key::Char('a'), true, false)), b"\x1b[27;5;97~");
+        assert_eq!(tracker.encode(&press(Key::Enter, false, true)), b"\x1b[27;2;13~");
+
+        tracker.feed(b"\x1b[>4m");
+        assert_eq!(tracker.mode().modify_other_keys, 0);
+    }
+
+    #[test]
+    fn test_kitty_protocol() {
+        let mut tracker = KeyboardTracker::new();
+        // Push disambiguate, plus an unsupported flag, then query
+        assert_eq!(tracker.feed(b"\x1b[>3u\x1b[?u"), b"\x1b[?1u");
+        assert_eq!(tracker.encode(&press(Key::Char('a'), true, true)), b"\x1b[97;6u");
+        assert_eq!(tracker.encode(&press(Key::Escape, false, false)), b"\x1b[27u");
+        assert_eq!(tracker.encode(&press(Key::Char('a'), false, false)), b"a");
+        assert_eq!(tracker.encode(&press(Key::Enter, false, false)), b"\r");
+
+        tracker.feed(b"\x1b[=8;2u");
+        assert_eq!(tracker.mode().kitty_flags, 9);
+        assert_eq!(tracker.encode(&press(Key::Char('a'), false, false)), b"\x1b[97u");
+
+        tracker.feed(b"\x1b[<u");
+        assert_eq!(tracker.mode().kitty_flags, 0);
+        assert_eq!(tracker.encode(&press(Key::Escape, false, false)), b"\x1b");
+    }
+
+    #[test]
+    fn test_kitty_flags_per_screen() {
+        let mut tracker = KeyboardTracker::new();
+        tracker.feed(b"\x1b[?1049h\x1b[>1u");
+        assert_eq!(tracker.mode().kitty_flags, 1);
+        // The editor exits without popping
+        tracker.feed(b"\x1b[?1049l");
+        assert_eq!(tracker.mode().kitty_flags, 0);
+    }
+}
@@ -4,15 +4,42 @@
 //! - PTY creation and management (portable-pty)
 //! - VT100/ANSI escape sequence parsing
 //! - Terminal session lifecycle
+//! - Working directory tracking (OSC 7)
+//! - Command start/end detection (OSC 133)
+//! - Predictive local echo
+//! - Input coalescing for slow links
+//! - Keyboard encoding (xterm, modifyOtherKeys, kitty protocol)
+//! - Bracketed paste and paste checks
+//! - Searchable scrollback
+//! - Transcript export (plain text, HTML)
 //! - Input/output handling
+//! - Reproducible workloads for benchmarks
 
+pub mod coalesce;
+pub mod commands;
+pub mod cwd;
+pub mod keyboard;
 pub mod pty;
 pub mod parser;
+pub mod paste;
+pub mod predict;
+pub mod scrollback;
 pub mod session;
+pub mod transcript;
+pub mod workload;
 
+pub use coalesce::{CoalesceMode, CoalesceStats, InputCoalescer};
+pub use commands::{CommandEvent, CommandSource, CommandTracker};
+pub use cwd::{CwdSource, CwdTracker, WorkingDirectory};
+pub use keyboard::{encode_key, Key, KeyPress, KeyboardMode, KeyboardTracker, Modifiers};
 pub use pty::{PtyHandle, PtyConfig};
 pub use parser::{AnsiParser, ParsedEvent};
+pub use paste::{check_paste, encode_paste, PasteModeTracker, PasteWarning};
+pub use predict::{EchoMode, EchoPredictor};
+pub use scrollback::{Color, Scrollback, SearchMatch, SearchQuery, SearchResults, Style, StyleSpan};
 pub use session::{TerminalSession, SessionConfig};
+pub use transcript::{render_transcript, TranscriptFormat};
+pub use workload::{DaemonStage, Workload};
 
 #[cfg(test)]
 mod tests {
@@ -0,0 +1,201 @@
+//! Pasting text into a terminal
+//!
+//! Programs that enable bracketed paste (`CSI ? 2004 h`) receive pasted text
+//! between `CSI 200 ~` and `CSI 201 ~`, so a shell can tell it from typing
+//! and will not run a pasted newline. `PasteModeTracker` follows the mode
+//! from a terminal's output and `encode_paste` frames the text to match.
+//!
+//! `check_paste` finds what makes a paste risky to send without a second
+//! look: newlines that run commands, and control characters hidden in text
+//! copied from a web page.
+
+use serde::{Deserialize, Serialize};
+use vte::{Params, Perform};
+
+const PASTE_START: &[u8] = b"\x1b[200~";
+const PASTE_END: &[u8] = b"\x1b[201~";
+
+/// Something a paste does besides insert plain text
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum PasteWarning {
+    /// Line breaks; without bracketed paste each one runs a command
+    Newlines { line_breaks: usize },
+    /// Escape sequences or other C0/C1 controls (tabs and line breaks
+    /// aside), which can act as keystrokes or rewrite the screen
+    ControlCharacters { count: usize },
+    /// Text that would end a bracketed paste early, turning the rest into
+    /// typed input
+    BracketEnd,
+}
+
+/// Everything risky about `text`, empty when it is plain text on one line
+pub fn check_paste(text: &str) -> Vec<PasteWarning> {
+    let mut warnings = Vec::new();
+
+    let line_breaks = text.replace("\r\n", "\n").matches(['\r', '\n']).count();
+    if line_breaks > 0 {
+        warnings.push(PasteWarning::Newlines { line_breaks });
+    }
+
+    let controls = text
+        .chars()
+        .filter(|&c| c.is_control() && !matches!(c, '\t' | '\r' | '\n'))
+        .count();
+    if controls > 0 {
+        warnings.push(PasteWarning::ControlCharacters { count: controls });
+    }
+
+    if text.as_bytes().windows(PASTE_END.len()).any(|w| w == PASTE_END) {
+        warnings.push(PasteWarning::BracketEnd);
+    }
+    warnings
+}
+
+/// The bytes to send for pasting `text`
+///
+/// Line breaks become carriage returns, as if typed, and bracket markers
+/// inside the text are dropped so it cannot end the paste itself.
+pub fn encode_paste(text: &str, bracketed: bool) -> Vec<u8> {
+    let text = text.replace("\r\n", "\r").replace('\n', "\r");
+    let mut body = text.into_bytes();
+    for marker in [PASTE_START, PASTE_END] {
+        while let Some(at) = body.windows(marker.len()).position(|w| w == marker) {
+            body.drain(at..at + marker.len());
+        }
+    }
+
+    if !bracketed {
+        return body;
+    }
+    let mut out = Vec::with_capacity(body.len() + PASTE_START.len() + PASTE_END.len());
+    out.extend_from_slice(PASTE_START);
+    out.extend(body);
+    out.extend_from_slice(PASTE_END);
+    out
+}
+
+/// Follows whether a terminal's program has bracketed paste on
+pub struct PasteModeTracker {
+    parser: vte::Parser,
+    performer: ModePerformer,
+}
+
+impl PasteModeTracker {
+    pub fn new() -> Self {
+        Self {
+            parser: vte::Parser::new(),
+            performer: ModePerformer::default(),
+        }
+    }
+
+    /// Scan terminal output
+    pub fn feed(&mut self, data: &[u8]) {
+        for byte in data {
+            self.parser.advance(&mut self.performer, *byte);
+        }
+    }
+
+    /// Whether pastes should be bracketed
+    pub fn bracketed(&self) -> bool {
+        self.performer.bracketed
+    }
+}
+
+impl Default for PasteModeTracker {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[derive(Default)]
+struct ModePerformer {
+    bracketed: bool,
+}
+
+impl Perform for ModePerformer {
+    fn csi_dispatch(&mut self, params: &Params, intermediates: &[u8], _ignore: bool, c: char) {
+        if intermediates != b"?" || !params.iter().any(|param| param.first() == Some(&2004)) {
+            return;
+        }
+        match c {
+            'h' => self.bracketed = true,
+            'l' => self.bracketed = false,
+            _ => {}
+        }
+    }
+
+    fn esc_dispatch(&mut self, intermediates: &[u8], _ignore: bool, byte: u8) {
+        // RIS resets every mode
+        if intermediates.is_empty() && byte == b'c' {
+            self.bracketed = false;
+        }
+    }
+
+    fn print(&mut self, _c: char) {}
+
+    fn execute(&mut self, _byte: u8) {}
+
+    fn hook(&mut self, _params: &Params, _intermediates: &[u8], _ignore: bool, _c: char) {}
+
+    fn put(&mut self, _byte: u8) {}
+
+    fn unhook(&mut self) {}
+
+    fn osc_dispatch(&mut self, _params: &[&[u8]], _bell_terminated: bool) {}
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tracks_bracketed_paste_mode() {
+        let mut tracker = PasteModeTracker::new();
+        assert!(!tracker.bracketed());
+
+        // Split across reads, alongside another private mode
+        tracker.feed(b"\x1b[?1;20");
+        tracker.feed(b"04h$ ");
+        assert!(tracker.bracketed());
+
+        tracker.feed(b"\x1b[?2004l");
+        assert!(!tracker.bracketed());
+        tracker.feed(b"\x1b[?2004h\x1bc");
+        assert!(!tracker.bracketed());
+    }
+
+    #[test]
+    fn test_encodes_paste() {
+        assert_eq!(encode_paste("ls -la\n", false), b"ls -la\r");
+        assert_eq!(
+            encode_paste("a\r\nb", true),
+            b"\x1b[200~a\rb\x1b[201~".to_vec()
+        );
+        // A paste cannot close its own brackets
+        assert_eq!(
+            encode_paste("x\x1b[201~rm -rf ~\n", true),
+            b"\x1b[200~xrm -rf ~\r\x1b[201~".to_vec()
+        );
+    }
+
+    #[test]
+    fn test_checks_paste() {
+        assert!(check_paste("git status").is_empty());
+        assert_eq!(
+            check_paste("curl https://example.com/install.sh | sh\n"),
+            [PasteWarning::Newlines { line_breaks: 1 }]
+        );
+        assert_eq!(
+            check_paste("make\r\nmake install"),
+            [PasteWarning::Newlines { line_breaks: 1 }]
+        );
+        assert_eq!(
+            check_paste("echo hi\x1b[201~\x1b[2K"),
+            [
+                PasteWarning::ControlCharacters { count: 2 },
+                PasteWarning::BracketEnd,
+            ]
+        );
+    }
+}
@@ -0,0 +1,410 @@
+//! Predictive local echo
+//!
+//! On slow links, keystrokes are drawn underlined as soon as they are typed
+//! and left for the remote echo to overwrite. Predictions are matched against
+//! the echoed output character by character: a match confirms the oldest
+//! one, anything else (other text, cursor movement, a redraw) takes them all
+//! back off the screen.
+//!
+//! Only printable ASCII is predicted. Any other key (Enter, Backspace,
+//! arrows) starts a new epoch whose predictions stay hidden until the remote
+//! end has echoed one of them, so password prompts and programs that do not
+//! echo never show stray characters. Nothing is predicted on the alternate
+//! screen.
+
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+use serde::{Deserialize, Serialize};
+use vte::{Params, Perform};
+
+/// Round trip above which adaptive mode starts showing predictions
+const SHOW_ABOVE: Duration = Duration::from_millis(30);
+
+/// Round trip below which adaptive mode stops showing them again
+const HIDE_BELOW: Duration = Duration::from_millis(20);
+
+/// Shortest time a prediction waits for its echo
+const MIN_EXPIRY: Duration = Duration::from_secs(1);
+
+/// Most unconfirmed keystrokes tracked at once
+const MAX_PENDING: usize = 256;
+
+/// When to show predicted keystrokes
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EchoMode {
+    /// Pass input and output through untouched
+    #[default]
+    Off,
+    /// Show predictions while the measured round trip is slow
+    Adaptive,
+    /// Show predictions whenever the remote end is echoing
+    Always,
+}
+
+struct Prediction {
+    byte: u8,
+    epoch: u64,
+    typed_at: Instant,
+}
+
+/// Local echo for one terminal
+///
+/// Pass every input chunk through `input` and every output chunk through
+/// `output`, in order, and write what they return to the display; call
+/// `expire` once `deadline` passes.
+pub struct EchoPredictor {
+    mode: EchoMode,
+    parser: vte::Parser,
+    scanner: Scanner,
+    pending: VecDeque<Prediction>,
+    /// How many of the oldest pending predictions are on screen
+    shown: usize,
+    epoch: u64,
+    /// Latest epoch with an echoed prediction
+    confirmed_epoch: u64,
+    alt_screen: bool,
+    srtt: Option<Duration>,
+    slow: bool,
+    /// Output after the last complete action, held back while predictions
+    /// are pending so nothing is inserted inside an escape sequence
+    held: Vec<u8>,
+}
+
+impl EchoPredictor {
+    pub fn new(mode: EchoMode) -> Self {
+        Self {
+            mode,
+            parser: vte::Parser::new(),
+            scanner: Scanner::default(),
+            pending: VecDeque::new(),
+            shown: 0,
+            epoch: 1,
+            confirmed_epoch: 0,
+            alt_screen: false,
+            srtt: None,
+            slow: false,
+            held: Vec::new(),
+        }
+    }
+
+    /// Note input sent to the remote end; returns bytes that draw it
+    pub fn input(&mut self, data: &[u8], now: Instant) -> Vec<u8> {
+        if self.mode == EchoMode::Off || self.alt_screen {
+            return Vec::new();
+        }
+        if data.is_empty() || !data.iter().all(|b| (0x20..0x7f).contains(b)) {
+            self.epoch += 1;
+            return Vec::new();
+        }
+
+        for &byte in data {
+            if self.pending.len() == MAX_PENDING {
+                break;
+            }
+            self.pending.push_back(Prediction {
+                byte,
+                epoch: self.epoch,
+                typed_at: now,
+            });
+        }
+        self.draw()
+    }
+
+    /// Match output against the predictions; returns what to display
+    pub fn output(&mut self, data: &[u8], now: Instant) -> Vec<u8> {
+        if self.mode == EchoMode::Off {
+            return data.to_vec();
+        }
+
+        let mut buf = std::mem::take(&mut self.held);
+        let offset = buf.len();
+        buf.extend_from_slice(data);
+
+        let mut out = Vec::with_capacity(buf.len());
+        // Start of the action being parsed; bytes before it are in `out`
+        let mut start = 0;
+        for i in offset..buf.len() {
+            self.parser.advance(&mut self.scanner, buf[i]);
+            let Some(action) = self.scanner.action.take() else {
+                continue;
+            };
+            match action {
+                Action::Print(c) => {
+                    match self.pending.front().map(|p| char::from(p.byte) == c) {
+                        Some(true) => self.confirm(now),
+                        Some(false) => out.extend(self.reject()),
+                        None => {}
+                    }
+                }
+                Action::Neutral => {}
+                Action::AltScreen(on) => {
+                    out.extend(self.reject());
+                    self.alt_screen = on;
+                }
+                Action::Other => out.extend(self.reject()),
+            }
+            out.extend_from_slice(&buf[start..=i]);
+            start = i + 1;
+        }
+
+        if self.pending.is_empty() {
+            out.extend_from_slice(&buf[start..]);
+        } else {
+            out.extend(self.draw());
+            self.held = buf[start..].to_vec();
+        }
+        out
+    }
+
+    /// When the oldest prediction gives up waiting for its echo
+    pub fn deadline(&self) -> Option<Instant> {
+        self.pending
+            .front()
+            .map(|prediction| prediction.typed_at + self.expiry())
+    }
+
+    /// Take back predictions whose echo never came
+    pub fn expire(&mut self, now: Instant) -> Vec<u8> {
+        match self.deadline() {
+            Some(deadline) if deadline <= now => {}
+            _ => return Vec::new(),
+        }
+        let mut out = self.reject();
+        out.append(&mut self.held);
+        out
+    }
+
+    /// Smoothed time from keystroke to echo
+    pub fn srtt(&self) -> Option<Duration> {
+        self.srtt
+    }
+
+    fn expiry(&self) -> Duration {
+        self.srtt.map_or(MIN_EXPIRY, |srtt| (srtt * 4).max(MIN_EXPIRY))
+    }
+
+    fn showing(&self) -> bool {
+        let enabled = match self.mode {
+            EchoMode::Off => false,
+            EchoMode::Adaptive => self.slow,
+            EchoMode::Always => true,
+        };
+        enabled && !self.alt_screen && self.confirmed_epoch == self.epoch
+    }
+
+    /// Draw pending predictions not yet on screen, leaving the cursor put
+    fn draw(&mut self) -> Vec<u8> {
+        if !self.showing() || self.shown == self.pending.len() {
+            return Vec::new();
+        }
+        let mut out = b"\x1b7".to_vec();
+        if self.shown > 0 {
+            out.extend(format!("\x1b[{}C", self.shown).into_bytes());
+        }
+        out.extend_from_slice(b"\x1b[4m");
+        out.extend(self.pending.iter().skip(self.shown).map(|p| p.byte));
+        out.extend_from_slice(b"\x1b8");
+        self.shown = self.pending.len();
+        out
+    }
+
+    fn confirm(&mut self, now: Instant) {
+        let Some(prediction) = self.pending.pop_front() else {
+            return;
+        };
+        self.shown = self.shown.saturating_sub(1);
+        self.confirmed_epoch = self.confirmed_epoch.max(prediction.epoch);
+
+        let sample = now.saturating_duration_since(prediction.typed_at);
+        let srtt = match self.srtt {
+            Some(srtt) => (srtt * 7 + sample) / 8,
+            None => sample,
+        };
+        self.srtt = Some(srtt);
+        if srtt > SHOW_ABOVE {
+            self.slow = true;
+        } else if srtt < HIDE_BELOW {
+            self.slow = false;
+        }
+    }
+
+    /// Drop every prediction; returns bytes erasing those on screen
+    fn reject(&mut self) -> Vec<u8> {
+        if self.pending.is_empty() {
+            return Vec::new();
+        }
+        let shown = std::mem::take(&mut self.shown);
+        self.pending.clear();
+        self.epoch += 1;
+        if shown == 0 {
+            Vec::new()
+        } else {
+            format!("\x1b[{}X", shown).into_bytes()
+        }
+    }
+}
+
+/// What a complete piece of output does to the predictions
+enum Action {
+    Print(char),
+    /// Leaves the cursor and the predicted cells alone
+    Neutral,
+    AltScreen(bool),
+    Other,
+}
+
+#[derive(Default)]
+struct Scanner {
+    action: Option<Action>,
+}
+
+impl Perform for Scanner {
+    fn print(&mut self, c: char) {
+        self.action = Some(Action::Print(c));
+    }
+
+    fn execute(&mut self, _byte: u8) {
+        self.action = Some(Action::Other);
+    }
+
+    fn hook(&mut self, _params: &Params, _intermediates: &[u8], _ignore: bool, _c: char) {
+        self.action = Some(Action::Neutral);
+    }
+
+    fn put(&mut self, _byte: u8) {
+        self.action = Some(Action::Neutral);
+    }
+
+    fn unhook(&mut self) {
+        self.action = Some(Action::Neutral);
+    }
+
+    fn osc_dispatch(&mut self, _params: &[&[u8]], _bell_terminated: bool) {
+        self.action = Some(Action::Neutral);
+    }
+
+    fn csi_dispatch(&mut self, params: &Params, intermediates: &[u8], _ignore: bool, c: char) {
+        let alt_screen = intermediates == b"?"
+            && params
+                .iter()
+                .any(|param| matches!(param.first(), Some(47 | 1047 | 1049)));
+        self.action = Some(match c {
+            'm' if intermediates.is_empty() => Action::Neutral,
+            'h' if alt_screen => Action::AltScreen(true),
+            'l' if alt_screen => Action::AltScreen(false),
+            _ => Action::Other,
+        });
+    }
+
+    fn esc_dispatch(&mut self, _intermediates: &[u8], _ignore: bool, _byte: u8) {
+        self.action = Some(Action::Other);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A predictor past its first echo, showing predictions
+    fn confirmed(now: Instant) -> EchoPredictor {
+        let mut predictor = EchoPredictor::new(EchoMode::Always);
+        assert!(predictor.input(b"l", now).is_empty());
+        assert_eq!(predictor.output(b"l", now), b"l");
+        predictor
+    }
+
+    #[test]
+    fn test_draws_and_confirms() {
+        let now = Instant::now();
+        let mut predictor = confirmed(now);
+
+        assert_eq!(predictor.input(b"s", now), b"\x1b7\x1b[4ms\x1b8");
+        assert_eq!(predictor.input(b" -", now), b"\x1b7\x1b[1C\x1b[4m -\x1b8");
+
+        // Highlighted echo of the first two; the third is still drawn
+        assert_eq!(predictor.output(b"\x1b[32ms ", now), b"\x1b[32ms ");
+        assert_eq!(predictor.output(b"-", now), b"-");
+        assert_eq!(predictor.deadline(), None);
+    }
+
+    #[test]
+    fn test_takes_back_wrong_predictions() {
+        let now = Instant::now();
+        let mut predictor = confirmed(now);
+        predictor.input(b"ab", now);
+
+        assert_eq!(predictor.output(b"x", now), b"\x1b[2Xx");
+        // New epoch: hidden until something is echoed
+        assert!(predictor.input(b"c", now).is_empty());
+        assert_eq!(predictor.output(b"cd", now), b"cd");
+        assert!(!predictor.input(b"e", now).is_empty());
+    }
+
+    #[test]
+    fn test_control_keys_hide_predictions() {
+        let now = Instant::now();
+        let mut predictor = confirmed(now);
+
+        assert!(predictor.input(b"\r", now).is_empty());
+        // Typed at a password prompt: never echoed, never shown
+        assert!(predictor.input(b"hunter2", now).is_empty());
+        let deadline = predictor.deadline().unwrap();
+        assert!(predictor.expire(deadline).is_empty());
+        assert_eq!(predictor.deadline(), None);
+    }
+
+    #[test]
+    fn test_erases_before_split_sequences() {
+        let now = Instant::now();
+        let mut predictor = confirmed(now);
+        predictor.input(b"k", now);
+
+        assert!(predictor.output(b"\x1b[", now).is_empty());
+        assert_eq!(predictor.output(b"K", now), b"\x1b[1X\x1b[K");
+    }
+
+    #[test]
+    fn test_expires_unechoed_predictions() {
+        let now = Instant::now();
+        let mut predictor = confirmed(now);
+        predictor.input(b"q", now);
+
+        assert!(predictor.expire(now).is_empty());
+        assert_eq!(predictor.expire(now + MIN_EXPIRY), b"\x1b[1X");
+    }
+
+    #[test]
+    fn test_alternate_screen_disables_prediction() {
+        let now = Instant::now();
+        let mut predictor = confirmed(now);
+
+        predictor.output(b"\x1b[?1049h", now);
+        assert!(predictor.input(b"j", now).is_empty());
+        assert_eq!(predictor.deadline(), None);
+    }
+
+    #[test]
+    fn test_adaptive_follows_round_trip() {
+        let now = Instant::now();
+        let mut predictor = EchoPredictor::new(EchoMode::Adaptive);
+        predictor.input(b"a", now);
+        predictor.output(b"a", now + Duration::from_millis(5));
+        assert!(predictor.input(b"b", now).is_empty());
+
+        let mut predictor = EchoPredictor::new(EchoMode::Adaptive);
+        predictor.input(b"a", now);
+        predictor.output(b"a", now + Duration::from_millis(120));
+        assert_eq!(predictor.srtt(), Some(Duration::from_millis(120)));
+        assert!(!predictor.input(b"b", now).is_empty());
+    }
+
+    #[test]
+    fn test_off_passes_through() {
+        let now = Instant::now();
+        let mut predictor = EchoPredictor::new(EchoMode::Off);
+        assert!(predictor.input(b"a", now).is_empty());
+        assert_eq!(predictor.output(b"\x1b[", now), b"\x1b[");
+    }
+}
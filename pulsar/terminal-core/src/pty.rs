@@ -24,10 +24,15 @@ impl Default for PtyConfig {
 }
 
 /// Handle to a PTY (pseudo-terminal)
+///
+/// Reading, writing and resizing lock separately, so a read blocked
+/// waiting for output does not hold up input.
 pub struct PtyHandle {
     master: Mutex<Box<dyn MasterPty + Send>>,
     reader: Mutex<Box<dyn Read + Send>>,
     writer: Mutex<Box<dyn Write + Send>>,
+    /// The shell's process ID, where the platform reports one
+    pid: Option<u32>,
 }
 
 impl PtyHandle {
@@ -59,9 +64,11 @@ impl PtyHandle {
         let mut cmd = CommandBuilder::new(&shell);
         cmd.env("TERM", "xterm-256color");
 
-        pair.slave
+        let child = pair
+            .slave
             .spawn_command(cmd)
             .context("Failed to spawn shell in PTY")?;
+        let pid = child.process_id();
 
         // Extract reader and writer from master
         let mut master = pair.master;
@@ -76,11 +83,17 @@ impl PtyHandle {
             master: Mutex::new(master),
             reader: Mutex::new(reader),
             writer: Mutex::new(writer),
+            pid,
         })
     }
 
+    /// Process ID of the shell, the root of the PTY's process tree
+    pub fn process_id(&self) -> Option<u32> {
+        self.pid
+    }
+
     /// Resize the PTY
-    pub fn resize(&mut self, cols: u16, rows: u16) -> Result<()> {
+    pub fn resize(&self, cols: u16, rows: u16) -> Result<()> {
         self.master
             .lock()
             .map_err(|e| anyhow::anyhow!("Failed to lock master PTY: {}", e))?
@@ -94,7 +107,7 @@ impl PtyHandle {
     }
 
     /// Write data to PTY (send input)
-    pub fn write(&mut self, data: &[u8]) -> Result<usize> {
+    pub fn write(&self, data: &[u8]) -> Result<usize> {
         self.writer
             .lock()
             .map_err(|e| anyhow::anyhow!("Failed to lock writer: {}", e))?
@@ -103,7 +116,7 @@ impl PtyHandle {
     }
 
     /// Read data from PTY (get output)
-    pub fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+    pub fn read(&self, buf: &mut [u8]) -> Result<usize> {
         self.reader
             .lock()
             .map_err(|e| anyhow::anyhow!("Failed to lock reader: {}", e))?
@@ -112,7 +125,7 @@ impl PtyHandle {
     }
 
     /// Try to read without blocking
-    pub fn try_read(&mut self, buf: &mut [u8]) -> Result<usize> {
+    pub fn try_read(&self, buf: &mut [u8]) -> Result<usize> {
         // Use the same method as read() since portable-pty doesn't provide
         // a non-blocking read directly. The caller should handle this with timeouts.
         self.read(buf)
@@ -0,0 +1,513 @@
+//! Searchable scrollback
+//!
+//! Keeps the most recent lines of a terminal's output as plain text, with
+//! escape sequences interpreted only as far as they change a line's text:
+//! carriage return, backspace, tab, cursor movement within the line and
+//! erase-in-line. That covers shells, progress bars and build logs; the
+//! screens of full-screen programs (editors, pagers) come out jumbled.
+//!
+//! Colors and attributes set with SGR are kept alongside the text as spans,
+//! so a transcript can be exported looking as it did on screen.
+
+use anyhow::{bail, Result};
+use regex::RegexBuilder;
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use vte::{Params, Perform};
+
+/// Lines kept unless configured otherwise
+pub const DEFAULT_SCROLLBACK_LINES: usize = 10_000;
+
+/// Matches returned unless the query asks for fewer
+pub const DEFAULT_MAX_RESULTS: usize = 1_000;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SearchQuery {
+    pub pattern: String,
+    /// Treat `pattern` as a regular expression rather than literal text
+    #[serde(default)]
+    pub regex: bool,
+    #[serde(default)]
+    pub case_sensitive: bool,
+    #[serde(default)]
+    pub max_results: Option<usize>,
+}
+
+/// One occurrence of the pattern
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SearchMatch {
+    /// Line number since the session started; lines dropped from the
+    /// scrollback keep their numbers counted
+    pub line: u64,
+    /// Columns to highlight, in characters: `start..end`
+    pub start: usize,
+    pub end: usize,
+    /// The whole line
+    pub text: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SearchResults {
+    /// Newest first
+    pub matches: Vec<SearchMatch>,
+    /// More matches exist beyond `max_results`
+    pub truncated: bool,
+}
+
+/// A color set by SGR
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Color {
+    /// One of the 256 palette colors; 0-15 are the themeable ones
+    Indexed(u8),
+    Rgb(u8, u8, u8),
+}
+
+/// Character attributes; the default is the terminal's default
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Style {
+    pub fg: Option<Color>,
+    pub bg: Option<Color>,
+    pub bold: bool,
+    pub dim: bool,
+    pub italic: bool,
+    pub underline: bool,
+    pub inverse: bool,
+}
+
+impl Style {
+    /// Apply an SGR sequence's parameters
+    fn apply(&mut self, params: &Params) {
+        let mut iter = params.iter();
+        while let Some(param) = iter.next() {
+            match param[0] {
+                0 => *self = Style::default(),
+                1 => self.bold = true,
+                2 => self.dim = true,
+                3 => self.italic = true,
+                4 => self.underline = true,
+                7 => self.inverse = true,
+                22 => {
+                    self.bold = false;
+                    self.dim = false;
+                }
+                23 => self.italic = false,
+                24 => self.underline = false,
+                27 => self.inverse = false,
+                n @ 30..=37 => self.fg = Some(Color::Indexed((n - 30) as u8)),
+                38 => self.fg = extended_color(param, &mut iter),
+                39 => self.fg = None,
+                n @ 40..=47 => self.bg = Some(Color::Indexed((n - 40) as u8)),
+                48 => self.bg = extended_color(param, &mut iter),
+                49 => self.bg = None,
+                n @ 90..=97 => self.fg = Some(Color::Indexed((n - 90 + 8) as u8)),
+                n @ 100..=107 => self.bg = Some(Color::Indexed((n - 100 + 8) as u8)),
+                _ => {}
+            }
+        }
+    }
+}
+
+/// The color of an SGR 38/48 parameter, given as subparameters
+/// (`38:5:n`, `38:2::r:g:b`) or as the parameters after it (`38;5;n`)
+fn extended_color<'a>(param: &[u16], rest: &mut impl Iterator<Item = &'a [u16]>) -> Option<Color> {
+    let values: Vec<u16> = if param.len() > 1 {
+        let mut values = param[1..].to_vec();
+        // The colon form may carry a color space id before r:g:b
+        if values.first() == Some(&2) && values.len() == 5 {
+            values.remove(1);
+        }
+        values
+    } else {
+        let kind = rest.next()?[0];
+        let count = if kind == 2 { 3 } else { 1 };
+        std::iter::once(kind)
+            .chain(rest.take(count).map(|p| p[0]))
+            .collect()
+    };
+    match values[..] {
+        [5, n, ..] => Some(Color::Indexed(n as u8)),
+        [2, r, g, b, ..] => Some(Color::Rgb(r as u8, g as u8, b as u8)),
+        _ => None,
+    }
+}
+
+/// A stretch of a line drawn with one non-default style, in characters:
+/// `start..end`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StyleSpan {
+    pub start: usize,
+    pub end: usize,
+    pub style: Style,
+}
+
+pub struct Scrollback {
+    parser: vte::Parser,
+    lines: LineBuffer,
+}
+
+impl Scrollback {
+    pub fn new(max_lines: usize) -> Self {
+        Self {
+            parser: vte::Parser::new(),
+            lines: LineBuffer {
+                max_lines: max_lines.max(1),
+                ..Default::default()
+            },
+        }
+    }
+
+    /// Append terminal output
+    pub fn feed(&mut self, data: &[u8]) {
+        for byte in data {
+            self.parser.advance(&mut self.lines, *byte);
+        }
+    }
+
+    /// Number of the first line still kept
+    pub fn first_line(&self) -> u64 {
+        self.lines.dropped
+    }
+
+    /// Number of the line being written
+    pub fn last_line(&self) -> u64 {
+        self.lines.dropped + self.lines.complete.len() as u64
+    }
+
+    /// Text of line `line`, if it is still kept
+    pub fn line(&self, line: u64) -> Option<String> {
+        let index = usize::try_from(line.checked_sub(self.lines.dropped)?).ok()?;
+        match index.cmp(&self.lines.complete.len()) {
+            std::cmp::Ordering::Less => Some(self.lines.complete[index].clone()),
+            std::cmp::Ordering::Equal => Some(self.lines.current.iter().collect()),
+            std::cmp::Ordering::Greater => None,
+        }
+    }
+
+    /// Styled stretches of line `line`, if it is still kept; unstyled
+    /// text has none
+    pub fn styles(&self, line: u64) -> Option<Vec<StyleSpan>> {
+        let index = usize::try_from(line.checked_sub(self.lines.dropped)?).ok()?;
+        match index.cmp(&self.lines.complete.len()) {
+            std::cmp::Ordering::Less => Some(self.lines.complete_styles[index].clone()),
+            std::cmp::Ordering::Equal => Some(spans(&self.lines.current_styles)),
+            std::cmp::Ordering::Greater => None,
+        }
+    }
+
+    /// Kept lines numbered `start..=end`, oldest first
+    pub fn range(&self, start: u64, end: u64) -> Vec<(u64, String)> {
+        (start.max(self.first_line())..=end.min(self.last_line()))
+            .filter_map(|line| Some((line, self.line(line)?)))
+            .collect()
+    }
+
+    /// Kept lines with their numbers, oldest first
+    pub fn lines(&self) -> Vec<(u64, String)> {
+        let current: String = self.lines.current.iter().collect();
+        self.lines
+            .complete
+            .iter()
+            .cloned()
+            .chain(std::iter::once(current))
+            .enumerate()
+            .map(|(i, line)| (self.lines.dropped + i as u64, line))
+            .collect()
+    }
+
+    pub fn search(&self, query: &SearchQuery) -> Result<SearchResults> {
+        if query.pattern.is_empty() {
+            bail!("Search pattern is empty");
+        }
+        let pattern = if query.regex {
+            query.pattern.clone()
+        } else {
+            regex::escape(&query.pattern)
+        };
+        let regex = RegexBuilder::new(&pattern)
+            .case_insensitive(!query.case_sensitive)
+            .build()?;
+        let max_results = query.max_results.unwrap_or(DEFAULT_MAX_RESULTS);
+
+        let mut matches = Vec::new();
+        for (line, text) in self.lines().into_iter().rev() {
+            let mut in_line: Vec<SearchMatch> = regex
+                .find_iter(&text)
+                .filter(|m| !m.is_empty())
+                .map(|m| SearchMatch {
+                    line,
+                    start: text[..m.start()].chars().count(),
+                    end: text[..m.end()].chars().count(),
+                    text: text.clone(),
+                })
+                .collect();
+            in_line.reverse();
+
+            for found in in_line {
+                if matches.len() == max_results {
+                    return Ok(SearchResults {
+                        matches,
+                        truncated: true,
+                    });
+                }
+                matches.push(found);
+            }
+        }
+
+        Ok(SearchResults {
+            matches,
+            truncated: false,
+        })
+    }
+}
+
+impl Default for Scrollback {
+    fn default() -> Self {
+        Self::new(DEFAULT_SCROLLBACK_LINES)
+    }
+}
+
+/// Runs of non-default style in a line's per-character styles
+fn spans(styles: &[Style]) -> Vec<StyleSpan> {
+    let mut spans: Vec<StyleSpan> = Vec::new();
+    for (i, style) in styles.iter().enumerate() {
+        match spans.last_mut() {
+            Some(span) if span.end == i && span.style == *style => span.end += 1,
+            _ if *style != Style::default() => spans.push(StyleSpan {
+                start: i,
+                end: i + 1,
+                style: *style,
+            }),
+            _ => {}
+        }
+    }
+    spans
+}
+
+#[derive(Default)]
+struct LineBuffer {
+    complete: VecDeque<String>,
+    /// Styles of `complete`, line for line
+    complete_styles: VecDeque<Vec<StyleSpan>>,
+    current: Vec<char>,
+    /// Style of each character of `current`; left empty while the line
+    /// has only default-styled text, which is most output
+    current_styles: Vec<Style>,
+    cursor: usize,
+    style: Style,
+    max_lines: usize,
+    /// Lines dropped off the top
+    dropped: u64,
+}
+
+impl LineBuffer {
+    fn write(&mut self, c: char) {
+        if self.cursor < self.current.len() {
+            self.current[self.cursor] = c;
+        } else {
+            self.current.resize(self.cursor, ' ');
+            self.current.push(c);
+        }
+
+        if self.style != Style::default() || !self.current_styles.is_empty() {
+            self.current_styles.resize(self.current.len(), Style::default());
+            self.current_styles[self.cursor] = self.style;
+        }
+        self.cursor += 1;
+    }
+
+    fn new_line(&mut self) {
+        let line: String = self.current.drain(..).collect();
+        let line = line.trim_end().to_string();
+        if self.current_styles.is_empty() {
+            self.complete_styles.push_back(Vec::new());
+        } else {
+            self.current_styles.truncate(line.chars().count());
+            self.complete_styles.push_back(spans(&self.current_styles));
+            self.current_styles.clear();
+        }
+        self.complete.push_back(line);
+        self.cursor = 0;
+        // The line being written counts towards the limit
+        while self.complete.len() >= self.max_lines {
+            self.complete.pop_front();
+            self.complete_styles.pop_front();
+            self.dropped += 1;
+        }
+    }
+}
+
+impl Perform for LineBuffer {
+    fn print(&mut self, c: char) {
+        self.write(c);
+    }
+
+    fn execute(&mut self, byte: u8) {
+        match byte {
+            b'\n' => self.new_line(),
+            b'\r' => self.cursor = 0,
+            0x08 => self.cursor = self.cursor.saturating_sub(1),
+            b'\t' => self.cursor = (self.cursor / 8 + 1) * 8,
+            _ => {}
+        }
+    }
+
+    fn hook(&mut self, _params: &Params, _intermediates: &[u8], _ignore: bool, _c: char) {}
+
+    fn put(&mut self, _byte: u8) {}
+
+    fn unhook(&mut self) {}
+
+    fn osc_dispatch(&mut self, _params: &[&[u8]], _bell_terminated: bool) {}
+
+    fn csi_dispatch(&mut self, params: &Params, intermediates: &[u8], _ignore: bool, c: char) {
+        if !intermediates.is_empty() {
+            return;
+        }
+        let first = params
+            .iter()
+            .next()
+            .and_then(|p| p.first().copied())
+            .unwrap_or(0) as usize;
+        let count = first.max(1);
+
+        match c {
+            // Cursor forward / back
+            'C' => self.cursor += count,
+            'D' => self.cursor = self.cursor.saturating_sub(count),
+            // Cursor to column
+            'G' => self.cursor = count - 1,
+            // Erase in line: to the end, to the start, all
+            'K' => match first {
+                0 => {
+                    self.current.truncate(self.cursor);
+                    self.current_styles.truncate(self.cursor);
+                }
+                1 => {
+                    let end = (self.cursor + 1).min(self.current.len());
+                    self.current[..end].fill(' ');
+                    let end = end.min(self.current_styles.len());
+                    self.current_styles[..end].fill(Style::default());
+                }
+                2 => {
+                    self.current.clear();
+                    self.current_styles.clear();
+                }
+                _ => {}
+            },
+            'm' => self.style.apply(params),
+            _ => {}
+        }
+    }
+
+    fn esc_dispatch(&mut self, _intermediates: &[u8], _ignore: bool, _byte: u8) {}
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn query(pattern: &str) -> SearchQuery {
+        SearchQuery {
+            pattern: pattern.to_string(),
+            regex: false,
+            case_sensitive: false,
+            max_results: None,
+        }
+    }
+
+    #[test]
+    fn test_keeps_plain_lines() {
+        let mut scrollback = Scrollback::new(3);
+        scrollback.feed(b"\x1b[1;31merror\x1b[0m: build failed\r\n");
+        // A progress bar redrawn in place
+        scrollback.feed(b"[##  ] 50%\r[####] 100%\x1b[K\r\n");
+        scrollback.feed(b"$ ls\x08\x08ls -l");
+
+        assert_eq!(
+            scrollback.lines(),
+            [
+                (0, "error: build failed".to_string()),
+                (1, "[####] 100%".to_string()),
+                (2, "$ ls -l".to_string()),
+            ]
+        );
+
+        scrollback.feed(b"\r\ntotal 0\r\n");
+        assert_eq!(scrollback.first_line(), 2);
+        assert_eq!(scrollback.last_line(), 4);
+        assert_eq!(scrollback.lines()[0].1, "$ ls -l");
+        assert_eq!(scrollback.line(1), None);
+        assert_eq!(scrollback.line(3).as_deref(), Some("total 0"));
+        assert_eq!(scrollback.range(0, 3).len(), 2);
+    }
+
+    #[test]
+    fn test_keeps_styles() {
+        let mut scrollback = Scrollback::default();
+        scrollback.feed(b"\x1b[1;31merror\x1b[0m: \x1b[38;5;208mslow\x1b[39m  \r\n");
+        scrollback.feed(b"\x1b[38:2::10:20:30mrgb\x1b[m plain");
+
+        let red_bold = Style {
+            fg: Some(Color::Indexed(1)),
+            bold: true,
+            ..Style::default()
+        };
+        let orange = Style {
+            fg: Some(Color::Indexed(208)),
+            ..Style::default()
+        };
+        assert_eq!(
+            scrollback.styles(0).unwrap(),
+            [
+                StyleSpan { start: 0, end: 5, style: red_bold },
+                StyleSpan { start: 7, end: 11, style: orange },
+            ]
+        );
+
+        let rgb = Style {
+            fg: Some(Color::Rgb(10, 20, 30)),
+            ..Style::default()
+        };
+        assert_eq!(
+            scrollback.styles(1).unwrap(),
+            [StyleSpan { start: 0, end: 3, style: rgb }]
+        );
+        assert_eq!(scrollback.styles(2), None);
+    }
+
+    #[test]
+    fn test_search() {
+        let mut scrollback = Scrollback::default();
+        scrollback.feed("Compiling api\r\nerror[E0308]: mismatched types\r\n".as_bytes());
+        scrollback.feed("  --> src/main.rs:4:5\r\nwarning: unused ö Error\r\n$ ".as_bytes());
+
+        let results = scrollback.search(&query("error")).unwrap();
+        assert!(!results.truncated);
+        let found: Vec<_> = results.matches.iter().map(|m| (m.line, m.start, m.end)).collect();
+        assert_eq!(found, [(3, 18, 23), (1, 0, 5)]);
+
+        let mut exact = query("error");
+        exact.case_sensitive = true;
+        assert_eq!(scrollback.search(&exact).unwrap().matches.len(), 1);
+
+        let mut regex = query(r"\w+\.rs:\d+");
+        regex.regex = true;
+        let found = &scrollback.search(&regex).unwrap().matches[0];
+        assert_eq!(&found.text[found.start..found.end], "main.rs:4");
+
+        // Literal search doesn't interpret the pattern
+        assert!(scrollback.search(&query("e.ror")).unwrap().matches.is_empty());
+
+        let mut limited = query("r");
+        limited.max_results = Some(2);
+        let results = scrollback.search(&limited).unwrap();
+        assert!(results.truncated);
+        assert_eq!(results.matches[0].line, 3);
+
+        assert!(scrollback.search(&query("")).is_err());
+        let mut invalid = query("(");
+        invalid.regex = true;
+        assert!(scrollback.search(&invalid).is_err());
+    }
+}
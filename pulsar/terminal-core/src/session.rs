@@ -41,22 +41,27 @@ impl TerminalSession {
         &self.config.name
     }
 
-    pub fn resize(&mut self, cols: u16, rows: u16) -> Result<()> {
+    pub fn resize(&self, cols: u16, rows: u16) -> Result<()> {
         self.pty.resize(cols, rows)
     }
 
+    /// Process ID of the session's shell
+    pub fn process_id(&self) -> Option<u32> {
+        self.pty.process_id()
+    }
+
     /// Write data to the PTY (send input)
-    pub fn write(&mut self, data: &[u8]) -> Result<usize> {
+    pub fn write(&self, data: &[u8]) -> Result<usize> {
         self.pty.write(data)
     }
 
     /// Read data from the PTY (get output)
-    pub fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+    pub fn read(&self, buf: &mut [u8]) -> Result<usize> {
         self.pty.read(buf)
     }
 
     /// Try to read without blocking
-    pub fn try_read(&mut self, buf: &mut [u8]) -> Result<usize> {
+    pub fn try_read(&self, buf: &mut [u8]) -> Result<usize> {
         self.pty.try_read(buf)
     }
 }
@@ -0,0 +1,218 @@
+//! Transcript export
+//!
+//! Renders a scrollback as plain text, or as a standalone HTML page with
+//! colors and attributes as inline styles, for sharing a session or
+//! attaching it to a ticket. The HTML needs no stylesheet or script.
+
+use serde::{Deserialize, Serialize};
+
+use crate::scrollback::{Color, Scrollback, Style, StyleSpan};
+
+/// Default foreground and background of the HTML page
+const DEFAULT_FG: &str = "#d4d4d4";
+const DEFAULT_BG: &str = "#1e1e1e";
+
+/// xterm's first 16 palette colors
+const BASE_COLORS: [(u8, u8, u8); 16] = [
+    (0x00, 0x00, 0x00),
+    (0xcd, 0x00, 0x00),
+    (0x00, 0xcd, 0x00),
+    (0xcd, 0xcd, 0x00),
+    (0x00, 0x00, 0xee),
+    (0xcd, 0x00, 0xcd),
+    (0x00, 0xcd, 0xcd),
+    (0xe5, 0xe5, 0xe5),
+    (0x7f, 0x7f, 0x7f),
+    (0xff, 0x00, 0x00),
+    (0x00, 0xff, 0x00),
+    (0xff, 0xff, 0x00),
+    (0x5c, 0x5c, 0xff),
+    (0xff, 0x00, 0xff),
+    (0x00, 0xff, 0xff),
+    (0xff, 0xff, 0xff),
+];
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TranscriptFormat {
+    #[default]
+    Text,
+    Html,
+}
+
+impl TranscriptFormat {
+    /// File extension for saving the transcript
+    pub fn extension(self) -> &'static str {
+        match self {
+            TranscriptFormat::Text => "txt",
+            TranscriptFormat::Html => "html",
+        }
+    }
+}
+
+/// Render everything kept in `scrollback`; trailing blank lines are left
+/// out. `title` heads the HTML page.
+pub fn render_transcript(scrollback: &Scrollback, format: TranscriptFormat, title: &str) -> String {
+    let mut lines = scrollback.lines();
+    while lines.last().is_some_and(|(_, text)| text.trim().is_empty()) {
+        lines.pop();
+    }
+
+    match format {
+        TranscriptFormat::Text => {
+            let mut out: String = lines
+                .iter()
+                .map(|(_, text)| text.trim_end())
+                .collect::<Vec<_>>()
+                .join("\n");
+            out.push('\n');
+            out
+        }
+        TranscriptFormat::Html => {
+            let mut body = String::new();
+            for (line, text) in &lines {
+                let spans = scrollback.styles(*line).unwrap_or_default();
+                push_html_line(&mut body, text, &spans);
+                body.push('\n');
+            }
+            format!(
+                "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>{title}</title>\n</head>\n\
+                 <body style=\"margin:0;background:{bg}\">\n\
+                 <pre style=\"margin:0;padding:1em;color:{fg};background:{bg};\
+                 font-family:ui-monospace,Menlo,Consolas,monospace;font-size:13px;line-height:1.3\">\n\
+                 {body}</pre>\n</body>\n</html>\n",
+                title = escape_html(title),
+                fg = DEFAULT_FG,
+                bg = DEFAULT_BG,
+            )
+        }
+    }
+}
+
+/// Append one line, wrapping each styled stretch in a `<span>`
+fn push_html_line(out: &mut String, text: &str, spans: &[StyleSpan]) {
+    let chars: Vec<char> = text.chars().collect();
+    let mut at = 0;
+    for span in spans {
+        let start = span.start.min(chars.len());
+        let end = span.end.min(chars.len());
+        if start >= end {
+            continue;
+        }
+        out.push_str(&escape_html(&chars[at..start].iter().collect::<String>()));
+        out.push_str(&format!("<span style=\"{}\">", css(&span.style)));
+        out.push_str(&escape_html(&chars[start..end].iter().collect::<String>()));
+        out.push_str("</span>");
+        at = end;
+    }
+    out.push_str(&escape_html(&chars[at..].iter().collect::<String>()));
+}
+
+fn css(style: &Style) -> String {
+    let (mut fg, mut bg) = (style.fg.map(rgb), style.bg.map(rgb));
+    // Bold brightens the eight basic colors, as most terminals draw it
+    if style.bold {
+        if let Some(Color::Indexed(n @ 0..=7)) = style.fg {
+            fg = Some(rgb(Color::Indexed(n + 8)));
+        }
+    }
+    if style.inverse {
+        (fg, bg) = (
+            Some(bg.unwrap_or_else(|| DEFAULT_BG.to_string())),
+            Some(fg.unwrap_or_else(|| DEFAULT_FG.to_string())),
+        );
+    }
+
+    let mut rules = Vec::new();
+    if let Some(fg) = fg {
+        rules.push(format!("color:{}", fg));
+    }
+    if let Some(bg) = bg {
+        rules.push(format!("background:{}", bg));
+    }
+    if style.bold {
+        rules.push("font-weight:bold".to_string());
+    }
+    if style.dim {
+        rules.push("opacity:0.6".to_string());
+    }
+    if style.italic {
+        rules.push("font-style:italic".to_string());
+    }
+    if style.underline {
+        rules.push("text-decoration:underline".to_string());
+    }
+    rules.join(";")
+}
+
+/// A color as CSS hex
+fn rgb(color: Color) -> String {
+    let (r, g, b) = match color {
+        Color::Rgb(r, g, b) => (r, g, b),
+        Color::Indexed(n @ 0..=15) => BASE_COLORS[n as usize],
+        // 6x6x6 color cube
+        Color::Indexed(n @ 16..=231) => {
+            let level = |v: u8| if v == 0 { 0 } else { 55 + v * 40 };
+            let n = n - 16;
+            (level(n / 36), level(n / 6 % 6), level(n % 6))
+        }
+        // Grayscale ramp
+        Color::Indexed(n) => {
+            let v = 8 + (n - 232) * 10;
+            (v, v, v)
+        }
+    };
+    format!("#{:02x}{:02x}{:02x}", r, g, b)
+}
+
+fn escape_html(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            _ => out.push(c),
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn scrollback() -> Scrollback {
+        let mut scrollback = Scrollback::default();
+        scrollback.feed(b"$ make\r\n\x1b[1;31merror\x1b[0m: <missing> & \x1b[7mgone\x1b[27m\r\n\r\n");
+        scrollback
+    }
+
+    #[test]
+    fn test_renders_text() {
+        assert_eq!(
+            render_transcript(&scrollback(), TranscriptFormat::Text, "build"),
+            "$ make\nerror: <missing> & gone\n"
+        );
+    }
+
+    #[test]
+    fn test_renders_html() {
+        let html = render_transcript(&scrollback(), TranscriptFormat::Html, "a <b>");
+        assert!(html.starts_with("<!DOCTYPE html>"));
+        assert!(html.contains("<title>a &lt;b&gt;</title>"));
+        assert!(html.contains(
+            "<span style=\"color:#ff0000;font-weight:bold\">error</span>: &lt;missing&gt; &amp; "
+        ));
+        assert!(html.contains("<span style=\"color:#1e1e1e;background:#d4d4d4\">gone</span>\n</pre>"));
+    }
+
+    #[test]
+    fn test_palette() {
+        assert_eq!(rgb(Color::Indexed(4)), "#0000ee");
+        assert_eq!(rgb(Color::Indexed(196)), "#ff0000");
+        assert_eq!(rgb(Color::Indexed(244)), "#808080");
+        assert_eq!(rgb(Color::Rgb(1, 2, 3)), "#010203");
+    }
+}
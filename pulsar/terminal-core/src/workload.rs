@@ -0,0 +1,229 @@
+//! Reproducible terminal output for benchmarks and performance budgets
+//!
+//! Each workload stands in for a kind of output that stresses the PTY to
+//! render pipeline differently: a large log piped through `cat`, `yes`, and
+//! a full-screen TUI repainting every cell. Output is generated from a fixed
+//! seed, so every run (and every crate benchmarking it) sees the same bytes.
+//!
+//! The `budget_*` tests below are skipped by default; run them against an
+//! optimized build to check for regressions:
+//!
+//! ```text
+//! cargo test --release -p terminal-core workload -- --ignored --nocapture
+//! ```
+
+use crate::{CommandTracker, CwdTracker, Scrollback};
+
+/// Bytes the daemon reads from a PTY at a time
+pub const PTY_READ_SIZE: usize = 8192;
+
+/// A kind of terminal output
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Workload {
+    /// `cat` of a colored application log
+    LogDump,
+    /// `yes`: the shortest possible lines, as fast as they come
+    Yes,
+    /// A full-screen TUI redrawing an 80x24 screen
+    TuiRedraw,
+}
+
+impl Workload {
+    pub const ALL: [Workload; 3] = [Workload::LogDump, Workload::Yes, Workload::TuiRedraw];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Workload::LogDump => "log_dump",
+            Workload::Yes => "yes",
+            Workload::TuiRedraw => "tui_redraw",
+        }
+    }
+
+    /// About `size` bytes of this output; a 100MB log is `generate(100 << 20)`
+    pub fn generate(self, size: usize) -> Vec<u8> {
+        let mut rng = Lcg(0x5eed);
+        let mut out = Vec::with_capacity(size + 4096);
+        let mut n = 0u64;
+        while out.len() < size {
+            match self {
+                Workload::LogDump => log_line(&mut out, &mut rng, n),
+                Workload::Yes => out.extend_from_slice(b"y\r\n"),
+                Workload::TuiRedraw => tui_frame(&mut out, &mut rng, n),
+            }
+            n += 1;
+        }
+        out
+    }
+}
+
+/// The daemon's per-read work on output: scrollback, working directory and
+/// command tracking
+pub struct DaemonStage {
+    pub scrollback: Scrollback,
+    pub cwd: CwdTracker,
+    pub commands: CommandTracker,
+}
+
+impl DaemonStage {
+    pub fn new(scrollback_lines: usize) -> Self {
+        Self {
+            scrollback: Scrollback::new(scrollback_lines),
+            cwd: CwdTracker::new(),
+            commands: CommandTracker::new(),
+        }
+    }
+
+    pub fn feed(&mut self, data: &[u8]) {
+        self.scrollback.feed(data);
+        self.cwd.feed(data);
+        self.commands.feed(data);
+    }
+}
+
+/// Small deterministic generator; quality only needs to defeat branch
+/// prediction
+struct Lcg(u64);
+
+impl Lcg {
+    fn step(&mut self) -> u64 {
+        self.0 = self
+            .0
+            .wrapping_mul(6364136223846793005)
+            .wrapping_add(1442695040888963407);
+        self.0 >> 33
+    }
+
+    fn below(&mut self, n: u64) -> u64 {
+        self.step() % n
+    }
+}
+
+const WORDS: [&str; 16] = [
+    "session", "attached", "client", "bytes", "flushed", "retry", "upstream", "latency",
+    "cache", "miss", "request", "completed", "worker", "queue", "drained", "timeout",
+];
+
+fn log_line(out: &mut Vec<u8>, rng: &mut Lcg, n: u64) {
+    let (color, level) = match rng.below(20) {
+        0 => (31, "ERROR"),
+        1 | 2 => (33, "WARN "),
+        3..=5 => (36, "DEBUG"),
+        _ => (32, "INFO "),
+    };
+    let secs = n / 50;
+    out.extend(
+        format!(
+            "2025-11-06T{:02}:{:02}:{:02}.{:03}Z \x1b[{}m{}\x1b[0m \x1b[2mpulsar::worker{{id={}}}\x1b[0m:",
+            secs / 3600 % 24,
+            secs / 60 % 60,
+            secs % 60,
+            n % 1000,
+            color,
+            level,
+            rng.below(64),
+        )
+        .into_bytes(),
+    );
+    for _ in 0..4 + rng.below(12) {
+        out.push(b' ');
+        out.extend_from_slice(WORDS[rng.below(WORDS.len() as u64) as usize].as_bytes());
+    }
+    out.extend(format!(" elapsed_ms={}\r\n", rng.below(5000)).into_bytes());
+}
+
+fn tui_frame(out: &mut Vec<u8>, rng: &mut Lcg, n: u64) {
+    const COLS: u64 = 80;
+    const ROWS: u64 = 24;
+
+    out.extend_from_slice(b"\x1b[?25l\x1b[H");
+    // Status bar
+    out.extend(format!("\x1b[7m top - frame {:<8}", n).into_bytes());
+    out.resize(out.len() + COLS as usize - 21, b' ');
+    out.extend_from_slice(b"\x1b[0m");
+    for row in 2..=ROWS {
+        out.extend(format!("\x1b[{};1H", row).into_bytes());
+        let mut col = 0;
+        while col < COLS {
+            let width = (1 + rng.below(12)).min(COLS - col);
+            out.extend(format!("\x1b[38;5;{}m", rng.below(256)).into_bytes());
+            out.extend((0..width).map(|_| b'!' + rng.below(94) as u8));
+            col += width;
+        }
+        out.extend_from_slice(b"\x1b[0m\x1b[K");
+    }
+    out.extend_from_slice(b"\x1b[?25h");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::AnsiParser;
+    use std::time::{Duration, Instant};
+
+    /// Data run through each budget
+    const BUDGET_SIZE: usize = 32 << 20;
+
+    /// Slowest acceptable throughput, in MB/s of output, for a release build
+    const PARSER_BUDGET: f64 = 60.0;
+    const DAEMON_BUDGET: f64 = 25.0;
+
+    #[test]
+    fn test_workloads_are_reproducible() {
+        for workload in Workload::ALL {
+            let data = workload.generate(64 << 10);
+            assert!(data.len() >= 64 << 10, "{} too short", workload.name());
+            assert_eq!(data, workload.generate(64 << 10));
+            assert!(std::str::from_utf8(&data).is_ok());
+        }
+    }
+
+    #[test]
+    fn test_tui_frames_stay_on_screen() {
+        let data = Workload::TuiRedraw.generate(1);
+        // Every row is positioned, never scrolled
+        assert!(data.windows(7).any(|w| w == b"\x1b[24;1H"));
+        assert!(!data.contains(&b'\n'));
+    }
+
+    /// Time `run` over `data` fed in PTY-sized reads; returns MB/s
+    fn throughput(data: &[u8], mut run: impl FnMut(&[u8])) -> f64 {
+        let started = Instant::now();
+        for chunk in data.chunks(PTY_READ_SIZE) {
+            run(chunk);
+        }
+        let elapsed = started.elapsed().max(Duration::from_micros(1));
+        data.len() as f64 / (1 << 20) as f64 / elapsed.as_secs_f64()
+    }
+
+    fn check_budget(stage: &str, budget: f64, mut run: impl FnMut(&[u8])) {
+        for workload in Workload::ALL {
+            let data = workload.generate(BUDGET_SIZE);
+            let mbps = throughput(&data, &mut run);
+            println!("{:<8} {:<12} {:>8.1} MB/s (budget {})", stage, workload.name(), mbps, budget);
+            assert!(
+                mbps >= budget,
+                "{} on {} fell to {:.1} MB/s, budget is {}",
+                stage,
+                workload.name(),
+                mbps,
+                budget
+            );
+        }
+    }
+
+    #[test]
+    #[ignore = "performance budget; run with --release -- --ignored"]
+    fn budget_parser() {
+        let mut parser = AnsiParser::new();
+        check_budget("parser", PARSER_BUDGET, |chunk| {
+            std::hint::black_box(parser.parse(chunk));
+        });
+    }
+
+    #[test]
+    #[ignore = "performance budget; run with --release -- --ignored"]
+    fn budget_daemon_stage() {
+        let mut stage = DaemonStage::new(10_000);
+        check_budget("daemon", DAEMON_BUDGET, |chunk| stage.feed(chunk));
+    }
+}
@@ -0,0 +1,77 @@
+//! Cost of applying output to the screen buffer and exporting it for
+//! rendering, natively
+//!
+//! Run with `cargo bench --bench render` from this directory. Workloads come
+//! from terminal-core so the numbers line up with its `pipeline` benchmark;
+//! `PULSAR_BENCH_MB` sets their size (default 4MB).
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use terminal_core::workload::PTY_READ_SIZE;
+use terminal_core::Workload;
+use terminal_wasm::{AnsiParser, TerminalBuffer};
+
+fn workload_size() -> usize {
+    let mb = std::env::var("PULSAR_BENCH_MB")
+        .ok()
+        .and_then(|mb| mb.parse().ok())
+        .unwrap_or(4);
+    mb << 20
+}
+
+/// Parse every read into the buffer, then export the screen as the
+/// frontend does once per animation frame
+fn bench_render(c: &mut Criterion) {
+    let mut group = c.benchmark_group("render");
+    for workload in Workload::ALL {
+        let data = workload.generate(workload_size());
+        // Workloads are ASCII, so any split is valid UTF-8
+        let text = String::from_utf8(data).unwrap();
+        group.throughput(Throughput::Bytes(text.len() as u64));
+        group.bench_with_input(BenchmarkId::from_parameter(workload.name()), &text, |b, text| {
+            let mut buffer = TerminalBuffer::new(80, 24);
+            let mut parser = AnsiParser::new();
+            b.iter(|| {
+                for chunk in text.as_bytes().chunks(PTY_READ_SIZE) {
+                    parser.parse(std::str::from_utf8(chunk).unwrap(), &mut buffer);
+                    criterion::black_box(buffer.get_lines_json());
+                }
+            })
+        });
+    }
+    group.finish();
+}
+
+/// Parsing alone, without exporting; plain text runs take the bulk-copy
+/// path here
+fn bench_parse(c: &mut Criterion) {
+    let mut group = c.benchmark_group("parse");
+    for workload in Workload::ALL {
+        let text = String::from_utf8(workload.generate(workload_size())).unwrap();
+        group.throughput(Throughput::Bytes(text.len() as u64));
+        group.bench_with_input(BenchmarkId::from_parameter(workload.name()), &text, |b, text| {
+            let mut buffer = TerminalBuffer::new(80, 24);
+            let mut parser = AnsiParser::new();
+            b.iter(|| {
+                for chunk in text.as_bytes().chunks(PTY_READ_SIZE) {
+                    parser.parse(std::str::from_utf8(chunk).unwrap(), &mut buffer);
+                }
+            })
+        });
+    }
+    group.finish();
+}
+
+/// Exporting one full 80x24 screen
+fn bench_export(c: &mut Criterion) {
+    let mut buffer = TerminalBuffer::new(80, 24);
+    let mut parser = AnsiParser::new();
+    let frame = String::from_utf8(Workload::TuiRedraw.generate(1)).unwrap();
+    parser.parse(&frame, &mut buffer);
+
+    c.bench_function("export_lines_json", |b| {
+        b.iter(|| criterion::black_box(buffer.get_lines_json()))
+    });
+}
+
+criterion_group!(benches, bench_render, bench_parse, bench_export);
+criterion_main!(benches);
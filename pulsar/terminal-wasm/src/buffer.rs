@@ -1,6 +1,12 @@
 //! Terminal buffer - stores screen content and cursor state
 
 use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+use unicode_bidi::{BidiInfo, Level};
+use unicode_width::UnicodeWidthChar;
+
+/// Fills the cell after a double-width character
+pub const WIDE_CONTINUATION: char = '\0';
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 pub struct CharCell {
@@ -29,13 +35,52 @@ impl Default for CharCell {
     }
 }
 
+/// Scrolling margins, inclusive: rows set by DECSTBM and columns by
+/// DECSLRM. Scrolling, insert/delete line and wrapping stay inside them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Margins {
+    top: u16,
+    bottom: u16,
+    left: u16,
+    right: u16,
+}
+
+impl Margins {
+    fn full(cols: u16, rows: u16) -> Self {
+        Self {
+            top: 0,
+            bottom: rows.saturating_sub(1),
+            left: 0,
+            right: cols.saturating_sub(1),
+        }
+    }
+}
+
+/// A stretch of a line at one bidi direction, by column (`start..end`)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BidiRun {
+    pub start: u16,
+    pub end: u16,
+    pub rtl: bool,
+}
+
 pub struct TerminalBuffer {
     cols: u16,
     rows: u16,
     cursor_col: u16,
     cursor_row: u16,
     cells: Vec<CharCell>,
+    /// Row of `cells` holding screen row 0; scrolling the whole screen
+    /// rotates it rather than moving every cell
+    first_row: usize,
     current_style: CharCell,
+    /// IME preedit text, drawn at the cursor but never written to `cells`
+    composition: Option<String>,
+    margins: Margins,
+    /// DECLRMM: left/right margins may be set
+    lr_margin_mode: bool,
+    /// DECOM: cursor addressing is relative to the margins
+    origin_mode: bool,
 }
 
 impl TerminalBuffer {
@@ -47,7 +92,12 @@ impl TerminalBuffer {
             cursor_col: 0,
             cursor_row: 0,
             cells: vec![CharCell::default(); size],
+            first_row: 0,
             current_style: CharCell::default(),
+            composition: None,
+            margins: Margins::full(cols, rows),
+            lr_margin_mode: false,
+            origin_mode: false,
         }
     }
 
@@ -69,45 +119,150 @@ impl TerminalBuffer {
 
     pub fn resize(&mut self, cols: u16, rows: u16) {
         let new_size = (cols as usize) * (rows as usize);
+        self.cells.rotate_left(self.first_row * self.cols as usize);
+        self.first_row = 0;
         self.cells.resize(new_size, CharCell::default());
         self.cols = cols;
         self.rows = rows;
         self.cursor_col = self.cursor_col.min(cols.saturating_sub(1));
         self.cursor_row = self.cursor_row.min(rows.saturating_sub(1));
+        self.margins = Margins::full(cols, rows);
     }
 
     fn index(&self, col: u16, row: u16) -> usize {
-        (row as usize) * (self.cols as usize) + (col as usize)
+        let row = (row as usize + self.first_row) % (self.rows.max(1) as usize);
+        row * (self.cols as usize) + (col as usize)
     }
 
     pub fn put_char(&mut self, ch: char) {
-        if self.cursor_col >= self.cols {
+        // Combining marks have no cell of their own
+        let width = ch.width().unwrap_or(0) as u16;
+        if width == 0 {
+            self.combine(ch);
+            return;
+        }
+        if self.cursor_col + width > self.wrap_edge() {
             self.newline();
         }
 
         let idx = self.index(self.cursor_col, self.cursor_row);
         if idx < self.cells.len() {
+            self.unsplit_wide(idx);
             self.cells[idx] = CharCell {
                 ch,
                 ..self.current_style
             };
+            if width == 2 && self.cursor_col + 1 < self.cols {
+                self.unsplit_wide(idx + 1);
+                self.cells[idx + 1] = CharCell {
+                    ch: WIDE_CONTINUATION,
+                    ..self.current_style
+                };
+            }
+        }
+
+        // A wide character on a one-column screen overhangs it
+        self.cursor_col = (self.cursor_col + width).min(self.cols);
+    }
+
+    /// Write a run of printable ASCII, as `put_char` would one at a time
+    /// but a screen row at a time
+    pub fn put_ascii(&mut self, text: &[u8]) {
+        let mut text = text;
+        while !text.is_empty() {
+            let edge = self.wrap_edge();
+            if self.cursor_col >= edge {
+                self.newline();
+                continue;
+            }
+            let (row, rest) = text.split_at(text.len().min((edge - self.cursor_col) as usize));
+            let start = self.index(self.cursor_col, self.cursor_row);
+            let end = start + row.len();
+            self.unsplit_wide(start);
+            self.unsplit_wide(end - 1);
+            for (cell, &byte) in self.cells[start..end].iter_mut().zip(row) {
+                *cell = CharCell {
+                    ch: byte as char,
+                    ..self.current_style
+                };
+            }
+            self.cursor_col += row.len() as u16;
+            text = rest;
         }
+    }
 
-        self.cursor_col += 1;
+    /// Column text wraps at: the right margin when writing inside it (or
+    /// just past it, waiting to wrap), else the screen edge
+    fn wrap_edge(&self) -> u16 {
+        if self.cursor_col <= self.margins.right + 1 {
+            self.margins.right + 1
+        } else {
+            self.cols
+        }
+    }
+
+    /// Fold a combining mark into the character before the cursor when
+    /// Unicode has a precomposed form (NFC), so `e` + U+0301 shows as `é`;
+    /// marks without one are dropped
+    fn combine(&mut self, mark: char) {
+        if self.cursor_col == 0 {
+            return;
+        }
+        let mut idx = self.index(self.cursor_col - 1, self.cursor_row);
+        if self.cells[idx].ch == WIDE_CONTINUATION && self.cursor_col > 1 {
+            idx -= 1;
+        }
+        if let Some(composed) = unicode_normalization::char::compose(self.cells[idx].ch, mark) {
+            self.cells[idx].ch = composed;
+        }
+    }
+
+    /// Blank the other half of a double-width character about to be
+    /// overwritten at `idx`, so no half of it is left behind
+    fn unsplit_wide(&mut self, idx: usize) {
+        let col = idx % (self.cols as usize);
+        if self.cells[idx].ch == WIDE_CONTINUATION {
+            if col > 0 {
+                self.cells[idx - 1].ch = ' ';
+            }
+        } else if col + 1 < self.cols as usize && self.cells[idx + 1].ch == WIDE_CONTINUATION {
+            self.cells[idx + 1].ch = ' ';
+        }
     }
 
     pub fn newline(&mut self) {
-        self.cursor_col = 0;
-        if self.cursor_row + 1 < self.rows {
+        self.carriage_return();
+        self.line_feed();
+    }
+
+    /// Move down a line, scrolling the region when at its bottom (IND)
+    pub fn line_feed(&mut self) {
+        if self.cursor_row == self.margins.bottom {
+            if self.in_lr_margins() {
+                self.scroll_up(1);
+            }
+        } else if self.cursor_row + 1 < self.rows {
             self.cursor_row += 1;
-        } else {
-            // Scroll up
-            self.scroll_up(1);
+        }
+    }
+
+    /// Move up a line, scrolling the region down when at its top (RI)
+    pub fn reverse_line_feed(&mut self) {
+        if self.cursor_row == self.margins.top {
+            if self.in_lr_margins() {
+                self.scroll_down(1);
+            }
+        } else if self.cursor_row > 0 {
+            self.cursor_row -= 1;
         }
     }
 
     pub fn carriage_return(&mut self) {
-        self.cursor_col = 0;
+        self.cursor_col = if self.cursor_col >= self.margins.left {
+            self.margins.left
+        } else {
+            0
+        };
     }
 
     pub fn tab(&mut self) {
@@ -122,25 +277,204 @@ impl TerminalBuffer {
         }
     }
 
+    // Relative cursor movement stops at a margin when starting inside it
+
     pub fn cursor_up(&mut self, n: u16) {
-        self.cursor_row = self.cursor_row.saturating_sub(n);
+        let limit = if self.cursor_row >= self.margins.top { self.margins.top } else { 0 };
+        self.cursor_row = self.cursor_row.saturating_sub(n).max(limit);
     }
 
     pub fn cursor_down(&mut self, n: u16) {
-        self.cursor_row = (self.cursor_row + n).min(self.rows - 1);
+        let limit = if self.cursor_row <= self.margins.bottom {
+            self.margins.bottom
+        } else {
+            self.rows - 1
+        };
+        self.cursor_row = self.cursor_row.saturating_add(n).min(limit);
     }
 
     pub fn cursor_forward(&mut self, n: u16) {
-        self.cursor_col = (self.cursor_col + n).min(self.cols - 1);
+        let limit = if self.cursor_col <= self.margins.right {
+            self.margins.right
+        } else {
+            self.cols - 1
+        };
+        self.cursor_col = self.cursor_col.saturating_add(n).min(limit);
     }
 
     pub fn cursor_backward(&mut self, n: u16) {
-        self.cursor_col = self.cursor_col.saturating_sub(n);
+        let limit = if self.cursor_col >= self.margins.left { self.margins.left } else { 0 };
+        self.cursor_col = self.cursor_col.saturating_sub(n).max(limit);
     }
 
+    /// Absolute cursor position, relative to the margins in origin mode
     pub fn cursor_goto(&mut self, col: u16, row: u16) {
-        self.cursor_col = col.min(self.cols - 1);
-        self.cursor_row = row.min(self.rows - 1);
+        if self.origin_mode {
+            let m = self.margins;
+            self.cursor_col = col.saturating_add(m.left).min(m.right);
+            self.cursor_row = row.saturating_add(m.top).min(m.bottom);
+        } else {
+            self.cursor_col = col.min(self.cols - 1);
+            self.cursor_row = row.min(self.rows - 1);
+        }
+    }
+
+    /// Move to column `col` of the current line (CHA)
+    pub fn cursor_to_col(&mut self, col: u16) {
+        let left = if self.origin_mode { self.margins.left } else { 0 };
+        self.cursor_col = col.saturating_add(left).min(self.cols - 1);
+    }
+
+    /// Move to row `row`, keeping the column (VPA)
+    pub fn cursor_to_row(&mut self, row: u16) {
+        self.cursor_row = if self.origin_mode {
+            row.saturating_add(self.margins.top).min(self.margins.bottom)
+        } else {
+            row.min(self.rows - 1)
+        };
+    }
+
+    /// Blank `n` characters from the cursor on, without moving it (ECH)
+    pub fn erase_chars(&mut self, n: u16) {
+        let start = self.index(self.cursor_col, self.cursor_row);
+        self.blank(start, n.min(self.cols - self.cursor_col));
+    }
+
+    // Scroll regions and margins
+
+    /// Set the scrolling region to rows `top..=bottom` (DECSTBM); an
+    /// invalid region is ignored. Homes the cursor.
+    pub fn set_scroll_region(&mut self, top: u16, bottom: u16) {
+        if top >= bottom || bottom >= self.rows {
+            return;
+        }
+        self.margins.top = top;
+        self.margins.bottom = bottom;
+        self.cursor_goto(0, 0);
+    }
+
+    /// Set the left and right margins to columns `left..=right` (DECSLRM);
+    /// ignored unless left/right margin mode is on. Homes the cursor.
+    pub fn set_lr_margins(&mut self, left: u16, right: u16) {
+        if !self.lr_margin_mode || left >= right || right >= self.cols {
+            return;
+        }
+        self.margins.left = left;
+        self.margins.right = right;
+        self.cursor_goto(0, 0);
+    }
+
+    /// DECLRMM; turning it off drops the left and right margins
+    pub fn set_lr_margin_mode(&mut self, enabled: bool) {
+        self.lr_margin_mode = enabled;
+        if !enabled {
+            self.margins.left = 0;
+            self.margins.right = self.cols.saturating_sub(1);
+        }
+    }
+
+    pub fn lr_margin_mode(&self) -> bool {
+        self.lr_margin_mode
+    }
+
+    /// DECOM; homes the cursor either way
+    pub fn set_origin_mode(&mut self, enabled: bool) {
+        self.origin_mode = enabled;
+        self.cursor_goto(0, 0);
+    }
+
+    fn in_lr_margins(&self) -> bool {
+        (self.margins.left..=self.margins.right).contains(&self.cursor_col)
+    }
+
+    fn in_margins(&self) -> bool {
+        (self.margins.top..=self.margins.bottom).contains(&self.cursor_row) && self.in_lr_margins()
+    }
+
+    /// Scroll the region up `n` lines (SU), blanking lines at its bottom
+    pub fn scroll_up(&mut self, n: u16) {
+        self.shift_rows_up(self.margins.top, n);
+    }
+
+    /// Scroll the region down `n` lines (SD), blanking lines at its top
+    pub fn scroll_down(&mut self, n: u16) {
+        self.shift_rows_down(self.margins.top, n);
+    }
+
+    /// Insert `n` blank lines at the cursor, pushing lines below it off
+    /// the bottom of the region (IL)
+    pub fn insert_lines(&mut self, n: u16) {
+        if self.in_margins() {
+            self.shift_rows_down(self.cursor_row, n);
+            self.cursor_col = self.margins.left;
+        }
+    }
+
+    /// Delete `n` lines at the cursor, pulling blank lines in at the
+    /// bottom of the region (DL)
+    pub fn delete_lines(&mut self, n: u16) {
+        if self.in_margins() {
+            self.shift_rows_up(self.cursor_row, n);
+            self.cursor_col = self.margins.left;
+        }
+    }
+
+    /// Move rows `top..=margins.bottom` up by `n` within the left and right
+    /// margins
+    fn shift_rows_up(&mut self, top: u16, n: u16) {
+        let Margins { bottom, left, right, .. } = self.margins;
+        let n = n.min(bottom + 1 - top);
+        if self.is_whole_screen(top) {
+            self.first_row = (self.first_row + n as usize) % self.rows as usize;
+            for row in bottom + 1 - n..=bottom {
+                self.blank(self.index(0, row), self.cols);
+            }
+            return;
+        }
+        for row in top..=bottom {
+            let dest = self.index(left, row);
+            if row + n <= bottom {
+                let src = self.index(left, row + n);
+                self.cells.copy_within(src..=src + (right - left) as usize, dest);
+            } else {
+                self.blank(dest, right - left + 1);
+            }
+        }
+    }
+
+    /// Move rows `top..=margins.bottom` down by `n` within the left and
+    /// right margins
+    fn shift_rows_down(&mut self, top: u16, n: u16) {
+        let Margins { bottom, left, right, .. } = self.margins;
+        let n = n.min(bottom + 1 - top);
+        if self.is_whole_screen(top) {
+            self.first_row = (self.first_row + (self.rows - n) as usize) % self.rows as usize;
+            for row in 0..n {
+                self.blank(self.index(0, row), self.cols);
+            }
+            return;
+        }
+        for row in (top..=bottom).rev() {
+            let dest = self.index(left, row);
+            if row >= top + n {
+                let src = self.index(left, row - n);
+                self.cells.copy_within(src..=src + (right - left) as usize, dest);
+            } else {
+                self.blank(dest, right - left + 1);
+            }
+        }
+    }
+
+    /// Rows `top..=margins.bottom` within the left and right margins are
+    /// the whole screen
+    fn is_whole_screen(&self, top: u16) -> bool {
+        top == 0 && self.margins == Margins::full(self.cols, self.rows)
+    }
+
+    fn blank(&mut self, start: usize, len: u16) {
+        for cell in &mut self.cells[start..start + len as usize] {
+            *cell = CharCell::default();
+        }
     }
 
     pub fn clear(&mut self) {
@@ -197,21 +531,6 @@ impl TerminalBuffer {
         }
     }
 
-    fn scroll_up(&mut self, n: u16) {
-        let n = n as usize;
-        let cols = self.cols as usize;
-        let scroll_amount = n * cols;
-
-        // Move lines up
-        self.cells.copy_within(scroll_amount.., 0);
-
-        // Clear bottom lines
-        let clear_start = self.cells.len() - scroll_amount;
-        for cell in &mut self.cells[clear_start..] {
-            *cell = CharCell::default();
-        }
-    }
-
     // Style methods
     pub fn reset_style(&mut self) {
         self.current_style = CharCell::default();
@@ -248,32 +567,135 @@ impl TerminalBuffer {
     pub fn reset(&mut self) {
         self.clear();
         self.current_style = CharCell::default();
+        self.margins = Margins::full(self.cols, self.rows);
+        self.lr_margin_mode = false;
+        self.origin_mode = false;
+    }
+
+    // IME composition
+    pub fn set_composition(&mut self, text: Option<String>) {
+        self.composition = text;
+    }
+
+    pub fn composition(&self) -> Option<&str> {
+        self.composition.as_deref()
+    }
+
+    /// Where the preedit text ends, for placing the candidate window
+    pub fn composition_end(&self) -> (u16, u16) {
+        let mut end = (self.cursor_col, self.cursor_row);
+        self.layout_composition(|col, row, width, _| end = (col + width, row));
+        end
+    }
+
+    /// Lay out the preedit text from the cursor, wrapping like output
+    /// would and clipping at the bottom of the screen
+    fn layout_composition(&self, mut place: impl FnMut(u16, u16, u16, char)) {
+        let Some(text) = &self.composition else {
+            return;
+        };
+        let (mut col, mut row) = (self.cursor_col, self.cursor_row);
+        for ch in text.chars() {
+            let width = ch.width().unwrap_or(0) as u16;
+            if width == 0 {
+                continue;
+            }
+            if col + width > self.cols {
+                col = 0;
+                row += 1;
+            }
+            if row >= self.rows {
+                break;
+            }
+            place(col, row, width, ch);
+            col += width;
+        }
+    }
+
+    /// The screen as displayed, row 0 first: cells with the preedit text
+    /// drawn over them
+    fn display_cells(&self) -> Cow<'_, [CharCell]> {
+        if self.composition.is_none() && self.first_row == 0 {
+            return Cow::Borrowed(&self.cells);
+        }
+        let mut cells = self.cells.clone();
+        cells.rotate_left(self.first_row * self.cols as usize);
+        let style = CharCell {
+            underline: true,
+            ..CharCell::default()
+        };
+        self.layout_composition(|col, row, width, ch| {
+            let idx = (row as usize) * (self.cols as usize) + (col as usize);
+            cells[idx] = CharCell { ch, ..style };
+            if width == 2 && col + 1 < self.cols {
+                cells[idx + 1] = CharCell {
+                    ch: WIDE_CONTINUATION,
+                    ..style
+                };
+            }
+        });
+        Cow::Owned(cells)
     }
 
     // Export methods
     pub fn get_lines_json(&self) -> String {
-        let mut lines = Vec::new();
-        for row in 0..self.rows {
-            let start = self.index(0, row);
-            let end = start + (self.cols as usize);
-            let line: Vec<&CharCell> = self.cells[start..end].iter().collect();
-            lines.push(line);
-        }
+        let cells = self.display_cells();
+        let lines: Vec<&[CharCell]> = cells.chunks(self.cols.max(1) as usize).collect();
         serde_json::to_string(&lines).unwrap_or_else(|_| "[]".to_string())
     }
 
+    /// Bidi runs for each line, in display order, as JSON
+    ///
+    /// Lines without right-to-left text are `null` and display as stored.
+    /// Each line is its own left-to-right paragraph, so a shell prompt
+    /// stays put when a command prints Hebrew or Arabic; the frontend
+    /// draws each run's cells in the given order, reversing `rtl` runs.
+    pub fn get_bidi_runs_json(&self) -> String {
+        let cells = self.display_cells();
+        let lines: Vec<Option<Vec<BidiRun>>> = cells
+            .chunks(self.cols.max(1) as usize)
+            .map(Self::bidi_runs)
+            .collect();
+        serde_json::to_string(&lines).unwrap_or_else(|_| "[]".to_string())
+    }
+
+    fn bidi_runs(line: &[CharCell]) -> Option<Vec<BidiRun>> {
+        // The line as text, with the column each byte of it came from
+        let mut text = String::new();
+        let mut cols = Vec::new();
+        for (col, cell) in line.iter().enumerate() {
+            if cell.ch != WIDE_CONTINUATION {
+                text.push(cell.ch);
+                cols.resize(text.len(), col as u16);
+            }
+        }
+        let col_at = |byte: usize| cols.get(byte).copied().unwrap_or(line.len() as u16);
+
+        let info = BidiInfo::new(&text, Some(Level::ltr()));
+        if !info.has_rtl() {
+            return None;
+        }
+        let para = info.paragraphs.first()?;
+        let (levels, runs) = info.visual_runs(para, para.range.clone());
+        Some(
+            runs.into_iter()
+                .map(|run| BidiRun {
+                    start: col_at(run.start),
+                    end: col_at(run.end),
+                    rtl: levels[run.start].is_rtl(),
+                })
+                .collect(),
+        )
+    }
+
     pub fn get_screen_text(&self) -> String {
+        let cells = self.display_cells();
         let mut result = String::new();
-        for row in 0..self.rows {
-            for col in 0..self.cols {
-                let idx = self.index(col, row);
-                if idx < self.cells.len() {
-                    result.push(self.cells[idx].ch);
-                }
-            }
-            if row < self.rows - 1 {
+        for (row, line) in cells.chunks(self.cols.max(1) as usize).enumerate() {
+            if row > 0 {
                 result.push('\n');
             }
+            result.extend(line.iter().map(|cell| cell.ch).filter(|&ch| ch != WIDE_CONTINUATION));
         }
         result
     }
@@ -4,6 +4,9 @@
 //! - ANSI/VT100 escape sequence parsing
 //! - Terminal buffer management
 //! - Screen rendering
+//! - IME composition (preedit shown at the cursor)
+//! - Scroll regions and left/right margins (DECSTBM, DECSLRM)
+//! - Combining marks composed (NFC) and optional bidi run export
 //! - SSH key generation (future)
 
 use wasm_bindgen::prelude::*;
@@ -13,7 +16,7 @@ mod parser;
 mod buffer;
 
 pub use parser::AnsiParser;
-pub use buffer::TerminalBuffer;
+pub use buffer::{BidiRun, TerminalBuffer};
 
 /// Initialize the WASM module
 #[wasm_bindgen(start)]
@@ -36,6 +39,7 @@ pub fn version() -> String {
 pub struct Terminal {
     buffer: TerminalBuffer,
     parser: AnsiParser,
+    bidi: bool,
 }
 
 #[wasm_bindgen]
@@ -48,6 +52,7 @@ impl Terminal {
         Self {
             buffer: TerminalBuffer::new(cols, rows),
             parser: AnsiParser::new(),
+            bidi: false,
         }
     }
 
@@ -96,6 +101,25 @@ impl Terminal {
         self.buffer.get_lines_json()
     }
 
+    /// Turn bidi run export on or off; off by default, as many programs
+    /// already lay out right-to-left text themselves
+    pub fn set_bidi(&mut self, enabled: bool) {
+        self.bidi = enabled;
+    }
+
+    pub fn bidi(&self) -> bool {
+        self.bidi
+    }
+
+    /// Display-order runs for each visible line as JSON, `null` for lines
+    /// that need no reordering; `[]` when bidi is off
+    pub fn get_bidi_runs_json(&self) -> String {
+        if !self.bidi {
+            return "[]".to_string();
+        }
+        self.buffer.get_bidi_runs_json()
+    }
+
     /// Get entire screen as text
     pub fn get_screen_text(&self) -> String {
         self.buffer.get_screen_text()
@@ -111,6 +135,43 @@ impl Terminal {
         self.buffer.reset();
         self.parser.reset();
     }
+
+    /// IME composition began (`compositionstart`)
+    pub fn composition_start(&mut self) {
+        self.buffer.set_composition(Some(String::new()));
+    }
+
+    /// The preedit text changed (`compositionupdate`); it is shown
+    /// underlined at the cursor until committed
+    pub fn composition_update(&mut self, text: &str) {
+        self.buffer.set_composition(Some(text.to_string()));
+    }
+
+    /// Composition finished (`compositionend`); returns the text to send
+    /// to the PTY, whose echo then lands in the buffer as usual
+    pub fn composition_commit(&mut self, text: &str) -> String {
+        self.buffer.set_composition(None);
+        text.to_string()
+    }
+
+    /// Composition abandoned without input
+    pub fn composition_cancel(&mut self) {
+        self.buffer.set_composition(None);
+    }
+
+    pub fn is_composing(&self) -> bool {
+        self.buffer.composition().is_some()
+    }
+
+    /// Column just past the preedit text, for placing the candidate window
+    pub fn composition_end_col(&self) -> u16 {
+        self.buffer.composition_end().0
+    }
+
+    /// Row of the end of the preedit text
+    pub fn composition_end_row(&self) -> u16 {
+        self.buffer.composition_end().1
+    }
 }
 
 #[cfg(test)]
@@ -134,4 +195,96 @@ mod tests {
         let screen = term.get_screen_text();
         assert!(screen.contains("Hello, World!"));
     }
+
+    #[wasm_bindgen_test]
+    fn test_combining_marks_compose() {
+        let mut term = Terminal::new(10, 1);
+        term.write("cafe\u{301} n\u{303}").unwrap();
+        assert_eq!(term.get_screen_text().trim_end(), "café ñ");
+        assert_eq!(term.cursor_col(), 6);
+    }
+
+    #[wasm_bindgen_test]
+    fn test_bidi_runs() {
+        let mut term = Terminal::new(12, 2);
+        term.write("$ cat \u{5e9}\u{5dc}\u{5d5}\u{5dd}\r\nls").unwrap();
+        assert_eq!(term.get_bidi_runs_json(), "[]");
+
+        term.set_bidi(true);
+        let runs: Vec<Option<Vec<BidiRun>>> =
+            serde_json::from_str(&term.get_bidi_runs_json()).unwrap();
+        // The Hebrew word is one right-to-left run between the prompt and
+        // the trailing blanks; the second line needs nothing
+        assert_eq!(
+            runs[0].as_deref(),
+            Some(
+                &[
+                    BidiRun { start: 0, end: 6, rtl: false },
+                    BidiRun { start: 6, end: 10, rtl: true },
+                    BidiRun { start: 10, end: 12, rtl: false },
+                ][..]
+            )
+        );
+        assert_eq!(runs[1], None);
+    }
+
+    #[wasm_bindgen_test]
+    fn test_scroll_region_keeps_status_line() {
+        let mut term = Terminal::new(10, 4);
+        term.write("top\r\na\r\nb\r\nstatus").unwrap();
+
+        // Scroll rows 2-3 only, as a pager above a status line does
+        term.write("\x1b[2;3r\x1b[3;1H\nc").unwrap();
+        let screen = term.get_screen_text();
+        let lines: Vec<&str> = screen.lines().map(str::trim_end).collect();
+        assert_eq!(lines, ["top", "b", "c", "status"]);
+
+        // Reverse index at the top of the region scrolls it back down
+        term.write("\x1b[2;1H\x1bMz").unwrap();
+        let screen = term.get_screen_text();
+        let lines: Vec<&str> = screen.lines().map(str::trim_end).collect();
+        assert_eq!(lines, ["top", "z", "b", "status"]);
+    }
+
+    #[wasm_bindgen_test]
+    fn test_left_right_margins_scroll_one_pane() {
+        let mut term = Terminal::new(6, 3);
+        term.write("aa|xx\r\nbb|yy\r\ncc|zz").unwrap();
+
+        // Without DECLRMM, CSI s does not set margins
+        term.write("\x1b[4;5s").unwrap();
+        term.write("\x1b[?69h\x1b[4;5s\x1b[1;4H\x1b[M").unwrap();
+        let screen = term.get_screen_text();
+        let lines: Vec<&str> = screen.lines().map(str::trim_end).collect();
+        assert_eq!(lines, ["aa|yy", "bb|zz", "cc|"]);
+
+        // Origin mode addresses the cursor inside the margins
+        term.write("\x1b[?6h\x1b[3;1HQ").unwrap();
+        assert_eq!(term.cursor_row(), 2);
+        assert_eq!(term.cursor_col(), 4);
+    }
+
+    #[wasm_bindgen_test]
+    fn test_composition_overlays_cursor() {
+        let mut term = Terminal::new(8, 2);
+        term.write("$ ").unwrap();
+
+        term.composition_start();
+        term.composition_update("にほんご");
+        // Four double-width characters wrap after the first three
+        assert!(term.get_screen_text().starts_with("$ にほん"));
+        assert_eq!(term.composition_end_col(), 2);
+        assert_eq!(term.composition_end_row(), 1);
+        // Nothing is written to the buffer
+        assert_eq!(term.cursor_col(), 2);
+
+        assert_eq!(term.composition_commit("日本語"), "日本語");
+        assert!(!term.is_composing());
+        assert_eq!(term.get_screen_text().trim_end(), "$");
+
+        // The echo of the committed text takes two cells per character
+        term.write("日本語").unwrap();
+        assert_eq!(term.cursor_col(), 8);
+        assert!(term.get_screen_text().starts_with("$ 日本語"));
+    }
 }
@@ -1,44 +1,104 @@
 //! ANSI/VT100 escape sequence parser
 //!
-//! High-performance parser using the vte crate
+//! High-performance parser using the vte crate. Between escape sequences,
+//! runs of printable ASCII skip vte: they are found eight bytes at a time
+//! and copied into the buffer a row at a time, which is most of what large
+//! plain output (logs, `cat`) consists of.
 
 use vte::{Perform, Parser as VteParser};
 use crate::buffer::TerminalBuffer;
 
 pub struct AnsiParser {
     vte_parser: VteParser,
+    /// vte is between sequences, so plain text may bypass it
+    ground: bool,
 }
 
 impl AnsiParser {
     pub fn new() -> Self {
         Self {
             vte_parser: VteParser::new(),
+            ground: true,
         }
     }
 
     pub fn parse(&mut self, data: &str, buffer: &mut TerminalBuffer) {
-        let mut performer = BufferPerformer { buffer };
-        for byte in data.bytes() {
+        let bytes = data.as_bytes();
+        let mut performer = BufferPerformer {
+            buffer,
+            was_ground: false,
+            finished: false,
+        };
+        let mut i = 0;
+        while i < bytes.len() {
+            if self.ground {
+                let run = plain_run(&bytes[i..]);
+                if run > 0 {
+                    performer.buffer.put_ascii(&bytes[i..i + run]);
+                    i += run;
+                    continue;
+                }
+            }
+
+            let byte = bytes[i];
+            performer.was_ground = self.ground;
+            performer.finished = false;
             self.vte_parser.advance(&mut performer, byte);
+            // A string terminated by ESC leaves vte in an escape sequence
+            self.ground = performer.finished && byte != 0x1b;
+            i += 1;
         }
     }
 
     pub fn reset(&mut self) {
         self.vte_parser = VteParser::new();
+        self.ground = true;
+    }
+}
+
+/// Length of the run of printable ASCII (0x20-0x7e) that `bytes` starts
+/// with, checked a word at a time
+fn plain_run(bytes: &[u8]) -> usize {
+    const ONES: u64 = 0x0101_0101_0101_0101;
+    const HIGH: u64 = 0x8080_8080_8080_8080;
+
+    let mut run = 0;
+    for chunk in bytes.chunks_exact(8) {
+        let word = u64::from_le_bytes(chunk.try_into().unwrap());
+        // High bit set in each byte below 0x20, equal to 0x7f, or non-ASCII
+        let below_space = word.wrapping_sub(ONES * 0x20) & !word;
+        let xor_del = word ^ (ONES * 0x7f);
+        let del = xor_del.wrapping_sub(ONES) & !xor_del;
+        if (below_space | del | word) & HIGH != 0 {
+            break;
+        }
+        run += 8;
     }
+    run + bytes[run..]
+        .iter()
+        .take_while(|byte| (0x20..0x7f).contains(*byte))
+        .count()
 }
 
 /// Performer that writes to TerminalBuffer
 struct BufferPerformer<'a> {
     buffer: &'a mut TerminalBuffer,
+    /// vte was in the ground state before the current byte
+    was_ground: bool,
+    /// The current byte completed whatever it was part of
+    finished: bool,
 }
 
 impl<'a> Perform for BufferPerformer<'a> {
     fn print(&mut self, c: char) {
+        self.finished = true;
         self.buffer.put_char(c);
     }
 
     fn execute(&mut self, byte: u8) {
+        // Controls inside a sequence leave it unfinished, except CAN and
+        // SUB, which cancel it
+        self.finished = self.was_ground || matches!(byte, 0x18 | 0x1a);
         match byte {
             b'\n' => self.buffer.newline(),
             b'\r' => self.buffer.carriage_return(),
@@ -59,19 +119,30 @@ impl<'a> Perform for BufferPerformer<'a> {
 
     fn unhook(&mut self) {
         // DCS end - not implemented yet
+        self.finished = true;
     }
 
     fn osc_dispatch(&mut self, _params: &[&[u8]], _bell_terminated: bool) {
         // OSC sequences - not implemented yet
+        self.finished = true;
     }
 
     fn csi_dispatch(
         &mut self,
         params: &vte::Params,
-        _intermediates: &[u8],
+        intermediates: &[u8],
         _ignore: bool,
         action: char,
     ) {
+        self.finished = true;
+        if intermediates == b"?" {
+            self.private_mode(params, action);
+            return;
+        }
+        if !intermediates.is_empty() {
+            return;
+        }
+
         match action {
             'A' => {
                 // Cursor up
@@ -93,6 +164,21 @@ impl<'a> Perform for BufferPerformer<'a> {
                 let n = params.iter().next().map(|p| p[0]).unwrap_or(1) as u16;
                 self.buffer.cursor_backward(n);
             }
+            'G' | '`' => {
+                // Cursor to column
+                let n = params.iter().next().map(|p| p[0]).unwrap_or(1).max(1);
+                self.buffer.cursor_to_col(n - 1);
+            }
+            'd' => {
+                // Cursor to row
+                let n = params.iter().next().map(|p| p[0]).unwrap_or(1).max(1);
+                self.buffer.cursor_to_row(n - 1);
+            }
+            'X' => {
+                // Erase characters
+                let n = params.iter().next().map(|p| p[0]).unwrap_or(1).max(1);
+                self.buffer.erase_chars(n);
+            }
             'H' | 'f' => {
                 // Cursor position
                 let mut iter = params.iter();
@@ -120,6 +206,48 @@ impl<'a> Perform for BufferPerformer<'a> {
                     _ => {},
                 }
             }
+            'r' => {
+                // Set top and bottom margins (DECSTBM)
+                let mut iter = params.iter();
+                let top = iter.next().map(|p| p[0]).filter(|&n| n > 0).unwrap_or(1);
+                let bottom = iter
+                    .next()
+                    .map(|p| p[0])
+                    .filter(|&n| n > 0)
+                    .unwrap_or(self.buffer.rows());
+                self.buffer.set_scroll_region(top - 1, bottom - 1);
+            }
+            's' if self.buffer.lr_margin_mode() => {
+                // Set left and right margins (DECSLRM)
+                let mut iter = params.iter();
+                let left = iter.next().map(|p| p[0]).filter(|&n| n > 0).unwrap_or(1);
+                let right = iter
+                    .next()
+                    .map(|p| p[0])
+                    .filter(|&n| n > 0)
+                    .unwrap_or(self.buffer.cols());
+                self.buffer.set_lr_margins(left - 1, right - 1);
+            }
+            'S' => {
+                // Scroll up
+                let n = params.iter().next().map(|p| p[0]).unwrap_or(1).max(1);
+                self.buffer.scroll_up(n);
+            }
+            'T' => {
+                // Scroll down
+                let n = params.iter().next().map(|p| p[0]).unwrap_or(1).max(1);
+                self.buffer.scroll_down(n);
+            }
+            'L' => {
+                // Insert lines
+                let n = params.iter().next().map(|p| p[0]).unwrap_or(1).max(1);
+                self.buffer.insert_lines(n);
+            }
+            'M' => {
+                // Delete lines
+                let n = params.iter().next().map(|p| p[0]).unwrap_or(1).max(1);
+                self.buffer.delete_lines(n);
+            }
             'm' => {
                 // SGR - Select Graphic Rendition (colors, styles)
                 if params.is_empty() {
@@ -154,7 +282,34 @@ impl<'a> Perform for BufferPerformer<'a> {
         }
     }
 
-    fn esc_dispatch(&mut self, _intermediates: &[u8], _ignore: bool, _byte: u8) {
-        // ESC sequences - not implemented yet
+    fn esc_dispatch(&mut self, intermediates: &[u8], _ignore: bool, byte: u8) {
+        self.finished = true;
+        if !intermediates.is_empty() {
+            return;
+        }
+        match byte {
+            b'D' => self.buffer.line_feed(),         // Index
+            b'E' => self.buffer.newline(),           // Next line
+            b'M' => self.buffer.reverse_line_feed(), // Reverse index
+            _ => {}
+        }
+    }
+}
+
+impl<'a> BufferPerformer<'a> {
+    /// DEC private modes (`CSI ? n h` / `CSI ? n l`)
+    fn private_mode(&mut self, params: &vte::Params, action: char) {
+        let enabled = match action {
+            'h' => true,
+            'l' => false,
+            _ => return,
+        };
+        for param in params.iter() {
+            match param[0] {
+                6 => self.buffer.set_origin_mode(enabled),
+                69 => self.buffer.set_lr_margin_mode(enabled),
+                _ => {}
+            }
+        }
     }
 }
@@ -0,0 +1,128 @@
+//! Conformance corpus: recorded output of real programs, rendered and
+//! compared against golden snapshots
+//!
+//! Each `corpus/<name>.ansi` is raw PTY output captured at 80x24 with
+//! `script` (tmux with three panes, `npm install` failing offline, vim
+//! showing a Rust file with syntax highlighting), cut before the program
+//! restored the screen on exit. `corpus/<name>.snap` holds the screen text,
+//! cursor and styled cells it should render to.
+//!
+//! After an intended rendering change, rewrite the snapshots with
+//! `UPDATE_SNAPSHOTS=1 cargo test --test corpus` and review the diff.
+
+#![cfg(not(target_arch = "wasm32"))]
+
+use serde::Deserialize;
+use std::fmt::Write;
+use std::path::Path;
+use terminal_wasm::{AnsiParser, TerminalBuffer};
+
+const COLS: u16 = 80;
+const ROWS: u16 = 24;
+
+/// A cell as exported by `get_lines_json`
+#[derive(Deserialize, PartialEq)]
+struct Cell {
+    ch: char,
+    fg: u8,
+    bg: u8,
+    bold: bool,
+    dim: bool,
+    italic: bool,
+    underline: bool,
+    inverse: bool,
+}
+
+impl Cell {
+    fn attributes(&self) -> String {
+        let mut out = format!("fg={} bg={}", self.fg, self.bg);
+        for (set, name) in [
+            (self.bold, "bold"),
+            (self.dim, "dim"),
+            (self.italic, "italic"),
+            (self.underline, "underline"),
+            (self.inverse, "inverse"),
+        ] {
+            if set {
+                out.push(' ');
+                out.push_str(name);
+            }
+        }
+        out
+    }
+}
+
+/// Screen text, cursor, and every run of cells not in the default style
+fn snapshot(buffer: &TerminalBuffer) -> String {
+    let mut out = String::from("--- screen ---\n");
+    for line in buffer.get_screen_text().lines() {
+        out.push_str(line.trim_end());
+        out.push('\n');
+    }
+    writeln!(out, "--- cursor ---\n{},{}", buffer.cursor_row(), buffer.cursor_col()).unwrap();
+
+    out.push_str("--- styles ---\n");
+    let lines: Vec<Vec<Cell>> = serde_json::from_str(&buffer.get_lines_json()).unwrap();
+    let default = Cell {
+        ch: ' ',
+        fg: 7,
+        bg: 0,
+        bold: false,
+        dim: false,
+        italic: false,
+        underline: false,
+        inverse: false,
+    };
+    for (row, line) in lines.iter().enumerate() {
+        let mut col = 0;
+        while col < line.len() {
+            let style = line[col].attributes();
+            let start = col;
+            while col < line.len() && line[col].attributes() == style {
+                col += 1;
+            }
+            if style != default.attributes() {
+                writeln!(out, "{} {}..{} {}", row, start, col, style).unwrap();
+            }
+        }
+    }
+    out
+}
+
+fn render(data: &[u8]) -> TerminalBuffer {
+    let mut buffer = TerminalBuffer::new(COLS, ROWS);
+    let mut parser = AnsiParser::new();
+    parser.parse(&String::from_utf8_lossy(data), &mut buffer);
+    buffer
+}
+
+#[test]
+fn test_corpus_matches_snapshots() {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/corpus");
+    let update = std::env::var_os("UPDATE_SNAPSHOTS").is_some();
+
+    let mut inputs: Vec<_> = std::fs::read_dir(&dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "ansi"))
+        .collect();
+    inputs.sort();
+    assert!(!inputs.is_empty(), "No corpus in {}", dir.display());
+
+    let mut mismatched = Vec::new();
+    for input in inputs {
+        let actual = snapshot(&render(&std::fs::read(&input).unwrap()));
+        let golden = input.with_extension("snap");
+        if update {
+            std::fs::write(&golden, &actual).unwrap();
+            continue;
+        }
+        let expected = std::fs::read_to_string(&golden)
+            .unwrap_or_else(|_| panic!("Missing snapshot {}", golden.display()));
+        if actual != expected {
+            eprintln!("--- {} rendered as:\n{}", input.display(), actual);
+            mismatched.push(input);
+        }
+    }
+    assert!(mismatched.is_empty(), "Rendering changed for {:?}", mismatched);
+}
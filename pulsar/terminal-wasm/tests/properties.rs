@@ -0,0 +1,114 @@
+//! Property tests for the parser and screen buffer
+//!
+//! Streams mix printable text (wide characters and combining marks
+//! included), C0 controls, and CSI, OSC and ESC sequences with arbitrary
+//! parameters, so every code path sees inputs no program would send.
+
+#![cfg(not(target_arch = "wasm32"))]
+
+use proptest::prelude::*;
+use terminal_wasm::{AnsiParser, TerminalBuffer};
+
+/// One piece of terminal output
+fn token() -> impl Strategy<Value = String> {
+    prop_oneof![
+        4 => "[a-zA-Z0-9 .,:;/|_-]{1,20}",
+        1 => prop::sample::select(vec!["日本", "é", "e\u{301}", "\u{5e9}\u{5dc}", "🦀", "\u{200b}"])
+            .prop_map(str::to_string),
+        2 => prop::sample::select(vec!["\r", "\n", "\r\n", "\t", "\x08", "\x07", "\x00"])
+            .prop_map(str::to_string),
+        3 => (
+            prop::sample::select(vec!["", "?", ">", "!"]),
+            prop::collection::vec(0u16..300, 0..5),
+            prop::sample::select("@ABCDEFGHJKLMPSTXdfhlmnrsu`".chars().collect::<Vec<_>>()),
+        )
+            .prop_map(|(prefix, params, action)| {
+                let params: Vec<String> = params.iter().map(u16::to_string).collect();
+                format!("\x1b[{}{}{}", prefix, params.join(";"), action)
+            }),
+        1 => "[ -~]{0,30}".prop_map(|text| format!("\x1b]0;{}\x07", text)),
+        1 => prop::sample::select(vec!["\x1bD", "\x1bE", "\x1bM", "\x1b7", "\x1b8", "\x1bc", "\x1b(B"])
+            .prop_map(str::to_string),
+    ]
+}
+
+fn stream() -> impl Strategy<Value = String> {
+    prop::collection::vec(token(), 0..200).prop_map(|tokens| tokens.concat())
+}
+
+fn render(cols: u16, rows: u16, chunks: &[&str]) -> TerminalBuffer {
+    let mut buffer = TerminalBuffer::new(cols, rows);
+    let mut parser = AnsiParser::new();
+    for chunk in chunks {
+        parser.parse(chunk, &mut buffer);
+    }
+    buffer
+}
+
+proptest! {
+    /// The column may be one past the edge after writing the last cell,
+    /// until the next character wraps
+    #[test]
+    fn test_cursor_stays_on_screen(cols in 1u16..120, rows in 1u16..50, data in stream()) {
+        let buffer = render(cols, rows, &[&data]);
+        prop_assert!(buffer.cursor_col() <= cols);
+        prop_assert!(buffer.cursor_row() < rows);
+    }
+
+    #[test]
+    fn test_export_has_screen_shape(cols in 1u16..120, rows in 1u16..50, data in stream()) {
+        let buffer = render(cols, rows, &[&data]);
+        let lines: Vec<Vec<serde_json::Value>> =
+            serde_json::from_str(&buffer.get_lines_json()).unwrap();
+        prop_assert_eq!(lines.len(), rows as usize);
+        prop_assert!(lines.iter().all(|line| line.len() == cols as usize));
+        prop_assert_eq!(buffer.get_screen_text().split('\n').count(), rows as usize);
+    }
+
+    /// Output split across reads renders as if it arrived at once
+    #[test]
+    fn test_split_reads_render_the_same(data in stream(), split in any::<prop::sample::Index>()) {
+        let boundaries: Vec<usize> = (0..=data.len()).filter(|&i| data.is_char_boundary(i)).collect();
+        let at = boundaries[split.index(boundaries.len())];
+        let (head, tail) = data.split_at(at);
+
+        let whole = render(80, 24, &[&data]);
+        let split = render(80, 24, &[head, tail]);
+        prop_assert_eq!(whole.get_lines_json(), split.get_lines_json());
+        prop_assert_eq!(
+            (whole.cursor_col(), whole.cursor_row()),
+            (split.cursor_col(), split.cursor_row())
+        );
+    }
+
+    /// Plain text copied a run at a time renders as if written a character
+    /// at a time
+    #[test]
+    fn test_runs_render_like_characters(cols in 1u16..120, rows in 1u16..50, data in stream()) {
+        let chars: Vec<String> = data.chars().map(String::from).collect();
+        let chars: Vec<&str> = chars.iter().map(String::as_str).collect();
+
+        let whole = render(cols, rows, &[&data]);
+        let one_by_one = render(cols, rows, &chars);
+        prop_assert_eq!(whole.get_lines_json(), one_by_one.get_lines_json());
+        prop_assert_eq!(
+            (whole.cursor_col(), whole.cursor_row()),
+            (one_by_one.cursor_col(), one_by_one.cursor_row())
+        );
+    }
+
+    #[test]
+    fn test_resize_between_writes(
+        first in stream(),
+        second in stream(),
+        size in (1u16..120, 1u16..50),
+    ) {
+        let mut buffer = TerminalBuffer::new(80, 24);
+        let mut parser = AnsiParser::new();
+        parser.parse(&first, &mut buffer);
+        buffer.resize(size.0, size.1);
+        parser.parse(&second, &mut buffer);
+        prop_assert!(buffer.cursor_col() <= size.0);
+        prop_assert!(buffer.cursor_row() < size.1);
+    }
+}
@@ -0,0 +1,982 @@
+//! High-level transfers over a TFT transport
+//!
+//! Sending a file runs the protocol in order:
+//! 1. `TransferInit` announces the file's size and Merkle root
+//! 2. The receiver's `TransferResponse` settles the framing and chunk
+//!    compression for the rest of the transfer and may skip chunks it
+//!    already has
+//! 3. Chunks go one at a time, each answered by a `ChunkAck`; a chunk
+//!    that arrives damaged is sent again. Chunks go compressed when that
+//!    shrinks them, unless the file's first chunk shows it doesn't compress
+//! 4. `TransferComplete`, which the receiver echoes once the file is
+//!    verified and in place
+//!
+//! A receiver that already holds an identical file answers the init with
+//! `resume_from_chunk` at the chunk count, so nothing is sent again; that
+//! is what makes [`Client::sync_dir`] cheap to repeat.
+//!
+//! A directory goes as a `Manifest` of the whole tree first, from which the
+//! receiver creates its directories and symlinks, then one transfer per
+//! file. Modes and mtimes from the manifest are applied once every file is
+//! in place.
+//!
+//! Received files are written beside their target and renamed over it
+//! only once verified, so a failed or cancelled transfer leaves the old
+//! file as it was.
+
+use crate::error::{ClientError, Result};
+use chrono::Utc;
+use pulsar_fs::AtomicFile;
+use std::collections::HashSet;
+use std::future::Future;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+use tft_core::codec::{decode_binary, encode_frame, MAX_CHUNK_SIZE};
+use tft_core::protocol::{
+    ChunkAck, ChunkMessage, CompressedChunk, CompressionType, ErrorMessage, TransferComplete,
+    TransferInit, TransferResponse,
+};
+use tft_core::{
+    decode_message, hash_file, tree, ChunkCompressor, ChunkInfo, FileChunker, Framing, MerkleTree,
+    Message, DEFAULT_CHUNK_SIZE, PROTOCOL_VERSION,
+};
+use tft_transports::{Transport, TransportError};
+use tokio::task::{spawn_blocking, JoinError};
+use tokio_util::sync::CancellationToken;
+use tracing::debug;
+use uuid::Uuid;
+
+/// Times a chunk is sent before the transfer gives up on it
+const MAX_CHUNK_ATTEMPTS: usize = 3;
+
+/// How long telling the peer about a failure may take
+const ABORT_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// How far a file has got
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Progress {
+    /// Local path of the file
+    pub path: PathBuf,
+    pub bytes: u64,
+    pub total: u64,
+}
+
+/// A file that was sent or received
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Transferred {
+    /// Local path of the file
+    pub path: PathBuf,
+    pub bytes: u64,
+    /// The receiver already had the file, so nothing was sent
+    pub skipped: bool,
+}
+
+type ProgressCallback = Arc<dyn Fn(&Progress) + Send + Sync>;
+
+/// One end of a TFT session over a connected transport
+///
+/// Each call runs to the end of its transfer before returning, so calls
+/// on one client never interleave on the wire.
+pub struct Client<T> {
+    transport: T,
+    chunk_size: usize,
+    progress: Option<ProgressCallback>,
+    cancel: CancellationToken,
+    /// Transfer in progress, named in errors sent to the peer
+    transfer_id: Option<Uuid>,
+    /// Framing of the transfer in progress
+    framing: Framing,
+    /// Keep modes and mtimes in directory transfers
+    preserve_metadata: bool,
+    /// Chunk compressions to use, in preference order
+    compressions: Vec<CompressionType>,
+    /// Level to ask for when sending
+    compression_level: Option<i32>,
+}
+
+impl<T: Transport> Client<T> {
+    pub fn new(transport: T) -> Self {
+        Self {
+            transport,
+            chunk_size: DEFAULT_CHUNK_SIZE,
+            progress: None,
+            cancel: CancellationToken::new(),
+            transfer_id: None,
+            framing: Framing::Ndjson,
+            preserve_metadata: true,
+            compressions: CompressionType::SUPPORTED.to_vec(),
+            compression_level: None,
+        }
+    }
+
+    /// Chunk size for files this side sends, at most [`MAX_CHUNK_SIZE`]
+    pub fn with_chunk_size(mut self, chunk_size: usize) -> Self {
+        self.chunk_size = chunk_size.clamp(1, MAX_CHUNK_SIZE);
+        self
+    }
+
+    /// Call `progress` after every chunk sent or received
+    pub fn with_progress(mut self, progress: impl Fn(&Progress) + Send + Sync + 'static) -> Self {
+        self.progress = Some(Arc::new(progress));
+        self
+    }
+
+    /// Whether directory transfers keep file modes and mtimes, which they
+    /// do by default
+    ///
+    /// A sender without it leaves them out of the manifest; a receiver
+    /// without it ignores them.
+    pub fn with_metadata(mut self, preserve: bool) -> Self {
+        self.preserve_metadata = preserve;
+        self
+    }
+
+    /// Chunk compressions to offer as sender and accept as receiver, in
+    /// preference order, and the level to ask for; all that are supported,
+    /// at their default level, unless set
+    ///
+    /// An empty list sends and receives chunks as they are.
+    pub fn with_compression(
+        mut self,
+        compressions: &[CompressionType],
+        level: Option<i32>,
+    ) -> Self {
+        self.compressions = compressions
+            .iter()
+            .copied()
+            .filter(|c| CompressionType::SUPPORTED.contains(c))
+            .collect();
+        self.compression_level = level;
+        self
+    }
+
+    /// Stop the call in progress once `cancel` fires; the peer is told the
+    /// transfer was cancelled
+    pub fn with_cancellation(mut self, cancel: CancellationToken) -> Self {
+        self.cancel = cancel;
+        self
+    }
+
+    pub fn transport(&mut self) -> &mut T {
+        &mut self.transport
+    }
+
+    pub fn into_inner(self) -> T {
+        self.transport
+    }
+
+    /// Send the file at `path` to the peer
+    pub async fn send_file(&mut self, path: impl AsRef<Path>) -> Result<Transferred> {
+        let path = path.as_ref();
+        let result = match path.file_name().and_then(|name| name.to_str()) {
+            Some(filename) => self.send(path, filename.to_string(), None).await,
+            None => Err(ClientError::Io(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("{:?} has no UTF-8 file name", path),
+            ))),
+        };
+        self.finish(result).await
+    }
+
+    /// Receive one file into `dir`, replacing any file of the same name
+    pub async fn receive_file(&mut self, dir: impl AsRef<Path>) -> Result<Transferred> {
+        let result = match self.receive(dir.as_ref()).await {
+            Ok(Some(file)) => Ok(file),
+            Ok(None) => Err(ClientError::Transport(TransportError::Closed(
+                "peer hung up before sending a file".to_string(),
+            ))),
+            Err(e) => Err(e),
+        };
+        self.finish(result).await
+    }
+
+    /// Send the tree under `dir`, then disconnect
+    ///
+    /// Directories, symlinks that stay inside the tree and (unless turned
+    /// off with [`with_metadata`](Self::with_metadata)) modes and mtimes go
+    /// in the manifest; files the peer already has are skipped.
+    /// Disconnecting is how [`receive_dir`](Self::receive_dir) on the other
+    /// side knows the sync is complete.
+    pub async fn sync_dir(&mut self, dir: impl AsRef<Path>) -> Result<Vec<Transferred>> {
+        let root = dir.as_ref().to_path_buf();
+        let result = async {
+            let manifest = {
+                let (root, preserve) = (root.clone(), self.preserve_metadata);
+                self.cancellable(spawn_blocking(move || tree::scan(&root, preserve)))
+                    .await?
+                    .map_err(join_error)??
+            };
+            self.begin(None);
+            self.send_message(&Message::Manifest(manifest.clone()))
+                .await?;
+
+            let mut sent = Vec::new();
+            for entry in manifest.files() {
+                let path = tree::local_path(&root, &entry.path);
+                let directory = entry.parent().map(str::to_string);
+                sent.push(
+                    self.send(&path, entry.name().to_string(), directory)
+                        .await?,
+                );
+            }
+            self.transport.disconnect().await?;
+            Ok(sent)
+        }
+        .await;
+        self.finish(result).await
+    }
+
+    /// Receive a tree into `dir`, laid out as the sender has it, until the
+    /// peer disconnects
+    ///
+    /// Once a manifest has arrived, only the files it lists are accepted.
+    pub async fn receive_dir(&mut self, dir: impl AsRef<Path>) -> Result<Vec<Transferred>> {
+        let dir = dir.as_ref();
+        let result = async {
+            let mut received = Vec::new();
+            let mut manifest = None;
+            let mut expected = HashSet::new();
+            loop {
+                self.begin(None);
+                let init = match self.receive_message().await {
+                    Ok(Message::Manifest(listed)) if manifest.is_none() => {
+                        expected = listed.files().map(|file| file.path.clone()).collect();
+                        let root = dir.to_path_buf();
+                        manifest = Some(
+                            self.cancellable(spawn_blocking(move || {
+                                tree::create_entries(&root, &listed).map(|()| listed)
+                            }))
+                            .await?
+                            .map_err(join_error)??,
+                        );
+                        continue;
+                    }
+                    Ok(Message::TransferInit(init)) => init,
+                    Ok(other) => return Err(unexpected("a transfer init", &other)),
+                    Err(ClientError::Transport(TransportError::Closed(_))) => break,
+                    Err(e) => return Err(e),
+                };
+
+                if manifest.is_some() {
+                    let path = match &init.directory {
+                        Some(directory) => format!("{}/{}", directory, init.filename),
+                        None => init.filename.clone(),
+                    };
+                    if !expected.remove(&path) {
+                        return Err(ClientError::Protocol(format!(
+                            "{} is not a file of the manifest",
+                            path
+                        )));
+                    }
+                }
+                received.push(self.receive_init(dir, init).await?);
+            }
+
+            if let Some(manifest) = manifest.filter(|_| self.preserve_metadata) {
+                let root = dir.to_path_buf();
+                spawn_blocking(move || tree::apply_metadata(&root, &manifest))
+                    .await
+                    .map_err(join_error)??;
+            }
+            Ok(received)
+        }
+        .await;
+        self.finish(result).await
+    }
+
+    async fn send(
+        &mut self,
+        path: &Path,
+        filename: String,
+        directory: Option<String>,
+    ) -> Result<Transferred> {
+        self.begin(None);
+        let chunk_size = self.chunk_size;
+        let hashes = {
+            let path = path.to_path_buf();
+            self.cancellable(spawn_blocking(move || hash_file(&path, chunk_size)))
+                .await?
+                .map_err(join_error)??
+        };
+        let size = hashes.size;
+        let total_chunks = hashes.chunk_hashes.len();
+
+        let transfer_id = Uuid::new_v4();
+        self.begin(Some(transfer_id));
+        self.send_message(&Message::TransferInit(TransferInit {
+            version: PROTOCOL_VERSION.to_string(),
+            transfer_id,
+            filename: filename.clone(),
+            size,
+            chunk_size,
+            total_chunks,
+            merkle_root: hashes.merkle_root(),
+            encrypted: false,
+            compression: CompressionType::None,
+            framings: Framing::SUPPORTED.to_vec(),
+            compressions: self.compressions.clone(),
+            compression_level: self.compression_level,
+            directory,
+        }))
+        .await?;
+
+        let response = match self.receive_message().await? {
+            Message::TransferResponse(response) if response.transfer_id == transfer_id => response,
+            other => return Err(unexpected("a transfer response", &other)),
+        };
+        if !response.accepted {
+            return Err(ClientError::Rejected(filename));
+        }
+        if !Framing::SUPPORTED.contains(&response.framing) {
+            return Err(ClientError::Protocol(format!(
+                "peer chose unsupported framing {:?}",
+                response.framing
+            )));
+        }
+        self.framing = response.framing;
+        if response.compression != CompressionType::None
+            && !self.compressions.contains(&response.compression)
+        {
+            return Err(ClientError::Protocol(format!(
+                "peer chose compression {:?}, which was not offered",
+                response.compression
+            )));
+        }
+        let mut compressor = ChunkCompressor::new(response.compression, response.compression_level);
+
+        let mut progress = Progress {
+            path: path.to_path_buf(),
+            bytes: 0,
+            total: size,
+        };
+        let first = response.resume_from_chunk.unwrap_or(0);
+        if response.resume_from_chunk == Some(total_chunks) {
+            debug!("{:?} is already up to date on the peer", path);
+            progress.bytes = size;
+            self.report(&progress);
+            return Ok(Transferred {
+                path: path.to_path_buf(),
+                bytes: size,
+                skipped: true,
+            });
+        }
+        if first > total_chunks {
+            return Err(ClientError::Protocol(format!(
+                "peer asked to resume from chunk {} of {}",
+                first, total_chunks
+            )));
+        }
+
+        let file = Arc::new(FileChunker::new(chunk_size).open(path)?);
+        if compressor.is_enabled() && first < total_chunks {
+            let file = file.clone();
+            let incompressible = self
+                .cancellable(spawn_blocking(move || {
+                    compressor.is_incompressible(&file.chunk(first)?)
+                }))
+                .await?
+                .map_err(join_error)??;
+            if incompressible {
+                debug!("{:?} doesn't compress, sending it as it is", path);
+                compressor = ChunkCompressor::default();
+            }
+        }
+
+        progress.bytes = (first as u64 * chunk_size as u64).min(size);
+        for index in first..total_chunks {
+            let (data, compressed) = {
+                let file = file.clone();
+                self.cancellable(spawn_blocking(move || -> io::Result<_> {
+                    let data = file.chunk(index)?.into_owned();
+                    let compressed = compressor.compress(&data)?;
+                    Ok((data, compressed))
+                }))
+                .await?
+                .map_err(join_error)??
+            };
+            let hash = ChunkInfo::compute_hash(&data);
+            if hash != hashes.chunk_hashes[index] {
+                return Err(ClientError::Integrity(format!(
+                    "{:?} changed while it was being sent",
+                    path
+                )));
+            }
+
+            let len = data.len() as u64;
+            let message = match compressed {
+                Some(compressed) => Message::CompressedChunk(CompressedChunk {
+                    transfer_id,
+                    chunk_index: index,
+                    size: data.len(),
+                    data: compressed,
+                    hash,
+                }),
+                None => Message::Chunk(ChunkMessage {
+                    transfer_id,
+                    chunk_index: index,
+                    data,
+                    hash,
+                }),
+            };
+            self.send_chunk(transfer_id, index, message).await?;
+            progress.bytes += len;
+            self.report(&progress);
+        }
+
+        self.send_message(&Message::TransferComplete(TransferComplete {
+            transfer_id,
+            timestamp: Utc::now(),
+            total_bytes: size,
+        }))
+        .await?;
+        match self.receive_message().await? {
+            Message::TransferComplete(complete) if complete.transfer_id == transfer_id => {}
+            other => return Err(unexpected("the transfer to complete", &other)),
+        }
+        Ok(Transferred {
+            path: path.to_path_buf(),
+            bytes: size,
+            skipped: false,
+        })
+    }
+
+    /// Send a chunk until the peer acknowledges it intact
+    async fn send_chunk(
+        &mut self,
+        transfer_id: Uuid,
+        index: usize,
+        message: Message,
+    ) -> Result<()> {
+        for _ in 0..MAX_CHUNK_ATTEMPTS {
+            self.send_message(&message).await?;
+            match self.receive_message().await? {
+                Message::ChunkAck(ack)
+                    if ack.transfer_id == transfer_id && ack.chunk_index == index =>
+                {
+                    if ack.success {
+                        return Ok(());
+                    }
+                    debug!("Peer rejected chunk {}, sending it again", index);
+                }
+                other => return Err(unexpected("a chunk acknowledgement", &other)),
+            }
+        }
+        Err(ClientError::Integrity(format!(
+            "chunk {} arrived damaged {} times",
+            index, MAX_CHUNK_ATTEMPTS
+        )))
+    }
+
+    /// Receive the next file into `dir`, or `None` if the peer hangs up
+    /// first
+    async fn receive(&mut self, dir: &Path) -> Result<Option<Transferred>> {
+        self.begin(None);
+        let init = match self.receive_message().await {
+            Ok(Message::TransferInit(init)) => init,
+            Ok(other) => return Err(unexpected("a transfer init", &other)),
+            Err(ClientError::Transport(TransportError::Closed(_))) => return Ok(None),
+            Err(e) => return Err(e),
+        };
+        self.receive_init(dir, init).await.map(Some)
+    }
+
+    /// Receive the file `init` announces into `dir`
+    async fn receive_init(&mut self, dir: &Path, init: TransferInit) -> Result<Transferred> {
+        let transfer_id = init.transfer_id;
+        self.begin(Some(transfer_id));
+
+        if init.encrypted || init.compression != CompressionType::None {
+            self.respond(
+                transfer_id,
+                false,
+                None,
+                Framing::Ndjson,
+                ChunkCompressor::default(),
+            )
+            .await?;
+            return Err(ClientError::Protocol(format!(
+                "{} is encrypted or compressed, which this client does not support",
+                init.filename
+            )));
+        }
+
+        // The codec has checked the directory is relative and stays inside
+        let mut target = dir.to_path_buf();
+        if let Some(directory) = &init.directory {
+            target.extend(directory.split('/'));
+        }
+        target.push(&init.filename);
+        let mut progress = Progress {
+            path: target.clone(),
+            bytes: 0,
+            total: init.size,
+        };
+
+        let identical = {
+            let (target, chunk_size, merkle_root) =
+                (target.clone(), init.chunk_size, init.merkle_root.clone());
+            self.cancellable(spawn_blocking(move || {
+                is_identical(&target, init.size, chunk_size, &merkle_root)
+            }))
+            .await?
+            .map_err(join_error)??
+        };
+        if identical {
+            debug!("{:?} is already up to date", target);
+            let compressor = ChunkCompressor::default();
+            self.respond(
+                transfer_id,
+                true,
+                Some(init.total_chunks),
+                Framing::Ndjson,
+                compressor,
+            )
+            .await?;
+            progress.bytes = init.size;
+            self.report(&progress);
+            return Ok(Transferred {
+                path: target,
+                bytes: init.size,
+                skipped: true,
+            });
+        }
+
+        if let Some(parent) = target.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        let mut file = AtomicFile::create(&target)?;
+        let framing = Framing::negotiate(&init.framings);
+        let compressor = ChunkCompressor::new(
+            CompressionType::negotiate(&init.compressions, &self.compressions),
+            init.compression_level,
+        );
+        self.respond(transfer_id, true, None, framing, compressor)
+            .await?;
+        self.framing = framing;
+
+        let mut chunk_hashes = Vec::with_capacity(init.total_chunks);
+        loop {
+            let (index, len, data, compressed, hash) = match self.receive_message().await? {
+                Message::Chunk(chunk) if chunk.transfer_id == transfer_id => {
+                    let len = chunk.data.len();
+                    (chunk.chunk_index, len, chunk.data, false, chunk.hash)
+                }
+                Message::CompressedChunk(chunk)
+                    if chunk.transfer_id == transfer_id && compressor.is_enabled() =>
+                {
+                    (chunk.chunk_index, chunk.size, chunk.data, true, chunk.hash)
+                }
+                Message::TransferComplete(complete) if complete.transfer_id == transfer_id => {
+                    let root = MerkleTree::new(chunk_hashes).root().to_string();
+                    if complete.total_bytes != init.size
+                        || progress.bytes != init.size
+                        || root != init.merkle_root
+                    {
+                        return Err(ClientError::Integrity(format!(
+                            "{} does not match what the peer announced",
+                            init.filename
+                        )));
+                    }
+
+                    spawn_blocking(move || file.commit())
+                        .await
+                        .map_err(join_error)??;
+                    self.send_message(&Message::TransferComplete(TransferComplete {
+                        transfer_id,
+                        timestamp: Utc::now(),
+                        total_bytes: init.size,
+                    }))
+                    .await?;
+                    return Ok(Transferred {
+                        path: target,
+                        bytes: init.size,
+                        skipped: false,
+                    });
+                }
+                other => return Err(unexpected("a chunk", &other)),
+            };
+
+            if index != chunk_hashes.len() {
+                return Err(ClientError::Protocol(format!(
+                    "expected chunk {}, got {}",
+                    chunk_hashes.len(),
+                    index
+                )));
+            }
+            // Checked before decompressing, so a chunk can't inflate past it
+            let len = len as u64;
+            if index >= init.total_chunks
+                || len > init.chunk_size as u64
+                || progress.bytes + len > init.size
+            {
+                return Err(ClientError::Protocol(format!(
+                    "chunk {} does not fit the announced file",
+                    index
+                )));
+            }
+
+            let data = if compressed {
+                compressor.decompress(&data, len as usize)
+            } else {
+                Ok(data)
+            };
+            let data = data
+                .inspect_err(|e| debug!("Chunk {} failed to decompress: {}", index, e))
+                .ok()
+                .filter(|data| ChunkInfo::compute_hash(data) == hash);
+            let intact = data.is_some();
+            if let Some(data) = data {
+                file.write_all(&data)?;
+                chunk_hashes.push(hash);
+                progress.bytes += len;
+            }
+            self.send_message(&Message::ChunkAck(ChunkAck {
+                transfer_id,
+                chunk_index: index,
+                success: intact,
+            }))
+            .await?;
+            if intact {
+                self.report(&progress);
+            }
+        }
+    }
+
+    async fn respond(
+        &mut self,
+        transfer_id: Uuid,
+        accepted: bool,
+        resume_from_chunk: Option<usize>,
+        framing: Framing,
+        compressor: ChunkCompressor,
+    ) -> Result<()> {
+        self.send_message(&Message::TransferResponse(TransferResponse {
+            transfer_id,
+            accepted,
+            resume_from_chunk,
+            framing,
+            compression: compressor.algorithm(),
+            compression_level: compressor.level(),
+        }))
+        .await
+    }
+
+    /// Start a transfer; its handshake is always NDJSON
+    fn begin(&mut self, transfer_id: Option<Uuid>) {
+        self.transfer_id = transfer_id;
+        self.framing = Framing::Ndjson;
+    }
+
+    /// Tell the peer why a call failed, unless the peer itself or the
+    /// connection is why
+    async fn finish<R>(&mut self, result: Result<R>) -> Result<R> {
+        let code = match &result {
+            Err(ClientError::Cancelled) => "cancelled",
+            Err(ClientError::Integrity(_)) => "integrity_failed",
+            Err(ClientError::Io(_)) => "io_error",
+            Err(ClientError::Protocol(_)) | Err(ClientError::Codec(_)) => "protocol_error",
+            _ => return result,
+        };
+        let Err(error) = &result else {
+            return result;
+        };
+
+        let message = Message::Error(ErrorMessage {
+            transfer_id: self.transfer_id,
+            code: code.to_string(),
+            message: error.to_string(),
+        });
+        if let Ok(payload) = encode(&message, self.framing) {
+            let _ = tokio::time::timeout(ABORT_TIMEOUT, self.transport.send(&payload)).await;
+        }
+        result
+    }
+
+    async fn send_message(&mut self, message: &Message) -> Result<()> {
+        let payload = encode(message, self.framing)?;
+        let cancel = self.cancel.clone();
+        cancellable(&cancel, self.transport.send(&payload)).await??;
+        Ok(())
+    }
+
+    /// The next message from the peer; an `Error` from it becomes
+    /// [`ClientError::Peer`]
+    async fn receive_message(&mut self) -> Result<Message> {
+        let cancel = self.cancel.clone();
+        let payload = cancellable(&cancel, self.transport.receive()).await??;
+        let message = match self.framing {
+            Framing::Ndjson => decode_message(&payload)?,
+            Framing::LengthPrefixed => decode_binary(&payload)?,
+        };
+        match message {
+            Message::Error(error) => Err(ClientError::Peer {
+                code: error.code,
+                message: error.message,
+            }),
+            message => Ok(message),
+        }
+    }
+
+    async fn cancellable<F: Future>(&self, future: F) -> Result<F::Output> {
+        cancellable(&self.cancel, future).await
+    }
+
+    fn report(&self, progress: &Progress) {
+        if let Some(callback) = &self.progress {
+            callback(progress);
+        }
+    }
+}
+
+async fn cancellable<F: Future>(cancel: &CancellationToken, future: F) -> Result<F::Output> {
+    tokio::select! {
+        biased;
+        _ = cancel.cancelled() => Err(ClientError::Cancelled),
+        output = future => Ok(output),
+    }
+}
+
+/// Encode `message` for the transport, which frames each message itself,
+/// so binary framing goes without its length prefix
+fn encode(message: &Message, framing: Framing) -> Result<Vec<u8>> {
+    let mut frame = encode_frame(message, framing)?;
+    if framing == Framing::LengthPrefixed {
+        frame.drain(..4);
+    }
+    Ok(frame)
+}
+
+/// Whether `path` already holds the file a transfer init describes
+fn is_identical(path: &Path, size: u64, chunk_size: usize, merkle_root: &str) -> io::Result<bool> {
+    match std::fs::metadata(path) {
+        Ok(metadata) if metadata.is_file() && metadata.len() == size => {
+            Ok(hash_file(path, chunk_size)?.merkle_root() == merkle_root)
+        }
+        Ok(_) => Ok(false),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(false),
+        Err(e) => Err(e),
+    }
+}
+
+fn unexpected(expected: &str, message: &Message) -> ClientError {
+    ClientError::Protocol(format!(
+        "expected {}, got {:?}",
+        expected,
+        message.message_type()
+    ))
+}
+
+fn join_error(error: JoinError) -> ClientError {
+    ClientError::Io(io::Error::other(error))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+    use tft_transports::{LinkConditions, LoopbackTransport, RetryPolicy, TransportConfig};
+
+    async fn pair() -> (LoopbackTransport, LoopbackTransport) {
+        let config = TransportConfig {
+            host: "loopback".to_string(),
+            port: 0,
+            timeout_ms: 1000,
+            enable_0rtt: false,
+            keep_alive_ms: None,
+            tls: Default::default(),
+            retry: RetryPolicy::never(),
+            max_bytes_per_sec: None,
+            burst_bytes: None,
+        };
+        let (mut a, mut b) = LoopbackTransport::pair(LinkConditions::default());
+        a.connect(&config).await.unwrap();
+        b.connect(&config).await.unwrap();
+        (a, b)
+    }
+
+    #[tokio::test]
+    async fn test_send_and_receive_file() {
+        let (a, b) = pair().await;
+        let source = tempfile::tempdir().unwrap();
+        let dest = tempfile::tempdir().unwrap();
+        let path = source.path().join("data.bin");
+        let data: Vec<u8> = (0..10_000u32).map(|i| (i % 251) as u8).collect();
+        std::fs::write(&path, &data).unwrap();
+
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        let progress = seen.clone();
+        let mut sender = Client::new(a)
+            .with_chunk_size(4096)
+            .with_progress(move |p| progress.lock().unwrap().push(p.bytes));
+        let mut receiver = Client::new(b);
+
+        let (sent, received) =
+            tokio::join!(sender.send_file(&path), receiver.receive_file(dest.path()));
+        let (sent, received) = (sent.unwrap(), received.unwrap());
+        assert!(!sent.skipped);
+        assert_eq!(received.path, dest.path().join("data.bin"));
+        assert_eq!(std::fs::read(&received.path).unwrap(), data);
+        assert_eq!(*seen.lock().unwrap(), vec![4096, 8192, 10_000]);
+
+        // Sending it again finds it already there
+        let (sent, received) =
+            tokio::join!(sender.send_file(&path), receiver.receive_file(dest.path()));
+        assert!(sent.unwrap().skipped && received.unwrap().skipped);
+    }
+
+    #[tokio::test]
+    async fn test_compressed_transfers() {
+        let source = tempfile::tempdir().unwrap();
+        let log: Vec<u8> = (0..5000)
+            .flat_map(|i| format!("GET /api/items/{} 200 {}ms\n", i, i % 97).into_bytes())
+            .collect();
+        let mut state = 0x9e37_79b9_7f4a_7c15_u64;
+        let random: Vec<u8> = (0..log.len())
+            .map(|_| {
+                state ^= state << 13;
+                state ^= state >> 7;
+                state ^= state << 17;
+                state as u8
+            })
+            .collect();
+        std::fs::write(source.path().join("access.log"), &log).unwrap();
+        std::fs::write(source.path().join("random.bin"), &random).unwrap();
+
+        // What the receiver accepts decides; nothing in common sends plain chunks
+        let accepted: [&[CompressionType]; 3] =
+            [&[CompressionType::Zstd], &[CompressionType::Lz4], &[]];
+        for compressions in accepted {
+            let (a, b) = pair().await;
+            let dest = tempfile::tempdir().unwrap();
+            let mut sender = Client::new(a)
+                .with_chunk_size(16 * 1024)
+                .with_compression(CompressionType::SUPPORTED, Some(19));
+            let mut receiver = Client::new(b).with_compression(compressions, None);
+
+            for (name, data) in [("access.log", &log), ("random.bin", &random)] {
+                let path = source.path().join(name);
+                let (sent, received) =
+                    tokio::join!(sender.send_file(&path), receiver.receive_file(dest.path()));
+                assert_eq!(sent.unwrap().bytes, data.len() as u64);
+                assert_eq!(std::fs::read(received.unwrap().path).unwrap(), *data);
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_sync_dir_sends_only_changes() {
+        let source = tempfile::tempdir().unwrap();
+        let dest = tempfile::tempdir().unwrap();
+        std::fs::create_dir_all(source.path().join("docs/2024")).unwrap();
+        std::fs::write(source.path().join("readme.txt"), b"hello").unwrap();
+        std::fs::write(source.path().join("docs/2024/report.txt"), b"quarterly").unwrap();
+        std::fs::write(source.path().join("docs/empty"), b"").unwrap();
+
+        let sync = || async {
+            let (a, b) = pair().await;
+            let mut sender = Client::new(a).with_chunk_size(4);
+            let mut receiver = Client::new(b);
+            let (sent, received) = tokio::join!(
+                sender.sync_dir(source.path()),
+                receiver.receive_dir(dest.path())
+            );
+            assert_eq!(sent.as_ref().unwrap().len(), received.unwrap().len());
+            sent.unwrap()
+                .into_iter()
+                .filter(|file| !file.skipped)
+                .map(|file| file.path.strip_prefix(source.path()).unwrap().to_path_buf())
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(sync().await.len(), 3);
+        assert_eq!(
+            std::fs::read(dest.path().join("docs/2024/report.txt")).unwrap(),
+            b"quarterly"
+        );
+
+        std::fs::write(source.path().join("readme.txt"), b"hello again").unwrap();
+        assert_eq!(sync().await, vec![PathBuf::from("readme.txt")]);
+        assert_eq!(
+            std::fs::read(dest.path().join("readme.txt")).unwrap(),
+            b"hello again"
+        );
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_sync_dir_keeps_links_and_metadata() {
+        use std::os::unix::fs::PermissionsExt;
+        use std::time::{Duration, SystemTime};
+
+        let source = tempfile::tempdir().unwrap();
+        std::fs::create_dir_all(source.path().join("bin")).unwrap();
+        std::fs::create_dir_all(source.path().join("cache")).unwrap();
+        let script = source.path().join("bin/run.sh");
+        std::fs::write(&script, b"#!/bin/sh\n").unwrap();
+        std::fs::set_permissions(&script, std::fs::Permissions::from_mode(0o700)).unwrap();
+        let old = SystemTime::UNIX_EPOCH + Duration::from_secs(1_600_000_000);
+        std::fs::File::options()
+            .write(true)
+            .open(&script)
+            .unwrap()
+            .set_modified(old)
+            .unwrap();
+        std::os::unix::fs::symlink("bin/run.sh", source.path().join("run")).unwrap();
+
+        let root = source.path();
+        let sync = |preserve: bool| async move {
+            let dest = tempfile::tempdir().unwrap();
+            let (a, b) = pair().await;
+            let mut sender = Client::new(a).with_metadata(preserve);
+            let mut receiver = Client::new(b);
+            let (sent, received) =
+                tokio::join!(sender.sync_dir(root), receiver.receive_dir(dest.path()));
+            assert_eq!(sent.unwrap().len(), 1);
+            assert_eq!(received.unwrap().len(), 1);
+            dest
+        };
+
+        let dest = sync(true).await;
+        assert!(dest.path().join("cache").is_dir());
+        assert_eq!(
+            std::fs::read_link(dest.path().join("run")).unwrap(),
+            PathBuf::from("bin/run.sh")
+        );
+        let copied = std::fs::metadata(dest.path().join("bin/run.sh")).unwrap();
+        assert_eq!(copied.permissions().mode() & 0o777, 0o700);
+        assert_eq!(copied.modified().unwrap(), old);
+
+        let stripped = sync(false).await;
+        let copied = std::fs::metadata(stripped.path().join("bin/run.sh")).unwrap();
+        assert_ne!(copied.modified().unwrap(), old);
+    }
+
+    #[tokio::test]
+    async fn test_cancel_leaves_no_file() {
+        let (a, b) = pair().await;
+        let source = tempfile::tempdir().unwrap();
+        let dest = tempfile::tempdir().unwrap();
+        let path = source.path().join("big.bin");
+        std::fs::write(&path, vec![9u8; 64 * 1024]).unwrap();
+
+        let cancel = CancellationToken::new();
+        let on_progress = cancel.clone();
+        let mut sender = Client::new(a)
+            .with_chunk_size(1024)
+            .with_cancellation(cancel)
+            .with_progress(move |p| {
+                if p.bytes >= 4096 {
+                    on_progress.cancel();
+                }
+            });
+        let mut receiver = Client::new(b);
+
+        let (sent, received) =
+            tokio::join!(sender.send_file(&path), receiver.receive_file(dest.path()));
+        assert!(matches!(sent, Err(ClientError::Cancelled)));
+        assert!(matches!(received, Err(ClientError::Peer { ref code, .. }) if code == "cancelled"));
+        assert_eq!(std::fs::read_dir(dest.path()).unwrap().count(), 0);
+    }
+}
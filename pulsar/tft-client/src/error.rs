@@ -0,0 +1,34 @@
+//! Client errors
+
+use tft_core::DecodeError;
+use tft_transports::TransportError;
+use thiserror::Error;
+
+pub type Result<T> = std::result::Result<T, ClientError>;
+
+#[derive(Debug, Error)]
+pub enum ClientError {
+    #[error("Transport error: {0}")]
+    Transport(#[from] TransportError),
+
+    #[error("Invalid message: {0}")]
+    Codec(#[from] DecodeError),
+
+    #[error("IO error: {0}")]
+    Io(#[from] std::io::Error),
+
+    #[error("Protocol error: {0}")]
+    Protocol(String),
+
+    #[error("Peer rejected the transfer of {0}")]
+    Rejected(String),
+
+    #[error("Peer reported {code}: {message}")]
+    Peer { code: String, message: String },
+
+    #[error("Integrity check failed: {0}")]
+    Integrity(String),
+
+    #[error("Transfer cancelled")]
+    Cancelled,
+}
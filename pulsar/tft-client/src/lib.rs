@@ -0,0 +1,33 @@
+//! TFT Client Library
+//!
+//! A high-level async API for embedding TFT transfers in other tools,
+//! without the daemon:
+//! - [`Client::send_file`] and [`Client::receive_file`] move one file
+//! - [`Client::sync_dir`] and [`Client::receive_dir`] mirror a directory
+//!   tree, with its symlinks, modes and mtimes, skipping files the receiver
+//!   already has
+//!
+//! A [`Client`] runs over any connected [`Transport`]; progress is
+//! reported through a callback and every call can be stopped with a
+//! [`CancellationToken`].
+//!
+//! ```no_run
+//! # async fn run(transport: tft_transports::LoopbackTransport) -> tft_client::Result<()> {
+//! use tft_client::{CancellationToken, Client};
+//!
+//! let cancel = CancellationToken::new();
+//! let mut client = Client::new(transport)
+//!     .with_progress(|p| println!("{:?}: {}/{} bytes", p.path, p.bytes, p.total))
+//!     .with_cancellation(cancel.clone());
+//! client.send_file("report.pdf").await?;
+//! # Ok(())
+//! # }
+//! ```
+
+pub mod client;
+pub mod error;
+
+pub use client::{Client, Progress, Transferred};
+pub use error::{ClientError, Result};
+pub use tft_transports::{Transport, TransportError};
+pub use tokio_util::sync::CancellationToken;
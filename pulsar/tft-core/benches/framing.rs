@@ -0,0 +1,46 @@
+//! Compare CPU cost of NDJSON and length-prefixed binary framing
+//!
+//! Run with `cargo bench -p tft-core --bench framing`; throughput is
+//! reported per byte of chunk payload so results read as time per GB.
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use tft_core::codec::{decode_binary, decode_message, encode_frame};
+use tft_core::protocol::{ChunkMessage, Framing, Message};
+use uuid::Uuid;
+
+const CHUNK_SIZE: usize = 1024 * 1024;
+
+fn chunk() -> Message {
+    Message::Chunk(ChunkMessage {
+        transfer_id: Uuid::new_v4(),
+        chunk_index: 0,
+        data: (0..CHUNK_SIZE).map(|i| i as u8).collect(),
+        hash: "0".repeat(64),
+    })
+}
+
+fn bench_framing(c: &mut Criterion) {
+    let message = chunk();
+    let mut group = c.benchmark_group("framing");
+    group.throughput(Throughput::Bytes(CHUNK_SIZE as u64));
+
+    for framing in [Framing::Ndjson, Framing::LengthPrefixed] {
+        let frame = encode_frame(&message, framing).unwrap();
+
+        group.bench_with_input(BenchmarkId::new("encode", format!("{:?}", framing)), &framing, |b, &f| {
+            b.iter(|| encode_frame(&message, f).unwrap())
+        });
+
+        group.bench_with_input(BenchmarkId::new("decode", format!("{:?}", framing)), &frame, |b, frame| {
+            b.iter(|| match framing {
+                Framing::Ndjson => decode_message(frame).unwrap(),
+                Framing::LengthPrefixed => decode_binary(&frame[4..]).unwrap(),
+            })
+        });
+    }
+
+    group.finish();
+}
+
+criterion_group!(benches, bench_framing);
+criterion_main!(benches);
@@ -0,0 +1,82 @@
+//! End-to-end verification of a large file: single-threaded read-then-hash
+//! against the parallel pipeline in `tft_core::verify`
+//!
+//! Run with `cargo bench -p tft-core --bench verify`. The file is 10 GB by
+//! default; set `TFT_VERIFY_BENCH_MB` for a smaller one. It is written once
+//! before measuring, so reads mostly come from the page cache unless the
+//! file is larger than memory.
+
+use criterion::{criterion_group, criterion_main, Criterion, Throughput};
+use std::fs::File;
+use std::io::{BufWriter, Read, Write};
+use std::path::Path;
+use std::time::Duration;
+use tft_core::{hash_file, DEFAULT_CHUNK_SIZE};
+
+const DEFAULT_FILE_MB: u64 = 10 * 1024;
+
+fn file_mb() -> u64 {
+    std::env::var("TFT_VERIFY_BENCH_MB")
+        .ok()
+        .and_then(|mb| mb.parse().ok())
+        .unwrap_or(DEFAULT_FILE_MB)
+}
+
+fn write_file(path: &Path, mb: u64) {
+    let mut writer = BufWriter::new(File::create(path).unwrap());
+    let mut block: Vec<u8> = (0..1024 * 1024).map(|i| (i % 251) as u8).collect();
+    for n in 0..mb {
+        // Vary each block so chunks don't hash alike
+        block[..8].copy_from_slice(&n.to_le_bytes());
+        writer.write_all(&block).unwrap();
+    }
+    writer.flush().unwrap();
+}
+
+/// What verification did before the pipeline: read a chunk, hash it, feed
+/// the file hash, repeat
+fn hash_file_sequential(path: &Path, chunk_size: usize) -> (Vec<String>, String) {
+    let mut file = File::open(path).unwrap();
+    let mut file_hasher = blake3::Hasher::new();
+    let mut chunk_hashes = Vec::new();
+    let mut chunk = vec![0u8; chunk_size];
+    loop {
+        let mut filled = 0;
+        while filled < chunk_size {
+            match file.read(&mut chunk[filled..]).unwrap() {
+                0 => break,
+                n => filled += n,
+            }
+        }
+        if filled == 0 {
+            break;
+        }
+        chunk_hashes.push(blake3::hash(&chunk[..filled]).to_hex().to_string());
+        file_hasher.update(&chunk[..filled]);
+    }
+    (chunk_hashes, file_hasher.finalize().to_hex().to_string())
+}
+
+fn bench_verify(c: &mut Criterion) {
+    let mb = file_mb();
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("verify.bin");
+    write_file(&path, mb);
+
+    let mut group = c.benchmark_group(format!("verify_{}mb", mb));
+    group.throughput(Throughput::Bytes(mb * 1024 * 1024));
+    group.sample_size(10);
+    group.measurement_time(Duration::from_secs((mb / 100).max(10)));
+
+    group.bench_function("sequential", |b| {
+        b.iter(|| hash_file_sequential(&path, DEFAULT_CHUNK_SIZE))
+    });
+    group.bench_function("pipeline", |b| {
+        b.iter(|| hash_file(&path, DEFAULT_CHUNK_SIZE).unwrap())
+    });
+
+    group.finish();
+}
+
+criterion_group!(benches, bench_verify);
+criterion_main!(benches);
@@ -0,0 +1,15 @@
+//! Fuzz the length-prefixed bincode decoder with arbitrary payloads
+//!
+//! Run with: `cargo +nightly fuzz run decode_binary`
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use tft_core::codec::{decode_binary, encode_binary};
+
+fuzz_target!(|data: &[u8]| {
+    if let Ok(message) = decode_binary(data) {
+        let frame = encode_binary(&message).expect("accepted message must re-encode");
+        decode_binary(&frame[4..]).expect("re-encoded message must decode");
+    }
+});
@@ -0,0 +1,17 @@
+//! Fuzz the strict NDJSON decoder with arbitrary frames
+//!
+//! Run with: `cargo +nightly fuzz run decode_message`
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use tft_core::codec::{decode_message, encode_message};
+
+fuzz_target!(|data: &[u8]| {
+    // Decoding must never panic; anything accepted must re-encode and
+    // decode to an equally valid message.
+    if let Ok(message) = decode_message(data) {
+        let frame = encode_message(&message).expect("accepted message must re-encode");
+        decode_message(&frame).expect("re-encoded message must decode");
+    }
+});
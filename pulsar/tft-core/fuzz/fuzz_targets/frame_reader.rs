@@ -0,0 +1,20 @@
+//! Fuzz the size-bounded frame reader with arbitrary byte streams
+//!
+//! Run with: `cargo +nightly fuzz run frame_reader`
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use tft_core::codec::FrameReader;
+
+fuzz_target!(|data: &[u8]| {
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .build()
+        .expect("failed to build runtime");
+
+    runtime.block_on(async {
+        // Small limit so oversized frames are exercised constantly
+        let mut reader = FrameReader::with_limit(data, 1024);
+        while let Ok(Some(_)) = reader.next_message().await {}
+    });
+});
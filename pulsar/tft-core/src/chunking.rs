@@ -1,18 +1,381 @@
 //! File chunking utilities
+//!
+//! Chunks are read either with ordinary reads into a buffer or as views
+//! into a memory map of the file. Mapping avoids a copy per chunk for
+//! huge files, but is only used where it is safe: on 64-bit platforms,
+//! where a large file fits the address space, and on local disks, where
+//! the file can't be truncated from another machine under the map.
+//!
+//! Chunks can also be compressed for the wire with zstd or LZ4, at the
+//! level the transfer's handshake settled on. Files whose start doesn't
+//! compress, such as media and archives, are sent as they are.
 
+use crate::protocol::CompressionType;
 use blake3::Hasher;
+use memmap2::Mmap;
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+use std::fs::File;
+use std::io::{self, Read, Seek, SeekFrom};
+use std::path::Path;
+use std::sync::Mutex;
 
+/// Files at least this large are mapped by [`ChunkStrategy::Auto`] (256 MB)
+pub const DEFAULT_MMAP_THRESHOLD: u64 = 256 * 1024 * 1024;
+
+/// Bytes compressed to judge whether a file is worth compressing (64 KB)
+pub const COMPRESSION_SAMPLE_SIZE: usize = 64 * 1024;
+
+/// Share of a sample compression has to save, in percent, for the rest of
+/// the file to be compressed
+const MIN_COMPRESSION_SAVING: usize = 10;
+
+/// How a file's chunks are read
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ChunkStrategy {
+    /// Map files at or above the threshold, read smaller ones
+    #[default]
+    Auto,
+    /// Always read into a buffer
+    Read,
+    /// Map every file where that is safe
+    Mmap,
+}
+
+/// Chunking settings
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ChunkerConfig {
+    pub chunk_size: usize,
+    pub strategy: ChunkStrategy,
+    /// Size in bytes from which `Auto` maps a file
+    pub mmap_threshold: u64,
+}
+
+impl Default for ChunkerConfig {
+    fn default() -> Self {
+        Self {
+            chunk_size: crate::DEFAULT_CHUNK_SIZE,
+            strategy: ChunkStrategy::Auto,
+            mmap_threshold: DEFAULT_MMAP_THRESHOLD,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
 pub struct FileChunker {
     chunk_size: usize,
+    strategy: ChunkStrategy,
+    mmap_threshold: u64,
 }
 
 impl FileChunker {
     pub fn new(chunk_size: usize) -> Self {
-        Self { chunk_size }
+        Self {
+            chunk_size,
+            strategy: ChunkStrategy::Auto,
+            mmap_threshold: DEFAULT_MMAP_THRESHOLD,
+        }
+    }
+
+    pub fn from_config(config: &ChunkerConfig) -> Self {
+        Self::new(config.chunk_size)
+            .with_strategy(config.strategy)
+            .with_mmap_threshold(config.mmap_threshold)
+    }
+
+    pub fn with_strategy(mut self, strategy: ChunkStrategy) -> Self {
+        self.strategy = strategy;
+        self
+    }
+
+    pub fn with_mmap_threshold(mut self, bytes: u64) -> Self {
+        self.mmap_threshold = bytes;
+        self
     }
 
     pub fn chunk_count(&self, file_size: u64) -> usize {
-        ((file_size as f64) / (self.chunk_size as f64)).ceil() as usize
+        if self.chunk_size == 0 {
+            return 0;
+        }
+        file_size.div_ceil(self.chunk_size as u64) as usize
+    }
+
+    /// Open `path` for chunking with the configured strategy
+    pub fn open(&self, path: &Path) -> io::Result<ChunkedFile> {
+        if self.chunk_size == 0 {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "chunk size must not be zero"));
+        }
+
+        let file = File::open(path)?;
+        let len = file.metadata()?.len();
+
+        let backend = if self.should_map(path, len) {
+            // SAFETY: the map is only read. Another process truncating the
+            // file while it is mapped would fault the reader; network
+            // mounts, where that can happen behind our back, are never
+            // mapped.
+            let map = unsafe { Mmap::map(&file)? };
+            #[cfg(unix)]
+            map.advise(memmap2::Advice::Sequential)?;
+            Backend::Mapped(map)
+        } else {
+            Backend::Read(Mutex::new(file))
+        };
+
+        Ok(ChunkedFile {
+            chunk_size: self.chunk_size,
+            len,
+            backend,
+        })
+    }
+
+    fn should_map(&self, path: &Path, len: u64) -> bool {
+        let wanted = match self.strategy {
+            ChunkStrategy::Read => false,
+            ChunkStrategy::Mmap => true,
+            ChunkStrategy::Auto => len >= self.mmap_threshold,
+        };
+        // Empty files can't be mapped; 32-bit address spaces can't hold
+        // the files worth mapping
+        wanted && len > 0 && cfg!(target_pointer_width = "64") && !is_network_mount(path)
+    }
+}
+
+/// A file opened for chunking
+pub struct ChunkedFile {
+    chunk_size: usize,
+    len: u64,
+    backend: Backend,
+}
+
+enum Backend {
+    Mapped(Mmap),
+    Read(Mutex<File>),
+}
+
+impl ChunkedFile {
+    pub fn len(&self) -> u64 {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Whether chunks are views into a memory map
+    pub fn is_mapped(&self) -> bool {
+        matches!(self.backend, Backend::Mapped(_))
+    }
+
+    pub fn chunk_count(&self) -> usize {
+        self.len.div_ceil(self.chunk_size as u64) as usize
+    }
+
+    /// The bytes of chunk `index`: borrowed from the map, or read into a
+    /// new buffer
+    pub fn chunk(&self, index: usize) -> io::Result<Cow<'_, [u8]>> {
+        if index >= self.chunk_count() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("chunk {} is past the end of the file", index),
+            ));
+        }
+        let offset = index as u64 * self.chunk_size as u64;
+        let size = (self.len - offset).min(self.chunk_size as u64) as usize;
+
+        match &self.backend {
+            Backend::Mapped(map) => Ok(Cow::Borrowed(&map[offset as usize..offset as usize + size])),
+            Backend::Read(file) => {
+                let mut file = file.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+                file.seek(SeekFrom::Start(offset))?;
+                let mut buffer = vec![0; size];
+                file.read_exact(&mut buffer)?;
+                Ok(Cow::Owned(buffer))
+            }
+        }
+    }
+
+    /// Whether chunk `index` is all there and hashes to `hash`
+    ///
+    /// For a partially received file: a chunk past its end, cut short, or
+    /// never written (a hole reads as zeros) doesn't match.
+    pub fn verify_chunk(&self, index: usize, hash: &str) -> io::Result<bool> {
+        if index >= self.chunk_count() {
+            return Ok(false);
+        }
+        Ok(ChunkInfo::compute_hash(&self.chunk(index)?) == hash)
+    }
+
+    /// Every chunk, in order
+    pub fn chunks(&self) -> impl Iterator<Item = io::Result<Cow<'_, [u8]>>> + '_ {
+        (0..self.chunk_count()).map(move |index| self.chunk(index))
+    }
+
+    /// Position, size and hash of chunk `index`
+    pub fn chunk_info(&self, index: usize) -> io::Result<ChunkInfo> {
+        let data = self.chunk(index)?;
+        Ok(ChunkInfo {
+            index,
+            offset: index as u64 * self.chunk_size as u64,
+            size: data.len(),
+            hash: ChunkInfo::compute_hash(&data),
+        })
+    }
+}
+
+/// Whether `path` is on a network filesystem (NFS, SMB, AFS, FUSE, ...)
+#[cfg(target_os = "linux")]
+fn is_network_mount(path: &Path) -> bool {
+    use std::os::unix::ffi::OsStrExt;
+
+    const NETWORK_FILESYSTEMS: [u32; 9] = [
+        0x0000_6969, // NFS
+        0x0000_517B, // SMB
+        0xFF53_4D42, // CIFS
+        0xFE53_4D42, // SMB2
+        0x5346_414F, // AFS
+        0x7375_7245, // Coda
+        0x00C3_6400, // Ceph
+        0x0102_1997, // 9p
+        0x6573_5546, // FUSE (sshfs and friends)
+    ];
+
+    let Ok(path) = std::ffi::CString::new(path.as_os_str().as_bytes()) else {
+        return false;
+    };
+    let mut stat = std::mem::MaybeUninit::<libc::statfs>::uninit();
+    // SAFETY: `path` is NUL-terminated and `stat` is only read on success
+    if unsafe { libc::statfs(path.as_ptr(), stat.as_mut_ptr()) } != 0 {
+        return false;
+    }
+    // f_type's width differs between targets; the magic numbers are 32-bit
+    #[allow(clippy::unnecessary_cast)]
+    let magic = unsafe { stat.assume_init() }.f_type as u32;
+    NETWORK_FILESYSTEMS.contains(&magic)
+}
+
+/// Whether `path` is on a network filesystem (NFS, SMB, AFP, ...)
+#[cfg(target_os = "macos")]
+fn is_network_mount(path: &Path) -> bool {
+    use std::os::unix::ffi::OsStrExt;
+
+    let Ok(path) = std::ffi::CString::new(path.as_os_str().as_bytes()) else {
+        return false;
+    };
+    let mut stat = std::mem::MaybeUninit::<libc::statfs>::uninit();
+    // SAFETY: `path` is NUL-terminated and `stat` is only read on success
+    if unsafe { libc::statfs(path.as_ptr(), stat.as_mut_ptr()) } != 0 {
+        return false;
+    }
+    let stat = unsafe { stat.assume_init() };
+    // SAFETY: the kernel NUL-terminates f_fstypename
+    let name = unsafe { std::ffi::CStr::from_ptr(stat.f_fstypename.as_ptr()) };
+    matches!(
+        name.to_bytes(),
+        b"nfs" | b"smbfs" | b"afpfs" | b"webdav" | b"cifs" | b"macfuse" | b"osxfuse"
+    )
+}
+
+/// Whether `path` is on a network share (a UNC path)
+#[cfg(windows)]
+fn is_network_mount(path: &Path) -> bool {
+    use std::path::{Component, Prefix};
+
+    matches!(
+        path.components().next(),
+        Some(Component::Prefix(prefix))
+            if matches!(prefix.kind(), Prefix::UNC(..) | Prefix::VerbatimUNC(..))
+    )
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "macos", windows)))]
+fn is_network_mount(_path: &Path) -> bool {
+    false
+}
+
+/// Compresses and decompresses chunks with one algorithm and level
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ChunkCompressor {
+    algorithm: CompressionType,
+    level: Option<i32>,
+}
+
+impl ChunkCompressor {
+    /// A compressor for `algorithm` at `level`, clamped to the levels it
+    /// has; `None` takes its default
+    pub fn new(algorithm: CompressionType, level: Option<i32>) -> Self {
+        let level = match algorithm {
+            CompressionType::Zstd => {
+                let range = zstd::compression_level_range();
+                level.map(|level| level.clamp(*range.start(), *range.end()))
+            }
+            CompressionType::None | CompressionType::Lz4 => None,
+        };
+        Self { algorithm, level }
+    }
+
+    pub fn algorithm(&self) -> CompressionType {
+        self.algorithm
+    }
+
+    /// The level in use; `None` for the default or an algorithm without
+    /// levels
+    pub fn level(&self) -> Option<i32> {
+        self.level
+    }
+
+    /// Whether chunks are compressed at all
+    pub fn is_enabled(&self) -> bool {
+        self.algorithm != CompressionType::None
+    }
+
+    /// `data` compressed, or `None` if that wouldn't make it smaller
+    pub fn compress(&self, data: &[u8]) -> io::Result<Option<Vec<u8>>> {
+        let compressed = match self.algorithm {
+            CompressionType::None => return Ok(None),
+            CompressionType::Zstd => {
+                zstd::bulk::compress(data, self.level.unwrap_or(zstd::DEFAULT_COMPRESSION_LEVEL))?
+            }
+            CompressionType::Lz4 => lz4_flex::block::compress(data),
+        };
+        Ok((compressed.len() < data.len()).then_some(compressed))
+    }
+
+    /// A chunk that was `size` bytes before compression
+    ///
+    /// Data that would inflate past `size` is rejected rather than
+    /// decompressed, so a peer can't make us allocate more than the chunk.
+    pub fn decompress(&self, data: &[u8], size: usize) -> io::Result<Vec<u8>> {
+        let decompressed = match self.algorithm {
+            CompressionType::None => data.to_vec(),
+            CompressionType::Zstd => zstd::bulk::decompress(data, size)?,
+            CompressionType::Lz4 => lz4_flex::block::decompress(data, size)
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?,
+        };
+        if decompressed.len() != size {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("chunk decompressed to {} bytes, not {}", decompressed.len(), size),
+            ));
+        }
+        Ok(decompressed)
+    }
+
+    /// Whether a file starting with `sample` saves too little by
+    /// compression to be worth it, as already compressed data does
+    ///
+    /// Only the first [`COMPRESSION_SAMPLE_SIZE`] bytes are tried.
+    pub fn is_incompressible(&self, sample: &[u8]) -> io::Result<bool> {
+        let sample = &sample[..sample.len().min(COMPRESSION_SAMPLE_SIZE)];
+        let compressed = self.compress(sample)?;
+        Ok(match compressed {
+            Some(compressed) => {
+                compressed.len() * 100 > sample.len() * (100 - MIN_COMPRESSION_SAVING)
+            }
+            None => true,
+        })
     }
 }
 
@@ -31,3 +394,91 @@ impl ChunkInfo {
         hasher.finalize().to_hex().to_string()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_mapped_and_read_chunks_match() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("data.bin");
+        let data: Vec<u8> = (0..10_000u32).map(|i| (i % 253) as u8).collect();
+        std::fs::write(&path, &data).unwrap();
+
+        let read = FileChunker::new(4096).with_strategy(ChunkStrategy::Read).open(&path).unwrap();
+        let mapped = FileChunker::new(4096).with_strategy(ChunkStrategy::Mmap).open(&path).unwrap();
+        assert!(!read.is_mapped());
+        assert_eq!(mapped.is_mapped(), cfg!(target_pointer_width = "64"));
+
+        for file in [&read, &mapped] {
+            let chunks: Vec<_> = file.chunks().map(|chunk| chunk.unwrap().into_owned()).collect();
+            let expected: Vec<_> = data.chunks(4096).map(<[u8]>::to_vec).collect();
+            assert_eq!(chunks, expected);
+
+            let last = file.chunk_info(2).unwrap();
+            assert_eq!((last.offset, last.size), (8192, 1808));
+            assert_eq!(last.hash, ChunkInfo::compute_hash(&data[8192..]));
+            assert!(file.chunk(3).is_err());
+        }
+        #[cfg(target_pointer_width = "64")]
+        assert!(matches!(mapped.chunk(0).unwrap(), Cow::Borrowed(_)));
+    }
+
+    #[test]
+    fn test_auto_strategy_by_size() {
+        let dir = tempfile::tempdir().unwrap();
+        let small = dir.path().join("small.bin");
+        std::fs::write(&small, vec![1u8; 100]).unwrap();
+        let empty = dir.path().join("empty.bin");
+        std::fs::write(&empty, b"").unwrap();
+
+        let config = ChunkerConfig {
+            chunk_size: 64,
+            mmap_threshold: 100,
+            ..Default::default()
+        };
+        let chunker = FileChunker::from_config(&config);
+        assert_eq!(chunker.open(&small).unwrap().is_mapped(), cfg!(target_pointer_width = "64"));
+        assert!(!chunker.clone().with_mmap_threshold(101).open(&small).unwrap().is_mapped());
+
+        let empty = chunker.with_strategy(ChunkStrategy::Mmap).open(&empty).unwrap();
+        assert!(!empty.is_mapped());
+        assert_eq!(empty.chunk_count(), 0);
+    }
+
+    #[test]
+    fn test_compression_roundtrip_and_detection() {
+        let log: Vec<u8> = (0..2000)
+            .flat_map(|i| {
+                format!("2024-05-01T12:00:{:02} INFO request {} served\n", i % 60, i).into_bytes()
+            })
+            .collect();
+        // xorshift output stands in for an already compressed file
+        let mut state = 0x2545_f491_4f6c_dd1d_u64;
+        let random: Vec<u8> = (0..log.len())
+            .map(|_| {
+                state ^= state << 13;
+                state ^= state >> 7;
+                state ^= state << 17;
+                state as u8
+            })
+            .collect();
+
+        for algorithm in [CompressionType::Zstd, CompressionType::Lz4] {
+            let compressor = ChunkCompressor::new(algorithm, Some(3));
+            let compressed = compressor.compress(&log).unwrap().unwrap();
+            assert!(compressed.len() < log.len() / 4, "{:?}", algorithm);
+            assert_eq!(compressor.decompress(&compressed, log.len()).unwrap(), log);
+            // A peer lying about the size gets an error, not a huge buffer
+            assert!(compressor.decompress(&compressed, log.len() - 1).is_err());
+
+            assert!(!compressor.is_incompressible(&log).unwrap());
+            assert!(compressor.is_incompressible(&random).unwrap());
+        }
+
+        assert_eq!(ChunkCompressor::new(CompressionType::Zstd, Some(1000)).level(), Some(22));
+        assert_eq!(ChunkCompressor::new(CompressionType::Lz4, Some(9)).level(), None);
+        assert_eq!(ChunkCompressor::default().compress(&log).unwrap(), None);
+    }
+}
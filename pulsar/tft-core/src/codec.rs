@@ -0,0 +1,980 @@
+//! Strict decoding and framing for TFT protocol messages
+//!
+//! Every frame received from a peer goes through this layer before it is
+//! acted on. Frames are bounded in size, and decoded messages are checked
+//! for field consistency so a malicious peer cannot trigger huge
+//! allocations or feed nonsensical values into the transfer state machine.
+//!
+//! Two framings are supported: NDJSON (always used for the handshake) and
+//! a length-prefixed bincode framing negotiated for bulk transfers.
+
+use crate::identity::IdentityProof;
+use crate::protocol::{
+    Capabilities, ChunkAck, ChunkMessage, CompressedChunk, DirectoryManifest, EntryKind,
+    ErrorMessage, Framing, Message, ResumeOffer, ResumeRequest, TransferComplete, TransferInit,
+    TransferResponse,
+};
+use crate::tree::{is_contained_link, is_plain_component};
+use crate::PROTOCOL_VERSION;
+use bincode::Options;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use thiserror::Error;
+use tokio::io::{AsyncBufRead, AsyncBufReadExt, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+/// Largest chunk a peer may announce or send (4 MB)
+pub const MAX_CHUNK_SIZE: usize = 4 * crate::DEFAULT_CHUNK_SIZE;
+
+/// Largest NDJSON frame accepted from a peer
+///
+/// Chunk payloads are JSON byte arrays, so a full chunk can take up to four
+/// bytes per payload byte; the remainder leaves room for the envelope.
+pub const MAX_FRAME_SIZE: usize = 4 * MAX_CHUNK_SIZE + 64 * 1024;
+
+/// Largest length-prefixed binary frame accepted from a peer
+pub const MAX_BINARY_FRAME_SIZE: usize = MAX_CHUNK_SIZE + 64 * 1024;
+
+/// Longest filename accepted in a transfer init
+pub const MAX_FILENAME_LEN: usize = 255;
+
+/// Longest free-form text field (error codes and messages)
+pub const MAX_TEXT_LEN: usize = 4096;
+
+/// Most entries in any one capability list
+pub const MAX_CAPABILITY_ENTRIES: usize = 64;
+
+/// Most missing ranges in one resume offer
+pub const MAX_RESUME_RANGES: usize = 64 * 1024;
+
+/// Most entries in one directory manifest
+pub const MAX_MANIFEST_ENTRIES: usize = 100_000;
+
+/// Errors produced while decoding a frame
+#[derive(Debug, Error)]
+pub enum DecodeError {
+    #[error("Frame of {size} bytes exceeds limit of {max} bytes")]
+    FrameTooLarge { size: usize, max: usize },
+
+    #[error("Stream ended in the middle of a frame")]
+    Truncated,
+
+    #[error("Malformed message: {0}")]
+    Malformed(#[from] serde_json::Error),
+
+    #[error("Malformed binary message: {0}")]
+    MalformedBinary(#[from] bincode::Error),
+
+    #[error("Invalid field `{field}`: {reason}")]
+    InvalidField { field: &'static str, reason: String },
+
+    #[error("Unsupported protocol version: {0}")]
+    UnsupportedVersion(String),
+
+    #[error("IO error: {0}")]
+    Io(#[from] std::io::Error),
+}
+
+/// Decode and validate a single NDJSON frame
+///
+/// A trailing newline (and carriage return) is tolerated.
+pub fn decode_message(frame: &[u8]) -> Result<Message, DecodeError> {
+    if frame.len() > MAX_FRAME_SIZE {
+        return Err(DecodeError::FrameTooLarge {
+            size: frame.len(),
+            max: MAX_FRAME_SIZE,
+        });
+    }
+
+    let frame = frame.strip_suffix(b"\n").unwrap_or(frame);
+    let frame = frame.strip_suffix(b"\r").unwrap_or(frame);
+
+    let message: Message = serde_json::from_slice(frame)?;
+    validate_message(&message)?;
+    Ok(message)
+}
+
+/// Encode a message as a single NDJSON frame (including the newline)
+pub fn encode_message(message: &Message) -> Result<Vec<u8>, serde_json::Error> {
+    let mut frame = serde_json::to_vec(message)?;
+    frame.push(b'\n');
+    Ok(frame)
+}
+
+/// Decode and validate a length-prefixed binary payload (without the prefix)
+pub fn decode_binary(payload: &[u8]) -> Result<Message, DecodeError> {
+    if payload.len() > MAX_BINARY_FRAME_SIZE {
+        return Err(DecodeError::FrameTooLarge {
+            size: payload.len(),
+            max: MAX_BINARY_FRAME_SIZE,
+        });
+    }
+
+    let wire: WireMessage = bincode_options().deserialize(payload)?;
+    let message = Message::from(wire);
+    validate_message(&message)?;
+    Ok(message)
+}
+
+/// Encode a message as a length-prefixed binary frame (including the prefix)
+pub fn encode_binary(message: &Message) -> Result<Vec<u8>, bincode::Error> {
+    let payload = bincode_options().serialize(&WireRef::from(message))?;
+    let mut frame = Vec::with_capacity(4 + payload.len());
+    frame.extend_from_slice(&(payload.len() as u32).to_be_bytes());
+    frame.extend_from_slice(&payload);
+    Ok(frame)
+}
+
+/// Encode a message using the given framing
+pub fn encode_frame(message: &Message, framing: Framing) -> Result<Vec<u8>, DecodeError> {
+    match framing {
+        Framing::Ndjson => Ok(encode_message(message)?),
+        Framing::LengthPrefixed => Ok(encode_binary(message)?),
+    }
+}
+
+fn bincode_options() -> impl Options {
+    bincode::options().with_limit(MAX_BINARY_FRAME_SIZE as u64)
+}
+
+// `Message` is internally tagged for JSON, which bincode cannot decode.
+// These mirror it with serde's default external tagging; variant order
+// must match between the two, and new variants go at the end so older
+// peers keep decoding the ones they know.
+
+#[derive(Serialize)]
+enum WireRef<'a> {
+    TransferInit(&'a TransferInit),
+    TransferResponse(&'a TransferResponse),
+    Chunk(&'a ChunkMessage),
+    ChunkAck(&'a ChunkAck),
+    TransferComplete(&'a TransferComplete),
+    Error(&'a ErrorMessage),
+    Capabilities(&'a Capabilities),
+    Identity(&'a IdentityProof),
+    ResumeRequest(&'a ResumeRequest),
+    ResumeOffer(&'a ResumeOffer),
+    Manifest(&'a DirectoryManifest),
+    CompressedChunk(&'a CompressedChunk),
+}
+
+#[derive(Deserialize)]
+enum WireMessage {
+    TransferInit(TransferInit),
+    TransferResponse(TransferResponse),
+    Chunk(ChunkMessage),
+    ChunkAck(ChunkAck),
+    TransferComplete(TransferComplete),
+    Error(ErrorMessage),
+    Capabilities(Capabilities),
+    Identity(IdentityProof),
+    ResumeRequest(ResumeRequest),
+    ResumeOffer(ResumeOffer),
+    Manifest(DirectoryManifest),
+    CompressedChunk(CompressedChunk),
+}
+
+impl<'a> From<&'a Message> for WireRef<'a> {
+    fn from(message: &'a Message) -> Self {
+        match message {
+            Message::TransferInit(m) => WireRef::TransferInit(m),
+            Message::TransferResponse(m) => WireRef::TransferResponse(m),
+            Message::Chunk(m) => WireRef::Chunk(m),
+            Message::ChunkAck(m) => WireRef::ChunkAck(m),
+            Message::TransferComplete(m) => WireRef::TransferComplete(m),
+            Message::Error(m) => WireRef::Error(m),
+            Message::Capabilities(m) => WireRef::Capabilities(m),
+            Message::Identity(m) => WireRef::Identity(m),
+            Message::ResumeRequest(m) => WireRef::ResumeRequest(m),
+            Message::ResumeOffer(m) => WireRef::ResumeOffer(m),
+            Message::Manifest(m) => WireRef::Manifest(m),
+            Message::CompressedChunk(m) => WireRef::CompressedChunk(m),
+        }
+    }
+}
+
+impl From<WireMessage> for Message {
+    fn from(wire: WireMessage) -> Self {
+        match wire {
+            WireMessage::TransferInit(m) => Message::TransferInit(m),
+            WireMessage::TransferResponse(m) => Message::TransferResponse(m),
+            WireMessage::Chunk(m) => Message::Chunk(m),
+            WireMessage::ChunkAck(m) => Message::ChunkAck(m),
+            WireMessage::TransferComplete(m) => Message::TransferComplete(m),
+            WireMessage::Error(m) => Message::Error(m),
+            WireMessage::Capabilities(m) => Message::Capabilities(m),
+            WireMessage::Identity(m) => Message::Identity(m),
+            WireMessage::ResumeRequest(m) => Message::ResumeRequest(m),
+            WireMessage::ResumeOffer(m) => Message::ResumeOffer(m),
+            WireMessage::Manifest(m) => Message::Manifest(m),
+            WireMessage::CompressedChunk(m) => Message::CompressedChunk(m),
+        }
+    }
+}
+
+/// Check field-level invariants of a decoded message
+pub fn validate_message(message: &Message) -> Result<(), DecodeError> {
+    match message {
+        Message::TransferInit(init) => validate_transfer_init(init),
+        Message::TransferResponse(_) | Message::ChunkAck(_) | Message::TransferComplete(_) => {
+            Ok(())
+        }
+        Message::Chunk(chunk) => validate_chunk(chunk),
+        Message::Error(err) => {
+            check_len("code", &err.code, MAX_TEXT_LEN)?;
+            check_len("message", &err.message, MAX_TEXT_LEN)
+        }
+        Message::Capabilities(caps) => validate_capabilities(caps),
+        // Ed25519 signatures are 64 bytes
+        Message::Identity(proof) => {
+            let signature = &proof.signature;
+            if signature.len() != 128 || !signature.bytes().all(|b| b.is_ascii_hexdigit()) {
+                return Err(invalid("signature", "must be a 128-character hex signature"));
+            }
+            Ok(())
+        }
+        Message::ResumeRequest(request) => {
+            check_chunk_size(request.chunk_size)?;
+            check_hash("merkle_root", &request.merkle_root, request.size == 0)
+        }
+        Message::ResumeOffer(offer) => validate_resume_offer(offer),
+        Message::Manifest(manifest) => validate_manifest(manifest),
+        Message::CompressedChunk(chunk) => validate_compressed_chunk(chunk),
+    }
+}
+
+/// Check a directory manifest: paths are relative and stay inside the
+/// tree, each is listed once and after its directory, and symlinks stay
+/// inside too
+///
+/// Peers exchanging manifests outside TFT messages should check them with
+/// this before creating anything.
+pub fn validate_manifest(manifest: &DirectoryManifest) -> Result<(), DecodeError> {
+    if manifest.entries.len() > MAX_MANIFEST_ENTRIES {
+        return Err(invalid(
+            "entries",
+            format!("more than {} entries", MAX_MANIFEST_ENTRIES),
+        ));
+    }
+
+    // Whether each path seen so far is a directory
+    let mut seen = HashMap::with_capacity(manifest.entries.len());
+    for entry in &manifest.entries {
+        check_len("path", &entry.path, MAX_TEXT_LEN)?;
+        check_len("path", entry.name(), MAX_FILENAME_LEN)?;
+        if !entry.path.split('/').all(is_plain_component) {
+            return Err(invalid("path", "must be a relative path without `.` or `..`"));
+        }
+        if let Some(parent) = entry.parent() {
+            if seen.get(parent) != Some(&true) {
+                return Err(invalid(
+                    "path",
+                    format!("{} is not listed after its directory", entry.path),
+                ));
+            }
+        }
+
+        let is_dir = entry.kind == EntryKind::Directory;
+        if seen.insert(entry.path.as_str(), is_dir).is_some() {
+            return Err(invalid("path", format!("{} is listed twice", entry.path)));
+        }
+        if let EntryKind::Symlink { target } = &entry.kind {
+            check_len("target", target, MAX_TEXT_LEN)?;
+            if !is_contained_link(&entry.path, target) {
+                return Err(invalid(
+                    "target",
+                    format!("{} links outside the tree", entry.path),
+                ));
+            }
+        }
+        if entry.mode.is_some_and(|mode| mode > 0o7777) {
+            return Err(invalid("mode", "must be Unix permission bits"));
+        }
+    }
+    Ok(())
+}
+
+fn validate_resume_offer(offer: &ResumeOffer) -> Result<(), DecodeError> {
+    if offer.missing.len() > MAX_RESUME_RANGES {
+        return Err(invalid(
+            "missing",
+            format!("more than {} ranges", MAX_RESUME_RANGES),
+        ));
+    }
+    let mut previous_end = 0;
+    for range in &offer.missing {
+        if range.start >= range.end || range.start < previous_end {
+            return Err(invalid("missing", "ranges must be ordered and non-empty"));
+        }
+        previous_end = range.end;
+    }
+    Ok(())
+}
+
+fn validate_capabilities(caps: &Capabilities) -> Result<(), DecodeError> {
+    if !is_compatible_version(&caps.version) {
+        return Err(DecodeError::UnsupportedVersion(caps.version.clone()));
+    }
+    if caps.max_chunk_size == 0 {
+        return Err(invalid("max_chunk_size", "must not be zero"));
+    }
+
+    let lists = [
+        ("compression", caps.compression.len()),
+        ("hash_algorithms", caps.hash_algorithms.len()),
+        ("encryption", caps.encryption.len()),
+        ("features", caps.features.len()),
+    ];
+    for (field, len) in lists {
+        if len > MAX_CAPABILITY_ENTRIES {
+            return Err(invalid(
+                field,
+                format!("more than {} entries", MAX_CAPABILITY_ENTRIES),
+            ));
+        }
+    }
+    for feature in &caps.features {
+        check_len("features", feature, MAX_TEXT_LEN)?;
+    }
+    Ok(())
+}
+
+fn validate_transfer_init(init: &TransferInit) -> Result<(), DecodeError> {
+    if !is_compatible_version(&init.version) {
+        return Err(DecodeError::UnsupportedVersion(init.version.clone()));
+    }
+
+    if init.filename.is_empty() {
+        return Err(invalid("filename", "must not be empty"));
+    }
+    check_len("filename", &init.filename, MAX_FILENAME_LEN)?;
+    if init.filename.contains(['/', '\\', '\0']) || init.filename == "." || init.filename == ".." {
+        return Err(invalid("filename", "must be a plain file name"));
+    }
+    if let Some(directory) = &init.directory {
+        check_len("directory", directory, MAX_TEXT_LEN)?;
+        let escapes = directory.split('/').any(|part| {
+            part.is_empty() || part == "." || part == ".." || part.contains(['\\', '\0', ':'])
+        });
+        if escapes {
+            return Err(invalid("directory", "must be a relative path without `.` or `..`"));
+        }
+    }
+
+    check_chunk_size(init.chunk_size)?;
+
+    let expected_chunks = init.size.div_ceil(init.chunk_size as u64);
+    if init.total_chunks as u64 != expected_chunks {
+        return Err(invalid(
+            "total_chunks",
+            format!("expected {} for {} bytes", expected_chunks, init.size),
+        ));
+    }
+
+    check_hash("merkle_root", &init.merkle_root, init.size == 0)
+}
+
+fn validate_chunk(chunk: &ChunkMessage) -> Result<(), DecodeError> {
+    if chunk.data.len() > MAX_CHUNK_SIZE {
+        return Err(invalid(
+            "data",
+            format!("{} bytes exceeds chunk limit", chunk.data.len()),
+        ));
+    }
+    check_hash("hash", &chunk.hash, false)
+}
+
+/// Both sizes are bounded by the chunk limit: the payload because chunks
+/// that don't shrink are sent plain, the original so decompression can't
+/// be made to allocate more
+fn validate_compressed_chunk(chunk: &CompressedChunk) -> Result<(), DecodeError> {
+    if chunk.size > MAX_CHUNK_SIZE {
+        return Err(invalid(
+            "size",
+            format!("{} bytes exceeds chunk limit", chunk.size),
+        ));
+    }
+    if chunk.data.len() > MAX_CHUNK_SIZE {
+        return Err(invalid(
+            "data",
+            format!("{} bytes exceeds chunk limit", chunk.data.len()),
+        ));
+    }
+    check_hash("hash", &chunk.hash, false)
+}
+
+fn check_chunk_size(chunk_size: usize) -> Result<(), DecodeError> {
+    if chunk_size == 0 || chunk_size > MAX_CHUNK_SIZE {
+        return Err(invalid(
+            "chunk_size",
+            format!("must be between 1 and {}", MAX_CHUNK_SIZE),
+        ));
+    }
+    Ok(())
+}
+
+/// Major versions must match; minor versions may differ
+fn is_compatible_version(version: &str) -> bool {
+    let major = |v: &str| v.split('.').next().map(str::to_owned);
+    match (major(version), major(PROTOCOL_VERSION)) {
+        (Some(theirs), Some(ours)) => !theirs.is_empty() && theirs == ours,
+        _ => false,
+    }
+}
+
+/// BLAKE3 hex digests are exactly 64 hex characters
+fn check_hash(field: &'static str, value: &str, allow_empty: bool) -> Result<(), DecodeError> {
+    if allow_empty && value.is_empty() {
+        return Ok(());
+    }
+    if value.len() != 64 || !value.bytes().all(|b| b.is_ascii_hexdigit()) {
+        return Err(invalid(field, "must be a 64-character hex digest"));
+    }
+    Ok(())
+}
+
+fn check_len(field: &'static str, value: &str, max: usize) -> Result<(), DecodeError> {
+    if value.len() > max {
+        return Err(invalid(field, format!("longer than {} bytes", max)));
+    }
+    Ok(())
+}
+
+fn invalid(field: &'static str, reason: impl Into<String>) -> DecodeError {
+    DecodeError::InvalidField {
+        field,
+        reason: reason.into(),
+    }
+}
+
+/// Reads size-bounded frames from an async stream
+///
+/// Unlike `read_line`, the reader stops buffering once a frame exceeds the
+/// limit. A `FrameTooLarge` error leaves the stream mid-frame, so callers
+/// should drop the connection rather than keep reading.
+///
+/// Readers start in NDJSON framing; call [`FrameReader::set_framing`] once
+/// the handshake has settled on a different one.
+pub struct FrameReader<R> {
+    inner: R,
+    max_frame_size: usize,
+    framing: Framing,
+    buf: Vec<u8>,
+}
+
+impl<R: AsyncBufRead + Unpin> FrameReader<R> {
+    /// Create a reader with the default frame limit
+    pub fn new(inner: R) -> Self {
+        Self::with_limit(inner, MAX_FRAME_SIZE)
+    }
+
+    /// Create a reader with a custom frame limit
+    pub fn with_limit(inner: R, max_frame_size: usize) -> Self {
+        Self {
+            inner,
+            max_frame_size,
+            framing: Framing::Ndjson,
+            buf: Vec::new(),
+        }
+    }
+
+    /// Switch framing for all subsequent frames
+    pub fn set_framing(&mut self, framing: Framing) {
+        self.framing = framing;
+    }
+
+    /// Framing currently used to read frames
+    pub fn framing(&self) -> Framing {
+        self.framing
+    }
+
+    /// Read the next raw frame payload; `None` on clean EOF
+    ///
+    /// For NDJSON the newline is stripped; for binary framing the length
+    /// prefix is.
+    pub async fn next_frame(&mut self) -> Result<Option<&[u8]>, DecodeError> {
+        self.buf.clear();
+
+        if self.framing == Framing::LengthPrefixed {
+            return self.next_binary_frame().await;
+        }
+
+        loop {
+            let available = self.inner.fill_buf().await?;
+            if available.is_empty() {
+                if self.buf.is_empty() {
+                    return Ok(None);
+                }
+                return Err(DecodeError::Truncated);
+            }
+
+            let (take, done) = match available.iter().position(|&b| b == b'\n') {
+                Some(pos) => (pos + 1, true),
+                None => (available.len(), false),
+            };
+
+            if self.buf.len() + take > self.max_frame_size + 1 {
+                return Err(DecodeError::FrameTooLarge {
+                    size: self.buf.len() + take,
+                    max: self.max_frame_size,
+                });
+            }
+
+            self.buf.extend_from_slice(&available[..take]);
+            self.inner.consume(take);
+
+            if done {
+                self.buf.pop();
+                return Ok(Some(&self.buf));
+            }
+        }
+    }
+
+    async fn next_binary_frame(&mut self) -> Result<Option<&[u8]>, DecodeError> {
+        let mut prefix = [0u8; 4];
+        let mut filled = 0;
+        while filled < prefix.len() {
+            match self.inner.read(&mut prefix[filled..]).await? {
+                0 if filled == 0 => return Ok(None),
+                0 => return Err(DecodeError::Truncated),
+                n => filled += n,
+            }
+        }
+
+        let len = u32::from_be_bytes(prefix) as usize;
+        let max = self.max_frame_size.min(MAX_BINARY_FRAME_SIZE);
+        if len > max {
+            return Err(DecodeError::FrameTooLarge { size: len, max });
+        }
+
+        self.buf.resize(len, 0);
+        match self.inner.read_exact(&mut self.buf).await {
+            Ok(_) => Ok(Some(&self.buf)),
+            Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => Err(DecodeError::Truncated),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Read and validate the next message; `None` on clean EOF
+    pub async fn next_message(&mut self) -> Result<Option<Message>, DecodeError> {
+        let framing = self.framing;
+        match self.next_frame().await? {
+            Some(frame) if framing == Framing::LengthPrefixed => decode_binary(frame).map(Some),
+            Some(frame) => decode_message(frame).map(Some),
+            None => Ok(None),
+        }
+    }
+}
+
+/// Writes frames to an async sink
+///
+/// Each `send` completes only once the frame has been handed to the
+/// underlying writer, so a slow peer applies backpressure to the producer
+/// instead of frames piling up in memory.
+pub struct FrameWriter<W> {
+    inner: W,
+    framing: Framing,
+}
+
+impl<W: AsyncWrite + Unpin> FrameWriter<W> {
+    /// Create a writer in NDJSON framing
+    pub fn new(inner: W) -> Self {
+        Self {
+            inner,
+            framing: Framing::Ndjson,
+        }
+    }
+
+    /// Switch framing for all subsequent frames
+    pub fn set_framing(&mut self, framing: Framing) {
+        self.framing = framing;
+    }
+
+    /// Framing currently used to write frames
+    pub fn framing(&self) -> Framing {
+        self.framing
+    }
+
+    /// Encode and write a single message
+    pub async fn send(&mut self, message: &Message) -> Result<(), DecodeError> {
+        let frame = encode_frame(message, self.framing)?;
+        self.inner.write_all(&frame).await?;
+        Ok(())
+    }
+
+    /// Flush buffered frames to the peer
+    pub async fn flush(&mut self) -> Result<(), DecodeError> {
+        self.inner.flush().await?;
+        Ok(())
+    }
+
+    /// Recover the underlying writer
+    pub fn into_inner(self) -> W {
+        self.inner
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::identity::{Challenge, Identity};
+    use crate::protocol::{
+        ChunkRange, CompressionType, EncryptionMode, HashAlgorithm, ManifestEntry,
+    };
+    use uuid::Uuid;
+
+    fn init(size: u64, chunk_size: usize, total_chunks: usize) -> Message {
+        Message::TransferInit(TransferInit {
+            version: PROTOCOL_VERSION.to_string(),
+            transfer_id: Uuid::new_v4(),
+            filename: "report.pdf".to_string(),
+            size,
+            chunk_size,
+            total_chunks,
+            merkle_root: "a".repeat(64),
+            encrypted: false,
+            compression: CompressionType::None,
+            framings: Framing::SUPPORTED.to_vec(),
+            compressions: CompressionType::SUPPORTED.to_vec(),
+            compression_level: None,
+            directory: None,
+        })
+    }
+
+    fn chunk(len: usize) -> Message {
+        Message::Chunk(ChunkMessage {
+            transfer_id: Uuid::new_v4(),
+            chunk_index: 0,
+            data: vec![7u8; len],
+            hash: "b".repeat(64),
+        })
+    }
+
+    #[test]
+    fn test_roundtrip() {
+        let frame = encode_message(&init(10, 4, 3)).unwrap();
+        assert!(matches!(decode_message(&frame), Ok(Message::TransferInit(_))));
+    }
+
+    #[test]
+    fn test_rejects_inconsistent_chunk_count() {
+        let frame = encode_message(&init(10, 4, 1000)).unwrap();
+        assert!(matches!(
+            decode_message(&frame),
+            Err(DecodeError::InvalidField { field: "total_chunks", .. })
+        ));
+
+        let frame = encode_message(&init(10, 0, 0)).unwrap();
+        assert!(matches!(
+            decode_message(&frame),
+            Err(DecodeError::InvalidField { field: "chunk_size", .. })
+        ));
+    }
+
+    #[test]
+    fn test_rejects_path_traversal_and_bad_version() {
+        let mut msg = init(10, 4, 3);
+        if let Message::TransferInit(ref mut i) = msg {
+            i.filename = "../etc/passwd".to_string();
+        }
+        assert!(decode_message(&encode_message(&msg).unwrap()).is_err());
+
+        let directories = [("docs/2024", true), ("docs/../..", false), ("/etc", false), ("C:", false)];
+        for (directory, ok) in directories {
+            let mut msg = init(10, 4, 3);
+            if let Message::TransferInit(ref mut i) = msg {
+                i.directory = Some(directory.to_string());
+            }
+            let decoded = decode_message(&encode_message(&msg).unwrap());
+            assert_eq!(decoded.is_ok(), ok, "{}", directory);
+        }
+
+        let mut msg = init(10, 4, 3);
+        if let Message::TransferInit(ref mut i) = msg {
+            i.version = "2.0".to_string();
+        }
+        assert!(matches!(
+            decode_message(&encode_message(&msg).unwrap()),
+            Err(DecodeError::UnsupportedVersion(_))
+        ));
+    }
+
+    #[test]
+    fn test_garbage_does_not_panic() {
+        for input in [&b""[..], b"{", b"null", b"{\"type\":\"chunk\"}", &[0xff, 0xfe, 0x00]] {
+            assert!(decode_message(input).is_err());
+        }
+    }
+
+    #[tokio::test]
+    async fn test_frame_reader_enforces_limit() {
+        let data = b"{\"type\":\"chunk_ack\"}\n";
+        let mut reader = FrameReader::with_limit(&data[..], 8);
+        assert!(matches!(
+            reader.next_frame().await,
+            Err(DecodeError::FrameTooLarge { .. })
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_frame_reader_reads_messages_until_eof() {
+        let mut data = encode_message(&init(10, 4, 3)).unwrap();
+        data.extend(encode_message(&init(0, 4, 0)).unwrap());
+
+        let mut reader = FrameReader::new(&data[..]);
+        assert!(reader.next_message().await.unwrap().is_some());
+        assert!(reader.next_message().await.unwrap().is_some());
+        assert!(reader.next_message().await.unwrap().is_none());
+
+        let mut truncated = FrameReader::new(&b"{\"type\""[..]);
+        assert!(matches!(truncated.next_frame().await, Err(DecodeError::Truncated)));
+    }
+
+    #[test]
+    fn test_binary_roundtrip_is_smaller() {
+        let message = chunk(4096);
+        let binary = encode_binary(&message).unwrap();
+        let json = encode_message(&message).unwrap();
+        assert!(binary.len() < json.len());
+
+        match decode_binary(&binary[4..]).unwrap() {
+            Message::Chunk(c) => assert_eq!(c.data.len(), 4096),
+            other => panic!("unexpected message: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_negotiate_framing() {
+        assert_eq!(Framing::negotiate(&[Framing::LengthPrefixed]), Framing::LengthPrefixed);
+        assert_eq!(Framing::negotiate(&[Framing::Ndjson]), Framing::Ndjson);
+        assert_eq!(Framing::negotiate(&[]), Framing::Ndjson);
+    }
+
+    #[test]
+    fn test_negotiate_compression() {
+        use CompressionType::{Lz4, Zstd};
+
+        assert_eq!(CompressionType::negotiate(&[Lz4, Zstd], CompressionType::SUPPORTED), Lz4);
+        assert_eq!(CompressionType::negotiate(&[Lz4, Zstd], &[Zstd]), Zstd);
+        assert_eq!(
+            CompressionType::negotiate(&[], CompressionType::SUPPORTED),
+            CompressionType::None
+        );
+
+        // Compressions a newer sender offers that we don't know are skipped
+        let json = String::from_utf8(encode_message(&init(10, 4, 3)).unwrap()).unwrap();
+        let frame = json.replace(r#"["zstd","lz4"]"#, r#"["brotli","lz4"]"#);
+        match decode_message(frame.as_bytes()).unwrap() {
+            Message::TransferInit(init) => assert_eq!(init.compressions, vec![Lz4]),
+            other => panic!("unexpected message: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_compressed_chunk_roundtrip_and_validate() {
+        let compressed = |size: usize| {
+            Message::CompressedChunk(CompressedChunk {
+                transfer_id: Uuid::new_v4(),
+                chunk_index: 0,
+                size,
+                data: vec![7u8; 64],
+                hash: "b".repeat(64),
+            })
+        };
+        match decode_binary(&encode_binary(&compressed(4096)).unwrap()[4..]).unwrap() {
+            Message::CompressedChunk(c) => assert_eq!((c.size, c.data.len()), (4096, 64)),
+            other => panic!("unexpected message: {:?}", other),
+        }
+
+        let frame = encode_message(&compressed(MAX_CHUNK_SIZE + 1)).unwrap();
+        assert!(matches!(
+            decode_message(&frame),
+            Err(DecodeError::InvalidField { field: "size", .. })
+        ));
+    }
+
+    #[test]
+    fn test_capabilities_tolerate_newer_and_older_peers() {
+        // A newer peer: an unknown compression, an unknown field
+        let frame = br#"{"type":"capabilities","version":"1.3","compression":["brotli","zstd"],"resume":true,"max_chunk_size":65536,"streams":8}"#;
+        let peer = match decode_message(frame).unwrap() {
+            Message::Capabilities(caps) => caps,
+            other => panic!("unexpected message: {:?}", other),
+        };
+        assert_eq!(peer.compression, vec![CompressionType::Zstd]);
+        assert_eq!(peer.hash_algorithms, vec![HashAlgorithm::Blake3]);
+
+        let agreed = Capabilities::local().with_feature("sparse").negotiate(&peer);
+        assert_eq!(agreed.compression, vec![CompressionType::Zstd]);
+        assert_eq!(agreed.encryption, vec![EncryptionMode::None]);
+        assert!(agreed.resume && !agreed.batch);
+        assert_eq!(agreed.max_chunk_size, 65536);
+        assert!(!agreed.supports("sparse"));
+
+        // An older peer never sends capabilities at all
+        let agreed = Capabilities::local().negotiate(&Capabilities::default());
+        assert_eq!(agreed, Capabilities::default());
+    }
+
+    #[test]
+    fn test_identity_handshake() {
+        let identity = Identity::from_pkcs8(&Identity::generate_pkcs8().unwrap()).unwrap();
+        let ours = Capabilities {
+            challenge: Some(Challenge::random().unwrap()),
+            ..Capabilities::local()
+        };
+        let theirs = match decode_message(&encode_message(&Message::Capabilities(ours.clone())).unwrap()) {
+            Ok(Message::Capabilities(caps)) => caps,
+            other => panic!("unexpected message: {:?}", other),
+        };
+        assert_eq!(theirs.challenge, ours.challenge);
+        assert_eq!(ours.negotiate(&theirs).challenge, None);
+
+        let answer = Message::Identity(identity.prove(&theirs.challenge.unwrap()));
+        match decode_binary(&encode_binary(&answer).unwrap()[4..]).unwrap() {
+            Message::Identity(proof) => {
+                assert_eq!(proof.verify(&ours.challenge.unwrap()).unwrap(), identity.peer_id())
+            }
+            other => panic!("unexpected message: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_capabilities_roundtrip_both_framings() {
+        let caps = Message::Capabilities(Capabilities::local().with_feature("sparse"));
+        let json = decode_message(&encode_message(&caps).unwrap()).unwrap();
+        let binary = decode_binary(&encode_binary(&caps).unwrap()[4..]).unwrap();
+        for decoded in [json, binary] {
+            match decoded {
+                Message::Capabilities(c) => {
+                    assert_eq!(c, Capabilities::local().with_feature("sparse"))
+                }
+                other => panic!("unexpected message: {:?}", other),
+            }
+        }
+
+        let mut bad = Capabilities::local();
+        bad.version = "2.0".to_string();
+        let frame = encode_message(&Message::Capabilities(bad)).unwrap();
+        assert!(matches!(decode_message(&frame), Err(DecodeError::UnsupportedVersion(_))));
+    }
+
+    #[test]
+    fn test_resume_messages_roundtrip_and_validate() {
+        let transfer_id = Uuid::new_v4();
+        let request = Message::ResumeRequest(ResumeRequest {
+            transfer_id,
+            size: 10,
+            chunk_size: 4,
+            merkle_root: "a".repeat(64),
+        });
+        let offer = |missing: Vec<ChunkRange>| {
+            Message::ResumeOffer(ResumeOffer {
+                transfer_id,
+                accepted: true,
+                missing,
+                framing: Framing::LengthPrefixed,
+            })
+        };
+        let range = |start, end| ChunkRange { start, end };
+
+        let json = decode_message(&encode_message(&request).unwrap()).unwrap();
+        assert!(matches!(json, Message::ResumeRequest(r) if r.transfer_id == transfer_id));
+        let good = offer(vec![range(0, 1), range(2, 3)]);
+        match decode_binary(&encode_binary(&good).unwrap()[4..]).unwrap() {
+            Message::ResumeOffer(o) => assert_eq!(o.missing_chunks(3).collect::<Vec<_>>(), [0, 2]),
+            other => panic!("unexpected message: {:?}", other),
+        }
+
+        let bad = [
+            vec![range(1, 1)],
+            vec![range(2, 3), range(0, 1)],
+            vec![range(0, 2), range(1, 3)],
+        ];
+        for missing in bad {
+            let frame = encode_message(&offer(missing)).unwrap();
+            assert!(matches!(
+                decode_message(&frame),
+                Err(DecodeError::InvalidField { field: "missing", .. })
+            ));
+        }
+    }
+
+    #[test]
+    fn test_manifest_roundtrip_and_validate() {
+        let entry = |path: &str, kind: EntryKind| ManifestEntry {
+            path: path.to_string(),
+            kind,
+            mode: Some(0o755),
+            mtime: None,
+        };
+        let link = |target: &str| EntryKind::Symlink {
+            target: target.to_string(),
+        };
+        let manifest = |entries| {
+            Message::Manifest(DirectoryManifest {
+                manifest_id: Uuid::new_v4(),
+                entries,
+            })
+        };
+
+        let good = manifest(vec![
+            entry("docs", EntryKind::Directory),
+            entry("docs/report.txt", EntryKind::File { size: 9 }),
+            entry("docs/readme", link("report.txt")),
+        ]);
+        match decode_binary(&encode_binary(&good).unwrap()[4..]).unwrap() {
+            Message::Manifest(m) => assert_eq!(m.total_size(), 9),
+            other => panic!("unexpected message: {:?}", other),
+        }
+        assert!(decode_message(&encode_message(&good).unwrap()).is_ok());
+
+        let bad = [
+            // File before its directory, and under a file or link
+            (vec![entry("docs/report.txt", EntryKind::File { size: 1 })], "path"),
+            (
+                vec![entry("docs", link("other")), entry("docs/x", EntryKind::Directory)],
+                "path",
+            ),
+            (vec![entry("../etc", EntryKind::Directory)], "path"),
+            (
+                vec![entry("a", EntryKind::Directory), entry("a", EntryKind::Directory)],
+                "path",
+            ),
+            (vec![entry("latest", link("../outside"))], "target"),
+            (vec![entry("latest", link("/etc"))], "target"),
+        ];
+        for (entries, field) in bad {
+            let frame = encode_message(&manifest(entries)).unwrap();
+            match decode_message(&frame) {
+                Err(DecodeError::InvalidField { field: f, .. }) => assert_eq!(f, field),
+                other => panic!("expected an invalid {}, got {:?}", field, other),
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_switch_to_binary_after_handshake() {
+        let mut writer = FrameWriter::new(Vec::new());
+        writer.send(&init(10, 4, 3)).await.unwrap();
+        writer.set_framing(Framing::LengthPrefixed);
+        writer.send(&chunk(16)).await.unwrap();
+        let data = writer.into_inner();
+
+        let mut reader = FrameReader::new(&data[..]);
+        assert!(matches!(reader.next_message().await, Ok(Some(Message::TransferInit(_)))));
+        reader.set_framing(Framing::LengthPrefixed);
+        assert!(matches!(reader.next_message().await, Ok(Some(Message::Chunk(_)))));
+        assert!(reader.next_message().await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_binary_length_prefix_is_bounded() {
+        let data = (u32::MAX).to_be_bytes();
+        let mut reader = FrameReader::new(&data[..]);
+        reader.set_framing(Framing::LengthPrefixed);
+        assert!(matches!(
+            reader.next_frame().await,
+            Err(DecodeError::FrameTooLarge { .. })
+        ));
+    }
+}
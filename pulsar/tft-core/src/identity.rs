@@ -0,0 +1,210 @@
+//! Peer identities
+//!
+//! Each endpoint holds a long-lived Ed25519 key and is known to others by
+//! its public half, the [`PeerId`]. An endpoint proves it holds the key by
+//! signing a fresh [`Challenge`] from its peer, so a recorded proof can't
+//! be replayed in another session.
+
+use ring::rand::{SecureRandom, SystemRandom};
+use ring::signature::{self, Ed25519KeyPair, KeyPair};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::fmt;
+use std::str::FromStr;
+use thiserror::Error;
+
+/// Prefixed to every signed challenge so the key never signs anything
+/// that could be mistaken for another protocol's message
+const DOMAIN: &[u8] = b"tft identity v1\0";
+
+#[derive(Debug, Error)]
+pub enum IdentityError {
+    #[error("Invalid identity key: {0}")]
+    InvalidKey(String),
+
+    #[error("Malformed {0}")]
+    Malformed(&'static str),
+
+    #[error("Signature does not match peer {0}")]
+    BadSignature(PeerId),
+
+    #[error("Failed to generate randomness")]
+    Random,
+}
+
+/// An endpoint's Ed25519 public key, written as 64 hex characters
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct PeerId([u8; 32]);
+
+impl PeerId {
+    pub fn as_bytes(&self) -> &[u8; 32] {
+        &self.0
+    }
+}
+
+/// Random bytes an endpoint asks its peer to sign
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Challenge([u8; 32]);
+
+impl Challenge {
+    pub fn random() -> Result<Self, IdentityError> {
+        let mut bytes = [0; 32];
+        SystemRandom::new().fill(&mut bytes).map_err(|_| IdentityError::Random)?;
+        Ok(Self(bytes))
+    }
+}
+
+/// Proof that `peer_id` signed a challenge
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct IdentityProof {
+    pub peer_id: PeerId,
+    /// Ed25519 signature, hex
+    pub signature: String,
+}
+
+impl IdentityProof {
+    /// The proven identity, if the signature covers `challenge`
+    pub fn verify(&self, challenge: &Challenge) -> Result<PeerId, IdentityError> {
+        let signature = decode_hex(&self.signature).ok_or(IdentityError::Malformed("signature"))?;
+        signature::UnparsedPublicKey::new(&signature::ED25519, self.peer_id.as_bytes())
+            .verify(&signed_bytes(challenge), &signature)
+            .map_err(|_| IdentityError::BadSignature(self.peer_id))?;
+        Ok(self.peer_id)
+    }
+}
+
+/// An endpoint's identity key
+pub struct Identity {
+    key_pair: Ed25519KeyPair,
+}
+
+impl Identity {
+    /// A new key, PKCS#8 encoded for storage
+    pub fn generate_pkcs8() -> Result<Vec<u8>, IdentityError> {
+        Ed25519KeyPair::generate_pkcs8(&SystemRandom::new())
+            .map(|document| document.as_ref().to_vec())
+            .map_err(|_| IdentityError::Random)
+    }
+
+    pub fn from_pkcs8(pkcs8: &[u8]) -> Result<Self, IdentityError> {
+        let key_pair =
+            Ed25519KeyPair::from_pkcs8(pkcs8).map_err(|e| IdentityError::InvalidKey(e.to_string()))?;
+        Ok(Self { key_pair })
+    }
+
+    pub fn peer_id(&self) -> PeerId {
+        let mut bytes = [0; 32];
+        bytes.copy_from_slice(self.key_pair.public_key().as_ref());
+        PeerId(bytes)
+    }
+
+    /// Answer a peer's challenge
+    pub fn prove(&self, challenge: &Challenge) -> IdentityProof {
+        IdentityProof {
+            peer_id: self.peer_id(),
+            signature: encode_hex(self.key_pair.sign(&signed_bytes(challenge)).as_ref()),
+        }
+    }
+}
+
+impl fmt::Debug for Identity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Identity").field("peer_id", &self.peer_id()).finish()
+    }
+}
+
+fn signed_bytes(challenge: &Challenge) -> Vec<u8> {
+    [DOMAIN, &challenge.0[..]].concat()
+}
+
+// Both keys and challenges travel as hex, like the protocol's hashes
+
+macro_rules! hex_bytes {
+    ($type:ident, $what:literal) => {
+        impl fmt::Display for $type {
+            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                f.write_str(&encode_hex(&self.0))
+            }
+        }
+
+        impl FromStr for $type {
+            type Err = IdentityError;
+
+            fn from_str(s: &str) -> Result<Self, Self::Err> {
+                decode_hex(s)
+                    .and_then(|bytes| bytes.try_into().ok())
+                    .map(Self)
+                    .ok_or(IdentityError::Malformed($what))
+            }
+        }
+
+        impl Serialize for $type {
+            fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+                serializer.collect_str(self)
+            }
+        }
+
+        impl<'de> Deserialize<'de> for $type {
+            fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+                String::deserialize(deserializer)?
+                    .parse()
+                    .map_err(serde::de::Error::custom)
+            }
+        }
+    };
+}
+
+hex_bytes!(PeerId, "peer ID");
+hex_bytes!(Challenge, "challenge");
+
+fn encode_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn decode_hex(s: &str) -> Option<Vec<u8>> {
+    if !s.len().is_multiple_of(2) || !s.is_ascii() {
+        return None;
+    }
+    (0..s.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&s[i..i + 2], 16).ok())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_prove_and_verify() {
+        let identity = Identity::from_pkcs8(&Identity::generate_pkcs8().unwrap()).unwrap();
+        let challenge = Challenge::random().unwrap();
+
+        let proof = identity.prove(&challenge);
+        assert_eq!(proof.verify(&challenge).unwrap(), identity.peer_id());
+
+        // A proof is only good for the challenge it answered
+        let other = Challenge::random().unwrap();
+        assert!(matches!(proof.verify(&other), Err(IdentityError::BadSignature(_))));
+
+        // Nor can it be passed off as someone else's
+        let impostor = Identity::from_pkcs8(&Identity::generate_pkcs8().unwrap()).unwrap();
+        let forged = IdentityProof {
+            peer_id: impostor.peer_id(),
+            ..proof
+        };
+        assert!(forged.verify(&challenge).is_err());
+    }
+
+    #[test]
+    fn test_peer_id_text_form() {
+        let identity = Identity::from_pkcs8(&Identity::generate_pkcs8().unwrap()).unwrap();
+        let text = identity.peer_id().to_string();
+        assert_eq!(text.len(), 64);
+        assert_eq!(text.parse::<PeerId>().unwrap(), identity.peer_id());
+
+        let json = serde_json::to_string(&identity.peer_id()).unwrap();
+        assert_eq!(json, format!("\"{}\"", text));
+        assert!("abc".parse::<PeerId>().is_err());
+        assert!("zz".repeat(32).parse::<PeerId>().is_err());
+    }
+}
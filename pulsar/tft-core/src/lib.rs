@@ -1,20 +1,41 @@
 //! TFT (Terminal File Transfer) Protocol Core
 //!
 //! This crate provides the core protocol implementation for TFT, including:
-//! - NDJSON message definitions
-//! - File chunking and integrity verification
+//! - NDJSON message definitions and capability negotiation
+//! - Strict, size-bounded message decoding (NDJSON and length-prefixed binary)
+//! - File chunking (buffered or memory-mapped) and integrity verification
+//! - Per-chunk zstd or LZ4 compression, skipped for incompressible files
 //! - Encryption/decryption primitives
+//! - Ed25519 peer identities
 //! - Merkle tree construction for chunk verification
+//! - Resuming interrupted transfers from per-chunk state
+//! - Directory trees described by a manifest of paths, modes, symlinks and
+//!   mtimes
+//! - Parallel file hashing that overlaps disk reads with hashing
 
 pub mod protocol;
+pub mod codec;
 pub mod chunking;
 pub mod crypto;
+pub mod identity;
 pub mod merkle;
+pub mod resume;
+pub mod tree;
+pub mod verify;
 
-pub use protocol::{Message, MessageType};
-pub use chunking::{FileChunker, ChunkInfo};
+pub use protocol::{
+    Capabilities, ChunkRange, DirectoryManifest, EntryKind, Framing, ManifestEntry, Message,
+    MessageType,
+};
+pub use codec::{decode_message, encode_message, DecodeError, FrameReader, FrameWriter};
+pub use chunking::{
+    ChunkCompressor, ChunkInfo, ChunkStrategy, ChunkedFile, ChunkerConfig, FileChunker,
+};
 pub use crypto::{EncryptionKey, encrypt_chunk, decrypt_chunk};
+pub use identity::{Challenge, Identity, IdentityError, IdentityProof, PeerId};
 pub use merkle::MerkleTree;
+pub use resume::TransferState;
+pub use verify::{hash_file, FileHashes};
 
 /// TFT protocol version
 pub const PROTOCOL_VERSION: &str = "1.0";
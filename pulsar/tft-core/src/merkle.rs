@@ -4,7 +4,6 @@ use blake3::Hasher;
 
 pub struct MerkleTree {
     root: String,
-    #[allow(dead_code)]
     leaves: Vec<String>,
 }
 
@@ -21,6 +20,11 @@ impl MerkleTree {
         &self.root
     }
 
+    /// The chunk hashes the tree was built from
+    pub fn leaves(&self) -> &[String] {
+        &self.leaves
+    }
+
     fn compute_root(hashes: &[String]) -> String {
         if hashes.is_empty() {
             return String::new();
@@ -1,5 +1,6 @@
 //! TFT Protocol Message Definitions
 
+use crate::identity::{Challenge, IdentityProof};
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 use chrono::{DateTime, Utc};
@@ -20,10 +21,44 @@ pub enum Message {
     TransferComplete(TransferComplete),
     /// Error occurred
     Error(ErrorMessage),
+    /// Features the sender supports, exchanged at session start
+    Capabilities(Capabilities),
+    /// Answer to the challenge in the peer's capabilities
+    Identity(IdentityProof),
+    /// Continue an interrupted transfer, sent in place of `TransferInit`
+    ResumeRequest(ResumeRequest),
+    /// The chunks the receiver still needs to continue a transfer
+    ResumeOffer(ResumeOffer),
+    /// A directory tree, sent ahead of its files
+    Manifest(DirectoryManifest),
+    /// Send a file chunk compressed
+    CompressedChunk(CompressedChunk),
+}
+
+impl Message {
+    pub fn message_type(&self) -> MessageType {
+        match self {
+            Message::TransferInit(_) => MessageType::TransferInit,
+            Message::TransferResponse(_) => MessageType::TransferResponse,
+            Message::Chunk(_) => MessageType::Chunk,
+            Message::ChunkAck(_) => MessageType::ChunkAck,
+            Message::TransferComplete(_) => MessageType::TransferComplete,
+            Message::Error(_) => MessageType::Error,
+            Message::Capabilities(_) => MessageType::Capabilities,
+            Message::Identity(_) => MessageType::Identity,
+            Message::ResumeRequest(_) => MessageType::ResumeRequest,
+            Message::ResumeOffer(_) => MessageType::ResumeOffer,
+            Message::Manifest(_) => MessageType::Manifest,
+            Message::CompressedChunk(_) => MessageType::CompressedChunk,
+        }
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct TransferInit {
+    /// Protocol version spoken by the sender
+    #[serde(default = "default_version")]
+    pub version: String,
     pub transfer_id: Uuid,
     pub filename: String,
     pub size: u64,
@@ -32,6 +67,29 @@ pub struct TransferInit {
     pub merkle_root: String,
     pub encrypted: bool,
     pub compression: CompressionType,
+    /// Framings the sender can switch to after the handshake, in preference order
+    #[serde(default = "default_framings")]
+    pub framings: Vec<Framing>,
+    /// Chunk compressions the sender can switch to after the handshake, in
+    /// preference order
+    #[serde(default, with = "names")]
+    pub compressions: Vec<CompressionType>,
+    /// Level the sender would like to compress at; `None` for the
+    /// algorithm's default
+    #[serde(default)]
+    pub compression_level: Option<i32>,
+    /// Where the file goes under the receiver's destination, as a relative
+    /// `/`-separated path; `None` puts it in the destination itself
+    #[serde(default)]
+    pub directory: Option<String>,
+}
+
+fn default_version() -> String {
+    crate::PROTOCOL_VERSION.to_string()
+}
+
+fn default_framings() -> Vec<Framing> {
+    vec![Framing::Ndjson]
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -39,6 +97,16 @@ pub struct TransferResponse {
     pub transfer_id: Uuid,
     pub accepted: bool,
     pub resume_from_chunk: Option<usize>,
+    /// Framing both sides use for every message after this response
+    #[serde(default)]
+    pub framing: Framing,
+    /// Compression for the chunks that follow, one of those the init offered
+    #[serde(default)]
+    pub compression: CompressionType,
+    /// Level the sender compresses at; `None` for the algorithm's default
+    /// or an algorithm without levels
+    #[serde(default)]
+    pub compression_level: Option<i32>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -49,6 +117,20 @@ pub struct ChunkMessage {
     pub hash: String,
 }
 
+/// A chunk compressed with what the `TransferResponse` settled on
+///
+/// `hash` is of the chunk before compression, as in a [`ChunkMessage`].
+/// Chunks that compression wouldn't shrink go as a plain `Chunk` instead.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CompressedChunk {
+    pub transfer_id: Uuid,
+    pub chunk_index: usize,
+    /// Length of the chunk once decompressed
+    pub size: usize,
+    pub data: Vec<u8>,
+    pub hash: String,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ChunkAck {
     pub transfer_id: Uuid,
@@ -63,6 +145,133 @@ pub struct TransferComplete {
     pub total_bytes: u64,
 }
 
+/// Ask the receiver to continue an interrupted transfer
+///
+/// Describes the file as it is now; the receiver only keeps what it has if
+/// that still matches the transfer it was receiving.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ResumeRequest {
+    pub transfer_id: Uuid,
+    pub size: u64,
+    pub chunk_size: usize,
+    pub merkle_root: String,
+}
+
+/// Answer to a [`ResumeRequest`]
+///
+/// When not accepted the receiver has nothing usable and the sender starts
+/// over with a `TransferInit`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ResumeOffer {
+    pub transfer_id: Uuid,
+    pub accepted: bool,
+    /// Chunks still to send, in order and without overlaps
+    pub missing: Vec<ChunkRange>,
+    /// Framing both sides use for every message after this offer
+    #[serde(default)]
+    pub framing: Framing,
+}
+
+impl ResumeOffer {
+    /// Indices of the missing chunks, ignoring any at or past `total_chunks`
+    pub fn missing_chunks(&self, total_chunks: usize) -> impl Iterator<Item = usize> + '_ {
+        self.missing
+            .iter()
+            .flat_map(move |range| range.start.min(total_chunks)..range.end.min(total_chunks))
+    }
+}
+
+/// Chunks `start` up to but not including `end`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ChunkRange {
+    pub start: usize,
+    pub end: usize,
+}
+
+impl ChunkRange {
+    pub fn len(&self) -> usize {
+        self.end.saturating_sub(self.start)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+/// A directory tree to transfer
+///
+/// Sent before the first file's `TransferInit`. Directories and symlinks
+/// are created from the manifest alone; each regular file then follows as
+/// a transfer of its own, in the order listed, with its parent as the
+/// init's `directory`. Modes and mtimes are left out when the sender
+/// strips metadata, and the receiver applies whatever is present once the
+/// last file has arrived.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DirectoryManifest {
+    pub manifest_id: Uuid,
+    /// Everything under the root, each directory before its contents
+    pub entries: Vec<ManifestEntry>,
+}
+
+impl DirectoryManifest {
+    /// The regular files, each of which follows as a transfer
+    pub fn files(&self) -> impl Iterator<Item = &ManifestEntry> {
+        self.entries
+            .iter()
+            .filter(|entry| matches!(entry.kind, EntryKind::File { .. }))
+    }
+
+    /// Bytes in all the files together
+    pub fn total_size(&self) -> u64 {
+        self.entries
+            .iter()
+            .map(|entry| match entry.kind {
+                EntryKind::File { size } => size,
+                _ => 0,
+            })
+            .sum()
+    }
+}
+
+/// A file, directory or symlink under the root of a [`DirectoryManifest`]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ManifestEntry {
+    /// `/`-separated path relative to the root
+    pub path: String,
+    pub kind: EntryKind,
+    /// Unix permission bits
+    #[serde(default)]
+    pub mode: Option<u32>,
+    /// Last modification time
+    #[serde(default)]
+    pub mtime: Option<DateTime<Utc>>,
+}
+
+impl ManifestEntry {
+    /// The directory holding the entry, or `None` for the root
+    pub fn parent(&self) -> Option<&str> {
+        self.path.rsplit_once('/').map(|(parent, _)| parent)
+    }
+
+    /// The entry's own name, the last part of its path
+    pub fn name(&self) -> &str {
+        self.path.rsplit('/').next().unwrap_or_default()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EntryKind {
+    Directory,
+    File {
+        size: u64,
+    },
+    /// A relative link that stays inside the tree
+    Symlink {
+        target: String,
+    },
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ErrorMessage {
     pub transfer_id: Option<Uuid>,
@@ -70,11 +279,266 @@ pub struct ErrorMessage {
     pub message: String,
 }
 
-#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+/// Wire framing for protocol messages
+///
+/// The handshake (`TransferInit` / `TransferResponse`) is always NDJSON so
+/// it stays debuggable; the responder then picks the framing for the rest
+/// of the session.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Framing {
+    /// Newline-delimited JSON
+    #[default]
+    Ndjson,
+    /// Big-endian u32 length prefix followed by a bincode payload
+    LengthPrefixed,
+}
+
+impl Framing {
+    /// Framings this implementation supports, most preferred first
+    pub const SUPPORTED: &'static [Framing] = &[Framing::LengthPrefixed, Framing::Ndjson];
+
+    /// Pick the first offered framing we support, falling back to NDJSON
+    pub fn negotiate(offered: &[Framing]) -> Framing {
+        offered
+            .iter()
+            .copied()
+            .find(|f| Self::SUPPORTED.contains(f))
+            .unwrap_or_default()
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
 #[serde(rename_all = "lowercase")]
 pub enum CompressionType {
+    #[default]
     None,
     Zstd,
+    Lz4,
+}
+
+impl CompressionType {
+    /// Chunk compressions this implementation supports, most preferred first
+    pub const SUPPORTED: &'static [CompressionType] =
+        &[CompressionType::Zstd, CompressionType::Lz4];
+
+    /// Pick the first offered compression that is also accepted, falling
+    /// back to none
+    pub fn negotiate(offered: &[CompressionType], accepted: &[CompressionType]) -> CompressionType {
+        offered
+            .iter()
+            .copied()
+            .find(|c| accepted.contains(c))
+            .unwrap_or_default()
+    }
+}
+
+/// Digest used for chunk and file hashes
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum HashAlgorithm {
+    Blake3,
+    Sha256,
+}
+
+/// Payload encryption applied to chunks
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum EncryptionMode {
+    None,
+    ChaCha20Poly1305,
+}
+
+/// Features a peer supports
+///
+/// Each side sends one before its first transfer and both settle on
+/// [`Capabilities::negotiate`] of the pair, so a feature is only used once
+/// both ends advertise it. A peer that predates this message answers it
+/// with an `Error`; treat that peer as [`Capabilities::default`], which is
+/// what every 1.0 implementation speaks.
+///
+/// A side that wants to know who it is talking to sets `challenge`; the
+/// peer answers with an `Identity` message signing it.
+///
+/// Lists are in preference order. Missing fields take their defaults and
+/// values this side doesn't recognise are dropped, so newer peers can
+/// advertise more without older ones rejecting the message.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Capabilities {
+    /// Protocol version spoken by the sender
+    pub version: String,
+    #[serde(with = "names")]
+    pub compression: Vec<CompressionType>,
+    #[serde(with = "names")]
+    pub hash_algorithms: Vec<HashAlgorithm>,
+    #[serde(with = "names")]
+    pub encryption: Vec<EncryptionMode>,
+    /// Resuming a transfer from its first missing chunk
+    pub resume: bool,
+    /// Grouping transfers into a batch
+    pub batch: bool,
+    /// Largest chunk the sender will accept
+    pub max_chunk_size: usize,
+    /// Named flags for features that need no parameters
+    pub features: Vec<String>,
+    /// For the peer to sign with its identity key
+    pub challenge: Option<Challenge>,
+}
+
+impl Default for Capabilities {
+    fn default() -> Self {
+        Self {
+            version: crate::PROTOCOL_VERSION.to_string(),
+            compression: vec![CompressionType::None],
+            hash_algorithms: vec![HashAlgorithm::Blake3],
+            encryption: vec![EncryptionMode::None],
+            resume: false,
+            batch: false,
+            max_chunk_size: crate::DEFAULT_CHUNK_SIZE,
+            features: Vec::new(),
+            challenge: None,
+        }
+    }
+}
+
+impl Capabilities {
+    /// Feature flag for resuming with `ResumeRequest` / `ResumeOffer`,
+    /// which skips every chunk already received rather than only a prefix
+    pub const RESUME_OFFER: &'static str = "resume_offer";
+
+    /// Feature flag for sending a `Manifest` ahead of a directory's files
+    pub const DIRECTORY_MANIFEST: &'static str = "directory_manifest";
+
+    /// What this implementation supports
+    pub fn local() -> Self {
+        Self {
+            compression: vec![
+                CompressionType::Zstd,
+                CompressionType::Lz4,
+                CompressionType::None,
+            ],
+            encryption: vec![EncryptionMode::ChaCha20Poly1305, EncryptionMode::None],
+            resume: true,
+            max_chunk_size: crate::codec::MAX_CHUNK_SIZE,
+            ..Self::default()
+        }
+        .with_feature(Self::RESUME_OFFER)
+        .with_feature(Self::DIRECTORY_MANIFEST)
+    }
+
+    /// Add a feature flag
+    pub fn with_feature(mut self, feature: impl Into<String>) -> Self {
+        let feature = feature.into();
+        if !self.supports(&feature) {
+            self.features.push(feature);
+        }
+        self
+    }
+
+    /// Whether the feature flag is set
+    pub fn supports(&self, feature: &str) -> bool {
+        self.features.iter().any(|f| f == feature)
+    }
+
+    /// What both sides support, in this side's preference order
+    ///
+    /// A list with nothing in common falls back to the 1.0 default, which
+    /// every peer speaks.
+    pub fn negotiate(&self, peer: &Capabilities) -> Capabilities {
+        let baseline = Self::default();
+        Capabilities {
+            version: crate::PROTOCOL_VERSION.to_string(),
+            compression: common(&self.compression, &peer.compression, baseline.compression),
+            hash_algorithms: common(
+                &self.hash_algorithms,
+                &peer.hash_algorithms,
+                baseline.hash_algorithms,
+            ),
+            encryption: common(&self.encryption, &peer.encryption, baseline.encryption),
+            resume: self.resume && peer.resume,
+            batch: self.batch && peer.batch,
+            max_chunk_size: self.max_chunk_size.min(peer.max_chunk_size),
+            features: self
+                .features
+                .iter()
+                .filter(|f| peer.supports(f))
+                .cloned()
+                .collect(),
+            challenge: None,
+        }
+    }
+}
+
+fn common<T: Copy + PartialEq>(ours: &[T], theirs: &[T], fallback: Vec<T>) -> Vec<T> {
+    let shared: Vec<T> = ours.iter().copied().filter(|v| theirs.contains(v)).collect();
+    if shared.is_empty() {
+        fallback
+    } else {
+        shared
+    }
+}
+
+/// Capability lists travel as names in both framings so that a name this
+/// side doesn't know can be skipped instead of failing the whole message
+mod names {
+    use super::{CompressionType, EncryptionMode, HashAlgorithm};
+    use serde::de::value::StrDeserializer;
+    use serde::de::DeserializeOwned;
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub trait Named: Sized {
+        fn name(&self) -> &'static str;
+    }
+
+    impl Named for CompressionType {
+        fn name(&self) -> &'static str {
+            match self {
+                CompressionType::None => "none",
+                CompressionType::Zstd => "zstd",
+                CompressionType::Lz4 => "lz4",
+            }
+        }
+    }
+
+    impl Named for HashAlgorithm {
+        fn name(&self) -> &'static str {
+            match self {
+                HashAlgorithm::Blake3 => "blake3",
+                HashAlgorithm::Sha256 => "sha256",
+            }
+        }
+    }
+
+    impl Named for EncryptionMode {
+        fn name(&self) -> &'static str {
+            match self {
+                EncryptionMode::None => "none",
+                EncryptionMode::ChaCha20Poly1305 => "chacha20poly1305",
+            }
+        }
+    }
+
+    pub fn serialize<T: Named, S: Serializer>(
+        values: &[T],
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        serializer.collect_seq(values.iter().map(Named::name))
+    }
+
+    pub fn deserialize<'de, T, D>(deserializer: D) -> Result<Vec<T>, D::Error>
+    where
+        T: DeserializeOwned,
+        D: Deserializer<'de>,
+    {
+        let names = Vec::<String>::deserialize(deserializer)?;
+        Ok(names
+            .iter()
+            .filter_map(|name| {
+                T::deserialize(StrDeserializer::<serde::de::value::Error>::new(name)).ok()
+            })
+            .collect())
+    }
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -85,4 +549,10 @@ pub enum MessageType {
     ChunkAck,
     TransferComplete,
     Error,
+    Capabilities,
+    Identity,
+    ResumeRequest,
+    ResumeOffer,
+    Manifest,
+    CompressedChunk,
 }
@@ -0,0 +1,391 @@
+//! Resumable transfers
+//!
+//! The receiver journals each chunk it writes, beside the partial file:
+//! a header describing the transfer, then one line per chunk with the hash
+//! it arrived with. After an interruption the sender sends a
+//! `ResumeRequest` describing the file as it is now. The receiver answers
+//! from [`TransferState::offer`]: if the file is unchanged, every
+//! journalled chunk is re-read from the partial file and checked against
+//! its leaf hash, and only chunks that are missing or no longer match are
+//! asked for again.
+
+use crate::chunking::FileChunker;
+use crate::codec::MAX_RESUME_RANGES;
+use crate::merkle::MerkleTree;
+use crate::protocol::{ChunkRange, Framing, ResumeOffer, ResumeRequest, TransferInit};
+use serde::{Deserialize, Serialize};
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use uuid::Uuid;
+
+/// Which transfer a journal belongs to
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+struct Header {
+    transfer_id: Uuid,
+    size: u64,
+    chunk_size: usize,
+    merkle_root: String,
+}
+
+/// Per-chunk progress of a transfer being received, persisted as it grows
+pub struct TransferState {
+    path: PathBuf,
+    header: Header,
+    /// Leaf hash of each chunk written so far
+    leaves: Vec<Option<String>>,
+    journal: File,
+}
+
+impl TransferState {
+    /// Where the state for the partial file at `partial` lives
+    pub fn path_for(partial: &Path) -> PathBuf {
+        let mut name = partial.as_os_str().to_owned();
+        name.push(".tft-resume");
+        PathBuf::from(name)
+    }
+
+    /// Start a new journal at `path`, replacing any earlier one
+    pub fn create(path: &Path, init: &TransferInit) -> io::Result<Self> {
+        let header = Header {
+            transfer_id: init.transfer_id,
+            size: init.size,
+            chunk_size: init.chunk_size,
+            merkle_root: init.merkle_root.clone(),
+        };
+        Self::write(path, &header, &vec![None; init.total_chunks])
+    }
+
+    /// Load the journal at `path`
+    ///
+    /// A line cut short by a crash is ignored; that chunk is simply asked
+    /// for again.
+    pub fn open(path: &Path) -> io::Result<Self> {
+        let mut lines = BufReader::new(File::open(path)?).lines();
+        let header: Header = match lines.next() {
+            Some(line) => serde_json::from_str(&line?).map_err(invalid_data)?,
+            None => return Err(invalid_data("empty resume journal")),
+        };
+        if header.chunk_size == 0 {
+            return Err(invalid_data("chunk size must not be zero"));
+        }
+
+        let mut leaves = vec![None; header.size.div_ceil(header.chunk_size as u64) as usize];
+        for line in lines {
+            let line = line?;
+            let Some((index, hash)) = line.split_once(' ') else {
+                continue;
+            };
+            let complete = hash.len() == 64 && hash.bytes().all(|b| b.is_ascii_hexdigit());
+            match (index.parse::<usize>(), complete) {
+                (Ok(index), true) if index < leaves.len() => leaves[index] = Some(hash.to_string()),
+                _ => continue,
+            }
+        }
+
+        let journal = OpenOptions::new().append(true).open(path)?;
+        Ok(Self {
+            path: path.to_path_buf(),
+            header,
+            leaves,
+            journal,
+        })
+    }
+
+    pub fn transfer_id(&self) -> Uuid {
+        self.header.transfer_id
+    }
+
+    pub fn total_chunks(&self) -> usize {
+        self.leaves.len()
+    }
+
+    /// How many chunks have been received
+    pub fn received(&self) -> usize {
+        self.leaves.iter().filter(|leaf| leaf.is_some()).count()
+    }
+
+    pub fn is_complete(&self) -> bool {
+        self.leaves.iter().all(Option::is_some)
+    }
+
+    /// Record chunk `index` as written with `hash`
+    ///
+    /// Call once the chunk has been verified and written to the partial
+    /// file, so the journal never claims more than is on disk.
+    pub fn record(&mut self, index: usize, hash: &str) -> io::Result<()> {
+        if index >= self.leaves.len() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("chunk {} is past the end of the transfer", index),
+            ));
+        }
+        writeln!(self.journal, "{} {}", index, hash)?;
+        self.leaves[index] = Some(hash.to_string());
+        Ok(())
+    }
+
+    /// The chunks not yet received, as ranges
+    pub fn missing(&self) -> Vec<ChunkRange> {
+        let mut ranges: Vec<ChunkRange> = Vec::new();
+        for (index, leaf) in self.leaves.iter().enumerate() {
+            if leaf.is_some() {
+                continue;
+            }
+            match ranges.last_mut() {
+                Some(range) if range.end == index => range.end += 1,
+                _ => ranges.push(ChunkRange {
+                    start: index,
+                    end: index + 1,
+                }),
+            }
+        }
+        ranges
+    }
+
+    /// Re-read every recorded chunk of `partial` and forget those that no
+    /// longer match their leaf hash; returns how many were forgotten
+    pub fn verify_received(&mut self, partial: &Path) -> io::Result<usize> {
+        let file = match FileChunker::new(self.header.chunk_size).open(partial) {
+            Ok(file) => Some(file),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => None,
+            Err(e) => return Err(e),
+        };
+
+        let mut forgotten = 0;
+        for (index, leaf) in self.leaves.iter_mut().enumerate() {
+            let Some(hash) = leaf else {
+                continue;
+            };
+            let intact = match &file {
+                Some(file) => file.verify_chunk(index, hash)?,
+                None => false,
+            };
+            if !intact {
+                *leaf = None;
+                forgotten += 1;
+            }
+        }
+
+        if forgotten > 0 {
+            *self = Self::write(&self.path, &self.header, &self.leaves)?;
+        }
+        Ok(forgotten)
+    }
+
+    /// Answer a resume request for the transfer received into `partial`
+    ///
+    /// The offer is only accepted for the same transfer of an unchanged
+    /// file; then the received chunks are verified first, so the sender
+    /// never skips one that didn't make it to disk intact.
+    pub fn offer(
+        &mut self,
+        request: &ResumeRequest,
+        partial: &Path,
+        framing: Framing,
+    ) -> io::Result<ResumeOffer> {
+        let unchanged = request.transfer_id == self.header.transfer_id
+            && request.size == self.header.size
+            && request.chunk_size == self.header.chunk_size
+            && request.merkle_root == self.header.merkle_root;
+        if !unchanged {
+            return Ok(ResumeOffer {
+                transfer_id: request.transfer_id,
+                accepted: false,
+                missing: Vec::new(),
+                framing,
+            });
+        }
+
+        self.verify_received(partial)?;
+        let mut missing = self.missing();
+        // Past the limit, ask for everything from the last range on again
+        if missing.len() > MAX_RESUME_RANGES {
+            let start = missing[MAX_RESUME_RANGES - 1].start;
+            missing.truncate(MAX_RESUME_RANGES - 1);
+            missing.push(ChunkRange {
+                start,
+                end: self.leaves.len(),
+            });
+        }
+
+        Ok(ResumeOffer {
+            transfer_id: request.transfer_id,
+            accepted: true,
+            missing,
+            framing,
+        })
+    }
+
+    /// Check the received chunks against the transfer's Merkle root and
+    /// remove the journal
+    ///
+    /// Fails, keeping the journal, while chunks are missing or when the
+    /// leaves don't add up to the root.
+    pub fn finish(self) -> io::Result<()> {
+        let leaves: Option<Vec<String>> = self.leaves.iter().cloned().collect();
+        let Some(leaves) = leaves else {
+            return Err(invalid_data(format!(
+                "{} of {} chunks still missing",
+                self.leaves.len() - self.received(),
+                self.leaves.len()
+            )));
+        };
+        if MerkleTree::new(leaves).root() != self.header.merkle_root {
+            return Err(invalid_data("received chunks don't match the Merkle root"));
+        }
+
+        drop(self.journal);
+        match fs::remove_file(&self.path) {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e),
+            _ => Ok(()),
+        }
+    }
+
+    /// Write a complete journal beside `path` and move it into place
+    fn write(path: &Path, header: &Header, leaves: &[Option<String>]) -> io::Result<Self> {
+        let mut temp_name = path.as_os_str().to_owned();
+        temp_name.push(".tmp");
+        let temp = PathBuf::from(temp_name);
+
+        let mut contents = serde_json::to_string(header).map_err(invalid_data)?;
+        contents.push('\n');
+        for (index, leaf) in leaves.iter().enumerate() {
+            if let Some(hash) = leaf {
+                contents.push_str(&format!("{} {}\n", index, hash));
+            }
+        }
+        let mut file = File::create(&temp)?;
+        file.write_all(contents.as_bytes())?;
+        file.sync_all()?;
+        fs::rename(&temp, path)?;
+
+        Ok(Self {
+            path: path.to_path_buf(),
+            header: header.clone(),
+            leaves: leaves.to_vec(),
+            journal: OpenOptions::new().append(true).open(path)?,
+        })
+    }
+}
+
+fn invalid_data(error: impl Into<Box<dyn std::error::Error + Send + Sync>>) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, error)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chunking::ChunkInfo;
+    use crate::protocol::CompressionType;
+
+    fn init(data: &[u8], chunk_size: usize) -> TransferInit {
+        let hashes: Vec<String> = data
+            .chunks(chunk_size)
+            .map(ChunkInfo::compute_hash)
+            .collect();
+        TransferInit {
+            version: crate::PROTOCOL_VERSION.to_string(),
+            transfer_id: Uuid::new_v4(),
+            filename: "data.bin".to_string(),
+            size: data.len() as u64,
+            chunk_size,
+            total_chunks: hashes.len(),
+            merkle_root: MerkleTree::new(hashes).root().to_string(),
+            encrypted: false,
+            compression: CompressionType::None,
+            framings: vec![Framing::Ndjson],
+            compressions: Vec::new(),
+            compression_level: None,
+            directory: None,
+        }
+    }
+
+    fn request(init: &TransferInit) -> ResumeRequest {
+        ResumeRequest {
+            transfer_id: init.transfer_id,
+            size: init.size,
+            chunk_size: init.chunk_size,
+            merkle_root: init.merkle_root.clone(),
+        }
+    }
+
+    #[test]
+    fn test_resume_skips_only_intact_chunks() {
+        let dir = tempfile::tempdir().unwrap();
+        let partial = dir.path().join("data.bin.part");
+        let data: Vec<u8> = (0..10_000u32).map(|i| (i % 251) as u8).collect();
+        let init = init(&data, 1024);
+
+        // Chunks 0, 1, 2 and 4 arrive, then the connection drops; chunk 1
+        // is damaged on disk afterwards
+        let mut state = TransferState::create(&TransferState::path_for(&partial), &init).unwrap();
+        let mut written = data[..5 * 1024].to_vec();
+        written[3 * 1024..4 * 1024].fill(0);
+        written[1024] ^= 0xff;
+        fs::write(&partial, &written).unwrap();
+        for index in [0, 1, 2, 4] {
+            let chunk = &data[index * 1024..(index + 1) * 1024];
+            state
+                .record(index, &ChunkInfo::compute_hash(chunk))
+                .unwrap();
+        }
+        drop(state);
+
+        let mut state = TransferState::open(&TransferState::path_for(&partial)).unwrap();
+        assert_eq!(state.received(), 4);
+        let offer = state
+            .offer(&request(&init), &partial, Framing::Ndjson)
+            .unwrap();
+        assert!(offer.accepted);
+        assert_eq!(
+            offer.missing,
+            [
+                ChunkRange { start: 1, end: 2 },
+                ChunkRange { start: 3, end: 4 },
+                ChunkRange { start: 5, end: 10 }
+            ]
+        );
+
+        // The forgotten chunk stays forgotten across a reopen
+        let state = TransferState::open(&TransferState::path_for(&partial)).unwrap();
+        assert_eq!(state.received(), 3);
+        assert!(state.finish().is_err());
+
+        // A changed file starts over
+        let mut changed = request(&init);
+        changed.merkle_root = "f".repeat(64);
+        let mut state = TransferState::open(&TransferState::path_for(&partial)).unwrap();
+        assert!(
+            !state
+                .offer(&changed, &partial, Framing::Ndjson)
+                .unwrap()
+                .accepted
+        );
+    }
+
+    #[test]
+    fn test_finish_checks_merkle_root() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("data.bin.tft-resume");
+        let data = vec![9u8; 3000];
+        let init = init(&data, 1024);
+
+        let mut state = TransferState::create(&path, &init).unwrap();
+        for (index, chunk) in data.chunks(1024).enumerate() {
+            state
+                .record(index, &ChunkInfo::compute_hash(chunk))
+                .unwrap();
+        }
+        assert!(state.is_complete() && state.missing().is_empty());
+        state.finish().unwrap();
+        assert!(!path.exists());
+
+        let mut state = TransferState::create(&path, &init).unwrap();
+        for index in 0..3 {
+            state.record(index, &"0".repeat(64)).unwrap();
+        }
+        assert!(state.finish().is_err());
+        assert!(path.exists());
+    }
+}
@@ -0,0 +1,335 @@
+//! Directory-tree transfers
+//!
+//! The sender describes the tree with [`scan`] and sends the result as a
+//! `Manifest`, then each regular file as a transfer of its own. The
+//! receiver calls [`create_entries`] when the manifest arrives, so every
+//! file has its directory waiting, and [`apply_metadata`] after the last
+//! file, so writing the files doesn't disturb directory mtimes.
+//!
+//! Symlinks are recorded, never followed, and only when they stay inside
+//! the tree (see [`is_contained_link`]); a receiver never writes through
+//! one.
+
+use crate::protocol::{DirectoryManifest, EntryKind, ManifestEntry};
+use chrono::{DateTime, Utc};
+use std::fs::{self, File};
+use std::io;
+use std::path::{Component, Path, PathBuf};
+use tracing::debug;
+use uuid::Uuid;
+
+/// Describe everything under `root`, in name order
+///
+/// Modes and mtimes are included when `preserve_metadata` is set.
+/// Symlinks reaching outside the tree are left out.
+pub fn scan(root: &Path, preserve_metadata: bool) -> io::Result<DirectoryManifest> {
+    let mut entries = Vec::new();
+    walk(root, None, preserve_metadata, &mut entries)?;
+    Ok(DirectoryManifest {
+        manifest_id: Uuid::new_v4(),
+        entries,
+    })
+}
+
+fn walk(
+    dir: &Path,
+    prefix: Option<&str>,
+    preserve_metadata: bool,
+    entries: &mut Vec<ManifestEntry>,
+) -> io::Result<()> {
+    let mut children = fs::read_dir(dir)?.collect::<io::Result<Vec<_>>>()?;
+    children.sort_by_key(|child| child.file_name());
+
+    for child in children {
+        let name = child.file_name().into_string().map_err(|name| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("{:?} is not valid UTF-8", dir.join(name)),
+            )
+        })?;
+        let path = match prefix {
+            Some(parent) => format!("{}/{}", parent, name),
+            None => name,
+        };
+
+        let metadata = child.metadata()?;
+        let kind = if metadata.is_dir() {
+            EntryKind::Directory
+        } else if metadata.is_file() {
+            EntryKind::File {
+                size: metadata.len(),
+            }
+        } else if metadata.is_symlink() {
+            match link_target(&child.path())? {
+                Some(target) if is_contained_link(&path, &target) => EntryKind::Symlink { target },
+                _ => {
+                    debug!(
+                        "Leaving out {:?}, which links outside the tree",
+                        child.path()
+                    );
+                    continue;
+                }
+            }
+        } else {
+            continue;
+        };
+
+        let descend = kind == EntryKind::Directory;
+        entries.push(ManifestEntry {
+            mode: preserve_metadata.then(|| mode(&metadata)).flatten(),
+            mtime: preserve_metadata
+                .then(|| metadata.modified().ok().map(DateTime::<Utc>::from))
+                .flatten(),
+            path,
+            kind,
+        });
+        if descend {
+            let path = entries.last().map(|entry| entry.path.clone());
+            walk(&child.path(), path.as_deref(), preserve_metadata, entries)?;
+        }
+    }
+    Ok(())
+}
+
+/// A symlink's target as a `/`-separated relative path, or `None` if it
+/// is absolute or not UTF-8
+fn link_target(link: &Path) -> io::Result<Option<String>> {
+    let target = fs::read_link(link)?;
+    let mut parts = Vec::new();
+    for component in target.components() {
+        match component {
+            Component::Normal(part) => match part.to_str() {
+                Some(part) => parts.push(part),
+                None => return Ok(None),
+            },
+            Component::ParentDir => parts.push(".."),
+            Component::CurDir => {}
+            Component::RootDir | Component::Prefix(_) => return Ok(None),
+        }
+    }
+    Ok(Some(parts.join("/")))
+}
+
+#[cfg(unix)]
+fn mode(metadata: &fs::Metadata) -> Option<u32> {
+    use std::os::unix::fs::PermissionsExt;
+    Some(metadata.permissions().mode() & 0o7777)
+}
+
+#[cfg(not(unix))]
+fn mode(_metadata: &fs::Metadata) -> Option<u32> {
+    None
+}
+
+/// Whether a symlink at `path` pointing at `target` stays inside the tree
+///
+/// The target must be relative and may only climb with `..` at its start,
+/// no higher than the root. Every later part descends, so the link can't
+/// escape even by way of other links in the tree.
+pub fn is_contained_link(path: &str, target: &str) -> bool {
+    let depth = path.split('/').count() - 1;
+    let mut parts = target.split('/').peekable();
+    let mut climbed = 0;
+    while parts.next_if_eq(&"..").is_some() {
+        climbed += 1;
+    }
+    !target.is_empty() && climbed <= depth && parts.all(is_plain_component)
+}
+
+/// A path component that is neither empty, `.` nor `..`, and can't be read
+/// as a separator or drive on any platform
+pub(crate) fn is_plain_component(part: &str) -> bool {
+    !part.is_empty() && part != "." && part != ".." && !part.contains(['\\', '\0', ':'])
+}
+
+/// Where a manifest `path` lives under `root`
+///
+/// The path must have been validated, as the codec does for every
+/// manifest it decodes.
+pub fn local_path(root: &Path, path: &str) -> PathBuf {
+    let mut local = root.to_path_buf();
+    local.extend(path.split('/'));
+    local
+}
+
+/// Create the manifest's directories and symlinks under `root`
+///
+/// Existing directories are kept. A file or symlink where the manifest has
+/// a directory is replaced, so no file is later written through a link
+/// left over from before.
+pub fn create_entries(root: &Path, manifest: &DirectoryManifest) -> io::Result<()> {
+    fs::create_dir_all(root)?;
+    for entry in &manifest.entries {
+        let path = local_path(root, &entry.path);
+        let existing = match fs::symlink_metadata(&path) {
+            Ok(metadata) => Some(metadata),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => None,
+            Err(e) => return Err(e),
+        };
+
+        match &entry.kind {
+            EntryKind::Directory => {
+                match existing {
+                    Some(metadata) if metadata.is_dir() => continue,
+                    Some(_) => fs::remove_file(&path)?,
+                    None => {}
+                }
+                fs::create_dir(&path)?;
+            }
+            EntryKind::Symlink { target } => {
+                match existing {
+                    Some(metadata) if metadata.is_dir() => {
+                        return Err(io::Error::new(
+                            io::ErrorKind::AlreadyExists,
+                            format!("{:?} is a directory", path),
+                        ));
+                    }
+                    Some(metadata)
+                        if metadata.is_symlink() && fs::read_link(&path)? == Path::new(target) =>
+                    {
+                        continue;
+                    }
+                    Some(_) => fs::remove_file(&path)?,
+                    None => {}
+                }
+                symlink(target, &path)?;
+            }
+            EntryKind::File { .. } => {}
+        }
+    }
+    Ok(())
+}
+
+#[cfg(unix)]
+fn symlink(target: &str, link: &Path) -> io::Result<()> {
+    std::os::unix::fs::symlink(target, link)
+}
+
+#[cfg(not(unix))]
+fn symlink(target: &str, link: &Path) -> io::Result<()> {
+    tracing::warn!(
+        "Not creating symlink {:?} -> {}: unsupported on this platform",
+        link,
+        target
+    );
+    Ok(())
+}
+
+/// Set the modes and mtimes the manifest carries on what is under `root`
+///
+/// Contents come before their directory, so setting them doesn't change
+/// the directory's mtime afterwards. Symlinks keep their own. Modes are
+/// limited to the permission bits; setuid, setgid and sticky are dropped.
+pub fn apply_metadata(root: &Path, manifest: &DirectoryManifest) -> io::Result<()> {
+    for entry in manifest.entries.iter().rev() {
+        let is_dir = match entry.kind {
+            EntryKind::Directory => true,
+            EntryKind::File { .. } => false,
+            EntryKind::Symlink { .. } => continue,
+        };
+        let path = local_path(root, &entry.path);
+
+        // Windows can't open a directory to set its times
+        if let Some(mtime) = entry.mtime.filter(|_| cfg!(unix) || !is_dir) {
+            File::open(&path)?.set_modified(mtime.into())?;
+        }
+        if let Some(mode) = entry.mode {
+            set_mode(&path, mode & 0o777)?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(unix)]
+fn set_mode(path: &Path, mode: u32) -> io::Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+    fs::set_permissions(path, fs::Permissions::from_mode(mode))
+}
+
+#[cfg(not(unix))]
+fn set_mode(_path: &Path, _mode: u32) -> io::Result<()> {
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_contained_links() {
+        let cases = [
+            ("latest", "docs/report.txt", true),
+            ("docs/up", "../readme.txt", true),
+            ("docs/2024/up", "../../readme.txt", true),
+            ("docs/up", "../..", false),
+            ("latest", "../outside", false),
+            ("latest", "/etc/passwd", false),
+            // `x` may itself be a link to `.`, so `x/..` could be outside
+            ("latest", "x/..", false),
+            ("latest", "./docs", false),
+            ("latest", "", false),
+        ];
+        for (path, target, contained) in cases {
+            assert_eq!(
+                is_contained_link(path, target),
+                contained,
+                "{} -> {}",
+                path,
+                target
+            );
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_scan_create_and_apply() {
+        use std::os::unix::fs::PermissionsExt;
+        use std::time::{Duration, SystemTime};
+
+        let source = tempfile::tempdir().unwrap();
+        let dest = tempfile::tempdir().unwrap();
+        fs::create_dir_all(source.path().join("docs/empty")).unwrap();
+        fs::write(source.path().join("docs/run.sh"), b"#!/bin/sh\n").unwrap();
+        fs::set_permissions(
+            source.path().join("docs/run.sh"),
+            fs::Permissions::from_mode(0o750),
+        )
+        .unwrap();
+        let old = SystemTime::UNIX_EPOCH + Duration::from_secs(1_600_000_000);
+        File::options()
+            .write(true)
+            .open(source.path().join("docs/run.sh"))
+            .unwrap()
+            .set_modified(old)
+            .unwrap();
+        std::os::unix::fs::symlink("docs/run.sh", source.path().join("latest")).unwrap();
+        std::os::unix::fs::symlink("/etc", source.path().join("escape")).unwrap();
+
+        let manifest = scan(source.path(), true).unwrap();
+        let paths: Vec<_> = manifest.entries.iter().map(|e| e.path.as_str()).collect();
+        assert_eq!(paths, ["docs", "docs/empty", "docs/run.sh", "latest"]);
+        assert_eq!(manifest.total_size(), 10);
+        assert_eq!(manifest.files().count(), 1);
+
+        create_entries(dest.path(), &manifest).unwrap();
+        assert!(dest.path().join("docs/empty").is_dir());
+        assert_eq!(
+            fs::read_link(dest.path().join("latest")).unwrap(),
+            Path::new("docs/run.sh")
+        );
+        // Created again over itself, as a repeated sync does
+        create_entries(dest.path(), &manifest).unwrap();
+
+        fs::write(dest.path().join("docs/run.sh"), b"#!/bin/sh\n").unwrap();
+        apply_metadata(dest.path(), &manifest).unwrap();
+        let copied = fs::metadata(dest.path().join("docs/run.sh")).unwrap();
+        assert_eq!(copied.permissions().mode() & 0o777, 0o750);
+        assert_eq!(copied.modified().unwrap(), old);
+
+        let stripped = scan(source.path(), false).unwrap();
+        assert!(stripped
+            .entries
+            .iter()
+            .all(|e| e.mode.is_none() && e.mtime.is_none()));
+    }
+}
@@ -0,0 +1,139 @@
+//! Parallel file hashing for chunk verification
+//!
+//! A reader thread streams the file in chunk-sized buffers while the rayon
+//! pool hashes the batch read before, so disk reads overlap with hashing
+//! and every core takes a share of the chunk hashes. The whole-file BLAKE3
+//! is fed in order alongside, itself split across cores by BLAKE3's rayon
+//! support.
+
+use crate::merkle::MerkleTree;
+use rayon::prelude::*;
+use std::fs::File;
+use std::io::{self, Read};
+use std::path::Path;
+use std::sync::mpsc;
+use std::thread;
+
+/// Hashes of a file read in fixed-size chunks
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FileHashes {
+    /// BLAKE3 of each chunk, in order
+    pub chunk_hashes: Vec<String>,
+    /// BLAKE3 of the whole file
+    pub file_hash: String,
+    pub size: u64,
+}
+
+impl FileHashes {
+    /// Merkle root over the chunk hashes
+    pub fn merkle_root(&self) -> String {
+        MerkleTree::new(self.chunk_hashes.clone()).root().to_string()
+    }
+}
+
+/// Hash the file at `path` in chunks of `chunk_size` bytes
+pub fn hash_file(path: &Path, chunk_size: usize) -> io::Result<FileHashes> {
+    if chunk_size == 0 {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "chunk size must not be zero"));
+    }
+
+    let mut file = File::open(path)?;
+    let batch_len = rayon::current_num_threads().max(1);
+    // Room for the batch being hashed while the next one is read
+    let (sender, receiver) = mpsc::sync_channel::<io::Result<Vec<u8>>>(batch_len);
+
+    thread::scope(|scope| {
+        // Dropped on return, which unblocks the reader if hashing stops early
+        let receiver = receiver;
+
+        scope.spawn(move || loop {
+            match read_chunk(&mut file, chunk_size) {
+                Ok(chunk) if chunk.is_empty() => break,
+                Ok(chunk) => {
+                    if sender.send(Ok(chunk)).is_err() {
+                        break;
+                    }
+                }
+                Err(e) => {
+                    let _ = sender.send(Err(e));
+                    break;
+                }
+            }
+        });
+
+        let mut file_hasher = blake3::Hasher::new();
+        let mut chunk_hashes = Vec::new();
+        let mut size = 0;
+        let mut chunks = receiver.iter();
+        loop {
+            let batch = chunks.by_ref().take(batch_len).collect::<io::Result<Vec<_>>>()?;
+            if batch.is_empty() {
+                break;
+            }
+
+            let (hashes, ()) = rayon::join(
+                || {
+                    batch
+                        .par_iter()
+                        .map(|chunk| blake3::hash(chunk).to_hex().to_string())
+                        .collect::<Vec<_>>()
+                },
+                || {
+                    for chunk in &batch {
+                        file_hasher.update_rayon(chunk);
+                    }
+                },
+            );
+            chunk_hashes.extend(hashes);
+            size += batch.iter().map(|chunk| chunk.len() as u64).sum::<u64>();
+        }
+
+        Ok(FileHashes {
+            chunk_hashes,
+            file_hash: file_hasher.finalize().to_hex().to_string(),
+            size,
+        })
+    })
+}
+
+/// Read up to `chunk_size` bytes; shorter only at the end of the file
+fn read_chunk(file: &mut File, chunk_size: usize) -> io::Result<Vec<u8>> {
+    let mut chunk = Vec::with_capacity(chunk_size);
+    file.take(chunk_size as u64).read_to_end(&mut chunk)?;
+    Ok(chunk)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chunking::ChunkInfo;
+
+    #[test]
+    fn test_hash_file_matches_sequential() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("data.bin");
+        let data: Vec<u8> = (0..100_000u32).map(|i| (i * 7 % 251) as u8).collect();
+        std::fs::write(&path, &data).unwrap();
+
+        let hashes = hash_file(&path, 4096).unwrap();
+
+        let expected: Vec<_> = data.chunks(4096).map(ChunkInfo::compute_hash).collect();
+        assert_eq!(hashes.chunk_hashes, expected);
+        assert_eq!(hashes.chunk_hashes.len(), 25); // last chunk is short
+        assert_eq!(hashes.file_hash, blake3::hash(&data).to_hex().to_string());
+        assert_eq!(hashes.size, data.len() as u64);
+        assert_eq!(hashes.merkle_root(), MerkleTree::new(expected).root());
+    }
+
+    #[test]
+    fn test_hash_empty_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("empty");
+        std::fs::write(&path, b"").unwrap();
+
+        let hashes = hash_file(&path, 4096).unwrap();
+        assert!(hashes.chunk_hashes.is_empty());
+        assert_eq!(hashes.file_hash, blake3::hash(b"").to_hex().to_string());
+        assert!(hash_file(&path, 0).is_err());
+    }
+}
@@ -0,0 +1,21 @@
+//! Regenerate `include/tft.h` from the exported functions
+
+use std::env;
+use std::path::PathBuf;
+
+fn main() {
+    let crate_dir = PathBuf::from(env::var("CARGO_MANIFEST_DIR").unwrap());
+    println!("cargo:rerun-if-changed=src");
+    println!("cargo:rerun-if-changed=cbindgen.toml");
+
+    let config = cbindgen::Config::from_file(crate_dir.join("cbindgen.toml"))
+        .expect("cbindgen.toml is valid");
+    match cbindgen::generate_with_config(&crate_dir, config) {
+        Ok(bindings) => {
+            bindings.write_to_file(crate_dir.join("include/tft.h"));
+        }
+        // Keep the checked-in header rather than fail the build on a
+        // source the parser can't read yet
+        Err(e) => println!("cargo:warning=tft.h not regenerated: {}", e),
+    }
+}
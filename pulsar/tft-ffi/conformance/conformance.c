@@ -0,0 +1,214 @@
+/*
+ * Conformance checks for the TFT C ABI
+ *
+ * Every expected value below was produced by the Rust implementation; a
+ * build of libtft_ffi, or a reimplementation of the wire format, that
+ * disagrees with any of them cannot interoperate with Rust peers.
+ *
+ * Build and run with `make` in this directory. Exits non-zero if any
+ * check group fails.
+ */
+
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+#include <unistd.h>
+
+#include "tft.h"
+
+/* 10 000 bytes of i % 251, chunked by 4096 */
+#define DATA_LEN 10000
+#define CHUNK_SIZE 4096
+
+static const char *const CHUNK_HASHES[] = {
+    "015094013f57a5277b59d8475c0501042c0b642e531b0a1c8f58d2163229e969",
+    "535cf151d8b1d9c63d5ae6a37ef7771ccd6ac6d3582d4a76e3ff433e9daaf1cd",
+    "bdf815c482acce739d3c4f29cc1fa04db2b9185b5dfa869b48310f5fdf917c81",
+};
+static const char *const MERKLE_ROOT =
+    "0d756047048cf5aa670d11560a94402dc897af5f532f2027eaf7423ee1f59272";
+
+static const char *const TRANSFER_ID = "6f1c2a52-3c7e-4a35-9a7b-0c7d2f1e9b40";
+static const char *const ACK_JSON =
+    "{\"type\":\"chunk_ack\",\"transfer_id\":\"6f1c2a52-3c7e-4a35-9a7b-0c7d2f1e9b40\","
+    "\"chunk_index\":4,\"success\":true}";
+static const unsigned char ACK_BINARY[] = {
+    0x00, 0x00, 0x00, 0x14, 0x03, 0x10, 0x6f, 0x1c, 0x2a, 0x52, 0x3c, 0x7e,
+    0x4a, 0x35, 0x9a, 0x7b, 0x0c, 0x7d, 0x2f, 0x1e, 0x9b, 0x40, 0x04, 0x01,
+};
+
+static int failures = 0;
+
+#define CHECK(cond)                                                          \
+    do {                                                                     \
+        if (!(cond)) {                                                       \
+            const char *error = tft_last_error();                            \
+            fprintf(stderr, "  %s:%d: %s failed (%s)\n", __FILE__, __LINE__, \
+                    #cond, error ? error : "no error");                      \
+            return 1;                                                        \
+        }                                                                    \
+    } while (0)
+
+static void run(const char *name, int (*check)(void)) {
+    int failed = check();
+    printf("%s %s\n", failed ? "FAIL" : "ok  ", name);
+    failures += failed;
+}
+
+static char data_path[] = "/tmp/tft-conformance-XXXXXX";
+
+static int write_data(void) {
+    unsigned char data[DATA_LEN];
+    for (int i = 0; i < DATA_LEN; i++) {
+        data[i] = (unsigned char)(i % 251);
+    }
+    int fd = mkstemp(data_path);
+    if (fd < 0) {
+        return -1;
+    }
+    ssize_t written = write(fd, data, sizeof data);
+    close(fd);
+    return written == (ssize_t)sizeof data ? 0 : -1;
+}
+
+static int check_version(void) {
+    CHECK(strcmp(tft_protocol_version(), "1.0") == 0);
+    return 0;
+}
+
+static int check_chunking(void) {
+    TftChunkedFile *file = NULL;
+    CHECK(tft_chunked_file_open(data_path, CHUNK_SIZE, &file) == TFT_STATUS_OK);
+    CHECK(tft_chunked_file_len(file) == DATA_LEN);
+    CHECK(tft_chunked_file_chunk_count(file) == 3);
+
+    for (size_t i = 0; i < 3; i++) {
+        TftBuffer chunk = {0};
+        char hash[TFT_HASH_LEN + 1];
+        CHECK(tft_chunked_file_read(file, i, &chunk) == TFT_STATUS_OK);
+        CHECK(chunk.len == (i < 2 ? CHUNK_SIZE : DATA_LEN - 2 * CHUNK_SIZE));
+        CHECK(tft_chunk_hash(chunk.data, chunk.len, hash) == TFT_STATUS_OK);
+        CHECK(strcmp(hash, CHUNK_HASHES[i]) == 0);
+        tft_buffer_free(&chunk);
+    }
+
+    TftBuffer past_end = {0};
+    CHECK(tft_chunked_file_read(file, 3, &past_end) == TFT_STATUS_INVALID_ARGUMENT);
+    tft_chunked_file_free(file);
+    return 0;
+}
+
+static int check_merkle(void) {
+    char root[TFT_HASH_LEN + 1];
+    CHECK(tft_merkle_root(CHUNK_HASHES, 3, root) == TFT_STATUS_OK);
+    CHECK(strcmp(root, MERKLE_ROOT) == 0);
+
+    uint64_t size = 0;
+    CHECK(tft_file_merkle_root(data_path, CHUNK_SIZE, root, &size) == TFT_STATUS_OK);
+    CHECK(strcmp(root, MERKLE_ROOT) == 0);
+    CHECK(size == DATA_LEN);
+
+    bool valid = false;
+    CHECK(tft_merkle_verify(CHUNK_HASHES, 3, MERKLE_ROOT, &valid) == TFT_STATUS_OK);
+    CHECK(valid);
+    const char *reordered[] = {CHUNK_HASHES[1], CHUNK_HASHES[0], CHUNK_HASHES[2]};
+    CHECK(tft_merkle_verify(reordered, 3, MERKLE_ROOT, &valid) == TFT_STATUS_OK);
+    CHECK(!valid);
+
+    /* No chunks, empty root */
+    CHECK(tft_merkle_root(NULL, 0, root) == TFT_STATUS_OK);
+    CHECK(root[0] == '\0');
+    return 0;
+}
+
+static int check_encoding(void) {
+    TftBuffer frame = {0};
+    CHECK(tft_encode_message(ACK_JSON, TFT_FRAMING_NDJSON, &frame) == TFT_STATUS_OK);
+    CHECK(frame.len == strlen(ACK_JSON) + 1);
+    CHECK(memcmp(frame.data, ACK_JSON, strlen(ACK_JSON)) == 0);
+    CHECK(frame.data[frame.len - 1] == '\n');
+    tft_buffer_free(&frame);
+
+    CHECK(tft_encode_message(ACK_JSON, TFT_FRAMING_LENGTH_PREFIXED, &frame) == TFT_STATUS_OK);
+    CHECK(frame.len == sizeof ACK_BINARY);
+    CHECK(memcmp(frame.data, ACK_BINARY, sizeof ACK_BINARY) == 0);
+    tft_buffer_free(&frame);
+
+    char *json = NULL;
+    CHECK(tft_decode_message(ACK_BINARY, sizeof ACK_BINARY, TFT_FRAMING_LENGTH_PREFIXED,
+                             &json) == TFT_STATUS_OK);
+    CHECK(strcmp(json, ACK_JSON) == 0);
+    tft_string_free(json);
+    return 0;
+}
+
+static int check_chunks(void) {
+    static const unsigned char payload[] = "chunk payload";
+    TftFraming framings[] = {TFT_FRAMING_NDJSON, TFT_FRAMING_LENGTH_PREFIXED};
+
+    for (size_t i = 0; i < 2; i++) {
+        TftBuffer frame = {0};
+        TftChunk chunk;
+        char hash[TFT_HASH_LEN + 1];
+        CHECK(tft_encode_chunk(TRANSFER_ID, 7, payload, sizeof payload - 1, framings[i],
+                               &frame) == TFT_STATUS_OK);
+        CHECK(tft_decode_chunk(frame.data, frame.len, framings[i], &chunk) == TFT_STATUS_OK);
+        CHECK(chunk.chunk_index == 7);
+        CHECK(strcmp(chunk.transfer_id, TRANSFER_ID) == 0);
+        CHECK(chunk.data.len == sizeof payload - 1);
+        CHECK(memcmp(chunk.data.data, payload, chunk.data.len) == 0);
+        CHECK(tft_chunk_hash(payload, sizeof payload - 1, hash) == TFT_STATUS_OK);
+        CHECK(strcmp(chunk.hash, hash) == 0);
+        tft_buffer_free(&chunk.data);
+        tft_buffer_free(&frame);
+    }
+    return 0;
+}
+
+static int check_rejects(void) {
+    static const unsigned char garbage[] = "{\"type\":\"chunk\"}";
+    char *json = NULL;
+    CHECK(tft_decode_message(garbage, sizeof garbage - 1, TFT_FRAMING_NDJSON, &json) ==
+          TFT_STATUS_DECODE);
+    CHECK(json == NULL);
+    CHECK(tft_last_error() != NULL);
+
+    /* A length prefix that overstates the payload */
+    unsigned char truncated[sizeof ACK_BINARY - 1];
+    memcpy(truncated, ACK_BINARY, sizeof truncated);
+    CHECK(tft_decode_message(truncated, sizeof truncated, TFT_FRAMING_LENGTH_PREFIXED, &json) ==
+          TFT_STATUS_DECODE);
+
+    TftBuffer frame = {0};
+    CHECK(tft_encode_chunk("not-a-uuid", 0, NULL, 0, TFT_FRAMING_NDJSON, &frame) ==
+          TFT_STATUS_INVALID_ARGUMENT);
+    CHECK(tft_encode_message(NULL, TFT_FRAMING_NDJSON, &frame) == TFT_STATUS_NULL_POINTER);
+
+    /* Success clears the error */
+    char hash[TFT_HASH_LEN + 1];
+    CHECK(tft_chunk_hash(NULL, 0, hash) == TFT_STATUS_OK);
+    CHECK(tft_last_error() == NULL);
+    return 0;
+}
+
+int main(void) {
+    if (write_data() != 0) {
+        perror("writing test data");
+        return 2;
+    }
+
+    run("protocol version", check_version);
+    run("chunking", check_chunking);
+    run("merkle roots", check_merkle);
+    run("message encoding", check_encoding);
+    run("chunk frames", check_chunks);
+    run("malformed input", check_rejects);
+
+    unlink(data_path);
+    if (failures) {
+        printf("%d check group(s) failed\n", failures);
+        return 1;
+    }
+    printf("all checks passed\n");
+    return 0;
+}
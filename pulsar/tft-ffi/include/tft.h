@@ -0,0 +1,231 @@
+#ifndef TFT_H
+#define TFT_H
+
+/* Generated by cbindgen from tft-ffi; do not edit. Rebuild the crate to update. */
+
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+
+// Length of a hex-encoded hash, without the NUL
+#define TFT_HASH_LEN 64
+
+// Length of a hyphenated transfer ID, without the NUL
+#define TFT_UUID_LEN 36
+
+// Outcome of a call
+typedef enum TftStatus {
+  TFT_STATUS_OK = 0,
+  // A required pointer was NULL
+  TFT_STATUS_NULL_POINTER = 1,
+  // An argument was out of range or not valid UTF-8
+  TFT_STATUS_INVALID_ARGUMENT = 2,
+  // Reading a file failed
+  TFT_STATUS_IO = 3,
+  // A frame or message was malformed or failed validation
+  TFT_STATUS_DECODE = 4,
+  // A message could not be encoded
+  TFT_STATUS_ENCODE = 5,
+  // The library panicked; this is a bug
+  TFT_STATUS_PANIC = 6,
+} TftStatus;
+
+// Wire framing of a frame
+typedef enum TftFraming {
+  // One JSON object per line
+  TFT_FRAMING_NDJSON = 0,
+  // Big-endian u32 payload length, then a bincode payload
+  TFT_FRAMING_LENGTH_PREFIXED = 1,
+} TftFraming;
+
+// A file opened for chunking; close with [`tft_chunked_file_free`]
+typedef struct TftChunkedFile TftChunkedFile;
+
+// Bytes owned by the library; release with [`tft_buffer_free`]
+typedef struct TftBuffer {
+  uint8_t *data;
+  size_t len;
+} TftBuffer;
+
+// A decoded chunk; release `data` with `tft_buffer_free`
+//
+// The array lengths are `TFT_UUID_LEN + 1` and `TFT_HASH_LEN + 1`,
+// spelled out because cbindgen can't evaluate them.
+typedef struct TftChunk {
+  // Hyphenated transfer ID, NUL-terminated
+  char transfer_id[37];
+  size_t chunk_index;
+  // Hash the sender gave for `data`, NUL-terminated; already checked
+  // to be well-formed, not that it matches
+  char hash[65];
+  struct TftBuffer data;
+} TftChunk;
+
+#ifdef __cplusplus
+extern "C" {
+#endif // __cplusplus
+
+// Release a buffer's bytes and reset it to empty; NULL and empty
+// buffers are ignored
+//
+// # Safety
+//
+// `buffer` must be NULL or point to a buffer filled in by this library
+// and not yet freed.
+void tft_buffer_free(struct TftBuffer *buffer);
+
+// Protocol version spoken by this library, e.g. "1.0"
+const char *tft_protocol_version(void);
+
+// Message for the last failed call on this thread, or NULL if the last
+// call succeeded
+//
+// The string stays valid until the next call into the library on the
+// same thread.
+const char *tft_last_error(void);
+
+// Open the file at `path` to be read in chunks of `chunk_size` bytes
+//
+// Large files on local disks are memory-mapped, as by the Rust sender.
+//
+// # Safety
+//
+// `path` must be a NUL-terminated string and `out` valid for writes.
+enum TftStatus tft_chunked_file_open(const char *path,
+                                     size_t chunk_size,
+                                     struct TftChunkedFile **out);
+
+// Close a file; NULL is ignored
+//
+// # Safety
+//
+// `file` must be NULL or a file from [`tft_chunked_file_open`] that has
+// not been freed.
+void tft_chunked_file_free(struct TftChunkedFile *file);
+
+// Size of the file in bytes, or 0 for NULL
+//
+// # Safety
+//
+// `file` must be NULL or an open file.
+uint64_t tft_chunked_file_len(const struct TftChunkedFile *file);
+
+// Number of chunks in the file, or 0 for NULL
+//
+// # Safety
+//
+// `file` must be NULL or an open file.
+size_t tft_chunked_file_chunk_count(const struct TftChunkedFile *file);
+
+// Copy chunk `index` into `out`, to be released with `tft_buffer_free`
+//
+// # Safety
+//
+// `file` must be an open file and `out` valid for writes.
+enum TftStatus tft_chunked_file_read(const struct TftChunkedFile *file,
+                                     size_t index,
+                                     struct TftBuffer *out);
+
+// Hash of a chunk's bytes, as the protocol's `hash` field carries it
+//
+// # Safety
+//
+// `data` must be valid for reads of `len` bytes (or NULL when `len` is
+// 0) and `out_hex` valid for writes of `TFT_HASH_LEN + 1` bytes.
+enum TftStatus tft_chunk_hash(const uint8_t *data, size_t len, char *out_hex);
+
+// Encode a message given as JSON into one frame, including the newline
+// or length prefix
+//
+// The message is validated as a peer would validate it, so a frame this
+// returns will not be rejected for its fields.
+//
+// # Safety
+//
+// `json` must be a NUL-terminated string and `out` valid for writes.
+enum TftStatus tft_encode_message(const char *json, enum TftFraming framing, struct TftBuffer *out);
+
+// Decode and validate one frame, including its newline or length
+// prefix, into JSON; release the string with [`tft_string_free`]
+//
+// # Safety
+//
+// `frame` must be valid for reads of `len` bytes and `out_json` valid
+// for writes.
+enum TftStatus tft_decode_message(const uint8_t *frame,
+                                  size_t len,
+                                  enum TftFraming framing,
+                                  char **out_json);
+
+// Encode a chunk of transfer `transfer_id` into one frame, hashing
+// `data` for its `hash` field
+//
+// # Safety
+//
+// `transfer_id` must be a NUL-terminated string, `data` valid for reads
+// of `len` bytes (or NULL when `len` is 0) and `out` valid for writes.
+enum TftStatus tft_encode_chunk(const char *transfer_id,
+                                size_t chunk_index,
+                                const uint8_t *data,
+                                size_t len,
+                                enum TftFraming framing,
+                                struct TftBuffer *out);
+
+// Decode one frame that must hold a chunk
+//
+// # Safety
+//
+// `frame` must be valid for reads of `len` bytes and `out` valid for
+// writes.
+enum TftStatus tft_decode_chunk(const uint8_t *frame,
+                                size_t len,
+                                enum TftFraming framing,
+                                struct TftChunk *out);
+
+// Release a string returned by the library; NULL is ignored
+//
+// # Safety
+//
+// `string` must be NULL or a string from this library not yet freed.
+void tft_string_free(char *string);
+
+// Merkle root over `count` chunk hashes, as announced in a transfer init
+//
+// A file with no chunks has an empty root, written as "".
+//
+// # Safety
+//
+// `hashes` must point to `count` NUL-terminated strings (or be NULL when
+// `count` is 0) and `out_hex` be valid for writes of `TFT_HASH_LEN + 1`
+// bytes.
+enum TftStatus tft_merkle_root(const char *const *hashes, size_t count, char *out_hex);
+
+// Whether `count` chunk hashes produce the Merkle root `root`
+//
+// # Safety
+//
+// `hashes` must point to `count` NUL-terminated strings (or be NULL when
+// `count` is 0), `root` must be a NUL-terminated string and `out_valid`
+// valid for writes.
+enum TftStatus tft_merkle_verify(const char *const *hashes,
+                                 size_t count,
+                                 const char *root,
+                                 bool *out_valid);
+
+// Hash the file at `path` in chunks of `chunk_size` bytes, giving its
+// Merkle root and size
+//
+// # Safety
+//
+// `path` must be a NUL-terminated string, `out_root_hex` valid for
+// writes of `TFT_HASH_LEN + 1` bytes and `out_size` valid for writes.
+enum TftStatus tft_file_merkle_root(const char *path,
+                                    size_t chunk_size,
+                                    char *out_root_hex,
+                                    uint64_t *out_size);
+
+#ifdef __cplusplus
+}  // extern "C"
+#endif  // __cplusplus
+
+#endif  /* TFT_H */
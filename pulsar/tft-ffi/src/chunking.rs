@@ -0,0 +1,165 @@
+//! Chunking files and hashing chunks
+
+use crate::{bytes_arg, guard, out_arg, str_arg, write_hash, FfiError, TftBuffer, TftStatus};
+use std::ffi::c_char;
+use std::path::Path;
+use tft_core::{ChunkInfo, ChunkedFile, FileChunker};
+
+/// A file opened for chunking; close with [`tft_chunked_file_free`]
+pub struct TftChunkedFile(ChunkedFile);
+
+/// Open the file at `path` to be read in chunks of `chunk_size` bytes
+///
+/// Large files on local disks are memory-mapped, as by the Rust sender.
+///
+/// # Safety
+///
+/// `path` must be a NUL-terminated string and `out` valid for writes.
+#[no_mangle]
+pub unsafe extern "C" fn tft_chunked_file_open(
+    path: *const c_char,
+    chunk_size: usize,
+    out: *mut *mut TftChunkedFile,
+) -> TftStatus {
+    guard(|| {
+        let path = str_arg(path, "path")?;
+        let out = out_arg(out, "out")?;
+        let file = FileChunker::new(chunk_size).open(Path::new(path))?;
+        *out = Box::into_raw(Box::new(TftChunkedFile(file)));
+        Ok(())
+    })
+}
+
+/// Close a file; NULL is ignored
+///
+/// # Safety
+///
+/// `file` must be NULL or a file from [`tft_chunked_file_open`] that has
+/// not been freed.
+#[no_mangle]
+pub unsafe extern "C" fn tft_chunked_file_free(file: *mut TftChunkedFile) {
+    if !file.is_null() {
+        drop(Box::from_raw(file));
+    }
+}
+
+/// Size of the file in bytes, or 0 for NULL
+///
+/// # Safety
+///
+/// `file` must be NULL or an open file.
+#[no_mangle]
+pub unsafe extern "C" fn tft_chunked_file_len(file: *const TftChunkedFile) -> u64 {
+    file.as_ref().map_or(0, |file| file.0.len())
+}
+
+/// Number of chunks in the file, or 0 for NULL
+///
+/// # Safety
+///
+/// `file` must be NULL or an open file.
+#[no_mangle]
+pub unsafe extern "C" fn tft_chunked_file_chunk_count(file: *const TftChunkedFile) -> usize {
+    file.as_ref().map_or(0, |file| file.0.chunk_count())
+}
+
+/// Copy chunk `index` into `out`, to be released with `tft_buffer_free`
+///
+/// # Safety
+///
+/// `file` must be an open file and `out` valid for writes.
+#[no_mangle]
+pub unsafe extern "C" fn tft_chunked_file_read(
+    file: *const TftChunkedFile,
+    index: usize,
+    out: *mut TftBuffer,
+) -> TftStatus {
+    guard(|| {
+        let file = file
+            .as_ref()
+            .ok_or_else(|| FfiError::new(TftStatus::NullPointer, "file is NULL"))?;
+        let out = out_arg(out, "out")?;
+        let chunk = file.0.chunk(index).map_err(|e| {
+            if index >= file.0.chunk_count() {
+                FfiError::invalid(e.to_string())
+            } else {
+                e.into()
+            }
+        })?;
+        *out = TftBuffer::from_vec(chunk.into_owned());
+        Ok(())
+    })
+}
+
+/// Hash of a chunk's bytes, as the protocol's `hash` field carries it
+///
+/// # Safety
+///
+/// `data` must be valid for reads of `len` bytes (or NULL when `len` is
+/// 0) and `out_hex` valid for writes of `TFT_HASH_LEN + 1` bytes.
+#[no_mangle]
+pub unsafe extern "C" fn tft_chunk_hash(
+    data: *const u8,
+    len: usize,
+    out_hex: *mut c_char,
+) -> TftStatus {
+    guard(|| {
+        let data = bytes_arg(data, len, "data")?;
+        write_hash(out_hex, &ChunkInfo::compute_hash(data))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::ffi::{CStr, CString};
+
+    #[test]
+    fn test_chunks_match_core() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("data.bin");
+        let data: Vec<u8> = (0..10_000u32).map(|i| (i % 251) as u8).collect();
+        std::fs::write(&path, &data).unwrap();
+        let c_path = CString::new(path.to_str().unwrap()).unwrap();
+
+        unsafe {
+            let mut file = std::ptr::null_mut();
+            assert_eq!(
+                tft_chunked_file_open(c_path.as_ptr(), 4096, &mut file),
+                TftStatus::Ok
+            );
+            assert_eq!(tft_chunked_file_len(file), 10_000);
+            assert_eq!(tft_chunked_file_chunk_count(file), 3);
+
+            let mut chunk = TftBuffer::empty();
+            assert_eq!(tft_chunked_file_read(file, 2, &mut chunk), TftStatus::Ok);
+            let bytes = std::slice::from_raw_parts(chunk.data, chunk.len);
+            assert_eq!(bytes, &data[8192..]);
+
+            let mut hash = [0 as c_char; crate::TFT_HASH_LEN + 1];
+            assert_eq!(
+                tft_chunk_hash(chunk.data, chunk.len, hash.as_mut_ptr()),
+                TftStatus::Ok
+            );
+            let hash = CStr::from_ptr(hash.as_ptr()).to_str().unwrap();
+            assert_eq!(hash, ChunkInfo::compute_hash(&data[8192..]));
+            crate::tft_buffer_free(&mut chunk);
+
+            assert_eq!(
+                tft_chunked_file_read(file, 3, &mut chunk),
+                TftStatus::InvalidArgument
+            );
+            tft_chunked_file_free(file);
+
+            let missing = CString::new(dir.path().join("missing").to_str().unwrap()).unwrap();
+            assert_eq!(
+                tft_chunked_file_open(missing.as_ptr(), 4096, &mut file),
+                TftStatus::Io
+            );
+            assert_eq!(
+                tft_chunked_file_open(std::ptr::null(), 4096, &mut file),
+                TftStatus::NullPointer
+            );
+        }
+    }
+}
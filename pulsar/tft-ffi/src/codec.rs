@@ -0,0 +1,343 @@
+//! Encoding and decoding protocol frames
+//!
+//! Messages cross the boundary as JSON in the NDJSON shape, whichever
+//! framing goes on the wire, so callers need no bincode of their own.
+//! Chunks, the bulk of any transfer, also have their own calls that pass
+//! the payload as raw bytes.
+
+use crate::{bytes_arg, guard, out_arg, str_arg, FfiError, FfiResult, TftBuffer, TftStatus};
+use std::ffi::{c_char, CString};
+use tft_core::codec::{decode_binary, encode_frame, validate_message};
+use tft_core::protocol::ChunkMessage;
+use tft_core::{decode_message, ChunkInfo, Framing, Message};
+use uuid::Uuid;
+
+/// Length of a hyphenated transfer ID, without the NUL
+pub const TFT_UUID_LEN: usize = 36;
+
+/// Wire framing of a frame
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TftFraming {
+    /// One JSON object per line
+    Ndjson = 0,
+    /// Big-endian u32 payload length, then a bincode payload
+    LengthPrefixed = 1,
+}
+
+impl From<TftFraming> for Framing {
+    fn from(framing: TftFraming) -> Self {
+        match framing {
+            TftFraming::Ndjson => Framing::Ndjson,
+            TftFraming::LengthPrefixed => Framing::LengthPrefixed,
+        }
+    }
+}
+
+/// A decoded chunk; release `data` with `tft_buffer_free`
+///
+/// The array lengths are `TFT_UUID_LEN + 1` and `TFT_HASH_LEN + 1`,
+/// spelled out because cbindgen can't evaluate them.
+#[repr(C)]
+#[derive(Debug)]
+pub struct TftChunk {
+    /// Hyphenated transfer ID, NUL-terminated
+    pub transfer_id: [c_char; 37],
+    pub chunk_index: usize,
+    /// Hash the sender gave for `data`, NUL-terminated; already checked
+    /// to be well-formed, not that it matches
+    pub hash: [c_char; 65],
+    pub data: TftBuffer,
+}
+
+/// Encode a message given as JSON into one frame, including the newline
+/// or length prefix
+///
+/// The message is validated as a peer would validate it, so a frame this
+/// returns will not be rejected for its fields.
+///
+/// # Safety
+///
+/// `json` must be a NUL-terminated string and `out` valid for writes.
+#[no_mangle]
+pub unsafe extern "C" fn tft_encode_message(
+    json: *const c_char,
+    framing: TftFraming,
+    out: *mut TftBuffer,
+) -> TftStatus {
+    guard(|| {
+        let json = str_arg(json, "json")?;
+        let out = out_arg(out, "out")?;
+        let message: Message = serde_json::from_str(json)
+            .map_err(|e| FfiError::new(TftStatus::Decode, format!("Malformed message: {}", e)))?;
+        *out = TftBuffer::from_vec(encode(&message, framing)?);
+        Ok(())
+    })
+}
+
+/// Decode and validate one frame, including its newline or length
+/// prefix, into JSON; release the string with [`tft_string_free`]
+///
+/// # Safety
+///
+/// `frame` must be valid for reads of `len` bytes and `out_json` valid
+/// for writes.
+#[no_mangle]
+pub unsafe extern "C" fn tft_decode_message(
+    frame: *const u8,
+    len: usize,
+    framing: TftFraming,
+    out_json: *mut *mut c_char,
+) -> TftStatus {
+    guard(|| {
+        let frame = bytes_arg(frame, len, "frame")?;
+        let out_json = out_arg(out_json, "out_json")?;
+        let message = decode(frame, framing)?;
+        let json = serde_json::to_string(&message)
+            .map_err(|e| FfiError::new(TftStatus::Encode, e.to_string()))?;
+        // JSON escapes control characters, so there is no NUL to trip on
+        *out_json = CString::new(json).unwrap().into_raw();
+        Ok(())
+    })
+}
+
+/// Encode a chunk of transfer `transfer_id` into one frame, hashing
+/// `data` for its `hash` field
+///
+/// # Safety
+///
+/// `transfer_id` must be a NUL-terminated string, `data` valid for reads
+/// of `len` bytes (or NULL when `len` is 0) and `out` valid for writes.
+#[no_mangle]
+pub unsafe extern "C" fn tft_encode_chunk(
+    transfer_id: *const c_char,
+    chunk_index: usize,
+    data: *const u8,
+    len: usize,
+    framing: TftFraming,
+    out: *mut TftBuffer,
+) -> TftStatus {
+    guard(|| {
+        let transfer_id = parse_uuid(str_arg(transfer_id, "transfer_id")?)?;
+        let data = bytes_arg(data, len, "data")?;
+        let out = out_arg(out, "out")?;
+        let message = Message::Chunk(ChunkMessage {
+            transfer_id,
+            chunk_index,
+            data: data.to_vec(),
+            hash: ChunkInfo::compute_hash(data),
+        });
+        *out = TftBuffer::from_vec(encode(&message, framing)?);
+        Ok(())
+    })
+}
+
+/// Decode one frame that must hold a chunk
+///
+/// # Safety
+///
+/// `frame` must be valid for reads of `len` bytes and `out` valid for
+/// writes.
+#[no_mangle]
+pub unsafe extern "C" fn tft_decode_chunk(
+    frame: *const u8,
+    len: usize,
+    framing: TftFraming,
+    out: *mut TftChunk,
+) -> TftStatus {
+    guard(|| {
+        let frame = bytes_arg(frame, len, "frame")?;
+        let out = out_arg(out, "out")?;
+        let chunk = match decode(frame, framing)? {
+            Message::Chunk(chunk) => chunk,
+            other => {
+                return Err(FfiError::new(
+                    TftStatus::Decode,
+                    format!("Expected a chunk, got {:?}", other.message_type()),
+                ))
+            }
+        };
+
+        let mut decoded = TftChunk {
+            transfer_id: [0; 37],
+            chunk_index: chunk.chunk_index,
+            hash: [0; 65],
+            data: TftBuffer::from_vec(chunk.data),
+        };
+        copy_str(
+            &mut decoded.transfer_id,
+            &chunk.transfer_id.hyphenated().to_string(),
+        );
+        copy_str(&mut decoded.hash, &chunk.hash);
+        *out = decoded;
+        Ok(())
+    })
+}
+
+/// Release a string returned by the library; NULL is ignored
+///
+/// # Safety
+///
+/// `string` must be NULL or a string from this library not yet freed.
+#[no_mangle]
+pub unsafe extern "C" fn tft_string_free(string: *mut c_char) {
+    if !string.is_null() {
+        drop(CString::from_raw(string));
+    }
+}
+
+fn encode(message: &Message, framing: TftFraming) -> FfiResult<Vec<u8>> {
+    validate_message(message)?;
+    encode_frame(message, framing.into())
+        .map_err(|e| FfiError::new(TftStatus::Encode, e.to_string()))
+}
+
+fn decode(frame: &[u8], framing: TftFraming) -> FfiResult<Message> {
+    match framing {
+        TftFraming::Ndjson => Ok(decode_message(frame)?),
+        TftFraming::LengthPrefixed => {
+            let Some((prefix, payload)) = frame.split_first_chunk::<4>() else {
+                return Err(FfiError::new(
+                    TftStatus::Decode,
+                    "Frame is shorter than its length prefix",
+                ));
+            };
+            if u32::from_be_bytes(*prefix) as usize != payload.len() {
+                return Err(FfiError::new(
+                    TftStatus::Decode,
+                    format!(
+                        "Length prefix says {} bytes, frame has {}",
+                        u32::from_be_bytes(*prefix),
+                        payload.len()
+                    ),
+                ));
+            }
+            Ok(decode_binary(payload)?)
+        }
+    }
+}
+
+fn parse_uuid(value: &str) -> FfiResult<Uuid> {
+    Uuid::parse_str(value).map_err(|e| FfiError::invalid(format!("transfer_id: {}", e)))
+}
+
+/// Copy `value` into a fixed buffer that has room for it and a NUL
+fn copy_str(buffer: &mut [c_char], value: &str) {
+    for (slot, byte) in buffer.iter_mut().zip(value.bytes()) {
+        *slot = byte as c_char;
+    }
+    buffer[value.len().min(buffer.len() - 1)] = 0;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::ffi::CStr;
+
+    const TRANSFER_ID: &str = "6f1c2a52-3c7e-4a35-9a7b-0c7d2f1e9b40";
+
+    #[test]
+    fn test_message_roundtrip_both_framings() {
+        let json = CString::new(format!(
+            r#"{{"type":"chunk_ack","transfer_id":"{}","chunk_index":4,"success":true}}"#,
+            TRANSFER_ID
+        ))
+        .unwrap();
+
+        for framing in [TftFraming::Ndjson, TftFraming::LengthPrefixed] {
+            unsafe {
+                let mut frame = TftBuffer::empty();
+                assert_eq!(
+                    tft_encode_message(json.as_ptr(), framing, &mut frame),
+                    TftStatus::Ok
+                );
+
+                let mut decoded = std::ptr::null_mut();
+                let status = tft_decode_message(frame.data, frame.len, framing, &mut decoded);
+                assert_eq!(status, TftStatus::Ok);
+                let value: serde_json::Value =
+                    serde_json::from_str(CStr::from_ptr(decoded).to_str().unwrap()).unwrap();
+                assert_eq!(value["chunk_index"], 4);
+                tft_string_free(decoded);
+
+                // A frame cut short is rejected, not misread (NDJSON
+                // tolerates a missing newline, so cut into the object)
+                let status = tft_decode_message(frame.data, frame.len - 2, framing, &mut decoded);
+                assert_eq!(status, TftStatus::Decode);
+                crate::tft_buffer_free(&mut frame);
+            }
+        }
+    }
+
+    #[test]
+    fn test_chunk_roundtrip() {
+        let id = CString::new(TRANSFER_ID).unwrap();
+        let data = b"chunk payload";
+        unsafe {
+            let mut frame = TftBuffer::empty();
+            let status = tft_encode_chunk(
+                id.as_ptr(),
+                7,
+                data.as_ptr(),
+                data.len(),
+                TftFraming::LengthPrefixed,
+                &mut frame,
+            );
+            assert_eq!(status, TftStatus::Ok);
+
+            let mut chunk: TftChunk = std::mem::zeroed();
+            let status = tft_decode_chunk(
+                frame.data,
+                frame.len,
+                TftFraming::LengthPrefixed,
+                &mut chunk,
+            );
+            assert_eq!(status, TftStatus::Ok);
+            assert_eq!(chunk.chunk_index, 7);
+            assert_eq!(
+                CStr::from_ptr(chunk.transfer_id.as_ptr()).to_str().unwrap(),
+                TRANSFER_ID
+            );
+            assert_eq!(
+                CStr::from_ptr(chunk.hash.as_ptr()).to_str().unwrap(),
+                ChunkInfo::compute_hash(data)
+            );
+            assert_eq!(
+                std::slice::from_raw_parts(chunk.data.data, chunk.data.len),
+                data
+            );
+            crate::tft_buffer_free(&mut chunk.data);
+            crate::tft_buffer_free(&mut frame);
+
+            let bad = CString::new("not-a-uuid").unwrap();
+            let status = tft_encode_chunk(
+                bad.as_ptr(),
+                0,
+                data.as_ptr(),
+                1,
+                TftFraming::Ndjson,
+                &mut frame,
+            );
+            assert_eq!(status, TftStatus::InvalidArgument);
+        }
+    }
+
+    #[test]
+    fn test_invalid_messages_are_refused() {
+        // Valid JSON, but a path where a file name belongs
+        let json = CString::new(format!(
+            r#"{{"type":"transfer_init","transfer_id":"{}","filename":"../passwd","size":1,
+                "chunk_size":1,"total_chunks":1,"merkle_root":"{}","encrypted":false,
+                "compression":"none"}}"#,
+            TRANSFER_ID,
+            "a".repeat(64)
+        ))
+        .unwrap();
+        unsafe {
+            let mut frame = TftBuffer::empty();
+            let status = tft_encode_message(json.as_ptr(), TftFraming::Ndjson, &mut frame);
+            assert_eq!(status, TftStatus::Decode);
+            assert!(frame.data.is_null());
+        }
+    }
+}
@@ -0,0 +1,272 @@
+//! C ABI for TFT
+//!
+//! Lets agents written in C, C++ or Go chunk files, compute and check
+//! Merkle roots, and encode and decode protocol messages exactly as the
+//! Rust implementation does. The header is `include/tft.h`, regenerated
+//! by cbindgen on every build; `conformance/` holds a C program that
+//! checks a build against known values.
+//!
+//! Conventions shared by every function:
+//! - Fallible functions return a [`TftStatus`]; on failure
+//!   [`tft_last_error`] describes what went wrong
+//! - Strings are NUL-terminated UTF-8
+//! - Memory handed to the caller comes back through a matching `_free`
+//!   function, never the caller's `free`
+//! - Hashes are BLAKE3, written as [`TFT_HASH_LEN`] hex characters; hash
+//!   outputs need room for those plus the NUL
+//! - Panics are caught at the boundary and reported as
+//!   [`TftStatus::Panic`]
+
+mod chunking;
+mod codec;
+mod merkle;
+
+pub use chunking::*;
+pub use codec::*;
+pub use merkle::*;
+
+use std::cell::RefCell;
+use std::ffi::{c_char, CStr, CString};
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::OnceLock;
+
+/// Length of a hex-encoded hash, without the NUL
+pub const TFT_HASH_LEN: usize = 64;
+
+/// Outcome of a call
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TftStatus {
+    Ok = 0,
+    /// A required pointer was NULL
+    NullPointer = 1,
+    /// An argument was out of range or not valid UTF-8
+    InvalidArgument = 2,
+    /// Reading a file failed
+    Io = 3,
+    /// A frame or message was malformed or failed validation
+    Decode = 4,
+    /// A message could not be encoded
+    Encode = 5,
+    /// The library panicked; this is a bug
+    Panic = 6,
+}
+
+/// Bytes owned by the library; release with [`tft_buffer_free`]
+#[repr(C)]
+#[derive(Debug)]
+pub struct TftBuffer {
+    pub data: *mut u8,
+    pub len: usize,
+}
+
+impl TftBuffer {
+    fn empty() -> Self {
+        Self {
+            data: std::ptr::null_mut(),
+            len: 0,
+        }
+    }
+
+    fn from_vec(bytes: Vec<u8>) -> Self {
+        let bytes = bytes.into_boxed_slice();
+        let len = bytes.len();
+        Self {
+            data: Box::into_raw(bytes).cast(),
+            len,
+        }
+    }
+}
+
+/// Release a buffer's bytes and reset it to empty; NULL and empty
+/// buffers are ignored
+///
+/// # Safety
+///
+/// `buffer` must be NULL or point to a buffer filled in by this library
+/// and not yet freed.
+#[no_mangle]
+pub unsafe extern "C" fn tft_buffer_free(buffer: *mut TftBuffer) {
+    let Some(buffer) = buffer.as_mut() else {
+        return;
+    };
+    if !buffer.data.is_null() {
+        drop(Box::from_raw(std::ptr::slice_from_raw_parts_mut(
+            buffer.data,
+            buffer.len,
+        )));
+    }
+    *buffer = TftBuffer::empty();
+}
+
+/// Protocol version spoken by this library, e.g. "1.0"
+#[no_mangle]
+pub extern "C" fn tft_protocol_version() -> *const c_char {
+    static VERSION: OnceLock<CString> = OnceLock::new();
+    VERSION
+        .get_or_init(|| CString::new(tft_core::PROTOCOL_VERSION).unwrap())
+        .as_ptr()
+}
+
+thread_local! {
+    static LAST_ERROR: RefCell<Option<CString>> = const { RefCell::new(None) };
+}
+
+/// Message for the last failed call on this thread, or NULL if the last
+/// call succeeded
+///
+/// The string stays valid until the next call into the library on the
+/// same thread.
+#[no_mangle]
+pub extern "C" fn tft_last_error() -> *const c_char {
+    LAST_ERROR.with(|last| {
+        last.borrow()
+            .as_ref()
+            .map_or(std::ptr::null(), |message| message.as_ptr())
+    })
+}
+
+/// Why a call failed, before it becomes a status and a message
+pub(crate) struct FfiError {
+    status: TftStatus,
+    message: String,
+}
+
+impl FfiError {
+    pub(crate) fn new(status: TftStatus, message: impl Into<String>) -> Self {
+        Self {
+            status,
+            message: message.into(),
+        }
+    }
+
+    pub(crate) fn invalid(message: impl Into<String>) -> Self {
+        Self::new(TftStatus::InvalidArgument, message)
+    }
+}
+
+impl From<std::io::Error> for FfiError {
+    fn from(error: std::io::Error) -> Self {
+        Self::new(TftStatus::Io, error.to_string())
+    }
+}
+
+impl From<tft_core::DecodeError> for FfiError {
+    fn from(error: tft_core::DecodeError) -> Self {
+        Self::new(TftStatus::Decode, error.to_string())
+    }
+}
+
+pub(crate) type FfiResult<T> = Result<T, FfiError>;
+
+/// Run the body of an exported function, recording how it failed
+pub(crate) fn guard(body: impl FnOnce() -> FfiResult<()>) -> TftStatus {
+    let outcome = panic::catch_unwind(AssertUnwindSafe(body))
+        .unwrap_or_else(|_| Err(FfiError::new(TftStatus::Panic, "tft-ffi panicked")));
+    LAST_ERROR.with(|last| match outcome {
+        Ok(()) => {
+            *last.borrow_mut() = None;
+            TftStatus::Ok
+        }
+        Err(error) => {
+            // Messages come from Rust strings, which may hold a NUL
+            let message = error.message.replace('\0', "\\0");
+            *last.borrow_mut() = CString::new(message).ok();
+            error.status
+        }
+    })
+}
+
+/// A caller's string argument
+///
+/// # Safety
+///
+/// `ptr` must be NULL or a NUL-terminated string.
+pub(crate) unsafe fn str_arg<'a>(ptr: *const c_char, name: &str) -> FfiResult<&'a str> {
+    if ptr.is_null() {
+        return Err(FfiError::new(
+            TftStatus::NullPointer,
+            format!("{} is NULL", name),
+        ));
+    }
+    CStr::from_ptr(ptr)
+        .to_str()
+        .map_err(|_| FfiError::invalid(format!("{} is not valid UTF-8", name)))
+}
+
+/// A caller's byte range; NULL is allowed when it is empty
+///
+/// # Safety
+///
+/// `ptr` must be NULL or valid for reads of `len` bytes.
+pub(crate) unsafe fn bytes_arg<'a>(ptr: *const u8, len: usize, name: &str) -> FfiResult<&'a [u8]> {
+    if len == 0 {
+        return Ok(&[]);
+    }
+    if ptr.is_null() {
+        return Err(FfiError::new(
+            TftStatus::NullPointer,
+            format!("{} is NULL", name),
+        ));
+    }
+    Ok(std::slice::from_raw_parts(ptr, len))
+}
+
+/// Where to write a result
+///
+/// # Safety
+///
+/// `ptr` must be NULL or valid for writes.
+pub(crate) unsafe fn out_arg<'a, T>(ptr: *mut T, name: &str) -> FfiResult<&'a mut T> {
+    ptr.as_mut()
+        .ok_or_else(|| FfiError::new(TftStatus::NullPointer, format!("{} is NULL", name)))
+}
+
+/// Copy a hex hash and its NUL into a caller's buffer
+///
+/// # Safety
+///
+/// `out` must be NULL or valid for writes of `TFT_HASH_LEN + 1` bytes.
+pub(crate) unsafe fn write_hash(out: *mut c_char, hash: &str) -> FfiResult<()> {
+    if out.is_null() {
+        return Err(FfiError::new(TftStatus::NullPointer, "hash output is NULL"));
+    }
+    if hash.len() > TFT_HASH_LEN {
+        return Err(FfiError::invalid("hash is longer than TFT_HASH_LEN"));
+    }
+    std::ptr::copy_nonoverlapping(hash.as_ptr().cast(), out, hash.len());
+    *out.add(hash.len()) = 0;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_errors_are_reported_per_call() {
+        let status = guard(|| Err(FfiError::invalid("bad\0argument")));
+        assert_eq!(status, TftStatus::InvalidArgument);
+        let message = unsafe { CStr::from_ptr(tft_last_error()) };
+        assert_eq!(message.to_str().unwrap(), "bad\\0argument");
+
+        assert_eq!(guard(|| panic!("boom")), TftStatus::Panic);
+        assert_eq!(guard(|| Ok(())), TftStatus::Ok);
+        assert!(tft_last_error().is_null());
+
+        let version = unsafe { CStr::from_ptr(tft_protocol_version()) };
+        assert_eq!(version.to_str().unwrap(), tft_core::PROTOCOL_VERSION);
+    }
+
+    #[test]
+    fn test_buffer_free_resets() {
+        let mut buffer = TftBuffer::from_vec(vec![1, 2, 3]);
+        unsafe {
+            tft_buffer_free(&mut buffer);
+            assert!(buffer.data.is_null());
+            // A second free is harmless
+            tft_buffer_free(&mut buffer);
+            tft_buffer_free(std::ptr::null_mut());
+        }
+    }
+}
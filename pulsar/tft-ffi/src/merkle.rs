@@ -0,0 +1,138 @@
+//! Merkle roots over chunk hashes
+
+use crate::{guard, out_arg, str_arg, write_hash, FfiError, FfiResult, TftStatus};
+use std::ffi::c_char;
+use std::path::Path;
+use tft_core::{hash_file, MerkleTree};
+
+/// Merkle root over `count` chunk hashes, as announced in a transfer init
+///
+/// A file with no chunks has an empty root, written as "".
+///
+/// # Safety
+///
+/// `hashes` must point to `count` NUL-terminated strings (or be NULL when
+/// `count` is 0) and `out_hex` be valid for writes of `TFT_HASH_LEN + 1`
+/// bytes.
+#[no_mangle]
+pub unsafe extern "C" fn tft_merkle_root(
+    hashes: *const *const c_char,
+    count: usize,
+    out_hex: *mut c_char,
+) -> TftStatus {
+    guard(|| {
+        let tree = MerkleTree::new(hash_args(hashes, count)?);
+        write_hash(out_hex, tree.root())
+    })
+}
+
+/// Whether `count` chunk hashes produce the Merkle root `root`
+///
+/// # Safety
+///
+/// `hashes` must point to `count` NUL-terminated strings (or be NULL when
+/// `count` is 0), `root` must be a NUL-terminated string and `out_valid`
+/// valid for writes.
+#[no_mangle]
+pub unsafe extern "C" fn tft_merkle_verify(
+    hashes: *const *const c_char,
+    count: usize,
+    root: *const c_char,
+    out_valid: *mut bool,
+) -> TftStatus {
+    guard(|| {
+        let hashes = hash_args(hashes, count)?;
+        let root = str_arg(root, "root")?;
+        *out_arg(out_valid, "out_valid")? = MerkleTree::new(hashes).root() == root;
+        Ok(())
+    })
+}
+
+/// Hash the file at `path` in chunks of `chunk_size` bytes, giving its
+/// Merkle root and size
+///
+/// # Safety
+///
+/// `path` must be a NUL-terminated string, `out_root_hex` valid for
+/// writes of `TFT_HASH_LEN + 1` bytes and `out_size` valid for writes.
+#[no_mangle]
+pub unsafe extern "C" fn tft_file_merkle_root(
+    path: *const c_char,
+    chunk_size: usize,
+    out_root_hex: *mut c_char,
+    out_size: *mut u64,
+) -> TftStatus {
+    guard(|| {
+        let path = str_arg(path, "path")?;
+        let out_size = out_arg(out_size, "out_size")?;
+        let hashes = hash_file(Path::new(path), chunk_size)?;
+        write_hash(out_root_hex, &hashes.merkle_root())?;
+        *out_size = hashes.size;
+        Ok(())
+    })
+}
+
+/// # Safety
+///
+/// As for [`tft_merkle_root`].
+unsafe fn hash_args(hashes: *const *const c_char, count: usize) -> FfiResult<Vec<String>> {
+    if count == 0 {
+        return Ok(Vec::new());
+    }
+    if hashes.is_null() {
+        return Err(FfiError::new(TftStatus::NullPointer, "hashes is NULL"));
+    }
+    std::slice::from_raw_parts(hashes, count)
+        .iter()
+        .map(|&hash| str_arg(hash, "hash").map(str::to_owned))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::ffi::{CStr, CString};
+    use tft_core::ChunkInfo;
+
+    #[test]
+    fn test_root_and_verify() {
+        let hashes: Vec<CString> = [&b"one"[..], b"two", b"three"]
+            .iter()
+            .map(|chunk| CString::new(ChunkInfo::compute_hash(chunk)).unwrap())
+            .collect();
+        let pointers: Vec<_> = hashes.iter().map(|hash| hash.as_ptr()).collect();
+        let expected = MerkleTree::new(
+            hashes
+                .iter()
+                .map(|hash| hash.to_str().unwrap().to_owned())
+                .collect(),
+        );
+
+        unsafe {
+            let mut root = [0 as c_char; crate::TFT_HASH_LEN + 1];
+            assert_eq!(
+                tft_merkle_root(pointers.as_ptr(), 3, root.as_mut_ptr()),
+                TftStatus::Ok
+            );
+            assert_eq!(
+                CStr::from_ptr(root.as_ptr()).to_str().unwrap(),
+                expected.root()
+            );
+
+            let mut valid = false;
+            let status = tft_merkle_verify(pointers.as_ptr(), 3, root.as_ptr(), &mut valid);
+            assert_eq!(status, TftStatus::Ok);
+            assert!(valid);
+
+            // Dropping a chunk changes the root
+            tft_merkle_verify(pointers.as_ptr(), 2, root.as_ptr(), &mut valid);
+            assert!(!valid);
+
+            assert_eq!(
+                tft_merkle_root(std::ptr::null(), 0, root.as_mut_ptr()),
+                TftStatus::Ok
+            );
+            assert_eq!(root[0], 0);
+        }
+    }
+}
@@ -182,7 +182,7 @@ impl KnownHosts {
         lines.sort(); // Keep file sorted for readability
 
         let content = lines.join("\n") + "\n";
-        fs::write(&self.path, content)
+        pulsar_fs::write(&self.path, content)
             .with_context(|| format!("Failed to write known_hosts to {}", self.path.display()))?;
 
         tracing::debug!("Saved {} host keys to {}", self.hosts.len(), self.path.display());
@@ -4,12 +4,29 @@
 //! - QUIC/HTTP/3 (primary, high-performance)
 //! - SSH/SFTP (fallback, compatibility)
 //! - WebRTC (peer-to-peer, future)
+//! - Loopback (in-process, with simulated network conditions for tests)
+//!
+//! [`NetworkMonitor`] reports local network changes, on which transports
+//! migrate or reconnect through [`Transport::network_changed`].
+//!
+//! Transports cap their bandwidth with a [`Throttle`], from the config's
+//! `max_bytes_per_sec` or shared with others and changed at runtime.
+//!
+//! [`SftpServer`] accepts uploads from plain SFTP and `scp` clients into a
+//! directory per user, for the daemon to pick up.
 
 pub mod transport;
+pub mod retry;
+pub mod loopback;
+pub mod network;
+pub mod throttle;
 
 #[cfg(feature = "quic")]
 pub mod quic;
 
+#[cfg(feature = "quic")]
+pub mod tls;
+
 #[cfg(feature = "ssh")]
 pub mod ssh;
 
@@ -25,14 +42,24 @@ pub mod known_hosts;
 #[cfg(feature = "webrtc")]
 pub mod webrtc;
 
-pub use transport::{Transport, TransportConfig, TransportError};
+pub use transport::{TlsOptions, Transport, TransportConfig, TransportError};
+pub use retry::{Attempts, Backoff, RetryEvent, RetryObserver, RetryPolicy};
+pub use loopback::{LinkConditions, LinkControl, LinkStats, LoopbackTransport};
+pub use network::{ChangeKind, NetworkChange, NetworkMonitor, NetworkState};
+pub use throttle::{BandwidthLimit, Throttle};
+
+#[cfg(feature = "quic")]
+pub use quic::{is_replay_safe, QuicTransport};
 
 #[cfg(feature = "ssh")]
-pub use ssh_client::{SshSession, SshConfig, AuthMethod, spawn_ssh_io};
+pub use ssh_client::{scan_host_key, spawn_ssh_io, AuthMethod, ScannedHostKey, SshConfig, SshSession};
 
 #[cfg(feature = "ssh")]
 pub use known_hosts::{KnownHosts, HostKeyVerification};
 
+#[cfg(feature = "ssh")]
+pub use ssh::{load_or_create_host_key, SftpAuthHook, SftpCredential, SftpEvent, SftpServer};
+
 #[cfg(test)]
 mod tests {
     #[test]
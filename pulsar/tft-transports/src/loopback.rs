@@ -0,0 +1,511 @@
+//! In-process transport for tests and simulations
+//!
+//! [`LoopbackTransport::pair`] returns two ends of a simulated link. In
+//! each direction messages queue behind each other at the bandwidth cap,
+//! arrive after the latency plus random jitter (so a message can overtake
+//! the one before it) and are lost at the configured rate, never to
+//! arrive. Jitter and loss come from a seeded generator, so a failing
+//! simulation can be replayed exactly.
+//!
+//! A [`LinkControl`] changes the conditions mid-transfer or cuts the link:
+//! both ends then fail with [`TransportError::Closed`], messages in flight
+//! are lost, and each end must connect again once the link is restored,
+//! which is how a transfer exercises retries and resume without a network.
+
+use crate::retry::RetryObserver;
+use crate::throttle::Throttle;
+use crate::transport::{Transport, TransportConfig, TransportError};
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::Duration;
+use tokio::sync::{mpsc, watch};
+use tokio::time::Instant;
+
+/// Simulated network conditions, applied to each direction separately
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LinkConditions {
+    /// One-way delay
+    pub latency_ms: u64,
+    /// Up to this much is added to or taken off the latency of each message
+    pub jitter_ms: u64,
+    /// Fraction of messages lost (0.0-1.0)
+    pub loss: f64,
+    /// Bytes per second; `None` is unlimited
+    pub bandwidth_bytes_per_sec: Option<u64>,
+    /// Connects that fail before one succeeds
+    pub connect_failures: u32,
+    /// Seed for jitter and loss
+    pub seed: u64,
+}
+
+impl Default for LinkConditions {
+    fn default() -> Self {
+        Self {
+            latency_ms: 0,
+            jitter_ms: 0,
+            loss: 0.0,
+            bandwidth_bytes_per_sec: None,
+            connect_failures: 0,
+            seed: 1,
+        }
+    }
+}
+
+/// Messages carried in one direction of a link
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct LinkStats {
+    pub sent: u64,
+    pub lost: u64,
+    pub bytes_sent: u64,
+}
+
+enum Frame {
+    Data(Vec<u8>),
+    /// The sender disconnected
+    Close,
+}
+
+struct LinkState {
+    conditions: LinkConditions,
+    rng: u64,
+    connect_failures_left: u32,
+    /// Bumped on every cut; messages sent before it are lost
+    epoch: u64,
+    /// When each end's outgoing wire is free again
+    busy_until: [Option<Instant>; 2],
+    stats: [LinkStats; 2],
+}
+
+impl LinkState {
+    /// Next value in [0, 1) from a SplitMix64 sequence
+    fn random(&mut self) -> f64 {
+        self.rng = self.rng.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.rng;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^= z >> 31;
+        (z >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    fn latency(&mut self) -> Duration {
+        let latency = self.conditions.latency_ms as f64;
+        let jitter = self.conditions.jitter_ms as f64;
+        let offset = if jitter > 0.0 {
+            (self.random() * 2.0 - 1.0) * jitter
+        } else {
+            0.0
+        };
+        Duration::from_secs_f64((latency + offset).max(0.0) / 1000.0)
+    }
+}
+
+/// Changes the conditions of a loopback link, or cuts it
+#[derive(Clone)]
+pub struct LinkControl {
+    state: Arc<Mutex<LinkState>>,
+    up: Arc<watch::Sender<bool>>,
+}
+
+impl LinkControl {
+    fn new(conditions: LinkConditions) -> Self {
+        let (up, _) = watch::channel(true);
+        Self {
+            state: Arc::new(Mutex::new(LinkState {
+                rng: conditions.seed,
+                connect_failures_left: conditions.connect_failures,
+                conditions,
+                epoch: 0,
+                busy_until: [None; 2],
+                stats: [LinkStats::default(); 2],
+            })),
+            up: Arc::new(up),
+        }
+    }
+
+    fn state(&self) -> MutexGuard<'_, LinkState> {
+        self.state.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    /// Apply `conditions` to messages sent from now on
+    pub fn set_conditions(&self, conditions: LinkConditions) {
+        let mut state = self.state();
+        state.rng = conditions.seed;
+        state.connect_failures_left = conditions.connect_failures;
+        state.conditions = conditions;
+    }
+
+    pub fn conditions(&self) -> LinkConditions {
+        self.state().conditions.clone()
+    }
+
+    /// Drop the link: messages in flight are lost and both ends fail
+    /// until it is restored and they connect again
+    pub fn cut(&self) {
+        self.state().epoch += 1;
+        self.up.send_replace(false);
+    }
+
+    pub fn restore(&self) {
+        self.up.send_replace(true);
+    }
+
+    pub fn is_up(&self) -> bool {
+        *self.up.borrow()
+    }
+
+    /// Counters for messages sent by the first and the second end
+    pub fn stats(&self) -> (LinkStats, LinkStats) {
+        let stats = self.state().stats;
+        (stats[0], stats[1])
+    }
+}
+
+/// One end of a simulated link
+pub struct LoopbackTransport {
+    /// 0 or 1, indexing the link's per-direction state
+    end: usize,
+    link: LinkControl,
+    outgoing: mpsc::UnboundedSender<Frame>,
+    incoming: mpsc::UnboundedReceiver<Frame>,
+    up: watch::Receiver<bool>,
+    connected: bool,
+    retry_observer: Option<RetryObserver>,
+    throttle: Option<Throttle>,
+}
+
+impl LoopbackTransport {
+    /// Two ends of a link with `conditions`; each must connect before use
+    pub fn pair(conditions: LinkConditions) -> (Self, Self) {
+        let link = LinkControl::new(conditions);
+        let (to_second, from_first) = mpsc::unbounded_channel();
+        let (to_first, from_second) = mpsc::unbounded_channel();
+
+        let end = |end, outgoing, incoming| Self {
+            end,
+            link: link.clone(),
+            outgoing,
+            incoming,
+            up: link.up.subscribe(),
+            connected: false,
+            retry_observer: None,
+            throttle: None,
+        };
+        (end(0, to_second, from_second), end(1, to_first, from_first))
+    }
+
+    /// The link between this end and its peer
+    pub fn link(&self) -> LinkControl {
+        self.link.clone()
+    }
+
+    /// Report each connect attempt to `observer`
+    pub fn set_retry_observer(&mut self, observer: RetryObserver) {
+        self.retry_observer = Some(observer);
+    }
+
+    /// Limit this end's sends and receives with `throttle`, e.g. one
+    /// shared with other transports; otherwise connecting sets up one from
+    /// the config
+    pub fn set_throttle(&mut self, throttle: Throttle) {
+        self.throttle = Some(throttle);
+    }
+
+    /// The throttle in use, once set or connected
+    pub fn throttle(&self) -> Option<&Throttle> {
+        self.throttle.as_ref()
+    }
+
+    pub fn is_connected(&self) -> bool {
+        self.connected
+    }
+
+    fn connect_once(&mut self) -> Result<(), TransportError> {
+        if !*self.up.borrow_and_update() {
+            return Err(TransportError::ConnectionFailed("loopback link is down".to_string()));
+        }
+        {
+            let mut state = self.link.state();
+            if state.connect_failures_left > 0 {
+                state.connect_failures_left -= 1;
+                return Err(TransportError::ConnectionFailed(
+                    "simulated connect failure".to_string(),
+                ));
+            }
+        }
+
+        // A new connection starts clean, like a fresh stream would
+        while self.incoming.try_recv().is_ok() {}
+        self.connected = true;
+        Ok(())
+    }
+
+    fn check_connected(&mut self) -> Result<(), TransportError> {
+        if !self.connected {
+            return Err(TransportError::ConnectionFailed("not connected".to_string()));
+        }
+        if !*self.up.borrow() {
+            self.connected = false;
+            return Err(TransportError::Closed("loopback link was cut".to_string()));
+        }
+        Ok(())
+    }
+
+    /// Put `frame` on the wire: wait for the bandwidth it takes, then
+    /// deliver it after the latency unless it is lost on the way
+    async fn transmit(&mut self, frame: Frame) {
+        // Closing is never lost, so the peer always hears of it
+        let (len, is_data) = match &frame {
+            Frame::Data(data) => (data.len(), true),
+            Frame::Close => (0, false),
+        };
+
+        let (sent_at, latency, lost, epoch) = {
+            let mut state = self.link.state();
+            let now = Instant::now();
+            let start = state.busy_until[self.end].map_or(now, |busy| busy.max(now));
+            let wire_time = state
+                .conditions
+                .bandwidth_bytes_per_sec
+                .filter(|&bytes_per_sec| bytes_per_sec > 0)
+                .map_or(Duration::ZERO, |bytes_per_sec| {
+                    Duration::from_secs_f64(len as f64 / bytes_per_sec as f64)
+                });
+            let sent_at = start + wire_time;
+            state.busy_until[self.end] = Some(sent_at);
+
+            let loss = state.conditions.loss.clamp(0.0, 1.0);
+            let lost = is_data && loss > 0.0 && state.random() < loss;
+            let latency = state.latency();
+
+            if is_data {
+                let stats = &mut state.stats[self.end];
+                stats.sent += 1;
+                stats.bytes_sent += len as u64;
+                if lost {
+                    stats.lost += 1;
+                }
+            }
+            (sent_at, latency, lost, state.epoch)
+        };
+
+        tokio::time::sleep_until(sent_at).await;
+        if lost {
+            return;
+        }
+
+        let link = self.link.clone();
+        let outgoing = self.outgoing.clone();
+        tokio::spawn(async move {
+            tokio::time::sleep_until(sent_at + latency).await;
+            if link.is_up() && link.state().epoch == epoch {
+                let _ = outgoing.send(frame);
+            }
+        });
+    }
+}
+
+#[async_trait]
+impl Transport for LoopbackTransport {
+    async fn connect(&mut self, config: &TransportConfig) -> Result<(), TransportError> {
+        self.throttle
+            .get_or_insert_with(|| Throttle::new(config.bandwidth_limit()));
+        let observer = self.retry_observer.clone();
+        let mut attempts = config.retry.attempts(observer.as_ref());
+        loop {
+            attempts.begin();
+            match self.connect_once() {
+                Ok(()) => {
+                    attempts.succeeded();
+                    return Ok(());
+                }
+                Err(error) => attempts.failed(error).await?,
+            }
+        }
+    }
+
+    async fn send(&mut self, data: &[u8]) -> Result<(), TransportError> {
+        self.check_connected()?;
+        if let Some(throttle) = &self.throttle {
+            throttle.acquire(data.len()).await;
+        }
+        self.transmit(Frame::Data(data.to_vec())).await;
+        Ok(())
+    }
+
+    async fn receive(&mut self) -> Result<Vec<u8>, TransportError> {
+        self.check_connected()?;
+        loop {
+            tokio::select! {
+                frame = self.incoming.recv() => match frame {
+                    Some(Frame::Data(data)) => {
+                        if let Some(throttle) = &self.throttle {
+                            throttle.acquire(data.len()).await;
+                        }
+                        return Ok(data);
+                    }
+                    Some(Frame::Close) | None => {
+                        self.connected = false;
+                        return Err(TransportError::Closed("peer disconnected".to_string()));
+                    }
+                },
+                changed = self.up.changed() => {
+                    if changed.is_err() || !*self.up.borrow_and_update() {
+                        self.connected = false;
+                        return Err(TransportError::Closed("loopback link was cut".to_string()));
+                    }
+                }
+            }
+        }
+    }
+
+    async fn disconnect(&mut self) -> Result<(), TransportError> {
+        if self.connected && *self.up.borrow() {
+            self.transmit(Frame::Close).await;
+        }
+        self.connected = false;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::retry::RetryPolicy;
+
+    fn config(retry: RetryPolicy) -> TransportConfig {
+        TransportConfig {
+            host: "loopback".to_string(),
+            port: 0,
+            timeout_ms: 1000,
+            enable_0rtt: false,
+            keep_alive_ms: None,
+            tls: Default::default(),
+            retry,
+            max_bytes_per_sec: None,
+            burst_bytes: None,
+        }
+    }
+
+    async fn connected_pair(conditions: LinkConditions) -> (LoopbackTransport, LoopbackTransport) {
+        let (mut a, mut b) = LoopbackTransport::pair(conditions);
+        a.connect(&config(RetryPolicy::never())).await.unwrap();
+        b.connect(&config(RetryPolicy::never())).await.unwrap();
+        (a, b)
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_latency_and_bandwidth() {
+        let (mut a, mut b) = connected_pair(LinkConditions {
+            latency_ms: 50,
+            bandwidth_bytes_per_sec: Some(1000),
+            ..Default::default()
+        })
+        .await;
+
+        let start = Instant::now();
+        let receiver = tokio::spawn(async move {
+            let mut arrivals = Vec::new();
+            for _ in 0..2 {
+                let data = b.receive().await.unwrap();
+                arrivals.push((data[0], start.elapsed()));
+            }
+            arrivals
+        });
+
+        a.send(&[0; 500]).await.unwrap();
+        a.send(&[1; 500]).await.unwrap();
+        // The second message waited for the first to leave the wire
+        assert_eq!(start.elapsed(), Duration::from_secs(1));
+
+        assert_eq!(
+            receiver.await.unwrap(),
+            [(0, Duration::from_millis(550)), (1, Duration::from_millis(1050))]
+        );
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_throttled_by_config() {
+        let (mut a, mut b) = LoopbackTransport::pair(LinkConditions::default());
+        let throttled = TransportConfig {
+            max_bytes_per_sec: Some(1000),
+            ..config(RetryPolicy::never())
+        };
+        a.connect(&throttled).await.unwrap();
+        b.connect(&config(RetryPolicy::never())).await.unwrap();
+
+        let start = Instant::now();
+        for _ in 0..3 {
+            a.send(&[0; 1000]).await.unwrap();
+        }
+        // The burst and the frame overdrawing it pass at once
+        assert_eq!(start.elapsed(), Duration::from_secs(1));
+
+        a.throttle().unwrap().set_limit(None);
+        a.send(&[0; 1000]).await.unwrap();
+        assert_eq!(start.elapsed(), Duration::from_secs(1));
+        for _ in 0..4 {
+            b.receive().await.unwrap();
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_loss_is_seeded() {
+        let conditions = LinkConditions {
+            loss: 0.3,
+            seed: 42,
+            ..Default::default()
+        };
+
+        let mut delivered = Vec::new();
+        for _ in 0..2 {
+            let (mut a, mut b) = connected_pair(conditions.clone()).await;
+            for i in 0..100u8 {
+                a.send(&[i]).await.unwrap();
+            }
+            a.disconnect().await.unwrap();
+
+            let mut received = Vec::new();
+            while let Ok(data) = b.receive().await {
+                received.push(data[0]);
+            }
+            let (stats, _) = a.link().stats();
+            assert_eq!(stats.sent, 100);
+            assert_eq!(received.len() as u64, 100 - stats.lost);
+            assert!((15..=45).contains(&stats.lost), "lost {}", stats.lost);
+            delivered.push(received);
+        }
+        assert_eq!(delivered[0], delivered[1]);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_cut_and_reconnect() {
+        let (mut a, mut b) = LoopbackTransport::pair(LinkConditions {
+            latency_ms: 100,
+            connect_failures: 2,
+            ..Default::default()
+        });
+        let retry = RetryPolicy {
+            max_attempts: 3,
+            jitter: 0.0,
+            ..Default::default()
+        };
+        a.connect(&config(retry.clone())).await.unwrap();
+        b.connect(&config(RetryPolicy::never())).await.unwrap();
+
+        // In flight when the link goes down
+        a.send(b"lost").await.unwrap();
+        let link = a.link();
+        link.cut();
+        assert!(matches!(b.receive().await, Err(TransportError::Closed(_))));
+        assert!(matches!(a.send(b"more").await, Err(TransportError::Closed(_))));
+        assert!(a.connect(&config(RetryPolicy::never())).await.is_err());
+
+        link.restore();
+        a.connect(&config(retry)).await.unwrap();
+        b.connect(&config(RetryPolicy::never())).await.unwrap();
+        a.send(b"resumed").await.unwrap();
+        assert_eq!(b.receive().await.unwrap(), b"resumed");
+    }
+}
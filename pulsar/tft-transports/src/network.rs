@@ -0,0 +1,222 @@
+//! Network change detection
+//!
+//! A laptop moving from Wi-Fi to Ethernet, or a VPN coming up, changes the
+//! local address traffic leaves from. Connections bound to the old address
+//! are dead, but TCP only notices once a keepalive or write times out, many
+//! seconds later. A [`NetworkMonitor`] polls the source address of the
+//! default IPv4 and IPv6 routes and announces each change as it happens, so
+//! transports can migrate or reconnect straight away and UIs can say why a
+//! session paused.
+//!
+//! The route lookup connects an unbound UDP socket, which asks the OS for a
+//! route without sending anything; no interface enumeration or platform API
+//! is needed.
+
+use serde::{Deserialize, Serialize};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, UdpSocket};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::broadcast;
+use tracing::info;
+
+/// Stand-ins for "anywhere on the internet" (RFC 5737 and RFC 3849
+/// documentation addresses); never contacted
+const PROBE_V4: Ipv4Addr = Ipv4Addr::new(192, 0, 2, 1);
+const PROBE_V6: Ipv6Addr = Ipv6Addr::new(0x2001, 0xdb8, 0, 0, 0, 0, 0, 1);
+
+/// Source addresses of the default routes
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct NetworkState {
+    pub ipv4: Option<IpAddr>,
+    pub ipv6: Option<IpAddr>,
+}
+
+impl NetworkState {
+    /// Look the default routes up now
+    pub fn current() -> Self {
+        Self {
+            ipv4: route_source(IpAddr::V4(PROBE_V4)),
+            ipv6: route_source(IpAddr::V6(PROBE_V6)),
+        }
+    }
+
+    pub fn is_online(&self) -> bool {
+        self.ipv4.is_some() || self.ipv6.is_some()
+    }
+}
+
+/// What a network change amounts to
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ChangeKind {
+    /// No route anywhere any more
+    Lost,
+    /// Back online after being without a route
+    Restored,
+    /// Traffic now leaves from another address: another network, or a VPN
+    /// coming up or going down
+    Switched,
+}
+
+/// The default routes changed
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct NetworkChange {
+    pub kind: ChangeKind,
+    pub previous: NetworkState,
+    pub current: NetworkState,
+}
+
+impl NetworkChange {
+    /// None when nothing changed
+    pub fn between(previous: NetworkState, current: NetworkState) -> Option<Self> {
+        if previous == current {
+            return None;
+        }
+        let kind = match (previous.is_online(), current.is_online()) {
+            (_, false) => ChangeKind::Lost,
+            (false, true) => ChangeKind::Restored,
+            (true, true) => ChangeKind::Switched,
+        };
+        Some(Self {
+            kind,
+            previous,
+            current,
+        })
+    }
+
+    /// One line for a status bar or notification
+    pub fn describe(&self) -> String {
+        match self.kind {
+            ChangeKind::Lost => "Network connection lost".to_string(),
+            ChangeKind::Restored => "Network connection restored, reconnecting".to_string(),
+            ChangeKind::Switched => "Network changed, reconnecting".to_string(),
+        }
+    }
+}
+
+/// The local address the OS would send from to reach `peer` right now;
+/// None when there is no route
+pub fn route_source(peer: IpAddr) -> Option<IpAddr> {
+    let bind: SocketAddr = match peer {
+        IpAddr::V4(_) => (Ipv4Addr::UNSPECIFIED, 0).into(),
+        IpAddr::V6(_) => (Ipv6Addr::UNSPECIFIED, 0).into(),
+    };
+    let socket = UdpSocket::bind(bind).ok()?;
+    socket.connect((peer, 9)).ok()?;
+    let local = socket.local_addr().ok()?.ip();
+    (!local.is_unspecified()).then_some(local)
+}
+
+/// Whether a connection from `local` to `peer` has lost its path: traffic
+/// to `peer` would now leave from another address, or not at all
+pub fn path_moved(local: SocketAddr, peer: SocketAddr) -> bool {
+    route_source(peer.ip()) != Some(local.ip())
+}
+
+/// Watches the default routes and announces changes
+pub struct NetworkMonitor {
+    state: Mutex<NetworkState>,
+    changes: broadcast::Sender<NetworkChange>,
+}
+
+impl NetworkMonitor {
+    pub fn new() -> Self {
+        let (changes, _) = broadcast::channel(16);
+        Self {
+            state: Mutex::new(NetworkState::current()),
+            changes,
+        }
+    }
+
+    /// The routes as of the last check
+    pub fn state(&self) -> NetworkState {
+        *self.state.lock().unwrap()
+    }
+
+    /// Receive every change from now on
+    pub fn subscribe(&self) -> broadcast::Receiver<NetworkChange> {
+        self.changes.subscribe()
+    }
+
+    /// Look the routes up now, announcing a change if there was one
+    pub fn check(&self) -> Option<NetworkChange> {
+        self.update(NetworkState::current())
+    }
+
+    fn update(&self, current: NetworkState) -> Option<NetworkChange> {
+        let previous = std::mem::replace(&mut *self.state.lock().unwrap(), current);
+        let change = NetworkChange::between(previous, current)?;
+        info!(
+            "Network change ({:?}): {:?} -> {:?}",
+            change.kind, change.previous, change.current
+        );
+        let _ = self.changes.send(change.clone());
+        Some(change)
+    }
+
+    /// Check every `interval`
+    pub async fn run(self: Arc<Self>, interval: Duration) {
+        let mut interval = tokio::time::interval(interval);
+        loop {
+            interval.tick().await;
+            let monitor = Arc::clone(&self);
+            // Route lookups are syscalls, kept off the runtime's threads
+            let _ = tokio::task::spawn_blocking(move || monitor.check()).await;
+        }
+    }
+}
+
+impl Default for NetworkMonitor {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn state(ipv4: Option<&str>, ipv6: Option<&str>) -> NetworkState {
+        NetworkState {
+            ipv4: ipv4.map(|ip| ip.parse().unwrap()),
+            ipv6: ipv6.map(|ip| ip.parse().unwrap()),
+        }
+    }
+
+    #[test]
+    fn test_change_kinds() {
+        let wifi = state(Some("192.168.1.20"), Some("fe80::1"));
+        let vpn = state(Some("10.8.0.2"), Some("fe80::1"));
+        let offline = state(None, None);
+
+        assert!(NetworkChange::between(wifi, wifi).is_none());
+        assert_eq!(NetworkChange::between(wifi, vpn).unwrap().kind, ChangeKind::Switched);
+        assert_eq!(NetworkChange::between(wifi, offline).unwrap().kind, ChangeKind::Lost);
+        assert_eq!(NetworkChange::between(offline, vpn).unwrap().kind, ChangeKind::Restored);
+        // Losing only IPv6 still leaves a route
+        let v4_only = state(Some("192.168.1.20"), None);
+        assert_eq!(NetworkChange::between(wifi, v4_only).unwrap().kind, ChangeKind::Switched);
+    }
+
+    #[test]
+    fn test_monitor_announces_changes() {
+        let monitor = NetworkMonitor::new();
+        let mut changes = monitor.subscribe();
+        monitor.update(state(Some("192.168.1.20"), None));
+        let _ = changes.try_recv();
+
+        assert!(monitor.update(state(Some("192.168.1.20"), None)).is_none());
+        let change = monitor.update(state(Some("10.8.0.2"), None)).unwrap();
+        assert_eq!(changes.try_recv().unwrap(), change);
+        assert_eq!(monitor.state(), change.current);
+    }
+
+    #[test]
+    fn test_loopback_path() {
+        let local: SocketAddr = "127.0.0.1:50000".parse().unwrap();
+        let peer: SocketAddr = "127.0.0.1:22".parse().unwrap();
+        assert!(!path_moved(local, peer));
+        let stale: SocketAddr = "192.0.2.77:50000".parse().unwrap();
+        assert!(path_moved(stale, peer));
+    }
+}
@@ -1,37 +1,504 @@
 //! QUIC/HTTP/3 transport implementation
+//!
+//! Reconnects reuse the same endpoint and TLS client config so session
+//! tickets from the previous connection allow 0-RTT resumption. Data sent
+//! before the handshake is confirmed can be replayed by an attacker, so
+//! only frames the caller marks as replay-safe go out early; everything
+//! else waits for the handshake. Network changes are handled by rebinding
+//! the local socket, which migrates the live connection instead of
+//! dropping it. Connects are retried as the config's retry policy says.
 
-use crate::transport::{Transport, TransportConfig, TransportError};
+use crate::retry::RetryObserver;
+use crate::throttle::Throttle;
+use crate::tls::{self, FailureSlot};
+use crate::transport::{TlsOptions, Transport, TransportConfig, TransportError};
 use async_trait::async_trait;
+use quinn::{ClientConfig, Connection, Endpoint, RecvStream, SendStream, ZeroRttAccepted};
+use std::net::{Ipv4Addr, Ipv6Addr, SocketAddr, UdpSocket};
+use std::sync::Arc;
+use std::time::Duration;
+use tft_core::codec::MAX_BINARY_FRAME_SIZE;
+use tft_core::Message;
+use tracing::{debug, info, warn};
+
+/// ALPN spoken by the Pulsar daemon's QUIC endpoint
+const ALPN: &[u8] = b"h3";
+
+/// The settings a client config is built from. It is reused, keeping its
+/// session tickets for 0-RTT, only while these stay the same.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct ClientConfigKey {
+    tls: TlsOptions,
+    enable_0rtt: bool,
+    timeout_ms: u64,
+    keep_alive_ms: Option<u64>,
+}
+
+impl ClientConfigKey {
+    fn new(config: &TransportConfig) -> Self {
+        Self {
+            tls: config.tls.clone(),
+            enable_0rtt: config.enable_0rtt,
+            timeout_ms: config.timeout_ms,
+            keep_alive_ms: config.keep_alive_ms,
+        }
+    }
+}
 
 pub struct QuicTransport {
-    // TODO: Quinn connection
+    endpoint: Option<Endpoint>,
+    client_config: Option<(ClientConfigKey, ClientConfig)>,
+    connection: Option<Connection>,
+    stream: Option<(SendStream, RecvStream)>,
+    /// Pending 0-RTT outcome; `None` once the handshake is confirmed
+    zero_rtt: Option<ZeroRttAccepted>,
+    /// Frames sent as 0-RTT, kept for replay if the server rejects them
+    early_frames: Vec<Vec<u8>>,
+    config: Option<TransportConfig>,
+    /// Last certificate verification failure, for precise connect errors
+    tls_failure: FailureSlot,
+    retry_observer: Option<RetryObserver>,
+    throttle: Option<Throttle>,
 }
 
 impl QuicTransport {
     pub fn new() -> Self {
-        Self {}
+        Self {
+            endpoint: None,
+            client_config: None,
+            connection: None,
+            stream: None,
+            zero_rtt: None,
+            early_frames: Vec::new(),
+            config: None,
+            tls_failure: FailureSlot::default(),
+            retry_observer: None,
+            throttle: None,
+        }
+    }
+
+    /// Report each connect attempt to `observer`
+    pub fn set_retry_observer(&mut self, observer: RetryObserver) {
+        self.retry_observer = Some(observer);
+    }
+
+    /// Limit sends and receives with `throttle`, e.g. one shared with
+    /// other transports; otherwise connecting sets up one from the config
+    pub fn set_throttle(&mut self, throttle: Throttle) {
+        self.throttle = Some(throttle);
+    }
+
+    /// The throttle in use, once set or connected
+    pub fn throttle(&self) -> Option<&Throttle> {
+        self.throttle.as_ref()
+    }
+
+    /// Whether the current connection is still waiting on 0-RTT confirmation
+    pub fn is_early(&self) -> bool {
+        self.zero_rtt.is_some()
+    }
+
+    /// Send a frame that is safe to replay
+    ///
+    /// While a 0-RTT handshake is in flight the frame is sent immediately;
+    /// use [`is_replay_safe`] to decide whether a protocol message qualifies.
+    pub async fn send_replay_safe(&mut self, data: &[u8]) -> Result<(), TransportError> {
+        if self.zero_rtt.is_some() {
+            self.early_frames.push(data.to_vec());
+        }
+        self.write_frame(data).await
+    }
+
+    /// Drop the current connection and reconnect with the last config
+    ///
+    /// Uses 0-RTT when the previous connection left a session ticket.
+    pub async fn reconnect(&mut self) -> Result<(), TransportError> {
+        let config = self
+            .config
+            .clone()
+            .ok_or_else(|| TransportError::ConnectionFailed("not connected".to_string()))?;
+
+        if let Some(connection) = self.connection.take() {
+            connection.close(0u32.into(), b"reconnect");
+        }
+        self.stream = None;
+        self.zero_rtt = None;
+        self.early_frames.clear();
+
+        self.connect(&config).await
+    }
+
+    /// Move the connection onto a fresh local socket
+    ///
+    /// Call after a network change (e.g. Wi-Fi to Ethernet); the peer sees
+    /// a path migration rather than a new connection.
+    pub fn rebind(&self) -> Result<(), TransportError> {
+        let endpoint = self
+            .endpoint
+            .as_ref()
+            .ok_or_else(|| TransportError::ConnectionFailed("not connected".to_string()))?;
+
+        let local = endpoint.local_addr()?;
+        let socket = UdpSocket::bind(unspecified_addr(&local))?;
+        endpoint.rebind(socket)?;
+
+        info!("QUIC endpoint rebound to {}", endpoint.local_addr()?);
+        Ok(())
+    }
+
+    /// Wait for the handshake and replay early frames if 0-RTT was rejected
+    async fn confirm_handshake(&mut self) -> Result<(), TransportError> {
+        let Some(accepted) = self.zero_rtt.take() else {
+            return Ok(());
+        };
+
+        let frames = std::mem::take(&mut self.early_frames);
+        if accepted.await {
+            debug!("0-RTT accepted ({} early frames)", frames.len());
+            return Ok(());
+        }
+
+        warn!("0-RTT rejected by server, replaying {} frames", frames.len());
+        self.stream = None;
+        for frame in frames {
+            self.write_frame(&frame).await?;
+        }
+        Ok(())
+    }
+
+    async fn stream(&mut self) -> Result<&mut (SendStream, RecvStream), TransportError> {
+        if self.stream.is_none() {
+            let connection = self
+                .connection
+                .as_ref()
+                .ok_or_else(|| TransportError::ConnectionFailed("not connected".to_string()))?;
+            let pair = connection.open_bi().await.map_err(connection_error)?;
+            self.stream = Some(pair);
+        }
+        Ok(self.stream.as_mut().expect("stream opened above"))
+    }
+
+    async fn write_frame(&mut self, data: &[u8]) -> Result<(), TransportError> {
+        if data.len() > MAX_BINARY_FRAME_SIZE {
+            return Err(TransportError::Protocol(format!(
+                "frame of {} bytes exceeds limit",
+                data.len()
+            )));
+        }
+
+        if let Some(throttle) = &self.throttle {
+            throttle.acquire(data.len() + 4).await;
+        }
+        let (send, _) = self.stream().await?;
+        send.write_all(&(data.len() as u32).to_be_bytes())
+            .await
+            .map_err(write_error)?;
+        send.write_all(data).await.map_err(write_error)?;
+        Ok(())
+    }
+
+    /// Prefer the recorded certificate failure over quinn's generic error
+    fn connect_error(&self, host: &str, error: quinn::ConnectionError) -> TransportError {
+        match self.tls_failure.lock().unwrap().take() {
+            Some(failure) => failure.into_error(host),
+            None => match error {
+                quinn::ConnectionError::TimedOut => {
+                    TransportError::Timeout(format!("connecting to {}", host))
+                }
+                quinn::ConnectionError::ConnectionClosed(close)
+                    if close.error_code == quinn::TransportErrorCode::CONNECTION_REFUSED =>
+                {
+                    TransportError::Refused(host.to_string())
+                }
+                error => connection_error(error),
+            },
+        }
+    }
+
+    fn client_config(&mut self, config: &TransportConfig) -> Result<ClientConfig, TransportError> {
+        let key = ClientConfigKey::new(config);
+        if let Some((cached, client_config)) = &self.client_config {
+            if *cached == key {
+                return Ok(client_config.clone());
+            }
+        }
+
+        let provider = Arc::new(rustls::crypto::ring::default_provider());
+        let mut crypto = tls::client_crypto(&config.tls, provider, Arc::clone(&self.tls_failure))?;
+        crypto.alpn_protocols = vec![ALPN.to_vec()];
+        crypto.enable_early_data = config.enable_0rtt;
+
+        let quic_crypto = quinn::crypto::rustls::QuicClientConfig::try_from(crypto)
+            .map_err(|e| TransportError::ConnectionFailed(e.to_string()))?;
+        let mut client_config = ClientConfig::new(Arc::new(quic_crypto));
+
+        let mut transport = quinn::TransportConfig::default();
+        transport.max_idle_timeout(Some(
+            Duration::from_millis(config.timeout_ms)
+                .try_into()
+                .map_err(|_| TransportError::ConnectionFailed("timeout too large".to_string()))?,
+        ));
+        transport.keep_alive_interval(config.keep_alive_ms.map(Duration::from_millis));
+        client_config.transport_config(Arc::new(transport));
+
+        self.client_config = Some((key, client_config.clone()));
+        Ok(client_config)
+    }
+
+    async fn connect_once(&mut self, config: &TransportConfig) -> Result<(), TransportError> {
+        let addr = tokio::net::lookup_host((config.host.as_str(), config.port))
+            .await?
+            .next()
+            .ok_or_else(|| {
+                TransportError::ConnectionFailed(format!("could not resolve {}", config.host))
+            })?;
+
+        let client_config = self.client_config(config)?;
+        let endpoint = match &self.endpoint {
+            Some(endpoint) => endpoint.clone(),
+            None => {
+                let endpoint = Endpoint::client(unspecified_addr(&addr))?;
+                self.endpoint = Some(endpoint.clone());
+                endpoint
+            }
+        };
+
+        let connecting = endpoint
+            .connect_with(client_config, addr, &config.host)
+            .map_err(|e| TransportError::ConnectionFailed(e.to_string()))?;
+
+        let connection = if config.enable_0rtt {
+            match connecting.into_0rtt() {
+                Ok((connection, accepted)) => {
+                    debug!("Resuming QUIC session to {} with 0-RTT", addr);
+                    self.zero_rtt = Some(accepted);
+                    connection
+                }
+                Err(connecting) => connecting
+                    .await
+                    .map_err(|e| self.connect_error(&config.host, e))?,
+            }
+        } else {
+            connecting
+                .await
+                .map_err(|e| self.connect_error(&config.host, e))?
+        };
+
+        info!("QUIC connection to {} established", addr);
+        self.connection = Some(connection);
+        self.stream = None;
+        self.config = Some(config.clone());
+        Ok(())
+    }
+}
+
+impl Default for QuicTransport {
+    fn default() -> Self {
+        Self::new()
     }
 }
 
 #[async_trait]
 impl Transport for QuicTransport {
-    async fn connect(&mut self, _config: &TransportConfig) -> Result<(), TransportError> {
-        // TODO: Implement QUIC connection
-        Ok(())
+    async fn connect(&mut self, config: &TransportConfig) -> Result<(), TransportError> {
+        self.throttle
+            .get_or_insert_with(|| Throttle::new(config.bandwidth_limit()));
+        let observer = self.retry_observer.clone();
+        let mut attempts = config.retry.attempts(observer.as_ref());
+        loop {
+            attempts.begin();
+            match self.connect_once(config).await {
+                Ok(()) => {
+                    attempts.succeeded();
+                    return Ok(());
+                }
+                Err(error) => attempts.failed(error).await?,
+            }
+        }
     }
 
-    async fn send(&mut self, _data: &[u8]) -> Result<(), TransportError> {
-        // TODO: Send over QUIC
-        Ok(())
+    async fn send(&mut self, data: &[u8]) -> Result<(), TransportError> {
+        self.confirm_handshake().await?;
+        self.write_frame(data).await
     }
 
     async fn receive(&mut self) -> Result<Vec<u8>, TransportError> {
-        // TODO: Receive from QUIC
-        Ok(vec![])
+        let (_, recv) = self.stream().await?;
+
+        let mut prefix = [0u8; 4];
+        recv.read_exact(&mut prefix).await.map_err(read_error)?;
+
+        let len = u32::from_be_bytes(prefix) as usize;
+        if len > MAX_BINARY_FRAME_SIZE {
+            return Err(TransportError::Protocol(format!(
+                "frame of {} bytes exceeds limit",
+                len
+            )));
+        }
+
+        let mut data = vec![0u8; len];
+        recv.read_exact(&mut data).await.map_err(read_error)?;
+        if let Some(throttle) = &self.throttle {
+            throttle.acquire(len + 4).await;
+        }
+        Ok(data)
     }
 
     async fn disconnect(&mut self) -> Result<(), TransportError> {
-        // TODO: Close QUIC connection
+        if let Some((mut send, _)) = self.stream.take() {
+            let _ = send.finish();
+        }
+        if let Some(connection) = self.connection.take() {
+            connection.close(0u32.into(), b"disconnect");
+        }
+        if let Some(endpoint) = &self.endpoint {
+            endpoint.wait_idle().await;
+        }
+        self.zero_rtt = None;
+        self.early_frames.clear();
         Ok(())
     }
+
+    /// Migrate a live connection to the new path; reconnect one the change
+    /// has already killed
+    async fn network_changed(&mut self) -> Result<(), TransportError> {
+        let alive = self
+            .connection
+            .as_ref()
+            .is_some_and(|connection| connection.close_reason().is_none());
+        if alive {
+            match self.rebind() {
+                Ok(()) => return Ok(()),
+                Err(e) => warn!("QUIC rebind failed ({}), reconnecting", e),
+            }
+        }
+        if self.config.is_none() {
+            return Ok(());
+        }
+        self.reconnect().await
+    }
+}
+
+/// Whether a protocol message may be sent as replayable 0-RTT data
+///
+/// Chunks and acks are keyed by transfer and index, so a replay writes the
+/// same bytes or repeats the same ack. Anything that creates, completes or
+/// aborts a transfer must wait for the handshake.
+pub fn is_replay_safe(message: &Message) -> bool {
+    matches!(
+        message,
+        Message::Chunk(_) | Message::CompressedChunk(_) | Message::ChunkAck(_)
+    )
+}
+
+/// Losing the connection is retryable; a version mismatch is not
+fn connection_error(error: quinn::ConnectionError) -> TransportError {
+    match error {
+        quinn::ConnectionError::VersionMismatch => {
+            TransportError::ProtocolVersion(error.to_string())
+        }
+        quinn::ConnectionError::TimedOut => TransportError::Timeout(error.to_string()),
+        quinn::ConnectionError::Reset
+        | quinn::ConnectionError::ConnectionClosed(_)
+        | quinn::ConnectionError::ApplicationClosed(_)
+        | quinn::ConnectionError::LocallyClosed => TransportError::Closed(error.to_string()),
+        error => TransportError::ConnectionFailed(error.to_string()),
+    }
+}
+
+fn write_error(error: quinn::WriteError) -> TransportError {
+    match error {
+        quinn::WriteError::ConnectionLost(e) => connection_error(e),
+        error => TransportError::Protocol(error.to_string()),
+    }
+}
+
+fn read_error(error: quinn::ReadExactError) -> TransportError {
+    match error {
+        quinn::ReadExactError::ReadError(quinn::ReadError::ConnectionLost(e)) => {
+            connection_error(e)
+        }
+        quinn::ReadExactError::FinishedEarly(_) => {
+            TransportError::Closed("stream ended mid-frame".to_string())
+        }
+        error => TransportError::Protocol(error.to_string()),
+    }
+}
+
+fn unspecified_addr(peer: &SocketAddr) -> SocketAddr {
+    match peer {
+        SocketAddr::V4(_) => (Ipv4Addr::UNSPECIFIED, 0).into(),
+        SocketAddr::V6(_) => (Ipv6Addr::UNSPECIFIED, 0).into(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tft_core::protocol::{ChunkAck, ErrorMessage};
+    use uuid::Uuid;
+
+    #[test]
+    fn test_replay_safety_classification() {
+        let ack = Message::ChunkAck(ChunkAck {
+            transfer_id: Uuid::new_v4(),
+            chunk_index: 3,
+            success: true,
+        });
+        let error = Message::Error(ErrorMessage {
+            transfer_id: None,
+            code: "cancelled".to_string(),
+            message: "user cancelled".to_string(),
+        });
+
+        assert!(is_replay_safe(&ack));
+        assert!(!is_replay_safe(&error));
+    }
+
+    #[test]
+    fn test_client_config_follows_tls_options() {
+        let mut config = TransportConfig {
+            host: "localhost".to_string(),
+            port: 4433,
+            timeout_ms: 1000,
+            enable_0rtt: true,
+            keep_alive_ms: None,
+            tls: TlsOptions {
+                pinned_spki: vec![format!("sha256/{}", "A".repeat(43) + "=")],
+                ca_bundle: None,
+                use_system_roots: false,
+            },
+            retry: Default::default(),
+            max_bytes_per_sec: None,
+            burst_bytes: None,
+        };
+        let mut transport = QuicTransport::new();
+        transport.client_config(&config).unwrap();
+        let cached =
+            |transport: &QuicTransport| transport.client_config.as_ref().unwrap().0.clone();
+        assert_eq!(cached(&transport), ClientConfigKey::new(&config));
+
+        // Another pin must not reuse the old verifier
+        config.tls.pinned_spki = vec![format!("sha256/{}", "B".repeat(43) + "=")];
+        transport.client_config(&config).unwrap();
+        assert_eq!(cached(&transport).tls, config.tls);
+    }
+
+    #[test]
+    fn test_connection_errors_are_classified() {
+        let timeout = connection_error(quinn::ConnectionError::TimedOut);
+        assert!(matches!(timeout, TransportError::Timeout(_)));
+        assert!(timeout.is_retryable());
+
+        let version = connection_error(quinn::ConnectionError::VersionMismatch);
+        assert!(matches!(version, TransportError::ProtocolVersion(_)));
+        assert!(!version.is_retryable());
+
+        assert!(connection_error(quinn::ConnectionError::Reset).is_retryable());
+    }
+
+    #[test]
+    fn test_rebind_requires_endpoint() {
+        assert!(QuicTransport::new().rebind().is_err());
+    }
 }
@@ -0,0 +1,342 @@
+//! Connection retry policy shared by the transports
+//!
+//! A [`RetryPolicy`] says how many times to try connecting, how long to
+//! wait between attempts and which failures are worth another attempt.
+//! Each attempt is reported as [`RetryEvent`]s to an optional observer, so
+//! the daemon and UI can show "retrying in 2s" or record connect latency.
+
+use crate::transport::TransportError;
+use ring::rand::{SecureRandom, SystemRandom};
+use serde::{Deserialize, Serialize};
+use std::future::Future;
+use std::sync::Arc;
+use std::time::Duration;
+use tracing::{debug, warn};
+
+/// Receives retry events; called inline, so it should not block
+pub type RetryObserver = Arc<dyn Fn(&RetryEvent) + Send + Sync>;
+
+/// How delays grow between attempts
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case", tag = "kind")]
+pub enum Backoff {
+    /// The initial delay every time
+    Constant,
+    /// The initial delay times the attempt number
+    Linear,
+    /// The initial delay times `factor` for each attempt after the first
+    Exponential { factor: f64 },
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RetryPolicy {
+    /// Attempts in total, the first included; 1 disables retrying
+    #[serde(default = "default_max_attempts")]
+    pub max_attempts: u32,
+    #[serde(default = "default_initial_delay_ms")]
+    pub initial_delay_ms: u64,
+    /// Cap on any single delay
+    #[serde(default = "default_max_delay_ms")]
+    pub max_delay_ms: u64,
+    #[serde(default = "default_backoff")]
+    pub backoff: Backoff,
+    /// Fraction of each delay randomized away (0.0-1.0), so clients that
+    /// lost the same server do not reconnect in lockstep
+    #[serde(default = "default_jitter")]
+    pub jitter: f64,
+    /// Error codes (see [`TransportError::code`]) to retry; `None` retries
+    /// whatever [`TransportError::is_retryable`] allows
+    #[serde(default)]
+    pub retry_on: Option<Vec<String>>,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: default_max_attempts(),
+            initial_delay_ms: default_initial_delay_ms(),
+            max_delay_ms: default_max_delay_ms(),
+            backoff: default_backoff(),
+            jitter: default_jitter(),
+            retry_on: None,
+        }
+    }
+}
+
+fn default_max_attempts() -> u32 {
+    3
+}
+
+fn default_initial_delay_ms() -> u64 {
+    500
+}
+
+fn default_max_delay_ms() -> u64 {
+    10_000
+}
+
+fn default_backoff() -> Backoff {
+    Backoff::Exponential { factor: 2.0 }
+}
+
+fn default_jitter() -> f64 {
+    0.2
+}
+
+/// What happened on one connect attempt
+#[derive(Debug, Clone, PartialEq)]
+pub enum RetryEvent {
+    Attempt { attempt: u32, max_attempts: u32 },
+    Connected { attempt: u32 },
+    /// `retry_in` is `None` when this was the last attempt
+    Failed {
+        attempt: u32,
+        code: &'static str,
+        message: String,
+        retry_in: Option<Duration>,
+    },
+}
+
+impl RetryPolicy {
+    /// A policy that tries once
+    pub fn never() -> Self {
+        Self {
+            max_attempts: 1,
+            ..Self::default()
+        }
+    }
+
+    /// Whether `error` qualifies for another attempt
+    pub fn should_retry(&self, error: &TransportError) -> bool {
+        match &self.retry_on {
+            Some(codes) => codes.iter().any(|code| code == error.code()),
+            None => error.is_retryable(),
+        }
+    }
+
+    /// Delay before attempt `attempt + 1`, before jitter
+    pub fn delay(&self, attempt: u32) -> Duration {
+        let initial = self.initial_delay_ms as f64;
+        let ms = match self.backoff {
+            Backoff::Constant => initial,
+            Backoff::Linear => initial * attempt as f64,
+            Backoff::Exponential { factor } => initial * factor.powi(attempt as i32 - 1),
+        };
+        Duration::from_millis(ms.min(self.max_delay_ms as f64) as u64)
+    }
+
+    /// Track attempts under this policy, for connect loops that cannot
+    /// be written as a closure for [`run`](Self::run)
+    pub fn attempts<'a>(&'a self, observer: Option<&'a RetryObserver>) -> Attempts<'a> {
+        Attempts {
+            policy: self,
+            observer,
+            attempt: 0,
+        }
+    }
+
+    /// Run `connect` until it succeeds, fails in a way not worth retrying,
+    /// or runs out of attempts; the last error is returned
+    pub async fn run<T, F, Fut>(
+        &self,
+        observer: Option<&RetryObserver>,
+        mut connect: F,
+    ) -> Result<T, TransportError>
+    where
+        F: FnMut(u32) -> Fut,
+        Fut: Future<Output = Result<T, TransportError>>,
+    {
+        let mut attempts = self.attempts(observer);
+        loop {
+            let attempt = attempts.begin();
+            match connect(attempt).await {
+                Ok(value) => {
+                    attempts.succeeded();
+                    return Ok(value);
+                }
+                Err(error) => attempts.failed(error).await?,
+            }
+        }
+    }
+}
+
+/// Attempts made under a [`RetryPolicy`]
+///
+/// Call [`begin`](Self::begin) before each attempt, then
+/// [`succeeded`](Self::succeeded) or [`failed`](Self::failed).
+pub struct Attempts<'a> {
+    policy: &'a RetryPolicy,
+    observer: Option<&'a RetryObserver>,
+    attempt: u32,
+}
+
+impl Attempts<'_> {
+    fn emit(&self, event: RetryEvent) {
+        if let Some(observer) = self.observer {
+            observer(&event);
+        }
+    }
+
+    fn max_attempts(&self) -> u32 {
+        self.policy.max_attempts.max(1)
+    }
+
+    /// Start the next attempt, returning its number (from 1)
+    pub fn begin(&mut self) -> u32 {
+        self.attempt += 1;
+        self.emit(RetryEvent::Attempt {
+            attempt: self.attempt,
+            max_attempts: self.max_attempts(),
+        });
+        self.attempt
+    }
+
+    pub fn succeeded(&self) {
+        self.emit(RetryEvent::Connected {
+            attempt: self.attempt,
+        });
+    }
+
+    /// Wait out the delay when the attempt is worth repeating, otherwise
+    /// hand `error` back
+    pub async fn failed(&mut self, error: TransportError) -> Result<(), TransportError> {
+        let (attempt, max_attempts) = (self.attempt, self.max_attempts());
+        let retry_in = (attempt < max_attempts && self.policy.should_retry(&error))
+            .then(|| jittered(self.policy.delay(attempt), self.policy.jitter));
+        self.emit(RetryEvent::Failed {
+            attempt,
+            code: error.code(),
+            message: error.to_string(),
+            retry_in,
+        });
+        let Some(delay) = retry_in else {
+            if attempt > 1 {
+                warn!("Giving up after {} attempts: {}", attempt, error);
+            }
+            return Err(error);
+        };
+
+        debug!(
+            "Connect attempt {}/{} failed ({}), retrying in {:?}",
+            attempt, max_attempts, error, delay
+        );
+        tokio::time::sleep(delay).await;
+        Ok(())
+    }
+}
+
+/// Shorten `delay` by a random part of `jitter` of it
+fn jittered(delay: Duration, jitter: f64) -> Duration {
+    let jitter = jitter.clamp(0.0, 1.0);
+    if jitter == 0.0 {
+        return delay;
+    }
+    let mut bytes = [0u8; 4];
+    if SystemRandom::new().fill(&mut bytes).is_err() {
+        return delay;
+    }
+    let random = u32::from_le_bytes(bytes) as f64 / u32::MAX as f64;
+    delay.mul_f64(1.0 - jitter * random)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+
+    fn policy() -> RetryPolicy {
+        RetryPolicy {
+            max_attempts: 4,
+            initial_delay_ms: 100,
+            max_delay_ms: 300,
+            backoff: Backoff::Exponential { factor: 2.0 },
+            jitter: 0.0,
+            retry_on: None,
+        }
+    }
+
+    #[test]
+    fn test_backoff_curves() {
+        let exponential = policy();
+        let delays: Vec<_> = (1..=4).map(|n| exponential.delay(n).as_millis()).collect();
+        assert_eq!(delays, [100, 200, 300, 300]);
+
+        let linear = RetryPolicy {
+            backoff: Backoff::Linear,
+            ..policy()
+        };
+        assert_eq!(linear.delay(2), Duration::from_millis(200));
+
+        let jittered = jittered(Duration::from_millis(1000), 0.5);
+        assert!(jittered >= Duration::from_millis(500) && jittered <= Duration::from_millis(1000));
+    }
+
+    #[test]
+    fn test_retry_on_overrides_classification() {
+        let refused = TransportError::Refused("example.com".to_string());
+        let auth = TransportError::Auth("denied".to_string());
+        assert!(policy().should_retry(&refused));
+        assert!(!policy().should_retry(&auth));
+
+        let only_auth = RetryPolicy {
+            retry_on: Some(vec!["auth".to_string()]),
+            ..policy()
+        };
+        assert!(!only_auth.should_retry(&refused));
+        assert!(only_auth.should_retry(&auth));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_retries_until_connected() {
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let recorded = Arc::clone(&events);
+        let observer: RetryObserver = Arc::new(move |event| recorded.lock().unwrap().push(event.clone()));
+
+        let result = policy()
+            .run(Some(&observer), |attempt| async move {
+                if attempt < 3 {
+                    Err(TransportError::Timeout("connecting".to_string()))
+                } else {
+                    Ok(attempt)
+                }
+            })
+            .await;
+
+        assert_eq!(result.unwrap(), 3);
+        let events = events.lock().unwrap();
+        assert_eq!(events.len(), 6);
+        assert!(matches!(
+            events[1],
+            RetryEvent::Failed {
+                attempt: 1,
+                code: "timeout",
+                retry_in: Some(_),
+                ..
+            }
+        ));
+        assert_eq!(events[5], RetryEvent::Connected { attempt: 3 });
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_stops_on_errors_not_worth_retrying() {
+        let mut attempts = 0;
+        let result: Result<(), _> = policy()
+            .run(None, |_| {
+                attempts += 1;
+                async { Err(TransportError::Auth("denied".to_string())) }
+            })
+            .await;
+        assert!(matches!(result, Err(TransportError::Auth(_))));
+        assert_eq!(attempts, 1);
+
+        let mut attempts = 0;
+        let result: Result<(), _> = policy()
+            .run(None, |_| {
+                attempts += 1;
+                async { Err(TransportError::Closed("reset".to_string())) }
+            })
+            .await;
+        assert!(result.is_err());
+        assert_eq!(attempts, 4);
+    }
+}
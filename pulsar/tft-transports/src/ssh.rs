@@ -1,7 +1,33 @@
-//! SSH/SFTP transport implementation
+//! SSH/SFTP transport implementation, and an SFTP server for clients
+//! without TFT
+//!
+//! [`SftpServer`] lets legacy clients push files into a directory the
+//! daemon manages: users log in through per-user hooks, each sees only
+//! their own subdirectory, and finished uploads arrive as [`SftpEvent`]s.
+//! Besides SFTP it accepts uploads from `scp` in its legacy protocol
+//! (`scp -O`, and `scp` before OpenSSH 9.0).
 
 use crate::transport::{Transport, TransportConfig, TransportError};
 use async_trait::async_trait;
+use russh::keys::ssh_key::rand_core::OsRng;
+use russh::keys::ssh_key::LineEnding;
+use russh::keys::{Algorithm, PrivateKey, PublicKey};
+use russh::server::{self, Auth, Msg, Session};
+use russh::{Channel, ChannelId};
+use russh_sftp::protocol::{
+    Attrs, Data, File, FileAttributes, Handle, Name, OpenFlags, Status, StatusCode, Version,
+};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::io::{self, SeekFrom};
+use std::net::SocketAddr;
+use std::path::{Component, Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::{
+    AsyncBufReadExt, AsyncRead, AsyncReadExt, AsyncSeekExt, AsyncWrite, AsyncWriteExt, BufReader,
+};
+use tokio::sync::mpsc;
 
 pub struct SshTransport {
     // TODO: russh session
@@ -35,3 +61,981 @@ impl Transport for SshTransport {
         Ok(())
     }
 }
+
+/// Largest read served per SFTP request
+const MAX_READ: u32 = 256 * 1024;
+
+/// What happened on an [`SftpServer`], for the daemon to act on
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum SftpEvent {
+    /// A user logged in
+    LoggedIn {
+        user: String,
+        peer: Option<SocketAddr>,
+    },
+    /// A login was refused, by the user's hook or because the user is unknown
+    LoginRejected {
+        user: String,
+        peer: Option<SocketAddr>,
+        method: String,
+    },
+    /// A user finished uploading a file, over SFTP or `scp`, or gave one a
+    /// new name
+    FileReceived {
+        user: String,
+        path: PathBuf,
+        size: u64,
+    },
+}
+
+/// What a client logs in with
+#[derive(Debug, Clone, Copy)]
+pub enum SftpCredential<'a> {
+    Password(&'a str),
+    /// A key the client has proven it holds
+    PublicKey(&'a PublicKey),
+}
+
+impl SftpCredential<'_> {
+    /// Whether this is a public key listed in `authorized_keys`, lines in
+    /// the format of OpenSSH's `authorized_keys` without options
+    pub fn is_authorized_key(&self, authorized_keys: &[String]) -> bool {
+        let Self::PublicKey(key) = self else {
+            return false;
+        };
+        authorized_keys
+            .iter()
+            .filter_map(|line| PublicKey::from_openssh(line.trim()).ok())
+            .any(|authorized| authorized.key_data() == key.key_data())
+    }
+}
+
+/// Decides whether a user may log in with a credential
+pub type SftpAuthHook = Arc<dyn Fn(SftpCredential<'_>) -> bool + Send + Sync>;
+
+/// SFTP server for clients that can't speak TFT, e.g. `sftp`, WinSCP and
+/// `scp` (both its SFTP mode and the legacy protocol)
+///
+/// Each user has a hook deciding who may log in as them, and sees only
+/// `root/<user>`: paths resolve inside it and symlinks out of it are
+/// refused. Uploads are reported as [`SftpEvent::FileReceived`] once the
+/// client closes the file, and again under their new name when renamed.
+/// Events go to the sender given with [`SftpServer::with_events`]; none
+/// are dropped, as the daemon checks every received file.
+#[derive(Clone)]
+pub struct SftpServer {
+    root: PathBuf,
+    users: Arc<HashMap<String, SftpAuthHook>>,
+    events: mpsc::UnboundedSender<SftpEvent>,
+}
+
+impl SftpServer {
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        // Until `with_events`, events go nowhere
+        let (events, _) = mpsc::unbounded_channel();
+        Self {
+            root: root.into(),
+            users: Arc::new(HashMap::new()),
+            events,
+        }
+    }
+
+    /// Let `user` log in when `hook` accepts their credential. Names that
+    /// aren't a single path component are never accepted.
+    pub fn with_user(mut self, user: impl Into<String>, hook: SftpAuthHook) -> Self {
+        Arc::make_mut(&mut self.users).insert(user.into(), hook);
+        self
+    }
+
+    /// Send what happens on the server to `events`
+    pub fn with_events(mut self, events: mpsc::UnboundedSender<SftpEvent>) -> Self {
+        self.events = events;
+        self
+    }
+
+    /// Serve on `addr` with `host_key` until the listener fails
+    pub async fn run(
+        mut self,
+        addr: SocketAddr,
+        host_key: PrivateKey,
+    ) -> Result<(), TransportError> {
+        let config = server::Config {
+            keys: vec![host_key],
+            auth_rejection_time: Duration::from_secs(3),
+            auth_rejection_time_initial: Some(Duration::ZERO),
+            inactivity_timeout: Some(Duration::from_secs(600)),
+            ..Default::default()
+        };
+        tracing::info!("SFTP server listening on {}", addr);
+        server::Server::run_on_address(&mut self, Arc::new(config), addr)
+            .await
+            .map_err(TransportError::Io)
+    }
+
+    fn emit(&self, event: SftpEvent) {
+        // Nobody listening is fine
+        let _ = self.events.send(event);
+    }
+}
+
+/// Load the server's host key from `path`, or generate an Ed25519 key and
+/// save it there
+pub fn load_or_create_host_key(path: &Path) -> Result<PrivateKey, TransportError> {
+    if path.exists() {
+        return russh::keys::load_secret_key(path, None).map_err(|e| {
+            TransportError::Config(format!("Failed to load host key {}: {}", path.display(), e))
+        });
+    }
+
+    let key = PrivateKey::random(&mut OsRng, Algorithm::Ed25519)
+        .map_err(|e| TransportError::Config(format!("Failed to generate host key: {}", e)))?;
+    let encoded = key
+        .to_openssh(LineEnding::LF)
+        .map_err(|e| TransportError::Config(format!("Failed to encode host key: {}", e)))?;
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent).map_err(TransportError::Io)?;
+    }
+    std::fs::write(path, encoded.as_bytes()).map_err(TransportError::Io)?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        std::fs::set_permissions(path, std::fs::Permissions::from_mode(0o600))
+            .map_err(TransportError::Io)?;
+    }
+    tracing::info!("Generated SFTP host key at {}", path.display());
+    Ok(key)
+}
+
+/// One client connection to an [`SftpServer`]
+pub struct SftpConnection {
+    server: SftpServer,
+    peer: Option<SocketAddr>,
+    user: Option<String>,
+    channels: HashMap<ChannelId, Channel<Msg>>,
+    /// Channels running SFTP, closed when the client sends EOF
+    sftp_channels: HashSet<ChannelId>,
+}
+
+impl SftpConnection {
+    fn authenticate(&mut self, user: &str, credential: SftpCredential<'_>) -> Auth {
+        let accepted = is_file_name(user)
+            && self
+                .server
+                .users
+                .get(user)
+                .is_some_and(|hook| hook(credential));
+        if accepted {
+            self.user = Some(user.to_string());
+            self.server.emit(SftpEvent::LoggedIn {
+                user: user.to_string(),
+                peer: self.peer,
+            });
+            Auth::Accept
+        } else {
+            let method = match credential {
+                SftpCredential::Password(_) => "password",
+                SftpCredential::PublicKey(_) => "publickey",
+            };
+            self.server.emit(SftpEvent::LoginRejected {
+                user: user.to_string(),
+                peer: self.peer,
+                method: method.to_string(),
+            });
+            Auth::Reject {
+                proceed_with_methods: None,
+                partial_success: false,
+            }
+        }
+    }
+
+    /// The logged-in user and their directory
+    fn jail(&self) -> Option<(String, Jail)> {
+        let user = self.user.clone()?;
+        match Jail::new(self.server.root.join(&user)) {
+            Ok(jail) => Some((user, jail)),
+            Err(e) => {
+                tracing::warn!("No SFTP directory for {}: {}", user, e);
+                None
+            }
+        }
+    }
+}
+
+impl server::Server for SftpServer {
+    type Handler = SftpConnection;
+
+    fn new_client(&mut self, peer: Option<SocketAddr>) -> SftpConnection {
+        SftpConnection {
+            server: self.clone(),
+            peer,
+            user: None,
+            channels: HashMap::new(),
+            sftp_channels: HashSet::new(),
+        }
+    }
+}
+
+impl server::Handler for SftpConnection {
+    type Error = russh::Error;
+
+    async fn auth_password(&mut self, user: &str, password: &str) -> Result<Auth, Self::Error> {
+        Ok(self.authenticate(user, SftpCredential::Password(password)))
+    }
+
+    async fn auth_publickey(&mut self, user: &str, key: &PublicKey) -> Result<Auth, Self::Error> {
+        Ok(self.authenticate(user, SftpCredential::PublicKey(key)))
+    }
+
+    async fn channel_open_session(
+        &mut self,
+        channel: Channel<Msg>,
+        _session: &mut Session,
+    ) -> Result<bool, Self::Error> {
+        self.channels.insert(channel.id(), channel);
+        Ok(true)
+    }
+
+    async fn subsystem_request(
+        &mut self,
+        channel_id: ChannelId,
+        name: &str,
+        session: &mut Session,
+    ) -> Result<(), Self::Error> {
+        match (name, self.channels.remove(&channel_id), self.jail()) {
+            ("sftp", Some(channel), Some((user, jail))) => {
+                session.channel_success(channel_id)?;
+                self.sftp_channels.insert(channel_id);
+                let sftp = SftpSession::new(jail, user, self.server.events.clone());
+                russh_sftp::server::run(channel.into_stream(), sftp).await;
+            }
+            _ => session.channel_failure(channel_id)?,
+        }
+        Ok(())
+    }
+
+    async fn channel_eof(
+        &mut self,
+        channel_id: ChannelId,
+        session: &mut Session,
+    ) -> Result<(), Self::Error> {
+        // SFTP channels are closed when the client is done; `scp` channels
+        // close themselves once the upload is received
+        if self.sftp_channels.remove(&channel_id) {
+            session.close(channel_id)?;
+        }
+        Ok(())
+    }
+
+    async fn exec_request(
+        &mut self,
+        channel_id: ChannelId,
+        data: &[u8],
+        session: &mut Session,
+    ) -> Result<(), Self::Error> {
+        let command = String::from_utf8_lossy(data);
+        let target = scp_sink_target(&command);
+        match (target, self.channels.remove(&channel_id), self.jail()) {
+            (Some(target), Some(channel), Some((user, jail))) => {
+                session.channel_success(channel_id)?;
+                let handle = session.handle();
+                let events = self.server.events.clone();
+                tokio::spawn(async move {
+                    let status =
+                        match receive_scp(channel.into_stream(), &jail, &target, &user, &events)
+                            .await
+                        {
+                            Ok(()) => 0,
+                            Err(e) => {
+                                tracing::warn!("scp upload from {} failed: {}", user, e);
+                                1
+                            }
+                        };
+                    let _ = handle.exit_status_request(channel_id, status).await;
+                    let _ = handle.eof(channel_id).await;
+                    let _ = handle.close(channel_id).await;
+                });
+            }
+            _ => {
+                tracing::debug!("Refused exec request: {}", command);
+                session.channel_failure(channel_id)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The target of `scp -t [options] <target>`, the command `scp` runs to
+/// upload with its legacy protocol; None for any other command
+fn scp_sink_target(command: &str) -> Option<String> {
+    let mut words = command.split_whitespace();
+    if words.next()? != "scp" {
+        return None;
+    }
+    let mut sink = false;
+    let mut target = None;
+    for word in words {
+        match word {
+            "-t" => sink = true,
+            "--" => {}
+            flag if flag.starts_with('-') => {}
+            operand => target = Some(operand.trim_matches(|c| c == '\'' || c == '"').to_string()),
+        }
+    }
+    sink.then(|| target.unwrap_or_else(|| ".".to_string()))
+}
+
+/// Receive files sent by a legacy `scp` into `target`, a path in `jail`
+async fn receive_scp<S>(
+    stream: S,
+    jail: &Jail,
+    target: &str,
+    user: &str,
+    events: &mpsc::UnboundedSender<SftpEvent>,
+) -> io::Result<()>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    let mut stream = BufReader::new(stream);
+    let result = scp_sink(&mut stream, jail, target, user, events).await;
+    if let Err(e) = &result {
+        let _ = stream
+            .get_mut()
+            .write_all(format!("\x02scp: {}\n", e).as_bytes())
+            .await;
+        let _ = stream.get_mut().flush().await;
+    }
+    result
+}
+
+async fn scp_sink<S>(
+    stream: &mut BufReader<S>,
+    jail: &Jail,
+    target: &str,
+    user: &str,
+    events: &mpsc::UnboundedSender<SftpEvent>,
+) -> io::Result<()>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    let target = jail
+        .resolve(target)
+        .map_err(|_| io::Error::new(io::ErrorKind::PermissionDenied, "outside the inbox"))?;
+    // Directories entered with `D`, left with `E`
+    let mut dirs: Vec<PathBuf> = Vec::new();
+
+    scp_ack(stream).await?;
+    loop {
+        let mut line = Vec::new();
+        if stream.read_until(b'\n', &mut line).await? == 0 {
+            return Ok(());
+        }
+        let line = String::from_utf8_lossy(&line);
+        let line = line.trim_end_matches('\n');
+        let invalid =
+            || io::Error::new(io::ErrorKind::InvalidData, format!("unexpected '{}'", line));
+
+        match line.chars().next() {
+            Some(kind @ ('C' | 'D')) => {
+                let mut fields = line[1..].splitn(3, ' ');
+                let (_mode, size, name) = (fields.next(), fields.next(), fields.next());
+                let size: u64 = size.and_then(|s| s.parse().ok()).ok_or_else(invalid)?;
+                let name = name.filter(|name| is_file_name(name)).ok_or_else(invalid)?;
+                let path = match dirs.last() {
+                    Some(dir) => dir.join(name),
+                    None if target.is_dir() => target.join(name),
+                    None => target.clone(),
+                };
+
+                if kind == 'D' {
+                    match tokio::fs::create_dir(&path).await {
+                        Err(e) if e.kind() != io::ErrorKind::AlreadyExists => return Err(e),
+                        _ => {}
+                    }
+                    dirs.push(path);
+                    scp_ack(stream).await?;
+                    continue;
+                }
+
+                let mut file = tokio::fs::File::create(&path).await?;
+                scp_ack(stream).await?;
+                let copied = tokio::io::copy(&mut (&mut *stream).take(size), &mut file).await?;
+                file.flush().await?;
+                if copied < size {
+                    return Err(io::ErrorKind::UnexpectedEof.into());
+                }
+                // The source ends each file with a status byte
+                let mut status = [0u8];
+                stream.read_exact(&mut status).await?;
+                scp_ack(stream).await?;
+                let _ = events.send(SftpEvent::FileReceived {
+                    user: user.to_string(),
+                    path,
+                    size,
+                });
+            }
+            Some('E') => {
+                dirs.pop();
+                scp_ack(stream).await?;
+            }
+            // Modification times, which aren't kept
+            Some('T') => scp_ack(stream).await?,
+            // A warning from the source
+            Some('\x01') => {}
+            Some('\x02') => return Err(io::Error::other(line[1..].to_string())),
+            _ => return Err(invalid()),
+        }
+    }
+}
+
+async fn scp_ack<S: AsyncRead + AsyncWrite + Unpin>(stream: &mut BufReader<S>) -> io::Result<()> {
+    stream.write_all(b"\0").await?;
+    stream.flush().await
+}
+
+/// Whether `name` is a single path component, safe to join onto a directory
+fn is_file_name(name: &str) -> bool {
+    !name.is_empty() && name != "." && name != ".." && !name.contains(['/', '\\', '\0'])
+}
+
+/// A user's directory, which no path resolves outside of
+#[derive(Debug, Clone)]
+struct Jail {
+    root: PathBuf,
+}
+
+impl Jail {
+    fn new(root: PathBuf) -> io::Result<Self> {
+        std::fs::create_dir_all(&root)?;
+        Ok(Self {
+            root: root.canonicalize()?,
+        })
+    }
+
+    /// Where the client's `path` is on disk. Absolute paths start at the
+    /// root, `..` stops there, and paths through symlinks that lead out of
+    /// it are refused.
+    fn resolve(&self, path: &str) -> Result<PathBuf, StatusCode> {
+        let mut resolved = self.root.clone();
+        for component in Path::new(path).components() {
+            match component {
+                Component::Normal(name) => resolved.push(name),
+                Component::ParentDir if resolved != self.root => {
+                    resolved.pop();
+                }
+                _ => {}
+            }
+        }
+
+        let existing = resolved
+            .ancestors()
+            .find(|p| p.symlink_metadata().is_ok())
+            .unwrap_or(&self.root);
+        match existing.canonicalize() {
+            Ok(real) if real.starts_with(&self.root) => Ok(resolved),
+            Ok(_) => Err(StatusCode::PermissionDenied),
+            // A dangling symlink
+            Err(_) => Err(StatusCode::NoSuchFile),
+        }
+    }
+
+    /// `path` as the client sees it, e.g. `/reports/q3.csv`
+    fn client_path(&self, path: &Path) -> String {
+        let relative = path.strip_prefix(&self.root).unwrap_or(path);
+        let parts: Vec<_> = relative.iter().map(|part| part.to_string_lossy()).collect();
+        format!("/{}", parts.join("/"))
+    }
+}
+
+enum OpenHandle {
+    File {
+        file: tokio::fs::File,
+        path: PathBuf,
+        upload: bool,
+    },
+    /// Entries not yet listed
+    Dir(Option<Vec<File>>),
+}
+
+/// SFTP requests of one logged-in user, served from their [`Jail`]
+///
+/// Attribute changes are accepted and ignored; symlinks can't be created
+/// or read.
+struct SftpSession {
+    jail: Jail,
+    user: String,
+    events: mpsc::UnboundedSender<SftpEvent>,
+    handles: HashMap<String, OpenHandle>,
+    next_handle: u64,
+}
+
+impl SftpSession {
+    fn new(jail: Jail, user: String, events: mpsc::UnboundedSender<SftpEvent>) -> Self {
+        Self {
+            jail,
+            user,
+            events,
+            handles: HashMap::new(),
+            next_handle: 0,
+        }
+    }
+
+    fn insert(&mut self, handle: OpenHandle) -> String {
+        self.next_handle += 1;
+        let name = self.next_handle.to_string();
+        self.handles.insert(name.clone(), handle);
+        name
+    }
+
+    fn file(&mut self, handle: &str) -> Result<&mut tokio::fs::File, StatusCode> {
+        match self.handles.get_mut(handle) {
+            Some(OpenHandle::File { file, .. }) => Ok(file),
+            _ => Err(StatusCode::Failure),
+        }
+    }
+}
+
+fn ok(id: u32) -> Status {
+    Status {
+        id,
+        status_code: StatusCode::Ok,
+        error_message: "Ok".to_string(),
+        language_tag: "en-US".to_string(),
+    }
+}
+
+fn status(error: io::Error) -> StatusCode {
+    match error.kind() {
+        io::ErrorKind::NotFound => StatusCode::NoSuchFile,
+        io::ErrorKind::PermissionDenied => StatusCode::PermissionDenied,
+        _ => StatusCode::Failure,
+    }
+}
+
+impl russh_sftp::server::Handler for SftpSession {
+    type Error = StatusCode;
+
+    fn unimplemented(&self) -> Self::Error {
+        StatusCode::OpUnsupported
+    }
+
+    async fn init(
+        &mut self,
+        _version: u32,
+        _extensions: HashMap<String, String>,
+    ) -> Result<Version, Self::Error> {
+        Ok(Version::new())
+    }
+
+    async fn open(
+        &mut self,
+        id: u32,
+        filename: String,
+        pflags: OpenFlags,
+        _attrs: FileAttributes,
+    ) -> Result<Handle, Self::Error> {
+        let path = self.jail.resolve(&filename)?;
+        let options = tokio::fs::OpenOptions::from(std::fs::OpenOptions::from(pflags));
+        let file = options.open(&path).await.map_err(status)?;
+        let upload = pflags.intersects(OpenFlags::WRITE | OpenFlags::APPEND);
+        let handle = self.insert(OpenHandle::File { file, path, upload });
+        Ok(Handle { id, handle })
+    }
+
+    async fn close(&mut self, id: u32, handle: String) -> Result<Status, Self::Error> {
+        match self.handles.remove(&handle) {
+            Some(OpenHandle::File {
+                mut file,
+                path,
+                upload: true,
+            }) => {
+                file.flush().await.map_err(status)?;
+                let size = file.metadata().await.map_err(status)?.len();
+                let _ = self.events.send(SftpEvent::FileReceived {
+                    user: self.user.clone(),
+                    path,
+                    size,
+                });
+                Ok(ok(id))
+            }
+            Some(_) => Ok(ok(id)),
+            None => Err(StatusCode::Failure),
+        }
+    }
+
+    async fn read(
+        &mut self,
+        id: u32,
+        handle: String,
+        offset: u64,
+        len: u32,
+    ) -> Result<Data, Self::Error> {
+        let file = self.file(&handle)?;
+        file.seek(SeekFrom::Start(offset)).await.map_err(status)?;
+        let mut data = vec![0; len.min(MAX_READ) as usize];
+        let read = file.read(&mut data).await.map_err(status)?;
+        if read == 0 {
+            return Err(StatusCode::Eof);
+        }
+        data.truncate(read);
+        Ok(Data { id, data })
+    }
+
+    async fn write(
+        &mut self,
+        id: u32,
+        handle: String,
+        offset: u64,
+        data: Vec<u8>,
+    ) -> Result<Status, Self::Error> {
+        let file = self.file(&handle)?;
+        file.seek(SeekFrom::Start(offset)).await.map_err(status)?;
+        file.write_all(&data).await.map_err(status)?;
+        Ok(ok(id))
+    }
+
+    async fn lstat(&mut self, id: u32, path: String) -> Result<Attrs, Self::Error> {
+        let path = self.jail.resolve(&path)?;
+        let metadata = tokio::fs::symlink_metadata(&path).await.map_err(status)?;
+        Ok(Attrs {
+            id,
+            attrs: (&metadata).into(),
+        })
+    }
+
+    async fn fstat(&mut self, id: u32, handle: String) -> Result<Attrs, Self::Error> {
+        let metadata = self.file(&handle)?.metadata().await.map_err(status)?;
+        Ok(Attrs {
+            id,
+            attrs: (&metadata).into(),
+        })
+    }
+
+    async fn stat(&mut self, id: u32, path: String) -> Result<Attrs, Self::Error> {
+        let path = self.jail.resolve(&path)?;
+        let metadata = tokio::fs::metadata(&path).await.map_err(status)?;
+        Ok(Attrs {
+            id,
+            attrs: (&metadata).into(),
+        })
+    }
+
+    async fn setstat(
+        &mut self,
+        id: u32,
+        path: String,
+        _attrs: FileAttributes,
+    ) -> Result<Status, Self::Error> {
+        self.jail.resolve(&path)?;
+        Ok(ok(id))
+    }
+
+    async fn fsetstat(
+        &mut self,
+        id: u32,
+        handle: String,
+        _attrs: FileAttributes,
+    ) -> Result<Status, Self::Error> {
+        self.file(&handle)?;
+        Ok(ok(id))
+    }
+
+    async fn opendir(&mut self, id: u32, path: String) -> Result<Handle, Self::Error> {
+        let path = self.jail.resolve(&path)?;
+        let mut entries = tokio::fs::read_dir(&path).await.map_err(status)?;
+        let mut files = Vec::new();
+        while let Some(entry) = entries.next_entry().await.map_err(status)? {
+            if let Ok(metadata) = entry.metadata().await {
+                let name = entry.file_name().to_string_lossy().into_owned();
+                files.push(File::new(name, (&metadata).into()));
+            }
+        }
+        let handle = self.insert(OpenHandle::Dir(Some(files)));
+        Ok(Handle { id, handle })
+    }
+
+    async fn readdir(&mut self, id: u32, handle: String) -> Result<Name, Self::Error> {
+        match self.handles.get_mut(&handle) {
+            Some(OpenHandle::Dir(files)) => match files.take() {
+                Some(files) => Ok(Name { id, files }),
+                None => Err(StatusCode::Eof),
+            },
+            _ => Err(StatusCode::Failure),
+        }
+    }
+
+    async fn remove(&mut self, id: u32, filename: String) -> Result<Status, Self::Error> {
+        let path = self.jail.resolve(&filename)?;
+        tokio::fs::remove_file(&path).await.map_err(status)?;
+        Ok(ok(id))
+    }
+
+    async fn mkdir(
+        &mut self,
+        id: u32,
+        path: String,
+        _attrs: FileAttributes,
+    ) -> Result<Status, Self::Error> {
+        let path = self.jail.resolve(&path)?;
+        tokio::fs::create_dir(&path).await.map_err(status)?;
+        Ok(ok(id))
+    }
+
+    async fn rmdir(&mut self, id: u32, path: String) -> Result<Status, Self::Error> {
+        let path = self.jail.resolve(&path)?;
+        if path == self.jail.root {
+            return Err(StatusCode::PermissionDenied);
+        }
+        tokio::fs::remove_dir(&path).await.map_err(status)?;
+        Ok(ok(id))
+    }
+
+    async fn realpath(&mut self, id: u32, path: String) -> Result<Name, Self::Error> {
+        let path = self.jail.resolve(&path)?;
+        Ok(Name {
+            id,
+            files: vec![File::dummy(self.jail.client_path(&path))],
+        })
+    }
+
+    async fn rename(
+        &mut self,
+        id: u32,
+        oldpath: String,
+        newpath: String,
+    ) -> Result<Status, Self::Error> {
+        let from = self.jail.resolve(&oldpath)?;
+        let to = self.jail.resolve(&newpath)?;
+        if from == self.jail.root || to == self.jail.root {
+            return Err(StatusCode::PermissionDenied);
+        }
+        tokio::fs::rename(&from, &to).await.map_err(status)?;
+        // A new name needs the checks again, e.g. for a blocked extension
+        let metadata = tokio::fs::metadata(&to).await.map_err(status)?;
+        if metadata.is_file() {
+            let _ = self.events.send(SftpEvent::FileReceived {
+                user: self.user.clone(),
+                path: to,
+                size: metadata.len(),
+            });
+        }
+        Ok(ok(id))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use russh_sftp::server::Handler;
+
+    fn write_flags() -> OpenFlags {
+        OpenFlags::WRITE | OpenFlags::CREATE | OpenFlags::TRUNCATE
+    }
+
+    #[test]
+    fn test_jail() {
+        let dir = tempfile::tempdir().unwrap();
+        let jail = Jail::new(dir.path().join("alice")).unwrap();
+        let root = jail.root.clone();
+
+        assert_eq!(
+            jail.resolve("/reports/q3.csv").unwrap(),
+            root.join("reports/q3.csv")
+        );
+        assert_eq!(
+            jail.resolve("../../etc/passwd").unwrap(),
+            root.join("etc/passwd")
+        );
+        assert_eq!(jail.resolve(".").unwrap(), root);
+        assert_eq!(
+            jail.client_path(&root.join("reports/q3.csv")),
+            "/reports/q3.csv"
+        );
+        assert_eq!(jail.client_path(&root), "/");
+
+        #[cfg(unix)]
+        {
+            std::os::unix::fs::symlink(dir.path(), root.join("out")).unwrap();
+            assert_eq!(
+                jail.resolve("out/secret"),
+                Err(StatusCode::PermissionDenied)
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn test_sftp_upload() {
+        let dir = tempfile::tempdir().unwrap();
+        let jail = Jail::new(dir.path().join("alice")).unwrap();
+        let root = jail.root.clone();
+        let (events, mut received) = mpsc::unbounded_channel();
+        let mut session = SftpSession::new(jail, "alice".to_string(), events);
+
+        session
+            .mkdir(1, "/in".into(), FileAttributes::default())
+            .await
+            .unwrap();
+        let handle = session
+            .open(
+                2,
+                "/in/../in/report.csv".into(),
+                write_flags(),
+                FileAttributes::default(),
+            )
+            .await
+            .unwrap()
+            .handle;
+        session
+            .write(3, handle.clone(), 0, b"a,b\n".to_vec())
+            .await
+            .unwrap();
+        session
+            .write(4, handle.clone(), 4, b"1,2\n".to_vec())
+            .await
+            .unwrap();
+        session.close(5, handle).await.unwrap();
+
+        assert_eq!(
+            received.try_recv().unwrap(),
+            SftpEvent::FileReceived {
+                user: "alice".to_string(),
+                path: root.join("in/report.csv"),
+                size: 8,
+            }
+        );
+        assert_eq!(
+            std::fs::read(root.join("in/report.csv")).unwrap(),
+            b"a,b\n1,2\n"
+        );
+
+        // Reading sends no event
+        let handle = session
+            .open(
+                6,
+                "in/report.csv".into(),
+                OpenFlags::READ,
+                FileAttributes::default(),
+            )
+            .await
+            .unwrap()
+            .handle;
+        let data = session.read(7, handle.clone(), 4, 100).await.unwrap().data;
+        assert_eq!(data, b"1,2\n");
+        assert_eq!(
+            session.read(8, handle.clone(), 8, 100).await.unwrap_err(),
+            StatusCode::Eof
+        );
+        session.close(9, handle).await.unwrap();
+        assert!(received.try_recv().is_err());
+
+        let handle = session.opendir(10, "/in".into()).await.unwrap().handle;
+        let names: Vec<_> = session
+            .readdir(11, handle.clone())
+            .await
+            .unwrap()
+            .files
+            .into_iter()
+            .map(|f| f.filename)
+            .collect();
+        assert_eq!(names, vec!["report.csv"]);
+        assert_eq!(
+            session.readdir(12, handle).await.unwrap_err(),
+            StatusCode::Eof
+        );
+
+        let real = session.realpath(13, "in/../..".into()).await.unwrap();
+        assert_eq!(real.files[0].filename, "/");
+        assert_eq!(
+            session.rmdir(14, "/".into()).await.unwrap_err(),
+            StatusCode::PermissionDenied
+        );
+        assert_eq!(
+            session.stat(15, "/missing".into()).await.unwrap_err(),
+            StatusCode::NoSuchFile
+        );
+
+        // Renaming a file reports it under its new name
+        session
+            .rename(16, "/in/report.csv".into(), "/in/report.exe".into())
+            .await
+            .unwrap();
+        assert_eq!(
+            received.try_recv().unwrap(),
+            SftpEvent::FileReceived {
+                user: "alice".to_string(),
+                path: root.join("in/report.exe"),
+                size: 8,
+            }
+        );
+        session
+            .rename(17, "/in".into(), "/out".into())
+            .await
+            .unwrap();
+        assert!(received.try_recv().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_scp_sink() {
+        assert_eq!(
+            scp_sink_target("scp -t -- /uploads"),
+            Some("/uploads".to_string())
+        );
+        assert_eq!(scp_sink_target("scp -r -d -t ."), Some(".".to_string()));
+        assert_eq!(scp_sink_target("scp -f /etc/passwd"), None);
+        assert_eq!(scp_sink_target("rm -rf /"), None);
+
+        let dir = tempfile::tempdir().unwrap();
+        let jail = Jail::new(dir.path().join("bob")).unwrap();
+        let root = jail.root.clone();
+        let (events, mut received) = mpsc::unbounded_channel();
+        let (client, server) = tokio::io::duplex(1024);
+
+        let sink =
+            tokio::spawn(async move { receive_scp(server, &jail, "/", "bob", &events).await });
+
+        // What `scp -r logs bob@host:/` sends, each step acknowledged
+        let (mut from_sink, mut to_sink) = tokio::io::split(client);
+        let mut ack = [0xffu8];
+        for message in [
+            &b""[..],
+            b"D0755 0 logs\n",
+            b"C0644 6 app.log\n",
+            b"hello\n\0",
+            b"E\n",
+        ] {
+            to_sink.write_all(message).await.unwrap();
+            from_sink.read_exact(&mut ack).await.unwrap();
+            assert_eq!(ack, [0]);
+        }
+        drop((from_sink, to_sink));
+        sink.await.unwrap().unwrap();
+
+        assert_eq!(
+            std::fs::read(root.join("logs/app.log")).unwrap(),
+            b"hello\n"
+        );
+        assert_eq!(
+            received.try_recv().unwrap(),
+            SftpEvent::FileReceived {
+                user: "bob".to_string(),
+                path: root.join("logs/app.log"),
+                size: 6,
+            }
+        );
+
+        // Names can't leave the directory they're sent to
+        let (client, server) = tokio::io::duplex(1024);
+        let jail = Jail::new(root.clone()).unwrap();
+        let (events, _) = mpsc::unbounded_channel();
+        let sink =
+            tokio::spawn(async move { receive_scp(server, &jail, "/", "bob", &events).await });
+        let (mut from_sink, mut to_sink) = tokio::io::split(client);
+        from_sink.read_exact(&mut ack).await.unwrap();
+        to_sink.write_all(b"C0644 1 ../escape\n").await.unwrap();
+        assert!(sink.await.unwrap().is_err());
+        let mut error = Vec::new();
+        from_sink.read_to_end(&mut error).await.unwrap();
+        assert!(error.starts_with(b"\x02scp:"));
+        assert!(!dir.path().join("escape").exists());
+    }
+}
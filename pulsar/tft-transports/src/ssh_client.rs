@@ -1,11 +1,19 @@
 //! SSH client implementation using russh
+//!
+//! Failures come back as [`TransportError`]s: a rejected host key, failed
+//! authentication and an unreachable server are told apart, so callers
+//! know whether retrying can help. Connects are retried as the config's
+//! retry policy says.
 
 use crate::known_hosts::{HostKeyVerification, KnownHosts};
-use anyhow::{Context, Result};
+use crate::retry::{RetryObserver, RetryPolicy};
+use crate::transport::TransportError;
 use russh::client::{self, AuthResult, Handle, Msg};
 use russh::keys::*;
 use russh::*;
+use std::net::SocketAddr;
 use std::sync::{Arc, Mutex};
+use tokio::net::TcpStream;
 use tokio::sync::mpsc;
 
 pub struct SshConfig {
@@ -17,12 +25,18 @@ pub struct SshConfig {
     pub accept_unknown_hosts: bool,
     /// If true, accept changed host keys automatically (VERY INSECURE, for development only)
     pub accept_changed_hosts: bool,
+    /// How failed connects are retried
+    pub retry: RetryPolicy,
 }
 
+#[derive(Clone)]
 pub enum AuthMethod {
     Password(String),
     PublicKey { key_path: String, passphrase: Option<String> },
     Agent,
+    /// An OpenSSH private key and the certificate a CA signed for it, both
+    /// held in memory
+    Certificate { private_key: String, certificate: String },
 }
 
 struct Client {
@@ -32,6 +46,8 @@ struct Client {
     accept_unknown: bool,
     accept_changed: bool,
     fingerprint: Arc<Mutex<Option<String>>>,
+    /// Why the host key was rejected, reported instead of russh's error
+    rejection: Arc<Mutex<Option<TransportError>>>,
 }
 
 impl client::Handler for Client {
@@ -78,6 +94,10 @@ impl client::Handler for Client {
                     Ok(true)
                 } else {
                     tracing::error!("Rejecting unknown host key (set accept_unknown_hosts to accept)");
+                    *self.rejection.lock().unwrap() = Some(TransportError::HostKeyUnknown {
+                        host: self.hostname.clone(),
+                        fingerprint,
+                    });
                     Ok(false)
                 }
             }
@@ -100,6 +120,10 @@ impl client::Handler for Client {
                     Ok(true)
                 } else {
                     tracing::error!("Rejecting changed host key (set accept_changed_hosts to override)");
+                    *self.rejection.lock().unwrap() = Some(TransportError::HostKeyMismatch {
+                        host: self.hostname.clone(),
+                        fingerprint,
+                    });
                     Ok(false)
                 }
             }
@@ -111,6 +135,8 @@ pub struct SshSession {
     handle: Handle<Client>,
     channel: Channel<Msg>,
     fingerprint: String,
+    local_addr: SocketAddr,
+    peer_addr: SocketAddr,
 }
 
 impl SshSession {
@@ -118,14 +144,38 @@ impl SshSession {
     pub fn fingerprint(&self) -> &str {
         &self.fingerprint
     }
+
+    /// This end of the TCP connection
+    pub fn local_addr(&self) -> SocketAddr {
+        self.local_addr
+    }
+
+    pub fn peer_addr(&self) -> SocketAddr {
+        self.peer_addr
+    }
 }
 
 impl SshSession {
-    pub async fn connect(config: SshConfig) -> Result<Self> {
+    pub async fn connect(config: SshConfig) -> Result<Self, TransportError> {
+        Self::connect_observed(config, None).await
+    }
+
+    /// Connect, reporting each attempt to `observer`
+    pub async fn connect_observed(
+        config: SshConfig,
+        observer: Option<RetryObserver>,
+    ) -> Result<Self, TransportError> {
+        config
+            .retry
+            .run(observer.as_ref(), |_| Self::connect_once(&config))
+            .await
+    }
+
+    async fn connect_once(config: &SshConfig) -> Result<Self, TransportError> {
         // Load known_hosts
-        let known_hosts = Arc::new(Mutex::new(
-            KnownHosts::load().context("Failed to load known_hosts")?,
-        ));
+        let known_hosts = Arc::new(Mutex::new(KnownHosts::load().map_err(|e| {
+            TransportError::Config(format!("Failed to load known_hosts: {:#}", e))
+        })?));
 
         let client_config = client::Config {
             inactivity_timeout: Some(std::time::Duration::from_secs(3600)),
@@ -133,6 +183,7 @@ impl SshSession {
         };
 
         let fingerprint_holder = Arc::new(Mutex::new(None));
+        let rejection = Arc::new(Mutex::new(None));
 
         let handler = Client {
             known_hosts,
@@ -141,27 +192,31 @@ impl SshSession {
             accept_unknown: config.accept_unknown_hosts,
             accept_changed: config.accept_changed_hosts,
             fingerprint: Arc::clone(&fingerprint_holder),
+            rejection: Arc::clone(&rejection),
         };
 
-        let mut session = client::connect(
-            Arc::new(client_config),
-            (config.host.as_str(), config.port),
-            handler,
-        )
-        .await
-        .context("Failed to connect to SSH server")?;
+        // Connected here rather than by russh so the addresses are known,
+        // to tell later whether a network change stranded the session
+        let stream = TcpStream::connect((config.host.as_str(), config.port)).await?;
+        stream.set_nodelay(true)?;
+        let local_addr = stream.local_addr()?;
+        let peer_addr = stream.peer_addr()?;
+
+        let mut session = client::connect_stream(Arc::new(client_config), stream, handler)
+            .await
+            .map_err(|e| rejection.lock().unwrap().take().unwrap_or_else(|| e.into()))?;
 
         // Authenticate
-        let auth_result = match config.auth {
+        let auth_result = match &config.auth {
             AuthMethod::Password(password) => {
                 session
-                    .authenticate_password(config.username, password)
-                    .await
-                    .context("Password authentication failed")?
+                    .authenticate_password(&config.username, password)
+                    .await?
             }
             AuthMethod::PublicKey { key_path, passphrase } => {
-                let key = load_secret_key(&key_path, passphrase.as_deref())
-                    .context("Failed to load SSH key")?;
+                let key = load_secret_key(key_path, passphrase.as_deref()).map_err(|e| {
+                    TransportError::Config(format!("Failed to load SSH key {}: {}", key_path, e))
+                })?;
 
                 let key_with_alg = PrivateKeyWithHashAlg::new(
                     Arc::new(key),
@@ -169,24 +224,38 @@ impl SshSession {
                 );
 
                 session
-                    .authenticate_publickey(config.username, key_with_alg)
-                    .await
-                    .context("Public key authentication failed")?
+                    .authenticate_publickey(&config.username, key_with_alg)
+                    .await?
+            }
+            AuthMethod::Certificate { private_key, certificate } => {
+                let key = decode_secret_key(private_key, None).map_err(|e| {
+                    TransportError::Config(format!("Invalid certificate key: {}", e))
+                })?;
+                let certificate = Certificate::from_openssh(certificate).map_err(|e| {
+                    TransportError::Config(format!("Invalid SSH certificate: {}", e))
+                })?;
+
+                session
+                    .authenticate_openssh_cert(&config.username, Arc::new(key), certificate)
+                    .await?
             }
             AuthMethod::Agent => {
                 // Connect to SSH agent
                 let mut agent_client = russh::keys::agent::client::AgentClient::connect_env()
                     .await
-                    .context("Failed to connect to SSH agent")?;
+                    .map_err(|e| {
+                        TransportError::Auth(format!("Failed to connect to SSH agent: {}", e))
+                    })?;
 
                 // Get list of identities from agent
-                let identities = agent_client
-                    .request_identities()
-                    .await
-                    .context("Failed to get identities from SSH agent")?;
+                let identities = agent_client.request_identities().await.map_err(|e| {
+                    TransportError::Auth(format!("Failed to get identities from SSH agent: {}", e))
+                })?;
 
                 if identities.is_empty() {
-                    anyhow::bail!("No identities available in SSH agent");
+                    return Err(TransportError::Auth(
+                        "No identities available in SSH agent".to_string(),
+                    ));
                 }
 
                 // Try each identity until one works
@@ -225,22 +294,19 @@ impl SshSession {
 
                 auth_result.ok_or_else(|| {
                     let err_msg = last_error.unwrap_or_else(|| "No keys worked".to_string());
-                    anyhow::anyhow!("SSH agent authentication failed: {}", err_msg)
+                    TransportError::Auth(format!("SSH agent authentication failed: {}", err_msg))
                 })?
             }
         };
 
         if !matches!(auth_result, AuthResult::Success) {
-            anyhow::bail!("SSH authentication failed: {:?}", auth_result);
+            return Err(TransportError::Auth(format!("{:?}", auth_result)));
         }
 
         tracing::info!("SSH authentication successful");
 
         // Open a channel
-        let channel = session
-            .channel_open_session()
-            .await
-            .context("Failed to open SSH channel")?;
+        let channel = session.channel_open_session().await?;
 
         // Retrieve the stored fingerprint
         let fingerprint = fingerprint_holder
@@ -253,10 +319,12 @@ impl SshSession {
             handle: session,
             channel,
             fingerprint,
+            local_addr,
+            peer_addr,
         })
     }
 
-    pub async fn request_pty(&mut self, cols: u32, rows: u32) -> Result<()> {
+    pub async fn request_pty(&mut self, cols: u32, rows: u32) -> Result<(), TransportError> {
         let term = std::env::var("TERM").unwrap_or_else(|_| "xterm-256color".to_string());
 
         self.channel
@@ -269,40 +337,30 @@ impl SshSession {
                 0,          // pixel height (not used)
                 &[],        // terminal modes
             )
-            .await
-            .context("Failed to request PTY")?;
+            .await?;
 
         Ok(())
     }
 
-    pub async fn request_shell(&mut self) -> Result<()> {
-        self.channel
-            .request_shell(true)
-            .await
-            .context("Failed to request shell")?;
+    pub async fn request_shell(&mut self) -> Result<(), TransportError> {
+        self.channel.request_shell(true).await?;
 
         Ok(())
     }
 
-    pub async fn resize(&mut self, cols: u32, rows: u32) -> Result<()> {
-        self.channel
-            .window_change(cols, rows, 0, 0)
-            .await
-            .context("Failed to resize PTY")?;
+    pub async fn resize(&mut self, cols: u32, rows: u32) -> Result<(), TransportError> {
+        self.channel.window_change(cols, rows, 0, 0).await?;
 
         Ok(())
     }
 
-    pub async fn write(&mut self, data: &[u8]) -> Result<()> {
-        self.channel
-            .data(data)
-            .await
-            .context("Failed to write to SSH channel")?;
+    pub async fn write(&mut self, data: &[u8]) -> Result<(), TransportError> {
+        self.channel.data(data).await?;
 
         Ok(())
     }
 
-    pub async fn read(&mut self) -> Result<Option<Vec<u8>>> {
+    pub async fn read(&mut self) -> Result<Option<Vec<u8>>, TransportError> {
         match self.channel.wait().await {
             Some(ChannelMsg::Data { ref data }) => Ok(Some(data.to_vec())),
             Some(ChannelMsg::ExtendedData { ref data, ext: 1 }) => {
@@ -322,13 +380,87 @@ impl SshSession {
         }
     }
 
-    pub async fn close(self) -> Result<()> {
+    pub async fn close(self) -> Result<(), TransportError> {
         self.channel.eof().await?;
         self.handle.disconnect(Disconnect::ByApplication, "", "en").await?;
         Ok(())
     }
 }
 
+impl From<russh::Error> for TransportError {
+    fn from(error: russh::Error) -> Self {
+        match error {
+            russh::Error::IO(e) => e.into(),
+            russh::Error::ConnectionTimeout
+            | russh::Error::KeepaliveTimeout
+            | russh::Error::InactivityTimeout => TransportError::Timeout(error.to_string()),
+            russh::Error::Disconnect | russh::Error::HUP => {
+                TransportError::Closed(error.to_string())
+            }
+            russh::Error::NotAuthenticated | russh::Error::NoAuthMethod => {
+                TransportError::Auth(error.to_string())
+            }
+            russh::Error::NoCommonAlgo { .. } => TransportError::ProtocolVersion(error.to_string()),
+            error => TransportError::Protocol(error.to_string()),
+        }
+    }
+}
+
+/// A server's host key, as offered before authentication
+#[derive(Debug, Clone)]
+pub struct ScannedHostKey {
+    pub algorithm: String,
+    /// SHA256 fingerprint
+    pub fingerprint: String,
+    /// `<algorithm> <base64>`, as written in known_hosts
+    pub openssh: String,
+    /// How the key compares with the user's known_hosts
+    pub verification: HostKeyVerification,
+}
+
+/// Records the host key and ends the handshake there
+struct KeyScan {
+    key: Arc<Mutex<Option<russh::keys::PublicKey>>>,
+}
+
+impl client::Handler for KeyScan {
+    type Error = russh::Error;
+
+    async fn check_server_key(
+        &mut self,
+        server_public_key: &russh::keys::PublicKey,
+    ) -> Result<bool, Self::Error> {
+        *self.key.lock().unwrap() = Some(server_public_key.clone());
+        Ok(false)
+    }
+}
+
+/// Fetch a server's host key without logging in, like `ssh-keyscan`
+pub async fn scan_host_key(host: &str, port: u16) -> Result<ScannedHostKey, TransportError> {
+    let known_hosts = KnownHosts::load()
+        .map_err(|e| TransportError::Config(format!("Failed to load known_hosts: {:#}", e)))?;
+
+    let key = Arc::new(Mutex::new(None));
+    let handler = KeyScan {
+        key: Arc::clone(&key),
+    };
+    // Rejecting the key always fails the connect; only the key matters
+    let result = client::connect(Arc::new(client::Config::default()), (host, port), handler).await;
+    let Some(key) = key.lock().unwrap().take() else {
+        return Err(match result {
+            Ok(_) => TransportError::Protocol(format!("{}:{} sent no host key", host, port)),
+            Err(e) => e.into(),
+        });
+    };
+
+    Ok(ScannedHostKey {
+        algorithm: key.algorithm().to_string(),
+        fingerprint: KnownHosts::fingerprint(&key),
+        openssh: key.to_openssh().unwrap_or_default(),
+        verification: known_hosts.verify(host, port, &key),
+    })
+}
+
 /// Spawns a task to handle SSH I/O with mpsc channels
 pub fn spawn_ssh_io(
     mut session: SshSession,
@@ -375,3 +507,23 @@ pub fn spawn_ssh_io(
 
     (input_tx, output_rx)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_russh_errors_are_classified() {
+        let timeout = TransportError::from(russh::Error::ConnectionTimeout);
+        assert!(matches!(timeout, TransportError::Timeout(_)));
+        assert!(timeout.is_retryable());
+
+        let refused = std::io::Error::from(std::io::ErrorKind::ConnectionRefused);
+        assert!(matches!(
+            TransportError::from(russh::Error::IO(refused)),
+            TransportError::Refused(_)
+        ));
+
+        assert!(!TransportError::from(russh::Error::NotAuthenticated).is_retryable());
+    }
+}
@@ -0,0 +1,168 @@
+//! Bandwidth throttling
+//!
+//! A [`Throttle`] is a token bucket of bytes, refilled continuously at the
+//! limit's rate and holding at most its burst. Sending or receiving a frame
+//! takes its size from the bucket; a frame bigger than what is left is let
+//! through at once and the debt is waited off first, so frames of any size
+//! pass and the average rate holds. Clones share the bucket, so one limit
+//! can cover several transports, and [`Throttle::set_limit`] takes effect
+//! for the next frame, even mid-transfer.
+
+use serde::{Deserialize, Serialize};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::Duration;
+use tokio::time::Instant;
+
+/// A cap on transfer rate
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BandwidthLimit {
+    /// Average rate
+    pub bytes_per_sec: u64,
+    /// Bytes that may go out at once after an idle spell
+    pub burst_bytes: u64,
+}
+
+impl BandwidthLimit {
+    /// A limit of `bytes_per_sec`, bursting up to `burst_bytes` (one
+    /// second's worth by default); `None` if the rate is 0, i.e. unlimited
+    pub fn new(bytes_per_sec: u64, burst_bytes: Option<u64>) -> Option<Self> {
+        (bytes_per_sec > 0).then(|| Self {
+            bytes_per_sec,
+            burst_bytes: burst_bytes.unwrap_or(bytes_per_sec).max(1),
+        })
+    }
+}
+
+#[derive(Debug)]
+struct Bucket {
+    limit: Option<BandwidthLimit>,
+    /// Bytes available; negative while frames let through early are paid off
+    tokens: f64,
+    refilled: Instant,
+}
+
+impl Bucket {
+    fn refill(&mut self, now: Instant) {
+        if let Some(limit) = self.limit {
+            let elapsed = now.duration_since(self.refilled).as_secs_f64();
+            self.tokens =
+                (self.tokens + elapsed * limit.bytes_per_sec as f64).min(limit.burst_bytes as f64);
+        }
+        self.refilled = now;
+    }
+}
+
+/// Shared byte-rate limit for transports
+#[derive(Debug, Clone)]
+pub struct Throttle {
+    bucket: Arc<Mutex<Bucket>>,
+}
+
+impl Throttle {
+    /// A throttle enforcing `limit`, starting with a full burst; `None`
+    /// lets everything through
+    pub fn new(limit: Option<BandwidthLimit>) -> Self {
+        Self {
+            bucket: Arc::new(Mutex::new(Bucket {
+                limit,
+                tokens: limit.map_or(0.0, |limit| limit.burst_bytes as f64),
+                refilled: Instant::now(),
+            })),
+        }
+    }
+
+    fn bucket(&self) -> MutexGuard<'_, Bucket> {
+        self.bucket.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    pub fn limit(&self) -> Option<BandwidthLimit> {
+        self.bucket().limit
+    }
+
+    /// Enforce `limit` from the next frame on, returning the previous one
+    ///
+    /// Bytes already owed stay owed; a lower burst caps what has built up.
+    pub fn set_limit(&self, limit: Option<BandwidthLimit>) -> Option<BandwidthLimit> {
+        let mut bucket = self.bucket();
+        bucket.refill(Instant::now());
+        let previous = std::mem::replace(&mut bucket.limit, limit);
+        bucket.tokens = match (previous, limit) {
+            (_, None) => 0.0,
+            (None, Some(limit)) => limit.burst_bytes as f64,
+            (Some(_), Some(limit)) => bucket.tokens.min(limit.burst_bytes as f64),
+        };
+        previous
+    }
+
+    /// Wait until `bytes` may be sent or received under the limit
+    pub async fn acquire(&self, bytes: usize) {
+        let wait = {
+            let mut bucket = self.bucket();
+            let Some(limit) = bucket.limit else {
+                return;
+            };
+            bucket.refill(Instant::now());
+            let owed = -bucket.tokens;
+            bucket.tokens -= bytes as f64;
+            Duration::from_secs_f64(owed.max(0.0) / limit.bytes_per_sec as f64)
+        };
+        if !wait.is_zero() {
+            tokio::time::sleep(wait).await;
+        }
+    }
+}
+
+impl Default for Throttle {
+    fn default() -> Self {
+        Self::new(None)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test(start_paused = true)]
+    async fn test_rate_and_burst() {
+        let throttle = Throttle::new(BandwidthLimit::new(1000, Some(500)));
+        let start = Instant::now();
+
+        // The burst goes out at once, and so does the frame that overdraws it
+        throttle.acquire(500).await;
+        throttle.acquire(1000).await;
+        assert_eq!(start.elapsed(), Duration::ZERO);
+        // The next waits for the 1000 bytes owed
+        throttle.acquire(100).await;
+        assert_eq!(start.elapsed(), Duration::from_secs(1));
+
+        // Idle time refills no more than the burst
+        tokio::time::sleep(Duration::from_secs(10)).await;
+        let start = Instant::now();
+        throttle.acquire(900).await;
+        throttle.acquire(1).await;
+        assert_eq!(start.elapsed(), Duration::from_millis(400));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_limit_changes_at_runtime() {
+        let throttle = Throttle::default();
+        let shared = throttle.clone();
+        let start = Instant::now();
+        throttle.acquire(1 << 30).await;
+        assert_eq!(start.elapsed(), Duration::ZERO);
+
+        let limit = BandwidthLimit::new(100, None);
+        assert_eq!(shared.set_limit(limit), None);
+        assert_eq!(throttle.limit(), limit);
+        throttle.acquire(300).await;
+        throttle.acquire(1).await;
+        assert_eq!(start.elapsed(), Duration::from_secs(2));
+
+        // Lifting the limit forgives what is owed
+        assert_eq!(shared.set_limit(None), limit);
+        throttle.acquire(1000).await;
+        assert_eq!(start.elapsed(), Duration::from_secs(2));
+
+        assert_eq!(BandwidthLimit::new(0, Some(10)), None);
+    }
+}
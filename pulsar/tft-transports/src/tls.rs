@@ -0,0 +1,360 @@
+//! TLS trust configuration for the QUIC transport
+//!
+//! Server certificates can be trusted through the system store, a custom
+//! CA bundle, SPKI pins, or a combination. With pins and no CA source the
+//! pin alone establishes trust (typical for a self-signed daemon), but the
+//! certificate's validity period is still enforced.
+//!
+//! Verification failures are recorded so the transport can report a pin
+//! mismatch, an expired certificate and an unknown issuer as distinct
+//! errors instead of a generic handshake failure.
+
+use crate::transport::{TlsOptions, TransportError};
+use base64::{engine::general_purpose::STANDARD, Engine as _};
+use ring::digest::{digest, SHA256};
+use rustls::client::danger::{HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier};
+use rustls::client::WebPkiServerVerifier;
+use rustls::crypto::CryptoProvider;
+use rustls::pki_types::{CertificateDer, ServerName, UnixTime};
+use rustls::{CertificateError, DigitallySignedStruct, Error, RootCertStore, SignatureScheme};
+use std::sync::{Arc, Mutex};
+use tracing::warn;
+
+/// Prefix used for SPKI pins, matching the HPKP / curl `--pinnedpubkey` format
+pub const PIN_PREFIX: &str = "sha256/";
+
+/// Why the last server certificate was rejected
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TlsFailure {
+    /// The certificate chain was valid but its key is not pinned
+    PinMismatch { spki_sha256: String },
+    Expired,
+    NotYetValid,
+    /// No trusted CA issued the certificate
+    Untrusted(String),
+}
+
+impl TlsFailure {
+    /// Convert into the transport error reported to callers
+    pub fn into_error(self, host: &str) -> TransportError {
+        let host = host.to_string();
+        match self {
+            TlsFailure::PinMismatch { spki_sha256 } => {
+                TransportError::PinMismatch { host, spki_sha256 }
+            }
+            TlsFailure::Expired => TransportError::CertificateExpired(host),
+            TlsFailure::NotYetValid => TransportError::CertificateNotYetValid(host),
+            TlsFailure::Untrusted(reason) => TransportError::UntrustedCertificate { host, reason },
+        }
+    }
+}
+
+/// Shared slot the verifier writes its last failure into
+pub type FailureSlot = Arc<Mutex<Option<TlsFailure>>>;
+
+/// Build the rustls client config for the given trust options
+pub fn client_crypto(
+    options: &TlsOptions,
+    provider: Arc<CryptoProvider>,
+    failure: FailureSlot,
+) -> Result<rustls::ClientConfig, TransportError> {
+    let verifier = PinningVerifier::new(options, Arc::clone(&provider), failure)?;
+
+    Ok(rustls::ClientConfig::builder_with_provider(provider)
+        .with_protocol_versions(&[&rustls::version::TLS13])
+        .map_err(|e| TransportError::TlsConfig(e.to_string()))?
+        .dangerous()
+        .with_custom_certificate_verifier(Arc::new(verifier))
+        .with_no_client_auth())
+}
+
+/// Compute the pin string for a certificate's public key
+pub fn spki_pin(cert: &CertificateDer<'_>) -> Result<String, Error> {
+    let cert = webpki::EndEntityCert::try_from(cert)
+        .map_err(|_| Error::InvalidCertificate(CertificateError::BadEncoding))?;
+    let hash = digest(&SHA256, cert.subject_public_key_info().as_ref());
+    Ok(format!("{}{}", PIN_PREFIX, STANDARD.encode(hash.as_ref())))
+}
+
+/// Parse a `sha256/<base64>` pin, rejecting anything that is not a SHA-256 digest
+fn parse_pin(pin: &str) -> Result<String, TransportError> {
+    let encoded = pin
+        .trim()
+        .strip_prefix(PIN_PREFIX)
+        .ok_or_else(|| TransportError::TlsConfig(format!("pin must start with {}: {}", PIN_PREFIX, pin)))?;
+
+    match STANDARD.decode(encoded) {
+        Ok(bytes) if bytes.len() == 32 => Ok(format!("{}{}", PIN_PREFIX, encoded)),
+        _ => Err(TransportError::TlsConfig(format!("invalid SHA-256 pin: {}", pin))),
+    }
+}
+
+#[derive(Debug)]
+struct PinningVerifier {
+    /// Chain verifier; `None` in pin-only mode
+    chain: Option<Arc<WebPkiServerVerifier>>,
+    pins: Vec<String>,
+    provider: Arc<CryptoProvider>,
+    failure: FailureSlot,
+}
+
+impl PinningVerifier {
+    fn new(
+        options: &TlsOptions,
+        provider: Arc<CryptoProvider>,
+        failure: FailureSlot,
+    ) -> Result<Self, TransportError> {
+        let pins = options
+            .pinned_spki
+            .iter()
+            .map(|p| parse_pin(p))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let mut roots = RootCertStore::empty();
+        if options.use_system_roots {
+            let native = rustls_native_certs::load_native_certs();
+            for error in &native.errors {
+                warn!("Failed to load system certificate: {}", error);
+            }
+            roots.add_parsable_certificates(native.certs);
+        }
+        if let Some(path) = &options.ca_bundle {
+            let data = std::fs::read(path).map_err(|e| {
+                TransportError::TlsConfig(format!("cannot read CA bundle {}: {}", path.display(), e))
+            })?;
+            let certs = rustls_pemfile::certs(&mut data.as_slice())
+                .collect::<Result<Vec<_>, _>>()
+                .map_err(|e| {
+                    TransportError::TlsConfig(format!("invalid CA bundle {}: {}", path.display(), e))
+                })?;
+            let (added, _) = roots.add_parsable_certificates(certs);
+            if added == 0 {
+                return Err(TransportError::TlsConfig(format!(
+                    "CA bundle {} contains no usable certificates",
+                    path.display()
+                )));
+            }
+        }
+
+        let chain = if roots.is_empty() {
+            if pins.is_empty() {
+                return Err(TransportError::TlsConfig(
+                    "no trust source: enable system roots, supply a CA bundle or pin a key".to_string(),
+                ));
+            }
+            None
+        } else {
+            Some(chain_verifier(roots, &provider)?)
+        };
+
+        Ok(Self {
+            chain,
+            pins,
+            provider,
+            failure,
+        })
+    }
+
+    fn record(&self, failure: TlsFailure) {
+        *self.failure.lock().unwrap() = Some(failure);
+    }
+
+    fn record_error(&self, error: &Error) {
+        let failure = match error {
+            Error::InvalidCertificate(CertificateError::Expired)
+            | Error::InvalidCertificate(CertificateError::ExpiredContext { .. }) => TlsFailure::Expired,
+            Error::InvalidCertificate(CertificateError::NotValidYet)
+            | Error::InvalidCertificate(CertificateError::NotValidYetContext { .. }) => {
+                TlsFailure::NotYetValid
+            }
+            other => TlsFailure::Untrusted(other.to_string()),
+        };
+        self.record(failure);
+    }
+
+    /// Check the validity period in pin-only mode
+    ///
+    /// The server's own chain is used as trust anchors, so this enforces
+    /// dates and signatures without requiring a CA. Name checks are skipped:
+    /// the pinned key already identifies the server.
+    fn verify_pinned_chain(
+        &self,
+        end_entity: &CertificateDer<'_>,
+        intermediates: &[CertificateDer<'_>],
+        server_name: &ServerName<'_>,
+        now: UnixTime,
+    ) -> Result<(), Error> {
+        let mut anchors = RootCertStore::empty();
+        anchors.add_parsable_certificates(
+            std::iter::once(end_entity)
+                .chain(intermediates)
+                .map(|c| c.clone().into_owned()),
+        );
+
+        let verifier = chain_verifier(anchors, &self.provider)
+            .map_err(|e| Error::General(e.to_string()))?;
+        match verifier.verify_server_cert(end_entity, intermediates, server_name, &[], now) {
+            Ok(_) => Ok(()),
+            Err(Error::InvalidCertificate(CertificateError::NotValidForName))
+            | Err(Error::InvalidCertificate(CertificateError::NotValidForNameContext { .. })) => Ok(()),
+            Err(e) => Err(e),
+        }
+    }
+}
+
+impl ServerCertVerifier for PinningVerifier {
+    fn verify_server_cert(
+        &self,
+        end_entity: &CertificateDer<'_>,
+        intermediates: &[CertificateDer<'_>],
+        server_name: &ServerName<'_>,
+        ocsp_response: &[u8],
+        now: UnixTime,
+    ) -> Result<ServerCertVerified, Error> {
+        let result = match &self.chain {
+            Some(chain) => chain
+                .verify_server_cert(end_entity, intermediates, server_name, ocsp_response, now)
+                .map(|_| ()),
+            None => self.verify_pinned_chain(end_entity, intermediates, server_name, now),
+        };
+        if let Err(e) = result {
+            self.record_error(&e);
+            return Err(e);
+        }
+
+        if !self.pins.is_empty() {
+            let pin = spki_pin(end_entity)?;
+            if !self.pins.contains(&pin) {
+                self.record(TlsFailure::PinMismatch { spki_sha256: pin });
+                return Err(Error::InvalidCertificate(CertificateError::ApplicationVerificationFailure));
+            }
+        }
+
+        *self.failure.lock().unwrap() = None;
+        Ok(ServerCertVerified::assertion())
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, Error> {
+        rustls::crypto::verify_tls12_signature(
+            message,
+            cert,
+            dss,
+            &self.provider.signature_verification_algorithms,
+        )
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, Error> {
+        rustls::crypto::verify_tls13_signature(
+            message,
+            cert,
+            dss,
+            &self.provider.signature_verification_algorithms,
+        )
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.provider
+            .signature_verification_algorithms
+            .supported_schemes()
+    }
+}
+
+fn chain_verifier(
+    roots: RootCertStore,
+    provider: &Arc<CryptoProvider>,
+) -> Result<Arc<WebPkiServerVerifier>, TransportError> {
+    WebPkiServerVerifier::builder_with_provider(Arc::new(roots), Arc::clone(provider))
+        .build()
+        .map_err(|e| TransportError::TlsConfig(e.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn self_signed(expired: bool) -> CertificateDer<'static> {
+        let mut params = rcgen::CertificateParams::new(vec!["localhost".to_string()]).unwrap();
+        if expired {
+            params.not_before = rcgen::date_time_ymd(2000, 1, 1);
+            params.not_after = rcgen::date_time_ymd(2001, 1, 1);
+        }
+        let key = rcgen::KeyPair::generate().unwrap();
+        params.self_signed(&key).unwrap().der().clone()
+    }
+
+    fn verifier(pins: Vec<String>) -> (PinningVerifier, FailureSlot) {
+        let options = TlsOptions {
+            pinned_spki: pins,
+            ca_bundle: None,
+            use_system_roots: false,
+        };
+        let failure = FailureSlot::default();
+        let provider = Arc::new(rustls::crypto::ring::default_provider());
+        (PinningVerifier::new(&options, provider, Arc::clone(&failure)).unwrap(), failure)
+    }
+
+    fn verify(verifier: &PinningVerifier, cert: &CertificateDer<'_>) -> Result<ServerCertVerified, Error> {
+        let name = ServerName::try_from("127.0.0.1").unwrap();
+        verifier.verify_server_cert(cert, &[], &name, &[], UnixTime::now())
+    }
+
+    #[test]
+    fn test_pinned_self_signed_cert_is_accepted() {
+        let cert = self_signed(false);
+        let (verifier, failure) = verifier(vec![spki_pin(&cert).unwrap()]);
+
+        assert!(verify(&verifier, &cert).is_ok());
+        assert!(failure.lock().unwrap().is_none());
+    }
+
+    #[test]
+    fn test_pin_mismatch_is_distinguished_from_expiry() {
+        let pinned = self_signed(false);
+        let (verifier, failure) = verifier(vec![spki_pin(&pinned).unwrap()]);
+
+        let other = self_signed(false);
+        assert!(verify(&verifier, &other).is_err());
+        assert!(matches!(
+            failure.lock().unwrap().clone(),
+            Some(TlsFailure::PinMismatch { .. })
+        ));
+
+        let expired = self_signed(true);
+        let (verifier, failure) = self::verifier(vec![spki_pin(&expired).unwrap()]);
+        assert!(verify(&verifier, &expired).is_err());
+        assert_eq!(failure.lock().unwrap().clone(), Some(TlsFailure::Expired));
+    }
+
+    #[test]
+    fn test_rejects_malformed_pins_and_missing_trust() {
+        let options = TlsOptions {
+            pinned_spki: vec!["md5/abcd".to_string()],
+            ca_bundle: None,
+            use_system_roots: false,
+        };
+        let provider = Arc::new(rustls::crypto::ring::default_provider());
+        assert!(matches!(
+            PinningVerifier::new(&options, Arc::clone(&provider), FailureSlot::default()),
+            Err(TransportError::TlsConfig(_))
+        ));
+
+        let options = TlsOptions {
+            pinned_spki: Vec::new(),
+            ..options
+        };
+        assert!(matches!(
+            PinningVerifier::new(&options, provider, FailureSlot::default()),
+            Err(TransportError::TlsConfig(_))
+        ));
+    }
+}
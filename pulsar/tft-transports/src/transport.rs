@@ -1,19 +1,183 @@
 //! Transport layer abstraction
 
+use crate::retry::RetryPolicy;
+use crate::throttle::BandwidthLimit;
 use async_trait::async_trait;
 use serde::{Deserialize, Serialize};
+use std::io::ErrorKind;
+use std::path::PathBuf;
 use thiserror::Error;
 
+/// Why a transport operation failed
+///
+/// Variants say enough for callers to decide whether to retry
+/// ([`is_retryable`](Self::is_retryable)) and what to tell the user
+/// ([`action`](Self::action)); [`code`](Self::code) is a stable identifier
+/// for passing the kind across IPC.
 #[derive(Debug, Error)]
 pub enum TransportError {
     #[error("Connection failed: {0}")]
     ConnectionFailed(String),
 
+    #[error("Connection refused by {0}")]
+    Refused(String),
+
+    #[error("Timed out: {0}")]
+    Timeout(String),
+
+    #[error("Connection closed: {0}")]
+    Closed(String),
+
     #[error("IO error: {0}")]
-    Io(#[from] std::io::Error),
+    Io(std::io::Error),
 
     #[error("Protocol error: {0}")]
     Protocol(String),
+
+    #[error("Unsupported protocol version: {0}")]
+    ProtocolVersion(String),
+
+    #[error("Authentication failed: {0}")]
+    Auth(String),
+
+    #[error("Unknown host key for {host} ({fingerprint})")]
+    HostKeyUnknown { host: String, fingerprint: String },
+
+    #[error(
+        "Host key for {host} has changed: server key {fingerprint} does not match known_hosts"
+    )]
+    HostKeyMismatch { host: String, fingerprint: String },
+
+    #[error("Configuration error: {0}")]
+    Config(String),
+
+    #[error("TLS configuration error: {0}")]
+    TlsConfig(String),
+
+    #[error("Certificate pin mismatch for {host}: server key {spki_sha256} is not pinned")]
+    PinMismatch { host: String, spki_sha256: String },
+
+    #[error("Certificate for {0} has expired")]
+    CertificateExpired(String),
+
+    #[error("Certificate for {0} is not yet valid")]
+    CertificateNotYetValid(String),
+
+    #[error("Untrusted certificate for {host}: {reason}")]
+    UntrustedCertificate { host: String, reason: String },
+}
+
+impl TransportError {
+    /// Whether trying again later may succeed
+    ///
+    /// Network failures are transient; authentication, trust and protocol
+    /// failures repeat until the user or the server changes something.
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            TransportError::ConnectionFailed(_)
+            | TransportError::Refused(_)
+            | TransportError::Timeout(_)
+            | TransportError::Closed(_) => true,
+            TransportError::Io(e) => matches!(
+                e.kind(),
+                ErrorKind::ConnectionRefused
+                    | ErrorKind::ConnectionReset
+                    | ErrorKind::ConnectionAborted
+                    | ErrorKind::NotConnected
+                    | ErrorKind::BrokenPipe
+                    | ErrorKind::TimedOut
+                    | ErrorKind::Interrupted
+                    | ErrorKind::UnexpectedEof
+                    | ErrorKind::AddrNotAvailable
+            ),
+            TransportError::Protocol(_)
+            | TransportError::ProtocolVersion(_)
+            | TransportError::Auth(_)
+            | TransportError::HostKeyUnknown { .. }
+            | TransportError::HostKeyMismatch { .. }
+            | TransportError::Config(_)
+            | TransportError::TlsConfig(_)
+            | TransportError::PinMismatch { .. }
+            | TransportError::CertificateExpired(_)
+            | TransportError::CertificateNotYetValid(_)
+            | TransportError::UntrustedCertificate { .. } => false,
+        }
+    }
+
+    /// Stable snake_case identifier of the kind of failure
+    pub fn code(&self) -> &'static str {
+        match self {
+            TransportError::ConnectionFailed(_) => "connection_failed",
+            TransportError::Refused(_) => "refused",
+            TransportError::Timeout(_) => "timeout",
+            TransportError::Closed(_) => "closed",
+            TransportError::Io(_) => "io",
+            TransportError::Protocol(_) => "protocol",
+            TransportError::ProtocolVersion(_) => "protocol_version",
+            TransportError::Auth(_) => "auth",
+            TransportError::HostKeyUnknown { .. } => "host_key_unknown",
+            TransportError::HostKeyMismatch { .. } => "host_key_mismatch",
+            TransportError::Config(_) => "config",
+            TransportError::TlsConfig(_) => "tls_config",
+            TransportError::PinMismatch { .. } => "tls_pin_mismatch",
+            TransportError::CertificateExpired(_) => "certificate_expired",
+            TransportError::CertificateNotYetValid(_) => "certificate_not_yet_valid",
+            TransportError::UntrustedCertificate { .. } => "untrusted_certificate",
+        }
+    }
+
+    /// What the user can do about it, for showing with the error
+    pub fn action(&self) -> &'static str {
+        match self {
+            TransportError::ConnectionFailed(_)
+            | TransportError::Io(_)
+            | TransportError::Closed(_) => "Check your network connection and try again.",
+            TransportError::Refused(_) => {
+                "Check that the server is running and the port is correct."
+            }
+            TransportError::Timeout(_) => {
+                "The server did not answer in time; check the host and any firewall in between."
+            }
+            TransportError::Protocol(_) => {
+                "The server sent something unexpected; check that it is a compatible version."
+            }
+            TransportError::ProtocolVersion(_) => {
+                "Upgrade the client or the server so they share a protocol version."
+            }
+            TransportError::Auth(_) => "Check the username and credentials.",
+            TransportError::HostKeyUnknown { .. } => {
+                "Verify the fingerprint with the server's administrator, then trust it."
+            }
+            TransportError::HostKeyMismatch { .. } => {
+                "The server's identity changed. Unless you know why, do not connect: \
+                 someone may be intercepting the connection."
+            }
+            TransportError::Config(_) | TransportError::TlsConfig(_) => {
+                "Fix the connection settings."
+            }
+            TransportError::PinMismatch { .. } => {
+                "The server's certificate key is not pinned. \
+                 Update the pin only if the key was rotated on purpose."
+            }
+            TransportError::CertificateExpired(_) | TransportError::CertificateNotYetValid(_) => {
+                "Renew the server certificate, and check this computer's clock."
+            }
+            TransportError::UntrustedCertificate { .. } => {
+                "Add the issuing CA to the trusted bundle, or pin the server key."
+            }
+        }
+    }
+}
+
+/// Refused and timed-out connections get their own variants
+impl From<std::io::Error> for TransportError {
+    fn from(error: std::io::Error) -> Self {
+        match error.kind() {
+            ErrorKind::ConnectionRefused => TransportError::Refused(error.to_string()),
+            ErrorKind::TimedOut => TransportError::Timeout(error.to_string()),
+            _ => TransportError::Io(error),
+        }
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -21,6 +185,65 @@ pub struct TransportConfig {
     pub host: String,
     pub port: u16,
     pub timeout_ms: u64,
+    /// Attempt 0-RTT resumption when a session ticket is available
+    #[serde(default = "default_enable_0rtt")]
+    pub enable_0rtt: bool,
+    /// Keep-alive interval; keeps NAT bindings open across brief network blips
+    #[serde(default)]
+    pub keep_alive_ms: Option<u64>,
+    /// How the server certificate is trusted
+    #[serde(default)]
+    pub tls: TlsOptions,
+    /// How failed connects are retried
+    #[serde(default)]
+    pub retry: RetryPolicy,
+    /// Cap on bytes sent and on bytes received, each; unset or 0 is
+    /// unlimited
+    #[serde(default)]
+    pub max_bytes_per_sec: Option<u64>,
+    /// Bytes that may pass at once after an idle spell; defaults to one
+    /// second's worth
+    #[serde(default)]
+    pub burst_bytes: Option<u64>,
+}
+
+impl TransportConfig {
+    /// The configured bandwidth cap, if any
+    pub fn bandwidth_limit(&self) -> Option<BandwidthLimit> {
+        BandwidthLimit::new(self.max_bytes_per_sec?, self.burst_bytes)
+    }
+}
+
+/// Server certificate trust settings
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TlsOptions {
+    /// Accepted server keys as `sha256/<base64 SPKI digest>`
+    #[serde(default)]
+    pub pinned_spki: Vec<String>,
+    /// PEM bundle of additional trusted CAs
+    #[serde(default)]
+    pub ca_bundle: Option<PathBuf>,
+    /// Trust the operating system's certificate store
+    #[serde(default = "default_use_system_roots")]
+    pub use_system_roots: bool,
+}
+
+impl Default for TlsOptions {
+    fn default() -> Self {
+        Self {
+            pinned_spki: Vec::new(),
+            ca_bundle: None,
+            use_system_roots: true,
+        }
+    }
+}
+
+fn default_use_system_roots() -> bool {
+    true
+}
+
+fn default_enable_0rtt() -> bool {
+    true
 }
 
 #[async_trait]
@@ -29,4 +252,57 @@ pub trait Transport: Send + Sync {
     async fn send(&mut self, data: &[u8]) -> Result<(), TransportError>;
     async fn receive(&mut self) -> Result<Vec<u8>, TransportError>;
     async fn disconnect(&mut self) -> Result<(), TransportError>;
+
+    /// The local network changed (see [`crate::network`]); move the
+    /// connection onto the new path, or reconnect, before a timeout would
+    /// notice. Transports without a network path have nothing to do.
+    async fn network_changed(&mut self) -> Result<(), TransportError> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_io_errors_are_classified() {
+        let refused = TransportError::from(std::io::Error::from(ErrorKind::ConnectionRefused));
+        assert!(matches!(refused, TransportError::Refused(_)));
+        assert!(refused.is_retryable());
+
+        let timeout = TransportError::from(std::io::Error::from(ErrorKind::TimedOut));
+        assert_eq!(timeout.code(), "timeout");
+
+        assert!(
+            TransportError::from(std::io::Error::from(ErrorKind::ConnectionReset)).is_retryable()
+        );
+        assert!(
+            !TransportError::from(std::io::Error::from(ErrorKind::PermissionDenied)).is_retryable()
+        );
+    }
+
+    #[test]
+    fn test_trust_and_auth_failures_are_not_retryable() {
+        let errors = [
+            TransportError::Auth("password rejected".to_string()),
+            TransportError::HostKeyMismatch {
+                host: "example.com".to_string(),
+                fingerprint: "SHA256:abc".to_string(),
+            },
+            TransportError::PinMismatch {
+                host: "example.com".to_string(),
+                spki_sha256: "sha256/abc".to_string(),
+            },
+            TransportError::ProtocolVersion("no common version".to_string()),
+        ];
+        for error in &errors {
+            assert!(
+                !error.is_retryable(),
+                "{} should not be retried",
+                error.code()
+            );
+            assert!(!error.action().is_empty());
+        }
+    }
 }